@@ -0,0 +1,295 @@
+//! Intel AC'97 audio controllers
+//!
+//! The controller has two I/O BARs: the native audio mixer, the codec's
+//! registers, and the native audio bus master, whose DMA engines move
+//! samples between memory and the codec. Only PCM out is used. Its engine
+//! walks a ring of 32 buffer descriptors, playing each buffer in turn up
+//! to the last valid index, and halts there until software moves that
+//! index on. The driver keeps buffers filled ahead of the engine and
+//! reclaims them as the current index passes them.
+//!
+//! Codecs play 16-bit stereo. Those with variable rate audio take rates
+//! from 8 kHz to 48 kHz; the rest play at 48 kHz only.
+
+use alloc::boxed::Box;
+use kosh_driver::dma::{DmaMemory, DmaRegion, DMA_PAGE_SIZE};
+use kosh_driver::hal::PortIo;
+use kosh_types::DriverError;
+use crate::pcm::{FIXED_SAMPLE_RATE, MAX_SAMPLE_RATE, MIN_SAMPLE_RATE};
+
+/// Native audio mixer registers, 16 bits each
+pub const MIXER_RESET: u16 = 0x00;
+pub const MIXER_MASTER_VOLUME: u16 = 0x02;
+pub const MIXER_PCM_OUT_VOLUME: u16 = 0x18;
+pub const MIXER_EXTENDED_ID: u16 = 0x28;
+pub const MIXER_EXTENDED_CONTROL: u16 = 0x2A;
+pub const MIXER_FRONT_DAC_RATE: u16 = 0x2C;
+
+/// Variable rate audio, in the extended ID and extended control registers
+pub const EXTENDED_VRA: u16 = 1 << 0;
+
+/// Volume registers: attenuation in 1.5 dB steps for each side, and mute
+pub const VOLUME_MUTE: u16 = 1 << 15;
+/// PCM out at 0 dB gain
+pub const PCM_OUT_0DB: u16 = 0x0808;
+
+/// PCM out box of the bus master registers
+pub const PCM_OUT: u16 = 0x10;
+/// Registers within a box
+pub const BOX_BDBAR: u16 = 0x00;
+pub const BOX_CIV: u16 = 0x04;
+pub const BOX_LVI: u16 = 0x05;
+pub const BOX_STATUS: u16 = 0x06;
+pub const BOX_CONTROL: u16 = 0x0B;
+
+/// Box status bits; the last three are cleared by writing them
+pub const STATUS_DCH: u16 = 1 << 0;
+pub const STATUS_CELV: u16 = 1 << 1;
+pub const STATUS_LVBCI: u16 = 1 << 2;
+pub const STATUS_BCIS: u16 = 1 << 3;
+pub const STATUS_FIFOE: u16 = 1 << 4;
+const STATUS_CLEAR: u16 = STATUS_LVBCI | STATUS_BCIS | STATUS_FIFOE;
+
+/// Box control bits: run the engine, reset the box's registers
+pub const CONTROL_RUN: u8 = 1 << 0;
+pub const CONTROL_RESET: u8 = 1 << 1;
+
+/// Global control and status
+pub const GLOBAL_CONTROL: u16 = 0x2C;
+pub const GLOBAL_STATUS: u16 = 0x30;
+/// Cold reset is held while this bit is clear
+pub const GLOBAL_COLD_RESET: u32 = 1 << 1;
+pub const GLOBAL_PRIMARY_READY: u32 = 1 << 8;
+
+/// Buffer descriptors in the ring
+pub const BUFFER_COUNT: usize = 32;
+const DESCRIPTOR_SIZE: usize = 8;
+
+/// Bytes of each buffer; 1024 stereo frames, about 21 ms at 48 kHz
+pub const BUFFER_BYTES: usize = 4096;
+/// 16-bit samples of each buffer, what descriptors count in
+pub const BUFFER_SAMPLES: usize = BUFFER_BYTES / 2;
+
+/// Descriptor list in the first page, the buffers after it
+const BUFFERS_OFFSET: usize = DMA_PAGE_SIZE;
+const MEMORY_SIZE: usize = BUFFERS_OFFSET + BUFFER_COUNT * BUFFER_BYTES;
+
+/// Register polls before giving up on the controller
+const POLL_LIMIT: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ac97Error {
+    /// The codec or a reset did not finish in time
+    Timeout,
+    /// Out of DMA memory
+    NoMemory,
+}
+
+impl From<Ac97Error> for DriverError {
+    fn from(error: Ac97Error) -> Self {
+        match error {
+            Ac97Error::NoMemory => DriverError::ResourceBusy,
+            Ac97Error::Timeout => DriverError::InitializationFailed,
+        }
+    }
+}
+
+/// An AC'97 controller playing PCM out
+pub struct Ac97 {
+    io: Box<dyn PortIo>,
+    /// First ports of the mixer and bus master BARs
+    mixer: u16,
+    bus: u16,
+    dma: Box<dyn DmaMemory>,
+    memory: DmaRegion,
+    variable_rate: bool,
+    sample_rate: u32,
+    /// Descriptor the next buffer goes in
+    next: usize,
+    /// Buffers handed to the engine and not played yet, ending before
+    /// `next`
+    in_flight: usize,
+}
+
+impl Ac97 {
+    /// Reset the controller with mixer ports from `mixer` and bus master
+    /// ports from `bus`, and set PCM out up to play at 48 kHz
+    pub fn new(io: Box<dyn PortIo>, mixer: u16, bus: u16, mut dma: Box<dyn DmaMemory>) -> Result<Self, Ac97Error> {
+        let memory = dma.allocate(MEMORY_SIZE).ok_or(Ac97Error::NoMemory)?;
+        let mut ac97 = Self {
+            io,
+            mixer,
+            bus,
+            dma,
+            memory,
+            variable_rate: false,
+            sample_rate: FIXED_SAMPLE_RATE,
+            next: 0,
+            in_flight: 0,
+        };
+        ac97.reset()?;
+        Ok(ac97)
+    }
+
+    fn reset(&mut self) -> Result<(), Ac97Error> {
+        // Leave cold reset and wait for the codec to come up
+        self.io.write_u32(self.bus + GLOBAL_CONTROL, GLOBAL_COLD_RESET);
+        self.wait(|ac97| ac97.io.read_u32(ac97.bus + GLOBAL_STATUS) & GLOBAL_PRIMARY_READY != 0)?;
+
+        // Any write resets the codec's registers
+        self.write_mixer(MIXER_RESET, 0);
+        self.write_mixer(MIXER_MASTER_VOLUME, 0);
+        self.write_mixer(MIXER_PCM_OUT_VOLUME, PCM_OUT_0DB);
+        self.variable_rate = self.read_mixer(MIXER_EXTENDED_ID) & EXTENDED_VRA != 0;
+        if self.variable_rate {
+            let control = self.read_mixer(MIXER_EXTENDED_CONTROL);
+            self.write_mixer(MIXER_EXTENDED_CONTROL, control | EXTENDED_VRA);
+        }
+        self.sample_rate = self.negotiate_rate(FIXED_SAMPLE_RATE);
+
+        // Descriptors never change address, only length
+        for index in 0..BUFFER_COUNT {
+            let buffer = self.memory.phys + (BUFFERS_OFFSET + index * BUFFER_BYTES) as u64;
+            self.memory.write_u32(index * DESCRIPTOR_SIZE, buffer as u32);
+            self.memory.write_u32(index * DESCRIPTOR_SIZE + 4, 0);
+        }
+        self.reset_stream()
+    }
+
+    /// Halt PCM out, dropping what it had queued, and start the ring over
+    pub fn reset_stream(&mut self) -> Result<(), Ac97Error> {
+        self.io.write_u8(self.bus + PCM_OUT + BOX_CONTROL, 0);
+        self.io.write_u8(self.bus + PCM_OUT + BOX_CONTROL, CONTROL_RESET);
+        self.wait(|ac97| ac97.io.read_u8(ac97.bus + PCM_OUT + BOX_CONTROL) & CONTROL_RESET == 0)?;
+        self.io.write_u32(self.bus + PCM_OUT + BOX_BDBAR, self.memory.phys as u32);
+        self.next = 0;
+        self.in_flight = 0;
+        Ok(())
+    }
+
+    fn wait(&mut self, done: impl Fn(&mut Self) -> bool) -> Result<(), Ac97Error> {
+        for _ in 0..POLL_LIMIT {
+            if done(self) {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(Ac97Error::Timeout)
+    }
+
+    fn read_mixer(&mut self, register: u16) -> u16 {
+        self.io.read_u16(self.mixer + register)
+    }
+
+    fn write_mixer(&mut self, register: u16, value: u16) {
+        self.io.write_u16(self.mixer + register, value);
+    }
+
+    /// Whether the codec plays at rates other than 48 kHz
+    pub fn variable_rate(&self) -> bool {
+        self.variable_rate
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Play at the rate closest to `rate` the codec offers, returning it
+    ///
+    /// Queued buffers are dropped, since they were for the old rate.
+    pub fn set_sample_rate(&mut self, rate: u32) -> Result<u32, Ac97Error> {
+        self.reset_stream()?;
+        self.sample_rate = self.negotiate_rate(rate);
+        Ok(self.sample_rate)
+    }
+
+    /// Ask the codec for `rate`; it answers with the rate it will play at,
+    /// which may be rounded
+    fn negotiate_rate(&mut self, rate: u32) -> u32 {
+        if !self.variable_rate {
+            return FIXED_SAMPLE_RATE;
+        }
+        let rate = rate.clamp(MIN_SAMPLE_RATE, MAX_SAMPLE_RATE);
+        self.write_mixer(MIXER_FRONT_DAC_RATE, rate as u16);
+        match self.read_mixer(MIXER_FRONT_DAC_RATE) as u32 {
+            0 => FIXED_SAMPLE_RATE,
+            actual => actual,
+        }
+    }
+
+    /// Set the master volume, 0 to 100 for each side; 0 mutes
+    pub fn set_volume(&mut self, left: u8, right: u8) {
+        // 63 steps of attenuation from full volume
+        let attenuation = |volume: u8| (63 - (volume.min(100) as u16 * 63 / 100)) & 0x3F;
+        let mut value = attenuation(left) << 8 | attenuation(right);
+        if left == 0 && right == 0 {
+            value |= VOLUME_MUTE;
+        }
+        self.write_mixer(MIXER_MASTER_VOLUME, value);
+    }
+
+    /// Buffers that can be filled now
+    pub fn free_buffers(&self) -> usize {
+        // One descriptor stays unused, so a full ring never looks empty
+        BUFFER_COUNT - 1 - self.in_flight
+    }
+
+    /// Whether nothing is left to play
+    pub fn is_idle(&self) -> bool {
+        self.in_flight == 0
+    }
+
+    /// Reclaim the buffers the engine has finished, returning how many
+    pub fn reclaim(&mut self) -> usize {
+        let status = self.io.read_u16(self.bus + PCM_OUT + BOX_STATUS);
+        self.io.write_u16(self.bus + PCM_OUT + BOX_STATUS, status & STATUS_CLEAR);
+        let done = if status & STATUS_DCH != 0 {
+            // Halted at the last valid buffer, which is played too
+            self.in_flight
+        } else {
+            // Buffers before the current one are played
+            let current = self.io.read_u8(self.bus + PCM_OUT + BOX_CIV) as usize % BUFFER_COUNT;
+            let first = (self.next + BUFFER_COUNT - self.in_flight) % BUFFER_COUNT;
+            ((current + BUFFER_COUNT - first) % BUFFER_COUNT).min(self.in_flight)
+        };
+        self.in_flight -= done;
+        done
+    }
+
+    /// Copy up to a buffer of interleaved 16-bit stereo `samples` into the
+    /// next free buffer and hand it to the engine, returning how many were
+    /// taken; 0 if no buffer is free
+    pub fn queue(&mut self, samples: &[i16]) -> usize {
+        if self.free_buffers() == 0 || samples.is_empty() {
+            return 0;
+        }
+        // Whole frames only
+        let count = samples.len().min(BUFFER_SAMPLES) & !1;
+        if count == 0 {
+            return 0;
+        }
+        let index = self.next;
+        let offset = BUFFERS_OFFSET + index * BUFFER_BYTES;
+        for (sample_index, sample) in samples[..count].iter().enumerate() {
+            self.memory.write_u16(offset + sample_index * 2, *sample as u16);
+        }
+        self.memory.write_u32(index * DESCRIPTOR_SIZE + 4, count as u32);
+        self.next = (index + 1) % BUFFER_COUNT;
+        self.in_flight += 1;
+
+        // Moving the last valid index on restarts an engine halted there
+        self.io.write_u8(self.bus + PCM_OUT + BOX_LVI, index as u8);
+        let control = self.io.read_u8(self.bus + PCM_OUT + BOX_CONTROL);
+        if control & CONTROL_RUN == 0 {
+            self.io.write_u8(self.bus + PCM_OUT + BOX_CONTROL, control | CONTROL_RUN);
+        }
+        count
+    }
+}
+
+impl Drop for Ac97 {
+    fn drop(&mut self) {
+        self.io.write_u8(self.bus + PCM_OUT + BOX_CONTROL, 0);
+        self.dma.free(self.memory);
+    }
+}
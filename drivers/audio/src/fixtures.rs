@@ -0,0 +1,312 @@
+//! Emulated AC'97 controller
+//!
+//! Implements the mixer and PCM out bus master registers the way QEMU
+//! does: the engine plays the buffer at the current index, moves to the
+//! prefetched next one, and halts when the buffer it finished was the last
+//! valid one, resuming when that index moves on. Tests say how many frames
+//! the codec consumes and read back what it played.
+//!
+//! Test DMA memory is identity mapped but lies above 4 GiB, where the
+//! card's 32-bit addresses do not reach, so addresses the driver programs
+//! are matched against live regions by their low 32 bits.
+
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+use kosh_driver::dma::{DmaMemory, DmaRegion, DMA_PAGE_SIZE};
+use kosh_driver::hal::PortIo;
+use spin::Mutex;
+use crate::ac97::{
+    Ac97, BOX_BDBAR, BOX_CIV, BOX_CONTROL, BOX_LVI, BOX_STATUS, BUFFER_COUNT, CONTROL_RESET, CONTROL_RUN,
+    EXTENDED_VRA, GLOBAL_COLD_RESET, GLOBAL_CONTROL, GLOBAL_PRIMARY_READY, GLOBAL_STATUS, MIXER_EXTENDED_CONTROL,
+    MIXER_EXTENDED_ID, MIXER_FRONT_DAC_RATE, MIXER_MASTER_VOLUME, MIXER_PCM_OUT_VOLUME, MIXER_RESET, PCM_OUT,
+    STATUS_BCIS, STATUS_CELV, STATUS_DCH, STATUS_LVBCI,
+};
+use crate::pcm::FIXED_SAMPLE_RATE;
+
+/// Where the fake's BARs decode
+pub const MIXER_BASE: u16 = 0x1000;
+pub const BUS_BASE: u16 = 0x1100;
+
+/// Interrupt on completion, in a descriptor's flags
+const DESCRIPTOR_IOC: u16 = 1 << 15;
+
+/// Identity-mapped DMA memory the test keeps a handle on while the driver
+/// owns it
+#[derive(Clone, Default)]
+pub struct HeapDma(pub Arc<Mutex<Vec<DmaRegion>>>);
+
+impl HeapDma {
+    /// Regions allocated and not freed
+    pub fn live(&self) -> usize {
+        self.0.lock().len()
+    }
+
+    /// The address a 32-bit device address points at
+    fn resolve(&self, address: u32) -> u64 {
+        self.0.lock().iter()
+            .find(|region| {
+                let start = region.phys as u32;
+                address >= start && ((address - start) as usize) < region.size
+            })
+            .map(|region| region.phys + (address - region.phys as u32) as u64)
+            .expect("device address outside DMA memory")
+    }
+}
+
+impl DmaMemory for HeapDma {
+    fn allocate(&mut self, size: usize) -> Option<DmaRegion> {
+        let memory = vec![0u8; size + DMA_PAGE_SIZE].leak();
+        let start = (memory.as_ptr() as usize).next_multiple_of(DMA_PAGE_SIZE);
+        let region = DmaRegion { virt: start, phys: start as u64, size };
+        self.0.lock().push(region);
+        Some(region)
+    }
+
+    fn free(&mut self, region: DmaRegion) {
+        self.0.lock().retain(|live| *live != region);
+    }
+}
+
+fn peek_u16(address: u64) -> u16 {
+    unsafe { (address as *const u16).read_volatile() }
+}
+
+fn peek_u32(address: u64) -> u32 {
+    unsafe { (address as *const u32).read_volatile() }
+}
+
+pub struct Ac97State {
+    /// Whether the codec takes rates other than 48 kHz
+    pub variable_rate: bool,
+    pub global_control: u32,
+    pub master_volume: u16,
+    pub pcm_out_volume: u16,
+    extended_control: u16,
+    pub dac_rate: u16,
+    bdbar: u32,
+    civ: u8,
+    piv: u8,
+    lvi: u8,
+    status: u16,
+    control: u8,
+    /// Samples left in the current buffer, and where the next one is read
+    remaining: usize,
+    position: u64,
+    /// Whether the engine is playing rather than halted
+    pub running: bool,
+    /// Every sample played, in order
+    pub played: Vec<i16>,
+}
+
+impl Ac97State {
+    fn reset_mixer(&mut self) {
+        self.master_volume = 0x8000;
+        self.pcm_out_volume = 0x8808;
+        self.extended_control = 0;
+        self.dac_rate = FIXED_SAMPLE_RATE as u16;
+    }
+
+    fn reset_box(&mut self) {
+        self.bdbar = 0;
+        self.civ = 0;
+        self.piv = 0;
+        self.lvi = 0;
+        self.status = STATUS_DCH;
+        self.control = 0;
+        self.remaining = 0;
+        self.running = false;
+    }
+
+    /// Make the prefetched buffer current and load its descriptor
+    fn advance(&mut self, dma: &HeapDma) {
+        self.civ = self.piv;
+        self.piv = (self.piv + 1) % BUFFER_COUNT as u8;
+        let descriptor = dma.resolve(self.bdbar) + self.civ as u64 * 8;
+        self.position = dma.resolve(peek_u32(descriptor));
+        self.remaining = peek_u16(descriptor + 4) as usize;
+        self.status &= !(STATUS_DCH | STATUS_CELV);
+        self.running = true;
+    }
+
+    fn write_control(&mut self, value: u8, dma: &HeapDma) {
+        if value & CONTROL_RESET != 0 {
+            return self.reset_box();
+        }
+        let starting = value & CONTROL_RUN != 0 && self.control & CONTROL_RUN == 0;
+        self.control = value;
+        if value & CONTROL_RUN == 0 {
+            self.running = false;
+        } else if starting {
+            self.advance(dma);
+        }
+    }
+
+    fn write_lvi(&mut self, value: u8, dma: &HeapDma) {
+        self.lvi = value % BUFFER_COUNT as u8;
+        if self.control & CONTROL_RUN != 0 && self.status & STATUS_DCH != 0 {
+            self.advance(dma);
+        }
+    }
+
+    /// The codec consumes up to `frames` stereo frames
+    fn play(&mut self, frames: usize, dma: &HeapDma) {
+        let mut samples = frames * 2;
+        while self.running && samples > 0 {
+            let count = self.remaining.min(samples);
+            for _ in 0..count {
+                self.played.push(peek_u16(self.position) as i16);
+                self.position += 2;
+            }
+            self.remaining -= count;
+            samples -= count;
+            if self.remaining > 0 {
+                continue;
+            }
+            let descriptor = dma.resolve(self.bdbar) + self.civ as u64 * 8;
+            if peek_u16(descriptor + 6) & DESCRIPTOR_IOC != 0 {
+                self.status |= STATUS_BCIS;
+            }
+            if self.civ == self.lvi {
+                self.status |= STATUS_DCH | STATUS_CELV | STATUS_LVBCI;
+                self.running = false;
+            } else {
+                self.advance(dma);
+            }
+        }
+    }
+}
+
+/// Shared handle to an emulated controller; the driver gets it as its port
+/// I/O
+#[derive(Clone)]
+pub struct FakeAc97 {
+    pub state: Arc<Mutex<Ac97State>>,
+    pub dma: HeapDma,
+}
+
+impl FakeAc97 {
+    pub fn new(variable_rate: bool) -> Self {
+        let mut state = Ac97State {
+            variable_rate,
+            global_control: 0,
+            master_volume: 0,
+            pcm_out_volume: 0,
+            extended_control: 0,
+            dac_rate: 0,
+            bdbar: 0,
+            civ: 0,
+            piv: 0,
+            lvi: 0,
+            status: 0,
+            control: 0,
+            remaining: 0,
+            position: 0,
+            running: false,
+            played: Vec::new(),
+        };
+        state.reset_mixer();
+        state.reset_box();
+        Self { state: Arc::new(Mutex::new(state)), dma: HeapDma::default() }
+    }
+
+    /// The controller set up by the driver
+    pub fn ac97(&self) -> Ac97 {
+        Ac97::new(Box::new(self.clone()), MIXER_BASE, BUS_BASE, Box::new(self.dma.clone())).unwrap()
+    }
+
+    /// Let the codec consume up to `frames` frames
+    pub fn play(&self, frames: usize) {
+        self.state.lock().play(frames, &self.dma);
+    }
+
+    /// Take the samples played so far
+    pub fn take_played(&self) -> Vec<i16> {
+        core::mem::take(&mut self.state.lock().played)
+    }
+
+    pub fn running(&self) -> bool {
+        self.state.lock().running
+    }
+
+    fn read(&mut self, port: u16) -> u32 {
+        let state = self.state.lock();
+        if let Some(register) = port.checked_sub(MIXER_BASE).filter(|register| *register < 0x80) {
+            return match register {
+                MIXER_MASTER_VOLUME => state.master_volume,
+                MIXER_PCM_OUT_VOLUME => state.pcm_out_volume,
+                MIXER_EXTENDED_ID if state.variable_rate => EXTENDED_VRA,
+                MIXER_EXTENDED_CONTROL => state.extended_control,
+                MIXER_FRONT_DAC_RATE => state.dac_rate,
+                _ => 0,
+            } as u32;
+        }
+        match port.wrapping_sub(BUS_BASE) {
+            GLOBAL_CONTROL => state.global_control,
+            GLOBAL_STATUS => {
+                if state.global_control & GLOBAL_COLD_RESET != 0 { GLOBAL_PRIMARY_READY } else { 0 }
+            }
+            register => match register.wrapping_sub(PCM_OUT) {
+                BOX_BDBAR => state.bdbar,
+                BOX_CIV => state.civ as u32,
+                BOX_LVI => state.lvi as u32,
+                BOX_STATUS => state.status as u32,
+                BOX_CONTROL => state.control as u32,
+                _ => 0,
+            },
+        }
+    }
+
+    fn write(&mut self, port: u16, value: u32) {
+        let dma = self.dma.clone();
+        let mut state = self.state.lock();
+        if let Some(register) = port.checked_sub(MIXER_BASE).filter(|register| *register < 0x80) {
+            let value = value as u16;
+            match register {
+                MIXER_RESET => state.reset_mixer(),
+                MIXER_MASTER_VOLUME => state.master_volume = value,
+                MIXER_PCM_OUT_VOLUME => state.pcm_out_volume = value,
+                MIXER_EXTENDED_CONTROL => state.extended_control = value & EXTENDED_VRA,
+                // Without variable rate audio the rate stays at 48 kHz
+                MIXER_FRONT_DAC_RATE if state.extended_control & EXTENDED_VRA != 0 => state.dac_rate = value,
+                _ => {}
+            }
+            return;
+        }
+        match port.wrapping_sub(BUS_BASE) {
+            GLOBAL_CONTROL => state.global_control = value,
+            register => match register.wrapping_sub(PCM_OUT) {
+                BOX_BDBAR => state.bdbar = value & !7,
+                BOX_LVI => state.write_lvi(value as u8, &dma),
+                // Status bits other than DCH and CELV clear when written
+                BOX_STATUS => state.status &= !(value as u16 & !(STATUS_DCH | STATUS_CELV)),
+                BOX_CONTROL => state.write_control(value as u8, &dma),
+                _ => {}
+            },
+        }
+    }
+}
+
+impl PortIo for FakeAc97 {
+    fn read_u8(&mut self, port: u16) -> u8 {
+        self.read(port) as u8
+    }
+
+    fn write_u8(&mut self, port: u16, value: u8) {
+        self.write(port, value as u32);
+    }
+
+    fn read_u16(&mut self, port: u16) -> u16 {
+        self.read(port) as u16
+    }
+
+    fn write_u16(&mut self, port: u16, value: u16) {
+        self.write(port, value as u32);
+    }
+
+    fn read_u32(&mut self, port: u16) -> u32 {
+        self.read(port)
+    }
+
+    fn write_u32(&mut self, port: u16, value: u32) {
+        self.write(port, value);
+    }
+}
@@ -0,0 +1,412 @@
+#![no_std]
+
+extern crate alloc;
+
+pub mod ac97;
+pub mod pcm;
+
+use alloc::{vec, vec::Vec, string::String, boxed::Box};
+use kosh_driver::{
+    KoshDriver, DriverInfo, DriverType, HardwareId, DriverStatus, PowerEvent, DriverFactory,
+    DriverRequest, DriverResponse, DriverCapabilityType, HardwareCapability, MemoryCapability
+};
+use kosh_sync::Mutex;
+use kosh_types::{DriverError, Capability};
+use ac97::Ac97;
+use pcm::{square_wave, Converter, PcmFormat, DEVICE_CHANNELS, FIXED_SAMPLE_RATE};
+
+/// Devices the factory takes, by vendor and device ID: the Intel 82801AA
+/// AC'97 controller QEMU and VirtualBox emulate
+pub const AC97_HARDWARE_IDS: [(u32, u32); 1] = [(0x8086, 0x2415)];
+
+/// Most audio queued in the driver ahead of the card, in milliseconds;
+/// writes beyond it are refused until the card catches up
+pub const MAX_PENDING_MS: u32 = 2_000;
+
+/// A sound card playing 16-bit stereo
+pub trait AudioBackend: Send {
+    /// Rate the card plays at
+    fn sample_rate(&self) -> u32;
+
+    /// Play at the rate closest to `rate` the card offers, returning it;
+    /// whatever was queued is dropped
+    fn set_sample_rate(&mut self, rate: u32) -> Result<u32, DriverError>;
+
+    /// Hand up to a buffer of interleaved `samples` to the card, returning
+    /// how many it took; 0 when it has no room
+    fn queue(&mut self, samples: &[i16]) -> usize;
+
+    /// Make room for more by taking back buffers the card has played
+    fn reclaim(&mut self);
+
+    /// Whether the card has played everything it was given
+    fn is_idle(&self) -> bool;
+
+    /// Stop playing and drop what was queued
+    fn stop(&mut self) -> Result<(), DriverError>;
+
+    /// Set the output volume, 0 to 100 for each side
+    fn set_volume(&mut self, left: u8, right: u8);
+}
+
+impl AudioBackend for Ac97 {
+    fn sample_rate(&self) -> u32 {
+        Ac97::sample_rate(self)
+    }
+
+    fn set_sample_rate(&mut self, rate: u32) -> Result<u32, DriverError> {
+        Ok(Ac97::set_sample_rate(self, rate)?)
+    }
+
+    fn queue(&mut self, samples: &[i16]) -> usize {
+        Ac97::queue(self, samples)
+    }
+
+    fn reclaim(&mut self) {
+        Ac97::reclaim(self);
+    }
+
+    fn is_idle(&self) -> bool {
+        Ac97::is_idle(self)
+    }
+
+    fn stop(&mut self) -> Result<(), DriverError> {
+        Ok(self.reset_stream()?)
+    }
+
+    fn set_volume(&mut self, left: u8, right: u8) {
+        Ac97::set_volume(self, left, right)
+    }
+}
+
+/// PCM playback driver
+///
+/// Clients choose a format, then write samples in it. Writes are
+/// converted to what the card plays and queued, and `poll` moves queued
+/// samples to the card as it frees buffers.
+pub struct AudioDriver {
+    backend: Option<Box<dyn AudioBackend>>,
+    status: DriverStatus,
+    converter: Converter,
+    /// Converted samples the card has not taken yet
+    pending: Vec<i16>,
+}
+
+impl Default for AudioDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioDriver {
+    /// A driver with no card, which refuses playback
+    pub fn new() -> Self {
+        Self {
+            backend: None,
+            status: DriverStatus::Uninitialized,
+            converter: Converter::new(PcmFormat::default(), FIXED_SAMPLE_RATE)
+                .expect("the device format is supported"),
+            pending: Vec::new(),
+        }
+    }
+
+    /// A driver playing through `backend`; clients write in its format
+    /// until they choose another
+    pub fn with_backend(backend: Box<dyn AudioBackend>) -> Self {
+        let rate = backend.sample_rate();
+        let mut driver = Self::new();
+        driver.converter = Converter::new(PcmFormat::device(rate), rate)
+            .expect("the device format is supported");
+        driver.backend = Some(backend);
+        driver
+    }
+
+    fn backend(&mut self) -> Result<&mut Box<dyn AudioBackend>, DriverError> {
+        self.backend.as_mut().ok_or(DriverError::HardwareNotFound)
+    }
+
+    /// The format clients write in
+    pub fn format(&self) -> PcmFormat {
+        self.converter.format()
+    }
+
+    /// The format the card plays
+    pub fn device_format(&self) -> PcmFormat {
+        PcmFormat::device(self.backend.as_ref().map_or(FIXED_SAMPLE_RATE, |backend| backend.sample_rate()))
+    }
+
+    /// Write samples in `format` from now on, returning the format the
+    /// card plays them in
+    ///
+    /// The card is asked for the client's rate so samples need no rate
+    /// conversion where it can play them as they are. Anything queued in
+    /// the old format is dropped.
+    pub fn set_format(&mut self, format: PcmFormat) -> Result<PcmFormat, DriverError> {
+        if !format.is_supported() {
+            return Err(DriverError::InvalidRequest);
+        }
+        let rate = self.backend()?.set_sample_rate(format.sample_rate)?;
+        self.converter = Converter::new(format, rate)?;
+        self.pending.clear();
+        Ok(PcmFormat::device(rate))
+    }
+
+    /// Samples the driver holds at most
+    fn pending_limit(&self) -> usize {
+        (self.device_format().sample_rate * MAX_PENDING_MS / 1000) as usize * DEVICE_CHANNELS
+    }
+
+    /// Queue `samples` of the card's format behind what is already queued
+    fn enqueue(&mut self, samples: &[i16]) -> Result<(), DriverError> {
+        if self.pending.len() + samples.len() > self.pending_limit() {
+            return Err(DriverError::ResourceBusy);
+        }
+        self.pending.extend_from_slice(samples);
+        self.poll();
+        Ok(())
+    }
+
+    /// Play `bytes` of samples in the client's format
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), DriverError> {
+        self.backend()?;
+        // Convert a copy so a refused write leaves the stream as it was
+        let mut converter = self.converter.clone();
+        let mut samples = Vec::new();
+        converter.convert(bytes, &mut samples);
+        self.enqueue(&samples)?;
+        self.converter = converter;
+        Ok(())
+    }
+
+    /// Play a square wave of `frequency` Hz for `duration_ms`
+    pub fn play_tone(&mut self, frequency: u32, duration_ms: u32) -> Result<(), DriverError> {
+        let rate = self.backend()?.sample_rate();
+        self.enqueue(&square_wave(frequency, duration_ms, rate))
+    }
+
+    /// Stop playing and drop everything queued
+    pub fn stop(&mut self) -> Result<(), DriverError> {
+        self.backend()?.stop()?;
+        self.pending.clear();
+        self.converter.reset();
+        Ok(())
+    }
+
+    /// Hand queued samples to the card as far as it has room, returning
+    /// how many it took
+    pub fn poll(&mut self) -> usize {
+        let Some(backend) = self.backend.as_mut() else {
+            return 0;
+        };
+        backend.reclaim();
+        let mut taken = 0;
+        while taken < self.pending.len() {
+            let count = backend.queue(&self.pending[taken..]);
+            if count == 0 {
+                break;
+            }
+            taken += count;
+        }
+        self.pending.drain(..taken);
+        taken
+    }
+
+    /// Whether everything written has been played
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty() && self.backend.as_ref().is_none_or(|backend| backend.is_idle())
+    }
+}
+
+impl KoshDriver for AudioDriver {
+    fn init(&mut self, _capabilities: Vec<Capability>) -> Result<(), DriverError> {
+        self.status = DriverStatus::Initializing;
+        self.backend()?;
+        self.status = DriverStatus::Ready;
+        Ok(())
+    }
+
+    fn handle_request(&mut self, request: DriverRequest) -> Result<DriverResponse, DriverError> {
+        match request {
+            DriverRequest::Initialize => {
+                self.init(Vec::new())?;
+                Ok(DriverResponse::Success)
+            }
+
+            DriverRequest::Write { data, .. } => {
+                self.write(&data)?;
+                Ok(DriverResponse::Success)
+            }
+
+            DriverRequest::Control { command, data } => {
+                match command {
+                    // Set format command: a format as `FORMAT_BYTES`;
+                    // answers with the format the card plays
+                    0x01 => {
+                        let format = PcmFormat::from_bytes(&data).ok_or(DriverError::InvalidRequest)?;
+                        let device = self.set_format(format)?;
+                        Ok(DriverResponse::Data(device.to_bytes().to_vec()))
+                    }
+                    // Get format command: the client's format, then the
+                    // card's
+                    0x02 => {
+                        let mut bytes = self.format().to_bytes().to_vec();
+                        bytes.extend_from_slice(&self.device_format().to_bytes());
+                        Ok(DriverResponse::Data(bytes))
+                    }
+                    // Tone command: frequency in Hz and duration in
+                    // milliseconds, little-endian u16 each
+                    0x03 => {
+                        let field = |index: usize| {
+                            data.get(index..index + 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as u32)
+                        };
+                        let (frequency, duration) = field(0).zip(field(2)).ok_or(DriverError::InvalidRequest)?;
+                        self.play_tone(frequency, duration)?;
+                        Ok(DriverResponse::Success)
+                    }
+                    // Stop command
+                    0x04 => {
+                        self.stop()?;
+                        Ok(DriverResponse::Success)
+                    }
+                    // Set volume command: left and right, 0 to 100
+                    0x05 => {
+                        if data.len() < 2 || data[0] > 100 || data[1] > 100 {
+                            return Err(DriverError::InvalidRequest);
+                        }
+                        self.backend()?.set_volume(data[0], data[1]);
+                        Ok(DriverResponse::Success)
+                    }
+                    _ => Err(DriverError::InvalidRequest)
+                }
+            }
+
+            DriverRequest::Query { query_type } => {
+                match query_type {
+                    kosh_driver::QueryType::Status => {
+                        Ok(DriverResponse::Status(self.status))
+                    }
+                    kosh_driver::QueryType::HardwareInfo => {
+                        let info = self.get_driver_info();
+                        Ok(DriverResponse::Info(info))
+                    }
+                    _ => Err(DriverError::InvalidRequest)
+                }
+            }
+
+            _ => Err(DriverError::InvalidRequest)
+        }
+    }
+
+    fn cleanup(&mut self) -> Result<(), DriverError> {
+        self.status = DriverStatus::Stopping;
+        if self.backend.is_some() {
+            self.stop()?;
+        }
+        self.status = DriverStatus::Uninitialized;
+        Ok(())
+    }
+
+    fn get_required_capabilities(&self) -> Vec<DriverCapabilityType> {
+        let mut capabilities: Vec<DriverCapabilityType> = AC97_HARDWARE_IDS.iter()
+            .map(|&(vendor_id, device_id)| {
+                DriverCapabilityType::Hardware(HardwareCapability::PciDevice { vendor_id, device_id })
+            })
+            .collect();
+        capabilities.push(DriverCapabilityType::Memory(MemoryCapability::DmaMemory));
+        capabilities
+    }
+
+    fn get_provided_capabilities(&self) -> Vec<DriverCapabilityType> {
+        vec![DriverCapabilityType::Custom(String::from("pcm_playback"))]
+    }
+
+    fn get_driver_info(&self) -> DriverInfo {
+        DriverInfo {
+            name: String::from("AC'97 Audio Driver"),
+            version: String::from("0.1.0"),
+            vendor: String::from("Kosh OS"),
+            description: String::from("PCM playback through AC'97 audio controllers"),
+            driver_type: DriverType::Audio,
+            hardware_ids: AC97_HARDWARE_IDS.iter()
+                .map(|&(vendor_id, device_id)| HardwareId {
+                    vendor_id,
+                    device_id,
+                    subsystem_vendor_id: None,
+                    subsystem_device_id: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn handle_power_event(&mut self, event: PowerEvent) -> Result<(), DriverError> {
+        match event {
+            PowerEvent::Suspend => {
+                if self.backend.is_some() {
+                    self.stop()?;
+                }
+                self.status = DriverStatus::Suspended;
+                Ok(())
+            }
+            PowerEvent::Resume => {
+                self.status = DriverStatus::Ready;
+                Ok(())
+            }
+            PowerEvent::PowerDown => {
+                self.cleanup()
+            }
+            _ => Ok(())
+        }
+    }
+
+    fn get_status(&self) -> DriverStatus {
+        self.status
+    }
+}
+
+/// Global audio driver
+static AUDIO_DRIVER: Mutex<Option<AudioDriver>> = Mutex::new(None);
+
+/// Initialize the global driver playing through `backend`
+pub fn init_audio_driver(backend: Box<dyn AudioBackend>) -> Result<(), DriverError> {
+    let mut driver = AudioDriver::with_backend(backend);
+    driver.init(Vec::new())?;
+    *AUDIO_DRIVER.lock() = Some(driver);
+    Ok(())
+}
+
+/// Keep the card fed, returning how many samples it took
+pub fn audio_poll() -> usize {
+    AUDIO_DRIVER.lock().as_mut().map_or(0, AudioDriver::poll)
+}
+
+/// Handle a request to the global driver
+pub fn audio_request(request: DriverRequest) -> Result<DriverResponse, DriverError> {
+    AUDIO_DRIVER.lock().as_mut().ok_or(DriverError::HardwareNotFound)?.handle_request(request)
+}
+
+/// Driver factory matching the devices in `AC97_HARDWARE_IDS`
+///
+/// The driver needs the controller's I/O ports and DMA memory, which the
+/// factory interface cannot pass, so the driver process creates it with
+/// `init_audio_driver` instead.
+pub struct AudioDriverFactory;
+
+impl DriverFactory for AudioDriverFactory {
+    fn create_driver(&self, _hardware_id: &HardwareId) -> Result<Box<dyn KoshDriver>, DriverError> {
+        Err(DriverError::InvalidRequest)
+    }
+
+    fn can_handle(&self, hardware_id: &HardwareId) -> bool {
+        AC97_HARDWARE_IDS.contains(&(hardware_id.vendor_id, hardware_id.device_id))
+    }
+
+    fn get_driver_type(&self) -> DriverType {
+        DriverType::Audio
+    }
+}
+
+#[cfg(test)]
+mod fixtures;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use kosh_driver::dma::KernelDma;
+use kosh_driver::hal::HardwarePortIo;
+use kosh_driver::pci::{self, ConfigSpace, PortConfigSpace};
+use kosh_driver::report;
+use kosh_driver::time::{sleep_us, PeriodicTimer};
+use kosh_driver::{DriverMetadataRecord, DriverSignatureRecord, DriverType};
+use kosh_audio_driver::ac97::Ac97;
+use kosh_audio_driver::{audio_poll, init_audio_driver, AC97_HARDWARE_IDS};
+
+/// I/O space and bus master enable in the command register
+const COMMAND_IO_AND_BUS_MASTER: u32 = 0x5;
+
+/// Time between refills of the card's buffers, well inside the time the
+/// buffers it holds take to play
+const POLL_INTERVAL_US: u64 = 10_000;
+
+/// Read by the driver manager: the AC'97 controller QEMU and VirtualBox
+/// emulate. The device is found through the configuration ports and its
+/// BARs are granted once the manager binds it.
+#[used]
+#[link_section = ".kosh_driver"]
+static DRIVER_METADATA: DriverMetadataRecord = DriverMetadataRecord::new("ac97", "0.1.0", DriverType::Audio)
+    .with_hardware_id(0x8086, 0x2415)
+    .requires_io_ports(0xCF8, 0xCFF)
+    .requires_pci_device(0x8086, 0x2415)
+    .requires_dma_memory();
+
+/// Filled in when the driver is signed
+#[used]
+#[link_section = ".kosh_signature"]
+static DRIVER_SIGNATURE: DriverSignatureRecord = DriverSignatureRecord::UNSIGNED;
+
+/// Find the first AC'97 controller, enable it and set it up: BAR 0 holds
+/// the mixer registers and BAR 1 the bus master ones
+fn find_device() -> Option<Ac97> {
+    let mut config = PortConfigSpace::new(HardwarePortIo);
+    let device = pci::enumerate(&mut config)
+        .into_iter()
+        .find(|device| AC97_HARDWARE_IDS.contains(&(device.vendor_id as u32, device.device_id as u32)))?;
+    let bars = pci::io_bars(&mut config, device.address);
+    let port = |index: u8| bars.iter().find(|bar| bar.index == index).map(|bar| bar.port);
+    let (mixer, bus) = (port(0)?, port(1)?);
+
+    // Only touch the command half: status bits are cleared by writing 1
+    let command = config.read_u32(device.address, pci::COMMAND_STATUS) & 0xFFFF;
+    config.write_u32(device.address, pci::COMMAND_STATUS, command | COMMAND_IO_AND_BUS_MASTER);
+
+    match Ac97::new(Box::new(HardwarePortIo), mixer, bus, Box::new(KernelDma::default())) {
+        Ok(ac97) => Some(ac97),
+        Err(e) => panic!("Failed to set up AC'97 controller: {:?}", e),
+    }
+}
+
+/// Entry point for the audio driver process
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    if let Some(ac97) = find_device() {
+        if let Err(e) = init_audio_driver(Box::new(ac97)) {
+            report::fail(format_args!("Failed to initialize audio driver: {:?}", e));
+        }
+    }
+
+    // Main driver loop: buffer completion is not delivered as an
+    // interrupt yet, so refill the card now and then and sleep in between
+    let timer = PeriodicTimer::new(POLL_INTERVAL_US);
+    loop {
+        audio_poll();
+
+        match &timer {
+            Some(timer) => {
+                timer.wait();
+            }
+            None => sleep_us(POLL_INTERVAL_US),
+        }
+    }
+}
+
+/// Panic handler for the driver (only in non-test builds)
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    report::report_panic("audio", info)
+}
@@ -0,0 +1,174 @@
+//! PCM sample formats and conversion
+//!
+//! Clients write PCM in the format they chose: 8-bit unsigned or 16-bit
+//! signed little-endian samples, mono or stereo, at any rate the driver
+//! accepts. Cards play what their codec supports, so writes are converted
+//! to 16-bit stereo at the rate negotiated with the card. Rates are
+//! converted by repeating or dropping frames, which is cheap and good
+//! enough for system sounds.
+
+use alloc::vec::Vec;
+use kosh_types::DriverError;
+
+/// Rates clients may choose, the range AC'97 codecs with variable rate
+/// support cover
+pub const MIN_SAMPLE_RATE: u32 = 8_000;
+pub const MAX_SAMPLE_RATE: u32 = 48_000;
+
+/// The rate every card plays at
+pub const FIXED_SAMPLE_RATE: u32 = 48_000;
+
+/// Bytes of a format on the wire: rate as a little-endian u32, then
+/// channels and bits per sample
+pub const FORMAT_BYTES: usize = 6;
+
+/// Channels of the samples cards are given
+pub const DEVICE_CHANNELS: usize = 2;
+
+/// Longest tone the driver generates, in milliseconds
+pub const MAX_TONE_MS: u32 = 5_000;
+
+/// Amplitude of generated tones, a quarter of full scale
+pub const TONE_AMPLITUDE: i16 = i16::MAX / 4;
+
+/// How samples are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmFormat {
+    pub sample_rate: u32,
+    pub channels: u8,
+    /// 8 for unsigned bytes, 16 for signed little-endian words
+    pub bits: u8,
+}
+
+impl PcmFormat {
+    pub const fn new(sample_rate: u32, channels: u8, bits: u8) -> Self {
+        Self { sample_rate, channels, bits }
+    }
+
+    /// What cards play: 16-bit stereo at `sample_rate`
+    pub const fn device(sample_rate: u32) -> Self {
+        Self::new(sample_rate, DEVICE_CHANNELS as u8, 16)
+    }
+
+    /// Whether clients may write samples in this format
+    pub fn is_supported(&self) -> bool {
+        (MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&self.sample_rate)
+            && (1..=2).contains(&self.channels)
+            && matches!(self.bits, 8 | 16)
+    }
+
+    /// Bytes of one sample for every channel
+    pub fn frame_bytes(&self) -> usize {
+        self.channels as usize * (self.bits as usize / 8)
+    }
+
+    pub fn to_bytes(&self) -> [u8; FORMAT_BYTES] {
+        let rate = self.sample_rate.to_le_bytes();
+        [rate[0], rate[1], rate[2], rate[3], self.channels, self.bits]
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..FORMAT_BYTES)?;
+        let sample_rate = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        Some(Self::new(sample_rate, bytes[4], bytes[5]))
+    }
+}
+
+impl Default for PcmFormat {
+    fn default() -> Self {
+        Self::device(FIXED_SAMPLE_RATE)
+    }
+}
+
+/// Turns client samples into the 16-bit stereo a card plays
+///
+/// Writes may end part way through a frame, and rate conversion runs on
+/// across writes, so a stream converts the same however it is split.
+#[derive(Debug, Clone)]
+pub struct Converter {
+    from: PcmFormat,
+    to_rate: u32,
+    /// Bytes of a frame the last write ended in
+    partial: Vec<u8>,
+    /// Progress towards the next output frame, in units of the output rate
+    phase: u32,
+}
+
+impl Converter {
+    /// Convert samples in `from` to 16-bit stereo at `to_rate`
+    pub fn new(from: PcmFormat, to_rate: u32) -> Result<Self, DriverError> {
+        if !from.is_supported() || to_rate == 0 {
+            return Err(DriverError::InvalidRequest);
+        }
+        Ok(Self { from, to_rate, partial: Vec::new(), phase: 0 })
+    }
+
+    pub fn format(&self) -> PcmFormat {
+        self.from
+    }
+
+    /// Convert `bytes`, appending interleaved left and right samples to
+    /// `out`
+    pub fn convert(&mut self, bytes: &[u8], out: &mut Vec<i16>) {
+        let frame_bytes = self.from.frame_bytes();
+        let mut bytes = bytes;
+        if !self.partial.is_empty() {
+            let needed = (frame_bytes - self.partial.len()).min(bytes.len());
+            self.partial.extend_from_slice(&bytes[..needed]);
+            bytes = &bytes[needed..];
+            if self.partial.len() < frame_bytes {
+                return;
+            }
+            let frame = core::mem::take(&mut self.partial);
+            self.push_frame(&frame, out);
+        }
+        let mut frames = bytes.chunks_exact(frame_bytes);
+        for frame in &mut frames {
+            self.push_frame(frame, out);
+        }
+        self.partial.extend_from_slice(frames.remainder());
+    }
+
+    /// Emit `frame` as many times as the rates call for: each input frame
+    /// moves `to_rate` closer to the next output frame, and each output
+    /// frame is `from.sample_rate` away from the last
+    fn push_frame(&mut self, frame: &[u8], out: &mut Vec<i16>) {
+        let sample = |index: usize| match self.from.bits {
+            8 => (frame[index] as i16 - 0x80) << 8,
+            _ => i16::from_le_bytes([frame[index * 2], frame[index * 2 + 1]]),
+        };
+        let left = sample(0);
+        let right = if self.from.channels == 2 { sample(1) } else { left };
+        self.phase += self.to_rate;
+        while self.phase >= self.from.sample_rate {
+            self.phase -= self.from.sample_rate;
+            out.push(left);
+            out.push(right);
+        }
+    }
+
+    /// Forget a partial frame, as when the stream is stopped
+    pub fn reset(&mut self) {
+        self.partial.clear();
+        self.phase = 0;
+    }
+}
+
+/// A square wave of `frequency` Hz lasting `duration_ms`, as interleaved
+/// 16-bit stereo at `sample_rate`; a frequency of 0 gives silence
+pub fn square_wave(frequency: u32, duration_ms: u32, sample_rate: u32) -> Vec<i16> {
+    let frames = (sample_rate as u64 * duration_ms.min(MAX_TONE_MS) as u64 / 1000) as usize;
+    let mut samples = Vec::with_capacity(frames * DEVICE_CHANNELS);
+    for frame in 0..frames {
+        let value = if frequency == 0 {
+            0
+        } else if (frame as u64 * frequency as u64 * 2 / sample_rate as u64).is_multiple_of(2) {
+            TONE_AMPLITUDE
+        } else {
+            -TONE_AMPLITUDE
+        };
+        samples.push(value);
+        samples.push(value);
+    }
+    samples
+}
@@ -0,0 +1,173 @@
+use super::*;
+use crate::ac97::{BUFFER_COUNT, BUFFER_SAMPLES, PCM_OUT_0DB, VOLUME_MUTE};
+use crate::fixtures::FakeAc97;
+use crate::pcm::{FORMAT_BYTES, TONE_AMPLITUDE};
+
+/// `count` distinct stereo samples
+fn ramp(count: usize) -> Vec<i16> {
+    (0..count).map(|index| (index % 30_000) as i16).collect()
+}
+
+fn le_bytes(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|sample| sample.to_le_bytes()).collect()
+}
+
+#[test]
+fn test_pcm_conversion() {
+    let format = PcmFormat::new(22_050, 1, 8);
+    assert_eq!(PcmFormat::from_bytes(&format.to_bytes()), Some(format));
+    assert_eq!(PcmFormat::from_bytes(&format.to_bytes()[..FORMAT_BYTES - 1]), None);
+    assert_eq!(format.frame_bytes(), 1);
+    assert!(!PcmFormat::new(96_000, 2, 16).is_supported());
+    assert!(!PcmFormat::new(48_000, 3, 16).is_supported());
+    assert!(Converter::new(PcmFormat::new(48_000, 2, 24), 48_000).is_err());
+
+    // 8-bit unsigned mono: centred on 0x80 and copied to both sides
+    let mut converter = Converter::new(PcmFormat::new(48_000, 1, 8), 48_000).unwrap();
+    let mut out = Vec::new();
+    converter.convert(&[0x80, 0xFF, 0x00], &mut out);
+    assert_eq!(out, [0, 0, 0x7F00, 0x7F00, -0x8000, -0x8000]);
+
+    // Upsampling repeats frames, and a frame split across writes is kept
+    let mut converter = Converter::new(PcmFormat::new(8_000, 2, 16), 48_000).unwrap();
+    let bytes = le_bytes(&[1, -1, 2, -2]);
+    let mut out = Vec::new();
+    converter.convert(&bytes[..3], &mut out);
+    assert!(out.is_empty());
+    converter.convert(&bytes[3..], &mut out);
+    assert_eq!(out.len(), 2 * 6 * 2);
+    assert!(out[..12].chunks(2).all(|frame| frame == [1, -1]));
+    assert!(out[12..].chunks(2).all(|frame| frame == [2, -2]));
+
+    // Downsampling drops frames evenly however the stream is split
+    let input = le_bytes(&ramp(2 * 300));
+    let mut whole = Vec::new();
+    Converter::new(PcmFormat::new(48_000, 2, 16), 16_000).unwrap().convert(&input, &mut whole);
+    assert_eq!(whole.len(), 2 * 100);
+    let mut converter = Converter::new(PcmFormat::new(48_000, 2, 16), 16_000).unwrap();
+    let mut split = Vec::new();
+    for chunk in input.chunks(7) {
+        converter.convert(chunk, &mut split);
+    }
+    assert_eq!(split, whole);
+
+    // Tones alternate between half periods of plus and minus amplitude
+    let tone = square_wave(1_000, 10, 8_000);
+    assert_eq!(tone.len(), 2 * 80);
+    assert!(tone[..8].iter().all(|&sample| sample == TONE_AMPLITUDE));
+    assert!(tone[8..16].iter().all(|&sample| sample == -TONE_AMPLITUDE));
+    assert!(square_wave(0, 10, 8_000).iter().all(|&sample| sample == 0));
+}
+
+#[test]
+fn test_ac97_playback() {
+    let fake = FakeAc97::new(true);
+    let mut ac97 = fake.ac97();
+    assert!(ac97.variable_rate());
+    assert_eq!(ac97.sample_rate(), 48_000);
+    assert_eq!(fake.state.lock().pcm_out_volume, PCM_OUT_0DB);
+    assert_eq!(ac97.set_sample_rate(44_100), Ok(44_100));
+    assert_eq!(fake.state.lock().dac_rate, 44_100);
+    // Rates out of range are clamped to what codecs take
+    assert_eq!(ac97.set_sample_rate(100), Ok(8_000));
+    ac97.set_volume(0, 0);
+    assert_ne!(fake.state.lock().master_volume & VOLUME_MUTE, 0);
+
+    // Two and a half buffers are played in order, then the engine halts
+    let samples = ramp(BUFFER_SAMPLES * 5 / 2);
+    let mut taken = 0;
+    while taken < samples.len() {
+        taken += ac97.queue(&samples[taken..]);
+    }
+    assert_eq!(ac97.free_buffers(), BUFFER_COUNT - 1 - 3);
+    fake.play(BUFFER_SAMPLES / 2);
+    assert_eq!(ac97.reclaim(), 1);
+    fake.play(usize::MAX / 4);
+    assert!(!fake.running());
+    assert_eq!(ac97.reclaim(), 2);
+    assert!(ac97.is_idle());
+    assert_eq!(fake.take_played(), samples);
+
+    // Queueing again resumes the halted engine, and the ring wraps
+    for round in 0..3 {
+        let samples = ramp(BUFFER_SAMPLES * (BUFFER_COUNT - 1) - round);
+        let mut taken = 0;
+        while ac97.free_buffers() > 0 {
+            taken += ac97.queue(&samples[taken..]);
+        }
+        assert_eq!(ac97.queue(&samples[taken..]), 0);
+        assert!(fake.running());
+        fake.play(usize::MAX / 4);
+        ac97.reclaim();
+        assert!(ac97.is_idle());
+        assert_eq!(fake.take_played(), samples[..taken]);
+    }
+
+    // Codecs without variable rate audio stay at 48 kHz
+    let fixed = FakeAc97::new(false);
+    let mut ac97 = fixed.ac97();
+    assert_eq!(ac97.set_sample_rate(22_050), Ok(48_000));
+    assert_eq!(fixed.state.lock().dac_rate, 48_000);
+    drop(ac97);
+    assert_eq!(fixed.dma.live(), 0);
+}
+
+#[test]
+fn test_audio_driver_requests() {
+    let fake = FakeAc97::new(true);
+    let mut driver = AudioDriver::with_backend(Box::new(fake.ac97()));
+    driver.init(Vec::new()).unwrap();
+    assert_eq!(driver.get_status(), DriverStatus::Ready);
+
+    // The card is asked for the client's rate and plays it as is
+    let format = PcmFormat::new(16_000, 1, 16);
+    let response = driver.handle_request(DriverRequest::Control { command: 0x01, data: format.to_bytes().to_vec() });
+    let Ok(DriverResponse::Data(device)) = response else { panic!("unexpected response {:?}", response) };
+    assert_eq!(PcmFormat::from_bytes(&device), Some(PcmFormat::device(16_000)));
+    let Ok(DriverResponse::Data(formats)) = driver.handle_request(DriverRequest::Control { command: 0x02, data: Vec::new() })
+    else { panic!("no formats") };
+    assert_eq!(PcmFormat::from_bytes(&formats), Some(format));
+    assert_eq!(PcmFormat::from_bytes(&formats[FORMAT_BYTES..]), Some(PcmFormat::device(16_000)));
+
+    // Mono writes play on both sides
+    let mono = ramp(BUFFER_SAMPLES * 3);
+    driver.handle_request(DriverRequest::Write { offset: 0, data: le_bytes(&mono) }).unwrap();
+    while !driver.is_idle() {
+        fake.play(BUFFER_SAMPLES);
+        driver.poll();
+    }
+    let played = fake.take_played();
+    assert_eq!(played.len(), mono.len() * 2);
+    assert!(played.chunks(2).zip(&mono).all(|(frame, &sample)| frame == [sample, sample]));
+
+    // More than the driver holds is refused, and leaves the stream alone
+    let too_long = vec![0u8; (16_000 * MAX_PENDING_MS / 1000) as usize * 2 * 2];
+    let response = driver.handle_request(DriverRequest::Write { offset: 0, data: too_long });
+    assert!(matches!(response, Err(DriverError::ResourceBusy)));
+    assert!(driver.handle_request(DriverRequest::Write { offset: 0, data: vec![0x34] }).is_ok());
+
+    // Tones and stop
+    let tone = [0xE8, 0x03, 0x64, 0x00]; // 1000 Hz for 100 ms
+    driver.handle_request(DriverRequest::Control { command: 0x04, data: Vec::new() }).unwrap();
+    fake.take_played();
+    driver.handle_request(DriverRequest::Control { command: 0x03, data: tone.to_vec() }).unwrap();
+    while !driver.is_idle() {
+        fake.play(BUFFER_SAMPLES);
+        driver.poll();
+    }
+    assert_eq!(fake.take_played(), square_wave(1_000, 100, 16_000));
+    driver.handle_request(DriverRequest::Control { command: 0x03, data: tone.to_vec() }).unwrap();
+    driver.handle_request(DriverRequest::Control { command: 0x04, data: Vec::new() }).unwrap();
+    assert!(driver.is_idle());
+    assert!(!fake.running());
+
+    for (command, data) in [(0x01, PcmFormat::new(4_000, 2, 16).to_bytes().to_vec()), (0x03, vec![0xE8]), (0x05, vec![101, 0]), (0x42, Vec::new())] {
+        let response = driver.handle_request(DriverRequest::Control { command, data });
+        assert!(matches!(response, Err(DriverError::InvalidRequest)));
+    }
+
+    // Without a card nothing plays
+    let mut driver = AudioDriver::new();
+    assert!(matches!(driver.init(Vec::new()), Err(DriverError::HardwareNotFound)));
+    assert!(matches!(driver.write(&[0; 4]), Err(DriverError::HardwareNotFound)));
+}
@@ -0,0 +1,229 @@
+//! ANSI escape sequence parsing for the text console
+//!
+//! Covers what prompts and simple full-screen programs need: cursor
+//! movement and positioning, SGR colors, erasing the screen or a line,
+//! and saving and restoring the cursor. Sequences outside that subset are
+//! consumed without effect rather than printed.
+
+use alloc::vec::Vec;
+use crate::VgaColor;
+
+/// Most numeric parameters kept from one sequence; extra ones are ignored
+pub(crate) const MAX_PARAMS: usize = 16;
+
+/// What a byte of console output asks the console to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnsiAction {
+    /// Show a byte that is not part of a sequence
+    Print(u8),
+    /// `CUU`/`CUD`/`CUF`/`CUB`: move the cursor by rows and columns
+    MoveCursor { rows: isize, cols: isize },
+    /// `CUP`/`HVP`: move the cursor to a zero-based position
+    SetCursor { row: usize, col: usize },
+    /// `ED`
+    EraseDisplay(EraseMode),
+    /// `EL`
+    EraseLine(EraseMode),
+    /// `SGR`
+    SetGraphics(Vec<Graphics>),
+    /// `ESC 7` or `CSI s`
+    SaveCursor,
+    /// `ESC 8` or `CSI u`
+    RestoreCursor,
+}
+
+/// Part of the screen or line an erase covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EraseMode {
+    /// From the cursor to the end
+    ToEnd,
+    /// From the start up to and including the cursor
+    ToCursor,
+    All,
+    /// The whole screen and the scrollback (`ED` 3)
+    AllAndScrollback,
+}
+
+/// One change to the text attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Graphics {
+    /// Back to the console's default colors
+    Reset,
+    /// Bright foreground, as VGA has no bold face
+    Bold(bool),
+    Foreground(VgaColor),
+    Background(VgaColor),
+    DefaultForeground,
+    DefaultBackground,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    /// Inside `ESC [`, collecting parameters
+    Csi,
+}
+
+/// Byte-at-a-time parser, so sequences may be split across writes
+#[derive(Debug, Clone)]
+pub struct AnsiParser {
+    state: State,
+    params: Vec<u16>,
+    /// Parameter being read, if any digits were seen
+    current: Option<u16>,
+    /// `ESC [ ?` and similar private sequences are not supported
+    private: bool,
+}
+
+impl AnsiParser {
+    pub fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: Vec::new(),
+            current: None,
+            private: false,
+        }
+    }
+
+    /// Feed one byte, returning the action it completes, if any
+    pub fn feed(&mut self, byte: u8) -> Option<AnsiAction> {
+        match self.state {
+            State::Ground => match byte {
+                0x1b => {
+                    self.state = State::Escape;
+                    None
+                }
+                byte => Some(AnsiAction::Print(byte)),
+            },
+            State::Escape => {
+                self.state = State::Ground;
+                match byte {
+                    b'[' => {
+                        self.state = State::Csi;
+                        self.params.clear();
+                        self.current = None;
+                        self.private = false;
+                        None
+                    }
+                    b'7' => Some(AnsiAction::SaveCursor),
+                    b'8' => Some(AnsiAction::RestoreCursor),
+                    _ => None,
+                }
+            }
+            State::Csi => match byte {
+                b'0'..=b'9' => {
+                    let digit = (byte - b'0') as u16;
+                    self.current = Some(self.current.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                    None
+                }
+                b';' => {
+                    self.push_param();
+                    None
+                }
+                b'<'..=b'?' => {
+                    self.private = true;
+                    None
+                }
+                // Intermediate bytes
+                0x20..=0x2f => None,
+                0x40..=0x7e => {
+                    self.state = State::Ground;
+                    self.push_param();
+                    if self.private {
+                        return None;
+                    }
+                    self.csi_action(byte)
+                }
+                // Anything else aborts the sequence
+                _ => {
+                    self.state = State::Ground;
+                    None
+                }
+            },
+        }
+    }
+
+    fn push_param(&mut self) {
+        if self.params.len() < MAX_PARAMS {
+            self.params.push(self.current.unwrap_or(0));
+        }
+        self.current = None;
+    }
+
+    /// Parameter `index`, with 0 or a missing one meaning `default`
+    fn param(&self, index: usize, default: u16) -> u16 {
+        match self.params.get(index) {
+            Some(0) | None => default,
+            Some(&value) => value,
+        }
+    }
+
+    fn csi_action(&self, final_byte: u8) -> Option<AnsiAction> {
+        let count = self.param(0, 1) as isize;
+        let action = match final_byte {
+            b'A' => AnsiAction::MoveCursor { rows: -count, cols: 0 },
+            b'B' => AnsiAction::MoveCursor { rows: count, cols: 0 },
+            b'C' => AnsiAction::MoveCursor { rows: 0, cols: count },
+            b'D' => AnsiAction::MoveCursor { rows: 0, cols: -count },
+            b'H' | b'f' => AnsiAction::SetCursor {
+                row: self.param(0, 1) as usize - 1,
+                col: self.param(1, 1) as usize - 1,
+            },
+            b'J' => AnsiAction::EraseDisplay(erase_mode(self.params.first().copied().unwrap_or(0))?),
+            b'K' => AnsiAction::EraseLine(erase_mode(self.params.first().copied().unwrap_or(0))?),
+            b'm' => AnsiAction::SetGraphics(self.params.iter().filter_map(|&code| graphics(code)).collect()),
+            b's' => AnsiAction::SaveCursor,
+            b'u' => AnsiAction::RestoreCursor,
+            _ => return None,
+        };
+        Some(action)
+    }
+}
+
+fn erase_mode(code: u16) -> Option<EraseMode> {
+    match code {
+        0 => Some(EraseMode::ToEnd),
+        1 => Some(EraseMode::ToCursor),
+        2 => Some(EraseMode::All),
+        3 => Some(EraseMode::AllAndScrollback),
+        _ => None,
+    }
+}
+
+/// VGA colors in ANSI order: black, red, green, yellow, blue, magenta,
+/// cyan, white
+pub(crate) const COLORS: [VgaColor; 8] = [
+    VgaColor::Black, VgaColor::Red, VgaColor::Green, VgaColor::Brown,
+    VgaColor::Blue, VgaColor::Magenta, VgaColor::Cyan, VgaColor::LightGray,
+];
+
+/// The same in their bright variants
+const BRIGHT_COLORS: [VgaColor; 8] = [
+    VgaColor::DarkGray, VgaColor::LightRed, VgaColor::LightGreen, VgaColor::Yellow,
+    VgaColor::LightBlue, VgaColor::Pink, VgaColor::LightCyan, VgaColor::White,
+];
+
+fn graphics(code: u16) -> Option<Graphics> {
+    let color = |base: u16| (code - base) as usize;
+    match code {
+        0 => Some(Graphics::Reset),
+        1 => Some(Graphics::Bold(true)),
+        22 => Some(Graphics::Bold(false)),
+        30..=37 => Some(Graphics::Foreground(COLORS[color(30)])),
+        39 => Some(Graphics::DefaultForeground),
+        40..=47 => Some(Graphics::Background(COLORS[color(40)])),
+        49 => Some(Graphics::DefaultBackground),
+        90..=97 => Some(Graphics::Foreground(BRIGHT_COLORS[color(90)])),
+        100..=107 => Some(Graphics::Background(BRIGHT_COLORS[color(100)])),
+        _ => None,
+    }
+}
+
+/// Bright variant of a dark color, for bold text
+pub fn brighten(color: VgaColor) -> VgaColor {
+    match COLORS.iter().position(|&dark| dark == color) {
+        Some(index) => BRIGHT_COLORS[index],
+        None => color,
+    }
+}
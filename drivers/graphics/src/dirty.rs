@@ -0,0 +1,128 @@
+//! Dirty region tracking
+//!
+//! Console writes change cells in memory and note where; a flush then draws
+//! only the noted cells. The region is a short list of rectangles of cells.
+//! Cells written one after another along a line grow one rectangle, and
+//! past `MAX_DIRTY_RECTS` the list becomes the one rectangle around them
+//! all, which may draw more than changed but never less.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Most rectangles kept before they are merged into one
+pub const MAX_DIRTY_RECTS: usize = 8;
+
+/// A rectangle of cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellRect {
+    pub row: usize,
+    pub col: usize,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl CellRect {
+    pub const fn new(row: usize, col: usize, rows: usize, cols: usize) -> Self {
+        Self { row, col, rows, cols }
+    }
+
+    /// The single cell at `row`, `col`
+    pub const fn cell(row: usize, col: usize) -> Self {
+        Self::new(row, col, 1, 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0 || self.cols == 0
+    }
+
+    pub fn row_range(&self) -> Range<usize> {
+        self.row..self.row + self.rows
+    }
+
+    pub fn col_range(&self) -> Range<usize> {
+        self.col..self.col + self.cols
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.rows * self.cols
+    }
+
+    /// Whether `other` lies wholly inside; an empty rectangle lies inside
+    /// any other
+    pub fn contains(&self, other: &CellRect) -> bool {
+        other.is_empty()
+            || (other.row >= self.row && other.row + other.rows <= self.row + self.rows
+                && other.col >= self.col && other.col + other.cols <= self.col + self.cols)
+    }
+
+    /// The smallest rectangle covering both
+    pub fn union(&self, other: &CellRect) -> CellRect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let row = self.row.min(other.row);
+        let col = self.col.min(other.col);
+        let bottom = (self.row + self.rows).max(other.row + other.rows);
+        let right = (self.col + self.cols).max(other.col + other.cols);
+        CellRect::new(row, col, bottom - row, right - col)
+    }
+
+    /// Whether the union of the two covers no cell outside them: they span
+    /// the same rows and touch or overlap side by side, or the same columns
+    /// one above the other
+    fn joins(&self, other: &CellRect) -> bool {
+        let touch = |start: usize, length: usize, other_start: usize, other_length: usize| {
+            start <= other_start + other_length && other_start <= start + length
+        };
+        (self.row == other.row && self.rows == other.rows && touch(self.col, self.cols, other.col, other.cols))
+            || (self.col == other.col && self.cols == other.cols && touch(self.row, self.rows, other.row, other.rows))
+    }
+}
+
+/// The cells to draw at the next flush
+#[derive(Debug, Clone, Default)]
+pub struct DirtyRegion {
+    rects: Vec<CellRect>,
+}
+
+impl DirtyRegion {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn rects(&self) -> &[CellRect] {
+        &self.rects
+    }
+
+    /// Note that the cells of `rect` changed
+    pub fn add(&mut self, rect: CellRect) {
+        if rect.is_empty() || self.rects.iter().any(|dirty| dirty.contains(&rect)) {
+            return;
+        }
+        self.rects.retain(|dirty| !rect.contains(dirty));
+        let mut rect = rect;
+        // Grow a rectangle this one extends, as the next cell of a line
+        // extends the cells before it
+        while let Some(index) = self.rects.iter().position(|dirty| dirty.joins(&rect)) {
+            rect = rect.union(&self.rects.swap_remove(index));
+        }
+        self.rects.push(rect);
+        if self.rects.len() > MAX_DIRTY_RECTS {
+            let bounds = self.rects.iter().fold(CellRect::new(0, 0, 0, 0), |bounds, dirty| bounds.union(dirty));
+            self.rects.clear();
+            self.rects.push(bounds);
+        }
+    }
+
+    /// The rectangles noted since the last call
+    pub fn take(&mut self) -> Vec<CellRect> {
+        core::mem::take(&mut self.rects)
+    }
+}
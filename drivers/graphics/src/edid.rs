@@ -0,0 +1,191 @@
+//! EDID parsing
+//!
+//! A monitor describes itself in a 128-byte EDID base block: who made it,
+//! the resolutions it can show and, in the first detailed timing, the
+//! native one it looks best at. Only the base block is read; extension
+//! blocks such as CEA-861 are skipped.
+//!
+//! The boot loader hands no EDID on, so it comes from the display adapter:
+//! QEMU's standard VGA exposes it in its MMIO BAR. virtio-gpu's GET_EDID
+//! command would give one too, once there is a virtio-gpu driver.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use kosh_types::DriverError;
+
+/// Bytes in the EDID base block
+pub const EDID_BLOCK_LEN: usize = 128;
+
+const HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+
+/// Where the parts of the base block start
+const MANUFACTURER: usize = 0x08;
+const PRODUCT: usize = 0x0A;
+const SERIAL: usize = 0x0C;
+const VERSION: usize = 0x12;
+const ESTABLISHED_TIMINGS: usize = 0x23;
+const STANDARD_TIMINGS: usize = 0x26;
+const DESCRIPTORS: usize = 0x36;
+
+const STANDARD_TIMING_COUNT: usize = 8;
+const DESCRIPTOR_COUNT: usize = 4;
+const DESCRIPTOR_LEN: usize = 18;
+
+/// Display descriptor tag of the monitor name
+const DESCRIPTOR_NAME: u8 = 0xFC;
+
+/// Established timings, in bit order from bit 7 of the first byte; the
+/// interlaced 1024x768 is left out
+const ESTABLISHED: [Option<(u16, u16, u8)>; 17] = [
+    Some((720, 400, 70)), Some((720, 400, 88)), Some((640, 480, 60)), Some((640, 480, 67)),
+    Some((640, 480, 72)), Some((640, 480, 75)), Some((800, 600, 56)), Some((800, 600, 60)),
+    Some((800, 600, 72)), Some((800, 600, 75)), Some((832, 624, 75)), None,
+    Some((1024, 768, 60)), Some((1024, 768, 70)), Some((1024, 768, 75)), Some((1280, 1024, 75)),
+    Some((1152, 870, 75)),
+];
+
+/// A resolution the monitor can show, and how often it refreshes there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u16,
+    pub height: u16,
+    pub refresh_hz: u8,
+}
+
+/// What a monitor's EDID says about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edid {
+    /// Three-letter PNP ID of the maker
+    pub manufacturer: [u8; 3],
+    pub product: u16,
+    pub serial: u32,
+    /// EDID version and revision, as 1 and 4 for EDID 1.4
+    pub version: (u8, u8),
+    /// Monitor name, if a display descriptor gives one
+    pub name: Option<String>,
+    /// The native resolution, from the first detailed timing
+    pub preferred: Option<Resolution>,
+    /// Every resolution listed, detailed timings first
+    pub resolutions: Vec<Resolution>,
+}
+
+impl Edid {
+    /// Parse an EDID base block
+    ///
+    /// Blocks with a bad header or checksum are refused: adapters without a
+    /// monitor attached read back all ones or all zeros.
+    pub fn parse(block: &[u8]) -> Result<Self, DriverError> {
+        if block.len() < EDID_BLOCK_LEN || block[..8] != HEADER {
+            return Err(DriverError::InvalidRequest);
+        }
+        let block = &block[..EDID_BLOCK_LEN];
+        if block.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+            return Err(DriverError::InvalidRequest);
+        }
+
+        // Three five-bit letters, big-endian, 1 being 'A'
+        let packed = u16::from_be_bytes([block[MANUFACTURER], block[MANUFACTURER + 1]]);
+        let letter = |shift: u16| b'@' + ((packed >> shift) & 0x1F) as u8;
+        let version = (block[VERSION], block[VERSION + 1]);
+
+        let mut edid = Self {
+            manufacturer: [letter(10), letter(5), letter(0)],
+            product: u16::from_le_bytes([block[PRODUCT], block[PRODUCT + 1]]),
+            serial: u32::from_le_bytes([block[SERIAL], block[SERIAL + 1], block[SERIAL + 2], block[SERIAL + 3]]),
+            version,
+            name: None,
+            preferred: None,
+            resolutions: Vec::new(),
+        };
+
+        for descriptor in block[DESCRIPTORS..].chunks_exact(DESCRIPTOR_LEN).take(DESCRIPTOR_COUNT) {
+            match detailed_timing(descriptor) {
+                Some(resolution) => {
+                    edid.preferred.get_or_insert(resolution);
+                    edid.add(resolution);
+                }
+                None if descriptor[3] == DESCRIPTOR_NAME => edid.name = Some(descriptor_text(&descriptor[5..])),
+                None => {}
+            }
+        }
+        for entry in block[STANDARD_TIMINGS..].chunks_exact(2).take(STANDARD_TIMING_COUNT) {
+            if let Some(resolution) = standard_timing(entry[0], entry[1], version) {
+                edid.add(resolution);
+            }
+        }
+        let established = u32::from_be_bytes([0, block[ESTABLISHED_TIMINGS], block[ESTABLISHED_TIMINGS + 1], block[ESTABLISHED_TIMINGS + 2]]);
+        for (bit, timing) in ESTABLISHED.iter().enumerate() {
+            if let Some((width, height, refresh_hz)) = *timing {
+                if established & (1 << (23 - bit)) != 0 {
+                    edid.add(Resolution { width, height, refresh_hz });
+                }
+            }
+        }
+        Ok(edid)
+    }
+
+    /// Manufacturer ID as text, such as "DEL"
+    pub fn manufacturer(&self) -> &str {
+        core::str::from_utf8(&self.manufacturer).unwrap_or("???")
+    }
+
+    /// Whether the monitor lists `width` by `height` at any refresh rate
+    pub fn supports(&self, width: u16, height: u16) -> bool {
+        self.resolutions.iter().any(|resolution| (resolution.width, resolution.height) == (width, height))
+    }
+
+    fn add(&mut self, resolution: Resolution) {
+        if !self.resolutions.contains(&resolution) {
+            self.resolutions.push(resolution);
+        }
+    }
+}
+
+/// The resolution of an 18-byte detailed timing descriptor, None if it is
+/// a display descriptor instead
+fn detailed_timing(descriptor: &[u8]) -> Option<Resolution> {
+    let pixel_clock = u16::from_le_bytes([descriptor[0], descriptor[1]]) as u64 * 10_000;
+    if pixel_clock == 0 {
+        return None;
+    }
+    // The upper nibbles of bytes 4 and 7 extend the active sizes, the
+    // lower ones the blanking
+    let width = descriptor[2] as u16 | (descriptor[4] as u16 & 0xF0) << 4;
+    let h_blank = descriptor[3] as u16 | (descriptor[4] as u16 & 0x0F) << 8;
+    let height = descriptor[5] as u16 | (descriptor[7] as u16 & 0xF0) << 4;
+    let v_blank = descriptor[6] as u16 | (descriptor[7] as u16 & 0x0F) << 8;
+    let total = (width + h_blank) as u64 * (height + v_blank) as u64;
+    if width == 0 || height == 0 || total == 0 {
+        return None;
+    }
+    let refresh_hz = ((pixel_clock + total / 2) / total).min(u8::MAX as u64) as u8;
+    Some(Resolution { width, height, refresh_hz })
+}
+
+/// A two-byte standard timing, None for an unused slot
+fn standard_timing(first: u8, second: u8, version: (u8, u8)) -> Option<Resolution> {
+    if first <= 0x01 {
+        return None;
+    }
+    let width = (first as u16 + 31) * 8;
+    let height = match second >> 6 {
+        // 16:10 since EDID 1.3, square before
+        0 if version < (1, 3) => width,
+        0 => width * 10 / 16,
+        1 => width * 3 / 4,
+        2 => width * 4 / 5,
+        _ => width * 9 / 16,
+    };
+    Some(Resolution { width, height, refresh_hz: (second & 0x3F) + 60 })
+}
+
+/// Text of a display descriptor: up to 13 bytes, ended by a newline
+fn descriptor_text(bytes: &[u8]) -> String {
+    bytes.iter()
+        .take(13)
+        .take_while(|&&byte| byte != b'\n')
+        .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '?' })
+        .collect::<String>()
+        .trim_end()
+        .into()
+}
@@ -0,0 +1,272 @@
+//! PSF bitmap fonts
+//!
+//! Fonts in the PC Screen Font format the Linux console uses, versions 1
+//! and 2, with their Unicode tables. A character a font has no glyph for is
+//! drawn as U+FFFD, or as '?' in fonts without that.
+//!
+//! The font built into the driver is 8x16 and covers code page 437 and
+//! Latin-1, so the console has text to draw in graphics modes even when
+//! the VGA font cannot be read, as on displays the boot loader left in a
+//! graphics mode.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use kosh_types::DriverError;
+use crate::cp437::CP437;
+use crate::framebuffer::{Font, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+/// The built-in font, glyphs in code page 437 order then the rest of
+/// Latin-1
+pub static BUILTIN_FONT: &[u8] = include_bytes!("../fonts/kosh-8x16.psf");
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_HEADER_SIZE: usize = 4;
+/// Mode bits: 512 glyphs rather than 256, and a Unicode table
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_TABLE: u8 = 0x06;
+/// Unicode table entries ending a glyph's list and starting a sequence
+const PSF1_SEPARATOR: u16 = 0xFFFF;
+const PSF1_SEQUENCE: u16 = 0xFFFE;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_FLAG_TABLE: usize = 0x01;
+const PSF2_SEPARATOR: u8 = 0xFF;
+const PSF2_SEQUENCE: u8 = 0xFE;
+
+/// Widest and tallest glyph accepted, in pixels
+pub const MAX_GLYPH_SIZE: usize = 64;
+
+/// Most glyphs a font may have
+pub const MAX_GLYPHS: usize = 65536;
+
+/// Glyphs a terminal keeps drawn, enough for a screen of text in a few
+/// colors
+pub const GLYPH_CACHE_SIZE: usize = 512;
+
+/// A bitmap font
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PsfFont {
+    width: usize,
+    height: usize,
+    /// Bytes of a glyph line, leftmost pixel in the top bit of the first
+    line_bytes: usize,
+    glyph_count: usize,
+    glyphs: Vec<u8>,
+    /// Glyph of each character the Unicode table lists; empty if the font
+    /// has no table, and then glyphs go by character code
+    unicode: BTreeMap<char, usize>,
+    /// Glyph drawn for characters the font lacks
+    fallback: usize,
+}
+
+impl PsfFont {
+    /// Read a PSF font
+    pub fn parse(bytes: &[u8]) -> Result<Self, DriverError> {
+        if bytes.starts_with(&PSF2_MAGIC) {
+            Self::parse_psf2(bytes)
+        } else if bytes.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(bytes)
+        } else {
+            Err(DriverError::InvalidRequest)
+        }
+    }
+
+    /// The font built into the driver
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN_FONT).expect("the built-in font is a valid PSF font")
+    }
+
+    fn parse_psf1(bytes: &[u8]) -> Result<Self, DriverError> {
+        let mode = *bytes.get(2).ok_or(DriverError::InvalidRequest)?;
+        let height = *bytes.get(3).ok_or(DriverError::InvalidRequest)? as usize;
+        let glyph_count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+        let end = PSF1_HEADER_SIZE + glyph_count * height;
+        let glyphs = bytes.get(PSF1_HEADER_SIZE..end).ok_or(DriverError::InvalidRequest)?;
+
+        let mut unicode = BTreeMap::new();
+        if mode & PSF1_MODE_TABLE != 0 {
+            let entries = bytes[end..].chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+            let (mut glyph, mut sequence) = (0, false);
+            for entry in entries {
+                match entry {
+                    PSF1_SEPARATOR => {
+                        glyph += 1;
+                        sequence = false;
+                    }
+                    PSF1_SEQUENCE => sequence = true,
+                    // Sequences of combining characters are not drawn
+                    _ if sequence => {}
+                    code => {
+                        if let Some(character) = char::from_u32(code as u32) {
+                            unicode.entry(character).or_insert(glyph);
+                        }
+                    }
+                }
+                if glyph >= glyph_count {
+                    break;
+                }
+            }
+        }
+        Self::new(8, height, glyph_count, glyphs.to_vec(), unicode)
+    }
+
+    fn parse_psf2(bytes: &[u8]) -> Result<Self, DriverError> {
+        let field = |index: usize| {
+            bytes.get(index * 4..index * 4 + 4)
+                .map(|field| u32::from_le_bytes([field[0], field[1], field[2], field[3]]) as usize)
+                .ok_or(DriverError::InvalidRequest)
+        };
+        let header_size = field(2)?;
+        let flags = field(3)?;
+        let glyph_count = field(4)?;
+        let glyph_size = field(5)?;
+        let height = field(6)?;
+        let width = field(7)?;
+        let sizes_valid = (1..=MAX_GLYPH_SIZE).contains(&width) && (1..=MAX_GLYPH_SIZE).contains(&height);
+        if glyph_count > MAX_GLYPHS || !sizes_valid || glyph_size != width.div_ceil(8) * height {
+            return Err(DriverError::InvalidRequest);
+        }
+        let end = header_size + glyph_count * glyph_size;
+        let glyphs = bytes.get(header_size..end).ok_or(DriverError::InvalidRequest)?;
+
+        let mut unicode = BTreeMap::new();
+        if flags & PSF2_FLAG_TABLE != 0 {
+            for (glyph, entry) in bytes[end..].split(|&byte| byte == PSF2_SEPARATOR).take(glyph_count).enumerate() {
+                // Characters come first, then any sequences
+                let characters = entry.split(|&byte| byte == PSF2_SEQUENCE).next().unwrap_or(&[]);
+                for character in core::str::from_utf8(characters).unwrap_or("").chars() {
+                    unicode.entry(character).or_insert(glyph);
+                }
+            }
+        }
+        Self::new(width, height, glyph_count, glyphs.to_vec(), unicode)
+    }
+
+    fn new(width: usize, height: usize, glyph_count: usize, glyphs: Vec<u8>, unicode: BTreeMap<char, usize>) -> Result<Self, DriverError> {
+        if glyph_count == 0 || height == 0 || height > MAX_GLYPH_SIZE {
+            return Err(DriverError::InvalidRequest);
+        }
+        let mut font = Self { width, height, line_bytes: width.div_ceil(8), glyph_count, glyphs, unicode, fallback: 0 };
+        font.fallback = font.glyph_index('\u{FFFD}').or_else(|| font.glyph_index('?')).unwrap_or(0);
+        Ok(font)
+    }
+
+    /// Size of a glyph in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn glyph_count(&self) -> usize {
+        self.glyph_count
+    }
+
+    /// The glyph for `character`, None if the font has none
+    pub fn glyph_index(&self, character: char) -> Option<usize> {
+        if self.unicode.is_empty() {
+            return Some(character as usize).filter(|&glyph| glyph < self.glyph_count);
+        }
+        self.unicode.get(&character).copied()
+    }
+
+    /// The glyph `character` is drawn with
+    pub fn lookup(&self, character: char) -> usize {
+        self.glyph_index(character).unwrap_or(self.fallback)
+    }
+
+    /// Whether pixel `x`, `y` of `glyph` is set
+    pub fn is_set(&self, glyph: usize, x: usize, y: usize) -> bool {
+        let line = (glyph * self.height + y) * self.line_bytes;
+        self.glyphs[line + x / 8] & (0x80 >> (x % 8)) != 0
+    }
+
+    /// `glyph` in `style`, line after line
+    ///
+    /// Bold glyphs are drawn heavier by setting each pixel right of a set
+    /// one as well.
+    pub fn render(&self, glyph: usize, style: GlyphStyle) -> Vec<u32> {
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let set = self.is_set(glyph, x, y) || (style.bold && x > 0 && self.is_set(glyph, x - 1, y));
+                pixels.push(if set { style.foreground } else { style.background });
+            }
+        }
+        pixels
+    }
+
+    /// The font laid out in code page 437 order, as console cells are, for
+    /// drawing the console in graphics modes; None unless its glyphs are
+    /// the 8x16 the console uses
+    pub fn to_vga_font(&self) -> Option<Font> {
+        if self.width != GLYPH_WIDTH || self.height != GLYPH_HEIGHT {
+            return None;
+        }
+        let mut glyphs = Vec::with_capacity(CP437.len() * GLYPH_HEIGHT);
+        for character in CP437 {
+            // Cells holding 0 are blank
+            let glyph = self.lookup(if character == '\0' { ' ' } else { character });
+            let start = glyph * GLYPH_HEIGHT;
+            glyphs.extend_from_slice(&self.glyphs[start..start + GLYPH_HEIGHT]);
+        }
+        Font::from_bytes(&glyphs).ok()
+    }
+}
+
+/// Colors and weight a glyph is drawn in; colors are pixel values as the
+/// canvas stores them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GlyphStyle {
+    pub foreground: u32,
+    pub background: u32,
+    pub bold: bool,
+}
+
+/// Glyphs already drawn in their style, so text that repeats costs a copy
+/// per line rather than a bit test per pixel
+///
+/// When full the glyph drawn longest ago makes way. A cache holds glyphs of
+/// one font.
+pub struct GlyphCache {
+    capacity: usize,
+    glyphs: BTreeMap<(usize, GlyphStyle), Vec<u32>>,
+    /// Keys oldest first
+    order: VecDeque<(usize, GlyphStyle)>,
+}
+
+impl GlyphCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), glyphs: BTreeMap::new(), order: VecDeque::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.glyphs.clear();
+        self.order.clear();
+    }
+
+    /// The pixels of `glyph` of `font` in `style`, line after line
+    pub fn get(&mut self, font: &PsfFont, glyph: usize, style: GlyphStyle) -> &[u32] {
+        let key = (glyph, style);
+        if !self.glyphs.contains_key(&key) {
+            if self.order.len() >= self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.glyphs.remove(&oldest);
+                }
+            }
+            self.glyphs.insert(key, font.render(glyph, style));
+            self.order.push_back(key);
+        }
+        &self.glyphs[&key]
+    }
+}
@@ -0,0 +1,232 @@
+//! Drawing console cells into a linear framebuffer
+//!
+//! In graphics modes each cell is an 8x16 glyph drawn in its foreground
+//! color over its background. The glyphs are the ones VGA text mode shows,
+//! read from plane 2 of VGA memory before a graphics mode overwrites them
+//! and written back on the way to text mode again, or those of the built-in
+//! font where they cannot be read.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use kosh_driver::hal::{Mmio, PortIo};
+use kosh_types::DriverError;
+use crate::VgaChar;
+
+/// Size of a glyph, and so of a cell, in pixels
+pub const GLYPH_WIDTH: usize = 8;
+pub const GLYPH_HEIGHT: usize = 16;
+
+const GLYPH_COUNT: usize = 256;
+
+/// Bytes of a font: one byte per glyph line, leftmost pixel in bit 7
+pub const FONT_SIZE: usize = GLYPH_COUNT * GLYPH_HEIGHT;
+
+/// Bytes between glyphs in plane 2, which has room for 32 lines each
+const VGA_GLYPH_STRIDE: usize = 32;
+
+/// Physical address and size of the VGA memory window plane 2 is read
+/// through
+pub const VGA_MEMORY_ADDRESS: u64 = 0xA0000;
+pub const VGA_MEMORY_SIZE: usize = 0x10000;
+
+/// Sequencer and graphics controller index/data port pairs
+const SEQUENCER: u16 = 0x3C4;
+const GRAPHICS_CONTROLLER: u16 = 0x3CE;
+
+/// Register settings, index in the low byte and value in the high one,
+/// that put plane 2 alone at 0xA0000 with plain addressing
+const PLANE_2_ACCESS: [(u16, u16); 5] = [
+    (SEQUENCER, 0x0402),           // map mask: write plane 2
+    (SEQUENCER, 0x0704),           // memory mode: sequential, no odd/even
+    (GRAPHICS_CONTROLLER, 0x0204), // read map select: plane 2
+    (GRAPHICS_CONTROLLER, 0x0005), // graphics mode: no odd/even
+    (GRAPHICS_CONTROLLER, 0x0406), // miscellaneous: map at 0xA0000, 64 KiB
+];
+
+/// The text mode settings of the same registers, restored afterwards
+const TEXT_MODE_ACCESS: [(u16, u16); 5] = [
+    (SEQUENCER, 0x0302),
+    (SEQUENCER, 0x0304),
+    (GRAPHICS_CONTROLLER, 0x0004),
+    (GRAPHICS_CONTROLLER, 0x1005),
+    (GRAPHICS_CONTROLLER, 0x0E06),
+];
+
+/// The 16 text mode colors as red, green and blue
+pub const VGA_PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00), (0x00, 0x00, 0xAA), (0x00, 0xAA, 0x00), (0x00, 0xAA, 0xAA),
+    (0xAA, 0x00, 0x00), (0xAA, 0x00, 0xAA), (0xAA, 0x55, 0x00), (0xAA, 0xAA, 0xAA),
+    (0x55, 0x55, 0x55), (0x55, 0x55, 0xFF), (0x55, 0xFF, 0x55), (0x55, 0xFF, 0xFF),
+    (0xFF, 0x55, 0x55), (0xFF, 0x55, 0xFF), (0xFF, 0xFF, 0x55), (0xFF, 0xFF, 0xFF),
+];
+
+/// How a color is stored in a pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelFormat {
+    pub bpp: u8,
+    /// Lowest bit and width of each color; widths of 0 mean the pixel
+    /// indexes the palette instead
+    pub red: (u8, u8),
+    pub green: (u8, u8),
+    pub blue: (u8, u8),
+}
+
+impl PixelFormat {
+    /// The format VBE and the Bochs DISPI interface use at `bpp`, None for
+    /// depths the console cannot draw in
+    pub fn direct(bpp: u8) -> Option<Self> {
+        let (red, green, blue) = match bpp {
+            8 => ((0, 0), (0, 0), (0, 0)),
+            15 => ((10, 5), (5, 5), (0, 5)),
+            16 => ((11, 5), (5, 6), (0, 5)),
+            24 | 32 => ((16, 8), (8, 8), (0, 8)),
+            _ => return None,
+        };
+        Some(Self { bpp, red, green, blue })
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        (self.bpp as usize).div_ceil(8)
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.red.1 == 0
+    }
+
+    /// Pixel value of text mode color `color`
+    ///
+    /// Indexed formats use the color as the palette index, so the first
+    /// 16 palette entries must hold `VGA_PALETTE`.
+    pub fn encode(&self, color: u8) -> u32 {
+        let color = color & 0x0F;
+        if self.is_indexed() {
+            return color as u32;
+        }
+        self.encode_rgb(vga_rgb(color))
+    }
+
+    /// Pixel value of `rgb`, 0x00RRGGBB
+    ///
+    /// Indexed formats get the nearest text mode color.
+    pub fn encode_rgb(&self, rgb: u32) -> u32 {
+        if self.is_indexed() {
+            return nearest_vga_color(rgb) as u32;
+        }
+        let scale = |value: u32, (position, size): (u8, u8)| {
+            ((value & 0xFF) * ((1u32 << size) - 1) / 0xFF) << position
+        };
+        scale(rgb >> 16, self.red) | scale(rgb >> 8, self.green) | scale(rgb, self.blue)
+    }
+}
+
+/// Text mode color `color` as 0x00RRGGBB
+pub const fn vga_rgb(color: u8) -> u32 {
+    let (red, green, blue) = VGA_PALETTE[(color & 0x0F) as usize];
+    (red as u32) << 16 | (green as u32) << 8 | blue as u32
+}
+
+/// The text mode color closest to `rgb`
+fn nearest_vga_color(rgb: u32) -> u8 {
+    let distance = |color: u8| {
+        let other = vga_rgb(color);
+        (0..3).map(|channel| {
+            let shift = channel * 8;
+            let difference = ((rgb >> shift) & 0xFF) as i32 - ((other >> shift) & 0xFF) as i32;
+            (difference * difference) as u32
+        }).sum::<u32>()
+    };
+    (0..16).min_by_key(|&color| distance(color)).unwrap_or(0)
+}
+
+/// Where the pixels of a graphics mode go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramebufferLayout {
+    /// Bytes from the start of one line to the next
+    pub pitch: usize,
+    pub format: PixelFormat,
+}
+
+/// 256 glyphs of 8x16 pixels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Font {
+    glyphs: Vec<u8>,
+}
+
+impl Font {
+    /// A font of `FONT_SIZE` bytes, glyph after glyph
+    pub fn from_bytes(glyphs: &[u8]) -> Result<Self, DriverError> {
+        if glyphs.len() != FONT_SIZE {
+            return Err(DriverError::InvalidRequest);
+        }
+        Ok(Self { glyphs: glyphs.to_vec() })
+    }
+
+    /// Lines of the glyph for `character`, top first
+    pub fn glyph(&self, character: u8) -> &[u8] {
+        let start = character as usize * GLYPH_HEIGHT;
+        &self.glyphs[start..start + GLYPH_HEIGHT]
+    }
+
+    /// Read the font text mode shows from plane 2
+    ///
+    /// `memory` is the VGA memory window at `VGA_MEMORY_ADDRESS`. Only
+    /// valid while the adapter is in VGA text mode.
+    pub fn read_vga(io: &mut dyn PortIo, memory: &mut dyn Mmio) -> Self {
+        let mut glyphs = vec![0; FONT_SIZE];
+        with_plane_2(io, || {
+            for (index, line) in glyphs.iter_mut().enumerate() {
+                *line = memory.read_u8(index / GLYPH_HEIGHT * VGA_GLYPH_STRIDE + index % GLYPH_HEIGHT);
+            }
+        });
+        Self { glyphs }
+    }
+
+    /// Load the font back into plane 2 for text mode to show
+    pub fn write_vga(&self, io: &mut dyn PortIo, memory: &mut dyn Mmio) {
+        with_plane_2(io, || {
+            for (index, &line) in self.glyphs.iter().enumerate() {
+                memory.write_u8(index / GLYPH_HEIGHT * VGA_GLYPH_STRIDE + index % GLYPH_HEIGHT, line);
+            }
+        });
+    }
+}
+
+/// Run `access` with plane 2 mapped alone, then restore text mode
+fn with_plane_2(io: &mut dyn PortIo, access: impl FnOnce()) {
+    for (port, setting) in PLANE_2_ACCESS {
+        io.write_u16(port, setting);
+    }
+    access();
+    for (port, setting) in TEXT_MODE_ACCESS {
+        io.write_u16(port, setting);
+    }
+}
+
+/// Draw `cell` at `row`, `col` of the console
+pub fn draw_cell(framebuffer: &mut dyn Mmio, layout: &FramebufferLayout, font: &Font, row: usize, col: usize, cell: VgaChar) {
+    let attribute = cell.color_code.0;
+    let foreground = layout.format.encode(attribute & 0x0F);
+    let background = layout.format.encode(attribute >> 4);
+    let bytes = layout.format.bytes_per_pixel();
+    let left = col * GLYPH_WIDTH * bytes;
+    for (line, &bits) in font.glyph(cell.ascii_character).iter().enumerate() {
+        let start = (row * GLYPH_HEIGHT + line) * layout.pitch + left;
+        for x in 0..GLYPH_WIDTH {
+            let pixel = if bits & (0x80 >> x) != 0 { foreground } else { background };
+            write_pixel(framebuffer, start + x * bytes, bytes, pixel);
+        }
+    }
+}
+
+pub(crate) fn write_pixel(framebuffer: &mut dyn Mmio, offset: usize, bytes: usize, pixel: u32) {
+    match bytes {
+        1 => framebuffer.write_u8(offset, pixel as u8),
+        2 => framebuffer.write_u16(offset, pixel as u16),
+        3 => {
+            for (index, byte) in pixel.to_le_bytes()[..3].iter().enumerate() {
+                framebuffer.write_u8(offset + index, *byte);
+            }
+        }
+        _ => framebuffer.write_u32(offset, pixel),
+    }
+}
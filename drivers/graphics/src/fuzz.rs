@@ -0,0 +1,20 @@
+//! Fuzz entry point for the console's ANSI parser
+//!
+//! Any process that writes to the console feeds the parser, one byte at a
+//! time, so it sees arbitrary input split at arbitrary points.
+
+use crate::ansi::{AnsiAction, AnsiParser, MAX_PARAMS};
+
+/// Feed `data` to a fresh parser, checking the actions it produces
+pub fn ansi(data: &[u8]) {
+    let mut parser = AnsiParser::new();
+    for &byte in data {
+        match parser.feed(byte) {
+            Some(AnsiAction::Print(printed)) => assert_eq!(printed, byte, "printed a byte other than the one fed"),
+            Some(AnsiAction::SetGraphics(changes)) => {
+                assert!(changes.len() <= MAX_PARAMS, "more SGR changes than parameters kept")
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,15 +1,37 @@
-#![no_std]
+#![cfg_attr(not(feature = "fuzzing"), no_std)]
 
 extern crate alloc;
 
-use alloc::{vec, vec::Vec, string::String, boxed::Box};
+use alloc::{vec, vec::Vec, string::String, boxed::Box, collections::VecDeque};
 use kosh_driver::{
     KoshDriver, DriverInfo, DriverType, HardwareId, DriverStatus, PowerEvent,
     DriverRequest, DriverResponse, DriverCapabilityType
 };
 use kosh_types::{DriverError, Capability};
+use kosh_ipc::SharedBuffer;
+use kosh_ipc::shm::SharedRegion;
 use volatile::Volatile;
-use spin::Mutex;
+use kosh_sync::Mutex;
+
+pub mod ansi;
+pub mod cp437;
+pub mod dirty;
+pub mod edid;
+pub mod font;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+pub mod framebuffer;
+pub mod mode;
+pub mod terminal;
+pub mod utf8;
+
+use ansi::{AnsiAction, AnsiParser, EraseMode, Graphics};
+use dirty::{CellRect, DirtyRegion};
+use edid::Edid;
+use font::PsfFont;
+use framebuffer::{Font, FramebufferLayout};
+use mode::{BochsDisplay, DisplayAdapter, DisplayMode};
+use utf8::Utf8Decoder;
 
 /// VGA text mode colors
 #[allow(dead_code)]
@@ -58,6 +80,26 @@ const VGA_BUFFER_HEIGHT: usize = 25;
 const VGA_BUFFER_WIDTH: usize = 80;
 const VGA_BUFFER_ADDRESS: usize = 0xb8000;
 
+/// Size of a full-screen frame of (character, attribute) cells in text
+/// mode; larger modes take larger frames
+pub const VGA_FRAME_SIZE: usize = VGA_BUFFER_WIDTH * VGA_BUFFER_HEIGHT * 2;
+
+/// Lines kept after they scroll off the screen, unless configured otherwise
+pub const DEFAULT_SCROLLBACK_LINES: usize = 1000;
+
+/// Lines a page up or down moves the view by in text mode, keeping one
+/// line of context
+pub const SCROLL_PAGE_LINES: usize = VGA_BUFFER_HEIGHT - 1;
+
+/// One screen line of cells, as wide as the screen was
+type VgaLine = Vec<VgaChar>;
+
+/// A white on black space
+const BLANK: VgaChar = VgaChar {
+    ascii_character: b' ',
+    color_code: VgaColorCode((VgaColor::Black as u8) << 4 | VgaColor::White as u8),
+};
+
 /// VGA text mode buffer
 #[repr(transparent)]
 pub struct VgaBuffer {
@@ -65,12 +107,47 @@ pub struct VgaBuffer {
 }
 
 /// VGA text mode driver implementation
+///
+/// The console keeps its cells itself and draws them to VGA text memory,
+/// or into the framebuffer when a display adapter has set a graphics mode.
 pub struct VgaTextDriver {
     buffer: &'static mut VgaBuffer,
+    /// Console size in cells, which the display mode sets
+    cols: usize,
+    rows: usize,
+    /// What the screen shows, row by row, once flushed
+    cells: Vec<VgaChar>,
+    /// What the display holds, None where it is not known
+    shown: Vec<Option<VgaChar>>,
+    /// Cells changed since the last flush
+    dirty: DirtyRegion,
+    /// Adapter that sets display modes, if the display has one
+    adapter: Option<Box<dyn DisplayAdapter>>,
+    /// Font and pixel layout cells are drawn with in graphics modes
+    font: Option<Font>,
+    layout: Option<FramebufferLayout>,
     cursor_row: usize,
     cursor_col: usize,
     color_code: VgaColorCode,
+    /// Colors set with `set_color`, which SGR 0 returns to
+    default_colors: (VgaColor, VgaColor),
+    /// Colors chosen by escape sequences, before bold brightens them
+    colors: (VgaColor, VgaColor),
+    bold: bool,
+    ansi: AnsiParser,
+    /// Output is UTF-8, shown as the code page 437 characters cells hold
+    utf8: Utf8Decoder,
+    saved_cursor: Option<(usize, usize)>,
     status: DriverStatus,
+    /// Shared memory region clients render frames into
+    frame_region: Option<SharedRegion>,
+    /// Lines that scrolled off the top, oldest first
+    scrollback: VecDeque<VgaLine>,
+    scrollback_limit: usize,
+    /// Lines the view is scrolled back by; 0 shows the live screen
+    view_offset: usize,
+    /// Live screen, saved while the view shows scrollback
+    live_screen: Option<Vec<VgaLine>>,
     #[cfg(test)]
     test_buffer: Option<Box<VgaBuffer>>,
 }
@@ -82,10 +159,29 @@ impl VgaTextDriver {
         {
             Self {
                 buffer: unsafe { &mut *(VGA_BUFFER_ADDRESS as *mut VgaBuffer) },
+                cols: VGA_BUFFER_WIDTH,
+                rows: VGA_BUFFER_HEIGHT,
+                cells: vec![BLANK; VGA_BUFFER_WIDTH * VGA_BUFFER_HEIGHT],
+                shown: vec![None; VGA_BUFFER_WIDTH * VGA_BUFFER_HEIGHT],
+                dirty: DirtyRegion::new(),
+                adapter: None,
+                font: None,
+                layout: None,
                 cursor_row: 0,
                 cursor_col: 0,
                 color_code: VgaColorCode::new(VgaColor::White, VgaColor::Black),
+                default_colors: (VgaColor::White, VgaColor::Black),
+                colors: (VgaColor::White, VgaColor::Black),
+                bold: false,
+                ansi: AnsiParser::new(),
+                utf8: Utf8Decoder::new(),
+                saved_cursor: None,
                 status: DriverStatus::Uninitialized,
+                frame_region: None,
+                scrollback: VecDeque::new(),
+                scrollback_limit: DEFAULT_SCROLLBACK_LINES,
+                view_offset: 0,
+                live_screen: None,
                 #[cfg(test)]
                 test_buffer: None,
             }
@@ -118,20 +214,76 @@ impl VgaTextDriver {
         
         Self {
             buffer: buffer_ref,
+            cols: VGA_BUFFER_WIDTH,
+            rows: VGA_BUFFER_HEIGHT,
+            cells: vec![BLANK; VGA_BUFFER_WIDTH * VGA_BUFFER_HEIGHT],
+            shown: vec![None; VGA_BUFFER_WIDTH * VGA_BUFFER_HEIGHT],
+            dirty: DirtyRegion::new(),
+            adapter: None,
+            font: None,
+            layout: None,
             cursor_row: 0,
             cursor_col: 0,
             color_code: VgaColorCode::new(VgaColor::White, VgaColor::Black),
+            default_colors: (VgaColor::White, VgaColor::Black),
+            colors: (VgaColor::White, VgaColor::Black),
+            bold: false,
+            ansi: AnsiParser::new(),
+            utf8: Utf8Decoder::new(),
+            saved_cursor: None,
             status: DriverStatus::Uninitialized,
+            frame_region: None,
+            scrollback: VecDeque::new(),
+            scrollback_limit: DEFAULT_SCROLLBACK_LINES,
+            view_offset: 0,
+            live_screen: None,
             test_buffer: None,
         }
     }
 
+    /// Create a driver keeping `lines` of scrollback
+    pub fn with_scrollback(lines: usize) -> Self {
+        let mut driver = Self::new();
+        driver.scrollback_limit = lines;
+        driver
+    }
+
+    /// Create a driver showing its console through `adapter`, which can set
+    /// display modes
+    ///
+    /// The console starts in the mode the adapter shows.
+    pub fn with_adapter(adapter: Box<dyn DisplayAdapter>) -> Self {
+        let mut driver = Self::new();
+        // Graphics modes fall back to the built-in font where the
+        // adapter's cannot be read
+        driver.font = adapter.font().or_else(|| PsfFont::builtin().to_vga_font());
+        driver.layout = adapter.layout();
+        let (cols, rows) = adapter.mode().grid();
+        driver.adapter = Some(adapter);
+        driver.cols = cols;
+        driver.rows = rows;
+        driver.cells = vec![BLANK; cols * rows];
+        driver.shown = vec![None; cols * rows];
+        driver
+    }
+
     /// Write a single byte to the VGA buffer
+    ///
+    /// Output shows up on the live screen, so a scrolled-back view returns
+    /// to it first. The line is drawn once it ends, or at a `flush`.
     pub fn write_byte(&mut self, byte: u8) {
+        self.put_byte(byte);
+        if byte == b'\n' {
+            self.flush();
+        }
+    }
+
+    fn put_byte(&mut self, byte: u8) {
+        self.scroll_to_bottom();
         match byte {
             b'\n' => self.new_line(),
             byte => {
-                if self.cursor_col >= VGA_BUFFER_WIDTH {
+                if self.cursor_col >= self.cols {
                     self.new_line();
                 }
 
@@ -140,57 +292,176 @@ impl VgaTextDriver {
                     color_code: self.color_code,
                 };
 
-                self.buffer.chars[self.cursor_row][self.cursor_col].write(vga_char);
+                self.put(self.cursor_row, self.cursor_col, vga_char);
                 self.cursor_col += 1;
             }
         }
     }
 
     /// Write a string to the VGA buffer
+    ///
+    /// ANSI escape sequences are interpreted, see the `ansi` module. The
+    /// string is drawn in one flush at the end, so output scrolling many
+    /// lines draws the screen once.
     pub fn write_string(&mut self, s: &str) {
         for byte in s.bytes() {
-            match byte {
-                // Printable ASCII characters and newline
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                // Non-printable characters are replaced with ■
-                _ => self.write_byte(0xfe),
+            match self.ansi.feed(byte) {
+                Some(AnsiAction::Print(byte)) => self.print_byte(byte),
+                Some(action) => self.apply_ansi(action),
+                None => {}
             }
         }
+        self.flush();
     }
 
     /// Set the color for subsequent text output
+    ///
+    /// These also become the colors an SGR reset returns to.
     pub fn set_color(&mut self, foreground: VgaColor, background: VgaColor) {
+        self.default_colors = (foreground, background);
+        self.colors = (foreground, background);
+        self.bold = false;
         self.color_code = VgaColorCode::new(foreground, background);
     }
 
+    fn print_byte(&mut self, byte: u8) {
+        for character in self.utf8.feed(byte).into_iter().flatten() {
+            self.print_char(character);
+        }
+    }
+
+    fn print_char(&mut self, character: char) {
+        match character {
+            // Printable ASCII characters and newline
+            ' '..='~' | '\n' => self.put_byte(character as u8),
+            '\r' => self.cursor_col = 0,
+            '\x08' => self.cursor_col = self.cursor_col.saturating_sub(1),
+            // Other characters show as code page 437 has them; control
+            // characters and those it lacks are replaced with ■
+            _ => self.put_byte(cp437::from_char(character).filter(|_| !character.is_control()).unwrap_or(0xfe)),
+        }
+    }
+
+    fn apply_ansi(&mut self, action: AnsiAction) {
+        self.scroll_to_bottom();
+        let last_row = self.rows - 1;
+        let last_col = self.cols - 1;
+        match action {
+            AnsiAction::Print(byte) => self.print_byte(byte),
+            AnsiAction::MoveCursor { rows, cols } => {
+                self.cursor_row = self.cursor_row.saturating_add_signed(rows).min(last_row);
+                self.cursor_col = self.cursor_col.min(last_col).saturating_add_signed(cols).min(last_col);
+            }
+            AnsiAction::SetCursor { row, col } => {
+                self.cursor_row = row.min(last_row);
+                self.cursor_col = col.min(last_col);
+            }
+            AnsiAction::EraseDisplay(mode) => {
+                let (row, col) = (self.cursor_row, self.cursor_col.min(last_col));
+                match mode {
+                    EraseMode::ToEnd => {
+                        self.clear_cells(row, col..self.cols);
+                        (row + 1..self.rows).for_each(|row| self.clear_row(row));
+                    }
+                    EraseMode::ToCursor => {
+                        (0..row).for_each(|row| self.clear_row(row));
+                        self.clear_cells(row, 0..col + 1);
+                    }
+                    EraseMode::All => (0..self.rows).for_each(|row| self.clear_row(row)),
+                    EraseMode::AllAndScrollback => {
+                        (0..self.rows).for_each(|row| self.clear_row(row));
+                        self.scrollback.clear();
+                    }
+                }
+            }
+            AnsiAction::EraseLine(mode) => {
+                let (row, col) = (self.cursor_row, self.cursor_col.min(last_col));
+                match mode {
+                    EraseMode::ToEnd => self.clear_cells(row, col..self.cols),
+                    EraseMode::ToCursor => self.clear_cells(row, 0..col + 1),
+                    EraseMode::All | EraseMode::AllAndScrollback => self.clear_row(row),
+                }
+            }
+            AnsiAction::SetGraphics(changes) => {
+                // `ESC [ m` with no parameters is a reset
+                if changes.is_empty() {
+                    self.set_graphics(Graphics::Reset);
+                }
+                for change in changes {
+                    self.set_graphics(change);
+                }
+            }
+            AnsiAction::SaveCursor => self.saved_cursor = Some((self.cursor_row, self.cursor_col)),
+            AnsiAction::RestoreCursor => {
+                if let Some((row, col)) = self.saved_cursor {
+                    self.cursor_row = row;
+                    self.cursor_col = col;
+                }
+            }
+        }
+    }
+
+    fn set_graphics(&mut self, change: Graphics) {
+        match change {
+            Graphics::Reset => {
+                self.colors = self.default_colors;
+                self.bold = false;
+            }
+            Graphics::Bold(bold) => self.bold = bold,
+            Graphics::Foreground(color) => self.colors.0 = color,
+            Graphics::Background(color) => self.colors.1 = color,
+            Graphics::DefaultForeground => self.colors.0 = self.default_colors.0,
+            Graphics::DefaultBackground => self.colors.1 = self.default_colors.1,
+        }
+        let (foreground, background) = self.colors;
+        let foreground = if self.bold { ansi::brighten(foreground) } else { foreground };
+        self.color_code = VgaColorCode::new(foreground, background);
+    }
+
+    fn clear_cells(&mut self, row: usize, cols: core::ops::Range<usize>) {
+        let blank = VgaChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        for col in cols {
+            self.put(row, col, blank);
+        }
+    }
+
     /// Clear the entire screen
+    ///
+    /// The scrollback is kept.
     pub fn clear_screen(&mut self) {
+        self.scroll_to_bottom();
         let blank = VgaChar {
             ascii_character: b' ',
             color_code: self.color_code,
         };
 
-        for row in 0..VGA_BUFFER_HEIGHT {
-            for col in 0..VGA_BUFFER_WIDTH {
-                self.buffer.chars[row][col].write(blank);
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                self.put(row, col, blank);
             }
         }
 
         self.cursor_row = 0;
         self.cursor_col = 0;
+        self.flush();
     }
 
     /// Move to a new line
     fn new_line(&mut self) {
-        if self.cursor_row >= VGA_BUFFER_HEIGHT - 1 {
-            // Scroll up
-            for row in 1..VGA_BUFFER_HEIGHT {
-                for col in 0..VGA_BUFFER_WIDTH {
-                    let character = self.buffer.chars[row][col].read();
-                    self.buffer.chars[row - 1][col].write(character);
-                }
-            }
-            self.clear_row(VGA_BUFFER_HEIGHT - 1);
+        if self.cursor_row >= self.rows - 1 {
+            // Scroll up, keeping the top line
+            let top = self.read_row(0);
+            self.push_scrollback(top);
+            let blank = VgaChar {
+                ascii_character: b' ',
+                color_code: self.color_code,
+            };
+            self.cells.drain(..self.cols);
+            self.cells.resize(self.cols * self.rows, blank);
+            self.mark_all();
         } else {
             self.cursor_row += 1;
         }
@@ -203,14 +474,130 @@ impl VgaTextDriver {
             ascii_character: b' ',
             color_code: self.color_code,
         };
-        for col in 0..VGA_BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+        for col in 0..self.cols {
+            self.put(row, col, blank);
+        }
+    }
+
+    /// Configure how many lines of scrollback are kept
+    ///
+    /// Shrinking drops the oldest lines.
+    pub fn set_scrollback_limit(&mut self, lines: usize) {
+        self.scroll_to_bottom();
+        self.scrollback_limit = lines;
+        while self.scrollback.len() > lines {
+            self.scrollback.pop_front();
+        }
+    }
+
+    /// Number of lines held in the scrollback
+    pub fn scrollback_len(&self) -> usize {
+        self.scrollback.len()
+    }
+
+    /// Lines the view is currently scrolled back by
+    pub fn view_offset(&self) -> usize {
+        self.view_offset
+    }
+
+    /// Move the view back by `lines`, or forward when negative
+    ///
+    /// The view stops at the oldest line kept and at the live screen.
+    /// Returns whether it moved.
+    pub fn scroll_view(&mut self, lines: isize) -> bool {
+        let offset = (self.view_offset as isize).saturating_add(lines)
+            .clamp(0, self.scrollback.len() as isize) as usize;
+        if offset == self.view_offset {
+            return false;
+        }
+        if self.view_offset == 0 {
+            let live = (0..self.rows).map(|row| self.read_row(row)).collect();
+            self.live_screen = Some(live);
+        }
+        self.view_offset = offset;
+        self.draw_view();
+        self.flush();
+        if offset == 0 {
+            self.live_screen = None;
+        }
+        true
+    }
+
+    /// Shift+PageUp: one screen back through the scrollback
+    pub fn page_up(&mut self) -> bool {
+        self.scroll_view(self.rows as isize - 1)
+    }
+
+    /// Shift+PageDown: one screen forward, towards the live screen
+    pub fn page_down(&mut self) -> bool {
+        self.scroll_view(1 - self.rows as isize)
+    }
+
+    /// Scrollback followed by the live screen as text, one line each
+    ///
+    /// Trailing blanks are trimmed and cells outside printable ASCII come
+    /// out as '?'.
+    pub fn dump_scrollback(&self) -> String {
+        let live: Vec<VgaLine> = match &self.live_screen {
+            Some(live) => live.clone(),
+            None => (0..self.rows).map(|row| self.read_row(row)).collect(),
+        };
+        let mut text = String::new();
+        for line in self.scrollback.iter().chain(live.iter()) {
+            let start = text.len();
+            text.extend(line.iter().map(|cell| match cell.ascii_character {
+                byte @ 0x20..=0x7e => byte as char,
+                _ => '?',
+            }));
+            text.truncate(start + text[start..].trim_end().len());
+            text.push('\n');
+        }
+        text
+    }
+
+    fn scroll_to_bottom(&mut self) {
+        if self.view_offset > 0 {
+            self.scroll_view(-(self.view_offset as isize));
+        }
+    }
+
+    fn push_scrollback(&mut self, line: VgaLine) {
+        if self.scrollback_limit == 0 {
+            return;
+        }
+        if self.scrollback.len() >= self.scrollback_limit {
+            self.scrollback.pop_front();
+        }
+        self.scrollback.push_back(line);
+    }
+
+    fn read_row(&self, row: usize) -> VgaLine {
+        self.cells[row * self.cols..(row + 1) * self.cols].to_vec()
+    }
+
+    /// Draw the view `view_offset` lines back from the live screen
+    fn draw_view(&mut self) {
+        let Some(live) = &self.live_screen else {
+            return;
+        };
+        // Lines from before a mode switch are cut or padded to the screen
+        let first = self.scrollback.len() - self.view_offset;
+        let mut cells = Vec::with_capacity(self.cols * self.rows);
+        for row in 0..self.rows {
+            let line = first + row;
+            let cells_of_line = match self.scrollback.get(line) {
+                Some(cells) => cells,
+                None => &live[line - self.scrollback.len()],
+            };
+            cells.extend(cells_of_line.iter().copied().chain(core::iter::repeat(BLANK)).take(self.cols));
         }
+        self.cells = cells;
+        self.mark_all();
     }
 
     /// Set cursor position
     pub fn set_cursor(&mut self, row: usize, col: usize) {
-        if row < VGA_BUFFER_HEIGHT && col < VGA_BUFFER_WIDTH {
+        if row < self.rows && col < self.cols {
             self.cursor_row = row;
             self.cursor_col = col;
         }
@@ -220,6 +607,224 @@ impl VgaTextDriver {
     pub fn get_cursor(&self) -> (usize, usize) {
         (self.cursor_row, self.cursor_col)
     }
+
+    /// Console size as columns and rows of cells
+    pub fn console_size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    /// Bytes of a full-screen frame of (character, attribute) cells
+    pub fn frame_size(&self) -> usize {
+        self.cols * self.rows * 2
+    }
+
+    /// Copy raw (character, attribute) cell pairs to the screen, row-major
+    pub fn blit_cells(&mut self, cells: &[u8]) -> Result<(), DriverError> {
+        self.scroll_to_bottom();
+        let result = self.write_cells(cells);
+        self.flush();
+        result
+    }
+
+    /// Copy a frame from a client's shared memory region to the screen
+    ///
+    /// The region stays mapped between updates so repeated frames from the
+    /// same client cost no extra system calls.
+    fn blit_shared(&mut self, descriptor: &SharedBuffer) -> Result<(), DriverError> {
+        let cached = self.frame_region.as_ref()
+            .map(|region| region.id() == descriptor.region_id)
+            .unwrap_or(false);
+        if !cached {
+            self.frame_region = None;
+            let region = SharedRegion::open(descriptor, false)
+                .map_err(|_| DriverError::PermissionDenied)?;
+            self.frame_region = Some(region);
+        }
+
+        self.scroll_to_bottom();
+        let region = self.frame_region.take().ok_or(DriverError::InvalidRequest)?;
+        let result = region.slice_of(descriptor)
+            .ok_or(DriverError::InvalidRequest)
+            .and_then(|cells| self.write_cells(cells));
+        self.frame_region = Some(region);
+        self.flush();
+        result
+    }
+
+    fn write_cells(&mut self, cells: &[u8]) -> Result<(), DriverError> {
+        if cells.len() > self.frame_size() || cells.len() % 2 != 0 {
+            return Err(DriverError::InvalidRequest);
+        }
+
+        for (index, cell) in cells.chunks_exact(2).enumerate() {
+            let vga_char = VgaChar {
+                ascii_character: cell[0],
+                color_code: VgaColorCode(cell[1]),
+            };
+            self.put(index / self.cols, index % self.cols, vga_char);
+        }
+
+        Ok(())
+    }
+
+    /// The mode the display shows
+    pub fn display_mode(&self) -> DisplayMode {
+        self.adapter.as_ref().map_or(DisplayMode::TEXT, |adapter| adapter.mode())
+    }
+
+    /// Modes the display can switch to; only text mode without an adapter
+    pub fn display_modes(&mut self) -> Vec<DisplayMode> {
+        let mut modes = match self.adapter.as_mut() {
+            Some(adapter) => adapter.modes(),
+            None => vec![DisplayMode::TEXT],
+        };
+        // Nothing can be drawn in graphics modes without a font
+        if self.font.is_none() {
+            modes.retain(|mode| mode.is_text());
+        }
+        modes
+    }
+
+    /// The EDID base block of the attached monitor, if the adapter can
+    /// read one
+    pub fn read_edid(&mut self) -> Option<[u8; edid::EDID_BLOCK_LEN]> {
+        self.adapter.as_mut()?.read_edid()
+    }
+
+    /// Switch the display to `mode` and lay the console out for it
+    ///
+    /// Lines that no longer fit above the cursor go to the scrollback, and
+    /// lines are cut or padded to the new width.
+    pub fn set_display_mode(&mut self, mode: DisplayMode) -> Result<(), DriverError> {
+        if self.adapter.is_none() {
+            return Err(DriverError::HardwareNotFound);
+        }
+        if !self.display_modes().contains(&mode) {
+            return Err(DriverError::InvalidRequest);
+        }
+        let adapter = self.adapter.as_mut().ok_or(DriverError::HardwareNotFound)?;
+        let result = adapter.set_mode(mode);
+        // A failed switch may still have left another mode showing
+        self.layout = adapter.layout();
+        let (cols, rows) = adapter.mode().grid();
+        self.relayout(cols, rows);
+        self.flush();
+        result
+    }
+
+    /// Switch to the mode that suits the display best: the one it asks for
+    /// itself, else going by the monitor's EDID
+    ///
+    /// Returns the mode switched to, None if the mode stays as it is.
+    pub fn select_display_mode(&mut self) -> Result<Option<DisplayMode>, DriverError> {
+        let preferred = self.adapter.as_mut().and_then(|adapter| adapter.preferred_mode());
+        let edid = self.read_edid().and_then(|block| Edid::parse(&block).ok());
+        let modes = self.display_modes();
+        match mode::select_mode(&modes, preferred, edid.as_ref()) {
+            Some(mode) if mode != self.display_mode() => {
+                self.set_display_mode(mode)?;
+                Ok(Some(mode))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Follow the display to the mode it asks for now, if that may have
+    /// changed, as when a virtual display's window is resized
+    ///
+    /// Returns the mode switched to, None if the mode stays as it is.
+    pub fn poll_display(&mut self) -> Result<Option<DisplayMode>, DriverError> {
+        if !self.adapter.as_mut().is_some_and(|adapter| adapter.display_changed()) {
+            return Ok(None);
+        }
+        self.select_display_mode()
+    }
+
+    /// Lay the console out again for `cols` by `rows` cells, to be drawn
+    /// in full at the next flush
+    fn relayout(&mut self, cols: usize, rows: usize) {
+        self.scroll_to_bottom();
+        let lines: Vec<VgaLine> = (0..self.rows).map(|row| self.read_row(row)).collect();
+        // Keep the cursor's line on the screen
+        let dropped = (self.cursor_row + 1).saturating_sub(rows);
+        for line in &lines[..dropped] {
+            self.push_scrollback(line.clone());
+        }
+
+        self.cols = cols;
+        self.rows = rows;
+        self.cells = vec![BLANK; cols * rows];
+        // The mode switch left the display holding who knows what
+        self.shown = vec![None; cols * rows];
+        self.dirty = DirtyRegion::new();
+        for (row, line) in lines[dropped..].iter().take(rows).enumerate() {
+            for (col, cell) in line.iter().take(cols).enumerate() {
+                self.cells[row * cols + col] = *cell;
+            }
+        }
+        self.cursor_row -= dropped;
+        self.cursor_col = self.cursor_col.min(cols);
+        self.saved_cursor = self.saved_cursor
+            .map(|(row, col)| (row.saturating_sub(dropped).min(rows - 1), col.min(cols - 1)));
+        self.mark_all();
+    }
+
+    /// Set the cell at `row`, `col`, to be drawn at the next flush
+    fn put(&mut self, row: usize, col: usize, cell: VgaChar) {
+        let index = row * self.cols + col;
+        if self.cells[index] != cell {
+            self.cells[index] = cell;
+            self.dirty.add(CellRect::cell(row, col));
+        }
+    }
+
+    /// Note that every cell may have changed, as when the screen scrolls
+    fn mark_all(&mut self) {
+        self.dirty.add(CellRect::new(0, 0, self.rows, self.cols));
+    }
+
+    /// Draw the cell at `row`, `col` on the display
+    fn draw(&mut self, row: usize, col: usize) {
+        let cell = self.cells[row * self.cols + col];
+        match (&self.layout, &self.font, self.adapter.as_mut().and_then(|adapter| adapter.framebuffer())) {
+            (Some(layout), Some(font), Some(framebuffer)) => {
+                framebuffer::draw_cell(framebuffer, layout, font, row, col, cell);
+            }
+            (None, _, _) if row < VGA_BUFFER_HEIGHT && col < VGA_BUFFER_WIDTH => {
+                self.buffer.chars[row][col].write(cell);
+            }
+            // Without a font a graphics mode cannot show text
+            _ => {}
+        }
+    }
+
+    /// Draw the cells changed since the last flush and show them
+    ///
+    /// Only cells that differ from what the display holds are drawn, so
+    /// a screen scrolled by a line rewrites just the cells whose text
+    /// moved. Returns how many cells were drawn.
+    pub fn flush(&mut self) -> usize {
+        let mut drawn = 0;
+        for rect in self.dirty.take() {
+            for row in rect.row_range() {
+                for col in rect.col_range() {
+                    let index = row * self.cols + col;
+                    if self.shown[index] != Some(self.cells[index]) {
+                        self.draw(row, col);
+                        self.shown[index] = Some(self.cells[index]);
+                        drawn += 1;
+                    }
+                }
+            }
+        }
+        // Adapters that need telling show what was drawn
+        if drawn > 0 {
+            if let Some(adapter) = self.adapter.as_mut() {
+                adapter.flush();
+            }
+        }
+        drawn
+    }
 }
 
 impl KoshDriver for VgaTextDriver {
@@ -227,6 +832,10 @@ impl KoshDriver for VgaTextDriver {
         // Initialize VGA text mode
         self.status = DriverStatus::Initializing;
         
+        // Show the monitor's own resolution where the adapter can set it;
+        // the boot loader's mode stays if the switch fails
+        let _ = self.select_display_mode();
+        
         // Clear the screen and set default colors
         self.clear_screen();
         self.set_color(VgaColor::White, VgaColor::Black);
@@ -289,6 +898,65 @@ impl KoshDriver for VgaTextDriver {
                             Err(DriverError::InvalidRequest)
                         }
                     }
+                    // Blit frame from shared memory command
+                    0x04 => {
+                        let descriptor = SharedBuffer::from_bytes(&data)
+                            .ok_or(DriverError::InvalidRequest)?;
+                        if descriptor.length > self.frame_size() || descriptor.length % 2 != 0 {
+                            return Err(DriverError::InvalidRequest);
+                        }
+                        self.blit_shared(&descriptor)?;
+                        Ok(DriverResponse::Success)
+                    }
+                    // Scroll the view command: lines back as a little-endian
+                    // i32, negative to go forward
+                    0x05 => {
+                        let lines = data.get(..4)
+                            .and_then(|bytes| bytes.try_into().ok())
+                            .map(i32::from_le_bytes)
+                            .ok_or(DriverError::InvalidRequest)?;
+                        self.scroll_view(lines as isize);
+                        Ok(DriverResponse::Success)
+                    }
+                    // Dump scrollback command
+                    0x06 => {
+                        Ok(DriverResponse::Data(self.dump_scrollback().into_bytes()))
+                    }
+                    // Set scrollback size command: lines as a little-endian u32
+                    0x07 => {
+                        let lines = data.get(..4)
+                            .and_then(|bytes| bytes.try_into().ok())
+                            .map(u32::from_le_bytes)
+                            .ok_or(DriverError::InvalidRequest)?;
+                        self.set_scrollback_limit(lines as usize);
+                        Ok(DriverResponse::Success)
+                    }
+                    // Display modes command: the current mode, then each
+                    // mode there is, as `MODE_BYTES` each
+                    0x08 => {
+                        let mut bytes = self.display_mode().to_bytes().to_vec();
+                        for mode in self.display_modes() {
+                            bytes.extend_from_slice(&mode.to_bytes());
+                        }
+                        Ok(DriverResponse::Data(bytes))
+                    }
+                    // Set display mode command: a mode as `MODE_BYTES`
+                    0x09 => {
+                        let mode = DisplayMode::from_bytes(&data).ok_or(DriverError::InvalidRequest)?;
+                        self.set_display_mode(mode)?;
+                        Ok(DriverResponse::Success)
+                    }
+                    // Read EDID command: the monitor's EDID base block
+                    0x0A => {
+                        let block = self.read_edid().ok_or(DriverError::HardwareNotFound)?;
+                        Ok(DriverResponse::Data(block.to_vec()))
+                    }
+                    // Flush command: draw what was written since the last
+                    // flush
+                    0x0B => {
+                        self.flush();
+                        Ok(DriverResponse::Success)
+                    }
                     _ => Err(DriverError::InvalidRequest)
                 }
             }
@@ -358,6 +1026,11 @@ impl KoshDriver for VgaTextDriver {
             }
             PowerEvent::Resume => {
                 self.status = DriverStatus::Ready;
+                // The adapter forgets its mode while suspended
+                let mode = self.display_mode();
+                if let Some(adapter) = self.adapter.as_mut() {
+                    adapter.set_mode(mode)?;
+                }
                 // Reinitialize display
                 self.clear_screen();
                 Ok(())
@@ -377,10 +1050,54 @@ impl KoshDriver for VgaTextDriver {
 /// Global VGA driver instance protected by mutex
 static VGA_DRIVER: Mutex<Option<VgaTextDriver>> = Mutex::new(None);
 
+/// PCI IDs of Bochs-compatible adapters: QEMU's standard VGA and
+/// bochs-display, and VirtualBox's VGA
+const BOCHS_DISPLAY_IDS: [(u16, u16); 2] = [(0x1234, 0x1111), (0x80EE, 0xBEEF)];
+
+/// Find a Bochs-compatible adapter and map its memory
+///
+/// The framebuffer is BAR 0. QEMU's standard VGA also has a register BAR 2
+/// starting with the EDID; on other adapters reading it finds no valid
+/// EDID.
+fn find_display_adapter() -> Option<BochsDisplay> {
+    use kosh_driver::hal::{map_mmio, HardwarePortIo, Mmio};
+    use kosh_driver::pci::{self, PortConfigSpace};
+    
+    let boot = kosh_driver::display::boot_display()?;
+    let mut config = PortConfigSpace::new(HardwarePortIo);
+    let device = pci::enumerate(&mut config)
+        .into_iter()
+        .find(|device| BOCHS_DISPLAY_IDS.contains(&(device.vendor_id, device.device_id)))?;
+    let bars = pci::memory_bars(&mut config, device.address);
+    let framebuffer_bar = bars.iter().find(|bar| bar.index == 0)?;
+    let framebuffer = map_mmio(framebuffer_bar.address, framebuffer_bar.size as usize).ok()?;
+    let edid = bars.iter()
+        .find(|bar| bar.index == 2)
+        .and_then(|bar| map_mmio(bar.address, edid::EDID_BLOCK_LEN).ok())
+        .map(|window| Box::new(window) as Box<dyn Mmio>);
+    let vga_memory = map_mmio(framebuffer::VGA_MEMORY_ADDRESS, framebuffer::VGA_MEMORY_SIZE).ok()
+        .map(|window| Box::new(window) as Box<dyn Mmio>);
+    BochsDisplay::probe(
+        Box::new(HardwarePortIo),
+        Box::new(framebuffer),
+        framebuffer_bar.size as usize,
+        edid,
+        vga_memory,
+        DisplayMode::from_boot(&boot),
+        mode::boot_layout(&boot),
+    )
+}
+
 /// Initialize the global VGA driver
+///
+/// A Bochs-compatible adapter lets the console switch display modes;
+/// without one it stays in VGA text mode.
 pub fn init_vga_driver() -> Result<(), DriverError> {
     let mut driver_guard = VGA_DRIVER.lock();
-    let mut driver = VgaTextDriver::new();
+    let mut driver = match find_display_adapter() {
+        Some(adapter) => VgaTextDriver::with_adapter(Box::new(adapter)),
+        None => VgaTextDriver::new(),
+    };
     driver.init(Vec::new())?;
     *driver_guard = Some(driver);
     Ok(())
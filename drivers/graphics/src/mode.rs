@@ -0,0 +1,384 @@
+//! Display modes and mode setting
+//!
+//! The console starts in the mode the boot loader left, normally VGA text.
+//! A display adapter that can set modes offers more: the driver picks the
+//! one the monitor's EDID calls native, and clients can switch with a
+//! control command. Text modes count their size in character cells and
+//! graphics modes in pixels; after a switch the console is laid out again
+//! for the new size.
+//!
+//! QEMU's standard VGA, Bochs and VirtualBox all set graphics modes through
+//! the Bochs DISPI registers, which `BochsDisplay` drives.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use kosh_driver::display::{DisplayInfo, DISPLAY_INDEXED, DISPLAY_RGB};
+use kosh_driver::hal::{Mmio, PortIo};
+use kosh_types::DriverError;
+use crate::edid::{Edid, EDID_BLOCK_LEN};
+use crate::framebuffer::{Font, FramebufferLayout, PixelFormat, GLYPH_HEIGHT, GLYPH_WIDTH, VGA_PALETTE};
+
+/// Bytes of a mode in control commands: width and height as little-endian
+/// `u16`s, then the depth
+pub const MODE_BYTES: usize = 5;
+
+/// A mode a display can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayMode {
+    pub width: u16,
+    pub height: u16,
+    /// Bits per pixel; 0 for text modes
+    pub bpp: u8,
+}
+
+impl DisplayMode {
+    /// VGA text mode 3
+    pub const TEXT: Self = Self { width: 80, height: 25, bpp: 0 };
+
+    pub const fn graphics(width: u16, height: u16, bpp: u8) -> Self {
+        Self { width, height, bpp }
+    }
+
+    /// The mode the boot loader left
+    pub fn from_boot(info: &DisplayInfo) -> Self {
+        if info.is_graphics() {
+            Self::graphics(info.width as u16, info.height as u16, info.bpp as u8)
+        } else {
+            Self::TEXT
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.bpp == 0
+    }
+
+    /// Console size in this mode, as columns and rows of cells
+    pub fn grid(&self) -> (usize, usize) {
+        if self.is_text() {
+            (self.width as usize, self.height as usize)
+        } else {
+            (self.width as usize / GLYPH_WIDTH, self.height as usize / GLYPH_HEIGHT)
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; MODE_BYTES] {
+        let [width_low, width_high] = self.width.to_le_bytes();
+        let [height_low, height_high] = self.height.to_le_bytes();
+        [width_low, width_high, height_low, height_high, self.bpp]
+    }
+
+    /// Decode a mode; a text mode's size is not taken from the bytes, as
+    /// only 80x25 is offered
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; MODE_BYTES] = bytes.get(..MODE_BYTES)?.try_into().ok()?;
+        let mode = Self::graphics(u16::from_le_bytes([bytes[0], bytes[1]]), u16::from_le_bytes([bytes[2], bytes[3]]), bytes[4]);
+        Some(if mode.is_text() { Self::TEXT } else { mode })
+    }
+}
+
+/// Pixel layout of the framebuffer the boot loader left, None in text mode
+pub fn boot_layout(info: &DisplayInfo) -> Option<FramebufferLayout> {
+    let format = match info.kind {
+        DISPLAY_RGB => PixelFormat {
+            bpp: info.bpp as u8,
+            red: (info.red_position, info.red_size),
+            green: (info.green_position, info.green_size),
+            blue: (info.blue_position, info.blue_size),
+        },
+        DISPLAY_INDEXED => PixelFormat::direct(8)?,
+        _ => return None,
+    };
+    Some(FramebufferLayout { pitch: info.pitch as usize, format })
+}
+
+/// A display adapter the console can set modes on
+pub trait DisplayAdapter: Send {
+    /// Modes the adapter can switch to
+    fn modes(&mut self) -> Vec<DisplayMode>;
+    /// The mode shown now
+    fn mode(&self) -> DisplayMode;
+    /// Where the pixels of the current mode go, None in text modes
+    fn layout(&self) -> Option<FramebufferLayout>;
+    /// The framebuffer of the current graphics mode
+    fn framebuffer(&mut self) -> Option<&mut dyn Mmio>;
+    /// The attached monitor's EDID base block, if the adapter can read one
+    fn read_edid(&mut self) -> Option<[u8; EDID_BLOCK_LEN]>;
+    /// The font text is drawn with in graphics modes
+    fn font(&self) -> Option<Font>;
+    /// Switch to `mode`, one of `modes`
+    fn set_mode(&mut self, mode: DisplayMode) -> Result<(), DriverError>;
+    /// Show what was drawn into the framebuffer since the last flush;
+    /// adapters scanning out of the framebuffer directly need nothing
+    fn flush(&mut self) {}
+    /// The mode the display itself asks for, such as the size of the
+    /// window a virtual display is shown in
+    fn preferred_mode(&mut self) -> Option<DisplayMode> {
+        None
+    }
+    /// Whether the preferred mode may have changed since last asked, as
+    /// when a virtual display's window is resized
+    fn display_changed(&mut self) -> bool {
+        false
+    }
+}
+
+/// The mode to show on a monitor described by `edid`
+///
+/// A mode the display asks for itself, `preferred`, is taken if offered.
+/// Otherwise the monitor's native resolution is, else the largest offered
+/// one it lists, each at the greatest depth there is. Without either
+/// nothing is chosen: the mode the boot loader set is known to show.
+pub fn select_mode(modes: &[DisplayMode], preferred: Option<DisplayMode>, edid: Option<&Edid>) -> Option<DisplayMode> {
+    if let Some(preferred) = preferred.filter(|mode| modes.contains(mode)) {
+        return Some(preferred);
+    }
+    let edid = edid?;
+    let graphics = || modes.iter().filter(|mode| !mode.is_text());
+    if let Some(preferred) = edid.preferred {
+        let native = graphics()
+            .filter(|mode| (mode.width, mode.height) == (preferred.width, preferred.height))
+            .max_by_key(|mode| mode.bpp);
+        if native.is_some() {
+            return native.copied();
+        }
+    }
+    graphics()
+        .filter(|mode| edid.supports(mode.width, mode.height))
+        .max_by_key(|mode| (mode.width as u32 * mode.height as u32, mode.bpp))
+        .copied()
+}
+
+/// DISPI index and data ports
+const DISPI_INDEX: u16 = 0x01CE;
+const DISPI_DATA: u16 = 0x01CF;
+
+/// DISPI registers
+const DISPI_ID: u16 = 0x00;
+const DISPI_XRES: u16 = 0x01;
+const DISPI_YRES: u16 = 0x02;
+const DISPI_BPP: u16 = 0x03;
+const DISPI_ENABLE: u16 = 0x04;
+const DISPI_VIRT_WIDTH: u16 = 0x06;
+const DISPI_VIDEO_MEMORY_64K: u16 = 0x0A;
+
+/// Interface versions that can set 24 and 32 bpp modes, and the first
+/// that reports its memory size
+const DISPI_ID_MIN: u16 = 0xB0C2;
+const DISPI_ID_MAX: u16 = 0xB0C5;
+const DISPI_ID_MEMORY_SIZE: u16 = 0xB0C4;
+
+/// `DISPI_ENABLE` bits; with `GETCAPS` set the size and depth registers
+/// read back their largest values
+const DISPI_ENABLED: u16 = 0x01;
+const DISPI_GETCAPS: u16 = 0x02;
+const DISPI_LFB_ENABLED: u16 = 0x40;
+
+/// VGA DAC palette ports, for 8 bpp modes
+const DAC_WRITE_INDEX: u16 = 0x3C8;
+const DAC_DATA: u16 = 0x3C9;
+
+/// Resolutions offered where the adapter and its memory allow
+pub const RESOLUTIONS: [(u16, u16); 13] = [
+    (640, 480), (800, 600), (1024, 768), (1280, 720), (1280, 800), (1280, 1024), (1440, 900),
+    (1600, 900), (1680, 1050), (1920, 1080), (1920, 1200), (2560, 1440), (2560, 1600),
+];
+
+/// Depths offered, each one the console can draw in
+const DEPTHS: [u8; 4] = [8, 16, 24, 32];
+
+/// A Bochs-compatible display adapter
+pub struct BochsDisplay {
+    io: Box<dyn PortIo>,
+    framebuffer: Box<dyn Mmio>,
+    /// Bytes of video memory graphics modes may use
+    memory_size: usize,
+    /// Register window holding the EDID, on QEMU's standard VGA
+    edid: Option<Box<dyn Mmio>>,
+    /// VGA memory window at 0xA0000, to save and restore the text font
+    vga_memory: Option<Box<dyn Mmio>>,
+    font: Option<Font>,
+    /// Largest width, height and depth the adapter takes
+    limits: (u16, u16, u8),
+    mode: DisplayMode,
+    layout: Option<FramebufferLayout>,
+}
+
+impl BochsDisplay {
+    /// Take over a Bochs-compatible adapter, None if the DISPI registers do
+    /// not answer
+    ///
+    /// `framebuffer` maps `framebuffer_size` bytes of its video memory.
+    /// `mode` and `layout` describe what the boot loader left. The text
+    /// font is saved while text mode still shows, so text mode can be
+    /// returned to.
+    pub fn probe(
+        mut io: Box<dyn PortIo>,
+        framebuffer: Box<dyn Mmio>,
+        framebuffer_size: usize,
+        edid: Option<Box<dyn Mmio>>,
+        mut vga_memory: Option<Box<dyn Mmio>>,
+        mode: DisplayMode,
+        layout: Option<FramebufferLayout>,
+    ) -> Option<Self> {
+        let id = read_dispi(io.as_mut(), DISPI_ID);
+        if !(DISPI_ID_MIN..=DISPI_ID_MAX).contains(&id) {
+            return None;
+        }
+        let enable = read_dispi(io.as_mut(), DISPI_ENABLE);
+        write_dispi(io.as_mut(), DISPI_ENABLE, enable | DISPI_GETCAPS);
+        let limits = (
+            read_dispi(io.as_mut(), DISPI_XRES),
+            read_dispi(io.as_mut(), DISPI_YRES),
+            read_dispi(io.as_mut(), DISPI_BPP) as u8,
+        );
+        write_dispi(io.as_mut(), DISPI_ENABLE, enable);
+
+        let memory_size = if id >= DISPI_ID_MEMORY_SIZE {
+            (read_dispi(io.as_mut(), DISPI_VIDEO_MEMORY_64K) as usize * 0x10000).min(framebuffer_size)
+        } else {
+            framebuffer_size
+        };
+        let font = match vga_memory.as_mut() {
+            Some(memory) if mode.is_text() => Some(Font::read_vga(io.as_mut(), memory.as_mut())),
+            _ => None,
+        };
+        Some(Self { io, framebuffer, memory_size, edid, vga_memory, font, limits, mode, layout })
+    }
+
+    fn fits(&self, width: u16, height: u16, bpp: u8) -> bool {
+        let (max_width, max_height, max_bpp) = self.limits;
+        // DISPI widths go in steps of 8 pixels
+        width.is_multiple_of(8) && width <= max_width && height <= max_height && bpp <= max_bpp
+            && width as usize * height as usize * (bpp as usize).div_ceil(8) <= self.memory_size
+    }
+
+    /// Switch the DISPI interface off, handing the screen back to VGA text
+    ///
+    /// Graphics modes wipe video memory, and the font in plane 2 with it,
+    /// so the saved font is loaded again.
+    fn show_text(&mut self) -> Result<(), DriverError> {
+        write_dispi(self.io.as_mut(), DISPI_ENABLE, 0);
+        self.mode = DisplayMode::TEXT;
+        self.layout = None;
+        match (&self.font, self.vga_memory.as_mut()) {
+            (Some(font), Some(memory)) => {
+                font.write_vga(self.io.as_mut(), memory.as_mut());
+                Ok(())
+            }
+            _ => Err(DriverError::HardwareNotFound),
+        }
+    }
+
+    /// Load the text mode colors into the first palette entries
+    fn load_palette(&mut self) {
+        self.io.write_u8(DAC_WRITE_INDEX, 0);
+        for (red, green, blue) in VGA_PALETTE {
+            // The DAC takes six bits per color
+            for value in [red, green, blue] {
+                self.io.write_u8(DAC_DATA, value >> 2);
+            }
+        }
+    }
+}
+
+impl DisplayAdapter for BochsDisplay {
+    fn modes(&mut self) -> Vec<DisplayMode> {
+        let mut resolutions = RESOLUTIONS.to_vec();
+        // Offer the monitor's native resolution even if it is unusual
+        let native = self.read_edid()
+            .and_then(|block| Edid::parse(&block).ok())
+            .and_then(|edid| edid.preferred);
+        if let Some(native) = native {
+            if !resolutions.contains(&(native.width, native.height)) {
+                resolutions.push((native.width, native.height));
+            }
+        }
+
+        let mut modes = Vec::new();
+        // Text mode needs its font back, so it is only offered once saved
+        if self.font.is_some() {
+            modes.push(DisplayMode::TEXT);
+        }
+        for (width, height) in resolutions {
+            for bpp in DEPTHS {
+                if self.fits(width, height, bpp) {
+                    modes.push(DisplayMode::graphics(width, height, bpp));
+                }
+            }
+        }
+        if !modes.contains(&self.mode) {
+            modes.push(self.mode);
+        }
+        modes
+    }
+
+    fn mode(&self) -> DisplayMode {
+        self.mode
+    }
+
+    fn layout(&self) -> Option<FramebufferLayout> {
+        self.layout
+    }
+
+    fn framebuffer(&mut self) -> Option<&mut dyn Mmio> {
+        match self.layout {
+            Some(_) => Some(self.framebuffer.as_mut()),
+            None => None,
+        }
+    }
+
+    fn read_edid(&mut self) -> Option<[u8; EDID_BLOCK_LEN]> {
+        let window = self.edid.as_mut()?;
+        let mut block = [0; EDID_BLOCK_LEN];
+        for (offset, byte) in block.iter_mut().enumerate() {
+            *byte = window.read_u8(offset);
+        }
+        Some(block)
+    }
+
+    fn font(&self) -> Option<Font> {
+        self.font.clone()
+    }
+
+    fn set_mode(&mut self, mode: DisplayMode) -> Result<(), DriverError> {
+        if mode.is_text() {
+            return self.show_text();
+        }
+        let format = PixelFormat::direct(mode.bpp).ok_or(DriverError::InvalidRequest)?;
+        if !self.fits(mode.width, mode.height, mode.bpp) {
+            return Err(DriverError::InvalidRequest);
+        }
+
+        let io = self.io.as_mut();
+        // Registers only take new values while the interface is off
+        write_dispi(io, DISPI_ENABLE, 0);
+        write_dispi(io, DISPI_XRES, mode.width);
+        write_dispi(io, DISPI_YRES, mode.height);
+        write_dispi(io, DISPI_BPP, mode.bpp as u16);
+        write_dispi(io, DISPI_VIRT_WIDTH, mode.width);
+        write_dispi(io, DISPI_ENABLE, DISPI_ENABLED | DISPI_LFB_ENABLED);
+
+        // The adapter keeps its old values for a mode it will not show
+        let shown = (read_dispi(io, DISPI_XRES), read_dispi(io, DISPI_YRES), read_dispi(io, DISPI_BPP));
+        if shown != (mode.width, mode.height, mode.bpp as u16) {
+            let _ = self.show_text();
+            return Err(DriverError::InvalidRequest);
+        }
+        if format.is_indexed() {
+            self.load_palette();
+        }
+        self.mode = mode;
+        self.layout = Some(FramebufferLayout { pitch: mode.width as usize * format.bytes_per_pixel(), format });
+        Ok(())
+    }
+}
+
+fn read_dispi(io: &mut dyn PortIo, register: u16) -> u16 {
+    io.write_u16(DISPI_INDEX, register);
+    io.read_u16(DISPI_DATA)
+}
+
+fn write_dispi(io: &mut dyn PortIo, register: u16, value: u16) {
+    io.write_u16(DISPI_INDEX, register);
+    io.write_u16(DISPI_DATA, value);
+}
@@ -0,0 +1,451 @@
+//! Terminal widget
+//!
+//! A grid of character cells drawn with a PSF font onto a pixel canvas:
+//! the framebuffer, or memory shown some other way, such as a display
+//! service surface. The grid is as large as the canvas holds, so 1024x768
+//! pixels fit 128x48 cells of the built-in font.
+//!
+//! Output is decoded as UTF-8 and ANSI escape sequences are interpreted as
+//! on the console. Colors are 0x00RRGGBB; SGR picks from the 16 text mode
+//! colors, and bold text is drawn heavier as well as brighter. Rendering
+//! draws only the cells that changed since the last time, from glyphs
+//! cached in their colors.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use kosh_driver::hal::Mmio;
+use crate::ansi::{self, AnsiAction, AnsiParser, EraseMode, Graphics};
+use crate::font::{GlyphCache, GlyphStyle, PsfFont, GLYPH_CACHE_SIZE};
+use crate::framebuffer::{self, vga_rgb, FramebufferLayout};
+use crate::utf8::Utf8Decoder;
+use crate::VgaColor;
+
+/// Colors text starts in, light gray on black
+pub const DEFAULT_FOREGROUND: u32 = vga_rgb(VgaColor::LightGray as u8);
+pub const DEFAULT_BACKGROUND: u32 = vga_rgb(VgaColor::Black as u8);
+
+/// Columns between tab stops
+const TAB_WIDTH: usize = 8;
+
+/// Pixels a terminal draws on
+pub trait Canvas {
+    /// Width and height in pixels
+    fn size(&self) -> (usize, usize);
+
+    /// The value of a pixel of color `rgb`, 0x00RRGGBB
+    fn encode(&self, rgb: u32) -> u32 {
+        rgb
+    }
+
+    /// Write `pixels`, as `encode` gives them, to line `y` from column `x`
+    /// on; whatever is off the canvas is left out
+    fn write_row(&mut self, x: usize, y: usize, pixels: &[u32]);
+}
+
+/// A canvas of 0x00RRGGBB pixels in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelBuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+}
+
+impl PixelBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, pixels: vec![0; width * height] }
+    }
+
+    /// Every pixel, line after line
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl Canvas for PixelBuffer {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn write_row(&mut self, x: usize, y: usize, pixels: &[u32]) {
+        if y >= self.height || x >= self.width {
+            return;
+        }
+        let count = pixels.len().min(self.width - x);
+        let start = y * self.width + x;
+        self.pixels[start..start + count].copy_from_slice(&pixels[..count]);
+    }
+}
+
+/// A canvas over the framebuffer of a graphics mode
+pub struct FramebufferCanvas<'a> {
+    framebuffer: &'a mut dyn Mmio,
+    layout: FramebufferLayout,
+    width: usize,
+    height: usize,
+}
+
+impl<'a> FramebufferCanvas<'a> {
+    /// Draw into `framebuffer`, `width` by `height` pixels laid out as
+    /// `layout` says
+    pub fn new(framebuffer: &'a mut dyn Mmio, layout: FramebufferLayout, width: usize, height: usize) -> Self {
+        Self { framebuffer, layout, width, height }
+    }
+}
+
+impl Canvas for FramebufferCanvas<'_> {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn encode(&self, rgb: u32) -> u32 {
+        self.layout.format.encode_rgb(rgb)
+    }
+
+    fn write_row(&mut self, x: usize, y: usize, pixels: &[u32]) {
+        if y >= self.height || x >= self.width {
+            return;
+        }
+        let bytes = self.layout.format.bytes_per_pixel();
+        let start = y * self.layout.pitch + x * bytes;
+        for (index, &pixel) in pixels.iter().take(self.width - x).enumerate() {
+            framebuffer::write_pixel(self.framebuffer, start + index * bytes, bytes, pixel);
+        }
+    }
+}
+
+/// What one cell shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub character: char,
+    pub foreground: u32,
+    pub background: u32,
+    pub bold: bool,
+}
+
+/// A terminal emulator drawn onto a `Canvas`
+///
+/// The terminal keeps no scrollback; lines scrolled off the top are gone.
+pub struct Terminal {
+    font: PsfFont,
+    glyphs: GlyphCache,
+    cols: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+    /// Cells changed since the last render
+    dirty: Vec<bool>,
+    cursor_row: usize,
+    /// May equal `cols` after the last column is written, wrapping only
+    /// when the next character comes
+    cursor_col: usize,
+    cursor_visible: bool,
+    /// Where the last render drew the cursor
+    drawn_cursor: Option<(usize, usize)>,
+    default_colors: (u32, u32),
+    /// Colors chosen by escape sequences, None for the defaults
+    foreground: Option<VgaColor>,
+    background: Option<VgaColor>,
+    bold: bool,
+    ansi: AnsiParser,
+    utf8: Utf8Decoder,
+    saved_cursor: Option<(usize, usize)>,
+}
+
+impl Terminal {
+    /// A terminal filling a canvas of `width` by `height` pixels with cells
+    /// of `font`'s glyphs
+    pub fn new(font: PsfFont, width: usize, height: usize) -> Self {
+        let (cols, rows) = Self::grid(&font, width, height);
+        let default_colors = (DEFAULT_FOREGROUND, DEFAULT_BACKGROUND);
+        let blank = Cell { character: ' ', foreground: default_colors.0, background: default_colors.1, bold: false };
+        Self {
+            font,
+            glyphs: GlyphCache::new(GLYPH_CACHE_SIZE),
+            cols,
+            rows,
+            cells: vec![blank; cols * rows],
+            dirty: vec![true; cols * rows],
+            cursor_row: 0,
+            cursor_col: 0,
+            cursor_visible: true,
+            drawn_cursor: None,
+            default_colors,
+            foreground: None,
+            background: None,
+            bold: false,
+            ansi: AnsiParser::new(),
+            utf8: Utf8Decoder::new(),
+            saved_cursor: None,
+        }
+    }
+
+    /// Cells of `font` that fit `width` by `height` pixels, at least one
+    fn grid(font: &PsfFont, width: usize, height: usize) -> (usize, usize) {
+        ((width / font.width()).max(1), (height / font.height()).max(1))
+    }
+
+    /// Size as columns and rows of cells
+    pub fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    /// Cursor position as row and column
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cursor_row, self.cursor_col)
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Cell {
+        self.cells[row * self.cols + col]
+    }
+
+    pub fn font(&self) -> &PsfFont {
+        &self.font
+    }
+
+    /// Set the colors text is written in when no escape sequence chose
+    /// others, and that SGR 0 returns to
+    pub fn set_default_colors(&mut self, foreground: u32, background: u32) {
+        self.default_colors = (foreground, background);
+    }
+
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+    }
+
+    /// Draw every cell at the next render, as when the canvas lost what
+    /// was drawn on it
+    pub fn invalidate(&mut self) {
+        self.dirty.fill(true);
+    }
+
+    /// Write UTF-8 output, interpreting ANSI escape sequences
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            match self.ansi.feed(byte) {
+                Some(AnsiAction::Print(byte)) => {
+                    for character in self.utf8.feed(byte).into_iter().flatten() {
+                        self.print(character);
+                    }
+                }
+                Some(action) => self.apply_ansi(action),
+                None => {}
+            }
+        }
+    }
+
+    pub fn write_str(&mut self, text: &str) {
+        self.write(text.as_bytes());
+    }
+
+    fn print(&mut self, character: char) {
+        match character {
+            '\n' => self.new_line(),
+            '\r' => self.cursor_col = 0,
+            '\x08' => self.cursor_col = self.cursor_col.min(self.cols - 1).saturating_sub(1),
+            '\t' => self.cursor_col = ((self.cursor_col / TAB_WIDTH + 1) * TAB_WIDTH).min(self.cols - 1),
+            // Other control characters have no effect
+            character if character.is_control() => {}
+            character => {
+                if self.cursor_col >= self.cols {
+                    self.new_line();
+                }
+                let (foreground, background) = self.colors();
+                let cell = Cell { character, foreground, background, bold: self.bold };
+                self.put(self.cursor_row, self.cursor_col, cell);
+                self.cursor_col += 1;
+            }
+        }
+    }
+
+    /// Colors text is written in now
+    fn colors(&self) -> (u32, u32) {
+        let foreground = match self.foreground {
+            Some(color) if self.bold => vga_rgb(ansi::brighten(color) as u8),
+            Some(color) => vga_rgb(color as u8),
+            // A default that is a text mode color brightens like one
+            None if self.bold => match ansi::COLORS.iter().find(|&&color| vga_rgb(color as u8) == self.default_colors.0) {
+                Some(&color) => vga_rgb(ansi::brighten(color) as u8),
+                None => self.default_colors.0,
+            },
+            None => self.default_colors.0,
+        };
+        let background = self.background.map_or(self.default_colors.1, |color| vga_rgb(color as u8));
+        (foreground, background)
+    }
+
+    fn blank(&self) -> Cell {
+        let (foreground, background) = self.colors();
+        Cell { character: ' ', foreground, background, bold: false }
+    }
+
+    fn put(&mut self, row: usize, col: usize, cell: Cell) {
+        let index = row * self.cols + col;
+        if self.cells[index] != cell {
+            self.cells[index] = cell;
+            self.dirty[index] = true;
+        }
+    }
+
+    fn new_line(&mut self) {
+        if self.cursor_row + 1 < self.rows {
+            self.cursor_row += 1;
+        } else {
+            let blank = self.blank();
+            self.cells.drain(..self.cols);
+            self.cells.resize(self.cols * self.rows, blank);
+            self.invalidate();
+        }
+        self.cursor_col = 0;
+    }
+
+    fn clear_cells(&mut self, row: usize, cols: core::ops::Range<usize>) {
+        let blank = self.blank();
+        for col in cols {
+            self.put(row, col, blank);
+        }
+    }
+
+    fn apply_ansi(&mut self, action: AnsiAction) {
+        let last_row = self.rows - 1;
+        let last_col = self.cols - 1;
+        match action {
+            AnsiAction::Print(byte) => {
+                for character in self.utf8.feed(byte).into_iter().flatten() {
+                    self.print(character);
+                }
+            }
+            AnsiAction::MoveCursor { rows, cols } => {
+                self.cursor_row = self.cursor_row.saturating_add_signed(rows).min(last_row);
+                self.cursor_col = self.cursor_col.min(last_col).saturating_add_signed(cols).min(last_col);
+            }
+            AnsiAction::SetCursor { row, col } => {
+                self.cursor_row = row.min(last_row);
+                self.cursor_col = col.min(last_col);
+            }
+            AnsiAction::EraseDisplay(mode) => {
+                let (row, col) = (self.cursor_row, self.cursor_col.min(last_col));
+                match mode {
+                    EraseMode::ToEnd => {
+                        self.clear_cells(row, col..self.cols);
+                        (row + 1..self.rows).for_each(|row| self.clear_cells(row, 0..self.cols));
+                    }
+                    EraseMode::ToCursor => {
+                        (0..row).for_each(|row| self.clear_cells(row, 0..self.cols));
+                        self.clear_cells(row, 0..col + 1);
+                    }
+                    EraseMode::All | EraseMode::AllAndScrollback => {
+                        (0..self.rows).for_each(|row| self.clear_cells(row, 0..self.cols));
+                    }
+                }
+            }
+            AnsiAction::EraseLine(mode) => {
+                let (row, col) = (self.cursor_row, self.cursor_col.min(last_col));
+                match mode {
+                    EraseMode::ToEnd => self.clear_cells(row, col..self.cols),
+                    EraseMode::ToCursor => self.clear_cells(row, 0..col + 1),
+                    EraseMode::All | EraseMode::AllAndScrollback => self.clear_cells(row, 0..self.cols),
+                }
+            }
+            AnsiAction::SetGraphics(changes) => {
+                // `ESC [ m` with no parameters is a reset
+                if changes.is_empty() {
+                    self.set_graphics(Graphics::Reset);
+                }
+                for change in changes {
+                    self.set_graphics(change);
+                }
+            }
+            AnsiAction::SaveCursor => self.saved_cursor = Some((self.cursor_row, self.cursor_col)),
+            AnsiAction::RestoreCursor => {
+                if let Some((row, col)) = self.saved_cursor {
+                    self.cursor_row = row;
+                    self.cursor_col = col;
+                }
+            }
+        }
+    }
+
+    fn set_graphics(&mut self, change: Graphics) {
+        match change {
+            Graphics::Reset => {
+                self.foreground = None;
+                self.background = None;
+                self.bold = false;
+            }
+            Graphics::Bold(bold) => self.bold = bold,
+            Graphics::Foreground(color) => self.foreground = Some(color),
+            Graphics::Background(color) => self.background = Some(color),
+            Graphics::DefaultForeground => self.foreground = None,
+            Graphics::DefaultBackground => self.background = None,
+        }
+    }
+
+    /// Fit the terminal to a canvas of `width` by `height` pixels
+    ///
+    /// Lines that no longer fit above the cursor are dropped, and lines are
+    /// cut or padded to the new width.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let (cols, rows) = Self::grid(&self.font, width, height);
+        if (cols, rows) == (self.cols, self.rows) {
+            return;
+        }
+        // Keep the cursor's line on the screen
+        let dropped = (self.cursor_row + 1).saturating_sub(rows);
+        let blank = Cell { character: ' ', foreground: self.default_colors.0, background: self.default_colors.1, bold: false };
+        let mut cells = vec![blank; cols * rows];
+        for row in 0..rows.min(self.rows - dropped) {
+            for col in 0..cols.min(self.cols) {
+                cells[row * cols + col] = self.cells[(row + dropped) * self.cols + col];
+            }
+        }
+        self.cells = cells;
+        self.dirty = vec![true; cols * rows];
+        self.cols = cols;
+        self.rows = rows;
+        self.cursor_row -= dropped;
+        self.cursor_col = self.cursor_col.min(cols);
+        self.saved_cursor = self.saved_cursor
+            .map(|(row, col)| (row.saturating_sub(dropped).min(rows - 1), col.min(cols - 1)));
+        self.drawn_cursor = None;
+    }
+
+    /// Draw the cells that changed since the last render, and the cursor,
+    /// returning how many cells were drawn
+    pub fn render(&mut self, canvas: &mut dyn Canvas) -> usize {
+        let cursor = self.cursor_visible.then(|| (self.cursor_row, self.cursor_col.min(self.cols - 1)));
+        // The cell the cursor left shows normally again
+        if self.drawn_cursor != cursor {
+            for (row, col) in [self.drawn_cursor, cursor].into_iter().flatten() {
+                self.dirty[row * self.cols + col] = true;
+            }
+        }
+
+        let (width, height) = (self.font.width(), self.font.height());
+        let mut drawn = 0;
+        for index in 0..self.cells.len() {
+            if !core::mem::take(&mut self.dirty[index]) {
+                continue;
+            }
+            let (row, col) = (index / self.cols, index % self.cols);
+            let cell = self.cells[index];
+            // The cursor shows as the cell in reverse
+            let (foreground, background) = if cursor == Some((row, col)) {
+                (cell.background, cell.foreground)
+            } else {
+                (cell.foreground, cell.background)
+            };
+            let style = GlyphStyle { foreground: canvas.encode(foreground), background: canvas.encode(background), bold: cell.bold };
+            let glyph = self.font.lookup(cell.character);
+            let pixels = self.glyphs.get(&self.font, glyph, style);
+            for (line, pixels) in pixels.chunks_exact(width).enumerate() {
+                canvas.write_row(col * width, row * height + line, pixels);
+            }
+            drawn += 1;
+        }
+        self.drawn_cursor = cursor;
+        drawn
+    }
+}
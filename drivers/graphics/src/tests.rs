@@ -220,4 +220,654 @@ fn test_vga_driver_invalid_utf8() {
     let response = driver.handle_request(request);
     assert!(response.is_err());
     assert!(matches!(response.unwrap_err(), DriverError::InvalidRequest));
-}
\ No newline at end of file
+}
+#[test]
+fn test_vga_driver_blit_cells() {
+    let mut driver = VgaTextDriver::new();
+    driver.init(Vec::new()).unwrap();
+    
+    // Two cells: 'O' and 'K' in yellow on blue
+    let cells = [b'O', 0x1E, b'K', 0x1E];
+    assert!(driver.blit_cells(&cells).is_ok());
+    
+    // Odd-length and oversized frames are rejected
+    assert!(driver.blit_cells(&[b'X']).is_err());
+    assert!(driver.blit_cells(&vec![0u8; crate::VGA_FRAME_SIZE + 2]).is_err());
+}
+
+#[test]
+fn test_vga_driver_blit_shared_invalid_descriptor() {
+    let mut driver = VgaTextDriver::new();
+    driver.init(Vec::new()).unwrap();
+    
+    // A truncated shared buffer descriptor is rejected before mapping
+    let request = DriverRequest::Control {
+        command: 0x04,
+        data: vec![1, 2, 3],
+    };
+    
+    let response = driver.handle_request(request);
+    assert!(matches!(response, Err(DriverError::InvalidRequest)));
+    
+    // So is a frame larger than the screen
+    let descriptor = kosh_ipc::SharedBuffer::new(1, 0, crate::VGA_FRAME_SIZE + 2);
+    let request = DriverRequest::Control {
+        command: 0x04,
+        data: descriptor.to_bytes().to_vec(),
+    };
+    
+    let response = driver.handle_request(request);
+    assert!(matches!(response, Err(DriverError::InvalidRequest)));
+}
+
+#[test]
+fn test_vga_driver_scrollback() {
+    let mut driver = VgaTextDriver::with_scrollback(30);
+    driver.init(Vec::new()).unwrap();
+    for line in 0..60 {
+        driver.write_string(&alloc::format!("line {}\n", line));
+    }
+    // Only the newest lines are kept
+    assert_eq!(driver.scrollback_len(), 30);
+    
+    assert!(driver.page_up());
+    assert_eq!(driver.view_offset(), crate::SCROLL_PAGE_LINES);
+    assert!(driver.page_up());
+    assert_eq!(driver.view_offset(), 30);
+    assert!(!driver.page_up());
+    
+    // The dump holds the scrollback and the live screen, whatever is shown
+    let dump = driver.dump_scrollback();
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(lines.len(), 30 + 25);
+    assert_eq!(lines[0], "line 6");
+    assert_eq!(lines[53], "line 59");
+    
+    // New output returns to the live screen
+    driver.write_string("more");
+    assert_eq!(driver.view_offset(), 0);
+    assert_eq!(driver.dump_scrollback(), alloc::format!("{}more\n", &dump[..dump.len() - 1]));
+}
+
+#[test]
+fn test_vga_driver_scrollback_control() {
+    let mut driver = VgaTextDriver::new();
+    driver.init(Vec::new()).unwrap();
+    for line in 0..40 {
+        driver.write_string(&alloc::format!("{}\n", line));
+    }
+    
+    let scroll = |lines: i32| DriverRequest::Control { command: 0x05, data: lines.to_le_bytes().to_vec() };
+    assert!(matches!(driver.handle_request(scroll(5)), Ok(DriverResponse::Success)));
+    assert_eq!(driver.view_offset(), 5);
+    assert!(matches!(driver.handle_request(scroll(-100)), Ok(DriverResponse::Success)));
+    assert_eq!(driver.view_offset(), 0);
+    assert!(matches!(driver.handle_request(DriverRequest::Control { command: 0x05, data: vec![1] }), Err(DriverError::InvalidRequest)));
+    
+    match driver.handle_request(DriverRequest::Control { command: 0x06, data: vec![] }) {
+        Ok(DriverResponse::Data(bytes)) => assert!(bytes.starts_with(b"VGA Text Mode Driver Initialized\n0\n")),
+        other => panic!("Expected scrollback dump, got {:?}", other),
+    }
+    
+    // Shrinking the scrollback drops the oldest lines
+    let limit = DriverRequest::Control { command: 0x07, data: 4u32.to_le_bytes().to_vec() };
+    assert!(matches!(driver.handle_request(limit), Ok(DriverResponse::Success)));
+    assert_eq!(driver.scrollback_len(), 4);
+}
+
+#[test]
+fn test_ansi_parser() {
+    use crate::ansi::{AnsiAction, AnsiParser, EraseMode, Graphics};
+    
+    let mut parser = AnsiParser::new();
+    let mut feed = |text: &[u8]| -> Vec<AnsiAction> {
+        text.iter().filter_map(|&byte| parser.feed(byte)).collect()
+    };
+    
+    assert_eq!(feed(b"a\x1b[2J"), vec![AnsiAction::Print(b'a'), AnsiAction::EraseDisplay(EraseMode::All)]);
+    assert_eq!(feed(b"\x1b[5;10H\x1b[H"), vec![
+        AnsiAction::SetCursor { row: 4, col: 9 },
+        AnsiAction::SetCursor { row: 0, col: 0 },
+    ]);
+    assert_eq!(feed(b"\x1b[3D\x1b[A"), vec![
+        AnsiAction::MoveCursor { rows: 0, cols: -3 },
+        AnsiAction::MoveCursor { rows: -1, cols: 0 },
+    ]);
+    assert_eq!(feed(b"\x1b[1;31;44m\x1b[m"), vec![
+        AnsiAction::SetGraphics(vec![Graphics::Bold(true), Graphics::Foreground(VgaColor::Red), Graphics::Background(VgaColor::Blue)]),
+        AnsiAction::SetGraphics(vec![Graphics::Reset]),
+    ]);
+    assert_eq!(feed(b"\x1b7\x1b[K\x1b8"), vec![
+        AnsiAction::SaveCursor,
+        AnsiAction::EraseLine(EraseMode::ToEnd),
+        AnsiAction::RestoreCursor,
+    ]);
+    
+    // Sequences may be split across writes; unsupported ones vanish
+    assert_eq!(feed(b"\x1b["), vec![]);
+    assert_eq!(feed(b"32mok"), vec![
+        AnsiAction::SetGraphics(vec![Graphics::Foreground(VgaColor::Green)]),
+        AnsiAction::Print(b'o'),
+        AnsiAction::Print(b'k'),
+    ]);
+    assert_eq!(feed(b"\x1b[?25l\x1b[6n!"), vec![AnsiAction::Print(b'!')]);
+}
+
+#[test]
+fn test_vga_driver_ansi_output() {
+    let mut driver = VgaTextDriver::new();
+    driver.init(Vec::new()).unwrap();
+    
+    driver.write_string("\x1b[2J\x1b[H\x1b[1;32mkosh\x1b[0m> ls");
+    let bright_green = crate::VgaColorCode::new(VgaColor::LightGreen, VgaColor::Black);
+    let default = crate::VgaColorCode::new(VgaColor::White, VgaColor::Black);
+    assert_eq!(driver.buffer.chars[0][0].read().color_code, bright_green);
+    assert_eq!(driver.buffer.chars[0][4].read().color_code, default);
+    assert_eq!(driver.get_cursor(), (0, 8));
+    
+    // Rewrite the line in place and position text by coordinates
+    driver.write_string("\r\x1b[Kdone\x1b7\x1b[3;5Hx\x1b8!");
+    let dump = driver.dump_scrollback();
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(lines[0], "done!");
+    assert_eq!(lines[2], "    x");
+    
+    // Cursor movement stops at the screen edges
+    driver.write_string("\x1b[100A\x1b[100D");
+    assert_eq!(driver.get_cursor(), (0, 0));
+}
+
+/// An EDID base block for a Dell monitor with a 1920x1080 native mode,
+/// 1280x1024 as a standard timing and 640x480 and 1024x768 as established
+/// ones
+fn edid_block() -> [u8; crate::edid::EDID_BLOCK_LEN] {
+    let mut block = [0u8; crate::edid::EDID_BLOCK_LEN];
+    block[..8].copy_from_slice(&[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00]);
+    block[0x08..0x0C].copy_from_slice(&[0x10, 0xAC, 0x21, 0x43]);
+    block[0x12..0x14].copy_from_slice(&[1, 4]);
+    block[0x23..0x25].copy_from_slice(&[0x20, 0x08]);
+    for slot in block[0x26..0x36].chunks_exact_mut(2) {
+        slot.copy_from_slice(&[0x01, 0x01]);
+    }
+    block[0x26..0x28].copy_from_slice(&[0x81, 0x80]);
+    // 148.5 MHz, 1920 + 280 by 1080 + 45
+    block[0x36..0x3E].copy_from_slice(&[0x02, 0x3A, 0x80, 0x18, 0x71, 0x38, 0x2D, 0x40]);
+    block[0x48..0x4D].copy_from_slice(&[0, 0, 0, 0xFC, 0]);
+    block[0x4D..0x5A].copy_from_slice(b"KOSH MONITOR\n");
+    block[127] = 0u8.wrapping_sub(block.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)));
+    block
+}
+
+#[test]
+fn test_edid_parsing() {
+    use crate::edid::{Edid, Resolution};
+    
+    let edid = Edid::parse(&edid_block()).unwrap();
+    assert_eq!(edid.manufacturer(), "DEL");
+    assert_eq!((edid.product, edid.version), (0x4321, (1, 4)));
+    assert_eq!(edid.name.as_deref(), Some("KOSH MONITOR"));
+    assert_eq!(edid.preferred, Some(Resolution { width: 1920, height: 1080, refresh_hz: 60 }));
+    assert!(edid.supports(1280, 1024) && edid.supports(640, 480) && edid.supports(1024, 768));
+    assert!(!edid.supports(800, 600));
+    
+    let mut corrupt = edid_block();
+    corrupt[0x40] ^= 1;
+    assert!(Edid::parse(&corrupt).is_err());
+    assert!(Edid::parse(&[0xFF; 128]).is_err());
+}
+
+#[test]
+fn test_mode_selection() {
+    use crate::edid::Edid;
+    use crate::mode::{select_mode, DisplayMode};
+    
+    let edid = Edid::parse(&edid_block()).unwrap();
+    let native = [
+        DisplayMode::TEXT,
+        DisplayMode::graphics(1920, 1080, 16),
+        DisplayMode::graphics(1920, 1080, 32),
+        DisplayMode::graphics(2560, 1440, 32),
+    ];
+    assert_eq!(select_mode(&native, None, Some(&edid)), Some(DisplayMode::graphics(1920, 1080, 32)));
+    
+    // Without the native resolution the largest one the monitor lists wins
+    let listed = [DisplayMode::graphics(640, 480, 32), DisplayMode::graphics(1024, 768, 8), DisplayMode::graphics(1280, 720, 32)];
+    assert_eq!(select_mode(&listed, None, Some(&edid)), Some(DisplayMode::graphics(1024, 768, 8)));
+    assert_eq!(select_mode(&native, None, None), None);
+    
+    // What the display asks for itself wins, if it is offered
+    let window = DisplayMode::graphics(2560, 1440, 32);
+    assert_eq!(select_mode(&native, Some(window), Some(&edid)), Some(window));
+    assert_eq!(select_mode(&native, Some(window), None), Some(window));
+    assert_eq!(select_mode(&listed, Some(window), Some(&edid)), Some(DisplayMode::graphics(1024, 768, 8)));
+    
+    let mode = DisplayMode::graphics(1024, 768, 32);
+    assert_eq!(DisplayMode::from_bytes(&mode.to_bytes()), Some(mode));
+    assert_eq!(mode.grid(), (128, 48));
+    assert_eq!(DisplayMode::from_bytes(&[0, 0, 0, 0, 0]), Some(DisplayMode::TEXT));
+    assert_eq!(DisplayMode::from_bytes(&[0; 4]), None);
+}
+
+/// Video memory tests can look into after handing it to the driver, with
+/// a count of the bytes written to it
+#[derive(Clone)]
+struct VideoMemory(alloc::sync::Arc<Vec<core::sync::atomic::AtomicU8>>, alloc::sync::Arc<core::sync::atomic::AtomicUsize>);
+
+impl VideoMemory {
+    fn new(size: usize) -> Self {
+        Self(
+            alloc::sync::Arc::new((0..size).map(|_| core::sync::atomic::AtomicU8::new(0)).collect()),
+            alloc::sync::Arc::new(core::sync::atomic::AtomicUsize::new(0)),
+        )
+    }
+    
+    fn writes(&self) -> usize {
+        self.1.load(core::sync::atomic::Ordering::Relaxed)
+    }
+    
+    fn pixel(&self, x: usize, y: usize, pitch: usize) -> u32 {
+        let offset = y * pitch + x * 4;
+        u32::from_le_bytes(core::array::from_fn(|index| self.0[offset + index].load(core::sync::atomic::Ordering::Relaxed)))
+    }
+}
+
+impl kosh_driver::hal::Mmio for VideoMemory {
+    fn read_u8(&mut self, offset: usize) -> u8 {
+        self.0[offset].load(core::sync::atomic::Ordering::Relaxed)
+    }
+    fn write_u8(&mut self, offset: usize, value: u8) {
+        self.0[offset].store(value, core::sync::atomic::Ordering::Relaxed);
+        self.1.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+    }
+    fn read_u16(&mut self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.read_u8(offset), self.read_u8(offset + 1)])
+    }
+    fn write_u16(&mut self, offset: usize, value: u16) {
+        for (index, byte) in value.to_le_bytes().into_iter().enumerate() {
+            self.write_u8(offset + index, byte);
+        }
+    }
+    fn read_u32(&mut self, offset: usize) -> u32 {
+        u32::from_le_bytes(core::array::from_fn(|index| self.read_u8(offset + index)))
+    }
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        for (index, byte) in value.to_le_bytes().into_iter().enumerate() {
+            self.write_u8(offset + index, byte);
+        }
+    }
+}
+
+/// An adapter that takes any of a fixed set of modes
+struct FakeDisplay {
+    modes: Vec<crate::mode::DisplayMode>,
+    mode: crate::mode::DisplayMode,
+    memory: VideoMemory,
+    edid: Option<[u8; crate::edid::EDID_BLOCK_LEN]>,
+    /// Whether the VGA font can be read
+    has_font: bool,
+}
+
+impl crate::mode::DisplayAdapter for FakeDisplay {
+    fn modes(&mut self) -> Vec<crate::mode::DisplayMode> {
+        self.modes.clone()
+    }
+    fn mode(&self) -> crate::mode::DisplayMode {
+        self.mode
+    }
+    fn layout(&self) -> Option<crate::framebuffer::FramebufferLayout> {
+        let format = crate::framebuffer::PixelFormat::direct(self.mode.bpp)?;
+        Some(crate::framebuffer::FramebufferLayout { pitch: self.mode.width as usize * format.bytes_per_pixel(), format })
+    }
+    fn framebuffer(&mut self) -> Option<&mut dyn kosh_driver::hal::Mmio> {
+        Some(&mut self.memory)
+    }
+    fn read_edid(&mut self) -> Option<[u8; crate::edid::EDID_BLOCK_LEN]> {
+        self.edid
+    }
+    fn font(&self) -> Option<crate::framebuffer::Font> {
+        if !self.has_font {
+            return None;
+        }
+        // Every glyph a solid top line, so drawn text is easy to find
+        let glyphs: Vec<u8> = (0..crate::framebuffer::FONT_SIZE)
+            .map(|index| if index % crate::framebuffer::GLYPH_HEIGHT == 0 { 0xFF } else { 0 })
+            .collect();
+        crate::framebuffer::Font::from_bytes(&glyphs).ok()
+    }
+    fn set_mode(&mut self, mode: crate::mode::DisplayMode) -> Result<(), DriverError> {
+        self.mode = mode;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_vga_driver_display_modes() {
+    use crate::mode::DisplayMode;
+    
+    let memory = VideoMemory::new(800 * 600 * 4);
+    let small = DisplayMode::graphics(640, 480, 32);
+    let large = DisplayMode::graphics(800, 600, 32);
+    let adapter = FakeDisplay {
+        modes: vec![DisplayMode::TEXT, small, DisplayMode::graphics(640, 480, 8), large],
+        mode: DisplayMode::TEXT,
+        memory: memory.clone(),
+        edid: Some(edid_block()),
+        has_font: true,
+    };
+    
+    // The monitor lists 640x480 but not 800x600
+    let mut driver = VgaTextDriver::with_adapter(alloc::boxed::Box::new(adapter));
+    driver.init(Vec::new()).unwrap();
+    assert_eq!(driver.display_mode(), small);
+    assert_eq!(driver.console_size(), (80, 30));
+    
+    // Foreground white on black, starting the second line
+    let white = 0x00FF_FFFF;
+    assert_eq!(memory.pixel(0, 16, 640 * 4), white);
+    assert_eq!(memory.pixel(0, 17, 640 * 4), 0);
+    
+    match driver.handle_request(DriverRequest::Control { command: 0x08, data: vec![] }) {
+        Ok(DriverResponse::Data(bytes)) => {
+            assert_eq!(bytes.len(), 5 * 5);
+            assert_eq!(&bytes[..5], &small.to_bytes());
+        }
+        other => panic!("Expected display modes, got {:?}", other),
+    }
+    
+    // Fill the screen, then lay it out again for the larger mode
+    for line in 0..40 {
+        driver.write_string(&alloc::format!("line {}\n", line));
+    }
+    let scrollback = driver.scrollback_len();
+    let set = |mode: DisplayMode| DriverRequest::Control { command: 0x09, data: mode.to_bytes().to_vec() };
+    assert!(matches!(driver.handle_request(set(large)), Ok(DriverResponse::Success)));
+    assert_eq!(driver.console_size(), (100, 37));
+    assert_eq!(driver.get_cursor(), (29, 0));
+    assert_eq!(memory.pixel(0, 28 * 16, 800 * 4), white);
+    
+    // Back to text mode the bottom lines stay, the rest scrolls back
+    assert!(matches!(driver.handle_request(set(DisplayMode::TEXT)), Ok(DriverResponse::Success)));
+    assert_eq!(driver.console_size(), (80, 25));
+    assert_eq!(driver.get_cursor(), (24, 0));
+    assert_eq!(driver.scrollback_len(), scrollback + 5);
+    let dump = driver.dump_scrollback();
+    assert_eq!(dump.lines().nth(scrollback + 5 + 23), Some("line 39"));
+    assert_eq!(driver.buffer.chars[23][5].read().ascii_character, b'3');
+    
+    assert!(matches!(driver.handle_request(set(DisplayMode::graphics(1024, 768, 32))), Err(DriverError::InvalidRequest)));
+    match driver.handle_request(DriverRequest::Control { command: 0x0A, data: vec![] }) {
+        Ok(DriverResponse::Data(bytes)) => assert_eq!(bytes, edid_block().to_vec()),
+        other => panic!("Expected the EDID, got {:?}", other),
+    }
+    
+    // Without an adapter only text mode is there
+    let mut plain = VgaTextDriver::new();
+    plain.init(Vec::new()).unwrap();
+    assert_eq!(plain.display_modes(), vec![DisplayMode::TEXT]);
+    assert!(matches!(plain.handle_request(set(small)), Err(DriverError::HardwareNotFound)));
+    assert!(matches!(plain.handle_request(DriverRequest::Control { command: 0x0A, data: vec![] }), Err(DriverError::HardwareNotFound)));
+}
+
+#[test]
+fn test_psf_fonts() {
+    use crate::font::{PsfFont, GlyphCache, GlyphStyle};
+    
+    let font = PsfFont::builtin();
+    assert_eq!((font.width(), font.height()), (8, 16));
+    assert!(font.glyph_count() >= 256);
+    for character in ['A', 'é', '─', '█', 'ß', '\u{FFFD}'] {
+        assert!(font.glyph_index(character).is_some(), "no glyph for {:?}", character);
+    }
+    // Characters the font lacks are drawn as the replacement character
+    assert_eq!(font.glyph_index('漢'), None);
+    assert_eq!(font.lookup('漢'), font.lookup('\u{FFFD}'));
+    let block = font.lookup('█');
+    assert!((0..8).all(|x| (0..16).all(|y| font.is_set(block, x, y))));
+    
+    // A PSF1 font of 256 glyphs with a table giving glyph 1 to 'x' and 'y'
+    let mut psf1 = vec![0x36, 0x04, 0x02, 4];
+    psf1.extend((0..256 * 4).map(|index| if index / 4 == 1 && index % 4 == 0 { 0x81 } else { 0 }));
+    psf1.extend_from_slice(&[0xFF, 0xFF]);
+    psf1.extend_from_slice(&[b'x', 0, b'y', 0, 0xFF, 0xFF]);
+    let small = PsfFont::parse(&psf1).unwrap();
+    assert_eq!((small.width(), small.height(), small.glyph_count()), (8, 4, 256));
+    assert_eq!(small.glyph_index('y'), Some(1));
+    assert_eq!(small.glyph_index('z'), None);
+    assert!(small.is_set(1, 0, 0) && small.is_set(1, 7, 0) && !small.is_set(1, 1, 0));
+    
+    // Bold sets the pixel right of each set one
+    let style = GlyphStyle { foreground: 1, background: 0, bold: true };
+    assert_eq!(&small.render(1, style)[..8], &[1, 1, 0, 0, 0, 0, 0, 1]);
+    
+    // Truncated fonts and unknown formats are rejected
+    assert!(PsfFont::parse(&psf1[..100]).is_err());
+    assert!(PsfFont::parse(b"not a font").is_err());
+    
+    // The cache draws each glyph once per style and keeps to its capacity
+    let mut cache = GlyphCache::new(2);
+    let plain = GlyphStyle { foreground: 1, background: 0, bold: false };
+    assert_eq!(cache.get(&small, 1, plain)[0], 1);
+    cache.get(&small, 1, plain);
+    cache.get(&small, 1, style);
+    assert_eq!(cache.len(), 2);
+    cache.get(&small, 2, plain);
+    assert_eq!(cache.len(), 2);
+    
+    // Laid out for the console, glyphs follow code page 437
+    let vga = font.to_vga_font().unwrap();
+    assert!(vga.glyph(0xDB).iter().all(|&line| line == 0xFF));
+    assert!(vga.glyph(0).iter().all(|&line| line == 0));
+    assert!(small.to_vga_font().is_none());
+}
+
+#[test]
+fn test_utf8_decoder() {
+    use crate::utf8::{Utf8Decoder, REPLACEMENT};
+    
+    let decode = |bytes: &[u8]| -> alloc::string::String {
+        let mut decoder = Utf8Decoder::new();
+        bytes.iter().flat_map(|&byte| decoder.feed(byte)).flatten().collect()
+    };
+    let text = "ascii é ─ 😀";
+    assert_eq!(decode(text.as_bytes()), text);
+    
+    // A character split between writes comes out once it is complete
+    let mut decoder = Utf8Decoder::new();
+    assert_eq!(decoder.feed(0xE2), [None, None]);
+    assert_eq!(decoder.feed(0x94), [None, None]);
+    assert_eq!(decoder.feed(0x80), [Some('─'), None]);
+    
+    // Each malformed sequence is replaced once
+    let replaced = |text: &str| text.replace('?', &alloc::string::String::from(REPLACEMENT));
+    assert_eq!(decode(&[b'a', 0x80, b'b']), replaced("a?b"));
+    assert_eq!(decode(&[0xC3, b'a']), replaced("?a"));
+    assert_eq!(decode(&[0xE2, 0x94, 0xC3, 0xA9]), replaced("?é"));
+    // Overlong forms, surrogates and bytes UTF-8 never uses
+    assert_eq!(decode(&[0xC0, 0xAF]), replaced("??"));
+    assert_eq!(decode(&[0xE0, 0x80, 0xAF]), replaced("?"));
+    assert_eq!(decode(&[0xED, 0xA0, 0x80]), replaced("?"));
+    assert_eq!(decode(&[0xFF]), replaced("?"));
+}
+
+#[test]
+fn test_vga_driver_unicode_output() {
+    let mut driver = VgaTextDriver::new();
+    driver.init(Vec::new()).unwrap();
+    
+    // Characters code page 437 has are shown as it has them, the rest as ■
+    driver.write_string("\x1b[2J\x1b[Hé─漢\x07");
+    let shown: Vec<u8> = (0..4).map(|col| driver.buffer.chars[0][col].read().ascii_character).collect();
+    assert_eq!(shown, vec![0x82, 0xC4, 0xFE, 0xFE]);
+    assert_eq!(driver.get_cursor(), (0, 4));
+}
+
+#[test]
+fn test_vga_driver_builtin_font() {
+    use crate::mode::DisplayMode;
+    
+    // An adapter left in a graphics mode, whose VGA font cannot be read
+    let memory = VideoMemory::new(1024 * 768 * 4);
+    let mode = DisplayMode::graphics(1024, 768, 32);
+    let adapter = FakeDisplay {
+        modes: vec![mode],
+        mode,
+        memory: memory.clone(),
+        edid: None,
+        has_font: false,
+    };
+    let mut driver = VgaTextDriver::with_adapter(alloc::boxed::Box::new(adapter));
+    driver.init(Vec::new()).unwrap();
+    assert_eq!(driver.display_mode(), mode);
+    assert_eq!(driver.console_size(), (128, 48));
+    
+    // A full block fills its cell
+    driver.write_string("\x1b[2J\x1b[H█");
+    let white = 0x00FF_FFFF;
+    assert!((0..16).all(|y| (0..8).all(|x| memory.pixel(x, y, 1024 * 4) == white)));
+    assert_eq!(memory.pixel(8, 0, 1024 * 4), 0);
+}
+
+#[test]
+fn test_terminal_rendering() {
+    use crate::font::PsfFont;
+    use crate::terminal::{Canvas, PixelBuffer, Terminal, DEFAULT_BACKGROUND, DEFAULT_FOREGROUND};
+    
+    let mut canvas = PixelBuffer::new(1024, 768);
+    let mut terminal = Terminal::new(PsfFont::builtin(), 1024, 768);
+    assert_eq!(terminal.size(), (128, 48));
+    
+    // The first render draws every cell, later ones only what changed
+    assert_eq!(terminal.render(&mut canvas), 128 * 48);
+    terminal.write_str("█\x1b[1;31m█\x1b[0m\x1b[44m \x1b[0m");
+    assert_eq!(terminal.render(&mut canvas), 4);
+    assert_eq!(terminal.render(&mut canvas), 0);
+    
+    let bright_red = crate::framebuffer::vga_rgb(VgaColor::LightRed as u8);
+    let blue = crate::framebuffer::vga_rgb(VgaColor::Blue as u8);
+    assert_eq!(canvas.pixel(0, 0), DEFAULT_FOREGROUND);
+    assert_eq!(canvas.pixel(8, 15), bright_red);
+    assert!(terminal.cell(0, 1).bold);
+    assert_eq!(canvas.pixel(16, 0), blue);
+    // The cursor shows the cell after the text in reverse
+    assert_eq!(terminal.cursor(), (0, 3));
+    assert_eq!(canvas.pixel(24, 0), DEFAULT_FOREGROUND);
+    
+    // UTF-8 split across writes, and characters the font lacks
+    terminal.write(&[0xC3]);
+    terminal.write(&[0xA9]);
+    terminal.write_str("漢");
+    assert_eq!(terminal.cell(0, 3).character, 'é');
+    assert_eq!(terminal.cell(0, 4).character, '漢');
+    terminal.render(&mut canvas);
+    
+    // Bold defaults brighten too, light gray to white
+    terminal.write_str("\r\x1b[1mx\x1b[0m");
+    assert_eq!(terminal.cell(0, 0).foreground, crate::framebuffer::vga_rgb(VgaColor::White as u8));
+    
+    // Output past the bottom scrolls everything up a line
+    for line in 0..48 {
+        terminal.write_str(&alloc::format!("\nline {}", line));
+    }
+    assert_eq!(terminal.cursor(), (47, 7));
+    assert_eq!(terminal.cell(0, 5).character, '0');
+    assert_eq!(terminal.render(&mut canvas), 128 * 48);
+    assert_eq!(canvas.pixel(0, 0), DEFAULT_BACKGROUND);
+    
+    // A smaller canvas keeps the cursor's line
+    terminal.resize(640, 480);
+    assert_eq!(terminal.size(), (80, 30));
+    assert_eq!(terminal.cursor(), (29, 7));
+    assert_eq!(terminal.cell(29, 5).character, '4');
+    let mut small = PixelBuffer::new(640, 480);
+    assert_eq!(terminal.render(&mut small), 80 * 30);
+    assert_eq!(small.size(), (640, 480));
+}
+
+#[test]
+fn test_dirty_region() {
+    use crate::dirty::{CellRect, DirtyRegion, MAX_DIRTY_RECTS};
+    
+    // Cells written along a line grow one rectangle
+    let mut region = DirtyRegion::new();
+    for col in 0..5 {
+        region.add(CellRect::cell(3, col));
+    }
+    assert_eq!(region.rects(), &[CellRect::new(3, 0, 1, 5)]);
+    
+    // Cells already covered add nothing, and a larger rectangle replaces
+    // those inside it
+    region.add(CellRect::cell(3, 2));
+    assert_eq!(region.rects().len(), 1);
+    region.add(CellRect::new(4, 0, 1, 5));
+    assert_eq!(region.rects(), &[CellRect::new(3, 0, 2, 5)]);
+    region.add(CellRect::new(0, 0, 25, 80));
+    assert_eq!(region.rects(), &[CellRect::new(0, 0, 25, 80)]);
+    assert_eq!(region.take().len(), 1);
+    assert!(region.is_empty());
+    
+    // Too many scattered cells become the rectangle around them
+    for index in 0..=MAX_DIRTY_RECTS {
+        region.add(CellRect::cell(index * 2, index * 3));
+    }
+    let bounds = CellRect::new(0, 0, MAX_DIRTY_RECTS * 2 + 1, MAX_DIRTY_RECTS * 3 + 1);
+    assert_eq!(region.rects(), &[bounds]);
+    assert_eq!(bounds.cell_count(), (MAX_DIRTY_RECTS * 2 + 1) * (MAX_DIRTY_RECTS * 3 + 1));
+}
+
+#[test]
+fn test_vga_driver_batched_flush() {
+    use crate::mode::DisplayMode;
+    
+    let mut driver = VgaTextDriver::new();
+    driver.init(Vec::new()).unwrap();
+    
+    driver.write_string("\x1b[2J\x1b[H");
+    
+    // Bytes written one at a time show at the end of the line, or when
+    // flushed
+    driver.write_byte(b'a');
+    assert_eq!(driver.buffer.chars[0][0].read().ascii_character, b' ');
+    driver.write_byte(b'\n');
+    assert_eq!(driver.buffer.chars[0][0].read().ascii_character, b'a');
+    driver.write_byte(b'b');
+    assert!(matches!(driver.handle_request(DriverRequest::Control { command: 0x0B, data: vec![] }), Ok(DriverResponse::Success)));
+    assert_eq!(driver.buffer.chars[1][0].read().ascii_character, b'b');
+    assert_eq!(driver.flush(), 0);
+    
+    // Text rewritten as it was draws nothing
+    driver.write_string("\x1b[Ha");
+    assert_eq!(driver.buffer.chars[0][0].read().ascii_character, b'a');
+    driver.write_string("\x1b[Ha");
+    assert_eq!(driver.flush(), 0);
+    
+    // In a graphics mode, output scrolling many lines draws the screen
+    // once rather than once a line
+    let memory = VideoMemory::new(640 * 480 * 4);
+    let mode = DisplayMode::graphics(640, 480, 32);
+    let adapter = FakeDisplay {
+        modes: vec![mode],
+        mode,
+        memory: memory.clone(),
+        edid: None,
+        has_font: true,
+    };
+    let mut driver = VgaTextDriver::with_adapter(alloc::boxed::Box::new(adapter));
+    driver.init(Vec::new()).unwrap();
+    let screen = 640 * 480 * 4;
+    
+    let log: alloc::string::String = (0..100).map(|line| alloc::format!("[ ok ] started service {}\n", line)).collect();
+    let before = memory.writes();
+    driver.write_string(&log);
+    let batched = memory.writes() - before;
+    assert!(batched <= screen, "{} bytes written", batched);
+    assert_eq!(driver.get_cursor(), (29, 0));
+    let dump = driver.dump_scrollback();
+    assert_eq!(dump.lines().rev().find(|line| !line.is_empty()), Some("[ ok ] started service 99"));
+    
+    let before = memory.writes();
+    for line in log.lines() {
+        driver.write_string(line);
+        driver.write_string("\n");
+    }
+    assert!(memory.writes() - before > 2 * batched);
+}
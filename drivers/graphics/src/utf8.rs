@@ -0,0 +1,60 @@
+//! UTF-8 decoding of console output
+//!
+//! Output arrives a byte at a time and a character may be split across
+//! writes, so the decoder keeps a partial sequence between bytes. Malformed
+//! input decodes to U+FFFD, once for each bad sequence: a byte that cannot
+//! start one, a sequence cut short, an overlong form or a surrogate.
+
+/// What malformed input decodes to
+pub const REPLACEMENT: char = '\u{FFFD}';
+
+#[derive(Debug, Clone, Default)]
+pub struct Utf8Decoder {
+    /// Bits of the character decoded so far
+    code: u32,
+    /// Continuation bytes still to come
+    remaining: u8,
+    /// Least the sequence may encode; anything less is overlong
+    min: u32,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one byte, returning the characters it completes: the
+    /// replacement for a sequence it cuts short, if any, then its own
+    pub fn feed(&mut self, byte: u8) -> [Option<char>; 2] {
+        if self.remaining == 0 {
+            return [self.start(byte), None];
+        }
+        if byte & 0xC0 != 0x80 {
+            self.remaining = 0;
+            return [Some(REPLACEMENT), self.start(byte)];
+        }
+        self.code = self.code << 6 | (byte & 0x3F) as u32;
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            return [None, None];
+        }
+        let decoded = char::from_u32(self.code).filter(|_| self.code >= self.min);
+        [Some(decoded.unwrap_or(REPLACEMENT)), None]
+    }
+
+    /// Start a sequence with `byte`, returning it if it is one on its own
+    fn start(&mut self, byte: u8) -> Option<char> {
+        let (code, remaining, min) = match byte {
+            0x00..=0x7F => return Some(byte as char),
+            0xC2..=0xDF => (byte & 0x1F, 1, 0x80),
+            0xE0..=0xEF => (byte & 0x0F, 2, 0x800),
+            0xF0..=0xF4 => (byte & 0x07, 3, 0x10000),
+            // Continuation bytes, and bytes UTF-8 never uses
+            _ => return Some(REPLACEMENT),
+        };
+        self.code = code as u32;
+        self.remaining = remaining;
+        self.min = min;
+        None
+    }
+}
@@ -0,0 +1,144 @@
+//! Recorded PS/2 controller register transactions
+//!
+//! Modelled on the port I/O QEMU's i8042 emulation answers with, trimmed to
+//! the accesses the driver makes. Status 0x1C is an idle controller (system
+//! flag, command/data and unlocked bits set), 0x1D additionally has the
+//! output buffer full and 0x1E has the input buffer still busy.
+
+use alloc::boxed::Box;
+use kosh_driver::hal::replay::{ReplayIo, Transaction};
+use kosh_driver::mock::MockBackend;
+use kosh_driver::KoshDriver;
+use super::PS2KeyboardDriver;
+
+const DATA: usize = 0x60;
+const STATUS: usize = 0x64;
+const COMMAND: usize = 0x64;
+
+const IDLE: u8 = 0x1C;
+const OUTPUT_FULL: u8 = 0x1D;
+const INPUT_BUSY: u8 = 0x1E;
+
+/// Full controller bring-up followed by a keyboard reset
+pub const PS2_INIT_HANDSHAKE: &[Transaction] = &[
+    // Disable both ports
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0xAD),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0xA7),
+    // Flush a stale byte left over from the firmware
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0xFA),
+    Transaction::read_u8(STATUS, IDLE),
+    // Read configuration byte: IRQs on, system flag, translation
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0x20),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0x47),
+    // Write it back with IRQs masked
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0x60),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(DATA, 0x44),
+    // Controller self test; the controller is briefly busy afterwards
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0xAA),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0x55),
+    // First port interface test
+    Transaction::read_u8(STATUS, INPUT_BUSY),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0xAB),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0x00),
+    // Enable first port and its interrupt
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0xAE),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0x60),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(DATA, 0x45),
+    // Keyboard reset: ACK then BAT passed
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(DATA, 0xFF),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0xFA),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0xAA),
+];
+
+/// Controller reports a failed self test (0xFC)
+pub const PS2_SELF_TEST_FAILURE: &[Transaction] = &[
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0xAD),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0xA7),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0x20),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0x47),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0x60),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(DATA, 0x44),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(COMMAND, 0xAA),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0xFC),
+];
+
+/// Keyboard interrupt delivering the make and break codes for 'A'
+pub const PS2_KEY_A_PRESS_RELEASE: &[Transaction] = &[
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0x1E),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0x9E),
+];
+
+/// Caps Lock pressed, then the LED command lighting its LED
+pub const PS2_CAPS_LOCK_LEDS: &[Transaction] = &[
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0x3A),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(DATA, 0xED),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0xFA),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(DATA, 0x04),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0xFA),
+];
+
+/// Typematic command for 750 ms and 100 ms, which the keyboard asks to
+/// have resent once
+pub const PS2_TYPEMATIC_WITH_RESEND: &[Transaction] = &[
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(DATA, 0xF3),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0xFE),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(DATA, 0xF3),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0xFA),
+    Transaction::read_u8(STATUS, IDLE),
+    Transaction::write_u8(DATA, 0x4C),
+    Transaction::read_u8(STATUS, OUTPUT_FULL),
+    Transaction::read_u8(DATA, 0xFA),
+];
+
+/// Driver backed by a replay of the init handshake, repeated on every
+/// re-initialisation
+pub fn replay_driver() -> PS2KeyboardDriver {
+    PS2KeyboardDriver::with_port_io(Box::new(ReplayIo::looping(PS2_INIT_HANDSHAKE)))
+}
+
+/// Driver started on a simulated controller, and the machine to feed it
+/// scancodes through
+pub fn mock_driver() -> (PS2KeyboardDriver, MockBackend) {
+    let machine = MockBackend::new();
+    let mut driver = PS2KeyboardDriver::with_backend(&machine);
+    driver.init(alloc::vec![]).expect("simulated controller starts");
+    (driver, machine)
+}
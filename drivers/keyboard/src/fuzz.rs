@@ -0,0 +1,30 @@
+//! Fuzz entry point for scancode decoding
+//!
+//! The keyboard, or anything that can inject a recording, decides the
+//! scancode stream, so the decoder must cope with prefixes and releases in
+//! any order.
+
+use crate::scancode::{decode_scancode, Decoded, EXTENDED_PREFIX};
+use kosh_driver::input::{KeyEventType, KeyModifiers};
+
+/// Decode `data` as a scancode stream, checking each key it produces
+pub fn scancodes(data: &[u8]) {
+    let mut extended = false;
+    let mut modifiers = KeyModifiers::empty();
+    for &byte in data {
+        match decode_scancode(byte, extended, modifiers) {
+            Decoded::Prefix => {
+                assert_eq!(byte, EXTENDED_PREFIX, "prefix from a byte other than 0xE0");
+                extended = true;
+            }
+            Decoded::Key(key) => {
+                assert_eq!(key.scancode, byte & 0x7F, "scancode kept its release bit");
+                if key.event_type == KeyEventType::KeyRelease {
+                    assert!(key.ascii_char.is_none(), "a release typed a character");
+                }
+                modifiers = key.modifiers;
+                extended = false;
+            }
+        }
+    }
+}
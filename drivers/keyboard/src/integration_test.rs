@@ -2,13 +2,14 @@
 /// These tests show how the driver would be used in a real system
 
 use super::*;
+use crate::fixtures::replay_driver;
 use alloc::{vec, vec::Vec};
 use kosh_driver::{DriverRequest, DriverResponse, QueryType};
 
 /// Simulate a user space process requesting keyboard input
 #[test]
 fn test_user_space_communication() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Simulate user typing "Hello"
@@ -53,7 +54,7 @@ fn test_user_space_communication() {
 /// Test driver status queries from user space
 #[test]
 fn test_status_queries() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // User space queries driver status
@@ -85,7 +86,7 @@ fn test_status_queries() {
 /// Test control commands from system services
 #[test]
 fn test_system_control() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Add some events
@@ -115,7 +116,7 @@ fn test_system_control() {
 /// Test error handling in driver communication
 #[test]
 fn test_error_handling() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Test invalid control command
@@ -146,7 +147,7 @@ fn test_error_handling() {
 /// Test modifier key combinations
 #[test]
 fn test_modifier_combinations() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Test Ctrl+C combination
@@ -175,7 +176,7 @@ fn test_modifier_combinations() {
 /// Test special key handling (arrows, function keys)
 #[test]
 fn test_special_keys() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Test extended scancode sequence (arrow key)
@@ -210,7 +211,7 @@ fn test_special_keys() {
 /// Test queue overflow behavior
 #[test]
 fn test_queue_overflow() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Set a small queue size
@@ -245,7 +246,7 @@ fn test_queue_overflow() {
 /// Test power management integration
 #[test]
 fn test_power_management_integration() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Add some events
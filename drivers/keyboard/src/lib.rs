@@ -1,14 +1,25 @@
-#![no_std]
+#![cfg_attr(not(feature = "fuzzing"), no_std)]
 
 extern crate alloc;
 
+pub mod recording;
+pub mod scancode;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+
 use alloc::{vec, vec::Vec, string::String, boxed::Box, collections::VecDeque};
 use kosh_driver::{
     KoshDriver, DriverInfo, DriverType, HardwareId, DriverStatus, PowerEvent,
     DriverRequest, DriverResponse, DriverCapabilityType, HardwareCapability
 };
-use kosh_types::{DriverError, Capability};
-use spin::Mutex;
+use kosh_driver::hal::{PortIo, HardwareBackend, RealBackend};
+pub use kosh_driver::input::{InputEvent, KeyCode, KeyEventType, KeyModifiers, keycode_to_ascii};
+use kosh_driver::input::{InputRecord, encode_input_batch};
+use kosh_ipc::IpcError;
+use kosh_types::{DriverError, Capability, ProcessId};
+use kosh_sync::Mutex;
+use recording::{InputRecording, Replay, ReplaySpeed};
+use scancode::Decoded;
 // use volatile::Volatile; // Not needed for this implementation
 use bitflags::bitflags;
 
@@ -17,6 +28,53 @@ const PS2_DATA_PORT: u16 = 0x60;
 const PS2_STATUS_PORT: u16 = 0x64;
 const PS2_COMMAND_PORT: u16 = 0x64;
 
+/// PS/2 controller commands
+const PS2_CMD_READ_CONFIG: u8 = 0x20;
+const PS2_CMD_WRITE_CONFIG: u8 = 0x60;
+const PS2_CMD_DISABLE_PORT2: u8 = 0xA7;
+const PS2_CMD_SELF_TEST: u8 = 0xAA;
+const PS2_CMD_TEST_PORT1: u8 = 0xAB;
+const PS2_CMD_DISABLE_PORT1: u8 = 0xAD;
+const PS2_CMD_ENABLE_PORT1: u8 = 0xAE;
+
+/// PS/2 controller and keyboard responses
+const PS2_SELF_TEST_PASSED: u8 = 0x55;
+const PS2_PORT_TEST_PASSED: u8 = 0x00;
+const KEYBOARD_RESET: u8 = 0xFF;
+const KEYBOARD_ACK: u8 = 0xFA;
+const KEYBOARD_BAT_PASSED: u8 = 0xAA;
+const KEYBOARD_RESEND: u8 = 0xFE;
+
+/// Keyboard commands that take an argument byte
+const KEYBOARD_SET_LEDS: u8 = 0xED;
+const KEYBOARD_SET_TYPEMATIC: u8 = 0xF3;
+
+/// Times a keyboard command byte is sent before a resend request fails it
+const KEYBOARD_COMMAND_TRIES: usize = 3;
+
+/// Milliseconds a command byte sent from the main loop waits for its
+/// answer before the command is given up
+const KEYBOARD_COMMAND_TIMEOUT_MS: u64 = 100;
+
+/// Commands the driver holds for the keyboard; more are dropped
+const KEYBOARD_COMMAND_QUEUE: usize = 8;
+
+/// LED byte bits of `KEYBOARD_SET_LEDS`
+const LED_SCROLL_LOCK: u8 = 1 << 0;
+const LED_NUM_LOCK: u8 = 1 << 1;
+const LED_CAPS_LOCK: u8 = 1 << 2;
+
+/// Controller configuration byte bits
+const PS2_CONFIG_PORT1_IRQ: u8 = 1 << 0;
+const PS2_CONFIG_PORT2_IRQ: u8 = 1 << 1;
+const PS2_CONFIG_PORT1_CLOCK_DISABLED: u8 = 1 << 4;
+
+/// Status polls before a controller handshake step gives up
+const PS2_TIMEOUT_POLLS: usize = 10_000;
+
+/// Upper bound on stale bytes drained from the output buffer during init
+const PS2_MAX_FLUSH: usize = 16;
+
 /// PS/2 status register bits
 bitflags! {
     #[derive(Debug, Clone, Copy)]
@@ -32,499 +90,342 @@ bitflags! {
     }
 }
 
-/// Key codes for common keys
+/// Keyboard command with its argument, sent a byte at a time from the
+/// driver's main loop as the keyboard acknowledges each
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(u8)]
-pub enum KeyCode {
-    // Letters
-    A = 0x1E, B = 0x30, C = 0x2E, D = 0x20, E = 0x12, F = 0x21, G = 0x22,
-    H = 0x23, I = 0x17, J = 0x24, K = 0x25, L = 0x26, M = 0x32, N = 0x31,
-    O = 0x18, P = 0x19, Q = 0x10, R = 0x13, S = 0x1F, T = 0x14, U = 0x16,
-    V = 0x2F, W = 0x11, X = 0x2D, Y = 0x15, Z = 0x2C,
-    
-    // Numbers
-    Key0 = 0x0B, Key1 = 0x02, Key2 = 0x03, Key3 = 0x04, Key4 = 0x05,
-    Key5 = 0x06, Key6 = 0x07, Key7 = 0x08, Key8 = 0x09, Key9 = 0x0A,
-    
-    // Function keys
-    F1 = 0x3B, F2 = 0x3C, F3 = 0x3D, F4 = 0x3E, F5 = 0x3F, F6 = 0x40,
-    F7 = 0x41, F8 = 0x42, F9 = 0x43, F10 = 0x44, F11 = 0x57, F12 = 0x58,
-    
-    // Special keys
-    Escape = 0x01,
-    Backspace = 0x0E,
-    Tab = 0x0F,
-    Enter = 0x1C,
-    Space = 0x39,
-    LeftShift = 0x2A,
-    RightShift = 0x36,
-    LeftCtrl = 0x1D,
-    LeftAlt = 0x38,
-    CapsLock = 0x3A,
-    
-    // Arrow keys (extended scancodes)
-    ArrowUp = 0x48,
-    ArrowDown = 0x50,
-    ArrowLeft = 0x4B,
-    ArrowRight = 0x4D,
-    
-    // Other keys
-    Delete = 0x53,
-    Home = 0x47,
-    End = 0x4F,
-    PageUp = 0x49,
-    PageDown = 0x51,
-    Insert = 0x52,
-    
-    // Unknown key
-    Unknown = 0xFF,
+struct PendingCommand {
+    bytes: [u8; 2],
+    /// Byte being sent, or waiting for its answer
+    next: usize,
+    /// Times that byte has been sent
+    tries: usize,
+    /// When that byte was sent, if it waits for its answer
+    sent_ms: Option<u64>,
 }
 
-/// Key event types
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum KeyEventType {
-    KeyPress,
-    KeyRelease,
+impl PendingCommand {
+    fn new(command: u8, argument: u8) -> Self {
+        Self { bytes: [command, argument], next: 0, tries: 0, sent_ms: None }
+    }
 }
 
-/// Input event structure
-#[derive(Debug, Clone)]
-pub struct InputEvent {
-    pub event_type: KeyEventType,
-    pub key_code: KeyCode,
-    pub scancode: u8,
-    pub modifiers: KeyModifiers,
-    pub ascii_char: Option<char>,
-    pub timestamp: u64, // In a real implementation, this would be a proper timestamp
-}
+/// Typematic setting of a PS/2 keyboard: how long a held key waits
+/// before it repeats and how fast it repeats
+///
+/// This is the `KEYBOARD_SET_TYPEMATIC` argument byte. Bits 5-6 give the
+/// delay, 250 ms to 1 s in steps of 250 ms. Bits 0-4 give the repeat
+/// period, (8 + bits 0-2) * 2^(bits 3-4) * 4.17 ms, from about 33 ms
+/// (30 repeats a second) to 500 ms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Typematic(u8);
+
+impl Typematic {
+    /// What the keyboard uses after a reset: 500 ms, then 10.9 repeats a
+    /// second
+    pub const DEFAULT: Self = Self(0x2B);
+
+    /// The setting nearest `delay_ms` and a repeat every `interval_ms`
+    pub fn from_ms(delay_ms: u16, interval_ms: u16) -> Self {
+        let delay = (delay_ms.saturating_add(125) / 250).clamp(1, 4) as u8 - 1;
+        let rate = (0..32u8)
+            .min_by_key(|&rate| Self(rate).interval_ms().abs_diff(interval_ms))
+            .unwrap_or(Self::DEFAULT.0 & 0x1F);
+        Self(delay << 5 | rate)
+    }
 
-/// Key modifier flags
-bitflags! {
-    #[derive(Debug, Clone, Copy)]
-    pub struct KeyModifiers: u8 {
-        const SHIFT = 1 << 0;
-        const CTRL = 1 << 1;
-        const ALT = 1 << 2;
-        const CAPS_LOCK = 1 << 3;
-        const NUM_LOCK = 1 << 4;
-        const SCROLL_LOCK = 1 << 5;
+    /// The setting the argument byte `byte` gives
+    pub fn from_byte(byte: u8) -> Self {
+        Self(byte & 0x7F)
+    }
+
+    /// The argument byte
+    pub fn to_byte(self) -> u8 {
+        self.0
+    }
+
+    /// Delay before a held key repeats
+    pub fn delay_ms(self) -> u16 {
+        ((self.0 >> 5 & 0x3) as u16 + 1) * 250
+    }
+
+    /// Time between repeats, to the millisecond
+    pub fn interval_ms(self) -> u16 {
+        let period = (8 + (self.0 & 0x7) as u32) << (self.0 >> 3 & 0x3);
+        ((period * 417 + 50) / 100) as u16
     }
 }
 
 /// PS/2 keyboard driver implementation
 pub struct PS2KeyboardDriver {
+    io: Box<dyn PortIo>,
     status: DriverStatus,
     event_queue: VecDeque<InputEvent>,
     modifiers: KeyModifiers,
     extended_scancode: bool,
     max_queue_size: usize,
+    /// Millisecond clock stamping events and timing recordings and replays
+    clock: fn() -> u64,
+    /// Scancodes captured since recording started, and when it started
+    recording: Option<(u64, InputRecording)>,
+    /// Recording being injected
+    replay: Option<Replay>,
+    /// LED byte the keyboard was last sent
+    leds: u8,
+    /// Typematic setting asked for
+    typematic: Typematic,
+    /// Commands for the keyboard, the one in flight first
+    commands: VecDeque<PendingCommand>,
+    /// Acknowledge and resend bytes that answered no command in flight
+    stray_answers: u64,
 }
 
 impl PS2KeyboardDriver {
     /// Create a new PS/2 keyboard driver instance
     pub fn new() -> Self {
+        Self::with_backend(&RealBackend)
+    }
+
+    /// Create a driver for the controller of the given machine
+    pub fn with_backend(backend: &dyn HardwareBackend) -> Self {
+        Self::with_port_io(backend.port_io())
+    }
+
+    /// Create a driver that reaches the controller through the given port backend
+    pub fn with_port_io(io: Box<dyn PortIo>) -> Self {
         Self {
+            io,
             status: DriverStatus::Uninitialized,
             event_queue: VecDeque::new(),
             modifiers: KeyModifiers::empty(),
             extended_scancode: false,
             max_queue_size: 256,
+            clock: kosh_driver::time::monotonic_ms,
+            recording: None,
+            replay: None,
+            leds: 0,
+            typematic: Typematic::DEFAULT,
+            commands: VecDeque::new(),
+            stray_answers: 0,
         }
     }
 
+    /// Stamp events, recordings and replays with `clock`, in milliseconds
+    pub fn set_clock(&mut self, clock: fn() -> u64) {
+        self.clock = clock;
+    }
+
     /// Read a byte from the PS/2 data port
-    fn read_data(&self) -> u8 {
-        // In a real implementation, this would use proper I/O port access
-        // For now, we'll simulate reading from the port
-        unsafe {
-            // This is a placeholder - in real implementation would use:
-            // x86_64::instructions::port::Port::new(PS2_DATA_PORT).read()
-            0
-        }
+    fn read_data(&mut self) -> u8 {
+        self.io.read_u8(PS2_DATA_PORT)
     }
 
     /// Read the PS/2 status register
-    fn read_status(&self) -> PS2Status {
-        // In a real implementation, this would use proper I/O port access
-        // For now, we'll simulate reading from the port
-        unsafe {
-            // This is a placeholder - in real implementation would use:
-            // PS2Status::from_bits_truncate(x86_64::instructions::port::Port::new(PS2_STATUS_PORT).read())
-            PS2Status::empty()
-        }
+    fn read_status(&mut self) -> PS2Status {
+        PS2Status::from_bits_truncate(self.io.read_u8(PS2_STATUS_PORT))
     }
 
     /// Write a command to the PS/2 command port
-    fn write_command(&self, command: u8) {
-        // In a real implementation, this would use proper I/O port access
-        unsafe {
-            // This is a placeholder - in real implementation would use:
-            // x86_64::instructions::port::Port::new(PS2_COMMAND_PORT).write(command)
-        }
+    fn write_command(&mut self, command: u8) -> Result<(), DriverError> {
+        self.wait_input_empty()?;
+        self.io.write_u8(PS2_COMMAND_PORT, command);
+        Ok(())
     }
 
-    /// Convert scancode to keycode
-    fn scancode_to_keycode(&self, scancode: u8) -> KeyCode {
-        match scancode {
-            // Letters
-            0x1E => KeyCode::A, 0x30 => KeyCode::B, 0x2E => KeyCode::C, 0x20 => KeyCode::D,
-            0x12 => KeyCode::E, 0x21 => KeyCode::F, 0x22 => KeyCode::G, 0x23 => KeyCode::H,
-            0x17 => KeyCode::I, 0x24 => KeyCode::J, 0x25 => KeyCode::K, 0x26 => KeyCode::L,
-            0x32 => KeyCode::M, 0x31 => KeyCode::N, 0x18 => KeyCode::O, 0x19 => KeyCode::P,
-            0x10 => KeyCode::Q, 0x13 => KeyCode::R, 0x1F => KeyCode::S, 0x14 => KeyCode::T,
-            0x16 => KeyCode::U, 0x2F => KeyCode::V, 0x11 => KeyCode::W, 0x2D => KeyCode::X,
-            0x15 => KeyCode::Y, 0x2C => KeyCode::Z,
-            
-            // Numbers
-            0x0B => KeyCode::Key0, 0x02 => KeyCode::Key1, 0x03 => KeyCode::Key2,
-            0x04 => KeyCode::Key3, 0x05 => KeyCode::Key4, 0x06 => KeyCode::Key5,
-            0x07 => KeyCode::Key6, 0x08 => KeyCode::Key7, 0x09 => KeyCode::Key8,
-            0x0A => KeyCode::Key9,
-            
-            // Function keys
-            0x3B => KeyCode::F1, 0x3C => KeyCode::F2, 0x3D => KeyCode::F3, 0x3E => KeyCode::F4,
-            0x3F => KeyCode::F5, 0x40 => KeyCode::F6, 0x41 => KeyCode::F7, 0x42 => KeyCode::F8,
-            0x43 => KeyCode::F9, 0x44 => KeyCode::F10, 0x57 => KeyCode::F11, 0x58 => KeyCode::F12,
-            
-            // Special keys
-            0x01 => KeyCode::Escape,
-            0x0E => KeyCode::Backspace,
-            0x0F => KeyCode::Tab,
-            0x1C => KeyCode::Enter,
-            0x39 => KeyCode::Space,
-            0x2A => KeyCode::LeftShift,
-            0x36 => KeyCode::RightShift,
-            0x1D => KeyCode::LeftCtrl,
-            0x38 => KeyCode::LeftAlt,
-            0x3A => KeyCode::CapsLock,
-            
-            // Extended keys (when extended_scancode is true)
-            0x48 if self.extended_scancode => KeyCode::ArrowUp,
-            0x50 if self.extended_scancode => KeyCode::ArrowDown,
-            0x4B if self.extended_scancode => KeyCode::ArrowLeft,
-            0x4D if self.extended_scancode => KeyCode::ArrowRight,
-            0x53 if self.extended_scancode => KeyCode::Delete,
-            0x47 if self.extended_scancode => KeyCode::Home,
-            0x4F if self.extended_scancode => KeyCode::End,
-            0x49 if self.extended_scancode => KeyCode::PageUp,
-            0x51 if self.extended_scancode => KeyCode::PageDown,
-            0x52 if self.extended_scancode => KeyCode::Insert,
-            
-            _ => KeyCode::Unknown,
-        }
+    /// Write a byte to the PS/2 data port (controller argument or keyboard command)
+    fn write_data(&mut self, data: u8) -> Result<(), DriverError> {
+        self.wait_input_empty()?;
+        self.io.write_u8(PS2_DATA_PORT, data);
+        Ok(())
     }
 
-    /// Convert keycode to ASCII character (considering modifiers)
-    fn keycode_to_ascii(&self, key_code: KeyCode) -> Option<char> {
-        let shift_pressed = self.modifiers.contains(KeyModifiers::SHIFT);
-        let caps_lock = self.modifiers.contains(KeyModifiers::CAPS_LOCK);
-        
-        match key_code {
-            // Letters - handle each key individually since range patterns don't work with enums
-            KeyCode::A => {
-                let base_char = 'a';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::B => {
-                let base_char = 'b';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::C => {
-                let base_char = 'c';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::D => {
-                let base_char = 'd';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::E => {
-                let base_char = 'e';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::F => {
-                let base_char = 'f';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::G => {
-                let base_char = 'g';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::H => {
-                let base_char = 'h';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::I => {
-                let base_char = 'i';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::J => {
-                let base_char = 'j';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::K => {
-                let base_char = 'k';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::L => {
-                let base_char = 'l';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::M => {
-                let base_char = 'm';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::N => {
-                let base_char = 'n';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::O => {
-                let base_char = 'o';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::P => {
-                let base_char = 'p';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
+    /// Wait until the controller has accepted the previous byte
+    fn wait_input_empty(&mut self) -> Result<(), DriverError> {
+        for _ in 0..PS2_TIMEOUT_POLLS {
+            if !self.read_status().contains(PS2Status::INPUT_BUFFER_FULL) {
+                return Ok(());
             }
-            KeyCode::Q => {
-                let base_char = 'q';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::R => {
-                let base_char = 'r';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::S => {
-                let base_char = 's';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::T => {
-                let base_char = 't';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::U => {
-                let base_char = 'u';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            KeyCode::V => {
-                let base_char = 'v';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
+        }
+        Err(DriverError::InitializationFailed)
+    }
+
+    /// Wait for a response byte and read it
+    fn read_response(&mut self) -> Result<u8, DriverError> {
+        for _ in 0..PS2_TIMEOUT_POLLS {
+            if self.read_status().contains(PS2Status::OUTPUT_BUFFER_FULL) {
+                return Ok(self.read_data());
             }
-            KeyCode::W => {
-                let base_char = 'w';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
+        }
+        Err(DriverError::InitializationFailed)
+    }
+
+    /// LED byte showing the lock state in `modifiers`
+    fn led_byte(modifiers: KeyModifiers) -> u8 {
+        let mut leds = 0;
+        if modifiers.contains(KeyModifiers::SCROLL_LOCK) {
+            leds |= LED_SCROLL_LOCK;
+        }
+        if modifiers.contains(KeyModifiers::NUM_LOCK) {
+            leds |= LED_NUM_LOCK;
+        }
+        if modifiers.contains(KeyModifiers::CAPS_LOCK) {
+            leds |= LED_CAPS_LOCK;
+        }
+        leds
+    }
+
+    /// Set the lock state, as the input service keeps it; the LEDs follow
+    /// from the main loop
+    ///
+    /// Only the Caps, Num and Scroll Lock bits of `locks` are taken.
+    pub fn set_lock_state(&mut self, locks: KeyModifiers) {
+        let lock_bits = KeyModifiers::CAPS_LOCK | KeyModifiers::NUM_LOCK | KeyModifiers::SCROLL_LOCK;
+        self.modifiers = (self.modifiers - lock_bits) | (locks & lock_bits);
+    }
+
+    /// Set how held keys repeat; the keyboard is told from the main loop
+    pub fn set_typematic(&mut self, typematic: Typematic) {
+        self.typematic = typematic;
+        self.queue_command(PendingCommand::new(KEYBOARD_SET_TYPEMATIC, typematic.to_byte()));
+    }
+
+    /// The typematic setting asked for
+    pub fn typematic(&self) -> Typematic {
+        self.typematic
+    }
+
+    /// Acknowledge and resend bytes that arrived with no command waiting
+    /// for them, such as late answers to a command given up
+    pub fn stray_answers(&self) -> u64 {
+        self.stray_answers
+    }
+
+    /// Whether a command waits to be sent or for the keyboard's answer
+    pub fn has_pending_commands(&self) -> bool {
+        !self.commands.is_empty()
+    }
+
+    fn queue_command(&mut self, command: PendingCommand) {
+        if self.commands.len() < KEYBOARD_COMMAND_QUEUE {
+            self.commands.push_back(command);
+        }
+    }
+
+    /// Move the keyboard commands along, from the driver's main loop
+    ///
+    /// Sends the next byte of the command in flight once the keyboard has
+    /// answered the last one, and starts an LED update when the lock state
+    /// no longer matches the LEDs. The interrupt path only records the
+    /// answers, so it never waits on the keyboard. A command the keyboard
+    /// does not answer in time is given up; for the LEDs, the keyboard is
+    /// not asked again until the lock state next changes.
+    pub fn send_pending_commands(&mut self) {
+        if self.commands.is_empty() {
+            let leds = Self::led_byte(self.modifiers);
+            if leds == self.leds {
+                return;
+            }
+            self.leds = leds;
+            self.commands.push_back(PendingCommand::new(KEYBOARD_SET_LEDS, leds));
+        }
+        let now = (self.clock)();
+        let Some(command) = self.commands.front_mut() else {
+            return;
+        };
+        match command.sent_ms {
+            Some(sent_ms) if now.saturating_sub(sent_ms) >= KEYBOARD_COMMAND_TIMEOUT_MS => {
+                self.commands.pop_front();
+            }
+            Some(_) => {}
+            None => {
+                let byte = command.bytes[command.next];
+                command.tries += 1;
+                command.sent_ms = Some(now);
+                if self.write_data(byte).is_err() {
+                    self.commands.pop_front();
                 }
             }
-            KeyCode::X => {
-                let base_char = 'x';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
+        }
+    }
+
+    /// Match a byte from the keyboard to the command in flight, returning
+    /// false if it is no answer to one
+    fn take_answer(&mut self, byte: u8) -> bool {
+        let Some(command) = self.commands.front_mut().filter(|command| command.sent_ms.is_some()) else {
+            return false;
+        };
+        match byte {
+            KEYBOARD_ACK => {
+                command.next += 1;
+                command.tries = 0;
+                command.sent_ms = None;
+                if command.next == command.bytes.len() {
+                    self.commands.pop_front();
                 }
             }
-            KeyCode::Y => {
-                let base_char = 'y';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
+            KEYBOARD_RESEND if command.tries < KEYBOARD_COMMAND_TRIES => command.sent_ms = None,
+            KEYBOARD_RESEND => {
+                self.commands.pop_front();
             }
-            KeyCode::Z => {
-                let base_char = 'z';
-                if shift_pressed ^ caps_lock {
-                    Some(base_char.to_ascii_uppercase())
-                } else {
-                    Some(base_char)
-                }
-            }
-            
-            // Numbers
-            KeyCode::Key0 => Some(if shift_pressed { ')' } else { '0' }),
-            KeyCode::Key1 => Some(if shift_pressed { '!' } else { '1' }),
-            KeyCode::Key2 => Some(if shift_pressed { '@' } else { '2' }),
-            KeyCode::Key3 => Some(if shift_pressed { '#' } else { '3' }),
-            KeyCode::Key4 => Some(if shift_pressed { '$' } else { '4' }),
-            KeyCode::Key5 => Some(if shift_pressed { '%' } else { '5' }),
-            KeyCode::Key6 => Some(if shift_pressed { '^' } else { '6' }),
-            KeyCode::Key7 => Some(if shift_pressed { '&' } else { '7' }),
-            KeyCode::Key8 => Some(if shift_pressed { '*' } else { '8' }),
-            KeyCode::Key9 => Some(if shift_pressed { '(' } else { '9' }),
-            
-            // Special characters
-            KeyCode::Space => Some(' '),
-            KeyCode::Tab => Some('\t'),
-            KeyCode::Enter => Some('\n'),
-            KeyCode::Backspace => Some('\x08'),
-            
-            _ => None,
+            _ => return false,
         }
+        true
+    }
+
+    /// Give a keyboard that has just been reset the LEDs and typematic
+    /// setting it had
+    fn restore_keyboard_settings(&mut self) {
+        // A reset turns the LEDs off, restores the default typematic and
+        // forgets any command it was given
+        self.leds = 0;
+        self.commands.clear();
+        if self.typematic != Typematic::DEFAULT {
+            self.set_typematic(self.typematic);
+        }
+    }
+
+    // Unit tests poke at the decoding through the driver's own state
+
+    /// Convert scancode to keycode
+    #[cfg(test)]
+    fn scancode_to_keycode(&self, scancode: u8) -> KeyCode {
+        scancode::scancode_to_keycode(scancode, self.extended_scancode)
+    }
+
+    /// Convert keycode to ASCII character (considering modifiers)
+    #[cfg(test)]
+    fn keycode_to_ascii(&self, key_code: KeyCode) -> Option<char> {
+        keycode_to_ascii(key_code, self.modifiers)
     }
 
     /// Update modifier state based on key press/release
+    #[cfg(test)]
     fn update_modifiers(&mut self, key_code: KeyCode, event_type: KeyEventType) {
-        match (key_code, event_type) {
-            (KeyCode::LeftShift | KeyCode::RightShift, KeyEventType::KeyPress) => {
-                self.modifiers.insert(KeyModifiers::SHIFT);
-            }
-            (KeyCode::LeftShift | KeyCode::RightShift, KeyEventType::KeyRelease) => {
-                self.modifiers.remove(KeyModifiers::SHIFT);
-            }
-            (KeyCode::LeftCtrl, KeyEventType::KeyPress) => {
-                self.modifiers.insert(KeyModifiers::CTRL);
-            }
-            (KeyCode::LeftCtrl, KeyEventType::KeyRelease) => {
-                self.modifiers.remove(KeyModifiers::CTRL);
-            }
-            (KeyCode::LeftAlt, KeyEventType::KeyPress) => {
-                self.modifiers.insert(KeyModifiers::ALT);
-            }
-            (KeyCode::LeftAlt, KeyEventType::KeyRelease) => {
-                self.modifiers.remove(KeyModifiers::ALT);
-            }
-            (KeyCode::CapsLock, KeyEventType::KeyPress) => {
-                self.modifiers.toggle(KeyModifiers::CAPS_LOCK);
-            }
-            _ => {}
-        }
+        self.modifiers = scancode::update_modifiers(self.modifiers, key_code, event_type);
     }
 
     /// Process a scancode and generate input events
     fn process_scancode(&mut self, scancode: u8) {
-        // Handle extended scancodes (0xE0 prefix)
-        if scancode == 0xE0 {
-            self.extended_scancode = true;
-            return;
+        if let Some((start_ms, recording)) = self.recording.as_mut() {
+            recording.push((self.clock)().saturating_sub(*start_ms), scancode);
         }
 
-        // Determine if this is a key press or release
-        let is_release = (scancode & 0x80) != 0;
-        let base_scancode = scancode & 0x7F;
-        let event_type = if is_release {
-            KeyEventType::KeyRelease
-        } else {
-            KeyEventType::KeyPress
-        };
-
-        // Convert scancode to keycode
-        let key_code = self.scancode_to_keycode(base_scancode);
-        
-        // Update modifier state
-        self.update_modifiers(key_code, event_type);
-        
-        // Generate ASCII character if applicable
-        let ascii_char = if event_type == KeyEventType::KeyPress {
-            self.keycode_to_ascii(key_code)
-        } else {
-            None
-        };
-
-        // Create input event
-        let event = InputEvent {
-            event_type,
-            key_code,
-            scancode: base_scancode,
-            modifiers: self.modifiers,
-            ascii_char,
-            timestamp: 0, // In real implementation, would use actual timestamp
-        };
-
-        // Add to event queue
-        self.queue_event(event);
-        
-        // Reset extended scancode flag
-        self.extended_scancode = false;
+        match scancode::decode_scancode(scancode, self.extended_scancode, self.modifiers) {
+            // The next scancode is an extended key
+            Decoded::Prefix => self.extended_scancode = true,
+            Decoded::Key(key) => {
+                self.modifiers = key.modifiers;
+                let event = InputEvent {
+                    event_type: key.event_type,
+                    key_code: key.key_code,
+                    scancode: key.scancode,
+                    modifiers: key.modifiers,
+                    ascii_char: key.ascii_char,
+                    timestamp: (self.clock)(),
+                };
+                self.queue_event(event);
+                self.extended_scancode = false;
+            }
+        }
     }
 
     /// Add an event to the input queue
@@ -552,32 +453,147 @@ impl PS2KeyboardDriver {
         self.event_queue.len()
     }
 
+    /// Send the queued events as input batches with `send`
+    ///
+    /// Events that could not be sent stay queued.
+    pub fn forward_events<F>(&mut self, mut send: F) -> Result<(), IpcError>
+    where
+        F: FnMut(&[u8]) -> Result<(), IpcError>,
+    {
+        while self.has_events() {
+            let records: Vec<InputRecord> = self.event_queue.iter().cloned().map(InputRecord::Key).collect();
+            let (batch, count) = encode_input_batch(&records);
+            send(&batch)?;
+            self.event_queue.drain(..count);
+        }
+        Ok(())
+    }
+
     /// Clear all queued events
     pub fn clear_events(&mut self) {
         self.event_queue.clear();
     }
 
+    /// Start capturing every scancode the driver decodes, replacing any
+    /// unfinished recording
+    pub fn start_recording(&mut self) {
+        self.recording = Some(((self.clock)(), InputRecording::new()));
+    }
+
+    /// Stop capturing and hand out what was recorded
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        self.recording.take().map(|(_, recording)| recording)
+    }
+
+    /// Whether scancodes are being captured
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Start injecting `recording`, replacing any unfinished replay
+    ///
+    /// Replayed scancodes go through the same path as injected key presses,
+    /// so they are decoded, queued and recorded like hardware input. Call
+    /// `poll_replay` to inject the ones that are due.
+    pub fn start_replay(&mut self, recording: InputRecording, speed: ReplaySpeed) {
+        self.replay = Some(Replay::new(recording, speed, (self.clock)()));
+    }
+
+    /// Abandon the running replay
+    pub fn stop_replay(&mut self) {
+        self.replay = None;
+    }
+
+    /// Whether a replay is still injecting scancodes
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// When the next replayed scancode is due, on the driver's clock
+    pub fn next_replay_ms(&self) -> Option<u64> {
+        self.replay.as_ref()?.next_due_ms()
+    }
+
+    /// Inject the replayed scancodes that are due, returning how many
+    pub fn poll_replay(&mut self) -> usize {
+        let Some(mut replay) = self.replay.take() else {
+            return 0;
+        };
+        let now_ms = (self.clock)();
+        let mut injected = 0;
+        while let Some(scancode) = replay.next_due(now_ms) {
+            self.process_scancode(scancode);
+            injected += 1;
+        }
+        if !replay.is_finished() {
+            self.replay = Some(replay);
+        }
+        injected
+    }
+
     /// Handle keyboard interrupt (would be called by interrupt handler)
+    ///
+    /// An answer to the command in flight moves it along; a key that
+    /// toggles a lock leaves relighting the LEDs to `send_pending_commands`.
     pub fn handle_interrupt(&mut self) {
         let status = self.read_status();
         
         if status.contains(PS2Status::OUTPUT_BUFFER_FULL) {
             let scancode = self.read_data();
+            if self.take_answer(scancode) {
+                return;
+            }
+            // An answer nothing waits for is not a key
+            if scancode == KEYBOARD_ACK || scancode == KEYBOARD_RESEND {
+                self.stray_answers += 1;
+                return;
+            }
             self.process_scancode(scancode);
         }
     }
 
     /// Initialize the PS/2 keyboard controller
     fn initialize_controller(&mut self) -> Result<(), DriverError> {
-        // In a real implementation, this would:
-        // 1. Disable interrupts
-        // 2. Flush output buffer
-        // 3. Set controller configuration
-        // 4. Enable keyboard
-        // 5. Set scan code set
-        // 6. Enable interrupts
-        
-        // For now, just simulate successful initialization
+        // Keep both devices quiet while the controller is reconfigured
+        self.write_command(PS2_CMD_DISABLE_PORT1)?;
+        self.write_command(PS2_CMD_DISABLE_PORT2)?;
+
+        // Drop anything left in the output buffer
+        for _ in 0..PS2_MAX_FLUSH {
+            if !self.read_status().contains(PS2Status::OUTPUT_BUFFER_FULL) {
+                break;
+            }
+            self.read_data();
+        }
+
+        // Mask interrupts during the self tests; scancode translation stays
+        // on so the keyboard keeps reporting set 1 scancodes
+        self.write_command(PS2_CMD_READ_CONFIG)?;
+        let config = self.read_response()? & !(PS2_CONFIG_PORT1_IRQ | PS2_CONFIG_PORT2_IRQ);
+        self.write_command(PS2_CMD_WRITE_CONFIG)?;
+        self.write_data(config)?;
+
+        self.write_command(PS2_CMD_SELF_TEST)?;
+        if self.read_response()? != PS2_SELF_TEST_PASSED {
+            return Err(DriverError::HardwareNotFound);
+        }
+
+        self.write_command(PS2_CMD_TEST_PORT1)?;
+        if self.read_response()? != PS2_PORT_TEST_PASSED {
+            return Err(DriverError::HardwareNotFound);
+        }
+
+        // Enable the keyboard port and its interrupt
+        self.write_command(PS2_CMD_ENABLE_PORT1)?;
+        self.write_command(PS2_CMD_WRITE_CONFIG)?;
+        self.write_data((config | PS2_CONFIG_PORT1_IRQ) & !PS2_CONFIG_PORT1_CLOCK_DISABLED)?;
+
+        // Reset the keyboard itself: ACK followed by basic assurance test result
+        self.write_data(KEYBOARD_RESET)?;
+        if self.read_response()? != KEYBOARD_ACK || self.read_response()? != KEYBOARD_BAT_PASSED {
+            return Err(DriverError::InitializationFailed);
+        }
+
         Ok(())
     }
 }
@@ -592,9 +608,13 @@ impl KoshDriver for PS2KeyboardDriver {
         // Clear any existing events
         self.clear_events();
         
-        // Reset modifier state
+        // Reset modifier state; the reset left the LEDs off and the
+        // typematic setting at its default
         self.modifiers = KeyModifiers::empty();
         self.extended_scancode = false;
+        self.leds = 0;
+        self.typematic = Typematic::DEFAULT;
+        self.commands.clear();
         
         self.status = DriverStatus::Ready;
         Ok(())
@@ -668,6 +688,46 @@ impl KoshDriver for PS2KeyboardDriver {
                             Err(DriverError::InvalidRequest)
                         }
                     }
+                    // Start recording
+                    0x04 => {
+                        self.start_recording();
+                        Ok(DriverResponse::Success)
+                    }
+                    // Stop recording and return it in the recording format
+                    0x05 => {
+                        let recording = self.stop_recording().ok_or(DriverError::InvalidRequest)?;
+                        Ok(DriverResponse::Data(recording.to_bytes()))
+                    }
+                    // Replay a recording: speed byte, then the recording
+                    0x06 => {
+                        let (&speed, recording) = data.split_first().ok_or(DriverError::InvalidRequest)?;
+                        let recording = InputRecording::from_bytes(recording)?;
+                        self.start_replay(recording, ReplaySpeed::from_byte(speed));
+                        self.poll_replay();
+                        Ok(DriverResponse::Success)
+                    }
+                    // Stop replaying
+                    0x07 => {
+                        self.stop_replay();
+                        Ok(DriverResponse::Success)
+                    }
+                    // Set the lock state and LEDs: a `KeyModifiers` byte
+                    0x08 => {
+                        let &locks = data.first().ok_or(DriverError::InvalidRequest)?;
+                        self.set_lock_state(KeyModifiers::from_bits_truncate(locks));
+                        Ok(DriverResponse::Success)
+                    }
+                    // Set the typematic delay and repeat interval: two
+                    // little-endian u16 millisecond counts
+                    0x09 => {
+                        if data.len() < 4 {
+                            return Err(DriverError::InvalidRequest);
+                        }
+                        let delay_ms = u16::from_le_bytes([data[0], data[1]]);
+                        let interval_ms = u16::from_le_bytes([data[2], data[3]]);
+                        self.set_typematic(Typematic::from_ms(delay_ms, interval_ms));
+                        Ok(DriverResponse::Success)
+                    }
                     _ => Err(DriverError::InvalidRequest)
                 }
             }
@@ -687,6 +747,7 @@ impl KoshDriver for PS2KeyboardDriver {
                             self.event_count() as u8,
                             self.max_queue_size as u8,
                             self.modifiers.bits(),
+                            self.typematic.to_byte(),
                         ];
                         Ok(DriverResponse::Data(stats))
                     }
@@ -760,7 +821,9 @@ impl KoshDriver for PS2KeyboardDriver {
             PowerEvent::Resume => {
                 self.status = DriverStatus::Ready;
                 // Reinitialize controller
-                self.initialize_controller()
+                self.initialize_controller()?;
+                self.restore_keyboard_settings();
+                Ok(())
             }
             PowerEvent::PowerDown => {
                 self.cleanup()
@@ -806,6 +869,71 @@ pub fn keyboard_has_events() -> bool {
     }
 }
 
+/// Start recording input on the global keyboard driver
+pub fn keyboard_start_recording() -> Result<(), DriverError> {
+    let mut driver_guard = KEYBOARD_DRIVER.lock();
+    let driver = driver_guard.as_mut().ok_or(DriverError::InitializationFailed)?;
+    driver.start_recording();
+    Ok(())
+}
+
+/// Stop recording input and store the recording at `path`
+pub fn keyboard_save_recording(path: &str) -> Result<(), DriverError> {
+    let recording = {
+        let mut driver_guard = KEYBOARD_DRIVER.lock();
+        let driver = driver_guard.as_mut().ok_or(DriverError::InitializationFailed)?;
+        driver.stop_recording().ok_or(DriverError::InvalidRequest)?
+    };
+    recording::save(path, &recording)
+}
+
+/// Replay the recording stored at `path` into the global keyboard driver
+pub fn keyboard_replay_file(path: &str, speed: ReplaySpeed) -> Result<(), DriverError> {
+    let recording = recording::load(path)?;
+    let mut driver_guard = KEYBOARD_DRIVER.lock();
+    let driver = driver_guard.as_mut().ok_or(DriverError::InitializationFailed)?;
+    driver.start_replay(recording, speed);
+    Ok(())
+}
+
+/// Inject due replayed scancodes into the global keyboard driver
+pub fn keyboard_poll_replay() -> usize {
+    let mut driver_guard = KEYBOARD_DRIVER.lock();
+    match driver_guard.as_mut() {
+        Some(driver) => driver.poll_replay(),
+        None => 0,
+    }
+}
+
+/// Whether the global keyboard driver is replaying a recording
+pub fn keyboard_is_replaying() -> bool {
+    KEYBOARD_DRIVER.lock().as_ref().is_some_and(|driver| driver.is_replaying())
+}
+
+/// Send the global keyboard driver's queued events to the input service
+/// at `pid`
+pub fn keyboard_forward_events(pid: ProcessId) -> Result<(), IpcError> {
+    let mut driver_guard = KEYBOARD_DRIVER.lock();
+    match driver_guard.as_mut() {
+        Some(driver) => driver.forward_events(|batch| kosh_ipc::syscall::send_message(pid, batch)),
+        None => Ok(()),
+    }
+}
+
+/// Send the global keyboard driver's due keyboard commands, from the
+/// driver's main loop
+pub fn keyboard_send_commands() {
+    if let Some(driver) = KEYBOARD_DRIVER.lock().as_mut() {
+        driver.send_pending_commands();
+    }
+}
+
+/// Whether the global keyboard driver has a command waiting to be sent or
+/// answered
+pub fn keyboard_has_pending_commands() -> bool {
+    KEYBOARD_DRIVER.lock().as_ref().is_some_and(|driver| driver.has_pending_commands())
+}
+
 /// Handle keyboard interrupt (called by interrupt handler)
 pub fn keyboard_interrupt_handler() {
     let mut driver_guard = KEYBOARD_DRIVER.lock();
@@ -840,6 +968,9 @@ pub fn register_keyboard_driver() -> Result<(), DriverError> {
     init_keyboard_driver()
 }
 
+#[cfg(test)]
+mod fixtures;
+
 #[cfg(test)]
 mod tests;
 
@@ -3,35 +3,77 @@
 
 extern crate alloc;
 
-use kosh_keyboard_driver::{keyboard_interrupt_handler, register_keyboard_driver};
+use kosh_driver::input::INPUT_SERVICE_NAME;
+use kosh_driver::report;
+use kosh_driver::{DriverMetadataRecord, DriverSignatureRecord, DriverType};
+use kosh_ipc::irq::{self, IRQ_WAIT_NOHANG};
+use kosh_posix::NamedProcess;
+use kosh_keyboard_driver::{
+    keyboard_forward_events, keyboard_has_pending_commands, keyboard_interrupt_handler, keyboard_is_replaying,
+    keyboard_poll_replay, keyboard_send_commands, register_keyboard_driver,
+};
+
+/// IRQ line of the PS/2 keyboard port
+const KEYBOARD_IRQ: u32 = 1;
+
+/// Read by the driver manager; the PS/2 controller is not enumerable, so
+/// no hardware IDs are listed, only its data and command ports
+#[used]
+#[link_section = ".kosh_driver"]
+static DRIVER_METADATA: DriverMetadataRecord = DriverMetadataRecord::new("ps2-keyboard", "0.1.0", DriverType::Input)
+    .requires_io_ports(0x60, 0x64)
+    .requires_irq(KEYBOARD_IRQ);
+
+/// Filled in when the driver is signed
+#[used]
+#[link_section = ".kosh_signature"]
+static DRIVER_SIGNATURE: DriverSignatureRecord = DriverSignatureRecord::UNSIGNED;
 
 /// Entry point for the keyboard driver process
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     // Initialize the keyboard driver
     if let Err(e) = register_keyboard_driver() {
-        // In a real implementation, this would log the error
-        panic!("Failed to initialize keyboard driver: {:?}", e);
+        report::fail(format_args!("Failed to initialize keyboard driver: {:?}", e));
     }
 
+    if let Err(e) = irq::register(KEYBOARD_IRQ, 0) {
+        report::fail(format_args!("Failed to claim keyboard IRQ: {:?}", e));
+    }
+
+    // The input service may start after the driver, so it is looked up
+    // by name as input arrives
+    let mut input_service = NamedProcess::new(INPUT_SERVICE_NAME);
+
     // Main driver loop
     loop {
-        // In a real implementation, this would:
-        // 1. Wait for IPC messages from the driver manager
-        // 2. Handle driver requests
-        // 3. Process hardware interrupts
-        // 4. Send responses back to requesters
-
-        // For now, just halt
-        #[cfg(target_arch = "x86_64")]
-        unsafe {
-            core::arch::asm!("hlt");
+        // Relight the LEDs or send the next command byte; the interrupt
+        // handler only takes the keyboard's answers
+        keyboard_send_commands();
+
+        // A running replay, or a command that may time out, needs the loop
+        // to keep turning; otherwise sleep until the controller has a byte
+        let flags = if keyboard_is_replaying() || keyboard_has_pending_commands() { IRQ_WAIT_NOHANG } else { 0 };
+        match irq::wait(KEYBOARD_IRQ, flags) {
+            Ok(0) => {}
+            Ok(_) => {
+                keyboard_interrupt_handler();
+                let _ = irq::acknowledge(KEYBOARD_IRQ);
+            }
+            Err(e) => report::fail(format_args!("Waiting for keyboard IRQ failed: {:?}", e)),
         }
 
-        #[cfg(not(target_arch = "x86_64"))]
-        {
-            // For other architectures, just loop
-            continue;
+        // Inject replayed input that has come due
+        keyboard_poll_replay();
+
+        // Hand the keys to the input service; if it is not running yet
+        // they wait in the queue
+        if let Some(pid) = input_service.pid() {
+            // Sending fails once the service is gone; look it up again, as
+            // a restarted one runs under a new process ID
+            if keyboard_forward_events(pid).is_err() {
+                input_service.forget();
+            }
         }
     }
 }
@@ -39,14 +81,8 @@ pub extern "C" fn _start() -> ! {
 /// Panic handler for the driver (only in non-test builds)
 #[cfg(not(test))]
 #[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
-    // In a real implementation, this would log the panic and notify the driver manager
-    loop {
-        #[cfg(target_arch = "x86_64")]
-        unsafe {
-            core::arch::asm!("hlt");
-        }
-    }
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    report::report_panic("keyboard", info)
 }
 
 /// Keyboard interrupt handler entry point
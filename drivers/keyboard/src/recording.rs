@@ -0,0 +1,198 @@
+//! Recording and deterministic replay of keyboard input
+//!
+//! A recording holds the raw scancode stream together with the time each
+//! byte arrived. Replaying it feeds the same bytes through the scancode
+//! decoder, so extended-key prefixes and modifier state come out exactly
+//! as they did originally, and the resulting `InputEvent`s match.
+//!
+//! Recordings are stored through fs-service in a small binary format:
+//! the magic `KINP`, a format version byte, a little-endian `u32` entry
+//! count, then per entry a `u32` delay in milliseconds since the previous
+//! entry followed by the scancode byte.
+
+use alloc::vec::Vec;
+use kosh_posix::{close, open, read, write_all, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY};
+use kosh_types::DriverError;
+
+const MAGIC: &[u8; 4] = b"KINP";
+const FORMAT_VERSION: u8 = 1;
+const HEADER_LEN: usize = 9;
+const ENTRY_LEN: usize = 5;
+
+/// One scancode byte and when it arrived
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordedScancode {
+    /// Milliseconds since the recording started
+    pub time_ms: u64,
+    pub scancode: u8,
+}
+
+/// A captured scancode stream
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputRecording {
+    scancodes: Vec<RecordedScancode>,
+}
+
+impl InputRecording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a scancode seen `time_ms` after the recording started
+    ///
+    /// Times never go backwards; an earlier time is recorded as simultaneous
+    /// with the previous scancode.
+    pub fn push(&mut self, time_ms: u64, scancode: u8) {
+        let time_ms = self.scancodes.last().map_or(time_ms, |last| time_ms.max(last.time_ms));
+        self.scancodes.push(RecordedScancode { time_ms, scancode });
+    }
+
+    pub fn scancodes(&self) -> &[RecordedScancode] {
+        &self.scancodes
+    }
+
+    pub fn len(&self) -> usize {
+        self.scancodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scancodes.is_empty()
+    }
+
+    /// Time from the start of the recording to its last scancode
+    pub fn duration_ms(&self) -> u64 {
+        self.scancodes.last().map_or(0, |last| last.time_ms)
+    }
+
+    /// Encode in the on-disk format
+    ///
+    /// Delays longer than `u32::MAX` milliseconds (about 49 days) are
+    /// shortened to that.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.scancodes.len() * ENTRY_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&(self.scancodes.len() as u32).to_le_bytes());
+
+        let mut previous_ms = 0;
+        for entry in &self.scancodes {
+            let delay = (entry.time_ms - previous_ms).min(u32::MAX as u64) as u32;
+            bytes.extend_from_slice(&delay.to_le_bytes());
+            bytes.push(entry.scancode);
+            previous_ms = entry.time_ms;
+        }
+        bytes
+    }
+
+    /// Decode the on-disk format
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DriverError> {
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC || bytes[4] != FORMAT_VERSION {
+            return Err(DriverError::InvalidRequest);
+        }
+        let count = u32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]) as usize;
+        let entries = &bytes[HEADER_LEN..];
+        if entries.len() != count * ENTRY_LEN {
+            return Err(DriverError::InvalidRequest);
+        }
+
+        let mut recording = Self { scancodes: Vec::with_capacity(count) };
+        let mut time_ms = 0u64;
+        for entry in entries.chunks_exact(ENTRY_LEN) {
+            time_ms += u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) as u64;
+            recording.scancodes.push(RecordedScancode { time_ms, scancode: entry[4] });
+        }
+        Ok(recording)
+    }
+}
+
+/// How fast a recording is played back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaySpeed {
+    /// With the original timing
+    Original,
+    /// With all delays divided by the factor
+    Accelerated(u32),
+    /// Everything at once
+    Immediate,
+}
+
+impl ReplaySpeed {
+    /// Decode the speed byte of the replay control command: 0 is original
+    /// timing, `u8::MAX` immediate, anything else an acceleration factor
+    pub fn from_byte(byte: u8) -> Self {
+        match byte {
+            0 | 1 => ReplaySpeed::Original,
+            u8::MAX => ReplaySpeed::Immediate,
+            factor => ReplaySpeed::Accelerated(factor as u32),
+        }
+    }
+
+    fn scale(&self, time_ms: u64) -> u64 {
+        match *self {
+            ReplaySpeed::Original => time_ms,
+            ReplaySpeed::Accelerated(factor) => time_ms / factor.max(1) as u64,
+            ReplaySpeed::Immediate => 0,
+        }
+    }
+}
+
+/// Playback position in a recording
+#[derive(Debug, Clone)]
+pub struct Replay {
+    recording: InputRecording,
+    speed: ReplaySpeed,
+    start_ms: u64,
+    next: usize,
+}
+
+impl Replay {
+    /// Start playing `recording` at `now_ms`
+    pub fn new(recording: InputRecording, speed: ReplaySpeed, now_ms: u64) -> Self {
+        Self { recording, speed, start_ms: now_ms, next: 0 }
+    }
+
+    /// Next scancode whose time has come by `now_ms`
+    pub fn next_due(&mut self, now_ms: u64) -> Option<u8> {
+        let entry = self.recording.scancodes.get(self.next)?;
+        if self.start_ms + self.speed.scale(entry.time_ms) > now_ms {
+            return None;
+        }
+        self.next += 1;
+        Some(entry.scancode)
+    }
+
+    /// When the next scancode is due, for sleeping until then
+    pub fn next_due_ms(&self) -> Option<u64> {
+        let entry = self.recording.scancodes.get(self.next)?;
+        Some(self.start_ms + self.speed.scale(entry.time_ms))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.recording.scancodes.len()
+    }
+}
+
+/// Write a recording to `path` through fs-service
+pub fn save(path: &str, recording: &InputRecording) -> Result<(), DriverError> {
+    let fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0o644).map_err(|_| DriverError::PermissionDenied)?;
+    let written = write_all(fd, &recording.to_bytes());
+    let closed = close(fd);
+    written.and(closed).map_err(|_| DriverError::ResourceBusy)
+}
+
+/// Read a recording from `path` through fs-service
+pub fn load(path: &str) -> Result<InputRecording, DriverError> {
+    let fd = open(path, O_RDONLY, 0).map_err(|_| DriverError::PermissionDenied)?;
+    let mut bytes = Vec::new();
+    let mut chunk = [0u8; 1024];
+    let result = loop {
+        match read(fd, &mut chunk) {
+            Ok(0) => break Ok(()),
+            Ok(count) => bytes.extend_from_slice(&chunk[..count]),
+            Err(_) => break Err(DriverError::ResourceBusy),
+        }
+    };
+    let _ = close(fd);
+    result?;
+    InputRecording::from_bytes(&bytes)
+}
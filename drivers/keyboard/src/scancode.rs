@@ -0,0 +1,141 @@
+//! Scancode set 1 decoding
+//!
+//! The PS/2 driver's view of a scancode stream as pure functions of the
+//! byte, whether an `0xE0` prefix came before it and the modifiers held, so
+//! the decoding can be exercised on the host and fed arbitrary streams by
+//! the fuzz targets.
+
+use kosh_driver::input::{keycode_to_ascii, KeyCode, KeyEventType, KeyModifiers};
+
+/// Scancode that marks the next one as an extended key
+pub const EXTENDED_PREFIX: u8 = 0xE0;
+
+/// What one byte of a scancode stream decodes to
+#[derive(Debug, Clone, Copy)]
+pub enum Decoded {
+    /// `0xE0`: the next byte is an extended key
+    Prefix,
+    Key(DecodedKey),
+}
+
+/// A key press or release, with the modifiers in effect after it
+#[derive(Debug, Clone, Copy)]
+pub struct DecodedKey {
+    pub event_type: KeyEventType,
+    pub key_code: KeyCode,
+    /// Scancode without the release bit
+    pub scancode: u8,
+    pub modifiers: KeyModifiers,
+    /// Character typed, for presses only
+    pub ascii_char: Option<char>,
+}
+
+/// Decode `scancode`, `extended` when the byte before it was the prefix
+pub fn decode_scancode(scancode: u8, extended: bool, modifiers: KeyModifiers) -> Decoded {
+    if scancode == EXTENDED_PREFIX {
+        return Decoded::Prefix;
+    }
+
+    let base_scancode = scancode & 0x7F;
+    let event_type = if scancode & 0x80 != 0 {
+        KeyEventType::KeyRelease
+    } else {
+        KeyEventType::KeyPress
+    };
+    let key_code = scancode_to_keycode(base_scancode, extended);
+    let modifiers = update_modifiers(modifiers, key_code, event_type);
+    let ascii_char = match event_type {
+        KeyEventType::KeyPress => keycode_to_ascii(key_code, modifiers),
+        _ => None,
+    };
+
+    Decoded::Key(DecodedKey { event_type, key_code, scancode: base_scancode, modifiers, ascii_char })
+}
+
+/// Convert a scancode without its release bit to a keycode
+pub fn scancode_to_keycode(scancode: u8, extended: bool) -> KeyCode {
+    match scancode {
+        // Letters
+        0x1E => KeyCode::A, 0x30 => KeyCode::B, 0x2E => KeyCode::C, 0x20 => KeyCode::D,
+        0x12 => KeyCode::E, 0x21 => KeyCode::F, 0x22 => KeyCode::G, 0x23 => KeyCode::H,
+        0x17 => KeyCode::I, 0x24 => KeyCode::J, 0x25 => KeyCode::K, 0x26 => KeyCode::L,
+        0x32 => KeyCode::M, 0x31 => KeyCode::N, 0x18 => KeyCode::O, 0x19 => KeyCode::P,
+        0x10 => KeyCode::Q, 0x13 => KeyCode::R, 0x1F => KeyCode::S, 0x14 => KeyCode::T,
+        0x16 => KeyCode::U, 0x2F => KeyCode::V, 0x11 => KeyCode::W, 0x2D => KeyCode::X,
+        0x15 => KeyCode::Y, 0x2C => KeyCode::Z,
+
+        // Numbers
+        0x0B => KeyCode::Key0, 0x02 => KeyCode::Key1, 0x03 => KeyCode::Key2,
+        0x04 => KeyCode::Key3, 0x05 => KeyCode::Key4, 0x06 => KeyCode::Key5,
+        0x07 => KeyCode::Key6, 0x08 => KeyCode::Key7, 0x09 => KeyCode::Key8,
+        0x0A => KeyCode::Key9,
+
+        // Function keys
+        0x3B => KeyCode::F1, 0x3C => KeyCode::F2, 0x3D => KeyCode::F3, 0x3E => KeyCode::F4,
+        0x3F => KeyCode::F5, 0x40 => KeyCode::F6, 0x41 => KeyCode::F7, 0x42 => KeyCode::F8,
+        0x43 => KeyCode::F9, 0x44 => KeyCode::F10, 0x57 => KeyCode::F11, 0x58 => KeyCode::F12,
+
+        // Special keys
+        0x01 => KeyCode::Escape,
+        0x0E => KeyCode::Backspace,
+        0x0F => KeyCode::Tab,
+        0x1C => KeyCode::Enter,
+        0x39 => KeyCode::Space,
+        0x2A => KeyCode::LeftShift,
+        0x36 => KeyCode::RightShift,
+        0x1D => KeyCode::LeftCtrl,
+        0x38 => KeyCode::LeftAlt,
+        0x3A => KeyCode::CapsLock,
+        0x45 if !extended => KeyCode::NumLock,
+        0x46 if !extended => KeyCode::ScrollLock,
+
+        // Extended keys, after an 0xE0 prefix
+        0x48 if extended => KeyCode::ArrowUp,
+        0x50 if extended => KeyCode::ArrowDown,
+        0x4B if extended => KeyCode::ArrowLeft,
+        0x4D if extended => KeyCode::ArrowRight,
+        0x53 if extended => KeyCode::Delete,
+        0x47 if extended => KeyCode::Home,
+        0x4F if extended => KeyCode::End,
+        0x49 if extended => KeyCode::PageUp,
+        0x51 if extended => KeyCode::PageDown,
+        0x52 if extended => KeyCode::Insert,
+
+        _ => KeyCode::Unknown,
+    }
+}
+
+/// Modifiers in effect after a key press or release
+pub fn update_modifiers(mut modifiers: KeyModifiers, key_code: KeyCode, event_type: KeyEventType) -> KeyModifiers {
+    match (key_code, event_type) {
+        (KeyCode::LeftShift | KeyCode::RightShift, KeyEventType::KeyPress) => {
+            modifiers.insert(KeyModifiers::SHIFT);
+        }
+        (KeyCode::LeftShift | KeyCode::RightShift, KeyEventType::KeyRelease) => {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        (KeyCode::LeftCtrl, KeyEventType::KeyPress) => {
+            modifiers.insert(KeyModifiers::CTRL);
+        }
+        (KeyCode::LeftCtrl, KeyEventType::KeyRelease) => {
+            modifiers.remove(KeyModifiers::CTRL);
+        }
+        (KeyCode::LeftAlt, KeyEventType::KeyPress) => {
+            modifiers.insert(KeyModifiers::ALT);
+        }
+        (KeyCode::LeftAlt, KeyEventType::KeyRelease) => {
+            modifiers.remove(KeyModifiers::ALT);
+        }
+        (KeyCode::CapsLock, KeyEventType::KeyPress) => {
+            modifiers.toggle(KeyModifiers::CAPS_LOCK);
+        }
+        (KeyCode::NumLock, KeyEventType::KeyPress) => {
+            modifiers.toggle(KeyModifiers::NUM_LOCK);
+        }
+        (KeyCode::ScrollLock, KeyEventType::KeyPress) => {
+            modifiers.toggle(KeyModifiers::SCROLL_LOCK);
+        }
+        _ => {}
+    }
+    modifiers
+}
@@ -1,4 +1,5 @@
 use super::*;
+use crate::fixtures::replay_driver;
 use alloc::vec;
 use kosh_driver::{DriverRequest, DriverResponse, QueryType, DriverFactory};
 use bitflags::Flags;
@@ -13,7 +14,7 @@ fn test_keyboard_driver_creation() {
 
 #[test]
 fn test_keyboard_driver_initialization() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     let result = driver.init(vec![]);
     assert!(result.is_ok());
     assert_eq!(driver.get_status(), DriverStatus::Ready);
@@ -104,7 +105,7 @@ fn test_modifier_state_management() {
 
 #[test]
 fn test_scancode_processing() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Test key press
@@ -130,7 +131,7 @@ fn test_scancode_processing() {
 
 #[test]
 fn test_extended_scancode_processing() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Test extended scancode sequence (arrow key)
@@ -145,9 +146,38 @@ fn test_extended_scancode_processing() {
     assert_eq!(event.scancode, 0x48);
 }
 
+#[test]
+fn test_decode_scancode_without_driver() {
+    use crate::scancode::{decode_scancode, Decoded};
+
+    assert!(matches!(decode_scancode(0xE0, false, KeyModifiers::empty()), Decoded::Prefix));
+
+    // Left shift held, then A pressed and released
+    let Decoded::Key(shift) = decode_scancode(0x2A, false, KeyModifiers::empty()) else {
+        panic!("shift decoded as a prefix");
+    };
+    assert!(shift.modifiers.contains(KeyModifiers::SHIFT));
+    let Decoded::Key(press) = decode_scancode(0x1E, false, shift.modifiers) else {
+        panic!("A decoded as a prefix");
+    };
+    assert_eq!(press.ascii_char, Some('A'));
+    let Decoded::Key(release) = decode_scancode(0x9E, false, press.modifiers) else {
+        panic!("A release decoded as a prefix");
+    };
+    assert_eq!(release.event_type, KeyEventType::KeyRelease);
+    assert_eq!(release.scancode, 0x1E);
+    assert_eq!(release.ascii_char, None);
+
+    // 0x48 is arrow up only after the prefix
+    let Decoded::Key(arrow) = decode_scancode(0x48, true, KeyModifiers::empty()) else {
+        panic!("arrow up decoded as a prefix");
+    };
+    assert_eq!(arrow.key_code, KeyCode::ArrowUp);
+}
+
 #[test]
 fn test_event_queue_management() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Test queue size limit
@@ -170,7 +200,7 @@ fn test_event_queue_management() {
 
 #[test]
 fn test_driver_requests() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Test initialization request
@@ -192,7 +222,7 @@ fn test_driver_requests() {
 
 #[test]
 fn test_control_commands() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Add some events
@@ -227,7 +257,7 @@ fn test_control_commands() {
 
 #[test]
 fn test_read_events() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Add some events
@@ -255,7 +285,7 @@ fn test_read_events() {
 
 #[test]
 fn test_power_management() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Add some events
@@ -342,7 +372,7 @@ fn test_driver_factory() {
 
 #[test]
 fn test_caps_lock_behavior() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Test normal letter
@@ -370,7 +400,7 @@ fn test_caps_lock_behavior() {
 
 #[test]
 fn test_cleanup() {
-    let mut driver = PS2KeyboardDriver::new();
+    let mut driver = replay_driver();
     driver.init(vec![]).unwrap();
     
     // Add some events and set modifiers
@@ -385,4 +415,271 @@ fn test_cleanup() {
     assert_eq!(driver.get_status(), DriverStatus::Uninitialized);
     assert!(!driver.has_events());
     assert!(driver.modifiers.is_empty());
-}
\ No newline at end of file
+}
+#[test]
+fn test_replayed_init_handshake_and_interrupt() {
+    use crate::fixtures::{PS2_INIT_HANDSHAKE, PS2_KEY_A_PRESS_RELEASE};
+    use kosh_driver::hal::replay::ReplayIo;
+
+    let io = ReplayIo::new(PS2_INIT_HANDSHAKE).then(PS2_KEY_A_PRESS_RELEASE);
+    let mut driver = PS2KeyboardDriver::with_port_io(Box::new(io));
+    assert!(driver.init(vec![]).is_ok());
+    assert_eq!(driver.get_status(), DriverStatus::Ready);
+
+    driver.handle_interrupt();
+    driver.handle_interrupt();
+
+    let press = driver.get_next_event().unwrap();
+    assert_eq!(press.event_type, KeyEventType::KeyPress);
+    assert_eq!(press.key_code, KeyCode::A);
+    assert_eq!(press.ascii_char, Some('a'));
+
+    let release = driver.get_next_event().unwrap();
+    assert_eq!(release.event_type, KeyEventType::KeyRelease);
+    assert_eq!(release.key_code, KeyCode::A);
+    assert!(!driver.has_events());
+}
+
+#[test]
+fn test_replayed_self_test_failure() {
+    use crate::fixtures::PS2_SELF_TEST_FAILURE;
+    use kosh_driver::hal::replay::ReplayIo;
+
+    let mut driver = PS2KeyboardDriver::with_port_io(Box::new(ReplayIo::new(PS2_SELF_TEST_FAILURE)));
+    assert!(matches!(driver.init(vec![]), Err(DriverError::HardwareNotFound)));
+    assert_eq!(driver.get_status(), DriverStatus::Initializing);
+}
+
+#[test]
+fn test_mock_scancode_stream() {
+    let (mut driver, machine) = crate::fixtures::mock_driver();
+    // Reset, with the IRQ on and translation kept
+    assert_eq!(machine.keyboard_bytes(), [0xFF]);
+    assert_eq!(machine.ps2_config(), 0x45);
+
+    // Shift+H, then I
+    machine.push_scancodes(&[0x2A, 0x23, 0xA3, 0xAA, 0x17, 0x97]);
+    while machine.pending_scancodes() > 0 {
+        driver.handle_interrupt();
+    }
+    let mut typed = String::new();
+    while let Some(event) = driver.get_next_event() {
+        if event.event_type == KeyEventType::KeyPress {
+            typed.extend(event.ascii_char);
+        }
+    }
+    assert_eq!(typed, "Hi");
+}
+
+#[test]
+fn test_mock_caps_lock_lights_led() {
+    let (mut driver, machine) = crate::fixtures::mock_driver();
+    machine.push_scancodes(&[0x3A, 0xBA, 0x1E]);
+    for _ in 0..8 {
+        driver.handle_interrupt();
+        driver.send_pending_commands();
+    }
+
+    assert_eq!(machine.keyboard_bytes(), [0xFF, 0xED, 0x04]);
+    assert!(!driver.has_pending_commands());
+    assert_eq!(driver.stray_answers(), 0);
+    let typed: Vec<_> = core::iter::from_fn(|| driver.get_next_event())
+        .filter_map(|event| event.ascii_char)
+        .collect();
+    assert_eq!(typed, ['A']);
+}
+
+#[test]
+fn test_recording_format_round_trip() {
+    use crate::recording::InputRecording;
+
+    let mut recording = InputRecording::new();
+    recording.push(0, 0xE0);
+    recording.push(0, 0x48);
+    recording.push(120, 0x1E);
+    // Out of order times are clamped rather than stored backwards
+    recording.push(100, 0x9E);
+
+    let decoded = InputRecording::from_bytes(&recording.to_bytes()).unwrap();
+    assert_eq!(decoded, recording);
+    assert_eq!(decoded.duration_ms(), 120);
+
+    let mut truncated = recording.to_bytes();
+    truncated.pop();
+    assert!(matches!(InputRecording::from_bytes(&truncated), Err(DriverError::InvalidRequest)));
+    assert!(matches!(InputRecording::from_bytes(b"KINX\x01\0\0\0\0"), Err(DriverError::InvalidRequest)));
+}
+
+#[test]
+fn test_record_and_replay_with_timing() {
+    use crate::recording::ReplaySpeed;
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    static NOW_MS: AtomicU64 = AtomicU64::new(1000);
+    let mut driver = replay_driver();
+    driver.init(vec![]).unwrap();
+    driver.set_clock(|| NOW_MS.load(Ordering::SeqCst));
+
+    // Shift+A, then A alone 50 ms later
+    driver.start_recording();
+    driver.process_scancode(0x2A);
+    driver.process_scancode(0x1E);
+    driver.process_scancode(0xAA);
+    NOW_MS.store(1050, Ordering::SeqCst);
+    driver.process_scancode(0x1E);
+    let recording = driver.stop_recording().unwrap();
+    assert_eq!(recording.len(), 4);
+    assert_eq!(recording.duration_ms(), 50);
+    driver.clear_events();
+
+    // Played back twice as fast, the second A is due after 25 ms
+    NOW_MS.store(5000, Ordering::SeqCst);
+    driver.start_replay(recording, ReplaySpeed::Accelerated(2));
+    assert_eq!(driver.poll_replay(), 3);
+    assert_eq!(driver.next_replay_ms(), Some(5025));
+    NOW_MS.store(5024, Ordering::SeqCst);
+    assert_eq!(driver.poll_replay(), 0);
+    NOW_MS.store(5025, Ordering::SeqCst);
+    assert_eq!(driver.poll_replay(), 1);
+    assert!(!driver.is_replaying());
+
+    let chars: Vec<Option<char>> = core::iter::from_fn(|| driver.get_next_event())
+        .filter(|event| event.event_type == KeyEventType::KeyPress)
+        .map(|event| event.ascii_char)
+        .collect();
+    assert_eq!(chars, vec![None, Some('A'), Some('a')]);
+}
+
+#[test]
+fn test_record_and_replay_commands() {
+    let mut driver = replay_driver();
+    driver.init(vec![]).unwrap();
+    driver.set_clock(|| 0);
+
+    let start = DriverRequest::Control { command: 0x04, data: vec![] };
+    assert!(matches!(driver.handle_request(start), Ok(DriverResponse::Success)));
+    driver.process_scancode(0x23); // H
+    driver.process_scancode(0x17); // I
+    let stop = DriverRequest::Control { command: 0x05, data: vec![] };
+    let bytes = match driver.handle_request(stop) {
+        Ok(DriverResponse::Data(bytes)) => bytes,
+        _ => panic!("Expected recording data"),
+    };
+    assert!(!driver.is_recording());
+    driver.clear_events();
+
+    // Immediate replay injects everything at once
+    let mut data = vec![u8::MAX];
+    data.extend(bytes);
+    let replay = DriverRequest::Control { command: 0x06, data };
+    assert!(matches!(driver.handle_request(replay), Ok(DriverResponse::Success)));
+    assert_eq!(driver.get_next_event().unwrap().ascii_char, Some('h'));
+    assert_eq!(driver.get_next_event().unwrap().ascii_char, Some('i'));
+
+    let bad = DriverRequest::Control { command: 0x06, data: vec![0, 1, 2] };
+    assert!(matches!(driver.handle_request(bad), Err(DriverError::InvalidRequest)));
+}
+
+#[test]
+fn test_forward_events_as_input_batches() {
+    let mut driver = PS2KeyboardDriver::new();
+    driver.process_scancode(0x23); // H
+    driver.process_scancode(0xA3); // H released
+
+    // Nothing is lost while the input service cannot take the batch
+    assert!(driver.forward_events(|_| Err(IpcError::ChannelFull)).is_err());
+    assert_eq!(driver.event_count(), 2);
+
+    let mut batches = Vec::new();
+    driver.forward_events(|batch| {
+        batches.push(kosh_driver::input::decode_input_batch(batch).unwrap());
+        Ok(())
+    }).unwrap();
+    assert!(!driver.has_events());
+    assert_eq!(batches.len(), 1);
+    match &batches[0][..] {
+        [InputRecord::Key(press), InputRecord::Key(release)] => {
+            assert_eq!((press.event_type, press.key_code, press.ascii_char), (KeyEventType::KeyPress, KeyCode::H, Some('h')));
+            assert_eq!(release.event_type, KeyEventType::KeyRelease);
+        }
+        other => panic!("Expected two key records, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_typematic_encoding() {
+    assert_eq!(Typematic::DEFAULT.delay_ms(), 500);
+    assert_eq!(Typematic::DEFAULT.interval_ms(), 92);
+    assert_eq!(Typematic::from_ms(500, 92), Typematic::DEFAULT);
+
+    let typematic = Typematic::from_ms(750, 100);
+    assert_eq!(typematic.to_byte(), 0x4C);
+    assert_eq!((typematic.delay_ms(), typematic.interval_ms()), (750, 100));
+
+    // Out of range values take the nearest setting
+    assert_eq!(Typematic::from_ms(0, 0).to_byte(), 0x00);
+    assert_eq!(Typematic::from_ms(u16::MAX, u16::MAX).to_byte(), 0x7F);
+}
+
+#[test]
+fn test_lock_leds_and_typematic_commands() {
+    use crate::fixtures::{PS2_INIT_HANDSHAKE, PS2_CAPS_LOCK_LEDS, PS2_TYPEMATIC_WITH_RESEND};
+    use kosh_driver::hal::replay::ReplayIo;
+
+    let io = ReplayIo::new(PS2_INIT_HANDSHAKE).then(PS2_CAPS_LOCK_LEDS).then(PS2_TYPEMATIC_WITH_RESEND);
+    let mut driver = PS2KeyboardDriver::with_port_io(Box::new(io));
+    driver.init(vec![]).unwrap();
+
+    // The interrupt only takes the key; the main loop sends each command
+    // byte once the keyboard has acknowledged the last
+    driver.handle_interrupt();
+    assert!(driver.modifiers.contains(KeyModifiers::CAPS_LOCK));
+    driver.send_pending_commands();
+    assert_eq!(driver.leds, LED_CAPS_LOCK);
+    driver.handle_interrupt();
+    driver.send_pending_commands();
+    driver.handle_interrupt();
+    assert!(!driver.has_pending_commands());
+
+    let response = driver.handle_request(DriverRequest::Control {
+        command: 0x09,
+        data: [750u16.to_le_bytes(), 100u16.to_le_bytes()].concat(),
+    });
+    assert!(matches!(response, Ok(DriverResponse::Success)));
+    assert_eq!(driver.typematic().to_byte(), 0x4C);
+    for _ in 0..3 {
+        driver.send_pending_commands();
+        driver.handle_interrupt();
+    }
+    assert!(!driver.has_pending_commands());
+
+    // The LEDs already show this state, so nothing is sent
+    let response = driver.handle_request(DriverRequest::Control {
+        command: 0x08,
+        data: vec![(KeyModifiers::CAPS_LOCK | KeyModifiers::SHIFT).bits()],
+    });
+    assert!(matches!(response, Ok(DriverResponse::Success)));
+    assert!(!driver.modifiers.contains(KeyModifiers::SHIFT));
+    driver.send_pending_commands();
+    assert!(!driver.has_pending_commands());
+
+    let short = DriverRequest::Control { command: 0x09, data: vec![1, 2] };
+    assert!(driver.handle_request(short).is_err());
+}
+
+#[test]
+fn test_unmatched_answers_are_counted() {
+    let (mut driver, machine) = crate::fixtures::mock_driver();
+
+    // An acknowledge with no command in flight is neither a key nor an
+    // answer
+    machine.push_scancodes(&[0xFA, 0xFE, 0x1E]);
+    while machine.pending_scancodes() > 0 {
+        driver.handle_interrupt();
+    }
+    assert_eq!(driver.stray_answers(), 2);
+    let typed: Vec<_> = core::iter::from_fn(|| driver.get_next_event())
+        .filter_map(|event| event.ascii_char)
+        .collect();
+    assert_eq!(typed, ['a']);
+}
@@ -0,0 +1,220 @@
+//! Legacy (PIO) ATA controller access
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use kosh_driver::hal::PortIo;
+use kosh_types::DriverError;
+
+/// I/O base of the primary ATA channel
+pub const PRIMARY_IO_BASE: u16 = 0x1F0;
+
+/// Task file register offsets from the I/O base
+const REG_DATA: u16 = 0;
+const REG_SECTOR_COUNT: u16 = 2;
+const REG_LBA_LOW: u16 = 3;
+const REG_LBA_MID: u16 = 4;
+const REG_LBA_HIGH: u16 = 5;
+const REG_DRIVE_SELECT: u16 = 6;
+const REG_STATUS: u16 = 7;
+const REG_COMMAND: u16 = 7;
+
+/// Status register bits
+const STATUS_ERR: u8 = 1 << 0;
+const STATUS_DRQ: u8 = 1 << 3;
+const STATUS_BSY: u8 = 1 << 7;
+
+const CMD_READ_SECTORS: u8 = 0x20;
+const CMD_IDENTIFY: u8 = 0xEC;
+
+/// Drive select bit for LBA addressing
+const DRIVE_SELECT_LBA: u8 = 1 << 6;
+
+/// Status polls before a command is considered hung
+const ATA_TIMEOUT_POLLS: usize = 100_000;
+
+/// Words in an IDENTIFY DEVICE response
+pub const IDENTIFY_WORDS: usize = 256;
+
+/// Bytes in a sector
+pub const SECTOR_SIZE: usize = 512;
+
+/// Sectors one READ SECTORS command transfers at most
+const MAX_SECTORS_PER_COMMAND: usize = 256;
+
+/// First sector 28-bit LBA cannot address
+pub const LBA28_LIMIT: u64 = 1 << 28;
+
+/// Drive on an ATA channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtaDrive {
+    Master,
+    Slave,
+}
+
+impl AtaDrive {
+    fn select_value(self) -> u8 {
+        match self {
+            AtaDrive::Master => 0xA0,
+            AtaDrive::Slave => 0xB0,
+        }
+    }
+}
+
+/// Device identity decoded from IDENTIFY DEVICE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtaIdentity {
+    pub model: String,
+    pub serial: String,
+    pub firmware: String,
+    /// Sectors addressable with 28-bit LBA
+    pub lba28_sectors: u32,
+    /// Sectors addressable with 48-bit LBA, if supported
+    pub lba48_sectors: Option<u64>,
+}
+
+impl AtaIdentity {
+    /// Decode a raw IDENTIFY DEVICE block
+    pub fn parse(words: &[u16; IDENTIFY_WORDS]) -> Self {
+        let lba48_supported = words[83] & (1 << 10) != 0;
+        let lba48_sectors = (words[100] as u64)
+            | (words[101] as u64) << 16
+            | (words[102] as u64) << 32
+            | (words[103] as u64) << 48;
+
+        Self {
+            model: ata_string(&words[27..47]),
+            serial: ata_string(&words[10..20]),
+            firmware: ata_string(&words[23..27]),
+            lba28_sectors: words[60] as u32 | (words[61] as u32) << 16,
+            lba48_sectors: if lba48_supported { Some(lba48_sectors) } else { None },
+        }
+    }
+
+    /// Total addressable sectors
+    pub fn sector_count(&self) -> u64 {
+        self.lba48_sectors.unwrap_or(self.lba28_sectors as u64)
+    }
+}
+
+/// ATA strings store two characters per word, first character in the high byte
+fn ata_string(words: &[u16]) -> String {
+    let mut text = String::new();
+    for word in words {
+        text.push((word >> 8) as u8 as char);
+        text.push((word & 0xFF) as u8 as char);
+    }
+    String::from(text.trim())
+}
+
+/// One ATA channel driven through programmed I/O
+pub struct AtaController {
+    io: Box<dyn PortIo>,
+    base: u16,
+}
+
+impl AtaController {
+    pub fn new(io: Box<dyn PortIo>, base: u16) -> Self {
+        Self { io, base }
+    }
+
+    fn read_status(&mut self) -> u8 {
+        self.io.read_u8(self.base + REG_STATUS)
+    }
+
+    /// Issue IDENTIFY DEVICE and decode the response
+    pub fn identify(&mut self, drive: AtaDrive) -> Result<AtaIdentity, DriverError> {
+        self.io.write_u8(self.base + REG_DRIVE_SELECT, drive.select_value());
+        self.io.write_u8(self.base + REG_SECTOR_COUNT, 0);
+        self.io.write_u8(self.base + REG_LBA_LOW, 0);
+        self.io.write_u8(self.base + REG_LBA_MID, 0);
+        self.io.write_u8(self.base + REG_LBA_HIGH, 0);
+        self.io.write_u8(self.base + REG_COMMAND, CMD_IDENTIFY);
+
+        // A status of zero means nothing is attached
+        let mut status = self.read_status();
+        if status == 0 {
+            return Err(DriverError::HardwareNotFound);
+        }
+
+        let mut polls = 0;
+        while status & STATUS_BSY != 0 {
+            polls += 1;
+            if polls == ATA_TIMEOUT_POLLS {
+                return Err(DriverError::InitializationFailed);
+            }
+            status = self.read_status();
+        }
+
+        // ATAPI and SATA devices leave their signature in the LBA registers
+        if self.io.read_u8(self.base + REG_LBA_MID) != 0 || self.io.read_u8(self.base + REG_LBA_HIGH) != 0 {
+            return Err(DriverError::HardwareNotFound);
+        }
+
+        let mut polls = 0;
+        while status & (STATUS_DRQ | STATUS_ERR) == 0 {
+            polls += 1;
+            if polls == ATA_TIMEOUT_POLLS {
+                return Err(DriverError::InitializationFailed);
+            }
+            status = self.read_status();
+        }
+        if status & STATUS_ERR != 0 {
+            return Err(DriverError::InitializationFailed);
+        }
+
+        let mut words = [0u16; IDENTIFY_WORDS];
+        for word in words.iter_mut() {
+            *word = self.io.read_u16(self.base + REG_DATA);
+        }
+
+        Ok(AtaIdentity::parse(&words))
+    }
+
+    /// Wait out BSY, then for the drive to have data ready or report an error
+    fn wait_data_ready(&mut self) -> Result<(), DriverError> {
+        for _ in 0..ATA_TIMEOUT_POLLS {
+            let status = self.read_status();
+            if status & STATUS_BSY != 0 {
+                continue;
+            }
+            if status & STATUS_ERR != 0 {
+                return Err(DriverError::InvalidRequest);
+            }
+            if status & STATUS_DRQ != 0 {
+                return Ok(());
+            }
+        }
+        Err(DriverError::ResourceBusy)
+    }
+
+    /// Read whole sectors from `lba` on into `buffer`, with 28-bit LBA
+    pub fn read_sectors(&mut self, drive: AtaDrive, lba: u64, buffer: &mut [u8]) -> Result<(), DriverError> {
+        let sectors = (buffer.len() / SECTOR_SIZE) as u64;
+        if buffer.len() % SECTOR_SIZE != 0 || lba + sectors > LBA28_LIMIT {
+            return Err(DriverError::InvalidRequest);
+        }
+
+        for (index, chunk) in buffer.chunks_mut(MAX_SECTORS_PER_COMMAND * SECTOR_SIZE).enumerate() {
+            let lba = lba + (index * MAX_SECTORS_PER_COMMAND) as u64;
+            let count = chunk.len() / SECTOR_SIZE;
+            self.io.write_u8(
+                self.base + REG_DRIVE_SELECT,
+                drive.select_value() | DRIVE_SELECT_LBA | ((lba >> 24) as u8 & 0x0F),
+            );
+            // A count of zero asks for 256 sectors
+            self.io.write_u8(self.base + REG_SECTOR_COUNT, count as u8);
+            self.io.write_u8(self.base + REG_LBA_LOW, lba as u8);
+            self.io.write_u8(self.base + REG_LBA_MID, (lba >> 8) as u8);
+            self.io.write_u8(self.base + REG_LBA_HIGH, (lba >> 16) as u8);
+            self.io.write_u8(self.base + REG_COMMAND, CMD_READ_SECTORS);
+
+            for sector in chunk.chunks_mut(SECTOR_SIZE) {
+                self.wait_data_ready()?;
+                for pair in sector.chunks_mut(2) {
+                    pair.copy_from_slice(&self.io.read_u16(self.base + REG_DATA).to_le_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+//! Recorded ATA register transactions
+//!
+//! Modelled on what QEMU's IDE emulation (`-drive if=ide`, 32 MiB image)
+//! presents on the primary channel.
+
+use kosh_driver::hal::replay::Transaction;
+
+/// IDENTIFY DEVICE response of QEMU's emulated hard disk
+pub const QEMU_HARDDISK_IDENTIFY: [u16; 256] = [
+    0x0040, 0x0041, 0x0000, 0x0010, 0x7E00, 0x0200, 0x003F, 0x0000,
+    0x0000, 0x0000, 0x514D, 0x3030, 0x3030, 0x3120, 0x2020, 0x2020,
+    0x2020, 0x2020, 0x2020, 0x2020, 0x0003, 0x0200, 0x0004, 0x322E,
+    0x352B, 0x2020, 0x2020, 0x5145, 0x4D55, 0x2048, 0x4152, 0x4444,
+    0x4953, 0x4B20, 0x2020, 0x2020, 0x2020, 0x2020, 0x2020, 0x2020,
+    0x2020, 0x2020, 0x2020, 0x2020, 0x2020, 0x2020, 0x2020, 0x8010,
+    0x0001, 0x0B00, 0x0000, 0x0200, 0x0200, 0x0007, 0x0041, 0x0010,
+    0x003F, 0xFFF0, 0x000F, 0x0110, 0x0000, 0x0001, 0x0007, 0x0407,
+    0x0003, 0x0078, 0x0078, 0x0078, 0x0078, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x00F0, 0x0016, 0x4000, 0x7400, 0x4000, 0x4000, 0x3400, 0x4000,
+    0x003F, 0x0000, 0x0000, 0x0000, 0x0000, 0x6001, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0001, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x06A5,
+];
+
+/// Command phase of IDENTIFY DEVICE on the primary master: the drive is busy
+/// for one poll, then raises DRQ with an ATA signature in the LBA registers
+pub const ATA_IDENTIFY_COMMAND: &[Transaction] = &[
+    Transaction::write_u8(0x1F6, 0xA0),
+    Transaction::write_u8(0x1F2, 0x00),
+    Transaction::write_u8(0x1F3, 0x00),
+    Transaction::write_u8(0x1F4, 0x00),
+    Transaction::write_u8(0x1F5, 0x00),
+    Transaction::write_u8(0x1F7, 0xEC),
+    Transaction::read_u8(0x1F7, 0xD0),
+    Transaction::read_u8(0x1F7, 0x58),
+    Transaction::read_u8(0x1F4, 0x00),
+    Transaction::read_u8(0x1F5, 0x00),
+];
+
+/// IDENTIFY DEVICE against an empty channel; the floating bus reads as zero
+/// on QEMU
+pub const ATA_IDENTIFY_NO_DEVICE: &[Transaction] = &[
+    Transaction::write_u8(0x1F6, 0xA0),
+    Transaction::write_u8(0x1F2, 0x00),
+    Transaction::write_u8(0x1F3, 0x00),
+    Transaction::write_u8(0x1F4, 0x00),
+    Transaction::write_u8(0x1F5, 0x00),
+    Transaction::write_u8(0x1F7, 0xEC),
+    Transaction::read_u8(0x1F7, 0x00),
+];
+
+/// Full IDENTIFY DEVICE exchange: command phase plus the 256-word data transfer
+pub fn ata_identify_transactions() -> alloc::vec::Vec<Transaction> {
+    let mut script = ATA_IDENTIFY_COMMAND.to_vec();
+    script.extend(QEMU_HARDDISK_IDENTIFY.iter().map(|&word| Transaction::read_u16(0x1F0, word)));
+    script
+}
+
+/// Disk image of `sectors` sectors, each filled with its own number and
+/// the offset into it, so a misplaced sector shows
+pub fn numbered_disk_image(sectors: usize) -> alloc::vec::Vec<u8> {
+    (0..sectors)
+        .flat_map(|sector| (0..512).map(move |offset| (sector as u8) ^ (offset as u8)))
+        .collect()
+}
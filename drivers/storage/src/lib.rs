@@ -1,7 +1,15 @@
 #![no_std]
 
+extern crate alloc;
+
+use alloc::boxed::Box;
+use kosh_driver::hal::{PortIo, HardwareBackend, RealBackend};
 use kosh_types::DriverError;
 
+pub mod ata;
+
+use ata::{AtaController, AtaDrive, AtaIdentity, PRIMARY_IO_BASE, SECTOR_SIZE};
+
 pub trait KoshDriver {
     fn init(&mut self) -> Result<(), DriverError>;
     fn handle_request(&mut self, request: DriverRequest) -> DriverResponse;
@@ -37,19 +45,47 @@ pub struct DriverCapabilities {
 
 pub struct StorageDriver {
     initialized: bool,
+    controller: AtaController,
+    identity: Option<AtaIdentity>,
 }
 
 impl StorageDriver {
     pub fn new() -> Self {
+        Self::with_backend(&RealBackend)
+    }
+
+    /// Create a driver for the primary ATA channel of the given machine
+    pub fn with_backend(backend: &dyn HardwareBackend) -> Self {
+        Self::with_port_io(backend.port_io())
+    }
+
+    /// Create a driver that reaches the primary ATA channel through the given port backend
+    pub fn with_port_io(io: Box<dyn PortIo>) -> Self {
         Self {
             initialized: false,
+            controller: AtaController::new(io, PRIMARY_IO_BASE),
+            identity: None,
         }
     }
+
+    /// Identity of the attached disk, once initialized
+    pub fn identity(&self) -> Option<&AtaIdentity> {
+        self.identity.as_ref()
+    }
+
+    /// Read whole blocks from block `lba` on into `buffer`
+    pub fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), DriverError> {
+        let sectors = self.identity.as_ref().ok_or(DriverError::HardwareNotFound)?.sector_count();
+        if lba + (buffer.len() / SECTOR_SIZE) as u64 > sectors {
+            return Err(DriverError::InvalidRequest);
+        }
+        self.controller.read_sectors(AtaDrive::Master, lba, buffer)
+    }
 }
 
 impl KoshDriver for StorageDriver {
     fn init(&mut self) -> Result<(), DriverError> {
-        // Storage driver initialization logic will be implemented later
+        self.identity = Some(self.controller.identify(AtaDrive::Master)?);
         self.initialized = true;
         Ok(())
     }
@@ -64,14 +100,21 @@ impl KoshDriver for StorageDriver {
 
     fn cleanup(&mut self) {
         self.initialized = false;
+        self.identity = None;
     }
 
     fn get_capabilities(&self) -> DriverCapabilities {
         DriverCapabilities {
             can_read: true,
             can_write: true,
-            block_size: 512,
+            block_size: SECTOR_SIZE as u32,
             max_transfer_size: 65536,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod fixtures;
+
+#[cfg(test)]
+mod tests;
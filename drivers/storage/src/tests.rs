@@ -0,0 +1,68 @@
+use super::*;
+use crate::fixtures::{ata_identify_transactions, numbered_disk_image, ATA_IDENTIFY_NO_DEVICE, QEMU_HARDDISK_IDENTIFY};
+use alloc::vec;
+use kosh_driver::hal::replay::ReplayIo;
+use kosh_driver::mock::MockBackend;
+
+#[test]
+fn test_identify_block_parsing() {
+    let identity = AtaIdentity::parse(&QEMU_HARDDISK_IDENTIFY);
+    assert_eq!(identity.model, "QEMU HARDDISK");
+    assert_eq!(identity.serial, "QM00001");
+    assert_eq!(identity.firmware, "2.5+");
+    assert_eq!(identity.lba28_sectors, 65536);
+    assert_eq!(identity.lba48_sectors, Some(65536));
+    assert_eq!(identity.sector_count(), 65536);
+}
+
+#[test]
+fn test_replayed_identify() {
+    let io = ReplayIo::new(&ata_identify_transactions());
+    let mut driver = StorageDriver::with_port_io(Box::new(io));
+    assert!(driver.init().is_ok());
+
+    let identity = driver.identity().unwrap();
+    assert_eq!(identity.model, "QEMU HARDDISK");
+    assert_eq!(identity.sector_count() * 512, 32 * 1024 * 1024);
+
+    driver.cleanup();
+    assert!(driver.identity().is_none());
+}
+
+#[test]
+fn test_replayed_identify_without_device() {
+    let mut driver = StorageDriver::with_port_io(Box::new(ReplayIo::new(ATA_IDENTIFY_NO_DEVICE)));
+    assert!(matches!(driver.init(), Err(DriverError::HardwareNotFound)));
+    assert!(driver.identity().is_none());
+}
+
+#[test]
+fn test_mock_disk_block_reads() {
+    let machine = MockBackend::new();
+    let image = numbered_disk_image(300);
+    machine.attach_disk(image.clone());
+    let mut driver = StorageDriver::with_backend(&machine);
+
+    let mut buffer = vec![0u8; 2 * 512];
+    assert!(matches!(driver.read_blocks(0, &mut buffer), Err(DriverError::HardwareNotFound)));
+    assert!(driver.init().is_ok());
+    assert_eq!(driver.identity().unwrap().model, "KOSH MOCK DISK");
+    assert_eq!(driver.identity().unwrap().sector_count(), 300);
+
+    assert!(driver.read_blocks(7, &mut buffer).is_ok());
+    assert_eq!(buffer, image[7 * 512..9 * 512]);
+
+    // More than one command's worth of sectors
+    let mut whole = vec![0u8; image.len()];
+    assert!(driver.read_blocks(0, &mut whole).is_ok());
+    assert_eq!(whole, image);
+
+    assert!(matches!(driver.read_blocks(299, &mut buffer), Err(DriverError::InvalidRequest)));
+    assert!(matches!(driver.read_blocks(0, &mut buffer[..100]), Err(DriverError::InvalidRequest)));
+}
+
+#[test]
+fn test_mock_channel_without_disk() {
+    let mut driver = StorageDriver::with_backend(&MockBackend::new());
+    assert!(matches!(driver.init(), Err(DriverError::HardwareNotFound)));
+}
@@ -0,0 +1,288 @@
+//! Touch calibration
+//!
+//! A calibration maps the raw coordinates the panel reports onto the
+//! screen's with an affine transform, which corrects offset, scale,
+//! rotation and swapped axes. It is found by touching targets shown at
+//! known places: each calibrated coordinate is fitted by least squares as
+//! a weighted sum of the raw x and y plus an offset, so three targets that
+//! are not in a line are enough and more average out an unsteady hand.
+//!
+//! The calibration is kept through fs-service at `CALIBRATION_PATH` and
+//! read when the driver starts, in a small binary format: the magic
+//! `KTCL`, a format version byte, then the six coefficients as
+//! little-endian `i32`s.
+
+use alloc::vec::Vec;
+use kosh_posix::{close, open, read, write_all, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY};
+use kosh_types::DriverError;
+
+/// Where the calibration is kept
+pub const CALIBRATION_PATH: &str = "/etc/touch-calibration";
+
+/// Fewest targets a calibration takes
+pub const MIN_CALIBRATION_POINTS: usize = 3;
+
+/// Most targets a calibration takes
+pub const MAX_CALIBRATION_POINTS: usize = 16;
+
+const MAGIC: &[u8; 4] = b"KTCL";
+const FORMAT_VERSION: u8 = 1;
+const ENCODED_LEN: usize = 5 + 6 * 4;
+
+/// Panel coordinates, x then y
+pub type Point = (u16, u16);
+
+/// 1.0 in 16.16 fixed point
+const ONE: i32 = 0x10000;
+
+/// Touch calibration data
+///
+/// Calibrated coordinates are `x_scale * x + x_skew * y + x_offset` and
+/// `y_skew * x + y_scale * y + y_offset` of the raw ones, all in 16.16
+/// fixed point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TouchCalibration {
+    pub x_scale: i32,
+    pub x_skew: i32,
+    pub x_offset: i32,
+    pub y_skew: i32,
+    pub y_scale: i32,
+    pub y_offset: i32,
+}
+
+impl Default for TouchCalibration {
+    fn default() -> Self {
+        Self {
+            x_scale: ONE,
+            x_skew: 0,
+            x_offset: 0,
+            y_skew: 0,
+            y_scale: ONE,
+            y_offset: 0,
+        }
+    }
+}
+
+impl TouchCalibration {
+    /// Calibrated coordinates of raw `(x, y)`, kept within the panel's range
+    pub fn apply(&self, x: u16, y: u16) -> (u16, u16) {
+        let transform = |scale: i32, skew: i32, offset: i32, along: u16, across: u16| {
+            let value = scale as i64 * along as i64 + skew as i64 * across as i64 + offset as i64;
+            // Round to the nearest coordinate
+            ((value + (ONE as i64 / 2)) >> 16).clamp(0, u16::MAX as i64) as u16
+        };
+        (
+            transform(self.x_scale, self.x_skew, self.x_offset, x, y),
+            transform(self.y_scale, self.y_skew, self.y_offset, y, x),
+        )
+    }
+
+    /// Encode in the on-disk format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(ENCODED_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        for coefficient in [self.x_scale, self.x_skew, self.x_offset, self.y_skew, self.y_scale, self.y_offset] {
+            bytes.extend_from_slice(&coefficient.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decode the on-disk format
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DriverError> {
+        if bytes.len() != ENCODED_LEN || &bytes[..4] != MAGIC || bytes[4] != FORMAT_VERSION {
+            return Err(DriverError::InvalidRequest);
+        }
+        let coefficient = |index: usize| {
+            let start = 5 + index * 4;
+            i32::from_le_bytes([bytes[start], bytes[start + 1], bytes[start + 2], bytes[start + 3]])
+        };
+        Ok(Self {
+            x_scale: coefficient(0),
+            x_skew: coefficient(1),
+            x_offset: coefficient(2),
+            y_skew: coefficient(3),
+            y_scale: coefficient(4),
+            y_offset: coefficient(5),
+        })
+    }
+}
+
+fn det3(m: [[i128; 3]; 3]) -> i128 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+/// `numerator / denominator` in 16.16 fixed point, rounded; None if it
+/// does not fit
+fn fixed_quotient(numerator: i128, denominator: i128) -> Option<i32> {
+    let scaled = numerator.checked_mul(ONE as i128)?;
+    let rounded = (scaled + scaled.signum() * denominator.abs() / 2) / denominator;
+    i32::try_from(rounded).ok()
+}
+
+/// Fit the transform taking each raw touch in `samples` to its target
+///
+/// Each sample is `(raw, target)`. None if there are too few samples or
+/// the touches lie in a line, which leaves a direction unmeasured.
+pub fn solve(samples: &[(Point, Point)]) -> Option<TouchCalibration> {
+    if samples.len() < MIN_CALIBRATION_POINTS || samples.len() > MAX_CALIBRATION_POINTS {
+        return None;
+    }
+    // Normal equations of the least squares fit, shared by both axes
+    let mut normal = [[0i128; 3]; 3];
+    let mut rhs_x = [0i128; 3];
+    let mut rhs_y = [0i128; 3];
+    for &((x, y), (target_x, target_y)) in samples {
+        let row = [x as i128, y as i128, 1];
+        for i in 0..3 {
+            for j in 0..3 {
+                normal[i][j] += row[i] * row[j];
+            }
+            rhs_x[i] += row[i] * target_x as i128;
+            rhs_y[i] += row[i] * target_y as i128;
+        }
+    }
+    let det = det3(normal);
+    if det == 0 {
+        return None;
+    }
+    // Cramer's rule: the coefficient of column `column`
+    let coefficient = |rhs: &[i128; 3], column: usize| {
+        let mut replaced = normal;
+        for row in 0..3 {
+            replaced[row][column] = rhs[row];
+        }
+        fixed_quotient(det3(replaced), det)
+    };
+    Some(TouchCalibration {
+        x_scale: coefficient(&rhs_x, 0)?,
+        x_skew: coefficient(&rhs_x, 1)?,
+        x_offset: coefficient(&rhs_x, 2)?,
+        y_skew: coefficient(&rhs_y, 0)?,
+        y_scale: coefficient(&rhs_y, 1)?,
+        y_offset: coefficient(&rhs_y, 2)?,
+    })
+}
+
+/// A calibration being run: the targets shown and the touches made so far
+#[derive(Debug, Clone)]
+pub struct CalibrationRun {
+    targets: Vec<Point>,
+    touches: Vec<Point>,
+}
+
+impl CalibrationRun {
+    /// Start a calibration with `targets` shown in turn
+    pub fn new(targets: Vec<Point>) -> Result<Self, DriverError> {
+        if !(MIN_CALIBRATION_POINTS..=MAX_CALIBRATION_POINTS).contains(&targets.len()) {
+            return Err(DriverError::InvalidRequest);
+        }
+        Ok(Self { targets, touches: Vec::new() })
+    }
+
+    /// Decode the targets of the calibrate control command: `u16` x and y
+    /// pairs, little-endian
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DriverError> {
+        if !data.len().is_multiple_of(4) {
+            return Err(DriverError::InvalidRequest);
+        }
+        let targets = data.chunks_exact(4)
+            .map(|pair| (u16::from_le_bytes([pair[0], pair[1]]), u16::from_le_bytes([pair[2], pair[3]])))
+            .collect();
+        Self::new(targets)
+    }
+
+    /// Index of the target to touch next
+    pub fn next_target(&self) -> usize {
+        self.touches.len()
+    }
+
+    /// Record a touch at raw `(x, y)` on the current target; true once
+    /// every target has been touched
+    pub fn add_touch(&mut self, x: u16, y: u16) -> bool {
+        if self.touches.len() < self.targets.len() {
+            self.touches.push((x, y));
+        }
+        self.touches.len() == self.targets.len()
+    }
+
+    /// The calibration the touches give, None if they cannot give one
+    pub fn finish(&self) -> Option<TouchCalibration> {
+        let samples: Vec<_> = self.touches.iter().copied().zip(self.targets.iter().copied()).collect();
+        solve(&samples)
+    }
+}
+
+/// Store `calibration` at `path` through fs-service
+pub fn save(path: &str, calibration: &TouchCalibration) -> Result<(), DriverError> {
+    let fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0o644).map_err(|_| DriverError::PermissionDenied)?;
+    let written = write_all(fd, &calibration.to_bytes());
+    let closed = close(fd);
+    written.and(closed).map_err(|_| DriverError::ResourceBusy)
+}
+
+/// Read a calibration from `path` through fs-service
+pub fn load(path: &str) -> Result<TouchCalibration, DriverError> {
+    let fd = open(path, O_RDONLY, 0).map_err(|_| DriverError::PermissionDenied)?;
+    let mut bytes = [0u8; ENCODED_LEN + 1];
+    let result = read(fd, &mut bytes).map_err(|_| DriverError::ResourceBusy);
+    let _ = close(fd);
+    TouchCalibration::from_bytes(&bytes[..result?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGETS: [(u16, u16); 4] = [(6_000, 6_000), (59_000, 6_000), (59_000, 59_000), (6_000, 59_000)];
+
+    fn run_with(raw: impl Fn(u16, u16) -> (u16, u16)) -> Option<TouchCalibration> {
+        let mut run = CalibrationRun::new(TARGETS.to_vec()).unwrap();
+        for (index, &(x, y)) in TARGETS.iter().enumerate() {
+            assert_eq!(run.next_target(), index);
+            let (raw_x, raw_y) = raw(x, y);
+            assert_eq!(run.add_touch(raw_x, raw_y), index == TARGETS.len() - 1);
+        }
+        run.finish()
+    }
+
+    fn assert_near(actual: (u16, u16), expected: (u16, u16)) {
+        assert!(actual.0.abs_diff(expected.0) <= 1 && actual.1.abs_diff(expected.1) <= 1,
+                "{:?} is not {:?}", actual, expected);
+    }
+
+    #[test]
+    fn test_calibration_corrects_offset_and_scale() {
+        // The panel reports half the distance, shifted
+        let calibration = run_with(|x, y| (x / 2 + 1_000, y / 2 + 3_000)).unwrap();
+        for &(x, y) in &TARGETS {
+            assert_near(calibration.apply(x / 2 + 1_000, y / 2 + 3_000), (x, y));
+        }
+        assert_near(calibration.apply(1_000, 3_000), (0, 0));
+        // Past the panel's range is kept at its edge
+        assert_eq!(calibration.apply(65_535, 65_535), (65_535, 65_535));
+    }
+
+    #[test]
+    fn test_calibration_corrects_swapped_axes() {
+        let calibration = run_with(|x, y| (y, x)).unwrap();
+        assert!(calibration.x_scale.abs() <= 1 && calibration.x_skew.abs_diff(ONE) <= 1);
+        assert!(calibration.y_scale.abs() <= 1 && calibration.y_skew.abs_diff(ONE) <= 1);
+        assert_near(calibration.apply(20_000, 40_000), (40_000, 20_000));
+    }
+
+    #[test]
+    fn test_calibration_rejects_bad_input() {
+        // Every touch on one spot
+        assert_eq!(run_with(|_, _| (30_000, 30_000)), None);
+        assert!(CalibrationRun::new(TARGETS[..2].to_vec()).is_err());
+        assert!(CalibrationRun::from_bytes(&[0; 13]).is_err());
+        assert_eq!(CalibrationRun::from_bytes(&[0; 12]).unwrap().targets.len(), 3);
+
+        let calibration = run_with(|x, y| (x / 2, y)).unwrap();
+        assert_eq!(TouchCalibration::from_bytes(&calibration.to_bytes()).ok(), Some(calibration));
+        assert!(TouchCalibration::from_bytes(&calibration.to_bytes()[1..]).is_err());
+    }
+}
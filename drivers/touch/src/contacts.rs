@@ -0,0 +1,314 @@
+//! Contact tracking
+//!
+//! The controller reports each finger under a tracking ID of its own,
+//! which can be any number and is not reused promptly. `ContactTracker`
+//! gives each contact a slot from 0 to `MAX_CONTACTS` - 1 for as long as
+//! it touches, and that slot is the `touch_id` of its events, so a
+//! consumer can follow a finger from Down through its Moves to Up or
+//! Cancel. It also keeps where each contact has been, for gestures.
+//!
+//! Two kinds of report are dropped on the way:
+//!
+//! - palms: a contact whose area or pressure says it is a resting hand is
+//!   never delivered, and one that grows into a palm after its Down is
+//!   cancelled
+//! - spurious cancels: a Cancel for a contact that is not touching, as a
+//!   controller reports in storms when noise upsets it, is dropped, and
+//!   past `CANCEL_STORM_LIMIT` in one `CANCEL_STORM_WINDOW_US` the rest of
+//!   the window's cancels are ignored, leaving their contacts touching
+
+use alloc::collections::VecDeque;
+use crate::{TouchEventType, TouchInputEvent};
+pub use kosh_driver::input::RawTouch;
+
+/// Most contacts tracked at once
+pub const MAX_CONTACTS: usize = 10;
+
+/// Points of trajectory kept per contact, the latest ones
+pub const MAX_TRAJECTORY_POINTS: usize = 32;
+
+/// Cancels in one window beyond which the window's others are ignored
+pub const CANCEL_STORM_LIMIT: u32 = 4;
+
+/// Window cancel storms are counted over
+pub const CANCEL_STORM_WINDOW_US: u64 = 50_000;
+
+/// Which contacts count as palms
+#[derive(Debug, Clone, Copy)]
+pub struct PalmRejection {
+    /// Area, in square millimetres, no finger covers
+    pub max_finger_area: u16,
+    /// Pressure that with half `max_finger_area` marks a palm pressed flat
+    pub palm_pressure: u8,
+}
+
+impl Default for PalmRejection {
+    fn default() -> Self {
+        Self {
+            max_finger_area: 300,
+            palm_pressure: 240,
+        }
+    }
+}
+
+impl PalmRejection {
+    /// Whether `touch` is a palm rather than a finger
+    pub fn is_palm(&self, touch: &RawTouch) -> bool {
+        touch.area >= self.max_finger_area
+            || (touch.pressure >= self.palm_pressure && touch.area >= self.max_finger_area / 2)
+    }
+}
+
+/// A point a contact passed through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrajectoryPoint {
+    pub x: u16,
+    pub y: u16,
+    pub timestamp_us: u64,
+}
+
+/// A contact touching the panel
+#[derive(Debug, Clone)]
+pub struct Contact {
+    /// The controller's ID for the contact
+    pub tracking_id: u16,
+    /// Slot, the `touch_id` of its events
+    pub touch_id: u8,
+    /// Whether it was taken for a palm; a palm's events are not delivered
+    pub palm: bool,
+    /// Latest points it passed through, oldest first
+    pub trajectory: VecDeque<TrajectoryPoint>,
+    /// Last event delivered for it
+    pub last_delivered: Option<TouchInputEvent>,
+}
+
+impl Contact {
+    fn record(&mut self, touch: &RawTouch) {
+        if self.trajectory.len() == MAX_TRAJECTORY_POINTS {
+            self.trajectory.pop_front();
+        }
+        self.trajectory.push_back(TrajectoryPoint { x: touch.x, y: touch.y, timestamp_us: touch.timestamp_us });
+    }
+}
+
+/// Counts of reports the tracker dropped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrackerStatistics {
+    pub palms_rejected: u32,
+    pub cancels_dropped: u32,
+    /// Downs dropped because every slot was taken
+    pub contacts_dropped: u32,
+}
+
+/// Tracks contacts across their events
+pub struct ContactTracker {
+    slots: [Option<Contact>; MAX_CONTACTS],
+    palm_rejection: PalmRejection,
+    /// Start of the current cancel storm window and cancels in it
+    cancel_window_start: u64,
+    cancels_in_window: u32,
+    statistics: TrackerStatistics,
+}
+
+impl ContactTracker {
+    /// Create a tracker with no contacts
+    pub fn new() -> Self {
+        Self {
+            slots: [const { None }; MAX_CONTACTS],
+            palm_rejection: PalmRejection::default(),
+            cancel_window_start: 0,
+            cancels_in_window: 0,
+            statistics: TrackerStatistics::default(),
+        }
+    }
+
+    /// Set which contacts count as palms
+    pub fn set_palm_rejection(&mut self, palm_rejection: PalmRejection) {
+        self.palm_rejection = palm_rejection;
+    }
+
+    /// The event to deliver for `touch`, if any
+    pub fn track(&mut self, touch: &RawTouch) -> Option<TouchInputEvent> {
+        match touch.event_type {
+            TouchEventType::Down => self.begin(touch),
+            TouchEventType::Move => self.moved(touch),
+            TouchEventType::Cancel if self.cancel_is_spurious(touch) => {
+                self.statistics.cancels_dropped += 1;
+                None
+            }
+            TouchEventType::Up | TouchEventType::Cancel => self.ended(touch),
+        }
+    }
+
+    fn begin(&mut self, touch: &RawTouch) -> Option<TouchInputEvent> {
+        // A Down for a contact already touching starts it over
+        if let Some(slot) = self.slot_of(touch.tracking_id) {
+            self.slots[slot] = None;
+        }
+        let Some(slot) = self.slots.iter().position(Option::is_none) else {
+            self.statistics.contacts_dropped += 1;
+            return None;
+        };
+        let palm = self.palm_rejection.is_palm(touch);
+        let mut contact = Contact {
+            tracking_id: touch.tracking_id,
+            touch_id: slot as u8,
+            palm,
+            trajectory: VecDeque::new(),
+            last_delivered: None,
+        };
+        contact.record(touch);
+        self.slots[slot] = Some(contact);
+        if palm {
+            self.statistics.palms_rejected += 1;
+            return None;
+        }
+        Some(event_for(slot, TouchEventType::Down, touch))
+    }
+
+    fn moved(&mut self, touch: &RawTouch) -> Option<TouchInputEvent> {
+        let slot = self.slot_of(touch.tracking_id)?;
+        let palm_rejection = self.palm_rejection;
+        let contact = self.slots[slot].as_mut()?;
+        contact.record(touch);
+        if contact.palm {
+            return None;
+        }
+        if palm_rejection.is_palm(touch) {
+            // Its Down went out as a finger's; take it back
+            contact.palm = true;
+            self.statistics.palms_rejected += 1;
+            return Some(event_for(slot, TouchEventType::Cancel, touch));
+        }
+        Some(event_for(slot, TouchEventType::Move, touch))
+    }
+
+    /// A contact's Up or Cancel, which frees its slot
+    fn ended(&mut self, touch: &RawTouch) -> Option<TouchInputEvent> {
+        let slot = self.slot_of(touch.tracking_id)?;
+        let contact = self.slots[slot].take()?;
+        if contact.palm {
+            return None;
+        }
+        Some(event_for(slot, touch.event_type, touch))
+    }
+
+    fn cancel_is_spurious(&mut self, touch: &RawTouch) -> bool {
+        if touch.timestamp_us.saturating_sub(self.cancel_window_start) >= CANCEL_STORM_WINDOW_US {
+            self.cancel_window_start = touch.timestamp_us;
+            self.cancels_in_window = 0;
+        }
+        self.cancels_in_window += 1;
+        self.slot_of(touch.tracking_id).is_none() || self.cancels_in_window > CANCEL_STORM_LIMIT
+    }
+
+    fn slot_of(&self, tracking_id: u16) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.as_ref().is_some_and(|contact| contact.tracking_id == tracking_id))
+    }
+
+    /// The contact in slot `touch_id`, if one is touching
+    pub fn contact(&self, touch_id: u8) -> Option<&Contact> {
+        self.slots.get(touch_id as usize)?.as_ref()
+    }
+
+    /// Contacts touching, palms included
+    pub fn contacts(&self) -> impl Iterator<Item = &Contact> {
+        self.slots.iter().flatten()
+    }
+
+    /// Note that `event` was delivered, so that the contact's next
+    /// events are measured from it
+    pub fn note_delivered(&mut self, event: &TouchInputEvent) {
+        if let Some(Some(contact)) = self.slots.get_mut(event.touch_id as usize) {
+            contact.last_delivered = Some(*event);
+        }
+    }
+
+    /// Drop every contact, as when the panel stops reporting
+    pub fn clear(&mut self) {
+        self.slots = [const { None }; MAX_CONTACTS];
+    }
+
+    /// Counts of reports dropped
+    pub fn statistics(&self) -> TrackerStatistics {
+        self.statistics
+    }
+}
+
+fn event_for(slot: usize, event_type: TouchEventType, touch: &RawTouch) -> TouchInputEvent {
+    TouchInputEvent {
+        event_type,
+        x: touch.x,
+        y: touch.y,
+        pressure: touch.pressure,
+        timestamp_us: touch.timestamp_us,
+        touch_id: slot as u8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(event_type: TouchEventType, tracking_id: u16, x: u16, area: u16, timestamp_us: u64) -> RawTouch {
+        RawTouch { event_type, tracking_id, x, y: 1_000, pressure: 100, area, timestamp_us }
+    }
+
+    #[test]
+    fn test_contacts_get_slots() {
+        let mut tracker = ContactTracker::new();
+        let down = tracker.track(&touch(TouchEventType::Down, 700, 10, 40, 0)).unwrap();
+        let other = tracker.track(&touch(TouchEventType::Down, 12, 20, 40, 1)).unwrap();
+        assert_eq!((down.touch_id, other.touch_id), (0, 1));
+
+        let moved = tracker.track(&touch(TouchEventType::Move, 12, 25, 40, 2)).unwrap();
+        assert_eq!((moved.event_type, moved.touch_id), (TouchEventType::Move, 1));
+        assert_eq!(tracker.contact(1).unwrap().trajectory.len(), 2);
+
+        // An ended contact's slot is taken by the next
+        let up = tracker.track(&touch(TouchEventType::Up, 700, 10, 40, 3)).unwrap();
+        assert_eq!((up.event_type, up.touch_id), (TouchEventType::Up, 0));
+        assert!(tracker.contact(0).is_none());
+        assert_eq!(tracker.track(&touch(TouchEventType::Down, 701, 30, 40, 4)).unwrap().touch_id, 0);
+
+        // Events of a contact never seen go nowhere
+        assert_eq!(tracker.track(&touch(TouchEventType::Move, 999, 0, 40, 5)), None);
+        assert_eq!(tracker.contacts().count(), 2);
+    }
+
+    #[test]
+    fn test_palms_are_rejected() {
+        let mut tracker = ContactTracker::new();
+        assert_eq!(tracker.track(&touch(TouchEventType::Down, 1, 10, 900, 0)), None);
+        assert_eq!(tracker.track(&touch(TouchEventType::Move, 1, 15, 900, 1)), None);
+        assert_eq!(tracker.track(&touch(TouchEventType::Up, 1, 15, 900, 2)), None);
+        assert_eq!(tracker.contacts().count(), 0);
+
+        // A finger that flattens into a palm is cancelled once
+        assert!(tracker.track(&touch(TouchEventType::Down, 2, 10, 40, 3)).is_some());
+        let cancel = tracker.track(&touch(TouchEventType::Move, 2, 12, 500, 4)).unwrap();
+        assert_eq!(cancel.event_type, TouchEventType::Cancel);
+        assert_eq!(tracker.track(&touch(TouchEventType::Move, 2, 14, 500, 5)), None);
+        assert_eq!(tracker.track(&touch(TouchEventType::Up, 2, 14, 500, 6)), None);
+        assert_eq!(tracker.statistics().palms_rejected, 2);
+    }
+
+    #[test]
+    fn test_cancel_storms_are_filtered() {
+        let mut tracker = ContactTracker::new();
+        for id in 0..6 {
+            tracker.track(&touch(TouchEventType::Down, id, 10, 40, 0));
+        }
+        // Repeats of a cancel are dropped
+        assert!(tracker.track(&touch(TouchEventType::Cancel, 0, 10, 40, 100_000)).is_some());
+        assert_eq!(tracker.track(&touch(TouchEventType::Cancel, 0, 10, 40, 100_001)), None);
+        assert!(tracker.track(&touch(TouchEventType::Cancel, 1, 10, 40, 100_002)).is_some());
+        assert!(tracker.track(&touch(TouchEventType::Cancel, 2, 10, 40, 100_003)).is_some());
+        // Past the limit the window's cancels are ignored
+        assert_eq!(tracker.track(&touch(TouchEventType::Cancel, 3, 10, 40, 100_004)), None);
+        assert!(tracker.track(&touch(TouchEventType::Move, 3, 12, 40, 100_005)).is_some());
+        assert!(tracker.track(&touch(TouchEventType::Cancel, 3, 10, 40, 200_000)).is_some());
+        assert_eq!(tracker.statistics().cancels_dropped, 2);
+        assert_eq!(tracker.contacts().count(), 2);
+    }
+}
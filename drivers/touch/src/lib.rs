@@ -1,56 +1,94 @@
 //! Touch Input Driver
 //! 
 //! Provides touch input handling with low-latency optimizations
+//!
+//! Events are streamed to a subscriber, normally the input manager, as
+//! they arrive: each interrupt's events go out as one IPC message in the
+//! batch format of `kosh_driver::input`. A process subscribes with the
+//! `CONTROL_SUBSCRIBE` control command. Without a subscriber, events wait
+//! in a bounded buffer that `Read` drains in the same format.
+//!
+//! Coordinates go through a `TouchCalibration`, read from fs-service when
+//! the driver starts. `CONTROL_CALIBRATE` runs a new calibration: the
+//! next touch on each target it lists is taken in place of an event, and
+//! once all are in the fitted calibration is applied and saved.
+//!
+//! Contacts are tracked from the controller's reports by
+//! `contacts::ContactTracker`, which gives each a slot for its
+//! `touch_id`, rejects palms and filters spurious cancels, and keeps each
+//! contact's trajectory for gesture recognition.
+//!
+//! Reports come from the `TouchPanel` of the `HardwareBackend` the driver
+//! was built with, so tests can feed it from a simulated panel.
 
 #![no_std]
 
 extern crate alloc;
 
-use alloc::vec::Vec;
-use shared_kosh_driver::{KoshDriver, DriverError, DriverCapability, DriverRequest, DriverResponse};
+pub mod calibration;
+pub mod contacts;
+
+use alloc::{boxed::Box, vec, vec::Vec, string::String};
+use kosh_driver::{
+    KoshDriver, DriverInfo, DriverType, DriverStatus, PowerEvent,
+    DriverRequest, DriverResponse, DriverCapabilityType, QueryType
+};
+use kosh_driver::hal::{HardwareBackend, RealBackend, TouchPanel};
+pub use kosh_driver::input::{
+    TouchInputEvent, TouchEventType, TOUCH_BATCH_MAX_EVENTS, encode_touch_batch, decode_touch_batch
+};
+use kosh_ipc::IpcError;
+use kosh_types::{DriverError, Capability, ProcessId};
+pub use calibration::{TouchCalibration, CalibrationRun, CALIBRATION_PATH};
+pub use contacts::{ContactTracker, Contact, RawTouch, PalmRejection, TrajectoryPoint};
+
+/// Control command: drop buffered events
+pub const CONTROL_CLEAR_EVENTS: u32 = 0x01;
+/// Control command: stream events to the process whose ID the data holds,
+/// as a little-endian `u32`, in place of any earlier subscriber
+pub const CONTROL_SUBSCRIBE: u32 = 0x02;
+/// Control command: stop streaming and buffer events again
+pub const CONTROL_UNSUBSCRIBE: u32 = 0x03;
+/// Control command: calibrate on the targets the data lists, as
+/// little-endian `u16` x and y pairs, at least three of them
+pub const CONTROL_CALIBRATE: u32 = 0x04;
+/// Control command: abandon a calibration and keep the current one
+pub const CONTROL_CANCEL_CALIBRATION: u32 = 0x05;
+
+/// Where calibration stands, as the statistics query reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CalibrationState {
+    /// No calibration running
+    Idle = 0,
+    /// Waiting for touches on the targets
+    Running = 1,
+    /// The last calibration's touches could not be fitted
+    Failed = 2,
+}
 
 /// Touch input driver
 pub struct TouchDriver {
-    /// Driver capabilities
-    capabilities: Vec<DriverCapability>,
+    /// Touch controller
+    panel: Box<dyn TouchPanel>,
+    /// Driver status
+    status: DriverStatus,
     /// Touch input buffer
     input_buffer: Vec<TouchInputEvent>,
     /// Maximum buffer size
     max_buffer_size: usize,
+    /// Contacts touching the panel
+    contacts: ContactTracker,
+    /// Process events are streamed to
+    subscriber: Option<ProcessId>,
     /// Touch sensitivity settings
     sensitivity: TouchSensitivity,
     /// Calibration data
     calibration: TouchCalibration,
-}
-
-/// Touch input event
-#[derive(Debug, Clone, Copy)]
-pub struct TouchInputEvent {
-    /// Event type
-    pub event_type: TouchEventType,
-    /// X coordinate (0-65535)
-    pub x: u16,
-    /// Y coordinate (0-65535)
-    pub y: u16,
-    /// Pressure (0-255, 0 = no pressure)
-    pub pressure: u8,
-    /// Timestamp in microseconds
-    pub timestamp_us: u64,
-    /// Touch ID for multi-touch
-    pub touch_id: u8,
-}
-
-/// Touch event types
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum TouchEventType {
-    /// Touch down
-    Down,
-    /// Touch move
-    Move,
-    /// Touch up
-    Up,
-    /// Touch cancel
-    Cancel,
+    /// Calibration being run
+    calibration_run: Option<CalibrationRun>,
+    /// Whether the last calibration failed
+    calibration_failed: bool,
 }
 
 /// Touch sensitivity configuration
@@ -74,43 +112,25 @@ impl Default for TouchSensitivity {
     }
 }
 
-/// Touch calibration data
-#[derive(Debug, Clone, Copy)]
-pub struct TouchCalibration {
-    /// X-axis offset
-    pub x_offset: i16,
-    /// Y-axis offset
-    pub y_offset: i16,
-    /// X-axis scale factor (fixed point, 16.16)
-    pub x_scale: u32,
-    /// Y-axis scale factor (fixed point, 16.16)
-    pub y_scale: u32,
-}
-
-impl Default for TouchCalibration {
-    fn default() -> Self {
-        Self {
-            x_offset: 0,
-            y_offset: 0,
-            x_scale: 0x10000, // 1.0 in 16.16 fixed point
-            y_scale: 0x10000, // 1.0 in 16.16 fixed point
-        }
-    }
-}
-
 impl TouchDriver {
     /// Create new touch driver
     pub fn new() -> Self {
+        Self::with_backend(&RealBackend)
+    }
+
+    /// Create a driver for the touch controller of the given machine
+    pub fn with_backend(backend: &dyn HardwareBackend) -> Self {
         Self {
-            capabilities: vec![
-                DriverCapability::InputDevice,
-                DriverCapability::InterruptHandler,
-                DriverCapability::LowLatency,
-            ],
+            panel: backend.touch_panel(),
+            status: DriverStatus::Uninitialized,
             input_buffer: Vec::new(),
             max_buffer_size: 64,
+            contacts: ContactTracker::new(),
+            subscriber: None,
             sensitivity: TouchSensitivity::default(),
             calibration: TouchCalibration::default(),
+            calibration_run: None,
+            calibration_failed: false,
         }
     }
 
@@ -129,40 +149,36 @@ impl TouchDriver {
     /// Handle touch interrupt (called from interrupt handler)
     pub fn handle_touch_interrupt(&mut self) -> Result<(), DriverError> {
         // Read touch data from hardware
-        let touch_events = self.read_touch_data()?;
+        let touches = self.read_touch_data()?;
         
         // Process and buffer touch events
-        for event in touch_events {
-            self.process_touch_event(event)?;
+        for touch in touches {
+            self.process_touch_event(touch)?;
         }
         
+        self.deliver_events();
         Ok(())
     }
 
     /// Read touch data from hardware
-    fn read_touch_data(&self) -> Result<Vec<TouchInputEvent>, DriverError> {
-        // In a real implementation, this would read from touch controller registers
-        // For simulation, generate a sample touch event
-        let mut events = Vec::new();
-        
-        // Simulate touch data (this would come from hardware)
-        let sample_event = TouchInputEvent {
-            event_type: TouchEventType::Down,
-            x: 32768, // Center of screen
-            y: 32768,
-            pressure: 128,
-            timestamp_us: self.get_current_time_us(),
-            touch_id: 0,
-        };
-        
-        events.push(sample_event);
-        Ok(events)
+    fn read_touch_data(&mut self) -> Result<Vec<RawTouch>, DriverError> {
+        self.panel.read_reports()
     }
 
     /// Process a touch event
-    fn process_touch_event(&mut self, mut event: TouchInputEvent) -> Result<(), DriverError> {
+    fn process_touch_event(&mut self, touch: RawTouch) -> Result<(), DriverError> {
+        if self.calibration_run.is_some() {
+            self.record_calibration_touch(&touch);
+            return Ok(());
+        }
+
         // Apply calibration
-        event = self.apply_calibration(event);
+        let touch = self.apply_calibration(touch);
+
+        // Follow the contact; palms and spurious cancels end here
+        let Some(event) = self.contacts.track(&touch) else {
+            return Ok(());
+        };
         
         // Apply sensitivity filtering
         if !self.passes_sensitivity_filter(&event) {
@@ -176,6 +192,7 @@ impl TouchDriver {
         }
         
         self.input_buffer.push(event);
+        self.contacts.note_delivered(&event);
         
         // Notify kernel of touch event for responsiveness optimization
         self.notify_kernel_touch_event(event)?;
@@ -184,59 +201,104 @@ impl TouchDriver {
     }
 
     /// Apply calibration to touch coordinates
-    fn apply_calibration(&self, mut event: TouchInputEvent) -> TouchInputEvent {
-        // Apply offset
-        let x_adjusted = (event.x as i32) + (self.calibration.x_offset as i32);
-        let y_adjusted = (event.y as i32) + (self.calibration.y_offset as i32);
-        
-        // Apply scale (16.16 fixed point math)
-        let x_scaled = ((x_adjusted * (self.calibration.x_scale as i32)) >> 16) as u16;
-        let y_scaled = ((y_adjusted * (self.calibration.y_scale as i32)) >> 16) as u16;
-        
-        event.x = x_scaled;
-        event.y = y_scaled;
-        
-        event
+    fn apply_calibration(&self, mut touch: RawTouch) -> RawTouch {
+        (touch.x, touch.y) = self.calibration.apply(touch.x, touch.y);
+        touch
     }
 
-    /// Check if event passes sensitivity filter
-    fn passes_sensitivity_filter(&self, event: &TouchInputEvent) -> bool {
-        // Check pressure threshold
-        if event.pressure < self.sensitivity.min_pressure {
-            return false;
+    /// Start a calibration; touches are taken for it until it ends
+    pub fn start_calibration(&mut self, run: CalibrationRun) {
+        self.calibration_run = Some(run);
+        self.calibration_failed = false;
+        // A touch in progress would end against the new calibration
+        self.input_buffer.clear();
+        self.contacts.clear();
+    }
+
+    /// Abandon the calibration being run
+    pub fn cancel_calibration(&mut self) {
+        self.calibration_run = None;
+    }
+
+    /// Where calibration stands
+    pub fn calibration_state(&self) -> CalibrationState {
+        if self.calibration_run.is_some() {
+            CalibrationState::Running
+        } else if self.calibration_failed {
+            CalibrationState::Failed
+        } else {
+            CalibrationState::Idle
         }
-        
-        // For move events, check movement threshold
-        if event.event_type == TouchEventType::Move {
-            if let Some(last_event) = self.input_buffer.last() {
-                if last_event.touch_id == event.touch_id {
-                    let dx = (event.x as i32) - (last_event.x as i32);
-                    let dy = (event.y as i32) - (last_event.y as i32);
-                    let distance_sq = (dx * dx + dy * dy) as u32;
-                    let threshold_sq = (self.sensitivity.movement_threshold as u32).pow(2);
-                    
-                    if distance_sq < threshold_sq {
-                        return false; // Movement too small
-                    }
-                }
+    }
+
+    /// Take a raw touch for the calibration being run
+    ///
+    /// Only the press counts, so a finger resting on a target gives one
+    /// touch. Once every target has one, the fitted calibration is applied
+    /// and saved.
+    fn record_calibration_touch(&mut self, touch: &RawTouch) {
+        if touch.event_type != TouchEventType::Down || touch.pressure < self.sensitivity.min_pressure {
+            return;
+        }
+        let Some(run) = self.calibration_run.as_mut() else {
+            return;
+        };
+        if !run.add_touch(touch.x, touch.y) {
+            return;
+        }
+        match run.finish() {
+            Some(calibration) => {
+                self.calibration = calibration;
+                // The calibration holds until reboot even if it cannot be kept
+                let _ = calibration::save(CALIBRATION_PATH, &calibration);
             }
+            None => self.calibration_failed = true,
         }
-        
-        // Check debounce time
-        if let Some(last_event) = self.input_buffer.last() {
-            if last_event.touch_id == event.touch_id {
-                let time_diff = event.timestamp_us.saturating_sub(last_event.timestamp_us);
-                if time_diff < self.sensitivity.debounce_time_us as u64 {
-                    return false; // Too soon after last event
+        self.calibration_run = None;
+    }
+
+    /// Check if event passes sensitivity filter
+    ///
+    /// A contact's Up and Cancel always pass, so that whoever saw it begin
+    /// sees it end.
+    fn passes_sensitivity_filter(&self, event: &TouchInputEvent) -> bool {
+        match event.event_type {
+            // Check pressure threshold
+            TouchEventType::Down => event.pressure >= self.sensitivity.min_pressure,
+            TouchEventType::Move => {
+                let Some(last_event) = self.contacts.contact(event.touch_id).and_then(|contact| contact.last_delivered) else {
+                    return true;
+                };
+
+                // Check movement threshold
+                let dx = (event.x as i32) - (last_event.x as i32);
+                let dy = (event.y as i32) - (last_event.y as i32);
+                let distance_sq = (dx * dx + dy * dy) as u32;
+                let threshold_sq = (self.sensitivity.movement_threshold as u32).pow(2);
+                if distance_sq < threshold_sq {
+                    return false; // Movement too small
                 }
+
+                // Check debounce time
+                let time_diff = event.timestamp_us.saturating_sub(last_event.timestamp_us);
+                time_diff >= self.sensitivity.debounce_time_us as u64
             }
+            TouchEventType::Up | TouchEventType::Cancel => true,
         }
-        
-        true
+    }
+
+    /// Contacts touching the panel, with their trajectories
+    pub fn contacts(&self) -> impl Iterator<Item = &Contact> {
+        self.contacts.contacts()
+    }
+
+    /// Set which contacts count as palms
+    pub fn set_palm_rejection(&mut self, palm_rejection: PalmRejection) {
+        self.contacts.set_palm_rejection(palm_rejection);
     }
 
     /// Notify kernel of touch event for responsiveness optimization
-    fn notify_kernel_touch_event(&self, event: TouchInputEvent) -> Result<(), DriverError> {
+    fn notify_kernel_touch_event(&self, _event: TouchInputEvent) -> Result<(), DriverError> {
         // In a real implementation, this would use a system call or IPC
         // to notify the kernel's responsiveness system
         
@@ -244,13 +306,6 @@ impl TouchDriver {
         Ok(())
     }
 
-    /// Get current time in microseconds
-    fn get_current_time_us(&self) -> u64 {
-        // In a real implementation, this would read from a high-resolution timer
-        // For simulation, return a placeholder value
-        1000000 // 1 second
-    }
-
     /// Get pending touch events
     pub fn get_pending_events(&mut self) -> Vec<TouchInputEvent> {
         let events = self.input_buffer.clone();
@@ -258,6 +313,55 @@ impl TouchDriver {
         events
     }
 
+    /// Encode the oldest pending events as a batch and drop them from the
+    /// buffer
+    pub fn take_batch(&mut self) -> Vec<u8> {
+        let batch = encode_touch_batch(&self.input_buffer);
+        let count = self.input_buffer.len().min(TOUCH_BATCH_MAX_EVENTS);
+        self.input_buffer.drain(..count);
+        batch
+    }
+
+    /// Stream events to `pid` from now on
+    pub fn subscribe(&mut self, pid: ProcessId) {
+        self.subscriber = Some(pid);
+        self.deliver_events();
+    }
+
+    /// Stop streaming events
+    pub fn unsubscribe(&mut self) {
+        self.subscriber = None;
+    }
+
+    /// Process events are streamed to
+    pub fn subscriber(&self) -> Option<ProcessId> {
+        self.subscriber
+    }
+
+    /// Send the pending events to the subscriber
+    ///
+    /// Events the subscriber has no room for stay buffered until the next
+    /// interrupt; a subscriber that has gone away is dropped.
+    fn deliver_events(&mut self) {
+        let Some(pid) = self.subscriber else {
+            return;
+        };
+        while !self.input_buffer.is_empty() {
+            let batch = encode_touch_batch(&self.input_buffer);
+            match kosh_ipc::syscall::send_message(pid, &batch) {
+                Ok(()) => {
+                    let count = self.input_buffer.len().min(TOUCH_BATCH_MAX_EVENTS);
+                    self.input_buffer.drain(..count);
+                }
+                Err(IpcError::ChannelFull | IpcError::WouldBlock) => return,
+                Err(_) => {
+                    self.subscriber = None;
+                    return;
+                }
+            }
+        }
+    }
+
     /// Set touch sensitivity
     pub fn set_sensitivity(&mut self, sensitivity: TouchSensitivity) {
         self.sensitivity = sensitivity;
@@ -273,6 +377,9 @@ impl TouchDriver {
         TouchStatistics {
             events_buffered: self.input_buffer.len(),
             buffer_capacity: self.max_buffer_size,
+            subscriber: self.subscriber,
+            active_contacts: self.contacts.contacts().filter(|contact| !contact.palm).count(),
+            tracker: self.contacts.statistics(),
             sensitivity: self.sensitivity,
             calibration: self.calibration,
         }
@@ -284,45 +391,133 @@ impl TouchDriver {
 pub struct TouchStatistics {
     pub events_buffered: usize,
     pub buffer_capacity: usize,
+    pub subscriber: Option<ProcessId>,
+    /// Fingers touching, palms left out
+    pub active_contacts: usize,
+    pub tracker: contacts::TrackerStatistics,
     pub sensitivity: TouchSensitivity,
     pub calibration: TouchCalibration,
 }
 
 impl KoshDriver for TouchDriver {
-    fn init(&mut self) -> Result<(), DriverError> {
-        self.init_hardware()
+    fn init(&mut self, _capabilities: Vec<Capability>) -> Result<(), DriverError> {
+        self.status = DriverStatus::Initializing;
+        self.init_hardware()?;
+        // Without a saved calibration, coordinates are taken as they come
+        self.calibration = calibration::load(CALIBRATION_PATH).unwrap_or_default();
+        self.status = DriverStatus::Ready;
+        Ok(())
     }
 
-    fn handle_request(&mut self, request: DriverRequest) -> DriverResponse {
+    fn handle_request(&mut self, request: DriverRequest) -> Result<DriverResponse, DriverError> {
         match request {
-            DriverRequest::GetCapabilities => {
-                DriverResponse::Capabilities(self.capabilities.clone())
+            DriverRequest::Initialize => {
+                self.init(Vec::new())?;
+                Ok(DriverResponse::Success)
             }
-            DriverRequest::ReadData => {
-                let events = self.get_pending_events();
-                DriverResponse::Data(alloc::format!("Touch events: {} pending", events.len()).into_bytes())
+            DriverRequest::Read { .. } => {
+                Ok(DriverResponse::Data(self.take_batch()))
             }
-            DriverRequest::WriteData(_data) => {
-                DriverResponse::Error("Touch driver is read-only".to_string())
-            }
-            DriverRequest::Configure(config) => {
-                // Parse configuration (simplified)
-                DriverResponse::Success
+            DriverRequest::Control { command, data } => {
+                match command {
+                    CONTROL_CLEAR_EVENTS => {
+                        self.input_buffer.clear();
+                        Ok(DriverResponse::Success)
+                    }
+                    CONTROL_SUBSCRIBE => {
+                        let pid = data.get(..4).ok_or(DriverError::InvalidRequest)?;
+                        self.subscribe(ProcessId::from_le_bytes([pid[0], pid[1], pid[2], pid[3]]));
+                        Ok(DriverResponse::Success)
+                    }
+                    CONTROL_UNSUBSCRIBE => {
+                        self.unsubscribe();
+                        Ok(DriverResponse::Success)
+                    }
+                    CONTROL_CALIBRATE => {
+                        self.start_calibration(CalibrationRun::from_bytes(&data)?);
+                        Ok(DriverResponse::Success)
+                    }
+                    CONTROL_CANCEL_CALIBRATION => {
+                        self.cancel_calibration();
+                        Ok(DriverResponse::Success)
+                    }
+                    _ => Err(DriverError::InvalidRequest)
+                }
             }
-            DriverRequest::GetStatus => {
-                let stats = self.get_statistics();
-                DriverResponse::Data(alloc::format!("Touch driver: {} events buffered", stats.events_buffered).into_bytes())
+            DriverRequest::Query { query_type } => {
+                match query_type {
+                    QueryType::Status => Ok(DriverResponse::Status(self.status)),
+                    QueryType::HardwareInfo => Ok(DriverResponse::Info(self.get_driver_info())),
+                    QueryType::Statistics => {
+                        let stats = self.get_statistics();
+                        Ok(DriverResponse::Data(vec![
+                            stats.events_buffered as u8,
+                            stats.buffer_capacity as u8,
+                            stats.subscriber.is_some() as u8,
+                            self.calibration_state() as u8,
+                            stats.active_contacts as u8,
+                        ]))
+                    }
+                    _ => Err(DriverError::InvalidRequest)
+                }
             }
+            // Touch driver is read-only
+            _ => Err(DriverError::InvalidRequest)
         }
     }
 
-    fn cleanup(&mut self) {
+    fn cleanup(&mut self) -> Result<(), DriverError> {
         // Clean up touch driver resources
         self.input_buffer.clear();
+        self.contacts.clear();
+        self.subscriber = None;
+        self.calibration_run = None;
+        self.status = DriverStatus::Uninitialized;
+        Ok(())
+    }
+
+    fn get_required_capabilities(&self) -> Vec<DriverCapabilityType> {
+        vec![DriverCapabilityType::HardwareAccess]
+    }
+
+    fn get_provided_capabilities(&self) -> Vec<DriverCapabilityType> {
+        vec![
+            DriverCapabilityType::Custom(String::from("touch_input")),
+            DriverCapabilityType::Custom(String::from("input_events")),
+        ]
+    }
+
+    fn get_driver_info(&self) -> DriverInfo {
+        DriverInfo {
+            name: String::from("Touch Input Driver"),
+            version: String::from("0.1.0"),
+            vendor: String::from("Kosh OS"),
+            description: String::from("Touch screen driver streaming calibrated touch events to a subscriber"),
+            driver_type: DriverType::Input,
+            hardware_ids: Vec::new(),
+        }
+    }
+
+    fn handle_power_event(&mut self, event: PowerEvent) -> Result<(), DriverError> {
+        match event {
+            PowerEvent::Suspend => {
+                // A touch in progress does not survive the suspend
+                self.input_buffer.clear();
+                self.contacts.clear();
+                self.status = DriverStatus::Suspended;
+                Ok(())
+            }
+            PowerEvent::Resume => {
+                self.status = DriverStatus::Ready;
+                self.init_hardware()
+            }
+            PowerEvent::PowerDown => self.cleanup(),
+            _ => Ok(())
+        }
     }
 
-    fn get_capabilities(&self) -> Vec<DriverCapability> {
-        self.capabilities.clone()
+    fn get_status(&self) -> DriverStatus {
+        self.status
     }
 }
 
@@ -345,13 +540,14 @@ mod tests {
     #[test]
     fn test_calibration_application() {
         let driver = TouchDriver::new();
-        let event = TouchInputEvent {
+        let event = RawTouch {
             event_type: TouchEventType::Down,
+            tracking_id: 0,
             x: 1000,
             y: 2000,
             pressure: 100,
+            area: 50,
             timestamp_us: 0,
-            touch_id: 0,
         };
         
         let calibrated = driver.apply_calibration(event);
@@ -388,4 +584,137 @@ mod tests {
         
         assert!(driver.passes_sensitivity_filter(&good_event));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_touch_batch_round_trip() {
+        let events: Vec<TouchInputEvent> = (0..TOUCH_BATCH_MAX_EVENTS as u16 + 3)
+            .map(|i| TouchInputEvent {
+                event_type: TouchEventType::Move,
+                x: i,
+                y: 65535 - i,
+                pressure: 200,
+                timestamp_us: 1_000_000 + i as u64,
+                touch_id: 1,
+            })
+            .collect();
+
+        // A batch holds what fits in one message; the rest wait
+        let mut driver = TouchDriver::new();
+        driver.max_buffer_size = events.len();
+        driver.input_buffer = events.clone();
+        let first = decode_touch_batch(&driver.take_batch()).unwrap();
+        assert_eq!(first, events[..TOUCH_BATCH_MAX_EVENTS]);
+        let rest = decode_touch_batch(&driver.take_batch()).unwrap();
+        assert_eq!(rest, events[TOUCH_BATCH_MAX_EVENTS..]);
+        assert!(decode_touch_batch(&driver.take_batch()).unwrap().is_empty());
+
+        let mut corrupt = encode_touch_batch(&events[..1]);
+        corrupt[7] = 9; // No such event type
+        assert!(decode_touch_batch(&corrupt).is_err());
+        assert!(decode_touch_batch(&corrupt[..10]).is_err());
+    }
+
+    #[test]
+    fn test_subscription_control() {
+        let mut driver = TouchDriver::new();
+        let subscribe = |pid: ProcessId| DriverRequest::Control { command: CONTROL_SUBSCRIBE, data: pid.to_le_bytes().to_vec() };
+
+        assert!(driver.handle_request(subscribe(42)).is_ok());
+        assert_eq!(driver.subscriber(), Some(42));
+        assert!(driver.handle_request(subscribe(43)).is_ok());
+        assert_eq!(driver.subscriber(), Some(43));
+
+        let short = DriverRequest::Control { command: CONTROL_SUBSCRIBE, data: vec![1] };
+        assert!(driver.handle_request(short).is_err());
+
+        let unsubscribe = DriverRequest::Control { command: CONTROL_UNSUBSCRIBE, data: Vec::new() };
+        assert!(driver.handle_request(unsubscribe).is_ok());
+        assert_eq!(driver.subscriber(), None);
+    }
+
+    #[test]
+    fn test_calibration_control() {
+        let mut driver = TouchDriver::new();
+        let touch = |event_type, x, y| RawTouch { event_type, tracking_id: 0, x, y, pressure: 100, area: 50, timestamp_us: 0 };
+        let targets: Vec<u8> = [(1000u16, 1000u16), (60000, 1000), (30000, 60000)].iter()
+            .flat_map(|&(x, y)| x.to_le_bytes().into_iter().chain(y.to_le_bytes()))
+            .collect();
+
+        let too_few = DriverRequest::Control { command: CONTROL_CALIBRATE, data: targets[..8].to_vec() };
+        assert!(driver.handle_request(too_few).is_err());
+        assert_eq!(driver.calibration_state(), CalibrationState::Idle);
+
+        let calibrate = DriverRequest::Control { command: CONTROL_CALIBRATE, data: targets };
+        assert!(driver.handle_request(calibrate).is_ok());
+        assert_eq!(driver.calibration_state(), CalibrationState::Running);
+
+        // Touches go to the calibration, not to the buffer
+        driver.process_touch_event(touch(TouchEventType::Down, 500, 500)).unwrap();
+        driver.process_touch_event(touch(TouchEventType::Move, 600, 600)).unwrap();
+        driver.process_touch_event(touch(TouchEventType::Down, 30000, 500)).unwrap();
+        assert!(driver.input_buffer.is_empty());
+        assert_eq!(driver.calibration_run.as_ref().unwrap().next_target(), 2);
+
+        let cancel = DriverRequest::Control { command: CONTROL_CANCEL_CALIBRATION, data: Vec::new() };
+        assert!(driver.handle_request(cancel).is_ok());
+        assert_eq!(driver.calibration_state(), CalibrationState::Idle);
+        assert_eq!(driver.get_statistics().calibration, TouchCalibration::default());
+        driver.process_touch_event(touch(TouchEventType::Down, 500, 500)).unwrap();
+        assert_eq!(driver.input_buffer.len(), 1);
+    }
+
+    #[test]
+    fn test_contacts_reach_the_buffer() {
+        let mut driver = TouchDriver::new();
+        let touch = |event_type, tracking_id, x, pressure, area, timestamp_us| RawTouch {
+            event_type, tracking_id, x, y: 1000, pressure, area, timestamp_us,
+        };
+        driver.process_touch_event(touch(TouchEventType::Down, 31, 1000, 100, 50, 0)).unwrap();
+        // A palm comes down beside the finger and goes unseen
+        driver.process_touch_event(touch(TouchEventType::Down, 32, 9000, 200, 800, 10)).unwrap();
+        // Too small a move is filtered, a larger one goes out
+        driver.process_touch_event(touch(TouchEventType::Move, 31, 1002, 100, 50, 5000)).unwrap();
+        driver.process_touch_event(touch(TouchEventType::Move, 31, 1100, 100, 50, 10000)).unwrap();
+        assert_eq!(driver.get_statistics().active_contacts, 1);
+        assert_eq!(driver.contacts().find(|contact| contact.tracking_id == 31).unwrap().trajectory.len(), 3);
+        // An Up reports no pressure but still ends the contact
+        driver.process_touch_event(touch(TouchEventType::Up, 31, 1100, 0, 0, 11000)).unwrap();
+        driver.process_touch_event(touch(TouchEventType::Up, 32, 9000, 0, 0, 11000)).unwrap();
+
+        let events = driver.get_pending_events();
+        let kinds: Vec<_> = events.iter().map(|event| (event.event_type, event.touch_id, event.x)).collect();
+        assert_eq!(kinds, [
+            (TouchEventType::Down, 0, 1000),
+            (TouchEventType::Move, 0, 1100),
+            (TouchEventType::Up, 0, 1100),
+        ]);
+        assert_eq!(driver.get_statistics().tracker.palms_rejected, 1);
+        assert_eq!(driver.contacts().count(), 0);
+    }
+
+    #[test]
+    fn test_mock_panel_reports_reach_the_buffer() {
+        let machine = kosh_driver::mock::MockBackend::new();
+        let mut driver = TouchDriver::with_backend(&machine);
+        let touch = |event_type, x, timestamp_us| RawTouch {
+            event_type, tracking_id: 7, x, y: 2000, pressure: 100, area: 50, timestamp_us,
+        };
+
+        // Nothing touching, nothing to report
+        driver.handle_touch_interrupt().unwrap();
+        assert!(driver.get_pending_events().is_empty());
+
+        machine.push_touches(&[touch(TouchEventType::Down, 1000, 0), touch(TouchEventType::Move, 1200, 8000)]);
+        driver.handle_touch_interrupt().unwrap();
+        machine.push_touches(&[touch(TouchEventType::Up, 1200, 16000)]);
+        driver.handle_touch_interrupt().unwrap();
+        assert_eq!(machine.pending_touches(), 0);
+
+        let events: Vec<_> = driver.get_pending_events().iter().map(|event| (event.event_type, event.x, event.y)).collect();
+        assert_eq!(events, [
+            (TouchEventType::Down, 1000, 2000),
+            (TouchEventType::Move, 1200, 2000),
+            (TouchEventType::Up, 1200, 2000),
+        ]);
+    }
+}
@@ -0,0 +1,484 @@
+//! Emulated xHCI controller and USB devices
+//!
+//! The descriptors are those QEMU's `usb-kbd` and `usb-mouse` present. The
+//! controller implements just enough of xHCI to enumerate them: it reads
+//! the rings the driver writes in memory, answers commands and control
+//! transfers, and completes interrupt transfers with reports the test
+//! sends. Test DMA memory is identity mapped, so physical addresses can be
+//! dereferenced directly.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
+use kosh_driver::hal::Mmio;
+use spin::Mutex;
+use crate::xhci::{
+    DmaMemory, DmaPool, DmaRegion, Trb, COMPLETION_SHORT_PACKET, COMPLETION_SUCCESS, DMA_PAGE_SIZE, TRB_ADDRESS_DEVICE,
+    TRB_COMMAND_COMPLETION, TRB_CONFIGURE_ENDPOINT, TRB_DATA, TRB_ENABLE_SLOT, TRB_EVALUATE_CONTEXT,
+    TRB_LINK, TRB_NORMAL, TRB_SETUP, TRB_STATUS, TRB_TRANSFER_EVENT,
+};
+
+pub const QEMU_KEYBOARD_DEVICE: [u8; 18] = [
+    0x12, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x08, 0x27, 0x06, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x0B, 0x01,
+];
+
+pub const QEMU_KEYBOARD_CONFIGURATION: [u8; 34] = [
+    // Configuration 1, one interface
+    0x09, 0x02, 0x22, 0x00, 0x01, 0x01, 0x06, 0xA0, 0x32,
+    // Interface 0: HID, boot subclass, keyboard protocol
+    0x09, 0x04, 0x00, 0x00, 0x01, 0x03, 0x01, 0x01, 0x07,
+    // HID descriptor, 63-byte report descriptor
+    0x09, 0x21, 0x11, 0x01, 0x00, 0x01, 0x22, 0x3F, 0x00,
+    // Endpoint 1 IN, interrupt, 8 bytes every 7 ms
+    0x07, 0x05, 0x81, 0x03, 0x08, 0x00, 0x07,
+];
+
+pub const QEMU_MOUSE_DEVICE: [u8; 18] = [
+    0x12, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x08, 0x27, 0x06, 0x01, 0x00, 0x00, 0x00, 0x01, 0x03, 0x0A, 0x01,
+];
+
+pub const QEMU_MOUSE_CONFIGURATION: [u8; 34] = [
+    0x09, 0x02, 0x22, 0x00, 0x01, 0x01, 0x05, 0xA0, 0x32,
+    // Interface 0: HID, boot subclass, mouse protocol
+    0x09, 0x04, 0x00, 0x00, 0x01, 0x03, 0x01, 0x02, 0x07,
+    0x09, 0x21, 0x01, 0x00, 0x00, 0x01, 0x22, 0x34, 0x00,
+    // Endpoint 1 IN, interrupt, 4 bytes every 10 ms
+    0x07, 0x05, 0x81, 0x03, 0x04, 0x00, 0x0A,
+];
+
+/// Page-aligned, identity-mapped DMA memory of `pages` pages
+pub fn dma_pool(pages: usize) -> DmaPool {
+    let memory = vec![0u8; (pages + 1) * DMA_PAGE_SIZE].leak();
+    let start = (memory.as_ptr() as usize).next_multiple_of(DMA_PAGE_SIZE);
+    unsafe { DmaPool::new(start, start as u64, pages * DMA_PAGE_SIZE) }
+}
+
+/// A pool the test keeps a handle on while the driver owns it
+#[derive(Clone)]
+pub struct SharedPool(pub Arc<Mutex<DmaPool>>);
+
+impl SharedPool {
+    pub fn new(pages: usize) -> Self {
+        Self(Arc::new(Mutex::new(dma_pool(pages))))
+    }
+
+    pub fn free_pages(&self) -> usize {
+        self.0.lock().free_pages()
+    }
+}
+
+impl DmaMemory for SharedPool {
+    fn allocate(&mut self) -> Option<DmaRegion> {
+        self.0.lock().allocate()
+    }
+
+    fn free(&mut self, region: DmaRegion) {
+        self.0.lock().free(region)
+    }
+}
+
+const CAP_LENGTH: usize = 0x20;
+const RUNTIME: usize = 0x1000;
+const DOORBELLS: usize = 0x2000;
+const MAX_SLOTS: u32 = 8;
+const PORTS: usize = 4;
+
+/// A device plugged into an emulated port
+#[derive(Clone)]
+pub struct FakeDevice {
+    pub device_descriptor: &'static [u8],
+    pub configuration: &'static [u8],
+    /// Port speed ID
+    pub speed: u32,
+}
+
+impl FakeDevice {
+    pub fn keyboard() -> Self {
+        Self { device_descriptor: &QEMU_KEYBOARD_DEVICE, configuration: &QEMU_KEYBOARD_CONFIGURATION, speed: 1 }
+    }
+
+    pub fn mouse() -> Self {
+        Self { device_descriptor: &QEMU_MOUSE_DEVICE, configuration: &QEMU_MOUSE_CONFIGURATION, speed: 1 }
+    }
+}
+
+/// Producer state of a ring the driver writes
+#[derive(Clone, Copy)]
+struct RingCursor {
+    dequeue: u64,
+    cycle: bool,
+}
+
+impl RingCursor {
+    /// Next TRB the driver handed over, following link TRBs
+    fn next(&mut self) -> Option<(u64, Trb)> {
+        loop {
+            let trb = read_trb(self.dequeue);
+            if trb.cycle() != self.cycle {
+                return None;
+            }
+            if trb.trb_type() == TRB_LINK {
+                self.dequeue = trb.parameter;
+                // Toggle cycle
+                if trb.control & 2 != 0 {
+                    self.cycle = !self.cycle;
+                }
+                continue;
+            }
+            let address = self.dequeue;
+            self.dequeue += 16;
+            return Some((address, trb));
+        }
+    }
+}
+
+struct Slot {
+    port: usize,
+    control: RingCursor,
+    max_packet_size: u16,
+    configuration: Option<u8>,
+    boot_protocol: bool,
+    endpoints: BTreeMap<u8, RingCursor>,
+    /// Queued transfers by endpoint, with their buffer and length
+    pending: BTreeMap<u8, Vec<(u64, u64, u32)>>,
+}
+
+#[derive(Default)]
+struct Port {
+    device: Option<FakeDevice>,
+    enabled: bool,
+    reset_change: bool,
+}
+
+pub struct FakeState {
+    command: u32,
+    crcr: u64,
+    dcbaa: u64,
+    erstba: u64,
+    command_ring: Option<RingCursor>,
+    event_enqueue: usize,
+    event_cycle: bool,
+    ports: Vec<Port>,
+    slots: BTreeMap<u8, Slot>,
+    next_slot: u8,
+    /// Registers without behaviour, as last written
+    registers: BTreeMap<usize, u32>,
+    /// Command stays set, so the controller never finishes resetting
+    pub stuck_in_reset: bool,
+}
+
+fn read_u32(address: u64) -> u32 {
+    unsafe { core::ptr::read_volatile(address as *const u32) }
+}
+
+fn write_u32(address: u64, value: u32) {
+    unsafe { core::ptr::write_volatile(address as *mut u32, value) }
+}
+
+fn read_u64(address: u64) -> u64 {
+    read_u32(address) as u64 | (read_u32(address + 4) as u64) << 32
+}
+
+fn read_trb(address: u64) -> Trb {
+    Trb { parameter: read_u64(address), status: read_u32(address + 8), control: read_u32(address + 12) }
+}
+
+fn write_bytes(address: u64, bytes: &[u8]) {
+    for (offset, byte) in bytes.iter().enumerate() {
+        unsafe { core::ptr::write_volatile((address as usize + offset) as *mut u8, *byte) }
+    }
+}
+
+impl FakeState {
+    /// Host controller reset: forget rings and slots, disable ports
+    fn reset(&mut self) {
+        self.command = 0;
+        self.command_ring = None;
+        self.event_enqueue = 0;
+        self.event_cycle = true;
+        self.slots.clear();
+        self.next_slot = 0;
+        for port in &mut self.ports {
+            port.enabled = false;
+            port.reset_change = false;
+        }
+    }
+
+    fn post_event(&mut self, mut event: Trb) {
+        let segment = read_u64(self.erstba);
+        let size = read_u32(self.erstba + 8) as usize;
+        event.control = (event.control & !1) | self.event_cycle as u32;
+        let address = segment + (self.event_enqueue * 16) as u64;
+        write_u32(address, event.parameter as u32);
+        write_u32(address + 4, (event.parameter >> 32) as u32);
+        write_u32(address + 8, event.status);
+        write_u32(address + 12, event.control);
+        self.event_enqueue += 1;
+        if self.event_enqueue == size {
+            self.event_enqueue = 0;
+            self.event_cycle = !self.event_cycle;
+        }
+    }
+
+    fn complete_command(&mut self, address: u64, code: u8, slot_id: u8) {
+        self.post_event(Trb::new(TRB_COMMAND_COMPLETION, address, (code as u32) << 24, (slot_id as u32) << 24));
+    }
+
+    fn run_commands(&mut self) {
+        let mut ring = *self.command_ring.get_or_insert(RingCursor {
+            dequeue: self.crcr & !0x3F,
+            cycle: self.crcr & 1 != 0,
+        });
+        while let Some((address, trb)) = ring.next() {
+            let slot_id = trb.slot_id();
+            match trb.trb_type() {
+                TRB_ENABLE_SLOT => {
+                    self.next_slot += 1;
+                    self.complete_command(address, COMPLETION_SUCCESS, self.next_slot);
+                }
+                TRB_ADDRESS_DEVICE => {
+                    let input = trb.parameter;
+                    let port = (read_u32(input + 32 + 4) >> 16 & 0xFF) as usize;
+                    let endpoint0 = input + 64;
+                    let dequeue = read_u64(endpoint0 + 8);
+                    let output = read_u64(self.dcbaa + slot_id as u64 * 8);
+                    // Copy the slot context over, marked addressed
+                    for offset in (0..32).step_by(4) {
+                        write_u32(output + offset, read_u32(input + 32 + offset));
+                    }
+                    write_u32(output + 12, 2 << 27 | slot_id as u32);
+                    self.slots.insert(slot_id, Slot {
+                        port,
+                        control: RingCursor { dequeue: dequeue & !0xF, cycle: dequeue & 1 != 0 },
+                        max_packet_size: (read_u32(endpoint0 + 4) >> 16) as u16,
+                        configuration: None,
+                        boot_protocol: false,
+                        endpoints: BTreeMap::new(),
+                        pending: BTreeMap::new(),
+                    });
+                    self.complete_command(address, COMPLETION_SUCCESS, slot_id);
+                }
+                TRB_EVALUATE_CONTEXT => {
+                    let max_packet_size = (read_u32(trb.parameter + 64 + 4) >> 16) as u16;
+                    if let Some(slot) = self.slots.get_mut(&slot_id) {
+                        slot.max_packet_size = max_packet_size;
+                    }
+                    self.complete_command(address, COMPLETION_SUCCESS, slot_id);
+                }
+                TRB_CONFIGURE_ENDPOINT => {
+                    let input = trb.parameter;
+                    let add_flags = read_u32(input + 4);
+                    let slot = self.slots.get_mut(&slot_id).expect("configure before address");
+                    for dci in 2..32u8 {
+                        if add_flags & 1 << dci != 0 {
+                            let dequeue = read_u64(input + 32 * (dci as u64 + 1) + 8);
+                            slot.endpoints.insert(dci, RingCursor { dequeue: dequeue & !0xF, cycle: dequeue & 1 != 0 });
+                        }
+                    }
+                    self.complete_command(address, COMPLETION_SUCCESS, slot_id);
+                }
+                _ => self.complete_command(address, 5, slot_id),
+            }
+        }
+        self.command_ring = Some(ring);
+    }
+
+    fn run_control(&mut self, slot_id: u8) {
+        let Some(slot) = self.slots.get_mut(&slot_id) else {
+            return;
+        };
+        let device = self.ports[slot.port - 1].device.clone().expect("slot without device");
+        let mut events = Vec::new();
+        let mut setup = 0u64;
+        let mut data: Option<(u64, u32)> = None;
+        while let Some((address, trb)) = slot.control.next() {
+            match trb.trb_type() {
+                TRB_SETUP => {
+                    setup = trb.parameter;
+                    data = None;
+                }
+                TRB_DATA => data = Some((trb.parameter, trb.status & 0x1FFFF)),
+                TRB_STATUS => {
+                    let (request, value, length) = ((setup >> 8) as u8, (setup >> 16) as u16, (setup >> 48) as usize);
+                    let response: &[u8] = match (request, value >> 8) {
+                        (0x06, 1) => device.device_descriptor,
+                        (0x06, 2) => device.configuration,
+                        _ => &[],
+                    };
+                    match request {
+                        0x09 => slot.configuration = Some(value as u8),
+                        0x0B => slot.boot_protocol = value == 0,
+                        _ => {}
+                    }
+                    if let Some((buffer, _)) = data {
+                        write_bytes(buffer, &response[..length.min(response.len())]);
+                    }
+                    events.push(Trb::new(
+                        TRB_TRANSFER_EVENT,
+                        address,
+                        (COMPLETION_SUCCESS as u32) << 24,
+                        (slot_id as u32) << 24 | 1 << 16,
+                    ));
+                }
+                _ => {}
+            }
+        }
+        for event in events {
+            self.post_event(event);
+        }
+    }
+
+    fn queue_transfers(&mut self, slot_id: u8, dci: u8) {
+        let Some(slot) = self.slots.get_mut(&slot_id) else {
+            return;
+        };
+        let Some(ring) = slot.endpoints.get_mut(&dci) else {
+            return;
+        };
+        while let Some((address, trb)) = ring.next() {
+            if trb.trb_type() == TRB_NORMAL {
+                slot.pending.entry(dci).or_default().push((address, trb.parameter, trb.status & 0x1FFFF));
+            }
+        }
+    }
+
+    fn port_status(&self, index: usize) -> u32 {
+        let port = &self.ports[index];
+        let Some(device) = &port.device else {
+            return 1 << 9;
+        };
+        1 | (port.enabled as u32) << 1 | 1 << 9 | device.speed << 10 | (port.reset_change as u32) << 21
+    }
+}
+
+/// Register window of the emulated controller; clones share the controller
+#[derive(Clone)]
+pub struct FakeXhci(Arc<Mutex<FakeState>>);
+
+impl FakeXhci {
+    pub fn new(devices: &[(usize, FakeDevice)]) -> Self {
+        let mut ports: Vec<Port> = (0..PORTS).map(|_| Port::default()).collect();
+        for (port, device) in devices {
+            ports[port - 1].device = Some(device.clone());
+        }
+        Self(Arc::new(Mutex::new(FakeState {
+            command: 0,
+            crcr: 0,
+            dcbaa: 0,
+            erstba: 0,
+            command_ring: None,
+            event_enqueue: 0,
+            event_cycle: true,
+            ports,
+            slots: BTreeMap::new(),
+            next_slot: 0,
+            registers: BTreeMap::new(),
+            stuck_in_reset: false,
+        })))
+    }
+
+    pub fn set_stuck_in_reset(&self) {
+        self.0.lock().stuck_in_reset = true;
+    }
+
+    /// Whether the device on `port` was configured and put in boot protocol
+    pub fn is_configured(&self, port: usize) -> bool {
+        let state = self.0.lock();
+        state.slots.values().any(|slot| slot.port == port && slot.configuration == Some(1) && slot.boot_protocol)
+    }
+
+    /// Complete the oldest queued transfer on the device's endpoint 1 IN
+    /// with `report`; false if none is queued
+    pub fn send_report(&self, port: usize, report: &[u8]) -> bool {
+        let mut state = self.0.lock();
+        let Some((&slot_id, slot)) = state.slots.iter_mut().find(|(_, slot)| slot.port == port) else {
+            return false;
+        };
+        let Some((address, buffer, length)) = slot.pending.get_mut(&3).filter(|pending| !pending.is_empty()).map(|pending| pending.remove(0)) else {
+            return false;
+        };
+        write_bytes(buffer, report);
+        let residual = length.saturating_sub(report.len() as u32);
+        let code = if residual > 0 { COMPLETION_SHORT_PACKET } else { COMPLETION_SUCCESS };
+        state.post_event(Trb::new(TRB_TRANSFER_EVENT, address, (code as u32) << 24 | residual, (slot_id as u32) << 24 | 3 << 16));
+        true
+    }
+}
+
+impl Mmio for FakeXhci {
+    fn read_u8(&mut self, offset: usize) -> u8 {
+        match offset {
+            0 => CAP_LENGTH as u8,
+            _ => 0,
+        }
+    }
+
+    fn write_u8(&mut self, _offset: usize, _value: u8) {}
+
+    fn read_u16(&mut self, _offset: usize) -> u16 {
+        0
+    }
+
+    fn write_u16(&mut self, _offset: usize, _value: u16) {}
+
+    fn read_u32(&mut self, offset: usize) -> u32 {
+        let state = self.0.lock();
+        match offset {
+            0x04 => MAX_SLOTS | (PORTS as u32) << 24,
+            0x14 => DOORBELLS as u32,
+            0x18 => RUNTIME as u32,
+            // USBCMD
+            0x20 => state.command,
+            // USBSTS: halted unless running
+            0x24 => (state.command & 1 == 0) as u32,
+            // PAGESIZE: 4 KiB
+            0x28 => 1,
+            port if (CAP_LENGTH + 0x400..CAP_LENGTH + 0x400 + PORTS * 0x10).contains(&port) => {
+                let relative = port - CAP_LENGTH - 0x400;
+                if relative.is_multiple_of(0x10) { state.port_status(relative / 0x10) } else { 0 }
+            }
+            _ => state.registers.get(&offset).copied().unwrap_or(0),
+        }
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        let mut state = self.0.lock();
+        match offset {
+            0x20 => {
+                state.command = value;
+                if value & 2 != 0 && !state.stuck_in_reset {
+                    state.reset();
+                }
+            }
+            0x38 => state.crcr = (state.crcr & !0xFFFF_FFFF) | value as u64,
+            0x3C => state.crcr = (state.crcr & 0xFFFF_FFFF) | (value as u64) << 32,
+            0x50 => state.dcbaa = (state.dcbaa & !0xFFFF_FFFF) | value as u64,
+            0x54 => state.dcbaa = (state.dcbaa & 0xFFFF_FFFF) | (value as u64) << 32,
+            erstba if erstba == RUNTIME + 0x30 => state.erstba = (state.erstba & !0xFFFF_FFFF) | value as u64,
+            erstba if erstba == RUNTIME + 0x34 => state.erstba = (state.erstba & 0xFFFF_FFFF) | (value as u64) << 32,
+            port if (CAP_LENGTH + 0x400..CAP_LENGTH + 0x400 + PORTS * 0x10).contains(&port) => {
+                let relative = port - CAP_LENGTH - 0x400;
+                if !relative.is_multiple_of(0x10) {
+                    return;
+                }
+                let port = &mut state.ports[relative / 0x10];
+                if value & 1 << 4 != 0 && port.device.is_some() {
+                    port.enabled = true;
+                    port.reset_change = true;
+                }
+                if value & 1 << 21 != 0 {
+                    port.reset_change = false;
+                }
+            }
+            doorbell if doorbell >= DOORBELLS => {
+                let slot_id = ((doorbell - DOORBELLS) / 4) as u8;
+                match (slot_id, value as u8) {
+                    (0, _) => state.run_commands(),
+                    (slot_id, 1) => state.run_control(slot_id),
+                    (slot_id, dci) => state.queue_transfers(slot_id, dci),
+                }
+            }
+            _ => {
+                state.registers.insert(offset, value);
+            }
+        }
+    }
+}
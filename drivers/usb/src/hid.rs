@@ -0,0 +1,201 @@
+//! HID boot protocol report translation
+//!
+//! Boot keyboards report the modifier keys as a bitmap and up to six other
+//! keys held down; presses and releases are found by comparing each report
+//! with the previous one. Boot mice report buttons and relative movement.
+//! Both come out as the same events the PS/2 drivers produce.
+
+use alloc::vec::Vec;
+use kosh_driver::input::{
+    keycode_to_ascii, InputEvent, KeyCode, KeyEventType, KeyModifiers, MouseButtons, MouseEvent,
+};
+
+/// Length of a boot keyboard report
+pub const KEYBOARD_REPORT_LENGTH: usize = 8;
+/// Boot mouse reports carry at least buttons, X and Y
+pub const MOUSE_REPORT_MIN_LENGTH: usize = 3;
+
+/// Usage reported in every key slot when too many keys are held
+const USAGE_ERROR_ROLL_OVER: u8 = 0x01;
+
+/// Modifier byte bits, in order, and the key each one stands for
+///
+/// Kosh has no key codes for the right Ctrl and Alt keys or the GUI keys;
+/// the right-hand ones report as their left counterparts.
+const MODIFIER_KEYS: [Option<KeyCode>; 8] = [
+    Some(KeyCode::LeftCtrl),
+    Some(KeyCode::LeftShift),
+    Some(KeyCode::LeftAlt),
+    None,
+    Some(KeyCode::LeftCtrl),
+    Some(KeyCode::RightShift),
+    Some(KeyCode::LeftAlt),
+    None,
+];
+
+/// Key for a HID keyboard page usage
+pub fn usage_to_keycode(usage: u8) -> Option<KeyCode> {
+    const LETTERS: [KeyCode; 26] = [
+        KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+        KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N,
+        KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R, KeyCode::S, KeyCode::T, KeyCode::U,
+        KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    ];
+    const DIGITS: [KeyCode; 10] = [
+        KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5,
+        KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9, KeyCode::Key0,
+    ];
+    const FUNCTION_KEYS: [KeyCode; 12] = [
+        KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+        KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    ];
+
+    match usage {
+        0x04..=0x1D => Some(LETTERS[(usage - 0x04) as usize]),
+        0x1E..=0x27 => Some(DIGITS[(usage - 0x1E) as usize]),
+        0x28 => Some(KeyCode::Enter),
+        0x29 => Some(KeyCode::Escape),
+        0x2A => Some(KeyCode::Backspace),
+        0x2B => Some(KeyCode::Tab),
+        0x2C => Some(KeyCode::Space),
+        0x39 => Some(KeyCode::CapsLock),
+        0x3A..=0x45 => Some(FUNCTION_KEYS[(usage - 0x3A) as usize]),
+        0x49 => Some(KeyCode::Insert),
+        0x4A => Some(KeyCode::Home),
+        0x4B => Some(KeyCode::PageUp),
+        0x4C => Some(KeyCode::Delete),
+        0x4D => Some(KeyCode::End),
+        0x4E => Some(KeyCode::PageDown),
+        0x4F => Some(KeyCode::ArrowRight),
+        0x50 => Some(KeyCode::ArrowLeft),
+        0x51 => Some(KeyCode::ArrowDown),
+        0x52 => Some(KeyCode::ArrowUp),
+        _ => None,
+    }
+}
+
+/// Key state of one boot keyboard
+pub struct BootKeyboard {
+    /// Modifier byte and key usages of the last report
+    previous_modifiers: u8,
+    previous_keys: [u8; 6],
+    /// Lock state, which the reports do not carry
+    caps_lock: bool,
+}
+
+impl BootKeyboard {
+    pub fn new() -> Self {
+        Self {
+            previous_modifiers: 0,
+            previous_keys: [0; 6],
+            caps_lock: false,
+        }
+    }
+
+    /// Modifiers in effect with `modifier_byte` held
+    fn modifiers(&self, modifier_byte: u8) -> KeyModifiers {
+        let mut modifiers = KeyModifiers::empty();
+        if modifier_byte & 0x22 != 0 {
+            modifiers.insert(KeyModifiers::SHIFT);
+        }
+        if modifier_byte & 0x11 != 0 {
+            modifiers.insert(KeyModifiers::CTRL);
+        }
+        if modifier_byte & 0x44 != 0 {
+            modifiers.insert(KeyModifiers::ALT);
+        }
+        if self.caps_lock {
+            modifiers.insert(KeyModifiers::CAPS_LOCK);
+        }
+        modifiers
+    }
+
+    fn event(&self, event_type: KeyEventType, key_code: KeyCode, modifier_byte: u8, timestamp: u64) -> InputEvent {
+        let modifiers = self.modifiers(modifier_byte);
+        let ascii_char = match event_type {
+            KeyEventType::KeyPress => keycode_to_ascii(key_code, modifiers),
+            KeyEventType::KeyRelease => None,
+        };
+        InputEvent {
+            event_type,
+            key_code,
+            // Key codes are numbered after set 1 scancodes
+            scancode: key_code as u8,
+            modifiers,
+            ascii_char,
+            timestamp,
+        }
+    }
+
+    /// Events for the changes since the previous report
+    ///
+    /// Modifier changes come first, so keys pressed in the same report
+    /// already see them; releases come before presses.
+    pub fn process_report(&mut self, report: &[u8], timestamp: u64) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        if report.len() < KEYBOARD_REPORT_LENGTH {
+            return events;
+        }
+        let keys = &report[2..KEYBOARD_REPORT_LENGTH];
+        // Too many keys held to tell which: keep the last known state
+        if keys.iter().all(|&usage| usage == USAGE_ERROR_ROLL_OVER) {
+            return events;
+        }
+
+        let modifier_byte = report[0];
+        let mut held = self.previous_modifiers;
+        for (bit, key_code) in MODIFIER_KEYS.iter().enumerate() {
+            let mask = 1 << bit;
+            if (held ^ modifier_byte) & mask == 0 {
+                continue;
+            }
+            held ^= mask;
+            if let Some(key_code) = *key_code {
+                let event_type = if modifier_byte & mask != 0 { KeyEventType::KeyPress } else { KeyEventType::KeyRelease };
+                events.push(self.event(event_type, key_code, held, timestamp));
+            }
+        }
+
+        for &usage in self.previous_keys.iter().filter(|&&usage| usage > USAGE_ERROR_ROLL_OVER && !keys.contains(&usage)) {
+            if let Some(key_code) = usage_to_keycode(usage) {
+                events.push(self.event(KeyEventType::KeyRelease, key_code, modifier_byte, timestamp));
+            }
+        }
+        for &usage in keys.iter().filter(|&&usage| usage > USAGE_ERROR_ROLL_OVER && !self.previous_keys.contains(&usage)) {
+            let Some(key_code) = usage_to_keycode(usage) else {
+                continue;
+            };
+            if key_code == KeyCode::CapsLock {
+                self.caps_lock = !self.caps_lock;
+            }
+            events.push(self.event(KeyEventType::KeyPress, key_code, modifier_byte, timestamp));
+        }
+
+        self.previous_modifiers = modifier_byte;
+        self.previous_keys.copy_from_slice(keys);
+        events
+    }
+}
+
+impl Default for BootKeyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Translate a boot mouse report
+///
+/// The wheel byte is optional in the boot format; reports without it
+/// leave the wheel still.
+pub fn mouse_report_to_event(report: &[u8], timestamp: u64) -> Option<MouseEvent> {
+    if report.len() < MOUSE_REPORT_MIN_LENGTH {
+        return None;
+    }
+    Some(MouseEvent {
+        dx: report[1] as i8 as i32,
+        dy: report[2] as i8 as i32,
+        wheel: report.get(3).map_or(0, |&wheel| wheel as i8),
+        buttons: MouseButtons::from_bits_truncate(report[0]),
+        timestamp,
+    })
+}
@@ -0,0 +1,382 @@
+#![no_std]
+
+extern crate alloc;
+
+pub mod hid;
+pub mod usb;
+pub mod xhci;
+
+use alloc::{vec, vec::Vec, string::String, boxed::Box, collections::{BTreeMap, VecDeque}};
+use kosh_driver::{
+    KoshDriver, DriverInfo, DriverType, HardwareId, DriverStatus, PowerEvent,
+    DriverRequest, DriverResponse, DriverCapabilityType, HardwareCapability, MemoryCapability
+};
+use kosh_driver::hal::Mmio;
+pub use kosh_driver::input::{InputEvent, KeyModifiers, MouseButtons, MouseEvent};
+use kosh_driver::input::{InputRecord, encode_input_batch};
+use kosh_ipc::IpcError;
+use kosh_types::{DriverError, Capability, ProcessId};
+use kosh_sync::Mutex;
+use hid::BootKeyboard;
+use usb::BootProtocol;
+use xhci::{DmaMemory, XhciController};
+
+/// PCI class code of xHCI controllers: serial bus, USB, xHCI
+pub const XHCI_CLASS: (u8, u8, u8) = (0x0C, 0x03, 0x30);
+
+/// Controllers the factory takes, by vendor and device ID
+const XHCI_HARDWARE_IDS: [(u32, u32); 4] = [
+    (0x1B36, 0x000D), // QEMU qemu-xhci
+    (0x1033, 0x0194), // NEC uPD720200, QEMU nec-usb-xhci
+    (0x8086, 0x1E31), // Intel 7 Series
+    (0x8086, 0x8C31), // Intel 8 Series
+];
+
+/// USB keyboards and mice behind an xHCI controller
+///
+/// Reports are turned into the same `InputEvent`s the PS/2 keyboard driver
+/// queues, and into `MouseEvent`s, each in its own queue.
+pub struct UsbHidDriver {
+    controller: XhciController,
+    status: DriverStatus,
+    keyboards: BTreeMap<(u8, u8), BootKeyboard>,
+    key_events: VecDeque<InputEvent>,
+    mouse_events: VecDeque<MouseEvent>,
+    max_queue_size: usize,
+    /// Millisecond clock stamping events
+    clock: fn() -> u64,
+}
+
+impl UsbHidDriver {
+    /// Create a driver for the controller with registers `mmio`
+    pub fn new(mmio: Box<dyn Mmio>, dma: Box<dyn DmaMemory>) -> Self {
+        Self {
+            controller: XhciController::new(mmio, dma),
+            status: DriverStatus::Uninitialized,
+            keyboards: BTreeMap::new(),
+            key_events: VecDeque::new(),
+            mouse_events: VecDeque::new(),
+            max_queue_size: 256,
+            clock: kosh_driver::time::monotonic_ms,
+        }
+    }
+
+    /// Stamp events with `clock`, in milliseconds
+    pub fn set_clock(&mut self, clock: fn() -> u64) {
+        self.clock = clock;
+    }
+
+    pub fn controller(&self) -> &XhciController {
+        &self.controller
+    }
+
+    /// Look for newly connected devices
+    pub fn rescan(&mut self) -> Result<usize, DriverError> {
+        Ok(self.controller.enumerate()?)
+    }
+
+    /// Collect completed reports into the event queues
+    ///
+    /// Returns the number of events queued.
+    pub fn poll(&mut self) -> usize {
+        if !self.controller.is_running() {
+            return 0;
+        }
+        let timestamp = (self.clock)();
+        let mut queued = 0;
+        for report in self.controller.poll() {
+            match report.protocol {
+                BootProtocol::Keyboard => {
+                    let keyboard = self.keyboards.entry((report.slot_id, report.interface)).or_default();
+                    for event in keyboard.process_report(&report.data, timestamp) {
+                        Self::queue(&mut self.key_events, self.max_queue_size, event);
+                        queued += 1;
+                    }
+                }
+                BootProtocol::Mouse => {
+                    if let Some(event) = hid::mouse_report_to_event(&report.data, timestamp) {
+                        Self::queue(&mut self.mouse_events, self.max_queue_size, event);
+                        queued += 1;
+                    }
+                }
+            }
+        }
+        queued
+    }
+
+    /// Add an event, dropping the oldest ones if the queue is full
+    fn queue<T>(queue: &mut VecDeque<T>, max_size: usize, event: T) {
+        while queue.len() >= max_size {
+            queue.pop_front();
+        }
+        queue.push_back(event);
+    }
+
+    pub fn get_next_event(&mut self) -> Option<InputEvent> {
+        self.key_events.pop_front()
+    }
+
+    pub fn get_next_mouse_event(&mut self) -> Option<MouseEvent> {
+        self.mouse_events.pop_front()
+    }
+
+    pub fn has_events(&self) -> bool {
+        !self.key_events.is_empty() || !self.mouse_events.is_empty()
+    }
+
+    pub fn clear_events(&mut self) {
+        self.key_events.clear();
+        self.mouse_events.clear();
+    }
+
+    /// Send the queued key and then mouse events as input batches with
+    /// `send`
+    ///
+    /// Events that could not be sent stay queued.
+    pub fn forward_events<F>(&mut self, mut send: F) -> Result<(), IpcError>
+    where
+        F: FnMut(&[u8]) -> Result<(), IpcError>,
+    {
+        while !self.key_events.is_empty() {
+            let records: Vec<InputRecord> = self.key_events.iter().cloned().map(InputRecord::Key).collect();
+            let (batch, count) = encode_input_batch(&records);
+            send(&batch)?;
+            self.key_events.drain(..count);
+        }
+        while !self.mouse_events.is_empty() {
+            let records: Vec<InputRecord> = self.mouse_events.iter().copied().map(InputRecord::Mouse).collect();
+            let (batch, count) = encode_input_batch(&records);
+            send(&batch)?;
+            self.mouse_events.drain(..count);
+        }
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), DriverError> {
+        self.keyboards.clear();
+        self.clear_events();
+        self.controller.start()?;
+        self.controller.enumerate()?;
+        Ok(())
+    }
+}
+
+impl KoshDriver for UsbHidDriver {
+    fn init(&mut self, _capabilities: Vec<Capability>) -> Result<(), DriverError> {
+        self.status = DriverStatus::Initializing;
+        self.start()?;
+        self.status = DriverStatus::Ready;
+        Ok(())
+    }
+
+    fn handle_request(&mut self, request: DriverRequest) -> Result<DriverResponse, DriverError> {
+        match request {
+            DriverRequest::Initialize => {
+                self.init(Vec::new())?;
+                Ok(DriverResponse::Success)
+            }
+
+            DriverRequest::Read { .. } => {
+                // Key events in the PS/2 driver's layout
+                self.poll();
+                let mut event_data = Vec::new();
+                while let Some(event) = self.get_next_event() {
+                    event_data.push(event.event_type as u8);
+                    event_data.push(event.key_code as u8);
+                    event_data.push(event.scancode);
+                    event_data.push(event.modifiers.bits());
+                    match event.ascii_char {
+                        Some(ascii) => event_data.extend([1, ascii as u8]),
+                        None => event_data.extend([0, 0]),
+                    }
+                }
+                Ok(DriverResponse::Data(event_data))
+            }
+
+            DriverRequest::Control { command, .. } => {
+                match command {
+                    // Clear event queues
+                    0x01 => {
+                        self.clear_events();
+                        Ok(DriverResponse::Success)
+                    }
+                    // Look for newly connected devices
+                    0x02 => {
+                        self.rescan()?;
+                        Ok(DriverResponse::Success)
+                    }
+                    // Read mouse events: dx and dy as i32, wheel, buttons
+                    0x03 => {
+                        self.poll();
+                        let mut event_data = Vec::new();
+                        while let Some(event) = self.get_next_mouse_event() {
+                            event_data.extend(event.dx.to_le_bytes());
+                            event_data.extend(event.dy.to_le_bytes());
+                            event_data.push(event.wheel as u8);
+                            event_data.push(event.buttons.bits());
+                        }
+                        Ok(DriverResponse::Data(event_data))
+                    }
+                    _ => Err(DriverError::InvalidRequest)
+                }
+            }
+
+            DriverRequest::Query { query_type } => {
+                match query_type {
+                    kosh_driver::QueryType::Status => {
+                        Ok(DriverResponse::Status(self.status))
+                    }
+                    kosh_driver::QueryType::HardwareInfo => {
+                        Ok(DriverResponse::Info(self.get_driver_info()))
+                    }
+                    kosh_driver::QueryType::Statistics => {
+                        // Devices, then queued key and mouse events
+                        let stats = vec![
+                            self.controller.devices().len() as u8,
+                            self.key_events.len() as u8,
+                            self.mouse_events.len() as u8,
+                        ];
+                        Ok(DriverResponse::Data(stats))
+                    }
+                    _ => Err(DriverError::InvalidRequest)
+                }
+            }
+
+            _ => Err(DriverError::InvalidRequest)
+        }
+    }
+
+    fn cleanup(&mut self) -> Result<(), DriverError> {
+        self.status = DriverStatus::Stopping;
+        self.controller.stop();
+        self.keyboards.clear();
+        self.clear_events();
+        self.status = DriverStatus::Uninitialized;
+        Ok(())
+    }
+
+    fn get_required_capabilities(&self) -> Vec<DriverCapabilityType> {
+        vec![
+            DriverCapabilityType::Hardware(HardwareCapability::PciDevice { vendor_id: 0x1B36, device_id: 0x000D }),
+            DriverCapabilityType::Memory(MemoryCapability::DmaMemory),
+            DriverCapabilityType::HardwareAccess,
+        ]
+    }
+
+    fn get_provided_capabilities(&self) -> Vec<DriverCapabilityType> {
+        vec![
+            DriverCapabilityType::Custom(String::from("keyboard_input")),
+            DriverCapabilityType::Custom(String::from("mouse_input")),
+            DriverCapabilityType::Custom(String::from("input_events")),
+        ]
+    }
+
+    fn get_driver_info(&self) -> DriverInfo {
+        DriverInfo {
+            name: String::from("USB HID Driver"),
+            version: String::from("1.0.0"),
+            vendor: String::from("Kosh OS"),
+            description: String::from("xHCI host controller driver with boot protocol USB keyboards and mice"),
+            driver_type: DriverType::Input,
+            hardware_ids: XHCI_HARDWARE_IDS.iter()
+                .map(|&(vendor_id, device_id)| HardwareId {
+                    vendor_id,
+                    device_id,
+                    subsystem_vendor_id: None,
+                    subsystem_device_id: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn handle_power_event(&mut self, event: PowerEvent) -> Result<(), DriverError> {
+        match event {
+            PowerEvent::Suspend => {
+                self.controller.stop();
+                self.clear_events();
+                self.status = DriverStatus::Suspended;
+                Ok(())
+            }
+            PowerEvent::Resume => {
+                // Devices may have changed while suspended; start afresh
+                self.start()?;
+                self.status = DriverStatus::Ready;
+                Ok(())
+            }
+            PowerEvent::PowerDown => {
+                self.cleanup()
+            }
+            _ => Ok(())
+        }
+    }
+
+    fn get_status(&self) -> DriverStatus {
+        self.status
+    }
+}
+
+/// Global USB driver instance protected by mutex
+static USB_DRIVER: Mutex<Option<UsbHidDriver>> = Mutex::new(None);
+
+/// Initialize the global USB driver on the given controller
+pub fn init_usb_driver(mmio: Box<dyn Mmio>, dma: Box<dyn DmaMemory>) -> Result<(), DriverError> {
+    let mut driver_guard = USB_DRIVER.lock();
+    let mut driver = UsbHidDriver::new(mmio, dma);
+    driver.init(Vec::new())?;
+    *driver_guard = Some(driver);
+    Ok(())
+}
+
+/// Collect reports into the global driver's event queues
+pub fn usb_poll() -> usize {
+    let mut driver_guard = USB_DRIVER.lock();
+    match driver_guard.as_mut() {
+        Some(driver) => driver.poll(),
+        None => 0,
+    }
+}
+
+/// Send the global driver's queued events to the input service at `pid`
+pub fn usb_forward_events(pid: ProcessId) -> Result<(), IpcError> {
+    match USB_DRIVER.lock().as_mut() {
+        Some(driver) => driver.forward_events(|batch| kosh_ipc::syscall::send_message(pid, batch)),
+        None => Ok(()),
+    }
+}
+
+/// Get the next key event from the global USB driver
+pub fn usb_get_event() -> Option<InputEvent> {
+    USB_DRIVER.lock().as_mut().and_then(UsbHidDriver::get_next_event)
+}
+
+/// Get the next mouse event from the global USB driver
+pub fn usb_get_mouse_event() -> Option<MouseEvent> {
+    USB_DRIVER.lock().as_mut().and_then(UsbHidDriver::get_next_mouse_event)
+}
+
+/// Driver factory matching the xHCI controllers in `XHCI_HARDWARE_IDS`
+///
+/// The driver needs the controller's mapped registers and DMA memory,
+/// which the factory interface cannot pass, so the driver process creates
+/// it with `init_usb_driver` instead.
+pub struct UsbDriverFactory;
+
+impl kosh_driver::DriverFactory for UsbDriverFactory {
+    fn create_driver(&self, _hardware_id: &HardwareId) -> Result<Box<dyn KoshDriver>, DriverError> {
+        Err(DriverError::InvalidRequest)
+    }
+
+    fn can_handle(&self, hardware_id: &HardwareId) -> bool {
+        XHCI_HARDWARE_IDS.contains(&(hardware_id.vendor_id, hardware_id.device_id))
+    }
+
+    fn get_driver_type(&self) -> DriverType {
+        DriverType::Input
+    }
+}
+
+#[cfg(test)]
+mod fixtures;
+
+#[cfg(test)]
+mod tests;
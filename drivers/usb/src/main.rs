@@ -0,0 +1,148 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use kosh_driver::dma::{DmaBuffer, DmaCache};
+use kosh_driver::input::INPUT_SERVICE_NAME;
+use kosh_driver::hal::{map_mmio, HardwarePortIo};
+use kosh_driver::pci::{self, ConfigSpace, PciAddress, PortConfigSpace};
+use kosh_driver::report;
+use kosh_driver::time::{sleep_us, PeriodicTimer};
+use kosh_driver::{DriverMetadataRecord, DriverSignatureRecord, DriverType};
+use kosh_ipc::irq;
+use kosh_posix::NamedProcess;
+use kosh_usb_driver::xhci::{DmaPool, DMA_PAGE_SIZE};
+use kosh_usb_driver::{init_usb_driver, usb_forward_events, usb_poll, XHCI_CLASS};
+
+/// Memory space and bus master enable in the command register
+const COMMAND_MEMORY_AND_BUS_MASTER: u32 = 0x6;
+/// Register window mapped for the controller
+const REGISTER_WINDOW_SIZE: usize = 64 * 1024;
+
+const DMA_POOL_SIZE: usize = 64 * DMA_PAGE_SIZE;
+
+/// Time between polls of a controller that cannot interrupt, the usual
+/// HID report interval
+const POLL_INTERVAL_US: u64 = 8_000;
+
+/// Read by the driver manager: QEMU qemu-xhci and nec-usb-xhci, Intel 7
+/// and 8 Series. The controller is found through the configuration ports
+/// and its registers are granted once the manager binds it.
+#[used]
+#[link_section = ".kosh_driver"]
+static DRIVER_METADATA: DriverMetadataRecord = DriverMetadataRecord::new("usb-xhci", "0.1.0", DriverType::Input)
+    .with_hardware_id(0x1B36, 0x000D)
+    .with_hardware_id(0x1033, 0x0194)
+    .with_hardware_id(0x8086, 0x1E31)
+    .with_hardware_id(0x8086, 0x8C31)
+    .requires_io_ports(0xCF8, 0xCFF)
+    .requires_pci_device(0x1B36, 0x000D)
+    .requires_pci_device(0x1033, 0x0194)
+    .requires_pci_device(0x8086, 0x1E31)
+    .requires_pci_device(0x8086, 0x8C31)
+    .requires_dma_memory();
+
+/// Filled in when the driver is signed
+#[used]
+#[link_section = ".kosh_signature"]
+static DRIVER_SIGNATURE: DriverSignatureRecord = DriverSignatureRecord::UNSIGNED;
+
+/// Find the first xHCI controller, enable it and return its address and
+/// register base
+fn find_controller(config: &mut dyn ConfigSpace) -> Option<(PciAddress, usize)> {
+    let device = pci::enumerate(config)
+        .into_iter()
+        .find(|device| (device.class, device.subclass, device.prog_if) == XHCI_CLASS)?;
+
+    let low = config.read_u32(device.address, pci::BAR0);
+    // Bits 1-2 give the BAR width; 64-bit BARs continue in the next one
+    let high = if low & 0x6 == 0x4 { config.read_u32(device.address, pci::BAR0 + 4) } else { 0 };
+
+    // Only touch the command half: status bits are cleared by writing 1
+    let command = config.read_u32(device.address, pci::COMMAND_STATUS) & 0xFFFF;
+    config.write_u32(device.address, pci::COMMAND_STATUS, command | COMMAND_MEMORY_AND_BUS_MASTER);
+
+    Some((device.address, ((high as u64) << 32 | (low & !0xF) as u64) as usize))
+}
+
+/// Have the controller signal its interrupter with a message, returning
+/// the line it raises
+///
+/// None where the platform or the controller has no MSI.
+fn enable_interrupt(config: &mut dyn ConfigSpace, address: PciAddress) -> Option<u32> {
+    let capability = pci::msi_capability(config, address)?;
+    let allocation = irq::allocate_msi(address.bus, address.device, address.function, 1, 0).ok()?;
+    pci::enable_msi(config, address, &capability, allocation.address, allocation.data_for(0), 1);
+    allocation.line(0)
+}
+
+/// Entry point for the USB driver process
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let mut config = PortConfigSpace::new(HardwarePortIo);
+    let mut line = None;
+    if let Some((pci_address, registers)) = find_controller(&mut config) {
+        let mmio = match map_mmio(registers as u64, REGISTER_WINDOW_SIZE) {
+            Ok(mmio) => mmio,
+            Err(e) => report::fail(format_args!("Failed to map xHCI registers: {:?}", e)),
+        };
+        // Rings and contexts are shared with the controller without any
+        // syncing, so the pool is uncached
+        let pages = match DmaBuffer::allocate(DMA_POOL_SIZE, DmaCache::Uncached) {
+            Ok(pages) => pages,
+            Err(e) => report::fail(format_args!("Failed to allocate xHCI DMA memory: {:?}", e)),
+        };
+        let (address, device_address) = pages.leak();
+        let dma = unsafe { DmaPool::new(address, device_address, DMA_POOL_SIZE) };
+        if let Err(e) = init_usb_driver(Box::new(mmio), Box::new(dma)) {
+            report::fail(format_args!("Failed to initialize USB driver: {:?}", e));
+        }
+        line = enable_interrupt(&mut config, pci_address);
+    }
+
+    // Main driver loop: sleep until the controller's interrupter fires;
+    // without MSI, poll it once per report interval instead
+    let timer = match line {
+        Some(_) => None,
+        None => PeriodicTimer::new(POLL_INTERVAL_US),
+    };
+    // The input service may start after the driver, so it is looked up
+    // by name as input arrives
+    let mut input_service = NamedProcess::new(INPUT_SERVICE_NAME);
+    loop {
+        match (line, &timer) {
+            (Some(line), _) => {
+                if let Err(e) = irq::wait(line, 0) {
+                    report::fail(format_args!("Waiting for xHCI interrupt failed: {:?}", e));
+                }
+            }
+            (None, Some(timer)) => {
+                timer.wait();
+            }
+            (None, None) => sleep_us(POLL_INTERVAL_US),
+        }
+
+        // Queue input from completed reports and hand it to the input
+        // service; if it is not running yet the input waits in the queue
+        usb_poll();
+        if let Some(line) = line {
+            let _ = irq::acknowledge(line);
+        }
+        if let Some(pid) = input_service.pid() {
+            // Sending fails once the service is gone; look it up again, as
+            // a restarted one runs under a new process ID
+            if usb_forward_events(pid).is_err() {
+                input_service.forget();
+            }
+        }
+    }
+}
+
+/// Panic handler for the driver (only in non-test builds)
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    report::report_panic("usb", info)
+}
@@ -0,0 +1,371 @@
+use super::*;
+use crate::fixtures::{
+    dma_pool, FakeDevice, FakeXhci, SharedPool, QEMU_KEYBOARD_CONFIGURATION, QEMU_KEYBOARD_DEVICE,
+    QEMU_MOUSE_CONFIGURATION,
+};
+use crate::hid::usage_to_keycode;
+use crate::usb::{Configuration, DeviceDescriptor, SetupPacket, Speed, DESCRIPTOR_CONFIGURATION};
+use crate::xhci::{endpoint_interval, DmaMemory, Ring, Trb, DMA_PAGE_SIZE, TRB_LINK, TRB_NORMAL};
+use kosh_driver::{DriverFactory, QueryType};
+use kosh_driver::input::{KeyCode, KeyEventType};
+
+const POOL_PAGES: usize = 64;
+
+fn fixed_clock() -> u64 {
+    42
+}
+
+/// A driver on an emulated controller with a keyboard on port 1 and a
+/// mouse on port 3
+fn hid_driver() -> (UsbHidDriver, FakeXhci, SharedPool) {
+    let fake = FakeXhci::new(&[(1, FakeDevice::keyboard()), (3, FakeDevice::mouse())]);
+    let pool = SharedPool::new(POOL_PAGES);
+    let mut driver = UsbHidDriver::new(Box::new(fake.clone()), Box::new(pool.clone()));
+    driver.set_clock(fixed_clock);
+    (driver, fake, pool)
+}
+
+fn trb_at(address: u64) -> Trb {
+    let words = address as *const u32;
+    unsafe {
+        Trb {
+            parameter: words.read_volatile() as u64 | (words.add(1).read_volatile() as u64) << 32,
+            status: words.add(2).read_volatile(),
+            control: words.add(3).read_volatile(),
+        }
+    }
+}
+
+#[test]
+fn test_device_descriptor_parsing() {
+    let descriptor = DeviceDescriptor::parse(&QEMU_KEYBOARD_DEVICE).unwrap();
+    assert_eq!(descriptor.usb_version, 0x0200);
+    assert_eq!(descriptor.max_packet_size0, 8);
+    assert_eq!(descriptor.vendor_id, 0x0627);
+    assert_eq!(descriptor.product_id, 0x0001);
+    assert_eq!(descriptor.configurations, 1);
+
+    // The 8-byte prefix only carries the control packet size
+    let prefix = DeviceDescriptor::parse(&QEMU_KEYBOARD_DEVICE[..8]).unwrap();
+    assert_eq!(prefix.max_packet_size0, 8);
+    assert_eq!(prefix.vendor_id, 0);
+
+    assert!(DeviceDescriptor::parse(&QEMU_KEYBOARD_DEVICE[..4]).is_none());
+    assert!(DeviceDescriptor::parse(&QEMU_KEYBOARD_CONFIGURATION[..18]).is_none());
+}
+
+#[test]
+fn test_configuration_parsing() {
+    assert_eq!(Configuration::total_length(&QEMU_KEYBOARD_CONFIGURATION[..9]), Some(34));
+
+    let keyboard = Configuration::parse(&QEMU_KEYBOARD_CONFIGURATION).unwrap();
+    assert_eq!(keyboard.value, 1);
+    assert_eq!(keyboard.hid_interfaces.len(), 1);
+    let interface = keyboard.hid_interfaces[0];
+    assert_eq!(interface.protocol, BootProtocol::Keyboard);
+    assert_eq!(interface.endpoint_address, 0x81);
+    assert_eq!(interface.endpoint_number(), 1);
+    assert_eq!(interface.max_packet_size, 8);
+
+    let mouse = Configuration::parse(&QEMU_MOUSE_CONFIGURATION).unwrap();
+    assert_eq!(mouse.hid_interfaces[0].protocol, BootProtocol::Mouse);
+    assert_eq!(mouse.hid_interfaces[0].max_packet_size, 4);
+    assert_eq!(mouse.hid_interfaces[0].interval, 10);
+}
+
+#[test]
+fn test_configuration_skips_non_boot_interfaces() {
+    let mut bytes = QEMU_KEYBOARD_CONFIGURATION;
+    // Report protocol only: no boot subclass
+    bytes[9 + 6] = 0;
+    let configuration = Configuration::parse(&bytes).unwrap();
+    assert!(configuration.hid_interfaces.is_empty());
+}
+
+#[test]
+fn test_setup_packet_encoding() {
+    let packet = SetupPacket::get_descriptor(DESCRIPTOR_CONFIGURATION, 0, 9);
+    assert!(packet.is_device_to_host());
+    assert_eq!(packet.to_u64(), 0x0009_0000_0200_0680);
+
+    let packet = SetupPacket::set_boot_protocol(2);
+    assert!(!packet.is_device_to_host());
+    assert_eq!(packet.to_u64(), 0x0000_0002_0000_0B21);
+
+    assert_eq!(SetupPacket::set_configuration(1).to_u64(), 0x0000_0000_0001_0900);
+}
+
+#[test]
+fn test_usage_to_keycode() {
+    assert_eq!(usage_to_keycode(0x04), Some(KeyCode::A));
+    assert_eq!(usage_to_keycode(0x1D), Some(KeyCode::Z));
+    assert_eq!(usage_to_keycode(0x1E), Some(KeyCode::Key1));
+    assert_eq!(usage_to_keycode(0x27), Some(KeyCode::Key0));
+    assert_eq!(usage_to_keycode(0x28), Some(KeyCode::Enter));
+    assert_eq!(usage_to_keycode(0x3A), Some(KeyCode::F1));
+    assert_eq!(usage_to_keycode(0x52), Some(KeyCode::ArrowUp));
+    assert_eq!(usage_to_keycode(0x00), None);
+}
+
+#[test]
+fn test_keyboard_report_press_and_release() {
+    let mut keyboard = BootKeyboard::new();
+
+    let events = keyboard.process_report(&[0, 0, 0x04, 0, 0, 0, 0, 0], 7);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event_type, KeyEventType::KeyPress);
+    assert_eq!(events[0].key_code, KeyCode::A);
+    assert_eq!(events[0].ascii_char, Some('a'));
+    assert_eq!(events[0].timestamp, 7);
+
+    // Holding the key does not repeat it
+    assert!(keyboard.process_report(&[0, 0, 0x04, 0, 0, 0, 0, 0], 8).is_empty());
+
+    let events = keyboard.process_report(&[0; 8], 9);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event_type, KeyEventType::KeyRelease);
+    assert_eq!(events[0].key_code, KeyCode::A);
+    assert_eq!(events[0].ascii_char, None);
+}
+
+#[test]
+fn test_keyboard_report_modifiers() {
+    let mut keyboard = BootKeyboard::new();
+
+    // Left shift and A in the same report: the shift comes first
+    let events = keyboard.process_report(&[0x02, 0, 0x04, 0, 0, 0, 0, 0], 0);
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].key_code, KeyCode::LeftShift);
+    assert_eq!(events[0].event_type, KeyEventType::KeyPress);
+    assert_eq!(events[1].key_code, KeyCode::A);
+    assert!(events[1].modifiers.contains(KeyModifiers::SHIFT));
+    assert_eq!(events[1].ascii_char, Some('A'));
+
+    let events = keyboard.process_report(&[0; 8], 1);
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].key_code, KeyCode::LeftShift);
+    assert_eq!(events[0].event_type, KeyEventType::KeyRelease);
+    assert_eq!(events[1].key_code, KeyCode::A);
+    assert_eq!(events[1].event_type, KeyEventType::KeyRelease);
+}
+
+#[test]
+fn test_keyboard_report_caps_lock() {
+    let mut keyboard = BootKeyboard::new();
+    keyboard.process_report(&[0, 0, 0x39, 0, 0, 0, 0, 0], 0);
+    keyboard.process_report(&[0; 8], 0);
+
+    let events = keyboard.process_report(&[0, 0, 0x05, 0, 0, 0, 0, 0], 0);
+    assert!(events[0].modifiers.contains(KeyModifiers::CAPS_LOCK));
+    assert_eq!(events[0].ascii_char, Some('B'));
+}
+
+#[test]
+fn test_keyboard_report_rollover_keeps_state() {
+    let mut keyboard = BootKeyboard::new();
+    keyboard.process_report(&[0, 0, 0x04, 0x05, 0, 0, 0, 0], 0);
+
+    assert!(keyboard.process_report(&[0, 0, 1, 1, 1, 1, 1, 1], 0).is_empty());
+
+    // Back from rollover, only the key let go is released
+    let events = keyboard.process_report(&[0, 0, 0x04, 0, 0, 0, 0, 0], 0);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].key_code, KeyCode::B);
+    assert_eq!(events[0].event_type, KeyEventType::KeyRelease);
+}
+
+#[test]
+fn test_keyboard_report_too_short() {
+    let mut keyboard = BootKeyboard::new();
+    assert!(keyboard.process_report(&[0, 0, 0x04], 0).is_empty());
+}
+
+#[test]
+fn test_mouse_report() {
+    let event = hid::mouse_report_to_event(&[0x01, 0x05, 0xFE, 0xFF], 3).unwrap();
+    assert_eq!(event.dx, 5);
+    assert_eq!(event.dy, -2);
+    assert_eq!(event.wheel, -1);
+    assert_eq!(event.buttons, MouseButtons::LEFT);
+    assert_eq!(event.timestamp, 3);
+
+    // The wheel byte is optional
+    let event = hid::mouse_report_to_event(&[0x06, 0, 0], 0).unwrap();
+    assert_eq!(event.wheel, 0);
+    assert_eq!(event.buttons, MouseButtons::RIGHT | MouseButtons::MIDDLE);
+
+    assert!(hid::mouse_report_to_event(&[0, 1], 0).is_none());
+}
+
+#[test]
+fn test_endpoint_interval() {
+    // 10 frames is 80 microframes; the nearest power of two below is 2^6
+    assert_eq!(endpoint_interval(Speed::Full, 10), 6);
+    assert_eq!(endpoint_interval(Speed::Low, 1), 3);
+    assert_eq!(endpoint_interval(Speed::Full, 255), 10);
+    assert_eq!(endpoint_interval(Speed::High, 4), 3);
+    assert_eq!(endpoint_interval(Speed::Super, 0), 0);
+}
+
+#[test]
+fn test_dma_pool_allocation() {
+    let mut pool = dma_pool(2);
+    let first = pool.allocate().unwrap();
+    let second = pool.allocate().unwrap();
+    assert!(pool.allocate().is_none());
+    assert_eq!(first.size, DMA_PAGE_SIZE);
+    assert_eq!(second.phys, first.phys + DMA_PAGE_SIZE as u64);
+
+    pool.free(first);
+    pool.free(first);
+    assert_eq!(pool.free_pages(), 1);
+    assert_eq!(pool.allocate(), Some(first));
+}
+
+#[test]
+fn test_ring_wraps_through_link() {
+    let mut pool = dma_pool(1);
+    let memory = pool.allocate().unwrap();
+    let mut ring = Ring::new(memory, 4);
+
+    let addresses: Vec<u64> = (0..3).map(|index| ring.push(Trb::new(TRB_NORMAL, index, 0, 0))).collect();
+    assert_eq!(addresses, [memory.phys, memory.phys + 16, memory.phys + 32]);
+
+    // The link was handed over with the first lap's cycle and points back
+    let link = trb_at(memory.phys + 48);
+    assert_eq!(link.trb_type(), TRB_LINK);
+    assert_eq!(link.parameter, memory.phys);
+    assert!(link.cycle());
+
+    // The second lap uses the other cycle state
+    assert_eq!(ring.push(Trb::new(TRB_NORMAL, 3, 0, 0)), memory.phys);
+    let trb = trb_at(memory.phys);
+    assert_eq!(trb.parameter, 3);
+    assert!(!trb.cycle());
+}
+
+#[test]
+fn test_driver_enumerates_keyboard_and_mouse() {
+    let (mut driver, fake, _pool) = hid_driver();
+    assert!(driver.init(vec![]).is_ok());
+    assert_eq!(driver.get_status(), DriverStatus::Ready);
+    assert_eq!(driver.controller().max_ports(), 4);
+
+    let devices = driver.controller().devices();
+    assert_eq!(devices.len(), 2);
+    let keyboard = devices.iter().find(|device| device.port == 1).unwrap();
+    assert_eq!((keyboard.keyboards, keyboard.mice), (1, 0));
+    assert_eq!((keyboard.vendor_id, keyboard.product_id), (0x0627, 0x0001));
+    assert_eq!(keyboard.speed, Speed::Full);
+    let mouse = devices.iter().find(|device| device.port == 3).unwrap();
+    assert_eq!((mouse.keyboards, mouse.mice), (0, 1));
+
+    assert!(fake.is_configured(1));
+    assert!(fake.is_configured(3));
+
+    // Nothing new to find
+    assert!(matches!(driver.rescan(), Ok(0)));
+}
+
+#[test]
+fn test_driver_queues_key_events() {
+    let (mut driver, fake, _pool) = hid_driver();
+    driver.init(vec![]).unwrap();
+
+    assert!(fake.send_report(1, &[0x02, 0, 0x0B, 0, 0, 0, 0, 0]));
+    assert_eq!(driver.poll(), 2);
+    assert_eq!(driver.get_next_event().unwrap().key_code, KeyCode::LeftShift);
+    let event = driver.get_next_event().unwrap();
+    assert_eq!(event.key_code, KeyCode::H);
+    assert_eq!(event.ascii_char, Some('H'));
+    assert_eq!(event.timestamp, 42);
+
+    // The transfer was queued again for the next report
+    assert!(fake.send_report(1, &[0; 8]));
+    assert_eq!(driver.poll(), 2);
+}
+
+#[test]
+fn test_driver_queues_mouse_events() {
+    let (mut driver, fake, _pool) = hid_driver();
+    driver.init(vec![]).unwrap();
+
+    // A short report without the wheel byte
+    assert!(fake.send_report(3, &[0x01, 0x10, 0xF0]));
+    assert_eq!(driver.poll(), 1);
+    let event = driver.get_next_mouse_event().unwrap();
+    assert_eq!((event.dx, event.dy, event.wheel), (16, -16, 0));
+    assert_eq!(event.buttons, MouseButtons::LEFT);
+    assert!(driver.get_next_event().is_none());
+
+    assert!(fake.send_report(3, &[0, 1, 2, 3]));
+    let response = driver.handle_request(DriverRequest::Control { command: 0x03, data: vec![] }).unwrap();
+    assert!(matches!(response, DriverResponse::Data(data) if data == [1, 0, 0, 0, 2, 0, 0, 0, 3, 0]));
+}
+
+#[test]
+fn test_driver_read_request_layout() {
+    let (mut driver, fake, _pool) = hid_driver();
+    driver.init(vec![]).unwrap();
+
+    fake.send_report(1, &[0, 0, 0x04, 0, 0, 0, 0, 0]);
+    let response = driver.handle_request(DriverRequest::Read { offset: 0, length: 0 }).unwrap();
+    let expected = vec![KeyEventType::KeyPress as u8, KeyCode::A as u8, KeyCode::A as u8, 0, 1, b'a'];
+    assert!(matches!(response, DriverResponse::Data(data) if data == expected));
+
+    let response = driver.handle_request(DriverRequest::Query { query_type: QueryType::Statistics }).unwrap();
+    assert!(matches!(response, DriverResponse::Data(data) if data == [2, 0, 0]));
+}
+
+#[test]
+fn test_driver_cleanup_returns_dma_memory() {
+    let (mut driver, _fake, pool) = hid_driver();
+    driver.init(vec![]).unwrap();
+    assert!(pool.free_pages() < POOL_PAGES);
+
+    assert!(driver.cleanup().is_ok());
+    assert_eq!(driver.get_status(), DriverStatus::Uninitialized);
+    assert_eq!(pool.free_pages(), POOL_PAGES);
+}
+
+#[test]
+fn test_driver_suspend_and_resume() {
+    let (mut driver, fake, pool) = hid_driver();
+    driver.init(vec![]).unwrap();
+
+    driver.handle_power_event(PowerEvent::Suspend).unwrap();
+    assert_eq!(driver.get_status(), DriverStatus::Suspended);
+    assert_eq!(pool.free_pages(), POOL_PAGES);
+    assert_eq!(driver.poll(), 0);
+
+    driver.handle_power_event(PowerEvent::Resume).unwrap();
+    assert_eq!(driver.get_status(), DriverStatus::Ready);
+    assert_eq!(driver.controller().devices().len(), 2);
+    assert!(fake.send_report(3, &[0, 1, 1]));
+    assert_eq!(driver.poll(), 1);
+}
+
+#[test]
+fn test_controller_reset_timeout() {
+    let fake = FakeXhci::new(&[]);
+    fake.set_stuck_in_reset();
+    let mut driver = UsbHidDriver::new(Box::new(fake), Box::new(SharedPool::new(POOL_PAGES)));
+    assert!(matches!(driver.init(vec![]), Err(DriverError::InitializationFailed)));
+}
+
+#[test]
+fn test_driver_out_of_dma_memory() {
+    let fake = FakeXhci::new(&[(1, FakeDevice::keyboard())]);
+    let mut driver = UsbHidDriver::new(Box::new(fake), Box::new(SharedPool::new(1)));
+    assert!(driver.init(vec![]).is_err());
+}
+
+#[test]
+fn test_factory_matches_xhci_controllers() {
+    let factory = UsbDriverFactory;
+    let id = |vendor_id, device_id| HardwareId { vendor_id, device_id, subsystem_vendor_id: None, subsystem_device_id: None };
+    assert!(factory.can_handle(&id(0x1B36, 0x000D)));
+    assert!(factory.can_handle(&id(0x8086, 0x1E31)));
+    assert!(!factory.can_handle(&id(0x8086, 0x100E)));
+    assert_eq!(factory.get_driver_type(), DriverType::Input);
+}
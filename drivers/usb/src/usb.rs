@@ -0,0 +1,281 @@
+//! USB standard requests and descriptors
+//!
+//! Only what enumeration of a HID device needs: the device descriptor, the
+//! configuration descriptor with its interfaces and endpoints, and the
+//! handful of standard and HID class requests sent on endpoint 0.
+
+use alloc::vec::Vec;
+
+/// Descriptor types
+pub const DESCRIPTOR_DEVICE: u8 = 1;
+pub const DESCRIPTOR_CONFIGURATION: u8 = 2;
+pub const DESCRIPTOR_INTERFACE: u8 = 4;
+pub const DESCRIPTOR_ENDPOINT: u8 = 5;
+
+/// Standard requests
+const REQUEST_GET_DESCRIPTOR: u8 = 0x06;
+const REQUEST_SET_CONFIGURATION: u8 = 0x09;
+
+/// HID class requests
+const HID_REQUEST_SET_IDLE: u8 = 0x0A;
+const HID_REQUEST_SET_PROTOCOL: u8 = 0x0B;
+
+/// `bmRequestType` values
+const DEVICE_TO_HOST_STANDARD: u8 = 0x80;
+const HOST_TO_DEVICE_STANDARD: u8 = 0x00;
+const HOST_TO_DEVICE_CLASS_INTERFACE: u8 = 0x21;
+
+pub const CLASS_HID: u8 = 0x03;
+/// HID interfaces that speak the fixed boot report formats
+pub const HID_SUBCLASS_BOOT: u8 = 0x01;
+
+/// Length of the device descriptor, and of its prefix that holds
+/// `bMaxPacketSize0`
+pub const DEVICE_DESCRIPTOR_LENGTH: u16 = 18;
+pub const DEVICE_DESCRIPTOR_PREFIX_LENGTH: u16 = 8;
+/// Length of the configuration descriptor header holding `wTotalLength`
+pub const CONFIGURATION_HEADER_LENGTH: u16 = 9;
+
+const ENDPOINT_DIRECTION_IN: u8 = 0x80;
+const ENDPOINT_TRANSFER_TYPE_MASK: u8 = 0x03;
+const ENDPOINT_TRANSFER_INTERRUPT: u8 = 0x03;
+
+/// Bus speeds, numbered as xHCI port speed IDs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speed {
+    Full,
+    Low,
+    High,
+    Super,
+}
+
+impl Speed {
+    pub fn from_port_speed(value: u32) -> Option<Self> {
+        match value {
+            1 => Some(Speed::Full),
+            2 => Some(Speed::Low),
+            3 => Some(Speed::High),
+            4 => Some(Speed::Super),
+            _ => None,
+        }
+    }
+
+    pub fn port_speed(self) -> u32 {
+        match self {
+            Speed::Full => 1,
+            Speed::Low => 2,
+            Speed::High => 3,
+            Speed::Super => 4,
+        }
+    }
+
+    /// Endpoint 0 packet size to start with, before the device descriptor
+    /// tells the real one
+    pub fn default_max_packet_size(self) -> u16 {
+        match self {
+            Speed::Low | Speed::Full => 8,
+            Speed::High => 64,
+            Speed::Super => 512,
+        }
+    }
+}
+
+/// The 8-byte setup stage of a control transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetupPacket {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl SetupPacket {
+    pub fn get_descriptor(descriptor_type: u8, index: u8, length: u16) -> Self {
+        Self {
+            request_type: DEVICE_TO_HOST_STANDARD,
+            request: REQUEST_GET_DESCRIPTOR,
+            value: (descriptor_type as u16) << 8 | index as u16,
+            index: 0,
+            length,
+        }
+    }
+
+    pub fn set_configuration(configuration: u8) -> Self {
+        Self {
+            request_type: HOST_TO_DEVICE_STANDARD,
+            request: REQUEST_SET_CONFIGURATION,
+            value: configuration as u16,
+            index: 0,
+            length: 0,
+        }
+    }
+
+    /// Switch a HID interface to the boot protocol
+    pub fn set_boot_protocol(interface: u8) -> Self {
+        Self {
+            request_type: HOST_TO_DEVICE_CLASS_INTERFACE,
+            request: HID_REQUEST_SET_PROTOCOL,
+            value: 0,
+            index: interface as u16,
+            length: 0,
+        }
+    }
+
+    /// Only report on change, not repeatedly while nothing changes
+    pub fn set_idle_infinite(interface: u8) -> Self {
+        Self {
+            request_type: HOST_TO_DEVICE_CLASS_INTERFACE,
+            request: HID_REQUEST_SET_IDLE,
+            value: 0,
+            index: interface as u16,
+            length: 0,
+        }
+    }
+
+    pub fn is_device_to_host(&self) -> bool {
+        self.request_type & 0x80 != 0
+    }
+
+    /// The packet as it goes on the wire, little-endian
+    pub fn to_u64(self) -> u64 {
+        self.request_type as u64
+            | (self.request as u64) << 8
+            | (self.value as u64) << 16
+            | (self.index as u64) << 32
+            | (self.length as u64) << 48
+    }
+}
+
+/// Fields of the device descriptor enumeration uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceDescriptor {
+    pub usb_version: u16,
+    pub class: u8,
+    pub max_packet_size0: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub configurations: u8,
+}
+
+impl DeviceDescriptor {
+    /// Parse a full descriptor, or just `bMaxPacketSize0` from its first 8
+    /// bytes with the remaining fields zero
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < DEVICE_DESCRIPTOR_PREFIX_LENGTH as usize || bytes[1] != DESCRIPTOR_DEVICE {
+            return None;
+        }
+        let full = bytes.len() >= DEVICE_DESCRIPTOR_LENGTH as usize;
+        let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        Some(Self {
+            usb_version: u16_at(2),
+            class: bytes[4],
+            max_packet_size0: bytes[7],
+            vendor_id: if full { u16_at(8) } else { 0 },
+            product_id: if full { u16_at(10) } else { 0 },
+            configurations: if full { bytes[17] } else { 0 },
+        })
+    }
+}
+
+/// Boot protocol a HID interface speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootProtocol {
+    Keyboard,
+    Mouse,
+}
+
+impl BootProtocol {
+    fn from_interface_protocol(protocol: u8) -> Option<Self> {
+        match protocol {
+            1 => Some(BootProtocol::Keyboard),
+            2 => Some(BootProtocol::Mouse),
+            _ => None,
+        }
+    }
+}
+
+/// A boot keyboard or mouse interface and its interrupt IN endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HidInterface {
+    pub interface_number: u8,
+    pub protocol: BootProtocol,
+    pub endpoint_address: u8,
+    pub max_packet_size: u16,
+    /// `bInterval` as the device reports it
+    pub interval: u8,
+}
+
+impl HidInterface {
+    /// Endpoint number without the direction bit
+    pub fn endpoint_number(&self) -> u8 {
+        self.endpoint_address & 0x0F
+    }
+}
+
+/// A configuration and the boot HID interfaces in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Configuration {
+    pub value: u8,
+    pub hid_interfaces: Vec<HidInterface>,
+}
+
+impl Configuration {
+    /// `wTotalLength` from the configuration descriptor header
+    pub fn total_length(header: &[u8]) -> Option<u16> {
+        if header.len() < CONFIGURATION_HEADER_LENGTH as usize || header[1] != DESCRIPTOR_CONFIGURATION {
+            return None;
+        }
+        Some(u16::from_le_bytes([header[2], header[3]]))
+    }
+
+    /// Parse a configuration descriptor with everything that follows it
+    ///
+    /// Interfaces other than boot keyboards and mice, and alternate
+    /// settings, are skipped.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        Self::total_length(bytes)?;
+        let value = bytes[5];
+
+        let mut hid_interfaces = Vec::new();
+        // Boot interface being collected, until its interrupt IN endpoint
+        let mut current: Option<(u8, BootProtocol)> = None;
+        let mut offset = 0;
+        while offset + 2 <= bytes.len() {
+            let length = bytes[offset] as usize;
+            if length < 2 || offset + length > bytes.len() {
+                return None;
+            }
+            let descriptor = &bytes[offset..offset + length];
+            match descriptor[1] {
+                DESCRIPTOR_INTERFACE if length >= 9 => {
+                    let alternate = descriptor[3];
+                    let is_boot_hid = descriptor[5] == CLASS_HID && descriptor[6] == HID_SUBCLASS_BOOT;
+                    current = match BootProtocol::from_interface_protocol(descriptor[7]) {
+                        Some(protocol) if alternate == 0 && is_boot_hid => Some((descriptor[2], protocol)),
+                        _ => None,
+                    };
+                }
+                DESCRIPTOR_ENDPOINT if length >= 7 => {
+                    let address = descriptor[2];
+                    let is_interrupt_in = address & ENDPOINT_DIRECTION_IN != 0
+                        && descriptor[3] & ENDPOINT_TRANSFER_TYPE_MASK == ENDPOINT_TRANSFER_INTERRUPT;
+                    if let Some((interface_number, protocol)) = current.filter(|_| is_interrupt_in) {
+                        hid_interfaces.push(HidInterface {
+                            interface_number,
+                            protocol,
+                            endpoint_address: address,
+                            max_packet_size: u16::from_le_bytes([descriptor[4], descriptor[5]]) & 0x07FF,
+                            interval: descriptor[6],
+                        });
+                        current = None;
+                    }
+                }
+                _ => {}
+            }
+            offset += length;
+        }
+
+        Some(Self { value, hid_interfaces })
+    }
+}
@@ -0,0 +1,973 @@
+//! xHCI host controller
+//!
+//! A single interrupter and one segment per ring. Starting the controller
+//! resets it and sets up the command and event rings; `enumerate` then
+//! resets every connected root hub port, addresses the device behind it
+//! and configures the boot keyboard and mouse interfaces it finds, leaving
+//! a transfer queued on each interrupt endpoint. `poll` clears the
+//! interrupter's interrupt, hands out the reports that completed and
+//! queues the next transfers.
+//!
+//! Hubs are not walked, so only devices on root hub ports are found, and
+//! devices plugged in later are only picked up by the next `enumerate`.
+
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use kosh_driver::hal::Mmio;
+use kosh_types::DriverError;
+use crate::usb::{
+    BootProtocol, Configuration, DeviceDescriptor, HidInterface, SetupPacket, Speed,
+    CONFIGURATION_HEADER_LENGTH, DESCRIPTOR_CONFIGURATION, DESCRIPTOR_DEVICE,
+    DEVICE_DESCRIPTOR_LENGTH, DEVICE_DESCRIPTOR_PREFIX_LENGTH,
+};
+
+/// Capability registers
+const CAP_LENGTH: usize = 0x00;
+const HCS_PARAMS1: usize = 0x04;
+const HCS_PARAMS2: usize = 0x08;
+const HCC_PARAMS1: usize = 0x10;
+const DOORBELL_OFFSET: usize = 0x14;
+const RUNTIME_OFFSET: usize = 0x18;
+
+/// Operational registers, relative to `CAP_LENGTH`
+const USB_CMD: usize = 0x00;
+const USB_STS: usize = 0x04;
+const PAGE_SIZE: usize = 0x08;
+const CRCR: usize = 0x18;
+const DCBAAP: usize = 0x30;
+const CONFIG: usize = 0x38;
+const PORT_REGISTERS: usize = 0x400;
+const PORT_REGISTER_STRIDE: usize = 0x10;
+
+/// Interrupter 0 registers, relative to the runtime registers
+const IMAN: usize = 0x20;
+const ERSTSZ: usize = 0x28;
+const ERSTBA: usize = 0x30;
+const ERDP: usize = 0x38;
+
+const CMD_RUN: u32 = 1 << 0;
+const CMD_RESET: u32 = 1 << 1;
+const CMD_INTERRUPTER_ENABLE: u32 = 1 << 2;
+const STS_HALTED: u32 = 1 << 0;
+const STS_EVENT_INTERRUPT: u32 = 1 << 3;
+const STS_NOT_READY: u32 = 1 << 11;
+const HCC_CONTEXT_64: u32 = 1 << 2;
+
+const PORT_CONNECTED: u32 = 1 << 0;
+const PORT_ENABLED: u32 = 1 << 1;
+const PORT_RESET: u32 = 1 << 4;
+const PORT_SPEED_SHIFT: u32 = 10;
+const PORT_SPEED_MASK: u32 = 0xF;
+const PORT_RESET_CHANGE: u32 = 1 << 21;
+/// Status change bits 17 to 23, cleared by writing 1
+const PORT_CHANGE_BITS: u32 = 0x7F << 17;
+
+const IMAN_PENDING: u32 = 1 << 0;
+const IMAN_ENABLE: u32 = 1 << 1;
+const ERDP_HANDLER_BUSY: u64 = 1 << 3;
+const CRCR_RING_CYCLE: u64 = 1 << 0;
+
+/// TRB layout
+const TRB_SIZE: usize = 16;
+const TRB_CYCLE: u32 = 1 << 0;
+const TRB_TOGGLE_CYCLE: u32 = 1 << 1;
+const TRB_INTERRUPT_ON_SHORT: u32 = 1 << 2;
+const TRB_INTERRUPT_ON_COMPLETION: u32 = 1 << 5;
+const TRB_IMMEDIATE_DATA: u32 = 1 << 6;
+const TRB_TYPE_SHIFT: u32 = 10;
+const TRB_DIRECTION_IN: u32 = 1 << 16;
+const TRB_TRANSFER_OUT_DATA: u32 = 2 << 16;
+const TRB_TRANSFER_IN_DATA: u32 = 3 << 16;
+const TRB_SLOT_SHIFT: u32 = 24;
+
+/// TRB types
+pub const TRB_NORMAL: u32 = 1;
+pub const TRB_SETUP: u32 = 2;
+pub const TRB_DATA: u32 = 3;
+pub const TRB_STATUS: u32 = 4;
+pub const TRB_LINK: u32 = 6;
+pub const TRB_ENABLE_SLOT: u32 = 9;
+pub const TRB_ADDRESS_DEVICE: u32 = 11;
+pub const TRB_CONFIGURE_ENDPOINT: u32 = 12;
+pub const TRB_EVALUATE_CONTEXT: u32 = 13;
+pub const TRB_TRANSFER_EVENT: u32 = 32;
+pub const TRB_COMMAND_COMPLETION: u32 = 33;
+pub const TRB_PORT_STATUS_CHANGE: u32 = 34;
+
+/// Completion codes
+pub const COMPLETION_SUCCESS: u8 = 1;
+pub const COMPLETION_SHORT_PACKET: u8 = 13;
+
+/// Endpoint context types
+const ENDPOINT_TYPE_CONTROL: u32 = 4;
+const ENDPOINT_TYPE_INTERRUPT_IN: u32 = 7;
+/// Retries before the controller gives up on a transaction
+const ENDPOINT_ERROR_COUNT: u32 = 3;
+
+/// Device context index of endpoint 0
+const CONTROL_DCI: u8 = 1;
+
+const COMMAND_RING_TRBS: usize = 64;
+const EVENT_RING_TRBS: usize = 64;
+const TRANSFER_RING_TRBS: usize = 32;
+
+/// Register and event polls before giving up on the controller
+const POLL_LIMIT: usize = 100_000;
+/// Events kept while waiting for a command or control transfer
+const MAX_PENDING_EVENTS: usize = 64;
+
+/// Size of the pages `DmaMemory` hands out; the only controller page size
+/// supported
+pub const DMA_PAGE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XhciError {
+    /// The controller did not respond in time
+    Timeout,
+    /// Out of DMA memory
+    NoMemory,
+    NotRunning,
+    PortDisabled,
+    UnsupportedSpeed,
+    UnsupportedPageSize,
+    CommandFailed(u8),
+    TransferFailed(u8),
+    InvalidDescriptor,
+}
+
+impl From<XhciError> for DriverError {
+    fn from(error: XhciError) -> Self {
+        match error {
+            XhciError::NoMemory => DriverError::ResourceBusy,
+            XhciError::PortDisabled | XhciError::UnsupportedSpeed => DriverError::HardwareNotFound,
+            _ => DriverError::InitializationFailed,
+        }
+    }
+}
+
+/// Memory the controller reaches by DMA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaRegion {
+    /// Address the driver uses
+    pub virt: usize,
+    /// Address the controller uses
+    pub phys: u64,
+    pub size: usize,
+}
+
+impl DmaRegion {
+    fn pointer(&self, offset: usize, width: usize) -> usize {
+        assert!(offset + width <= self.size, "access outside DMA region");
+        self.virt + offset
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile(self.pointer(offset, 4) as *const u32) }
+    }
+
+    fn write_u32(&self, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile(self.pointer(offset, 4) as *mut u32, value) }
+    }
+
+    fn read_u64(&self, offset: usize) -> u64 {
+        self.read_u32(offset) as u64 | (self.read_u32(offset + 4) as u64) << 32
+    }
+
+    fn write_u64(&self, offset: usize, value: u64) {
+        self.write_u32(offset, value as u32);
+        self.write_u32(offset + 4, (value >> 32) as u32);
+    }
+
+    fn read_trb(&self, index: usize) -> Trb {
+        let offset = index * TRB_SIZE;
+        Trb {
+            parameter: self.read_u64(offset),
+            status: self.read_u32(offset + 8),
+            control: self.read_u32(offset + 12),
+        }
+    }
+
+    /// The control dword, holding the cycle bit, is written last so the
+    /// controller never sees a half-written TRB as its own
+    fn write_trb(&self, index: usize, trb: Trb) {
+        let offset = index * TRB_SIZE;
+        self.write_u64(offset, trb.parameter);
+        self.write_u32(offset + 8, trb.status);
+        self.write_u32(offset + 12, trb.control);
+    }
+
+    fn zero(&self) {
+        unsafe { core::ptr::write_bytes(self.pointer(0, self.size) as *mut u8, 0, self.size) }
+    }
+
+    fn read_bytes(&self, length: usize) -> Vec<u8> {
+        let source = self.pointer(0, length) as *const u8;
+        (0..length).map(|index| unsafe { core::ptr::read_volatile(source.add(index)) }).collect()
+    }
+}
+
+/// Source of memory the controller can reach
+pub trait DmaMemory: Send {
+    /// A zeroed, page-aligned page, or None when none is left
+    fn allocate(&mut self) -> Option<DmaRegion>;
+
+    /// Return a page from `allocate`
+    fn free(&mut self, region: DmaRegion);
+}
+
+/// Pages carved out of one physically contiguous region
+pub struct DmaPool {
+    virt: usize,
+    phys: u64,
+    free: Vec<usize>,
+}
+
+impl DmaPool {
+    /// # Safety
+    /// `virt..virt + size` must be writable memory at physical address
+    /// `phys`, not used for anything else while the pool or any page from
+    /// it is in use. Both addresses must be page aligned.
+    pub unsafe fn new(virt: usize, phys: u64, size: usize) -> Self {
+        assert!(virt.is_multiple_of(DMA_PAGE_SIZE) && phys.is_multiple_of(DMA_PAGE_SIZE as u64), "DMA pool not page aligned");
+        Self {
+            virt,
+            phys,
+            // Hand out pages from the start of the region first
+            free: (0..size / DMA_PAGE_SIZE).rev().collect(),
+        }
+    }
+
+    pub fn free_pages(&self) -> usize {
+        self.free.len()
+    }
+}
+
+impl DmaMemory for DmaPool {
+    fn allocate(&mut self) -> Option<DmaRegion> {
+        let page = self.free.pop()?;
+        let region = DmaRegion {
+            virt: self.virt + page * DMA_PAGE_SIZE,
+            phys: self.phys + (page * DMA_PAGE_SIZE) as u64,
+            size: DMA_PAGE_SIZE,
+        };
+        region.zero();
+        Some(region)
+    }
+
+    fn free(&mut self, region: DmaRegion) {
+        let page = (region.phys - self.phys) as usize / DMA_PAGE_SIZE;
+        if !self.free.contains(&page) {
+            self.free.push(page);
+        }
+    }
+}
+
+/// Transfer request block
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Trb {
+    pub parameter: u64,
+    pub status: u32,
+    pub control: u32,
+}
+
+impl Trb {
+    pub fn new(trb_type: u32, parameter: u64, status: u32, flags: u32) -> Self {
+        Self {
+            parameter,
+            status,
+            control: trb_type << TRB_TYPE_SHIFT | flags,
+        }
+    }
+
+    pub fn trb_type(&self) -> u32 {
+        (self.control >> TRB_TYPE_SHIFT) & 0x3F
+    }
+
+    pub fn cycle(&self) -> bool {
+        self.control & TRB_CYCLE != 0
+    }
+
+    pub fn completion_code(&self) -> u8 {
+        (self.status >> 24) as u8
+    }
+
+    pub fn slot_id(&self) -> u8 {
+        (self.control >> TRB_SLOT_SHIFT) as u8
+    }
+
+    /// Device context index of the endpoint a transfer event is for
+    pub fn endpoint_id(&self) -> u8 {
+        ((self.control >> 16) & 0x1F) as u8
+    }
+
+    /// Bytes a transfer event's TRB did not transfer
+    pub fn residual_length(&self) -> usize {
+        (self.status & 0x00FF_FFFF) as usize
+    }
+}
+
+/// Ring the driver produces TRBs on: the command ring or a transfer ring
+///
+/// The last slot holds a link TRB back to the start, which toggles the
+/// cycle bit the controller expects.
+pub struct Ring {
+    memory: DmaRegion,
+    trbs: usize,
+    enqueue: usize,
+    cycle: bool,
+}
+
+impl Ring {
+    pub fn new(memory: DmaRegion, trbs: usize) -> Self {
+        assert!(trbs >= 2 && trbs * TRB_SIZE <= memory.size, "ring does not fit its memory");
+        memory.write_trb(trbs - 1, Trb::new(TRB_LINK, memory.phys, 0, TRB_TOGGLE_CYCLE));
+        Self {
+            memory,
+            trbs,
+            enqueue: 0,
+            cycle: true,
+        }
+    }
+
+    pub fn phys(&self) -> u64 {
+        self.memory.phys
+    }
+
+    /// Hand `trb` to the controller, returning the address it was put at
+    pub fn push(&mut self, mut trb: Trb) -> u64 {
+        trb.control = (trb.control & !TRB_CYCLE) | self.cycle as u32;
+        self.memory.write_trb(self.enqueue, trb);
+        let address = self.memory.phys + (self.enqueue * TRB_SIZE) as u64;
+
+        self.enqueue += 1;
+        if self.enqueue == self.trbs - 1 {
+            let mut link = self.memory.read_trb(self.enqueue);
+            link.control = (link.control & !TRB_CYCLE) | self.cycle as u32;
+            self.memory.write_trb(self.enqueue, link);
+            self.enqueue = 0;
+            self.cycle = !self.cycle;
+        }
+        address
+    }
+}
+
+/// Ring the controller produces events on, with its one-entry segment table
+pub struct EventRing {
+    memory: DmaRegion,
+    table: DmaRegion,
+    trbs: usize,
+    dequeue: usize,
+    cycle: bool,
+}
+
+impl EventRing {
+    pub fn new(memory: DmaRegion, table: DmaRegion, trbs: usize) -> Self {
+        assert!(trbs * TRB_SIZE <= memory.size, "ring does not fit its memory");
+        table.write_u64(0, memory.phys);
+        table.write_u32(8, trbs as u32);
+        Self {
+            memory,
+            table,
+            trbs,
+            dequeue: 0,
+            cycle: true,
+        }
+    }
+
+    /// The next event, if the controller has written one
+    pub fn pop(&mut self) -> Option<Trb> {
+        let event = self.memory.read_trb(self.dequeue);
+        if event.cycle() != self.cycle {
+            return None;
+        }
+        self.dequeue += 1;
+        if self.dequeue == self.trbs {
+            self.dequeue = 0;
+            self.cycle = !self.cycle;
+        }
+        Some(event)
+    }
+
+    pub fn dequeue_pointer(&self) -> u64 {
+        self.memory.phys + (self.dequeue * TRB_SIZE) as u64
+    }
+}
+
+/// A report received on a boot interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub slot_id: u8,
+    pub interface: u8,
+    pub protocol: BootProtocol,
+    pub data: Vec<u8>,
+}
+
+/// An interrupt IN endpoint reports arrive on
+struct Endpoint {
+    dci: u8,
+    interface: HidInterface,
+    ring: Ring,
+    buffer: DmaRegion,
+}
+
+/// An addressed device
+struct Device {
+    slot_id: u8,
+    port: u8,
+    speed: Speed,
+    input_context: DmaRegion,
+    output_context: DmaRegion,
+    control_ring: Ring,
+    /// Data stage buffer of control transfers
+    buffer: DmaRegion,
+    descriptor: Option<DeviceDescriptor>,
+    endpoints: Vec<Endpoint>,
+}
+
+/// Device attached to a root hub port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub slot_id: u8,
+    pub port: u8,
+    pub speed: Speed,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub keyboards: usize,
+    pub mice: usize,
+}
+
+/// xHCI interval exponent for an interrupt endpoint's `bInterval`
+///
+/// Full and low speed devices give the interval in frames, faster ones as
+/// an exponent already.
+pub fn endpoint_interval(speed: Speed, interval: u8) -> u32 {
+    match speed {
+        Speed::High | Speed::Super => interval.clamp(1, 16) as u32 - 1,
+        Speed::Full | Speed::Low => {
+            let microframes = interval.max(1) as u32 * 8;
+            (31 - microframes.leading_zeros()).clamp(3, 10)
+        }
+    }
+}
+
+pub struct XhciController {
+    mmio: Box<dyn Mmio>,
+    dma: Box<dyn DmaMemory>,
+    operational: usize,
+    runtime: usize,
+    doorbells: usize,
+    max_slots: u8,
+    max_ports: u8,
+    context_size: usize,
+    running: bool,
+    dcbaa: Option<DmaRegion>,
+    command_ring: Option<Ring>,
+    event_ring: Option<EventRing>,
+    /// Every page taken from `dma`, returned on stop
+    allocations: Vec<DmaRegion>,
+    /// Events that arrived while waiting for another one
+    pending_events: VecDeque<Trb>,
+    devices: Vec<Device>,
+}
+
+impl XhciController {
+    /// A controller whose registers are `mmio`, using pages from `dma`
+    pub fn new(mmio: Box<dyn Mmio>, dma: Box<dyn DmaMemory>) -> Self {
+        Self {
+            mmio,
+            dma,
+            operational: 0,
+            runtime: 0,
+            doorbells: 0,
+            max_slots: 0,
+            max_ports: 0,
+            context_size: 32,
+            running: false,
+            dcbaa: None,
+            command_ring: None,
+            event_ring: None,
+            allocations: Vec::new(),
+            pending_events: VecDeque::new(),
+            devices: Vec::new(),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn max_ports(&self) -> u8 {
+        self.max_ports
+    }
+
+    fn allocate(&mut self) -> Result<DmaRegion, XhciError> {
+        let region = self.dma.allocate().ok_or(XhciError::NoMemory)?;
+        self.allocations.push(region);
+        Ok(region)
+    }
+
+    fn read_operational(&mut self, register: usize) -> u32 {
+        self.mmio.read_u32(self.operational + register)
+    }
+
+    fn write_operational(&mut self, register: usize, value: u32) {
+        self.mmio.write_u32(self.operational + register, value);
+    }
+
+    fn write_operational_u64(&mut self, register: usize, value: u64) {
+        self.write_operational(register, value as u32);
+        self.write_operational(register + 4, (value >> 32) as u32);
+    }
+
+    fn write_runtime(&mut self, register: usize, value: u32) {
+        self.mmio.write_u32(self.runtime + register, value);
+    }
+
+    fn write_runtime_u64(&mut self, register: usize, value: u64) {
+        self.write_runtime(register, value as u32);
+        self.write_runtime(register + 4, (value >> 32) as u32);
+    }
+
+    fn read_port(&mut self, port: u8) -> u32 {
+        self.read_operational(PORT_REGISTERS + (port as usize - 1) * PORT_REGISTER_STRIDE)
+    }
+
+    fn write_port(&mut self, port: u8, value: u32) {
+        self.write_operational(PORT_REGISTERS + (port as usize - 1) * PORT_REGISTER_STRIDE, value);
+    }
+
+    fn ring_doorbell(&mut self, slot_id: u8, target: u8) {
+        self.mmio.write_u32(self.doorbells + slot_id as usize * 4, target as u32);
+    }
+
+    /// Wait until the bits of `mask` in an operational register read `expected`
+    fn wait_operational(&mut self, register: usize, mask: u32, expected: u32) -> Result<(), XhciError> {
+        for _ in 0..POLL_LIMIT {
+            if self.read_operational(register) & mask == expected {
+                return Ok(());
+            }
+        }
+        Err(XhciError::Timeout)
+    }
+
+    /// Reset the controller and start it with empty rings
+    pub fn start(&mut self) -> Result<(), XhciError> {
+        if self.running {
+            self.stop();
+        }
+
+        let cap_length = self.mmio.read_u8(CAP_LENGTH) as usize;
+        let structural = self.mmio.read_u32(HCS_PARAMS1);
+        let scratchpad_params = self.mmio.read_u32(HCS_PARAMS2);
+        let capabilities = self.mmio.read_u32(HCC_PARAMS1);
+        self.operational = cap_length;
+        self.runtime = (self.mmio.read_u32(RUNTIME_OFFSET) & !0x1F) as usize;
+        self.doorbells = (self.mmio.read_u32(DOORBELL_OFFSET) & !0x3) as usize;
+        self.max_slots = structural as u8;
+        self.max_ports = (structural >> 24) as u8;
+        self.context_size = if capabilities & HCC_CONTEXT_64 != 0 { 64 } else { 32 };
+
+        self.reset()?;
+        // Bit n set means pages of 2^(n + 12) bytes
+        if self.read_operational(PAGE_SIZE) & 1 == 0 {
+            return Err(XhciError::UnsupportedPageSize);
+        }
+        let max_slots = self.max_slots as u32;
+        self.write_operational(CONFIG, max_slots);
+
+        let dcbaa = self.allocate()?;
+        let scratchpads = ((scratchpad_params >> 21) & 0x1F) << 5 | (scratchpad_params >> 27) & 0x1F;
+        if scratchpads > 0 {
+            let array = self.allocate()?;
+            for index in 0..scratchpads as usize {
+                let page = self.allocate()?;
+                array.write_u64(index * 8, page.phys);
+            }
+            dcbaa.write_u64(0, array.phys);
+        }
+        self.write_operational_u64(DCBAAP, dcbaa.phys);
+        self.dcbaa = Some(dcbaa);
+
+        let command_ring = Ring::new(self.allocate()?, COMMAND_RING_TRBS);
+        self.write_operational_u64(CRCR, command_ring.phys() | CRCR_RING_CYCLE);
+        self.command_ring = Some(command_ring);
+
+        let event_ring = EventRing::new(self.allocate()?, self.allocate()?, EVENT_RING_TRBS);
+        let (dequeue, table) = (event_ring.dequeue_pointer(), event_ring.table.phys);
+        self.event_ring = Some(event_ring);
+        self.write_runtime(ERSTSZ, 1);
+        self.write_runtime_u64(ERDP, dequeue);
+        self.write_runtime_u64(ERSTBA, table);
+        self.write_runtime(IMAN, IMAN_ENABLE | IMAN_PENDING);
+
+        self.write_operational(USB_CMD, CMD_RUN | CMD_INTERRUPTER_ENABLE);
+        self.wait_operational(USB_STS, STS_HALTED, 0)?;
+        self.running = true;
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), XhciError> {
+        self.wait_operational(USB_STS, STS_NOT_READY, 0)?;
+        let command = self.read_operational(USB_CMD);
+        self.write_operational(USB_CMD, command & !CMD_RUN);
+        self.wait_operational(USB_STS, STS_HALTED, STS_HALTED)?;
+        self.write_operational(USB_CMD, CMD_RESET);
+        self.wait_operational(USB_CMD, CMD_RESET, 0)?;
+        self.wait_operational(USB_STS, STS_NOT_READY, 0)
+    }
+
+    /// Halt the controller and give back all its memory
+    pub fn stop(&mut self) {
+        let command = self.read_operational(USB_CMD);
+        self.write_operational(USB_CMD, command & !(CMD_RUN | CMD_INTERRUPTER_ENABLE));
+        // Memory goes back even if the controller does not halt in time
+        let _ = self.wait_operational(USB_STS, STS_HALTED, STS_HALTED);
+
+        self.running = false;
+        self.devices.clear();
+        self.pending_events.clear();
+        self.dcbaa = None;
+        self.command_ring = None;
+        self.event_ring = None;
+        for region in core::mem::take(&mut self.allocations) {
+            self.dma.free(region);
+        }
+    }
+
+    /// Clear the event interrupt in the status register and the
+    /// interrupter, both written 1 to clear
+    ///
+    /// Done before the event ring is read, so an event that lands while it
+    /// is read raises the next interrupt.
+    fn clear_interrupt(&mut self) {
+        if self.running {
+            self.write_operational(USB_STS, STS_EVENT_INTERRUPT);
+            self.write_runtime(IMAN, IMAN_ENABLE | IMAN_PENDING);
+        }
+    }
+
+    fn next_event(&mut self) -> Option<Trb> {
+        let event_ring = self.event_ring.as_mut()?;
+        let event = event_ring.pop()?;
+        let dequeue = event_ring.dequeue_pointer();
+        self.write_runtime_u64(ERDP, dequeue | ERDP_HANDLER_BUSY);
+        Some(event)
+    }
+
+    /// Wait for the event `matches` accepts, keeping the others for `poll`
+    fn wait_event(&mut self, matches: impl Fn(&Trb) -> bool) -> Result<Trb, XhciError> {
+        for _ in 0..POLL_LIMIT {
+            let Some(event) = self.next_event() else {
+                continue;
+            };
+            if matches(&event) {
+                return Ok(event);
+            }
+            if self.pending_events.len() >= MAX_PENDING_EVENTS {
+                self.pending_events.pop_front();
+            }
+            self.pending_events.push_back(event);
+        }
+        Err(XhciError::Timeout)
+    }
+
+    fn command(&mut self, trb: Trb) -> Result<Trb, XhciError> {
+        let address = self.command_ring.as_mut().ok_or(XhciError::NotRunning)?.push(trb);
+        self.ring_doorbell(0, 0);
+        let event = self.wait_event(|event| {
+            event.trb_type() == TRB_COMMAND_COMPLETION && event.parameter == address
+        })?;
+        match event.completion_code() {
+            COMPLETION_SUCCESS => Ok(event),
+            code => Err(XhciError::CommandFailed(code)),
+        }
+    }
+
+    /// Look for devices on every connected port that has none yet
+    ///
+    /// Returns the number of devices found. A device that fails to come up
+    /// is skipped; its port is tried again on the next call.
+    pub fn enumerate(&mut self) -> Result<usize, XhciError> {
+        if !self.running {
+            return Err(XhciError::NotRunning);
+        }
+        let mut found = 0;
+        for port in 1..=self.max_ports {
+            if self.devices.iter().any(|device| device.port == port) {
+                continue;
+            }
+            if self.read_port(port) & PORT_CONNECTED == 0 {
+                continue;
+            }
+            if self.attach(port).is_ok() {
+                found += 1;
+            }
+        }
+        Ok(found)
+    }
+
+    /// Reset a port and return the speed of the device behind it
+    fn reset_port(&mut self, port: u8) -> Result<Speed, XhciError> {
+        let status = self.read_port(port);
+        // USB 3 ports enable themselves; USB 2 ones need a reset
+        if status & PORT_ENABLED == 0 {
+            let preserved = status & !(PORT_ENABLED | PORT_CHANGE_BITS);
+            self.write_port(port, preserved | PORT_RESET);
+            let mut reset_done = false;
+            for _ in 0..POLL_LIMIT {
+                if self.read_port(port) & PORT_RESET_CHANGE != 0 {
+                    reset_done = true;
+                    break;
+                }
+            }
+            if !reset_done {
+                return Err(XhciError::Timeout);
+            }
+            let status = self.read_port(port);
+            self.write_port(port, (status & !(PORT_ENABLED | PORT_CHANGE_BITS)) | PORT_RESET_CHANGE);
+        }
+
+        let status = self.read_port(port);
+        if status & PORT_ENABLED == 0 {
+            return Err(XhciError::PortDisabled);
+        }
+        Speed::from_port_speed((status >> PORT_SPEED_SHIFT) & PORT_SPEED_MASK).ok_or(XhciError::UnsupportedSpeed)
+    }
+
+    fn attach(&mut self, port: u8) -> Result<(), XhciError> {
+        let speed = self.reset_port(port)?;
+        let slot_id = self.command(Trb::new(TRB_ENABLE_SLOT, 0, 0, 0))?.slot_id();
+        let mut device = self.address_device(slot_id, port, speed)?;
+
+        // Endpoint 0 packet size is only known from the descriptor
+        let prefix = self.control_transfer(
+            &mut device,
+            SetupPacket::get_descriptor(DESCRIPTOR_DEVICE, 0, DEVICE_DESCRIPTOR_PREFIX_LENGTH),
+        )?;
+        let max_packet_size = DeviceDescriptor::parse(&prefix).ok_or(XhciError::InvalidDescriptor)?.max_packet_size0;
+        let max_packet_size = match (speed, max_packet_size) {
+            // Super speed devices give it as an exponent
+            (Speed::Super, exponent) => 1u16 << exponent.min(9),
+            (_, 0) => return Err(XhciError::InvalidDescriptor),
+            (_, size) => size as u16,
+        };
+        if max_packet_size != speed.default_max_packet_size() {
+            self.set_control_packet_size(&mut device, max_packet_size)?;
+        }
+
+        let descriptor = self.control_transfer(
+            &mut device,
+            SetupPacket::get_descriptor(DESCRIPTOR_DEVICE, 0, DEVICE_DESCRIPTOR_LENGTH),
+        )?;
+        device.descriptor = DeviceDescriptor::parse(&descriptor);
+
+        let header = self.control_transfer(
+            &mut device,
+            SetupPacket::get_descriptor(DESCRIPTOR_CONFIGURATION, 0, CONFIGURATION_HEADER_LENGTH),
+        )?;
+        let total_length = Configuration::total_length(&header)
+            .ok_or(XhciError::InvalidDescriptor)?
+            .min(DMA_PAGE_SIZE as u16);
+        let bytes = self.control_transfer(
+            &mut device,
+            SetupPacket::get_descriptor(DESCRIPTOR_CONFIGURATION, 0, total_length),
+        )?;
+        let configuration = Configuration::parse(&bytes).ok_or(XhciError::InvalidDescriptor)?;
+
+        // Devices without boot interfaces stay addressed but unconfigured
+        if !configuration.hid_interfaces.is_empty() {
+            self.control_transfer(&mut device, SetupPacket::set_configuration(configuration.value))?;
+            for interface in &configuration.hid_interfaces {
+                self.control_transfer(&mut device, SetupPacket::set_boot_protocol(interface.interface_number))?;
+                if interface.protocol == BootProtocol::Keyboard {
+                    self.control_transfer(&mut device, SetupPacket::set_idle_infinite(interface.interface_number))?;
+                }
+            }
+            self.configure_endpoints(&mut device, &configuration.hid_interfaces)?;
+            for index in 0..device.endpoints.len() {
+                self.queue_report(&mut device, index);
+            }
+        }
+
+        self.devices.push(device);
+        Ok(())
+    }
+
+    fn address_device(&mut self, slot_id: u8, port: u8, speed: Speed) -> Result<Device, XhciError> {
+        let device = Device {
+            slot_id,
+            port,
+            speed,
+            input_context: self.allocate()?,
+            output_context: self.allocate()?,
+            control_ring: Ring::new(self.allocate()?, TRANSFER_RING_TRBS),
+            buffer: self.allocate()?,
+            descriptor: None,
+            endpoints: Vec::new(),
+        };
+        self.dcbaa.ok_or(XhciError::NotRunning)?.write_u64(slot_id as usize * 8, device.output_context.phys);
+
+        let input = device.input_context;
+        // Add the slot and endpoint 0
+        input.write_u32(4, 0b11);
+        let slot = self.context_size;
+        input.write_u32(slot, speed.port_speed() << 20 | 1 << 27);
+        input.write_u32(slot + 4, (port as u32) << 16);
+        self.write_control_endpoint(&device, speed.default_max_packet_size());
+
+        self.command(Trb::new(TRB_ADDRESS_DEVICE, input.phys, 0, (slot_id as u32) << TRB_SLOT_SHIFT))?;
+        Ok(device)
+    }
+
+    /// Endpoint 0 context in the input context
+    fn write_control_endpoint(&self, device: &Device, max_packet_size: u16) {
+        let endpoint = self.context_size * (CONTROL_DCI as usize + 1);
+        let input = device.input_context;
+        input.write_u32(endpoint + 4, ENDPOINT_ERROR_COUNT << 1 | ENDPOINT_TYPE_CONTROL << 3 | (max_packet_size as u32) << 16);
+        input.write_u64(endpoint + 8, device.control_ring.phys() | 1);
+        // Average TRB length: control transfers are mostly 8-byte setups
+        input.write_u32(endpoint + 16, 8);
+    }
+
+    fn set_control_packet_size(&mut self, device: &mut Device, max_packet_size: u16) -> Result<(), XhciError> {
+        device.input_context.zero();
+        device.input_context.write_u32(4, 1 << CONTROL_DCI);
+        self.write_control_endpoint(device, max_packet_size);
+        let input = device.input_context.phys;
+        self.command(Trb::new(TRB_EVALUATE_CONTEXT, input, 0, (device.slot_id as u32) << TRB_SLOT_SHIFT))?;
+        Ok(())
+    }
+
+    /// Run a control transfer on endpoint 0, returning the data stage
+    fn control_transfer(&mut self, device: &mut Device, setup: SetupPacket) -> Result<Vec<u8>, XhciError> {
+        let length = (setup.length as usize).min(device.buffer.size);
+        let device_to_host = setup.is_device_to_host();
+        let transfer_type = match (length, device_to_host) {
+            (0, _) => 0,
+            (_, true) => TRB_TRANSFER_IN_DATA,
+            (_, false) => TRB_TRANSFER_OUT_DATA,
+        };
+
+        device.control_ring.push(Trb::new(TRB_SETUP, setup.to_u64(), 8, TRB_IMMEDIATE_DATA | transfer_type));
+        if length > 0 {
+            let direction = if device_to_host { TRB_DIRECTION_IN } else { 0 };
+            device.control_ring.push(Trb::new(TRB_DATA, device.buffer.phys, length as u32, direction));
+        }
+        // The status stage goes the other way to the data, in if there is none
+        let status_direction = if length > 0 && device_to_host { 0 } else { TRB_DIRECTION_IN };
+        device.control_ring.push(Trb::new(TRB_STATUS, 0, 0, status_direction | TRB_INTERRUPT_ON_COMPLETION));
+        self.ring_doorbell(device.slot_id, CONTROL_DCI);
+
+        let slot_id = device.slot_id;
+        let event = self.wait_event(|event| {
+            event.trb_type() == TRB_TRANSFER_EVENT && event.slot_id() == slot_id && event.endpoint_id() == CONTROL_DCI
+        })?;
+        match event.completion_code() {
+            COMPLETION_SUCCESS if device_to_host => Ok(device.buffer.read_bytes(length)),
+            COMPLETION_SUCCESS => Ok(Vec::new()),
+            code => Err(XhciError::TransferFailed(code)),
+        }
+    }
+
+    fn configure_endpoints(&mut self, device: &mut Device, interfaces: &[HidInterface]) -> Result<(), XhciError> {
+        let input = device.input_context;
+        input.zero();
+
+        let mut add_flags = 1;
+        let mut last_dci = CONTROL_DCI;
+        for interface in interfaces {
+            let dci = interface.endpoint_number() * 2 + 1;
+            let endpoint = Endpoint {
+                dci,
+                interface: *interface,
+                ring: Ring::new(self.allocate()?, TRANSFER_RING_TRBS),
+                buffer: self.allocate()?,
+            };
+
+            let context = self.context_size * (dci as usize + 1);
+            let max_packet_size = interface.max_packet_size as u32;
+            input.write_u32(context, endpoint_interval(device.speed, interface.interval) << 16);
+            input.write_u32(context + 4, ENDPOINT_ERROR_COUNT << 1 | ENDPOINT_TYPE_INTERRUPT_IN << 3 | max_packet_size << 16);
+            input.write_u64(context + 8, endpoint.ring.phys() | 1);
+            // Average TRB length and payload per service interval: one packet
+            input.write_u32(context + 16, max_packet_size | max_packet_size << 16);
+
+            add_flags |= 1 << dci;
+            last_dci = last_dci.max(dci);
+            device.endpoints.push(endpoint);
+        }
+
+        // The slot context, as the controller left it, with the new last entry
+        let slot = self.context_size;
+        for offset in (0..self.context_size).step_by(4) {
+            input.write_u32(slot + offset, device.output_context.read_u32(offset));
+        }
+        let entries = input.read_u32(slot) & !(0x1F << 27) | (last_dci as u32) << 27;
+        input.write_u32(slot, entries);
+        input.write_u32(4, add_flags);
+
+        self.command(Trb::new(TRB_CONFIGURE_ENDPOINT, input.phys, 0, (device.slot_id as u32) << TRB_SLOT_SHIFT))?;
+        Ok(())
+    }
+
+    /// Queue a transfer for the next report on an endpoint
+    fn queue_report(&mut self, device: &mut Device, index: usize) {
+        let endpoint = &mut device.endpoints[index];
+        let length = endpoint.interface.max_packet_size as u32;
+        let flags = TRB_INTERRUPT_ON_SHORT | TRB_INTERRUPT_ON_COMPLETION;
+        endpoint.ring.push(Trb::new(TRB_NORMAL, endpoint.buffer.phys, length, flags));
+        let (slot_id, dci) = (device.slot_id, endpoint.dci);
+        self.ring_doorbell(slot_id, dci);
+    }
+
+    /// Reports completed since the last poll
+    ///
+    /// Each endpoint that delivered one gets its next transfer queued. An
+    /// endpoint whose transfer failed is halted and stays silent.
+    pub fn poll(&mut self) -> Vec<Report> {
+        self.clear_interrupt();
+        let mut events: Vec<Trb> = self.pending_events.drain(..).collect();
+        while let Some(event) = self.next_event() {
+            events.push(event);
+        }
+
+        let mut reports = Vec::new();
+        for event in events.iter().filter(|event| event.trb_type() == TRB_TRANSFER_EVENT) {
+            let Some(device_index) = self.devices.iter().position(|device| device.slot_id == event.slot_id()) else {
+                continue;
+            };
+            let mut device = self.devices.swap_remove(device_index);
+            if let Some(index) = device.endpoints.iter().position(|endpoint| endpoint.dci == event.endpoint_id()) {
+                if matches!(event.completion_code(), COMPLETION_SUCCESS | COMPLETION_SHORT_PACKET) {
+                    let endpoint = &device.endpoints[index];
+                    let length = (endpoint.interface.max_packet_size as usize).saturating_sub(event.residual_length());
+                    reports.push(Report {
+                        slot_id: device.slot_id,
+                        interface: endpoint.interface.interface_number,
+                        protocol: endpoint.interface.protocol,
+                        data: endpoint.buffer.read_bytes(length),
+                    });
+                    self.queue_report(&mut device, index);
+                }
+            }
+            self.devices.push(device);
+        }
+        reports
+    }
+
+    pub fn devices(&self) -> Vec<DeviceInfo> {
+        self.devices.iter()
+            .map(|device| {
+                let count = |protocol| device.endpoints.iter().filter(|endpoint| endpoint.interface.protocol == protocol).count();
+                DeviceInfo {
+                    slot_id: device.slot_id,
+                    port: device.port,
+                    speed: device.speed,
+                    vendor_id: device.descriptor.map_or(0, |descriptor| descriptor.vendor_id),
+                    product_id: device.descriptor.map_or(0, |descriptor| descriptor.product_id),
+                    keyboards: count(BootProtocol::Keyboard),
+                    mice: count(BootProtocol::Mouse),
+                }
+            })
+            .collect()
+    }
+}
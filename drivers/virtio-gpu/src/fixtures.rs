@@ -0,0 +1,502 @@
+//! Emulated virtio-gpu device
+//!
+//! Implements the modern virtio PCI register structures and enough of the
+//! 2D command set to drive a console: it reads the control queue the
+//! driver writes in memory, keeps the resources it is asked to create and
+//! copies their backing to its own copy on transfers, as QEMU does. Test
+//! DMA memory is identity mapped, so device addresses can be dereferenced
+//! directly.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
+use kosh_driver::hal::Mmio;
+use spin::Mutex;
+use crate::gpu::{
+    Rect, CMD_GET_DISPLAY_INFO, CMD_GET_EDID, CMD_RESOURCE_ATTACH_BACKING, CMD_RESOURCE_CREATE_2D,
+    CMD_RESOURCE_DETACH_BACKING, CMD_RESOURCE_FLUSH, CMD_RESOURCE_UNREF, CMD_SET_SCANOUT, CMD_TRANSFER_TO_HOST_2D,
+    FEATURE_EDID, FORMAT_B8G8R8X8_UNORM, HEADER_SIZE, MAX_SCANOUTS, RESP_ERR_UNSPEC, RESP_OK_DISPLAY_INFO,
+    RESP_OK_EDID, RESP_OK_NODATA,
+};
+use crate::transport::{VirtioPci, FEATURE_VERSION_1, STATUS_FEATURES_OK};
+use crate::virtqueue::{DmaMemory, DmaRegion, DESCRIPTOR_NEXT, DESCRIPTOR_WRITE, DMA_PAGE_SIZE};
+
+/// Error responses
+pub const RESP_ERR_INVALID_SCANOUT_ID: u32 = 0x1202;
+pub const RESP_ERR_INVALID_RESOURCE_ID: u32 = 0x1203;
+pub const RESP_ERR_INVALID_PARAMETER: u32 = 0x1205;
+
+/// Bytes between the notification addresses of consecutive queues
+pub const NOTIFY_MULTIPLIER: u32 = 4;
+
+const QUEUE_COUNT: usize = 2;
+const MAX_QUEUE_SIZE: u16 = 256;
+
+fn peek_u16(address: u64) -> u16 {
+    unsafe { (address as *const u16).read_volatile() }
+}
+
+fn peek_u32(address: u64) -> u32 {
+    unsafe { (address as *const u32).read_volatile() }
+}
+
+fn peek_u64(address: u64) -> u64 {
+    peek_u32(address) as u64 | (peek_u32(address + 4) as u64) << 32
+}
+
+fn poke_u16(address: u64, value: u16) {
+    unsafe { (address as *mut u16).write_volatile(value) }
+}
+
+fn poke_u32(address: u64, value: u32) {
+    unsafe { (address as *mut u32).write_volatile(value) }
+}
+
+fn field(bytes: &[u8], index: usize) -> u32 {
+    let offset = HEADER_SIZE + index * 4;
+    bytes.get(offset..offset + 4).map_or(0, |field| u32::from_le_bytes(field.try_into().unwrap()))
+}
+
+/// Identity-mapped DMA memory the test keeps a handle on while the driver
+/// owns it
+#[derive(Clone, Default)]
+pub struct HeapDma(pub Arc<Mutex<Vec<DmaRegion>>>);
+
+impl HeapDma {
+    /// Regions allocated and not freed
+    pub fn live(&self) -> usize {
+        self.0.lock().len()
+    }
+}
+
+impl DmaMemory for HeapDma {
+    fn allocate(&mut self, size: usize) -> Option<DmaRegion> {
+        let memory = vec![0u8; size + DMA_PAGE_SIZE].leak();
+        let start = (memory.as_ptr() as usize).next_multiple_of(DMA_PAGE_SIZE);
+        let region = DmaRegion { virt: start, phys: start as u64, size };
+        self.0.lock().push(region);
+        Some(region)
+    }
+
+    fn free(&mut self, region: DmaRegion) {
+        self.0.lock().retain(|live| *live != region);
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Queue {
+    size: u16,
+    descriptors: u64,
+    available: u64,
+    used: u64,
+    enabled: bool,
+    last_available: u16,
+}
+
+/// A resource the device keeps
+pub struct Resource {
+    pub width: u32,
+    pub height: u32,
+    pub backing: Option<(u64, u32)>,
+    /// The host's copy, one pixel each
+    pub host: Vec<u32>,
+}
+
+pub struct GpuState {
+    pub status: u8,
+    pub device_features: u64,
+    pub driver_features: u64,
+    feature_select: u32,
+    driver_feature_select: u32,
+    queue_select: u16,
+    queues: [Queue; QUEUE_COUNT],
+    /// Size the host shows scanout 0 at
+    pub scanout_size: (u32, u32),
+    pub edid: Option<Vec<u8>>,
+    pub events: u32,
+    pub resources: BTreeMap<u32, Resource>,
+    /// Resource on scanout 0, 0 for none
+    pub scanout_resource: u32,
+    /// Every command handled, in order
+    pub commands: Vec<u32>,
+    pub flushes: Vec<Rect>,
+}
+
+impl GpuState {
+    fn reset(&mut self) {
+        self.status = 0;
+        self.driver_features = 0;
+        self.queues = [Queue::default(); QUEUE_COUNT];
+        self.resources.clear();
+        self.scanout_resource = 0;
+    }
+
+    fn write_status(&mut self, status: u8) {
+        if status == 0 {
+            return self.reset();
+        }
+        self.status = status;
+        if status & STATUS_FEATURES_OK != 0 && self.driver_features & !self.device_features != 0 {
+            self.status &= !STATUS_FEATURES_OK;
+        }
+    }
+
+    fn selected(&mut self) -> Option<&mut Queue> {
+        self.queues.get_mut(self.queue_select as usize)
+    }
+
+    fn read_common(&mut self, offset: usize) -> u32 {
+        match offset {
+            0x04 => (self.device_features >> (self.feature_select * 32).min(63)) as u32,
+            0x12 => QUEUE_COUNT as u32,
+            0x14 => self.status as u32,
+            0x16 => self.queue_select as u32,
+            0x18 => self.selected().map_or(0, |queue| if queue.size == 0 { MAX_QUEUE_SIZE } else { queue.size }) as u32,
+            0x1C => self.selected().map_or(0, |queue| queue.enabled as u32),
+            // Each queue is notified at its own index
+            0x1E => self.queue_select as u32,
+            _ => 0,
+        }
+    }
+
+    fn write_common(&mut self, offset: usize, value: u32) {
+        let half = |current: u64, high: bool| {
+            if high { (current & 0xFFFF_FFFF) | (value as u64) << 32 } else { (current & !0xFFFF_FFFF) | value as u64 }
+        };
+        match offset {
+            0x00 => self.feature_select = value,
+            0x08 => self.driver_feature_select = value,
+            0x0C => {
+                let high = self.driver_feature_select == 1;
+                self.driver_features = half(self.driver_features, high);
+            }
+            0x14 => self.write_status(value as u8),
+            0x16 => self.queue_select = value as u16,
+            0x18 => {
+                if let Some(queue) = self.selected() {
+                    queue.size = (value as u16).min(MAX_QUEUE_SIZE);
+                }
+            }
+            0x1C => {
+                if let Some(queue) = self.selected() {
+                    queue.enabled = value != 0;
+                }
+            }
+            0x20..=0x37 => {
+                let high = offset % 8 == 4;
+                if let Some(queue) = self.selected() {
+                    let address = match offset & !7 {
+                        0x20 => &mut queue.descriptors,
+                        0x28 => &mut queue.available,
+                        _ => &mut queue.used,
+                    };
+                    *address = half(*address, high);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Handle every request the driver made available on queue `index`
+    fn process(&mut self, index: usize) {
+        let Some(&queue) = self.queues.get(index).filter(|queue| queue.enabled) else {
+            return;
+        };
+        let mut last_available = queue.last_available;
+        while last_available != peek_u16(queue.available + 2) {
+            let head = peek_u16(queue.available + 4 + (last_available % queue.size) as u64 * 2);
+            let mut request = Vec::new();
+            let mut response_buffer = None;
+            let mut descriptor = head;
+            loop {
+                let address = queue.descriptors + descriptor as u64 * 16;
+                let (buffer, length) = (peek_u64(address), peek_u32(address + 8));
+                let flags = peek_u16(address + 12);
+                if flags & DESCRIPTOR_WRITE != 0 {
+                    response_buffer = Some((buffer, length));
+                } else {
+                    request.extend((0..length as u64).map(|byte| unsafe { ((buffer + byte) as *const u8).read_volatile() }));
+                }
+                if flags & DESCRIPTOR_NEXT == 0 {
+                    break;
+                }
+                descriptor = peek_u16(address + 14);
+            }
+
+            let response = self.handle(&request);
+            let written = match response_buffer {
+                Some((buffer, length)) => {
+                    let written = response.len().min(length as usize);
+                    for (offset, &byte) in response[..written].iter().enumerate() {
+                        unsafe { ((buffer + offset as u64) as *mut u8).write_volatile(byte) }
+                    }
+                    written
+                }
+                None => 0,
+            };
+
+            let used_index = peek_u16(queue.used + 2);
+            let entry = queue.used + 4 + (used_index % queue.size) as u64 * 8;
+            poke_u32(entry, head as u32);
+            poke_u32(entry + 4, written as u32);
+            poke_u16(queue.used + 2, used_index.wrapping_add(1));
+            last_available = last_available.wrapping_add(1);
+        }
+        self.queues[index].last_available = last_available;
+    }
+
+    fn handle(&mut self, request: &[u8]) -> Vec<u8> {
+        let command = u32::from_le_bytes(request[..4].try_into().unwrap());
+        self.commands.push(command);
+        let field = |index| field(request, index);
+        let rect = |first| Rect::new(field(first), field(first + 1), field(first + 2), field(first + 3));
+        let result = match command {
+            CMD_GET_DISPLAY_INFO => {
+                let mut response = header(RESP_OK_DISPLAY_INFO);
+                let (width, height) = self.scanout_size;
+                for value in [0, 0, width, height, (width > 0) as u32, 0] {
+                    response.extend_from_slice(&value.to_le_bytes());
+                }
+                response.resize(HEADER_SIZE + MAX_SCANOUTS * 24, 0);
+                return response;
+            }
+            CMD_GET_EDID => match &self.edid {
+                Some(edid) if self.driver_features & FEATURE_EDID != 0 => {
+                    let mut response = header(RESP_OK_EDID);
+                    response.extend_from_slice(&(edid.len() as u32).to_le_bytes());
+                    response.extend_from_slice(&[0; 4]);
+                    response.extend_from_slice(edid);
+                    response.resize(HEADER_SIZE + 8 + 1024, 0);
+                    return response;
+                }
+                _ => Err(RESP_ERR_UNSPEC),
+            },
+            CMD_RESOURCE_CREATE_2D => {
+                let (id, format, width, height) = (field(0), field(1), field(2), field(3));
+                if id == 0 || self.resources.contains_key(&id) {
+                    Err(RESP_ERR_INVALID_RESOURCE_ID)
+                } else if format != FORMAT_B8G8R8X8_UNORM || width == 0 || height == 0 {
+                    Err(RESP_ERR_INVALID_PARAMETER)
+                } else {
+                    let host = vec![0; width as usize * height as usize];
+                    self.resources.insert(id, Resource { width, height, backing: None, host });
+                    Ok(())
+                }
+            }
+            CMD_RESOURCE_UNREF => match self.resources.remove(&field(0)) {
+                Some(_) => {
+                    if self.scanout_resource == field(0) {
+                        self.scanout_resource = 0;
+                    }
+                    Ok(())
+                }
+                None => Err(RESP_ERR_INVALID_RESOURCE_ID),
+            },
+            CMD_RESOURCE_ATTACH_BACKING => match self.resources.get_mut(&field(0)) {
+                Some(resource) if field(1) == 1 => {
+                    resource.backing = Some((field(2) as u64 | (field(3) as u64) << 32, field(4)));
+                    Ok(())
+                }
+                Some(_) => Err(RESP_ERR_INVALID_PARAMETER),
+                None => Err(RESP_ERR_INVALID_RESOURCE_ID),
+            },
+            CMD_RESOURCE_DETACH_BACKING => match self.resources.get_mut(&field(0)) {
+                Some(resource) => {
+                    resource.backing = None;
+                    Ok(())
+                }
+                None => Err(RESP_ERR_INVALID_RESOURCE_ID),
+            },
+            CMD_SET_SCANOUT => {
+                let (area, scanout, id) = (rect(0), field(4), field(5));
+                match self.resources.get(&id) {
+                    _ if scanout != 0 => Err(RESP_ERR_INVALID_SCANOUT_ID),
+                    _ if id == 0 => {
+                        self.scanout_resource = 0;
+                        Ok(())
+                    }
+                    Some(resource) if area.x + area.width <= resource.width && area.y + area.height <= resource.height => {
+                        self.scanout_resource = id;
+                        Ok(())
+                    }
+                    Some(_) => Err(RESP_ERR_INVALID_PARAMETER),
+                    None => Err(RESP_ERR_INVALID_RESOURCE_ID),
+                }
+            }
+            CMD_TRANSFER_TO_HOST_2D => {
+                let (area, offset, id) = (rect(0), field(4) as u64 | (field(5) as u64) << 32, field(6));
+                match self.resources.get_mut(&id) {
+                    Some(resource) => match resource.backing {
+                        Some((backing, length)) => {
+                            // Line by line from `offset`, a resource width apart
+                            let stride = resource.width as u64 * 4;
+                            for line in 0..area.height as u64 {
+                                for x in 0..area.width as u64 {
+                                    let source = offset + stride * line + x * 4;
+                                    if source + 4 <= length as u64 {
+                                        let target = (area.y as u64 + line) * resource.width as u64 + area.x as u64 + x;
+                                        resource.host[target as usize] = peek_u32(backing + source);
+                                    }
+                                }
+                            }
+                            Ok(())
+                        }
+                        None => Err(RESP_ERR_UNSPEC),
+                    },
+                    None => Err(RESP_ERR_INVALID_RESOURCE_ID),
+                }
+            }
+            CMD_RESOURCE_FLUSH => {
+                if self.resources.contains_key(&field(4)) {
+                    self.flushes.push(rect(0));
+                    Ok(())
+                } else {
+                    Err(RESP_ERR_INVALID_RESOURCE_ID)
+                }
+            }
+            _ => Err(RESP_ERR_UNSPEC),
+        };
+        header(match result {
+            Ok(()) => RESP_OK_NODATA,
+            Err(error) => error,
+        })
+    }
+}
+
+fn header(kind: u32) -> Vec<u8> {
+    let mut bytes = vec![0; HEADER_SIZE];
+    bytes[..4].copy_from_slice(&kind.to_le_bytes());
+    bytes
+}
+
+/// Which register structure a view of the device is
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Structure {
+    Common,
+    Notify,
+    Device,
+}
+
+/// An emulated virtio-gpu with one scanout
+#[derive(Clone)]
+pub struct FakeVirtioGpu {
+    pub state: Arc<Mutex<GpuState>>,
+}
+
+impl FakeVirtioGpu {
+    /// A device showing scanout 0 at `width` by `height`, reading `edid`
+    /// for the monitor if given one
+    pub fn new(width: u32, height: u32, edid: Option<Vec<u8>>) -> Self {
+        let edid_feature = if edid.is_some() { FEATURE_EDID } else { 0 };
+        Self::with_features(width, height, edid, FEATURE_VERSION_1 | edid_feature)
+    }
+
+    pub fn with_features(width: u32, height: u32, edid: Option<Vec<u8>>, device_features: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(GpuState {
+                status: 0,
+                device_features,
+                driver_features: 0,
+                feature_select: 0,
+                driver_feature_select: 0,
+                queue_select: 0,
+                queues: [Queue::default(); QUEUE_COUNT],
+                scanout_size: (width, height),
+                edid,
+                events: 0,
+                resources: BTreeMap::new(),
+                scanout_resource: 0,
+                commands: Vec::new(),
+                flushes: Vec::new(),
+            })),
+        }
+    }
+
+    /// The device's register structures, for the driver
+    pub fn transport(&self) -> VirtioPci {
+        let view = |structure| alloc::boxed::Box::new(DeviceView { state: self.state.clone(), structure });
+        VirtioPci::new(view(Structure::Common), view(Structure::Notify), NOTIFY_MULTIPLIER, view(Structure::Device))
+    }
+
+    /// The host window showing the scanout was resized
+    pub fn resize(&self, width: u32, height: u32) {
+        let mut state = self.state.lock();
+        state.scanout_size = (width, height);
+        state.events |= crate::gpu::EVENT_DISPLAY;
+    }
+
+    /// A pixel of the host's copy of the resource on the scanout
+    pub fn scanout_pixel(&self, x: u32, y: u32) -> Option<u32> {
+        let state = self.state.lock();
+        let resource = state.resources.get(&state.scanout_resource)?;
+        resource.host.get((y * resource.width + x) as usize).copied()
+    }
+
+    /// Size of the resource on the scanout
+    pub fn scanout_resource_size(&self) -> Option<(u32, u32)> {
+        let state = self.state.lock();
+        state.resources.get(&state.scanout_resource).map(|resource| (resource.width, resource.height))
+    }
+}
+
+struct DeviceView {
+    state: Arc<Mutex<GpuState>>,
+    structure: Structure,
+}
+
+impl DeviceView {
+    fn read(&mut self, offset: usize) -> u32 {
+        let mut state = self.state.lock();
+        match self.structure {
+            Structure::Common => state.read_common(offset),
+            Structure::Notify => 0,
+            Structure::Device => match offset {
+                0x00 => state.events,
+                // One scanout, no capability sets
+                0x08 => 1,
+                _ => 0,
+            },
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: u32) {
+        let mut state = self.state.lock();
+        match self.structure {
+            Structure::Common => state.write_common(offset, value),
+            Structure::Notify => {
+                if offset == value as usize * NOTIFY_MULTIPLIER as usize {
+                    state.process(value as usize);
+                }
+            }
+            Structure::Device => {
+                if offset == 0x04 {
+                    state.events &= !value;
+                }
+            }
+        }
+    }
+}
+
+impl Mmio for DeviceView {
+    fn read_u8(&mut self, offset: usize) -> u8 {
+        self.read(offset) as u8
+    }
+
+    fn write_u8(&mut self, offset: usize, value: u8) {
+        self.write(offset, value as u32);
+    }
+
+    fn read_u16(&mut self, offset: usize) -> u16 {
+        self.read(offset) as u16
+    }
+
+    fn write_u16(&mut self, offset: usize, value: u16) {
+        self.write(offset, value as u32);
+    }
+
+    fn read_u32(&mut self, offset: usize) -> u32 {
+        self.read(offset)
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        self.write(offset, value);
+    }
+}
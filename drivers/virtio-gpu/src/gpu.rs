@@ -0,0 +1,290 @@
+//! virtio-gpu 2D commands
+//!
+//! Everything goes over the control queue as a request and a response,
+//! each starting with a control header. 2D output takes a resource the
+//! host keeps, backed by guest memory the driver draws into: the driver
+//! transfers what it drew to the host's copy and flushes it to the
+//! scanout it is attached to. The cursor queue and 3D are not used.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::transport::{VirtioError, VirtioPci};
+use crate::virtqueue::{queue_layout, DmaMemory, DmaRegion, Segment, Virtqueue, DMA_PAGE_SIZE, MAX_QUEUE_SIZE};
+
+/// Virtio device type of GPUs
+pub const VIRTIO_DEVICE_GPU: u16 = 16;
+
+/// The device can read the monitor's EDID
+pub const FEATURE_EDID: u64 = 1 << 1;
+
+/// Device configuration: pending events, events to clear, scanouts
+const CONFIG_EVENTS_READ: usize = 0x00;
+const CONFIG_EVENTS_CLEAR: usize = 0x04;
+const CONFIG_NUM_SCANOUTS: usize = 0x08;
+
+/// The display configuration changed; get the display info again
+pub const EVENT_DISPLAY: u32 = 1 << 0;
+
+const CONTROL_QUEUE: u16 = 0;
+
+/// Commands
+pub const CMD_GET_DISPLAY_INFO: u32 = 0x0100;
+pub const CMD_RESOURCE_CREATE_2D: u32 = 0x0101;
+pub const CMD_RESOURCE_UNREF: u32 = 0x0102;
+pub const CMD_SET_SCANOUT: u32 = 0x0103;
+pub const CMD_RESOURCE_FLUSH: u32 = 0x0104;
+pub const CMD_TRANSFER_TO_HOST_2D: u32 = 0x0105;
+pub const CMD_RESOURCE_ATTACH_BACKING: u32 = 0x0106;
+pub const CMD_RESOURCE_DETACH_BACKING: u32 = 0x0107;
+pub const CMD_GET_EDID: u32 = 0x010A;
+
+/// Responses; 0x1200 and up are errors
+pub const RESP_OK_NODATA: u32 = 0x1100;
+pub const RESP_OK_DISPLAY_INFO: u32 = 0x1101;
+pub const RESP_OK_EDID: u32 = 0x1104;
+pub const RESP_ERR_UNSPEC: u32 = 0x1200;
+
+/// Blue, green, red and an unused byte in memory order: a little-endian
+/// 0x00RRGGBB pixel, as the console draws at 32 bpp
+pub const FORMAT_B8G8R8X8_UNORM: u32 = 2;
+
+/// Bytes of the control header every request and response starts with
+pub const HEADER_SIZE: usize = 24;
+
+/// Scanouts a display info response describes
+pub const MAX_SCANOUTS: usize = 16;
+const DISPLAY_ONE_SIZE: usize = 24;
+const DISPLAY_INFO_SIZE: usize = HEADER_SIZE + MAX_SCANOUTS * DISPLAY_ONE_SIZE;
+
+/// Most EDID bytes a response carries
+const MAX_EDID_SIZE: usize = 1024;
+const EDID_RESPONSE_SIZE: usize = HEADER_SIZE + 8 + MAX_EDID_SIZE;
+
+/// Requests go at the start of the command page, responses after them
+const RESPONSE_OFFSET: usize = DMA_PAGE_SIZE / 2;
+
+/// Used ring polls before giving up on a request
+const POLL_LIMIT: usize = 1_000_000;
+
+/// A rectangle of a resource, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    fn bytes(&self) -> [u32; 4] {
+        [self.x, self.y, self.width, self.height]
+    }
+}
+
+/// One scanout in the display info: the size the host shows it at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scanout {
+    pub rect: Rect,
+    pub enabled: bool,
+}
+
+/// A request: control header with the command, then its fields
+fn request(command: u32, fields: &[u32]) -> Vec<u8> {
+    let mut bytes = vec![0; HEADER_SIZE];
+    bytes[..4].copy_from_slice(&command.to_le_bytes());
+    for field in fields {
+        bytes.extend_from_slice(&field.to_le_bytes());
+    }
+    bytes
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+/// A virtio-gpu device, set up and ready for commands
+pub struct VirtioGpu {
+    transport: VirtioPci,
+    dma: Box<dyn DmaMemory>,
+    control: Virtqueue,
+    /// Page requests and responses are copied through
+    commands: DmaRegion,
+    features: u64,
+    scanouts: u32,
+    next_resource: u32,
+}
+
+impl VirtioGpu {
+    /// Reset the device and set it up: features, then the control queue
+    pub fn new(mut transport: VirtioPci, mut dma: Box<dyn DmaMemory>) -> Result<Self, VirtioError> {
+        transport.reset()?;
+        let features = transport.negotiate(FEATURE_EDID)?;
+
+        let size = transport.max_queue_size(CONTROL_QUEUE).min(MAX_QUEUE_SIZE);
+        if size == 0 {
+            transport.fail();
+            return Err(VirtioError::NoQueue);
+        }
+        // Queue sizes need not be powers of two on modern devices, but the
+        // ones used here are
+        let size = 1 << size.ilog2();
+        let queue_memory = dma.allocate(queue_layout(size).3).ok_or(VirtioError::NoMemory)?;
+        let commands = match dma.allocate(DMA_PAGE_SIZE) {
+            Some(commands) => commands,
+            None => {
+                dma.free(queue_memory);
+                return Err(VirtioError::NoMemory);
+            }
+        };
+        let control = Virtqueue::new(queue_memory, size);
+        if let Err(error) = transport.setup_queue(CONTROL_QUEUE, &control) {
+            transport.fail();
+            dma.free(queue_memory);
+            dma.free(commands);
+            return Err(error);
+        }
+        transport.driver_ok();
+        let scanouts = transport.read_device_u32(CONFIG_NUM_SCANOUTS).min(MAX_SCANOUTS as u32);
+
+        Ok(Self {
+            transport,
+            dma,
+            control,
+            commands,
+            features,
+            scanouts,
+            next_resource: 1,
+        })
+    }
+
+    pub fn features(&self) -> u64 {
+        self.features
+    }
+
+    pub fn scanout_count(&self) -> u32 {
+        self.scanouts
+    }
+
+    pub fn dma(&mut self) -> &mut dyn DmaMemory {
+        self.dma.as_mut()
+    }
+
+    /// Send `request` and wait for its response of up to `response_size`
+    /// bytes
+    fn command(&mut self, request: &[u8], response_size: usize) -> Result<Vec<u8>, VirtioError> {
+        self.commands.write_bytes(0, request);
+        self.commands.write_bytes(RESPONSE_OFFSET, &[0; HEADER_SIZE]);
+        let segments = [
+            Segment { address: self.commands.phys, length: request.len() as u32, device_writes: false },
+            Segment { address: self.commands.phys + RESPONSE_OFFSET as u64, length: response_size as u32, device_writes: true },
+        ];
+        let head = self.control.push(&segments).ok_or(VirtioError::NoMemory)?;
+        self.transport.notify(CONTROL_QUEUE);
+
+        for _ in 0..POLL_LIMIT {
+            if let Some((used, _)) = self.control.pop_used() {
+                if used != head {
+                    continue;
+                }
+                let response = self.commands.read_bytes(RESPONSE_OFFSET, response_size);
+                return match read_u32(&response, 0) {
+                    kind if kind >= RESP_ERR_UNSPEC => Err(VirtioError::Request(kind)),
+                    _ => Ok(response),
+                };
+            }
+            core::hint::spin_loop();
+        }
+        Err(VirtioError::Timeout)
+    }
+
+    /// Send a request answered with no data
+    fn command_nodata(&mut self, command: u32, fields: &[u32]) -> Result<(), VirtioError> {
+        self.command(&request(command, fields), HEADER_SIZE).map(|_| ())
+    }
+
+    /// The size the host shows each scanout at
+    pub fn display_info(&mut self) -> Result<Vec<Scanout>, VirtioError> {
+        let response = self.command(&request(CMD_GET_DISPLAY_INFO, &[]), DISPLAY_INFO_SIZE)?;
+        Ok((0..self.scanouts as usize)
+            .map(|index| {
+                let offset = HEADER_SIZE + index * DISPLAY_ONE_SIZE;
+                let field = |number: usize| read_u32(&response, offset + number * 4);
+                Scanout {
+                    rect: Rect::new(field(0), field(1), field(2), field(3)),
+                    enabled: field(4) != 0,
+                }
+            })
+            .collect())
+    }
+
+    /// The EDID of the monitor on `scanout`, None if the device cannot
+    /// read one
+    pub fn edid(&mut self, scanout: u32) -> Result<Option<Vec<u8>>, VirtioError> {
+        if self.features & FEATURE_EDID == 0 {
+            return Ok(None);
+        }
+        let response = self.command(&request(CMD_GET_EDID, &[scanout, 0]), EDID_RESPONSE_SIZE)?;
+        let size = (read_u32(&response, HEADER_SIZE) as usize).min(MAX_EDID_SIZE);
+        Ok((size > 0).then(|| response[HEADER_SIZE + 8..HEADER_SIZE + 8 + size].to_vec()))
+    }
+
+    /// Create a `width` by `height` resource of `FORMAT_B8G8R8X8_UNORM`
+    /// pixels, returning its ID
+    pub fn create_resource(&mut self, width: u32, height: u32) -> Result<u32, VirtioError> {
+        let resource = self.next_resource;
+        self.command_nodata(CMD_RESOURCE_CREATE_2D, &[resource, FORMAT_B8G8R8X8_UNORM, width, height])?;
+        self.next_resource = self.next_resource.wrapping_add(1).max(1);
+        Ok(resource)
+    }
+
+    /// Back `resource` with the guest memory `backing`, one contiguous
+    /// entry
+    pub fn attach_backing(&mut self, resource: u32, backing: &DmaRegion) -> Result<(), VirtioError> {
+        let fields = [resource, 1, backing.phys as u32, (backing.phys >> 32) as u32, backing.size as u32, 0];
+        self.command_nodata(CMD_RESOURCE_ATTACH_BACKING, &fields)
+    }
+
+    pub fn detach_backing(&mut self, resource: u32) -> Result<(), VirtioError> {
+        self.command_nodata(CMD_RESOURCE_DETACH_BACKING, &[resource, 0])
+    }
+
+    pub fn unref_resource(&mut self, resource: u32) -> Result<(), VirtioError> {
+        self.command_nodata(CMD_RESOURCE_UNREF, &[resource, 0])
+    }
+
+    /// Show `rect` of `resource` on `scanout`; resource 0 switches the
+    /// scanout off
+    pub fn set_scanout(&mut self, scanout: u32, resource: u32, rect: Rect) -> Result<(), VirtioError> {
+        let [x, y, width, height] = rect.bytes();
+        self.command_nodata(CMD_SET_SCANOUT, &[x, y, width, height, scanout, resource])
+    }
+
+    /// Copy `rect` of the backing, starting `offset` bytes in, to the
+    /// host's copy of `resource`
+    pub fn transfer_to_host(&mut self, resource: u32, rect: Rect, offset: u64) -> Result<(), VirtioError> {
+        let [x, y, width, height] = rect.bytes();
+        self.command_nodata(CMD_TRANSFER_TO_HOST_2D, &[x, y, width, height, offset as u32, (offset >> 32) as u32, resource, 0])
+    }
+
+    /// Show the host's copy of `rect` of `resource` on the scanouts it is on
+    pub fn flush_resource(&mut self, resource: u32, rect: Rect) -> Result<(), VirtioError> {
+        let [x, y, width, height] = rect.bytes();
+        self.command_nodata(CMD_RESOURCE_FLUSH, &[x, y, width, height, resource, 0])
+    }
+
+    /// Whether the display configuration changed since last asked,
+    /// acknowledging the change
+    pub fn display_changed(&mut self) -> bool {
+        let events = self.transport.read_device_u32(CONFIG_EVENTS_READ);
+        if events & EVENT_DISPLAY == 0 {
+            return false;
+        }
+        self.transport.write_device_u32(CONFIG_EVENTS_CLEAR, EVENT_DISPLAY);
+        true
+    }
+}
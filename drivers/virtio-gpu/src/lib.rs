@@ -0,0 +1,285 @@
+#![no_std]
+
+extern crate alloc;
+
+pub mod gpu;
+
+pub use kosh_driver::virtio::{transport, virtqueue};
+
+use alloc::{vec::Vec, boxed::Box};
+use kosh_driver::{DriverFactory, DriverType, HardwareId, KoshDriver};
+use kosh_driver::hal::Mmio;
+use kosh_graphics_driver::VgaTextDriver;
+use kosh_graphics_driver::edid::EDID_BLOCK_LEN;
+use kosh_graphics_driver::framebuffer::{Font, FramebufferLayout, PixelFormat};
+use kosh_graphics_driver::mode::{DisplayAdapter, DisplayMode, RESOLUTIONS};
+use kosh_sync::Mutex;
+use kosh_types::DriverError;
+use gpu::{Rect, VirtioGpu};
+use virtqueue::{DmaRegion, DMA_PAGE_SIZE};
+
+/// Devices the factory takes, by vendor and device ID: virtio-gpu-pci and
+/// virtio-vga, which are the same modern virtio device
+pub const VIRTIO_GPU_HARDWARE_IDS: [(u32, u32); 1] = [(0x1AF4, 0x1050)];
+
+/// The one depth offered; resources are `FORMAT_B8G8R8X8_UNORM`
+const DEPTH: u8 = 32;
+const BYTES_PER_PIXEL: usize = 4;
+
+/// Guest memory backing the resource on the scanout
+///
+/// Writes are tracked so a flush only transfers the lines drawn since the
+/// last one.
+pub struct Framebuffer {
+    memory: DmaRegion,
+    /// Bytes written since the last flush, as a range
+    dirty: Option<(usize, usize)>,
+}
+
+impl Framebuffer {
+    fn touch(&mut self, offset: usize, width: usize) {
+        let (start, end) = self.dirty.unwrap_or((offset, offset + width));
+        self.dirty = Some((start.min(offset), end.max(offset + width)));
+    }
+}
+
+impl Mmio for Framebuffer {
+    fn read_u8(&mut self, offset: usize) -> u8 {
+        self.memory.read_u8(offset)
+    }
+
+    fn write_u8(&mut self, offset: usize, value: u8) {
+        self.memory.write_u8(offset, value);
+        self.touch(offset, 1);
+    }
+
+    fn read_u16(&mut self, offset: usize) -> u16 {
+        self.memory.read_u16(offset)
+    }
+
+    fn write_u16(&mut self, offset: usize, value: u16) {
+        self.memory.write_u16(offset, value);
+        self.touch(offset, 2);
+    }
+
+    fn read_u32(&mut self, offset: usize) -> u32 {
+        self.memory.read_u32(offset)
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        self.memory.write_u32(offset, value);
+        self.touch(offset, 4);
+    }
+}
+
+/// A resource on the scanout
+struct Surface {
+    resource: u32,
+    framebuffer: Framebuffer,
+}
+
+/// A virtio-gpu scanout the console shows on
+///
+/// Each graphics mode is a resource of its size backed by guest memory the
+/// console draws into; switching modes creates a new one and drops the
+/// old. virtio-gpu has no text mode, so once a graphics mode shows the
+/// console stays in graphics modes. The font comes from VGA text mode, so
+/// it must be read before the first switch, on virtio-vga; without one the
+/// console draws with the graphics driver's built-in font.
+pub struct VirtioGpuDisplay {
+    gpu: VirtioGpu,
+    scanout: u32,
+    font: Option<Font>,
+    mode: DisplayMode,
+    surface: Option<Surface>,
+}
+
+impl VirtioGpuDisplay {
+    /// Show the console on `scanout` of `gpu`, drawing text with `font`
+    ///
+    /// `mode` is what shows until the first switch, text on virtio-vga.
+    pub fn new(gpu: VirtioGpu, scanout: u32, font: Option<Font>, mode: DisplayMode) -> Self {
+        Self { gpu, scanout, font, mode, surface: None }
+    }
+
+    /// The size the host shows the scanout at, None if it is disabled
+    fn scanout_size(&mut self) -> Option<(u16, u16)> {
+        let scanouts = self.gpu.display_info().ok()?;
+        let scanout = scanouts.get(self.scanout as usize).filter(|scanout| scanout.enabled)?;
+        let size = (u16::try_from(scanout.rect.width).ok()?, u16::try_from(scanout.rect.height).ok()?);
+        (size.0 > 0 && size.1 > 0).then_some(size)
+    }
+
+    /// Create a resource for `mode` and put it on the scanout
+    fn create_surface(&mut self, mode: DisplayMode) -> Result<Surface, DriverError> {
+        let (width, height) = (mode.width as u32, mode.height as u32);
+        let size = (width as usize * height as usize * BYTES_PER_PIXEL).next_multiple_of(DMA_PAGE_SIZE);
+        let memory = self.gpu.dma().allocate(size).ok_or(DriverError::ResourceBusy)?;
+        let resource = match self.gpu.create_resource(width, height) {
+            Ok(resource) => resource,
+            Err(error) => {
+                self.gpu.dma().free(memory);
+                return Err(error.into());
+            }
+        };
+        let shown = self.gpu.attach_backing(resource, &memory)
+            .and_then(|_| self.gpu.set_scanout(self.scanout, resource, Rect::new(0, 0, width, height)));
+        if let Err(error) = shown {
+            let _ = self.gpu.unref_resource(resource);
+            self.gpu.dma().free(memory);
+            return Err(error.into());
+        }
+        Ok(Surface { resource, framebuffer: Framebuffer { memory, dirty: None } })
+    }
+
+    /// Drop a resource no longer on the scanout
+    fn destroy_surface(&mut self, surface: Surface) {
+        let _ = self.gpu.detach_backing(surface.resource);
+        let _ = self.gpu.unref_resource(surface.resource);
+        self.gpu.dma().free(surface.framebuffer.memory);
+    }
+}
+
+impl DisplayAdapter for VirtioGpuDisplay {
+    /// The scanout's own size, then the usual resolutions, all at 32 bpp
+    fn modes(&mut self) -> Vec<DisplayMode> {
+        let mut modes = Vec::new();
+        if let Some((width, height)) = self.scanout_size() {
+            modes.push(DisplayMode::graphics(width, height, DEPTH));
+        }
+        for &(width, height) in RESOLUTIONS.iter() {
+            let mode = DisplayMode::graphics(width, height, DEPTH);
+            if !modes.contains(&mode) {
+                modes.push(mode);
+            }
+        }
+        if !self.mode.is_text() && !modes.contains(&self.mode) {
+            modes.push(self.mode);
+        }
+        modes
+    }
+
+    fn mode(&self) -> DisplayMode {
+        self.mode
+    }
+
+    fn layout(&self) -> Option<FramebufferLayout> {
+        self.surface.as_ref()?;
+        Some(FramebufferLayout {
+            pitch: self.mode.width as usize * BYTES_PER_PIXEL,
+            format: PixelFormat::direct(DEPTH)?,
+        })
+    }
+
+    fn framebuffer(&mut self) -> Option<&mut dyn Mmio> {
+        Some(&mut self.surface.as_mut()?.framebuffer)
+    }
+
+    fn read_edid(&mut self) -> Option<[u8; EDID_BLOCK_LEN]> {
+        let edid = self.gpu.edid(self.scanout).ok()??;
+        edid.get(..EDID_BLOCK_LEN)?.try_into().ok()
+    }
+
+    fn font(&self) -> Option<Font> {
+        self.font.clone()
+    }
+
+    fn set_mode(&mut self, mode: DisplayMode) -> Result<(), DriverError> {
+        if mode.is_text() || mode.bpp != DEPTH {
+            return Err(DriverError::InvalidRequest);
+        }
+        // The new resource goes on the scanout before the old one is
+        // dropped, so a failed switch leaves the old mode showing
+        let surface = self.create_surface(mode)?;
+        if let Some(previous) = self.surface.replace(surface) {
+            self.destroy_surface(previous);
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    /// Transfer the lines drawn since the last flush and show them
+    fn flush(&mut self) {
+        let width = self.mode.width as u32;
+        let pitch = width as usize * BYTES_PER_PIXEL;
+        let Some(surface) = self.surface.as_mut() else {
+            return;
+        };
+        let Some((start, end)) = surface.framebuffer.dirty.take() else {
+            return;
+        };
+        let resource = surface.resource;
+        let first = start / pitch;
+        let lines = (end - 1) / pitch + 1 - first;
+        let rect = Rect::new(0, first as u32, width, lines as u32);
+        let _ = self.gpu.transfer_to_host(resource, rect, (first * pitch) as u64)
+            .and_then(|_| self.gpu.flush_resource(resource, rect));
+    }
+
+    fn preferred_mode(&mut self) -> Option<DisplayMode> {
+        let (width, height) = self.scanout_size()?;
+        Some(DisplayMode::graphics(width, height, DEPTH))
+    }
+
+    fn display_changed(&mut self) -> bool {
+        self.gpu.display_changed()
+    }
+}
+
+/// Global virtio-gpu console, the graphics driver's console shown through
+/// a virtio-gpu scanout
+static VIRTIO_GPU_DRIVER: Mutex<Option<VgaTextDriver>> = Mutex::new(None);
+
+/// Initialize the global driver on scanout 0 of `gpu`
+///
+/// The console takes the size the host shows the scanout at, or failing
+/// that the monitor's EDID.
+pub fn init_virtio_gpu_driver(gpu: VirtioGpu, font: Option<Font>, mode: DisplayMode) -> Result<(), DriverError> {
+    let mut driver = VgaTextDriver::with_adapter(Box::new(VirtioGpuDisplay::new(gpu, 0, font, mode)));
+    driver.init(Vec::new())?;
+    *VIRTIO_GPU_DRIVER.lock() = Some(driver);
+    Ok(())
+}
+
+/// Follow the host resizing the scanout, returning whether the console
+/// switched modes
+pub fn virtio_gpu_poll() -> bool {
+    match VIRTIO_GPU_DRIVER.lock().as_mut() {
+        Some(driver) => matches!(driver.poll_display(), Ok(Some(_))),
+        None => false,
+    }
+}
+
+/// Write text to the global virtio-gpu console
+pub fn virtio_gpu_write(text: &str) {
+    if let Some(driver) = VIRTIO_GPU_DRIVER.lock().as_mut() {
+        driver.write_string(text);
+    }
+}
+
+/// Driver factory matching the devices in `VIRTIO_GPU_HARDWARE_IDS`
+///
+/// The driver needs the device's mapped register structures and DMA
+/// memory, which the factory interface cannot pass, so the driver process
+/// creates it with `init_virtio_gpu_driver` instead.
+pub struct VirtioGpuDriverFactory;
+
+impl DriverFactory for VirtioGpuDriverFactory {
+    fn create_driver(&self, _hardware_id: &HardwareId) -> Result<Box<dyn KoshDriver>, DriverError> {
+        Err(DriverError::InvalidRequest)
+    }
+
+    fn can_handle(&self, hardware_id: &HardwareId) -> bool {
+        VIRTIO_GPU_HARDWARE_IDS.contains(&(hardware_id.vendor_id, hardware_id.device_id))
+    }
+
+    fn get_driver_type(&self) -> DriverType {
+        DriverType::Graphics
+    }
+}
+
+#[cfg(test)]
+mod fixtures;
+
+#[cfg(test)]
+mod tests;
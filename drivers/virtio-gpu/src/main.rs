@@ -0,0 +1,119 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use kosh_driver::hal::{map_mmio, HardwareMmio, HardwarePortIo};
+use kosh_driver::pci::{self, ConfigSpace, PortConfigSpace};
+use kosh_driver::report;
+use kosh_driver::time::{sleep_us, PeriodicTimer};
+use kosh_driver::{DriverMetadataRecord, DriverSignatureRecord, DriverType};
+use kosh_graphics_driver::framebuffer::{Font, VGA_MEMORY_ADDRESS, VGA_MEMORY_SIZE};
+use kosh_graphics_driver::mode::DisplayMode;
+use kosh_virtio_gpu_driver::gpu::VirtioGpu;
+use kosh_virtio_gpu_driver::transport::{Structure, VirtioCapabilities, VirtioPci};
+use kosh_virtio_gpu_driver::virtqueue::KernelDma;
+use kosh_virtio_gpu_driver::{init_virtio_gpu_driver, virtio_gpu_poll, VIRTIO_GPU_HARDWARE_IDS};
+
+/// Memory space and bus master enable in the command register
+const COMMAND_MEMORY_AND_BUS_MASTER: u32 = 0x6;
+
+/// Time between checks for the host resizing the display
+const POLL_INTERVAL_US: u64 = 100_000;
+
+/// Read by the driver manager: QEMU virtio-gpu-pci and virtio-vga. The
+/// device is found through the configuration ports and its BARs are
+/// granted once the manager binds it; the VGA ports and memory are for
+/// reading the font on virtio-vga.
+#[used]
+#[link_section = ".kosh_driver"]
+static DRIVER_METADATA: DriverMetadataRecord = DriverMetadataRecord::new("virtio-gpu", "0.1.0", DriverType::Graphics)
+    .with_hardware_id(0x1AF4, 0x1050)
+    .requires_io_ports(0xCF8, 0xCFF)
+    .requires_io_ports(0x3C4, 0x3CF)
+    .requires_mmio(VGA_MEMORY_ADDRESS, VGA_MEMORY_SIZE as u64)
+    .requires_pci_device(0x1AF4, 0x1050)
+    .requires_dma_memory();
+
+/// Filled in when the driver is signed
+#[used]
+#[link_section = ".kosh_signature"]
+static DRIVER_SIGNATURE: DriverSignatureRecord = DriverSignatureRecord::UNSIGNED;
+
+/// Find the first virtio-gpu, enable it and map its register structures
+fn find_device() -> Option<VirtioPci> {
+    let mut config = PortConfigSpace::new(HardwarePortIo);
+    let device = pci::enumerate(&mut config)
+        .into_iter()
+        .find(|device| VIRTIO_GPU_HARDWARE_IDS.contains(&(device.vendor_id as u32, device.device_id as u32)))?;
+    let capabilities = VirtioCapabilities::read(&mut config, device.address)?;
+    let bars = pci::memory_bars(&mut config, device.address);
+
+    // Only touch the command half: status bits are cleared by writing 1
+    let command = config.read_u32(device.address, pci::COMMAND_STATUS) & 0xFFFF;
+    config.write_u32(device.address, pci::COMMAND_STATUS, command | COMMAND_MEMORY_AND_BUS_MASTER);
+
+    let map = |structure: Structure| -> Option<Box<HardwareMmio>> {
+        let bar = bars.iter().find(|bar| bar.index == structure.bar)?;
+        map_mmio(bar.address + structure.offset as u64, structure.length as usize).ok().map(Box::new)
+    };
+    Some(VirtioPci::new(
+        map(capabilities.common)?,
+        map(capabilities.notify)?,
+        capabilities.notify_multiplier,
+        map(capabilities.device)?,
+    ))
+}
+
+/// The mode the boot loader left and, in text mode, the font it shows
+///
+/// On virtio-vga the font is only there until the first graphics mode.
+fn boot_console() -> (DisplayMode, Option<Font>) {
+    match kosh_driver::display::boot_display() {
+        Some(boot) if boot.is_text() => {
+            let font = map_mmio(VGA_MEMORY_ADDRESS, VGA_MEMORY_SIZE)
+                .ok()
+                .map(|mut memory| Font::read_vga(&mut HardwarePortIo, &mut memory));
+            (DisplayMode::TEXT, font)
+        }
+        Some(boot) => (DisplayMode::from_boot(&boot), None),
+        None => (DisplayMode::TEXT, None),
+    }
+}
+
+/// Entry point for the virtio-gpu driver process
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    if let Some(transport) = find_device() {
+        let gpu = match VirtioGpu::new(transport, Box::new(KernelDma::default())) {
+            Ok(gpu) => gpu,
+            Err(e) => report::fail(format_args!("Failed to set up virtio-gpu: {:?}", e)),
+        };
+        let (mode, font) = boot_console();
+        if let Err(e) = init_virtio_gpu_driver(gpu, font, mode) {
+            report::fail(format_args!("Failed to initialize virtio-gpu driver: {:?}", e));
+        }
+    }
+
+    // Main driver loop: display events are not delivered as interrupts
+    // yet, so check for them now and then and sleep in between
+    let timer = PeriodicTimer::new(POLL_INTERVAL_US);
+    loop {
+        virtio_gpu_poll();
+
+        match &timer {
+            Some(timer) => {
+                timer.wait();
+            }
+            None => sleep_us(POLL_INTERVAL_US),
+        }
+    }
+}
+
+/// Panic handler for the driver (only in non-test builds)
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    report::report_panic("virtio-gpu", info)
+}
@@ -0,0 +1,191 @@
+use super::*;
+use crate::fixtures::{FakeVirtioGpu, HeapDma, RESP_ERR_INVALID_RESOURCE_ID};
+use crate::gpu::{Scanout, CMD_RESOURCE_FLUSH, CMD_RESOURCE_UNREF, FEATURE_EDID};
+use crate::transport::{
+    VirtioError, FEATURE_VERSION_1, STATUS_ACKNOWLEDGE, STATUS_DRIVER, STATUS_DRIVER_OK, STATUS_FAILED,
+    STATUS_FEATURES_OK,
+};
+use crate::virtqueue::{queue_layout, DmaMemory, Segment, Virtqueue, DESCRIPTOR_NEXT, DESCRIPTOR_WRITE};
+use kosh_driver::{DriverRequest, DriverResponse};
+use kosh_graphics_driver::framebuffer::{FONT_SIZE, GLYPH_HEIGHT};
+use alloc::vec;
+
+const WHITE: u32 = 0x00FF_FFFF;
+
+/// Every glyph but blanks a solid top line, so drawn text is easy to find
+fn font() -> Font {
+    let glyphs: Vec<u8> = (0..FONT_SIZE)
+        .map(|index| {
+            let blank = matches!((index / GLYPH_HEIGHT) as u8, 0 | b' ');
+            if index % GLYPH_HEIGHT == 0 && !blank { 0xFF } else { 0 }
+        })
+        .collect();
+    Font::from_bytes(&glyphs).unwrap()
+}
+
+/// An EDID base block, only the header filled in
+fn edid_block() -> Vec<u8> {
+    let mut block = vec![0u8; EDID_BLOCK_LEN];
+    block[..8].copy_from_slice(&[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00]);
+    block
+}
+
+fn gpu(fake: &FakeVirtioGpu, dma: &HeapDma) -> VirtioGpu {
+    VirtioGpu::new(fake.transport(), Box::new(dma.clone())).unwrap()
+}
+
+#[test]
+fn test_virtqueue_chains() {
+    let mut dma = HeapDma::default();
+    let memory = dma.allocate(queue_layout(8).3).unwrap();
+    let mut queue = Virtqueue::new(memory, 8);
+    let (descriptors, available, used) = queue.addresses();
+    assert_eq!(available - descriptors, 8 * 16);
+    assert_eq!(used % 4, 0);
+
+    let segments = [
+        Segment { address: 0x1000, length: 24, device_writes: false },
+        Segment { address: 0x2000, length: 64, device_writes: true },
+    ];
+    let head = queue.push(&segments).unwrap();
+    assert_eq!(queue.free_descriptors(), 6);
+    let descriptor = |index: u16| {
+        let offset = index as usize * 16;
+        (memory.read_u32(offset), memory.read_u32(offset + 8), memory.read_u16(offset + 12), memory.read_u16(offset + 14))
+    };
+    let (address, length, flags, next) = descriptor(head);
+    assert_eq!((address, length, flags), (0x1000, 24, DESCRIPTOR_NEXT));
+    assert_eq!(descriptor(next), (0x2000, 64, DESCRIPTOR_WRITE, 0));
+    let available_offset = (available - descriptors) as usize;
+    assert_eq!(memory.read_u16(available_offset + 2), 1);
+    assert_eq!(memory.read_u16(available_offset + 4), head);
+
+    // Nothing used until the device says so
+    assert_eq!(queue.pop_used(), None);
+    let used_offset = (used - descriptors) as usize;
+    memory.write_u32(used_offset + 4, head as u32);
+    memory.write_u32(used_offset + 8, 40);
+    memory.write_u16(used_offset + 2, 1);
+    assert_eq!(queue.pop_used(), Some((head, 40)));
+    assert_eq!(queue.free_descriptors(), 8);
+    assert_eq!(queue.pop_used(), None);
+
+    assert_eq!(queue.push(&[segments[0]; 9]), None);
+    assert_eq!(queue.push(&[]), None);
+}
+
+#[test]
+fn test_device_setup() {
+    let fake = FakeVirtioGpu::new(1024, 768, Some(edid_block()));
+    let dma = HeapDma::default();
+    let gpu = gpu(&fake, &dma);
+    assert_eq!(gpu.features(), FEATURE_VERSION_1 | FEATURE_EDID);
+    assert_eq!(gpu.scanout_count(), 1);
+    {
+        let state = fake.state.lock();
+        assert_eq!(state.status, STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK | STATUS_DRIVER_OK);
+        assert_eq!(state.driver_features, FEATURE_VERSION_1 | FEATURE_EDID);
+    }
+    // The control queue and the command page
+    assert_eq!(dma.live(), 2);
+
+    // Legacy-only devices are refused
+    let legacy = FakeVirtioGpu::with_features(1024, 768, None, FEATURE_EDID);
+    let refused = VirtioGpu::new(legacy.transport(), Box::new(HeapDma::default()));
+    assert!(matches!(refused, Err(VirtioError::FeaturesRefused)));
+    assert_ne!(legacy.state.lock().status & STATUS_FAILED, 0);
+}
+
+#[test]
+fn test_display_info_and_edid() {
+    let fake = FakeVirtioGpu::new(1280, 800, Some(edid_block()));
+    let dma = HeapDma::default();
+    let mut gpu = gpu(&fake, &dma);
+    assert_eq!(gpu.display_info().unwrap(), vec![Scanout { rect: Rect::new(0, 0, 1280, 800), enabled: true }]);
+    assert_eq!(gpu.edid(0).unwrap(), Some(edid_block()));
+
+    // Devices without the feature are not asked
+    let plain = FakeVirtioGpu::new(1280, 800, None);
+    let mut gpu = self::gpu(&plain, &dma);
+    assert_eq!(gpu.edid(0).unwrap(), None);
+
+    // Error responses come back as errors
+    let missing = gpu.set_scanout(0, 7, Rect::new(0, 0, 1280, 800));
+    assert_eq!(missing, Err(VirtioError::Request(RESP_ERR_INVALID_RESOURCE_ID)));
+    let resource = gpu.create_resource(64, 64).unwrap();
+    assert_eq!(resource, 1);
+    assert_eq!(gpu.create_resource(64, 64), Ok(2));
+}
+
+#[test]
+fn test_console_on_virtio_gpu() {
+    let fake = FakeVirtioGpu::new(1024, 768, Some(edid_block()));
+    let dma = HeapDma::default();
+    let display = VirtioGpuDisplay::new(gpu(&fake, &dma), 0, Some(font()), DisplayMode::TEXT);
+    let mut driver = VgaTextDriver::with_adapter(Box::new(display));
+
+    // The console takes the size the host shows
+    driver.init(Vec::new()).unwrap();
+    assert_eq!(driver.display_mode(), DisplayMode::graphics(1024, 768, 32));
+    assert_eq!(driver.console_size(), (128, 48));
+    assert_eq!(fake.scanout_resource_size(), Some((1024, 768)));
+    assert_eq!(fake.scanout_pixel(0, 0), Some(WHITE));
+    assert_eq!(fake.scanout_pixel(0, 1), Some(0));
+    assert_eq!(fake.scanout_pixel(0, 16), Some(0));
+    // The queue, the command page and the framebuffer
+    assert_eq!(dma.live(), 3);
+
+    // Only the lines drawn are transferred and flushed
+    fake.state.lock().flushes.clear();
+    driver.write_string("hello");
+    assert_eq!(fake.scanout_pixel(0, 16), Some(WHITE));
+    let flushes = fake.state.lock().flushes.clone();
+    assert!(!flushes.is_empty());
+    assert!(flushes.iter().all(|&rect| rect == Rect::new(0, 16, 1024, 16)));
+
+    match driver.handle_request(DriverRequest::Control { command: 0x08, data: vec![] }) {
+        Ok(DriverResponse::Data(bytes)) => {
+            assert_eq!(&bytes[..5], &DisplayMode::graphics(1024, 768, 32).to_bytes());
+            assert!(bytes.chunks_exact(5).all(|mode| mode[4] == 32));
+        }
+        other => panic!("Expected display modes, got {:?}", other),
+    }
+
+    // A new resource replaces the old one, which is dropped with its memory
+    let mode = DisplayMode::graphics(800, 600, 32);
+    let set = |mode: DisplayMode| DriverRequest::Control { command: 0x09, data: mode.to_bytes().to_vec() };
+    assert!(matches!(driver.handle_request(set(mode)), Ok(DriverResponse::Success)));
+    assert_eq!(driver.console_size(), (100, 37));
+    assert_eq!(fake.scanout_resource_size(), Some((800, 600)));
+    assert_eq!(fake.state.lock().resources.len(), 1);
+    assert!(fake.state.lock().commands.contains(&CMD_RESOURCE_UNREF));
+    assert_eq!(dma.live(), 3);
+    assert_eq!(fake.scanout_pixel(0, 16), Some(WHITE));
+
+    // There is no text mode to go back to
+    assert!(matches!(driver.handle_request(set(DisplayMode::TEXT)), Err(DriverError::InvalidRequest)));
+    assert_eq!(driver.display_mode(), mode);
+}
+
+#[test]
+fn test_console_follows_resize() {
+    let fake = FakeVirtioGpu::new(1024, 768, None);
+    let dma = HeapDma::default();
+    let display = VirtioGpuDisplay::new(gpu(&fake, &dma), 0, Some(font()), DisplayMode::TEXT);
+    let mut driver = VgaTextDriver::with_adapter(Box::new(display));
+    driver.init(Vec::new()).unwrap();
+    assert!(matches!(driver.poll_display(), Ok(None)));
+
+    // The host window is resized
+    fake.resize(1280, 800);
+    let flushes = fake.state.lock().commands.iter().filter(|&&command| command == CMD_RESOURCE_FLUSH).count();
+    assert!(matches!(driver.poll_display(), Ok(Some(mode)) if mode == DisplayMode::graphics(1280, 800, 32)));
+    assert_eq!(driver.console_size(), (160, 50));
+    assert_eq!(fake.scanout_resource_size(), Some((1280, 800)));
+    assert_eq!(fake.state.lock().events, 0);
+    assert_eq!(fake.scanout_pixel(0, 0), Some(WHITE));
+    assert!(fake.state.lock().commands.iter().filter(|&&command| command == CMD_RESOURCE_FLUSH).count() > flushes);
+
+    // Nothing more until the next resize
+    assert!(matches!(driver.poll_display(), Ok(None)));
+}
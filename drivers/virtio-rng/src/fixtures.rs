@@ -0,0 +1,298 @@
+//! Emulated virtio-rng device
+//!
+//! Implements the modern virtio PCI register structures and the request
+//! queue: every buffer the driver makes available is filled with bytes
+//! counting up from 1, up to however many the device is told it has left.
+//! Test DMA memory is identity mapped, so device addresses can be
+//! dereferenced directly.
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use kosh_driver::hal::Mmio;
+use spin::Mutex;
+use crate::transport::{VirtioPci, FEATURE_VERSION_1, STATUS_FEATURES_OK};
+use crate::virtqueue::{DmaMemory, DmaRegion, DESCRIPTOR_NEXT, DESCRIPTOR_WRITE, DMA_PAGE_SIZE};
+
+/// Bytes between the notification addresses of consecutive queues
+pub const NOTIFY_MULTIPLIER: u32 = 4;
+
+const MAX_QUEUE_SIZE: u16 = 64;
+
+fn peek_u16(address: u64) -> u16 {
+    unsafe { (address as *const u16).read_volatile() }
+}
+
+fn peek_u32(address: u64) -> u32 {
+    unsafe { (address as *const u32).read_volatile() }
+}
+
+fn peek_u64(address: u64) -> u64 {
+    peek_u32(address) as u64 | (peek_u32(address + 4) as u64) << 32
+}
+
+fn poke_u16(address: u64, value: u16) {
+    unsafe { (address as *mut u16).write_volatile(value) }
+}
+
+fn poke_u32(address: u64, value: u32) {
+    unsafe { (address as *mut u32).write_volatile(value) }
+}
+
+/// Identity-mapped DMA memory the test keeps a handle on while the driver
+/// owns it
+#[derive(Clone, Default)]
+pub struct HeapDma(pub Arc<Mutex<Vec<DmaRegion>>>);
+
+impl HeapDma {
+    /// Regions allocated and not freed
+    pub fn live(&self) -> usize {
+        self.0.lock().len()
+    }
+}
+
+impl DmaMemory for HeapDma {
+    fn allocate(&mut self, size: usize) -> Option<DmaRegion> {
+        let memory = vec![0u8; size + DMA_PAGE_SIZE].leak();
+        let start = (memory.as_ptr() as usize).next_multiple_of(DMA_PAGE_SIZE);
+        let region = DmaRegion { virt: start, phys: start as u64, size };
+        self.0.lock().push(region);
+        Some(region)
+    }
+
+    fn free(&mut self, region: DmaRegion) {
+        self.0.lock().retain(|live| *live != region);
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Queue {
+    size: u16,
+    descriptors: u64,
+    available: u64,
+    used: u64,
+    enabled: bool,
+    last_available: u16,
+}
+
+pub struct RngState {
+    pub status: u8,
+    pub device_features: u64,
+    pub driver_features: u64,
+    feature_select: u32,
+    driver_feature_select: u32,
+    queue_select: u16,
+    queue: Queue,
+    /// Random bytes the device has left to give
+    pub remaining: usize,
+    next: u8,
+    /// Requests handled
+    pub requests: usize,
+}
+
+impl RngState {
+    fn reset(&mut self) {
+        self.status = 0;
+        self.driver_features = 0;
+        self.queue = Queue::default();
+    }
+
+    fn write_status(&mut self, status: u8) {
+        if status == 0 {
+            return self.reset();
+        }
+        self.status = status;
+        if status & STATUS_FEATURES_OK != 0 && self.driver_features & !self.device_features != 0 {
+            self.status &= !STATUS_FEATURES_OK;
+        }
+    }
+
+    fn selected(&mut self) -> Option<&mut Queue> {
+        (self.queue_select == 0).then_some(&mut self.queue)
+    }
+
+    fn read_common(&mut self, offset: usize) -> u32 {
+        match offset {
+            0x04 => (self.device_features >> (self.feature_select * 32).min(63)) as u32,
+            0x12 => 1,
+            0x14 => self.status as u32,
+            0x16 => self.queue_select as u32,
+            0x18 => self.selected().map_or(0, |queue| if queue.size == 0 { MAX_QUEUE_SIZE } else { queue.size }) as u32,
+            0x1C => self.selected().map_or(0, |queue| queue.enabled as u32),
+            0x1E => self.queue_select as u32,
+            _ => 0,
+        }
+    }
+
+    fn write_common(&mut self, offset: usize, value: u32) {
+        let half = |current: u64, high: bool| {
+            if high { (current & 0xFFFF_FFFF) | (value as u64) << 32 } else { (current & !0xFFFF_FFFF) | value as u64 }
+        };
+        match offset {
+            0x00 => self.feature_select = value,
+            0x08 => self.driver_feature_select = value,
+            0x0C => {
+                let high = self.driver_feature_select == 1;
+                self.driver_features = half(self.driver_features, high);
+            }
+            0x14 => self.write_status(value as u8),
+            0x16 => self.queue_select = value as u16,
+            0x18 => {
+                if let Some(queue) = self.selected() {
+                    queue.size = (value as u16).min(MAX_QUEUE_SIZE);
+                }
+            }
+            0x1C => {
+                if let Some(queue) = self.selected() {
+                    queue.enabled = value != 0;
+                }
+            }
+            0x20..=0x37 => {
+                let high = offset % 8 == 4;
+                if let Some(queue) = self.selected() {
+                    let address = match offset & !7 {
+                        0x20 => &mut queue.descriptors,
+                        0x28 => &mut queue.available,
+                        _ => &mut queue.used,
+                    };
+                    *address = half(*address, high);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Fill every buffer the driver made available on the request queue
+    fn process(&mut self) {
+        let queue = self.queue;
+        if !queue.enabled {
+            return;
+        }
+        let mut last_available = queue.last_available;
+        while last_available != peek_u16(queue.available + 2) {
+            let head = peek_u16(queue.available + 4 + (last_available % queue.size) as u64 * 2);
+            let mut written = 0;
+            let mut descriptor = head;
+            loop {
+                let address = queue.descriptors + descriptor as u64 * 16;
+                let (buffer, length) = (peek_u64(address), peek_u32(address + 8));
+                let flags = peek_u16(address + 12);
+                if flags & DESCRIPTOR_WRITE != 0 {
+                    let count = (length as usize).min(self.remaining);
+                    for offset in 0..count {
+                        self.next = self.next.wrapping_add(1);
+                        unsafe { ((buffer + offset as u64) as *mut u8).write_volatile(self.next) }
+                    }
+                    self.remaining -= count;
+                    written += count;
+                }
+                if flags & DESCRIPTOR_NEXT == 0 {
+                    break;
+                }
+                descriptor = peek_u16(address + 14);
+            }
+            self.requests += 1;
+
+            let used_index = peek_u16(queue.used + 2);
+            let entry = queue.used + 4 + (used_index % queue.size) as u64 * 8;
+            poke_u32(entry, head as u32);
+            poke_u32(entry + 4, written as u32);
+            poke_u16(queue.used + 2, used_index.wrapping_add(1));
+            last_available = last_available.wrapping_add(1);
+        }
+        self.queue.last_available = last_available;
+    }
+}
+
+/// Which register structure a view of the device is
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Structure {
+    Common,
+    Notify,
+    Device,
+}
+
+/// An emulated virtio-rng
+#[derive(Clone)]
+pub struct FakeVirtioRng {
+    pub state: Arc<Mutex<RngState>>,
+}
+
+impl FakeVirtioRng {
+    /// A device with `remaining` random bytes to give
+    pub fn new(remaining: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(RngState {
+                status: 0,
+                device_features: FEATURE_VERSION_1,
+                driver_features: 0,
+                feature_select: 0,
+                driver_feature_select: 0,
+                queue_select: 0,
+                queue: Queue::default(),
+                remaining,
+                next: 0,
+                requests: 0,
+            })),
+        }
+    }
+
+    /// The device's register structures, for the driver
+    pub fn transport(&self) -> VirtioPci {
+        let view = |structure| alloc::boxed::Box::new(DeviceView { state: self.state.clone(), structure });
+        VirtioPci::new(view(Structure::Common), view(Structure::Notify), NOTIFY_MULTIPLIER, view(Structure::Device))
+    }
+}
+
+struct DeviceView {
+    state: Arc<Mutex<RngState>>,
+    structure: Structure,
+}
+
+impl DeviceView {
+    fn read(&mut self, offset: usize) -> u32 {
+        let mut state = self.state.lock();
+        match self.structure {
+            Structure::Common => state.read_common(offset),
+            // The device has no configuration
+            Structure::Notify | Structure::Device => 0,
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: u32) {
+        let mut state = self.state.lock();
+        match self.structure {
+            Structure::Common => state.write_common(offset, value),
+            Structure::Notify => {
+                if value == 0 && offset == 0 {
+                    state.process();
+                }
+            }
+            Structure::Device => {}
+        }
+    }
+}
+
+impl Mmio for DeviceView {
+    fn read_u8(&mut self, offset: usize) -> u8 {
+        self.read(offset) as u8
+    }
+
+    fn write_u8(&mut self, offset: usize, value: u8) {
+        self.write(offset, value as u32);
+    }
+
+    fn read_u16(&mut self, offset: usize) -> u16 {
+        self.read(offset) as u16
+    }
+
+    fn write_u16(&mut self, offset: usize, value: u16) {
+        self.write(offset, value as u32);
+    }
+
+    fn read_u32(&mut self, offset: usize) -> u32 {
+        self.read(offset)
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        self.write(offset, value);
+    }
+}
@@ -0,0 +1,242 @@
+#![no_std]
+
+extern crate alloc;
+
+pub mod rng;
+
+pub use kosh_driver::virtio::{transport, virtqueue};
+
+use alloc::{vec, vec::Vec, string::String, boxed::Box};
+use kosh_driver::{
+    KoshDriver, DriverInfo, DriverType, HardwareId, DriverStatus, PowerEvent, DriverFactory,
+    DriverRequest, DriverResponse, DriverCapabilityType, MemoryCapability, HardwareCapability
+};
+use kosh_sync::Mutex;
+use kosh_types::{DriverError, Capability};
+use rng::VirtioRng;
+
+/// Devices the factory takes, by vendor and device ID: the transitional
+/// and the modern virtio-rng-pci
+pub const VIRTIO_RNG_HARDWARE_IDS: [(u32, u32); 2] = [(0x1AF4, 0x1005), (0x1AF4, 0x1044)];
+
+/// Most bytes one read request returns
+pub const MAX_READ: usize = 4096;
+
+/// Bytes handed to the kernel each time it is fed, enough to seed its
+/// pool on their own
+pub const FEED_BYTES: usize = 64;
+
+/// Bits of entropy each byte from the device carries
+const BITS_PER_BYTE: u32 = 8;
+
+/// A hardware random number generator
+pub trait EntropySource: Send {
+    /// Fill the start of `out` with random bytes, returning how many were
+    /// filled; fewer than asked for when the source runs short
+    fn read(&mut self, out: &mut [u8]) -> Result<usize, DriverError>;
+}
+
+impl EntropySource for VirtioRng {
+    fn read(&mut self, out: &mut [u8]) -> Result<usize, DriverError> {
+        Ok(VirtioRng::read(self, out)?)
+    }
+}
+
+/// Entropy source driver
+///
+/// Reads random bytes for clients that ask, and feeds the kernel's pool.
+pub struct RngDriver {
+    source: Option<Box<dyn EntropySource>>,
+    status: DriverStatus,
+}
+
+impl Default for RngDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RngDriver {
+    /// A driver with no device, which refuses reads
+    pub fn new() -> Self {
+        Self { source: None, status: DriverStatus::Uninitialized }
+    }
+
+    /// A driver reading from `source`
+    pub fn with_source(source: Box<dyn EntropySource>) -> Self {
+        Self { source: Some(source), status: DriverStatus::Uninitialized }
+    }
+
+    /// Up to `length` random bytes, as many as the device has now
+    pub fn read(&mut self, length: usize) -> Result<Vec<u8>, DriverError> {
+        let source = self.source.as_mut().ok_or(DriverError::HardwareNotFound)?;
+        let mut bytes = vec![0; length.min(MAX_READ)];
+        let mut filled = 0;
+        while filled < bytes.len() {
+            let count = source.read(&mut bytes[filled..])?;
+            if count == 0 {
+                break;
+            }
+            filled += count;
+        }
+        bytes.truncate(filled);
+        Ok(bytes)
+    }
+
+    /// Hand `FEED_BYTES` from the device to the kernel's pool, returning
+    /// the bits of entropy it counted
+    pub fn feed(&mut self) -> Result<u32, DriverError> {
+        let bytes = self.read(FEED_BYTES)?;
+        if bytes.is_empty() {
+            return Ok(0);
+        }
+        kosh_driver::entropy::add_entropy(&bytes, bytes.len() as u32 * BITS_PER_BYTE)
+            .ok_or(DriverError::PermissionDenied)
+    }
+}
+
+impl KoshDriver for RngDriver {
+    fn init(&mut self, _capabilities: Vec<Capability>) -> Result<(), DriverError> {
+        self.status = DriverStatus::Initializing;
+        if self.source.is_none() {
+            return Err(DriverError::HardwareNotFound);
+        }
+        self.status = DriverStatus::Ready;
+        Ok(())
+    }
+
+    fn handle_request(&mut self, request: DriverRequest) -> Result<DriverResponse, DriverError> {
+        match request {
+            DriverRequest::Initialize => {
+                self.init(Vec::new())?;
+                Ok(DriverResponse::Success)
+            }
+
+            DriverRequest::Read { length, .. } => {
+                Ok(DriverResponse::Data(self.read(length)?))
+            }
+
+            DriverRequest::Query { query_type } => {
+                match query_type {
+                    kosh_driver::QueryType::Status => {
+                        Ok(DriverResponse::Status(self.status))
+                    }
+                    kosh_driver::QueryType::HardwareInfo => {
+                        let info = self.get_driver_info();
+                        Ok(DriverResponse::Info(info))
+                    }
+                    _ => Err(DriverError::InvalidRequest)
+                }
+            }
+
+            _ => Err(DriverError::InvalidRequest)
+        }
+    }
+
+    fn cleanup(&mut self) -> Result<(), DriverError> {
+        self.status = DriverStatus::Uninitialized;
+        Ok(())
+    }
+
+    fn get_required_capabilities(&self) -> Vec<DriverCapabilityType> {
+        let mut capabilities: Vec<DriverCapabilityType> = VIRTIO_RNG_HARDWARE_IDS.iter()
+            .map(|&(vendor_id, device_id)| {
+                DriverCapabilityType::Hardware(HardwareCapability::PciDevice { vendor_id, device_id })
+            })
+            .collect();
+        capabilities.push(DriverCapabilityType::Memory(MemoryCapability::DmaMemory));
+        capabilities
+    }
+
+    fn get_provided_capabilities(&self) -> Vec<DriverCapabilityType> {
+        vec![DriverCapabilityType::Custom(String::from("entropy_source"))]
+    }
+
+    fn get_driver_info(&self) -> DriverInfo {
+        DriverInfo {
+            name: String::from("virtio-rng Driver"),
+            version: String::from("0.1.0"),
+            vendor: String::from("Kosh OS"),
+            description: String::from("Random bytes from virtio entropy devices"),
+            driver_type: DriverType::System,
+            hardware_ids: VIRTIO_RNG_HARDWARE_IDS.iter()
+                .map(|&(vendor_id, device_id)| HardwareId {
+                    vendor_id,
+                    device_id,
+                    subsystem_vendor_id: None,
+                    subsystem_device_id: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn handle_power_event(&mut self, event: PowerEvent) -> Result<(), DriverError> {
+        match event {
+            PowerEvent::Suspend => {
+                self.status = DriverStatus::Suspended;
+                Ok(())
+            }
+            PowerEvent::Resume => {
+                self.status = DriverStatus::Ready;
+                Ok(())
+            }
+            PowerEvent::PowerDown => {
+                self.cleanup()
+            }
+            _ => Ok(())
+        }
+    }
+
+    fn get_status(&self) -> DriverStatus {
+        self.status
+    }
+}
+
+/// Global entropy source driver
+static VIRTIO_RNG_DRIVER: Mutex<Option<RngDriver>> = Mutex::new(None);
+
+/// Initialize the global driver reading from `source`
+pub fn init_virtio_rng_driver(source: Box<dyn EntropySource>) -> Result<(), DriverError> {
+    let mut driver = RngDriver::with_source(source);
+    driver.init(Vec::new())?;
+    *VIRTIO_RNG_DRIVER.lock() = Some(driver);
+    Ok(())
+}
+
+/// Feed the kernel's pool from the global driver, returning the bits of
+/// entropy it counted
+pub fn virtio_rng_feed() -> Result<u32, DriverError> {
+    VIRTIO_RNG_DRIVER.lock().as_mut().ok_or(DriverError::HardwareNotFound)?.feed()
+}
+
+/// Handle a request to the global driver
+pub fn virtio_rng_request(request: DriverRequest) -> Result<DriverResponse, DriverError> {
+    VIRTIO_RNG_DRIVER.lock().as_mut().ok_or(DriverError::HardwareNotFound)?.handle_request(request)
+}
+
+/// Driver factory matching the devices in `VIRTIO_RNG_HARDWARE_IDS`
+///
+/// The driver needs the device's mapped register structures and DMA
+/// memory, which the factory interface cannot pass, so the driver process
+/// creates it with `init_virtio_rng_driver` instead.
+pub struct VirtioRngDriverFactory;
+
+impl DriverFactory for VirtioRngDriverFactory {
+    fn create_driver(&self, _hardware_id: &HardwareId) -> Result<Box<dyn KoshDriver>, DriverError> {
+        Err(DriverError::InvalidRequest)
+    }
+
+    fn can_handle(&self, hardware_id: &HardwareId) -> bool {
+        VIRTIO_RNG_HARDWARE_IDS.contains(&(hardware_id.vendor_id, hardware_id.device_id))
+    }
+
+    fn get_driver_type(&self) -> DriverType {
+        DriverType::System
+    }
+}
+
+#[cfg(test)]
+mod fixtures;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,99 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use kosh_driver::hal::{map_mmio, HardwareMmio, HardwarePortIo};
+use kosh_driver::pci::{self, ConfigSpace, PortConfigSpace};
+use kosh_driver::report;
+use kosh_driver::time::{sleep_us, PeriodicTimer};
+use kosh_driver::{DriverMetadataRecord, DriverSignatureRecord, DriverType};
+use kosh_virtio_rng_driver::rng::VirtioRng;
+use kosh_virtio_rng_driver::transport::{Structure, VirtioCapabilities, VirtioPci};
+use kosh_virtio_rng_driver::virtqueue::KernelDma;
+use kosh_virtio_rng_driver::{init_virtio_rng_driver, virtio_rng_feed, VIRTIO_RNG_HARDWARE_IDS};
+
+/// Memory space and bus master enable in the command register
+const COMMAND_MEMORY_AND_BUS_MASTER: u32 = 0x6;
+
+/// Time between feeds of the kernel's pool once it is seeded
+const FEED_INTERVAL_US: u64 = 10_000_000;
+
+/// Read by the driver manager: QEMU virtio-rng-pci, transitional and
+/// modern. The device is found through the configuration ports and its
+/// BARs are granted once the manager binds it.
+#[used]
+#[link_section = ".kosh_driver"]
+static DRIVER_METADATA: DriverMetadataRecord = DriverMetadataRecord::new("virtio-rng", "0.1.0", DriverType::System)
+    .with_hardware_id(0x1AF4, 0x1005)
+    .with_hardware_id(0x1AF4, 0x1044)
+    .requires_io_ports(0xCF8, 0xCFF)
+    .requires_pci_device(0x1AF4, 0x1005)
+    .requires_pci_device(0x1AF4, 0x1044)
+    .requires_dma_memory();
+
+/// Filled in when the driver is signed
+#[used]
+#[link_section = ".kosh_signature"]
+static DRIVER_SIGNATURE: DriverSignatureRecord = DriverSignatureRecord::UNSIGNED;
+
+/// Find the first virtio-rng, enable it and map its register structures
+fn find_device() -> Option<VirtioPci> {
+    let mut config = PortConfigSpace::new(HardwarePortIo);
+    let device = pci::enumerate(&mut config)
+        .into_iter()
+        .find(|device| VIRTIO_RNG_HARDWARE_IDS.contains(&(device.vendor_id as u32, device.device_id as u32)))?;
+    let capabilities = VirtioCapabilities::read(&mut config, device.address)?;
+    let bars = pci::memory_bars(&mut config, device.address);
+
+    // Only touch the command half: status bits are cleared by writing 1
+    let command = config.read_u32(device.address, pci::COMMAND_STATUS) & 0xFFFF;
+    config.write_u32(device.address, pci::COMMAND_STATUS, command | COMMAND_MEMORY_AND_BUS_MASTER);
+
+    let map = |structure: Structure| -> Option<Box<HardwareMmio>> {
+        let bar = bars.iter().find(|bar| bar.index == structure.bar)?;
+        map_mmio(bar.address + structure.offset as u64, structure.length as usize).ok().map(Box::new)
+    };
+    Some(VirtioPci::new(
+        map(capabilities.common)?,
+        map(capabilities.notify)?,
+        capabilities.notify_multiplier,
+        map(capabilities.device)?,
+    ))
+}
+
+/// Entry point for the virtio-rng driver process
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    if let Some(transport) = find_device() {
+        let rng = match VirtioRng::new(transport, Box::new(KernelDma::default())) {
+            Ok(rng) => rng,
+            Err(e) => report::fail(format_args!("Failed to set up virtio-rng: {:?}", e)),
+        };
+        if let Err(e) = init_virtio_rng_driver(Box::new(rng)) {
+            report::fail(format_args!("Failed to initialize virtio-rng driver: {:?}", e));
+        }
+    }
+
+    // Main driver loop: the first feed seeds the kernel's pool if nothing
+    // else has, and later ones keep fresh entropy going into it
+    let timer = PeriodicTimer::new(FEED_INTERVAL_US);
+    loop {
+        let _ = virtio_rng_feed();
+
+        match &timer {
+            Some(timer) => {
+                timer.wait();
+            }
+            None => sleep_us(FEED_INTERVAL_US),
+        }
+    }
+}
+
+/// Panic handler for the driver (only in non-test builds)
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    report::report_panic("virtio-rng", info)
+}
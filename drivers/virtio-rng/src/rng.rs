@@ -0,0 +1,99 @@
+//! virtio entropy device
+//!
+//! The device has a single request queue. The driver hands it buffers
+//! and the device hands each back holding as many random bytes as it
+//! had, which may be fewer than the buffer holds. The bytes come from the
+//! host's generator, so each carries a full eight bits of entropy.
+
+use alloc::boxed::Box;
+use crate::transport::{VirtioError, VirtioPci};
+use crate::virtqueue::{queue_layout, DmaMemory, DmaRegion, Segment, Virtqueue, DMA_PAGE_SIZE, MAX_QUEUE_SIZE};
+
+/// Virtio device type of entropy sources
+pub const VIRTIO_DEVICE_ENTROPY: u16 = 4;
+
+const REQUEST_QUEUE: u16 = 0;
+
+/// Most bytes one request asks for: the page they are read through
+pub const MAX_REQUEST: usize = DMA_PAGE_SIZE;
+
+/// Used ring polls before giving up on a request
+const POLL_LIMIT: usize = 1_000_000;
+
+/// A virtio-rng device, set up and ready for requests
+pub struct VirtioRng {
+    transport: VirtioPci,
+    dma: Box<dyn DmaMemory>,
+    requests: Virtqueue,
+    /// Page the device writes random bytes into
+    buffer: DmaRegion,
+}
+
+impl VirtioRng {
+    /// Reset the device and set it up: no features beyond virtio 1.0,
+    /// then the request queue
+    pub fn new(mut transport: VirtioPci, mut dma: Box<dyn DmaMemory>) -> Result<Self, VirtioError> {
+        transport.reset()?;
+        transport.negotiate(0)?;
+
+        let size = transport.max_queue_size(REQUEST_QUEUE).min(MAX_QUEUE_SIZE);
+        if size == 0 {
+            transport.fail();
+            return Err(VirtioError::NoQueue);
+        }
+        let size = 1 << size.ilog2();
+        let queue_memory = dma.allocate(queue_layout(size).3).ok_or(VirtioError::NoMemory)?;
+        let buffer = match dma.allocate(MAX_REQUEST) {
+            Some(buffer) => buffer,
+            None => {
+                dma.free(queue_memory);
+                return Err(VirtioError::NoMemory);
+            }
+        };
+        let requests = Virtqueue::new(queue_memory, size);
+        if let Err(error) = transport.setup_queue(REQUEST_QUEUE, &requests) {
+            transport.fail();
+            dma.free(queue_memory);
+            dma.free(buffer);
+            return Err(error);
+        }
+        transport.driver_ok();
+
+        Ok(Self { transport, dma, requests, buffer })
+    }
+
+    /// Fill the start of `out` with random bytes, up to `MAX_REQUEST` of
+    /// them, returning how many the device gave
+    pub fn read(&mut self, out: &mut [u8]) -> Result<usize, VirtioError> {
+        let length = out.len().min(MAX_REQUEST);
+        if length == 0 {
+            return Ok(0);
+        }
+        let segment = Segment { address: self.buffer.phys, length: length as u32, device_writes: true };
+        let head = self.requests.push(&[segment]).ok_or(VirtioError::NoMemory)?;
+        self.transport.notify(REQUEST_QUEUE);
+
+        for _ in 0..POLL_LIMIT {
+            if let Some((used, written)) = self.requests.pop_used() {
+                // A request that timed out earlier may complete late
+                if used != head {
+                    continue;
+                }
+                let written = (written as usize).min(length);
+                out[..written].copy_from_slice(&self.buffer.read_bytes(0, written));
+                return Ok(written);
+            }
+            core::hint::spin_loop();
+        }
+        Err(VirtioError::Timeout)
+    }
+}
+
+impl Drop for VirtioRng {
+    /// Stop the device before its queue and buffer are given back
+    fn drop(&mut self) {
+        let _ = self.transport.reset();
+        self.dma.free(self.requests.memory());
+        self.dma.free(self.buffer);
+    }
+}
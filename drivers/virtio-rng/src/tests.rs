@@ -0,0 +1,80 @@
+use super::*;
+use crate::fixtures::{FakeVirtioRng, HeapDma};
+use crate::rng::MAX_REQUEST;
+use crate::transport::{STATUS_DRIVER_OK, STATUS_FAILED};
+use kosh_driver::QueryType;
+
+fn rng(fake: &FakeVirtioRng, dma: &HeapDma) -> VirtioRng {
+    VirtioRng::new(fake.transport(), Box::new(dma.clone())).unwrap()
+}
+
+#[test]
+fn test_device_reads() {
+    let fake = FakeVirtioRng::new(10);
+    let dma = HeapDma::default();
+    let mut rng = rng(&fake, &dma);
+    assert_eq!(fake.state.lock().status & (STATUS_DRIVER_OK | STATUS_FAILED), STATUS_DRIVER_OK);
+    assert_eq!(dma.live(), 2);
+
+    let mut bytes = [0u8; 8];
+    assert_eq!(rng.read(&mut bytes), Ok(8));
+    assert_eq!(bytes, [1, 2, 3, 4, 5, 6, 7, 8]);
+    // The device runs short: only what it had is returned
+    assert_eq!(rng.read(&mut bytes), Ok(2));
+    assert_eq!(bytes[..2], [9, 10]);
+    assert_eq!(rng.read(&mut []), Ok(0));
+    assert_eq!(fake.state.lock().requests, 2);
+
+    // Requests are capped at the buffer page
+    fake.state.lock().remaining = usize::MAX;
+    let mut large = vec![0u8; MAX_REQUEST + 16];
+    assert_eq!(rng.read(&mut large), Ok(MAX_REQUEST));
+
+    drop(rng);
+    assert_eq!(fake.state.lock().status, 0);
+    assert_eq!(dma.live(), 0);
+}
+
+#[test]
+fn test_driver_requests() {
+    let fake = FakeVirtioRng::new(6000);
+    let dma = HeapDma::default();
+    let mut driver = RngDriver::with_source(Box::new(rng(&fake, &dma)));
+    assert!(driver.init(Vec::new()).is_ok());
+
+    match driver.handle_request(DriverRequest::Read { offset: 0, length: 16 }) {
+        Ok(DriverResponse::Data(bytes)) => assert_eq!(bytes, (1..=16).collect::<Vec<u8>>()),
+        _ => panic!("expected random bytes"),
+    }
+    // Long reads take several requests and stop at `MAX_READ`
+    match driver.handle_request(DriverRequest::Read { offset: 0, length: 2 * MAX_READ }) {
+        Ok(DriverResponse::Data(bytes)) => assert_eq!(bytes.len(), MAX_READ),
+        _ => panic!("expected random bytes"),
+    }
+    // The device ran dry partway through
+    match driver.handle_request(DriverRequest::Read { offset: 0, length: MAX_READ }) {
+        Ok(DriverResponse::Data(bytes)) => assert_eq!(bytes.len(), 6000 - 16 - MAX_READ),
+        _ => panic!("expected random bytes"),
+    }
+    assert!(matches!(
+        driver.handle_request(DriverRequest::Query { query_type: QueryType::Status }),
+        Ok(DriverResponse::Status(DriverStatus::Ready))
+    ));
+    assert!(matches!(
+        driver.handle_request(DriverRequest::Write { offset: 0, data: vec![0] }),
+        Err(DriverError::InvalidRequest)
+    ));
+
+    drop(driver);
+    assert_eq!(dma.live(), 0);
+
+    let mut unbound = RngDriver::new();
+    assert!(matches!(unbound.init(Vec::new()), Err(DriverError::HardwareNotFound)));
+    assert!(matches!(unbound.read(8), Err(DriverError::HardwareNotFound)));
+    assert!(!VirtioRngDriverFactory.can_handle(&HardwareId {
+        vendor_id: 0x1AF4,
+        device_id: 0x1050,
+        subsystem_vendor_id: None,
+        subsystem_device_id: None,
+    }));
+}
@@ -1,7 +1,12 @@
 fn main() {
-    // Use our custom linker script
-    println!("cargo:rustc-link-arg=-Tkernel/linker.ld");
+    // Use our custom linker script; RISC-V is linked where its firmware jumps
+    let script = match std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() {
+        Ok("riscv64") => "linker-riscv64.ld",
+        _ => "linker.ld",
+    };
+    println!("cargo:rustc-link-arg=-Tkernel/{}", script);
     
-    // Rerun if the linker script changes
+    // Rerun if the linker scripts change
     println!("cargo:rerun-if-changed=linker.ld");
-}
\ No newline at end of file
+    println!("cargo:rerun-if-changed=linker-riscv64.ld");
+}
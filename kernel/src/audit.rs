@@ -0,0 +1,189 @@
+//! Security audit log
+//!
+//! Events that matter to the security model are kept apart from the kernel
+//! log, so a chatty subsystem cannot push them out: capability checks that
+//! failed, system calls refused with `PermissionDenied`, drivers
+//! registering and unregistering, and processes killed by a signal. They
+//! go into a ring buffer of fixed-size records, the oldest dropped when it
+//! is full. Every record is numbered, so a reader can ask for the records
+//! after the last one it saw and tell from a gap that some were lost.
+//!
+//! `SYS_AUDIT` reads the log, with read access to the "audit" system
+//! resource; the shell's `audit` command shows it.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use spin::Mutex;
+use crate::ipc::capability::{CapabilityType, ResourceId};
+use crate::process::ProcessId;
+
+/// Records the ring buffer holds
+pub const AUDIT_CAPACITY: usize = 256;
+
+/// Bytes of text a record carries
+pub const DETAIL_LEN: usize = 48;
+
+/// `AuditRecord::kind` values
+pub const AUDIT_CAPABILITY_DENIED: u32 = 1;
+pub const AUDIT_SYSCALL_DENIED: u32 = 2;
+pub const AUDIT_DRIVER_LOADED: u32 = 3;
+pub const AUDIT_DRIVER_UNLOADED: u32 = 4;
+pub const AUDIT_PROCESS_KILLED: u32 = 5;
+
+/// One event, as `SYS_AUDIT` returns it
+///
+/// The layout is shared with `kosh_posix::audit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct AuditRecord {
+    /// Counts up from 1 across the whole log
+    pub sequence: u64,
+    /// Milliseconds since boot
+    pub time_ms: u64,
+    pub kind: u32,
+    /// Process the event concerns
+    pub process: u32,
+    /// Capability type, system call number, driver domain or signal
+    pub value: u64,
+    /// Capability and resource, or system call name; UTF-8 padded with
+    /// NULs
+    pub detail: [u8; DETAIL_LEN],
+}
+
+/// What happened, as code that records an event describes it
+#[derive(Debug, Clone, Copy)]
+pub enum AuditEvent<'a> {
+    /// A capability check failed
+    CapabilityDenied { capability_type: CapabilityType, resource: &'a ResourceId },
+    /// A system call failed with `PermissionDenied`
+    SyscallDenied { syscall: u64, name: &'a str },
+    /// A process registered as a driver, in DMA domain `domain`
+    DriverLoaded { domain: u64 },
+    DriverUnloaded { driver: u64 },
+    /// A process was terminated by `signal`
+    ProcessKilled { signal: u32 },
+}
+
+/// Writes into a record's detail, cutting what does not fit
+struct DetailWriter {
+    detail: [u8; DETAIL_LEN],
+    len: usize,
+}
+
+impl Write for DetailWriter {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        for &byte in text.as_bytes() {
+            if self.len == DETAIL_LEN {
+                break;
+            }
+            self.detail[self.len] = byte;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+struct AuditLog {
+    records: VecDeque<AuditRecord>,
+    next_sequence: u64,
+}
+
+impl AuditLog {
+    const fn new() -> Self {
+        Self { records: VecDeque::new(), next_sequence: 1 }
+    }
+
+    fn push(&mut self, mut record: AuditRecord) {
+        if self.records.len() == AUDIT_CAPACITY {
+            self.records.pop_front();
+        }
+        record.sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.records.push_back(record);
+    }
+
+    /// Up to `max` records numbered after `since`, oldest first
+    fn after(&self, since: u64, max: usize) -> Vec<AuditRecord> {
+        self.records.iter().filter(|record| record.sequence > since).take(max).copied().collect()
+    }
+}
+
+static LOG: Mutex<AuditLog> = Mutex::new(AuditLog::new());
+
+/// Record an event concerning `process`
+pub fn record(process: ProcessId, event: AuditEvent) {
+    let mut detail = DetailWriter { detail: [0; DETAIL_LEN], len: 0 };
+    let (kind, value) = match event {
+        AuditEvent::CapabilityDenied { capability_type, resource } => {
+            let _ = write!(detail, "{} {}", capability_type, resource);
+            (AUDIT_CAPABILITY_DENIED, capability_type.to_raw())
+        }
+        AuditEvent::SyscallDenied { syscall, name } => {
+            let _ = detail.write_str(name);
+            (AUDIT_SYSCALL_DENIED, syscall)
+        }
+        AuditEvent::DriverLoaded { domain } => (AUDIT_DRIVER_LOADED, domain),
+        AuditEvent::DriverUnloaded { driver } => (AUDIT_DRIVER_UNLOADED, driver),
+        AuditEvent::ProcessKilled { signal } => (AUDIT_PROCESS_KILLED, signal as u64),
+    };
+    LOG.lock().push(AuditRecord {
+        sequence: 0,
+        time_ms: crate::monitor::uptime_ms(),
+        kind,
+        process: process.0,
+        value,
+        detail: detail.detail,
+    });
+}
+
+/// Up to `max` records numbered after `since`, oldest first
+pub fn read(since: u64, max: usize) -> Vec<AuditRecord> {
+    LOG.lock().after(since, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_with(kind: u32) -> AuditRecord {
+        AuditRecord { sequence: 0, time_ms: 0, kind, process: 0, value: 0, detail: [0; DETAIL_LEN] }
+    }
+
+    #[test_case]
+    fn test_ring_drops_the_oldest_records() {
+        let mut log = AuditLog::new();
+        for _ in 0..AUDIT_CAPACITY + 3 {
+            log.push(record_with(AUDIT_SYSCALL_DENIED));
+        }
+        let records = log.after(0, usize::MAX);
+        assert_eq!(records.len(), AUDIT_CAPACITY);
+        assert_eq!(records[0].sequence, 4);
+        assert_eq!(records.last().unwrap().sequence, AUDIT_CAPACITY as u64 + 3);
+
+        let newest = log.after(AUDIT_CAPACITY as u64 + 1, usize::MAX);
+        assert_eq!(newest.len(), 2);
+        assert_eq!(log.after(0, 5).len(), 5);
+    }
+
+    #[test_case]
+    fn test_events_are_recorded_with_their_detail() {
+        let process = ProcessId::new(9740);
+        let since = LOG.lock().next_sequence - 1;
+        record(process, AuditEvent::SyscallDenied { syscall: 59, name: "klog" });
+        let resource = ResourceId::System("a-resource-name-far-longer-than-the-detail-field".into());
+        record(process, AuditEvent::CapabilityDenied { capability_type: CapabilityType::Write, resource: &resource });
+
+        let records: Vec<AuditRecord> = read(since, usize::MAX).into_iter()
+            .filter(|record| record.process == process.0)
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].kind, AUDIT_SYSCALL_DENIED);
+        assert_eq!(records[0].value, 59);
+        assert_eq!(&records[0].detail[..5], b"klog\0");
+        assert_eq!(records[1].kind, AUDIT_CAPABILITY_DENIED);
+        assert_eq!(records[1].value, CapabilityType::Write.to_raw());
+        assert_eq!(&records[1].detail[..6], b"Write ");
+        assert!(records[1].detail.iter().all(|&byte| byte != 0));
+    }
+}
@@ -0,0 +1,96 @@
+//! Backtraces from frame pointers
+//!
+//! The kernel is built with frame pointers, so every function's frame
+//! starts with the caller's frame pointer and its return address. Walking
+//! that chain up from a frame pointer gives the calls that led there,
+//! without unwinding tables. Addresses come out raw; `addr2line -e` on the
+//! kernel image turns them into source lines.
+//!
+//! The walk stops at anything that does not look like a frame further up
+//! the same stack, and reads no word whose page is unmapped, so a
+//! corrupted chain ends the backtrace rather than faulting again.
+
+use crate::memory::vmm::{walk_active, VirtualAddress};
+
+/// Most frames a backtrace follows
+pub const MAX_FRAMES: usize = 32;
+
+/// One call on the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub frame_pointer: u64,
+    pub return_address: u64,
+}
+
+/// Frame pointer of the calling function
+#[inline(always)]
+pub fn frame_pointer() -> u64 {
+    let rbp: u64;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+    rbp
+}
+
+/// The frames above the one `frame_pointer` points at, innermost first
+pub fn frames(frame_pointer: u64) -> Frames {
+    Frames { next: frame_pointer, remaining: MAX_FRAMES }
+}
+
+pub struct Frames {
+    next: u64,
+    remaining: usize,
+}
+
+/// Read a word of the chain, if its page is mapped
+fn read_word(address: u64) -> Option<u64> {
+    walk_active(VirtualAddress::new(address as usize)).physical?;
+    Some(unsafe { core::ptr::read_volatile(address as *const u64) })
+}
+
+impl Iterator for Frames {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let frame_pointer = self.next;
+        if self.remaining == 0 || frame_pointer == 0 || frame_pointer % 8 != 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let caller = read_word(frame_pointer)?;
+        let return_address = read_word(frame_pointer.checked_add(8)?)?;
+        if return_address == 0 {
+            return None;
+        }
+        // Callers' frames are further up the stack; anything else is not
+        // a chain to follow
+        self.next = if caller > frame_pointer { caller } else { 0 };
+        Some(Frame { frame_pointer, return_address })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test_case]
+    fn test_frames_follow_chain() {
+        // Three frames, the outermost ending the chain with a null caller
+        let mut stack = [0u64; 6];
+        let base = stack.as_ptr() as u64;
+        stack[0] = base + 16;
+        stack[1] = 0x1111;
+        stack[2] = base + 32;
+        stack[3] = 0x2222;
+        stack[4] = 0;
+        stack[5] = 0x3333;
+
+        let addresses: Vec<u64> = frames(base).map(|frame| frame.return_address).collect();
+        assert_eq!(addresses, [0x1111, 0x2222, 0x3333]);
+
+        // A caller below the frame is a broken chain
+        stack[2] = base;
+        assert_eq!(frames(base).count(), 2);
+        // As is a misaligned frame pointer
+        assert_eq!(frames(base + 4).count(), 0);
+    }
+}
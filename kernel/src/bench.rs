@@ -0,0 +1,160 @@
+//! In-kernel micro-benchmarks
+//!
+//! Built with the `bench` feature, the kernel times the pieces of its hot
+//! paths once it has booted and before it starts running processes: the
+//! system call dispatcher, building, queueing and dequeueing IPC messages
+//! of several payload sizes, and the register and address space part of a
+//! context switch. Each figure is logged as percentiles of per-operation
+//! nanoseconds, so a change to one of those paths can be compared against
+//! the kernel before it. `userspace/bench` measures the same paths end to
+//! end from user mode, traps and scheduling included.
+//!
+//! A sample times a batch of operations and divides, which keeps the cost
+//! of reading the clock out of the figures on a slow clock source.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::ipc::message::{create_message, MessageData, MessageType};
+use crate::ipc::queue::MessageQueue;
+use crate::process::context::{CpuContext, TrapFrame};
+use crate::process::ProcessId;
+use crate::syscall::numbers::SYS_GETPID;
+use crate::time::monotonic_ns;
+
+/// Samples taken for each benchmark
+const SAMPLES: usize = 1000;
+
+/// Operations timed together in one sample
+const BATCH: u64 = 16;
+
+/// IPC payload sizes measured, in bytes
+const PAYLOAD_SIZES: [usize; 5] = [0, 64, 512, 1024, 4096];
+
+/// Percentiles of a set of samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub min: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+    pub mean: u64,
+}
+
+impl Summary {
+    /// Summarize `samples`, which are sorted in place
+    pub fn of(samples: &mut [u64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let total: u128 = samples.iter().map(|&sample| sample as u128).sum();
+        Some(Self {
+            min: samples[0],
+            p50: percentile(samples, 50),
+            p90: percentile(samples, 90),
+            p99: percentile(samples, 99),
+            max: samples[samples.len() - 1],
+            mean: (total / samples.len() as u128) as u64,
+        })
+    }
+}
+
+/// Nearest-rank percentile of sorted, non-empty `samples`
+fn percentile(samples: &[u64], percent: usize) -> u64 {
+    let rank = (samples.len() * percent).div_ceil(100).max(1);
+    samples[rank - 1]
+}
+
+/// Time `SAMPLES` batches of `operation`, in nanoseconds per operation
+fn measure(mut operation: impl FnMut()) -> Summary {
+    // One batch first, so the figures start with warm caches
+    for _ in 0..BATCH {
+        operation();
+    }
+    let mut samples = vec![0; SAMPLES];
+    for sample in samples.iter_mut() {
+        let start = monotonic_ns();
+        for _ in 0..BATCH {
+            operation();
+        }
+        *sample = monotonic_ns().wrapping_sub(start) / BATCH;
+    }
+    Summary::of(&mut samples).expect("SAMPLES is not zero")
+}
+
+fn report(name: &str, summary: &Summary) {
+    log::info!(
+        "bench {:<22} min {:>6} p50 {:>6} p90 {:>6} p99 {:>6} max {:>8} mean {:>6} ns",
+        name, summary.min, summary.p50, summary.p90, summary.p99, summary.max, summary.mean
+    );
+}
+
+/// Run every benchmark and log the results
+pub fn run() {
+    log::info!("Running kernel benchmarks, {} samples of {} operations each", SAMPLES, BATCH);
+
+    report("syscall dispatch", &measure(|| {
+        let _ = core::hint::black_box(crate::syscall::dispatcher::dispatch_syscall(
+            ProcessId::KERNEL,
+            SYS_GETPID,
+            [0; 6],
+        ));
+    }));
+
+    let mut queue = MessageQueue::new(ProcessId::KERNEL);
+    for size in PAYLOAD_SIZES {
+        let payload: Vec<u8> = vec![0xA5; size];
+        let summary = measure(|| {
+            let message = create_message(
+                ProcessId::KERNEL,
+                ProcessId::KERNEL,
+                MessageType::ServiceRequest,
+                MessageData::Bytes(payload.clone()),
+            );
+            let _ = queue.enqueue(message);
+            let _ = core::hint::black_box(queue.dequeue());
+        });
+        report(&alloc::format!("ipc {} bytes", size), &summary);
+        if size > 0 && summary.mean > 0 {
+            // Bytes per nanosecond is thousands of megabytes per second
+            log::info!("bench ipc {} bytes: {} MB/s", size, size as u64 * 1000 / summary.mean);
+        }
+    }
+
+    let mut frame = TrapFrame::default();
+    let mut context = CpuContext::default();
+    report("context switch", &measure(|| {
+        context.save_from_frame(core::hint::black_box(&frame));
+        context.load_into_frame(core::hint::black_box(&mut frame));
+        reload_address_space();
+    }));
+}
+
+/// Write CR3 back as it is, paying the TLB flush a switch between address
+/// spaces pays
+#[cfg(target_arch = "x86_64")]
+fn reload_address_space() {
+    use x86_64::registers::control::Cr3;
+    let (root, flags) = Cr3::read();
+    unsafe { Cr3::write(root, flags) };
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn reload_address_space() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_summary_percentiles() {
+        let mut samples: Vec<u64> = (1..=100).rev().collect();
+        let summary = Summary::of(&mut samples).unwrap();
+        assert_eq!(summary, Summary { min: 1, p50: 50, p90: 90, p99: 99, max: 100, mean: 50 });
+
+        let summary = Summary::of(&mut [7]).unwrap();
+        assert_eq!((summary.p50, summary.p99, summary.mean), (7, 7, 7));
+        assert!(Summary::of(&mut []).is_none());
+    }
+}
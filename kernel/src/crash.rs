@@ -0,0 +1,204 @@
+//! Crash reports that survive a warm reboot
+//!
+//! A panic writes a report into the persistent region the frame allocator
+//! keeps back at the top of memory: the panic message and where it was
+//! raised, the backtrace, and as many of the newest kernel log lines as
+//! still fit. A warm reboot leaves RAM as it was, so the next boot finds
+//! the report there, logs that the previous boot panicked and keeps a copy
+//! that `SYS_KLOG` hands out for `dmesg -p`. The region is then cleared
+//! for the next panic.
+//!
+//! A report is sealed with a magic number and a checksum once it is
+//! complete, so a panic cut short, or whatever the firmware left in the
+//! region after a cold boot, is never taken for one. `crashdump=off` on
+//! the kernel command line stops panics writing reports.
+
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::Mutex;
+use crate::memory::physical::PERSISTENT_REGION_SIZE;
+use crate::memory::vmm::kernel_layout::PHYSICAL_MEMORY_OFFSET;
+
+/// "KOSHCRSH"
+const MAGIC: u64 = u64::from_le_bytes(*b"KOSHCRSH");
+
+/// Magic, text length and checksum, ahead of the text
+const HEADER_SIZE: usize = 16;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Kernel address of the region; zero until `init` or once a panic has
+/// taken it
+static REGION: AtomicUsize = AtomicUsize::new(0);
+
+/// Report the previous boot left
+static PREVIOUS: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// Stop panics from writing reports
+pub fn disable(reason: &str) {
+    ENABLED.store(false, Ordering::Release);
+    log::info!("{}: crash reports disabled", reason);
+}
+
+/// Take over the persistent region: keep the report the previous boot
+/// left in it, if any, and clear it
+///
+/// Needs the heap for the copy.
+pub fn init() {
+    let Some(physical) = crate::memory::physical::persistent_region() else {
+        log::warn!("No room for crash reports at the top of memory");
+        return;
+    };
+    let address = PHYSICAL_MEMORY_OFFSET.as_usize() + physical;
+    let region = unsafe { core::slice::from_raw_parts_mut(address as *mut u8, PERSISTENT_REGION_SIZE) };
+
+    if let Some(report) = open(region) {
+        let summary = report.split(|&byte| byte == b'\n').next().unwrap_or_default();
+        log::warn!(
+            "The previous boot {}; `dmesg -p` shows its crash report",
+            core::str::from_utf8(summary).unwrap_or("panicked")
+        );
+        *PREVIOUS.lock() = Some(report.to_vec());
+    }
+    clear(region);
+    REGION.store(address, Ordering::Release);
+}
+
+/// Write the report for a panic, once
+///
+/// Called from the panic handler with interrupts off. A panic inside it
+/// finds the region taken and writes nothing more.
+pub fn record_panic(info: &PanicInfo, frame_pointer: u64) {
+    if !ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+    let address = REGION.swap(0, Ordering::AcqRel);
+    if address == 0 {
+        return;
+    }
+    let region = unsafe { core::slice::from_raw_parts_mut(address as *mut u8, PERSISTENT_REGION_SIZE) };
+
+    let mut report = ReportWriter::new(&mut region[HEADER_SIZE..]);
+    let uptime_ms = crate::time::monotonic_ms();
+    let _ = match info.location() {
+        Some(location) => writeln!(report, "panicked at {}:{}: {}", location.file(), location.line(), info.message()),
+        None => writeln!(report, "panicked: {}", info.message()),
+    };
+    let _ = writeln!(report, "Uptime: {}.{:03} s", uptime_ms / 1000, uptime_ms % 1000);
+    let _ = writeln!(report, "Backtrace:");
+    for frame in crate::backtrace::frames(frame_pointer) {
+        let _ = writeln!(report, "  0x{:016x}", frame.return_address);
+    }
+    let _ = writeln!(report, "Kernel log:");
+    let len = report.len;
+    let len = len + crate::klog::copy_newest(&mut region[HEADER_SIZE + len..]);
+
+    seal(region, len);
+    crate::serial_println!("Crash report saved ({} bytes)", len);
+}
+
+/// Bytes in the report the previous boot left; zero without one
+pub fn previous_len() -> usize {
+    PREVIOUS.lock().as_ref().map_or(0, Vec::len)
+}
+
+/// The report the previous boot left
+pub fn previous() -> Option<Vec<u8>> {
+    PREVIOUS.lock().clone()
+}
+
+/// Drop the report the previous boot left
+pub fn discard_previous() {
+    *PREVIOUS.lock() = None;
+}
+
+/// Report text cut off at the end of the region
+struct ReportWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> ReportWriter<'a> {
+    fn new(buffer: &'a mut [u8]) -> Self {
+        Self { buffer, len: 0 }
+    }
+}
+
+impl Write for ReportWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let take = s.len().min(self.buffer.len() - self.len);
+        self.buffer[self.len..self.len + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+        Ok(())
+    }
+}
+
+/// FNV-1a over the text
+fn checksum(text: &[u8]) -> u32 {
+    text.iter().fold(0x811C_9DC5, |hash: u32, &byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
+
+/// Mark the `len` bytes of text after the header as a complete report
+fn seal(region: &mut [u8], len: usize) {
+    let text_checksum = checksum(&region[HEADER_SIZE..HEADER_SIZE + len]);
+    region[8..12].copy_from_slice(&(len as u32).to_le_bytes());
+    region[12..16].copy_from_slice(&text_checksum.to_le_bytes());
+    // The magic goes last, so a report cut short never has it
+    unsafe { core::ptr::write_volatile(region.as_mut_ptr() as *mut u64, MAGIC) };
+}
+
+/// Text of the report in `region`, if it holds a complete one
+fn open(region: &[u8]) -> Option<&[u8]> {
+    let word = |offset: usize| u32::from_le_bytes([region[offset], region[offset + 1], region[offset + 2], region[offset + 3]]);
+    if region.len() < HEADER_SIZE || (word(0) as u64 | (word(4) as u64) << 32) != MAGIC {
+        return None;
+    }
+    let len = word(8) as usize;
+    let text = region.get(HEADER_SIZE..HEADER_SIZE.checked_add(len)?)?;
+    (checksum(text) == word(12)).then_some(text)
+}
+
+fn clear(region: &mut [u8]) {
+    region[..HEADER_SIZE].fill(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test_case]
+    fn test_seal_and_open() {
+        let mut region = vec![0u8; 256];
+        assert_eq!(open(&region), None);
+
+        let mut report = ReportWriter::new(&mut region[HEADER_SIZE..]);
+        let _ = writeln!(report, "panicked at src/main.rs:1: test");
+        let len = report.len;
+        seal(&mut region, len);
+        assert_eq!(open(&region), Some(&b"panicked at src/main.rs:1: test\n"[..]));
+
+        // A changed byte or a length past the region is no report
+        region[HEADER_SIZE] ^= 1;
+        assert_eq!(open(&region), None);
+        region[HEADER_SIZE] ^= 1;
+        region[8..12].copy_from_slice(&1000u32.to_le_bytes());
+        assert_eq!(open(&region), None);
+
+        seal(&mut region, len);
+        clear(&mut region);
+        assert_eq!(open(&region), None);
+    }
+
+    #[test_case]
+    fn test_report_writer_cut() {
+        let mut buffer = [0u8; 8];
+        let mut report = ReportWriter::new(&mut buffer);
+        let _ = write!(report, "0123456789");
+        assert_eq!(report.len, 8);
+        let _ = write!(report, "more");
+        assert_eq!(&buffer, b"01234567");
+    }
+}
@@ -0,0 +1,41 @@
+//! Userspace integration test boots
+//!
+//! `integration_tests=1` on the kernel command line of a debug kernel boots
+//! it for the userspace integration tests. Init asks with
+//! `SYS_TEST_CONTROL` whether this is such a boot and, if so, starts the
+//! test orchestrator in place of the shell. The orchestrator drives
+//! fs-service, driver-manager and IPC through real system calls and ends
+//! the run with `SYS_TEST_CONTROL` too, which leaves QEMU through the
+//! isa-debug-exit device with the codes the kernel's own test runner uses,
+//! so `scripts/run-integration-tests.sh` reads the outcome the same way.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::process::ProcessId;
+use crate::{exit_qemu, QemuExitCode};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Release);
+    log::info!("Integration test boot: init runs the test orchestrator");
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// End the run for `process`, which reported `status`, 0 when every test
+/// passed
+///
+/// Returns only when QEMU has no exit device to leave through.
+pub fn finish(process: ProcessId, status: i32) {
+    let code = if status == 0 {
+        log::info!("Integration tests passed");
+        QemuExitCode::Success
+    } else {
+        log::error!("Integration tests failed with status {} (reported by process {})", status, process.0);
+        QemuExitCode::Failed
+    };
+    exit_qemu(code);
+    log::warn!("No QEMU exit device; the integration test run stays up");
+}
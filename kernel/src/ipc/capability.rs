@@ -1,10 +1,15 @@
+use alloc::vec;
 use alloc::vec::Vec;
 use alloc::collections::BTreeMap;
 use alloc::string::String;
 use spin::Mutex;
 use core::fmt;
+use crate::memory::slab::{SlabBox, CAPABILITY_CACHE};
 use crate::process::ProcessId;
-use crate::{serial_println};
+use kosh_ipc::capability::{
+    CapabilityRecord, ResourceDescriptor, RECORD_DELEGATABLE, RESOURCE_ANY, RESOURCE_IO_PORTS, RESOURCE_IRQ,
+    RESOURCE_MEMORY_RANGE, RESOURCE_PCI_DEVICE, RESOURCE_PROCESS, RESOURCE_SHARED_MEMORY,
+};
 
 /// Capability identifier type
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -55,6 +60,49 @@ pub enum CapabilityType {
     Admin,
 }
 
+impl CapabilityType {
+    /// System call encoding of the type, see `kosh_ipc::capability::CapabilityKind`
+    pub fn to_raw(self) -> u64 {
+        match self {
+            CapabilityType::Read => 0,
+            CapabilityType::Write => 1,
+            CapabilityType::Execute => 2,
+            CapabilityType::Create => 3,
+            CapabilityType::Delete => 4,
+            CapabilityType::SendMessage => 5,
+            CapabilityType::ReceiveMessage => 6,
+            CapabilityType::SystemCall => 7,
+            CapabilityType::DeviceAccess => 8,
+            CapabilityType::MemoryManagement => 9,
+            CapabilityType::ProcessManagement => 10,
+            CapabilityType::FileSystem => 11,
+            CapabilityType::Network => 12,
+            CapabilityType::Admin => 13,
+        }
+    }
+    
+    /// Capability type for its system call encoding, see `kosh_ipc::capability::CapabilityKind`
+    pub fn from_raw(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(CapabilityType::Read),
+            1 => Some(CapabilityType::Write),
+            2 => Some(CapabilityType::Execute),
+            3 => Some(CapabilityType::Create),
+            4 => Some(CapabilityType::Delete),
+            5 => Some(CapabilityType::SendMessage),
+            6 => Some(CapabilityType::ReceiveMessage),
+            7 => Some(CapabilityType::SystemCall),
+            8 => Some(CapabilityType::DeviceAccess),
+            9 => Some(CapabilityType::MemoryManagement),
+            10 => Some(CapabilityType::ProcessManagement),
+            11 => Some(CapabilityType::FileSystem),
+            12 => Some(CapabilityType::Network),
+            13 => Some(CapabilityType::Admin),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for CapabilityType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -91,6 +139,103 @@ pub enum ResourceId {
     Network(String),
     /// System resource
     System(String),
+    /// Shared memory region
+    SharedMemory(u64),
+    /// I/O ports `start..=end`
+    IoPorts { start: u16, end: u16 },
+    /// Physical memory `start..start + size`, such as device registers
+    MemoryRange { start: u64, size: u64 },
+}
+
+impl ResourceId {
+    /// The PCI function with requester ID `requester`, as the
+    /// `pci:<bus>:<device>.<function>` device MSI allocation checks for
+    pub fn pci_device(requester: u16) -> Self {
+        ResourceId::Device(alloc::format!("pci:{:02x}:{:02x}.{}", requester >> 8, (requester >> 3) & 0x1F, requester & 0x7))
+    }
+
+    /// Bus, device and function of a `pci:<bus>:<device>.<function>`
+    /// device name
+    fn pci_function(name: &str) -> Option<(u8, u8, u8)> {
+        let (bus, rest) = name.strip_prefix("pci:")?.split_once(':')?;
+        let (device, function) = rest.split_once('.')?;
+        let bus = u8::from_str_radix(bus, 16).ok()?;
+        let device = u8::from_str_radix(device, 16).ok().filter(|&device| device < 32)?;
+        let function = function.parse::<u8>().ok().filter(|&function| function < 8)?;
+        Some((bus, device, function))
+    }
+
+    /// Resource named by a descriptor passed in from userspace
+    ///
+    /// IRQ lines map to the `irq:<line>` devices interrupt registration
+    /// checks for, PCI functions to the `pci:` devices MSI allocation
+    /// checks for.
+    pub fn from_descriptor(descriptor: &ResourceDescriptor) -> Option<Self> {
+        match descriptor.kind {
+            RESOURCE_ANY => Some(ResourceId::Any),
+            RESOURCE_PROCESS => Some(ResourceId::Process(ProcessId::new(u32::try_from(descriptor.first).ok()?))),
+            RESOURCE_IO_PORTS => {
+                let start = u16::try_from(descriptor.first).ok()?;
+                let end = u16::try_from(descriptor.second).ok()?;
+                (start <= end).then_some(ResourceId::IoPorts { start, end })
+            }
+            RESOURCE_MEMORY_RANGE => {
+                descriptor.first.checked_add(descriptor.second)?;
+                (descriptor.second != 0).then_some(ResourceId::MemoryRange { start: descriptor.first, size: descriptor.second })
+            }
+            RESOURCE_IRQ => Some(ResourceId::Device(alloc::format!("irq:{}", u32::try_from(descriptor.first).ok()?))),
+            RESOURCE_SHARED_MEMORY => Some(ResourceId::SharedMemory(descriptor.first)),
+            RESOURCE_PCI_DEVICE => Some(ResourceId::pci_device(u16::try_from(descriptor.first).ok()?)),
+            _ => None,
+        }
+    }
+    
+    /// Descriptor for the resource, to pass it back to userspace
+    ///
+    /// Named resources other than IRQ lines and PCI functions have no
+    /// descriptor and come out as `RESOURCE_NAMED`.
+    pub fn to_descriptor(&self) -> ResourceDescriptor {
+        match self {
+            ResourceId::Any => ResourceDescriptor::any(),
+            ResourceId::Process(pid) => ResourceDescriptor::process(pid.0),
+            ResourceId::IoPorts { start, end } => ResourceDescriptor::io_ports(*start, *end),
+            ResourceId::MemoryRange { start, size } => ResourceDescriptor::memory_range(*start, *size),
+            ResourceId::SharedMemory(id) => ResourceDescriptor::shared_memory(*id),
+            ResourceId::Device(name) => match name.strip_prefix("irq:").and_then(|line| line.parse().ok()) {
+                Some(line) => ResourceDescriptor::irq(line),
+                None => match Self::pci_function(name) {
+                    Some((bus, device, function)) => ResourceDescriptor::pci_device(bus, device, function),
+                    None => ResourceDescriptor::named(),
+                },
+            },
+            ResourceId::File(_) | ResourceId::Network(_) | ResourceId::System(_) => ResourceDescriptor::named(),
+        }
+    }
+    
+    /// Whether a grant on `self` covers `resource`
+    ///
+    /// Port and memory ranges cover the ranges lying inside them; other
+    /// resources only cover themselves.
+    fn covers(&self, resource: &ResourceId) -> bool {
+        match (self, resource) {
+            (ResourceId::Any, _) => true,
+            (
+                ResourceId::IoPorts { start, end },
+                ResourceId::IoPorts { start: wanted_start, end: wanted_end },
+            ) => start <= wanted_start && wanted_end <= end && wanted_start <= wanted_end,
+            (
+                ResourceId::MemoryRange { start, size },
+                ResourceId::MemoryRange { start: wanted_start, size: wanted_size },
+            ) => {
+                let end = start.saturating_add(*size);
+                match wanted_start.checked_add(*wanted_size) {
+                    Some(wanted_end) => start <= wanted_start && wanted_end <= end,
+                    None => false,
+                }
+            }
+            (a, b) => a == b,
+        }
+    }
 }
 
 impl fmt::Display for ResourceId {
@@ -102,6 +247,9 @@ impl fmt::Display for ResourceId {
             ResourceId::File(path) => write!(f, "file:{}", path),
             ResourceId::Network(endpoint) => write!(f, "network:{}", endpoint),
             ResourceId::System(name) => write!(f, "system:{}", name),
+            ResourceId::SharedMemory(id) => write!(f, "shm:{}", id),
+            ResourceId::IoPorts { start, end } => write!(f, "ioport:0x{:x}-0x{:x}", start, end),
+            ResourceId::MemoryRange { start, size } => write!(f, "memory:0x{:x}+0x{:x}", start, size),
         }
     }
 }
@@ -119,7 +267,11 @@ pub struct Capability {
     pub owner: ProcessId,
     /// Process that granted this capability (None for system-granted)
     pub granter: Option<ProcessId>,
-    /// Whether this capability can be delegated to other processes
+    /// Capability of the granter this one was delegated from; revoking it
+    /// revokes this one too
+    pub parent: Option<CapabilityId>,
+    /// Whether the owner holds the Grant right, to delegate this
+    /// capability or a narrower one to other processes
     pub delegatable: bool,
     /// Expiration time (None for permanent)
     pub expires_at: Option<u64>,
@@ -144,6 +296,7 @@ impl Capability {
             resource,
             owner,
             granter,
+            parent: None,
             delegatable: false,
             expires_at: None,
             created_at,
@@ -170,12 +323,8 @@ impl Capability {
             return false;
         }
         
-        // Check resource match
-        match (&self.resource, resource) {
-            (ResourceId::Any, _) => true, // Wildcard matches everything
-            (a, b) if a == b => true,     // Exact match
-            _ => false,
-        }
+        // Check resource match: wildcard, exact or enclosing range
+        self.resource.covers(resource)
     }
     
     /// Set expiration time for this capability
@@ -187,6 +336,17 @@ impl Capability {
     pub fn make_delegatable(&mut self) {
         self.delegatable = true;
     }
+    
+    /// Record of the capability as `SYS_LIST_CAPABILITIES` returns it
+    pub fn to_record(&self) -> CapabilityRecord {
+        CapabilityRecord {
+            id: self.id.0,
+            kind: self.capability_type.to_raw(),
+            resource: self.resource.to_descriptor(),
+            granter: self.granter.map_or(0, |granter| granter.0),
+            flags: if self.delegatable { RECORD_DELEGATABLE } else { 0 },
+        }
+    }
 }
 
 impl fmt::Display for Capability {
@@ -199,8 +359,8 @@ impl fmt::Display for Capability {
 /// Set of capabilities owned by a process
 #[derive(Debug, Clone)]
 pub struct CapabilitySet {
-    /// List of capabilities
-    capabilities: Vec<Capability>,
+    /// List of capabilities, each in the capability slab cache
+    capabilities: Vec<SlabBox<Capability>>,
 }
 
 impl CapabilitySet {
@@ -212,14 +372,16 @@ impl CapabilitySet {
     }
     
     /// Add a capability to the set
-    pub fn add(&mut self, capability: Capability) {
+    pub fn add(&mut self, capability: Capability) -> Result<(), CapabilityError> {
+        let capability = SlabBox::new(&CAPABILITY_CACHE, capability).map_err(|_| CapabilityError::ResourceExhausted)?;
         self.capabilities.push(capability);
+        Ok(())
     }
     
     /// Remove a capability by ID
     pub fn remove(&mut self, capability_id: CapabilityId) -> Option<Capability> {
         if let Some(pos) = self.capabilities.iter().position(|c| c.id == capability_id) {
-            Some(self.capabilities.remove(pos))
+            Some(SlabBox::into_inner(self.capabilities.remove(pos)))
         } else {
             None
         }
@@ -235,6 +397,7 @@ impl CapabilitySet {
     pub fn get_capabilities_by_type(&self, capability_type: CapabilityType) -> Vec<&Capability> {
         self.capabilities.iter()
             .filter(|cap| cap.capability_type == capability_type && !cap.is_expired())
+            .map(|cap| &**cap)
             .collect()
     }
     
@@ -242,6 +405,7 @@ impl CapabilitySet {
     pub fn get_capabilities_by_resource(&self, resource: &ResourceId) -> Vec<&Capability> {
         self.capabilities.iter()
             .filter(|cap| cap.matches(cap.capability_type, resource))
+            .map(|cap| &**cap)
             .collect()
     }
     
@@ -268,8 +432,8 @@ impl CapabilitySet {
     }
     
     /// Get all capabilities (for debugging)
-    pub fn get_all(&self) -> &[Capability] {
-        &self.capabilities
+    pub fn get_all(&self) -> Vec<&Capability> {
+        self.capabilities.iter().map(|cap| &**cap).collect()
     }
 }
 
@@ -347,16 +511,138 @@ impl CapabilityManager {
             .entry(process_id)
             .or_insert_with(CapabilitySet::new);
         
-        capability_set.add(capability);
+        capability_set.add(capability)?;
         self.total_capabilities_created += 1;
         
-        serial_println!("Granted {} capability to process {} for resource {}", 
+        log::debug!("Granted {} capability to process {} for resource {}", 
                        capability_type, process_id.0, 
                        capability_set.capabilities.last().unwrap().resource);
         
         Ok(capability_id)
     }
     
+    /// Whether `process` may grant and revoke anything: the kernel, init
+    /// and administrators
+    fn is_privileged(&self, process: ProcessId) -> bool {
+        process == ProcessId::KERNEL
+            || process == ProcessId::INIT
+            || self.process_capabilities.get(&process)
+                .is_some_and(|capability_set| capability_set.has_capability(CapabilityType::Admin, &ResourceId::Any))
+    }
+    
+    /// The capability `granter` hands out `capability_type` on `resource`
+    /// from, None if it is privileged and needs none
+    ///
+    /// Other processes pass on what they hold with the Grant right, or a
+    /// part of it.
+    fn grant_source(
+        &self,
+        granter: ProcessId,
+        capability_type: CapabilityType,
+        resource: &ResourceId,
+    ) -> Result<Option<CapabilityId>, CapabilityError> {
+        if self.is_privileged(granter) {
+            return Ok(None);
+        }
+        let held: Vec<&Capability> = self.process_capabilities.get(&granter)
+            .map(|capability_set| capability_set.capabilities.iter()
+                .filter(|cap| cap.matches(capability_type, resource))
+                .map(|cap| &**cap)
+                .collect())
+            .unwrap_or_default();
+        match held.iter().find(|cap| cap.delegatable) {
+            Some(source) => Ok(Some(source.id)),
+            None if held.is_empty() => Err(CapabilityError::PermissionDenied),
+            None => Err(CapabilityError::NotDelegatable),
+        }
+    }
+    
+    /// Hand `target` a capability `granter` may give, see `grant_source`
+    fn grant_from(
+        &mut self,
+        granter: ProcessId,
+        target: ProcessId,
+        capability_type: CapabilityType,
+        resource: ResourceId,
+        delegatable: bool,
+    ) -> Result<CapabilityId, CapabilityError> {
+        let parent = self.grant_source(granter, capability_type, &resource)?;
+        self.add_granted(granter, target, capability_type, resource, delegatable, parent)
+    }
+    
+    /// Record a capability `granter` handed `target`, delegated from
+    /// `parent` if it has one
+    fn add_granted(
+        &mut self,
+        granter: ProcessId,
+        target: ProcessId,
+        capability_type: CapabilityType,
+        resource: ResourceId,
+        delegatable: bool,
+        parent: Option<CapabilityId>,
+    ) -> Result<CapabilityId, CapabilityError> {
+        let mut capability = Capability::new(capability_type, resource, target, Some(granter));
+        capability.parent = parent;
+        capability.delegatable = delegatable;
+        let capability_id = capability.id;
+        
+        self.process_capabilities
+            .entry(target)
+            .or_insert_with(CapabilitySet::new)
+            .add(capability)?;
+        self.total_capabilities_created += 1;
+        Ok(capability_id)
+    }
+    
+    /// Owner and parent of capability `capability_id`
+    fn lineage_of(&self, capability_id: CapabilityId) -> Option<(ProcessId, Option<CapabilityId>)> {
+        self.process_capabilities.values()
+            .flat_map(|capability_set| capability_set.capabilities.iter())
+            .find(|cap| cap.id == capability_id)
+            .map(|cap| (cap.owner, cap.parent))
+    }
+    
+    /// Whether `revoker` may revoke `capability_id`: its owner may drop
+    /// it, and whoever holds a capability it was delegated from, directly
+    /// or through others, may take it back
+    fn may_revoke(&self, revoker: ProcessId, capability_id: CapabilityId) -> bool {
+        if self.is_privileged(revoker) {
+            return true;
+        }
+        let mut current = Some(capability_id);
+        // Delegation chains cannot loop, as a parent exists before its children
+        while let Some((owner, parent)) = current.and_then(|id| self.lineage_of(id)) {
+            if owner == revoker {
+                return true;
+            }
+            current = parent;
+        }
+        false
+    }
+    
+    /// Remove `root` and every capability delegated from it, directly or
+    /// through others
+    ///
+    /// Returns how many capabilities were removed.
+    fn revoke_tree(&mut self, root: CapabilityId) -> usize {
+        let mut revoked = vec![root];
+        let mut next = 0;
+        while next < revoked.len() {
+            let parent = revoked[next];
+            next += 1;
+            let children: Vec<CapabilityId> = self.process_capabilities.values()
+                .flat_map(|capability_set| capability_set.capabilities.iter())
+                .filter(|cap| cap.parent == Some(parent))
+                .map(|cap| cap.id)
+                .collect();
+            revoked.extend(children);
+        }
+        for capability_set in self.process_capabilities.values_mut() {
+            capability_set.capabilities.retain(|cap| !revoked.contains(&cap.id));
+        }
+        revoked.len()
+    }
+    
     /// Check if a process has a specific capability
     fn check_capability(
         &mut self,
@@ -370,14 +656,16 @@ impl CapabilityManager {
             let has_capability = capability_set.has_capability(capability_type, resource);
             if !has_capability {
                 self.checks_failed += 1;
-                serial_println!("Capability check failed: process {} lacks {} for {}", 
+                log::warn!("Capability check failed: process {} lacks {} for {}", 
                                process_id.0, capability_type, resource);
+                audit_denial(process_id, capability_type, resource);
             }
             has_capability
         } else {
             self.checks_failed += 1;
-            serial_println!("Capability check failed: no capabilities for process {}", 
+            log::warn!("Capability check failed: no capabilities for process {}", 
                            process_id.0);
+            audit_denial(process_id, capability_type, resource);
             false
         }
     }
@@ -406,7 +694,7 @@ impl CapabilityManager {
                 return Err(CapabilityError::CapabilityExpired);
             }
             
-            capability.clone()
+            Capability::clone(capability)
         };
         
         // Create a new capability for the target process
@@ -420,6 +708,7 @@ impl CapabilityManager {
         // Inherit expiration and delegation properties
         new_capability.expires_at = source_capability.expires_at;
         new_capability.delegatable = source_capability.delegatable;
+        new_capability.parent = Some(capability_id);
         
         let new_capability_id = new_capability.id;
         
@@ -428,31 +717,89 @@ impl CapabilityManager {
             .entry(to_process)
             .or_insert_with(CapabilitySet::new);
         
-        target_set.add(new_capability);
+        target_set.add(new_capability)?;
         self.total_capabilities_created += 1;
         
-        serial_println!("Delegated capability {} from process {} to process {}", 
+        log::debug!("Delegated capability {} from process {} to process {}", 
                        capability_id.0, from_process.0, to_process.0);
         
         Ok(new_capability_id)
     }
     
-    /// Revoke a capability from a process
+    /// Copy each unexpired messaging capability of `parent` to `child`
+    ///
+    /// Only the types in `INHERITED_ON_FORK` are copied; any other right the
+    /// child needs must be granted to it explicitly. Each copy is recorded
+    /// as delegated from the original, so revoking the parent's capability
+    /// also takes it from the child. Returns how many capabilities were
+    /// copied.
+    fn inherit_capabilities(&mut self, parent: ProcessId, child: ProcessId) -> Result<usize, CapabilityError> {
+        let inherited: Vec<Capability> = match self.process_capabilities.get(&parent) {
+            Some(parent_set) => parent_set.capabilities.iter()
+                .filter(|cap| !cap.is_expired() && INHERITED_ON_FORK.contains(&cap.capability_type))
+                .map(|cap| {
+                    let mut copy = Capability::new(cap.capability_type, cap.resource.clone(), child, cap.granter);
+                    copy.expires_at = cap.expires_at;
+                    copy.delegatable = cap.delegatable;
+                    copy.parent = Some(cap.id);
+                    copy
+                })
+                .collect(),
+            None => return Ok(0),
+        };
+        
+        let count = inherited.len();
+        let child_set = self.process_capabilities
+            .entry(child)
+            .or_insert_with(CapabilitySet::new);
+        for capability in inherited {
+            child_set.add(capability)?;
+        }
+        self.total_capabilities_created += count as u64;
+        Ok(count)
+    }
+    
+    /// Revoke a capability from a process, along with every capability
+    /// delegated from it
+    ///
+    /// Returns how many capabilities were revoked.
     fn revoke_capability(
         &mut self,
         process_id: ProcessId,
         capability_id: CapabilityId,
-    ) -> Result<(), CapabilityError> {
-        let capability_set = self.process_capabilities.get_mut(&process_id)
-            .ok_or(CapabilityError::CapabilityNotFound)?;
-        
-        capability_set.remove(capability_id)
-            .ok_or(CapabilityError::CapabilityNotFound)?;
+    ) -> Result<usize, CapabilityError> {
+        let held = self.process_capabilities.get(&process_id)
+            .is_some_and(|capability_set| capability_set.capabilities.iter().any(|cap| cap.id == capability_id));
+        if !held {
+            return Err(CapabilityError::CapabilityNotFound);
+        }
         
-        serial_println!("Revoked capability {} from process {}", 
-                       capability_id.0, process_id.0);
+        let revoked = self.revoke_tree(capability_id);
+        log::debug!("Revoked capability {} from process {} ({} in total)", 
+                       capability_id.0, process_id.0, revoked);
         
-        Ok(())
+        Ok(revoked)
+    }
+    
+    /// Drop every capability of an exiting process
+    ///
+    /// Capabilities delegated from its own stay with their holders, now
+    /// hanging off whatever the dropped ones were delegated from, so they
+    /// can still be revoked from further up. Returns how many capabilities
+    /// were dropped.
+    fn release_process(&mut self, process_id: ProcessId) -> usize {
+        let Some(capability_set) = self.process_capabilities.remove(&process_id) else {
+            return 0;
+        };
+        for dropped in &capability_set.capabilities {
+            for cap in self.process_capabilities.values_mut()
+                .flat_map(|capability_set| capability_set.capabilities.iter_mut())
+                .filter(|cap| cap.parent == Some(dropped.id))
+            {
+                cap.parent = dropped.parent;
+            }
+        }
+        capability_set.len()
     }
     
     /// Get capability statistics
@@ -488,7 +835,7 @@ impl CapabilityManager {
         }
         
         if total_cleaned > 0 {
-            serial_println!("Cleaned up {} expired capabilities", total_cleaned);
+            log::debug!("Cleaned up {} expired capabilities", total_cleaned);
         }
         
         total_cleaned
@@ -523,12 +870,12 @@ fn generate_capability_id() -> CapabilityId {
 
 /// Initialize the capability system
 pub fn init_capability_system() -> Result<(), &'static str> {
-    serial_println!("Initializing capability system...");
+    log::info!("Initializing capability system...");
     
     let manager = CapabilityManager::new();
     *CAPABILITY_MANAGER.lock() = Some(manager);
     
-    serial_println!("Capability system initialized");
+    log::info!("Capability system initialized");
     Ok(())
 }
 
@@ -544,6 +891,129 @@ pub fn create_capability(
     manager.grant_capability(process_id, capability_type, resource, granter)
 }
 
+/// Whether `granter` owns the endpoint a message capability on
+/// `resource` is for: its own, or that of a child of it
+///
+/// A process decides who may message it, and a parent, which may also
+/// signal its children, decides for them.
+fn owns_endpoint(granter: ProcessId, capability_type: CapabilityType, resource: &ResourceId) -> bool {
+    let ResourceId::Process(endpoint) = *resource else {
+        return false;
+    };
+    matches!(capability_type, CapabilityType::SendMessage | CapabilityType::ReceiveMessage)
+        && (endpoint == granter
+            || crate::process::get_process(endpoint).is_some_and(|process| process.parent_pid == Some(granter)))
+}
+
+/// Grant a capability to `target` on behalf of `granter`, with the Grant
+/// right if `delegatable`
+///
+/// Fails with `PermissionDenied` unless `granter` holds a covering
+/// capability, and with `NotDelegatable` unless that one carries the Grant
+/// right; see `CapabilityManager::grant_source`. The new capability is
+/// revoked along with the one it was granted from. Message capabilities
+/// on the granter itself or one of its children need neither.
+pub fn grant_capability(
+    granter: ProcessId,
+    target: ProcessId,
+    capability_type: CapabilityType,
+    resource: ResourceId,
+    delegatable: bool,
+) -> Result<CapabilityId, CapabilityError> {
+    // Looked up before taking the capability lock, as it locks the process table
+    let own_endpoint = owns_endpoint(granter, capability_type, &resource);
+    let mut manager = CAPABILITY_MANAGER.lock();
+    let manager = manager.as_mut().ok_or(CapabilityError::ResourceExhausted)?;
+    let result = if own_endpoint {
+        manager.add_granted(granter, target, capability_type, resource.clone(), delegatable, None)
+    } else {
+        manager.grant_from(granter, target, capability_type, resource.clone(), delegatable)
+    };
+    match &result {
+        Ok(capability_id) => log::debug!("Process {} granted {} for {} to process {} as capability {}",
+                                         granter.0, capability_type, resource, target.0, capability_id.0),
+        Err(e) => log::debug!("Process {} may not grant {} for {}: {}", granter.0, capability_type, resource, e),
+    }
+    result
+}
+
+/// Capability types a forked child inherits from its parent
+///
+/// The rest of the parent's rights, such as init's system capabilities,
+/// stay with the parent; the child starts from the default user set.
+const INHERITED_ON_FORK: [CapabilityType; 2] = [CapabilityType::SendMessage, CapabilityType::ReceiveMessage];
+
+/// Give a forked `child` a copy of `parent`'s messaging capabilities
+pub fn inherit_capabilities(parent: ProcessId, child: ProcessId) -> Result<usize, CapabilityError> {
+    let mut manager = CAPABILITY_MANAGER.lock();
+    let manager = manager.as_mut().ok_or(CapabilityError::ResourceExhausted)?;
+    manager.inherit_capabilities(parent, child)
+}
+
+/// Drop every capability of an exiting process, so a process that later
+/// gets its ID does not hold them
+pub fn release_process(process_id: ProcessId) -> Result<usize, CapabilityError> {
+    let mut manager = CAPABILITY_MANAGER.lock();
+    let manager = manager.as_mut().ok_or(CapabilityError::ResourceExhausted)?;
+    Ok(manager.release_process(process_id))
+}
+
+/// Revoke capability `capability_id` of `target` on behalf of `revoker`,
+/// and every capability delegated from it
+///
+/// A process may drop its own capabilities and take back those delegated
+/// from its own; the kernel, init and administrators may revoke any.
+/// Returns how many capabilities were revoked.
+pub fn revoke_capability_as(
+    revoker: ProcessId,
+    target: ProcessId,
+    capability_id: CapabilityId,
+) -> Result<usize, CapabilityError> {
+    let mut manager = CAPABILITY_MANAGER.lock();
+    let manager = manager.as_mut().ok_or(CapabilityError::ResourceExhausted)?;
+    if manager.lineage_of(capability_id).map(|(owner, _)| owner) != Some(target) {
+        return Err(CapabilityError::CapabilityNotFound);
+    }
+    if !manager.may_revoke(revoker, capability_id) {
+        log::debug!("Process {} may not revoke capability {} of process {}", revoker.0, capability_id.0, target.0);
+        return Err(CapabilityError::PermissionDenied);
+    }
+    manager.revoke_capability(target, capability_id)
+}
+
+/// Whether `granter` may hand out `capability_type` on `resource`, as it
+/// does by attaching a capability to a message
+pub fn can_grant(granter: ProcessId, capability_type: CapabilityType, resource: &ResourceId) -> bool {
+    CAPABILITY_MANAGER.lock().as_ref()
+        .is_some_and(|manager| manager.grant_source(granter, capability_type, resource).is_ok())
+}
+
+/// Whether `process_id` holds `capability_type` on `resource`, without
+/// counting or logging a failed check
+///
+/// For processes asking about themselves, where a missing capability is
+/// an answer rather than a denial.
+pub fn holds_capability(
+    process_id: ProcessId,
+    capability_type: CapabilityType,
+    resource: &ResourceId,
+) -> bool {
+    CAPABILITY_MANAGER.lock().as_ref()
+        .and_then(|manager| manager.process_capabilities.get(&process_id))
+        .is_some_and(|capability_set| capability_set.has_capability(capability_type, resource))
+}
+
+/// Unexpired capabilities of `process_id`, oldest first
+pub fn list_capabilities(process_id: ProcessId) -> Vec<Capability> {
+    CAPABILITY_MANAGER.lock().as_ref()
+        .and_then(|manager| manager.process_capabilities.get(&process_id))
+        .map(|capability_set| capability_set.capabilities.iter()
+            .filter(|cap| !cap.is_expired())
+            .map(|cap| Capability::clone(cap))
+            .collect())
+        .unwrap_or_default()
+}
+
 /// Check if a process has a specific capability
 pub fn check_capability(
     process_id: ProcessId,
@@ -558,6 +1028,11 @@ pub fn check_capability(
     }
 }
 
+/// Record a failed capability check in the audit log
+fn audit_denial(process_id: ProcessId, capability_type: CapabilityType, resource: &ResourceId) {
+    crate::audit::record(process_id, crate::audit::AuditEvent::CapabilityDenied { capability_type, resource });
+}
+
 /// Delegate a capability from one process to another
 pub fn delegate_capability(
     from_process: ProcessId,
@@ -569,11 +1044,12 @@ pub fn delegate_capability(
     manager.delegate_capability(from_process, to_process, capability_id)
 }
 
-/// Revoke a capability from a process
+/// Revoke a capability from a process, along with every capability
+/// delegated from it
 pub fn revoke_capability(
     process_id: ProcessId,
     capability_id: CapabilityId,
-) -> Result<(), CapabilityError> {
+) -> Result<usize, CapabilityError> {
     let mut manager = CAPABILITY_MANAGER.lock();
     let manager = manager.as_mut().ok_or(CapabilityError::ResourceExhausted)?;
     manager.revoke_capability(process_id, capability_id)
@@ -617,6 +1093,7 @@ fn get_current_time_ms() -> u64 {
 mod tests {
     use super::*;
     use alloc::string::ToString;
+    use kosh_ipc::capability::RESOURCE_NAMED;
     
     #[test_case]
     fn test_capability_creation() {
@@ -673,7 +1150,7 @@ mod tests {
         );
         
         let capability_id = capability.id;
-        capability_set.add(capability);
+        capability_set.add(capability).unwrap();
         
         assert!(!capability_set.is_empty());
         assert_eq!(capability_set.len(), 1);
@@ -707,4 +1184,156 @@ mod tests {
         // But not different capability types
         assert!(!wildcard_capability.matches(CapabilityType::Write, &ResourceId::File("any.txt".to_string())));
     }
-}
\ No newline at end of file
+    
+    #[test_case]
+    fn test_range_capability() {
+        let process_id = ProcessId::new(1);
+        let ports = Capability::new(
+            CapabilityType::DeviceAccess,
+            ResourceId::IoPorts { start: 0x60, end: 0x64 },
+            process_id,
+            None,
+        );
+        let registers = Capability::new(
+            CapabilityType::DeviceAccess,
+            ResourceId::MemoryRange { start: 0xFEB0_0000, size: 0x1_0000 },
+            process_id,
+            None,
+        );
+        
+        // Ranges cover what lies inside them
+        assert!(ports.matches(CapabilityType::DeviceAccess, &ResourceId::IoPorts { start: 0x60, end: 0x60 }));
+        assert!(ports.matches(CapabilityType::DeviceAccess, &ResourceId::IoPorts { start: 0x64, end: 0x64 }));
+        assert!(registers.matches(CapabilityType::DeviceAccess, &ResourceId::MemoryRange { start: 0xFEB0_1000, size: 0x1000 }));
+        assert!(registers.matches(CapabilityType::DeviceAccess, &ResourceId::MemoryRange { start: 0xFEB0_0000, size: 0x1_0000 }));
+        
+        // But nothing reaching outside
+        assert!(!ports.matches(CapabilityType::DeviceAccess, &ResourceId::IoPorts { start: 0x64, end: 0x65 }));
+        assert!(!ports.matches(CapabilityType::DeviceAccess, &ResourceId::IoPorts { start: 0xCF8, end: 0xCFB }));
+        assert!(!registers.matches(CapabilityType::DeviceAccess, &ResourceId::MemoryRange { start: 0xFEB0_F000, size: 0x2000 }));
+        assert!(!registers.matches(CapabilityType::DeviceAccess, &ResourceId::MemoryRange { start: 0xFEB0_0000, size: u64::MAX }));
+    }
+    
+    #[test_case]
+    fn test_grant_needs_covering_capability() {
+        let mut manager = CapabilityManager::new();
+        let manager_pid = ProcessId::new(3);
+        let driver_pid = ProcessId::new(40);
+        let ports = ResourceId::IoPorts { start: 0x60, end: 0x64 };
+        let held = manager.grant_from(ProcessId::INIT, manager_pid, CapabilityType::DeviceAccess, ports, true).unwrap();
+        
+        // A process may pass on what it holds, or a part of it
+        let one_port = ResourceId::IoPorts { start: 0x60, end: 0x60 };
+        assert_eq!(manager.grant_source(manager_pid, CapabilityType::DeviceAccess, &one_port), Ok(Some(held)));
+        
+        // But nothing more
+        assert_eq!(manager.grant_source(manager_pid, CapabilityType::DeviceAccess, &ResourceId::IoPorts { start: 0x60, end: 0x70 }),
+                   Err(CapabilityError::PermissionDenied));
+        assert_eq!(manager.grant_source(manager_pid, CapabilityType::Network, &ResourceId::Any), Err(CapabilityError::PermissionDenied));
+        assert_eq!(manager.grant_source(driver_pid, CapabilityType::DeviceAccess, &one_port), Err(CapabilityError::PermissionDenied));
+        
+        // Init and administrators may grant anything
+        assert_eq!(manager.grant_source(ProcessId::INIT, CapabilityType::Network, &ResourceId::Any), Ok(None));
+        manager.grant_capability(driver_pid, CapabilityType::Admin, ResourceId::Any, None).unwrap();
+        assert_eq!(manager.grant_source(driver_pid, CapabilityType::Network, &ResourceId::Any), Ok(None));
+    }
+    
+    #[test_case]
+    fn test_delegation_needs_grant_right() {
+        let mut manager = CapabilityManager::new();
+        let (manager_pid, driver_pid, helper_pid) = (ProcessId::new(3), ProcessId::new(40), ProcessId::new(41));
+        let irq = ResourceId::Device("irq:1".to_string());
+        manager.grant_from(ProcessId::INIT, manager_pid, CapabilityType::DeviceAccess, irq.clone(), true).unwrap();
+        manager.grant_from(manager_pid, driver_pid, CapabilityType::DeviceAccess, irq.clone(), false).unwrap();
+        
+        // The driver holds the capability, but without the Grant right
+        assert!(manager.check_capability(driver_pid, CapabilityType::DeviceAccess, &irq));
+        assert_eq!(manager.grant_from(driver_pid, helper_pid, CapabilityType::DeviceAccess, irq, false),
+                   Err(CapabilityError::NotDelegatable));
+    }
+    
+    #[test_case]
+    fn test_revocation_is_recursive() {
+        let mut manager = CapabilityManager::new();
+        let (manager_pid, driver_pid, helper_pid) = (ProcessId::new(3), ProcessId::new(40), ProcessId::new(41));
+        let ports = ResourceId::IoPorts { start: 0x60, end: 0x64 };
+        let root = manager.grant_from(ProcessId::INIT, manager_pid, CapabilityType::DeviceAccess, ports.clone(), true).unwrap();
+        let middle = manager.grant_from(manager_pid, driver_pid, CapabilityType::DeviceAccess, ports.clone(), true).unwrap();
+        let leaf = manager.grant_from(driver_pid, helper_pid, CapabilityType::DeviceAccess, ports.clone(), false).unwrap();
+        
+        // Only holders up the chain may take a capability back
+        assert!(manager.may_revoke(manager_pid, leaf));
+        assert!(manager.may_revoke(helper_pid, leaf));
+        assert!(!manager.may_revoke(helper_pid, middle));
+        assert!(!manager.may_revoke(ProcessId::new(42), leaf));
+        
+        assert_eq!(manager.revoke_capability(driver_pid, middle), Ok(2));
+        assert_eq!(manager.lineage_of(leaf), None);
+        assert!(!manager.check_capability(helper_pid, CapabilityType::DeviceAccess, &ports));
+        assert_eq!(manager.lineage_of(root), Some((manager_pid, None)));
+        assert_eq!(manager.revoke_capability(driver_pid, middle), Err(CapabilityError::CapabilityNotFound));
+    }
+    
+    #[test_case]
+    fn test_forked_child_inherits_capabilities() {
+        let mut manager = CapabilityManager::new();
+        let (parent_pid, child_pid) = (ProcessId::new(50), ProcessId::new(51));
+        let peer = ResourceId::Process(ProcessId::new(52));
+        let send = manager.grant_capability(parent_pid, CapabilityType::SendMessage, ResourceId::Any, None).unwrap();
+        manager.grant_capability(parent_pid, CapabilityType::ReceiveMessage, ResourceId::Any, None).unwrap();
+        manager.grant_capability(parent_pid, CapabilityType::ProcessManagement, ResourceId::Any, None).unwrap();
+        
+        // System rights stay with the parent
+        assert_eq!(manager.inherit_capabilities(parent_pid, child_pid), Ok(2));
+        assert!(manager.check_capability(child_pid, CapabilityType::SendMessage, &peer));
+        assert!(manager.check_capability(child_pid, CapabilityType::ReceiveMessage, &peer));
+        assert!(!manager.check_capability(child_pid, CapabilityType::ProcessManagement, &peer));
+        
+        // The copies go with the parent's capabilities
+        assert_eq!(manager.revoke_capability(parent_pid, send), Ok(2));
+        assert!(!manager.check_capability(child_pid, CapabilityType::SendMessage, &peer));
+        assert_eq!(manager.inherit_capabilities(ProcessId::new(53), child_pid), Ok(0));
+    }
+    
+    #[test_case]
+    fn test_exit_drops_capabilities() {
+        let mut manager = CapabilityManager::new();
+        let (grandparent, parent_pid, child_pid) = (ProcessId::new(60), ProcessId::new(61), ProcessId::new(62));
+        let peer = ResourceId::Process(ProcessId::new(63));
+        let root = manager.grant_capability(grandparent, CapabilityType::SendMessage, ResourceId::Any, None).unwrap();
+        manager.inherit_capabilities(grandparent, parent_pid).unwrap();
+        manager.inherit_capabilities(parent_pid, child_pid).unwrap();
+        
+        assert_eq!(manager.release_process(parent_pid), 1);
+        assert!(!manager.check_capability(parent_pid, CapabilityType::SendMessage, &peer));
+        assert!(manager.check_capability(child_pid, CapabilityType::SendMessage, &peer));
+        assert_eq!(manager.release_process(parent_pid), 0);
+        
+        // The child's copy still goes with the capability it came from
+        assert_eq!(manager.revoke_capability(grandparent, root), Ok(2));
+        assert!(!manager.check_capability(child_pid, CapabilityType::SendMessage, &peer));
+    }
+    
+    #[test_case]
+    fn test_resource_from_descriptor() {
+        assert_eq!(ResourceId::from_descriptor(&ResourceDescriptor::io_ports(0x60, 0x64)), Some(ResourceId::IoPorts { start: 0x60, end: 0x64 }));
+        assert_eq!(ResourceId::from_descriptor(&ResourceDescriptor::irq(1)), Some(ResourceId::Device("irq:1".to_string())));
+        assert_eq!(ResourceId::from_descriptor(&ResourceDescriptor::memory_range(0xFEB0_0000, 0x1000)), Some(ResourceId::MemoryRange { start: 0xFEB0_0000, size: 0x1000 }));
+        
+        // Empty, reversed or wrapping ranges are rejected
+        assert_eq!(ResourceId::from_descriptor(&ResourceDescriptor::io_ports(0x64, 0x60)), None);
+        assert_eq!(ResourceId::from_descriptor(&ResourceDescriptor::memory_range(0x1000, 0)), None);
+        assert_eq!(ResourceId::from_descriptor(&ResourceDescriptor::memory_range(u64::MAX, 2)), None);
+        
+        // Descriptors go back the way they came, names without one come out named
+        let irq = ResourceId::Device("irq:1".to_string());
+        assert_eq!(ResourceId::from_descriptor(&irq.to_descriptor()), Some(irq));
+        let nvme = ResourceDescriptor::pci_device(0x3a, 0x1f, 7);
+        assert_eq!(ResourceId::from_descriptor(&nvme), Some(ResourceId::Device("pci:3a:1f.7".to_string())));
+        assert_eq!(ResourceId::from_descriptor(&nvme).unwrap().to_descriptor(), nvme);
+        assert_eq!(ResourceId::System("memory".to_string()).to_descriptor().kind, RESOURCE_NAMED);
+        assert_eq!(CapabilityType::from_raw(8), Some(CapabilityType::DeviceAccess));
+        assert_eq!(CapabilityType::from_raw(14), None);
+        assert_eq!(CapabilityType::from_raw(CapabilityType::Admin.to_raw()), Some(CapabilityType::Admin));
+    }
+}
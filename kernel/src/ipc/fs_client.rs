@@ -0,0 +1,202 @@
+//! Kernel client of the file system service
+//!
+//! File system calls are forwarded to the fs-service process as service
+//! frames sent from the kernel. The calling process is blocked until the
+//! response arrives; it then repeats the system call and collects the
+//! response recorded for it. At most one request per process is in flight.
+//!
+//! Each request names the calling process, so the service resolves paths
+//! in that process's namespace rather than in the kernel's.
+
+use alloc::collections::BTreeMap;
+use core::mem::{discriminant, Discriminant};
+use spin::Mutex;
+use kosh_service::{wire, FileSystemRequest, ServiceData, ServiceMessage, ServiceResponse, ServiceType};
+use crate::ipc::message::{Message, MessageData, MessageError, MessageType};
+use crate::process::{BlockReason, ProcessId, ProcessState};
+
+/// Process name under which the file system service runs
+pub const FS_SERVICE_NAME: &str = "fs-service";
+
+/// Largest read or write forwarded in one request; larger transfers are
+/// split by the caller. Kept below the service's shared memory threshold so
+/// data always comes back inline.
+pub const MAX_FS_TRANSFER: usize = kosh_ipc::ZERO_COPY_THRESHOLD - 1;
+
+/// File system client errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsClientError {
+    /// No file system service is running
+    ServiceUnavailable,
+    /// The request could not be queued to the service
+    SendFailed(MessageError),
+}
+
+/// Outcome of forwarding a request
+#[derive(Debug, Clone)]
+pub enum FsCall {
+    /// The service answered
+    Complete {
+        service: ProcessId,
+        response: ServiceResponse,
+    },
+    /// The caller was blocked and must repeat the call once rescheduled
+    Pending,
+}
+
+/// A request waiting for its response
+struct PendingRequest {
+    request_id: u64,
+    service: ProcessId,
+    operation: Discriminant<FileSystemRequest>,
+    response: Option<ServiceResponse>,
+}
+
+struct FsClient {
+    next_request_id: u64,
+    pending: BTreeMap<ProcessId, PendingRequest>,
+}
+
+static FS_CLIENT: Mutex<FsClient> = Mutex::new(FsClient {
+    next_request_id: 1,
+    pending: BTreeMap::new(),
+});
+
+/// Process ID of the running file system service
+pub fn fs_service() -> Option<ProcessId> {
+    crate::process::find_process_by_name(FS_SERVICE_NAME)
+}
+
+/// Queue a request frame to the service without waiting for the answer
+fn send_request(service: ProcessId, request_id: u64, request: FileSystemRequest, caller: Option<ProcessId>) -> Result<(), FsClientError> {
+    let frame = wire::encode_message(&ServiceMessage {
+        service_type: ServiceType::FileSystem,
+        request_id,
+        data: ServiceData::FileSystemRequest(request),
+        on_behalf_of: caller.map(|pid| pid.0),
+    });
+
+    // The kernel is trusted to reach any service; no capability check
+    let message = Message::new(ProcessId::KERNEL, service, MessageType::ServiceRequest, MessageData::Bytes(frame));
+    crate::ipc::queue::enqueue_message(service, message).map_err(FsClientError::SendFailed)
+}
+
+/// Forward `request` on behalf of `pid`, or collect the response to the
+/// request issued by an earlier attempt of the same call
+pub fn call(pid: ProcessId, request: FileSystemRequest) -> Result<FsCall, FsClientError> {
+    {
+        let mut client = FS_CLIENT.lock();
+        match client.pending.get(&pid) {
+            Some(pending) if pending.operation == discriminant(&request) => {
+                if pending.response.is_some() {
+                    let pending = client.pending.remove(&pid).unwrap();
+                    return Ok(FsCall::Complete {
+                        service: pending.service,
+                        response: pending.response.unwrap(),
+                    });
+                }
+                // Woken before the response arrived; keep waiting
+                drop(client);
+                block(pid);
+                return Ok(FsCall::Pending);
+            }
+            // A different call abandons the stale request; its response is dropped
+            Some(_) => {
+                let stale = client.pending.remove(&pid).unwrap();
+                crate::process::realtime::reclaim(pid, stale.service);
+            }
+            None => {}
+        }
+    }
+
+    let service = fs_service().ok_or(FsClientError::ServiceUnavailable)?;
+    let operation = discriminant(&request);
+    let request_id = {
+        let mut client = FS_CLIENT.lock();
+        let request_id = client.next_request_id;
+        client.next_request_id += 1;
+        client.pending.insert(pid, PendingRequest { request_id, service, operation, response: None });
+        request_id
+    };
+
+    if let Err(error) = send_request(service, request_id, request, Some(pid)) {
+        FS_CLIENT.lock().pending.remove(&pid);
+        return Err(error);
+    }
+    // The service runs at least at the caller's real-time priority until
+    // it answers
+    crate::process::realtime::lend(pid, service);
+
+    block(pid);
+
+    // The response may have arrived before the caller was blocked
+    if FS_CLIENT.lock().pending.get(&pid).map_or(false, |pending| pending.response.is_some()) {
+        wake(pid);
+    }
+
+    Ok(FsCall::Pending)
+}
+
+/// Forward a request whose response nobody waits for (e.g. close on exit)
+pub fn notify(service: ProcessId, request: FileSystemRequest) -> Result<(), FsClientError> {
+    let request_id = {
+        let mut client = FS_CLIENT.lock();
+        let request_id = client.next_request_id;
+        client.next_request_id += 1;
+        request_id
+    };
+    send_request(service, request_id, request, None)
+}
+
+/// Accept a message addressed to the kernel
+///
+/// Returns false if it is not a response to an outstanding request.
+pub fn deliver_reply(message: &Message) -> bool {
+    let response = match wire::decode_response(message.data.as_bytes()) {
+        Ok(response) => response,
+        Err(_) => return false,
+    };
+
+    let waiter = {
+        let mut client = FS_CLIENT.lock();
+        let entry = client.pending.iter_mut().find(|(_, pending)| {
+            pending.request_id == response.request_id && pending.service == message.header.sender
+        });
+        match entry {
+            Some((pid, pending)) => {
+                pending.response = Some(response);
+                Some(*pid)
+            }
+            None => None,
+        }
+    };
+
+    match waiter {
+        Some(pid) => {
+            crate::process::realtime::reclaim(pid, message.header.sender);
+            wake(pid);
+            true
+        }
+        None => {
+            log::debug!("Dropping unsolicited response from process {}", message.header.sender.0);
+            false
+        }
+    }
+}
+
+/// Forget the outstanding request of an exiting process
+pub fn release_process(pid: ProcessId) {
+    FS_CLIENT.lock().pending.remove(&pid);
+}
+
+fn block(pid: ProcessId) {
+    let _ = crate::process::set_process_state(pid, ProcessState::Blocked(BlockReason::WaitingForIo));
+}
+
+fn wake(pid: ProcessId) {
+    let blocked = crate::process::get_process(pid)
+        .map_or(false, |info| info.state == ProcessState::Blocked(BlockReason::WaitingForIo));
+    if blocked {
+        let _ = crate::process::set_process_state(pid, ProcessState::Ready);
+    }
+}
@@ -0,0 +1,213 @@
+//! Futexes: blocking on a word of user memory
+//!
+//! A process waits while a 32-bit word still holds the value it expects,
+//! and another wakes it after changing the word. Userspace locks keep
+//! their state in the word and only enter the kernel when they have to
+//! sleep or someone is asleep, so uncontended locking costs no system
+//! call.
+//!
+//! Waiters are keyed on the physical address of the word, so a futex in
+//! shared memory works across processes whatever address each maps it at.
+//! As with `poll`, a blocked waiter is woken by `wake` or its timeout and
+//! repeats the call to collect the result.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+use crate::memory::vmm::VirtualAddress;
+use crate::process::{ProcessId, ProcessState, BlockReason, get_process, set_process_state, with_address_space};
+
+/// Timeout value that waits until woken
+pub const FUTEX_INFINITE: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexError {
+    /// The word is misaligned or not mapped
+    BadAddress,
+    /// The timeout elapsed before a wake
+    TimedOut,
+    /// The caller was blocked until woken or timed out
+    WouldBlock,
+}
+
+/// A process blocked on a futex
+#[derive(Debug, Clone, Copy)]
+struct FutexWaiter {
+    /// Physical address of the word
+    key: u64,
+    /// Orders waiters on one word, so wakes go to the longest waiting
+    sequence: u64,
+    /// Arming of the timeout in the timer wheel, if there is one
+    timeout: Option<u64>,
+    woken: bool,
+    timed_out: bool,
+}
+
+struct Futexes {
+    waiters: BTreeMap<ProcessId, FutexWaiter>,
+    next_sequence: u64,
+}
+
+static FUTEXES: Mutex<Futexes> = Mutex::new(Futexes { waiters: BTreeMap::new(), next_sequence: 0 });
+
+/// Physical address of the word at `address` in a process
+fn futex_key(process_id: ProcessId, address: u64) -> Result<u64, FutexError> {
+    if address % core::mem::size_of::<u32>() as u64 != 0 {
+        return Err(FutexError::BadAddress);
+    }
+    let virt_addr = VirtualAddress(address as usize);
+    let physical = match with_address_space(process_id, |space| space.map(|space| space.translate(virt_addr))) {
+        Ok(Some(physical)) => physical.map(|physical| physical.as_u64()),
+        // Processes sharing the kernel address space
+        Ok(None) => crate::memory::vmm::translate_virtual_address(virt_addr).map(|physical| physical as u64),
+        Err(_) => None,
+    };
+    physical.ok_or(FutexError::BadAddress)
+}
+
+/// Block the caller while the word at `address` holds `expected`
+///
+/// Returns at once if the word holds another value. Otherwise the caller
+/// is blocked and `WouldBlock` returned; once woken it repeats the call and
+/// gets `Ok` after a `wake`, or `TimedOut` after `timeout_ns`. The word is
+/// not read again on the repeated call, as it has usually changed by then.
+pub fn wait(process_id: ProcessId, address: u64, expected: u32, timeout_ns: u64) -> Result<(), FutexError> {
+    let key = futex_key(process_id, address)?;
+    {
+        let mut futexes = FUTEXES.lock();
+        if let Some(waiter) = futexes.waiters.get(&process_id).copied() {
+            if waiter.woken || waiter.timed_out {
+                futexes.waiters.remove(&process_id);
+                return if waiter.woken { Ok(()) } else { Err(FutexError::TimedOut) };
+            }
+            // Woken by something else; keep waiting
+        } else {
+            // Read under the lock, so a wake after the word changed cannot
+            // slip in between the check and the caller joining the waiters
+            let word = unsafe { &*(address as *const AtomicU32) };
+            if word.load(Ordering::SeqCst) != expected {
+                return Ok(());
+            }
+            if timeout_ns == 0 {
+                return Err(FutexError::TimedOut);
+            }
+            let timeout = (timeout_ns != FUTEX_INFINITE)
+                .then(|| crate::process::timer::arm_futex_timeout(process_id, timeout_ns));
+            let sequence = futexes.next_sequence;
+            futexes.next_sequence += 1;
+            futexes.waiters.insert(process_id, FutexWaiter { key, sequence, timeout, woken: false, timed_out: false });
+        }
+    }
+
+    let _ = set_process_state(process_id, ProcessState::Blocked(BlockReason::WaitingForFutex));
+    // A wake may have come while the caller was being blocked
+    let mut futexes = FUTEXES.lock();
+    if futexes.waiters.get(&process_id).is_some_and(|waiter| waiter.woken) {
+        futexes.waiters.remove(&process_id);
+        let _ = set_process_state(process_id, ProcessState::Ready);
+        return Ok(());
+    }
+    Err(FutexError::WouldBlock)
+}
+
+/// Wake up to `count` processes waiting on the word at `address`
+///
+/// The longest waiting go first. Returns how many were woken.
+pub fn wake(process_id: ProcessId, address: u64, count: usize) -> Result<usize, FutexError> {
+    let key = futex_key(process_id, address)?;
+    let woken = {
+        let mut futexes = FUTEXES.lock();
+        let waiting = longest_waiting(&futexes.waiters, key, count);
+        for pid in &waiting {
+            if let Some(waiter) = futexes.waiters.get_mut(pid) {
+                waiter.woken = true;
+            }
+        }
+        waiting
+    };
+
+    for &pid in &woken {
+        wake_blocked(pid);
+    }
+    Ok(woken.len())
+}
+
+/// Up to `count` processes waiting on `key` and not yet woken, longest first
+fn longest_waiting(waiters: &BTreeMap<ProcessId, FutexWaiter>, key: u64, count: usize) -> Vec<ProcessId> {
+    let mut waiting: Vec<(u64, ProcessId)> = waiters.iter()
+        .filter(|(_, waiter)| waiter.key == key && !waiter.woken && !waiter.timed_out)
+        .map(|(&pid, waiter)| (waiter.sequence, pid))
+        .collect();
+    waiting.sort_unstable();
+    waiting.into_iter().take(count).map(|(_, pid)| pid).collect()
+}
+
+fn wake_blocked(pid: ProcessId) {
+    let blocked = get_process(pid)
+        .is_some_and(|info| info.state == ProcessState::Blocked(BlockReason::WaitingForFutex));
+    if blocked {
+        let _ = set_process_state(pid, ProcessState::Ready);
+    }
+}
+
+/// Time out the wait of `process_id` if `arming` is still its timeout
+///
+/// Called from the timer wheel.
+pub fn timed_out(process_id: ProcessId, arming: u64) {
+    let expired = {
+        let mut futexes = FUTEXES.lock();
+        match futexes.waiters.get_mut(&process_id) {
+            Some(waiter) if waiter.timeout == Some(arming) && !waiter.woken => {
+                waiter.timed_out = true;
+                true
+            }
+            _ => false,
+        }
+    };
+    if expired {
+        wake_blocked(process_id);
+    }
+}
+
+/// Forget the wait of a terminating process
+pub fn release_process(process_id: ProcessId) {
+    FUTEXES.lock().waiters.remove(&process_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waiter(key: u64, sequence: u64) -> FutexWaiter {
+        FutexWaiter { key, sequence, timeout: None, woken: false, timed_out: false }
+    }
+
+    #[test_case]
+    fn test_longest_waiting_first() {
+        let mut waiters = BTreeMap::new();
+        waiters.insert(ProcessId::new(9200), waiter(0x1000, 3));
+        waiters.insert(ProcessId::new(9201), waiter(0x1000, 1));
+        waiters.insert(ProcessId::new(9202), waiter(0x2000, 0));
+        waiters.insert(ProcessId::new(9203), waiter(0x1000, 2));
+
+        assert_eq!(longest_waiting(&waiters, 0x1000, 2), [ProcessId::new(9201), ProcessId::new(9203)]);
+        assert_eq!(longest_waiting(&waiters, 0x1000, usize::MAX).len(), 3);
+        assert!(longest_waiting(&waiters, 0x3000, 1).is_empty());
+    }
+
+    #[test_case]
+    fn test_woken_waiters_are_skipped() {
+        let mut waiters = BTreeMap::new();
+        waiters.insert(ProcessId::new(9204), FutexWaiter { woken: true, ..waiter(0x1000, 0) });
+        waiters.insert(ProcessId::new(9205), FutexWaiter { timed_out: true, ..waiter(0x1000, 1) });
+        waiters.insert(ProcessId::new(9206), waiter(0x1000, 2));
+
+        assert_eq!(longest_waiting(&waiters, 0x1000, 1), [ProcessId::new(9206)]);
+    }
+
+    #[test_case]
+    fn test_misaligned_address() {
+        assert_eq!(futex_key(ProcessId::new(9207), 0x1002), Err(FutexError::BadAddress));
+    }
+}
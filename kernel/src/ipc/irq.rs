@@ -0,0 +1,333 @@
+//! Delivery of hardware interrupts to userspace drivers
+//!
+//! A driver claims a line, and the kernel then masks the line whenever it
+//! fires and records it as pending for the owner. The line stays masked
+//! until the driver has serviced its device and acknowledged the
+//! interrupt, so a level-triggered device cannot storm the CPU before
+//! its driver gets to run.
+//!
+//! Message-signaled interrupts (MSI and MSI-X) get lines of their own,
+//! above the wired ones. A driver with device access to a PCI function
+//! allocates a block of them, aligned to its power-of-two size as MSI
+//! requires, and is told the message the function must write to raise
+//! each one. The kernel never masks these lines; the device holds back
+//! further messages until its driver has serviced it.
+//!
+//! The dispatch path runs in interrupt context, so line state is kept in
+//! atomics rather than behind a lock a syscall could be holding.
+
+use alloc::format;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use kosh_ipc::irq::{IrqNotification, MsiAllocation, IRQ_NOTIFY_MESSAGE, IRQ_WAIT_NOHANG, MAX_MSI_VECTORS};
+use crate::process::{ProcessId, ProcessState, BlockReason, get_process, set_process_state};
+use crate::ipc::capability::{CapabilityType, ResourceId, check_capability};
+use crate::ipc::{Message, MessageType, MessageData};
+
+/// Highest wired line number plus one on any platform, and the first
+/// message-signaled line
+pub const MSI_FIRST_LINE: usize = 64;
+
+/// Message-signaled lines
+pub const MSI_LINES: usize = MAX_MSI_VECTORS as usize;
+
+/// Highest line number plus one
+pub const MAX_IRQ_LINES: usize = MSI_FIRST_LINE + MSI_LINES;
+
+/// Owner value of an unclaimed line
+const NO_OWNER: u32 = u32::MAX;
+
+/// Registration flags understood by `register`
+const KNOWN_FLAGS: u64 = IRQ_NOTIFY_MESSAGE;
+
+static OWNERS: [AtomicU32; MAX_IRQ_LINES] = [const { AtomicU32::new(NO_OWNER) }; MAX_IRQ_LINES];
+static FLAGS: [AtomicU64; MAX_IRQ_LINES] = [const { AtomicU64::new(0) }; MAX_IRQ_LINES];
+/// Interrupts taken since the owner last waited
+static PENDING: [AtomicU32; MAX_IRQ_LINES] = [const { AtomicU32::new(0) }; MAX_IRQ_LINES];
+
+/// IRQ routing errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// The line does not exist or is reserved for the kernel, or an MSI
+    /// block is not a power of two lines up to `MSI_LINES`
+    InvalidLine,
+    /// Unknown registration flags
+    InvalidFlags,
+    /// The caller lacks device access to the line
+    PermissionDenied,
+    /// Another process owns the line
+    AlreadyClaimed,
+    /// The caller does not own the line
+    NotOwner,
+    /// Nothing is pending; the caller was blocked until the line fires
+    WouldBlock,
+    /// The platform cannot take message-signaled interrupts
+    Unsupported,
+    /// No block of message-signaled lines that size is free
+    Exhausted,
+}
+
+fn owner(line: usize) -> Option<ProcessId> {
+    match OWNERS[line].load(Ordering::Acquire) {
+        NO_OWNER => None,
+        pid => Some(ProcessId::new(pid)),
+    }
+}
+
+/// Mask or unmask a line at the interrupt controller
+///
+/// Lines drivers cannot claim, such as the PIC cascade, are never touched.
+fn set_masked(line: usize, masked: bool) {
+    if crate::platform::irq_line_is_routable(line) {
+        crate::platform::set_irq_masked(line, masked);
+    }
+}
+
+fn check_owner(process_id: ProcessId, line: usize) -> Result<(), IrqError> {
+    if line >= MAX_IRQ_LINES {
+        return Err(IrqError::InvalidLine);
+    }
+    if owner(line) != Some(process_id) {
+        return Err(IrqError::NotOwner);
+    }
+    Ok(())
+}
+
+/// Claim `line` for a process holding device access to `irq:<line>`
+///
+/// The line is unmasked once claimed.
+pub fn register(process_id: ProcessId, line: usize, flags: u64) -> Result<(), IrqError> {
+    if line >= MAX_IRQ_LINES || !crate::platform::irq_line_is_routable(line) {
+        return Err(IrqError::InvalidLine);
+    }
+    let resource = ResourceId::Device(format!("irq:{}", line));
+    if !check_capability(process_id, CapabilityType::DeviceAccess, &resource) {
+        return Err(IrqError::PermissionDenied);
+    }
+    claim(process_id, line, flags)
+}
+
+fn claim(process_id: ProcessId, line: usize, flags: u64) -> Result<(), IrqError> {
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(IrqError::InvalidFlags);
+    }
+    OWNERS[line]
+        .compare_exchange(NO_OWNER, process_id.0, Ordering::AcqRel, Ordering::Acquire)
+        .map_err(|_| IrqError::AlreadyClaimed)?;
+    FLAGS[line].store(flags, Ordering::Release);
+    PENDING[line].store(0, Ordering::Release);
+    log::debug!("Process {} claimed IRQ {}", process_id.0, line);
+    set_masked(line, false);
+    Ok(())
+}
+
+/// Allocate and claim `count` message-signaled lines for the PCI function
+/// with requester ID `requester`, for a process holding device access to
+/// the function
+///
+/// `count` must be a power of two, as MSI can only ask for such blocks.
+pub fn allocate_msi(process_id: ProcessId, requester: u16, count: usize, flags: u64) -> Result<MsiAllocation, IrqError> {
+    if !count.is_power_of_two() || count > MSI_LINES {
+        return Err(IrqError::InvalidLine);
+    }
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(IrqError::InvalidFlags);
+    }
+    if !check_capability(process_id, CapabilityType::DeviceAccess, &ResourceId::pci_device(requester)) {
+        return Err(IrqError::PermissionDenied);
+    }
+    let first = reserve_msi_block(process_id, count)?;
+    for line in first..first + count {
+        FLAGS[line].store(flags, Ordering::Release);
+        PENDING[line].store(0, Ordering::Release);
+    }
+    let Some((address, data)) = crate::platform::msi_message(first - MSI_FIRST_LINE) else {
+        release_lines(first..first + count);
+        return Err(IrqError::Unsupported);
+    };
+    log::debug!("Process {} allocated MSI lines {}..{} for PCI {:04x}", process_id.0, first, first + count, requester);
+    Ok(MsiAllocation { first_line: first as u32, count: count as u32, address, data, reserved: 0 })
+}
+
+/// Claim the first free block of `count` message-signaled lines aligned
+/// to `count`, returning its first line
+fn reserve_msi_block(process_id: ProcessId, count: usize) -> Result<usize, IrqError> {
+    for first in (MSI_FIRST_LINE..MAX_IRQ_LINES).step_by(count) {
+        let mut claimed = 0;
+        while claimed < count
+            && OWNERS[first + claimed]
+                .compare_exchange(NO_OWNER, process_id.0, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            claimed += 1;
+        }
+        if claimed == count {
+            return Ok(first);
+        }
+        release_lines(first..first + claimed);
+    }
+    Err(IrqError::Exhausted)
+}
+
+fn release_lines(lines: core::ops::Range<usize>) {
+    for line in lines {
+        FLAGS[line].store(0, Ordering::Release);
+        OWNERS[line].store(NO_OWNER, Ordering::Release);
+    }
+}
+
+/// Give `line` back; it is masked until claimed again
+pub fn unregister(process_id: ProcessId, line: usize) -> Result<(), IrqError> {
+    check_owner(process_id, line)?;
+    set_masked(line, true);
+    FLAGS[line].store(0, Ordering::Release);
+    PENDING[line].store(0, Ordering::Release);
+    OWNERS[line].store(NO_OWNER, Ordering::Release);
+    log::debug!("Process {} released IRQ {}", process_id.0, line);
+    Ok(())
+}
+
+/// Collect the interrupts taken on `line` since the last wait
+///
+/// With nothing pending the caller is blocked and `WouldBlock` returned,
+/// unless `IRQ_WAIT_NOHANG` is set, which returns 0 instead. A blocked
+/// caller is woken by `dispatch` and repeats the call.
+pub fn wait(process_id: ProcessId, line: usize, flags: u64) -> Result<u32, IrqError> {
+    check_owner(process_id, line)?;
+    let pending = PENDING[line].swap(0, Ordering::AcqRel);
+    if pending > 0 || flags & IRQ_WAIT_NOHANG != 0 {
+        return Ok(pending);
+    }
+
+    let _ = set_process_state(process_id, ProcessState::Blocked(BlockReason::WaitingForInterrupt));
+    // The line may have fired while the caller was being blocked
+    let pending = PENDING[line].swap(0, Ordering::AcqRel);
+    if pending > 0 {
+        let _ = set_process_state(process_id, ProcessState::Ready);
+        return Ok(pending);
+    }
+    Err(IrqError::WouldBlock)
+}
+
+/// Unmask `line` once the owner has serviced its device
+pub fn acknowledge(process_id: ProcessId, line: usize) -> Result<(), IrqError> {
+    check_owner(process_id, line)?;
+    set_masked(line, false);
+    Ok(())
+}
+
+/// Route an interrupt on `line` to its owner; called in interrupt context
+///
+/// Masks the line, records it as pending, queues a notification for
+/// owners that asked for one and wakes an owner blocked in `wait`.
+/// Returns false when no process owns the line, leaving the interrupt to
+/// the kernel. Every device interrupt passes through here, so its timing
+/// is mixed into the random pool first.
+pub fn dispatch(line: usize) -> bool {
+    crate::random::add_interrupt_randomness(line);
+    if line >= MAX_IRQ_LINES {
+        return false;
+    }
+    let Some(owner) = owner(line) else {
+        return false;
+    };
+
+    set_masked(line, true);
+    PENDING[line].fetch_add(1, Ordering::AcqRel);
+
+    if FLAGS[line].load(Ordering::Acquire) & IRQ_NOTIFY_MESSAGE != 0 {
+        let notification = IrqNotification { line: line as u32 };
+        let message = Message::new(
+            ProcessId::KERNEL,
+            owner,
+            MessageType::Signal,
+            MessageData::Bytes(notification.to_bytes().to_vec()),
+        );
+        let _ = crate::ipc::queue::enqueue_message(owner, message);
+    }
+
+    let waiting = get_process(owner)
+        .map_or(false, |info| info.state == ProcessState::Blocked(BlockReason::WaitingForInterrupt));
+    if waiting {
+        let _ = set_process_state(owner, ProcessState::Ready);
+    }
+    true
+}
+
+/// Give back every line owned by a terminating process
+pub fn release_process(process_id: ProcessId) {
+    for line in 0..MAX_IRQ_LINES {
+        if owner(line) == Some(process_id) {
+            let _ = unregister(process_id, line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A line neither platform lets drivers claim, so the tests never
+    /// mask or unmask real hardware
+    const TEST_LINE: usize = 2;
+
+    #[test_case]
+    fn test_irq_claim_is_exclusive() {
+        let first = ProcessId::new(9101);
+        let second = ProcessId::new(9102);
+
+        assert_eq!(claim(first, TEST_LINE, 0), Ok(()));
+        assert_eq!(claim(second, TEST_LINE, 0), Err(IrqError::AlreadyClaimed));
+        assert_eq!(unregister(second, TEST_LINE), Err(IrqError::NotOwner));
+        assert_eq!(unregister(first, TEST_LINE), Ok(()));
+        assert_eq!(owner(TEST_LINE), None);
+    }
+
+    #[test_case]
+    fn test_irq_unknown_flags_rejected() {
+        let pid = ProcessId::new(9103);
+        assert_eq!(claim(pid, TEST_LINE, 1 << 7), Err(IrqError::InvalidFlags));
+        assert_eq!(owner(TEST_LINE), None);
+    }
+
+    #[test_case]
+    fn test_irq_dispatch_counts_pending() {
+        let pid = ProcessId::new(9104);
+        assert_eq!(claim(pid, TEST_LINE, 0), Ok(()));
+
+        assert_eq!(wait(pid, TEST_LINE, IRQ_WAIT_NOHANG), Ok(0));
+        assert!(dispatch(TEST_LINE));
+        assert!(dispatch(TEST_LINE));
+        assert_eq!(wait(pid, TEST_LINE, IRQ_WAIT_NOHANG), Ok(2));
+        assert_eq!(wait(pid, TEST_LINE, IRQ_WAIT_NOHANG), Ok(0));
+
+        release_process(pid);
+        assert!(!dispatch(TEST_LINE));
+    }
+
+    #[test_case]
+    fn test_msi_blocks_are_aligned() {
+        let first = ProcessId::new(9106);
+        let second = ProcessId::new(9107);
+
+        assert_eq!(reserve_msi_block(first, 2), Ok(MSI_FIRST_LINE));
+        // The next free block of four starts on a multiple of four
+        assert_eq!(reserve_msi_block(second, 4), Ok(MSI_FIRST_LINE + 4));
+        assert_eq!(reserve_msi_block(second, 1), Ok(MSI_FIRST_LINE + 2));
+        assert_eq!(reserve_msi_block(first, MSI_LINES), Err(IrqError::Exhausted));
+        assert_eq!(owner(MSI_FIRST_LINE + 8), None);
+
+        release_process(first);
+        release_process(second);
+        assert_eq!(reserve_msi_block(first, MSI_LINES), Ok(MSI_FIRST_LINE));
+        release_process(first);
+        assert!(!dispatch(MSI_FIRST_LINE));
+    }
+
+    #[test_case]
+    fn test_irq_unrouted_line_rejected() {
+        let pid = ProcessId::new(9105);
+        assert_eq!(register(pid, MAX_IRQ_LINES, 0), Err(IrqError::InvalidLine));
+        assert_eq!(register(pid, TEST_LINE, 0), Err(IrqError::InvalidLine));
+        assert_eq!(wait(pid, MAX_IRQ_LINES, 0), Err(IrqError::InvalidLine));
+    }
+}
@@ -1,9 +1,10 @@
 use alloc::{vec, vec::Vec};
 use alloc::string::String;
 use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
 use crate::process::ProcessId;
-use crate::ipc::capability::CapabilitySet;
-use crate::{serial_println};
+use crate::ipc::capability::{self, CapabilitySet, CapabilityType, ResourceId};
+use crate::trace::Tracepoint;
 
 /// Message identifier type
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -62,8 +63,17 @@ pub enum MessageData {
         error_code: u32,
         message: String,
     },
+    /// Payload left in a shared memory region granted to the receiver
+    SharedMemory {
+        region_id: u64,
+        offset: usize,
+        length: usize,
+    },
 }
 
+/// Size of an encoded shared memory descriptor (region id, offset, length)
+pub const SHARED_DESCRIPTOR_SIZE: usize = 24;
+
 impl MessageData {
     /// Get the size of the message data in bytes
     pub fn size(&self) -> usize {
@@ -74,6 +84,7 @@ impl MessageData {
             MessageData::Structured { data, .. } => data.len() + 4, // +4 for type_id
             MessageData::SystemCall { .. } => 4 + 6 * 8, // call_number + 6 u64 args
             MessageData::Error { message, .. } => 4 + message.len(), // error_code + message
+            MessageData::SharedMemory { .. } => SHARED_DESCRIPTOR_SIZE, // only the descriptor is queued
         }
     }
     
@@ -81,6 +92,64 @@ impl MessageData {
     pub fn is_empty(&self) -> bool {
         matches!(self, MessageData::Empty)
     }
+    
+    /// Raw payload bytes as delivered to user space
+    ///
+    /// Variants without a byte payload (system calls, errors) yield an empty slice.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            MessageData::Bytes(data) => data,
+            MessageData::Text(text) => text.as_bytes(),
+            MessageData::Structured { data, .. } => data,
+            _ => &[],
+        }
+    }
+    
+    /// Number of bytes delivered to user space when this message is received
+    pub fn delivered_len(&self) -> usize {
+        match self {
+            MessageData::SharedMemory { .. } => SHARED_DESCRIPTOR_SIZE,
+            data => data.as_bytes().len(),
+        }
+    }
+    
+    /// Check whether the payload lives in shared memory instead of the message
+    pub fn is_shared(&self) -> bool {
+        matches!(self, MessageData::SharedMemory { .. })
+    }
+    
+    /// Decode a shared memory descriptor sent by user space
+    pub fn shared_from_descriptor(descriptor: &[u8]) -> Option<Self> {
+        if descriptor.len() != SHARED_DESCRIPTOR_SIZE {
+            return None;
+        }
+        
+        let field = |index: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&descriptor[index * 8..index * 8 + 8]);
+            u64::from_le_bytes(bytes)
+        };
+        
+        Some(MessageData::SharedMemory {
+            region_id: field(0),
+            offset: field(1) as usize,
+            length: field(2) as usize,
+        })
+    }
+    
+    /// Encode the shared memory descriptor for delivery to user space
+    pub fn shared_descriptor(&self) -> Option<[u8; SHARED_DESCRIPTOR_SIZE]> {
+        match self {
+            MessageData::SharedMemory { region_id, offset, length } => {
+                let mut descriptor = [0u8; SHARED_DESCRIPTOR_SIZE];
+                descriptor[0..8].copy_from_slice(&region_id.to_le_bytes());
+                descriptor[8..16].copy_from_slice(&(*offset as u64).to_le_bytes());
+                descriptor[16..24].copy_from_slice(&(*length as u64).to_le_bytes());
+                Some(descriptor)
+            }
+            _ => None,
+        }
+    }
 }
 
 /// Message header containing metadata
@@ -289,6 +358,40 @@ fn generate_message_id() -> MessageId {
     }
 }
 
+/// Messages refused at send and at receive for want of a capability
+static SENDS_DENIED: AtomicU64 = AtomicU64::new(0);
+static RECEIVES_DENIED: AtomicU64 = AtomicU64::new(0);
+
+/// Sends and receives refused so far
+pub fn denied_counts() -> (u64, u64) {
+    (SENDS_DENIED.load(Ordering::Relaxed), RECEIVES_DENIED.load(Ordering::Relaxed))
+}
+
+/// Whether `sender` may message `receiver`: through a secure channel
+/// joining them, or with SendMessage on the receiver
+///
+/// Messages from the kernel, such as service replies and interrupt
+/// notifications, need neither.
+fn may_send(sender: ProcessId, receiver: ProcessId) -> bool {
+    sender == ProcessId::KERNEL
+        || crate::ipc::security::in_secure_channel(sender, receiver)
+        || capability::check_capability(sender, CapabilityType::SendMessage, &ResourceId::Process(receiver))
+}
+
+/// Whether `receiver` may take a message from `sender`, see `may_send`
+fn may_receive(receiver: ProcessId, sender: ProcessId) -> bool {
+    sender == ProcessId::KERNEL
+        || crate::ipc::security::in_secure_channel(receiver, sender)
+        || capability::check_capability(receiver, CapabilityType::ReceiveMessage, &ResourceId::Process(sender))
+}
+
+/// Whether the sender may hand out every capability attached to `message`
+fn may_attach(message: &Message) -> bool {
+    let sender = message.header.sender;
+    message.capabilities.get_all().iter()
+        .all(|cap| capability::can_grant(sender, cap.capability_type, &cap.resource))
+}
+
 /// Create a new message
 pub fn create_message(
     sender: ProcessId,
@@ -297,14 +400,14 @@ pub fn create_message(
     data: MessageData,
 ) -> Message {
     let message = Message::new(sender, receiver, message_type, data);
-    serial_println!("Created message {} from {} to {}", 
+    log::debug!("Created message {} from {} to {}", 
                    message.header.message_id.0, sender.0, receiver.0);
     message
 }
 
 /// Send a message to another process
 pub fn send_message(message: Message) -> Result<(), MessageError> {
-    serial_println!("Sending message {} from {} to {}", 
+    log::debug!("Sending message {} from {} to {}", 
                    message.header.message_id.0, 
                    message.header.sender.0, 
                    message.header.receiver.0);
@@ -314,46 +417,84 @@ pub fn send_message(message: Message) -> Result<(), MessageError> {
         return Err(MessageError::SenderNotFound);
     }
     
+    // Messages to the kernel are service responses to requests it forwarded
+    if message.header.receiver == ProcessId::KERNEL {
+        return if crate::ipc::fs_client::deliver_reply(&message) {
+            Ok(())
+        } else {
+            Err(MessageError::InvalidMessage)
+        };
+    }
+    
     // Validate receiver exists
     if crate::process::get_process(message.header.receiver).is_none() {
         return Err(MessageError::ReceiverNotFound);
     }
     
     // Check capabilities for message sending
-    if !crate::ipc::capability::check_capability(
-        message.header.sender,
-        crate::ipc::capability::CapabilityType::SendMessage,
-        &crate::ipc::capability::ResourceId::Process(message.header.receiver),
-    ) {
-        serial_println!("Permission denied: sender {} lacks SendMessage capability for receiver {}", 
+    if !may_send(message.header.sender, message.header.receiver) {
+        SENDS_DENIED.fetch_add(1, Ordering::Relaxed);
+        log::warn!("Permission denied: sender {} lacks SendMessage capability for receiver {}", 
                        message.header.sender.0, message.header.receiver.0);
         return Err(MessageError::PermissionDenied);
     }
     
+    // Refuse up front a message the receiver could never take, rather than
+    // queue it where it would only block the messages behind it
+    if !may_receive(message.header.receiver, message.header.sender) {
+        SENDS_DENIED.fetch_add(1, Ordering::Relaxed);
+        log::warn!("Permission denied: receiver {} lacks ReceiveMessage capability for sender {}", 
+                       message.header.receiver.0, message.header.sender.0);
+        return Err(MessageError::PermissionDenied);
+    }
+    
+    // Capabilities travel only from a sender that may grant them
+    if !may_attach(&message) {
+        SENDS_DENIED.fetch_add(1, Ordering::Relaxed);
+        log::warn!("Permission denied: sender {} attached capabilities it may not grant", 
+                       message.header.sender.0);
+        return Err(MessageError::PermissionDenied);
+    }
+    
     // Add message to receiver's queue
-    crate::ipc::queue::enqueue_message(message.header.receiver, message)?;
+    let (sender, receiver) = (message.header.sender, message.header.receiver);
+    let message_id = message.header.message_id.0;
+    crate::ipc::queue::enqueue_message(receiver, message)?;
+    crate::trace::record(Tracepoint::IpcSend, sender, receiver.0 as u64, message_id);
+    
+    // A real-time sender lends its priority while the receiver serves it
+    crate::process::realtime::message_sent(sender, receiver);
     
     Ok(())
 }
 
 /// Receive a message for the current process
 pub fn receive_message(receiver: ProcessId) -> Result<Message, MessageError> {
-    serial_println!("Process {} attempting to receive message", receiver.0);
+    log::debug!("Process {} attempting to receive message", receiver.0);
     
     // Validate receiver exists
     if crate::process::get_process(receiver).is_none() {
         return Err(MessageError::ReceiverNotFound);
     }
     
-    // Check capabilities for message receiving
-    // Note: For now, we allow any process to receive messages sent to them
-    // In a more sophisticated system, we might require ReceiveMessage capability
+    // A message the receiver may no longer take (its capability was revoked
+    // after the send) is dropped, so it cannot hold up the messages behind it
+    while let Some(sender) = crate::ipc::queue::peek_sender(receiver) {
+        if may_receive(receiver, sender) {
+            break;
+        }
+        let dropped = crate::ipc::queue::dequeue_message(receiver)?;
+        RECEIVES_DENIED.fetch_add(1, Ordering::Relaxed);
+        log::warn!("Permission denied: receiver {} lacks ReceiveMessage capability for sender {}, message {} dropped", 
+                       receiver.0, sender.0, dropped.header.message_id.0);
+    }
     
     // Get message from receiver's queue
     let message = crate::ipc::queue::dequeue_message(receiver)?;
     
-    serial_println!("Process {} received message {} from {}", 
+    log::debug!("Process {} received message {} from {}", 
                    receiver.0, message.header.message_id.0, message.header.sender.0);
+    crate::trace::record(Tracepoint::IpcReceive, receiver, message.header.sender.0 as u64, message.header.message_id.0);
     
     Ok(message)
 }
@@ -392,6 +533,9 @@ mod tests {
         assert_eq!(MessageData::Bytes(vec![1, 2, 3]).size(), 3);
         assert_eq!(MessageData::Text("hello".to_string()).size(), 5);
         
+        let shared = MessageData::SharedMemory { region_id: 1, offset: 0, length: 1 << 20 };
+        assert_eq!(shared.size(), SHARED_DESCRIPTOR_SIZE);
+        
         let syscall_data = MessageData::SystemCall {
             call_number: 1,
             args: [0; 6],
@@ -399,6 +543,23 @@ mod tests {
         assert_eq!(syscall_data.size(), 4 + 6 * 8);
     }
     
+    #[test_case]
+    fn test_shared_descriptor_roundtrip() {
+        let data = MessageData::SharedMemory { region_id: 7, offset: 4096, length: 65536 };
+        let descriptor = data.shared_descriptor().unwrap();
+        
+        match MessageData::shared_from_descriptor(&descriptor) {
+            Some(MessageData::SharedMemory { region_id, offset, length }) => {
+                assert_eq!(region_id, 7);
+                assert_eq!(offset, 4096);
+                assert_eq!(length, 65536);
+            }
+            _ => panic!("descriptor did not decode"),
+        }
+        
+        assert!(MessageData::shared_from_descriptor(&descriptor[..8]).is_none());
+    }
+    
     #[test_case]
     fn test_message_creation() {
         let sender = ProcessId::new(1);
@@ -448,4 +609,31 @@ mod tests {
         message.set_priority(0);
         assert_eq!(message.header.priority, 0);
     }
+    
+    #[test_case]
+    fn test_peers_need_a_capability_or_channel() {
+        let (client, server) = (ProcessId::new(9710), ProcessId::new(9711));
+        assert!(!may_send(client, server));
+        assert!(!may_receive(server, client));
+        assert!(may_receive(server, ProcessId::KERNEL));
+        
+        crate::ipc::security::create_secure_ipc_channel(client, server).expect("channel");
+        assert!(may_send(client, server));
+        assert!(may_send(server, client));
+        assert!(may_receive(server, client));
+        crate::ipc::security::release_process(client);
+    }
+    
+    #[test_case]
+    fn test_attached_capabilities_must_be_grantable() {
+        let (sender, receiver) = (ProcessId::new(9712), ProcessId::new(9713));
+        let mut message = Message::new(sender, receiver, MessageType::ServiceRequest, MessageData::Empty);
+        assert!(may_attach(&message));
+        
+        let mut attached = CapabilitySet::new();
+        attached.add(capability::Capability::new(CapabilityType::Read, ResourceId::Any, receiver, Some(sender)))
+            .expect("capability");
+        message.add_capabilities(attached);
+        assert!(!may_attach(&message));
+    }
 }
\ No newline at end of file
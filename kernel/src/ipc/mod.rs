@@ -2,6 +2,12 @@ pub mod message;
 pub mod queue;
 pub mod capability;
 pub mod security;
+pub mod shm;
+pub mod poll;
+pub mod irq;
+pub mod fs_client;
+pub mod pipe;
+pub mod futex;
 
 #[cfg(test)]
 pub mod capability_test;
@@ -25,13 +31,13 @@ pub use security::{
     validate_capability_request, is_restricted_operation, create_secure_ipc_channel,
     revoke_process_capabilities
 };
+pub use shm::{ShmId, ShmError, create_region, map_region, unmap_region, grant_region};
 
 use crate::process::ProcessId;
-use crate::{serial_println, println};
 
 /// IPC system initialization
 pub fn init_ipc_system() -> Result<(), &'static str> {
-    serial_println!("Initializing IPC system...");
+    log::info!("Initializing IPC system...");
     
     // Initialize message queue system
     queue::init_message_queues()?;
@@ -42,7 +48,10 @@ pub fn init_ipc_system() -> Result<(), &'static str> {
     // Initialize security policy
     security::init_security_policy()?;
     
-    serial_println!("IPC system initialized successfully");
+    // Initialize shared memory regions
+    shm::init_shared_memory()?;
+    
+    log::info!("IPC system initialized successfully");
     Ok(())
 }
 
@@ -55,12 +64,16 @@ pub struct IpcStatistics {
     pub total_capabilities: usize,
     pub capability_checks_performed: u64,
     pub capability_checks_failed: u64,
+    /// Messages refused at send and at receive for want of a capability
+    pub sends_denied: u64,
+    pub receives_denied: u64,
 }
 
 /// Get IPC system statistics
 pub fn get_ipc_statistics() -> IpcStatistics {
     let queue_stats = queue::get_queue_statistics();
     let capability_stats = capability::get_capability_statistics();
+    let (sends_denied, receives_denied) = message::denied_counts();
     
     IpcStatistics {
         total_messages_sent: queue_stats.total_messages_sent,
@@ -69,6 +82,8 @@ pub fn get_ipc_statistics() -> IpcStatistics {
         total_capabilities: capability_stats.total_capabilities,
         capability_checks_performed: capability_stats.checks_performed,
         capability_checks_failed: capability_stats.checks_failed,
+        sends_denied,
+        receives_denied,
     }
 }
 
@@ -76,26 +91,27 @@ pub fn get_ipc_statistics() -> IpcStatistics {
 pub fn print_ipc_info() {
     let stats = get_ipc_statistics();
     
-    serial_println!("IPC System Statistics:");
-    serial_println!("  Messages sent: {}", stats.total_messages_sent);
-    serial_println!("  Messages received: {}", stats.total_messages_received);
-    serial_println!("  Active message queues: {}", stats.active_message_queues);
-    serial_println!("  Total capabilities: {}", stats.total_capabilities);
-    serial_println!("  Capability checks: {} (failed: {})", 
+    log::info!("IPC System Statistics:");
+    log::info!("  Messages sent: {}", stats.total_messages_sent);
+    log::info!("  Messages received: {}", stats.total_messages_received);
+    log::info!("  Active message queues: {}", stats.active_message_queues);
+    log::info!("  Total capabilities: {}", stats.total_capabilities);
+    log::info!("  Capability checks: {} (failed: {})", 
                    stats.capability_checks_performed, stats.capability_checks_failed);
+    log::info!("  Messages denied: {} sends, {} receives", stats.sends_denied, stats.receives_denied);
     
-    println!("IPC: {} queues, {} messages sent", 
+    log::info!("IPC: {} queues, {} messages sent", 
              stats.active_message_queues, stats.total_messages_sent);
 }
 
 /// Run capability-based security tests
 #[cfg(test)]
 pub fn run_capability_tests() -> Result<(), &'static str> {
-    serial_println!("Running capability-based security tests...");
+    crate::serial_println!("Running capability-based security tests...");
     
     capability_test::test_capability_security()?;
     capability_test::test_ipc_message_security()?;
     
-    serial_println!("All capability tests passed successfully!");
+    crate::serial_println!("All capability tests passed successfully!");
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,214 @@
+//! Kernel pipes
+//!
+//! A pipe is a bounded byte queue with a read end and a write end. Each end
+//! may be held by several descriptors, in several processes once a pipe is
+//! inherited across fork. Readers block while the pipe is empty and writers
+//! while it is full; a blocked caller is woken when the other side makes
+//! progress and repeats the call. Once every write end is closed, reads
+//! return end of file; once every read end is closed, writes fail with
+//! `BrokenPipe` and the writer is sent SIGPIPE.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use crate::process::{ProcessId, ProcessState, BlockReason, get_process, set_process_state, send_signal};
+use crate::process::signal::SIGPIPE;
+
+/// Bytes a pipe holds before writers block
+pub const PIPE_CAPACITY: usize = 4096;
+
+/// Pipe identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PipeId(pub u64);
+
+/// Which side of a pipe a descriptor holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipeEnd {
+    Read,
+    Write,
+}
+
+/// Pipe errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipeError {
+    /// No pipe with this identifier exists
+    NotFound,
+    /// The pipe is empty or full; the caller was blocked until that changes
+    WouldBlock,
+    /// Every read end is closed
+    BrokenPipe,
+}
+
+struct Pipe {
+    buffer: VecDeque<u8>,
+    readers: usize,
+    writers: usize,
+    /// Processes blocked on this pipe, from either end
+    waiting: Vec<ProcessId>,
+}
+
+impl Pipe {
+    /// Block the caller until the other side makes progress
+    fn wait(&mut self, process_id: ProcessId) {
+        if !self.waiting.contains(&process_id) {
+            self.waiting.push(process_id);
+        }
+        let _ = set_process_state(process_id, ProcessState::Blocked(BlockReason::WaitingForResource));
+    }
+
+    /// Wake every process blocked on the pipe so it retries
+    fn wake_all(&mut self) {
+        for process_id in self.waiting.drain(..) {
+            let blocked = get_process(process_id)
+                .map_or(false, |info| info.state == ProcessState::Blocked(BlockReason::WaitingForResource));
+            if blocked {
+                let _ = set_process_state(process_id, ProcessState::Ready);
+            }
+        }
+    }
+}
+
+// Blocking and waking happen with this lock held, so a wakeup cannot slip
+// in between a caller finding the pipe empty and the caller blocking
+static PIPES: Mutex<BTreeMap<PipeId, Pipe>> = Mutex::new(BTreeMap::new());
+static NEXT_PIPE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Create a pipe with one read end and one write end open
+pub fn create() -> PipeId {
+    let id = PipeId(NEXT_PIPE_ID.fetch_add(1, Ordering::Relaxed));
+    PIPES.lock().insert(id, Pipe {
+        buffer: VecDeque::new(),
+        readers: 1,
+        writers: 1,
+        waiting: Vec::new(),
+    });
+    id
+}
+
+/// Count another descriptor holding `end`, as after dup or fork
+pub fn open_end(id: PipeId, end: PipeEnd) -> Result<(), PipeError> {
+    let mut pipes = PIPES.lock();
+    let pipe = pipes.get_mut(&id).ok_or(PipeError::NotFound)?;
+    match end {
+        PipeEnd::Read => pipe.readers += 1,
+        PipeEnd::Write => pipe.writers += 1,
+    }
+    Ok(())
+}
+
+/// Drop a descriptor holding `end`; the pipe goes away with its last one
+pub fn close_end(id: PipeId, end: PipeEnd) {
+    let mut pipes = PIPES.lock();
+    let Some(pipe) = pipes.get_mut(&id) else {
+        return;
+    };
+    match end {
+        PipeEnd::Read => pipe.readers = pipe.readers.saturating_sub(1),
+        PipeEnd::Write => pipe.writers = pipe.writers.saturating_sub(1),
+    }
+    // The other side may now see end of file or a broken pipe
+    pipe.wake_all();
+    if pipe.readers == 0 && pipe.writers == 0 {
+        pipes.remove(&id);
+    }
+}
+
+/// Take up to `max` bytes; an empty result means end of file
+pub fn read(process_id: ProcessId, id: PipeId, max: usize) -> Result<Vec<u8>, PipeError> {
+    let mut pipes = PIPES.lock();
+    let pipe = pipes.get_mut(&id).ok_or(PipeError::NotFound)?;
+    if max == 0 {
+        return Ok(Vec::new());
+    }
+    if pipe.buffer.is_empty() {
+        if pipe.writers == 0 {
+            return Ok(Vec::new());
+        }
+        pipe.wait(process_id);
+        return Err(PipeError::WouldBlock);
+    }
+
+    let count = core::cmp::min(max, pipe.buffer.len());
+    let data = pipe.buffer.drain(..count).collect();
+    pipe.wake_all();
+    Ok(data)
+}
+
+/// Queue as much of `data` as fits, returning how many bytes were taken
+pub fn write(process_id: ProcessId, id: PipeId, data: &[u8]) -> Result<usize, PipeError> {
+    let mut pipes = PIPES.lock();
+    let pipe = pipes.get_mut(&id).ok_or(PipeError::NotFound)?;
+    if pipe.readers == 0 {
+        drop(pipes);
+        let _ = send_signal(ProcessId::KERNEL, process_id, SIGPIPE);
+        return Err(PipeError::BrokenPipe);
+    }
+    if data.is_empty() {
+        return Ok(0);
+    }
+    let space = PIPE_CAPACITY - pipe.buffer.len();
+    if space == 0 {
+        pipe.wait(process_id);
+        return Err(PipeError::WouldBlock);
+    }
+
+    let count = core::cmp::min(space, data.len());
+    pipe.buffer.extend(&data[..count]);
+    pipe.wake_all();
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Not a real process; blocking and signalling it are no-ops
+    const TEST_PID: ProcessId = ProcessId(9301);
+
+    #[test_case]
+    fn test_pipe_transfer_and_end_of_file() {
+        let id = create();
+        assert_eq!(write(TEST_PID, id, b"hello"), Ok(5));
+        assert_eq!(read(TEST_PID, id, 3), Ok(b"hel".to_vec()));
+
+        // Readers see the rest, then end of file once the writer is gone
+        close_end(id, PipeEnd::Write);
+        assert_eq!(read(TEST_PID, id, 16), Ok(b"lo".to_vec()));
+        assert_eq!(read(TEST_PID, id, 16), Ok(Vec::new()));
+
+        close_end(id, PipeEnd::Read);
+        assert_eq!(read(TEST_PID, id, 16), Err(PipeError::NotFound));
+    }
+
+    #[test_case]
+    fn test_pipe_blocks_when_empty_or_full() {
+        let id = create();
+        assert_eq!(read(TEST_PID, id, 16), Err(PipeError::WouldBlock));
+
+        // Writes past the capacity are short, then block
+        let data = [0xabu8; PIPE_CAPACITY + 100];
+        assert_eq!(write(TEST_PID, id, &data), Ok(PIPE_CAPACITY));
+        assert_eq!(write(TEST_PID, id, &data), Err(PipeError::WouldBlock));
+        assert_eq!(read(TEST_PID, id, 100).map(|bytes| bytes.len()), Ok(100));
+        assert_eq!(write(TEST_PID, id, &data), Ok(100));
+
+        close_end(id, PipeEnd::Read);
+        close_end(id, PipeEnd::Write);
+    }
+
+    #[test_case]
+    fn test_pipe_broken_and_shared_ends() {
+        let id = create();
+        // A second reader, as after fork, keeps the pipe usable
+        assert_eq!(open_end(id, PipeEnd::Read), Ok(()));
+        close_end(id, PipeEnd::Read);
+        assert_eq!(write(TEST_PID, id, b"x"), Ok(1));
+
+        close_end(id, PipeEnd::Read);
+        assert_eq!(write(TEST_PID, id, b"x"), Err(PipeError::BrokenPipe));
+
+        close_end(id, PipeEnd::Write);
+        assert_eq!(open_end(id, PipeEnd::Write), Err(PipeError::NotFound));
+    }
+}
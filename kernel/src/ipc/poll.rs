@@ -0,0 +1,192 @@
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+use crate::process::{ProcessId, ProcessState, BlockReason, set_process_state};
+use crate::ipc::queue;
+
+/// Poll event: a message is waiting to be received
+pub const POLL_IN: u32 = 0x1;
+
+/// Handle that matches messages from any sender
+pub const POLL_ANY_SENDER: u64 = 0;
+
+/// Timeout value that waits until an event arrives
+pub const POLL_INFINITE: u64 = u64::MAX;
+
+/// Maximum number of entries accepted by a single poll call
+pub const MAX_POLL_ENTRIES: usize = 64;
+
+/// One entry of the poll set shared with user space
+///
+/// `handle` names the channel to watch: the process ID of the peer, or
+/// `POLL_ANY_SENDER` for the caller's whole message queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct PollEntry {
+    pub handle: u64,
+    pub events: u32,
+    pub revents: u32,
+}
+
+/// Outcome of a poll attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollResult {
+    /// This many entries have events pending
+    Ready(usize),
+    /// The timeout elapsed without any event
+    TimedOut,
+    /// The caller was blocked and must poll again once rescheduled
+    Blocked,
+}
+
+/// A process sleeping in poll
+#[derive(Debug, Clone, Copy)]
+struct PollWaiter {
+    /// Milliseconds left before the wait times out (None = infinite)
+    remaining_ms: Option<u64>,
+    timed_out: bool,
+}
+
+/// Global table of processes blocked in poll
+static POLL_WAITERS: Mutex<BTreeMap<ProcessId, PollWaiter>> = Mutex::new(BTreeMap::new());
+
+/// Fill in `revents` for every entry and return the number of ready entries
+pub fn check_ready(process_id: ProcessId, entries: &mut [PollEntry]) -> usize {
+    let mut ready = 0;
+
+    for entry in entries.iter_mut() {
+        entry.revents = 0;
+
+        if entry.events & POLL_IN != 0 {
+            let sender = if entry.handle == POLL_ANY_SENDER {
+                None
+            } else {
+                Some(ProcessId::new(entry.handle as u32))
+            };
+
+            if queue::has_pending_message(process_id, sender) {
+                entry.revents |= POLL_IN;
+            }
+        }
+
+        if entry.revents != 0 {
+            ready += 1;
+        }
+    }
+
+    ready
+}
+
+/// Poll a set of channels, blocking the caller when nothing is ready
+///
+/// A blocked caller is woken when a message arrives or the timeout expires;
+/// it then repeats the call and receives `Ready` or `TimedOut`.
+pub fn poll(process_id: ProcessId, entries: &mut [PollEntry], timeout_ms: u64) -> PollResult {
+    let ready = check_ready(process_id, entries);
+    if ready > 0 {
+        POLL_WAITERS.lock().remove(&process_id);
+        return PollResult::Ready(ready);
+    }
+
+    {
+        let mut waiters = POLL_WAITERS.lock();
+        if let Some(waiter) = waiters.get(&process_id) {
+            if waiter.timed_out {
+                waiters.remove(&process_id);
+                return PollResult::TimedOut;
+            }
+            // Woken without an event; keep waiting on the original deadline
+        } else {
+            if timeout_ms == 0 {
+                return PollResult::TimedOut;
+            }
+            waiters.insert(process_id, PollWaiter {
+                remaining_ms: if timeout_ms == POLL_INFINITE { None } else { Some(timeout_ms) },
+                timed_out: false,
+            });
+        }
+    }
+
+    // A message may have arrived while the waiter was being registered
+    let ready = check_ready(process_id, entries);
+    if ready > 0 {
+        POLL_WAITERS.lock().remove(&process_id);
+        return PollResult::Ready(ready);
+    }
+
+    // With nothing left to handle, the process no longer runs for others
+    crate::process::realtime::idle(process_id);
+    let _ = set_process_state(process_id, ProcessState::Blocked(BlockReason::WaitingForMessage));
+    PollResult::Blocked
+}
+
+/// Wake a process blocked in poll because a message was queued for it
+pub fn notify_message(receiver: ProcessId) {
+    let waiting = POLL_WAITERS.lock().contains_key(&receiver);
+    if waiting {
+        log::debug!("Waking process {} blocked in poll", receiver.0);
+        let _ = set_process_state(receiver, ProcessState::Ready);
+    }
+}
+
+/// Advance poll timeouts by `elapsed_ms`, waking waiters whose time is up
+pub fn timer_tick(elapsed_ms: u64) {
+    let mut expired = alloc::vec::Vec::new();
+
+    {
+        let mut waiters = POLL_WAITERS.lock();
+        for (pid, waiter) in waiters.iter_mut() {
+            if waiter.timed_out {
+                continue;
+            }
+            if let Some(remaining) = waiter.remaining_ms.as_mut() {
+                *remaining = remaining.saturating_sub(elapsed_ms);
+                if *remaining == 0 {
+                    waiter.timed_out = true;
+                    expired.push(*pid);
+                }
+            }
+        }
+    }
+
+    for pid in expired {
+        let _ = set_process_state(pid, ProcessState::Ready);
+    }
+}
+
+/// Forget any poll state of a terminating process
+pub fn release_process(process_id: ProcessId) {
+    POLL_WAITERS.lock().remove(&process_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_poll_entry_layout() {
+        assert_eq!(core::mem::size_of::<PollEntry>(), 16);
+    }
+
+    #[test_case]
+    fn test_poll_zero_timeout_does_not_block() {
+        let pid = ProcessId::new(9001);
+        let mut entries = [PollEntry { handle: POLL_ANY_SENDER, events: POLL_IN, revents: 0 }];
+
+        assert_eq!(poll(pid, &mut entries, 0), PollResult::TimedOut);
+        assert_eq!(entries[0].revents, 0);
+        assert!(!POLL_WAITERS.lock().contains_key(&pid));
+    }
+
+    #[test_case]
+    fn test_poll_timeout_expires() {
+        let pid = ProcessId::new(9002);
+        let mut entries = [PollEntry { handle: POLL_ANY_SENDER, events: POLL_IN, revents: 0 }];
+
+        assert_eq!(poll(pid, &mut entries, 20), PollResult::Blocked);
+        timer_tick(10);
+        assert_eq!(poll(pid, &mut entries, 20), PollResult::Blocked);
+        timer_tick(10);
+        assert_eq!(poll(pid, &mut entries, 20), PollResult::TimedOut);
+        assert!(!POLL_WAITERS.lock().contains_key(&pid));
+    }
+}
@@ -1,9 +1,10 @@
 use alloc::collections::VecDeque;
 use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
 use spin::Mutex;
 use crate::process::ProcessId;
-use crate::ipc::message::{Message, MessageError};
-use crate::{serial_println};
+use crate::ipc::message::{Message, MessageError, MessageId, MessageType};
+use crate::memory::slab::{SlabBox, MESSAGE_CACHE};
 
 /// Maximum number of messages per process queue
 const MAX_MESSAGES_PER_QUEUE: usize = 256;
@@ -16,8 +17,8 @@ const MAX_QUEUE_SIZE_BYTES: usize = 64 * 1024; // 64KB per queue
 pub struct MessageQueue {
     /// Process ID that owns this queue
     pub process_id: ProcessId,
-    /// Queue of pending messages
-    pub messages: VecDeque<Message>,
+    /// Queue of pending messages, each in the message slab cache
+    pub messages: VecDeque<SlabBox<Message>>,
     /// Current total size of messages in bytes
     pub total_size: usize,
     /// Maximum number of messages allowed
@@ -76,11 +77,12 @@ impl MessageQueue {
             .position(|m| m.header.priority > message.header.priority)
             .unwrap_or(self.messages.len());
         
+        let message = SlabBox::new(&MESSAGE_CACHE, message).map_err(|_| MessageError::ResourceExhausted)?;
         self.messages.insert(insert_pos, message);
         self.total_size += message_size;
         self.messages_received += 1;
         
-        serial_println!("Enqueued message for process {} (queue size: {})", 
+        log::debug!("Enqueued message for process {} (queue size: {})", 
                        self.process_id.0, self.messages.len());
         
         Ok(())
@@ -92,10 +94,10 @@ impl MessageQueue {
             let message_size = message.total_size();
             self.total_size = self.total_size.saturating_sub(message_size);
             
-            serial_println!("Dequeued message for process {} (queue size: {})", 
+            log::debug!("Dequeued message for process {} (queue size: {})", 
                            self.process_id.0, self.messages.len());
             
-            Ok(message)
+            Ok(SlabBox::into_inner(message))
         } else {
             Err(MessageError::NoMessage)
         }
@@ -103,7 +105,7 @@ impl MessageQueue {
     
     /// Peek at the next message without removing it
     pub fn peek(&self) -> Option<&Message> {
-        self.messages.front()
+        self.messages.front().map(|message| &**message)
     }
     
     /// Get the number of messages in the queue
@@ -139,7 +141,7 @@ impl MessageQueue {
     pub fn clear(&mut self) {
         self.messages.clear();
         self.total_size = 0;
-        serial_println!("Cleared message queue for process {}", self.process_id.0);
+        log::debug!("Cleared message queue for process {}", self.process_id.0);
     }
 }
 
@@ -199,7 +201,7 @@ impl MessageQueueManager {
         self.queues.insert(process_id, queue);
         self.total_queues_created += 1;
         
-        serial_println!("Created message queue for process {}", process_id.0);
+        log::debug!("Created message queue for process {}", process_id.0);
         Ok(())
     }
     
@@ -211,7 +213,21 @@ impl MessageQueueManager {
         self.queues.get_mut(&process_id).unwrap()
     }
     
-    /// Remove a message queue for a process
+    /// Check whether a process has a message pending, optionally from a specific sender
+pub fn has_pending_message(process_id: ProcessId, sender: Option<ProcessId>) -> bool {
+    let manager = MESSAGE_QUEUE_MANAGER.lock();
+    let queue = match manager.as_ref().and_then(|manager| manager.queues.get(&process_id)) {
+        Some(queue) => queue,
+        None => return false,
+    };
+    
+    match sender {
+        Some(sender) => queue.messages.iter().any(|message| message.header.sender == sender),
+        None => !queue.is_empty(),
+    }
+}
+
+/// Remove a message queue for a process
     fn remove_queue(&mut self, process_id: ProcessId) -> Result<MessageQueue, MessageQueueError> {
         self.queues.remove(&process_id)
             .ok_or(MessageQueueError::QueueNotFound)
@@ -281,12 +297,12 @@ static MESSAGE_QUEUE_MANAGER: Mutex<Option<MessageQueueManager>> = Mutex::new(No
 
 /// Initialize the message queue system
 pub fn init_message_queues() -> Result<(), &'static str> {
-    serial_println!("Initializing message queue system...");
+    log::info!("Initializing message queue system...");
     
     let manager = MessageQueueManager::new();
     *MESSAGE_QUEUE_MANAGER.lock() = Some(manager);
     
-    serial_println!("Message queue system initialized");
+    log::info!("Message queue system initialized");
     Ok(())
 }
 
@@ -306,9 +322,15 @@ pub fn get_message_queue(process_id: ProcessId) -> Option<MessageQueueStatistics
 
 /// Enqueue a message to a process's queue
 pub fn enqueue_message(process_id: ProcessId, message: Message) -> Result<(), MessageError> {
-    let mut manager = MESSAGE_QUEUE_MANAGER.lock();
-    let manager = manager.as_mut().ok_or(MessageError::ResourceExhausted)?;
-    manager.enqueue_message(process_id, message)
+    {
+        let mut manager = MESSAGE_QUEUE_MANAGER.lock();
+        let manager = manager.as_mut().ok_or(MessageError::ResourceExhausted)?;
+        manager.enqueue_message(process_id, message)?;
+    }
+    
+    // Wake the receiver if it is blocked in poll (queue lock released first)
+    crate::ipc::poll::notify_message(process_id);
+    Ok(())
 }
 
 /// Dequeue a message from a process's queue
@@ -318,6 +340,24 @@ pub fn dequeue_message(process_id: ProcessId) -> Result<Message, MessageError> {
     manager.dequeue_message(process_id)
 }
 
+/// Get the payload size of the next pending message for a process
+pub fn peek_message_size(process_id: ProcessId) -> Option<usize> {
+    let manager = MESSAGE_QUEUE_MANAGER.lock();
+    let manager = manager.as_ref()?;
+    manager.queues.get(&process_id)?
+        .peek()
+        .map(|message| message.data.delivered_len())
+}
+
+/// Get the sender of the next pending message for a process
+pub fn peek_sender(process_id: ProcessId) -> Option<ProcessId> {
+    let manager = MESSAGE_QUEUE_MANAGER.lock();
+    let manager = manager.as_ref()?;
+    manager.queues.get(&process_id)?
+        .peek()
+        .map(|message| message.header.sender)
+}
+
 /// Remove a message queue for a process
 pub fn remove_message_queue(process_id: ProcessId) -> Result<(), MessageQueueError> {
     let mut manager = MESSAGE_QUEUE_MANAGER.lock();
@@ -343,17 +383,76 @@ pub fn get_queue_statistics() -> GlobalQueueStatistics {
     }
 }
 
+/// Payload bytes `try_queue_contents` copies of each message
+pub const CONTENTS_PAYLOAD_BYTES: usize = 16;
+
+/// A pending message as the debug monitor lists it
+#[derive(Debug, Clone)]
+pub struct PendingMessage {
+    pub message_id: MessageId,
+    pub sender: ProcessId,
+    pub message_type: MessageType,
+    /// Bytes the receiver will be handed
+    pub size: usize,
+    pub timestamp: u64,
+    /// The first `CONTENTS_PAYLOAD_BYTES` of the payload
+    pub payload: Vec<u8>,
+}
+
+/// What one queue holds, oldest message first
+#[derive(Debug, Clone)]
+pub struct QueueContents {
+    pub process_id: ProcessId,
+    pub pending: usize,
+    pub total_size: usize,
+    /// Up to the number of messages asked for
+    pub messages: Vec<PendingMessage>,
+}
+
+/// Every queue with messages pending and the first `max_messages` of
+/// them, or None while the queues are locked
+///
+/// Never waits, so it can run from a panic on the CPU that held the lock.
+pub fn try_queue_contents(max_messages: usize) -> Option<Vec<QueueContents>> {
+    let manager = MESSAGE_QUEUE_MANAGER.try_lock()?;
+    let Some(manager) = manager.as_ref() else {
+        return Some(Vec::new());
+    };
+    Some(manager.queues.values()
+        .filter(|queue| !queue.is_empty())
+        .map(|queue| QueueContents {
+            process_id: queue.process_id,
+            pending: queue.len(),
+            total_size: queue.total_size,
+            messages: queue.messages.iter()
+                .take(max_messages)
+                .map(|message| {
+                    let payload = message.data.as_bytes();
+                    PendingMessage {
+                        message_id: message.header.message_id,
+                        sender: message.header.sender,
+                        message_type: message.header.message_type,
+                        size: message.data.delivered_len(),
+                        timestamp: message.header.timestamp,
+                        payload: payload[..payload.len().min(CONTENTS_PAYLOAD_BYTES)].to_vec(),
+                    }
+                })
+                .collect(),
+        })
+        .collect())
+}
+
 /// Print message queue information
 pub fn print_queue_info() {
     let stats = get_queue_statistics();
     
-    serial_println!("Message Queue Statistics:");
-    serial_println!("  Active queues: {}", stats.active_queues);
-    serial_println!("  Pending messages: {}", stats.total_pending_messages);
-    serial_println!("  Messages sent: {}", stats.total_messages_sent);
-    serial_println!("  Messages received: {}", stats.total_messages_received);
-    serial_println!("  Queue full events: {}", stats.total_queue_full_events);
-    serial_println!("  Total queues created: {}", stats.total_queues_created);
+    log::debug!("Message Queue Statistics:");
+    log::debug!("  Active queues: {}", stats.active_queues);
+    log::debug!("  Pending messages: {}", stats.total_pending_messages);
+    log::debug!("  Messages sent: {}", stats.total_messages_sent);
+    log::debug!("  Messages received: {}", stats.total_messages_received);
+    log::debug!("  Queue full events: {}", stats.total_queue_full_events);
+    log::debug!("  Total queues created: {}", stats.total_queues_created);
 }
 
 #[cfg(test)]
@@ -1,10 +1,10 @@
 use alloc::{vec, vec::Vec};
+use alloc::collections::BTreeSet;
 use alloc::string::String;
 use crate::process::ProcessId;
 use crate::ipc::capability::{
     CapabilityType, ResourceId, CapabilityError, create_capability, check_capability
 };
-use crate::{serial_println};
 
 /// Security policy for the IPC system
 pub struct SecurityPolicy {
@@ -61,17 +61,17 @@ impl SecurityPolicy {
     pub fn grant_system_capabilities(&self, process_id: ProcessId) -> Result<Vec<crate::ipc::capability::CapabilityId>, CapabilityError> {
         let mut granted_capabilities = Vec::new();
         
-        serial_println!("Granting system capabilities to process {}", process_id.0);
+        log::debug!("Granting system capabilities to process {}", process_id.0);
         
         for (capability_type, resource) in &self.system_capabilities {
             match create_capability(process_id, *capability_type, resource.clone(), None) {
                 Ok(cap_id) => {
                     granted_capabilities.push(cap_id);
-                    serial_println!("Granted {} capability for {} to process {}", 
+                    log::debug!("Granted {} capability for {} to process {}", 
                                    capability_type, resource, process_id.0);
                 }
                 Err(e) => {
-                    serial_println!("Failed to grant {} capability to process {}: {}", 
+                    log::warn!("Failed to grant {} capability to process {}: {}", 
                                    capability_type, process_id.0, e);
                     return Err(e);
                 }
@@ -85,17 +85,17 @@ impl SecurityPolicy {
     pub fn grant_user_capabilities(&self, process_id: ProcessId) -> Result<Vec<crate::ipc::capability::CapabilityId>, CapabilityError> {
         let mut granted_capabilities = Vec::new();
         
-        serial_println!("Granting user capabilities to process {}", process_id.0);
+        log::debug!("Granting user capabilities to process {}", process_id.0);
         
         for (capability_type, resource) in &self.user_capabilities {
             match create_capability(process_id, *capability_type, resource.clone(), None) {
                 Ok(cap_id) => {
                     granted_capabilities.push(cap_id);
-                    serial_println!("Granted {} capability for {} to process {}", 
+                    log::debug!("Granted {} capability for {} to process {}", 
                                    capability_type, resource, process_id.0);
                 }
                 Err(e) => {
-                    serial_println!("Failed to grant {} capability to process {}: {}", 
+                    log::warn!("Failed to grant {} capability to process {}: {}", 
                                    capability_type, process_id.0, e);
                     return Err(e);
                 }
@@ -119,7 +119,7 @@ impl SecurityPolicy {
     ) -> bool {
         // Check if the operation is restricted
         if self.is_restricted_operation(capability_type) {
-            serial_println!("Restricted operation {} requested by process {}", 
+            log::debug!("Restricted operation {} requested by process {}", 
                            capability_type, requester.0);
             
             // Only allow if the requester has admin privileges
@@ -157,11 +157,11 @@ static SECURITY_POLICY: Mutex<Option<SecurityPolicy>> = Mutex::new(None);
 
 /// Initialize the security policy system
 pub fn init_security_policy() -> Result<(), &'static str> {
-    serial_println!("Initializing security policy...");
+    log::info!("Initializing security policy...");
     
     *SECURITY_POLICY.lock() = Some(SecurityPolicy::new());
     
-    serial_println!("Security policy initialized");
+    log::info!("Security policy initialized");
     Ok(())
 }
 
@@ -186,6 +186,17 @@ pub fn grant_user_process_capabilities(process_id: ProcessId) -> Result<Vec<crat
         .unwrap_or(Err(CapabilityError::ResourceExhausted))
 }
 
+/// Grant a newly created process the policy's default capabilities
+///
+/// A process started with no parent, such as init, is a system process;
+/// any other gets the user set on top of what it inherits from its parent.
+pub fn grant_default_capabilities(process_id: ProcessId, parent: Option<ProcessId>) -> Result<Vec<crate::ipc::capability::CapabilityId>, CapabilityError> {
+    match parent {
+        None => grant_system_process_capabilities(process_id),
+        Some(_) => grant_user_process_capabilities(process_id),
+    }
+}
+
 /// Validate a capability request against the security policy
 pub fn validate_capability_request(
     requester: ProcessId,
@@ -207,7 +218,7 @@ pub fn create_secure_ipc_channel(
     process_a: ProcessId,
     process_b: ProcessId,
 ) -> Result<(), CapabilityError> {
-    serial_println!("Creating secure IPC channel between processes {} and {}", 
+    log::debug!("Creating secure IPC channel between processes {} and {}", 
                    process_a.0, process_b.0);
     
     // Grant bidirectional message passing capabilities
@@ -225,17 +236,35 @@ pub fn create_secure_ipc_channel(
         None,
     )?;
     
-    serial_println!("Secure IPC channel created successfully");
+    SECURE_CHANNELS.lock().insert(channel_key(process_a, process_b));
+    log::debug!("Secure IPC channel created successfully");
     Ok(())
 }
 
+/// Pairs of processes joined by `create_secure_ipc_channel`, lower ID first
+static SECURE_CHANNELS: Mutex<BTreeSet<(ProcessId, ProcessId)>> = Mutex::new(BTreeSet::new());
+
+fn channel_key(process_a: ProcessId, process_b: ProcessId) -> (ProcessId, ProcessId) {
+    (process_a.min(process_b), process_a.max(process_b))
+}
+
+/// Whether a secure IPC channel joins `process_a` and `process_b`
+pub fn in_secure_channel(process_a: ProcessId, process_b: ProcessId) -> bool {
+    SECURE_CHANNELS.lock().contains(&channel_key(process_a, process_b))
+}
+
+/// Close the secure channels of an exiting process, so a process that
+/// later gets its ID is not a member
+pub fn release_process(process_id: ProcessId) {
+    SECURE_CHANNELS.lock().retain(|&(a, b)| a != process_id && b != process_id);
+}
+
 /// Revoke all capabilities for a process (used when process terminates)
 pub fn revoke_process_capabilities(process_id: ProcessId) -> Result<(), CapabilityError> {
-    serial_println!("Revoking all capabilities for process {}", process_id.0);
+    log::debug!("Revoking all capabilities for process {}", process_id.0);
     
-    // In a real implementation, we would iterate through all capabilities
-    // and revoke those owned by the process. For now, we'll just log it.
-    serial_println!("Process {} capabilities revoked", process_id.0);
+    let revoked = crate::ipc::capability::release_process(process_id)?;
+    log::debug!("Process {} capabilities revoked ({} in total)", process_id.0, revoked);
     
     Ok(())
 }
@@ -282,4 +311,16 @@ mod tests {
             &ResourceId::Process(ProcessId::new(2)),
         ));
     }
+    
+    #[test_case]
+    fn test_secure_channel_membership() {
+        let (a, b, other) = (ProcessId::new(9700), ProcessId::new(9701), ProcessId::new(9702));
+        create_secure_ipc_channel(b, a).expect("channel");
+        assert!(in_secure_channel(a, b));
+        assert!(in_secure_channel(b, a));
+        assert!(!in_secure_channel(a, other));
+        
+        release_process(a);
+        assert!(!in_secure_channel(a, b));
+    }
 }
\ No newline at end of file
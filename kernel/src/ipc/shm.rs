@@ -0,0 +1,360 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+use crate::process::ProcessId;
+use crate::memory::{PAGE_SIZE, bytes_to_pages};
+use crate::memory::physical::{self, PageFrame};
+use crate::memory::vmm::{self, VirtualAddress, MemoryProtection};
+use crate::ipc::capability::{CapabilityType, ResourceId, create_capability, check_capability};
+
+/// Base of the virtual window shared memory regions are mapped into
+const SHM_WINDOW_BASE: usize = 0x0000_5000_0000_0000;
+
+/// Size of the shared memory mapping window
+const SHM_WINDOW_SIZE: usize = 0x0000_0010_0000_0000;
+
+/// Largest single shared memory region (64 MiB)
+pub const MAX_SHM_REGION_SIZE: usize = 64 * 1024 * 1024;
+
+/// Mapping protection bits accepted by `map_region`
+pub const SHM_PROT_READ: u64 = 0x1;
+pub const SHM_PROT_WRITE: u64 = 0x2;
+
+/// Shared memory region identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ShmId(pub u64);
+
+/// A physically contiguous region that can be mapped by several processes
+#[derive(Debug)]
+struct SharedRegion {
+    id: ShmId,
+    owner: ProcessId,
+    start_frame: PageFrame,
+    page_count: usize,
+    /// Processes that currently have the region mapped, with the mapping address
+    mappings: Vec<(ProcessId, VirtualAddress)>,
+    /// The owner released the region; it is freed once the last mapping goes away
+    released: bool,
+    /// Contents have been cleared since allocation
+    zeroed: bool,
+}
+
+impl SharedRegion {
+    fn size(&self) -> usize {
+        self.page_count * PAGE_SIZE
+    }
+
+    fn mapping_for(&self, process_id: ProcessId) -> Option<VirtualAddress> {
+        self.mappings.iter()
+            .find(|(pid, _)| *pid == process_id)
+            .map(|(_, addr)| *addr)
+    }
+}
+
+/// Shared memory errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShmError {
+    /// Size is zero or exceeds the per-region limit
+    InvalidSize,
+    /// No region with this identifier exists
+    RegionNotFound,
+    /// Caller lacks the capability for this operation
+    PermissionDenied,
+    /// Not enough physical memory or mapping window space
+    OutOfMemory,
+    /// Page table update failed
+    MappingFailed,
+    /// Caller does not have the region mapped
+    NotMapped,
+}
+
+impl fmt::Display for ShmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShmError::InvalidSize => write!(f, "Invalid shared memory size"),
+            ShmError::RegionNotFound => write!(f, "Shared memory region not found"),
+            ShmError::PermissionDenied => write!(f, "Permission denied"),
+            ShmError::OutOfMemory => write!(f, "Out of memory"),
+            ShmError::MappingFailed => write!(f, "Failed to map shared memory"),
+            ShmError::NotMapped => write!(f, "Shared memory region not mapped"),
+        }
+    }
+}
+
+/// Shared memory statistics
+#[derive(Debug, Clone, Copy)]
+pub struct ShmStatistics {
+    pub active_regions: usize,
+    pub total_pages: usize,
+    pub active_mappings: usize,
+}
+
+struct ShmManager {
+    regions: BTreeMap<ShmId, SharedRegion>,
+    next_id: u64,
+    /// Next free address in the mapping window (addresses are never reused)
+    next_address: usize,
+}
+
+impl ShmManager {
+    fn new() -> Self {
+        Self {
+            regions: BTreeMap::new(),
+            next_id: 1,
+            next_address: SHM_WINDOW_BASE,
+        }
+    }
+
+    fn create(&mut self, owner: ProcessId, size: usize) -> Result<ShmId, ShmError> {
+        if size == 0 || size > MAX_SHM_REGION_SIZE {
+            return Err(ShmError::InvalidSize);
+        }
+
+        let page_count = bytes_to_pages(size);
+        let start_frame = physical::allocate_frames(page_count).ok_or(ShmError::OutOfMemory)?;
+
+        let id = ShmId(self.next_id);
+        self.next_id += 1;
+
+        // The creator may read, write and hand out access to the region
+        let resource = ResourceId::SharedMemory(id.0);
+        let granted = create_capability(owner, CapabilityType::Read, resource.clone(), None)
+            .and_then(|_| create_capability(owner, CapabilityType::Write, resource, None));
+        if granted.is_err() {
+            physical::deallocate_frames(start_frame, page_count);
+            return Err(ShmError::PermissionDenied);
+        }
+
+        self.regions.insert(id, SharedRegion {
+            id,
+            owner,
+            start_frame,
+            page_count,
+            mappings: Vec::new(),
+            released: false,
+            zeroed: false,
+        });
+
+        log::debug!("SHM: process {} created region {} ({} pages)", owner.0, id.0, page_count);
+        Ok(id)
+    }
+
+    fn map(&mut self, process_id: ProcessId, id: ShmId, writable: bool) -> Result<VirtualAddress, ShmError> {
+        let region = self.regions.get(&id).ok_or(ShmError::RegionNotFound)?;
+        if region.released {
+            return Err(ShmError::RegionNotFound);
+        }
+
+        let resource = ResourceId::SharedMemory(id.0);
+        if !check_capability(process_id, CapabilityType::Read, &resource) {
+            return Err(ShmError::PermissionDenied);
+        }
+        if writable && !check_capability(process_id, CapabilityType::Write, &resource) {
+            return Err(ShmError::PermissionDenied);
+        }
+
+        if let Some(address) = region.mapping_for(process_id) {
+            return Ok(address);
+        }
+
+        let size = region.size();
+        if self.next_address + size > SHM_WINDOW_BASE + SHM_WINDOW_SIZE {
+            return Err(ShmError::OutOfMemory);
+        }
+        let address = VirtualAddress::new(self.next_address);
+
+        let protection = MemoryProtection {
+            readable: true,
+            writable,
+            executable: false,
+            user_accessible: true,
+            uncached: false,
+        };
+        vmm::map_user_range(process_id, address, region.start_frame.address(), size, protection)
+            .map_err(|_| ShmError::MappingFailed)?;
+        self.next_address += size;
+
+        let region = self.regions.get_mut(&id).ok_or(ShmError::RegionNotFound)?;
+        if !region.zeroed {
+            // Never leak stale frame contents to user space
+            unsafe {
+                core::ptr::write_bytes(address.as_usize() as *mut u8, 0, size);
+            }
+            region.zeroed = true;
+        }
+        region.mappings.push((process_id, address));
+
+        log::debug!("SHM: process {} mapped region {} at 0x{:x}", process_id.0, id.0, address.as_usize());
+        Ok(address)
+    }
+
+    fn unmap(&mut self, process_id: ProcessId, id: ShmId) -> Result<(), ShmError> {
+        let region = self.regions.get_mut(&id).ok_or(ShmError::RegionNotFound)?;
+
+        let index = region.mappings.iter()
+            .position(|(pid, _)| *pid == process_id)
+            .ok_or(ShmError::NotMapped)?;
+        let (_, address) = region.mappings.swap_remove(index);
+
+        for page in 0..region.page_count {
+            let _ = vmm::unmap_user_page(process_id, VirtualAddress::new(address.as_usize() + page * PAGE_SIZE));
+        }
+
+        if region.owner == process_id {
+            region.released = true;
+        }
+
+        log::debug!("SHM: process {} unmapped region {}", process_id.0, id.0);
+        self.reap(id);
+        Ok(())
+    }
+
+    fn grant(&mut self, granter: ProcessId, id: ShmId, target: ProcessId, writable: bool) -> Result<(), ShmError> {
+        let region = self.regions.get(&id).ok_or(ShmError::RegionNotFound)?;
+        if region.owner != granter {
+            return Err(ShmError::PermissionDenied);
+        }
+
+        let resource = ResourceId::SharedMemory(id.0);
+        create_capability(target, CapabilityType::Read, resource.clone(), Some(granter))
+            .map_err(|_| ShmError::PermissionDenied)?;
+        if writable {
+            create_capability(target, CapabilityType::Write, resource, Some(granter))
+                .map_err(|_| ShmError::PermissionDenied)?;
+        }
+
+        log::debug!("SHM: process {} granted region {} to process {} (writable: {})",
+                       granter.0, id.0, target.0, writable);
+        Ok(())
+    }
+
+    /// Drop every mapping held by a process (used on process exit)
+    fn release_process(&mut self, process_id: ProcessId) {
+        let ids: Vec<ShmId> = self.regions.values()
+            .filter(|region| region.owner == process_id || region.mapping_for(process_id).is_some())
+            .map(|region| region.id)
+            .collect();
+
+        for id in ids {
+            if self.unmap(process_id, id).is_err() {
+                if let Some(region) = self.regions.get_mut(&id) {
+                    if region.owner == process_id {
+                        region.released = true;
+                    }
+                }
+                self.reap(id);
+            }
+        }
+    }
+
+    /// Free a released region once nobody maps it any more
+    fn reap(&mut self, id: ShmId) {
+        let unused = self.regions.get(&id)
+            .map(|region| region.released && region.mappings.is_empty())
+            .unwrap_or(false);
+
+        if unused {
+            if let Some(region) = self.regions.remove(&id) {
+                physical::deallocate_frames(region.start_frame, region.page_count);
+                log::debug!("SHM: freed region {}", id.0);
+            }
+        }
+    }
+
+    fn statistics(&self) -> ShmStatistics {
+        ShmStatistics {
+            active_regions: self.regions.len(),
+            total_pages: self.regions.values().map(|region| region.page_count).sum(),
+            active_mappings: self.regions.values().map(|region| region.mappings.len()).sum(),
+        }
+    }
+}
+
+/// Global shared memory manager
+static SHM_MANAGER: Mutex<Option<ShmManager>> = Mutex::new(None);
+
+/// Initialize the shared memory subsystem
+pub fn init_shared_memory() -> Result<(), &'static str> {
+    log::info!("Initializing shared memory subsystem...");
+    *SHM_MANAGER.lock() = Some(ShmManager::new());
+    Ok(())
+}
+
+/// Create a shared memory region owned by `owner`
+///
+/// Requires the MemoryManagement capability for the "shm" system resource.
+pub fn create_region(owner: ProcessId, size: usize) -> Result<ShmId, ShmError> {
+    if !check_capability(owner, CapabilityType::MemoryManagement, &ResourceId::System(String::from("shm"))) {
+        return Err(ShmError::PermissionDenied);
+    }
+
+    let mut manager = SHM_MANAGER.lock();
+    let manager = manager.as_mut().ok_or(ShmError::OutOfMemory)?;
+    manager.create(owner, size)
+}
+
+/// Map a region into the caller's address space, returning its address
+pub fn map_region(process_id: ProcessId, id: ShmId, prot: u64) -> Result<VirtualAddress, ShmError> {
+    let mut manager = SHM_MANAGER.lock();
+    let manager = manager.as_mut().ok_or(ShmError::RegionNotFound)?;
+    manager.map(process_id, id, prot & SHM_PROT_WRITE != 0)
+}
+
+/// Unmap a region from the caller's address space
+pub fn unmap_region(process_id: ProcessId, id: ShmId) -> Result<(), ShmError> {
+    let mut manager = SHM_MANAGER.lock();
+    let manager = manager.as_mut().ok_or(ShmError::RegionNotFound)?;
+    manager.unmap(process_id, id)
+}
+
+/// Allow another process to map a region owned by the caller
+pub fn grant_region(granter: ProcessId, id: ShmId, target: ProcessId, writable: bool) -> Result<(), ShmError> {
+    let mut manager = SHM_MANAGER.lock();
+    let manager = manager.as_mut().ok_or(ShmError::RegionNotFound)?;
+    manager.grant(granter, id, target, writable)
+}
+
+/// Check whether a process may reference a region in a message
+pub fn can_access(process_id: ProcessId, id: ShmId) -> bool {
+    let manager = SHM_MANAGER.lock();
+    let exists = manager.as_ref()
+        .map(|manager| manager.regions.contains_key(&id))
+        .unwrap_or(false);
+    exists && check_capability(process_id, CapabilityType::Read, &ResourceId::SharedMemory(id.0))
+}
+
+/// Release all regions owned or mapped by a terminating process
+pub fn release_process_regions(process_id: ProcessId) {
+    if let Some(manager) = SHM_MANAGER.lock().as_mut() {
+        manager.release_process(process_id);
+    }
+}
+
+/// Get shared memory statistics
+pub fn get_shm_statistics() -> ShmStatistics {
+    SHM_MANAGER.lock()
+        .as_ref()
+        .map(|manager| manager.statistics())
+        .unwrap_or(ShmStatistics { active_regions: 0, total_pages: 0, active_mappings: 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_shm_rejects_invalid_size() {
+        let mut manager = ShmManager::new();
+        assert_eq!(manager.create(ProcessId::new(1), 0), Err(ShmError::InvalidSize));
+        assert_eq!(manager.create(ProcessId::new(1), MAX_SHM_REGION_SIZE + 1), Err(ShmError::InvalidSize));
+    }
+
+    #[test_case]
+    fn test_shm_unknown_region() {
+        let mut manager = ShmManager::new();
+        assert_eq!(manager.map(ProcessId::new(1), ShmId(42), false), Err(ShmError::RegionNotFound));
+        assert_eq!(manager.unmap(ProcessId::new(1), ShmId(42)), Err(ShmError::RegionNotFound));
+    }
+}
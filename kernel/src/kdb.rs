@@ -0,0 +1,504 @@
+//! Kernel debug monitor on the serial console
+//!
+//! Ctrl+] then `d` on the serial line stops the boot CPU in a small
+//! command loop, read and echoed on the UART without interrupts, to look
+//! at registers, memory, page tables, processes, threads, message queues,
+//! the kernel log and backtraces of a machine that has no screen or whose
+//! userspace no longer answers. `continue` lets it run again.
+//!
+//! Like the F12 dashboard, the escape only records the request and the
+//! boot CPU's timer tick enters the monitor, when the interrupted code
+//! holds no kernel locks. Time stands still while it is shown; the other
+//! CPUs keep running their processes.
+//!
+//! A panic prints a backtrace and then waits for the same escape, so the
+//! state it left behind can be looked at before the machine is reset.
+//! Commands that need a lock the panicking code held say so instead of
+//! waiting; the commands allocate, so a panic inside the kernel heap
+//! leaves the monitor unusable.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+use crate::backtrace;
+use crate::memory::PAGE_SIZE;
+use crate::memory::vmm::{walk_active, VirtualAddress};
+use crate::platform::traits::ConsoleUart;
+use crate::process::{ProcessId, TrapFrame};
+use crate::serial::{ESCAPE, ESCAPE_MONITOR, SERIAL1};
+
+/// Longest command line
+const MAX_LINE: usize = 80;
+
+/// Log text `log` shows without a size
+const DEFAULT_LOG_BYTES: usize = 2048;
+
+/// Words `x` dumps without a count, and at most
+const DEFAULT_DUMP_WORDS: usize = 16;
+const MAX_DUMP_WORDS: usize = 512;
+
+/// Messages `ipc` lists per queue
+const IPC_MESSAGES_SHOWN: usize = 8;
+
+const PROMPT: &str = "kdb> ";
+
+/// Set by the serial escape, consumed at the next timer tick
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Break into the monitor at the next timer tick
+///
+/// Safe from interrupt context: only flags the request.
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Enter the monitor if it was asked for
+///
+/// Runs on the boot CPU's timer tick with the context the tick
+/// interrupted, and returns when the monitor is left.
+pub fn timer_tick(frame: &TrapFrame) {
+    if !REQUESTED.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    run(Entry::Break(*frame));
+}
+
+/// Wait on the serial line for the escape after a panic, then run the
+/// monitor for good
+///
+/// `frame_pointer` is the panic handler's, where backtraces start.
+pub fn on_panic(frame_pointer: u64) -> ! {
+    print("Ctrl+] then d on the serial console opens the debug monitor.\n");
+    let mut escaped = false;
+    loop {
+        let byte = read_byte();
+        if escaped && byte == ESCAPE_MONITOR {
+            run(Entry::Panic { frame_pointer });
+        }
+        escaped = byte == ESCAPE;
+    }
+}
+
+/// What the monitor was entered from
+#[derive(Debug, Clone, Copy)]
+enum Entry {
+    /// The serial escape, at a timer tick that interrupted this context
+    Break(TrapFrame),
+    /// A panic, from its handler
+    Panic { frame_pointer: u64 },
+}
+
+/// A monitor command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command<'a> {
+    Help,
+    Registers,
+    Backtrace,
+    /// Words of memory from an address
+    Dump(u64, usize),
+    /// The page-table entries translating an address
+    Walk(u64),
+    Processes,
+    Threads,
+    /// Pending messages of every queue
+    Queues,
+    Memory,
+    Interrupts,
+    /// The newest bytes of the kernel log
+    Log(usize),
+    /// Set the log levels, as the `log` boot parameter does
+    Level(&'a str),
+    Continue,
+}
+
+/// An address, in hex with or without `0x`
+fn parse_address(text: &str) -> Result<u64, &'static str> {
+    let digits = text.strip_prefix("0x").unwrap_or(text).replace('_', "");
+    u64::from_str_radix(&digits, 16).map_err(|_| "expected a hex address")
+}
+
+impl<'a> Command<'a> {
+    fn parse(line: &'a str) -> Result<Option<Self>, &'static str> {
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return Ok(None);
+        };
+        let arguments: Vec<&str> = words.collect();
+        let command = match (name, arguments.as_slice()) {
+            ("help" | "?", []) => Command::Help,
+            ("regs", []) => Command::Registers,
+            ("bt", []) => Command::Backtrace,
+            ("x", [address]) => Command::Dump(parse_address(address)?, DEFAULT_DUMP_WORDS),
+            ("x", [address, words]) => {
+                let words = words.parse().map_err(|_| "x takes a word count")?;
+                Command::Dump(parse_address(address)?, MAX_DUMP_WORDS.min(words))
+            }
+            ("x", _) => return Err("x takes an address and a word count"),
+            ("walk", [address]) => Command::Walk(parse_address(address)?),
+            ("walk", _) => return Err("walk takes an address"),
+            ("ps", []) => Command::Processes,
+            ("threads", []) => Command::Threads,
+            ("ipc", []) => Command::Queues,
+            ("mem", []) => Command::Memory,
+            ("irq", []) => Command::Interrupts,
+            ("log", []) => Command::Log(DEFAULT_LOG_BYTES),
+            ("log", [bytes]) => Command::Log(bytes.parse().map_err(|_| "log takes a byte count")?),
+            ("level", &[spec]) => Command::Level(spec),
+            ("level", _) => return Err("level takes a filter, such as info or ipc=trace"),
+            ("continue" | "c", []) => Command::Continue,
+            ("help" | "?" | "regs" | "bt" | "ps" | "threads" | "ipc" | "mem" | "irq" | "log" | "continue" | "c", _) => {
+                return Err("wrong number of arguments");
+            }
+            _ => return Err("unknown command, try help"),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// What a byte of input does to the line being typed
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Edit {
+    /// Echo this text and keep reading
+    Echo(String),
+    /// The line is finished
+    Enter,
+}
+
+/// The command line being typed, edited as bytes arrive
+struct LineEditor {
+    line: Vec<u8>,
+}
+
+impl LineEditor {
+    const fn new() -> Self {
+        Self { line: Vec::new() }
+    }
+
+    fn push(&mut self, byte: u8) -> Edit {
+        match byte {
+            b'\r' | b'\n' => Edit::Enter,
+            0x7F | 0x08 if self.line.pop().is_some() => Edit::Echo(String::from("\u{8} \u{8}")),
+            // Ctrl+C drops the line
+            0x03 => {
+                self.line.clear();
+                Edit::Echo(String::from("^C\n"))
+            }
+            0x20..=0x7E if self.line.len() < MAX_LINE => {
+                self.line.push(byte);
+                Edit::Echo(String::from(byte as char))
+            }
+            _ => Edit::Echo(String::new()),
+        }
+    }
+
+    fn take(&mut self) -> String {
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+        line
+    }
+}
+
+/// Write `text` to the console UART
+///
+/// The lock is only held while writing: a process on another CPU may log
+/// while holding a lock a command needs.
+fn print(text: &str) {
+    let mut serial = SERIAL1.lock();
+    for byte in text.bytes() {
+        serial.write_byte(byte);
+    }
+}
+
+/// Wait for a byte from the console UART
+fn read_byte() -> u8 {
+    loop {
+        if let Some(byte) = SERIAL1.lock().read_byte() {
+            return byte;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Read and carry out commands until `continue`
+fn run(entry: Entry) {
+    match entry {
+        Entry::Break(_) => print("\nkosh kernel debug monitor; the boot CPU is stopped. Type help for commands.\n"),
+        Entry::Panic { .. } => print("\nkosh kernel debug monitor, after a panic. Type help for commands.\n"),
+    }
+    let mut editor = LineEditor::new();
+    loop {
+        print(PROMPT);
+        loop {
+            match editor.push(read_byte()) {
+                Edit::Enter => break,
+                Edit::Echo(text) => print(&text),
+            }
+        }
+        print("\n");
+        let line = editor.take();
+        match Command::parse(&line) {
+            Ok(Some(Command::Continue)) => match entry {
+                Entry::Break(_) => break,
+                Entry::Panic { .. } => print("a panic cannot be continued from\n"),
+            },
+            Ok(Some(command)) => print(&execute(command, &entry)),
+            Ok(None) => {}
+            Err(e) => print(&format!("{}\n", e)),
+        }
+    }
+    print("Leaving the debug monitor\n");
+}
+
+fn execute(command: Command, entry: &Entry) -> String {
+    let mut out = String::new();
+    match command {
+        Command::Help => {
+            out.push_str("regs            registers where the monitor was entered\n");
+            out.push_str("bt              backtrace from there\n");
+            out.push_str("x <addr> [n]    n words of memory, 16 by default\n");
+            out.push_str("walk <addr>     page-table entries translating an address\n");
+            out.push_str("ps              processes\n");
+            out.push_str("threads         threads and their kernel stacks\n");
+            out.push_str("ipc             pending messages of every queue\n");
+            out.push_str("mem             physical memory and kernel heap use\n");
+            out.push_str("irq             interrupts taken per IRQ line\n");
+            out.push_str("log [bytes]     newest kernel log lines\n");
+            out.push_str("level <filter>  set log levels, such as info or ipc=trace\n");
+            out.push_str("continue, c     leave the monitor\n");
+        }
+        Command::Registers => registers(&mut out, entry),
+        Command::Backtrace => {
+            let (frame_pointer, mode) = match entry {
+                Entry::Break(frame) => {
+                    let _ = writeln!(out, "  0x{:016x}", frame.rip);
+                    (frame.rbp, if frame.from_user_mode() { "user" } else { "kernel" })
+                }
+                Entry::Panic { frame_pointer } => (*frame_pointer, "kernel"),
+            };
+            for frame in backtrace::frames(frame_pointer) {
+                let _ = writeln!(out, "  0x{:016x}", frame.return_address);
+            }
+            let _ = writeln!(out, "({} mode, from frame pointer 0x{:016x})", mode, frame_pointer);
+        }
+        Command::Dump(address, words) => dump(&mut out, address & !0x7, words),
+        Command::Walk(address) => page_walk(&mut out, address),
+        Command::Processes => match crate::process::try_list_processes() {
+            Some(processes) => {
+                let _ = writeln!(out, "{:>5} {:>5}  {:<10} {:>3} {:>10}  NAME", "PID", "PPID", "STATE", "CPU", "TIME MS");
+                for process in processes {
+                    let parent = process.parent_pid.map_or(String::from("-"), |pid| format!("{}", pid.0));
+                    let _ = writeln!(out, "{:>5} {:>5}  {:<10} {:>3} {:>10}  {}", process.pid.0, parent,
+                                     format!("{:?}", process.state), process.cpu, process.cpu_time_ms, process.name);
+                }
+            }
+            None => out.push_str("the process table is locked\n"),
+        },
+        Command::Threads => threads(&mut out),
+        Command::Queues => queues(&mut out),
+        Command::Memory => {
+            match crate::memory::physical::memory_stats() {
+                Some(stats) => {
+                    let _ = writeln!(out, "physical  {} / {} MiB used", stats.used_memory_mb(), stats.total_memory_mb());
+                }
+                None => out.push_str("physical  not initialized\n"),
+            }
+            let heap = crate::memory::heap::heap_stats();
+            let _ = writeln!(out, "heap      {} / {} KiB used, peak {} KiB", heap.current_bytes / 1024,
+                             heap.heap_size / 1024, heap.peak_bytes / 1024);
+        }
+        Command::Interrupts => {
+            for (line, count) in crate::platform::irq_counts() {
+                let _ = writeln!(out, "{:>4}  {}", line, count);
+            }
+            let _ = writeln!(out, "serial input dropped: {} bytes", crate::serial::dropped_input());
+        }
+        Command::Log(bytes) => {
+            out.push_str(&String::from_utf8_lossy(&crate::klog::read(bytes)));
+        }
+        Command::Level(spec) => {
+            if let Err(e) = crate::klog::configure(spec) {
+                let _ = writeln!(out, "{}", e);
+            }
+        }
+        Command::Continue => {}
+    }
+    out
+}
+
+fn registers(out: &mut String, entry: &Entry) {
+    match entry {
+        Entry::Break(frame) => {
+            let mode = if frame.from_user_mode() { "user" } else { "kernel" };
+            let _ = writeln!(out, "Interrupted in {} mode", mode);
+            let _ = writeln!(out, "RIP 0x{:016x}  CS {:#06x}  RFLAGS 0x{:016x}", frame.rip, frame.cs, frame.rflags);
+            let _ = writeln!(out, "RSP 0x{:016x}  SS {:#06x}", frame.rsp, frame.ss);
+            let _ = writeln!(out, "RAX 0x{:016x}  RBX 0x{:016x}  RCX 0x{:016x}", frame.rax, frame.rbx, frame.rcx);
+            let _ = writeln!(out, "RDX 0x{:016x}  RSI 0x{:016x}  RDI 0x{:016x}", frame.rdx, frame.rsi, frame.rdi);
+            let _ = writeln!(out, "RBP 0x{:016x}  R8  0x{:016x}  R9  0x{:016x}", frame.rbp, frame.r8, frame.r9);
+            let _ = writeln!(out, "R10 0x{:016x}  R11 0x{:016x}  R12 0x{:016x}", frame.r10, frame.r11, frame.r12);
+            let _ = writeln!(out, "R13 0x{:016x}  R14 0x{:016x}  R15 0x{:016x}", frame.r13, frame.r14, frame.r15);
+        }
+        Entry::Panic { frame_pointer } => {
+            let _ = writeln!(out, "Stopped by a panic; RBP of its handler 0x{:016x}", frame_pointer);
+        }
+    }
+    let _ = writeln!(out, "CR0 0x{:016x}  CR2 0x{:016x}", Cr0::read_raw(), Cr2::read().as_u64());
+    let _ = writeln!(out, "CR3 0x{:016x}  CR4 0x{:016x}", Cr3::read().0.start_address().as_u64(), Cr4::read_raw());
+    if let Some(pid) = crate::process::get_current_process() {
+        let _ = writeln!(out, "Current process {}", pid.0);
+    }
+}
+
+/// Dump `words` words from `address`, in the address space the CPU has
+/// loaded, leaving out pages that are not mapped
+fn dump(out: &mut String, address: u64, words: usize) {
+    let end = address.saturating_add(words as u64 * 8);
+    let mut line_start = address;
+    while line_start < end {
+        if walk_active(VirtualAddress::new(line_start as usize)).physical.is_none() {
+            let next_page = (line_start | (PAGE_SIZE as u64 - 1)).saturating_add(1);
+            let _ = writeln!(out, "0x{:016x}: <unmapped>", line_start);
+            line_start = next_page;
+            continue;
+        }
+        let _ = write!(out, "0x{:016x}:", line_start);
+        let mut word = line_start;
+        // Two words a line, stopping at the end of the page
+        while word < end && word < line_start + 16 && (word == line_start || word % PAGE_SIZE as u64 != 0) {
+            let value = unsafe { core::ptr::read_volatile(word as *const u64) };
+            let _ = write!(out, " 0x{:016x}", value);
+            word += 8;
+        }
+        out.push('\n');
+        line_start = word;
+    }
+}
+
+fn page_walk(out: &mut String, address: u64) {
+    let walk = walk_active(VirtualAddress::new(address as usize));
+    if walk.entries[0].is_none() {
+        out.push_str("not a canonical address\n");
+        return;
+    }
+    for entry in walk.entries.iter().flatten() {
+        let _ = writeln!(out, "L{}[{:>3}] 0x{:016x}  {:?}", entry.level, entry.index, entry.address, entry.flags);
+    }
+    match walk.physical {
+        Some(physical) => {
+            let _ = writeln!(out, "0x{:016x} -> physical 0x{:016x}", address, physical);
+        }
+        None => {
+            let _ = writeln!(out, "0x{:016x} is not mapped", address);
+        }
+    }
+}
+
+fn threads(out: &mut String) {
+    let Some(threads) = crate::process::thread::try_list() else {
+        out.push_str("the thread table is locked\n");
+        return;
+    };
+    let processes = crate::process::try_list_processes().unwrap_or_default();
+    let state = |tid: ProcessId| {
+        processes.iter().find(|process| process.pid == tid).map_or(String::from("-"), |process| format!("{:?}", process.state))
+    };
+    let _ = writeln!(out, "{:>5} {:>7}  {:<10} KERNEL STACK", "TID", "PROCESS", "STATE");
+    for thread in threads {
+        let stack = match (thread.kernel_stack, thread.exit_code) {
+            (Some((bottom, top)), _) => format!("0x{:016x}-0x{:016x}", bottom, top),
+            (None, Some(code)) => format!("exited with {}", code),
+            (None, None) => String::from("-"),
+        };
+        let _ = writeln!(out, "{:>5} {:>7}  {:<10} {}", thread.tid.0, thread.process.0, state(thread.tid), stack);
+    }
+}
+
+fn queues(out: &mut String) {
+    let Some(queues) = crate::ipc::queue::try_queue_contents(IPC_MESSAGES_SHOWN) else {
+        out.push_str("the message queues are locked\n");
+        return;
+    };
+    if queues.is_empty() {
+        out.push_str("no messages pending\n");
+    }
+    for queue in queues {
+        let _ = writeln!(out, "process {}: {} pending, {} bytes", queue.process_id.0, queue.pending, queue.total_size);
+        for message in &queue.messages {
+            let _ = write!(out, "  #{} from {} {:?}, {} bytes at {} ms:", message.message_id.as_u64(),
+                           message.sender.0, message.message_type, message.size, message.timestamp);
+            for byte in &message.payload {
+                let _ = write!(out, " {:02x}", byte);
+            }
+            out.push('\n');
+        }
+        if queue.pending > queue.messages.len() {
+            let _ = writeln!(out, "  ... {} more", queue.pending - queue.messages.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_parse_commands() {
+        assert_eq!(Command::parse("  "), Ok(None));
+        assert_eq!(Command::parse("ps"), Ok(Some(Command::Processes)));
+        assert_eq!(Command::parse("log"), Ok(Some(Command::Log(DEFAULT_LOG_BYTES))));
+        assert_eq!(Command::parse(" log  512 "), Ok(Some(Command::Log(512))));
+        assert_eq!(Command::parse("level ipc=trace"), Ok(Some(Command::Level("ipc=trace"))));
+        assert_eq!(Command::parse("c"), Ok(Some(Command::Continue)));
+        assert!(Command::parse("log lots").is_err());
+        assert!(Command::parse("level").is_err());
+        assert!(Command::parse("ps all").is_err());
+        assert!(Command::parse("reboot").is_err());
+    }
+
+    #[test_case]
+    fn test_parse_addresses() {
+        assert_eq!(Command::parse("x 0xffff_8000_0000_1000"), Ok(Some(Command::Dump(0xFFFF_8000_0000_1000, DEFAULT_DUMP_WORDS))));
+        assert_eq!(Command::parse("x 1000 4"), Ok(Some(Command::Dump(0x1000, 4))));
+        assert_eq!(Command::parse("x 1000 100000"), Ok(Some(Command::Dump(0x1000, MAX_DUMP_WORDS))));
+        assert_eq!(Command::parse("walk 0x7fff0000"), Ok(Some(Command::Walk(0x7FFF_0000))));
+        assert!(Command::parse("x").is_err());
+        assert!(Command::parse("x zz").is_err());
+        assert!(Command::parse("walk 1 2").is_err());
+    }
+
+    #[test_case]
+    fn test_dump_marks_unmapped_pages() {
+        let values = [0x1111_2222_3333_4444u64, 0x5555_6666_7777_8888];
+        let mut out = String::new();
+        dump(&mut out, values.as_ptr() as u64, 2);
+        assert!(out.contains("0x1111222233334444 0x5555666677778888"));
+
+        // Nothing is mapped in the lowest page
+        let mut out = String::new();
+        dump(&mut out, 0, 4);
+        assert_eq!(out, "0x0000000000000000: <unmapped>\n");
+    }
+
+    #[test_case]
+    fn test_line_editor() {
+        let mut editor = LineEditor::new();
+        for &byte in b"psx" {
+            assert_eq!(editor.push(byte), Edit::Echo(String::from(byte as char)));
+        }
+        assert_eq!(editor.push(0x7F), Edit::Echo(String::from("\u{8} \u{8}")));
+        // Other control bytes are ignored
+        assert_eq!(editor.push(0x1B), Edit::Echo(String::new()));
+        assert_eq!(editor.push(b'\r'), Edit::Enter);
+        assert_eq!(editor.take(), "ps");
+
+        // Backspace on an empty line echoes nothing
+        assert_eq!(editor.push(0x08), Edit::Echo(String::new()));
+        for _ in 0..MAX_LINE + 5 {
+            editor.push(b'a');
+        }
+        assert_eq!(editor.take().len(), MAX_LINE);
+    }
+}
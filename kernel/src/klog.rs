@@ -0,0 +1,377 @@
+//! Kernel log
+//!
+//! The kernel's backend for the `log` crate. Records carry a level and a
+//! subsystem, taken from the module that logged them ("process" for
+//! `kosh_kernel::process::scheduler`) unless the call names a target. Each
+//! record becomes one line:
+//!
+//! ```text
+//! [   12.345] WARN  syscall: Process 7 passed a bad pointer
+//! ```
+//!
+//! Every line goes to the serial port and into a fixed-size ring buffer
+//! that `SYS_KLOG` reads for `dmesg`; info and above also show on the
+//! kernel log terminal. The `log_level=` boot parameter sets which records
+//! are kept, either for all subsystems (`log_level=debug`) or with
+//! overrides per subsystem (`log_level=info,syscall=trace,ipc=warn`).
+
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::str::FromStr;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use spin::Mutex;
+
+/// Bytes of log text the ring buffer holds
+pub const KLOG_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Longest line a record becomes; longer messages are cut
+pub const MAX_RECORD_LEN: usize = 256;
+
+/// Level used until `log_level=` says otherwise
+pub const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+/// Most subsystems `log_level=` can give their own level
+const MAX_TARGET_FILTERS: usize = 8;
+
+/// Longest subsystem name a filter can hold
+const MAX_TARGET_LEN: usize = 16;
+
+/// Crate name module paths start with
+const CRATE_PREFIX: &str = "kosh_kernel::";
+
+static LOGGER: KernelLogger = KernelLogger;
+
+static RING: Mutex<LogRing<KLOG_BUFFER_SIZE>> = Mutex::new(LogRing::new());
+
+static FILTERS: Mutex<Filters> = Mutex::new(Filters::new());
+
+/// Install the logger
+///
+/// Called first thing at boot; records logged before are dropped.
+pub fn init() {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(DEFAULT_LEVEL);
+    }
+}
+
+/// Apply a `log_level=` value
+///
+/// The first entry may be a bare level for every subsystem; the others
+/// are `subsystem=level`. Nothing changes if any entry is invalid.
+pub fn configure(spec: &str) -> Result<(), &'static str> {
+    let filters = Filters::parse(spec)?;
+    log::set_max_level(filters.max_level());
+    *FILTERS.lock() = filters;
+    Ok(())
+}
+
+/// Keep records at `level` and above from every subsystem
+///
+/// Overrides for single subsystems are dropped.
+pub fn set_level(level: LevelFilter) {
+    let mut filters = FILTERS.lock();
+    *filters = Filters::new();
+    filters.default = level;
+    log::set_max_level(level);
+}
+
+/// Level for a `SYS_KLOG` level number, 0 (off) to 5 (trace)
+pub fn level_from_raw(raw: u64) -> Option<LevelFilter> {
+    match raw {
+        0 => Some(LevelFilter::Off),
+        1 => Some(LevelFilter::Error),
+        2 => Some(LevelFilter::Warn),
+        3 => Some(LevelFilter::Info),
+        4 => Some(LevelFilter::Debug),
+        5 => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+/// Bytes of log text held
+pub fn len() -> usize {
+    RING.lock().len
+}
+
+/// The newest whole lines of the log that fit in `max` bytes
+pub fn read(max: usize) -> Vec<u8> {
+    RING.lock().newest(max)
+}
+
+/// Copy the newest whole lines that fit into `out`, returning how many
+/// bytes were copied
+///
+/// Allocates nothing and gives up rather than wait for the log, for the
+/// panic handler; it copies nothing if the panic hit while logging.
+pub fn copy_newest(out: &mut [u8]) -> usize {
+    RING.try_lock().map_or(0, |ring| ring.copy_newest(out))
+}
+
+/// Subsystem a record target names
+///
+/// Module paths are cut to the module under the crate root; explicit
+/// targets are used as they are.
+pub fn subsystem(target: &str) -> &str {
+    match target.strip_prefix(CRATE_PREFIX) {
+        Some(path) => path.split("::").next().unwrap_or(path),
+        None => target,
+    }
+}
+
+struct KernelLogger;
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= FILTERS.lock().level_for(subsystem(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut line = RecordLine::new();
+        let uptime_ms = crate::time::monotonic_ms();
+        let _ = write!(
+            line,
+            "[{:5}.{:03}] {:<5} {}: {}",
+            uptime_ms / 1000,
+            uptime_ms % 1000,
+            record.level(),
+            subsystem(record.target()),
+            record.args()
+        );
+        let text = line.finish();
+
+        RING.lock().push(text.as_bytes());
+        crate::serial_print!("{}", text);
+        if record.level() <= Level::Info {
+            crate::print!("{}", text);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Levels by subsystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Filters {
+    default: LevelFilter,
+    targets: [([u8; MAX_TARGET_LEN], usize, LevelFilter); MAX_TARGET_FILTERS],
+    count: usize,
+}
+
+impl Filters {
+    const fn new() -> Self {
+        Self {
+            default: DEFAULT_LEVEL,
+            targets: [([0; MAX_TARGET_LEN], 0, LevelFilter::Off); MAX_TARGET_FILTERS],
+            count: 0,
+        }
+    }
+
+    fn parse(spec: &str) -> Result<Self, &'static str> {
+        let mut filters = Self::new();
+        for (index, entry) in spec.split(',').enumerate() {
+            match entry.split_once('=') {
+                None if index == 0 => {
+                    filters.default = LevelFilter::from_str(entry).map_err(|_| "unknown log level")?;
+                }
+                None => return Err("expected subsystem=level"),
+                Some((target, level)) => {
+                    let level = LevelFilter::from_str(level).map_err(|_| "unknown log level")?;
+                    if target.is_empty() || target.len() > MAX_TARGET_LEN {
+                        return Err("bad subsystem name");
+                    }
+                    if filters.count == MAX_TARGET_FILTERS {
+                        return Err("too many subsystem levels");
+                    }
+                    let mut name = [0; MAX_TARGET_LEN];
+                    name[..target.len()].copy_from_slice(target.as_bytes());
+                    filters.targets[filters.count] = (name, target.len(), level);
+                    filters.count += 1;
+                }
+            }
+        }
+        Ok(filters)
+    }
+
+    fn level_for(&self, subsystem: &str) -> LevelFilter {
+        self.targets[..self.count]
+            .iter()
+            .find(|(name, len, _)| &name[..*len] == subsystem.as_bytes())
+            .map_or(self.default, |&(_, _, level)| level)
+    }
+
+    /// Most verbose level any subsystem gets, for `log::set_max_level`
+    fn max_level(&self) -> LevelFilter {
+        self.targets[..self.count]
+            .iter()
+            .map(|&(_, _, level)| level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+/// One formatted record, cut to `MAX_RECORD_LEN` without allocating
+struct RecordLine {
+    bytes: [u8; MAX_RECORD_LEN],
+    len: usize,
+}
+
+impl RecordLine {
+    fn new() -> Self {
+        Self { bytes: [0; MAX_RECORD_LEN], len: 0 }
+    }
+
+    /// The line with its newline, which always fits
+    fn finish(&mut self) -> &str {
+        self.len = self.len.min(MAX_RECORD_LEN - 1);
+        // A cut may have split a character
+        while core::str::from_utf8(&self.bytes[..self.len]).is_err() {
+            self.len -= 1;
+        }
+        self.bytes[self.len] = b'\n';
+        self.len += 1;
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("\n")
+    }
+}
+
+impl Write for RecordLine {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // Keep room for the newline; the rest is cut off
+        let room = MAX_RECORD_LEN - 1 - self.len.min(MAX_RECORD_LEN - 1);
+        let take = s.len().min(room);
+        self.bytes[self.len..self.len + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+        Ok(())
+    }
+}
+
+/// Ring of log text that drops whole lines, oldest first, when full
+struct LogRing<const N: usize> {
+    bytes: [u8; N],
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize> LogRing<N> {
+    const fn new() -> Self {
+        Self { bytes: [0; N], start: 0, len: 0 }
+    }
+
+    /// Append a line ending in a newline
+    fn push(&mut self, line: &[u8]) {
+        if line.len() > N {
+            return;
+        }
+        while self.len + line.len() > N {
+            self.drop_oldest_line();
+        }
+        for &byte in line {
+            self.bytes[(self.start + self.len) % N] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn drop_oldest_line(&mut self) {
+        while self.len > 0 {
+            let byte = self.bytes[self.start];
+            self.start = (self.start + 1) % N;
+            self.len -= 1;
+            if byte == b'\n' {
+                break;
+            }
+        }
+    }
+
+    fn byte(&self, offset: usize) -> u8 {
+        self.bytes[(self.start + offset) % N]
+    }
+
+    /// Offset of the first whole line among the newest `max` bytes
+    fn first_of_newest(&self, max: usize) -> usize {
+        let mut first = self.len.saturating_sub(max);
+        // Start at a line boundary
+        if first > 0 && self.byte(first - 1) != b'\n' {
+            while first < self.len && self.byte(first) != b'\n' {
+                first += 1;
+            }
+            first = (first + 1).min(self.len);
+        }
+        first
+    }
+
+    fn newest(&self, max: usize) -> Vec<u8> {
+        (self.first_of_newest(max)..self.len).map(|offset| self.byte(offset)).collect()
+    }
+
+    fn copy_newest(&self, out: &mut [u8]) -> usize {
+        let first = self.first_of_newest(out.len());
+        for (slot, offset) in out.iter_mut().zip(first..self.len) {
+            *slot = self.byte(offset);
+        }
+        self.len - first
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_subsystem() {
+        assert_eq!(subsystem("kosh_kernel::process::scheduler"), "process");
+        assert_eq!(subsystem("kosh_kernel::monitor"), "monitor");
+        assert_eq!(subsystem("syscall"), "syscall");
+    }
+
+    #[test_case]
+    fn test_filters_parse() {
+        let filters = Filters::parse("warn,syscall=trace,ipc=off").unwrap();
+        assert_eq!(filters.level_for("memory"), LevelFilter::Warn);
+        assert_eq!(filters.level_for("syscall"), LevelFilter::Trace);
+        assert_eq!(filters.level_for("ipc"), LevelFilter::Off);
+        assert_eq!(filters.max_level(), LevelFilter::Trace);
+
+        assert_eq!(Filters::parse("debug").unwrap().max_level(), LevelFilter::Debug);
+        assert_eq!(Filters::parse("process=error").unwrap().level_for("memory"), DEFAULT_LEVEL);
+        assert!(Filters::parse("loud").is_err());
+        assert!(Filters::parse("info,trace").is_err());
+        assert!(Filters::parse("info,a-very-long-subsystem-name=info").is_err());
+    }
+
+    #[test_case]
+    fn test_level_from_raw() {
+        assert_eq!(level_from_raw(0), Some(LevelFilter::Off));
+        assert_eq!(level_from_raw(5), Some(LevelFilter::Trace));
+        assert_eq!(level_from_raw(6), None);
+    }
+
+    #[test_case]
+    fn test_log_ring_drops_whole_lines() {
+        let mut ring: LogRing<16> = LogRing::new();
+        ring.push(b"first\n");
+        ring.push(b"second\n");
+        assert_eq!(ring.newest(64), b"first\nsecond\n");
+        ring.push(b"third\n");
+        assert_eq!(ring.newest(64), b"second\nthird\n");
+        // Only whole lines are returned
+        assert_eq!(ring.newest(8), b"third\n");
+        assert_eq!(ring.newest(3), b"");
+
+        let mut out = [0u8; 10];
+        assert_eq!(ring.copy_newest(&mut out), 6);
+        assert_eq!(&out[..6], b"third\n");
+        assert_eq!(ring.copy_newest(&mut out[..3]), 0);
+    }
+
+    #[test_case]
+    fn test_record_line_cut() {
+        let mut line = RecordLine::new();
+        for _ in 0..MAX_RECORD_LEN {
+            let _ = line.write_str("é");
+        }
+        let text = line.finish();
+        assert!(text.len() <= MAX_RECORD_LEN);
+        assert!(text.ends_with("é\n"));
+    }
+}
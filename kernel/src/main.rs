@@ -12,6 +12,12 @@ use multiboot2::BootInformation;
 
 mod serial;
 mod vga_buffer;
+mod vt;
+mod klog;
+mod audit;
+mod trace;
+mod time;
+mod random;
 mod boot;
 mod memory;
 mod process;
@@ -19,6 +25,16 @@ mod ipc;
 mod syscall;
 mod power;
 mod platform;
+mod smp;
+mod monitor;
+mod kdb;
+mod backtrace;
+mod crash;
+mod trust;
+#[cfg(debug_assertions)]
+mod integration;
+#[cfg(feature = "bench")]
+mod bench;
 
 #[cfg(test)]
 mod test_harness;
@@ -116,12 +132,11 @@ static MULTIBOOT2_HEADER: Multiboot2Header = {
 
 /// Parse boot parameters from multiboot2 command line
 fn parse_boot_parameters(boot_info: &BootInformation) {
-    serial_println!("Parsing boot parameters...");
+    log::debug!("Parsing boot parameters...");
     
     if let Some(command_line_tag) = boot_info.command_line_tag() {
         if let Ok(cmdline) = command_line_tag.cmdline() {
-            serial_println!("Kernel command line: {}", cmdline);
-            println!("Boot parameters: {}", cmdline);
+            log::info!("Boot parameters: {}", cmdline);
             
             // Parse individual parameters
             for param in cmdline.split_whitespace() {
@@ -129,104 +144,143 @@ fn parse_boot_parameters(boot_info: &BootInformation) {
                     match key {
                         "debug" => {
                             if value == "1" || value == "true" {
-                                serial_println!("Debug mode enabled");
-                                println!("Debug mode: ON");
+                                log::info!("Debug mode: ON");
                             }
                         }
-                        "log_level" => {
-                            serial_println!("Log level set to: {}", value);
-                            println!("Log level: {}", value);
-                        }
+                        "log_level" => match klog::configure(value) {
+                            Ok(()) => log::info!("Log level: {}", value),
+                            Err(error) => log::warn!("Ignoring log_level={}: {}", value, error),
+                        },
                         "safe_mode" => {
                             if value == "1" || value == "true" {
-                                serial_println!("Safe mode enabled");
-                                println!("Safe mode: ON");
+                                log::info!("Safe mode: ON");
+                                trust::allow_unsigned_drivers("Safe mode");
                             }
                         }
                         "driver_autoload" => {
                             if value == "false" || value == "0" {
-                                serial_println!("Driver autoload disabled");
-                                println!("Driver autoload: OFF");
+                                log::info!("Driver autoload: OFF");
                             }
                         }
                         "recovery" => {
                             if value == "1" || value == "true" {
-                                serial_println!("Recovery mode enabled");
-                                println!("Recovery mode: ON");
+                                log::info!("Recovery mode: ON");
+                                trust::allow_unsigned_drivers("Recovery mode");
                             }
                         }
                         "single_user" => {
                             if value == "1" || value == "true" {
-                                serial_println!("Single user mode enabled");
-                                println!("Single user mode: ON");
+                                log::info!("Single user mode: ON");
+                            }
+                        }
+                        "stats" => {
+                            if value == "1" || value == "true" {
+                                log::info!("Statistics dashboard enabled");
+                                monitor::enable();
+                            }
+                        }
+                        "aslr" => {
+                            if value == "0" || value == "false" || value == "off" {
+                                memory::aslr::disable("Boot parameter");
+                            }
+                        }
+                        "trace" => match trace::configure(value) {
+                            Ok(()) => log::info!("Tracing: {}", value),
+                            Err(error) => log::warn!("Ignoring trace={}: {}", value, error),
+                        },
+                        "crashdump" => {
+                            if value == "0" || value == "false" || value == "off" {
+                                crash::disable("Boot parameter");
+                            }
+                        }
+                        "integration_tests" => {
+                            if value == "1" || value == "true" {
+                                enable_integration_tests();
                             }
                         }
                         _ => {
-                            serial_println!("Unknown boot parameter: {}={}", key, value);
+                            log::warn!("Unknown boot parameter: {}={}", key, value);
                         }
                     }
                 } else {
                     // Handle boolean flags without values
                     match param {
                         "debug" => {
-                            serial_println!("Debug mode enabled (flag)");
-                            println!("Debug mode: ON");
+                            log::info!("Debug mode: ON");
                         }
                         "safe_mode" => {
-                            serial_println!("Safe mode enabled (flag)");
-                            println!("Safe mode: ON");
+                            log::info!("Safe mode: ON");
+                            trust::allow_unsigned_drivers("Safe mode");
                         }
+                        "recovery" => {
+                            log::info!("Recovery mode: ON");
+                            trust::allow_unsigned_drivers("Recovery mode");
+                        }
+                        "stats" => {
+                            log::info!("Statistics dashboard enabled");
+                            monitor::enable();
+                        }
+                        "noaslr" => {
+                            memory::aslr::disable("Boot parameter");
+                        }
+                        "integration_tests" => enable_integration_tests(),
                         _ => {
-                            serial_println!("Unknown boot flag: {}", param);
+                            log::warn!("Unknown boot flag: {}", param);
                         }
                     }
                 }
             }
         }
     } else {
-        serial_println!("No command line parameters found");
-        println!("No boot parameters");
+        log::info!("No boot parameters");
     }
     
     // Display additional boot information
     if let Some(boot_loader_name_tag) = boot_info.boot_loader_name_tag() {
         if let Ok(name) = boot_loader_name_tag.name() {
-            serial_println!("Bootloader: {}", name);
-            println!("Bootloader: {}", name);
+            log::info!("Bootloader: {}", name);
         }
     }
     
     // Display ELF sections if available
     if let Some(elf_sections_tag) = boot_info.elf_sections_tag() {
-        serial_println!("ELF sections available: {} sections", elf_sections_tag.sections().count());
+        log::debug!("ELF sections available: {} sections", elf_sections_tag.sections().count());
     }
     
     // Display framebuffer info if available
     if let Some(framebuffer_tag) = boot_info.framebuffer_tag() {
         if let Ok(framebuffer) = framebuffer_tag {
-            serial_println!("Framebuffer: {}x{} @ {} bpp", 
-                           framebuffer.width(), 
-                           framebuffer.height(),
-                           framebuffer.bpp());
+            log::debug!("Framebuffer: {}x{} @ {} bpp", 
+                        framebuffer.width(), 
+                        framebuffer.height(),
+                        framebuffer.bpp());
         }
     }
     
-    serial_println!("Boot parameter parsing complete");
+    log::debug!("Boot parameter parsing complete");
+}
+
+/// Boot for the userspace integration tests, which only debug kernels run
+fn enable_integration_tests() {
+    #[cfg(debug_assertions)]
+    integration::enable();
+    #[cfg(not(debug_assertions))]
+    log::warn!("Ignoring integration_tests: only debug kernels run integration tests");
 }
 
 #[cfg(target_arch = "x86_64")]
 #[no_mangle]
 pub extern "C" fn _start(multiboot_info_addr: usize) -> ! {
-    // Initialize early console output for debugging
-    serial_println!("Kosh Kernel Starting...");
-    println!("Kosh Kernel Starting...");
+    // Everything after this goes through the kernel log
+    klog::init();
+    log::info!("Kosh Kernel Starting...");
 
     // Parse multiboot2 information
     let boot_info = unsafe { BootInformation::load(multiboot_info_addr as *const _) };
     
     match boot_info {
         Ok(boot_info) => {
-            serial_println!("Multiboot2 info parsed successfully");
+            log::debug!("Multiboot2 info parsed successfully");
             
             // Parse and display boot parameters
             parse_boot_parameters(&boot_info);
@@ -235,7 +289,7 @@ pub extern "C" fn _start(multiboot_info_addr: usize) -> ! {
             boot::init_kernel(boot_info);
         }
         Err(e) => {
-            serial_println!("Failed to parse multiboot2 info: {:?}", e);
+            log::error!("Failed to parse multiboot2 info: {:?}", e);
             panic!("Failed to parse multiboot2 information");
         }
     }
@@ -243,51 +297,70 @@ pub extern "C" fn _start(multiboot_info_addr: usize) -> ! {
     #[cfg(test)]
     test_main();
 
-    println!("Kosh kernel initialized successfully!");
+    #[cfg(feature = "bench")]
+    bench::run();
 
-    // Halt the CPU in an infinite loop
-    loop {
-        #[cfg(target_arch = "x86_64")]
-        x86_64::instructions::hlt();
-        
-        #[cfg(target_arch = "aarch64")]
-        unsafe { core::arch::asm!("wfi") }; // Wait for interrupt on ARM64
-    }
+    log::info!("Kosh kernel initialized successfully!");
+
+    // The boot thread becomes the idle loop; the timer switches to processes
+    process::preempt::idle_loop()
 }
 
 #[cfg(target_arch = "aarch64")]
 #[no_mangle]
-pub extern "C" fn _start() -> ! {
-    // Initialize early console output for debugging
-    serial_println!("Kosh Kernel Starting on ARM64...");
-    println!("Kosh Kernel Starting on ARM64...");
+pub extern "C" fn _start(dtb_address: usize) -> ! {
+    // Everything after this goes through the kernel log
+    klog::init();
+    log::info!("Kosh Kernel Starting on ARM64...");
 
     // Initialize platform abstraction layer first
     init_platform_abstraction();
     
-    // Initialize kernel without multiboot2 info (ARM64 uses different boot protocol)
-    boot::init_kernel_arm64();
+    // ARM64 boot loaders pass a device tree in x0 instead of multiboot2 info
+    boot::init_kernel_arm64(dtb_address);
 
     #[cfg(test)]
     test_main();
 
-    println!("Kosh kernel initialized successfully on ARM64!");
+    log::info!("Kosh kernel initialized successfully on ARM64!");
 
-    // Halt the CPU in an infinite loop
-    loop {
-        unsafe { core::arch::asm!("wfi") }; // Wait for interrupt
-    }
+    // The boot thread becomes the idle loop; the timer switches to processes
+    process::preempt::idle_loop()
+}
+
+/// Rust entry on RISC-V 64, called by `platform::riscv64::entry` on the
+/// boot stack
+#[cfg(target_arch = "riscv64")]
+pub extern "C" fn riscv64_main(hart_id: usize, dtb_address: usize) -> ! {
+    platform::riscv64::smp::set_boot_hart(hart_id);
+    
+    // Everything after this goes through the kernel log
+    klog::init();
+    log::info!("Kosh Kernel Starting on RISC-V 64 (hart {})...", hart_id);
+
+    init_platform_abstraction();
+    
+    // The firmware passes a device tree in a1
+    boot::init_kernel_riscv64(dtb_address);
+
+    #[cfg(test)]
+    test_main();
+
+    log::info!("Kosh kernel initialized successfully on RISC-V 64!");
+
+    // The boot thread becomes the idle loop; the timer switches to processes
+    process::preempt::idle_loop()
 }
 
 /// Initialize platform abstraction layer
 fn init_platform_abstraction() {
-    serial_println!("Initializing platform abstraction layer...");
+    log::info!("Initializing platform abstraction layer...");
     
     // Initialize the appropriate platform
     #[cfg(target_arch = "x86_64")]
     {
         if let Err(e) = platform::x86_64::init() {
-            serial_println!("Failed to initialize x86_64 platform: {:?}", e);
+            log::error!("Failed to initialize x86_64 platform: {:?}", e);
             panic!("Platform initialization failed");
         }
     }
@@ -295,12 +368,20 @@ fn init_platform_abstraction() {
     #[cfg(target_arch = "aarch64")]
     {
         if let Err(e) = platform::aarch64::init() {
-            serial_println!("Failed to initialize ARM64 platform: {:?}", e);
+            log::error!("Failed to initialize ARM64 platform: {:?}", e);
+            panic!("Platform initialization failed");
+        }
+    }
+    
+    #[cfg(target_arch = "riscv64")]
+    {
+        if let Err(e) = platform::riscv64::init() {
+            log::error!("Failed to initialize RISC-V 64 platform: {:?}", e);
             panic!("Platform initialization failed");
         }
     }
     
-    serial_println!("Platform abstraction layer initialized successfully");
+    log::info!("Platform abstraction layer initialized successfully");
 }
 
 #[panic_handler]
@@ -308,6 +389,9 @@ fn panic(info: &PanicInfo) -> ! {
     // Disable interrupts to prevent further issues
     x86_64::instructions::interrupts::disable();
     
+    // The statistics dashboard may own the screen
+    vga_buffer::force_console();
+    
     // Output panic information to both serial and VGA console
     serial_println!("\n!!! KERNEL PANIC !!!");
     println!("\n!!! KERNEL PANIC !!!");
@@ -322,13 +406,20 @@ fn panic(info: &PanicInfo) -> ! {
     serial_println!("Panic message: {}", message);
     println!("Message: {}", message);
     
+    let frame_pointer = backtrace::frame_pointer();
+    serial_println!("Backtrace:");
+    for frame in backtrace::frames(frame_pointer) {
+        serial_println!("  0x{:016x}", frame.return_address);
+    }
+    
+    // Kept for the next boot, in case this one ends in a warm reboot
+    crash::record_panic(info, frame_pointer);
+    
     serial_println!("System halted.");
     println!("System halted.");
     
-    // Halt the CPU
-    loop {
-        x86_64::instructions::hlt();
-    }
+    // Nothing runs on this CPU again, but its state can be inspected
+    kdb::on_panic(frame_pointer)
 }
 
 #[cfg(test)]
@@ -360,7 +451,7 @@ fn run_comprehensive_tests() {
     runner.run_all_tests();
 }
 
-#[cfg(test)]
+#[cfg(any(test, debug_assertions))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum QemuExitCode {
@@ -368,14 +459,18 @@ pub enum QemuExitCode {
     Failed = 0x11,
 }
 
-#[cfg(test)]
+/// Leave QEMU through its isa-debug-exit device; returns where there is
+/// none
+#[cfg(any(test, debug_assertions))]
 pub fn exit_qemu(exit_code: QemuExitCode) {
-    use x86_64::instructions::port::Port;
-
+    #[cfg(target_arch = "x86_64")]
     unsafe {
+        use x86_64::instructions::port::Port;
         let mut port = Port::new(0xf4);
         port.write(exit_code as u32);
     }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = exit_code;
 }
 
 #[test_case]
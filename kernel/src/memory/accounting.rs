@@ -0,0 +1,145 @@
+//! Per-process memory accounting
+//!
+//! Each process is charged for the address space its mappings and heap
+//! reserve, and for the pages populated in them so far. A process may be
+//! given a limit on what it reserves; `mmap` and `brk` fail with ENOMEM
+//! once it would be exceeded. The limit applies to reserved rather than
+//! populated memory because a page fault has nobody to return an error
+//! to.
+//!
+//! Shared memory regions, DMA buffers and the program image are not
+//! charged yet.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::process::ProcessId;
+
+/// Memory charged to one process
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Bytes of address space reserved by mappings and the heap
+    pub reserved_bytes: usize,
+    /// Pages populated in them
+    pub resident_pages: usize,
+    /// Size of the heap grown with `brk`
+    pub heap_bytes: usize,
+    /// Most bytes the process may reserve; None if unlimited
+    pub limit: Option<usize>,
+}
+
+static ACCOUNTS: Mutex<BTreeMap<ProcessId, MemoryUsage>> = Mutex::new(BTreeMap::new());
+
+/// Charge `bytes` of address space to `process`
+///
+/// Returns false, charging nothing, if that would take the process past
+/// its limit.
+pub fn try_reserve(process: ProcessId, bytes: usize) -> bool {
+    let mut accounts = ACCOUNTS.lock();
+    let usage = accounts.entry(process).or_default();
+    let reserved = match usage.reserved_bytes.checked_add(bytes) {
+        Some(reserved) => reserved,
+        None => return false,
+    };
+    if usage.limit.is_some_and(|limit| reserved > limit) {
+        log::debug!("Process {} would exceed its memory limit of {} bytes", process.0, usage.limit.unwrap_or(0));
+        return false;
+    }
+    usage.reserved_bytes = reserved;
+    true
+}
+
+/// Return `bytes` of address space reserved by `process`
+pub fn unreserve(process: ProcessId, bytes: usize) {
+    if let Some(usage) = ACCOUNTS.lock().get_mut(&process) {
+        usage.reserved_bytes = usage.reserved_bytes.saturating_sub(bytes);
+    }
+}
+
+/// A page was populated for `process`
+pub fn page_populated(process: ProcessId) {
+    ACCOUNTS.lock().entry(process).or_default().resident_pages += 1;
+}
+
+/// `count` populated pages of `process` were freed or unmapped
+pub fn pages_released(process: ProcessId, count: usize) {
+    if let Some(usage) = ACCOUNTS.lock().get_mut(&process) {
+        usage.resident_pages = usage.resident_pages.saturating_sub(count);
+    }
+}
+
+/// Record the size of the heap of `process`
+pub fn set_heap_size(process: ProcessId, bytes: usize) {
+    ACCOUNTS.lock().entry(process).or_default().heap_bytes = bytes;
+}
+
+/// Limit what `process` may reserve to `limit` bytes, or lift the limit
+/// with `None`
+///
+/// A limit below what the process already reserved only stops it from
+/// growing further.
+pub fn set_limit(process: ProcessId, limit: Option<usize>) {
+    ACCOUNTS.lock().entry(process).or_default().limit = limit;
+    log::debug!("Process {} memory limit set to {:?}", process.0, limit);
+}
+
+/// Memory charged to `process`
+pub fn usage(process: ProcessId) -> MemoryUsage {
+    ACCOUNTS.lock().get(&process).copied().unwrap_or_default()
+}
+
+/// Processes with populated pages and how many, most first
+pub fn by_resident_pages() -> Vec<(ProcessId, usize)> {
+    let mut processes: Vec<(ProcessId, usize)> = ACCOUNTS.lock().iter()
+        .filter(|(_, usage)| usage.resident_pages > 0)
+        .map(|(&process, usage)| (process, usage.resident_pages))
+        .collect();
+    processes.sort_by(|a, b| b.1.cmp(&a.1));
+    processes
+}
+
+/// Forget the account of an exiting process, after its mappings are gone
+pub fn release_process(process: ProcessId) {
+    ACCOUNTS.lock().remove(&process);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_reservations_respect_the_limit() {
+        let process = ProcessId::new(9500);
+        set_limit(process, Some(8192));
+        assert!(try_reserve(process, 4096));
+        assert!(try_reserve(process, 4096));
+        assert!(!try_reserve(process, 1));
+        assert_eq!(usage(process).reserved_bytes, 8192);
+
+        unreserve(process, 4096);
+        assert!(try_reserve(process, 4096));
+        set_limit(process, None);
+        assert!(try_reserve(process, usize::MAX - 8192));
+        assert!(!try_reserve(process, usize::MAX));
+        release_process(process);
+        assert_eq!(usage(process), MemoryUsage::default());
+    }
+
+    #[test_case]
+    fn test_largest_process_comes_first() {
+        let (small, large) = (ProcessId::new(9510), ProcessId::new(9511));
+        page_populated(small);
+        for _ in 0..3 {
+            page_populated(large);
+        }
+        let processes = by_resident_pages();
+        let position = |process| processes.iter().position(|&(pid, _)| pid == process);
+        assert!(position(large) < position(small));
+        assert!(processes.contains(&(large, 3)));
+
+        pages_released(large, 3);
+        assert!(!by_resident_pages().iter().any(|&(pid, _)| pid == large));
+        release_process(small);
+        release_process(large);
+    }
+}
@@ -0,0 +1,130 @@
+//! Address space layout randomization
+//!
+//! Every process image gets a layout of its own: the top of its stack, the
+//! base of its heap and the base of its mapping window are each moved a
+//! random number of pages within a span of their own, so an exploit cannot
+//! count on where anything is. A forked child keeps its parent's layout,
+//! as it keeps a copy of the address space; `exec` draws a new one.
+//!
+//! The layout also carries the image's stack canary, which is placed at
+//! the top of the new stack for the runtime to check its frames against.
+//! Its low byte is always zero, so a string overflow cannot write it back
+//! and a string read stops before it.
+//!
+//! `aslr=off` on the kernel command line puts every image at the fixed
+//! bases, so addresses repeat from boot to boot while debugging. Canaries
+//! stay random, as they move nothing.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::memory::PAGE_SIZE;
+use crate::memory::mmap::{HEAP_BASE, MMAP_WINDOW_BASE, STACK_TOP};
+
+/// Span the heap base is moved up within
+const HEAP_SPAN: usize = 0x0000_0100_0000_0000;
+
+/// Span the mapping window base is moved up within
+const MMAP_SPAN: usize = 0x0000_0100_0000_0000;
+
+/// Span the stack top is moved down within
+const STACK_SPAN: usize = 0x0000_0004_0000_0000;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Where the parts of a process image go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserLayout {
+    /// End of the stack, which grows down from here
+    pub stack_top: usize,
+    pub heap_base: usize,
+    pub mmap_base: usize,
+    pub stack_canary: u64,
+}
+
+impl UserLayout {
+    /// Everything at the fixed bases, without a canary
+    pub const FIXED: Self = Self {
+        stack_top: STACK_TOP,
+        heap_base: HEAP_BASE,
+        mmap_base: MMAP_WINDOW_BASE,
+        stack_canary: 0,
+    };
+
+    /// The bases moved by whole pages drawn from `random`
+    pub fn randomized(mut random: impl FnMut() -> u64) -> Self {
+        let offset = |span: usize, value: u64| (value % (span / PAGE_SIZE) as u64) as usize * PAGE_SIZE;
+        Self {
+            stack_top: STACK_TOP - offset(STACK_SPAN, random()),
+            heap_base: HEAP_BASE + offset(HEAP_SPAN, random()),
+            mmap_base: MMAP_WINDOW_BASE + offset(MMAP_SPAN, random()),
+            stack_canary: canary(random()),
+        }
+    }
+}
+
+impl Default for UserLayout {
+    fn default() -> Self {
+        Self::FIXED
+    }
+}
+
+fn canary(value: u64) -> u64 {
+    value & !0xFF
+}
+
+/// Put every image at the fixed bases from now on
+pub fn disable(reason: &str) {
+    ENABLED.store(false, Ordering::Release);
+    log::warn!("{}: address space randomization disabled", reason);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// A layout for a new process image
+pub fn new_layout() -> UserLayout {
+    if is_enabled() {
+        UserLayout::randomized(crate::random::random_u64)
+    } else {
+        UserLayout { stack_canary: canary(crate::random::random_u64()), ..UserLayout::FIXED }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::mmap::{MAX_HEAP_SIZE, MMAP_WINDOW_SIZE, STACK_SIZE};
+
+    #[test_case]
+    fn test_randomized_layout_bounds() {
+        for value in [0, 1, 0x1234_5678_9ABC_DEF0, u64::MAX] {
+            let layout = UserLayout::randomized(|| value);
+            for address in [layout.stack_top, layout.heap_base, layout.mmap_base] {
+                assert_eq!(address % PAGE_SIZE, 0);
+            }
+            assert!(layout.stack_top <= STACK_TOP && layout.stack_top > STACK_TOP - STACK_SPAN);
+            assert!(layout.heap_base >= HEAP_BASE && layout.heap_base < HEAP_BASE + HEAP_SPAN);
+            assert!(layout.mmap_base >= MMAP_WINDOW_BASE && layout.mmap_base < MMAP_WINDOW_BASE + MMAP_SPAN);
+            // The areas keep their order whatever the offsets
+            assert!(layout.heap_base + MAX_HEAP_SIZE < layout.mmap_base);
+            assert!(layout.mmap_base + MMAP_WINDOW_SIZE < layout.stack_top - STACK_SIZE);
+            assert_eq!(layout.stack_canary & 0xFF, 0);
+        }
+        assert_eq!(UserLayout::randomized(|| 0), UserLayout::FIXED);
+    }
+
+    #[test_case]
+    fn test_randomized_layouts_differ() {
+        let mut next = 0u64;
+        let mut counter = move || {
+            next = next.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            next
+        };
+        let first = UserLayout::randomized(&mut counter);
+        let second = UserLayout::randomized(&mut counter);
+        assert_ne!(first.stack_top, second.stack_top);
+        assert_ne!(first.heap_base, second.heap_base);
+        assert_ne!(first.mmap_base, second.mmap_base);
+        assert_ne!(first.stack_canary, second.stack_canary);
+    }
+}
@@ -0,0 +1,258 @@
+//! DMA support for drivers
+//!
+//! Wraps the platform cache maintenance operations so drivers can hand
+//! buffers to devices and take them back without stale cache lines on
+//! non-coherent platforms such as most ARM boards.
+//!
+//! Drivers also get their DMA buffers here: physically contiguous frames
+//! mapped into the driver and into its IOMMU domain. The driver is told the
+//! address its devices reach the buffer at, which is the I/O virtual address
+//! when remapping hardware translates DMA and the physical address otherwise.
+
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+use crate::platform::{self, DmaDirection, VirtualAddress, PhysicalAddress, PlatformError};
+use crate::memory::{PAGE_SIZE, bytes_to_pages};
+use crate::memory::physical::{self, PageFrame};
+use crate::memory::vmm::{self, MemoryProtection};
+use crate::memory::iommu::{self, IommuError};
+use crate::process::ProcessId;
+
+/// Base of the virtual window DMA buffers are mapped into
+const DMA_WINDOW_BASE: usize = 0x0000_7100_0000_0000;
+
+/// Size of the DMA buffer mapping window
+const DMA_WINDOW_SIZE: usize = 0x0000_0010_0000_0000;
+
+/// Largest single DMA buffer (4 MiB of contiguous frames)
+pub const MAX_DMA_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// DMA errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaError {
+    /// Buffer address or length is invalid
+    InvalidBuffer,
+    /// Cache maintenance failed on this platform
+    CacheMaintenanceFailed,
+    /// No contiguous frames or window space left for the buffer
+    OutOfMemory,
+    /// The buffer could not be mapped into the driver or its domain
+    MappingFailed,
+}
+
+/// How the CPU caches a DMA buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaCacheMode {
+    /// Cached; the driver syncs around transfers on non-coherent platforms
+    Cached,
+    /// Uncached; never needs syncing, for descriptor rings and the like
+    Uncached,
+}
+
+impl DmaCacheMode {
+    pub fn from_raw(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(DmaCacheMode::Cached),
+            1 => Some(DmaCacheMode::Uncached),
+            _ => None,
+        }
+    }
+}
+
+/// A DMA buffer handed to a driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaAllocation {
+    /// Where the driver reaches the buffer
+    pub address: vmm::VirtualAddress,
+    /// Where the driver's devices reach the buffer
+    pub device_address: u64,
+}
+
+#[derive(Debug)]
+struct DmaBuffer {
+    owner: ProcessId,
+    start_frame: PageFrame,
+    page_count: usize,
+    /// I/O virtual address in the owner's domain, if it has one
+    iova: Option<u64>,
+}
+
+struct DmaAllocator {
+    /// Buffers keyed by their address in the owner
+    buffers: BTreeMap<usize, DmaBuffer>,
+    /// Next free address in the mapping window (addresses are never reused)
+    next_address: usize,
+}
+
+impl DmaAllocator {
+    const fn new() -> Self {
+        Self {
+            buffers: BTreeMap::new(),
+            next_address: DMA_WINDOW_BASE,
+        }
+    }
+
+    fn allocate(&mut self, owner: ProcessId, size: usize, cache: DmaCacheMode) -> Result<DmaAllocation, DmaError> {
+        if size == 0 || size > MAX_DMA_BUFFER_SIZE {
+            return Err(DmaError::InvalidBuffer);
+        }
+        let page_count = bytes_to_pages(size);
+        let mapped_size = page_count * PAGE_SIZE;
+        if self.next_address + mapped_size > DMA_WINDOW_BASE + DMA_WINDOW_SIZE {
+            return Err(DmaError::OutOfMemory);
+        }
+
+        let start_frame = physical::allocate_frames(page_count).ok_or(DmaError::OutOfMemory)?;
+        let physical_address = start_frame.address() as u64;
+
+        // A driver without a domain can only use DMA while nothing remaps it
+        let iova = match iommu::map_for_driver(owner, PhysicalAddress::new(physical_address), mapped_size, true) {
+            Ok(iova) => Some(iova),
+            Err(IommuError::NotInitialized | IommuError::DomainNotFound) if !iommu::remaps_dma() => None,
+            Err(_) => {
+                physical::deallocate_frames(start_frame, page_count);
+                return Err(DmaError::MappingFailed);
+            }
+        };
+
+        let address = vmm::VirtualAddress::new(self.next_address);
+        let protection = match cache {
+            DmaCacheMode::Cached => MemoryProtection::user_read_write(),
+            DmaCacheMode::Uncached => MemoryProtection::user_device(true),
+        };
+        if vmm::map_user_range(owner, address, physical_address as usize, mapped_size, protection).is_err() {
+            if let Some(iova) = iova {
+                let _ = iommu::unmap_for_driver(owner, iova);
+            }
+            physical::deallocate_frames(start_frame, page_count);
+            return Err(DmaError::MappingFailed);
+        }
+        self.next_address += mapped_size;
+
+        // Never leak stale frame contents to the driver or its device
+        unsafe {
+            core::ptr::write_bytes(address.as_usize() as *mut u8, 0, mapped_size);
+        }
+
+        let device_address = match iova {
+            Some(iova) if iommu::remaps_dma() => iova,
+            _ => physical_address,
+        };
+        self.buffers.insert(address.as_usize(), DmaBuffer { owner, start_frame, page_count, iova });
+
+        log::debug!("DMA: process {} allocated {} pages at 0x{:x} (device 0x{:x}, {:?})",
+                       owner.0, page_count, address.as_usize(), device_address, cache);
+        Ok(DmaAllocation { address, device_address })
+    }
+
+    fn free(&mut self, owner: ProcessId, address: usize) -> Result<(), DmaError> {
+        match self.buffers.get(&address) {
+            Some(buffer) if buffer.owner == owner => {}
+            _ => return Err(DmaError::InvalidBuffer),
+        }
+        let buffer = self.buffers.remove(&address).ok_or(DmaError::InvalidBuffer)?;
+
+        // Revoke the device's access before the frames can be reused
+        if let Some(iova) = buffer.iova {
+            let _ = iommu::unmap_for_driver(owner, iova);
+        }
+        for page in 0..buffer.page_count {
+            let _ = vmm::unmap_user_page(owner, vmm::VirtualAddress::new(address + page * PAGE_SIZE));
+        }
+        physical::deallocate_frames(buffer.start_frame, buffer.page_count);
+        Ok(())
+    }
+
+    fn release_process(&mut self, owner: ProcessId) {
+        let addresses: alloc::vec::Vec<usize> = self.buffers.iter()
+            .filter(|(_, buffer)| buffer.owner == owner)
+            .map(|(&address, _)| address)
+            .collect();
+        for address in addresses {
+            let _ = self.free(owner, address);
+        }
+    }
+}
+
+static DMA_ALLOCATOR: Mutex<DmaAllocator> = Mutex::new(DmaAllocator::new());
+
+impl From<PlatformError> for DmaError {
+    fn from(_error: PlatformError) -> Self {
+        DmaError::CacheMaintenanceFailed
+    }
+}
+
+/// Make a buffer coherent for a device before starting DMA
+pub fn sync_for_device(addr: VirtualAddress, size: usize, direction: DmaDirection) -> Result<(), DmaError> {
+    if addr.as_u64() == 0 {
+        return Err(DmaError::InvalidBuffer);
+    }
+    
+    platform::current_platform()
+        .cache_operations()
+        .sync_for_device(addr, size, direction)?;
+    
+    log::debug!("DMA sync for device: {} ({} bytes, {:?})", addr, size, direction);
+    Ok(())
+}
+
+/// Make a buffer coherent for the CPU after DMA has completed
+pub fn sync_for_cpu(addr: VirtualAddress, size: usize, direction: DmaDirection) -> Result<(), DmaError> {
+    if addr.as_u64() == 0 {
+        return Err(DmaError::InvalidBuffer);
+    }
+    
+    platform::current_platform()
+        .cache_operations()
+        .sync_for_cpu(addr, size, direction)?;
+    
+    log::debug!("DMA sync for CPU: {} ({} bytes, {:?})", addr, size, direction);
+    Ok(())
+}
+
+/// Whether DMA on this platform needs explicit cache maintenance
+pub fn needs_cache_maintenance() -> bool {
+    !platform::current_platform().cache_operations().is_dma_coherent()
+}
+
+/// Allocate a zeroed, physically contiguous DMA buffer for a driver
+pub fn allocate_buffer(owner: ProcessId, size: usize, cache: DmaCacheMode) -> Result<DmaAllocation, DmaError> {
+    DMA_ALLOCATOR.lock().allocate(owner, size, cache)
+}
+
+/// Free a DMA buffer by the address it was mapped at in its owner
+pub fn free_buffer(owner: ProcessId, address: usize) -> Result<(), DmaError> {
+    DMA_ALLOCATOR.lock().free(owner, address)
+}
+
+/// Free every DMA buffer of a terminating process
+pub fn release_process(owner: ProcessId) {
+    DMA_ALLOCATOR.lock().release_process(owner);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_dma_rejects_invalid_size() {
+        let mut allocator = DmaAllocator::new();
+        let pid = ProcessId::new(9301);
+        assert_eq!(allocator.allocate(pid, 0, DmaCacheMode::Cached), Err(DmaError::InvalidBuffer));
+        assert_eq!(allocator.allocate(pid, MAX_DMA_BUFFER_SIZE + 1, DmaCacheMode::Uncached), Err(DmaError::InvalidBuffer));
+        assert_eq!(allocator.next_address, DMA_WINDOW_BASE);
+    }
+
+    #[test_case]
+    fn test_dma_free_unknown_buffer() {
+        let mut allocator = DmaAllocator::new();
+        assert_eq!(allocator.free(ProcessId::new(9302), DMA_WINDOW_BASE), Err(DmaError::InvalidBuffer));
+    }
+
+    #[test_case]
+    fn test_dma_cache_mode_from_raw() {
+        assert_eq!(DmaCacheMode::from_raw(0), Some(DmaCacheMode::Cached));
+        assert_eq!(DmaCacheMode::from_raw(1), Some(DmaCacheMode::Uncached));
+        assert_eq!(DmaCacheMode::from_raw(2), None);
+    }
+}
@@ -4,7 +4,10 @@ use spin::Mutex;
 use alloc::vec::Vec;
 use crate::memory::{PAGE_SIZE, align_up};
 use crate::memory::physical::allocate_frames;
-use crate::{serial_println, println};
+#[cfg(debug_assertions)]
+use crate::memory::kasan::{self, Quarantine, Violation, ViolationKind, REDZONE_SIZE, REDZONE_PATTERN, FREED_PATTERN};
+#[cfg(debug_assertions)]
+use crate::memory::heap_debug::{self, LeakReport, LeakSample};
 
 /// Minimum allocation size (to reduce fragmentation)
 const MIN_ALLOC_SIZE: usize = 16;
@@ -25,6 +28,15 @@ struct BlockHeader {
     is_free: bool,
     #[cfg(debug_assertions)]
     alloc_id: u64,
+    /// Size requested by the caller, excluding redzones
+    #[cfg(debug_assertions)]
+    requested_size: usize,
+    /// Block has been freed but is held in the quarantine
+    #[cfg(debug_assertions)]
+    quarantined: bool,
+    /// `heap_debug` tag active when the block was allocated
+    #[cfg(debug_assertions)]
+    tag: u8,
 }
 
 impl BlockHeader {
@@ -37,6 +49,12 @@ impl BlockHeader {
             is_free: true,
             #[cfg(debug_assertions)]
             alloc_id: 0,
+            #[cfg(debug_assertions)]
+            requested_size: 0,
+            #[cfg(debug_assertions)]
+            quarantined: false,
+            #[cfg(debug_assertions)]
+            tag: heap_debug::UNTAGGED,
         }
     }
 
@@ -61,6 +79,24 @@ impl BlockHeader {
     fn total_size(&self) -> usize {
         core::mem::size_of::<BlockHeader>() + self.size
     }
+
+    /// Get the pointer handed out to the caller (after the front redzone)
+    #[cfg(debug_assertions)]
+    fn user_ptr(&self) -> *mut u8 {
+        unsafe { self.data_ptr().add(REDZONE_SIZE) }
+    }
+
+    /// Build a violation report for this block
+    #[cfg(debug_assertions)]
+    fn violation(&self, kind: ViolationKind, bad_addr: usize) -> Violation {
+        Violation {
+            kind,
+            object_addr: self.user_ptr() as usize,
+            object_size: self.requested_size,
+            bad_addr,
+            alloc_id: self.alloc_id,
+        }
+    }
 }
 
 /// Allocation tracking for debugging
@@ -106,6 +142,9 @@ pub struct KernelHeapAllocator {
     /// Next allocation ID for debugging
     #[cfg(debug_assertions)]
     next_alloc_id: u64,
+    /// Freed blocks awaiting reuse (use-after-free detection)
+    #[cfg(debug_assertions)]
+    quarantine: Quarantine<NonNull<BlockHeader>>,
 }
 
 // SAFETY: KernelHeapAllocator is only used in kernel context where we control access
@@ -122,6 +161,8 @@ impl KernelHeapAllocator {
             stats: AllocationStats::new(),
             #[cfg(debug_assertions)]
             next_alloc_id: 1,
+            #[cfg(debug_assertions)]
+            quarantine: Quarantine::new(),
         }
     }
 
@@ -160,7 +201,7 @@ impl KernelHeapAllocator {
         self.stats.heap_size = heap_size;
         self.stats.free_bytes = initial_size;
 
-        serial_println!("Kernel heap initialized: {} KB at 0x{:x}", 
+        log::info!("Kernel heap initialized: {} KB at 0x{:x}", 
                        heap_size / 1024, heap_start as usize);
 
         Ok(())
@@ -168,7 +209,7 @@ impl KernelHeapAllocator {
 
     /// Allocate memory with the given layout
     pub fn allocate(&mut self, layout: Layout) -> Result<NonNull<u8>, &'static str> {
-        let size = layout.size().max(MIN_ALLOC_SIZE);
+        let size = Self::block_size_for(layout.size());
         let align = layout.align();
 
         if size > MAX_ALLOC_SIZE {
@@ -176,8 +217,19 @@ impl KernelHeapAllocator {
         }
 
         // Find a suitable free block
+        #[cfg(not(debug_assertions))]
         let block = self.find_free_block(size, align)?;
 
+        // Quarantined blocks are only recycled early when the heap runs dry
+        #[cfg(debug_assertions)]
+        let block = match self.find_free_block(size, align) {
+            Ok(block) => block,
+            Err(_) => {
+                self.flush_quarantine();
+                self.find_free_block(size, align)?
+            }
+        };
+
         // Split the block if it's significantly larger than needed
         self.split_block(block, size);
 
@@ -187,6 +239,10 @@ impl KernelHeapAllocator {
             #[cfg(debug_assertions)]
             {
                 (*block.as_ptr()).alloc_id = self.next_alloc_id;
+                (*block.as_ptr()).requested_size = layout.size();
+                (*block.as_ptr()).quarantined = false;
+                (*block.as_ptr()).tag = heap_debug::current_tag();
+                heap_debug::record_alloc((*block.as_ptr()).tag, layout.size());
                 self.next_alloc_id += 1;
             }
         }
@@ -205,23 +261,50 @@ impl KernelHeapAllocator {
             self.stats.peak_bytes = self.stats.current_bytes;
         }
 
+        #[cfg(not(debug_assertions))]
         let data_ptr = unsafe { (*block.as_ptr()).data_ptr() };
 
         #[cfg(debug_assertions)]
-        {
+        let data_ptr = unsafe {
+            let header = &*block.as_ptr();
+            let user_ptr = header.user_ptr();
+            let requested = header.requested_size;
+
+            // Redzones on both sides; the back redzone absorbs any slack left
+            // when the block was not split
+            kasan::poison(header.data_ptr(), REDZONE_SIZE, REDZONE_PATTERN);
+            kasan::poison(user_ptr.add(requested), header.size - REDZONE_SIZE - requested, REDZONE_PATTERN);
+
             // Fill allocated memory with a pattern for debugging
-            unsafe {
-                ptr::write_bytes(data_ptr, 0xAA, size);
-            }
-        }
+            ptr::write_bytes(user_ptr, 0xAA, requested);
+            user_ptr
+        };
 
         Ok(NonNull::new(data_ptr).unwrap())
     }
 
     /// Deallocate memory
     pub fn deallocate(&mut self, ptr: NonNull<u8>) -> Result<(), &'static str> {
+        #[cfg(not(debug_assertions))]
         let data_ptr = ptr.as_ptr();
 
+        #[cfg(debug_assertions)]
+        let data_ptr = {
+            let user_addr = ptr.as_ptr() as usize;
+            let heap_start = self.heap_start as usize;
+            if user_addr < heap_start + core::mem::size_of::<BlockHeader>() + REDZONE_SIZE
+                || user_addr >= heap_start + self.heap_size {
+                kasan::report(Violation {
+                    kind: ViolationKind::InvalidFree,
+                    object_addr: user_addr,
+                    object_size: 0,
+                    bad_addr: user_addr,
+                    alloc_id: 0,
+                });
+            }
+            ptr.as_ptr().wrapping_sub(REDZONE_SIZE)
+        };
+
         // Get the block header
         let block_ptr = unsafe { BlockHeader::from_data_ptr(data_ptr) };
         let block = NonNull::new(block_ptr).ok_or("Invalid pointer")?;
@@ -229,41 +312,209 @@ impl KernelHeapAllocator {
         // Validate the block
         unsafe {
             if !(*block.as_ptr()).is_valid() {
+                #[cfg(debug_assertions)]
+                kasan::report(Violation {
+                    kind: ViolationKind::InvalidFree,
+                    object_addr: ptr.as_ptr() as usize,
+                    object_size: 0,
+                    bad_addr: block_ptr as usize,
+                    alloc_id: 0,
+                });
+                #[cfg(not(debug_assertions))]
                 return Err("Heap corruption detected: invalid magic number");
             }
 
             if (*block.as_ptr()).is_free {
+                #[cfg(debug_assertions)]
+                {
+                    log::debug!("Double free of a block allocated under tag {}",
+                                heap_debug::tag_name((*block.as_ptr()).tag));
+                    kasan::report((*block.as_ptr()).violation(ViolationKind::DoubleFree, ptr.as_ptr() as usize));
+                }
+                #[cfg(not(debug_assertions))]
                 return Err("Double free detected");
             }
 
             let size = (*block.as_ptr()).size;
 
-            // Mark as free
-            (*block.as_ptr()).is_free = true;
-
             #[cfg(debug_assertions)]
             {
-                // Fill freed memory with a pattern for debugging
-                ptr::write_bytes(data_ptr, 0xDD, size);
+                self.check_redzones(block);
+                heap_debug::record_free((*block.as_ptr()).tag, (*block.as_ptr()).requested_size);
             }
 
+            // Mark as free
+            (*block.as_ptr()).is_free = true;
+
             // Update statistics
             self.stats.total_deallocations += 1;
             self.stats.current_allocations -= 1;
             self.stats.bytes_deallocated += size;
             self.stats.current_bytes -= size;
-            self.stats.free_bytes += size;
 
-            // Add back to free list
-            self.add_to_free_list(block);
+            #[cfg(debug_assertions)]
+            self.quarantine_block(block);
 
-            // Try to coalesce with adjacent free blocks
-            self.coalesce_free_blocks(block);
+            #[cfg(not(debug_assertions))]
+            self.release_block(block);
         }
 
         Ok(())
     }
 
+    /// Size of the heap block backing an allocation of `requested` bytes
+    #[cfg(not(debug_assertions))]
+    fn block_size_for(requested: usize) -> usize {
+        requested.max(MIN_ALLOC_SIZE)
+    }
+
+    /// Size of the heap block backing an allocation of `requested` bytes,
+    /// including the front and back redzones
+    #[cfg(debug_assertions)]
+    fn block_size_for(requested: usize) -> usize {
+        let rounded = (requested.max(MIN_ALLOC_SIZE) + REDZONE_SIZE - 1) & !(REDZONE_SIZE - 1);
+        rounded + 2 * REDZONE_SIZE
+    }
+
+    /// Return a freed block to the free list
+    fn release_block(&mut self, block: NonNull<BlockHeader>) {
+        unsafe {
+            self.stats.free_bytes += (*block.as_ptr()).size;
+
+            #[cfg(debug_assertions)]
+            {
+                (*block.as_ptr()).quarantined = false;
+            }
+        }
+
+        // Add back to free list
+        self.add_to_free_list(block);
+
+        // Try to coalesce with adjacent free blocks
+        self.coalesce_free_blocks(block);
+    }
+
+    /// Verify both redzones of an allocated block, reporting any overwrite
+    #[cfg(debug_assertions)]
+    fn check_redzones(&self, block: NonNull<BlockHeader>) {
+        unsafe {
+            let header = &*block.as_ptr();
+            let requested = header.requested_size;
+
+            if let Some(bad) = kasan::find_corruption(header.data_ptr(), REDZONE_SIZE, REDZONE_PATTERN) {
+                kasan::report(header.violation(ViolationKind::HeapUnderflow, bad));
+            }
+
+            let tail = header.user_ptr().add(requested);
+            let tail_len = header.size - REDZONE_SIZE - requested;
+            if let Some(bad) = kasan::find_corruption(tail, tail_len, REDZONE_PATTERN) {
+                kasan::report(header.violation(ViolationKind::HeapOverflow, bad));
+            }
+        }
+    }
+
+    /// Verify that a quarantined block still carries the freed pattern
+    #[cfg(debug_assertions)]
+    fn check_poison(&self, block: NonNull<BlockHeader>) {
+        unsafe {
+            let header = &*block.as_ptr();
+            let poisoned_len = header.size - REDZONE_SIZE;
+            if let Some(bad) = kasan::find_corruption(header.user_ptr(), poisoned_len, FREED_PATTERN) {
+                kasan::report(header.violation(ViolationKind::UseAfterFree, bad));
+            }
+        }
+    }
+
+    /// Poison a freed block and park it in the quarantine, releasing the
+    /// oldest quarantined block if the quarantine is full
+    #[cfg(debug_assertions)]
+    fn quarantine_block(&mut self, block: NonNull<BlockHeader>) {
+        unsafe {
+            let header = &mut *block.as_ptr();
+            kasan::poison(header.user_ptr(), header.size - REDZONE_SIZE, FREED_PATTERN);
+            header.quarantined = true;
+        }
+
+        if let Some(evicted) = self.quarantine.push(block) {
+            self.check_poison(evicted);
+            self.release_block(evicted);
+        }
+    }
+
+    /// Release every quarantined block back to the free list
+    #[cfg(debug_assertions)]
+    fn flush_quarantine(&mut self) {
+        while let Some(block) = self.quarantine.pop() {
+            self.check_poison(block);
+            self.release_block(block);
+        }
+    }
+
+    /// Walk every block in the heap and verify headers, redzones of live
+    /// allocations and poison of quarantined blocks
+    #[cfg(debug_assertions)]
+    pub fn check_integrity(&self) {
+        if self.heap_start.is_null() {
+            return;
+        }
+
+        let heap_end = self.heap_start as usize + self.heap_size;
+        let mut current = self.heap_start as usize;
+
+        while current < heap_end {
+            let block = current as *mut BlockHeader;
+            unsafe {
+                if !(*block).is_valid() || current + (*block).total_size() > heap_end {
+                    kasan::report(Violation {
+                        kind: ViolationKind::HeaderCorruption,
+                        object_addr: (*block).data_ptr() as usize,
+                        object_size: 0,
+                        bad_addr: current,
+                        alloc_id: 0,
+                    });
+                }
+
+                let block = NonNull::new_unchecked(block);
+                if !(*block.as_ptr()).is_free {
+                    self.check_redzones(block);
+                } else if (*block.as_ptr()).quarantined {
+                    self.check_poison(block);
+                }
+
+                current += (*block.as_ptr()).total_size();
+            }
+        }
+    }
+
+    /// Gather the live blocks allocated from allocation `since` on
+    #[cfg(debug_assertions)]
+    fn leak_report(&self, since: u64) -> LeakReport {
+        let mut report = LeakReport::new();
+        if self.heap_start.is_null() {
+            return report;
+        }
+
+        let heap_end = self.heap_start as usize + self.heap_size;
+        let mut current = self.heap_start as usize;
+        while current < heap_end {
+            let block = unsafe { &*(current as *const BlockHeader) };
+            // A corrupted header ends the walk; check_integrity reports it
+            if !block.is_valid() || current + block.total_size() > heap_end {
+                break;
+            }
+            if !block.is_free && block.alloc_id >= since {
+                report.add(LeakSample {
+                    alloc_id: block.alloc_id,
+                    address: block.user_ptr() as usize,
+                    size: block.requested_size,
+                    tag: block.tag,
+                });
+            }
+            current += block.total_size();
+        }
+        report
+    }
+
     /// Find a free block that can satisfy the allocation
     fn find_free_block(&mut self, size: usize, _align: usize) -> Result<NonNull<BlockHeader>, &'static str> {
         let mut current = self.free_list_head;
@@ -373,16 +624,22 @@ impl KernelHeapAllocator {
 
     /// Print heap statistics
     pub fn print_stats(&self) {
-        serial_println!("Kernel Heap Statistics:");
-        serial_println!("  Heap size: {} KB", self.stats.heap_size / 1024);
-        serial_println!("  Current allocations: {}", self.stats.current_allocations);
-        serial_println!("  Total allocations: {}", self.stats.total_allocations);
-        serial_println!("  Total deallocations: {}", self.stats.total_deallocations);
-        serial_println!("  Current bytes: {} KB", self.stats.current_bytes / 1024);
-        serial_println!("  Peak bytes: {} KB", self.stats.peak_bytes / 1024);
-        serial_println!("  Free bytes: {} KB", self.stats.free_bytes / 1024);
-
-        println!("Heap: {} KB total, {} KB used, {} KB free",
+        log::debug!("Kernel Heap Statistics:");
+        log::debug!("  Heap size: {} KB", self.stats.heap_size / 1024);
+        log::debug!("  Current allocations: {}", self.stats.current_allocations);
+        log::debug!("  Total allocations: {}", self.stats.total_allocations);
+        log::debug!("  Total deallocations: {}", self.stats.total_deallocations);
+        log::debug!("  Current bytes: {} KB", self.stats.current_bytes / 1024);
+        log::debug!("  Peak bytes: {} KB", self.stats.peak_bytes / 1024);
+        log::debug!("  Free bytes: {} KB", self.stats.free_bytes / 1024);
+        #[cfg(debug_assertions)]
+        {
+            log::debug!("  Quarantined blocks: {}", self.quarantine.len());
+            log::debug!("  Outstanding by tag:");
+            heap_debug::print_outstanding();
+        }
+
+        log::info!("Heap: {} KB total, {} KB used, {} KB free",
                 self.stats.heap_size / 1024,
                 (self.stats.heap_size - self.stats.free_bytes) / 1024,
                 self.stats.free_bytes / 1024);
@@ -448,7 +705,7 @@ unsafe impl GlobalAlloc for GlobalKernelAllocator {
     unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
         if let Some(non_null_ptr) = NonNull::new(ptr) {
             if let Err(e) = KERNEL_HEAP.lock().deallocate(non_null_ptr) {
-                serial_println!("Deallocation error: {}", e);
+                log::warn!("Deallocation error: {}", e);
                 // In a real kernel, we might want to panic here
             }
         }
@@ -458,7 +715,10 @@ unsafe impl GlobalAlloc for GlobalKernelAllocator {
 /// Initialize the kernel heap
 pub fn init_kernel_heap(heap_size_pages: usize) -> Result<(), &'static str> {
     KERNEL_HEAP.lock().init(heap_size_pages)?;
-    serial_println!("Kernel heap allocator initialized successfully");
+    log::info!("Kernel heap allocator initialized successfully");
+    #[cfg(debug_assertions)]
+    log::info!("KASAN-lite enabled: {}-byte redzones, {}-entry quarantine",
+                   REDZONE_SIZE, kasan::QUARANTINE_SLOTS);
     Ok(())
 }
 
@@ -477,9 +737,41 @@ pub fn validate_heap() -> Result<(), &'static str> {
     KERNEL_HEAP.lock().validate_heap()
 }
 
+/// Periodic redzone and poison sweep, driven from the timer tick.
+/// Skips the sweep if the heap is currently locked to avoid deadlocking
+/// against an interrupted allocation.
+#[cfg(debug_assertions)]
+pub fn periodic_integrity_check() {
+    if !kasan::periodic_check_due() {
+        return;
+    }
+
+    if let Some(heap) = KERNEL_HEAP.try_lock() {
+        heap.check_integrity();
+    }
+}
+
+/// Start a leak window: later leak reports only list blocks allocated
+/// from now on
+#[cfg(debug_assertions)]
+pub fn mark_leak_window() {
+    let next_alloc_id = KERNEL_HEAP.lock().next_alloc_id;
+    heap_debug::set_mark(next_alloc_id);
+}
+
+/// Print the blocks allocated since the mark that are still live, by tag,
+/// and return how many there are
+#[cfg(debug_assertions)]
+pub fn report_leaks() -> usize {
+    // Gathered under the lock, printed after it
+    let report = KERNEL_HEAP.lock().leak_report(heap_debug::mark());
+    report.print();
+    report.blocks
+}
+
 /// Test the heap allocator
 pub fn test_heap_allocator() {
-    serial_println!("Testing kernel heap allocator...");
+    log::debug!("Testing kernel heap allocator...");
 
     // Test basic allocation and deallocation
     {
@@ -488,7 +780,7 @@ pub fn test_heap_allocator() {
         
         match ptr {
             Ok(ptr) => {
-                serial_println!("Allocated 64 bytes at 0x{:x}", ptr.as_ptr() as usize);
+                log::debug!("Allocated 64 bytes at 0x{:x}", ptr.as_ptr() as usize);
                 
                 // Write some data to test
                 unsafe {
@@ -497,13 +789,13 @@ pub fn test_heap_allocator() {
                 
                 // Deallocate
                 if let Err(e) = KERNEL_HEAP.lock().deallocate(ptr) {
-                    serial_println!("Deallocation failed: {}", e);
+                    log::warn!("Deallocation failed: {}", e);
                 } else {
-                    serial_println!("Successfully deallocated 64 bytes");
+                    log::debug!("Successfully deallocated 64 bytes");
                 }
             }
             Err(e) => {
-                serial_println!("Allocation failed: {}", e);
+                log::warn!("Allocation failed: {}", e);
             }
         }
     }
@@ -516,11 +808,11 @@ pub fn test_heap_allocator() {
         
         match KERNEL_HEAP.lock().allocate(layout) {
             Ok(ptr) => {
-                serial_println!("Allocated {} bytes at 0x{:x}", size, ptr.as_ptr() as usize);
+                log::debug!("Allocated {} bytes at 0x{:x}", size, ptr.as_ptr() as usize);
                 ptrs.push((ptr, layout));
             }
             Err(e) => {
-                serial_println!("Allocation {} failed: {}", i, e);
+                log::warn!("Allocation {} failed: {}", i, e);
                 break;
             }
         }
@@ -529,18 +821,18 @@ pub fn test_heap_allocator() {
     // Deallocate all
     for (ptr, _layout) in ptrs {
         if let Err(e) = KERNEL_HEAP.lock().deallocate(ptr) {
-            serial_println!("Deallocation failed: {}", e);
+            log::warn!("Deallocation failed: {}", e);
         }
     }
 
     // Validate heap integrity
     match validate_heap() {
-        Ok(()) => serial_println!("Heap validation passed"),
-        Err(e) => serial_println!("Heap validation failed: {}", e),
+        Ok(()) => log::debug!("Heap validation passed"),
+        Err(e) => log::warn!("Heap validation failed: {}", e),
     }
 
     // Print final statistics
     print_heap_stats();
 
-    serial_println!("Heap allocator test complete");
+    log::info!("Heap allocator test complete");
 }
\ No newline at end of file
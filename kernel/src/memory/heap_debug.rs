@@ -0,0 +1,239 @@
+//! Kernel heap allocation tracking for debug builds
+//!
+//! Every heap block records the tag that was active on its CPU when it was
+//! allocated. A code path tags its allocations by holding the guard
+//! `enter` returns; the system call dispatcher enters the name of each
+//! call, so the IPC and driver paths are tagged without touching them.
+//!
+//! Outstanding allocations and bytes are counted per tag. A leak report
+//! walks the heap for blocks still live that were allocated after a mark
+//! and groups them by tag; `SYS_DEBUG_DUMP` sets the mark and prints the
+//! report, so a path can be exercised between the two and whatever it
+//! left behind shows up. Double frees name the tag of the allocation.
+//!
+//! The tracker runs inside the allocator and never allocates: tags live in
+//! a fixed table, and allocations under a tag that no longer fits in it
+//! are counted as untagged.
+
+use core::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use spin::Mutex;
+use crate::smp::{self, MAX_CPUS};
+
+/// Most distinct tags
+pub const MAX_TAGS: usize = 128;
+
+/// Tag of allocations made outside any tagged path
+pub const UNTAGGED: u8 = 0;
+
+/// Live blocks a leak report lists one by one; the rest are only counted
+pub const LEAK_SAMPLES: usize = 16;
+
+struct TagTable {
+    names: [&'static str; MAX_TAGS],
+    count: usize,
+}
+
+impl TagTable {
+    const fn new() -> Self {
+        let mut names = [""; MAX_TAGS];
+        names[UNTAGGED as usize] = "untagged";
+        Self { names, count: 1 }
+    }
+
+    /// Index of `name`, adding it if there is room
+    fn index_of(&mut self, name: &'static str) -> u8 {
+        if let Some(index) = self.names[..self.count].iter().position(|&known| known == name) {
+            return index as u8;
+        }
+        if self.count == MAX_TAGS {
+            return UNTAGGED;
+        }
+        self.names[self.count] = name;
+        self.count += 1;
+        (self.count - 1) as u8
+    }
+}
+
+static TAGS: Mutex<TagTable> = Mutex::new(TagTable::new());
+
+/// Tag active on each CPU
+static CURRENT_TAG: [AtomicU8; MAX_CPUS] = [const { AtomicU8::new(UNTAGGED) }; MAX_CPUS];
+
+/// Live allocations and their bytes, per tag
+static OUTSTANDING: [AtomicUsize; MAX_TAGS] = [const { AtomicUsize::new(0) }; MAX_TAGS];
+static OUTSTANDING_BYTES: [AtomicUsize; MAX_TAGS] = [const { AtomicUsize::new(0) }; MAX_TAGS];
+
+/// Allocation ID from which leak reports count
+static MARK: AtomicU64 = AtomicU64::new(0);
+
+/// Restores the previous tag of its CPU when dropped
+pub struct TagGuard {
+    cpu: usize,
+    previous: u8,
+}
+
+impl Drop for TagGuard {
+    fn drop(&mut self) {
+        CURRENT_TAG[self.cpu].store(self.previous, Ordering::Relaxed);
+    }
+}
+
+/// Tag the allocations the calling CPU makes until the guard is dropped
+pub fn enter(name: &'static str) -> TagGuard {
+    let tag = TAGS.lock().index_of(name);
+    let cpu = smp::current_cpu();
+    let previous = CURRENT_TAG[cpu].swap(tag, Ordering::Relaxed);
+    TagGuard { cpu, previous }
+}
+
+/// Tag for an allocation being made on the calling CPU
+pub fn current_tag() -> u8 {
+    CURRENT_TAG[smp::current_cpu()].load(Ordering::Relaxed)
+}
+
+/// Name of `tag`
+pub fn tag_name(tag: u8) -> &'static str {
+    TAGS.lock().names.get(tag as usize).copied().filter(|name| !name.is_empty()).unwrap_or("unknown")
+}
+
+/// Count an allocation of `size` bytes under `tag`
+pub fn record_alloc(tag: u8, size: usize) {
+    OUTSTANDING[tag as usize].fetch_add(1, Ordering::Relaxed);
+    OUTSTANDING_BYTES[tag as usize].fetch_add(size, Ordering::Relaxed);
+}
+
+/// Count the release of an allocation of `size` bytes made under `tag`
+pub fn record_free(tag: u8, size: usize) {
+    OUTSTANDING[tag as usize].fetch_sub(1, Ordering::Relaxed);
+    OUTSTANDING_BYTES[tag as usize].fetch_sub(size, Ordering::Relaxed);
+}
+
+/// Report only allocations from `alloc_id` on
+pub fn set_mark(alloc_id: u64) {
+    MARK.store(alloc_id, Ordering::Relaxed);
+}
+
+/// Allocation ID leak reports start from
+pub fn mark() -> u64 {
+    MARK.load(Ordering::Relaxed)
+}
+
+/// Log the live allocations and bytes of every tag that has any
+pub fn print_outstanding() {
+    let count = TAGS.lock().count;
+    for tag in 0..count {
+        let allocations = OUTSTANDING[tag].load(Ordering::Relaxed);
+        if allocations > 0 {
+            log::info!("  {:<20} {:>8} allocations {:>10} bytes",
+                       tag_name(tag as u8), allocations, OUTSTANDING_BYTES[tag].load(Ordering::Relaxed));
+        }
+    }
+}
+
+/// A live block in a leak report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeakSample {
+    pub alloc_id: u64,
+    pub address: usize,
+    pub size: usize,
+    pub tag: u8,
+}
+
+/// Blocks allocated after the mark and still live, gathered while the heap
+/// is locked and printed once it is not
+pub struct LeakReport {
+    /// Blocks and bytes per tag
+    per_tag: [(usize, usize); MAX_TAGS],
+    /// The first blocks found, oldest allocation first
+    samples: [Option<LeakSample>; LEAK_SAMPLES],
+    pub blocks: usize,
+    pub bytes: usize,
+}
+
+impl LeakReport {
+    pub const fn new() -> Self {
+        Self { per_tag: [(0, 0); MAX_TAGS], samples: [None; LEAK_SAMPLES], blocks: 0, bytes: 0 }
+    }
+
+    /// Count a live block, keeping it as a sample if it is among the
+    /// oldest seen
+    pub fn add(&mut self, sample: LeakSample) {
+        let (blocks, bytes) = &mut self.per_tag[sample.tag as usize];
+        *blocks += 1;
+        *bytes += sample.size;
+        self.blocks += 1;
+        self.bytes += sample.size;
+
+        let position = self.samples.iter()
+            .position(|slot| slot.map_or(true, |kept| sample.alloc_id < kept.alloc_id));
+        if let Some(position) = position {
+            self.samples[position..].rotate_right(1);
+            self.samples[position] = Some(sample);
+        }
+    }
+
+    /// Log the report
+    pub fn print(&self) {
+        log::info!("Heap leak report: {} blocks, {} bytes live since allocation #{}",
+                   self.blocks, self.bytes, mark());
+        for (tag, &(blocks, bytes)) in self.per_tag.iter().enumerate() {
+            if blocks > 0 {
+                log::info!("  {:<20} {:>8} blocks {:>10} bytes", tag_name(tag as u8), blocks, bytes);
+            }
+        }
+        for sample in self.samples.iter().flatten() {
+            log::info!("  #{:<8} 0x{:016x} {:>8} bytes  {}",
+                       sample.alloc_id, sample.address, sample.size, tag_name(sample.tag));
+        }
+        if self.blocks > LEAK_SAMPLES {
+            log::info!("  ... and {} more", self.blocks - LEAK_SAMPLES);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_tags_are_restored_and_reused() {
+        let before = current_tag();
+        {
+            let _outer = enter("test_outer");
+            let outer = current_tag();
+            {
+                let _inner = enter("test_inner");
+                assert_ne!(current_tag(), outer);
+                assert_eq!(tag_name(current_tag()), "test_inner");
+            }
+            assert_eq!(current_tag(), outer);
+            drop(enter("test_outer"));
+            assert_eq!(current_tag(), outer);
+        }
+        assert_eq!(current_tag(), before);
+    }
+
+    #[test_case]
+    fn test_full_table_falls_back_to_untagged() {
+        let mut table = TagTable::new();
+        assert_eq!(table.index_of("a"), 1);
+        assert_eq!(table.index_of("b"), 2);
+        assert_eq!(table.index_of("a"), 1);
+        table.count = MAX_TAGS;
+        assert_eq!(table.index_of("new"), UNTAGGED);
+        assert_eq!(table.index_of("a"), 1);
+    }
+
+    #[test_case]
+    fn test_leak_report_keeps_oldest_samples() {
+        let mut report = LeakReport::new();
+        for alloc_id in (1..=LEAK_SAMPLES as u64 + 4).rev() {
+            report.add(LeakSample { alloc_id, address: 0x1000 * alloc_id as usize, size: 32, tag: 3 });
+        }
+        assert_eq!(report.blocks, LEAK_SAMPLES + 4);
+        assert_eq!(report.bytes, 32 * (LEAK_SAMPLES + 4));
+        assert_eq!(report.per_tag[3], (LEAK_SAMPLES + 4, 32 * (LEAK_SAMPLES + 4)));
+        assert_eq!(report.samples[0].map(|sample| sample.alloc_id), Some(1));
+        assert_eq!(report.samples[LEAK_SAMPLES - 1].map(|sample| sample.alloc_id), Some(LEAK_SAMPLES as u64));
+    }
+}
@@ -0,0 +1,389 @@
+//! IOMMU domains for userspace drivers
+//!
+//! Every registered driver gets its own DMA translation domain. Devices the
+//! driver owns are attached to that domain, and only buffers explicitly
+//! mapped for the driver are reachable through it, so a compromised driver
+//! cannot point its device at kernel memory or at another driver's buffers.
+//!
+//! Without remapping hardware the manager runs in software-only mode: the
+//! bookkeeping is identical, but nothing stops a device from bypassing it.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+use crate::platform::{IommuDomainId, DmaDeviceId, PhysicalAddress, PlatformError};
+use crate::platform::traits::IommuOperations;
+use crate::process::ProcessId;
+use super::PAGE_SIZE;
+
+/// First I/O virtual address handed out in each domain
+///
+/// The low range is left unmapped so a zero or small bogus DMA address faults.
+pub const IOVA_BASE: u64 = 0x1000_0000;
+
+/// End of the I/O virtual address space (48-bit)
+pub const IOVA_LIMIT: u64 = 1 << 48;
+
+/// IOMMU errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IommuError {
+    /// The IOMMU manager has not been initialized
+    NotInitialized,
+    /// The driver already has a domain
+    DomainExists,
+    /// The driver has no domain
+    DomainNotFound,
+    /// All domain identifiers are in use
+    NoFreeDomains,
+    /// Address range is empty, misaligned or outside the IOVA space
+    InvalidRange,
+    /// No mapping exists at the given I/O virtual address
+    NotMapped,
+    /// The device is attached to another driver's domain
+    DeviceInUse,
+    /// The remapping hardware rejected the operation
+    HardwareError,
+}
+
+impl fmt::Display for IommuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IommuError::NotInitialized => write!(f, "IOMMU not initialized"),
+            IommuError::DomainExists => write!(f, "DMA domain already exists"),
+            IommuError::DomainNotFound => write!(f, "DMA domain not found"),
+            IommuError::NoFreeDomains => write!(f, "No free DMA domain identifiers"),
+            IommuError::InvalidRange => write!(f, "Invalid DMA address range"),
+            IommuError::NotMapped => write!(f, "I/O virtual address not mapped"),
+            IommuError::DeviceInUse => write!(f, "Device attached to another driver"),
+            IommuError::HardwareError => write!(f, "IOMMU hardware error"),
+        }
+    }
+}
+
+impl From<PlatformError> for IommuError {
+    fn from(_error: PlatformError) -> Self {
+        IommuError::HardwareError
+    }
+}
+
+/// A buffer mapped into a driver's domain
+#[derive(Debug, Clone, Copy)]
+struct DmaMapping {
+    physical: PhysicalAddress,
+    size: usize,
+    writable: bool,
+}
+
+/// Translation domain owned by one driver process
+#[derive(Debug)]
+struct DriverDomain {
+    id: IommuDomainId,
+    devices: Vec<DmaDeviceId>,
+    /// Next unused I/O virtual address (bump allocated)
+    next_iova: u64,
+    /// Mappings keyed by their starting I/O virtual address
+    mappings: BTreeMap<u64, DmaMapping>,
+}
+
+/// IOMMU statistics
+#[derive(Debug, Clone)]
+pub struct IommuStatistics {
+    pub hardware: Option<&'static str>,
+    pub domains: usize,
+    pub attached_devices: usize,
+    pub mappings: usize,
+    pub mapped_bytes: usize,
+}
+
+/// Per-driver DMA domain manager
+pub struct IommuManager {
+    backend: Option<Box<dyn IommuOperations>>,
+    domains: BTreeMap<ProcessId, DriverDomain>,
+    device_owners: BTreeMap<DmaDeviceId, ProcessId>,
+    next_domain_id: u16,
+}
+
+impl IommuManager {
+    pub fn new(backend: Option<Box<dyn IommuOperations>>) -> Self {
+        Self {
+            backend,
+            domains: BTreeMap::new(),
+            device_owners: BTreeMap::new(),
+            // Domain 0 is reserved by VT-d in caching mode
+            next_domain_id: 1,
+        }
+    }
+
+    fn allocate_domain_id(&mut self) -> Result<IommuDomainId, IommuError> {
+        for _ in 0..u16::MAX {
+            let candidate = IommuDomainId(self.next_domain_id);
+            self.next_domain_id = self.next_domain_id.checked_add(1).unwrap_or(1);
+
+            if !self.domains.values().any(|domain| domain.id == candidate) {
+                return Ok(candidate);
+            }
+        }
+        Err(IommuError::NoFreeDomains)
+    }
+
+    pub fn create_domain(&mut self, driver: ProcessId) -> Result<IommuDomainId, IommuError> {
+        if self.domains.contains_key(&driver) {
+            return Err(IommuError::DomainExists);
+        }
+
+        let id = self.allocate_domain_id()?;
+        if let Some(backend) = self.backend.as_mut() {
+            backend.create_domain(id)?;
+        }
+
+        self.domains.insert(driver, DriverDomain {
+            id,
+            devices: Vec::new(),
+            next_iova: IOVA_BASE,
+            mappings: BTreeMap::new(),
+        });
+
+        log::debug!("Created DMA domain {} for driver process {}", id.0, driver.0);
+        Ok(id)
+    }
+
+    pub fn destroy_domain(&mut self, driver: ProcessId) -> Result<(), IommuError> {
+        let domain = self.domains.remove(&driver).ok_or(IommuError::DomainNotFound)?;
+
+        for device in &domain.devices {
+            self.device_owners.remove(device);
+        }
+
+        if let Some(backend) = self.backend.as_mut() {
+            // Block the devices first so they cannot DMA into pages being freed
+            for device in &domain.devices {
+                backend.detach_device(*device)?;
+            }
+            for (iova, mapping) in &domain.mappings {
+                backend.unmap(domain.id, *iova, mapping.size)?;
+            }
+            backend.destroy_domain(domain.id)?;
+        }
+
+        log::debug!("Destroyed DMA domain {} of driver process {}", domain.id.0, driver.0);
+        Ok(())
+    }
+
+    pub fn attach_device(&mut self, driver: ProcessId, device: DmaDeviceId) -> Result<(), IommuError> {
+        match self.device_owners.get(&device) {
+            Some(owner) if *owner == driver => return Ok(()),
+            Some(_) => return Err(IommuError::DeviceInUse),
+            None => {}
+        }
+
+        let domain = self.domains.get_mut(&driver).ok_or(IommuError::DomainNotFound)?;
+        if let Some(backend) = self.backend.as_mut() {
+            backend.attach_device(domain.id, device)?;
+        }
+
+        domain.devices.push(device);
+        self.device_owners.insert(device, driver);
+        Ok(())
+    }
+
+    pub fn detach_device(&mut self, driver: ProcessId, device: DmaDeviceId) -> Result<(), IommuError> {
+        if self.device_owners.get(&device) != Some(&driver) {
+            return Err(IommuError::DeviceInUse);
+        }
+
+        let domain = self.domains.get_mut(&driver).ok_or(IommuError::DomainNotFound)?;
+        if let Some(backend) = self.backend.as_mut() {
+            backend.detach_device(device)?;
+        }
+
+        domain.devices.retain(|attached| *attached != device);
+        self.device_owners.remove(&device);
+        Ok(())
+    }
+
+    /// Map a physical buffer into the driver's domain and return its I/O virtual address
+    pub fn map(&mut self, driver: ProcessId, physical: PhysicalAddress, size: usize, writable: bool) -> Result<u64, IommuError> {
+        if size == 0 || physical.as_u64() % PAGE_SIZE as u64 != 0 {
+            return Err(IommuError::InvalidRange);
+        }
+
+        let domain = self.domains.get_mut(&driver).ok_or(IommuError::DomainNotFound)?;
+        let mapped_size = super::align_up(size) as u64;
+        let iova = domain.next_iova;
+        if iova.checked_add(mapped_size).map_or(true, |end| end > IOVA_LIMIT) {
+            return Err(IommuError::InvalidRange);
+        }
+
+        if let Some(backend) = self.backend.as_mut() {
+            backend.map(domain.id, iova, physical, size, writable)?;
+        }
+
+        // Leave an unmapped guard page between buffers to catch device overruns
+        domain.next_iova = iova + mapped_size + PAGE_SIZE as u64;
+        domain.mappings.insert(iova, DmaMapping { physical, size, writable });
+        Ok(iova)
+    }
+
+    pub fn unmap(&mut self, driver: ProcessId, iova: u64) -> Result<(), IommuError> {
+        let domain = self.domains.get_mut(&driver).ok_or(IommuError::DomainNotFound)?;
+        let mapping = domain.mappings.remove(&iova).ok_or(IommuError::NotMapped)?;
+
+        if let Some(backend) = self.backend.as_mut() {
+            backend.unmap(domain.id, iova, mapping.size)?;
+        }
+        Ok(())
+    }
+
+    /// Physical address a device of `driver` reaches at `iova`, and whether it may write there
+    pub fn translate(&self, driver: ProcessId, iova: u64) -> Option<(PhysicalAddress, bool)> {
+        let domain = self.domains.get(&driver)?;
+        let (start, mapping) = domain.mappings.range(..=iova).next_back()?;
+        let offset = iova - start;
+        if offset >= mapping.size as u64 {
+            return None;
+        }
+        Some((PhysicalAddress::new(mapping.physical.as_u64() + offset), mapping.writable))
+    }
+
+    pub fn statistics(&self) -> IommuStatistics {
+        let mut stats = IommuStatistics {
+            hardware: self.backend.as_ref().map(|backend| backend.name()),
+            domains: self.domains.len(),
+            attached_devices: self.device_owners.len(),
+            mappings: 0,
+            mapped_bytes: 0,
+        };
+        for domain in self.domains.values() {
+            stats.mappings += domain.mappings.len();
+            stats.mapped_bytes += domain.mappings.values().map(|mapping| mapping.size).sum::<usize>();
+        }
+        stats
+    }
+}
+
+/// Global IOMMU manager
+static IOMMU_MANAGER: Mutex<Option<IommuManager>> = Mutex::new(None);
+
+/// Initialize the IOMMU manager without remapping hardware
+pub fn init_iommu() -> Result<(), IommuError> {
+    *IOMMU_MANAGER.lock() = Some(IommuManager::new(None));
+    log::info!("IOMMU manager initialized (software-only, DMA is not isolated)");
+    Ok(())
+}
+
+/// Initialize the IOMMU manager on top of remapping hardware and enable translation
+pub fn init_iommu_with_backend(mut backend: Box<dyn IommuOperations>) -> Result<(), IommuError> {
+    backend.enable()?;
+    log::info!("IOMMU manager initialized with {} DMA remapping", backend.name());
+    *IOMMU_MANAGER.lock() = Some(IommuManager::new(Some(backend)));
+    Ok(())
+}
+
+fn with_manager<T>(f: impl FnOnce(&mut IommuManager) -> Result<T, IommuError>) -> Result<T, IommuError> {
+    let mut manager = IOMMU_MANAGER.lock();
+    let manager = manager.as_mut().ok_or(IommuError::NotInitialized)?;
+    f(manager)
+}
+
+/// Create the DMA domain for a driver process
+pub fn create_driver_domain(driver: ProcessId) -> Result<IommuDomainId, IommuError> {
+    with_manager(|manager| manager.create_domain(driver))
+}
+
+/// Tear down a driver's DMA domain, blocking its devices
+pub fn destroy_driver_domain(driver: ProcessId) -> Result<(), IommuError> {
+    with_manager(|manager| manager.destroy_domain(driver))
+}
+
+/// Give a driver's domain control over a device's DMA
+pub fn attach_device(driver: ProcessId, device: DmaDeviceId) -> Result<(), IommuError> {
+    with_manager(|manager| manager.attach_device(driver, device))
+}
+
+/// Remove a device from a driver's domain
+pub fn detach_device(driver: ProcessId, device: DmaDeviceId) -> Result<(), IommuError> {
+    with_manager(|manager| manager.detach_device(driver, device))
+}
+
+/// Make a buffer reachable by a driver's devices
+pub fn map_for_driver(driver: ProcessId, physical: PhysicalAddress, size: usize, writable: bool) -> Result<u64, IommuError> {
+    with_manager(|manager| manager.map(driver, physical, size, writable))
+}
+
+/// Revoke device access to a buffer previously mapped with `map_for_driver`
+pub fn unmap_for_driver(driver: ProcessId, iova: u64) -> Result<(), IommuError> {
+    with_manager(|manager| manager.unmap(driver, iova))
+}
+
+/// Translate an I/O virtual address in a driver's domain
+pub fn translate(driver: ProcessId, iova: u64) -> Option<(PhysicalAddress, bool)> {
+    IOMMU_MANAGER.lock().as_ref()?.translate(driver, iova)
+}
+
+/// Whether remapping hardware translates DMA, so devices use I/O virtual
+/// addresses rather than physical ones
+pub fn remaps_dma() -> bool {
+    IOMMU_MANAGER.lock().as_ref().map_or(false, |manager| manager.backend.is_some())
+}
+
+/// Get IOMMU statistics
+pub fn get_iommu_statistics() -> Option<IommuStatistics> {
+    IOMMU_MANAGER.lock().as_ref().map(|manager| manager.statistics())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_domains_are_isolated() {
+        let mut manager = IommuManager::new(None);
+        let net = ProcessId::new(100);
+        let disk = ProcessId::new(101);
+
+        let net_domain = manager.create_domain(net).unwrap();
+        let disk_domain = manager.create_domain(disk).unwrap();
+        assert_ne!(net_domain, disk_domain);
+        assert_eq!(manager.create_domain(net), Err(IommuError::DomainExists));
+
+        let iova = manager.map(net, PhysicalAddress::new(0x20_0000), 0x1800, true).unwrap();
+        assert_eq!(iova, IOVA_BASE);
+        assert_eq!(manager.translate(net, iova + 0x10), Some((PhysicalAddress::new(0x20_0010), true)));
+        assert_eq!(manager.translate(net, iova + 0x1800), None);
+        assert_eq!(manager.translate(disk, iova), None);
+
+        manager.unmap(net, iova).unwrap();
+        assert_eq!(manager.translate(net, iova), None);
+        assert_eq!(manager.unmap(net, iova), Err(IommuError::NotMapped));
+    }
+
+    #[test_case]
+    fn test_device_ownership() {
+        let mut manager = IommuManager::new(None);
+        let first = ProcessId::new(200);
+        let second = ProcessId::new(201);
+        let nic = DmaDeviceId::pci(0, 0, 3, 0);
+
+        manager.create_domain(first).unwrap();
+        manager.create_domain(second).unwrap();
+        manager.attach_device(first, nic).unwrap();
+        assert_eq!(manager.attach_device(second, nic), Err(IommuError::DeviceInUse));
+
+        manager.destroy_domain(first).unwrap();
+        manager.attach_device(second, nic).unwrap();
+        assert_eq!(manager.statistics().attached_devices, 1);
+    }
+
+    #[test_case]
+    fn test_map_rejects_bad_ranges() {
+        let mut manager = IommuManager::new(None);
+        let driver = ProcessId::new(300);
+
+        assert_eq!(manager.map(driver, PhysicalAddress::new(0x1000), 0x1000, false), Err(IommuError::DomainNotFound));
+        manager.create_domain(driver).unwrap();
+        assert_eq!(manager.map(driver, PhysicalAddress::new(0x1001), 0x1000, false), Err(IommuError::InvalidRange));
+        assert_eq!(manager.map(driver, PhysicalAddress::new(0x1000), 0, false), Err(IommuError::InvalidRange));
+    }
+}
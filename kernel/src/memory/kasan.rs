@@ -0,0 +1,218 @@
+//! Kernel address sanitizer-lite for debug builds
+//!
+//! When compiled with debug assertions the kernel heap surrounds every
+//! allocation with redzones, poisons freed memory and holds freed blocks in
+//! a quarantine before they become reusable. This module provides the shadow
+//! patterns, the quarantine ring and the violation reporting used by the heap.
+//! Violations are reported through the panic path so they are never missed.
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Size of the redzone placed before and after each allocation.
+/// Kept at 16 bytes so user pointers stay 16-byte aligned.
+pub const REDZONE_SIZE: usize = 16;
+
+/// Byte pattern written into redzones
+pub const REDZONE_PATTERN: u8 = 0xFA;
+
+/// Byte pattern written into freed (quarantined) memory
+pub const FREED_PATTERN: u8 = 0xFD;
+
+/// Number of freed blocks held back before being returned to the free list
+pub const QUARANTINE_SLOTS: usize = 64;
+
+/// Number of timer ticks between periodic heap integrity sweeps
+pub const CHECK_INTERVAL_TICKS: u64 = 100;
+
+/// Kind of memory safety violation detected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// Write before the start of an allocation
+    HeapUnderflow,
+    /// Write past the end of an allocation
+    HeapOverflow,
+    /// Write to memory after it was freed
+    UseAfterFree,
+    /// Block freed twice
+    DoubleFree,
+    /// Pointer passed to free was never returned by the allocator
+    InvalidFree,
+    /// Block header overwritten
+    HeaderCorruption,
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViolationKind::HeapUnderflow => write!(f, "heap-buffer-underflow"),
+            ViolationKind::HeapOverflow => write!(f, "heap-buffer-overflow"),
+            ViolationKind::UseAfterFree => write!(f, "heap-use-after-free"),
+            ViolationKind::DoubleFree => write!(f, "double-free"),
+            ViolationKind::InvalidFree => write!(f, "invalid-free"),
+            ViolationKind::HeaderCorruption => write!(f, "heap-header-corruption"),
+        }
+    }
+}
+
+/// Details of a detected violation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    /// Start of the user region of the affected allocation
+    pub object_addr: usize,
+    /// Requested size of the affected allocation
+    pub object_size: usize,
+    /// Address of the first corrupted byte
+    pub bad_addr: usize,
+    /// Allocation ID of the affected block
+    pub alloc_id: u64,
+}
+
+/// Total number of violations reported since boot
+static VIOLATIONS_REPORTED: AtomicU64 = AtomicU64::new(0);
+
+/// Report a violation and halt through the kernel panic path
+pub fn report(violation: Violation) -> ! {
+    VIOLATIONS_REPORTED.fetch_add(1, Ordering::Relaxed);
+
+    log::debug!("==================================================");
+    log::debug!("KASAN: {} at 0x{:x}", violation.kind, violation.bad_addr);
+    log::debug!("  object: 0x{:x}, {} bytes, alloc #{}",
+                   violation.object_addr, violation.object_size, violation.alloc_id);
+    if violation.bad_addr >= violation.object_addr {
+        log::debug!("  offset: +{} from object start",
+                       violation.bad_addr - violation.object_addr);
+    } else {
+        log::debug!("  offset: -{} from object start",
+                       violation.object_addr - violation.bad_addr);
+    }
+    log::debug!("==================================================");
+
+    panic!("KASAN: {} at 0x{:x} (object 0x{:x}, {} bytes, alloc #{})",
+           violation.kind, violation.bad_addr, violation.object_addr,
+           violation.object_size, violation.alloc_id);
+}
+
+/// Get the number of violations reported since boot
+pub fn violations_reported() -> u64 {
+    VIOLATIONS_REPORTED.load(Ordering::Relaxed)
+}
+
+/// Fill a region with the given pattern
+///
+/// # Safety
+/// `start..start + len` must be valid, writable heap memory.
+pub unsafe fn poison(start: *mut u8, len: usize, pattern: u8) {
+    core::ptr::write_bytes(start, pattern, len);
+}
+
+/// Find the first byte in a region that does not match the pattern
+///
+/// # Safety
+/// `start..start + len` must be valid, readable heap memory.
+pub unsafe fn find_corruption(start: *const u8, len: usize, pattern: u8) -> Option<usize> {
+    (0..len).find(|&i| core::ptr::read_volatile(start.add(i)) != pattern)
+        .map(|i| start as usize + i)
+}
+
+/// Fixed-size FIFO of freed blocks awaiting reuse
+pub struct Quarantine<T: Copy> {
+    slots: [Option<T>; QUARANTINE_SLOTS],
+    head: usize,
+    len: usize,
+}
+
+impl<T: Copy> Quarantine<T> {
+    /// Create an empty quarantine
+    pub const fn new() -> Self {
+        Self {
+            slots: [None; QUARANTINE_SLOTS],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Add a block, returning the oldest block if the quarantine was full
+    pub fn push(&mut self, entry: T) -> Option<T> {
+        let evicted = if self.len == QUARANTINE_SLOTS {
+            let oldest = self.slots[self.head].take();
+            self.head = (self.head + 1) % QUARANTINE_SLOTS;
+            self.len -= 1;
+            oldest
+        } else {
+            None
+        };
+
+        let tail = (self.head + self.len) % QUARANTINE_SLOTS;
+        self.slots[tail] = Some(entry);
+        self.len += 1;
+        evicted
+    }
+
+    /// Remove the oldest block
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let oldest = self.slots[self.head].take();
+        self.head = (self.head + 1) % QUARANTINE_SLOTS;
+        self.len -= 1;
+        oldest
+    }
+
+    /// Number of blocks currently held
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the quarantine is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Tick counter driving periodic heap sweeps
+static TICKS_SINCE_CHECK: AtomicU64 = AtomicU64::new(0);
+
+/// Returns true once every `CHECK_INTERVAL_TICKS` calls
+pub fn periodic_check_due() -> bool {
+    let ticks = TICKS_SINCE_CHECK.fetch_add(1, Ordering::Relaxed) + 1;
+    if ticks >= CHECK_INTERVAL_TICKS {
+        TICKS_SINCE_CHECK.store(0, Ordering::Relaxed);
+        true
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_find_corruption() {
+        let mut buffer = [0u8; 32];
+        unsafe {
+            poison(buffer.as_mut_ptr(), buffer.len(), REDZONE_PATTERN);
+            assert_eq!(find_corruption(buffer.as_ptr(), buffer.len(), REDZONE_PATTERN), None);
+        }
+
+        buffer[20] = 0x41;
+        let bad = unsafe { find_corruption(buffer.as_ptr(), buffer.len(), REDZONE_PATTERN) };
+        assert_eq!(bad, Some(buffer.as_ptr() as usize + 20));
+    }
+
+    #[test_case]
+    fn test_quarantine_evicts_oldest() {
+        let mut quarantine: Quarantine<usize> = Quarantine::new();
+        for i in 0..QUARANTINE_SLOTS {
+            assert_eq!(quarantine.push(i), None);
+        }
+        assert_eq!(quarantine.len(), QUARANTINE_SLOTS);
+
+        assert_eq!(quarantine.push(1000), Some(0));
+        assert_eq!(quarantine.pop(), Some(1));
+        assert_eq!(quarantine.len(), QUARANTINE_SLOTS - 1);
+    }
+}
@@ -0,0 +1,261 @@
+//! Guarded kernel stacks
+//!
+//! Kernel stacks are mapped into a window of kernel address space of their
+//! own, one fixed-size slot per stack, with the page at the bottom of each
+//! slot left unmapped as a guard. A stack that overflows runs into its
+//! guard page and faults instead of overwriting whatever lies below. The
+//! fault can usually not be delivered on the stack that overflowed, so it
+//! escalates to a double fault, which runs on an interrupt stack of its
+//! own; either handler asks `find_overflow` whose stack the faulting
+//! address lies below and names the owner before panicking.
+//!
+//! A stack smaller than its slot leaves the top of the slot unmapped as
+//! well, which widens the guard of the stack in the slot above.
+//!
+//! The boot CPU starts on the stack the boot loader set up, which has no
+//! guard page; everything started after memory management is up runs on a
+//! guarded stack. The registry is only ever tried, not waited for, from a
+//! fault handler, as the faulting code may hold it.
+
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+use crate::memory::{physical, PAGE_SIZE};
+use crate::memory::physical::PageFrame;
+use crate::memory::vmm::{self, kernel_layout, MemoryProtection, VirtualAddress};
+use crate::process::ProcessId;
+
+/// Address space each stack takes, its guard page included
+pub const SLOT_SIZE: usize = 128 * 1024;
+
+/// Largest stack a slot holds
+pub const MAX_STACK_SIZE: usize = SLOT_SIZE - PAGE_SIZE;
+
+/// Most kernel stacks at once
+pub const MAX_STACKS: usize = kernel_layout::KERNEL_STACKS_SIZE / SLOT_SIZE;
+
+/// What a kernel stack is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackOwner {
+    /// Boot and idle stack of a secondary CPU
+    Cpu(usize),
+    /// Stack the double fault handler of a CPU runs on
+    DoubleFault(usize),
+    /// Kernel stack of a thread
+    Thread { process: ProcessId, thread: ProcessId },
+}
+
+impl fmt::Display for StackOwner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StackOwner::Cpu(cpu) => write!(f, "idle stack of CPU {}", cpu),
+            StackOwner::DoubleFault(cpu) => write!(f, "double fault stack of CPU {}", cpu),
+            StackOwner::Thread { process, thread } => {
+                write!(f, "kernel stack of thread {} of process {}", thread.0, process.0)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelStackError {
+    /// More than `MAX_STACK_SIZE` bytes, or none
+    InvalidSize,
+    /// Every slot of the window is taken
+    WindowFull,
+    OutOfMemory,
+}
+
+impl fmt::Display for KernelStackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KernelStackError::InvalidSize => write!(f, "Invalid kernel stack size"),
+            KernelStackError::WindowFull => write!(f, "No kernel stack slot left"),
+            KernelStackError::OutOfMemory => write!(f, "Out of memory for kernel stack"),
+        }
+    }
+}
+
+/// A taken slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Slot {
+    /// Mapped bytes above the guard page
+    size: usize,
+    owner: StackOwner,
+}
+
+static SLOTS: Mutex<[Option<Slot>; MAX_STACKS]> = Mutex::new([None; MAX_STACKS]);
+
+/// Lowest address of slot `index`, where its guard page is
+fn slot_base(index: usize) -> usize {
+    kernel_layout::KERNEL_STACKS_START.0 + index * SLOT_SIZE
+}
+
+/// Lowest mapped address of the stack in slot `index`
+fn slot_bottom(index: usize) -> usize {
+    slot_base(index) + PAGE_SIZE
+}
+
+/// The slot whose stack `address` lies below, unmapped, and by how many
+/// bytes
+///
+/// An address in the guard page of a slot, or in the unmapped top of the
+/// slot under it, belongs to the stack that overflowed into it.
+fn overflowed_slot(slots: &[Option<Slot>], address: usize) -> Option<(usize, usize)> {
+    let offset = address.checked_sub(kernel_layout::KERNEL_STACKS_START.0)?;
+    let index = offset / SLOT_SIZE;
+    let within = offset % SLOT_SIZE;
+    let below = if within < PAGE_SIZE {
+        index
+    } else {
+        let size = slots.get(index)?.map_or(0, |slot| slot.size);
+        if within < PAGE_SIZE + size {
+            // Inside a mapped stack
+            return None;
+        }
+        index + 1
+    };
+    slots.get(below)?.as_ref()?;
+    Some((below, slot_bottom(below) - address))
+}
+
+/// A stack that overflowed, as found by `find_overflow`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow {
+    pub owner: StackOwner,
+    /// Lowest mapped address of the stack
+    pub bottom: usize,
+    pub top: usize,
+    /// How far below the bottom the faulting address is
+    pub depth: usize,
+}
+
+/// The stack `address` lies below, if a fault on `address` means a kernel
+/// stack overflowed
+///
+/// Returns None as well if the registry is held, so a fault handler never
+/// waits on the code it interrupted.
+pub fn find_overflow(address: usize) -> Option<Overflow> {
+    let slots = SLOTS.try_lock()?;
+    let (index, depth) = overflowed_slot(&slots[..], address)?;
+    let slot = slots[index]?;
+    let bottom = slot_bottom(index);
+    Some(Overflow { owner: slot.owner, bottom, top: bottom + slot.size, depth })
+}
+
+/// A kernel stack with a guard page below it, unmapped and freed when
+/// dropped
+pub struct KernelStack {
+    slot: usize,
+    frames: Vec<PageFrame>,
+}
+
+impl KernelStack {
+    /// Map a stack of `size` bytes, rounded up to whole pages, for `owner`
+    pub fn allocate(size: usize, owner: StackOwner) -> Result<Self, KernelStackError> {
+        let size = crate::memory::align_up(size);
+        if size == 0 || size > MAX_STACK_SIZE {
+            return Err(KernelStackError::InvalidSize);
+        }
+        let slot = {
+            let mut slots = SLOTS.lock();
+            let slot = slots.iter().position(Option::is_none).ok_or(KernelStackError::WindowFull)?;
+            slots[slot] = Some(Slot { size, owner });
+            slot
+        };
+
+        // Dropping the stack returns whatever was mapped so far
+        let mut stack = Self { slot, frames: Vec::with_capacity(size / PAGE_SIZE) };
+        for page in 0..size / PAGE_SIZE {
+            let frame = physical::allocate_frame().ok_or(KernelStackError::OutOfMemory)?;
+            let address = VirtualAddress(slot_bottom(slot) + page * PAGE_SIZE);
+            if let Err(e) = vmm::map_virtual_to_physical(address, frame.address(), MemoryProtection::read_write()) {
+                log::debug!("Cannot map kernel stack page at {:#x}: {}", address.0, e);
+                physical::deallocate_frame(frame);
+                return Err(KernelStackError::OutOfMemory);
+            }
+            stack.frames.push(frame);
+        }
+        log::debug!("Kernel stack for {} at {:#x}-{:#x}", owner, stack.bottom(), stack.top());
+        Ok(stack)
+    }
+
+    /// Lowest mapped address
+    pub fn bottom(&self) -> usize {
+        slot_bottom(self.slot)
+    }
+
+    /// Initial stack pointer, 16-byte aligned
+    pub fn top(&self) -> u64 {
+        (self.bottom() + self.frames.len() * PAGE_SIZE) as u64
+    }
+
+    /// Keep the stack for as long as the system runs and return its top
+    pub fn leak(self) -> u64 {
+        let top = self.top();
+        core::mem::forget(self);
+        top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        for (page, &frame) in self.frames.iter().enumerate() {
+            let _ = vmm::unmap_virtual_address(VirtualAddress(self.bottom() + page * PAGE_SIZE));
+            physical::deallocate_frame(frame);
+        }
+        SLOTS.lock()[self.slot] = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_overflow_is_charged_to_the_stack_above() {
+        let owner = StackOwner::Cpu(1);
+        let mut slots = [None; 4];
+        slots[1] = Some(Slot { size: 16 * 1024, owner });
+        slots[2] = Some(Slot { size: 8 * 1024, owner });
+
+        // The guard page of slot 1
+        assert_eq!(overflowed_slot(&slots, slot_base(1) + PAGE_SIZE - 8), Some((1, 8)));
+        // Inside the stack of slot 1
+        assert_eq!(overflowed_slot(&slots, slot_bottom(1)), None);
+        assert_eq!(overflowed_slot(&slots, slot_bottom(1) + 16 * 1024 - 8), None);
+        // The unmapped top of slot 1 lies below the guard of slot 2
+        assert_eq!(overflowed_slot(&slots, slot_bottom(1) + 16 * 1024), Some((2, SLOT_SIZE - 16 * 1024)));
+        // Nothing lives in slots 0 and 3
+        assert_eq!(overflowed_slot(&slots, slot_base(0)), None);
+        assert_eq!(overflowed_slot(&slots, slot_bottom(2) + 8 * 1024), None);
+        assert_eq!(overflowed_slot(&slots, kernel_layout::KERNEL_STACKS_START.0 - 8), None);
+    }
+
+    #[test_case]
+    fn test_stack_is_guarded() {
+        let owner = StackOwner::Thread { process: ProcessId::new(9600), thread: ProcessId::new(9601) };
+        let stack = KernelStack::allocate(8 * 1024, owner).expect("kernel stack");
+        assert_eq!(stack.top() % 16, 0);
+        assert_eq!(stack.top() as usize - stack.bottom(), 8 * 1024);
+        assert!(vmm::is_virtual_address_mapped(VirtualAddress(stack.bottom())));
+        assert!(!vmm::is_virtual_address_mapped(VirtualAddress(stack.bottom() - 8)));
+
+        let overflow = find_overflow(stack.bottom() - 8).expect("overflow");
+        assert_eq!(overflow.owner, owner);
+        assert_eq!(overflow.depth, 8);
+        assert_eq!(find_overflow(stack.bottom()), None);
+
+        let bottom = stack.bottom();
+        drop(stack);
+        assert!(!vmm::is_virtual_address_mapped(VirtualAddress(bottom)));
+        assert_eq!(find_overflow(bottom - 8), None);
+    }
+
+    #[test_case]
+    fn test_oversized_stack_is_refused() {
+        assert!(matches!(KernelStack::allocate(MAX_STACK_SIZE + 1, StackOwner::Cpu(1)),
+                         Err(KernelStackError::InvalidSize)));
+        assert!(matches!(KernelStack::allocate(0, StackOwner::Cpu(1)), Err(KernelStackError::InvalidSize)));
+    }
+}
@@ -0,0 +1,1082 @@
+//! Memory mappings created by `mmap`
+//!
+//! A mapping only reserves address space when it is created; its pages are
+//! populated one at a time by the page fault handler. Anonymous pages start
+//! out zeroed. Pages of a file mapping are read from the file system
+//! service into a kernel page cache shared by every mapping of the same
+//! open file. A shared mapping maps the cached frame itself, and the pages
+//! it dirtied are written back when it is unmapped. A private mapping maps
+//! the cached frame read-only and gets its own copy of a page on the first
+//! write to it.
+//!
+//! The file system service has no inode numbers on its interface, so a file
+//! is identified by the service and the service's descriptor for it. That
+//! descriptor stays open while a mapping uses it, even after the process
+//! closed its own descriptor.
+//!
+//! Reading a page takes several round trips to the service. The faulting
+//! process is blocked in the meantime and repeats the faulting access when
+//! woken, which continues the read where it stopped.
+//!
+//! The heap `brk` moves is an anonymous mapping of its own, which grows
+//! and shrinks in place, and the stack of a new image is another, below an
+//! unmapped guard page. The segments of a program image `exec` loads are
+//! anonymous mappings as well, placed below the heap where the image asks
+//! and filled from it as they are created. Where the heap, the stack and
+//! the mapping window start is the process's layout from `aslr`.
+//!
+//! A forked child gets a copy of every mapping of its parent. Populated
+//! pages with a frame of their own are copied into frames of the child's,
+//! shared anonymous ones included, as a frame has a single owner; file
+//! pages map the same cache frame, a private one still read-only until
+//! written. Mappings, the heap and the
+//! stack are charged to their owner in `accounting`, and a fault that
+//! finds no free frame turns to `oom`.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+use kosh_service::{FileSystemRequest, ServiceData, ServiceStatus};
+use crate::ipc::fs_client::{self, FsCall, MAX_FS_TRANSFER};
+use crate::memory::{PAGE_SIZE, accounting, align_up, oom};
+use crate::memory::aslr::UserLayout;
+use crate::memory::physical::{self, PageFrame};
+use crate::memory::vmm::{self, kernel_layout::PHYSICAL_MEMORY_OFFSET, MemoryProtection, VirtualAddress};
+use crate::process::ProcessId;
+
+/// Base of the virtual window mappings are placed in, before
+/// randomization
+pub const MMAP_WINDOW_BASE: usize = 0x0000_6000_0000_0000;
+
+/// Size of the mapping window
+pub const MMAP_WINDOW_SIZE: usize = 0x0000_0010_0000_0000;
+
+/// Base of the heap `brk` grows, below the shared memory window, before
+/// randomization
+pub const HEAP_BASE: usize = 0x0000_4000_0000_0000;
+
+/// Largest heap
+pub const MAX_HEAP_SIZE: usize = 0x0000_0010_0000_0000;
+
+/// Top of the stack of a new image, before randomization
+pub const STACK_TOP: usize = 0x0000_7FFF_0000_0000;
+
+/// Size of the stack of a new image
+pub const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Protection bits accepted by `mmap`
+pub const PROT_READ: u64 = 0x1;
+pub const PROT_WRITE: u64 = 0x2;
+pub const PROT_EXEC: u64 = 0x4;
+
+/// Not backed by a file
+pub const MAP_ANONYMOUS: u64 = 0x1;
+/// Writes reach the file and every other shared mapping of it; without
+/// this flag the mapping is private
+pub const MAP_SHARED: u64 = 0x2;
+
+/// Memory mapping errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmapError {
+    /// Zero length, unaligned offset or no mapping at the given address
+    InvalidArgument,
+    /// Out of frames or mapping window space
+    OutOfMemory,
+    /// Page table update failed
+    MappingFailed,
+    /// The file system service could not be reached
+    ServiceUnavailable,
+    /// The owner's memory limit would be exceeded
+    LimitExceeded,
+}
+
+impl fmt::Display for MmapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MmapError::InvalidArgument => write!(f, "Invalid mapping argument"),
+            MmapError::OutOfMemory => write!(f, "Out of memory"),
+            MmapError::MappingFailed => write!(f, "Failed to map page"),
+            MmapError::ServiceUnavailable => write!(f, "File system service unavailable"),
+            MmapError::LimitExceeded => write!(f, "Memory limit exceeded"),
+        }
+    }
+}
+
+/// An open file, as the service holding it knows it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileId {
+    pub service: ProcessId,
+    pub remote_fd: u32,
+}
+
+/// The file region behind a mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileBacking {
+    pub file: FileId,
+    /// Page-aligned offset of the first mapped byte
+    pub offset: u64,
+}
+
+/// Outcome of a page fault in a mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultResult {
+    /// The page is mapped; the access can be retried
+    Resolved,
+    /// The page is being read in; the process was blocked and retries the
+    /// access once woken
+    Pending,
+    /// No mapping allows the access
+    Unhandled,
+    /// No frame is left for the page, even after killing another process
+    OutOfMemory,
+}
+
+/// How a page of a mapping is currently mapped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MappedPage {
+    /// The page cache frame of the file page
+    Cached,
+    /// A frame of the mapping's own: anonymous memory or a private copy
+    Owned(PageFrame),
+}
+
+/// A file page in the page cache
+#[derive(Debug, Clone, Copy)]
+struct CachedPage {
+    frame: PageFrame,
+    /// Bytes of the page inside the file; the rest reads as zero and is
+    /// never written back
+    length: usize,
+}
+
+/// A file page being read in for a process
+#[derive(Debug)]
+struct PageFill {
+    file: FileId,
+    offset: u64,
+    frame: PageFrame,
+    filled: usize,
+}
+
+#[derive(Debug)]
+struct Mapping {
+    owner: ProcessId,
+    start: VirtualAddress,
+    /// Page multiple
+    length: usize,
+    protection: MemoryProtection,
+    shared: bool,
+    backing: Option<FileBacking>,
+    /// Populated pages by index
+    pages: BTreeMap<usize, MappedPage>,
+    kind: MappingKind,
+}
+
+/// What a mapping is for; `munmap` only removes what `mmap` created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MappingKind {
+    Mapped,
+    /// The heap, which `brk` resizes
+    Heap,
+    /// The stack of the current image
+    Stack,
+    /// A segment of the current image
+    Image,
+}
+
+impl Mapping {
+    fn contains(&self, address: VirtualAddress) -> bool {
+        address.0 >= self.start.0 && address.0 < self.start.0 + self.length
+    }
+
+    fn page_address(&self, index: usize) -> VirtualAddress {
+        VirtualAddress::new(self.start.0 + index * PAGE_SIZE)
+    }
+
+    /// Protection a cached frame is mapped with; private mappings must not
+    /// write to it
+    fn cached_protection(&self) -> MemoryProtection {
+        MemoryProtection {
+            writable: self.protection.writable && self.shared,
+            ..self.protection
+        }
+    }
+}
+
+struct MmapManager {
+    mappings: Vec<Mapping>,
+    cache: BTreeMap<(FileId, u64), CachedPage>,
+    fills: BTreeMap<ProcessId, PageFill>,
+    /// Files whose descriptor the process closed while they were mapped
+    orphaned: BTreeSet<FileId>,
+    /// Next free address in each process's mapping window (addresses are
+    /// never reused)
+    next_addresses: BTreeMap<ProcessId, usize>,
+    /// End of each process's heap, if it moved it
+    breaks: BTreeMap<ProcessId, usize>,
+    /// Layout of each process that has one; the others use the fixed bases
+    layouts: BTreeMap<ProcessId, UserLayout>,
+}
+
+static MMAP_MANAGER: Mutex<MmapManager> = Mutex::new(MmapManager::new());
+
+/// Kernel view of a frame through the physical memory window
+fn frame_bytes(frame: PageFrame) -> &'static mut [u8; PAGE_SIZE] {
+    unsafe { &mut *((PHYSICAL_MEMORY_OFFSET.as_usize() + frame.address()) as *mut [u8; PAGE_SIZE]) }
+}
+
+impl MmapManager {
+    const fn new() -> Self {
+        Self {
+            mappings: Vec::new(),
+            cache: BTreeMap::new(),
+            fills: BTreeMap::new(),
+            orphaned: BTreeSet::new(),
+            next_addresses: BTreeMap::new(),
+            breaks: BTreeMap::new(),
+            layouts: BTreeMap::new(),
+        }
+    }
+
+    fn layout(&self, owner: ProcessId) -> UserLayout {
+        self.layouts.get(&owner).copied().unwrap_or(UserLayout::FIXED)
+    }
+
+    /// Place what `owner` maps from now on by `layout`
+    fn set_layout(&mut self, owner: ProcessId, layout: UserLayout) {
+        self.layouts.insert(owner, layout);
+        self.next_addresses.remove(&owner);
+    }
+
+    fn map(&mut self, owner: ProcessId, length: usize, protection: MemoryProtection, shared: bool,
+           backing: Option<FileBacking>) -> Result<VirtualAddress, MmapError> {
+        if length == 0 {
+            return Err(MmapError::InvalidArgument);
+        }
+        if length > MMAP_WINDOW_SIZE {
+            return Err(MmapError::OutOfMemory);
+        }
+        if backing.map_or(false, |backing| backing.offset % PAGE_SIZE as u64 != 0) {
+            return Err(MmapError::InvalidArgument);
+        }
+
+        let length = align_up(length);
+        // Leave an unmapped page between mappings to catch overruns
+        let reserved = length.checked_add(PAGE_SIZE).ok_or(MmapError::OutOfMemory)?;
+        let window_base = self.layout(owner).mmap_base;
+        let next_address = self.next_addresses.get(&owner).copied().unwrap_or(window_base);
+        if next_address + reserved > window_base + MMAP_WINDOW_SIZE {
+            return Err(MmapError::OutOfMemory);
+        }
+        if !accounting::try_reserve(owner, length) {
+            return Err(MmapError::LimitExceeded);
+        }
+        let start = VirtualAddress::new(next_address);
+        self.next_addresses.insert(owner, next_address + reserved);
+
+        self.mappings.push(Mapping {
+            owner,
+            start,
+            length,
+            protection,
+            shared,
+            backing,
+            pages: BTreeMap::new(),
+            kind: MappingKind::Mapped,
+        });
+        Ok(start)
+    }
+
+    /// Reserve the stack of `owner`'s image, returning its top
+    ///
+    /// The page below it is left out of every mapping, so running off the
+    /// end of the stack faults rather than reaching what lies below.
+    fn map_stack(&mut self, owner: ProcessId) -> Result<VirtualAddress, MmapError> {
+        if self.mappings.iter().any(|mapping| mapping.owner == owner && mapping.kind == MappingKind::Stack) {
+            return Err(MmapError::InvalidArgument);
+        }
+        if !accounting::try_reserve(owner, STACK_SIZE) {
+            return Err(MmapError::LimitExceeded);
+        }
+        let top = self.layout(owner).stack_top;
+        self.mappings.push(Mapping {
+            owner,
+            start: VirtualAddress::new(top - STACK_SIZE),
+            length: STACK_SIZE,
+            protection: MemoryProtection::user_read_write(),
+            shared: false,
+            backing: None,
+            pages: BTreeMap::new(),
+            kind: MappingKind::Stack,
+        });
+        Ok(VirtualAddress::new(top))
+    }
+
+    /// Reserve the page-aligned range of a segment of `owner`'s image
+    ///
+    /// Segments lie below the heap, so they cannot run into it, the stack
+    /// or the mapping window, and must not overlap one another.
+    fn map_image(&mut self, owner: ProcessId, start: VirtualAddress, length: usize,
+                 protection: MemoryProtection) -> Result<(), MmapError> {
+        let end = start.0.checked_add(length).ok_or(MmapError::InvalidArgument)?;
+        if length == 0 || length % PAGE_SIZE != 0 || !start.is_aligned() || start.0 == 0
+            || end > self.layout(owner).heap_base {
+            return Err(MmapError::InvalidArgument);
+        }
+        if self.mappings.iter().any(|mapping| mapping.owner == owner
+            && mapping.start.0 < end && start.0 < mapping.start.0 + mapping.length) {
+            return Err(MmapError::InvalidArgument);
+        }
+        if !accounting::try_reserve(owner, length) {
+            return Err(MmapError::LimitExceeded);
+        }
+        self.mappings.push(Mapping {
+            owner,
+            start,
+            length,
+            protection,
+            shared: false,
+            backing: None,
+            pages: BTreeMap::new(),
+            kind: MappingKind::Image,
+        });
+        Ok(())
+    }
+
+    /// The frame of `owner`'s populated, private page at `address`
+    fn owned_frame(&self, owner: ProcessId, address: VirtualAddress) -> Option<PageFrame> {
+        let mapping = self.mappings.iter().find(|mapping| mapping.owner == owner && mapping.contains(address))?;
+        match mapping.pages.get(&((address.align_down().0 - mapping.start.0) / PAGE_SIZE)) {
+            Some(MappedPage::Owned(frame)) => Some(*frame),
+            _ => None,
+        }
+    }
+
+    /// Remove a whole mapping; partial unmaps are not supported
+    fn unmap(&mut self, owner: ProcessId, start: VirtualAddress, length: usize) -> Result<(), MmapError> {
+        let index = self.mappings.iter()
+            .position(|mapping| mapping.owner == owner && mapping.start == start && mapping.kind == MappingKind::Mapped)
+            .ok_or(MmapError::InvalidArgument)?;
+        if length == 0 || align_up(length) != self.mappings[index].length {
+            return Err(MmapError::InvalidArgument);
+        }
+
+        let mapping = self.mappings.swap_remove(index);
+        self.teardown(mapping);
+        Ok(())
+    }
+
+    /// Write back, unmap and free the pages of a removed mapping
+    fn teardown(&mut self, mapping: Mapping) {
+        self.release_pages(&mapping, &mapping.pages);
+        accounting::unreserve(mapping.owner, mapping.length);
+
+        if let Some(backing) = mapping.backing {
+            self.release_file(backing.file);
+        }
+    }
+
+    /// Write back, unmap and free `pages` of `mapping`
+    fn release_pages(&self, mapping: &Mapping, pages: &BTreeMap<usize, MappedPage>) {
+        for (&index, &page) in pages {
+            let address = mapping.page_address(index);
+            match page {
+                MappedPage::Cached => {
+                    let dirty = mapping.shared && vmm::take_user_page_dirty(mapping.owner, address);
+                    let _ = vmm::unmap_user_page(mapping.owner, address);
+                    if let (true, Some(backing)) = (dirty, mapping.backing) {
+                        let offset = backing.offset + (index * PAGE_SIZE) as u64;
+                        if let Some(cached) = self.cache.get(&(backing.file, offset)) {
+                            write_back(backing.file, offset, cached);
+                        }
+                    }
+                }
+                MappedPage::Owned(frame) => {
+                    let _ = vmm::unmap_user_page(mapping.owner, address);
+                    physical::deallocate_frame(frame);
+                }
+            }
+        }
+        accounting::pages_released(mapping.owner, pages.len());
+    }
+
+    /// Drop the cached pages of a file nothing maps any more
+    fn release_file(&mut self, file: FileId) {
+        if self.mappings.iter().any(|mapping| mapping.backing.map(|backing| backing.file) == Some(file)) {
+            return;
+        }
+
+        let offsets: Vec<u64> = self.cache.keys()
+            .filter(|(cached_file, _)| *cached_file == file)
+            .map(|(_, offset)| *offset)
+            .collect();
+        for offset in offsets {
+            if let Some(cached) = self.cache.remove(&(file, offset)) {
+                physical::deallocate_frame(cached.frame);
+            }
+        }
+
+        if self.orphaned.remove(&file) {
+            let _ = fs_client::notify(file.service, FileSystemRequest::Close { fd: file.remote_fd });
+        }
+    }
+
+    /// Keep a mapped file open on the service after its descriptor is closed
+    fn retain_on_close(&mut self, file: FileId) -> bool {
+        let mapped = self.mappings.iter().any(|mapping| mapping.backing.map(|backing| backing.file) == Some(file));
+        if mapped {
+            self.orphaned.insert(file);
+        }
+        mapped
+    }
+
+    fn release_process(&mut self, owner: ProcessId) {
+        if let Some(fill) = self.fills.remove(&owner) {
+            physical::deallocate_frame(fill.frame);
+        }
+
+        while let Some(index) = self.mappings.iter().position(|mapping| mapping.owner == owner) {
+            let mapping = self.mappings.swap_remove(index);
+            self.teardown(mapping);
+        }
+        self.breaks.remove(&owner);
+        self.next_addresses.remove(&owner);
+        self.layouts.remove(&owner);
+    }
+
+    /// Give `child` a copy of every mapping of `parent`, its layout and its
+    /// heap
+    ///
+    /// On failure the child keeps what was copied so far, which its
+    /// release frees.
+    fn fork(&mut self, parent: ProcessId, child: ProcessId) -> Result<(), MmapError> {
+        if let Some(&layout) = self.layouts.get(&parent) {
+            self.layouts.insert(child, layout);
+        }
+        if let Some(&next_address) = self.next_addresses.get(&parent) {
+            self.next_addresses.insert(child, next_address);
+        }
+        if let Some(&heap_break) = self.breaks.get(&parent) {
+            self.breaks.insert(child, heap_break);
+            accounting::set_heap_size(child, heap_break - self.layout(parent).heap_base);
+        }
+
+        let parents: Vec<usize> = (0..self.mappings.len())
+            .filter(|&index| self.mappings[index].owner == parent)
+            .collect();
+        for index in parents {
+            let mapping = &self.mappings[index];
+            if !accounting::try_reserve(child, mapping.length) {
+                return Err(MmapError::LimitExceeded);
+            }
+            let mut copy = Mapping {
+                owner: child,
+                start: mapping.start,
+                length: mapping.length,
+                protection: mapping.protection,
+                shared: mapping.shared,
+                backing: mapping.backing,
+                pages: BTreeMap::new(),
+                kind: mapping.kind,
+            };
+            let result = self.copy_pages(&self.mappings[index], &mut copy);
+            self.mappings.push(copy);
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Populate `copy` with the populated pages of `mapping`
+    fn copy_pages(&self, mapping: &Mapping, copy: &mut Mapping) -> Result<(), MmapError> {
+        for (&index, &page) in &mapping.pages {
+            let (frame, protection) = match page {
+                MappedPage::Owned(frame) => {
+                    let copied = physical::allocate_frame().ok_or(MmapError::OutOfMemory)?;
+                    frame_bytes(copied).copy_from_slice(frame_bytes(frame));
+                    (copied, mapping.protection)
+                }
+                MappedPage::Cached => {
+                    let backing = mapping.backing.expect("cached page without a file");
+                    let offset = backing.offset + (index * PAGE_SIZE) as u64;
+                    (self.cache[&(backing.file, offset)].frame, mapping.cached_protection())
+                }
+            };
+            let page_copy = match page {
+                MappedPage::Owned(_) => MappedPage::Owned(frame),
+                MappedPage::Cached => MappedPage::Cached,
+            };
+            if vmm::map_user_page(copy.owner, copy.page_address(index), frame.address(), protection).is_err() {
+                if let MappedPage::Owned(frame) = page_copy {
+                    physical::deallocate_frame(frame);
+                }
+                return Err(MmapError::MappingFailed);
+            }
+            copy.pages.insert(index, page_copy);
+            accounting::page_populated(copy.owner);
+        }
+        Ok(())
+    }
+
+    /// Current end of the heap of `owner`
+    fn heap_break(&self, owner: ProcessId) -> usize {
+        self.breaks.get(&owner).copied().unwrap_or(self.layout(owner).heap_base)
+    }
+
+    /// Move the end of the heap of `owner` to `new_break`
+    ///
+    /// Pages past the new end are freed when the heap shrinks; new ones
+    /// are populated by the page fault handler like any anonymous mapping.
+    fn set_break(&mut self, owner: ProcessId, new_break: usize) -> Result<usize, MmapError> {
+        let heap_base = self.layout(owner).heap_base;
+        if new_break < heap_base {
+            return Err(MmapError::InvalidArgument);
+        }
+        if new_break - heap_base > MAX_HEAP_SIZE {
+            return Err(MmapError::OutOfMemory);
+        }
+        let old_length = align_up(self.heap_break(owner) - heap_base);
+        let new_length = align_up(new_break - heap_base);
+        if new_length > old_length && !accounting::try_reserve(owner, new_length - old_length) {
+            return Err(MmapError::LimitExceeded);
+        }
+
+        match self.mappings.iter().position(|mapping| mapping.owner == owner && mapping.kind == MappingKind::Heap) {
+            Some(index) => {
+                if new_length < old_length {
+                    let freed = self.mappings[index].pages.split_off(&(new_length / PAGE_SIZE));
+                    self.release_pages(&self.mappings[index], &freed);
+                    accounting::unreserve(owner, old_length - new_length);
+                }
+                self.mappings[index].length = new_length;
+            }
+            None => self.mappings.push(Mapping {
+                owner,
+                start: VirtualAddress::new(heap_base),
+                length: new_length,
+                protection: MemoryProtection::user_read_write(),
+                shared: false,
+                backing: None,
+                pages: BTreeMap::new(),
+                kind: MappingKind::Heap,
+            }),
+        }
+        self.breaks.insert(owner, new_break);
+        accounting::set_heap_size(owner, new_break - heap_base);
+        Ok(new_break)
+    }
+
+    fn fault(&mut self, pid: ProcessId, address: VirtualAddress, write: bool) -> FaultResult {
+        let mapping_index = match self.mappings.iter().position(|mapping| mapping.owner == pid && mapping.contains(address)) {
+            Some(index) => index,
+            None => return FaultResult::Unhandled,
+        };
+        let mapping = &self.mappings[mapping_index];
+        if !mapping.protection.readable || (write && !mapping.protection.writable) {
+            return FaultResult::Unhandled;
+        }
+
+        let page_index = (address.align_down().0 - mapping.start.0) / PAGE_SIZE;
+        let private_write = write && !mapping.shared;
+        match mapping.pages.get(&page_index).copied() {
+            Some(MappedPage::Cached) if private_write => {
+                // Copy on write: the shared cache frame makes way for a copy
+                let _ = vmm::unmap_user_page(pid, mapping.page_address(page_index));
+                self.mappings[mapping_index].pages.remove(&page_index);
+                accounting::pages_released(pid, 1);
+            }
+            // Mapped by now, e.g. by another CPU faulting on the same page
+            Some(_) => return FaultResult::Resolved,
+            None => {}
+        }
+
+        let mapping = &self.mappings[mapping_index];
+        let page = match mapping.backing {
+            None => {
+                let frame = match physical::allocate_frame() {
+                    Some(frame) => frame,
+                    None => return FaultResult::OutOfMemory,
+                };
+                frame_bytes(frame).fill(0);
+                MappedPage::Owned(frame)
+            }
+            Some(backing) => {
+                let offset = backing.offset + (page_index * PAGE_SIZE) as u64;
+                let cached = match self.cached_page(pid, backing.file, offset) {
+                    Ok(Some(cached)) => cached,
+                    Ok(None) => return FaultResult::Pending,
+                    Err(MmapError::OutOfMemory) => return FaultResult::OutOfMemory,
+                    Err(err) => {
+                        log::warn!("mmap: failed to read page at offset {} for process {}: {}", offset, pid.0, err);
+                        return FaultResult::Unhandled;
+                    }
+                };
+                if private_write {
+                    let frame = match physical::allocate_frame() {
+                        Some(frame) => frame,
+                        None => return FaultResult::OutOfMemory,
+                    };
+                    frame_bytes(frame).copy_from_slice(frame_bytes(cached.frame));
+                    MappedPage::Owned(frame)
+                } else {
+                    MappedPage::Cached
+                }
+            }
+        };
+
+        let mapping = &mut self.mappings[mapping_index];
+        let address = mapping.page_address(page_index);
+        let (frame, protection) = match page {
+            MappedPage::Owned(frame) => (frame, mapping.protection),
+            MappedPage::Cached => {
+                let backing = mapping.backing.expect("cached page without a file");
+                let offset = backing.offset + (page_index * PAGE_SIZE) as u64;
+                (self.cache[&(backing.file, offset)].frame, mapping.cached_protection())
+            }
+        };
+        if vmm::map_user_page(pid, address, frame.address(), protection).is_err() {
+            if let MappedPage::Owned(frame) = page {
+                physical::deallocate_frame(frame);
+            }
+            return FaultResult::Unhandled;
+        }
+        mapping.pages.insert(page_index, page);
+        accounting::page_populated(pid);
+        FaultResult::Resolved
+    }
+
+    fn fault_in_range(&mut self, pid: ProcessId, start: usize, len: usize, write: bool) -> FaultResult {
+        let end = start.saturating_add(len);
+        let mut page = VirtualAddress::new(start).align_down().0;
+        while page < end {
+            let address = VirtualAddress::new(page);
+            let unpopulated = self.mappings.iter()
+                .find(|mapping| mapping.owner == pid && mapping.contains(address))
+                .map(|mapping| {
+                    let index = (page - mapping.start.0) / PAGE_SIZE;
+                    match mapping.pages.get(&index) {
+                        None => true,
+                        Some(MappedPage::Cached) => write && !mapping.shared,
+                        Some(MappedPage::Owned(_)) => false,
+                    }
+                })
+                .unwrap_or(false);
+            if unpopulated {
+                match self.fault(pid, address, write) {
+                    FaultResult::Resolved => {}
+                    result => return result,
+                }
+            }
+            page += PAGE_SIZE;
+        }
+        FaultResult::Resolved
+    }
+
+    /// The cached page at `offset` of `file`, reading it in for `pid` first
+    ///
+    /// Returns `None` while the read is in progress; `pid` is then blocked
+    /// until the next part arrives.
+    fn cached_page(&mut self, pid: ProcessId, file: FileId, offset: u64) -> Result<Option<CachedPage>, MmapError> {
+        if let Some(cached) = self.cache.get(&(file, offset)) {
+            return Ok(Some(*cached));
+        }
+
+        let mut fill = match self.fills.remove(&pid) {
+            Some(fill) if fill.file == file && fill.offset == offset => fill,
+            stale => {
+                // A fill the process abandoned, e.g. after being signalled
+                if let Some(stale) = stale {
+                    physical::deallocate_frame(stale.frame);
+                }
+                let frame = physical::allocate_frame().ok_or(MmapError::OutOfMemory)?;
+                PageFill { file, offset, frame, filled: 0 }
+            }
+        };
+
+        loop {
+            let size = core::cmp::min(PAGE_SIZE - fill.filled, MAX_FS_TRANSFER);
+            let request = FileSystemRequest::ReadAt {
+                fd: file.remote_fd,
+                offset: offset + fill.filled as u64,
+                size,
+            };
+            let response = match fs_client::call(pid, request) {
+                Ok(FsCall::Complete { response, .. }) => response,
+                Ok(FsCall::Pending) => {
+                    self.fills.insert(pid, fill);
+                    return Ok(None);
+                }
+                Err(_) => {
+                    physical::deallocate_frame(fill.frame);
+                    return Err(MmapError::ServiceUnavailable);
+                }
+            };
+
+            let bytes = match (response.status, response.data) {
+                (ServiceStatus::Success, ServiceData::Binary(bytes)) => bytes,
+                // The service answers a failed read with no data
+                (ServiceStatus::Success, ServiceData::Empty) => Vec::new(),
+                _ => {
+                    physical::deallocate_frame(fill.frame);
+                    return Err(MmapError::MappingFailed);
+                }
+            };
+            let count = bytes.len().min(size);
+            frame_bytes(fill.frame)[fill.filled..fill.filled + count].copy_from_slice(&bytes[..count]);
+            fill.filled += count;
+
+            // A short read is the end of the file
+            if count < size || fill.filled == PAGE_SIZE {
+                frame_bytes(fill.frame)[fill.filled..].fill(0);
+                let cached = CachedPage { frame: fill.frame, length: fill.filled };
+                self.cache.insert((file, offset), cached);
+                return Ok(Some(cached));
+            }
+        }
+    }
+}
+
+/// Queue the file part of a cached page to be written to the file
+///
+/// Nobody waits for the writes; the service handles them before any later
+/// request for the same file.
+fn write_back(file: FileId, offset: u64, cached: &CachedPage) {
+    let bytes = &frame_bytes(cached.frame)[..cached.length];
+    for (chunk_index, chunk) in bytes.chunks(MAX_FS_TRANSFER).enumerate() {
+        let request = FileSystemRequest::WriteAt {
+            fd: file.remote_fd,
+            offset: offset + (chunk_index * MAX_FS_TRANSFER) as u64,
+            data: chunk.to_vec(),
+        };
+        if fs_client::notify(file.service, request).is_err() {
+            log::warn!("mmap: failed to write back page at offset {}", offset);
+            return;
+        }
+    }
+}
+
+/// Reserve a mapping of `length` bytes for `owner`, returning its address
+///
+/// Address hints are not supported; the mapping is placed in the mapping
+/// window.
+pub fn create_mapping(owner: ProcessId, length: usize, protection: MemoryProtection, shared: bool,
+                      backing: Option<FileBacking>) -> Result<VirtualAddress, MmapError> {
+    let address = MMAP_MANAGER.lock().map(owner, length, protection, shared, backing)?;
+    log::debug!("mmap: process {} mapped {} bytes at 0x{:x}", owner.0, length, address.as_usize());
+    Ok(address)
+}
+
+/// Remove the mapping starting at `start`, writing back its dirty shared pages
+pub fn remove_mapping(owner: ProcessId, start: VirtualAddress, length: usize) -> Result<(), MmapError> {
+    MMAP_MANAGER.lock().unmap(owner, start, length)
+}
+
+/// Move the end of `owner`'s heap to `new_break` and return it; 0 only
+/// returns the current end
+pub fn set_heap_break(owner: ProcessId, new_break: usize) -> Result<usize, MmapError> {
+    let mut manager = MMAP_MANAGER.lock();
+    if new_break == 0 {
+        return Ok(manager.heap_break(owner));
+    }
+    manager.set_break(owner, new_break)
+}
+
+/// Grow or shrink `owner`'s heap by `increment` bytes, returning its old end
+pub fn grow_heap(owner: ProcessId, increment: isize) -> Result<usize, MmapError> {
+    let mut manager = MMAP_MANAGER.lock();
+    let old_break = manager.heap_break(owner);
+    let new_break = old_break.checked_add_signed(increment).ok_or(MmapError::InvalidArgument)?;
+    manager.set_break(owner, new_break)?;
+    Ok(old_break)
+}
+
+/// Place the heap, stack and mappings of `owner` by `layout` from now on
+///
+/// Called before the image maps anything.
+pub fn set_layout(owner: ProcessId, layout: UserLayout) {
+    MMAP_MANAGER.lock().set_layout(owner, layout);
+}
+
+/// Reserve the stack of `owner`'s new image and put the layout's canary
+/// in its top eight bytes, returning the initial stack pointer below them
+pub fn create_stack(owner: ProcessId) -> Result<usize, MmapError> {
+    let (top, canary) = {
+        let mut manager = MMAP_MANAGER.lock();
+        let top = manager.map_stack(owner)?;
+        (top.0, manager.layout(owner).stack_canary)
+    };
+    // Populated like any other fault, which may have to free memory first
+    let canary_address = VirtualAddress::new(top - core::mem::size_of::<u64>());
+    match handle_page_fault(owner, canary_address, true) {
+        FaultResult::Resolved => {}
+        FaultResult::OutOfMemory => return Err(MmapError::OutOfMemory),
+        _ => return Err(MmapError::MappingFailed),
+    }
+    let frame = MMAP_MANAGER.lock().owned_frame(owner, canary_address).ok_or(MmapError::MappingFailed)?;
+    let offset = canary_address.0 % PAGE_SIZE;
+    frame_bytes(frame)[offset..offset + 8].copy_from_slice(&canary.to_le_bytes());
+    // The ABI wants the stack 16-byte aligned
+    Ok(top - 16)
+}
+
+/// Map a segment of `owner`'s new image: `length` bytes at `start`, the
+/// first of them `data` and the rest zero
+///
+/// The pages holding data are populated and filled now; the zeroed rest,
+/// such as the bss, is left to the page fault handler.
+pub fn map_image_segment(owner: ProcessId, start: VirtualAddress, length: usize, data: &[u8],
+                         protection: MemoryProtection) -> Result<(), MmapError> {
+    let end = start.0.checked_add(length).ok_or(MmapError::InvalidArgument)?;
+    if data.len() > length || end > STACK_TOP {
+        return Err(MmapError::InvalidArgument);
+    }
+    let page_start = start.align_down();
+    MMAP_MANAGER.lock().map_image(owner, page_start, align_up(end) - page_start.0, protection)?;
+
+    let mut copied = 0;
+    while copied < data.len() {
+        let address = VirtualAddress::new(start.0 + copied);
+        match handle_page_fault(owner, address, false) {
+            FaultResult::Resolved => {}
+            FaultResult::OutOfMemory => return Err(MmapError::OutOfMemory),
+            _ => return Err(MmapError::MappingFailed),
+        }
+        let frame = MMAP_MANAGER.lock().owned_frame(owner, address).ok_or(MmapError::MappingFailed)?;
+        let offset = address.0 % PAGE_SIZE;
+        let count = core::cmp::min(PAGE_SIZE - offset, data.len() - copied);
+        frame_bytes(frame)[offset..offset + count].copy_from_slice(&data[copied..copied + count]);
+        copied += count;
+    }
+    log::debug!("mmap: process {} image segment of {} bytes at 0x{:x}", owner.0, length, start.as_usize());
+    Ok(())
+}
+
+/// Populate the page of `pid`'s mappings that `address` faulted on
+///
+/// Out of frames, another process is killed to free some and the fault
+/// retried, for as long as there is one to kill.
+pub fn handle_page_fault(pid: ProcessId, address: VirtualAddress, write: bool) -> FaultResult {
+    loop {
+        // Killing a process removes its mappings, so the lock is dropped first
+        let result = MMAP_MANAGER.lock().fault(pid, address, write);
+        if result != FaultResult::OutOfMemory || !oom::reclaim(pid) {
+            return result;
+        }
+    }
+}
+
+/// Populate every unpopulated page of `[start, start + len)` that lies in a
+/// mapping of `pid`, before the kernel accesses the range itself
+///
+/// Stops at the first page that is not resolved; addresses outside any
+/// mapping are left alone.
+pub fn fault_in_range(pid: ProcessId, start: usize, len: usize, write: bool) -> FaultResult {
+    loop {
+        // Pages populated before running out are skipped on the retry
+        let result = MMAP_MANAGER.lock().fault_in_range(pid, start, len, write);
+        if result != FaultResult::OutOfMemory || !oom::reclaim(pid) {
+            return result;
+        }
+    }
+}
+
+/// Called when a process closes its descriptor for `file`; returns true if
+/// a mapping still uses the file, which is then closed on the service once
+/// the last mapping is gone
+pub fn retain_on_close(file: FileId) -> bool {
+    MMAP_MANAGER.lock().retain_on_close(file)
+}
+
+/// Give the forked `child` a copy of every mapping of `parent`
+///
+/// On failure, what was copied stays with the child, to be freed when it
+/// is released.
+pub fn fork_mappings(parent: ProcessId, child: ProcessId) -> Result<(), MmapError> {
+    MMAP_MANAGER.lock().fork(parent, child)
+}
+
+/// Remove every mapping of `owner`'s image before `exec` replaces it
+///
+/// Unlike at exit, the account and its limit stay with the process.
+pub fn release_image(owner: ProcessId) {
+    MMAP_MANAGER.lock().release_process(owner);
+    accounting::set_heap_size(owner, 0);
+}
+
+/// Remove every mapping of a terminating process and close its account
+pub fn release_process_mappings(pid: ProcessId) {
+    MMAP_MANAGER.lock().release_process(pid);
+    accounting::release_process(pid);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: FileId = FileId { service: ProcessId(2), remote_fd: 5 };
+
+    #[test_case]
+    fn test_mmap_reserves_distinct_ranges() {
+        let mut manager = MmapManager::new();
+        let pid = ProcessId::new(1);
+        let first = manager.map(pid, 100, MemoryProtection::user_read_write(), false, None).unwrap();
+        let second = manager.map(pid, PAGE_SIZE, MemoryProtection::user_read_write(), false, None).unwrap();
+
+        assert_eq!(first.as_usize(), MMAP_WINDOW_BASE);
+        assert!(first.is_aligned() && second.is_aligned());
+        // One page rounded up, plus the guard page
+        assert_eq!(second.as_usize(), first.as_usize() + 2 * PAGE_SIZE);
+    }
+
+    #[test_case]
+    fn test_mmap_rejects_invalid_arguments() {
+        let mut manager = MmapManager::new();
+        let pid = ProcessId::new(1);
+        let protection = MemoryProtection::user_read_write();
+        assert_eq!(manager.map(pid, 0, protection, false, None), Err(MmapError::InvalidArgument));
+
+        let unaligned = FileBacking { file: FILE, offset: 100 };
+        assert_eq!(manager.map(pid, PAGE_SIZE, protection, true, Some(unaligned)), Err(MmapError::InvalidArgument));
+
+        let start = manager.map(pid, PAGE_SIZE, protection, false, None).unwrap();
+        // Only whole mappings of the owner can be removed
+        assert_eq!(manager.unmap(ProcessId::new(9), start, PAGE_SIZE), Err(MmapError::InvalidArgument));
+        assert_eq!(manager.unmap(pid, start, 2 * PAGE_SIZE), Err(MmapError::InvalidArgument));
+        assert_eq!(manager.unmap(pid, start, PAGE_SIZE), Ok(()));
+        assert_eq!(manager.unmap(pid, start, PAGE_SIZE), Err(MmapError::InvalidArgument));
+    }
+
+    #[test_case]
+    fn test_mmap_retains_mapped_files() {
+        let mut manager = MmapManager::new();
+        let pid = ProcessId::new(1);
+        assert!(!manager.retain_on_close(FILE));
+
+        let backing = FileBacking { file: FILE, offset: 0 };
+        manager.map(pid, PAGE_SIZE, MemoryProtection::user_read_write(), true, Some(backing)).unwrap();
+        assert!(manager.retain_on_close(FILE));
+        assert!(manager.orphaned.contains(&FILE));
+    }
+
+    #[test_case]
+    fn test_mmap_fault_outside_mappings() {
+        let mut manager = MmapManager::new();
+        let pid = ProcessId::new(1);
+        let read_only = MemoryProtection { writable: false, ..MemoryProtection::user_read_write() };
+        let start = manager.map(pid, PAGE_SIZE, read_only, false, None).unwrap();
+
+        assert_eq!(manager.fault(pid, VirtualAddress::new(MMAP_WINDOW_BASE - PAGE_SIZE), false), FaultResult::Unhandled);
+        assert_eq!(manager.fault(ProcessId::new(9), start, false), FaultResult::Unhandled);
+        // Writes to a read-only mapping are not resolved
+        assert_eq!(manager.fault(pid, start, true), FaultResult::Unhandled);
+    }
+
+    #[test_case]
+    fn test_heap_is_charged_and_limited() {
+        let mut manager = MmapManager::new();
+        let pid = ProcessId::new(9530);
+        accounting::set_limit(pid, Some(2 * PAGE_SIZE));
+
+        assert_eq!(manager.heap_break(pid), HEAP_BASE);
+        assert_eq!(manager.set_break(pid, HEAP_BASE + 100), Ok(HEAP_BASE + 100));
+        assert_eq!(accounting::usage(pid).heap_bytes, 100);
+        assert_eq!(accounting::usage(pid).reserved_bytes, PAGE_SIZE);
+        // The heap cannot be unmapped like a mapping
+        assert_eq!(manager.unmap(pid, VirtualAddress::new(HEAP_BASE), PAGE_SIZE), Err(MmapError::InvalidArgument));
+
+        assert_eq!(manager.set_break(pid, HEAP_BASE + 3 * PAGE_SIZE), Err(MmapError::LimitExceeded));
+        assert_eq!(manager.map(pid, 2 * PAGE_SIZE, MemoryProtection::user_read_write(), false, None),
+                   Err(MmapError::LimitExceeded));
+        assert_eq!(manager.set_break(pid, HEAP_BASE - 1), Err(MmapError::InvalidArgument));
+
+        assert_eq!(manager.set_break(pid, HEAP_BASE), Ok(HEAP_BASE));
+        assert_eq!(accounting::usage(pid).reserved_bytes, 0);
+        manager.release_process(pid);
+        accounting::release_process(pid);
+    }
+
+    #[test_case]
+    fn test_layout_places_heap_stack_and_mappings() {
+        let mut manager = MmapManager::new();
+        let pid = ProcessId::new(9531);
+        let layout = UserLayout {
+            stack_top: STACK_TOP - 5 * PAGE_SIZE,
+            heap_base: HEAP_BASE + 7 * PAGE_SIZE,
+            mmap_base: MMAP_WINDOW_BASE + 3 * PAGE_SIZE,
+            stack_canary: 0x1122_3344_5566_7700,
+        };
+        manager.set_layout(pid, layout);
+
+        let start = manager.map(pid, PAGE_SIZE, MemoryProtection::user_read_write(), false, None).unwrap();
+        assert_eq!(start.as_usize(), layout.mmap_base);
+        assert_eq!(manager.heap_break(pid), layout.heap_base);
+        assert_eq!(manager.set_break(pid, HEAP_BASE), Err(MmapError::InvalidArgument));
+
+        let top = manager.map_stack(pid).unwrap();
+        assert_eq!(top.as_usize(), layout.stack_top);
+        assert_eq!(manager.map_stack(pid), Err(MmapError::InvalidArgument));
+        // The guard page below the stack belongs to no mapping
+        let bottom = layout.stack_top - STACK_SIZE;
+        assert_eq!(manager.fault(pid, VirtualAddress::new(bottom - PAGE_SIZE), true), FaultResult::Unhandled);
+        assert_eq!(manager.unmap(pid, VirtualAddress::new(bottom), STACK_SIZE), Err(MmapError::InvalidArgument));
+
+        // Other processes keep the fixed bases
+        let other = ProcessId::new(9532);
+        assert_eq!(manager.heap_break(other), HEAP_BASE);
+        manager.release_process(pid);
+        assert_eq!(manager.heap_break(pid), HEAP_BASE);
+        assert_eq!(accounting::usage(pid).reserved_bytes, 0);
+        accounting::release_process(pid);
+    }
+
+    #[test_case]
+    fn test_image_segments_stay_below_the_heap_and_apart() {
+        let mut manager = MmapManager::new();
+        let pid = ProcessId::new(9533);
+        let protection = MemoryProtection::user_read_write();
+        let text = VirtualAddress::new(0x40_0000);
+
+        assert_eq!(manager.map_image(pid, text, 2 * PAGE_SIZE, protection), Ok(()));
+        assert_eq!(accounting::usage(pid).reserved_bytes, 2 * PAGE_SIZE);
+        // Overlapping another segment, unaligned, at zero or into the heap
+        assert_eq!(manager.map_image(pid, VirtualAddress::new(0x40_1000), PAGE_SIZE, protection),
+                   Err(MmapError::InvalidArgument));
+        assert_eq!(manager.map_image(pid, VirtualAddress::new(0x40_2001), PAGE_SIZE, protection),
+                   Err(MmapError::InvalidArgument));
+        assert_eq!(manager.map_image(pid, VirtualAddress::new(0), PAGE_SIZE, protection),
+                   Err(MmapError::InvalidArgument));
+        assert_eq!(manager.map_image(pid, VirtualAddress::new(HEAP_BASE - PAGE_SIZE), 2 * PAGE_SIZE, protection),
+                   Err(MmapError::InvalidArgument));
+        assert_eq!(manager.map_image(pid, VirtualAddress::new(0x40_2000), PAGE_SIZE, protection), Ok(()));
+        // Only what mmap created can be unmapped
+        assert_eq!(manager.unmap(pid, text, 2 * PAGE_SIZE), Err(MmapError::InvalidArgument));
+
+        manager.release_process(pid);
+        assert_eq!(accounting::usage(pid).reserved_bytes, 0);
+        accounting::release_process(pid);
+    }
+
+    #[test_case]
+    fn test_fork_copies_mappings_and_heap() {
+        let mut manager = MmapManager::new();
+        let parent = ProcessId::new(9534);
+        let child = ProcessId::new(9535);
+        let start = manager.map(parent, PAGE_SIZE, MemoryProtection::user_read_write(), false, None).unwrap();
+        manager.set_break(parent, HEAP_BASE + 100).unwrap();
+
+        assert_eq!(manager.fork(parent, child), Ok(()));
+        assert_eq!(manager.heap_break(child), HEAP_BASE + 100);
+        assert_eq!(accounting::usage(child).reserved_bytes, 2 * PAGE_SIZE);
+        // The child's next mapping goes where the parent's would
+        let next = manager.map(child, PAGE_SIZE, MemoryProtection::user_read_write(), false, None).unwrap();
+        assert_eq!(next.as_usize(), start.as_usize() + 2 * PAGE_SIZE);
+        assert_eq!(manager.unmap(child, start, PAGE_SIZE), Ok(()));
+        // The parent keeps its own
+        assert_eq!(manager.unmap(parent, start, PAGE_SIZE), Ok(()));
+
+        for pid in [parent, child] {
+            manager.release_process(pid);
+            accounting::release_process(pid);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Device register windows mapped into driver processes
+//!
+//! A driver may map physical memory it holds a `DeviceAccess` grant for,
+//! as a `MemoryRange` covering the whole window. Windows are mapped
+//! uncached, since register reads and writes must reach the device, and
+//! stay mapped until the driver exits or the grant is revoked, so a driver
+//! never sees registers other than those it is granted.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::process::ProcessId;
+use crate::memory::{PAGE_SIZE, bytes_to_pages};
+use crate::memory::vmm::{self, VirtualAddress, MemoryProtection};
+use crate::ipc::capability::{CapabilityType, ResourceId, check_capability, holds_capability};
+
+/// Base of the virtual window device registers are mapped into
+const MMIO_WINDOW_BASE: usize = 0x0000_7000_0000_0000;
+
+/// Size of the device register mapping window
+const MMIO_WINDOW_SIZE: usize = 0x0000_0010_0000_0000;
+
+/// Largest single register window (256 MiB, a large PCI BAR)
+pub const MAX_MMIO_WINDOW_SIZE: usize = 256 * 1024 * 1024;
+
+/// MMIO mapping errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmioError {
+    /// Empty, oversized or wrapping range
+    InvalidRange,
+    /// The caller has no grant covering the range
+    PermissionDenied,
+    /// The mapping window is used up
+    OutOfAddressSpace,
+    /// The page tables could not be updated
+    MappingFailed,
+}
+
+/// A register window mapped into a driver
+#[derive(Debug, Clone, Copy)]
+struct MmioMapping {
+    address: VirtualAddress,
+    page_count: usize,
+    /// Registers the driver asked for, which its grant must still cover
+    physical: u64,
+    size: usize,
+}
+
+impl MmioMapping {
+    fn unmap(&self, process_id: ProcessId) {
+        for page in 0..self.page_count {
+            let address = VirtualAddress::new(self.address.as_usize() + page * PAGE_SIZE);
+            let _ = vmm::unmap_user_page(process_id, address);
+        }
+    }
+}
+
+struct MmioManager {
+    mappings: BTreeMap<ProcessId, Vec<MmioMapping>>,
+    /// Next free address in the mapping window (addresses are never reused)
+    next_address: usize,
+}
+
+impl MmioManager {
+    const fn new() -> Self {
+        Self {
+            mappings: BTreeMap::new(),
+            next_address: MMIO_WINDOW_BASE,
+        }
+    }
+
+    /// Reserve window space for `page_count` pages
+    fn reserve(&mut self, page_count: usize) -> Result<VirtualAddress, MmioError> {
+        let size = page_count * PAGE_SIZE;
+        if self.next_address + size > MMIO_WINDOW_BASE + MMIO_WINDOW_SIZE {
+            return Err(MmioError::OutOfAddressSpace);
+        }
+        let address = VirtualAddress::new(self.next_address);
+        self.next_address += size;
+        Ok(address)
+    }
+
+    fn map(&mut self, process_id: ProcessId, physical: u64, size: usize, writable: bool) -> Result<VirtualAddress, MmioError> {
+        if size == 0 || size > MAX_MMIO_WINDOW_SIZE || physical.checked_add(size as u64).is_none() {
+            return Err(MmioError::InvalidRange);
+        }
+
+        let resource = ResourceId::MemoryRange { start: physical, size: size as u64 };
+        if !check_capability(process_id, CapabilityType::DeviceAccess, &resource) {
+            return Err(MmioError::PermissionDenied);
+        }
+
+        // Registers need not start on a page boundary; the whole pages
+        // around them are mapped and the address inside returned
+        let offset = physical as usize % PAGE_SIZE;
+        let page_count = bytes_to_pages(offset + size);
+        let base = self.reserve(page_count)?;
+
+        vmm::map_user_range(
+            process_id,
+            base,
+            physical as usize - offset,
+            page_count * PAGE_SIZE,
+            MemoryProtection::user_device(writable),
+        ).map_err(|_| MmioError::MappingFailed)?;
+
+        self.mappings.entry(process_id).or_default().push(MmioMapping { address: base, page_count, physical, size });
+
+        log::debug!("MMIO: process {} mapped 0x{:x} ({} bytes) at 0x{:x}",
+                       process_id.0, physical, size, base.as_usize() + offset);
+        Ok(VirtualAddress::new(base.as_usize() + offset))
+    }
+
+    fn release_process(&mut self, process_id: ProcessId) {
+        for mapping in self.mappings.remove(&process_id).unwrap_or_default() {
+            mapping.unmap(process_id);
+        }
+    }
+
+    fn unmap_revoked(&mut self) {
+        for (&process_id, mappings) in self.mappings.iter_mut() {
+            mappings.retain(|mapping| {
+                let resource = ResourceId::MemoryRange { start: mapping.physical, size: mapping.size as u64 };
+                let granted = holds_capability(process_id, CapabilityType::DeviceAccess, &resource);
+                if !granted {
+                    log::debug!("MMIO: grant of 0x{:x} revoked, unmapping it from process {}",
+                                   mapping.physical, process_id.0);
+                    mapping.unmap(process_id);
+                }
+                granted
+            });
+        }
+        self.mappings.retain(|_, mappings| !mappings.is_empty());
+    }
+}
+
+static MMIO_MANAGER: Mutex<MmioManager> = Mutex::new(MmioManager::new());
+
+/// Map `size` bytes of device memory at `physical` into a driver
+///
+/// Returns the virtual address of `physical` in the driver.
+pub fn map_device_memory(process_id: ProcessId, physical: u64, size: usize, writable: bool) -> Result<VirtualAddress, MmioError> {
+    MMIO_MANAGER.lock().map(process_id, physical, size, writable)
+}
+
+/// Unmap every register window whose grant was revoked
+pub fn unmap_revoked() {
+    MMIO_MANAGER.lock().unmap_revoked();
+}
+
+/// Unmap every register window of a terminating process
+pub fn release_process(process_id: ProcessId) {
+    MMIO_MANAGER.lock().release_process(process_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_mmio_rejects_invalid_range() {
+        let mut manager = MmioManager::new();
+        let pid = ProcessId::new(9201);
+        assert_eq!(manager.map(pid, 0xFEB0_0000, 0, true).unwrap_err(), MmioError::InvalidRange);
+        assert_eq!(manager.map(pid, 0xFEB0_0000, MAX_MMIO_WINDOW_SIZE + 1, true).unwrap_err(), MmioError::InvalidRange);
+        assert_eq!(manager.map(pid, u64::MAX - 0xFFF, 0x2000, true).unwrap_err(), MmioError::InvalidRange);
+    }
+
+    #[test_case]
+    fn test_mmio_requires_grant() {
+        let mut manager = MmioManager::new();
+        let pid = ProcessId::new(9202);
+        assert_eq!(manager.map(pid, 0xFEB0_0000, 0x1000, true).unwrap_err(), MmioError::PermissionDenied);
+        assert!(manager.mappings.is_empty());
+        assert_eq!(manager.next_address, MMIO_WINDOW_BASE);
+    }
+
+    #[test_case]
+    fn test_mmio_window_exhaustion() {
+        let mut manager = MmioManager::new();
+        let pages = MMIO_WINDOW_SIZE / PAGE_SIZE;
+        assert_eq!(manager.reserve(pages).map(|address| address.as_usize()), Ok(MMIO_WINDOW_BASE));
+        assert_eq!(manager.reserve(1).unwrap_err(), MmioError::OutOfAddressSpace);
+    }
+}
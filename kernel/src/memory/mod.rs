@@ -1,10 +1,23 @@
 pub mod physical;
 pub mod vmm;
 pub mod heap;
+pub mod slab;
 pub mod swap;
 pub mod swap_file;
 pub mod swap_config;
 pub mod swap_algorithm;
+pub mod mmap;
+pub mod aslr;
+pub mod accounting;
+pub mod oom;
+pub mod kstack;
+pub mod dma;
+pub mod mmio;
+pub mod iommu;
+#[cfg(debug_assertions)]
+pub mod kasan;
+#[cfg(debug_assertions)]
+pub mod heap_debug;
 
 #[cfg(test)]
 pub mod tests;
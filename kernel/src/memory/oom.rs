@@ -0,0 +1,63 @@
+//! Out-of-memory handling
+//!
+//! When a page fault finds no free frame, the process with the most
+//! resident pages is killed to free some, and the fault is retried. If
+//! the faulting process is itself the largest it gets nothing: a fault
+//! from user mode kills it, a system call fails with ENOMEM. Either way
+//! the kernel keeps running and the log says who went and why.
+//!
+//! The kernel and init are never chosen.
+
+use alloc::string::String;
+use crate::memory::PAGE_SIZE;
+use crate::memory::accounting;
+use crate::process::{ProcessId, owning_process};
+use crate::process::signal::SIGKILL;
+
+/// The process to kill from `candidates`, ordered by resident pages
+fn select_victim(candidates: &[(ProcessId, usize)]) -> Option<(ProcessId, usize)> {
+    candidates.iter()
+        .copied()
+        .find(|&(process, _)| process != ProcessId::KERNEL && process != ProcessId::INIT)
+}
+
+/// No frame is left for `requester`; free memory by killing the process
+/// using the most
+///
+/// Returns true if another process was killed and the allocation is worth
+/// retrying.
+pub fn reclaim(requester: ProcessId) -> bool {
+    let requester = owning_process(requester);
+    let (victim, pages) = match select_victim(&accounting::by_resident_pages()) {
+        Some(victim) => victim,
+        None => {
+            log::error!("Out of memory: nothing to reclaim for process {}", requester.0);
+            return false;
+        }
+    };
+    let name = crate::process::get_process(victim).map(|info| info.name).unwrap_or_else(String::new);
+    let kib = pages * PAGE_SIZE / 1024;
+
+    if victim == requester {
+        log::error!("Out of memory: process {} ({}) uses the most memory, {} KiB, and gets no more",
+                    victim.0, name, kib);
+        return false;
+    }
+    log::error!("Out of memory: killed process {} ({}) using {} KiB to satisfy process {}",
+                victim.0, name, kib, requester.0);
+    crate::syscall::terminate_by_signal(victim, SIGKILL);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_victim_is_the_largest_user_process() {
+        let (small, large) = (ProcessId::new(9520), ProcessId::new(9521));
+        let candidates = [(ProcessId::INIT, 500), (large, 40), (small, 2)];
+        assert_eq!(select_victim(&candidates), Some((large, 40)));
+        assert_eq!(select_victim(&[(ProcessId::KERNEL, 10), (ProcessId::INIT, 5)]), None);
+    }
+}
@@ -1,7 +1,10 @@
 use multiboot2::{BootInformation, MemoryAreaType};
 use spin::Mutex;
 use crate::memory::{PAGE_SIZE, align_down};
-use crate::{serial_println, println};
+
+/// Bytes kept back at the top of memory for data that has to survive a
+/// warm reboot, such as the last panic's crash report
+pub const PERSISTENT_REGION_SIZE: usize = 64 * 1024;
 
 /// Physical page frame number
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -72,6 +75,8 @@ pub struct PhysicalMemoryManager {
     reserved_frames: usize,
     /// Start of the bitmap in memory
     bitmap_start: usize,
+    /// Start of the persistent region, if the top area has room for it
+    persistent_start: Option<usize>,
 }
 
 impl PhysicalMemoryManager {
@@ -82,12 +87,16 @@ impl PhysicalMemoryManager {
         
         // Find the largest usable memory area to place our bitmap
         let mut max_end_addr = 0;
+        let mut top_area_start = 0;
         let mut _total_memory = 0;
         
         for area in memory_map.memory_areas() {
             // Check if memory area is available
             if area.typ() == MemoryAreaType::Available {
-                max_end_addr = max_end_addr.max(area.end_address() as usize);
+                if area.end_address() as usize > max_end_addr {
+                    max_end_addr = area.end_address() as usize;
+                    top_area_start = area.start_address() as usize;
+                }
                 _total_memory += area.size() as usize;
             }
         }
@@ -119,6 +128,13 @@ impl PhysicalMemoryManager {
             }
         }
         
+        // The persistent region has to be at the same place every boot, so
+        // it is the last pages of the highest area
+        let persistent_start = max_end_addr
+            .checked_sub(PERSISTENT_REGION_SIZE)
+            .map(align_down)
+            .filter(|&start| start >= top_area_start && start >= bitmap_end);
+        
         // Initialize bitmap memory
         let bitmap = unsafe {
             core::slice::from_raw_parts_mut(bitmap_start as *mut u8, bitmap_size)
@@ -134,17 +150,21 @@ impl PhysicalMemoryManager {
             used_frames: 0,
             reserved_frames: 0,
             bitmap_start,
+            persistent_start,
         };
         
         // Mark available memory areas as free
         manager.parse_memory_map(&memory_map)?;
         
-        serial_println!("Physical memory manager initialized:");
-        serial_println!("  Total frames: {}", manager.total_frames);
-        serial_println!("  Free frames: {}", manager.free_frames);
-        serial_println!("  Used frames: {}", manager.used_frames);
-        serial_println!("  Reserved frames: {}", manager.reserved_frames);
-        serial_println!("  Bitmap at: 0x{:x} (size: {} bytes)", bitmap_start, bitmap_size);
+        log::info!("Physical memory manager initialized:");
+        log::debug!("  Total frames: {}", manager.total_frames);
+        log::debug!("  Free frames: {}", manager.free_frames);
+        log::debug!("  Used frames: {}", manager.used_frames);
+        log::debug!("  Reserved frames: {}", manager.reserved_frames);
+        log::debug!("  Bitmap at: 0x{:x} (size: {} bytes)", bitmap_start, bitmap_size);
+        if let Some(start) = persistent_start {
+            log::debug!("  Persistent region at: 0x{:x}", start);
+        }
         
         Ok(manager)
     }
@@ -162,10 +182,12 @@ impl PhysicalMemoryManager {
                 for frame_num in start_frame.0..=end_frame.0 {
                     let frame_addr = frame_num * PAGE_SIZE;
                     
-                    // Skip low memory (first 1MB) and bitmap area
+                    // Skip low memory (first 1MB), the bitmap area and the
+                    // persistent region
                     if frame_addr < 0x100000 || 
                        (frame_addr >= self.bitmap_start && 
-                        frame_addr < self.bitmap_start + self.bitmap.len()) {
+                        frame_addr < self.bitmap_start + self.bitmap.len()) ||
+                       self.persistent_start.is_some_and(|start| frame_addr >= start) {
                         self.reserved_frames += 1;
                         continue;
                     }
@@ -261,7 +283,7 @@ impl PhysicalMemoryManager {
         
         if self.is_frame_free(frame) {
             // Already free, this might indicate a double-free bug
-            serial_println!("Warning: Attempted to free already free frame {}", frame.0);
+            log::warn!("Warning: Attempted to free already free frame {}", frame.0);
             return;
         }
         
@@ -346,13 +368,13 @@ impl PhysicalMemoryManager {
     /// Print memory statistics
     pub fn print_stats(&self) {
         let stats = self.stats();
-        serial_println!("Memory Statistics:");
-        serial_println!("  Total: {} MB ({} pages)", stats.total_memory_mb(), stats.total_pages);
-        serial_println!("  Used:  {} MB ({} pages)", stats.used_memory_mb(), stats.used_pages);
-        serial_println!("  Free:  {} MB ({} pages)", stats.free_memory_mb(), stats.free_pages);
-        serial_println!("  Reserved: {} pages", stats.reserved_pages);
+        log::debug!("Memory Statistics:");
+        log::debug!("  Total: {} MB ({} pages)", stats.total_memory_mb(), stats.total_pages);
+        log::debug!("  Used:  {} MB ({} pages)", stats.used_memory_mb(), stats.used_pages);
+        log::debug!("  Free:  {} MB ({} pages)", stats.free_memory_mb(), stats.free_pages);
+        log::debug!("  Reserved: {} pages", stats.reserved_pages);
         
-        println!("Memory: {} MB total, {} MB free, {} MB used", 
+        log::info!("Memory: {} MB total, {} MB free, {} MB used", 
                 stats.total_memory_mb(), stats.free_memory_mb(), stats.used_memory_mb());
     }
 }
@@ -367,7 +389,7 @@ pub fn init_physical_memory(boot_info: &BootInformation) -> Result<(), &'static
     
     *PHYSICAL_MEMORY_MANAGER.lock() = Some(manager);
     
-    serial_println!("Physical memory manager initialized successfully");
+    log::info!("Physical memory manager initialized successfully");
     Ok(())
 }
 
@@ -395,6 +417,13 @@ pub fn deallocate_frames(start_frame: PageFrame, count: usize) {
     }
 }
 
+/// Physical address of the region kept for data that survives a warm
+/// reboot, `PERSISTENT_REGION_SIZE` bytes long; never handed out by the
+/// allocator
+pub fn persistent_region() -> Option<usize> {
+    PHYSICAL_MEMORY_MANAGER.lock().as_ref()?.persistent_start
+}
+
 /// Get memory statistics
 #[allow(dead_code)]
 pub fn memory_stats() -> Option<MemoryStats> {
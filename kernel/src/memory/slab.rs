@@ -0,0 +1,529 @@
+//! Slab allocator for fixed-size kernel objects
+//!
+//! A cache hands out objects of one size from slabs of contiguous frames,
+//! reached through the physical memory window. Free objects are chained
+//! through their first word, so allocating and freeing take an object off
+//! or put it back on a list under the cache lock instead of searching the
+//! heap's free list, and short-lived objects of one kind never fragment the
+//! heap between longer-lived allocations.
+//!
+//! There are caches for the structures allocated on the IPC and scheduling
+//! paths (processes, messages and capabilities) and for page table nodes.
+//! `SlabBox` owns one object of a typed cache the way `Box` owns a heap
+//! allocation.
+//!
+//! In debug builds free objects carry the KASAN-lite freed pattern, which
+//! is checked when they are handed out again, and double or invalid frees
+//! are reported like heap violations.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use spin::Mutex;
+use crate::ipc::capability::Capability;
+use crate::ipc::message::Message;
+use crate::memory::{PAGE_SIZE, bytes_to_pages};
+use crate::memory::physical::{self, PageFrame};
+use crate::memory::vmm::kernel_layout::PHYSICAL_MEMORY_OFFSET;
+#[cfg(debug_assertions)]
+use crate::memory::kasan::{self, Violation, ViolationKind, FREED_PATTERN};
+use crate::process::Process;
+
+/// Objects a slab holds at least, unless one object spans pages
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+
+/// Completely free slabs a typed cache keeps instead of releasing
+const EMPTY_SLABS_KEPT: usize = 1;
+
+/// Free page table frames kept for the next page table
+const PAGE_TABLES_KEPT: usize = 16;
+
+/// Allocation statistics of one cache
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    pub slabs: usize,
+    pub objects_in_use: usize,
+    pub peak_objects: usize,
+    pub total_allocations: u64,
+    pub total_frees: u64,
+    pub failed_allocations: u64,
+}
+
+/// One slab: contiguous frames cut into objects
+#[derive(Debug)]
+struct Slab {
+    frame: PageFrame,
+    /// First free object, chained through the objects' first word; zero
+    /// when the slab is full
+    free: usize,
+    in_use: usize,
+    /// One bit per object, set while it is allocated
+    allocated: Vec<u64>,
+}
+
+/// Cache of equally sized objects
+#[derive(Debug)]
+pub struct SlabCache {
+    name: &'static str,
+    object_size: usize,
+    /// Distance between objects, the size rounded up to the alignment
+    stride: usize,
+    slab_pages: usize,
+    /// Slabs by base address
+    slabs: BTreeMap<usize, Slab>,
+    /// Base addresses of slabs with a free object
+    partial: BTreeSet<usize>,
+    empty_slabs: usize,
+    max_empty_slabs: usize,
+    stats: SlabStats,
+}
+
+impl SlabCache {
+    /// Create an empty cache for objects of `size` bytes aligned to `align`
+    ///
+    /// Alignments above the page size are not supported.
+    pub const fn new(name: &'static str, size: usize, align: usize, max_empty_slabs: usize) -> Self {
+        assert!(align.is_power_of_two() && align <= PAGE_SIZE);
+        // A free object must hold the free list link
+        let align = if align < core::mem::align_of::<usize>() { core::mem::align_of::<usize>() } else { align };
+        let size = if size < core::mem::size_of::<usize>() { core::mem::size_of::<usize>() } else { size };
+        let stride = (size + align - 1) & !(align - 1);
+        let slab_pages = if stride >= PAGE_SIZE {
+            bytes_to_pages(stride)
+        } else {
+            bytes_to_pages(stride * MIN_OBJECTS_PER_SLAB)
+        };
+
+        Self {
+            name,
+            object_size: size,
+            stride,
+            slab_pages,
+            slabs: BTreeMap::new(),
+            partial: BTreeSet::new(),
+            empty_slabs: 0,
+            max_empty_slabs,
+            stats: SlabStats {
+                name,
+                object_size: size,
+                objects_per_slab: slab_pages * PAGE_SIZE / stride,
+                slabs: 0,
+                objects_in_use: 0,
+                peak_objects: 0,
+                total_allocations: 0,
+                total_frees: 0,
+                failed_allocations: 0,
+            },
+        }
+    }
+
+    fn objects_per_slab(&self) -> usize {
+        self.stats.objects_per_slab
+    }
+
+    /// Allocate one object, growing the cache by a slab if needed
+    pub fn allocate(&mut self) -> Option<NonNull<u8>> {
+        let base = match self.partial.first() {
+            Some(&base) => base,
+            None => match self.grow() {
+                Some(base) => base,
+                None => {
+                    self.stats.failed_allocations += 1;
+                    return None;
+                }
+            },
+        };
+
+        let stride = self.stride;
+        let slab = self.slabs.get_mut(&base).expect("partial slab missing");
+        let object = slab.free;
+        slab.free = unsafe { ptr::read(object as *const usize) };
+        let index = (object - base) / stride;
+        slab.allocated[index / 64] |= 1 << (index % 64);
+        if slab.in_use == 0 {
+            self.empty_slabs -= 1;
+        }
+        slab.in_use += 1;
+        if slab.free == 0 {
+            self.partial.remove(&base);
+        }
+
+        #[cfg(debug_assertions)]
+        self.check_poison(object);
+
+        self.stats.total_allocations += 1;
+        self.stats.objects_in_use += 1;
+        self.stats.peak_objects = self.stats.peak_objects.max(self.stats.objects_in_use);
+        NonNull::new(object as *mut u8)
+    }
+
+    /// Return an object to the cache
+    ///
+    /// # Safety
+    /// `object` must not be used after it is freed.
+    pub unsafe fn deallocate(&mut self, object: NonNull<u8>) -> Result<(), &'static str> {
+        let address = object.as_ptr() as usize;
+        let slab_size = self.slab_pages * PAGE_SIZE;
+        let (base, index) = match self.slabs.range(..=address).next_back() {
+            Some((&base, _)) if address < base + slab_size && (address - base) % self.stride == 0 => {
+                (base, (address - base) / self.stride)
+            }
+            _ => return Err(self.free_error(false, address)),
+        };
+        if index >= self.objects_per_slab() {
+            return Err(self.free_error(false, address));
+        }
+
+        let slab = self.slabs.get_mut(&base).expect("slab vanished");
+        if slab.allocated[index / 64] & (1 << (index % 64)) == 0 {
+            return Err(self.free_error(true, address));
+        }
+        slab.allocated[index / 64] &= !(1 << (index % 64));
+
+        #[cfg(debug_assertions)]
+        kasan::poison(object.as_ptr(), self.stride, FREED_PATTERN);
+        ptr::write(address as *mut usize, slab.free);
+        slab.free = address;
+        slab.in_use -= 1;
+        let empty = slab.in_use == 0;
+        self.partial.insert(base);
+
+        self.stats.total_frees += 1;
+        self.stats.objects_in_use -= 1;
+        if empty {
+            self.empty_slabs += 1;
+            if self.empty_slabs > self.max_empty_slabs {
+                self.release(base);
+            }
+        }
+        Ok(())
+    }
+
+    /// Report a bad free; fatal in debug builds like a heap violation
+    #[cfg(debug_assertions)]
+    fn free_error(&self, double_free: bool, address: usize) -> &'static str {
+        kasan::report(Violation {
+            kind: if double_free { ViolationKind::DoubleFree } else { ViolationKind::InvalidFree },
+            object_addr: address,
+            object_size: self.object_size,
+            bad_addr: address,
+            alloc_id: 0,
+        });
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn free_error(&self, double_free: bool, _address: usize) -> &'static str {
+        if double_free { "Object freed twice" } else { "Object not allocated from this cache" }
+    }
+
+    /// Verify that a free object still carries the freed pattern
+    #[cfg(debug_assertions)]
+    fn check_poison(&self, object: usize) {
+        let link = core::mem::size_of::<usize>();
+        let start = (object + link) as *const u8;
+        if let Some(bad) = unsafe { kasan::find_corruption(start, self.stride - link, FREED_PATTERN) } {
+            kasan::report(Violation {
+                kind: ViolationKind::UseAfterFree,
+                object_addr: object,
+                object_size: self.object_size,
+                bad_addr: bad,
+                alloc_id: 0,
+            });
+        }
+    }
+
+    /// Add a slab, returning its base address
+    fn grow(&mut self) -> Option<usize> {
+        let frame = physical::allocate_frames(self.slab_pages)?;
+        let base = PHYSICAL_MEMORY_OFFSET.as_usize() + frame.address();
+        let count = self.objects_per_slab();
+
+        #[cfg(debug_assertions)]
+        unsafe {
+            kasan::poison(base as *mut u8, self.slab_pages * PAGE_SIZE, FREED_PATTERN);
+        }
+        // Chain the objects in address order
+        let mut next = 0;
+        for index in (0..count).rev() {
+            let object = base + index * self.stride;
+            unsafe { ptr::write(object as *mut usize, next) };
+            next = object;
+        }
+
+        self.slabs.insert(base, Slab {
+            frame,
+            free: base,
+            in_use: 0,
+            allocated: vec![0; count.div_ceil(64)],
+        });
+        self.partial.insert(base);
+        self.empty_slabs += 1;
+        self.stats.slabs += 1;
+        Some(base)
+    }
+
+    /// Give a completely free slab back to the frame allocator
+    fn release(&mut self, base: usize) {
+        if let Some(slab) = self.slabs.remove(&base) {
+            self.partial.remove(&base);
+            self.empty_slabs -= 1;
+            self.stats.slabs -= 1;
+            physical::deallocate_frames(slab.frame, self.slab_pages);
+        }
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        self.stats
+    }
+}
+
+/// Slab cache for values of type `T`
+pub struct ObjectCache<T> {
+    cache: Mutex<SlabCache>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ObjectCache<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            cache: Mutex::new(SlabCache::new(name, core::mem::size_of::<T>(), core::mem::align_of::<T>(), EMPTY_SLABS_KEPT)),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        self.cache.lock().stats()
+    }
+}
+
+/// Owning pointer to a value in an `ObjectCache`
+pub struct SlabBox<T: 'static> {
+    object: NonNull<T>,
+    cache: &'static ObjectCache<T>,
+}
+
+// SAFETY: a SlabBox owns its value like a Box does
+unsafe impl<T: Send> Send for SlabBox<T> {}
+unsafe impl<T: Sync> Sync for SlabBox<T> {}
+
+impl<T> SlabBox<T> {
+    /// Move `value` into an object from `cache`
+    pub fn new(cache: &'static ObjectCache<T>, value: T) -> Result<Self, &'static str> {
+        let object = cache.cache.lock().allocate().ok_or("Slab cache out of memory")?.cast::<T>();
+        unsafe { ptr::write(object.as_ptr(), value) };
+        Ok(Self { object, cache })
+    }
+
+    /// Move the value out, freeing its object
+    pub fn into_inner(this: Self) -> T {
+        let value = unsafe { ptr::read(this.object.as_ptr()) };
+        let (object, cache) = (this.object, this.cache);
+        core::mem::forget(this);
+        free_object(cache, object);
+        value
+    }
+}
+
+fn free_object<T>(cache: &'static ObjectCache<T>, object: NonNull<T>) {
+    if let Err(err) = unsafe { cache.cache.lock().deallocate(object.cast()) } {
+        log::warn!("Slab free error: {}", err);
+    }
+}
+
+impl<T> Drop for SlabBox<T> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.object.as_ptr()) };
+        free_object(self.cache, self.object);
+    }
+}
+
+impl<T> Deref for SlabBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.object.as_ref() }
+    }
+}
+
+impl<T> DerefMut for SlabBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.object.as_mut() }
+    }
+}
+
+impl<T: Clone> Clone for SlabBox<T> {
+    fn clone(&self) -> Self {
+        SlabBox::new(self.cache, (**self).clone()).expect("Slab cache out of memory")
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SlabBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+/// Cache for process control blocks
+pub static PROCESS_CACHE: ObjectCache<Process> = ObjectCache::new("process");
+
+/// Cache for queued IPC messages
+pub static MESSAGE_CACHE: ObjectCache<Message> = ObjectCache::new("message");
+
+/// Cache for capabilities held by processes
+pub static CAPABILITY_CACHE: ObjectCache<Capability> = ObjectCache::new("capability");
+
+/// Cache for page table nodes, one frame each
+static PAGE_TABLE_CACHE: Mutex<SlabCache> = Mutex::new(SlabCache::new("page table", PAGE_SIZE, PAGE_SIZE, PAGE_TABLES_KEPT));
+
+/// Allocate a zeroed frame for a page table
+pub fn allocate_page_table() -> Option<PageFrame> {
+    let object = PAGE_TABLE_CACHE.lock().allocate()?;
+    unsafe { ptr::write_bytes(object.as_ptr(), 0, PAGE_SIZE) };
+    Some(PageFrame::from_address(object.as_ptr() as usize - PHYSICAL_MEMORY_OFFSET.as_usize()))
+}
+
+/// Return a page table frame from `allocate_page_table`
+pub fn free_page_table(frame: PageFrame) {
+    let object = (PHYSICAL_MEMORY_OFFSET.as_usize() + frame.address()) as *mut u8;
+    let result = match NonNull::new(object) {
+        Some(object) => unsafe { PAGE_TABLE_CACHE.lock().deallocate(object) },
+        None => Err("Null page table"),
+    };
+    if let Err(err) = result {
+        log::warn!("Failed to free page table at 0x{:x}: {}", frame.address(), err);
+    }
+}
+
+/// Statistics of every kernel slab cache
+pub fn slab_stats() -> Vec<SlabStats> {
+    vec![
+        PROCESS_CACHE.stats(),
+        MESSAGE_CACHE.stats(),
+        CAPABILITY_CACHE.stats(),
+        PAGE_TABLE_CACHE.lock().stats(),
+    ]
+}
+
+/// Print slab cache statistics
+pub fn print_slab_stats() {
+    log::debug!("Slab caches:");
+    log::debug!("  {:<12} {:>6} {:>6} {:>8} {:>8} {:>10} {:>10}",
+                   "cache", "size", "slabs", "in use", "peak", "allocs", "frees");
+    for stats in slab_stats() {
+        log::debug!("  {:<12} {:>6} {:>6} {:>8} {:>8} {:>10} {:>10}",
+                       stats.name, stats.object_size, stats.slabs, stats.objects_in_use,
+                       stats.peak_objects, stats.total_allocations, stats.total_frees);
+        if stats.failed_allocations > 0 {
+            log::debug!("    {} failed allocations", stats.failed_allocations);
+        }
+    }
+
+    let objects: usize = slab_stats().iter().map(|stats| stats.objects_in_use).sum();
+    log::info!("Slab: {} objects in use across {} caches", objects, slab_stats().len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TEST_CACHE: ObjectCache<[u64; 5]> = ObjectCache::new("test");
+
+    #[test_case]
+    fn test_cache_geometry() {
+        let cache = SlabCache::new("geometry", 40, 8, 1);
+        assert_eq!(cache.stride, 40);
+        assert_eq!(cache.slab_pages, 1);
+        assert_eq!(cache.objects_per_slab(), PAGE_SIZE / 40);
+
+        // Tiny objects still hold the free list link
+        let tiny = SlabCache::new("tiny", 1, 1, 1);
+        assert_eq!(tiny.stride, core::mem::size_of::<usize>());
+
+        // One page table per slab
+        let tables = SlabCache::new("tables", PAGE_SIZE, PAGE_SIZE, 1);
+        assert_eq!(tables.slab_pages, 1);
+        assert_eq!(tables.objects_per_slab(), 1);
+
+        let large = SlabCache::new("large", 1024, 16, 1);
+        assert_eq!(large.slab_pages, 2);
+        assert_eq!(large.objects_per_slab(), 8);
+    }
+
+    #[test_case]
+    fn test_allocate_and_reuse() {
+        let mut cache = SlabCache::new("reuse", 64, 8, 0);
+        let first = cache.allocate().expect("object");
+        let second = cache.allocate().expect("object");
+        assert_eq!(second.as_ptr() as usize - first.as_ptr() as usize, 64);
+        assert_eq!(cache.stats().objects_in_use, 2);
+        assert_eq!(cache.stats().slabs, 1);
+
+        unsafe { cache.deallocate(second).unwrap() };
+        let again = cache.allocate().expect("object");
+        assert_eq!(again, second);
+
+        unsafe {
+            cache.deallocate(again).unwrap();
+            cache.deallocate(first).unwrap();
+        }
+        // No empty slabs are kept
+        let stats = cache.stats();
+        assert_eq!(stats.slabs, 0);
+        assert_eq!(stats.objects_in_use, 0);
+        assert_eq!(stats.peak_objects, 2);
+        assert_eq!(stats.total_allocations, 3);
+        assert_eq!(stats.total_frees, 3);
+    }
+
+    #[test_case]
+    fn test_cache_grows_past_one_slab() {
+        let mut cache = SlabCache::new("grow", 512, 8, 1);
+        let per_slab = cache.objects_per_slab();
+        let objects: Vec<_> = (0..per_slab + 1).map(|_| cache.allocate().expect("object")).collect();
+        assert_eq!(cache.stats().slabs, 2);
+
+        for object in objects {
+            unsafe { cache.deallocate(object).unwrap() };
+        }
+        // One empty slab is kept for the next allocation
+        assert_eq!(cache.stats().slabs, 1);
+        let base = *cache.slabs.keys().next().unwrap();
+        cache.release(base);
+        assert_eq!(cache.stats().slabs, 0);
+    }
+
+    #[test_case]
+    fn test_slab_box() {
+        let before = TEST_CACHE.stats().objects_in_use;
+        let mut value = SlabBox::new(&TEST_CACHE, [1, 2, 3, 4, 5]).expect("object");
+        value[4] = 50;
+        let copy = value.clone();
+        assert_eq!(TEST_CACHE.stats().objects_in_use, before + 2);
+
+        assert_eq!(SlabBox::into_inner(value), [1, 2, 3, 4, 50]);
+        drop(copy);
+        assert_eq!(TEST_CACHE.stats().objects_in_use, before);
+    }
+
+    #[test_case]
+    fn test_page_tables_are_zeroed() {
+        let frame = allocate_page_table().expect("page table");
+        let table = (PHYSICAL_MEMORY_OFFSET.as_usize() + frame.address()) as *mut u64;
+        unsafe {
+            assert!((0..PAGE_SIZE / 8).all(|index| *table.add(index) == 0));
+            *table = 0xDEAD;
+        }
+        free_page_table(frame);
+
+        let again = allocate_page_table().expect("page table");
+        assert_eq!(unsafe { *((PHYSICAL_MEMORY_OFFSET.as_usize() + again.address()) as *const u64) }, 0);
+        free_page_table(again);
+    }
+}
@@ -2,7 +2,6 @@ use crate::memory::{PAGE_SIZE, physical::PageFrame};
 use alloc::{vec, vec::Vec, boxed::Box};
 use alloc::collections::BTreeMap;
 use spin::Mutex;
-use crate::{serial_println, println};
 
 // Re-export swap modules that are in the same directory
 pub use crate::memory::swap_file;
@@ -80,6 +79,10 @@ pub enum SwapError {
     SlotInUse,
     /// Slot not in use
     SlotNotInUse,
+    /// The virtual page is not mapped to a frame
+    PageNotMapped,
+    /// No free frame to swap a page back into
+    OutOfMemory,
 }
 
 /// Swap space allocation tracking
@@ -164,13 +167,13 @@ impl SwapAllocator {
     }
 }
 
-/// Page-to-swap mapping entry
-#[derive(Debug, Clone, Copy)]
-struct SwapEntry {
+/// Location of a page in swap space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapEntry {
     /// The swap device index
-    device_index: usize,
+    pub device_index: usize,
     /// The swap slot in the device
-    slot: SwapSlot,
+    pub slot: SwapSlot,
 }
 
 /// Swap space manager
@@ -261,7 +264,7 @@ impl SwapManager {
         let device_index = self.devices.len();
         let slot_count = device.slot_count();
         
-        serial_println!("Adding swap device '{}' with {} slots ({} MB)", 
+        log::debug!("Adding swap device '{}' with {} slots ({} MB)", 
                        device.name(), slot_count, (slot_count * PAGE_SIZE) / (1024 * 1024));
         
         // Create allocator for this device
@@ -316,38 +319,30 @@ impl SwapManager {
         Ok(())
     }
     
-    /// Swap out a page to swap space
-    pub fn swap_out_page(&mut self, page_frame: PageFrame, page_data: &[u8; PAGE_SIZE]) -> Result<SwapSlot, SwapError> {
-        // Check if page is already swapped out
-        if self.page_to_swap.contains_key(&page_frame) {
-            return Err(SwapError::SlotInUse);
-        }
-        
+    /// Write a page to a free swap slot
+    ///
+    /// The slot is not associated with any page frame; the caller keeps the
+    /// returned entry, typically in the page table entry of the page.
+    pub fn write_entry(&mut self, page_data: &[u8; PAGE_SIZE]) -> Result<SwapEntry, SwapError> {
         // Find a device with free space
         for (device_index, allocator) in self.allocators.iter_mut().enumerate() {
             if let Some(slot) = allocator.allocate_slot() {
                 // Try to write to the device
                 match self.devices[device_index].write_page(slot, page_data) {
                     Ok(()) => {
-                        // Success - update mappings
-                        let swap_entry = SwapEntry {
-                            device_index,
-                            slot,
-                        };
-                        
-                        self.page_to_swap.insert(page_frame, swap_entry);
-                        self.swap_to_page.insert((device_index, slot), page_frame);
-                        
                         // Update statistics
                         self.total_stats.used_slots += 1;
                         self.total_stats.free_slots -= 1;
                         
-                        return Ok(slot);
+                        return Ok(SwapEntry {
+                            device_index,
+                            slot,
+                        });
                     }
                     Err(err) => {
                         // Failed to write - deallocate the slot
                         let _ = allocator.deallocate_slot(slot);
-                        serial_println!("Failed to write page to swap device {}: {:?}", device_index, err);
+                        log::warn!("Failed to write page to swap device {}: {:?}", device_index, err);
                         continue;
                     }
                 }
@@ -357,24 +352,19 @@ impl SwapManager {
         Err(SwapError::NoSpace)
     }
     
-    /// Swap in a page from swap space
-    pub fn swap_in_page(&mut self, page_frame: PageFrame, page_data: &mut [u8; PAGE_SIZE]) -> Result<(), SwapError> {
-        // Find the swap entry for this page
-        let swap_entry = self.page_to_swap.get(&page_frame)
-            .ok_or(SwapError::SlotNotInUse)?;
-        
-        let device_index = swap_entry.device_index;
-        let slot = swap_entry.slot;
+    /// Read a page back from `entry` and free its slot
+    pub fn read_entry(&mut self, entry: SwapEntry, page_data: &mut [u8; PAGE_SIZE]) -> Result<(), SwapError> {
+        let allocator = self.allocators.get_mut(entry.device_index)
+            .ok_or(SwapError::InvalidSlot)?;
+        if !allocator.is_slot_allocated(entry.slot) {
+            return Err(SwapError::SlotNotInUse);
+        }
         
         // Read from the device
-        self.devices[device_index].read_page(slot, page_data)?;
+        self.devices[entry.device_index].read_page(entry.slot, page_data)?;
         
         // Deallocate the swap slot
-        self.allocators[device_index].deallocate_slot(slot)?;
-        
-        // Remove mappings
-        self.page_to_swap.remove(&page_frame);
-        self.swap_to_page.remove(&(device_index, slot));
+        self.allocators[entry.device_index].deallocate_slot(entry.slot)?;
         
         // Update statistics
         self.total_stats.used_slots -= 1;
@@ -383,6 +373,37 @@ impl SwapManager {
         Ok(())
     }
     
+    /// Swap out a page to swap space
+    pub fn swap_out_page(&mut self, page_frame: PageFrame, page_data: &[u8; PAGE_SIZE]) -> Result<SwapSlot, SwapError> {
+        // Check if page is already swapped out
+        if self.page_to_swap.contains_key(&page_frame) {
+            return Err(SwapError::SlotInUse);
+        }
+        
+        let swap_entry = self.write_entry(page_data)?;
+        
+        // Update mappings
+        self.page_to_swap.insert(page_frame, swap_entry);
+        self.swap_to_page.insert((swap_entry.device_index, swap_entry.slot), page_frame);
+        
+        Ok(swap_entry.slot)
+    }
+    
+    /// Swap in a page from swap space
+    pub fn swap_in_page(&mut self, page_frame: PageFrame, page_data: &mut [u8; PAGE_SIZE]) -> Result<(), SwapError> {
+        // Find the swap entry for this page
+        let swap_entry = *self.page_to_swap.get(&page_frame)
+            .ok_or(SwapError::SlotNotInUse)?;
+        
+        self.read_entry(swap_entry, page_data)?;
+        
+        // Remove mappings
+        self.page_to_swap.remove(&page_frame);
+        self.swap_to_page.remove(&(swap_entry.device_index, swap_entry.slot));
+        
+        Ok(())
+    }
+    
     /// Check if a page is swapped out
     pub fn is_page_swapped(&self, page_frame: PageFrame) -> bool {
         self.page_to_swap.contains_key(&page_frame)
@@ -407,21 +428,21 @@ impl SwapManager {
     pub fn print_stats(&self) {
         let stats = self.stats();
         
-        serial_println!("Swap Space Statistics:");
-        serial_println!("  Total: {} MB ({} slots)", stats.total_mb(), stats.total_slots);
-        serial_println!("  Used:  {} MB ({} slots)", stats.used_mb(), stats.used_slots);
-        serial_println!("  Free:  {} MB ({} slots)", stats.free_mb(), stats.free_slots);
-        serial_println!("  Usage: {:.1}%", stats.usage_percent());
-        serial_println!("  Devices: {}", self.device_count());
+        log::debug!("Swap Space Statistics:");
+        log::debug!("  Total: {} MB ({} slots)", stats.total_mb(), stats.total_slots);
+        log::debug!("  Used:  {} MB ({} slots)", stats.used_mb(), stats.used_slots);
+        log::debug!("  Free:  {} MB ({} slots)", stats.free_mb(), stats.free_slots);
+        log::debug!("  Usage: {:.1}%", stats.usage_percent());
+        log::debug!("  Devices: {}", self.device_count());
         
         for (i, device) in self.devices.iter().enumerate() {
             if let Some(device_stats) = self.device_stats(i) {
-                serial_println!("    Device {}: '{}' - {} MB total, {} MB used", 
+                log::debug!("    Device {}: '{}' - {} MB total, {} MB used", 
                                i, device.name(), device_stats.total_mb(), device_stats.used_mb());
             }
         }
         
-        println!("Swap: {} MB total, {} MB free, {:.1}% used", 
+        log::info!("Swap: {} MB total, {} MB free, {:.1}% used", 
                 stats.total_mb(), stats.free_mb(), stats.usage_percent());
     }
 }
@@ -434,7 +455,7 @@ pub fn init_swap_manager() -> Result<(), SwapError> {
     let manager = SwapManager::new();
     *SWAP_MANAGER.lock() = Some(manager);
     
-    serial_println!("Swap manager initialized");
+    log::info!("Swap manager initialized");
     Ok(())
 }
 
@@ -466,6 +487,20 @@ pub fn swap_in_page(page_frame: PageFrame, page_data: &mut [u8; PAGE_SIZE]) -> R
     manager.swap_in_page(page_frame, page_data)
 }
 
+/// Write a page to swap space without tying it to a page frame
+pub fn write_to_swap(page_data: &[u8; PAGE_SIZE]) -> Result<SwapEntry, SwapError> {
+    let mut manager_guard = SWAP_MANAGER.lock();
+    let manager = manager_guard.as_mut().ok_or(SwapError::DeviceUnavailable)?;
+    manager.write_entry(page_data)
+}
+
+/// Read a page written by `write_to_swap` and free its slot
+pub fn read_from_swap(entry: SwapEntry, page_data: &mut [u8; PAGE_SIZE]) -> Result<(), SwapError> {
+    let mut manager_guard = SWAP_MANAGER.lock();
+    let manager = manager_guard.as_mut().ok_or(SwapError::DeviceUnavailable)?;
+    manager.read_entry(entry, page_data)
+}
+
 /// Check if a page is swapped out
 pub fn is_page_swapped(page_frame: PageFrame) -> bool {
     let manager_guard = SWAP_MANAGER.lock();
@@ -488,7 +523,7 @@ pub fn print_swap_stats() {
     if let Some(manager) = manager_guard.as_ref() {
         manager.print_stats();
     } else {
-        serial_println!("Swap manager not initialized");
+        log::info!("Swap manager not initialized");
     }
 }
 
@@ -621,6 +656,24 @@ mod tests {
         assert!(!manager.is_page_swapped(page_frame));
     }
     
+    #[test_case]
+    fn test_swap_entry_round_trip() {
+        let mut manager = SwapManager::new();
+        manager.add_device(Box::new(MockSwapDevice::new("test_swap", 1))).unwrap();
+        
+        let page_data = [0x5Au8; PAGE_SIZE];
+        let entry = manager.write_entry(&page_data).unwrap();
+        assert_eq!(manager.stats().used_slots, 1);
+        
+        let mut read_data = [0u8; PAGE_SIZE];
+        manager.read_entry(entry, &mut read_data).unwrap();
+        assert_eq!(read_data, page_data);
+        assert_eq!(manager.stats().used_slots, 0);
+        
+        // The slot was freed by the first read
+        assert_eq!(manager.read_entry(entry, &mut read_data), Err(SwapError::SlotNotInUse));
+    }
+    
     #[test_case]
     fn test_swap_stats() {
         let stats = SwapStats {
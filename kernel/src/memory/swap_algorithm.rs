@@ -1,9 +1,8 @@
-use crate::memory::{PAGE_SIZE, physical::PageFrame, vmm::VirtualAddress};
-use crate::memory::swap::{SwapSlot, SwapError, swap_out_page, swap_in_page, is_page_swapped};
+use crate::memory::{physical::PageFrame, vmm::{VirtualAddress, swap_out_virtual_page}};
+use crate::memory::swap::SwapError;
 use alloc::vec::Vec;
 use alloc::collections::{BTreeMap, VecDeque};
 use spin::Mutex;
-use crate::{serial_println, println};
 
 /// Page replacement algorithm types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -362,7 +361,7 @@ impl PageSwapper {
         if self.algorithm != algorithm {
             self.algorithm = algorithm;
             self.stats.algorithm_switches += 1;
-            serial_println!("Switched page replacement algorithm to {:?}", algorithm);
+            log::debug!("Switched page replacement algorithm to {:?}", algorithm);
         }
     }
     
@@ -393,29 +392,15 @@ impl PageSwapper {
         }
     }
     
-    /// Handle page fault - swap in a page if needed
+    /// Account for a page fault that swapped the page at `virtual_address`
+    /// back into `page_frame`
+    ///
+    /// The page fault handler in `vmm` has already read the page in and
+    /// mapped it; the page is tracked again from here on.
     pub fn handle_page_fault(&mut self, virtual_address: VirtualAddress, page_frame: PageFrame) -> Result<(), SwapError> {
         self.stats.page_faults += 1;
-        
-        // Check if page is swapped out
-        if is_page_swapped(page_frame) {
-            serial_println!("Page fault: swapping in page {} for virtual address 0x{:x}", 
-                           page_frame.0, virtual_address.as_usize());
-            
-            // Allocate buffer for page data
-            let mut page_data = [0u8; PAGE_SIZE];
-            
-            // Swap in the page
-            swap_in_page(page_frame, &mut page_data)?;
-            
-            self.stats.pages_swapped_in += 1;
-            
-            // Record access
-            self.access_page(virtual_address, page_frame, false);
-            
-            serial_println!("Successfully swapped in page {}", page_frame.0);
-        }
-        
+        self.stats.pages_swapped_in += 1;
+        self.access_page(virtual_address, page_frame, false);
         Ok(())
     }
     
@@ -425,7 +410,7 @@ impl PageSwapper {
         
         if current_page_count > self.memory_pressure_threshold {
             let pages_to_swap = current_page_count - self.memory_pressure_threshold;
-            serial_println!("Memory pressure detected: {} pages over threshold, swapping out {} pages", 
+            log::debug!("Memory pressure detected: {} pages over threshold, swapping out {} pages", 
                            current_page_count - self.memory_pressure_threshold, pages_to_swap);
             
             self.swap_out_pages(pages_to_swap)
@@ -440,28 +425,36 @@ impl PageSwapper {
         
         for _ in 0..count {
             if let Some(victim_page) = self.select_victim_page() {
-                if let Some(page_info) = self.page_info.get(&victim_page) {
-                    // Create page data buffer
-                    let page_data = [0u8; PAGE_SIZE]; // In real implementation, read from memory
-                    
-                    // Swap out the page
-                    match swap_out_page(victim_page, &page_data) {
-                        Ok(swap_slot) => {
-                            serial_println!("Swapped out page {} to slot {}", victim_page.0, swap_slot.slot());
-                            
-                            // Remove from algorithm tracking
-                            self.remove_page_from_tracking(victim_page);
-                            
-                            // Remove page info
-                            self.page_info.remove(&victim_page);
-                            
-                            self.stats.pages_swapped_out += 1;
-                            swapped_count += 1;
-                        }
-                        Err(e) => {
-                            serial_println!("Failed to swap out page {}: {:?}", victim_page.0, e);
-                            break;
-                        }
+                let virtual_address = match self.page_info.get(&victim_page) {
+                    Some(page_info) => page_info.virtual_address,
+                    None => {
+                        // Nothing left to evict it by
+                        self.remove_page_from_tracking(victim_page);
+                        continue;
+                    }
+                };
+                
+                // Swap out the page; it comes back through the page fault handler
+                let result = swap_out_virtual_page(virtual_address);
+                
+                // Either way the page leaves tracking, so a page that cannot
+                // be swapped out is not picked again
+                self.remove_page_from_tracking(victim_page);
+                self.page_info.remove(&victim_page);
+                
+                match result {
+                    Ok(_) => {
+                        log::debug!("Swapped out page {} at virtual address 0x{:x}", 
+                                       victim_page.0, virtual_address.as_usize());
+                        self.stats.pages_swapped_out += 1;
+                        swapped_count += 1;
+                    }
+                    Err(SwapError::PageNotMapped) => {
+                        log::debug!("Page {} at 0x{:x} is no longer mapped", victim_page.0, virtual_address.as_usize());
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to swap out page {}: {:?}", victim_page.0, e);
+                        break;
                     }
                 }
             } else {
@@ -470,7 +463,7 @@ impl PageSwapper {
         }
         
         if swapped_count > 0 {
-            serial_println!("Successfully swapped out {} pages", swapped_count);
+            log::debug!("Successfully swapped out {} pages", swapped_count);
         }
         
         Ok(swapped_count)
@@ -517,7 +510,7 @@ impl PageSwapper {
     /// Set memory pressure threshold
     pub fn set_memory_pressure_threshold(&mut self, threshold: usize) {
         self.memory_pressure_threshold = threshold;
-        serial_println!("Set memory pressure threshold to {} pages", threshold);
+        log::debug!("Set memory pressure threshold to {} pages", threshold);
     }
     
     /// Get statistics
@@ -527,16 +520,16 @@ impl PageSwapper {
     
     /// Print statistics
     pub fn print_stats(&self) {
-        serial_println!("Page Swapper Statistics:");
-        serial_println!("  Algorithm: {:?}", self.algorithm);
-        serial_println!("  Pages swapped out: {}", self.stats.pages_swapped_out);
-        serial_println!("  Pages swapped in: {}", self.stats.pages_swapped_in);
-        serial_println!("  Page faults handled: {}", self.stats.page_faults);
-        serial_println!("  Algorithm switches: {}", self.stats.algorithm_switches);
-        serial_println!("  Current pages tracked: {}", self.get_current_page_count());
-        serial_println!("  Memory pressure threshold: {}", self.memory_pressure_threshold);
-        
-        println!("Swap: {} out, {} in, {} faults, {:?} algorithm", 
+        log::debug!("Page Swapper Statistics:");
+        log::debug!("  Algorithm: {:?}", self.algorithm);
+        log::debug!("  Pages swapped out: {}", self.stats.pages_swapped_out);
+        log::debug!("  Pages swapped in: {}", self.stats.pages_swapped_in);
+        log::debug!("  Page faults handled: {}", self.stats.page_faults);
+        log::debug!("  Algorithm switches: {}", self.stats.algorithm_switches);
+        log::debug!("  Current pages tracked: {}", self.get_current_page_count());
+        log::debug!("  Memory pressure threshold: {}", self.memory_pressure_threshold);
+        
+        log::info!("Swap: {} out, {} in, {} faults, {:?} algorithm", 
                 self.stats.pages_swapped_out, self.stats.pages_swapped_in, 
                 self.stats.page_faults, self.algorithm);
     }
@@ -550,7 +543,7 @@ pub fn init_page_swapper(algorithm: PageReplacementAlgorithm, max_pages: usize)
     let swapper = PageSwapper::new(algorithm, max_pages);
     *PAGE_SWAPPER.lock() = Some(swapper);
     
-    serial_println!("Page swapper initialized with {:?} algorithm, {} max pages", algorithm, max_pages);
+    log::info!("Page swapper initialized with {:?} algorithm, {} max pages", algorithm, max_pages);
     Ok(())
 }
 
@@ -625,7 +618,7 @@ pub fn print_swapper_stats() {
     if let Some(swapper) = swapper_guard.as_ref() {
         swapper.print_stats();
     } else {
-        serial_println!("Page swapper not initialized");
+        log::info!("Page swapper not initialized");
     }
 }
 
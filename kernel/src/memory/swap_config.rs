@@ -3,7 +3,6 @@ use crate::memory::swap::swap_file::{FileSwapDevice, PartitionSwapDevice};
 use alloc::vec::Vec;
 use alloc::string::{String, ToString};
 use alloc::boxed::Box;
-use crate::{serial_println, println};
 
 /// Swap configuration entry
 #[derive(Debug, Clone)]
@@ -123,11 +122,11 @@ impl SwapConfigManager {
         // Create the swap device based on configuration
         let device: Box<dyn SwapDevice> = match &config.device_type {
             SwapDeviceConfig::File { path, size_mb } => {
-                serial_println!("Creating file-based swap device: {} ({} MB)", path, size_mb);
+                log::debug!("Creating file-based swap device: {} ({} MB)", path, size_mb);
                 Box::new(FileSwapDevice::new(path.clone(), *size_mb)?)
             }
             SwapDeviceConfig::Partition { device_path, partition_id, size_mb } => {
-                serial_println!("Creating partition-based swap device: {} (partition {}, {} MB)", 
+                log::debug!("Creating partition-based swap device: {} (partition {}, {} MB)", 
                                device_path, partition_id, size_mb);
                 Box::new(PartitionSwapDevice::new(device_path.clone(), *partition_id, *size_mb)?)
             }
@@ -142,7 +141,7 @@ impl SwapConfigManager {
             self.configs[b].priority.cmp(&self.configs[a].priority)
         });
         
-        serial_println!("Activated swap device {} with global index {}", config_index, device_index);
+        log::debug!("Activated swap device {} with global index {}", config_index, device_index);
         
         Ok(())
     }
@@ -151,7 +150,7 @@ impl SwapConfigManager {
     pub fn initialize_all(&mut self) -> Result<usize, SwapError> {
         let mut activated_count = 0;
         
-        serial_println!("Initializing swap devices from configuration...");
+        log::info!("Initializing swap devices from configuration...");
         
         for config_index in 0..self.configs.len() {
             if self.configs[config_index].enabled {
@@ -160,15 +159,14 @@ impl SwapConfigManager {
                         activated_count += 1;
                     }
                     Err(err) => {
-                        serial_println!("Failed to activate swap device {}: {:?}", config_index, err);
+                        log::warn!("Failed to activate swap device {}: {:?}", config_index, err);
                         // Continue with other devices
                     }
                 }
             }
         }
         
-        serial_println!("Activated {} swap devices", activated_count);
-        println!("Swap: {} devices configured and activated", activated_count);
+        log::debug!("Activated {} swap devices", activated_count);
         
         Ok(activated_count)
     }
@@ -190,9 +188,9 @@ impl SwapConfigManager {
     
     /// Print configuration summary
     pub fn print_config(&self) {
-        serial_println!("Swap Configuration:");
-        serial_println!("  Total configs: {}", self.config_count());
-        serial_println!("  Active devices: {}", self.active_count());
+        log::debug!("Swap Configuration:");
+        log::debug!("  Total configs: {}", self.config_count());
+        log::debug!("  Active devices: {}", self.active_count());
         
         for (i, config) in self.configs.iter().enumerate() {
             let status = if config.enabled { "enabled" } else { "disabled" };
@@ -200,11 +198,11 @@ impl SwapConfigManager {
             
             match &config.device_type {
                 SwapDeviceConfig::File { path, size_mb } => {
-                    serial_println!("    {}: File '{}' - {} MB, priority {}, {}{}", 
+                    log::debug!("    {}: File '{}' - {} MB, priority {}, {}{}", 
                                    i, path, size_mb, config.priority, status, active);
                 }
                 SwapDeviceConfig::Partition { device_path, partition_id, size_mb } => {
-                    serial_println!("    {}: Partition '{}' (ID {}) - {} MB, priority {}, {}{}", 
+                    log::debug!("    {}: Partition '{}' (ID {}) - {} MB, priority {}, {}{}", 
                                    i, device_path, partition_id, size_mb, config.priority, status, active);
                 }
             }
@@ -228,7 +226,7 @@ pub fn create_default_config() -> SwapConfigManager {
     
     manager.add_config(default_file_config);
     
-    serial_println!("Created default swap configuration");
+    log::debug!("Created default swap configuration");
     
     manager
 }
@@ -263,7 +261,7 @@ pub fn detect_swap_devices() -> SwapConfigManager {
     };
     manager.add_config(partition_config);
     
-    serial_println!("Detected {} potential swap devices", manager.config_count());
+    log::debug!("Detected {} potential swap devices", manager.config_count());
     
     manager
 }
@@ -1,12 +1,16 @@
 use crate::memory::{PAGE_SIZE, align_down, align_up};
 use crate::memory::physical::{PageFrame, allocate_frame, deallocate_frame};
-use crate::{serial_println, println};
+use crate::memory::slab::{allocate_page_table, free_page_table};
+use crate::memory::swap::{SwapEntry, SwapError, SwapSlot, read_from_swap, write_to_swap};
+use crate::process::ProcessId;
+use core::sync::atomic::{AtomicU64, Ordering};
 use spin::Mutex;
 use x86_64::structures::paging::{
-    PageTable, PageTableFlags, PhysFrame, Page, Size4KiB,
+    PageTable, PageTableEntry, PageTableFlags, PageTableIndex, PhysFrame, Page, Size4KiB,
     FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Translate,
     mapper::{MapToError, UnmapError}
 };
+use x86_64::registers::control::Cr3;
 use x86_64::{VirtAddr, PhysAddr};
 use alloc::vec::Vec;
 
@@ -53,6 +57,8 @@ pub struct MemoryProtection {
     pub writable: bool,
     pub executable: bool,
     pub user_accessible: bool,
+    /// Bypass the caches, as device registers need
+    pub uncached: bool,
 }
 
 impl MemoryProtection {
@@ -63,6 +69,7 @@ impl MemoryProtection {
             writable: false,
             executable: false,
             user_accessible: false,
+            uncached: false,
         }
     }
     
@@ -73,6 +80,7 @@ impl MemoryProtection {
             writable: true,
             executable: false,
             user_accessible: false,
+            uncached: false,
         }
     }
     
@@ -83,6 +91,7 @@ impl MemoryProtection {
             writable: false,
             executable: true,
             user_accessible: false,
+            uncached: false,
         }
     }
     
@@ -93,6 +102,18 @@ impl MemoryProtection {
             writable: true,
             executable: false,
             user_accessible: true,
+            uncached: false,
+        }
+    }
+    
+    /// Create user-accessible uncached protection for device registers
+    pub const fn user_device(writable: bool) -> Self {
+        Self {
+            readable: true,
+            writable,
+            executable: false,
+            user_accessible: true,
+            uncached: true,
         }
     }
     
@@ -112,6 +133,10 @@ impl MemoryProtection {
             flags |= PageTableFlags::USER_ACCESSIBLE;
         }
         
+        if self.uncached {
+            flags |= PageTableFlags::NO_CACHE | PageTableFlags::WRITE_THROUGH;
+        }
+        
         flags
     }
 }
@@ -158,7 +183,8 @@ pub struct KoshFrameAllocator;
 
 unsafe impl FrameAllocator<Size4KiB> for KoshFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
-        allocate_frame().map(|frame| {
+        // Only ever asked for page table frames
+        allocate_page_table().map(|frame| {
             let phys_addr = PhysAddr::new(frame.address() as u64);
             PhysFrame::containing_address(phys_addr)
         })
@@ -169,11 +195,53 @@ impl FrameDeallocator<Size4KiB> for KoshFrameAllocator {
     unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
         let phys_addr = frame.start_address().as_u64() as usize;
         let page_frame = PageFrame::from_address(phys_addr);
-        deallocate_frame(page_frame);
+        free_page_table(page_frame);
     }
 }
 
+/// Marks a non-present page table entry whose page is in swap space
+///
+/// The entry keeps the page's other flags, and its address field holds the
+/// swap location instead of a frame: the slot in the low 32 bits of the
+/// frame number and the device index in the 8 bits above.
+const SWAPPED: PageTableFlags = PageTableFlags::BIT_9;
+
+/// Page table entry address bits holding a swap location
+fn swap_entry_to_address(entry: SwapEntry) -> PhysAddr {
+    let key = ((entry.device_index as u64 & 0xFF) << 32) | (entry.slot.0 as u64 & 0xFFFF_FFFF);
+    PhysAddr::new(key << 12)
+}
+
+fn swap_entry_from_address(address: PhysAddr) -> SwapEntry {
+    let key = address.as_u64() >> 12;
+    SwapEntry {
+        device_index: (key >> 32) as usize & 0xFF,
+        slot: SwapSlot::new((key & 0xFFFF_FFFF) as usize),
+    }
+}
+
+/// Entries in a level 4 page table
+const LEVEL_4_ENTRIES: usize = 512;
+
+/// First level 4 slot of the higher half
+const HIGHER_HALF_SLOT: usize = 256;
+
+/// Set of level 4 slots, one bit each
+type SlotSet = [u64; LEVEL_4_ENTRIES / 64];
+
+fn slot_contains(slots: &SlotSet, index: usize) -> bool {
+    slots[index / 64] & (1 << (index % 64)) != 0
+}
+
+fn slot_insert(slots: &mut SlotSet, index: usize) {
+    slots[index / 64] |= 1 << (index % 64);
+}
+
 /// Virtual address space abstraction
+///
+/// Besides the kernel's own, every process has one. A process address
+/// space shares the kernel's lower level tables for the level 4 slots the
+/// kernel uses and owns the tables of all other slots.
 pub struct VirtualAddressSpace {
     /// Page table mapper
     mapper: OffsetPageTable<'static>,
@@ -183,6 +251,12 @@ pub struct VirtualAddressSpace {
     regions: Vec<VirtualMemoryRegion>,
     /// Physical memory offset for higher half kernel
     physical_memory_offset: VirtAddr,
+    /// Frame holding the level 4 table
+    root: PhysFrame,
+    /// Level 4 slots whose tables belong to the kernel address space
+    kernel_slots: SlotSet,
+    /// Page tables outside the kernel slots are owned and freed on drop
+    user: bool,
 }
 
 impl core::fmt::Debug for VirtualAddressSpace {
@@ -191,6 +265,8 @@ impl core::fmt::Debug for VirtualAddressSpace {
             .field("frame_allocator", &self.frame_allocator)
             .field("regions", &self.regions)
             .field("physical_memory_offset", &self.physical_memory_offset)
+            .field("root", &self.root)
+            .field("user", &self.user)
             .finish()
     }
 }
@@ -198,6 +274,7 @@ impl core::fmt::Debug for VirtualAddressSpace {
 impl VirtualAddressSpace {
     /// Create a new virtual address space
     pub unsafe fn new(level_4_table: &'static mut PageTable, physical_memory_offset: VirtAddr) -> Self {
+        let table_address = level_4_table as *mut PageTable as u64 - physical_memory_offset.as_u64();
         let mapper = OffsetPageTable::new(level_4_table, physical_memory_offset);
         
         Self {
@@ -205,9 +282,47 @@ impl VirtualAddressSpace {
             frame_allocator: KoshFrameAllocator,
             regions: Vec::new(),
             physical_memory_offset,
+            root: PhysFrame::containing_address(PhysAddr::new(table_address)),
+            kernel_slots: [0; LEVEL_4_ENTRIES / 64],
+            user: false,
         }
     }
     
+    /// Create a process address space on top of the kernel address space
+    /// `kernel`
+    ///
+    /// The kernel slots of the new level 4 table point at the kernel's own
+    /// lower level tables, so kernel mappings made later show up in every
+    /// process. All other slots start out empty.
+    pub fn new_user(kernel: &mut VirtualAddressSpace) -> Result<Self, &'static str> {
+        let frame = allocate_page_table().ok_or("Out of memory for a page table")?;
+        let offset = kernel.physical_memory_offset;
+        let table = unsafe { &mut *(offset + frame.address() as u64).as_mut_ptr::<PageTable>() };
+        
+        let kernel_slots = kernel.kernel_slots;
+        let kernel_table = kernel.mapper.level_4_table();
+        for index in 0..LEVEL_4_ENTRIES {
+            if slot_contains(&kernel_slots, index) {
+                table[index] = kernel_table[index].clone();
+            }
+        }
+        
+        let mut space = unsafe { Self::new(table, offset) };
+        space.kernel_slots = kernel_slots;
+        space.user = true;
+        Ok(space)
+    }
+    
+    /// Whether `virt_addr` lies in a level 4 slot shared with the kernel
+    pub fn is_kernel_address(&self, virt_addr: VirtualAddress) -> bool {
+        slot_contains(&self.kernel_slots, usize::from(virt_addr.as_virt_addr().p4_index()))
+    }
+    
+    /// Whether this is a process address space rather than the kernel's
+    pub fn is_user(&self) -> bool {
+        self.user
+    }
+    
     /// Map a virtual page to a physical frame
     pub fn map_page(&mut self, virt_addr: VirtualAddress, phys_frame: PageFrame, protection: MemoryProtection) -> Result<(), MapToError<Size4KiB>> {
         let page: Page<Size4KiB> = Page::containing_address(virt_addr.as_virt_addr());
@@ -271,6 +386,29 @@ impl VirtualAddressSpace {
         &self.regions
     }
     
+    /// Check that `[start, start + len)` is covered by user-accessible regions
+    /// with the required protection
+    pub fn check_user_access(&self, start: VirtualAddress, len: usize, write: bool) -> bool {
+        let end = match start.0.checked_add(len) {
+            Some(end) => end,
+            None => return false,
+        };
+        
+        let mut addr = start.0;
+        while addr < end {
+            let region = match self.find_region(VirtualAddress(addr)) {
+                Some(region) => region,
+                None => return false,
+            };
+            let protection = &region.protection;
+            if !protection.user_accessible || !protection.readable || (write && !protection.writable) {
+                return false;
+            }
+            addr = region.end().0;
+        }
+        true
+    }
+    
     /// Translate virtual address to physical address
     pub fn translate(&self, virt_addr: VirtualAddress) -> Option<PhysAddr> {
         self.mapper.translate_addr(virt_addr.as_virt_addr())
@@ -280,6 +418,135 @@ impl VirtualAddressSpace {
     pub fn is_mapped(&self, virt_addr: VirtualAddress) -> bool {
         self.translate(virt_addr).is_some()
     }
+    
+    /// Level 1 entry for a 4 KiB page, if the tables above it exist
+    fn leaf_entry_mut(&mut self, virt_addr: VirtualAddress) -> Option<&mut PageTableEntry> {
+        let addr = virt_addr.as_virt_addr();
+        let indices: [PageTableIndex; 3] = [addr.p4_index(), addr.p3_index(), addr.p2_index()];
+        let offset = self.physical_memory_offset;
+        
+        let mut table: &mut PageTable = self.mapper.level_4_table();
+        for index in indices {
+            // A missing table or a huge page ends the walk
+            let frame = table[index].frame().ok()?;
+            let next = (offset + frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
+            table = unsafe { &mut *next };
+        }
+        Some(&mut table[addr.p1_index()])
+    }
+    
+    /// Check whether the page was written since the last call, clearing
+    /// its dirty bit
+    pub fn take_dirty(&mut self, virt_addr: VirtualAddress) -> bool {
+        let page = virt_addr.align_down();
+        let entry = match self.leaf_entry_mut(page) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT | PageTableFlags::DIRTY) {
+            return false;
+        }
+        entry.set_flags(flags - PageTableFlags::DIRTY);
+        x86_64::instructions::tlb::flush(page.as_virt_addr());
+        true
+    }
+    
+    /// Write a mapped page to swap space, free its frame and leave a swap
+    /// entry in its place
+    ///
+    /// Only the calling CPU's TLB is flushed.
+    pub fn swap_out_page(&mut self, virt_addr: VirtualAddress) -> Result<PageFrame, SwapError> {
+        let offset = self.physical_memory_offset;
+        let page = virt_addr.align_down();
+        let entry = self.leaf_entry_mut(page).ok_or(SwapError::PageNotMapped)?;
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return Err(SwapError::PageNotMapped);
+        }
+        
+        let frame_addr = entry.addr();
+        let data = unsafe { &*(offset + frame_addr.as_u64()).as_ptr::<[u8; PAGE_SIZE]>() };
+        let swap_entry = write_to_swap(data)?;
+        
+        entry.set_addr(swap_entry_to_address(swap_entry), (flags - PageTableFlags::PRESENT) | SWAPPED);
+        x86_64::instructions::tlb::flush(page.as_virt_addr());
+        
+        let frame = PageFrame::from_address(frame_addr.as_u64() as usize);
+        deallocate_frame(frame);
+        Ok(frame)
+    }
+    
+    /// Bring a swapped-out page back into a fresh frame
+    ///
+    /// Returns the new frame, or `None` if the page was not swapped out and
+    /// the fault is not ours to handle.
+    pub fn swap_in_page(&mut self, virt_addr: VirtualAddress) -> Result<Option<PageFrame>, SwapError> {
+        let offset = self.physical_memory_offset;
+        let page = virt_addr.align_down();
+        let entry = match self.leaf_entry_mut(page) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let flags = entry.flags();
+        if flags.contains(PageTableFlags::PRESENT) || !flags.contains(SWAPPED) {
+            return Ok(None);
+        }
+        
+        let frame = allocate_frame().ok_or(SwapError::OutOfMemory)?;
+        let data = unsafe { &mut *(offset + frame.address() as u64).as_mut_ptr::<[u8; PAGE_SIZE]>() };
+        if let Err(err) = read_from_swap(swap_entry_from_address(entry.addr()), data) {
+            deallocate_frame(frame);
+            return Err(err);
+        }
+        
+        entry.set_addr(PhysAddr::new(frame.address() as u64), (flags - SWAPPED) | PageTableFlags::PRESENT);
+        x86_64::instructions::tlb::flush(page.as_virt_addr());
+        Ok(Some(frame))
+    }
+}
+
+impl Drop for VirtualAddressSpace {
+    /// Free the page tables of a process address space
+    ///
+    /// Leaf frames are left alone: they belong to whoever mapped them, such
+    /// as mmap or shared memory, and are released there.
+    fn drop(&mut self) {
+        if !self.user {
+            return;
+        }
+        
+        // The calling CPU must not be left running on freed tables
+        if Cr3::read().0 == self.root {
+            activate_address_space(None);
+        }
+        
+        let offset = self.physical_memory_offset;
+        let kernel_slots = self.kernel_slots;
+        let table = self.mapper.level_4_table();
+        for index in 0..LEVEL_4_ENTRIES {
+            if !slot_contains(&kernel_slots, index) {
+                unsafe { free_page_table(offset, &table[index], 3) };
+            }
+        }
+        free_page_table(PageFrame::from_address(self.root.start_address().as_u64() as usize));
+    }
+}
+
+/// Free the level `level` table `entry` points at and the tables below it
+unsafe fn free_page_table(offset: VirtAddr, entry: &PageTableEntry, level: u8) {
+    // Unused entries and huge pages have no table below them
+    let frame = match entry.frame() {
+        Ok(frame) => frame,
+        Err(_) => return,
+    };
+    if level > 1 {
+        let table = &*(offset + frame.start_address().as_u64()).as_ptr::<PageTable>();
+        for entry in table.iter() {
+            free_page_table(offset, entry, level - 1);
+        }
+    }
+    free_page_table(PageFrame::from_address(frame.start_address().as_u64() as usize));
 }
 
 /// Kernel virtual memory layout constants
@@ -304,6 +571,12 @@ pub mod kernel_layout {
     /// Kernel heap size (64MB)
     pub const KERNEL_HEAP_SIZE: usize = 64 * 1024 * 1024;
     
+    /// Guarded kernel stacks start address
+    pub const KERNEL_STACKS_START: VirtualAddress = VirtualAddress(0xFFFFFFFF90000000);
+    
+    /// Guarded kernel stacks size (256MB)
+    pub const KERNEL_STACKS_SIZE: usize = 256 * 1024 * 1024;
+    
     /// Physical memory mapping start (for higher half kernel)
     pub const PHYSICAL_MEMORY_OFFSET: VirtualAddress = VirtualAddress(0xFFFF800000000000);
 }
@@ -311,9 +584,15 @@ pub mod kernel_layout {
 /// Global virtual memory manager
 static VIRTUAL_MEMORY_MANAGER: Mutex<Option<VirtualAddressSpace>> = Mutex::new(None);
 
+/// Physical address of the kernel's level 4 table, loaded whenever no
+/// process runs; zero before virtual memory is initialized
+///
+/// Kept outside the manager lock so context switches never take it.
+static KERNEL_PAGE_TABLE: AtomicU64 = AtomicU64::new(0);
+
 /// Initialize virtual memory management
 pub unsafe fn init_virtual_memory() -> Result<(), &'static str> {
-    serial_println!("Initializing virtual memory management...");
+    log::info!("Initializing virtual memory management...");
     
     // Get the current level 4 page table
     let level_4_table = get_level_4_page_table();
@@ -325,10 +604,14 @@ pub unsafe fn init_virtual_memory() -> Result<(), &'static str> {
     // Set up kernel virtual memory regions
     setup_kernel_memory_layout(&mut vas)?;
     
+    // Every process address space shares these slots with the kernel
+    share_kernel_slots(&mut vas)?;
+    KERNEL_PAGE_TABLE.store(vas.root.start_address().as_u64(), Ordering::SeqCst);
+    
     // Store the virtual address space globally
     *VIRTUAL_MEMORY_MANAGER.lock() = Some(vas);
     
-    serial_println!("Virtual memory management initialized successfully");
+    log::info!("Virtual memory management initialized successfully");
     Ok(())
 }
 
@@ -344,9 +627,31 @@ unsafe fn get_level_4_page_table() -> &'static mut PageTable {
     &mut *page_table_ptr
 }
 
+/// Mark the level 4 slots the boot page tables use, and the whole higher
+/// half, as kernel slots
+///
+/// Empty higher half slots get a level 3 table up front. Process address
+/// spaces copy the kernel's level 4 entries only once, so a kernel mapping
+/// in a slot created later would otherwise never reach them.
+fn share_kernel_slots(vas: &mut VirtualAddressSpace) -> Result<(), &'static str> {
+    let mut slots = [0; LEVEL_4_ENTRIES / 64];
+    let table = vas.mapper.level_4_table();
+    for index in 0..LEVEL_4_ENTRIES {
+        if index >= HIGHER_HALF_SLOT && table[index].is_unused() {
+            let frame = allocate_page_table().ok_or("Out of memory for kernel page tables")?;
+            table[index].set_addr(PhysAddr::new(frame.address() as u64), PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+        }
+        if !table[index].is_unused() {
+            slot_insert(&mut slots, index);
+        }
+    }
+    vas.kernel_slots = slots;
+    Ok(())
+}
+
 /// Set up kernel virtual memory layout
 fn setup_kernel_memory_layout(vas: &mut VirtualAddressSpace) -> Result<(), &'static str> {
-    serial_println!("Setting up kernel virtual memory layout...");
+    log::debug!("Setting up kernel virtual memory layout...");
     
     // Add kernel code region
     let kernel_code_region = VirtualMemoryRegion::new(
@@ -375,7 +680,16 @@ fn setup_kernel_memory_layout(vas: &mut VirtualAddressSpace) -> Result<(), &'sta
     );
     vas.add_region(kernel_heap_region);
     
-    serial_println!("Kernel virtual memory layout configured");
+    // Add guarded kernel stacks region
+    let kernel_stacks_region = VirtualMemoryRegion::new(
+        kernel_layout::KERNEL_STACKS_START,
+        kernel_layout::KERNEL_STACKS_SIZE,
+        MemoryProtection::read_write(),
+        "Kernel Stacks"
+    );
+    vas.add_region(kernel_stacks_region);
+    
+    log::debug!("Kernel virtual memory layout configured");
     print_memory_layout(vas);
     
     Ok(())
@@ -383,10 +697,10 @@ fn setup_kernel_memory_layout(vas: &mut VirtualAddressSpace) -> Result<(), &'sta
 
 /// Print the current virtual memory layout
 pub fn print_memory_layout(vas: &VirtualAddressSpace) {
-    serial_println!("Virtual Memory Layout:");
+    log::debug!("Virtual Memory Layout:");
     
     for region in vas.regions() {
-        serial_println!(
+        log::debug!(
             "  {}: 0x{:016x} - 0x{:016x} ({} KB) [{}{}{}{}]",
             region.name,
             region.start.0,
@@ -399,7 +713,7 @@ pub fn print_memory_layout(vas: &VirtualAddressSpace) {
         );
     }
     
-    println!("Virtual memory layout configured with {} regions", vas.regions().len());
+    log::info!("Virtual memory layout configured with {} regions", vas.regions().len());
 }
 
 /// Map a virtual address to a physical address with protection
@@ -444,6 +758,60 @@ pub fn translate_virtual_address(virt_addr: VirtualAddress) -> Option<usize> {
     vas.translate(virt_addr).map(|phys_addr| phys_addr.as_u64() as usize)
 }
 
+/// One entry met on a page-table walk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalkEntry {
+    /// 4 for the level 4 table down to 1 for the last
+    pub level: u8,
+    pub index: usize,
+    pub flags: PageTableFlags,
+    /// Physical address the entry points at
+    pub address: u64,
+}
+
+/// The entries met translating one address, level 4 first
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PageWalk {
+    pub entries: [Option<WalkEntry>; 4],
+    /// Physical address the virtual one maps to, if it is mapped
+    pub physical: Option<u64>,
+}
+
+/// Walk the page tables the calling CPU has loaded for `virt_addr`
+///
+/// The tables are read directly rather than through the manager, so the
+/// walk takes no lock and is safe from a panic or the debug monitor.
+pub fn walk_active(virt_addr: VirtualAddress) -> PageWalk {
+    let mut walk = PageWalk::default();
+    let Ok(addr) = VirtAddr::try_new(virt_addr.0 as u64) else {
+        return walk;
+    };
+    let indices = [addr.p4_index(), addr.p3_index(), addr.p2_index(), addr.p1_index()];
+    let mut table_address = Cr3::read().0.start_address().as_u64();
+    for (step, index) in indices.into_iter().enumerate() {
+        let table = unsafe { &*((kernel_layout::PHYSICAL_MEMORY_OFFSET.0 as u64 + table_address) as *const PageTable) };
+        let entry = &table[index];
+        let level = 4 - step as u8;
+        walk.entries[step] = Some(WalkEntry {
+            level,
+            index: usize::from(index),
+            flags: entry.flags(),
+            address: entry.addr().as_u64(),
+        });
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            break;
+        }
+        // A huge page at level 3 or 2 ends the walk early
+        if level == 1 || entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            let page_mask = (1u64 << (12 + 9 * (level as u64 - 1))) - 1;
+            walk.physical = Some(entry.addr().as_u64() + (addr.as_u64() & page_mask));
+            break;
+        }
+        table_address = entry.addr().as_u64();
+    }
+    walk
+}
+
 /// Check if a virtual address is mapped
 pub fn is_virtual_address_mapped(virt_addr: VirtualAddress) -> bool {
     let manager = VIRTUAL_MEMORY_MANAGER.lock();
@@ -454,6 +822,132 @@ pub fn is_virtual_address_mapped(virt_addr: VirtualAddress) -> bool {
     }
 }
 
+/// Check and clear the dirty bit of the page containing `virt_addr`
+pub fn take_page_dirty(virt_addr: VirtualAddress) -> bool {
+    let mut manager = VIRTUAL_MEMORY_MANAGER.lock();
+    manager.as_mut().map_or(false, |vas| vas.take_dirty(virt_addr))
+}
+
+/// Create an address space for a new process
+pub fn create_user_address_space() -> Result<VirtualAddressSpace, &'static str> {
+    let mut manager = VIRTUAL_MEMORY_MANAGER.lock();
+    let kernel = manager.as_mut().ok_or("Virtual memory manager not initialized")?;
+    VirtualAddressSpace::new_user(kernel)
+}
+
+/// Switch the calling CPU to the page tables of `space`, or to the
+/// kernel's for `None`
+pub fn activate_address_space(space: Option<&VirtualAddressSpace>) {
+    let root = match space {
+        Some(space) => space.root,
+        None => match KERNEL_PAGE_TABLE.load(Ordering::SeqCst) {
+            0 => return,
+            address => PhysFrame::containing_address(PhysAddr::new(address)),
+        },
+    };
+    let (active, flags) = Cr3::read();
+    if active != root {
+        unsafe { Cr3::write(root, flags) };
+    }
+}
+
+/// Run `f` with the address space process `pid` runs in
+///
+/// Processes without an address space of their own, and callers that are
+/// not processes at all, use the kernel's.
+fn with_process_space<R>(pid: ProcessId, f: impl FnOnce(&mut VirtualAddressSpace) -> R) -> Result<R, &'static str> {
+    let mut f = Some(f);
+    let own = crate::process::with_address_space_mut(pid, |space| {
+        space.map(|space| f.take().expect("address space callback already run")(space))
+    });
+    if let Ok(Some(result)) = own {
+        return Ok(result);
+    }
+    
+    let mut manager = VIRTUAL_MEMORY_MANAGER.lock();
+    let vas = manager.as_mut().ok_or("Virtual memory manager not initialized")?;
+    Ok(f.take().expect("address space callback already run")(vas))
+}
+
+/// Map a physical range into the address space of process `pid`
+///
+/// Addresses in the kernel's slots are refused for processes with their
+/// own address space: a mapping there would show up in every process.
+pub fn map_user_range(pid: ProcessId, virt_start: VirtualAddress, phys_start: usize, size: usize, protection: MemoryProtection) -> Result<(), &'static str> {
+    with_process_space(pid, |vas| {
+        let last = VirtualAddress(virt_start.0 + size.max(1) - 1);
+        if vas.is_user() && (vas.is_kernel_address(virt_start) || vas.is_kernel_address(last)) {
+            return Err("Address reserved for the kernel");
+        }
+        vas.map_range(virt_start, phys_start, size, protection)
+            .map_err(|_| "Failed to map virtual range")
+    })?
+}
+
+/// Map a page into the address space of process `pid`
+pub fn map_user_page(pid: ProcessId, virt_addr: VirtualAddress, phys_addr: usize, protection: MemoryProtection) -> Result<(), &'static str> {
+    map_user_range(pid, virt_addr, phys_addr, PAGE_SIZE, protection)
+}
+
+/// Unmap a page from the address space of process `pid`
+pub fn unmap_user_page(pid: ProcessId, virt_addr: VirtualAddress) -> Result<(), &'static str> {
+    with_process_space(pid, |vas| {
+        vas.unmap_page(virt_addr).map_err(|_| "Failed to unmap virtual page")
+    })?
+}
+
+/// Check whether `virt_addr` is mapped in the address space of process
+/// `pid`
+pub fn is_user_address_mapped(pid: ProcessId, virt_addr: VirtualAddress) -> bool {
+    with_process_space(pid, |vas| vas.is_mapped(virt_addr)).unwrap_or(false)
+}
+
+/// Check and clear the dirty bit of a page in the address space of
+/// process `pid`
+pub fn take_user_page_dirty(pid: ProcessId, virt_addr: VirtualAddress) -> bool {
+    with_process_space(pid, |vas| vas.take_dirty(virt_addr)).unwrap_or(false)
+}
+
+/// Swap out the page containing `virt_addr`, returning the frame it
+/// occupied
+pub fn swap_out_virtual_page(virt_addr: VirtualAddress) -> Result<PageFrame, SwapError> {
+    let mut manager = VIRTUAL_MEMORY_MANAGER.lock();
+    let vas = manager.as_mut().ok_or(SwapError::DeviceUnavailable)?;
+    vas.swap_out_page(virt_addr)
+}
+
+/// Resolve a not-present page fault at `fault_addr` by swapping the page
+/// back in
+///
+/// Only the kernel address space is swapped, so a process address space
+/// never holds a swapped page.
+///
+/// Returns true if the faulting access can be retried, false if the
+/// address was never swapped out or the page could not be brought back.
+pub fn handle_page_fault(fault_addr: VirtualAddress) -> bool {
+    let swapped_in = {
+        let mut manager = VIRTUAL_MEMORY_MANAGER.lock();
+        match manager.as_mut() {
+            Some(vas) => vas.swap_in_page(fault_addr),
+            None => return false,
+        }
+    };
+    
+    match swapped_in {
+        Ok(Some(frame)) => {
+            // The replacement algorithm locks above the address space, so
+            // it is told only after the address space lock is released
+            let _ = crate::memory::swap_algorithm::handle_page_fault(fault_addr.align_down(), frame);
+            true
+        }
+        Ok(None) => false,
+        Err(err) => {
+            log::warn!("Failed to swap in page at 0x{:x}: {:?}", fault_addr.0, err);
+            false
+        }
+    }
+}
+
 /// Get virtual memory statistics
 pub fn print_virtual_memory_stats() {
     let manager = VIRTUAL_MEMORY_MANAGER.lock();
@@ -461,10 +955,9 @@ pub fn print_virtual_memory_stats() {
         print_memory_layout(vas);
         
         let total_virtual_size: usize = vas.regions().iter().map(|r| r.size).sum();
-        serial_println!("Total virtual address space: {} MB", total_virtual_size / (1024 * 1024));
-        println!("Virtual memory: {} MB address space configured", total_virtual_size / (1024 * 1024));
+        log::debug!("Total virtual address space: {} MB", total_virtual_size / (1024 * 1024));
     } else {
-        serial_println!("Virtual memory manager not initialized");
+        log::info!("Virtual memory manager not initialized");
     }
 }
 
@@ -472,6 +965,20 @@ pub fn print_virtual_memory_stats() {
 mod tests {
     use super::*;
     
+    #[test_case]
+    fn test_walk_active() {
+        let value = 0x1234u64;
+        let address = &value as *const u64 as usize;
+        let walk = walk_active(VirtualAddress::new(address));
+        let physical = walk.physical.expect("the stack is mapped");
+        assert_eq!(physical & 0xFFF, address as u64 & 0xFFF);
+        assert_eq!(walk.entries[0].map(|entry| entry.level), Some(4));
+
+        // Non-canonical addresses have no walk at all
+        let walk = walk_active(VirtualAddress::new(0x0000_8000_0000_0000));
+        assert!(walk.entries[0].is_none() && walk.physical.is_none());
+    }
+    
     #[test_case]
     fn test_virtual_address_creation() {
         let addr = VirtualAddress::new(0x1000);
@@ -537,6 +1044,50 @@ mod tests {
         assert_eq!(region.page_count(), 2);
     }
     
+    #[test_case]
+    fn test_swap_entry_encoding() {
+        let entry = SwapEntry { device_index: 3, slot: SwapSlot::new(0x1234_5678) };
+        let address = swap_entry_to_address(entry);
+        assert_eq!(address.as_u64() & 0xFFF, 0);
+        assert_eq!(swap_entry_from_address(address), entry);
+    }
+    
+    #[test_case]
+    fn test_slot_set() {
+        let mut slots = [0; LEVEL_4_ENTRIES / 64];
+        slot_insert(&mut slots, 0);
+        slot_insert(&mut slots, 65);
+        slot_insert(&mut slots, 511);
+        assert!(slot_contains(&slots, 0));
+        assert!(slot_contains(&slots, 65));
+        assert!(slot_contains(&slots, 511));
+        assert!(!slot_contains(&slots, 1));
+        assert!(!slot_contains(&slots, 64));
+    }
+    
+    #[test_case]
+    fn test_user_address_space_isolation() {
+        let mut space = create_user_address_space().expect("address space");
+        assert!(space.is_user());
+        
+        // Kernel code and the higher half are shared
+        let kernel_code = VirtualAddress::new(test_slot_set as usize);
+        assert!(space.is_kernel_address(kernel_code));
+        assert!(space.is_mapped(kernel_code));
+        assert!(space.is_kernel_address(kernel_layout::KERNEL_HEAP_START));
+        
+        // A process mapping stays out of the kernel's page tables
+        let address = VirtualAddress::new(0x0000_7000_0000_0000);
+        assert!(!space.is_kernel_address(address));
+        let frame = allocate_frame().expect("frame");
+        space.map_page(address, frame, MemoryProtection::user_read_write()).expect("map");
+        assert!(space.is_mapped(address));
+        assert!(!is_virtual_address_mapped(address));
+        
+        space.unmap_page(address).expect("unmap");
+        deallocate_frame(frame);
+    }
+    
     #[test_case]
     fn test_kernel_layout_constants() {
         // Verify kernel layout constants are properly defined
@@ -0,0 +1,387 @@
+//! Live statistics dashboard
+//!
+//! A full-screen health view that takes over the VGA display: memory use,
+//! per-CPU load, IPC rates, IRQ counts and the busiest processes, refreshed
+//! once per second. Meant for developers on real hardware without a serial
+//! cable. F12 toggles it, and the `stats` boot parameter shows it from boot.
+//!
+//! Sampling and drawing happen on the scheduler's timer tick, which only
+//! runs when the interrupted code holds no kernel locks. The keyboard
+//! interrupt merely records the toggle request.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Mutex;
+use crate::process::{ProcessId, ProcessState};
+use crate::smp::MAX_CPUS;
+use crate::vga_buffer::{Color, Writer, BUFFER_HEIGHT, BUFFER_WIDTH, WRITER};
+
+/// Scancode set 1 make code of F12
+pub const HOTKEY_SCANCODE: u8 = 0x58;
+
+/// Time between refreshes
+pub const REFRESH_INTERVAL_MS: u64 = 1000;
+
+/// Processes listed on the dashboard
+const TOP_PROCESSES: usize = 8;
+
+/// Width of the label column
+const LABEL_WIDTH: usize = 10;
+
+/// Whether the dashboard should be on screen
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Set by the hotkey, consumed at the next timer tick
+static TOGGLE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+static MONITOR: Mutex<Monitor> = Mutex::new(Monitor::new());
+
+/// Copy of the boot CPU's uptime, readable without the monitor lock so
+/// code running under it, such as the logger, can timestamp
+static UPTIME_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Show the dashboard from the next timer tick
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Time since the boot CPU's first timer tick
+pub fn uptime_ms() -> u64 {
+    UPTIME_MS.load(Ordering::Relaxed)
+}
+
+/// Handle a scancode seen by the keyboard interrupt
+///
+/// Safe from interrupt context: only flags the request.
+pub fn on_scancode(scancode: u8) {
+    if scancode == HOTKEY_SCANCODE {
+        TOGGLE_REQUESTED.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Account a timer tick on `cpu` and refresh the dashboard when due
+///
+/// `busy` tells whether the tick was charged to a process rather than the
+/// idle loop. Every CPU counts its load; the boot CPU keeps time and draws.
+pub fn timer_tick(cpu: usize, elapsed_ms: u64, busy: bool) {
+    let mut monitor = MONITOR.lock();
+    if cpu != 0 {
+        monitor.record_tick(cpu, 0, busy);
+        return;
+    }
+    monitor.record_tick(cpu, elapsed_ms, busy);
+    UPTIME_MS.store(monitor.uptime_ms, Ordering::Relaxed);
+
+    if TOGGLE_REQUESTED.swap(false, Ordering::SeqCst) {
+        ENABLED.fetch_xor(true, Ordering::SeqCst);
+    }
+
+    let enabled = ENABLED.load(Ordering::SeqCst);
+    if enabled == monitor.shown && (!enabled || monitor.since_refresh_ms < REFRESH_INTERVAL_MS) {
+        return;
+    }
+
+    // The interrupted boot code may be printing; retry on a later tick
+    let Some(mut writer) = WRITER.try_lock() else {
+        return;
+    };
+    if !enabled {
+        writer.show_console();
+        monitor.shown = false;
+        return;
+    }
+    if !monitor.shown {
+        writer.hide_console();
+        monitor.shown = true;
+    }
+    let snapshot = monitor.sample();
+    draw(&mut writer, &render(&snapshot));
+}
+
+/// Timer ticks seen by one CPU since the last refresh
+#[derive(Debug, Clone, Copy)]
+struct CpuLoad {
+    busy_ticks: u64,
+    total_ticks: u64,
+}
+
+impl CpuLoad {
+    const IDLE: Self = Self { busy_ticks: 0, total_ticks: 0 };
+
+    /// Busy share in percent, if the CPU took any tick
+    fn percent(&self) -> Option<u64> {
+        (self.total_ticks > 0).then(|| self.busy_ticks * 100 / self.total_ticks)
+    }
+}
+
+/// Running state of the dashboard between refreshes
+struct Monitor {
+    cpus: [CpuLoad; MAX_CPUS],
+    uptime_ms: u64,
+    since_refresh_ms: u64,
+    /// Counters at the last refresh, to turn totals into rates
+    last_sent: u64,
+    last_received: u64,
+    last_irqs: BTreeMap<usize, u64>,
+    last_cpu_time: BTreeMap<ProcessId, u64>,
+    /// Whether the dashboard currently owns the display
+    shown: bool,
+}
+
+impl Monitor {
+    const fn new() -> Self {
+        Self {
+            cpus: [CpuLoad::IDLE; MAX_CPUS],
+            uptime_ms: 0,
+            since_refresh_ms: 0,
+            last_sent: 0,
+            last_received: 0,
+            last_irqs: BTreeMap::new(),
+            last_cpu_time: BTreeMap::new(),
+            shown: false,
+        }
+    }
+
+    fn record_tick(&mut self, cpu: usize, elapsed_ms: u64, busy: bool) {
+        if let Some(load) = self.cpus.get_mut(cpu) {
+            load.total_ticks += 1;
+            if busy {
+                load.busy_ticks += 1;
+            }
+        }
+        self.uptime_ms += elapsed_ms;
+        self.since_refresh_ms += elapsed_ms;
+    }
+
+    /// Collect the statistics since the last refresh and start a new window
+    fn sample(&mut self) -> Snapshot {
+        let interval_ms = core::mem::take(&mut self.since_refresh_ms);
+
+        let cpu_load = self.cpus.iter()
+            .enumerate()
+            .filter_map(|(cpu, load)| load.percent().map(|percent| (cpu, percent)))
+            .collect();
+        self.cpus = [CpuLoad::IDLE; MAX_CPUS];
+
+        let (physical_used_mb, physical_total_mb) = crate::memory::physical::memory_stats()
+            .map_or((0, 0), |stats| (stats.used_memory_mb(), stats.total_memory_mb()));
+        let heap = crate::memory::heap::heap_stats();
+
+        let ipc = crate::ipc::get_ipc_statistics();
+        let messages_sent = rate(ipc.total_messages_sent, self.last_sent, interval_ms);
+        let messages_received = rate(ipc.total_messages_received, self.last_received, interval_ms);
+        self.last_sent = ipc.total_messages_sent;
+        self.last_received = ipc.total_messages_received;
+
+        let irq_counts = crate::platform::irq_counts();
+        let irqs = irq_counts.iter()
+            .map(|&(line, total)| {
+                let before = self.last_irqs.get(&line).copied().unwrap_or(0);
+                IrqLine { line, total, per_second: rate(total, before, interval_ms) }
+            })
+            .collect();
+        self.last_irqs = irq_counts.into_iter().collect();
+
+        let processes = crate::process::list_processes();
+        let process_count = processes.len();
+        let mut top: Vec<ProcessLine> = processes.iter()
+            .map(|process| {
+                let before = self.last_cpu_time.get(&process.pid).copied().unwrap_or(0);
+                ProcessLine {
+                    pid: process.pid,
+                    name: process.name.clone(),
+                    state: process.state,
+                    cpu_percent: rate(process.cpu_time_ms, before, interval_ms) / 10,
+                    cpu_time_ms: process.cpu_time_ms,
+                }
+            })
+            .collect();
+        top.sort_by(|a, b| b.cpu_percent.cmp(&a.cpu_percent).then(b.cpu_time_ms.cmp(&a.cpu_time_ms)));
+        top.truncate(TOP_PROCESSES);
+        self.last_cpu_time = processes.iter().map(|process| (process.pid, process.cpu_time_ms)).collect();
+
+        Snapshot {
+            uptime_ms: self.uptime_ms,
+            cpu_load,
+            physical_used_mb,
+            physical_total_mb,
+            heap_used_kb: heap.current_bytes / 1024,
+            heap_peak_kb: heap.peak_bytes / 1024,
+            heap_size_kb: heap.heap_size / 1024,
+            messages_sent,
+            messages_received,
+            active_queues: ipc.active_message_queues,
+            irqs,
+            process_count,
+            top,
+        }
+    }
+}
+
+/// Per-second rate of a counter over `interval_ms`
+///
+/// Zero over an empty interval, such as a frame drawn right at boot.
+fn rate(now: u64, before: u64, interval_ms: u64) -> u64 {
+    if interval_ms == 0 {
+        return 0;
+    }
+    now.saturating_sub(before) * 1000 / interval_ms
+}
+
+struct IrqLine {
+    line: usize,
+    total: u64,
+    per_second: u64,
+}
+
+struct ProcessLine {
+    pid: ProcessId,
+    name: String,
+    state: ProcessState,
+    cpu_percent: u64,
+    cpu_time_ms: u64,
+}
+
+/// Everything shown by one frame
+struct Snapshot {
+    uptime_ms: u64,
+    cpu_load: Vec<(usize, u64)>,
+    physical_used_mb: usize,
+    physical_total_mb: usize,
+    heap_used_kb: usize,
+    heap_peak_kb: usize,
+    heap_size_kb: usize,
+    messages_sent: u64,
+    messages_received: u64,
+    active_queues: usize,
+    irqs: Vec<IrqLine>,
+    process_count: usize,
+    top: Vec<ProcessLine>,
+}
+
+/// Lay out a frame, title first; the footer is drawn separately
+fn render(snapshot: &Snapshot) -> Vec<String> {
+    let seconds = snapshot.uptime_ms / 1000;
+    let mut lines = Vec::new();
+    lines.push(format!("Kosh live statistics{:>width$}", format!("up {:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60), width = BUFFER_WIDTH - 20));
+    lines.push(String::new());
+
+    lines.push(format!("{:<LABEL_WIDTH$}physical {} / {} MiB used", "Memory", snapshot.physical_used_mb, snapshot.physical_total_mb));
+    lines.push(format!("{:<LABEL_WIDTH$}heap {} / {} KiB used, peak {} KiB", "", snapshot.heap_used_kb, snapshot.heap_size_kb, snapshot.heap_peak_kb));
+
+    let cpus: Vec<String> = snapshot.cpu_load.iter().map(|(cpu, percent)| format!("cpu{} {:>3}%", cpu, percent)).collect();
+    lines.extend(wrap("CPU load", &cpus));
+
+    lines.push(format!("{:<LABEL_WIDTH$}sent {}/s, received {}/s, {} queues", "IPC", snapshot.messages_sent, snapshot.messages_received, snapshot.active_queues));
+
+    let irqs: Vec<String> = snapshot.irqs.iter().map(|irq| format!("{}: {} ({}/s)", irq.line, irq.total, irq.per_second)).collect();
+    lines.extend(wrap("IRQs", &irqs));
+
+    lines.push(String::new());
+    lines.push(format!("Processes ({})", snapshot.process_count));
+    lines.push(format!("{:>5}  {:<24} {:<10} {:>5} {:>12}", "PID", "NAME", "STATE", "CPU", "TOTAL MS"));
+    for process in &snapshot.top {
+        let name: String = process.name.chars().take(24).collect();
+        lines.push(format!("{:>5}  {:<24} {:<10} {:>4}% {:>12}", process.pid.0, name, format!("{:?}", process.state), process.cpu_percent, process.cpu_time_ms));
+    }
+    lines
+}
+
+/// Lay out `items` after `label`, continuing on indented lines as needed
+fn wrap(label: &str, items: &[String]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = format!("{:<LABEL_WIDTH$}", label);
+    for item in items {
+        if line.len() > LABEL_WIDTH && line.len() + 2 + item.len() > BUFFER_WIDTH {
+            lines.push(core::mem::replace(&mut line, format!("{:<LABEL_WIDTH$}", "")));
+        }
+        if line.len() > LABEL_WIDTH {
+            line.push_str("  ");
+        }
+        line.push_str(item);
+    }
+    if items.is_empty() {
+        line.push_str("none");
+    }
+    lines.push(line);
+    lines
+}
+
+fn draw(writer: &mut Writer, lines: &[String]) {
+    let footer = BUFFER_HEIGHT - 1;
+    for row in 0..footer {
+        let text = lines.get(row).map_or("", String::as_str);
+        match row {
+            0 => writer.draw_line(row, text, Color::White, Color::Blue),
+            _ => writer.draw_line(row, text, Color::LightGray, Color::Black),
+        }
+    }
+    writer.draw_line(footer, "F12: back to console", Color::Black, Color::LightGray);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            uptime_ms: 3_723_000,
+            cpu_load: alloc::vec![(0, 37)],
+            physical_used_mb: 12,
+            physical_total_mb: 512,
+            heap_used_kb: 300,
+            heap_peak_kb: 420,
+            heap_size_kb: 1024,
+            messages_sent: 120,
+            messages_received: 118,
+            active_queues: 5,
+            irqs: (0..16).map(|line| IrqLine { line, total: 1_000_000, per_second: 100 }).collect(),
+            process_count: 1,
+            top: alloc::vec![ProcessLine {
+                pid: ProcessId::new(7),
+                name: "a-process-name-longer-than-the-column".to_string(),
+                state: ProcessState::Running,
+                cpu_percent: 37,
+                cpu_time_ms: 4500,
+            }],
+        }
+    }
+
+    #[test_case]
+    fn test_rate() {
+        assert_eq!(rate(1500, 500, 1000), 1000);
+        assert_eq!(rate(150, 100, 500), 100);
+        assert_eq!(rate(100, 100, 0), 0);
+        // Counters never go backwards, but a restarted process can
+        assert_eq!(rate(10, 100, 1000), 0);
+    }
+
+    #[test_case]
+    fn test_cpu_load_window() {
+        let mut monitor = Monitor::new();
+        monitor.record_tick(0, 10, true);
+        monitor.record_tick(0, 10, false);
+        monitor.record_tick(0, 10, false);
+        monitor.record_tick(0, 10, true);
+        monitor.record_tick(MAX_CPUS, 10, true);
+
+        assert_eq!(monitor.cpus[0].percent(), Some(50));
+        assert_eq!(monitor.cpus[1].percent(), None);
+        assert_eq!(monitor.since_refresh_ms, 50);
+    }
+
+    #[test_case]
+    fn test_render_fits_screen() {
+        let lines = render(&snapshot());
+
+        assert!(lines.len() < BUFFER_HEIGHT);
+        assert!(lines.iter().all(|line| line.len() <= BUFFER_WIDTH));
+        assert!(lines[0].ends_with("up 01:02:03"));
+        // Sixteen IRQ lines do not fit on one row
+        assert!(lines.iter().filter(|line| line.contains("/s)")).count() > 1);
+    }
+}
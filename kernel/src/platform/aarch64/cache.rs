@@ -1,15 +1,68 @@
-//! ARM64 cache operations implementation (stub)
+//! ARM64 cache operations implementation
 
+#[cfg(target_arch = "aarch64")]
+use core::arch::asm;
 use super::super::traits::CacheOperations;
 use super::super::{VirtualAddress, PlatformResult};
 
-/// ARM64 cache operations implementation (stub)
+/// Data cache maintenance operation applied by virtual address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DcacheOp {
+    /// DC CVAC - clean to Point of Coherency
+    Clean,
+    /// DC IVAC - invalidate to Point of Coherency
+    Invalidate,
+    /// DC CIVAC - clean and invalidate to Point of Coherency
+    CleanInvalidate,
+}
+
+/// ARM64 cache operations implementation
 pub struct AArch64CacheOperations;
 
 impl AArch64CacheOperations {
     pub fn new() -> Self {
         Self
     }
+    
+    /// Apply a cache maintenance operation to every line covering the range
+    ///
+    /// An invalidate would also throw away whatever shares the first and
+    /// last line with the range, so lines only partly inside it are cleaned
+    /// and invalidated instead.
+    fn dcache_range_op(&self, start: VirtualAddress, size: usize, op: DcacheOp) -> PlatformResult<()> {
+        if size == 0 {
+            return Ok(());
+        }
+        
+        let line_size = self.dcache_line_size() as u64;
+        let mut addr = start.as_u64() & !(line_size - 1);
+        let end = start.as_u64() + size as u64;
+        
+        while addr < end {
+            let partial = addr < start.as_u64() || addr + line_size > end;
+            let line_op = if op == DcacheOp::Invalidate && partial { DcacheOp::CleanInvalidate } else { op };
+            #[cfg(target_arch = "aarch64")]
+            unsafe {
+                match line_op {
+                    DcacheOp::Clean => asm!("dc cvac, {}", in(reg) addr),
+                    DcacheOp::Invalidate => asm!("dc ivac, {}", in(reg) addr),
+                    DcacheOp::CleanInvalidate => asm!("dc civac, {}", in(reg) addr),
+                }
+            }
+            #[cfg(not(target_arch = "aarch64"))]
+            let _ = line_op;
+            
+            addr += line_size;
+        }
+        
+        // Wait for the maintenance to complete before any DMA is started
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            asm!("dsb sy");
+        }
+        
+        Ok(())
+    }
 }
 
 impl CacheOperations for AArch64CacheOperations {
@@ -38,13 +91,37 @@ impl CacheOperations for AArch64CacheOperations {
         Ok(())
     }
     
+    /// Smallest data cache line size, read from CTR_EL0.DminLine
+    fn dcache_line_size(&self) -> usize {
+        #[cfg(target_arch = "aarch64")]
+        {
+            let ctr: u64;
+            unsafe {
+                asm!("mrs {}, ctr_el0", out(reg) ctr);
+            }
+            4 << ((ctr >> 16) & 0xF) as usize
+        }
+        
+        #[cfg(not(target_arch = "aarch64"))]
+        {
+            64
+        }
+    }
+    
     fn clean_invalidate_dcache_range(&self, start: VirtualAddress, size: usize) -> PlatformResult<()> {
-        // ARM64 would use DC CIVAC (Clean and Invalidate by VA to PoC)
-        Ok(())
+        self.dcache_range_op(start, size, DcacheOp::CleanInvalidate)
     }
     
     fn invalidate_dcache_range(&self, start: VirtualAddress, size: usize) -> PlatformResult<()> {
-        // ARM64 would use DC IVAC (Invalidate by VA to PoC)
-        Ok(())
+        self.dcache_range_op(start, size, DcacheOp::Invalidate)
+    }
+    
+    fn clean_dcache_range(&self, start: VirtualAddress, size: usize) -> PlatformResult<()> {
+        self.dcache_range_op(start, size, DcacheOp::Clean)
     }
-}
\ No newline at end of file
+    
+    fn is_dma_coherent(&self) -> bool {
+        // Most ARM SoCs do not snoop CPU caches for device DMA
+        false
+    }
+}
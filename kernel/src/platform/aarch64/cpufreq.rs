@@ -0,0 +1,194 @@
+//! ARM64 CPU performance control through SCMI
+//!
+//! PSCI has no call that changes the CPU frequency, so this goes to the
+//! System Control and Management Interface firmware the device tree
+//! describes: its performance protocol lists the levels of a domain and
+//! sets one. Messages go through the shared memory transport, the channel
+//! signaled with the SMC the `arm,smc-id` property names. The SMC returns
+//! once the firmware has answered, so there is no completion interrupt to
+//! wait for.
+//!
+//! Every CPU is taken to be in performance domain 0. Without an SCMI node
+//! there is no backend, and the governors only track the frequency.
+
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::ptr;
+use spin::Mutex;
+use super::super::{FrequencyControl, PerformanceLevel, PlatformResult, PlatformError};
+
+/// Shared memory area fields
+const SHMEM_CHANNEL_STATUS: usize = 0x04;
+const SHMEM_FLAGS: usize = 0x10;
+const SHMEM_LENGTH: usize = 0x14;
+const SHMEM_HEADER: usize = 0x18;
+const SHMEM_PAYLOAD: usize = 0x1C;
+
+/// Channel status: free for the agent to write, or failed
+const CHANNEL_FREE: u32 = 1 << 0;
+const CHANNEL_ERROR: u32 = 1 << 1;
+
+/// Performance domain management protocol and its messages
+const PROTOCOL_PERFORMANCE: u32 = 0x13;
+const PERFORMANCE_DOMAIN_ATTRIBUTES: u32 = 0x3;
+const PERFORMANCE_DESCRIBE_LEVELS: u32 = 0x4;
+const PERFORMANCE_LEVEL_SET: u32 = 0x7;
+
+/// Status the firmware returns on success
+const SCMI_SUCCESS: i32 = 0;
+
+/// Domain every CPU is taken to belong to
+const CPU_DOMAIN: u32 = 0;
+
+/// Bytes of each level DESCRIBE_LEVELS returns: level, power cost and
+/// attributes
+const LEVEL_ENTRY_SIZE: usize = 12;
+
+/// Most levels kept
+const MAX_LEVELS: usize = 16;
+
+/// Payload words the longest reply read carries
+const MAX_REPLY_WORDS: usize = 2 + MAX_LEVELS * LEVEL_ENTRY_SIZE / 4;
+
+/// The shared memory channel and the SMC that signals it
+struct Channel {
+    shmem: usize,
+    smc_id: u32,
+    token: u32,
+}
+
+static CHANNEL: Mutex<Option<Channel>> = Mutex::new(None);
+
+/// Header of a command: message ID in bits 0-7, protocol in bits 10-17 and
+/// token in bits 18-27
+pub fn message_header(protocol: u32, message: u32, token: u32) -> u32 {
+    (message & 0xFF) | (protocol & 0xFF) << 10 | (token & 0x3FF) << 18
+}
+
+/// Frequency of performance `level`, scaled from the domain's sustained
+/// level and frequency
+pub fn level_frequency_mhz(level: u32, sustained_level: u32, sustained_khz: u32) -> u32 {
+    if sustained_level == 0 {
+        return 0;
+    }
+    (level as u64 * sustained_khz as u64 / sustained_level as u64 / 1000) as u32
+}
+
+fn smc(function: u32) {
+    unsafe {
+        asm!(
+            "smc #0",
+            inout("x0") function as u64 => _,
+            clobber_abi("C"),
+        );
+    }
+}
+
+impl Channel {
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ptr::read_volatile((self.shmem + offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { ptr::write_volatile((self.shmem + offset) as *mut u32, value) }
+    }
+
+    /// Send a performance protocol command and read back its reply,
+    /// status word removed
+    fn call(&mut self, message: u32, arguments: &[u32], reply: &mut [u32]) -> PlatformResult<usize> {
+        if self.read(SHMEM_CHANNEL_STATUS) & CHANNEL_FREE == 0 {
+            return Err(PlatformError::HardwareError);
+        }
+        self.token = self.token.wrapping_add(1);
+        self.write(SHMEM_FLAGS, 0);
+        self.write(SHMEM_LENGTH, 4 + arguments.len() as u32 * 4);
+        self.write(SHMEM_HEADER, message_header(PROTOCOL_PERFORMANCE, message, self.token));
+        for (index, &argument) in arguments.iter().enumerate() {
+            self.write(SHMEM_PAYLOAD + index * 4, argument);
+        }
+        self.write(SHMEM_CHANNEL_STATUS, 0);
+        smc(self.smc_id);
+
+        let status = self.read(SHMEM_CHANNEL_STATUS);
+        if status & CHANNEL_FREE == 0 || status & CHANNEL_ERROR != 0 {
+            return Err(PlatformError::HardwareError);
+        }
+        if self.read(SHMEM_PAYLOAD) as i32 != SCMI_SUCCESS {
+            return Err(PlatformError::UnsupportedOperation);
+        }
+        // The length covers the header and the status word
+        let words = (self.read(SHMEM_LENGTH) as usize / 4).saturating_sub(2).min(reply.len());
+        for (index, word) in reply[..words].iter_mut().enumerate() {
+            *word = self.read(SHMEM_PAYLOAD + 4 + index * 4);
+        }
+        Ok(words)
+    }
+
+    /// Levels of the CPU domain, slowest first as the firmware lists them
+    fn levels(&mut self) -> PlatformResult<Vec<PerformanceLevel>> {
+        let mut reply = [0u32; MAX_REPLY_WORDS];
+        self.call(PERFORMANCE_DOMAIN_ATTRIBUTES, &[CPU_DOMAIN], &mut reply)?;
+        let (sustained_khz, sustained_level) = (reply[2], reply[3]);
+
+        let mut levels = Vec::new();
+        while levels.len() < MAX_LEVELS {
+            let words = self.call(PERFORMANCE_DESCRIBE_LEVELS, &[CPU_DOMAIN, levels.len() as u32], &mut reply)?;
+            // Levels in this reply in bits 0-11, levels still to come in
+            // bits 16-31
+            let returned = (reply[0] & 0xFFF) as usize;
+            let remaining = reply[0] >> 16;
+            let entries = returned.min(words.saturating_sub(1) * 4 / LEVEL_ENTRY_SIZE);
+            for entry in 0..entries {
+                let level = reply[1 + entry * LEVEL_ENTRY_SIZE / 4];
+                if levels.len() < MAX_LEVELS {
+                    levels.push(PerformanceLevel {
+                        frequency_mhz: level_frequency_mhz(level, sustained_level, sustained_khz),
+                        control: level as u64,
+                    });
+                }
+            }
+            if remaining == 0 || entries == 0 {
+                break;
+            }
+        }
+        Ok(levels)
+    }
+}
+
+/// Find SCMI firmware in the device tree and read its levels
+pub fn probe() -> Option<FrequencyControl> {
+    let info = super::DEVICE_TREE.get()?;
+    let mut channel = Channel {
+        shmem: info.scmi_shmem? as usize,
+        smc_id: info.scmi_smc_id?,
+        token: 0,
+    };
+    let levels = channel.levels().ok().filter(|levels| !levels.is_empty())?;
+    *CHANNEL.lock() = Some(channel);
+    Some(FrequencyControl { backend: "scmi", levels })
+}
+
+/// Ask the firmware for `level` in the CPU domain
+pub fn set_level(level: &PerformanceLevel) -> PlatformResult<()> {
+    let mut channel = CHANNEL.lock();
+    let channel = channel.as_mut().ok_or(PlatformError::UnsupportedOperation)?;
+    channel.call(PERFORMANCE_LEVEL_SET, &[CPU_DOMAIN, level.control as u32], &mut [])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_message_header_layout() {
+        assert_eq!(message_header(PROTOCOL_PERFORMANCE, PERFORMANCE_LEVEL_SET, 5), 0x0014_4C07);
+    }
+
+    #[test_case]
+    fn test_levels_scale_from_the_sustained_point() {
+        assert_eq!(level_frequency_mhz(500, 1000, 2_000_000), 1000);
+        assert_eq!(level_frequency_mhz(1000, 1000, 2_000_000), 2000);
+        assert_eq!(level_frequency_mhz(1000, 0, 2_000_000), 0);
+    }
+}
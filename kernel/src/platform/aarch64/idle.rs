@@ -0,0 +1,108 @@
+//! ARM64 CPU idle states
+//!
+//! `wfi` is always offered. Deeper states come from the device tree's
+//! `idle-states` node and are entered with the PSCI CPU_SUSPEND call,
+//! through the HVC conduit secondary CPUs are started with. Only retention
+//! states are used, which return from the call with the CPU's registers
+//! intact: from a power-down state the CPU would restart at an entry point
+//! with nothing for the idle loop to resume, and states that stop the
+//! architected timer would sleep through the scheduler tick.
+
+use alloc::vec::Vec;
+use core::arch::asm;
+use super::super::CpuIdleState;
+use super::super::fdt::IdleStateNode;
+
+/// PSCI function IDs, SMC64 calling convention where there is one
+const PSCI_FEATURES: u64 = 0x8400_000A;
+const PSCI_CPU_SUSPEND_64: u64 = 0xC400_0001;
+
+/// PSCI_FEATURES flag of CPU_SUSPEND: power states use the extended format
+const FEATURE_EXTENDED_STATE_ID: i64 = 1 << 1;
+
+/// StateType bit of a power state that loses the CPU's context, in the
+/// original and the extended format
+const ORIGINAL_POWER_DOWN: u32 = 1 << 16;
+const EXTENDED_POWER_DOWN: u32 = 1 << 30;
+
+/// Control value of `wfi`; PSCI states use their power state
+const WFI: u64 = u64::MAX;
+
+fn psci_call(function: u64, argument: u64) -> i64 {
+    let result: i64;
+    unsafe {
+        asm!(
+            "hvc #0",
+            inout("x0") function => result,
+            in("x1") argument,
+            in("x2") 0u64,
+            in("x3") 0u64,
+            clobber_abi("C"),
+        );
+    }
+    result
+}
+
+/// Whether the idle loop can use `node`: a retention state the timer
+/// keeps running in
+pub fn is_usable(node: &IdleStateNode, extended_format: bool) -> bool {
+    let power_down = if extended_format { EXTENDED_POWER_DOWN } else { ORIGINAL_POWER_DOWN };
+    node.psci_suspend_param & power_down == 0 && !node.local_timer_stop
+}
+
+/// `wfi`, then the retention states of the device tree
+pub fn probe() -> Vec<CpuIdleState> {
+    let mut states = alloc::vec![CpuIdleState { name: "wfi", exit_latency_us: 1, target_residency_us: 1, control: WFI }];
+    let nodes = match super::DEVICE_TREE.get() {
+        Some(info) if !info.idle_states().is_empty() => info.idle_states(),
+        _ => return states,
+    };
+    // PSCI 0.2 has no PSCI_FEATURES and only the original format
+    let features = psci_call(PSCI_FEATURES, PSCI_CPU_SUSPEND_64);
+    let extended_format = features >= 0 && features & FEATURE_EXTENDED_STATE_ID != 0;
+
+    let mut retention: Vec<CpuIdleState> = nodes.iter()
+        .filter(|node| is_usable(node, extended_format))
+        .map(|node| CpuIdleState {
+            name: "psci-retention",
+            exit_latency_us: node.latency_us,
+            target_residency_us: node.min_residency_us,
+            control: node.psci_suspend_param as u64,
+        })
+        .collect();
+    retention.sort_by_key(|state| state.target_residency_us);
+    states.extend(retention);
+    states
+}
+
+/// Wait for an interrupt in `state`
+pub fn enter(state: &CpuIdleState) {
+    if state.control == WFI {
+        unsafe { asm!("wfi") };
+        return;
+    }
+    // Returns once an interrupt wakes the CPU, or at once if the firmware
+    // declines the state
+    psci_call(PSCI_CPU_SUSPEND_64, state.control);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_only_retention_states_are_used() {
+        let state = |psci_suspend_param, local_timer_stop| IdleStateNode {
+            psci_suspend_param,
+            local_timer_stop,
+            ..IdleStateNode::default()
+        };
+        assert!(is_usable(&state(0x0000_0001, false), false));
+        assert!(!is_usable(&state(0x0001_0000, false), false));
+        assert!(!is_usable(&state(0x0000_0001, true), false));
+
+        // The extended format moves StateType to bit 30
+        assert!(is_usable(&state(0x0001_0000, false), true));
+        assert!(!is_usable(&state(0x4000_0001, false), true));
+    }
+}
@@ -1,9 +1,213 @@
-//! ARM64 interrupt handling implementation (stub)
+//! ARM64 interrupt handling implementation
+//!
+//! Installs the EL1 exception vector table and drives a GICv2, at the
+//! addresses the device tree gives or else those of the QEMU `virt`
+//! machine.
 
+use core::arch::{asm, global_asm};
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use super::super::traits::{InterruptHandling, InterruptHandler};
 use super::super::{PlatformResult, PlatformError};
 
-/// ARM64 interrupt handler implementation (stub)
+/// GIC distributor and CPU interface, QEMU virt's until the device tree
+/// says otherwise
+static GICD_BASE: AtomicUsize = AtomicUsize::new(0x0800_0000);
+static GICC_BASE: AtomicUsize = AtomicUsize::new(0x0801_0000);
+
+/// Use the GIC found in the device tree; must precede `setup_interrupts`
+pub fn set_gic_bases(distributor: usize, cpu_interface: usize) {
+    GICD_BASE.store(distributor, Ordering::Relaxed);
+    GICC_BASE.store(cpu_interface, Ordering::Relaxed);
+}
+
+const GICD_CTLR: usize = 0x000;
+const GICD_ISENABLER: usize = 0x100;
+const GICD_ICENABLER: usize = 0x180;
+const GICC_CTLR: usize = 0x000;
+const GICC_PMR: usize = 0x004;
+const GICC_IAR: usize = 0x00C;
+const GICC_EOIR: usize = 0x010;
+
+/// Interrupt ID the GIC reports when nothing is pending
+const GIC_SPURIOUS: u32 = 1023;
+
+/// PPI of the EL1 physical timer
+pub const TIMER_PPI: u32 = 30;
+
+/// Interrupt IDs counted: the SGIs and PPIs plus the first SPIs
+pub const IRQ_LINES: usize = 64;
+
+/// Interrupts taken per interrupt ID since boot
+static IRQ_COUNTS: [AtomicU64; IRQ_LINES] = [const { AtomicU64::new(0) }; IRQ_LINES];
+
+/// Interrupts taken per interrupt ID since boot
+pub fn irq_counts() -> [u64; IRQ_LINES] {
+    core::array::from_fn(|line| IRQ_COUNTS[line].load(Ordering::Relaxed))
+}
+
+/// First shared peripheral interrupt; the IDs below are banked per CPU
+const FIRST_SPI: usize = 32;
+
+/// Lines userspace drivers may claim: the counted SPIs
+pub fn is_routable_irq(line: usize) -> bool {
+    (FIRST_SPI..IRQ_LINES).contains(&line)
+}
+
+/// Mask or unmask one interrupt ID at the distributor
+pub fn set_irq_masked(line: usize, masked: bool) {
+    let register = if masked { GICD_ICENABLER } else { GICD_ISENABLER };
+    // Writing zeros leaves the other enable bits alone
+    unsafe { gicd_write(register + (line / 32) * 4, 1 << (line % 32)) };
+}
+
+/// Registers saved by the IRQ entry, x0 at the lowest address
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ExceptionFrame {
+    pub x: [u64; 31],
+    pub elr: u64,
+    pub spsr: u64,
+    _padding: u64,
+}
+
+impl ExceptionFrame {
+    /// Whether the exception was taken from EL0
+    pub fn from_user_mode(&self) -> bool {
+        self.spsr & 0xF == 0
+    }
+}
+
+extern "C" {
+    static exception_vectors: u8;
+}
+
+// Sixteen 128-byte entries; only IRQs from EL1 (SPx) and from AArch64 EL0
+// are handled, everything else parks the CPU
+global_asm!(
+    ".balign 0x800",
+    ".global exception_vectors",
+    "exception_vectors:",
+    ".rept 5",
+    ".balign 0x80",
+    "b unhandled_exception",
+    ".endr",
+    ".balign 0x80",
+    "b irq_entry",
+    ".rept 3",
+    ".balign 0x80",
+    "b unhandled_exception",
+    ".endr",
+    ".balign 0x80",
+    "b irq_entry",
+    ".rept 6",
+    ".balign 0x80",
+    "b unhandled_exception",
+    ".endr",
+    "",
+    "irq_entry:",
+    "sub sp, sp, #272",
+    "stp x0, x1, [sp, #0]",
+    "stp x2, x3, [sp, #16]",
+    "stp x4, x5, [sp, #32]",
+    "stp x6, x7, [sp, #48]",
+    "stp x8, x9, [sp, #64]",
+    "stp x10, x11, [sp, #80]",
+    "stp x12, x13, [sp, #96]",
+    "stp x14, x15, [sp, #112]",
+    "stp x16, x17, [sp, #128]",
+    "stp x18, x19, [sp, #144]",
+    "stp x20, x21, [sp, #160]",
+    "stp x22, x23, [sp, #176]",
+    "stp x24, x25, [sp, #192]",
+    "stp x26, x27, [sp, #208]",
+    "stp x28, x29, [sp, #224]",
+    "mrs x9, elr_el1",
+    "mrs x10, spsr_el1",
+    "str x30, [sp, #240]",
+    "stp x9, x10, [sp, #248]",
+    "mov x0, sp",
+    "bl {handler}",
+    "ldp x9, x10, [sp, #248]",
+    "msr elr_el1, x9",
+    "msr spsr_el1, x10",
+    "ldr x30, [sp, #240]",
+    "ldp x28, x29, [sp, #224]",
+    "ldp x26, x27, [sp, #208]",
+    "ldp x24, x25, [sp, #192]",
+    "ldp x22, x23, [sp, #176]",
+    "ldp x20, x21, [sp, #160]",
+    "ldp x18, x19, [sp, #144]",
+    "ldp x16, x17, [sp, #128]",
+    "ldp x14, x15, [sp, #112]",
+    "ldp x12, x13, [sp, #96]",
+    "ldp x10, x11, [sp, #80]",
+    "ldp x8, x9, [sp, #64]",
+    "ldp x6, x7, [sp, #48]",
+    "ldp x4, x5, [sp, #32]",
+    "ldp x2, x3, [sp, #16]",
+    "ldp x0, x1, [sp, #0]",
+    "add sp, sp, #272",
+    "eret",
+    "",
+    "unhandled_exception:",
+    "wfe",
+    "b unhandled_exception",
+    handler = sym irq_handler,
+);
+
+unsafe fn gicd_write(offset: usize, value: u32) {
+    write_volatile((GICD_BASE.load(Ordering::Relaxed) + offset) as *mut u32, value);
+}
+
+unsafe fn gicc_write(offset: usize, value: u32) {
+    write_volatile((GICC_BASE.load(Ordering::Relaxed) + offset) as *mut u32, value);
+}
+
+unsafe fn gicc_read(offset: usize) -> u32 {
+    read_volatile((GICC_BASE.load(Ordering::Relaxed) + offset) as *const u32)
+}
+
+extern "C" fn irq_handler(frame: &mut ExceptionFrame) {
+    let iar = unsafe { gicc_read(GICC_IAR) };
+    let interrupt_id = iar & 0x3FF;
+    if interrupt_id == GIC_SPURIOUS {
+        return;
+    }
+    if let Some(count) = IRQ_COUNTS.get(interrupt_id as usize) {
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    if interrupt_id == TIMER_PPI {
+        super::timer::handle_timer_interrupt();
+        // Ticks are accounted here; register state is only switched on
+        // x86-64 until process contexts have an AArch64 layout
+        crate::process::preempt::tick(frame.from_user_mode());
+    } else {
+        crate::ipc::irq::dispatch(interrupt_id as usize);
+    }
+    
+    unsafe { gicc_write(GICC_EOIR, iar) };
+}
+
+/// Install the exception vectors and enable the calling CPU's GIC
+/// interface and timer PPI
+///
+/// The distributor is shared, but SGIs and PPIs are banked per CPU, so
+/// every CPU enables its own.
+pub fn init_cpu_interrupts() {
+    unsafe {
+        let vectors = &exception_vectors as *const u8 as u64;
+        asm!("msr vbar_el1, {}", "isb", in(reg) vectors);
+        
+        // SGIs and PPIs are enabled through the first ISENABLER word
+        gicd_write(GICD_ISENABLER, 1 << TIMER_PPI);
+        gicc_write(GICC_PMR, 0xFF);
+        gicc_write(GICC_CTLR, 1);
+    }
+}
+
+/// ARM64 interrupt handler implementation
 pub struct AArch64InterruptHandler {
     handlers: [Option<InterruptHandler>; 256],
 }
@@ -15,25 +219,27 @@ impl AArch64InterruptHandler {
         }
     }
     
-    /// Setup the interrupt vector table (stub)
+    /// Install the exception vectors and enable the GIC
     pub fn setup_interrupts(&mut self) -> PlatformResult<()> {
-        // ARM64 interrupt setup would configure VBAR_EL1 and exception vectors
+        unsafe { gicd_write(GICD_CTLR, 1) };
+        init_cpu_interrupts();
         Ok(())
     }
 }
 
 impl InterruptHandling for AArch64InterruptHandler {
     fn enable_interrupts(&self) {
-        // ARM64 interrupt enable would clear DAIF bits
+        unsafe { asm!("msr daifclr, #2") };
     }
     
     fn disable_interrupts(&self) {
-        // ARM64 interrupt disable would set DAIF bits
+        unsafe { asm!("msr daifset, #2") };
     }
     
     fn interrupts_enabled(&self) -> bool {
-        // ARM64 would check DAIF register
-        false
+        let daif: u64;
+        unsafe { asm!("mrs {}, daif", out(reg) daif) };
+        daif & (1 << 7) == 0 // Check I (IRQ mask) bit
     }
     
     fn register_interrupt_handler(&mut self, interrupt_number: u8, handler: InterruptHandler) -> PlatformResult<()> {
@@ -53,7 +259,7 @@ impl InterruptHandling for AArch64InterruptHandler {
     }
     
     fn send_eoi(&self, interrupt_number: u8) -> PlatformResult<()> {
-        // ARM64 would send EOI to GIC (Generic Interrupt Controller)
+        unsafe { gicc_write(GICC_EOIR, interrupt_number as u32) };
         Ok(())
     }
-}
\ No newline at end of file
+}
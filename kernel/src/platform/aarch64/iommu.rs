@@ -0,0 +1,131 @@
+//! ARM64 SMMUv3
+//!
+//! The SMMU and the stream IDs of PCI requesters come from the device
+//! tree: the `arm,smmu-v3` node and the host bridge's `iommu-map`. Domains,
+//! stream assignments and mappings are tracked in software so the generic
+//! IOMMU layer behaves the same on both architectures.
+//!
+//! The stream table, queues and stage-2 tables live in physical frames the
+//! SMMU walks, and the ARM64 port has no physical frame allocator yet, so
+//! translation is never turned on: `enable` refuses, and the IOMMU layer
+//! stays in software-only mode rather than hand drivers I/O virtual
+//! addresses nothing translates.
+
+use alloc::collections::BTreeMap;
+use super::super::traits::IommuOperations;
+use super::super::fdt::DeviceTreeInfo;
+use super::super::{PhysicalAddress, PlatformResult, PlatformError, IommuDomainId, DmaDeviceId};
+
+const PAGE_SIZE: u64 = 4096;
+
+/// ARM64 SMMUv3
+pub struct AArch64Smmu {
+    /// Physical base of the SMMU register frame
+    base: u64,
+    /// Where the stream IDs of PCI requesters come from
+    device_tree: &'static DeviceTreeInfo,
+    /// Page mappings per domain (IOVA page -> physical page, writable)
+    domains: BTreeMap<IommuDomainId, BTreeMap<u64, (u64, bool)>>,
+    /// Stream ID to domain assignment
+    streams: BTreeMap<DmaDeviceId, IommuDomainId>,
+}
+
+impl AArch64Smmu {
+    /// The SMMU the device tree describes, if it has one
+    pub fn from_device_tree(device_tree: &'static DeviceTreeInfo) -> Option<Self> {
+        Some(Self {
+            base: device_tree.smmu_base?,
+            device_tree,
+            domains: BTreeMap::new(),
+            streams: BTreeMap::new(),
+        })
+    }
+
+    /// Physical base of the register frame
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Stream ID the SMMU sees for `device`, if its DMA goes through it
+    pub fn stream_id(&self, device: DmaDeviceId) -> Option<u32> {
+        // Only segment 0 is behind the host bridge the tree maps
+        if device.segment() != 0 {
+            return None;
+        }
+        self.device_tree.smmu_stream_id(device.0 & 0xFFFF)
+    }
+
+    /// Look up the physical address a device would reach at `iova`
+    pub fn translate(&self, device: DmaDeviceId, iova: u64) -> Option<(PhysicalAddress, bool)> {
+        let domain = self.streams.get(&device)?;
+        let pages = self.domains.get(domain)?;
+        let (physical, writable) = pages.get(&(iova & !(PAGE_SIZE - 1)))?;
+        Some((PhysicalAddress::new(physical + (iova & (PAGE_SIZE - 1))), *writable))
+    }
+}
+
+impl IommuOperations for AArch64Smmu {
+    fn name(&self) -> &'static str {
+        "SMMUv3"
+    }
+
+    fn enable(&mut self) -> PlatformResult<()> {
+        // Without frames for the stream table and queues, SMMU_CR0.SMMUEN
+        // cannot be set; claiming otherwise would hand devices untranslated
+        // I/O virtual addresses
+        Err(PlatformError::UnsupportedOperation)
+    }
+
+    fn create_domain(&mut self, domain: IommuDomainId) -> PlatformResult<()> {
+        if self.domains.contains_key(&domain) {
+            return Err(PlatformError::InvalidAddress);
+        }
+        self.domains.insert(domain, BTreeMap::new());
+        Ok(())
+    }
+
+    fn destroy_domain(&mut self, domain: IommuDomainId) -> PlatformResult<()> {
+        if self.streams.values().any(|assigned| *assigned == domain) {
+            return Err(PlatformError::InvalidAddress);
+        }
+        self.domains.remove(&domain).ok_or(PlatformError::InvalidAddress)?;
+        Ok(())
+    }
+
+    fn attach_device(&mut self, domain: IommuDomainId, device: DmaDeviceId) -> PlatformResult<()> {
+        if !self.domains.contains_key(&domain) || self.stream_id(device).is_none() {
+            return Err(PlatformError::InvalidAddress);
+        }
+        self.streams.insert(device, domain);
+        Ok(())
+    }
+
+    fn detach_device(&mut self, device: DmaDeviceId) -> PlatformResult<()> {
+        self.streams.remove(&device).ok_or(PlatformError::InvalidAddress)?;
+        Ok(())
+    }
+
+    fn map(&mut self, domain: IommuDomainId, iova: u64, physical: PhysicalAddress, size: usize, writable: bool) -> PlatformResult<()> {
+        if iova % PAGE_SIZE != 0 || physical.as_u64() % PAGE_SIZE != 0 {
+            return Err(PlatformError::InvalidAddress);
+        }
+        let pages = self.domains.get_mut(&domain).ok_or(PlatformError::InvalidAddress)?;
+
+        let count = (size as u64 + PAGE_SIZE - 1) / PAGE_SIZE;
+        for page in 0..count {
+            let offset = page * PAGE_SIZE;
+            pages.insert(iova + offset, (physical.as_u64() + offset, writable));
+        }
+        Ok(())
+    }
+
+    fn unmap(&mut self, domain: IommuDomainId, iova: u64, size: usize) -> PlatformResult<()> {
+        let pages = self.domains.get_mut(&domain).ok_or(PlatformError::InvalidAddress)?;
+
+        let count = (size as u64 + PAGE_SIZE - 1) / PAGE_SIZE;
+        for page in 0..count {
+            pages.remove(&(iova + page * PAGE_SIZE));
+        }
+        Ok(())
+    }
+}
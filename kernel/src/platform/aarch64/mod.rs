@@ -3,13 +3,20 @@
 //! This module provides stub implementations for ARM64 support.
 //! These are placeholder implementations that will be expanded when
 //! ARM64 support is fully implemented.
+//!
+//! The memory map, UART, GIC, timer frequency and CPU topology come from
+//! the device tree the boot loader passes, once `init_device_tree` has
+//! read it; until then, or without one, the QEMU `virt` layout is assumed.
 
 use super::traits::*;
 use super::{
-    CpuInfo, CpuArchitecture, CpuFeatures, MemoryMap, MemoryRegion, MemoryRegionType,
+    CpuInfo, CpuArchitecture, CpuFeatures, CoreCapacity, MemoryMap, MemoryRegion, MemoryRegionType,
     VirtualAddress, PhysicalAddress, PageFlags, PlatformResult, PlatformError
 };
+use super::fdt::{DeviceTree, DeviceTreeInfo, FdtError, MAX_MEMORY_REGIONS};
 use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::vec::Vec;
+use spin::{Mutex, Once};
 
 pub mod registers;
 pub mod memory;
@@ -17,11 +24,26 @@ pub mod interrupts;
 pub mod cache;
 pub mod context;
 pub mod timer;
+pub mod clock;
 pub mod power;
+pub mod cpufreq;
+pub mod idle;
 pub mod io;
+pub mod uart;
+pub mod iommu;
+pub mod smp;
 
 pub use registers::AArch64Registers;
 
+/// The console UART of this platform
+pub type PlatformUart = uart::Pl011Uart;
+
+/// The console UART, the PL011 the device tree names or QEMU virt's
+pub fn console_uart() -> PlatformUart {
+    let base = uart_base().map_or(uart::QEMU_VIRT_PL011, |base| base as usize);
+    uart::Pl011Uart::new(base)
+}
+
 /// ARM64 platform implementation (stub)
 pub struct AArch64Platform {
     initialized: AtomicBool,
@@ -37,6 +59,66 @@ pub struct AArch64Platform {
 static mut PLATFORM_INSTANCE: Option<AArch64Platform> = None;
 static PLATFORM_INIT: AtomicBool = AtomicBool::new(false);
 
+/// Per-core capacities discovered from the device tree cpu nodes
+static CPU_CAPACITIES: Mutex<Option<Vec<CoreCapacity>>> = Mutex::new(None);
+
+/// Record the CPU topology from the device tree
+///
+/// Takes `(capacity-dmips-mhz, max frequency in MHz)` for each cpu node in
+/// core ID order.
+pub fn set_cpu_topology(cpu_nodes: &[(u32, u32)]) {
+    if cpu_nodes.is_empty() {
+        return;
+    }
+    *CPU_CAPACITIES.lock() = Some(CoreCapacity::from_device_tree(cpu_nodes));
+}
+
+/// What the boot loader's device tree describes
+static DEVICE_TREE: Once<DeviceTreeInfo> = Once::new();
+
+/// RAM banks from the device tree, and how many of them there are
+static MEMORY_REGIONS: Once<([MemoryRegion; MAX_MEMORY_REGIONS], usize)> = Once::new();
+
+/// Read the device tree at physical address `dtb_address` and configure
+/// the platform from it
+///
+/// Runs before the heap exists; the CPU topology is only turned into core
+/// capacities when first asked for.
+pub fn init_device_tree(dtb_address: usize) -> Result<&'static DeviceTreeInfo, FdtError> {
+    // The boot loader leaves the tree in RAM that is still identity mapped
+    let info = unsafe { DeviceTree::from_address(dtb_address) }?.info()?;
+    let info = DEVICE_TREE.call_once(|| info);
+    
+    if let (Some(distributor), Some(cpu_interface)) = (info.gic_distributor, info.gic_cpu_interface) {
+        interrupts::set_gic_bases(distributor as usize, cpu_interface as usize);
+    }
+    if let Some(frequency_hz) = info.timer_frequency {
+        timer::set_counter_frequency(frequency_hz as u64);
+    }
+    if !info.memory_regions().is_empty() {
+        MEMORY_REGIONS.call_once(|| {
+            let mut regions = [MemoryRegion { start_addr: 0, size: 0, region_type: MemoryRegionType::Available }; MAX_MEMORY_REGIONS];
+            for (region, &(base, size)) in regions.iter_mut().zip(info.memory_regions()) {
+                region.start_addr = base;
+                region.size = size;
+            }
+            (regions, info.memory_regions().len())
+        });
+    }
+    Ok(info)
+}
+
+/// What the boot loader's device tree describes, once `init_device_tree`
+/// has read it
+pub fn device_tree() -> Option<&'static DeviceTreeInfo> {
+    DEVICE_TREE.get()
+}
+
+/// Register base of the console UART the device tree names
+pub fn uart_base() -> Option<u64> {
+    DEVICE_TREE.get().and_then(|info| info.uart_base)
+}
+
 impl AArch64Platform {
     fn new() -> Self {
         Self {
@@ -64,18 +146,32 @@ impl PlatformInterface for AArch64Platform {
             has_security_extensions: true, // TrustZone is common
         };
         
+        // Without device tree information, assume four identical cores
+        let core_capacities = CPU_CAPACITIES.lock().clone()
+            .or_else(|| DEVICE_TREE.get()
+                .filter(|info| !info.cpus().is_empty())
+                .map(|info| CoreCapacity::from_device_tree(info.cpus())))
+            .unwrap_or_else(|| CoreCapacity::uniform(4, 1800));
+        
         CpuInfo {
             architecture: CpuArchitecture::AArch64,
             vendor: "ARM",
             model_name: "ARM64 CPU",
-            core_count: 4, // Default assumption
+            core_count: core_capacities.len() as u32,
             cache_line_size: 64, // Standard for ARM64
             features,
+            core_capacities,
         }
     }
     
     fn get_memory_map(&self) -> MemoryMap {
-        // ARM64 memory map (stub)
+        if let Some((regions, count)) = MEMORY_REGIONS.get() {
+            let regions = &regions[..*count];
+            let total_memory = regions.iter().map(|region| region.size).sum();
+            return MemoryMap { regions, total_memory, available_memory: total_memory };
+        }
+        
+        // Without a device tree, QEMU virt's default of 1 GiB at 1 GiB
         static REGIONS: [MemoryRegion; 1] = [
             MemoryRegion {
                 start_addr: 0x40000000, // 1GB
@@ -149,6 +245,15 @@ pub fn init() -> PlatformResult<()> {
     Err(PlatformError::HardwareError)
 }
 
+/// Start the periodic timer interrupt
+pub fn start_timer(frequency_hz: u32) -> PlatformResult<()> {
+    let platform = unsafe { PLATFORM_INSTANCE.as_mut() }.ok_or(PlatformError::HardwareError)?;
+    platform.interrupt_handler.setup_interrupts()?;
+    platform.timer_ops.setup_periodic_timer(frequency_hz)?;
+    platform.interrupt_handler.enable_interrupts();
+    Ok(())
+}
+
 /// Get the current platform instance (stub)
 pub fn get_platform() -> &'static dyn PlatformInterface {
     unsafe {
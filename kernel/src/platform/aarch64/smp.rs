@@ -0,0 +1,129 @@
+//! Secondary CPU startup on ARM64
+//!
+//! Secondary CPUs are started with the PSCI CPU_ON call through the HVC
+//! conduit used by QEMU `virt`. A started CPU enters `secondary_start` at
+//! EL1 in the same state the boot CPU was entered in, picks up its stack
+//! from the startup slot and continues in Rust.
+//!
+//! Until the device tree's cpu nodes are read, CPU `n` is assumed to have
+//! MPIDR affinity 0 of `n` in cluster 0, as on QEMU `virt`.
+
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use super::super::{PlatformResult, PlatformError};
+use super::{interrupts, timer};
+
+/// PSCI function ID of CPU_ON, SMC64 calling convention
+const PSCI_CPU_ON_64: u64 = 0xC400_0003;
+/// PSCI function ID of CPU_OFF
+const PSCI_CPU_OFF: u64 = 0x8400_0002;
+
+/// Time a started CPU gets to reach generic code
+const STARTUP_TIMEOUT_US: u64 = 200_000;
+
+/// Stack of the CPU being started; CPUs are started one at a time
+static SECONDARY_STACK_TOP: AtomicU64 = AtomicU64::new(0);
+
+extern "C" {
+    fn secondary_start();
+}
+
+// Compiled code uses FP/SIMD registers, so their trap is turned off first.
+// PSCI passes the context ID, the CPU index, in x0.
+global_asm!(
+    ".global secondary_start",
+    "secondary_start:",
+    "mov x1, #(3 << 20)",
+    "msr cpacr_el1, x1",
+    "isb",
+    "adrp x1, {stack}",
+    "add x1, x1, :lo12:{stack}",
+    "ldr x1, [x1]",
+    "mov sp, x1",
+    "bl {entry}",
+    "1:",
+    "wfe",
+    "b 1b",
+    stack = sym SECONDARY_STACK_TOP,
+    entry = sym secondary_entry,
+);
+
+fn psci_cpu_on(target_mpidr: u64, entry_point: u64, context_id: u64) -> i64 {
+    let result: i64;
+    unsafe {
+        asm!(
+            "hvc #0",
+            inout("x0") PSCI_CPU_ON_64 => result,
+            in("x1") target_mpidr,
+            in("x2") entry_point,
+            in("x3") context_id,
+            clobber_abi("C"),
+        );
+    }
+    result
+}
+
+/// Index of the calling CPU, from affinity 0 of its MPIDR
+pub fn current_cpu_index() -> usize {
+    let mpidr: u64;
+    unsafe { asm!("mrs {}, mpidr_el1", out(reg) mpidr) };
+    (mpidr & 0xFF) as usize
+}
+
+/// Nothing to prepare: PSCI starts each CPU at its entry point directly
+pub fn prepare() -> PlatformResult<()> {
+    Ok(())
+}
+
+/// Start CPU `cpu` through PSCI and wait until it is online
+pub fn start_cpu(cpu: usize, stack_top: usize, online: &AtomicBool) -> PlatformResult<()> {
+    SECONDARY_STACK_TOP.store(stack_top as u64, Ordering::SeqCst);
+
+    // The MMU is off, so the entry point's address is its physical address
+    let result = psci_cpu_on(cpu as u64, secondary_start as usize as u64, cpu as u64);
+    if result != 0 {
+        return Err(PlatformError::HardwareError);
+    }
+
+    let mut waited_us = 0;
+    while !online.load(Ordering::Acquire) {
+        if waited_us >= STARTUP_TIMEOUT_US {
+            return Err(PlatformError::HardwareError);
+        }
+        timer::busy_wait_us(1000);
+        waited_us += 1000;
+    }
+    Ok(())
+}
+
+/// First Rust code on a secondary CPU, on its own stack
+extern "C" fn secondary_entry(cpu: usize) -> ! {
+    interrupts::init_cpu_interrupts();
+    crate::smp::secondary_main(cpu)
+}
+
+/// Power the calling CPU off through PSCI with IRQs masked, so that
+/// CPU_ON can start it again
+pub fn stop_cpu(parked: &AtomicBool) -> ! {
+    unsafe { asm!("msr daifset, #2") };
+    parked.store(true, Ordering::Release);
+    unsafe {
+        asm!(
+            "hvc #0",
+            inout("x0") PSCI_CPU_OFF => _,
+            clobber_abi("C"),
+        );
+    }
+    // CPU_OFF only returns if the firmware refused
+    log::warn!("CPU {}: PSCI CPU_OFF refused, idling instead", current_cpu_index());
+    loop {
+        unsafe { asm!("wfi") };
+    }
+}
+
+/// Start the calling secondary CPU's timer and unmask IRQs
+pub fn start_timer() -> PlatformResult<()> {
+    timer::start_secondary_timer()?;
+    unsafe { asm!("msr daifclr, #2") };
+    Ok(())
+}
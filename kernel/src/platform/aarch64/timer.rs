@@ -1,40 +1,129 @@
-//! ARM64 timer operations implementation (stub)
+//! ARM64 timer operations implementation
+//!
+//! Uses the EL1 physical timer of the Generic Timer, which raises PPI 30.
 
 use super::super::traits::TimerOperations;
 use super::super::{PlatformResult, PlatformError};
+use core::arch::asm;
 use core::sync::atomic::{AtomicU64, Ordering};
 
-/// ARM64 timer operations implementation (stub)
-pub struct AArch64TimerOperations {
-    system_time: AtomicU64,
+/// CNTP_CTL_EL0 bits
+const CNTP_CTL_ENABLE: u64 = 1 << 0;
+
+/// Counter ticks between two periodic interrupts, 0 when not periodic
+static TICK_INTERVAL: AtomicU64 = AtomicU64::new(0);
+
+/// Counter rate the device tree gives, 0 to trust CNTFRQ_EL0
+static FREQUENCY_OVERRIDE: AtomicU64 = AtomicU64::new(0);
+
+/// Use the counter rate from the timer's device tree node, for firmware
+/// that leaves CNTFRQ_EL0 unset or wrong
+pub fn set_counter_frequency(frequency_hz: u64) {
+    FREQUENCY_OVERRIDE.store(frequency_hz, Ordering::Relaxed);
+}
+
+/// Rate of the system counter, in Hz
+pub fn counter_frequency() -> u64 {
+    match FREQUENCY_OVERRIDE.load(Ordering::Relaxed) {
+        0 => {
+            let frequency: u64;
+            unsafe { asm!("mrs {}, cntfrq_el0", out(reg) frequency) };
+            frequency
+        }
+        frequency => frequency,
+    }
+}
+
+/// Current value of the system counter
+pub fn counter() -> u64 {
+    let count: u64;
+    unsafe { asm!("isb", "mrs {}, cntpct_el0", out(reg) count) };
+    count
+}
+
+fn arm(interval: u64) {
+    unsafe {
+        asm!("msr cntp_tval_el0, {}", in(reg) interval);
+        asm!("msr cntp_ctl_el0, {}", in(reg) CNTP_CTL_ENABLE);
+        asm!("isb");
+    }
+}
+
+fn disarm() {
+    unsafe {
+        asm!("msr cntp_ctl_el0, {}", in(reg) 0u64);
+        asm!("isb");
+    }
 }
 
+/// Busy-wait for `microseconds` on the system counter
+pub fn busy_wait_us(microseconds: u64) {
+    let end = counter() + counter_frequency() * microseconds / 1_000_000;
+    while counter() < end {
+        core::hint::spin_loop();
+    }
+}
+
+/// Start the calling CPU's timer with the boot CPU's period
+///
+/// Each CPU has its own timer registers; the period is shared.
+pub fn start_secondary_timer() -> PlatformResult<()> {
+    match TICK_INTERVAL.load(Ordering::SeqCst) {
+        0 => Err(PlatformError::HardwareError),
+        interval => {
+            arm(interval);
+            Ok(())
+        }
+    }
+}
+
+/// Acknowledge a timer interrupt, re-arming the timer if it is periodic
+pub fn handle_timer_interrupt() {
+    match TICK_INTERVAL.load(Ordering::SeqCst) {
+        // The interrupt is level triggered; disable a one-shot timer to clear it
+        0 => disarm(),
+        interval => arm(interval),
+    }
+}
+
+/// ARM64 timer operations implementation
+pub struct AArch64TimerOperations;
+
 impl AArch64TimerOperations {
     pub fn new() -> Self {
-        Self {
-            system_time: AtomicU64::new(0),
-        }
+        Self
     }
 }
 
 impl TimerOperations for AArch64TimerOperations {
     fn get_system_time(&self) -> u64 {
-        // ARM64 would read from CNTPCT_EL0 (Physical Count register)
-        self.system_time.load(Ordering::SeqCst)
+        let frequency = counter_frequency();
+        if frequency == 0 {
+            return 0;
+        }
+        (counter() as u128 * 1_000_000_000 / frequency as u128) as u64
     }
     
     fn setup_periodic_timer(&mut self, frequency_hz: u32) -> PlatformResult<()> {
-        // ARM64 would program the Generic Timer
+        if frequency_hz == 0 {
+            return Err(PlatformError::UnsupportedOperation);
+        }
+        let interval = (counter_frequency() / frequency_hz as u64).max(1);
+        TICK_INTERVAL.store(interval, Ordering::SeqCst);
+        arm(interval);
         Ok(())
     }
     
     fn setup_oneshot_timer(&mut self, nanoseconds: u64) -> PlatformResult<()> {
-        // ARM64 one-shot timer setup
+        let interval = (nanoseconds as u128 * counter_frequency() as u128 / 1_000_000_000).max(1);
+        TICK_INTERVAL.store(0, Ordering::SeqCst);
+        arm(interval.min(u32::MAX as u128) as u64);
         Ok(())
     }
     
     fn stop_timer(&mut self) -> PlatformResult<()> {
-        // ARM64 timer stop
+        TICK_INTERVAL.store(0, Ordering::SeqCst);
+        disarm();
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,75 @@
+//! ARM64 console UART
+//!
+//! An Arm PL011, at the address the device tree gives. Its reference
+//! clock is not known without the clock tree, so the divisor is left as
+//! the firmware set it; QEMU ignores it anyway.
+
+use core::ptr::{read_volatile, write_volatile};
+use super::super::traits::ConsoleUart;
+
+/// PL011 of the QEMU `virt` machine, used without a device tree
+pub const QEMU_VIRT_PL011: usize = 0x0900_0000;
+
+/// Register offsets
+const UARTDR: usize = 0x000;
+const UARTFR: usize = 0x018;
+const UARTLCR_H: usize = 0x02C;
+const UARTCR: usize = 0x030;
+const UARTIMSC: usize = 0x038;
+const UARTICR: usize = 0x044;
+
+/// Flag register bits
+const FR_RXFE: u32 = 1 << 4;
+const FR_TXFF: u32 = 1 << 5;
+const FR_BUSY: u32 = 1 << 3;
+
+/// Line control: FIFOs on, 8 data bits, no parity, one stop bit
+const LCR_H_FEN_8N1: u32 = (1 << 4) | (0b11 << 5);
+
+/// Control: UART, transmitter and receiver enabled
+const CR_ENABLE: u32 = (1 << 0) | (1 << 8) | (1 << 9);
+
+/// A PL011 at a physical register base, identity mapped
+pub struct Pl011Uart {
+    base: usize,
+}
+
+impl Pl011Uart {
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    fn read(&self, register: usize) -> u32 {
+        unsafe { read_volatile((self.base + register) as *const u32) }
+    }
+
+    fn write(&self, register: usize, value: u32) {
+        unsafe { write_volatile((self.base + register) as *mut u32, value) };
+    }
+}
+
+impl ConsoleUart for Pl011Uart {
+    fn init(&mut self) {
+        // The line settings only change while the UART is off and idle
+        self.write(UARTCR, 0);
+        while self.read(UARTFR) & FR_BUSY != 0 {
+            core::hint::spin_loop();
+        }
+        self.write(UARTLCR_H, LCR_H_FEN_8N1);
+        // Interrupts stay masked; input is polled
+        self.write(UARTIMSC, 0);
+        self.write(UARTICR, 0x7FF);
+        self.write(UARTCR, CR_ENABLE);
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        while self.read(UARTFR) & FR_TXFF != 0 {
+            core::hint::spin_loop();
+        }
+        self.write(UARTDR, byte as u32);
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        (self.read(UARTFR) & FR_RXFE == 0).then(|| self.read(UARTDR) as u8)
+    }
+}
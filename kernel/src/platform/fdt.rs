@@ -0,0 +1,793 @@
+//! Flattened device tree parsing
+//!
+//! ARM64 boot loaders pass the physical address of a flattened device tree
+//! (DTB) in x0, RISC-V firmware in a1. It is read once at boot, before the
+//! heap exists, so the parser works on the blob in place and collects what
+//! the platform needs into fixed-size arrays: the RAM banks of the memory
+//! nodes, the PL011 or 16550 UART, the distributor and CPU interface of a
+//! GICv2, the PLIC and CLINT of a RISC-V machine, the frequency of the
+//! architected timer or the RISC-V timebase when the firmware states it,
+//! the capacity of each cpu node, the SMC function ID and shared memory of
+//! an SCMI firmware interface, the PSCI idle states of the `idle-states`
+//! node, and the SMMUv3 with the `iommu-map` that gives PCI requesters
+//! their stream IDs.
+//!
+//! Each `reg` is decoded with the `#address-cells` and `#size-cells` of the
+//! node's parent, defaulting to 2 and 1 as the specification says. Nodes
+//! deeper than `MAX_DEPTH` and entries beyond the array sizes are skipped.
+
+use core::fmt;
+
+/// Header magic, big endian like every other field
+const FDT_MAGIC: u32 = 0xD00D_FEED;
+
+/// Oldest format version whose layout is parsed
+const FDT_MIN_VERSION: u32 = 16;
+
+/// Structure block tokens
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// Bytes of the header fields that are read
+const HEADER_SIZE: usize = 40;
+
+/// Deepest node nesting followed
+pub const MAX_DEPTH: usize = 16;
+
+/// Most RAM banks recorded
+pub const MAX_MEMORY_REGIONS: usize = 8;
+
+/// Most cpu nodes recorded
+pub const MAX_CPUS: usize = 16;
+
+/// Most idle states recorded
+pub const MAX_IDLE_STATES: usize = 8;
+
+/// Most `iommu-map` entries recorded
+pub const MAX_IOMMU_MAP: usize = 8;
+
+/// Largest blob accepted, to bound a corrupt `totalsize`
+pub const MAX_FDT_SIZE: usize = 2 * 1024 * 1024;
+
+/// UARTs the console can use
+const UART_COMPATIBLE: &[&str] = &["arm,pl011", "ns16550a"];
+
+/// GICv2 and compatible interrupt controllers
+const GIC_COMPATIBLE: &[&str] = &["arm,gic-400", "arm,cortex-a15-gic", "arm,cortex-a9-gic"];
+
+/// The architected timer
+const TIMER_COMPATIBLE: &[&str] = &["arm,armv8-timer", "arm,armv7-timer"];
+
+/// RISC-V platform-level interrupt controllers
+const PLIC_COMPATIBLE: &[&str] = &["riscv,plic0", "sifive,plic-1.0.0"];
+
+/// RISC-V core-local interruptors
+const CLINT_COMPATIBLE: &[&str] = &["riscv,clint0", "sifive,clint0"];
+
+/// SCMI firmware reached through SMC calls, and its shared memory
+const SCMI_COMPATIBLE: &[&str] = &["arm,scmi-smc"];
+const SCMI_SHMEM_COMPATIBLE: &[&str] = &["arm,scmi-shmem"];
+
+/// CPU idle states entered through PSCI CPU_SUSPEND
+const IDLE_STATE_COMPATIBLE: &[&str] = &["arm,idle-state"];
+
+/// Arm's SMMUv3 DMA remapping unit
+const SMMU_COMPATIBLE: &[&str] = &["arm,smmu-v3"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdtError {
+    /// No device tree at the address, or not a version this parser reads
+    BadHeader,
+    /// A block or token points outside the blob
+    Truncated,
+    /// The structure block holds something other than a token
+    BadToken(u32),
+}
+
+impl fmt::Display for FdtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FdtError::BadHeader => write!(f, "No valid device tree header"),
+            FdtError::Truncated => write!(f, "Device tree is truncated"),
+            FdtError::BadToken(token) => write!(f, "Unknown device tree token {:#x}", token),
+        }
+    }
+}
+
+/// A CPU idle state node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IdleStateNode {
+    /// `power_state` argument of PSCI CPU_SUSPEND
+    pub psci_suspend_param: u32,
+    /// Entry and exit latency together
+    pub latency_us: u32,
+    pub min_residency_us: u32,
+    /// Whether the CPU's architected timer stops in the state
+    pub local_timer_stop: bool,
+}
+
+/// One entry of a PCI host bridge's `iommu-map`: `length` requester IDs
+/// from `rid_base` reach the IOMMU `iommu` as stream IDs from `stream_base`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IommuMapEntry {
+    pub rid_base: u32,
+    /// Phandle of the IOMMU node
+    pub iommu: u32,
+    pub stream_base: u32,
+    pub length: u32,
+}
+
+/// What the platform takes from the device tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceTreeInfo {
+    /// RAM banks as `(base, size)`
+    memory: [(u64, u64); MAX_MEMORY_REGIONS],
+    memory_count: usize,
+    /// Register base of the first usable UART
+    pub uart_base: Option<u64>,
+    pub gic_distributor: Option<u64>,
+    pub gic_cpu_interface: Option<u64>,
+    /// Counter frequency in Hz, if the timer node overrides CNTFRQ_EL0,
+    /// or the `timebase-frequency` of a RISC-V `cpus` node
+    pub timer_frequency: Option<u32>,
+    pub plic_base: Option<u64>,
+    pub clint_base: Option<u64>,
+    /// `(capacity-dmips-mhz, frequency in MHz)` per cpu node, 0 if absent
+    cpus: [(u32, u32); MAX_CPUS],
+    cpu_count: usize,
+    /// Function ID of the SMC that signals the SCMI firmware
+    pub scmi_smc_id: Option<u32>,
+    /// Address of the first SCMI shared memory area
+    pub scmi_shmem: Option<u64>,
+    idle_states: [IdleStateNode; MAX_IDLE_STATES],
+    idle_state_count: usize,
+    /// Register base of the first SMMUv3
+    pub smmu_base: Option<u64>,
+    smmu_phandle: Option<u32>,
+    iommu_map: [IommuMapEntry; MAX_IOMMU_MAP],
+    iommu_map_count: usize,
+}
+
+impl DeviceTreeInfo {
+    const fn new() -> Self {
+        Self {
+            memory: [(0, 0); MAX_MEMORY_REGIONS],
+            memory_count: 0,
+            uart_base: None,
+            gic_distributor: None,
+            gic_cpu_interface: None,
+            timer_frequency: None,
+            plic_base: None,
+            clint_base: None,
+            cpus: [(0, 0); MAX_CPUS],
+            cpu_count: 0,
+            scmi_smc_id: None,
+            scmi_shmem: None,
+            idle_states: [IdleStateNode {
+                psci_suspend_param: 0,
+                latency_us: 0,
+                min_residency_us: 0,
+                local_timer_stop: false,
+            }; MAX_IDLE_STATES],
+            idle_state_count: 0,
+            smmu_base: None,
+            smmu_phandle: None,
+            iommu_map: [IommuMapEntry { rid_base: 0, iommu: 0, stream_base: 0, length: 0 }; MAX_IOMMU_MAP],
+            iommu_map_count: 0,
+        }
+    }
+
+    /// RAM banks as `(base, size)`, in tree order
+    pub fn memory_regions(&self) -> &[(u64, u64)] {
+        &self.memory[..self.memory_count]
+    }
+
+    /// `(capacity-dmips-mhz, frequency in MHz)` per cpu node, in tree order
+    pub fn cpus(&self) -> &[(u32, u32)] {
+        &self.cpus[..self.cpu_count]
+    }
+
+    /// PSCI idle states, in tree order
+    pub fn idle_states(&self) -> &[IdleStateNode] {
+        &self.idle_states[..self.idle_state_count]
+    }
+
+    /// `iommu-map` entries of the PCI host bridges, in tree order
+    pub fn iommu_map(&self) -> &[IommuMapEntry] {
+        &self.iommu_map[..self.iommu_map_count]
+    }
+
+    /// Stream ID the SMMU sees for PCI requester ID `rid`, if the
+    /// requester's DMA goes through it
+    pub fn smmu_stream_id(&self, rid: u32) -> Option<u32> {
+        let smmu = self.smmu_phandle?;
+        self.iommu_map().iter()
+            .find(|entry| entry.iommu == smmu && rid.wrapping_sub(entry.rid_base) < entry.length)
+            .map(|entry| entry.stream_base + (rid - entry.rid_base))
+    }
+}
+
+/// A flattened device tree blob
+pub struct DeviceTree<'a> {
+    structure: &'a [u8],
+    strings: &'a [u8],
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, FdtError> {
+    let field = bytes.get(offset..offset + 4).ok_or(FdtError::Truncated)?;
+    Ok(u32::from_be_bytes([field[0], field[1], field[2], field[3]]))
+}
+
+/// Big-endian number of `cells` 32-bit cells at the start of `bytes`
+fn read_cells(bytes: &[u8], cells: u32) -> Option<u64> {
+    let cells = cells as usize;
+    if cells == 0 || cells > 2 || bytes.len() < cells * 4 {
+        return None;
+    }
+    Some(bytes[..cells * 4].iter().fold(0, |value, &byte| value << 8 | byte as u64))
+}
+
+/// The NUL-terminated string at the start of `bytes`
+fn c_string(bytes: &[u8]) -> &[u8] {
+    let length = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+    &bytes[..length]
+}
+
+/// Whether a `compatible` list names any of `wanted`
+fn is_compatible(compatible: &[u8], wanted: &[&str]) -> bool {
+    compatible.split(|&byte| byte == 0).any(|entry| wanted.iter().any(|name| name.as_bytes() == entry))
+}
+
+/// A node being walked, with what has been read of its properties
+#[derive(Clone, Copy)]
+struct Node<'a> {
+    name: &'a [u8],
+    /// Cell counts its children's `reg` use
+    address_cells: u32,
+    size_cells: u32,
+    compatible: &'a [u8],
+    device_type: &'a [u8],
+    reg: &'a [u8],
+    phandle: Option<u32>,
+    iommu_map: &'a [u8],
+    clock_frequency: Option<u32>,
+    timebase_frequency: Option<u32>,
+    capacity: Option<u32>,
+    smc_id: Option<u32>,
+    psci_suspend_param: Option<u32>,
+    entry_latency: Option<u32>,
+    exit_latency: Option<u32>,
+    min_residency: Option<u32>,
+    local_timer_stop: bool,
+}
+
+impl<'a> Node<'a> {
+    const fn new(name: &'a [u8]) -> Self {
+        Self {
+            name,
+            address_cells: 2,
+            size_cells: 1,
+            compatible: &[],
+            device_type: &[],
+            reg: &[],
+            phandle: None,
+            iommu_map: &[],
+            clock_frequency: None,
+            timebase_frequency: None,
+            capacity: None,
+            smc_id: None,
+            psci_suspend_param: None,
+            entry_latency: None,
+            exit_latency: None,
+            min_residency: None,
+            local_timer_stop: false,
+        }
+    }
+
+    /// `(address, size)` pairs of the node's `reg`, read with the cell
+    /// counts of `parent`
+    fn reg_entries(&self, parent: &Node) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let (address_cells, size_cells) = (parent.address_cells, parent.size_cells);
+        let entry = (address_cells + size_cells) as usize * 4;
+        self.reg.chunks_exact(entry.max(1)).filter_map(move |chunk| {
+            let address = read_cells(chunk, address_cells)?;
+            let size = if size_cells == 0 { 0 } else { read_cells(&chunk[address_cells as usize * 4..], size_cells)? };
+            Some((address, size))
+        })
+    }
+}
+
+impl<'a> DeviceTree<'a> {
+    /// Check the header of the blob in `data` and locate its blocks
+    pub fn new(data: &'a [u8]) -> Result<Self, FdtError> {
+        if data.len() < HEADER_SIZE || read_u32(data, 0)? != FDT_MAGIC {
+            return Err(FdtError::BadHeader);
+        }
+        let total_size = read_u32(data, 4)? as usize;
+        let structure_offset = read_u32(data, 8)? as usize;
+        let strings_offset = read_u32(data, 12)? as usize;
+        let version = read_u32(data, 20)?;
+        let last_compatible = read_u32(data, 24)?;
+        let strings_size = read_u32(data, 32)? as usize;
+        let structure_size = read_u32(data, 36)? as usize;
+        if version < FDT_MIN_VERSION && last_compatible < FDT_MIN_VERSION {
+            return Err(FdtError::BadHeader);
+        }
+
+        let data = data.get(..total_size).ok_or(FdtError::Truncated)?;
+        let structure = structure_offset.checked_add(structure_size)
+            .and_then(|end| data.get(structure_offset..end))
+            .ok_or(FdtError::Truncated)?;
+        let strings = strings_offset.checked_add(strings_size)
+            .and_then(|end| data.get(strings_offset..end))
+            .ok_or(FdtError::Truncated)?;
+        Ok(Self { structure, strings })
+    }
+
+    /// The blob the boot loader left at physical address `address`
+    ///
+    /// # Safety
+    ///
+    /// `address` must be identity mapped and readable for the size its
+    /// header gives, and stay unchanged while the tree is in use.
+    pub unsafe fn from_address(address: usize) -> Result<DeviceTree<'static>, FdtError> {
+        if address == 0 || address % 8 != 0 {
+            return Err(FdtError::BadHeader);
+        }
+        let header = core::slice::from_raw_parts(address as *const u8, HEADER_SIZE);
+        if read_u32(header, 0)? != FDT_MAGIC {
+            return Err(FdtError::BadHeader);
+        }
+        let total_size = read_u32(header, 4)? as usize;
+        if !(HEADER_SIZE..=MAX_FDT_SIZE).contains(&total_size) {
+            return Err(FdtError::BadHeader);
+        }
+        DeviceTree::new(core::slice::from_raw_parts(address as *const u8, total_size))
+    }
+
+    fn property_name(&self, offset: u32) -> &'a [u8] {
+        c_string(self.strings.get(offset as usize..).unwrap_or(&[]))
+    }
+
+    /// Walk the structure block and collect what the platform needs
+    pub fn info(&self) -> Result<DeviceTreeInfo, FdtError> {
+        let mut info = DeviceTreeInfo::new();
+        let mut stack = [Node::new(&[]); MAX_DEPTH];
+        // Nodes entered, including the ones too deep to keep
+        let mut depth = 0usize;
+        let mut offset = 0usize;
+
+        loop {
+            let token = read_u32(self.structure, offset)?;
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = c_string(self.structure.get(offset..).ok_or(FdtError::Truncated)?);
+                    offset = (offset + name.len() + 1 + 3) & !3;
+                    if depth < MAX_DEPTH {
+                        stack[depth] = Node::new(name);
+                    }
+                    depth += 1;
+                }
+                FDT_END_NODE => {
+                    depth = depth.checked_sub(1).ok_or(FdtError::BadToken(token))?;
+                    // The root has no parent and nothing to classify
+                    if depth > 0 && depth < MAX_DEPTH {
+                        self.classify(&stack[depth], &stack[depth - 1], &mut info);
+                    }
+                }
+                FDT_PROP => {
+                    let length = read_u32(self.structure, offset)? as usize;
+                    let name = self.property_name(read_u32(self.structure, offset + 4)?);
+                    let value = offset.checked_add(8 + length)
+                        .and_then(|end| self.structure.get(offset + 8..end))
+                        .ok_or(FdtError::Truncated)?;
+                    offset = (offset + 8 + length + 3) & !3;
+                    if depth > 0 && depth <= MAX_DEPTH {
+                        Self::set_property(&mut stack[depth - 1], name, value);
+                    }
+                }
+                FDT_NOP => {}
+                FDT_END => return Ok(info),
+                _ => return Err(FdtError::BadToken(token)),
+            }
+        }
+    }
+
+    fn set_property(node: &mut Node<'a>, name: &[u8], value: &'a [u8]) {
+        let cell = || read_cells(value, 1).map(|cell| cell as u32);
+        match name {
+            b"#address-cells" => node.address_cells = cell().unwrap_or(node.address_cells),
+            b"#size-cells" => node.size_cells = cell().unwrap_or(node.size_cells),
+            b"compatible" => node.compatible = value,
+            b"device_type" => node.device_type = c_string(value),
+            b"reg" => node.reg = value,
+            b"phandle" => node.phandle = cell(),
+            b"iommu-map" => node.iommu_map = value,
+            b"clock-frequency" => node.clock_frequency = cell(),
+            b"timebase-frequency" => node.timebase_frequency = cell(),
+            b"capacity-dmips-mhz" => node.capacity = cell(),
+            b"arm,smc-id" => node.smc_id = cell(),
+            b"arm,psci-suspend-param" => node.psci_suspend_param = cell(),
+            b"entry-latency-us" => node.entry_latency = cell(),
+            b"exit-latency-us" => node.exit_latency = cell(),
+            b"min-residency-us" => node.min_residency = cell(),
+            b"local-timer-stop" => node.local_timer_stop = true,
+            _ => {}
+        }
+    }
+
+    /// Record a node whose properties have all been read
+    fn classify(&self, node: &Node, parent: &Node, info: &mut DeviceTreeInfo) {
+        let is_memory = node.device_type == b"memory"
+            || node.name == b"memory" || node.name.starts_with(b"memory@");
+        if is_memory {
+            for (base, size) in node.reg_entries(parent).filter(|&(_, size)| size > 0) {
+                if info.memory_count < MAX_MEMORY_REGIONS {
+                    info.memory[info.memory_count] = (base, size);
+                    info.memory_count += 1;
+                }
+            }
+        } else if node.device_type == b"cpu" && parent.name == b"cpus" {
+            if info.cpu_count < MAX_CPUS {
+                let frequency_mhz = node.clock_frequency.map_or(0, |hz| hz / 1_000_000);
+                info.cpus[info.cpu_count] = (node.capacity.unwrap_or(0), frequency_mhz);
+                info.cpu_count += 1;
+            }
+        } else if is_compatible(node.compatible, UART_COMPATIBLE) {
+            if info.uart_base.is_none() {
+                info.uart_base = node.reg_entries(parent).next().map(|(base, _)| base);
+            }
+        } else if is_compatible(node.compatible, GIC_COMPATIBLE) {
+            let mut reg = node.reg_entries(parent);
+            info.gic_distributor = reg.next().map(|(base, _)| base);
+            info.gic_cpu_interface = reg.next().map(|(base, _)| base);
+        } else if is_compatible(node.compatible, TIMER_COMPATIBLE) {
+            info.timer_frequency = node.clock_frequency;
+        } else if is_compatible(node.compatible, PLIC_COMPATIBLE) {
+            info.plic_base = node.reg_entries(parent).next().map(|(base, _)| base);
+        } else if is_compatible(node.compatible, CLINT_COMPATIBLE) {
+            info.clint_base = node.reg_entries(parent).next().map(|(base, _)| base);
+        } else if is_compatible(node.compatible, SCMI_COMPATIBLE) {
+            info.scmi_smc_id = node.smc_id;
+        } else if is_compatible(node.compatible, SCMI_SHMEM_COMPATIBLE) {
+            if info.scmi_shmem.is_none() {
+                info.scmi_shmem = node.reg_entries(parent).next().map(|(base, _)| base);
+            }
+        } else if is_compatible(node.compatible, IDLE_STATE_COMPATIBLE) {
+            if let Some(psci_suspend_param) = node.psci_suspend_param {
+                if info.idle_state_count < MAX_IDLE_STATES {
+                    let latency_us = node.entry_latency.unwrap_or(0).saturating_add(node.exit_latency.unwrap_or(0));
+                    info.idle_states[info.idle_state_count] = IdleStateNode {
+                        psci_suspend_param,
+                        latency_us,
+                        min_residency_us: node.min_residency.unwrap_or(latency_us),
+                        local_timer_stop: node.local_timer_stop,
+                    };
+                    info.idle_state_count += 1;
+                }
+            }
+        } else if is_compatible(node.compatible, SMMU_COMPATIBLE) {
+            if info.smmu_base.is_none() {
+                info.smmu_base = node.reg_entries(parent).next().map(|(base, _)| base);
+                info.smmu_phandle = node.phandle;
+            }
+        } else if node.name == b"cpus" && node.timebase_frequency.is_some() {
+            info.timer_frequency = node.timebase_frequency;
+        }
+        
+        // Host bridges are matched by their map rather than a compatible;
+        // an SMMUv3 has one cell of IOMMU specifier, the stream ID
+        for entry in node.iommu_map.chunks_exact(16) {
+            let cell = |index: usize| read_cells(&entry[index * 4..], 1).unwrap_or(0) as u32;
+            if info.iommu_map_count < MAX_IOMMU_MAP {
+                info.iommu_map[info.iommu_map_count] = IommuMapEntry {
+                    rid_base: cell(0),
+                    iommu: cell(1),
+                    stream_base: cell(2),
+                    length: cell(3),
+                };
+                info.iommu_map_count += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// Builds a blob the way dtc lays one out
+    struct Builder {
+        structure: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Builder {
+        fn new() -> Self {
+            Self { structure: Vec::new(), strings: Vec::new() }
+        }
+
+        fn token(&mut self, token: u32) -> &mut Self {
+            self.structure.extend_from_slice(&token.to_be_bytes());
+            self
+        }
+
+        fn pad(&mut self) {
+            while self.structure.len() % 4 != 0 {
+                self.structure.push(0);
+            }
+        }
+
+        fn begin(&mut self, name: &str) -> &mut Self {
+            self.token(FDT_BEGIN_NODE);
+            self.structure.extend_from_slice(name.as_bytes());
+            self.structure.push(0);
+            self.pad();
+            self
+        }
+
+        fn end(&mut self) -> &mut Self {
+            self.token(FDT_END_NODE)
+        }
+
+        fn prop(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_offset = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.token(FDT_PROP);
+            self.structure.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            self.structure.extend_from_slice(&name_offset.to_be_bytes());
+            self.structure.extend_from_slice(value);
+            self.pad();
+            self
+        }
+
+        fn cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+            let value: Vec<u8> = cells.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+            self.prop(name, &value)
+        }
+
+        fn build(&mut self) -> Vec<u8> {
+            self.token(FDT_END);
+            // Header, then an empty reservation map
+            let structure_offset = HEADER_SIZE + 16;
+            let strings_offset = structure_offset + self.structure.len();
+            let total = strings_offset + self.strings.len();
+            let header = [
+                FDT_MAGIC, total as u32, structure_offset as u32, strings_offset as u32,
+                HEADER_SIZE as u32, 17, 16, 0, self.strings.len() as u32, self.structure.len() as u32,
+            ];
+            let mut blob: Vec<u8> = header.iter().flat_map(|field| field.to_be_bytes()).collect();
+            blob.extend_from_slice(&[0; 16]);
+            blob.extend_from_slice(&self.structure);
+            blob.extend_from_slice(&self.strings);
+            blob
+        }
+    }
+
+    /// The parts of the tree QEMU's `virt` machine passes that matter here
+    fn virt_tree() -> Vec<u8> {
+        let mut tree = Builder::new();
+        tree.begin("")
+            .cells("#address-cells", &[2]).cells("#size-cells", &[2])
+            .prop("compatible", b"linux,dummy-virt\0")
+            .begin("memory@40000000")
+                .prop("device_type", b"memory\0")
+                .cells("reg", &[0, 0x4000_0000, 0, 0x8000_0000])
+            .end()
+            .begin("pl011@9000000")
+                .prop("compatible", b"arm,pl011\0arm,primecell\0")
+                .cells("reg", &[0, 0x0900_0000, 0, 0x1000])
+            .end()
+            .begin("intc@8000000")
+                .prop("compatible", b"arm,cortex-a15-gic\0")
+                .cells("reg", &[0, 0x0800_0000, 0, 0x10000, 0, 0x0801_0000, 0, 0x10000])
+            .end()
+            .begin("timer")
+                .prop("compatible", b"arm,armv8-timer\0arm,armv7-timer\0")
+                .cells("clock-frequency", &[62_500_000])
+            .end()
+            .begin("cpus")
+                .cells("#address-cells", &[1]).cells("#size-cells", &[0])
+                .begin("cpu@0")
+                    .prop("device_type", b"cpu\0")
+                    .cells("reg", &[0])
+                    .cells("capacity-dmips-mhz", &[1024])
+                    .cells("clock-frequency", &[2_000_000_000])
+                .end()
+                .begin("cpu@1")
+                    .prop("device_type", b"cpu\0")
+                    .cells("reg", &[1])
+                    .cells("capacity-dmips-mhz", &[446])
+                .end()
+            .end()
+        .end();
+        tree.build()
+    }
+
+    #[test_case]
+    fn test_virt_tree_is_summarized() {
+        let blob = virt_tree();
+        let info = DeviceTree::new(&blob).expect("header").info().expect("structure");
+        assert_eq!(info.memory_regions(), &[(0x4000_0000, 0x8000_0000)]);
+        assert_eq!(info.uart_base, Some(0x0900_0000));
+        assert_eq!(info.gic_distributor, Some(0x0800_0000));
+        assert_eq!(info.gic_cpu_interface, Some(0x0801_0000));
+        assert_eq!(info.timer_frequency, Some(62_500_000));
+        assert_eq!(info.cpus(), &[(1024, 2000), (446, 0)]);
+        assert_eq!(info.scmi_smc_id, None);
+    }
+
+    #[test_case]
+    fn test_scmi_firmware_is_found() {
+        let mut tree = Builder::new();
+        tree.begin("")
+            .cells("#address-cells", &[2]).cells("#size-cells", &[2])
+            .begin("reserved-memory")
+                .cells("#address-cells", &[2]).cells("#size-cells", &[2])
+                .begin("scmi-shmem@4e000000")
+                    .prop("compatible", b"arm,scmi-shmem\0")
+                    .cells("reg", &[0, 0x4E00_0000, 0, 0x1000])
+                .end()
+            .end()
+            .begin("firmware")
+                .begin("scmi")
+                    .prop("compatible", b"arm,scmi-smc\0")
+                    .cells("arm,smc-id", &[0xC200_00FE])
+                    .cells("shmem", &[1])
+                    .begin("protocol@13")
+                        .cells("reg", &[0x13])
+                    .end()
+                .end()
+            .end()
+        .end();
+        let blob = tree.build();
+        let info = DeviceTree::new(&blob).unwrap().info().unwrap();
+        assert_eq!(info.scmi_smc_id, Some(0xC200_00FE));
+        assert_eq!(info.scmi_shmem, Some(0x4E00_0000));
+    }
+
+    #[test_case]
+    fn test_idle_states_are_found() {
+        let mut tree = Builder::new();
+        tree.begin("")
+            .begin("cpus")
+                .cells("#address-cells", &[1]).cells("#size-cells", &[0])
+                .begin("idle-states")
+                    .prop("entry-method", b"psci\0")
+                    .begin("cpu-retention")
+                        .prop("compatible", b"arm,idle-state\0")
+                        .cells("arm,psci-suspend-param", &[0x0000_0001])
+                        .cells("entry-latency-us", &[20])
+                        .cells("exit-latency-us", &[40])
+                        .cells("min-residency-us", &[100])
+                    .end()
+                    .begin("cpu-sleep")
+                        .prop("compatible", b"arm,idle-state\0")
+                        .prop("local-timer-stop", b"")
+                        .cells("arm,psci-suspend-param", &[0x0001_0000])
+                        .cells("entry-latency-us", &[300])
+                        .cells("exit-latency-us", &[1200])
+                    .end()
+                .end()
+            .end()
+        .end();
+        let blob = tree.build();
+        let info = DeviceTree::new(&blob).unwrap().info().unwrap();
+        assert_eq!(info.idle_states(), &[
+            IdleStateNode { psci_suspend_param: 1, latency_us: 60, min_residency_us: 100, local_timer_stop: false },
+            IdleStateNode { psci_suspend_param: 0x0001_0000, latency_us: 1500, min_residency_us: 1500, local_timer_stop: true },
+        ]);
+    }
+
+    #[test_case]
+    fn test_reg_follows_the_parent_cell_counts() {
+        let mut tree = Builder::new();
+        tree.begin("")
+            .cells("#address-cells", &[1]).cells("#size-cells", &[1])
+            .begin("memory")
+                .prop("device_type", b"memory\0")
+                .cells("reg", &[0x8000_0000, 0x1000_0000, 0xA000_0000, 0x2000_0000])
+            .end()
+        .end();
+        let blob = tree.build();
+        let info = DeviceTree::new(&blob).unwrap().info().unwrap();
+        assert_eq!(info.memory_regions(), &[(0x8000_0000, 0x1000_0000), (0xA000_0000, 0x2000_0000)]);
+        assert_eq!(info.uart_base, None);
+        assert_eq!(info.timer_frequency, None);
+    }
+
+    #[test_case]
+    fn test_riscv_virt_tree_is_summarized() {
+        let mut tree = Builder::new();
+        tree.begin("")
+            .cells("#address-cells", &[2]).cells("#size-cells", &[2])
+            .begin("cpus")
+                .cells("#address-cells", &[1]).cells("#size-cells", &[0])
+                .cells("timebase-frequency", &[10_000_000])
+                .begin("cpu@0")
+                    .prop("device_type", b"cpu\0")
+                    .cells("reg", &[0])
+                    .begin("interrupt-controller")
+                        .prop("compatible", b"riscv,cpu-intc\0")
+                    .end()
+                .end()
+            .end()
+            .begin("memory@80000000")
+                .prop("device_type", b"memory\0")
+                .cells("reg", &[0, 0x8000_0000, 0, 0x1000_0000])
+            .end()
+            .begin("soc")
+                .cells("#address-cells", &[2]).cells("#size-cells", &[2])
+                .begin("serial@10000000")
+                    .prop("compatible", b"ns16550a\0")
+                    .cells("reg", &[0, 0x1000_0000, 0, 0x100])
+                .end()
+                .begin("plic@c000000")
+                    .prop("compatible", b"sifive,plic-1.0.0\0riscv,plic0\0")
+                    .cells("reg", &[0, 0x0C00_0000, 0, 0x60_0000])
+                .end()
+                .begin("clint@2000000")
+                    .prop("compatible", b"sifive,clint0\0riscv,clint0\0")
+                    .cells("reg", &[0, 0x0200_0000, 0, 0x1_0000])
+                .end()
+            .end()
+        .end();
+        let blob = tree.build();
+        let info = DeviceTree::new(&blob).unwrap().info().unwrap();
+        assert_eq!(info.memory_regions(), &[(0x8000_0000, 0x1000_0000)]);
+        assert_eq!(info.uart_base, Some(0x1000_0000));
+        assert_eq!(info.plic_base, Some(0x0C00_0000));
+        assert_eq!(info.clint_base, Some(0x0200_0000));
+        assert_eq!(info.timer_frequency, Some(10_000_000));
+        assert_eq!(info.cpus(), &[(0, 0)]);
+        assert_eq!(info.gic_distributor, None);
+    }
+
+    #[test_case]
+    fn test_smmu_and_pci_stream_ids_are_found() {
+        let mut tree = Builder::new();
+        tree.begin("")
+            .cells("#address-cells", &[2]).cells("#size-cells", &[2])
+            .begin("pcie@10000000")
+                .prop("compatible", b"pci-host-ecam-generic\0")
+                .prop("device_type", b"pci\0")
+                .cells("iommu-map", &[0x0000, 0x8002, 0x0000, 0x100, 0x0100, 0x9999, 0x0, 0x100])
+            .end()
+            .begin("smmuv3@9050000")
+                .prop("compatible", b"arm,smmu-v3\0")
+                .cells("reg", &[0, 0x0905_0000, 0, 0x20000])
+                .cells("#iommu-cells", &[1])
+                .cells("phandle", &[0x8002])
+            .end()
+        .end();
+        let blob = tree.build();
+        let info = DeviceTree::new(&blob).unwrap().info().unwrap();
+        assert_eq!(info.smmu_base, Some(0x0905_0000));
+        assert_eq!(info.iommu_map().len(), 2);
+        assert_eq!(info.smmu_stream_id(0x0010), Some(0x0010));
+        assert_eq!(info.smmu_stream_id(0x00FF), Some(0x00FF));
+        // The second range goes to another IOMMU, and nothing covers the rest
+        assert_eq!(info.smmu_stream_id(0x0100), None);
+        assert_eq!(info.smmu_stream_id(0x0300), None);
+    }
+
+    #[test_case]
+    fn test_bad_blobs_are_refused() {
+        let mut blob = virt_tree();
+        assert!(matches!(DeviceTree::new(&blob[..HEADER_SIZE - 1]), Err(FdtError::BadHeader)));
+        assert!(matches!(DeviceTree::new(&blob[..blob.len() - 1]), Err(FdtError::Truncated)));
+
+        // A garbage token in place of the first FDT_BEGIN_NODE
+        blob[HEADER_SIZE + 16 + 3] = 7;
+        assert_eq!(DeviceTree::new(&blob).unwrap().info(), Err(FdtError::BadToken(7)));
+
+        blob[0] = 0;
+        assert!(matches!(DeviceTree::new(&blob), Err(FdtError::BadHeader)));
+    }
+}
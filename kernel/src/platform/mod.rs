@@ -5,10 +5,16 @@
 //! for the kernel to interact with different hardware platforms.
 
 use core::fmt;
+use core::sync::atomic::AtomicBool;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 pub mod traits;
+pub mod fdt;
 pub mod x86_64;
 pub mod aarch64;
+#[cfg(target_arch = "riscv64")]
+pub mod riscv64;
 
 #[cfg(test)]
 pub mod tests;
@@ -20,11 +26,15 @@ pub use self::x86_64::*;
 #[cfg(target_arch = "aarch64")]
 pub use self::aarch64::*;
 
+#[cfg(target_arch = "riscv64")]
+pub use self::riscv64::*;
+
 /// CPU architecture information
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CpuArchitecture {
     X86_64,
     AArch64,
+    RiscV64,
 }
 
 /// CPU feature flags
@@ -47,6 +57,84 @@ pub struct CpuInfo {
     pub core_count: u32,
     pub cache_line_size: u32,
     pub features: CpuFeatures,
+    /// Relative compute capacity of each core, indexed by core ID
+    pub core_capacities: Vec<CoreCapacity>,
+}
+
+impl CpuInfo {
+    /// Whether the cores differ in capacity (big.LITTLE / DynamIQ)
+    pub fn is_heterogeneous(&self) -> bool {
+        let mut capacities = self.core_capacities.iter().map(|core| core.capacity);
+        match capacities.next() {
+            Some(first) => capacities.any(|capacity| capacity != first),
+            None => false,
+        }
+    }
+    
+    /// Core IDs of the given class
+    pub fn cores_of_class(&self, class: CoreClass) -> Vec<u32> {
+        self.core_capacities.iter()
+            .filter(|core| core.class == class)
+            .map(|core| core.core_id)
+            .collect()
+    }
+}
+
+/// Capacity of the fastest core in the system
+pub const MAX_CORE_CAPACITY: u32 = 1024;
+
+/// Performance class of a core
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoreClass {
+    /// High-performance ("big") core, or any core of a uniform system
+    Big,
+    /// Energy-efficient ("LITTLE") core
+    Little,
+}
+
+/// Compute capacity of one core
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoreCapacity {
+    pub core_id: u32,
+    /// Capacity relative to the fastest core, scaled to `MAX_CORE_CAPACITY`
+    pub capacity: u32,
+    pub max_frequency_mhz: u32,
+    pub class: CoreClass,
+}
+
+impl CoreCapacity {
+    /// Uniform capacities for `core_count` identical cores
+    pub fn uniform(core_count: u32, max_frequency_mhz: u32) -> Vec<CoreCapacity> {
+        (0..core_count).map(|core_id| CoreCapacity {
+            core_id,
+            capacity: MAX_CORE_CAPACITY,
+            max_frequency_mhz,
+            class: CoreClass::Big,
+        }).collect()
+    }
+    
+    /// Build normalized capacities from device tree cpu nodes
+    ///
+    /// Each entry is the node's `capacity-dmips-mhz` and maximum frequency
+    /// (from its OPP table). Like Linux, raw capacity is dmips/MHz times
+    /// frequency, scaled so the fastest core gets `MAX_CORE_CAPACITY`. Cores
+    /// below half of the maximum count as LITTLE.
+    pub fn from_device_tree(cpu_nodes: &[(u32, u32)]) -> Vec<CoreCapacity> {
+        let raw: Vec<u64> = cpu_nodes.iter()
+            .map(|&(dmips_mhz, frequency_mhz)| dmips_mhz.max(1) as u64 * frequency_mhz.max(1) as u64)
+            .collect();
+        let max_raw = raw.iter().copied().max().unwrap_or(1);
+        
+        cpu_nodes.iter().zip(raw.iter()).enumerate().map(|(core_id, (&(_, frequency_mhz), &raw))| {
+            let capacity = ((raw * MAX_CORE_CAPACITY as u64) / max_raw) as u32;
+            CoreCapacity {
+                core_id: core_id as u32,
+                capacity,
+                max_frequency_mhz: frequency_mhz,
+                class: if capacity < MAX_CORE_CAPACITY / 2 { CoreClass::Little } else { CoreClass::Big },
+            }
+        }).collect()
+    }
 }
 
 /// Memory region type
@@ -154,6 +242,183 @@ impl fmt::Display for PhysicalAddress {
     }
 }
 
+/// Direction of a DMA transfer, used to pick the cache maintenance operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaDirection {
+    /// CPU writes the buffer, device reads it
+    ToDevice,
+    /// Device writes the buffer, CPU reads it
+    FromDevice,
+    /// Both sides read and write the buffer
+    Bidirectional,
+}
+
+impl DmaDirection {
+    /// Decode the direction value used by the DMA system calls
+    pub fn from_raw(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(DmaDirection::ToDevice),
+            1 => Some(DmaDirection::FromDevice),
+            2 => Some(DmaDirection::Bidirectional),
+            _ => None,
+        }
+    }
+}
+
+/// IOMMU translation domain identifier (one per isolated driver)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IommuDomainId(pub u16);
+
+/// DMA-capable device as seen by the IOMMU
+///
+/// On x86-64 this is the PCI requester ID (segment << 16 | bus << 8 | devfn),
+/// on AArch64 the SMMU stream ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DmaDeviceId(pub u32);
+
+impl DmaDeviceId {
+    /// Build a PCI requester ID
+    pub const fn pci(segment: u16, bus: u8, device: u8, function: u8) -> Self {
+        Self(((segment as u32) << 16) | ((bus as u32) << 8) | (((device as u32) & 0x1f) << 3) | ((function as u32) & 0x7))
+    }
+    
+    pub const fn segment(self) -> u16 {
+        (self.0 >> 16) as u16
+    }
+    
+    pub const fn bus(self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+    
+    /// Device and function packed as in the PCI requester ID
+    pub const fn devfn(self) -> u8 {
+        self.0 as u8
+    }
+}
+
+/// Free-running counter the monotonic clock can be read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    /// x86-64 time stamp counter, when it runs at a constant rate
+    Tsc,
+    /// x86-64 HPET main counter
+    Hpet,
+    /// ARM64 generic timer's physical counter
+    GenericTimer,
+    /// RISC-V `time` CSR
+    RiscvTime,
+}
+
+impl ClockSource {
+    pub fn raw(self) -> u8 {
+        match self {
+            ClockSource::Tsc => 1,
+            ClockSource::Hpet => 2,
+            ClockSource::GenericTimer => 3,
+            ClockSource::RiscvTime => 4,
+        }
+    }
+    
+    pub fn from_raw(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(ClockSource::Tsc),
+            2 => Some(ClockSource::Hpet),
+            3 => Some(ClockSource::GenericTimer),
+            4 => Some(ClockSource::RiscvTime),
+            _ => None,
+        }
+    }
+    
+    pub fn name(self) -> &'static str {
+        match self {
+            ClockSource::Tsc => "TSC",
+            ClockSource::Hpet => "HPET",
+            ClockSource::GenericTimer => "generic timer",
+            ClockSource::RiscvTime => "RISC-V timer",
+        }
+    }
+}
+
+/// A clock source and the rate it counts at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockCounter {
+    pub source: ClockSource,
+    pub frequency_hz: u64,
+}
+
+/// An operating point the CPUs can be switched to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerformanceLevel {
+    pub frequency_mhz: u32,
+    /// What the backend writes to select the level
+    pub control: u64,
+}
+
+/// How the CPU frequency is set, as `probe_frequency_control` found it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrequencyControl {
+    /// Short name of the mechanism, such as "intel-hwp"
+    pub backend: &'static str,
+    /// Levels the backend offers, slowest first
+    pub levels: Vec<PerformanceLevel>,
+}
+
+/// A low-power state an idle CPU can wait for interrupts in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuIdleState {
+    /// Short name, such as "hlt" or "mwait-c3"
+    pub name: &'static str,
+    /// Worst-case time from entering the state to running again
+    pub exit_latency_us: u32,
+    /// Shortest idle period the state saves power over
+    pub target_residency_us: u32,
+    /// What the backend uses to enter the state
+    pub control: u64,
+}
+
+/// What a battery reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BatteryStatus {
+    /// Whether a battery sits in the bay; the rest is meaningless if not
+    pub present: bool,
+    pub charging: bool,
+    pub discharging: bool,
+    /// The battery says it is critically low
+    pub critical: bool,
+    pub level_percent: u8,
+    /// Charge or discharge rate in mW, 0 if unknown
+    pub rate_mw: u32,
+    /// Energy left and energy when full, in mWh
+    pub remaining_mwh: u32,
+    pub full_mwh: u32,
+}
+
+/// What a sensor reaching a trip point calls for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TripKind {
+    /// Slow the CPUs down
+    Passive,
+    /// Throttle processes as well
+    Hot,
+    /// Power off before the hardware is damaged
+    Critical,
+}
+
+/// A temperature at which something must be done
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TripPoint {
+    pub kind: TripKind,
+    pub millicelsius: i32,
+}
+
+/// A temperature sensor, as `probe_thermal_sensors` found it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThermalSensor {
+    /// Short name, such as "cpu" or an ACPI thermal zone's
+    pub name: String,
+    pub trips: Vec<TripPoint>,
+}
+
 /// Platform-specific error types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PlatformError {
@@ -164,6 +429,7 @@ pub enum PlatformError {
     CacheOperationFailed,
     UnsupportedOperation,
     HardwareError,
+    OutOfMemory,
 }
 
 impl fmt::Display for PlatformError {
@@ -176,6 +442,7 @@ impl fmt::Display for PlatformError {
             PlatformError::CacheOperationFailed => write!(f, "Cache operation failed"),
             PlatformError::UnsupportedOperation => write!(f, "Unsupported operation"),
             PlatformError::HardwareError => write!(f, "Hardware error"),
+            PlatformError::OutOfMemory => write!(f, "Out of memory"),
         }
     }
 }
@@ -190,10 +457,407 @@ pub fn init() -> PlatformResult<()> {
     #[cfg(target_arch = "aarch64")]
     return aarch64::init();
     
-    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    #[cfg(target_arch = "riscv64")]
+    return riscv64::init();
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
+    Err(PlatformError::UnsupportedOperation)
+}
+
+/// Start the periodic timer interrupt that drives preemption
+pub fn start_timer(frequency_hz: u32) -> PlatformResult<()> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::start_timer(frequency_hz);
+    
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::start_timer(frequency_hz);
+    
+    #[cfg(target_arch = "riscv64")]
+    return riscv64::start_timer(frequency_hz);
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
+    Err(PlatformError::UnsupportedOperation)
+}
+
+/// Index of the CPU executing the caller
+///
+/// Only meaningful once `prepare_secondary_cpus` has recorded the boot CPU.
+pub fn current_cpu_index() -> usize {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::smp::current_cpu_index();
+    
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::smp::current_cpu_index();
+    
+    #[cfg(target_arch = "riscv64")]
+    return riscv64::smp::current_cpu_index();
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
+    0
+}
+
+/// Get the boot CPU ready to start the others
+pub fn prepare_secondary_cpus() -> PlatformResult<()> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::smp::prepare();
+    
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::smp::prepare();
+    
+    #[cfg(target_arch = "riscv64")]
+    return riscv64::smp::prepare();
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
+    Err(PlatformError::UnsupportedOperation)
+}
+
+/// Start secondary CPU `cpu` on the stack ending at `stack_top`
+///
+/// Waits until the CPU sets `online` from `crate::smp::secondary_main`.
+pub fn start_secondary_cpu(cpu: usize, stack_top: usize, online: &AtomicBool) -> PlatformResult<()> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::smp::start_cpu(cpu, stack_top, online);
+    
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::smp::start_cpu(cpu, stack_top, online);
+    
+    #[cfg(target_arch = "riscv64")]
+    return riscv64::smp::start_cpu(cpu, stack_top, online);
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
+    Err(PlatformError::UnsupportedOperation)
+}
+
+/// Halt the calling secondary CPU for good, setting `parked` once it no
+/// longer takes interrupts
+///
+/// `start_secondary_cpu` starts it again from its entry point.
+pub fn stop_current_cpu(parked: &AtomicBool) -> ! {
+    #[cfg(target_arch = "x86_64")]
+    x86_64::smp::stop_cpu(parked);
+    
+    #[cfg(target_arch = "aarch64")]
+    aarch64::smp::stop_cpu(parked);
+    
+    #[cfg(target_arch = "riscv64")]
+    riscv64::smp::stop_cpu(parked);
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
+    {
+        parked.store(true, core::sync::atomic::Ordering::Release);
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Start the periodic timer of the calling secondary CPU and enable
+/// interrupts on it
+pub fn start_secondary_timer() -> PlatformResult<()> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::smp::start_timer();
+    
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::smp::start_timer();
+    
+    #[cfg(target_arch = "riscv64")]
+    return riscv64::smp::start_timer();
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
     Err(PlatformError::UnsupportedOperation)
 }
 
+/// Let the process the calling CPU switches to use the I/O ports `bitmap`
+/// leaves clear, or none with `None`
+///
+/// The bitmap comes with a generation that changes whenever its contents
+/// do. Only x86-64 has I/O ports.
+pub fn set_io_bitmap(bitmap: Option<(u64, &[u8])>) {
+    #[cfg(target_arch = "x86_64")]
+    x86_64::smp::set_io_bitmap(bitmap);
+    
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = bitmap;
+}
+
+/// Interrupts taken since boot, as (IRQ line, count) for each line that
+/// fired at least once
+pub fn irq_counts() -> Vec<(usize, u64)> {
+    #[cfg(target_arch = "x86_64")]
+    let counts = x86_64::interrupts::irq_counts();
+    
+    #[cfg(target_arch = "aarch64")]
+    let counts = aarch64::interrupts::irq_counts();
+    
+    #[cfg(target_arch = "riscv64")]
+    let counts = riscv64::interrupts::irq_counts();
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
+    let counts: [u64; 0] = [];
+    
+    counts.iter().copied().enumerate().filter(|&(_, count)| count > 0).collect()
+}
+
+/// Whether userspace drivers may claim IRQ `line`
+pub fn irq_line_is_routable(line: usize) -> bool {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::interrupts::is_routable_irq(line);
+    
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::interrupts::is_routable_irq(line);
+    
+    #[cfg(target_arch = "riscv64")]
+    return riscv64::interrupts::is_routable_irq(line);
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
+    {
+        let _ = line;
+        false
+    }
+}
+
+/// Mask or unmask IRQ `line` at the interrupt controller
+pub fn set_irq_masked(line: usize, masked: bool) {
+    #[cfg(target_arch = "x86_64")]
+    x86_64::interrupts::set_irq_masked(line, masked);
+    
+    #[cfg(target_arch = "aarch64")]
+    aarch64::interrupts::set_irq_masked(line, masked);
+    
+    #[cfg(target_arch = "riscv64")]
+    riscv64::interrupts::set_irq_masked(line, masked);
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
+    let _ = (line, masked);
+}
+
+/// Address and data a device writes to raise message-signaled interrupt
+/// `index`, or None where the platform cannot take them
+pub fn msi_message(index: usize) -> Option<(u64, u32)> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::interrupts::msi_message(index);
+    
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        let _ = index;
+        None
+    }
+}
+
+/// Find how the CPU frequency can be set, if the hardware lets it be
+pub fn probe_frequency_control() -> Option<FrequencyControl> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::pstate::probe();
+    
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::cpufreq::probe();
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    None
+}
+
+/// Switch the CPUs to `level`, one of the levels `probe_frequency_control`
+/// returned
+pub fn set_performance_level(level: &PerformanceLevel) -> PlatformResult<()> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::pstate::set_level(level);
+    
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::cpufreq::set_level(level);
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        let _ = level;
+        Err(PlatformError::UnsupportedOperation)
+    }
+}
+
+/// Find the low-power states an idle CPU can enter, shallowest first
+///
+/// The first is always the plain wait for interrupts, `hlt` or `wfi`.
+pub fn probe_idle_states() -> Vec<CpuIdleState> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::cstate::probe();
+    
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::idle::probe();
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    alloc::vec![CpuIdleState { name: "wfi", exit_latency_us: 1, target_residency_us: 1, control: 0 }]
+}
+
+/// Wait in `state`, one of those `probe_idle_states` returned, until an
+/// interrupt arrives
+///
+/// Interrupts must be enabled, or only a reset wakes the CPU.
+pub fn enter_idle_state(state: &CpuIdleState) {
+    #[cfg(target_arch = "x86_64")]
+    x86_64::cstate::enter(state);
+    
+    #[cfg(target_arch = "aarch64")]
+    aarch64::idle::enter(state);
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        let _ = state;
+        unsafe { core::arch::asm!("wfi") };
+    }
+}
+
+/// The state of the machine's batteries taken together, None if it has
+/// none the firmware lets us read
+pub fn read_battery() -> Option<BatteryStatus> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::battery::read_battery();
+    
+    #[cfg(not(target_arch = "x86_64"))]
+    None
+}
+
+/// Whether the machine runs on external power, None if it cannot tell
+pub fn read_ac_online() -> Option<bool> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::battery::read_ac_online();
+    
+    #[cfg(not(target_arch = "x86_64"))]
+    None
+}
+
+/// Find the temperature sensors the platform offers
+///
+/// None on ARM64 yet: its SoC sensors each need a driver of their own.
+pub fn probe_thermal_sensors() -> Vec<ThermalSensor> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::thermal::probe();
+    
+    #[cfg(not(target_arch = "x86_64"))]
+    Vec::new()
+}
+
+/// Temperature of sensor `index` of those `probe_thermal_sensors`
+/// returned, in thousandths of a degree Celsius
+pub fn read_temperature(index: usize) -> Option<i32> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::thermal::read_temperature(index);
+    
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        let _ = index;
+        None
+    }
+}
+
+/// Power the machine off; returns only where the platform cannot
+pub fn power_off() {
+    #[cfg(target_arch = "x86_64")]
+    x86_64::acpi::shutdown();
+}
+
+/// Find the best counter for the monotonic clock, measuring its rate if
+/// the hardware does not report it
+pub fn probe_clock_counter() -> Option<ClockCounter> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::clock::probe();
+    
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::clock::probe();
+    
+    #[cfg(target_arch = "riscv64")]
+    return riscv64::clock::probe();
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
+    None
+}
+
+/// Current value of a counter `probe_clock_counter` returned
+pub fn read_clock_counter(source: ClockSource) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::clock::read(source);
+    
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::clock::read(source);
+    
+    #[cfg(target_arch = "riscv64")]
+    return riscv64::clock::read(source);
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
+    {
+        let _ = source;
+        0
+    }
+}
+
+/// Seconds since the Unix epoch from the real-time clock, if there is one
+pub fn read_rtc() -> Option<u64> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::clock::read_rtc();
+    
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::clock::read_rtc();
+    
+    #[cfg(target_arch = "riscv64")]
+    return riscv64::clock::read_rtc();
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
+    None
+}
+
+/// 64 random bits from the CPU's generator, if it has one
+///
+/// RDSEED or RDRAND on x86-64; other platforms offer none yet.
+pub fn hardware_random() -> Option<u64> {
+    #[cfg(target_arch = "x86_64")]
+    return x86_64::rng::random();
+    
+    #[cfg(not(target_arch = "x86_64"))]
+    None
+}
+
+/// Read a 1, 2 or 4 byte value from I/O port `port`
+///
+/// Only x86-64 has a port space.
+pub fn port_read(port: u16, width: usize) -> PlatformResult<u32> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        use traits::IoOperations;
+        let io = x86_64::io::X86_64IoOperations;
+        match width {
+            1 => Ok(io.port_read_u8(port) as u32),
+            2 => Ok(io.port_read_u16(port) as u32),
+            4 => Ok(io.port_read_u32(port)),
+            _ => Err(PlatformError::UnsupportedOperation),
+        }
+    }
+    
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        let _ = (port, width);
+        Err(PlatformError::UnsupportedOperation)
+    }
+}
+
+/// Write a 1, 2 or 4 byte value to I/O port `port`
+pub fn port_write(port: u16, width: usize, value: u32) -> PlatformResult<()> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        use traits::IoOperations;
+        let io = x86_64::io::X86_64IoOperations;
+        match width {
+            1 => io.port_write_u8(port, value as u8),
+            2 => io.port_write_u16(port, value as u16),
+            4 => io.port_write_u32(port, value),
+            _ => return Err(PlatformError::UnsupportedOperation),
+        }
+        Ok(())
+    }
+    
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        let _ = (port, width, value);
+        Err(PlatformError::UnsupportedOperation)
+    }
+}
+
 /// Get the current platform implementation
 pub fn current_platform() -> &'static dyn traits::PlatformInterface {
     #[cfg(target_arch = "x86_64")]
@@ -202,6 +866,9 @@ pub fn current_platform() -> &'static dyn traits::PlatformInterface {
     #[cfg(target_arch = "aarch64")]
     return aarch64::get_platform();
     
-    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    #[cfg(target_arch = "riscv64")]
+    return riscv64::get_platform();
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64")))]
     panic!("Unsupported platform");
 }
\ No newline at end of file
@@ -0,0 +1,78 @@
+//! RISC-V 64 cache operations implementation
+//!
+//! The base ISA has no cache maintenance by address; ordering is what
+//! `fence` and `fence.i` provide. Devices on the QEMU `virt` machine snoop
+//! the caches, so DMA is treated as coherent and range operations only
+//! order memory accesses.
+
+use core::arch::asm;
+use super::super::traits::CacheOperations;
+use super::super::{VirtualAddress, PlatformResult};
+
+/// RISC-V 64 cache operations implementation
+pub struct RiscV64CacheOperations;
+
+impl RiscV64CacheOperations {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+fn fence() {
+    unsafe { asm!("fence rw, rw") };
+}
+
+fn fence_i() {
+    unsafe { asm!("fence.i") };
+}
+
+impl CacheOperations for RiscV64CacheOperations {
+    fn flush_all(&self) -> PlatformResult<()> {
+        fence();
+        fence_i();
+        Ok(())
+    }
+    
+    fn flush_dcache(&self) -> PlatformResult<()> {
+        fence();
+        Ok(())
+    }
+    
+    fn flush_icache(&self) -> PlatformResult<()> {
+        fence_i();
+        Ok(())
+    }
+    
+    fn invalidate_dcache(&self) -> PlatformResult<()> {
+        fence();
+        Ok(())
+    }
+    
+    fn invalidate_icache(&self) -> PlatformResult<()> {
+        fence_i();
+        Ok(())
+    }
+    
+    fn dcache_line_size(&self) -> usize {
+        64
+    }
+    
+    fn clean_invalidate_dcache_range(&self, _start: VirtualAddress, _size: usize) -> PlatformResult<()> {
+        fence();
+        Ok(())
+    }
+    
+    fn invalidate_dcache_range(&self, _start: VirtualAddress, _size: usize) -> PlatformResult<()> {
+        fence();
+        Ok(())
+    }
+    
+    fn clean_dcache_range(&self, _start: VirtualAddress, _size: usize) -> PlatformResult<()> {
+        fence();
+        Ok(())
+    }
+    
+    fn is_dma_coherent(&self) -> bool {
+        true
+    }
+}
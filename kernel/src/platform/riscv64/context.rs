@@ -0,0 +1,46 @@
+//! RISC-V 64 context switching implementation (stub)
+
+use super::super::traits::{ContextSwitching, CpuContext, PlatformRegisters};
+use super::super::{VirtualAddress, PlatformResult};
+use super::registers::RiscV64Registers;
+
+/// RISC-V 64 context switching implementation (stub)
+pub struct RiscV64ContextSwitching;
+
+impl RiscV64ContextSwitching {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl ContextSwitching for RiscV64ContextSwitching {
+    fn save_context(&self, context: &mut CpuContext) -> PlatformResult<()> {
+        // The trap entry saves the registers; nothing else to store yet
+        Ok(())
+    }
+    
+    fn restore_context(&self, context: &CpuContext) -> PlatformResult<()> {
+        // Restoring would load sepc, sstatus and the registers, then sret
+        Ok(())
+    }
+    
+    fn switch_context(&self, old_context: &mut CpuContext, new_context: &CpuContext) -> PlatformResult<()> {
+        self.save_context(old_context)?;
+        self.restore_context(new_context)?;
+        Ok(())
+    }
+    
+    fn create_context(&self, entry_point: VirtualAddress, stack_pointer: VirtualAddress) -> CpuContext {
+        let registers = RiscV64Registers::new_kernel_mode(
+            entry_point.as_u64(),
+            stack_pointer.as_u64()
+        );
+        
+        CpuContext {
+            flags: registers.sstatus,
+            registers: PlatformRegisters::RiscV64(registers),
+            stack_pointer,
+            instruction_pointer: entry_point,
+        }
+    }
+}
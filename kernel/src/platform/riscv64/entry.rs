@@ -0,0 +1,35 @@
+//! RISC-V 64 kernel entry
+//!
+//! The firmware enters the kernel on one hart in S-mode, with translation
+//! and interrupts off, the hart ID in a0 and the device tree's physical
+//! address in a1, but no stack. `_start` gives the boot hart one, turns
+//! the FPU on and calls `riscv64_main` with both arguments untouched.
+
+use core::arch::global_asm;
+
+/// Stack of the boot hart until the kernel switches away from it
+pub const BOOT_STACK_SIZE: usize = 64 * 1024;
+
+global_asm!(
+    ".section .text.entry, \"ax\"",
+    ".global _start",
+    "_start:",
+    "csrw sie, zero",
+    "li t0, 1 << 13",
+    "csrs sstatus, t0",
+    // The boot hart is CPU 0
+    "li tp, 0",
+    "la sp, boot_stack_top",
+    "call {main}",
+    "1:",
+    "wfi",
+    "j 1b",
+    "",
+    ".section .bss.boot_stack, \"aw\", @nobits",
+    ".balign 16",
+    "boot_stack:",
+    ".space {stack_size}",
+    "boot_stack_top:",
+    main = sym crate::riscv64_main,
+    stack_size = const BOOT_STACK_SIZE,
+);
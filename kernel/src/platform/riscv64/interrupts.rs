@@ -0,0 +1,305 @@
+//! RISC-V 64 interrupt handling implementation
+//!
+//! Installs a direct-mode trap vector in `stvec` and drives the PLIC, at
+//! the address the device tree gives or else that of the QEMU `virt`
+//! machine. The timer interrupt arrives as the supervisor timer interrupt
+//! (see `timer`), device interrupts as the supervisor external interrupt,
+//! claimed from the hart's S-mode PLIC context.
+
+use core::arch::{asm, global_asm};
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use super::super::traits::{InterruptHandling, InterruptHandler};
+use super::super::{PlatformResult, PlatformError};
+use super::registers::{SSTATUS_SIE, SSTATUS_SPP};
+
+/// PLIC of QEMU virt until the device tree says otherwise
+static PLIC_BASE: AtomicUsize = AtomicUsize::new(0x0C00_0000);
+
+/// Use the PLIC found in the device tree; must precede `setup_interrupts`
+pub fn set_plic_base(base: usize) {
+    PLIC_BASE.store(base, Ordering::Relaxed);
+}
+
+/// PLIC register layout: a priority word per source, then an enable bitmap,
+/// threshold and claim register per context
+const PLIC_PRIORITY: usize = 0x0000;
+const PLIC_ENABLE: usize = 0x2000;
+const PLIC_ENABLE_STRIDE: usize = 0x80;
+const PLIC_CONTEXT: usize = 0x20_0000;
+const PLIC_CONTEXT_STRIDE: usize = 0x1000;
+const PLIC_THRESHOLD: usize = 0x0;
+const PLIC_CLAIM: usize = 0x4;
+
+/// scause: interrupt bit and the supervisor interrupt codes
+const SCAUSE_INTERRUPT: u64 = 1 << 63;
+const IRQ_S_TIMER: u64 = 5;
+const IRQ_S_EXTERNAL: u64 = 9;
+
+/// sie bit enabling the supervisor external interrupt
+const SIE_SEIE: u64 = 1 << 9;
+
+/// Interrupt lines counted: the first PLIC sources
+pub const IRQ_LINES: usize = 64;
+
+/// Source 0 means "no interrupt" at the PLIC, so its slot counts timer
+/// interrupts
+pub const TIMER_LINE: usize = 0;
+
+/// Interrupts taken per line since boot
+static IRQ_COUNTS: [AtomicU64; IRQ_LINES] = [const { AtomicU64::new(0) }; IRQ_LINES];
+
+/// Interrupts taken per line since boot
+pub fn irq_counts() -> [u64; IRQ_LINES] {
+    core::array::from_fn(|line| IRQ_COUNTS[line].load(Ordering::Relaxed))
+}
+
+/// Lines userspace drivers may claim: the counted PLIC sources
+pub fn is_routable_irq(line: usize) -> bool {
+    (1..IRQ_LINES).contains(&line)
+}
+
+/// PLIC context of a hart's S-mode; on QEMU virt each hart has an M-mode
+/// context followed by an S-mode one
+fn supervisor_context(hart: usize) -> usize {
+    hart * 2 + 1
+}
+
+unsafe fn plic_read(offset: usize) -> u32 {
+    read_volatile((PLIC_BASE.load(Ordering::Relaxed) + offset) as *const u32)
+}
+
+unsafe fn plic_write(offset: usize, value: u32) {
+    write_volatile((PLIC_BASE.load(Ordering::Relaxed) + offset) as *mut u32, value);
+}
+
+fn context_register(hart: usize, register: usize) -> usize {
+    PLIC_CONTEXT + supervisor_context(hart) * PLIC_CONTEXT_STRIDE + register
+}
+
+/// Mask or unmask one source for the boot hart, which takes all device
+/// interrupts
+pub fn set_irq_masked(line: usize, masked: bool) {
+    let enable = PLIC_ENABLE + supervisor_context(super::smp::boot_hart()) * PLIC_ENABLE_STRIDE + (line / 32) * 4;
+    unsafe {
+        // A source with priority 0 never interrupts
+        plic_write(PLIC_PRIORITY + line * 4, if masked { 0 } else { 1 });
+        let bits = plic_read(enable);
+        let bit = 1 << (line % 32);
+        plic_write(enable, if masked { bits & !bit } else { bits | bit });
+    }
+}
+
+/// Registers saved by the trap entry: x1-x31 (x2 as it was before the
+/// trap), then the trap CSRs
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct TrapFrame {
+    pub x: [u64; 31],
+    pub sepc: u64,
+    pub sstatus: u64,
+    pub scause: u64,
+}
+
+impl TrapFrame {
+    /// Whether the trap was taken from U-mode
+    pub fn from_user_mode(&self) -> bool {
+        self.sstatus & SSTATUS_SPP == 0
+    }
+}
+
+extern "C" {
+    fn trap_entry();
+}
+
+// Traps are taken on the interrupted stack; process contexts do not have a
+// RISC-V layout yet, so there is no kernel stack in sscratch to switch to.
+// Exceptions park the hart.
+global_asm!(
+    ".balign 4",
+    ".global trap_entry",
+    "trap_entry:",
+    "addi sp, sp, -272",
+    "sd x1, 0(sp)",
+    "sd x3, 16(sp)",
+    "sd x4, 24(sp)",
+    "sd x5, 32(sp)",
+    "sd x6, 40(sp)",
+    "sd x7, 48(sp)",
+    "sd x8, 56(sp)",
+    "sd x9, 64(sp)",
+    "sd x10, 72(sp)",
+    "sd x11, 80(sp)",
+    "sd x12, 88(sp)",
+    "sd x13, 96(sp)",
+    "sd x14, 104(sp)",
+    "sd x15, 112(sp)",
+    "sd x16, 120(sp)",
+    "sd x17, 128(sp)",
+    "sd x18, 136(sp)",
+    "sd x19, 144(sp)",
+    "sd x20, 152(sp)",
+    "sd x21, 160(sp)",
+    "sd x22, 168(sp)",
+    "sd x23, 176(sp)",
+    "sd x24, 184(sp)",
+    "sd x25, 192(sp)",
+    "sd x26, 200(sp)",
+    "sd x27, 208(sp)",
+    "sd x28, 216(sp)",
+    "sd x29, 224(sp)",
+    "sd x30, 232(sp)",
+    "sd x31, 240(sp)",
+    "addi t0, sp, 272",
+    "sd t0, 8(sp)",
+    "csrr t0, sepc",
+    "csrr t1, sstatus",
+    "csrr t2, scause",
+    "sd t0, 248(sp)",
+    "sd t1, 256(sp)",
+    "sd t2, 264(sp)",
+    "mv a0, sp",
+    "call {handler}",
+    "ld t0, 248(sp)",
+    "ld t1, 256(sp)",
+    "csrw sepc, t0",
+    "csrw sstatus, t1",
+    "ld x1, 0(sp)",
+    "ld x3, 16(sp)",
+    "ld x4, 24(sp)",
+    "ld x5, 32(sp)",
+    "ld x6, 40(sp)",
+    "ld x7, 48(sp)",
+    "ld x8, 56(sp)",
+    "ld x9, 64(sp)",
+    "ld x10, 72(sp)",
+    "ld x11, 80(sp)",
+    "ld x12, 88(sp)",
+    "ld x13, 96(sp)",
+    "ld x14, 104(sp)",
+    "ld x15, 112(sp)",
+    "ld x16, 120(sp)",
+    "ld x17, 128(sp)",
+    "ld x18, 136(sp)",
+    "ld x19, 144(sp)",
+    "ld x20, 152(sp)",
+    "ld x21, 160(sp)",
+    "ld x22, 168(sp)",
+    "ld x23, 176(sp)",
+    "ld x24, 184(sp)",
+    "ld x25, 192(sp)",
+    "ld x26, 200(sp)",
+    "ld x27, 208(sp)",
+    "ld x28, 216(sp)",
+    "ld x29, 224(sp)",
+    "ld x30, 232(sp)",
+    "ld x31, 240(sp)",
+    "addi sp, sp, 272",
+    "sret",
+    handler = sym trap_handler,
+);
+
+extern "C" fn trap_handler(frame: &mut TrapFrame) {
+    if frame.scause & SCAUSE_INTERRUPT == 0 {
+        loop {
+            unsafe { asm!("wfi") };
+        }
+    }
+    
+    match frame.scause & !SCAUSE_INTERRUPT {
+        IRQ_S_TIMER => {
+            IRQ_COUNTS[TIMER_LINE].fetch_add(1, Ordering::Relaxed);
+            super::timer::handle_timer_interrupt();
+            // Ticks are accounted here; register state is only switched on
+            // x86-64 until process contexts have a RISC-V layout
+            crate::process::preempt::tick(frame.from_user_mode());
+        }
+        IRQ_S_EXTERNAL => handle_external_interrupts(),
+        _ => {}
+    }
+}
+
+/// Claim and complete every source pending for the calling hart
+fn handle_external_interrupts() {
+    let hart = super::smp::current_hart();
+    loop {
+        let source = unsafe { plic_read(context_register(hart, PLIC_CLAIM)) };
+        if source == 0 {
+            break;
+        }
+        if let Some(count) = IRQ_COUNTS.get(source as usize) {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+        crate::ipc::irq::dispatch(source as usize);
+        unsafe { plic_write(context_register(hart, PLIC_CLAIM), source) };
+    }
+}
+
+/// Install the trap vector and open the calling hart's PLIC context
+///
+/// Every hart has its own context; sources are only enabled for the boot
+/// hart's.
+pub fn init_cpu_interrupts() {
+    unsafe {
+        asm!("csrw stvec, {}", in(reg) trap_entry as usize);
+        plic_write(context_register(super::smp::current_hart(), PLIC_THRESHOLD), 0);
+        asm!("csrs sie, {}", in(reg) SIE_SEIE);
+    }
+}
+
+/// RISC-V 64 interrupt handler implementation
+pub struct RiscV64InterruptHandler {
+    handlers: [Option<InterruptHandler>; 256],
+}
+
+impl RiscV64InterruptHandler {
+    pub fn new() -> Self {
+        Self {
+            handlers: [None; 256],
+        }
+    }
+    
+    /// Install the trap vector and open the PLIC
+    pub fn setup_interrupts(&mut self) -> PlatformResult<()> {
+        init_cpu_interrupts();
+        Ok(())
+    }
+}
+
+impl InterruptHandling for RiscV64InterruptHandler {
+    fn enable_interrupts(&self) {
+        unsafe { asm!("csrs sstatus, {}", in(reg) SSTATUS_SIE) };
+    }
+    
+    fn disable_interrupts(&self) {
+        unsafe { asm!("csrc sstatus, {}", in(reg) SSTATUS_SIE) };
+    }
+    
+    fn interrupts_enabled(&self) -> bool {
+        let sstatus: u64;
+        unsafe { asm!("csrr {}, sstatus", out(reg) sstatus) };
+        sstatus & SSTATUS_SIE != 0
+    }
+    
+    fn register_interrupt_handler(&mut self, interrupt_number: u8, handler: InterruptHandler) -> PlatformResult<()> {
+        if interrupt_number as usize >= self.handlers.len() {
+            return Err(PlatformError::InvalidAddress);
+        }
+        self.handlers[interrupt_number as usize] = Some(handler);
+        Ok(())
+    }
+    
+    fn unregister_interrupt_handler(&mut self, interrupt_number: u8) -> PlatformResult<()> {
+        if interrupt_number as usize >= self.handlers.len() {
+            return Err(PlatformError::InvalidAddress);
+        }
+        self.handlers[interrupt_number as usize] = None;
+        Ok(())
+    }
+    
+    fn send_eoi(&self, interrupt_number: u8) -> PlatformResult<()> {
+        let hart = super::smp::current_hart();
+        unsafe { plic_write(context_register(hart, PLIC_CLAIM), interrupt_number as u32) };
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+//! RISC-V 64 memory management with Sv39 paging
+//!
+//! Sv39 translates 39-bit virtual addresses, sign-extended to 64 bits,
+//! through three levels of 512-entry tables. `satp` holds the mode and the
+//! physical page number of the root table. Tables are reached through
+//! their physical addresses, which stay identity mapped for the kernel.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+use super::super::traits::MemoryManagement;
+use super::super::{VirtualAddress, PhysicalAddress, PageFlags, PlatformResult, PlatformError};
+
+const PAGE_SIZE: u64 = 4096;
+const PAGE_SHIFT: u64 = 12;
+const ENTRIES_PER_TABLE: usize = 512;
+
+/// Translation levels; level 2 is the root
+const LEVELS: usize = 3;
+
+/// `satp` mode field for Sv39
+const SATP_MODE_SV39: u64 = 8 << 60;
+const SATP_PPN_MASK: u64 = (1 << 44) - 1;
+
+/// Page table entry bits
+pub const PTE_V: u64 = 1 << 0;
+pub const PTE_R: u64 = 1 << 1;
+pub const PTE_W: u64 = 1 << 2;
+pub const PTE_X: u64 = 1 << 3;
+pub const PTE_U: u64 = 1 << 4;
+pub const PTE_G: u64 = 1 << 5;
+pub const PTE_A: u64 = 1 << 6;
+pub const PTE_D: u64 = 1 << 7;
+
+/// The physical page number starts at bit 10 and is 44 bits wide
+const PTE_PPN_SHIFT: u64 = 10;
+const PTE_PPN_MASK: u64 = ((1 << 44) - 1) << PTE_PPN_SHIFT;
+
+/// Whether `addr` is a valid Sv39 address: bits 63-39 copy bit 38
+pub fn is_canonical(addr: VirtualAddress) -> bool {
+    let high = (addr.as_u64() as i64) >> 38;
+    high == 0 || high == -1
+}
+
+/// Index into the table of `level` for `addr`
+pub fn table_index(addr: VirtualAddress, level: usize) -> usize {
+    ((addr.as_u64() >> (PAGE_SHIFT + 9 * level as u64)) & (ENTRIES_PER_TABLE as u64 - 1)) as usize
+}
+
+/// Physical address an entry points to
+fn entry_address(entry: u64) -> u64 {
+    ((entry & PTE_PPN_MASK) >> PTE_PPN_SHIFT) << PAGE_SHIFT
+}
+
+/// Whether a valid entry maps memory rather than pointing to the next table
+fn is_leaf(entry: u64) -> bool {
+    entry & (PTE_R | PTE_W | PTE_X) != 0
+}
+
+/// RISC-V 64 memory management implementation
+pub struct RiscV64MemoryManagement {
+    current_page_table: AtomicU64,
+}
+
+impl RiscV64MemoryManagement {
+    pub fn new() -> Self {
+        Self {
+            current_page_table: AtomicU64::new(0),
+        }
+    }
+    
+    /// Turn on Sv39 translation with the given root table
+    pub fn enable_mmu(&mut self, page_table_root: PhysicalAddress) -> PlatformResult<()> {
+        if page_table_root.as_u64() % PAGE_SIZE != 0 {
+            return Err(PlatformError::InvalidAddress);
+        }
+        let satp = SATP_MODE_SV39 | ((page_table_root.as_u64() >> PAGE_SHIFT) & SATP_PPN_MASK);
+        unsafe {
+            asm!("csrw satp, {}", "sfence.vma", in(reg) satp);
+        }
+        self.current_page_table.store(page_table_root.as_u64(), Ordering::SeqCst);
+        Ok(())
+    }
+    
+    /// Turn translation off (bare mode)
+    pub fn disable_mmu(&mut self) -> PlatformResult<()> {
+        unsafe {
+            asm!("csrw satp, zero", "sfence.vma");
+        }
+        Ok(())
+    }
+    
+    /// Flush the entire TLB of the calling hart
+    pub fn flush_tlb(&self) -> PlatformResult<()> {
+        unsafe {
+            asm!("sfence.vma");
+        }
+        Ok(())
+    }
+    
+    /// Flush the calling hart's TLB entries for one address
+    pub fn flush_tlb_address(&self, addr: VirtualAddress) -> PlatformResult<()> {
+        unsafe {
+            asm!("sfence.vma {}, zero", in(reg) addr.as_u64());
+        }
+        Ok(())
+    }
+    
+    /// Get the current page table root
+    pub fn get_page_table_root(&self) -> PhysicalAddress {
+        let satp: u64;
+        unsafe {
+            asm!("csrr {}, satp", out(reg) satp);
+        }
+        match satp & SATP_MODE_SV39 {
+            0 => PhysicalAddress::new(self.current_page_table.load(Ordering::SeqCst)),
+            _ => PhysicalAddress::new((satp & SATP_PPN_MASK) << PAGE_SHIFT),
+        }
+    }
+    
+    /// Switch to another root table, keeping translation on
+    pub fn set_page_table_root(&mut self, root: PhysicalAddress) -> PlatformResult<()> {
+        self.enable_mmu(root)
+    }
+    
+    fn table(address: u64) -> &'static mut [u64; ENTRIES_PER_TABLE] {
+        unsafe { &mut *(address as *mut [u64; ENTRIES_PER_TABLE]) }
+    }
+    
+    /// Walk to the level 0 entry for `addr`, creating missing tables if
+    /// `create` is set
+    fn leaf_entry(&self, addr: VirtualAddress, create: bool) -> PlatformResult<&'static mut u64> {
+        if !is_canonical(addr) {
+            return Err(PlatformError::InvalidAddress);
+        }
+        let mut table = self.get_page_table_root().as_u64();
+        if table == 0 {
+            return Err(PlatformError::MmuNotSupported);
+        }
+        
+        for level in (1..LEVELS).rev() {
+            let entry = &mut Self::table(table)[table_index(addr, level)];
+            if *entry & PTE_V == 0 {
+                if !create {
+                    return Err(PlatformError::InvalidAddress);
+                }
+                let next = allocate_table()?;
+                *entry = ((next >> PAGE_SHIFT) << PTE_PPN_SHIFT) | PTE_V;
+            } else if is_leaf(*entry) {
+                // Huge pages are left as they are
+                return Err(PlatformError::UnsupportedOperation);
+            }
+            table = entry_address(*entry);
+        }
+        Ok(&mut Self::table(table)[table_index(addr, 0)])
+    }
+}
+
+/// A zeroed, page-aligned frame for a page table
+fn allocate_table() -> PlatformResult<u64> {
+    let frame = crate::memory::physical::allocate_frame().ok_or(PlatformError::OutOfMemory)?;
+    let address = frame.address() as u64;
+    RiscV64MemoryManagement::table(address).fill(0);
+    Ok(address)
+}
+
+impl MemoryManagement for RiscV64MemoryManagement {
+    fn create_page_table(&self) -> PlatformResult<PhysicalAddress> {
+        allocate_table().map(PhysicalAddress::new)
+    }
+    
+    fn map_page(&mut self, 
+                virtual_addr: VirtualAddress, 
+                physical_addr: PhysicalAddress, 
+                flags: PageFlags) -> PlatformResult<()> {
+        if virtual_addr.as_u64() % PAGE_SIZE != 0 || physical_addr.as_u64() % PAGE_SIZE != 0 {
+            return Err(PlatformError::InvalidAddress);
+        }
+        let pte = convert_page_flags(flags);
+        if pte & (PTE_R | PTE_X) == 0 {
+            // Write-only and empty permissions are reserved encodings
+            return Err(PlatformError::InvalidPageFlags);
+        }
+        let entry = self.leaf_entry(virtual_addr, true)?;
+        *entry = ((physical_addr.as_u64() >> PAGE_SHIFT) << PTE_PPN_SHIFT) | pte;
+        self.flush_tlb_address(virtual_addr)
+    }
+    
+    fn unmap_page(&mut self, virtual_addr: VirtualAddress) -> PlatformResult<()> {
+        let entry = self.leaf_entry(virtual_addr, false)?;
+        *entry = 0;
+        self.flush_tlb_address(virtual_addr)
+    }
+    
+    fn translate_address(&self, virtual_addr: VirtualAddress) -> PlatformResult<PhysicalAddress> {
+        let entry = *self.leaf_entry(virtual_addr, false)?;
+        if entry & PTE_V == 0 {
+            return Err(PlatformError::InvalidAddress);
+        }
+        Ok(PhysicalAddress::new(entry_address(entry) + (virtual_addr.as_u64() & (PAGE_SIZE - 1))))
+    }
+    
+    fn update_page_flags(&mut self, virtual_addr: VirtualAddress, flags: PageFlags) -> PlatformResult<()> {
+        let entry = self.leaf_entry(virtual_addr, false)?;
+        if *entry & PTE_V == 0 {
+            return Err(PlatformError::InvalidAddress);
+        }
+        *entry = (*entry & PTE_PPN_MASK) | convert_page_flags(flags);
+        self.flush_tlb_address(virtual_addr)
+    }
+    
+    fn is_mapped(&self, virtual_addr: VirtualAddress) -> bool {
+        self.translate_address(virtual_addr).is_ok()
+    }
+}
+
+/// Convert generic page flags to Sv39 entry bits
+///
+/// Pages are always readable. Accessed and dirty are set up front, since
+/// a hart may fault instead of setting them itself. Sv39 has no per-page
+/// cache attributes, so the write-through and cache-disabled flags are
+/// dropped.
+pub fn convert_page_flags(flags: PageFlags) -> u64 {
+    let mut pte = 0u64;
+    
+    if flags.present { pte |= PTE_V | PTE_R | PTE_A; }
+    if flags.writable { pte |= PTE_W | PTE_D; }
+    if flags.executable { pte |= PTE_X; }
+    if flags.user_accessible { pte |= PTE_U; }
+    
+    pte
+}
+
+/// Convert Sv39 entry bits to generic page flags
+pub fn convert_from_sv39_flags(pte: u64) -> PageFlags {
+    PageFlags {
+        present: pte & PTE_V != 0,
+        writable: pte & PTE_W != 0,
+        user_accessible: pte & PTE_U != 0,
+        write_through: false,
+        cache_disabled: false,
+        accessed: pte & PTE_A != 0,
+        dirty: pte & PTE_D != 0,
+        executable: pte & PTE_X != 0,
+    }
+}
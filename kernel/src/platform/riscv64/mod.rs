@@ -0,0 +1,234 @@
+//! RISC-V 64 platform implementation
+//!
+//! Targets S-mode under SBI firmware (OpenSBI on the QEMU `virt`
+//! machine). The console, timer deadlines, hart start-up and reset go
+//! through the SBI; paging is Sv39; device interrupts come from the PLIC.
+//!
+//! The memory map, PLIC, timebase and hart count come from the device
+//! tree the firmware passes, once `init_device_tree` has read it; until
+//! then, or without one, the QEMU `virt` layout is assumed.
+
+use super::traits::*;
+use super::{
+    CpuInfo, CpuArchitecture, CpuFeatures, CoreCapacity, MemoryMap, MemoryRegion, MemoryRegionType,
+    VirtualAddress, PhysicalAddress, PlatformResult, PlatformError
+};
+use super::fdt::{DeviceTree, DeviceTreeInfo, FdtError, MAX_MEMORY_REGIONS};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Once;
+
+pub mod entry;
+pub mod sbi;
+pub mod registers;
+pub mod memory;
+pub mod interrupts;
+pub mod cache;
+pub mod context;
+pub mod timer;
+pub mod clock;
+pub mod power;
+pub mod io;
+pub mod uart;
+pub mod smp;
+
+pub use registers::RiscV64Registers;
+
+/// The console UART of this platform
+pub type PlatformUart = uart::SbiConsole;
+
+/// The console, through the firmware
+pub fn console_uart() -> PlatformUart {
+    uart::SbiConsole::new()
+}
+
+/// RISC-V 64 platform implementation
+pub struct RiscV64Platform {
+    initialized: AtomicBool,
+    memory_mgmt: memory::RiscV64MemoryManagement,
+    interrupt_handler: interrupts::RiscV64InterruptHandler,
+    cache_ops: cache::RiscV64CacheOperations,
+    context_switcher: context::RiscV64ContextSwitching,
+    timer_ops: timer::RiscV64TimerOperations,
+    power_mgmt: power::RiscV64PowerManagement,
+    io_ops: io::RiscV64IoOperations,
+}
+
+static mut PLATFORM_INSTANCE: Option<RiscV64Platform> = None;
+static PLATFORM_INIT: AtomicBool = AtomicBool::new(false);
+
+/// What the firmware's device tree describes
+static DEVICE_TREE: Once<DeviceTreeInfo> = Once::new();
+
+/// RAM banks from the device tree, and how many of them there are
+static MEMORY_REGIONS: Once<([MemoryRegion; MAX_MEMORY_REGIONS], usize)> = Once::new();
+
+/// Read the device tree at physical address `dtb_address` and configure
+/// the platform from it
+///
+/// Runs before the heap exists.
+pub fn init_device_tree(dtb_address: usize) -> Result<&'static DeviceTreeInfo, FdtError> {
+    // Translation is still off
+    let info = unsafe { DeviceTree::from_address(dtb_address) }?.info()?;
+    let info = DEVICE_TREE.call_once(|| info);
+    
+    if let Some(plic) = info.plic_base {
+        interrupts::set_plic_base(plic as usize);
+    }
+    if let Some(frequency_hz) = info.timer_frequency {
+        timer::set_counter_frequency(frequency_hz as u64);
+    }
+    if !info.memory_regions().is_empty() {
+        MEMORY_REGIONS.call_once(|| {
+            let mut regions = [MemoryRegion { start_addr: 0, size: 0, region_type: MemoryRegionType::Available }; MAX_MEMORY_REGIONS];
+            for (region, &(base, size)) in regions.iter_mut().zip(info.memory_regions()) {
+                region.start_addr = base;
+                region.size = size;
+            }
+            (regions, info.memory_regions().len())
+        });
+    }
+    Ok(info)
+}
+
+impl RiscV64Platform {
+    fn new() -> Self {
+        Self {
+            initialized: AtomicBool::new(false),
+            memory_mgmt: memory::RiscV64MemoryManagement::new(),
+            interrupt_handler: interrupts::RiscV64InterruptHandler::new(),
+            cache_ops: cache::RiscV64CacheOperations::new(),
+            context_switcher: context::RiscV64ContextSwitching::new(),
+            timer_ops: timer::RiscV64TimerOperations::new(),
+            power_mgmt: power::RiscV64PowerManagement::new(),
+            io_ops: io::RiscV64IoOperations::new(),
+        }
+    }
+}
+
+impl PlatformInterface for RiscV64Platform {
+    fn get_cpu_info(&self) -> CpuInfo {
+        // RV64GC: the F and D extensions give the FPU; V is not assumed
+        let features = CpuFeatures {
+            has_mmu: true,
+            has_cache: true,
+            has_fpu: true,
+            has_simd: false,
+            has_virtualization: false,
+            has_security_extensions: false,
+        };
+        
+        // Harts are identical unless the tree says otherwise; QEMU virt
+        // starts one by default
+        let core_capacities = DEVICE_TREE.get()
+            .filter(|info| !info.cpus().is_empty())
+            .map(|info| CoreCapacity::from_device_tree(info.cpus()))
+            .unwrap_or_else(|| CoreCapacity::uniform(1, 1000));
+        
+        CpuInfo {
+            architecture: CpuArchitecture::RiscV64,
+            vendor: "RISC-V",
+            model_name: "RV64GC hart",
+            core_count: core_capacities.len() as u32,
+            cache_line_size: 64,
+            features,
+            core_capacities,
+        }
+    }
+    
+    fn get_memory_map(&self) -> MemoryMap {
+        if let Some((regions, count)) = MEMORY_REGIONS.get() {
+            let regions = &regions[..*count];
+            let total_memory = regions.iter().map(|region| region.size).sum();
+            return MemoryMap { regions, total_memory, available_memory: total_memory };
+        }
+        
+        // Without a device tree, QEMU virt's default of 128 MiB at 2 GiB
+        static REGIONS: [MemoryRegion; 1] = [
+            MemoryRegion {
+                start_addr: 0x80000000, // 2GB
+                size: 0x8000000,        // 128MB
+                region_type: MemoryRegionType::Available,
+            }
+        ];
+        
+        MemoryMap {
+            regions: &REGIONS,
+            total_memory: 128 * 1024 * 1024,
+            available_memory: 128 * 1024 * 1024,
+        }
+    }
+    
+    fn setup_interrupts(&mut self) -> PlatformResult<()> {
+        self.interrupt_handler.setup_interrupts()
+    }
+    
+    fn enable_mmu(&mut self, page_table_root: PhysicalAddress) -> PlatformResult<()> {
+        self.memory_mgmt.enable_mmu(page_table_root)
+    }
+    
+    fn disable_mmu(&mut self) -> PlatformResult<()> {
+        self.memory_mgmt.disable_mmu()
+    }
+    
+    fn flush_tlb(&self) -> PlatformResult<()> {
+        self.memory_mgmt.flush_tlb()
+    }
+    
+    fn flush_tlb_address(&self, addr: VirtualAddress) -> PlatformResult<()> {
+        self.memory_mgmt.flush_tlb_address(addr)
+    }
+    
+    fn get_page_table_root(&self) -> PhysicalAddress {
+        self.memory_mgmt.get_page_table_root()
+    }
+    
+    fn set_page_table_root(&mut self, root: PhysicalAddress) -> PlatformResult<()> {
+        self.memory_mgmt.set_page_table_root(root)
+    }
+    
+    fn cache_operations(&self) -> &dyn CacheOperations {
+        &self.cache_ops
+    }
+    
+    fn get_constants(&self) -> PlatformConstants {
+        PlatformConstants {
+            page_size: 4096,
+            page_shift: 12,
+            virtual_address_bits: 39,  // Sv39
+            physical_address_bits: 56,
+            cache_line_size: 64,
+            max_interrupt_number: (interrupts::IRQ_LINES - 1) as u8,
+        }
+    }
+}
+
+/// Initialize the RISC-V 64 platform
+pub fn init() -> PlatformResult<()> {
+    if PLATFORM_INIT.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+        unsafe {
+            PLATFORM_INSTANCE = Some(RiscV64Platform::new());
+            if let Some(ref mut platform) = PLATFORM_INSTANCE {
+                platform.initialized.store(true, Ordering::SeqCst);
+                return Ok(());
+            }
+        }
+    }
+    Err(PlatformError::HardwareError)
+}
+
+/// Start the periodic timer interrupt
+pub fn start_timer(frequency_hz: u32) -> PlatformResult<()> {
+    let platform = unsafe { PLATFORM_INSTANCE.as_mut() }.ok_or(PlatformError::HardwareError)?;
+    platform.interrupt_handler.setup_interrupts()?;
+    platform.timer_ops.setup_periodic_timer(frequency_hz)?;
+    platform.interrupt_handler.enable_interrupts();
+    Ok(())
+}
+
+/// Get the current platform instance
+pub fn get_platform() -> &'static dyn PlatformInterface {
+    unsafe {
+        PLATFORM_INSTANCE.as_ref()
+            .expect("Platform not initialized")
+    }
+}
@@ -0,0 +1,64 @@
+//! RISC-V 64 power management implementation
+//!
+//! Reset and shutdown go through the SBI system reset extension.
+
+use core::arch::asm;
+use super::super::traits::PowerManagement;
+use super::super::{PlatformResult, PlatformError};
+use super::sbi;
+
+/// RISC-V 64 power management implementation
+pub struct RiscV64PowerManagement {
+    current_frequency: u32,
+}
+
+impl RiscV64PowerManagement {
+    pub fn new() -> Self {
+        Self {
+            current_frequency: 1000, // Default 1GHz
+        }
+    }
+}
+
+/// Park the calling hart for good
+fn halt() -> ! {
+    loop {
+        unsafe { asm!("csrci sstatus, 2", "wfi") };
+    }
+}
+
+impl PowerManagement for RiscV64PowerManagement {
+    fn cpu_idle(&self) {
+        unsafe { asm!("wfi") };
+    }
+    
+    fn cpu_halt(&self) -> ! {
+        halt()
+    }
+    
+    fn system_reset(&self) -> ! {
+        sbi::call(sbi::EID_SRST, 0, sbi::SRST_COLD_REBOOT, 0, 0);
+        // Only returns if the firmware lacks the extension
+        halt()
+    }
+    
+    fn system_shutdown(&self) -> ! {
+        sbi::call(sbi::EID_SRST, 0, sbi::SRST_SHUTDOWN, 0, 0);
+        halt()
+    }
+    
+    fn set_cpu_frequency(&mut self, frequency_mhz: u32) -> PlatformResult<()> {
+        // Frequency scaling would need a platform-specific clock driver
+        self.current_frequency = frequency_mhz;
+        Ok(())
+    }
+    
+    fn get_cpu_frequency(&self) -> u32 {
+        self.current_frequency
+    }
+    
+    fn set_core_state(&mut self, core_id: u32, enabled: bool) -> PlatformResult<()> {
+        // Harts would be stopped and started through the SBI HSM extension
+        Err(PlatformError::UnsupportedOperation)
+    }
+}
@@ -0,0 +1,95 @@
+//! RISC-V 64 register definitions
+
+/// sstatus bits
+pub const SSTATUS_SIE: u64 = 1 << 1;
+pub const SSTATUS_SPIE: u64 = 1 << 5;
+pub const SSTATUS_SPP: u64 = 1 << 8;
+/// Floating point unit state, Initial
+pub const SSTATUS_FS_INITIAL: u64 = 1 << 13;
+
+/// Register number of a0, the first argument and return value
+const A0: usize = 10;
+/// Register number of a7, which carries the system call number
+const A7: usize = 17;
+
+/// RISC-V 64 CPU registers structure
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct RiscV64Registers {
+    // General purpose registers (x0-x31); x0 always reads zero
+    pub x: [u64; 32],
+    
+    // Program counter, restored from sepc
+    pub pc: u64,
+    
+    // Supervisor status at the time of the trap
+    pub sstatus: u64,
+    
+    // Address translation and protection (Sv39 root and ASID)
+    pub satp: u64,
+}
+
+impl Default for RiscV64Registers {
+    fn default() -> Self {
+        Self {
+            x: [0; 32],
+            pc: 0,
+            sstatus: SSTATUS_SPP | SSTATUS_FS_INITIAL, // S-mode, interrupts masked
+            satp: 0,
+        }
+    }
+}
+
+impl RiscV64Registers {
+    /// Stack pointer (x2)
+    pub fn sp(&self) -> u64 {
+        self.x[2]
+    }
+    
+    /// Create a new register set for user mode
+    pub fn new_user_mode(entry_point: u64, stack_pointer: u64) -> Self {
+        let mut registers = Self {
+            pc: entry_point,
+            sstatus: SSTATUS_SPIE | SSTATUS_FS_INITIAL, // U-mode, interrupts on after sret
+            ..Default::default()
+        };
+        registers.x[2] = stack_pointer;
+        registers
+    }
+    
+    /// Create a new register set for kernel mode
+    pub fn new_kernel_mode(entry_point: u64, stack_pointer: u64) -> Self {
+        let mut registers = Self {
+            pc: entry_point,
+            sstatus: SSTATUS_SPP | SSTATUS_SPIE | SSTATUS_FS_INITIAL,
+            ..Default::default()
+        };
+        registers.x[2] = stack_pointer;
+        registers
+    }
+    
+    /// Set system call arguments (a0-a5)
+    pub fn set_syscall_args(&mut self, args: &[u64]) {
+        for (i, &arg) in args.iter().enumerate().take(6) {
+            self.x[A0 + i] = arg;
+        }
+    }
+    
+    /// Get system call arguments
+    pub fn get_syscall_args(&self) -> [u64; 6] {
+        [
+            self.x[A0], self.x[A0 + 1], self.x[A0 + 2],
+            self.x[A0 + 3], self.x[A0 + 4], self.x[A0 + 5],
+        ]
+    }
+    
+    /// Set system call return value
+    pub fn set_syscall_return(&mut self, value: u64) {
+        self.x[A0] = value;
+    }
+    
+    /// Get system call number
+    pub fn get_syscall_number(&self) -> u64 {
+        self.x[A7]
+    }
+}
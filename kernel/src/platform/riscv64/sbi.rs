@@ -0,0 +1,71 @@
+//! Supervisor Binary Interface calls
+//!
+//! The kernel runs in S-mode under firmware such as OpenSBI, which owns
+//! M-mode and with it the CLINT's timer compare registers, hart start-up
+//! and system reset. Each is asked for with an `ecall`: extension ID in
+//! a7, function ID in a6, arguments in a0-a5, error and value back in a0
+//! and a1.
+
+use core::arch::asm;
+
+/// Extension IDs
+pub const EID_LEGACY_CONSOLE_PUTCHAR: usize = 0x01;
+pub const EID_LEGACY_CONSOLE_GETCHAR: usize = 0x02;
+pub const EID_TIME: usize = 0x5449_4D45;
+pub const EID_HSM: usize = 0x0048_534D;
+pub const EID_SRST: usize = 0x5352_5354;
+
+/// HSM function IDs
+pub const HSM_HART_START: usize = 0;
+pub const HSM_HART_STOP: usize = 1;
+
+/// SRST reset types
+pub const SRST_SHUTDOWN: usize = 0;
+pub const SRST_COLD_REBOOT: usize = 1;
+
+/// Result of an SBI call: `error` is 0 on success, negative otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SbiRet {
+    pub error: isize,
+    pub value: usize,
+}
+
+/// Call function `fid` of extension `eid`
+pub fn call(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> SbiRet {
+    let error: isize;
+    let value: usize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") arg0 => error,
+            inlateout("a1") arg1 => value,
+            in("a2") arg2,
+            in("a6") fid,
+            in("a7") eid,
+            options(nostack),
+        );
+    }
+    SbiRet { error, value }
+}
+
+/// Call a legacy (v0.1) extension, which returns its result in a0 alone
+pub fn legacy_call(eid: usize, arg0: usize) -> isize {
+    let result: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") arg0 => result,
+            in("a7") eid,
+            options(nostack),
+        );
+    }
+    result
+}
+
+/// Program the calling hart's timer to fire when `time` reaches `deadline`
+///
+/// Also clears a pending timer interrupt once the deadline is in the
+/// future.
+pub fn set_timer(deadline: u64) {
+    call(EID_TIME, 0, deadline as usize, 0, 0);
+}
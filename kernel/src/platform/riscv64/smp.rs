@@ -0,0 +1,135 @@
+//! Secondary hart startup on RISC-V 64
+//!
+//! Harts are started with the SBI HSM `hart_start` call. A started hart
+//! enters `secondary_start` in S-mode with translation off, its hart ID in
+//! a0 and the opaque value, here its CPU index, in a1. It picks up its
+//! stack from the startup slot and continues in Rust.
+//!
+//! CPU indices are the kernel's, hart IDs the firmware's: the boot hart,
+//! whichever one the firmware chose, is CPU 0, and the other harts take
+//! the following indices in hart ID order. `tp` holds the CPU index of
+//! the hart running kernel code.
+
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use crate::smp::MAX_CPUS;
+use super::super::{PlatformResult, PlatformError};
+use super::{interrupts, sbi, timer};
+use super::registers::SSTATUS_SIE;
+
+/// Time a started hart gets to reach generic code
+const STARTUP_TIMEOUT_US: u64 = 200_000;
+
+/// Stack of the hart being started; harts are started one at a time
+static SECONDARY_STACK_TOP: AtomicU64 = AtomicU64::new(0);
+
+/// Hart ID of each CPU index
+static HART_IDS: [AtomicUsize; MAX_CPUS] = [const { AtomicUsize::new(0) }; MAX_CPUS];
+
+extern "C" {
+    fn secondary_start();
+}
+
+// Compiled code may use the FP registers, so the FPU is turned on first
+global_asm!(
+    ".balign 4",
+    ".global secondary_start",
+    "secondary_start:",
+    "li t0, 1 << 13",
+    "csrs sstatus, t0",
+    "mv tp, a1",
+    "la t0, {stack}",
+    "ld sp, 0(t0)",
+    "mv a0, a1",
+    "call {entry}",
+    "1:",
+    "wfi",
+    "j 1b",
+    stack = sym SECONDARY_STACK_TOP,
+    entry = sym secondary_entry,
+);
+
+/// Hart ID of CPU index `cpu` when the firmware booted on `boot_hart`
+pub fn hart_for_cpu(cpu: usize, boot_hart: usize) -> usize {
+    match cpu {
+        0 => boot_hart,
+        cpu if cpu <= boot_hart => cpu - 1,
+        cpu => cpu,
+    }
+}
+
+/// Record the hart the firmware entered the kernel on as CPU 0
+pub fn set_boot_hart(hart_id: usize) {
+    HART_IDS[0].store(hart_id, Ordering::Relaxed);
+}
+
+/// Hart ID of the boot CPU
+pub fn boot_hart() -> usize {
+    HART_IDS[0].load(Ordering::Relaxed)
+}
+
+/// Index of the calling CPU, kept in `tp`
+pub fn current_cpu_index() -> usize {
+    let cpu: usize;
+    unsafe { asm!("mv {}, tp", out(reg) cpu) };
+    cpu
+}
+
+/// Hart ID of the calling CPU
+pub fn current_hart() -> usize {
+    HART_IDS[current_cpu_index()].load(Ordering::Relaxed)
+}
+
+/// Nothing to prepare: HSM starts each hart at its entry point directly
+pub fn prepare() -> PlatformResult<()> {
+    Ok(())
+}
+
+/// Start CPU `cpu` through the SBI and wait until it is online
+pub fn start_cpu(cpu: usize, stack_top: usize, online: &AtomicBool) -> PlatformResult<()> {
+    let hart = hart_for_cpu(cpu, boot_hart());
+    HART_IDS[cpu].store(hart, Ordering::SeqCst);
+    SECONDARY_STACK_TOP.store(stack_top as u64, Ordering::SeqCst);
+
+    // Translation is off, so the entry point's address is its physical address
+    let result = sbi::call(sbi::EID_HSM, sbi::HSM_HART_START, hart, secondary_start as usize, cpu);
+    if result.error != 0 {
+        return Err(PlatformError::HardwareError);
+    }
+
+    let mut waited_us = 0;
+    while !online.load(Ordering::Acquire) {
+        if waited_us >= STARTUP_TIMEOUT_US {
+            return Err(PlatformError::HardwareError);
+        }
+        timer::busy_wait_us(1000);
+        waited_us += 1000;
+    }
+    Ok(())
+}
+
+/// First Rust code on a secondary hart, on its own stack
+extern "C" fn secondary_entry(cpu: usize) -> ! {
+    interrupts::init_cpu_interrupts();
+    crate::smp::secondary_main(cpu)
+}
+
+/// Stop the calling hart through the SBI with interrupts off, so that
+/// `hart_start` can start it again
+pub fn stop_cpu(parked: &AtomicBool) -> ! {
+    unsafe { asm!("csrc sstatus, {}", in(reg) SSTATUS_SIE) };
+    parked.store(true, Ordering::Release);
+    let result = sbi::call(sbi::EID_HSM, sbi::HSM_HART_STOP, 0, 0, 0);
+    // hart_stop only returns on failure
+    log::warn!("CPU {}: SBI hart_stop failed ({}), idling instead", current_cpu_index(), result.error);
+    loop {
+        unsafe { asm!("wfi") };
+    }
+}
+
+/// Start the calling secondary hart's timer and enable interrupts
+pub fn start_timer() -> PlatformResult<()> {
+    timer::start_secondary_timer()?;
+    unsafe { asm!("csrs sstatus, {}", in(reg) SSTATUS_SIE) };
+    Ok(())
+}
@@ -0,0 +1,119 @@
+//! RISC-V 64 timer operations implementation
+//!
+//! The `time` CSR reads the CLINT's machine timer. Its compare register
+//! belongs to M-mode, so deadlines are set through the SBI timer
+//! extension, which raises the supervisor timer interrupt when they pass.
+
+use super::super::traits::TimerOperations;
+use super::super::{PlatformResult, PlatformError};
+use super::sbi;
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Timebase of the QEMU `virt` machine, used without a device tree
+pub const QEMU_VIRT_TIMEBASE_HZ: u64 = 10_000_000;
+
+/// sie bit enabling the supervisor timer interrupt
+const SIE_STIE: u64 = 1 << 5;
+
+/// Timer ticks between two periodic interrupts, 0 when not periodic
+static TICK_INTERVAL: AtomicU64 = AtomicU64::new(0);
+
+/// Rate of the `time` CSR, from the device tree's `timebase-frequency`
+static TIMEBASE_HZ: AtomicU64 = AtomicU64::new(QEMU_VIRT_TIMEBASE_HZ);
+
+/// Use the timebase the device tree gives
+pub fn set_counter_frequency(frequency_hz: u64) {
+    if frequency_hz != 0 {
+        TIMEBASE_HZ.store(frequency_hz, Ordering::Relaxed);
+    }
+}
+
+/// Rate of the `time` CSR, in Hz
+pub fn counter_frequency() -> u64 {
+    TIMEBASE_HZ.load(Ordering::Relaxed)
+}
+
+/// Current value of the `time` CSR
+pub fn counter() -> u64 {
+    let count: u64;
+    unsafe { asm!("rdtime {}", out(reg) count) };
+    count
+}
+
+fn arm(interval: u64) {
+    sbi::set_timer(counter().wrapping_add(interval));
+    unsafe { asm!("csrs sie, {}", in(reg) SIE_STIE) };
+}
+
+fn disarm() {
+    // A deadline that never comes clears the pending interrupt
+    sbi::set_timer(u64::MAX);
+}
+
+/// Busy-wait for `microseconds` on the `time` CSR
+pub fn busy_wait_us(microseconds: u64) {
+    let end = counter() + counter_frequency() * microseconds / 1_000_000;
+    while counter() < end {
+        core::hint::spin_loop();
+    }
+}
+
+/// Start the calling hart's timer with the boot hart's period
+///
+/// Each hart has its own compare register; the period is shared.
+pub fn start_secondary_timer() -> PlatformResult<()> {
+    match TICK_INTERVAL.load(Ordering::SeqCst) {
+        0 => Err(PlatformError::HardwareError),
+        interval => {
+            arm(interval);
+            Ok(())
+        }
+    }
+}
+
+/// Acknowledge a timer interrupt, re-arming the timer if it is periodic
+pub fn handle_timer_interrupt() {
+    match TICK_INTERVAL.load(Ordering::SeqCst) {
+        0 => disarm(),
+        interval => arm(interval),
+    }
+}
+
+/// RISC-V 64 timer operations implementation
+pub struct RiscV64TimerOperations;
+
+impl RiscV64TimerOperations {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl TimerOperations for RiscV64TimerOperations {
+    fn get_system_time(&self) -> u64 {
+        (counter() as u128 * 1_000_000_000 / counter_frequency() as u128) as u64
+    }
+    
+    fn setup_periodic_timer(&mut self, frequency_hz: u32) -> PlatformResult<()> {
+        if frequency_hz == 0 {
+            return Err(PlatformError::UnsupportedOperation);
+        }
+        let interval = (counter_frequency() / frequency_hz as u64).max(1);
+        TICK_INTERVAL.store(interval, Ordering::SeqCst);
+        arm(interval);
+        Ok(())
+    }
+    
+    fn setup_oneshot_timer(&mut self, nanoseconds: u64) -> PlatformResult<()> {
+        let interval = (nanoseconds as u128 * counter_frequency() as u128 / 1_000_000_000).max(1);
+        TICK_INTERVAL.store(0, Ordering::SeqCst);
+        arm(interval.min(u64::MAX as u128) as u64);
+        Ok(())
+    }
+    
+    fn stop_timer(&mut self) -> PlatformResult<()> {
+        TICK_INTERVAL.store(0, Ordering::SeqCst);
+        disarm();
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+//! RISC-V console through the SBI
+//!
+//! The firmware already drives the machine's UART, so the console goes
+//! through its legacy console calls rather than a second driver of its
+//! own. They work before the device tree is read and need no MMIO
+//! mapping; OpenSBI keeps providing them.
+
+use super::super::traits::ConsoleUart;
+use super::sbi;
+
+/// The SBI console
+pub struct SbiConsole;
+
+impl SbiConsole {
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl ConsoleUart for SbiConsole {
+    fn init(&mut self) {
+        // The firmware set the line up before entering the kernel
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        sbi::legacy_call(sbi::EID_LEGACY_CONSOLE_PUTCHAR, byte as usize);
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        // -1 when nothing is waiting
+        match sbi::legacy_call(sbi::EID_LEGACY_CONSOLE_GETCHAR, 0) {
+            byte @ 0..=0xFF => Some(byte as u8),
+            _ => None,
+        }
+    }
+}
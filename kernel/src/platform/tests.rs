@@ -21,7 +21,7 @@ mod tests {
         
         // Test CPU info
         let cpu_info = platform.get_cpu_info();
-        assert!(matches!(cpu_info.architecture, CpuArchitecture::X86_64 | CpuArchitecture::AArch64));
+        assert!(matches!(cpu_info.architecture, CpuArchitecture::X86_64 | CpuArchitecture::AArch64 | CpuArchitecture::RiscV64));
         assert!(cpu_info.cache_line_size > 0);
         assert!(cpu_info.core_count > 0);
         
@@ -48,6 +48,21 @@ mod tests {
         assert_eq!(addr.as_mut_ptr::<u8>() as u64, 0x1000);
     }
     
+    #[test]
+    fn test_core_capacity_from_device_tree() {
+        // Two Cortex-A55 style LITTLE cores and two Cortex-A78 style big cores
+        let capacities = CoreCapacity::from_device_tree(&[(381, 1800), (381, 1800), (1024, 2800), (1024, 2800)]);
+
+        assert_eq!(capacities.len(), 4);
+        assert_eq!(capacities[2].capacity, MAX_CORE_CAPACITY);
+        assert!(capacities[0].capacity < MAX_CORE_CAPACITY / 2);
+        assert_eq!(capacities[0].class, CoreClass::Little);
+        assert_eq!(capacities[3].class, CoreClass::Big);
+
+        let uniform = CoreCapacity::uniform(2, 2000);
+        assert!(uniform.iter().all(|core| core.class == CoreClass::Big));
+    }
+
     #[test]
     fn test_physical_address() {
         let addr = PhysicalAddress::new(0x2000);
@@ -84,6 +99,62 @@ mod tests {
         assert_eq!(region.region_type, Available);
     }
     
+    /// Records the range operations of a non-coherent platform with
+    /// 64-byte lines
+    struct RecordingCache {
+        operations: spin::Mutex<alloc::vec::Vec<(&'static str, u64, usize)>>,
+    }
+    
+    impl RecordingCache {
+        fn record(&self, operation: &'static str, start: VirtualAddress, size: usize) -> PlatformResult<()> {
+            self.operations.lock().push((operation, start.as_u64(), size));
+            Ok(())
+        }
+    }
+    
+    impl traits::CacheOperations for RecordingCache {
+        fn flush_all(&self) -> PlatformResult<()> { Ok(()) }
+        fn flush_dcache(&self) -> PlatformResult<()> { Ok(()) }
+        fn flush_icache(&self) -> PlatformResult<()> { Ok(()) }
+        fn invalidate_dcache(&self) -> PlatformResult<()> { Ok(()) }
+        fn invalidate_icache(&self) -> PlatformResult<()> { Ok(()) }
+        fn dcache_line_size(&self) -> usize { 64 }
+        fn clean_invalidate_dcache_range(&self, start: VirtualAddress, size: usize) -> PlatformResult<()> {
+            self.record("clean+invalidate", start, size)
+        }
+        fn invalidate_dcache_range(&self, start: VirtualAddress, size: usize) -> PlatformResult<()> {
+            self.record("invalidate", start, size)
+        }
+        fn clean_dcache_range(&self, start: VirtualAddress, size: usize) -> PlatformResult<()> {
+            self.record("clean", start, size)
+        }
+        fn is_dma_coherent(&self) -> bool { false }
+    }
+    
+    #[test]
+    fn test_dma_invalidate_keeps_partial_lines() {
+        use traits::CacheOperations;
+        let cache = RecordingCache { operations: spin::Mutex::new(alloc::vec::Vec::new()) };
+        
+        // Partial lines at both ends around two whole ones
+        cache.sync_for_device(VirtualAddress::new(0x1010), 0xA0, DmaDirection::FromDevice).unwrap();
+        assert_eq!(*cache.operations.lock(), [
+            ("clean+invalidate", 0x1010, 0x30),
+            ("invalidate", 0x1040, 0x40),
+            ("clean+invalidate", 0x1080, 0x30),
+        ]);
+        
+        // Whole lines only
+        cache.operations.lock().clear();
+        cache.sync_for_cpu(VirtualAddress::new(0x2000), 0x80, DmaDirection::FromDevice).unwrap();
+        assert_eq!(*cache.operations.lock(), [("invalidate", 0x2000, 0x80)]);
+        
+        // Inside a single line
+        cache.operations.lock().clear();
+        cache.sync_for_cpu(VirtualAddress::new(0x3008), 0x10, DmaDirection::Bidirectional).unwrap();
+        assert_eq!(*cache.operations.lock(), [("clean+invalidate", 0x3008, 0x10)]);
+    }
+    
     #[cfg(target_arch = "x86_64")]
     #[test]
     fn test_x86_64_specific() {
@@ -121,4 +192,39 @@ mod tests {
         regs.set_syscall_return(42);
         assert_eq!(regs.x[0], 42);
     }
+    
+    #[cfg(target_arch = "riscv64")]
+    #[test]
+    fn test_riscv64_specific() {
+        use crate::platform::riscv64::registers::{RiscV64Registers, SSTATUS_SPP};
+        use crate::platform::riscv64::memory::{convert_page_flags, convert_from_sv39_flags, is_canonical, table_index};
+        use crate::platform::riscv64::smp::hart_for_cpu;
+        
+        let regs = RiscV64Registers::new_user_mode(0x1000, 0x2000);
+        assert_eq!(regs.pc, 0x1000);
+        assert_eq!(regs.sp(), 0x2000);
+        assert_eq!(regs.sstatus & SSTATUS_SPP, 0); // User mode
+        
+        let mut regs = RiscV64Registers::default();
+        regs.set_syscall_args(&[1, 2, 3, 4, 5, 6]);
+        assert_eq!(regs.get_syscall_args(), [1, 2, 3, 4, 5, 6]);
+        regs.set_syscall_return(42);
+        assert_eq!(regs.x[10], 42);
+        
+        // Sv39 splits bits 38-12 into three 9-bit indices
+        let addr = VirtualAddress::new(0x0000_003F_C020_3000);
+        assert!(is_canonical(addr));
+        assert!(!is_canonical(VirtualAddress::new(0x0000_0040_0000_0000)));
+        assert!(is_canonical(VirtualAddress::new(0xFFFF_FFC0_0000_0000)));
+        assert_eq!((table_index(addr, 2), table_index(addr, 1), table_index(addr, 0)), (255, 1, 3));
+        
+        let flags = PageFlags { writable: true, user_accessible: true, ..PageFlags::default() };
+        let round_trip = convert_from_sv39_flags(convert_page_flags(flags));
+        assert!(round_trip.present && round_trip.writable && round_trip.user_accessible);
+        assert!(!round_trip.executable);
+        
+        // The boot hart is CPU 0; the others follow in hart ID order
+        assert_eq!([0, 1, 2, 3].map(|cpu| hart_for_cpu(cpu, 2)), [2, 0, 1, 3]);
+        assert_eq!([0, 1, 2].map(|cpu| hart_for_cpu(cpu, 0)), [0, 1, 2]);
+    }
 }
\ No newline at end of file
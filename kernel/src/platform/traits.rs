@@ -5,7 +5,8 @@
 //! interface for the kernel.
 
 use super::{
-    CpuInfo, MemoryMap, VirtualAddress, PhysicalAddress, PageFlags, PlatformResult
+    CpuInfo, MemoryMap, VirtualAddress, PhysicalAddress, PageFlags, PlatformResult, DmaDirection,
+    IommuDomainId, DmaDeviceId
 };
 
 /// Main platform interface trait
@@ -64,11 +65,105 @@ pub trait CacheOperations: Send + Sync {
     /// Invalidate instruction cache
     fn invalidate_icache(&self) -> PlatformResult<()>;
     
+    /// Smallest data cache line size in bytes
+    fn dcache_line_size(&self) -> usize;
+    
     /// Clean and invalidate data cache for a specific range
     fn clean_invalidate_dcache_range(&self, start: VirtualAddress, size: usize) -> PlatformResult<()>;
     
     /// Invalidate data cache for a specific range
     fn invalidate_dcache_range(&self, start: VirtualAddress, size: usize) -> PlatformResult<()>;
+    
+    /// Clean (write back) data cache for a specific range without invalidating it
+    fn clean_dcache_range(&self, start: VirtualAddress, size: usize) -> PlatformResult<()>;
+    
+    /// Whether device DMA is cache-coherent on this platform
+    fn is_dma_coherent(&self) -> bool;
+    
+    /// Prepare a buffer before handing it to a device for DMA
+    fn sync_for_device(&self, start: VirtualAddress, size: usize, direction: DmaDirection) -> PlatformResult<()> {
+        if self.is_dma_coherent() || size == 0 {
+            return Ok(());
+        }
+        
+        match direction {
+            // Device reads the buffer: make CPU writes visible in memory
+            DmaDirection::ToDevice => self.clean_dcache_range(start, size),
+            // Device writes the buffer: drop dirty lines so they cannot be evicted over the DMA data
+            DmaDirection::FromDevice => self.invalidate_dma_range(start, size),
+            DmaDirection::Bidirectional => self.clean_invalidate_dcache_range(start, size),
+        }
+    }
+    
+    /// Reclaim a buffer for the CPU after a device finished DMA
+    fn sync_for_cpu(&self, start: VirtualAddress, size: usize, direction: DmaDirection) -> PlatformResult<()> {
+        if self.is_dma_coherent() || size == 0 {
+            return Ok(());
+        }
+        
+        match direction {
+            DmaDirection::ToDevice => Ok(()),
+            // Discard lines speculatively fetched while the device was writing
+            DmaDirection::FromDevice | DmaDirection::Bidirectional => self.invalidate_dma_range(start, size),
+        }
+    }
+    
+    /// Invalidate a buffer a device writes without losing what shares its
+    /// first and last cache lines
+    ///
+    /// Lines only partly inside the buffer also hold data next to it, which
+    /// a plain invalidate would throw away, so they are cleaned and
+    /// invalidated; only the whole lines in between are just invalidated.
+    fn invalidate_dma_range(&self, start: VirtualAddress, size: usize) -> PlatformResult<()> {
+        let line_size = self.dcache_line_size() as u64;
+        let begin = start.as_u64();
+        let end = begin + size as u64;
+        let whole_begin = (begin + line_size - 1) & !(line_size - 1);
+        let whole_end = end & !(line_size - 1);
+        if whole_begin >= whole_end {
+            // No whole line inside the buffer
+            return self.clean_invalidate_dcache_range(start, size);
+        }
+        
+        if begin < whole_begin {
+            self.clean_invalidate_dcache_range(start, (whole_begin - begin) as usize)?;
+        }
+        self.invalidate_dcache_range(VirtualAddress::new(whole_begin), (whole_end - whole_begin) as usize)?;
+        if whole_end < end {
+            self.clean_invalidate_dcache_range(VirtualAddress::new(whole_end), (end - whole_end) as usize)?;
+        }
+        Ok(())
+    }
+}
+
+/// IOMMU (VT-d / SMMU) operations trait
+///
+/// Each isolated driver gets its own translation domain; devices attached to
+/// a domain can only reach the I/O virtual addresses mapped into it.
+pub trait IommuOperations: Send + Sync {
+    /// Short name of the hardware ("VT-d", "SMMUv3", ...)
+    fn name(&self) -> &'static str;
+    
+    /// Enable DMA remapping; until then devices bypass translation
+    fn enable(&mut self) -> PlatformResult<()>;
+    
+    /// Create an empty translation domain
+    fn create_domain(&mut self, domain: IommuDomainId) -> PlatformResult<()>;
+    
+    /// Destroy a domain; attached devices must have been detached
+    fn destroy_domain(&mut self, domain: IommuDomainId) -> PlatformResult<()>;
+    
+    /// Route DMA from a device through a domain
+    fn attach_device(&mut self, domain: IommuDomainId, device: DmaDeviceId) -> PlatformResult<()>;
+    
+    /// Block DMA from a device again
+    fn detach_device(&mut self, device: DmaDeviceId) -> PlatformResult<()>;
+    
+    /// Map a page-aligned range of I/O virtual addresses to physical memory
+    fn map(&mut self, domain: IommuDomainId, iova: u64, physical: PhysicalAddress, size: usize, writable: bool) -> PlatformResult<()>;
+    
+    /// Remove a mapping and flush the IOTLB for it
+    fn unmap(&mut self, domain: IommuDomainId, iova: u64, size: usize) -> PlatformResult<()>;
 }
 
 /// Memory management operations trait
@@ -146,6 +241,22 @@ pub trait TimerOperations: Send + Sync {
     fn stop_timer(&mut self) -> PlatformResult<()>;
 }
 
+/// Console UART trait
+///
+/// The serial line `serial_print!` and the kernel log write to, and raw
+/// console input is read from. Transmission waits for the UART; reception
+/// never does.
+pub trait ConsoleUart: Send + Sync {
+    /// Set 8 data bits, no parity, one stop bit and enable the FIFOs
+    fn init(&mut self);
+    
+    /// Send one byte, waiting until the transmitter has room
+    fn write_byte(&mut self, byte: u8);
+    
+    /// Take a received byte, if one is waiting
+    fn read_byte(&mut self) -> Option<u8>;
+}
+
 /// Platform-specific constants
 #[derive(Debug, Clone, Copy)]
 pub struct PlatformConstants {
@@ -171,6 +282,8 @@ pub struct CpuContext {
 pub enum PlatformRegisters {
     X86_64(crate::platform::x86_64::X86_64Registers),
     AArch64(crate::platform::aarch64::AArch64Registers),
+    #[cfg(target_arch = "riscv64")]
+    RiscV64(crate::platform::riscv64::RiscV64Registers),
     Unsupported,
 }
 
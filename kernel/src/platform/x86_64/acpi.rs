@@ -0,0 +1,1039 @@
+//! ACPI tables on x86-64
+//!
+//! The boot loader hands over a copy of the RSDP, which points at the RSDT
+//! (ACPI 1.0) or the XSDT (ACPI 2.0 and later). Every table either lists is
+//! checked and kept, so other code can look tables up by signature; two are
+//! parsed here:
+//!
+//! - the MADT, for the local APIC of every CPU, the I/O APICs and the ISA
+//!   interrupt overrides, which SMP bring-up starts CPUs by;
+//! - the FADT, for the PM1 control blocks, the reset register and whether
+//!   the power button is a fixed feature, which power management uses to
+//!   reset and power off the machine.
+//!
+//! Powering off also needs the S5 sleep type from the DSDT. Rather than
+//! carry an AML interpreter, the `\_S5_` package is found by scanning the
+//! DSDT's bytes, which firmware encodes the same way everywhere. The
+//! processor performance states of the first static `_PSS` package in the
+//! DSDT or an SSDT are found the same way, for CPU frequency scaling;
+//! firmware that builds `_PSS` in a method is not understood. Devices can
+//! be looked up by `_HID` too, and thermal zones listed, and the objects
+//! they define read where they are constants.
+//!
+//! Only the fixed hardware of the legacy PM1 port blocks is supported, not
+//! hardware-reduced ACPI.
+
+use alloc::vec::Vec;
+use spin::Once;
+use x86_64::instructions::port::Port;
+use super::super::{PlatformResult, PlatformError};
+use super::timer;
+use crate::memory::vmm::kernel_layout::PHYSICAL_MEMORY_OFFSET;
+
+/// Length of the header every system description table starts with
+const SDT_HEADER_LEN: usize = 36;
+
+/// Longest table accepted; a larger length means a corrupt header
+const MAX_TABLE_LEN: usize = 1 << 20;
+
+/// MADT interrupt controller structure types
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_IO_APIC: u8 = 1;
+const MADT_INTERRUPT_OVERRIDE: u8 = 2;
+const MADT_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+const MADT_LOCAL_X2APIC: u8 = 9;
+
+/// Offset of the first MADT interrupt controller structure
+const MADT_ENTRIES_OFFSET: usize = 44;
+
+/// MADT flag: the machine also has dual 8259 PICs
+const MADT_PCAT_COMPAT: u32 = 1 << 0;
+
+/// Local APIC flags
+const LAPIC_ENABLED: u32 = 1 << 0;
+const LAPIC_ONLINE_CAPABLE: u32 = 1 << 1;
+
+/// Length of the ACPI 1.0 FADT, the shortest accepted
+const FADT_V1_LEN: usize = 116;
+
+/// FADT offsets of the fields past the ACPI 1.0 table
+const FADT_RESET_REGISTER: usize = 116;
+const FADT_RESET_VALUE: usize = 128;
+const FADT_X_DSDT: usize = 140;
+
+/// FADT flags
+const FADT_PWR_BUTTON: u32 = 1 << 4;
+const FADT_RESET_REG_SUP: u32 = 1 << 10;
+
+/// Generic address structure address spaces
+const GAS_SYSTEM_MEMORY: u8 = 0;
+const GAS_SYSTEM_IO: u8 = 1;
+
+/// PM1 control register bits
+const PM1_SCI_EN: u16 = 1 << 0;
+const PM1_SLP_TYP_SHIFT: u16 = 10;
+const PM1_SLP_TYP_MASK: u16 = 0b111 << PM1_SLP_TYP_SHIFT;
+const PM1_SLP_EN: u16 = 1 << 13;
+
+/// AML opcodes met on the way to the `\_S5_` and `_PSS` packages and
+/// device objects
+const AML_ZERO_OP: u8 = 0x00;
+const AML_ONE_OP: u8 = 0x01;
+const AML_NAME_OP: u8 = 0x08;
+const AML_BYTE_PREFIX: u8 = 0x0A;
+const AML_WORD_PREFIX: u8 = 0x0B;
+const AML_DWORD_PREFIX: u8 = 0x0C;
+const AML_STRING_PREFIX: u8 = 0x0D;
+const AML_QWORD_PREFIX: u8 = 0x0E;
+const AML_PACKAGE_OP: u8 = 0x12;
+const AML_METHOD_OP: u8 = 0x14;
+const AML_DUAL_NAME_PREFIX: u8 = 0x2E;
+const AML_MULTI_NAME_PREFIX: u8 = 0x2F;
+const AML_EXT_OP_PREFIX: u8 = 0x5B;
+const AML_DEVICE_OP: u8 = 0x82;
+const AML_THERMAL_ZONE_OP: u8 = 0x85;
+const AML_RETURN_OP: u8 = 0xA4;
+const AML_ONES_OP: u8 = 0xFF;
+const AML_ROOT_CHAR: u8 = b'\\';
+const AML_PARENT_PREFIX_CHAR: u8 = b'^';
+
+/// Integers in each `_PSS` entry
+const PSS_ENTRY_ELEMENTS: u8 = 6;
+
+/// Most performance states kept
+const MAX_PERFORMANCE_STATES: usize = 16;
+
+/// Time firmware gets to switch to ACPI mode, in milliseconds
+const ACPI_ENABLE_TIMEOUT_MS: u32 = 300;
+
+/// Time a reset or power-off gets to take effect before the caller falls
+/// back to something else
+const TRANSITION_WAIT_US: u64 = 100_000;
+
+/// Where the RSDP the boot loader found points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootTable {
+    /// Physical address of the RSDT, with 32-bit table pointers
+    Rsdt(u64),
+    /// Physical address of the XSDT, with 64-bit table pointers
+    Xsdt(u64),
+}
+
+/// A processor's local APIC, from the MADT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalApic {
+    /// ACPI processor UID, matching the processor objects in the DSDT
+    pub processor_uid: u32,
+    pub apic_id: u32,
+    /// Usable now
+    pub enabled: bool,
+    /// Not usable now, but can be brought online later
+    pub online_capable: bool,
+}
+
+/// An I/O APIC, from the MADT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u32,
+    /// First global system interrupt it handles
+    pub gsi_base: u32,
+}
+
+/// An ISA interrupt wired to a different global system interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptOverride {
+    pub source: u8,
+    pub gsi: u32,
+    /// MPS INTI polarity and trigger mode flags
+    pub flags: u16,
+}
+
+/// Contents of the ACPI MADT
+#[derive(Debug, Clone)]
+pub struct MadtInfo {
+    pub local_apic_address: u64,
+    /// Whether the legacy 8259 PICs are present as well
+    pub legacy_pics: bool,
+    /// Every processor, in table order
+    pub processors: Vec<LocalApic>,
+    pub io_apics: Vec<IoApic>,
+    pub overrides: Vec<InterruptOverride>,
+}
+
+impl MadtInfo {
+    /// Processors that can be started now
+    pub fn enabled_processors(&self) -> impl Iterator<Item = &LocalApic> {
+        self.processors.iter().filter(|processor| processor.enabled)
+    }
+
+    /// APIC IDs of the processors to start besides the boot CPU, in the
+    /// order firmware lists them
+    pub fn secondary_apic_ids(&self, boot_apic_id: u32) -> impl Iterator<Item = u32> + '_ {
+        self.enabled_processors()
+            .map(|processor| processor.apic_id)
+            .filter(move |&apic_id| apic_id != boot_apic_id)
+    }
+}
+
+/// A register described by an ACPI generic address structure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+/// Contents of the ACPI FADT that power management uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FadtInfo {
+    pub dsdt_address: u64,
+    /// Interrupt the SCI is wired to, in 8259 mode
+    pub sci_interrupt: u16,
+    /// Port that switches the machine into ACPI mode, 0 if it always is
+    pub smi_command: u32,
+    pub acpi_enable: u8,
+    pub pm1a_event_block: u32,
+    pub pm1b_event_block: u32,
+    pub pm1_event_length: u8,
+    pub pm1a_control_block: u32,
+    pub pm1b_control_block: u32,
+    /// CMOS register holding the century, 0 if there is none
+    pub century_register: u8,
+    /// Whether the power button is a fixed feature, signalled through the
+    /// PM1 status register, rather than a control method device
+    pub fixed_power_button: bool,
+    /// Register that resets the machine, with `reset_value` written to it
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+}
+
+/// A processor performance state (P-state) from a `_PSS` package
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerformanceState {
+    pub frequency_mhz: u32,
+    pub power_mw: u32,
+    pub latency_us: u32,
+    /// Written to the performance control register to enter the state
+    pub control: u64,
+    /// Read from the performance status register once in the state
+    pub status: u64,
+}
+
+/// The value of an object a device defines, where a scan can tell it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmlValue<'a> {
+    Integer(u64),
+    /// Without its terminating NUL
+    String(&'a [u8]),
+    /// Offset of the package in the AML it was found in
+    Package(usize),
+}
+
+/// What the ACPI tables describe
+#[derive(Debug)]
+pub struct AcpiInfo {
+    pub madt: Option<MadtInfo>,
+    pub fadt: Option<FadtInfo>,
+    /// SLP_TYPa and SLP_TYPb values of the S5 (soft off) state
+    pub s5_sleep_type: Option<(u8, u8)>,
+    /// P-states of the processors, fastest first as `_PSS` lists them
+    pub performance_states: Vec<PerformanceState>,
+    dsdt: Option<&'static [u8]>,
+    tables: Vec<&'static [u8]>,
+}
+
+impl AcpiInfo {
+    /// The table with `signature`, checksum verified
+    pub fn table(&self, signature: &[u8; 4]) -> Option<&'static [u8]> {
+        self.tables.iter().copied().find(|table| table[..4] == signature[..])
+    }
+
+    /// Signatures of every table found, in the order the root table lists them
+    pub fn signatures(&self) -> impl Iterator<Item = &[u8]> {
+        self.tables.iter().map(|table| &table[..4])
+    }
+
+    /// The AML of the DSDT and then of every SSDT, headers left off
+    pub fn definition_blocks(&self) -> impl Iterator<Item = &'static [u8]> + '_ {
+        self.dsdt.into_iter()
+            .chain(self.tables.iter().copied().filter(|table| table[..4] == b"SSDT"[..]))
+            .map(|table| &table[SDT_HEADER_LEN..])
+    }
+}
+
+static ACPI: Once<AcpiInfo> = Once::new();
+
+pub(super) fn read_u16(bytes: &[u8], offset: usize) -> PlatformResult<u16> {
+    let raw = bytes.get(offset..offset + 2).ok_or(PlatformError::InvalidAddress)?;
+    Ok(u16::from_le_bytes([raw[0], raw[1]]))
+}
+
+pub(super) fn read_u32(bytes: &[u8], offset: usize) -> PlatformResult<u32> {
+    let raw = bytes.get(offset..offset + 4).ok_or(PlatformError::InvalidAddress)?;
+    let mut value = [0u8; 4];
+    value.copy_from_slice(raw);
+    Ok(u32::from_le_bytes(value))
+}
+
+pub(super) fn read_u64(bytes: &[u8], offset: usize) -> PlatformResult<u64> {
+    let raw = bytes.get(offset..offset + 8).ok_or(PlatformError::InvalidAddress)?;
+    let mut value = [0u8; 8];
+    value.copy_from_slice(raw);
+    Ok(u64::from_le_bytes(value))
+}
+
+/// `table` cut to the length its header gives, if its checksum holds
+fn checked_table(table: &[u8]) -> PlatformResult<&[u8]> {
+    let length = read_u32(table, 4)? as usize;
+    if length < SDT_HEADER_LEN || length > table.len() {
+        return Err(PlatformError::InvalidAddress);
+    }
+    let table = &table[..length];
+    if table.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+        return Err(PlatformError::InvalidAddress);
+    }
+    Ok(table)
+}
+
+/// Physical addresses of the tables an RSDT or XSDT lists
+fn root_entries(root: &[u8], entry_size: usize) -> Vec<u64> {
+    root[SDT_HEADER_LEN..]
+        .chunks_exact(entry_size)
+        .map(|entry| if entry_size == 8 {
+            read_u64(entry, 0).unwrap_or(0)
+        } else {
+            read_u32(entry, 0).unwrap_or(0) as u64
+        })
+        .filter(|&address| address != 0)
+        .collect()
+}
+
+/// Parse an ACPI MADT
+pub fn parse_madt(table: &[u8]) -> PlatformResult<MadtInfo> {
+    if table.get(0..4) != Some(b"APIC".as_slice()) {
+        return Err(PlatformError::InvalidAddress);
+    }
+
+    let length = read_u32(table, 4)? as usize;
+    if length > table.len() || length < MADT_ENTRIES_OFFSET {
+        return Err(PlatformError::InvalidAddress);
+    }
+    let table = &table[..length];
+
+    let mut info = MadtInfo {
+        local_apic_address: read_u32(table, 36)? as u64,
+        legacy_pics: read_u32(table, 40)? & MADT_PCAT_COMPAT != 0,
+        processors: Vec::new(),
+        io_apics: Vec::new(),
+        overrides: Vec::new(),
+    };
+
+    let mut offset = MADT_ENTRIES_OFFSET;
+    while offset + 2 <= table.len() {
+        let entry_type = table[offset];
+        let entry_length = table[offset + 1] as usize;
+        if entry_length < 2 || offset + entry_length > table.len() {
+            return Err(PlatformError::InvalidAddress);
+        }
+        let entry = &table[offset..offset + entry_length];
+
+        match entry_type {
+            MADT_LOCAL_APIC if entry_length >= 8 => {
+                let flags = read_u32(entry, 4)?;
+                info.processors.push(LocalApic {
+                    processor_uid: entry[2] as u32,
+                    apic_id: entry[3] as u32,
+                    enabled: flags & LAPIC_ENABLED != 0,
+                    online_capable: flags & LAPIC_ONLINE_CAPABLE != 0,
+                });
+            }
+            MADT_IO_APIC if entry_length >= 12 => {
+                info.io_apics.push(IoApic {
+                    id: entry[2],
+                    address: read_u32(entry, 4)?,
+                    gsi_base: read_u32(entry, 8)?,
+                });
+            }
+            MADT_INTERRUPT_OVERRIDE if entry_length >= 10 => {
+                info.overrides.push(InterruptOverride {
+                    source: entry[3],
+                    gsi: read_u32(entry, 4)?,
+                    flags: read_u16(entry, 8)?,
+                });
+            }
+            MADT_LOCAL_APIC_ADDRESS_OVERRIDE if entry_length >= 12 => {
+                info.local_apic_address = read_u64(entry, 4)?;
+            }
+            MADT_LOCAL_X2APIC if entry_length >= 16 => {
+                let apic_id = read_u32(entry, 4)?;
+                // Firmware may list a processor in both forms
+                if !info.processors.iter().any(|processor| processor.apic_id == apic_id) {
+                    let flags = read_u32(entry, 8)?;
+                    info.processors.push(LocalApic {
+                        processor_uid: read_u32(entry, 12)?,
+                        apic_id,
+                        enabled: flags & LAPIC_ENABLED != 0,
+                        online_capable: flags & LAPIC_ONLINE_CAPABLE != 0,
+                    });
+                }
+            }
+            // NMI sources and the other structures are not used yet
+            _ => {}
+        }
+
+        offset += entry_length;
+    }
+
+    Ok(info)
+}
+
+fn parse_generic_address(bytes: &[u8], offset: usize) -> PlatformResult<GenericAddress> {
+    let raw = bytes.get(offset..offset + 12).ok_or(PlatformError::InvalidAddress)?;
+    Ok(GenericAddress {
+        address_space: raw[0],
+        bit_width: raw[1],
+        bit_offset: raw[2],
+        access_size: raw[3],
+        address: read_u64(raw, 4)?,
+    })
+}
+
+/// Parse an ACPI FADT
+pub fn parse_fadt(table: &[u8]) -> PlatformResult<FadtInfo> {
+    if table.get(0..4) != Some(b"FACP".as_slice()) {
+        return Err(PlatformError::InvalidAddress);
+    }
+
+    let length = read_u32(table, 4)? as usize;
+    if length > table.len() || length < FADT_V1_LEN {
+        return Err(PlatformError::InvalidAddress);
+    }
+    let table = &table[..length];
+
+    let flags = read_u32(table, 112)?;
+    let mut reset_register = None;
+    let mut reset_value = 0;
+    if flags & FADT_RESET_REG_SUP != 0 && length > FADT_RESET_VALUE {
+        let register = parse_generic_address(table, FADT_RESET_REGISTER)?;
+        if register.address != 0 {
+            reset_register = Some(register);
+            reset_value = table[FADT_RESET_VALUE];
+        }
+    }
+
+    // The 64-bit DSDT pointer wins where both are given
+    let x_dsdt = if length >= FADT_X_DSDT + 8 { read_u64(table, FADT_X_DSDT)? } else { 0 };
+    let dsdt_address = if x_dsdt != 0 { x_dsdt } else { read_u32(table, 40)? as u64 };
+
+    Ok(FadtInfo {
+        dsdt_address,
+        sci_interrupt: read_u16(table, 46)?,
+        smi_command: read_u32(table, 48)?,
+        acpi_enable: table[52],
+        pm1a_event_block: read_u32(table, 56)?,
+        pm1b_event_block: read_u32(table, 60)?,
+        pm1_event_length: table[88],
+        pm1a_control_block: read_u32(table, 64)?,
+        pm1b_control_block: read_u32(table, 68)?,
+        century_register: table[108],
+        fixed_power_button: flags & FADT_PWR_BUTTON == 0,
+        reset_register,
+        reset_value,
+    })
+}
+
+/// An AML integer constant, and the offset after it
+fn aml_integer(aml: &[u8], offset: usize) -> Option<(u64, usize)> {
+    let width = match *aml.get(offset)? {
+        AML_ZERO_OP => return Some((0, offset + 1)),
+        AML_ONE_OP => return Some((1, offset + 1)),
+        AML_ONES_OP => return Some((u64::MAX, offset + 1)),
+        AML_BYTE_PREFIX => 1,
+        AML_WORD_PREFIX => 2,
+        AML_DWORD_PREFIX => 4,
+        AML_QWORD_PREFIX => 8,
+        _ => return None,
+    };
+    let bytes = aml.get(offset + 1..offset + 1 + width)?;
+    let value = bytes.iter().rev().fold(0u64, |value, &byte| value << 8 | byte as u64);
+    Some((value, offset + 1 + width))
+}
+
+/// An AML integer constant small enough for a sleep type, and the offset
+/// after it
+fn aml_small_integer(aml: &[u8], offset: usize) -> Option<(u8, usize)> {
+    let (value, next) = aml_integer(aml, offset)?;
+    Some((u8::try_from(value).ok()?, next))
+}
+
+/// The PkgLength at `offset`, which counts itself and what follows it, and
+/// the offset after it
+///
+/// The lead byte's top two bits count the bytes that follow it; with any,
+/// only its low nibble is part of the length.
+fn aml_package_length(aml: &[u8], offset: usize) -> Option<(usize, usize)> {
+    let lead = *aml.get(offset)?;
+    let extra = (lead >> 6) as usize;
+    if extra == 0 {
+        return Some(((lead & 0x3F) as usize, offset + 1));
+    }
+    let mut length = (lead & 0x0F) as usize;
+    for index in 0..extra {
+        length |= (*aml.get(offset + 1 + index)? as usize) << (4 + 8 * index);
+    }
+    Some((length, offset + 1 + extra))
+}
+
+/// Offset of the package a `Name` definition of `name` holds, the first
+/// one in `aml`
+fn named_package(aml: &[u8], name: &[u8; 4]) -> Option<usize> {
+    aml.windows(4).enumerate().find_map(|(position, window)| {
+        // A Name() definition, with or without the root prefix
+        let named = window == name && match position {
+            0 => false,
+            1 => aml[0] == AML_NAME_OP,
+            _ => aml[position - 1] == AML_NAME_OP
+                || (aml[position - 1] == AML_ROOT_CHAR && aml[position - 2] == AML_NAME_OP),
+        };
+        (named && aml.get(position + 4) == Some(&AML_PACKAGE_OP)).then_some(position + 4)
+    })
+}
+
+/// Offset of the first element of the package at `offset`, how many it
+/// has, and the offset after the package
+fn package_elements(aml: &[u8], offset: usize) -> Option<(usize, u8, usize)> {
+    if aml.get(offset) != Some(&AML_PACKAGE_OP) {
+        return None;
+    }
+    let (length, elements) = aml_package_length(aml, offset + 1)?;
+    Some((elements + 1, *aml.get(elements)?, offset + 1 + length))
+}
+
+/// A constant integer, string or package at `offset`, and the offset
+/// after it
+fn aml_value(aml: &[u8], offset: usize) -> Option<(AmlValue<'_>, usize)> {
+    match *aml.get(offset)? {
+        AML_STRING_PREFIX => {
+            let text = aml.get(offset + 1..)?;
+            let length = text.iter().position(|&byte| byte == 0)?;
+            Some((AmlValue::String(&text[..length]), offset + 2 + length))
+        }
+        AML_PACKAGE_OP => {
+            let (_, _, next) = package_elements(aml, offset)?;
+            Some((AmlValue::Package(offset), next))
+        }
+        _ => aml_integer(aml, offset).map(|(value, next)| (AmlValue::Integer(value), next)),
+    }
+}
+
+/// Up to `max` elements of the package at `offset`, as far as they are
+/// constants
+pub(super) fn package_values(aml: &[u8], offset: usize, max: usize) -> Vec<AmlValue<'_>> {
+    let mut values = Vec::new();
+    let Some((mut element, count, _)) = package_elements(aml, offset) else {
+        return values;
+    };
+    for _ in 0..(count as usize).min(max) {
+        let Some((value, next)) = aml_value(aml, element) else {
+            break;
+        };
+        values.push(value);
+        element = next;
+    }
+    values
+}
+
+/// Offset after the NameString at `offset`
+fn aml_name_string_end(aml: &[u8], offset: usize) -> Option<usize> {
+    let mut offset = offset;
+    if aml.get(offset) == Some(&AML_ROOT_CHAR) {
+        offset += 1;
+    } else {
+        while aml.get(offset) == Some(&AML_PARENT_PREFIX_CHAR) {
+            offset += 1;
+        }
+    }
+    let segments = match *aml.get(offset)? {
+        AML_ZERO_OP => return Some(offset + 1),
+        AML_DUAL_NAME_PREFIX => {
+            offset += 1;
+            2
+        }
+        AML_MULTI_NAME_PREFIX => {
+            offset += 2;
+            *aml.get(offset - 1)? as usize
+        }
+        _ => 1,
+    };
+    let end = offset + segments * 4;
+    (end <= aml.len()).then_some(end)
+}
+
+/// The compressed form of a seven-character EISA ID such as "PNP0C0A", as
+/// the `EISAID` macro stores it
+pub fn eisa_id(id: &str) -> Option<u32> {
+    let bytes = id.as_bytes();
+    if bytes.len() != 7 || !bytes[..3].iter().all(u8::is_ascii_uppercase) {
+        return None;
+    }
+    let vendor = bytes[..3].iter().fold(0u16, |vendor, &letter| vendor << 5 | (letter - b'@') as u16);
+    let product = u16::from_str_radix(&id[3..], 16).ok()?;
+    let [vendor_high, vendor_low] = vendor.to_be_bytes();
+    let [product_high, product_low] = product.to_be_bytes();
+    Some(u32::from_le_bytes([vendor_high, vendor_low, product_high, product_low]))
+}
+
+/// The value of object `name` a device body defines, the first one in it
+///
+/// That is a `Name` definition, or a method that does nothing but return
+/// a constant; None for a method that does more.
+pub(super) fn device_object<'a>(body: &'a [u8], name: &[u8; 4]) -> Option<AmlValue<'a>> {
+    for position in 0..body.len() {
+        match body[position] {
+            AML_NAME_OP if body.get(position + 1..position + 5) == Some(&name[..]) => {
+                return aml_value(body, position + 5).map(|(value, _)| value);
+            }
+            AML_METHOD_OP => {
+                let Some((_, method_name)) = aml_package_length(body, position + 1) else {
+                    continue;
+                };
+                if body.get(method_name..method_name + 4) != Some(&name[..]) {
+                    continue;
+                }
+                // After the name come the method flags, then the body
+                if body.get(method_name + 5) != Some(&AML_RETURN_OP) {
+                    return None;
+                }
+                return aml_value(body, method_name + 6).map(|(value, _)| value);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Last name segments and bodies of the objects extended opcode `op`
+/// opens in `aml`, such as `Device`
+fn scoped_objects(aml: &[u8], op: u8) -> Vec<([u8; 4], &[u8])> {
+    let mut objects = Vec::new();
+    for position in 0..aml.len().saturating_sub(1) {
+        if aml[position] != AML_EXT_OP_PREFIX || aml[position + 1] != op {
+            continue;
+        }
+        let Some((length, name)) = aml_package_length(aml, position + 2) else {
+            continue;
+        };
+        let end = position + 2 + length;
+        let Some(start) = aml_name_string_end(aml, name).filter(|&start| start >= name + 4 && start <= end && end <= aml.len()) else {
+            continue;
+        };
+        let mut segment = [0; 4];
+        segment.copy_from_slice(&aml[start - 4..start]);
+        objects.push((segment, &aml[start..end]));
+    }
+    objects
+}
+
+/// Bodies of the `Device` definitions in `aml` whose `_HID` is `id`
+///
+/// A seven-character EISA ID matches in its compressed form or as a
+/// string; an ACPI ID such as "ACPI0003" only as a string.
+pub(super) fn find_devices<'a>(aml: &'a [u8], id: &str) -> Vec<&'a [u8]> {
+    let compressed = eisa_id(id);
+    scoped_objects(aml, AML_DEVICE_OP).into_iter()
+        .map(|(_, body)| body)
+        .filter(|body| match device_object(body, b"_HID") {
+            Some(AmlValue::Integer(value)) => compressed == Some(value as u32),
+            Some(AmlValue::String(text)) => text == id.as_bytes(),
+            _ => false,
+        })
+        .collect()
+}
+
+/// Names and bodies of the `ThermalZone` definitions in `aml`
+pub(super) fn thermal_zones(aml: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    scoped_objects(aml, AML_THERMAL_ZONE_OP)
+}
+
+/// SLP_TYPa and SLP_TYPb of the `\_S5_` package in a DSDT
+pub fn parse_s5_sleep_type(dsdt: &[u8]) -> Option<(u8, u8)> {
+    let aml = dsdt.get(SDT_HEADER_LEN..)?;
+    let (elements, _, _) = package_elements(aml, named_package(aml, b"_S5_")?)?;
+    let (sleep_type_a, next) = aml_small_integer(aml, elements)?;
+    let (sleep_type_b, _) = aml_small_integer(aml, next)?;
+    Some((sleep_type_a, sleep_type_b))
+}
+
+/// The P-states of the first `_PSS` package in a DSDT or SSDT
+///
+/// Each entry is a package of six integers: frequency, power, transition
+/// latency, bus master latency, control and status. Malformed entries end
+/// the list.
+pub fn parse_performance_states(table: &[u8]) -> Vec<PerformanceState> {
+    let mut states = Vec::new();
+    let Some(aml) = table.get(SDT_HEADER_LEN..) else {
+        return states;
+    };
+    let Some((mut entry, count, _)) = named_package(aml, b"_PSS").and_then(|offset| package_elements(aml, offset)) else {
+        return states;
+    };
+
+    for _ in 0..(count as usize).min(MAX_PERFORMANCE_STATES) {
+        let Some((elements, PSS_ENTRY_ELEMENTS, next)) = package_elements(aml, entry) else {
+            break;
+        };
+        let mut fields = [0u64; PSS_ENTRY_ELEMENTS as usize];
+        let mut offset = elements;
+        for field in &mut fields {
+            let Some((value, after)) = aml_integer(aml, offset) else {
+                return states;
+            };
+            *field = value;
+            offset = after;
+        }
+        let [frequency, power, latency, _, control, status] = fields;
+        if frequency == 0 || frequency > u32::MAX as u64 {
+            break;
+        }
+        states.push(PerformanceState {
+            frequency_mhz: frequency as u32,
+            power_mw: power as u32,
+            latency_us: latency as u32,
+            control,
+            status,
+        });
+        entry = next;
+    }
+    states
+}
+
+fn phys_to_virt(physical: u64) -> *mut u8 {
+    (PHYSICAL_MEMORY_OFFSET.as_usize() as u64 + physical) as *mut u8
+}
+
+/// The table at `physical`, as long as its header says, checksum verified
+///
+/// # Safety
+///
+/// `physical` must be the address of an ACPI table.
+unsafe fn map_table(physical: u64) -> PlatformResult<&'static [u8]> {
+    let header = core::slice::from_raw_parts(phys_to_virt(physical), SDT_HEADER_LEN);
+    let length = read_u32(header, 4)? as usize;
+    if length > MAX_TABLE_LEN {
+        return Err(PlatformError::InvalidAddress);
+    }
+    checked_table(core::slice::from_raw_parts(phys_to_virt(physical), length))
+}
+
+/// Find and parse the ACPI tables `root` leads to
+pub fn init(root: RootTable) -> PlatformResult<&'static AcpiInfo> {
+    let (address, signature, entry_size) = match root {
+        RootTable::Rsdt(address) => (address, b"RSDT", 4),
+        RootTable::Xsdt(address) => (address, b"XSDT", 8),
+    };
+    let root = unsafe { map_table(address)? };
+    if root[..4] != signature[..] {
+        return Err(PlatformError::InvalidAddress);
+    }
+
+    let mut tables = Vec::new();
+    for address in root_entries(root, entry_size) {
+        match unsafe { map_table(address) } {
+            Ok(table) => tables.push(table),
+            Err(_) => log::warn!("ACPI: skipping the corrupt table at 0x{:x}", address),
+        }
+    }
+
+    let find = |signature: &[u8; 4]| tables.iter().copied().find(|table| table[..4] == signature[..]);
+    let madt = find(b"APIC").and_then(|table| parse_madt(table).ok());
+    let fadt = find(b"FACP").and_then(|table| parse_fadt(table).ok());
+    let dsdt = fadt
+        .filter(|fadt| fadt.dsdt_address != 0)
+        .and_then(|fadt| unsafe { map_table(fadt.dsdt_address) }.ok())
+        .filter(|dsdt| dsdt[..4] == b"DSDT"[..]);
+    let s5_sleep_type = dsdt.and_then(parse_s5_sleep_type);
+    let performance_states = dsdt.into_iter()
+        .chain(tables.iter().copied().filter(|table| table[..4] == b"SSDT"[..]))
+        .map(parse_performance_states)
+        .find(|states| !states.is_empty())
+        .unwrap_or_default();
+
+    Ok(ACPI.call_once(|| AcpiInfo { madt, fadt, s5_sleep_type, performance_states, dsdt, tables }))
+}
+
+/// The ACPI tables, once `init` has found them
+pub fn info() -> Option<&'static AcpiInfo> {
+    ACPI.get()
+}
+
+pub fn madt() -> Option<&'static MadtInfo> {
+    info().and_then(|info| info.madt.as_ref())
+}
+
+pub fn fadt() -> Option<&'static FadtInfo> {
+    info().and_then(|info| info.fadt.as_ref())
+}
+
+/// The processors' P-states, fastest first; empty without a `_PSS`
+pub fn performance_states() -> &'static [PerformanceState] {
+    info().map_or(&[], |info| &info.performance_states)
+}
+
+/// Write `value` to the register `register` describes
+///
+/// Reset registers in PCI configuration space are not supported.
+fn write_register(register: GenericAddress, value: u8) {
+    unsafe {
+        match register.address_space {
+            GAS_SYSTEM_IO => Port::<u8>::new(register.address as u16).write(value),
+            GAS_SYSTEM_MEMORY => core::ptr::write_volatile(phys_to_virt(register.address), value),
+            _ => {}
+        }
+    }
+}
+
+/// Reset the machine through the FADT reset register
+///
+/// Returns if there is none, or it had no effect.
+pub fn reset() {
+    if let Some(fadt) = fadt() {
+        if let Some(register) = fadt.reset_register {
+            write_register(register, fadt.reset_value);
+            timer::busy_wait_us(TRANSITION_WAIT_US);
+        }
+    }
+}
+
+/// Switch the machine from legacy to ACPI mode, if firmware left it in
+/// legacy mode
+fn enable_acpi_mode(fadt: &FadtInfo) {
+    let mut control = Port::<u16>::new(fadt.pm1a_control_block as u16);
+    if unsafe { control.read() } & PM1_SCI_EN != 0 || fadt.smi_command == 0 || fadt.acpi_enable == 0 {
+        return;
+    }
+
+    unsafe { Port::<u8>::new(fadt.smi_command as u16).write(fadt.acpi_enable) };
+    for _ in 0..ACPI_ENABLE_TIMEOUT_MS {
+        if unsafe { control.read() } & PM1_SCI_EN != 0 {
+            return;
+        }
+        timer::busy_wait_us(1000);
+    }
+    log::warn!("ACPI: firmware did not switch to ACPI mode");
+}
+
+/// Put the sleep type `sleep_type` in the PM1 control register at `port`
+/// and enter it
+fn enter_sleep_type(port: u32, sleep_type: u8) {
+    let mut control = Port::<u16>::new(port as u16);
+    unsafe {
+        let value = control.read() & !PM1_SLP_TYP_MASK;
+        control.write(value | ((sleep_type as u16) << PM1_SLP_TYP_SHIFT) | PM1_SLP_EN);
+    }
+}
+
+/// Power the machine off by entering the S5 sleep state
+///
+/// Returns if the FADT or DSDT do not describe it, or it had no effect.
+pub fn shutdown() {
+    let info = match info() {
+        Some(info) => info,
+        None => return,
+    };
+    let (fadt, (sleep_type_a, sleep_type_b)) = match (info.fadt.as_ref(), info.s5_sleep_type) {
+        (Some(fadt), Some(sleep_types)) if fadt.pm1a_control_block != 0 => (fadt, sleep_types),
+        _ => return,
+    };
+
+    enable_acpi_mode(fadt);
+    enter_sleep_type(fadt.pm1a_control_block, sleep_type_a);
+    if fadt.pm1b_control_block != 0 {
+        enter_sleep_type(fadt.pm1b_control_block, sleep_type_b);
+    }
+    timer::busy_wait_us(TRANSITION_WAIT_US);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// A table with `signature` and `body` after the header, its length
+    /// and checksum filled in
+    fn table(signature: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut table = vec![0u8; SDT_HEADER_LEN];
+        table[0..4].copy_from_slice(signature);
+        table.extend_from_slice(body);
+        let length = table.len() as u32;
+        table[4..8].copy_from_slice(&length.to_le_bytes());
+        let sum = table.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        table[9] = 0u8.wrapping_sub(sum);
+        table
+    }
+
+    fn madt_table() -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&0xFEE0_0000u32.to_le_bytes());
+        body.extend_from_slice(&MADT_PCAT_COMPAT.to_le_bytes());
+        // Processors with APIC IDs 0, 2 (disabled) and 4
+        body.extend_from_slice(&[MADT_LOCAL_APIC, 8, 0, 0, 1, 0, 0, 0]);
+        body.extend_from_slice(&[MADT_LOCAL_APIC, 8, 1, 2, 2, 0, 0, 0]);
+        body.extend_from_slice(&[MADT_LOCAL_APIC, 8, 2, 4, 1, 0, 0, 0]);
+        // The same processor again as an x2APIC
+        body.extend_from_slice(&[MADT_LOCAL_X2APIC, 16, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0]);
+        // I/O APIC 1 at 0xFEC00000 from GSI 0
+        body.extend_from_slice(&[MADT_IO_APIC, 12, 1, 0, 0x00, 0x00, 0xC0, 0xFE, 0, 0, 0, 0]);
+        // The PIT's IRQ 0 on GSI 2
+        body.extend_from_slice(&[MADT_INTERRUPT_OVERRIDE, 10, 0, 0, 2, 0, 0, 0, 0, 0]);
+        table(b"APIC", &body)
+    }
+
+    #[test_case]
+    fn test_parse_madt() {
+        let info = parse_madt(&madt_table()).unwrap();
+
+        assert_eq!(info.local_apic_address, 0xFEE0_0000);
+        assert!(info.legacy_pics);
+        assert_eq!(info.processors.len(), 3);
+        assert!(!info.processors[1].enabled);
+        assert!(info.processors[1].online_capable);
+        assert_eq!(info.enabled_processors().count(), 2);
+        assert_eq!(info.secondary_apic_ids(0).collect::<Vec<_>>(), vec![4]);
+        assert_eq!(info.secondary_apic_ids(4).collect::<Vec<_>>(), vec![0]);
+
+        assert_eq!(info.io_apics, vec![IoApic { id: 1, address: 0xFEC0_0000, gsi_base: 0 }]);
+        assert_eq!(info.overrides, vec![InterruptOverride { source: 0, gsi: 2, flags: 0 }]);
+    }
+
+    #[test_case]
+    fn test_parse_fadt() {
+        let mut body = vec![0u8; 244 - SDT_HEADER_LEN];
+        let mut put = |offset: usize, bytes: &[u8]| {
+            body[offset - SDT_HEADER_LEN..offset - SDT_HEADER_LEN + bytes.len()].copy_from_slice(bytes)
+        };
+        put(40, &0x7FE0_0040u32.to_le_bytes());
+        put(46, &9u16.to_le_bytes());
+        put(48, &0xB2u32.to_le_bytes());
+        put(52, &[0xF1]);
+        put(56, &0x600u32.to_le_bytes());
+        put(64, &0x604u32.to_le_bytes());
+        put(88, &[4]);
+        put(108, &[0x32]);
+        put(112, &FADT_RESET_REG_SUP.to_le_bytes());
+        put(FADT_RESET_REGISTER, &[GAS_SYSTEM_IO, 8, 0, 1, 0xF9, 0x0C, 0, 0, 0, 0, 0, 0]);
+        put(FADT_RESET_VALUE, &[0x0F]);
+        let info = parse_fadt(&table(b"FACP", &body)).unwrap();
+
+        assert_eq!(info.dsdt_address, 0x7FE0_0040);
+        assert_eq!(info.sci_interrupt, 9);
+        assert_eq!(info.smi_command, 0xB2);
+        assert_eq!(info.pm1a_event_block, 0x600);
+        assert_eq!(info.pm1a_control_block, 0x604);
+        assert_eq!(info.pm1b_control_block, 0);
+        assert_eq!(info.century_register, 0x32);
+        assert!(info.fixed_power_button);
+        assert_eq!(info.reset_register.map(|register| (register.address_space, register.address)), Some((GAS_SYSTEM_IO, 0xCF9)));
+        assert_eq!(info.reset_value, 0x0F);
+
+        assert!(parse_fadt(&table(b"FACP", &body[..40])).is_err());
+    }
+
+    #[test_case]
+    fn test_parse_s5_sleep_type() {
+        // Scope (\) { Name (_S5_, Package (0x04) { 0x05, Zero, Zero, Zero }) }
+        let aml = [0x10, 0x0E, b'\\', AML_NAME_OP, b'_', b'S', b'5', b'_', AML_PACKAGE_OP, 0x07, 0x04,
+            AML_BYTE_PREFIX, 0x05, AML_ZERO_OP, AML_ZERO_OP, AML_ZERO_OP];
+        assert_eq!(parse_s5_sleep_type(&table(b"DSDT", &aml)), Some((5, 0)));
+
+        let rooted = [AML_NAME_OP, AML_ROOT_CHAR, b'_', b'S', b'5', b'_', AML_PACKAGE_OP, 0x06, 0x02,
+            AML_ONE_OP, AML_BYTE_PREFIX, 0x07];
+        assert_eq!(parse_s5_sleep_type(&table(b"DSDT", &rooted)), Some((1, 7)));
+
+        // A reference to _S5_ rather than its definition
+        let reference = [0x70, b'_', b'S', b'5', b'_', AML_PACKAGE_OP, 0x04, 0x01, AML_ONE_OP];
+        assert_eq!(parse_s5_sleep_type(&table(b"DSDT", &reference)), None);
+    }
+
+    #[test_case]
+    fn test_parse_performance_states() {
+        // Name (_PSS, Package (0x02) {
+        //     Package (0x06) { 0x0960, 0x61A8, 0x0A, 0x0A, 0x1800, 0x1800 },
+        //     Package (0x06) { 0x0320, 0x2710, 0x0A, 0x0A, 0x0800, 0x0800 } })
+        let entry = |frequency: u16, power: u16, ratio: u8| {
+            let [frequency_low, frequency_high] = frequency.to_le_bytes();
+            let [power_low, power_high] = power.to_le_bytes();
+            vec![AML_PACKAGE_OP, 0x14, 0x06,
+                AML_WORD_PREFIX, frequency_low, frequency_high, AML_WORD_PREFIX, power_low, power_high,
+                AML_BYTE_PREFIX, 0x0A, AML_BYTE_PREFIX, 0x0A,
+                AML_WORD_PREFIX, 0x00, ratio, AML_DWORD_PREFIX, 0x00, ratio, 0x00, 0x00]
+        };
+        let mut aml = vec![AML_NAME_OP, b'_', b'P', b'S', b'S', AML_PACKAGE_OP, 0x2C, 0x02];
+        aml.extend(entry(2400, 25000, 0x18));
+        aml.extend(entry(800, 10000, 0x08));
+        let states = parse_performance_states(&table(b"SSDT", &aml));
+
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[0], PerformanceState { frequency_mhz: 2400, power_mw: 25000, latency_us: 10, control: 0x1800, status: 0x1800 });
+        assert_eq!(states[1].frequency_mhz, 800);
+        assert_eq!(states[1].control, 0x0800);
+
+        // A truncated second entry ends the list
+        let cut = aml.len() - 3;
+        assert_eq!(parse_performance_states(&table(b"SSDT", &aml[..cut])).len(), 1);
+        assert!(parse_performance_states(&table(b"DSDT", &[AML_ZERO_OP])).is_empty());
+    }
+
+    #[test_case]
+    fn test_devices_are_found_by_hid() {
+        // Device (BAT0) { Name (_HID, EisaId ("PNP0C0A")) Name (_STA, 0x1F)
+        //     Method (_BST) { Return (Package (0x04) { Zero, 0x03E8, 0x2710, 0x2EE0 }) }
+        //     Method (_BIF) { Store (...) } }
+        let mut body = vec![b'B', b'A', b'T', b'0'];
+        body.extend_from_slice(&[AML_NAME_OP, b'_', b'H', b'I', b'D', AML_DWORD_PREFIX, 0x41, 0xD0, 0x0C, 0x0A]);
+        body.extend_from_slice(&[AML_NAME_OP, b'_', b'S', b'T', b'A', AML_BYTE_PREFIX, 0x1F]);
+        body.extend_from_slice(&[AML_METHOD_OP, 0x14, b'_', b'B', b'S', b'T', 0x00, AML_RETURN_OP,
+            AML_PACKAGE_OP, 0x0C, 0x04, AML_ZERO_OP, AML_WORD_PREFIX, 0xE8, 0x03,
+            AML_WORD_PREFIX, 0x10, 0x27, AML_WORD_PREFIX, 0xE0, 0x2E]);
+        body.extend_from_slice(&[AML_METHOD_OP, 0x08, b'_', b'B', b'I', b'F', 0x00, 0x70, 0x00]);
+        let mut aml = vec![AML_EXT_OP_PREFIX, AML_DEVICE_OP, body.len() as u8 + 1];
+        aml.extend_from_slice(&body);
+        // Device (ADP1) { Name (_HID, "ACPI0003") }
+        aml.extend_from_slice(&[AML_EXT_OP_PREFIX, AML_DEVICE_OP, 0x14, b'A', b'D', b'P', b'1',
+            AML_NAME_OP, b'_', b'H', b'I', b'D', AML_STRING_PREFIX]);
+        aml.extend_from_slice(b"ACPI0003\0");
+
+        assert_eq!(eisa_id("PNP0C0A"), Some(0x0A0C_D041));
+        let batteries = find_devices(&aml, "PNP0C0A");
+        assert_eq!(batteries.len(), 1);
+        let battery = batteries[0];
+        assert_eq!(device_object(battery, b"_STA"), Some(AmlValue::Integer(0x1F)));
+        let Some(AmlValue::Package(status)) = device_object(battery, b"_BST") else {
+            panic!("_BST is a constant package");
+        };
+        assert_eq!(package_values(battery, status, 8), vec![
+            AmlValue::Integer(0), AmlValue::Integer(1000), AmlValue::Integer(10000), AmlValue::Integer(12000),
+        ]);
+        // A method that does more than return a constant
+        assert_eq!(device_object(battery, b"_BIF"), None);
+
+        assert_eq!(find_devices(&aml, "ACPI0003").len(), 1);
+        assert!(find_devices(&aml, "PNP0C0D").is_empty());
+    }
+
+    #[test_case]
+    fn test_tables_are_checked() {
+        let mut madt = madt_table();
+        assert!(checked_table(&madt).is_ok());
+        madt[20] ^= 1;
+        assert!(checked_table(&madt).is_err());
+        assert!(checked_table(&madt[..20]).is_err());
+
+        let mut rsdt = table(b"RSDT", &[0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00]);
+        assert_eq!(root_entries(&rsdt, 4), vec![0x1000, 0x2000]);
+        rsdt.truncate(SDT_HEADER_LEN + 8);
+        assert_eq!(root_entries(&rsdt, 8), vec![0x1000]);
+    }
+}
@@ -0,0 +1,152 @@
+//! Local APIC
+//!
+//! Enough of the local APIC to start secondary CPUs, give each of them a
+//! periodic timer and take the I/O APIC's interrupts on the boot CPU. The
+//! boot CPU keeps the PIT as its tick, which arrives like the device
+//! interrupts: through the I/O APIC where the ACPI MADT lists one, and
+//! through the 8259 PICs otherwise.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use x86_64::registers::model_specific::Msr;
+use super::super::{PlatformResult, PlatformError};
+use crate::memory::vmm::kernel_layout::PHYSICAL_MEMORY_OFFSET;
+
+const IA32_APIC_BASE_MSR: u32 = 0x1B;
+/// IA32_APIC_BASE bits
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Register offsets
+const APIC_ID: usize = 0x020;
+const APIC_EOI: usize = 0x0B0;
+const APIC_SPURIOUS: usize = 0x0F0;
+const APIC_ICR_LOW: usize = 0x300;
+const APIC_ICR_HIGH: usize = 0x310;
+const APIC_LVT_TIMER: usize = 0x320;
+const APIC_TIMER_INITIAL_COUNT: usize = 0x380;
+const APIC_TIMER_CURRENT_COUNT: usize = 0x390;
+const APIC_TIMER_DIVIDE: usize = 0x3E0;
+
+/// Spurious vector register: APIC software enable
+const SPURIOUS_APIC_ENABLE: u32 = 1 << 8;
+
+/// Interrupt command register fields
+const ICR_DELIVERY_INIT: u32 = 0x5 << 8;
+const ICR_DELIVERY_STARTUP: u32 = 0x6 << 8;
+const ICR_SEND_PENDING: u32 = 1 << 12;
+const ICR_LEVEL_ASSERT: u32 = 1 << 14;
+
+/// LVT timer fields
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+
+/// Timer divide configuration for a divisor of 16
+const TIMER_DIVIDE_BY_16: u32 = 0x3;
+
+/// Period the APIC timer is measured over
+const CALIBRATION_PERIOD_US: u64 = 10_000;
+
+/// Vector raised for spurious APIC interrupts
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+/// Physical address of the local APIC registers, read on the boot CPU
+static APIC_BASE: AtomicU32 = AtomicU32::new(0);
+
+/// Timer count per tick of the periodic timer, from calibration
+static TIMER_COUNT_PER_TICK: AtomicU32 = AtomicU32::new(0);
+
+fn register(offset: usize) -> *mut u32 {
+    (PHYSICAL_MEMORY_OFFSET.as_usize() + APIC_BASE.load(Ordering::Relaxed) as usize + offset) as *mut u32
+}
+
+fn read(offset: usize) -> u32 {
+    unsafe { core::ptr::read_volatile(register(offset)) }
+}
+
+fn write(offset: usize, value: u32) {
+    unsafe { core::ptr::write_volatile(register(offset), value) }
+}
+
+/// Enable the local APIC of the calling CPU
+///
+/// The LINT pins keep the configuration left by the firmware, so PIC
+/// interrupts still reach the boot CPU in virtual wire mode when there is
+/// no I/O APIC. Enabling it again is harmless.
+pub fn init_local() -> PlatformResult<()> {
+    let mut base_msr = Msr::new(IA32_APIC_BASE_MSR);
+    let base = unsafe { base_msr.read() };
+    let address = base & APIC_BASE_ADDRESS_MASK;
+    if address > u32::MAX as u64 {
+        return Err(PlatformError::UnsupportedOperation);
+    }
+    if base & APIC_BASE_ENABLE == 0 {
+        unsafe { base_msr.write(base | APIC_BASE_ENABLE) };
+    }
+    APIC_BASE.store(address as u32, Ordering::Relaxed);
+
+    write(APIC_SPURIOUS, SPURIOUS_APIC_ENABLE | SPURIOUS_VECTOR as u32);
+    Ok(())
+}
+
+/// APIC ID of the calling CPU
+pub fn id() -> u32 {
+    read(APIC_ID) >> 24
+}
+
+/// Signal the end of an interrupt delivered by the local APIC
+pub fn end_of_interrupt() {
+    write(APIC_EOI, 0);
+}
+
+fn send_ipi(apic_id: u32, command: u32) {
+    write(APIC_ICR_HIGH, apic_id << 24);
+    write(APIC_ICR_LOW, command);
+    while read(APIC_ICR_LOW) & ICR_SEND_PENDING != 0 {
+        core::hint::spin_loop();
+    }
+}
+
+/// Send an INIT IPI, resetting the target CPU into its wait-for-SIPI state
+pub fn send_init(apic_id: u32) {
+    send_ipi(apic_id, ICR_DELIVERY_INIT | ICR_LEVEL_ASSERT);
+}
+
+/// Send a startup IPI, starting the target CPU in real mode at `page * 4096`
+pub fn send_startup(apic_id: u32, page: u8) {
+    send_ipi(apic_id, ICR_DELIVERY_STARTUP | ICR_LEVEL_ASSERT | page as u32);
+}
+
+/// Measure the APIC timer for a periodic timer at `tick_frequency_hz`
+///
+/// The APIC timer runs at the bus clock, which is the same on every CPU,
+/// so one calibration on the boot CPU serves all of them.
+pub fn calibrate_timer(tick_frequency_hz: u32) -> PlatformResult<()> {
+    if tick_frequency_hz == 0 {
+        return Err(PlatformError::UnsupportedOperation);
+    }
+    write(APIC_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+    write(APIC_LVT_TIMER, LVT_MASKED);
+    write(APIC_TIMER_INITIAL_COUNT, u32::MAX);
+    super::timer::busy_wait_us(CALIBRATION_PERIOD_US);
+    let elapsed = (u32::MAX - read(APIC_TIMER_CURRENT_COUNT)) as u64;
+    write(APIC_TIMER_INITIAL_COUNT, 0);
+
+    let per_tick = elapsed * 1_000_000 / CALIBRATION_PERIOD_US / tick_frequency_hz as u64;
+    if per_tick == 0 || per_tick > u32::MAX as u64 {
+        return Err(PlatformError::HardwareError);
+    }
+    TIMER_COUNT_PER_TICK.store(per_tick as u32, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Start the calibrated periodic timer of the calling CPU on `vector`
+pub fn start_periodic_timer(vector: u8) -> PlatformResult<()> {
+    let count = TIMER_COUNT_PER_TICK.load(Ordering::Relaxed);
+    if count == 0 {
+        return Err(PlatformError::HardwareError);
+    }
+    write(APIC_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+    write(APIC_LVT_TIMER, LVT_TIMER_PERIODIC | vector as u32);
+    write(APIC_TIMER_INITIAL_COUNT, count);
+    Ok(())
+}
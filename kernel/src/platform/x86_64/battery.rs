@@ -0,0 +1,225 @@
+//! ACPI batteries and AC adapter
+//!
+//! Control method batteries (PNP0C0A) and AC adapters (ACPI0003) are found
+//! in the DSDT and SSDTs by their `_HID`. There is no AML interpreter, so
+//! their `_STA`, `_BIF` or `_BIX`, `_BST` and `_PSR` objects are read only
+//! where they are constants: a `Name`, or a method that returns one. That
+//! covers virtual machines and simple firmware; batteries whose methods
+//! read the embedded controller are not understood, and read as absent.
+//!
+//! Several batteries are reported as one, their energies added up.
+
+use alloc::vec::Vec;
+use spin::Once;
+use super::super::BatteryStatus;
+use super::acpi::{self, AmlValue};
+
+/// `_STA` bit: a battery is in the bay
+const STA_BATTERY_PRESENT: u64 = 1 << 4;
+
+/// `_STA` of a device that has none: present and working
+const STA_DEFAULT: u64 = 0x1F;
+
+/// `_BST` state bits
+const BST_DISCHARGING: u64 = 1 << 0;
+const BST_CHARGING: u64 = 1 << 1;
+const BST_CRITICAL: u64 = 1 << 2;
+
+/// What a `_BIF`, `_BIX` or `_BST` field reads as when the battery does
+/// not know it
+const UNKNOWN: u64 = 0xFFFF_FFFF;
+
+/// `_BIF` power unit of capacities in mWh, rather than mAh
+const POWER_UNIT_MILLIWATT: u64 = 0;
+
+/// `_BIF` fields; `_BIX` has the same after its revision
+const BIF_POWER_UNIT: usize = 0;
+const BIF_DESIGN_CAPACITY: usize = 1;
+const BIF_FULL_CAPACITY: usize = 2;
+const BIF_DESIGN_VOLTAGE: usize = 4;
+
+/// `_BST` fields
+const BST_STATE: usize = 0;
+const BST_RATE: usize = 1;
+const BST_REMAINING: usize = 2;
+const BST_VOLTAGE: usize = 3;
+
+/// Most fields of a package read
+const MAX_FIELDS: usize = 8;
+
+/// Bodies of the battery and AC adapter devices
+struct Devices {
+    batteries: Vec<&'static [u8]>,
+    adapters: Vec<&'static [u8]>,
+}
+
+static DEVICES: Once<Devices> = Once::new();
+
+fn devices() -> &'static Devices {
+    DEVICES.call_once(|| {
+        let mut devices = Devices { batteries: Vec::new(), adapters: Vec::new() };
+        if let Some(info) = acpi::info() {
+            for aml in info.definition_blocks() {
+                devices.batteries.extend(acpi::find_devices(aml, "PNP0C0A"));
+                devices.adapters.extend(acpi::find_devices(aml, "ACPI0003"));
+            }
+        }
+        devices
+    })
+}
+
+/// Fields of package `name` of a device, None for those that are unknown
+/// or not integers
+fn package_fields(body: &[u8], name: &[u8; 4]) -> Option<Vec<Option<u64>>> {
+    let Some(AmlValue::Package(offset)) = acpi::device_object(body, name) else {
+        return None;
+    };
+    Some(acpi::package_values(body, offset, MAX_FIELDS).into_iter().map(|value| match value {
+        AmlValue::Integer(value) if value != UNKNOWN => Some(value),
+        _ => None,
+    }).collect())
+}
+
+/// A battery's status from its `_STA` value and the fields of its `_BIF`
+/// and `_BST`; None if they do not say how charged it is
+pub fn battery_status(sta: u64, info: &[Option<u64>], bst: &[Option<u64>]) -> Option<BatteryStatus> {
+    if sta & STA_BATTERY_PRESENT == 0 {
+        return Some(BatteryStatus::default());
+    }
+    let field = |fields: &[Option<u64>], index: usize| fields.get(index).copied().flatten();
+    let state = field(bst, BST_STATE)?;
+    let remaining = field(bst, BST_REMAINING)?;
+    let full = field(info, BIF_FULL_CAPACITY)
+        .filter(|&capacity| capacity != 0)
+        .or(field(info, BIF_DESIGN_CAPACITY))
+        .filter(|&capacity| capacity != 0)?;
+
+    // Capacities in mAh become energies at the present voltage, or the
+    // design one; without either they stay unknown
+    let milliwatts = field(info, BIF_POWER_UNIT) == Some(POWER_UNIT_MILLIWATT);
+    let voltage = field(bst, BST_VOLTAGE).or(field(info, BIF_DESIGN_VOLTAGE)).unwrap_or(0);
+    let energy = |value: u64| {
+        let value = if milliwatts { value } else { value.saturating_mul(voltage) / 1000 };
+        value.min(u32::MAX as u64) as u32
+    };
+
+    Some(BatteryStatus {
+        present: true,
+        charging: state & BST_CHARGING != 0,
+        discharging: state & BST_DISCHARGING != 0,
+        critical: state & BST_CRITICAL != 0,
+        level_percent: (remaining.min(full) * 100 / full) as u8,
+        rate_mw: field(bst, BST_RATE).map_or(0, energy),
+        remaining_mwh: energy(remaining),
+        full_mwh: energy(full),
+    })
+}
+
+/// Batteries taken together: their energies added up, and critical only
+/// once every one of them is
+pub fn combined_status(batteries: &[BatteryStatus]) -> BatteryStatus {
+    let present: Vec<&BatteryStatus> = batteries.iter().filter(|battery| battery.present).collect();
+    match present.as_slice() {
+        [] => BatteryStatus::default(),
+        [battery] => **battery,
+        _ => {
+            let remaining: u64 = present.iter().map(|battery| battery.remaining_mwh as u64).sum();
+            let full: u64 = present.iter().map(|battery| battery.full_mwh as u64).sum();
+            let level_percent = (remaining.min(full) * 100).checked_div(full).unwrap_or_else(|| {
+                present.iter().map(|battery| battery.level_percent as u64).sum::<u64>() / present.len() as u64
+            }) as u8;
+            BatteryStatus {
+                present: true,
+                charging: present.iter().any(|battery| battery.charging),
+                discharging: present.iter().any(|battery| battery.discharging),
+                critical: present.iter().all(|battery| battery.critical),
+                level_percent,
+                rate_mw: present.iter().map(|battery| battery.rate_mw).fold(0, u32::saturating_add),
+                remaining_mwh: remaining.min(u32::MAX as u64) as u32,
+                full_mwh: full.min(u32::MAX as u64) as u32,
+            }
+        }
+    }
+}
+
+fn read(body: &[u8]) -> Option<BatteryStatus> {
+    let sta = match acpi::device_object(body, b"_STA") {
+        Some(AmlValue::Integer(sta)) => sta,
+        _ => STA_DEFAULT,
+    };
+    let info = match package_fields(body, b"_BIX") {
+        // `_BIX` starts with its revision
+        Some(fields) => fields.into_iter().skip(1).collect(),
+        None => package_fields(body, b"_BIF").unwrap_or_default(),
+    };
+    let bst = package_fields(body, b"_BST").unwrap_or_default();
+    battery_status(sta, &info, &bst)
+}
+
+/// The batteries' combined status, None if there are none that can be read
+pub fn read_battery() -> Option<BatteryStatus> {
+    let batteries: Vec<BatteryStatus> = devices().batteries.iter().filter_map(|body| read(body)).collect();
+    (!batteries.is_empty()).then(|| combined_status(&batteries))
+}
+
+/// Whether the first AC adapter's `_PSR` says it is online
+pub fn read_ac_online() -> Option<bool> {
+    let adapter = devices().adapters.first()?;
+    match acpi::device_object(adapter, b"_PSR") {
+        Some(AmlValue::Integer(online)) => Some(online != 0),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_battery_status_from_fields() {
+        // 4000 mAh design, 3600 last full at 11.1 V, discharging at 1000 mA
+        let info = [Some(1), Some(4000), Some(3600), Some(1), Some(11100)];
+        let bst = [Some(BST_DISCHARGING), Some(1000), Some(900), None];
+        assert_eq!(battery_status(STA_DEFAULT, &info, &bst), Some(BatteryStatus {
+            present: true,
+            charging: false,
+            discharging: true,
+            critical: false,
+            level_percent: 25,
+            rate_mw: 11100,
+            remaining_mwh: 9990,
+            full_mwh: 39960,
+        }));
+
+        // Unknown last full capacity falls back to the design one
+        let info = [Some(POWER_UNIT_MILLIWATT), Some(50000), None];
+        let bst = [Some(BST_CHARGING), None, Some(40000), None];
+        let status = battery_status(STA_DEFAULT, &info, &bst).unwrap();
+        assert_eq!((status.level_percent, status.rate_mw, status.full_mwh), (80, 0, 50000));
+        assert!(status.charging);
+
+        assert_eq!(battery_status(0x0F, &info, &bst), Some(BatteryStatus::default()));
+        assert_eq!(battery_status(STA_DEFAULT, &info, &[]), None);
+    }
+
+    #[test_case]
+    fn test_batteries_are_combined() {
+        let battery = |remaining_mwh, full_mwh, critical| BatteryStatus {
+            present: true,
+            discharging: true,
+            critical,
+            level_percent: (remaining_mwh * 100 / full_mwh) as u8,
+            rate_mw: 5000,
+            remaining_mwh,
+            full_mwh,
+            ..BatteryStatus::default()
+        };
+        let combined = combined_status(&[battery(1000, 40000, true), battery(30000, 40000, false)]);
+        assert_eq!((combined.level_percent, combined.rate_mw), (38, 10000));
+        assert!(combined.discharging && !combined.critical);
+
+        let alone = battery(1000, 40000, true);
+        assert_eq!(combined_status(&[BatteryStatus::default(), alone]), alone);
+        assert!(!combined_status(&[]).present);
+    }
+}
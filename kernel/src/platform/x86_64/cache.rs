@@ -45,11 +45,16 @@ impl CacheOperations for X86_64CacheOperations {
         self.invalidate_dcache()
     }
     
+    fn dcache_line_size(&self) -> usize {
+        // Standard x86-64 cache line size
+        64
+    }
+    
     fn clean_invalidate_dcache_range(&self, start: VirtualAddress, size: usize) -> PlatformResult<()> {
         // x86-64 doesn't have cache line operations like ARM
         // Use CLFLUSH for individual cache lines
         unsafe {
-            let cache_line_size = 64; // Standard x86-64 cache line size
+            let cache_line_size = self.dcache_line_size() as u64;
             let start_addr = start.as_u64() & !(cache_line_size - 1); // Align to cache line
             let end_addr = (start.as_u64() + size as u64 + cache_line_size - 1) & !(cache_line_size - 1);
             
@@ -69,4 +74,18 @@ impl CacheOperations for X86_64CacheOperations {
         // x86-64 CLFLUSH both flushes and invalidates
         self.clean_invalidate_dcache_range(start, size)
     }
+    
+    fn clean_dcache_range(&self, _start: VirtualAddress, _size: usize) -> PlatformResult<()> {
+        // Caches are snooped by bus masters on x86-64, so there is nothing to
+        // write back; only order the preceding stores
+        unsafe {
+            asm!("sfence");
+        }
+        Ok(())
+    }
+    
+    fn is_dma_coherent(&self) -> bool {
+        // PCI DMA is cache-coherent on x86-64
+        true
+    }
 }
\ No newline at end of file
@@ -0,0 +1,211 @@
+//! x86-64 clock counters and real-time clock
+//!
+//! The TSC is preferred when CPUID reports it invariant, so it ticks at the
+//! same rate in every power state and on every CPU. Its rate comes from
+//! CPUID leaf 0x15 where the CPU reports it, and is otherwise measured
+//! against the PIT. The HPET is the fallback; until the ACPI HPET table is
+//! parsed it is looked for at the address chipsets conventionally use.
+//!
+//! The CMOS RTC keeps the time of day, in UTC.
+
+use super::super::{ClockCounter, ClockSource};
+use crate::memory::vmm::kernel_layout::PHYSICAL_MEMORY_OFFSET;
+use x86_64::instructions::port::Port;
+
+/// Conventional HPET register block address
+const HPET_BASE: usize = 0xFED0_0000;
+
+/// HPET register offsets
+const HPET_CAPABILITIES: usize = 0x000;
+const HPET_CONFIGURATION: usize = 0x010;
+const HPET_MAIN_COUNTER: usize = 0x0F0;
+
+/// Configuration bit that starts the main counter
+const HPET_ENABLE: u64 = 1 << 0;
+
+/// Longest counter period the HPET specification allows, in femtoseconds
+const HPET_MAX_PERIOD_FS: u64 = 100_000_000;
+
+const FEMTOS_PER_SEC: u64 = 1_000_000_000_000_000;
+
+/// Time the TSC is measured over when CPUID does not give its rate
+const TSC_CALIBRATION_US: u64 = 10_000;
+
+const CMOS_ADDRESS_PORT: u16 = 0x70;
+const CMOS_DATA_PORT: u16 = 0x71;
+
+/// CMOS RTC registers
+const RTC_SECONDS: u8 = 0x00;
+const RTC_MINUTES: u8 = 0x02;
+const RTC_HOURS: u8 = 0x04;
+const RTC_DAY: u8 = 0x07;
+const RTC_MONTH: u8 = 0x08;
+const RTC_YEAR: u8 = 0x09;
+const RTC_STATUS_A: u8 = 0x0A;
+const RTC_STATUS_B: u8 = 0x0B;
+
+/// Status A: an update is in progress and the registers may be torn
+const RTC_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// Status B: hours are 0-23 rather than 1-12 with a PM flag
+const RTC_24_HOUR: u8 = 1 << 1;
+/// Status B: values are binary rather than BCD
+const RTC_BINARY: u8 = 1 << 2;
+/// Hours register PM flag in 12-hour mode
+const RTC_PM: u8 = 1 << 7;
+
+/// Read attempts before giving up on a stable RTC value
+const RTC_READ_ATTEMPTS: usize = 16;
+
+/// Find the best counter, measuring the TSC if its rate is unknown
+pub fn probe() -> Option<ClockCounter> {
+    if has_invariant_tsc() {
+        let frequency_hz = tsc_frequency_from_cpuid().unwrap_or_else(calibrate_tsc);
+        if frequency_hz > 0 {
+            return Some(ClockCounter { source: ClockSource::Tsc, frequency_hz });
+        }
+    }
+    hpet_frequency().map(|frequency_hz| {
+        enable_hpet();
+        ClockCounter { source: ClockSource::Hpet, frequency_hz }
+    })
+}
+
+/// Current value of `source`
+pub fn read(source: ClockSource) -> u64 {
+    match source {
+        ClockSource::Tsc => read_tsc(),
+        ClockSource::Hpet => hpet_read(HPET_MAIN_COUNTER),
+        ClockSource::GenericTimer | ClockSource::RiscvTime => 0,
+    }
+}
+
+fn read_tsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+fn has_invariant_tsc() -> bool {
+    raw_cpuid::CpuId::new()
+        .get_advanced_power_mgmt_info()
+        .map_or(false, |info| info.has_invariant_tsc())
+}
+
+fn tsc_frequency_from_cpuid() -> Option<u64> {
+    raw_cpuid::CpuId::new()
+        .get_tsc_info()
+        .and_then(|info| info.tsc_frequency())
+        .filter(|&frequency| frequency > 0)
+}
+
+/// Count TSC ticks over a PIT-timed busy wait
+fn calibrate_tsc() -> u64 {
+    let start = read_tsc();
+    super::timer::busy_wait_us(TSC_CALIBRATION_US);
+    let elapsed = read_tsc().wrapping_sub(start);
+    elapsed * (1_000_000 / TSC_CALIBRATION_US)
+}
+
+fn hpet_register(offset: usize) -> *mut u64 {
+    (PHYSICAL_MEMORY_OFFSET.as_usize() + HPET_BASE + offset) as *mut u64
+}
+
+fn hpet_read(offset: usize) -> u64 {
+    unsafe { core::ptr::read_volatile(hpet_register(offset)) }
+}
+
+/// Main counter rate, if an HPET answers at the conventional address
+fn hpet_frequency() -> Option<u64> {
+    let capabilities = hpet_read(HPET_CAPABILITIES);
+    // Nothing there reads as all ones
+    if capabilities == u64::MAX {
+        return None;
+    }
+    let period_fs = capabilities >> 32;
+    if period_fs == 0 || period_fs > HPET_MAX_PERIOD_FS {
+        return None;
+    }
+    Some(FEMTOS_PER_SEC / period_fs)
+}
+
+fn enable_hpet() {
+    let configuration = hpet_read(HPET_CONFIGURATION);
+    unsafe { core::ptr::write_volatile(hpet_register(HPET_CONFIGURATION), configuration | HPET_ENABLE) };
+}
+
+fn cmos_read(register: u8) -> u8 {
+    unsafe {
+        Port::<u8>::new(CMOS_ADDRESS_PORT).write(register);
+        Port::<u8>::new(CMOS_DATA_PORT).read()
+    }
+}
+
+/// Seconds, minutes, hours, day, month and year, as the RTC stores them
+fn read_rtc_registers() -> [u8; 6] {
+    while cmos_read(RTC_STATUS_A) & RTC_UPDATE_IN_PROGRESS != 0 {
+        core::hint::spin_loop();
+    }
+    [RTC_SECONDS, RTC_MINUTES, RTC_HOURS, RTC_DAY, RTC_MONTH, RTC_YEAR].map(cmos_read)
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// Time of day from the CMOS RTC, as seconds since the Unix epoch
+///
+/// The registers are read until two reads agree, so an update between
+/// them cannot tear the value.
+pub fn read_rtc() -> Option<u64> {
+    let mut registers = read_rtc_registers();
+    let mut stable = false;
+    for _ in 0..RTC_READ_ATTEMPTS {
+        let again = read_rtc_registers();
+        if again == registers {
+            stable = true;
+            break;
+        }
+        registers = again;
+    }
+    if !stable {
+        return None;
+    }
+
+    let status_b = cmos_read(RTC_STATUS_B);
+    let [mut second, mut minute, raw_hour, mut day, mut month, mut year] = registers;
+    let pm = raw_hour & RTC_PM != 0;
+    let mut hour = raw_hour & !RTC_PM;
+    if status_b & RTC_BINARY == 0 {
+        second = from_bcd(second);
+        minute = from_bcd(minute);
+        hour = from_bcd(hour);
+        day = from_bcd(day);
+        month = from_bcd(month);
+        year = from_bcd(year);
+    }
+    if status_b & RTC_24_HOUR == 0 {
+        // 12 AM is hour 0 and 12 PM hour 12
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    // The century comes from the CMOS register the ACPI FADT names;
+    // without one, or with an implausible one, assume the 21st century
+    let century = super::acpi::fadt()
+        .map(|fadt| fadt.century_register)
+        .filter(|&register| register != 0)
+        .map(|register| {
+            let century = cmos_read(register);
+            if status_b & RTC_BINARY == 0 { from_bcd(century) } else { century }
+        })
+        .filter(|century| (19..=21).contains(century))
+        .unwrap_or(20);
+    Some(crate::time::unix_time(
+        century as u64 * 100 + year as u64,
+        month as u64,
+        day as u64,
+        hour as u64,
+        minute as u64,
+        second as u64,
+    ))
+}
@@ -0,0 +1,109 @@
+//! x86-64 CPU idle states
+//!
+//! `hlt` is always offered, as C1. Intel CPUs with MONITOR/MWAIT offer
+//! deeper C-states through MWAIT: CPUID leaf 5 counts the sub-states of
+//! each, and a hint of n - 1 in bits 4-7 enters C-state n. The states are
+//! named by the leaf's numbering, which is not always the one Intel
+//! markets. The leaf gives no latencies; ACPI `_CST` would, but it needs
+//! AML to be run, so the figures of recent Intel cores are taken.
+//!
+//! The local APIC timer, which drives the scheduler tick, stops below C1
+//! unless CPUID says it keeps running, so without that only `hlt` is
+//! offered. Interrupts break MWAIT as they break HLT; each CPU monitors a
+//! cache line of its own that nothing writes.
+
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::arch::x86_64::__cpuid;
+use super::super::CpuIdleState;
+use crate::smp::MAX_CPUS;
+
+/// CPUID feature bits
+const CPUID1_ECX_MONITOR: u32 = 1 << 3;
+const CPUID5_ECX_EXTENSIONS: u32 = 1 << 0;
+const CPUID6_EAX_ARAT: u32 = 1 << 2;
+
+/// Control value of `hlt`; MWAIT states use their hint
+const HLT: u64 = u64::MAX;
+
+/// Name, exit latency and target residency in µs of each C-state in the
+/// leaf 5 numbering; C0 and C1 are not entered through MWAIT
+const MWAIT_STATES: [(&str, u32, u32); 8] = [
+    ("", 0, 0),
+    ("", 0, 0),
+    ("mwait-c2", 70, 100),
+    ("mwait-c3", 85, 200),
+    ("mwait-c4", 124, 800),
+    ("mwait-c5", 200, 800),
+    ("mwait-c6", 480, 5000),
+    ("mwait-c7", 890, 5000),
+];
+
+/// A cache line for one CPU's MONITOR
+#[repr(align(64))]
+struct MonitorLine(u64);
+
+static MONITOR_LINES: [MonitorLine; MAX_CPUS] = [const { MonitorLine(0) }; MAX_CPUS];
+
+/// MWAIT states CPUID leaf 5 EDX lists: bits 4n to 4n+3 count the
+/// sub-states of C-state n, and the first of them is used
+pub fn mwait_states(edx: u32) -> Vec<CpuIdleState> {
+    (2..MWAIT_STATES.len())
+        .filter(|&n| (edx >> (4 * n)) & 0xF != 0)
+        .map(|n| {
+            let (name, exit_latency_us, target_residency_us) = MWAIT_STATES[n];
+            CpuIdleState { name, exit_latency_us, target_residency_us, control: (n as u64 - 1) << 4 }
+        })
+        .collect()
+}
+
+fn is_intel() -> bool {
+    let leaf = unsafe { __cpuid(0) };
+    // "GenuineIntel" as EBX, EDX, ECX
+    (leaf.ebx, leaf.edx, leaf.ecx) == (0x756E_6547, 0x4965_6E69, 0x6C65_746E)
+}
+
+/// `hlt`, then the MWAIT states if the CPU has them and its APIC timer
+/// keeps running in them
+pub fn probe() -> Vec<CpuIdleState> {
+    let mut states = alloc::vec![CpuIdleState { name: "hlt", exit_latency_us: 2, target_residency_us: 2, control: HLT }];
+    let has_mwait = is_intel()
+        && unsafe { __cpuid(0) }.eax >= 6
+        && unsafe { __cpuid(1) }.ecx & CPUID1_ECX_MONITOR != 0
+        && unsafe { __cpuid(5) }.ecx & CPUID5_ECX_EXTENSIONS != 0
+        && unsafe { __cpuid(6) }.eax & CPUID6_EAX_ARAT != 0;
+    if has_mwait {
+        states.extend(mwait_states(unsafe { __cpuid(5) }.edx));
+    }
+    states
+}
+
+/// Wait for an interrupt in `state`
+pub fn enter(state: &CpuIdleState) {
+    if state.control == HLT {
+        x86_64::instructions::hlt();
+        return;
+    }
+    let line = &MONITOR_LINES[super::smp::current_cpu_index()] as *const MonitorLine;
+    unsafe {
+        asm!("monitor", in("rax") line, in("ecx") 0, in("edx") 0, options(nostack));
+        asm!("mwait", in("eax") state.control as u32, in("ecx") 0, options(nostack));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_mwait_states_follow_cpuid() {
+        // Two C1, one C2 and two C3 sub-states
+        let states = mwait_states(0x0000_2120);
+        assert_eq!(states.len(), 2);
+        assert_eq!((states[0].name, states[0].control), ("mwait-c2", 0x10));
+        assert_eq!((states[1].name, states[1].control), ("mwait-c3", 0x20));
+        assert!(states[0].target_residency_us < states[1].target_residency_us);
+
+        assert!(mwait_states(0x0000_0020).is_empty());
+    }
+}
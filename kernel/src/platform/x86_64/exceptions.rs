@@ -0,0 +1,341 @@
+//! CPU exception handlers
+//!
+//! A fault raised by a user process terminates that process with the
+//! matching signal, and the CPU carries on with whatever the scheduler
+//! picks next. A fault in the kernel itself is fatal. Either way the
+//! registers, and the stack where it can be read safely, are dumped to
+//! serial first. Breakpoints are only logged.
+//!
+//! Page faults on pages that were swapped out are not errors: the page is
+//! swapped back in and the faulting instruction runs again. Neither are
+//! faults in an mmap region, which are populated on first access; a user
+//! process that has to wait for a file page to be read is blocked and
+//! retries the access once it is woken.
+//!
+//! Kernel stacks have unmapped guard pages below them. A kernel fault on a
+//! guard page, or a double fault whose stack pointer or last page fault
+//! address lies in one, is reported as an overflow of the stack it guards,
+//! naming the CPU or thread the stack belongs to.
+//!
+//! The entry stubs build the same `TrapFrame` as the interrupt entries, so
+//! a fault can switch contexts like the timer interrupt does. The error
+//! code, where the CPU pushes one, takes the place of the saved `rax`.
+
+use core::arch::global_asm;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::InterruptDescriptorTable;
+use x86_64::{PrivilegeLevel, VirtAddr};
+use crate::memory::kstack;
+use crate::memory::mmap::{self, FaultResult};
+use crate::memory::vmm::{handle_page_fault, is_user_address_mapped, VirtualAddress};
+use crate::process::signal::{SIGFPE, SIGILL, SIGSEGV};
+use crate::process::TrapFrame;
+use crate::serial_println;
+use crate::trace::Tracepoint;
+
+pub const DIVIDE_ERROR_VECTOR: u8 = 0;
+pub const BREAKPOINT_VECTOR: u8 = 3;
+pub const INVALID_OPCODE_VECTOR: u8 = 6;
+pub const DOUBLE_FAULT_VECTOR: u8 = 8;
+pub const GENERAL_PROTECTION_VECTOR: u8 = 13;
+pub const PAGE_FAULT_VECTOR: u8 = 14;
+
+/// Page fault error code bits
+const PF_PROTECTION_VIOLATION: u64 = 1 << 0;
+const PF_WRITE: u64 = 1 << 1;
+const PF_USER: u64 = 1 << 2;
+const PF_INSTRUCTION_FETCH: u64 = 1 << 4;
+
+/// Stack words dumped with a fault
+const STACK_DUMP_WORDS: u64 = 16;
+
+const PAGE_MASK: u64 = !0xFFF;
+
+extern "C" {
+    fn divide_error_entry();
+    fn breakpoint_entry();
+    fn invalid_opcode_entry();
+    fn double_fault_entry();
+    fn general_protection_entry();
+    fn page_fault_entry();
+}
+
+/// Exception entry stub; `error_code` for exceptions where the CPU pushes
+/// one, `no_error_code` otherwise
+///
+/// Jumps to `exception_common` with the vector in `esi` and the error code
+/// in `rdx`.
+macro_rules! exception_entry {
+    ($entry:literal, $vector:expr, no_error_code) => {
+        global_asm!(
+            concat!(".global ", $entry),
+            concat!($entry, ":"),
+            "push rax",
+            "push rbx",
+            "push rcx",
+            "push rdx",
+            "push rsi",
+            "push rdi",
+            "push rbp",
+            "push r8",
+            "push r9",
+            "push r10",
+            "push r11",
+            "push r12",
+            "push r13",
+            "push r14",
+            "push r15",
+            "xor edx, edx",
+            "mov esi, {vector}",
+            "jmp exception_common",
+            vector = const $vector,
+        );
+    };
+    ($entry:literal, $vector:expr, error_code) => {
+        global_asm!(
+            concat!(".global ", $entry),
+            concat!($entry, ":"),
+            "xchg rax, [rsp]",
+            "push rbx",
+            "push rcx",
+            "push rdx",
+            "push rsi",
+            "push rdi",
+            "push rbp",
+            "push r8",
+            "push r9",
+            "push r10",
+            "push r11",
+            "push r12",
+            "push r13",
+            "push r14",
+            "push r15",
+            "mov rdx, rax",
+            "mov esi, {vector}",
+            "jmp exception_common",
+            vector = const $vector,
+        );
+    };
+}
+
+exception_entry!("divide_error_entry", DIVIDE_ERROR_VECTOR, no_error_code);
+exception_entry!("breakpoint_entry", BREAKPOINT_VECTOR, no_error_code);
+exception_entry!("invalid_opcode_entry", INVALID_OPCODE_VECTOR, no_error_code);
+exception_entry!("double_fault_entry", DOUBLE_FAULT_VECTOR, error_code);
+exception_entry!("general_protection_entry", GENERAL_PROTECTION_VECTOR, error_code);
+exception_entry!("page_fault_entry", PAGE_FAULT_VECTOR, error_code);
+
+global_asm!(
+    "exception_common:",
+    "mov rdi, rsp",
+    "cld",
+    "call {handler}",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rbp",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rbx",
+    "pop rax",
+    "iretq",
+    handler = sym exception_handler,
+);
+
+/// Install the exception handlers in `idt`
+///
+/// The double fault handler runs on the IST stack, so a kernel stack
+/// overflow still gets reported instead of escalating to a triple fault.
+pub fn install(idt: &mut InterruptDescriptorTable) {
+    unsafe {
+        idt.divide_error.set_handler_addr(VirtAddr::new(divide_error_entry as u64));
+        // User code may hit int3 directly
+        idt.breakpoint.set_handler_addr(VirtAddr::new(breakpoint_entry as u64))
+            .set_privilege_level(PrivilegeLevel::Ring3);
+        idt.invalid_opcode.set_handler_addr(VirtAddr::new(invalid_opcode_entry as u64));
+        idt.double_fault.set_handler_addr(VirtAddr::new(double_fault_entry as u64))
+            .set_stack_index(crate::boot::DOUBLE_FAULT_IST_INDEX);
+        idt.general_protection_fault.set_handler_addr(VirtAddr::new(general_protection_entry as u64));
+        idt.page_fault.set_handler_addr(VirtAddr::new(page_fault_entry as u64));
+    }
+}
+
+fn exception_name(vector: u8) -> &'static str {
+    match vector {
+        DIVIDE_ERROR_VECTOR => "Divide error",
+        BREAKPOINT_VECTOR => "Breakpoint",
+        INVALID_OPCODE_VECTOR => "Invalid opcode",
+        DOUBLE_FAULT_VECTOR => "Double fault",
+        GENERAL_PROTECTION_VECTOR => "General protection fault",
+        PAGE_FAULT_VECTOR => "Page fault",
+        _ => "Unknown exception",
+    }
+}
+
+/// Signal a user process is killed with for a fault
+fn signal_for(vector: u8) -> u32 {
+    match vector {
+        DIVIDE_ERROR_VECTOR => SIGFPE,
+        INVALID_OPCODE_VECTOR => SIGILL,
+        _ => SIGSEGV,
+    }
+}
+
+/// Human readable page fault error code
+fn describe_page_fault(error_code: u64) -> (&'static str, &'static str, &'static str) {
+    let cause = if error_code & PF_PROTECTION_VIOLATION != 0 { "protection violation" } else { "page not present" };
+    let access = if error_code & PF_INSTRUCTION_FETCH != 0 {
+        "instruction fetch"
+    } else if error_code & PF_WRITE != 0 {
+        "write"
+    } else {
+        "read"
+    };
+    let mode = if error_code & PF_USER != 0 { "user" } else { "kernel" };
+    (cause, access, mode)
+}
+
+extern "C" fn exception_handler(frame: &mut TrapFrame, vector: u64, error_code: u64) {
+    let vector = vector as u8;
+    if vector == BREAKPOINT_VECTOR {
+        // rip already points past the int3
+        serial_println!("Breakpoint at 0x{:016x}", frame.rip.wrapping_sub(1));
+        dump_registers(frame);
+        return;
+    }
+
+    let fault_address = (vector == PAGE_FAULT_VECTOR).then(|| Cr2::read_raw());
+    if !frame.from_user_mode() && (vector == PAGE_FAULT_VECTOR || vector == DOUBLE_FAULT_VECTOR) {
+        // CR2 still holds the page fault that escalated to a double fault
+        let candidates = [frame.rsp, fault_address.unwrap_or_else(Cr2::read_raw)];
+        if let Some(overflow) = candidates.iter().find_map(|&address| kstack::find_overflow(address as usize)) {
+            report_stack_overflow(frame, vector, &overflow);
+        }
+    }
+    if let Some(address) = fault_address {
+        if crate::trace::is_enabled(Tracepoint::PageFault) {
+            let process = crate::process::get_current_process().unwrap_or(crate::process::ProcessId::KERNEL);
+            crate::trace::record(Tracepoint::PageFault, process, address, error_code);
+        }
+        
+        // A swapped-out page is brought back and the access retried
+        if error_code & PF_PROTECTION_VIOLATION == 0 && handle_page_fault(VirtualAddress(address as usize)) {
+            return;
+        }
+        if let Some(pid) = crate::process::get_current_process() {
+            match mmap::handle_page_fault(pid, VirtualAddress(address as usize), error_code & PF_WRITE != 0) {
+                FaultResult::Resolved => return,
+                FaultResult::Pending if frame.from_user_mode() => {
+                    crate::process::preempt::switch_away(frame);
+                    return;
+                }
+                // The out-of-memory handler has logged why
+                FaultResult::OutOfMemory if frame.from_user_mode() => {
+                    crate::process::preempt::kill_running_process(frame, crate::process::signal::SIGKILL);
+                    return;
+                }
+                // The kernel faults in mapped user memory before touching it
+                FaultResult::Pending | FaultResult::Unhandled | FaultResult::OutOfMemory => {}
+            }
+        }
+    }
+
+    serial_println!("EXCEPTION: {} (vector {}, error code 0x{:x})", exception_name(vector), vector, error_code);
+    if let Some(address) = fault_address {
+        let (cause, access, mode) = describe_page_fault(error_code);
+        serial_println!("  Address 0x{:016x}: {} on {} in {} mode", address, cause, access, mode);
+    }
+    dump_registers(frame);
+
+    // A double fault leaves nothing to resume, whoever caused it
+    let user_fault = frame.from_user_mode() && vector != DOUBLE_FAULT_VECTOR;
+    if vector != DOUBLE_FAULT_VECTOR {
+        dump_stack(frame, fault_address);
+    }
+
+    if user_fault {
+        crate::process::preempt::kill_running_process(frame, signal_for(vector));
+        return;
+    }
+    panic!("{} in kernel mode at 0x{:016x}", exception_name(vector), frame.rip);
+}
+
+/// Report a kernel stack overflow and stop
+///
+/// The overflowed stack cannot be dumped, and whatever ran on it cannot
+/// continue.
+fn report_stack_overflow(frame: &TrapFrame, vector: u8, overflow: &kstack::Overflow) -> ! {
+    serial_println!("EXCEPTION: {} (vector {}): kernel stack overflow", exception_name(vector), vector);
+    serial_println!("  Overflowed the {} (0x{:016x}-0x{:016x}) by {} bytes",
+                    overflow.owner, overflow.bottom, overflow.top, overflow.depth);
+    dump_registers(frame);
+    panic!("Kernel stack overflow on the {} at 0x{:016x}", overflow.owner, frame.rip);
+}
+
+fn dump_registers(frame: &TrapFrame) {
+    serial_println!("  RIP 0x{:016x}  CS {:#06x}  RFLAGS 0x{:016x}", frame.rip, frame.cs, frame.rflags);
+    serial_println!("  RSP 0x{:016x}  SS {:#06x}", frame.rsp, frame.ss);
+    serial_println!("  RAX 0x{:016x}  RBX 0x{:016x}  RCX 0x{:016x}", frame.rax, frame.rbx, frame.rcx);
+    serial_println!("  RDX 0x{:016x}  RSI 0x{:016x}  RDI 0x{:016x}", frame.rdx, frame.rsi, frame.rdi);
+    serial_println!("  RBP 0x{:016x}  R8  0x{:016x}  R9  0x{:016x}", frame.rbp, frame.r8, frame.r9);
+    serial_println!("  R10 0x{:016x}  R11 0x{:016x}  R12 0x{:016x}", frame.r10, frame.r11, frame.r12);
+    serial_println!("  R13 0x{:016x}  R14 0x{:016x}  R15 0x{:016x}", frame.r13, frame.r14, frame.r15);
+}
+
+/// Dump the top of the interrupted stack, stopping where reading could
+/// fault again
+///
+/// A user stack is only read where the process's page tables map it. The
+/// kernel's page tables may be locked by the faulting code, so a kernel
+/// stack is read without asking them, except for the page a page fault was
+/// raised on.
+fn dump_stack(frame: &TrapFrame, fault_address: Option<u64>) {
+    serial_println!("  Stack:");
+    let top = frame.rsp & !0x7;
+    for index in 0..STACK_DUMP_WORDS {
+        let address = top + index * 8;
+        if VirtAddr::try_new(address).is_err() {
+            break;
+        }
+        let readable = if frame.from_user_mode() {
+            // The faulting process's page tables are still loaded
+            crate::process::get_current_process()
+                .is_some_and(|pid| is_user_address_mapped(pid, VirtualAddress(address as usize)))
+        } else {
+            fault_address.map_or(true, |fault| fault & PAGE_MASK != address & PAGE_MASK)
+        };
+        if !readable {
+            serial_println!("    0x{:016x}: <unreadable>", address);
+            break;
+        }
+        let value = unsafe { core::ptr::read_volatile(address as *const u64) };
+        serial_println!("    0x{:016x}: 0x{:016x}", address, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_page_fault_description() {
+        assert_eq!(describe_page_fault(0), ("page not present", "read", "kernel"));
+        assert_eq!(describe_page_fault(PF_PROTECTION_VIOLATION | PF_WRITE | PF_USER), ("protection violation", "write", "user"));
+        assert_eq!(describe_page_fault(PF_INSTRUCTION_FETCH | PF_USER), ("page not present", "instruction fetch", "user"));
+    }
+
+    #[test_case]
+    fn test_fault_signals() {
+        assert_eq!(signal_for(DIVIDE_ERROR_VECTOR), SIGFPE);
+        assert_eq!(signal_for(INVALID_OPCODE_VECTOR), SIGILL);
+        assert_eq!(signal_for(GENERAL_PROTECTION_VECTOR), SIGSEGV);
+        assert_eq!(signal_for(PAGE_FAULT_VECTOR), SIGSEGV);
+    }
+}
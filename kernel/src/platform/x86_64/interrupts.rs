@@ -1,8 +1,450 @@
 //! x86-64 interrupt handling implementation
+//!
+//! The 16 legacy IRQ lines arrive through the I/O APIC when the ACPI MADT
+//! lists one, and through the 8259 PIC pair otherwise; either way line `n`
+//! raises vector `PIC_1_OFFSET + n`, and only the end-of-interrupt and the
+//! masking differ.
+//!
+//! Message-signaled interrupts need the local APIC, so they are only
+//! offered once the I/O APIC routing has brought it up. Each MSI line of
+//! the IRQ manager has a vector of its own from `MSI_VECTOR_BASE` up,
+//! reached through a table of identical entry stubs.
+//!
+//! System calls come in through the `int 0x80` gate, the only vector
+//! user mode may raise besides the breakpoint exception.
 
-use core::arch::asm;
-use super::super::traits::{InterruptHandling, InterruptHandler};
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use pic8259::ChainedPics;
+use spin::Mutex;
+use x86_64::structures::idt::InterruptDescriptorTable;
+use x86_64::{PrivilegeLevel, VirtAddr};
+use super::super::traits::{InterruptHandling, InterruptHandler, IoOperations};
 use super::super::{PlatformResult, PlatformError};
+use super::io::X86_64IoOperations;
+use crate::process::TrapFrame;
+
+/// Vector of the first interrupt of the master PIC
+pub const PIC_1_OFFSET: u8 = 32;
+/// Vector of the first interrupt of the slave PIC
+pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
+/// Vector of the PIT interrupt (IRQ 0)
+pub const TIMER_VECTOR: u8 = PIC_1_OFFSET;
+/// Vector of the PS/2 keyboard interrupt (IRQ 1)
+pub const KEYBOARD_VECTOR: u8 = PIC_1_OFFSET + 1;
+/// IRQ line of COM1, the console UART
+pub const SERIAL_IRQ: usize = 4;
+/// Vector of the local APIC timer driving secondary CPUs
+pub const APIC_TIMER_VECTOR: u8 = PIC_2_OFFSET + 8;
+
+/// Number of legacy IRQ lines behind the PIC pair
+pub const IRQ_LINES: usize = 16;
+
+/// Vector of the first message-signaled interrupt; a device sending a
+/// block of them sets the low bits of the vector, so the base is aligned
+/// to the largest block
+pub const MSI_VECTOR_BASE: u8 = 0x60;
+/// Message-signaled interrupt vectors, one per MSI line
+pub const MSI_VECTORS: usize = crate::ipc::irq::MSI_LINES;
+
+/// Vector of the system call gate, the one vector user mode may raise
+pub const SYSCALL_VECTOR: u8 = 0x80;
+
+// The MSI vectors stop short of the system call gate
+const _: () = assert!(MSI_VECTOR_BASE as usize + MSI_VECTORS <= SYSCALL_VECTOR as usize);
+
+/// Address window of the local APICs, which MSI messages are written to
+const MSI_ADDRESS_BASE: u64 = 0xFEE0_0000;
+const MSI_DESTINATION_SHIFT: u64 = 12;
+
+/// Bytes between the MSI entry stubs
+const MSI_STUB_STRIDE: usize = 64;
+
+const PS2_DATA_PORT: u16 = 0x60;
+const PS2_STATUS_PORT: u16 = 0x64;
+
+/// Interrupts taken per IRQ line since boot
+static IRQ_COUNTS: [AtomicU64; IRQ_LINES] = [const { AtomicU64::new(0) }; IRQ_LINES];
+
+/// Interrupts taken per IRQ line since boot
+pub fn irq_counts() -> [u64; IRQ_LINES] {
+    core::array::from_fn(|line| IRQ_COUNTS[line].load(Ordering::Relaxed))
+}
+
+/// The legacy 8259 PIC pair, remapped above the CPU exceptions
+pub static PICS: Mutex<ChainedPics> = Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+
+/// Whether the IRQ lines are routed through the I/O APIC rather than the PICs
+static IOAPIC_ROUTING: AtomicBool = AtomicBool::new(false);
+
+/// Whether the IRQ lines are routed through the I/O APIC rather than the PICs
+pub fn uses_ioapic() -> bool {
+    IOAPIC_ROUTING.load(Ordering::Relaxed)
+}
+
+/// Signal the end of an interrupt on IRQ line `line`
+fn end_of_interrupt(line: usize) {
+    if uses_ioapic() {
+        super::apic::end_of_interrupt();
+    } else {
+        unsafe {
+            PICS.lock().notify_end_of_interrupt(PIC_1_OFFSET + line as u8);
+        }
+    }
+}
+
+extern "C" {
+    fn timer_interrupt_entry();
+    fn apic_timer_interrupt_entry();
+    fn keyboard_interrupt_entry();
+    fn spurious_interrupt_entry();
+    fn irq3_entry();
+    fn irq4_entry();
+    fn irq5_entry();
+    fn irq6_entry();
+    fn irq7_entry();
+    fn irq8_entry();
+    fn irq9_entry();
+    fn irq10_entry();
+    fn irq11_entry();
+    fn irq12_entry();
+    fn irq13_entry();
+    fn irq14_entry();
+    fn irq15_entry();
+    fn syscall_interrupt_entry();
+    /// First of `MSI_VECTORS` stubs, `MSI_STUB_STRIDE` bytes apart
+    fn msi_entries();
+}
+
+/// Lines userspace drivers may claim: all but the timer and the cascade
+pub fn is_routable_irq(line: usize) -> bool {
+    line < IRQ_LINES && line != 0 && line != 2
+}
+
+/// Mask or unmask one IRQ line
+pub fn set_irq_masked(line: usize, masked: bool) {
+    if uses_ioapic() {
+        if let Err(e) = super::ioapic::set_isa_line_masked(line as u8, PIC_1_OFFSET + line as u8, masked) {
+            log::warn!("IRQ {}: cannot reach its I/O APIC entry: {}", line, e);
+        }
+        return;
+    }
+    // The interrupt handlers take the PIC lock for their EOI
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut pics = PICS.lock();
+        unsafe {
+            let [mut master, mut slave] = pics.read_masks();
+            let (mask, bit) = if line < 8 { (&mut master, line) } else { (&mut slave, line - 8) };
+            if masked {
+                *mask |= 1 << bit;
+            } else {
+                *mask &= !(1 << bit);
+            }
+            // The cascade stays open for the slave's lines
+            if !masked && line >= 8 {
+                master &= !(1 << 2);
+            }
+            pics.write_masks(master, slave);
+        }
+    });
+}
+
+/// Interrupt entry stub that hands the handler the whole interrupted
+/// register state as a `TrapFrame`
+///
+/// Every general purpose register is saved so the handler can replace the
+/// whole interrupted context, not just the callee-saved part.
+macro_rules! trap_frame_entry {
+    ($entry:literal, $handler:path) => {
+        global_asm!(
+            concat!(".global ", $entry),
+            concat!($entry, ":"),
+            "push rax",
+            "push rbx",
+            "push rcx",
+            "push rdx",
+            "push rsi",
+            "push rdi",
+            "push rbp",
+            "push r8",
+            "push r9",
+            "push r10",
+            "push r11",
+            "push r12",
+            "push r13",
+            "push r14",
+            "push r15",
+            "mov rdi, rsp",
+            "cld",
+            "call {handler}",
+            "pop r15",
+            "pop r14",
+            "pop r13",
+            "pop r12",
+            "pop r11",
+            "pop r10",
+            "pop r9",
+            "pop r8",
+            "pop rbp",
+            "pop rdi",
+            "pop rsi",
+            "pop rdx",
+            "pop rcx",
+            "pop rbx",
+            "pop rax",
+            "iretq",
+            handler = sym $handler,
+        );
+    };
+}
+
+trap_frame_entry!("timer_interrupt_entry", timer_interrupt_handler);
+trap_frame_entry!("apic_timer_interrupt_entry", apic_timer_interrupt_handler);
+
+extern "C" fn timer_interrupt_handler(frame: &mut TrapFrame) {
+    IRQ_COUNTS[0].fetch_add(1, Ordering::Relaxed);
+    super::timer::record_tick();
+    crate::process::preempt::on_timer_interrupt(frame);
+    end_of_interrupt(0);
+}
+
+/// Tick of a secondary CPU, from its local APIC timer
+///
+/// Only the boot CPU's PIT tick advances the system time. The tick also
+/// catches the CPU up with the last performance level set.
+extern "C" fn apic_timer_interrupt_handler(frame: &mut TrapFrame) {
+    super::pstate::sync_local();
+    crate::process::preempt::on_timer_interrupt(frame);
+    super::apic::end_of_interrupt();
+}
+
+trap_frame_entry!("syscall_interrupt_entry", syscall_interrupt_handler);
+
+/// Run the system call the interrupted process raised with `int 0x80`
+///
+/// This is the only way into the kernel for system calls: every userspace
+/// wrapper raises `int 0x80`, and LSTAR is never programmed. The number is
+/// in rax, the arguments in rdi, rsi, rdx, r10, r8 and r9, and the result
+/// or negative errno back in rax. A successful `exec` returns into the
+/// entry point of the new image instead.
+extern "C" fn syscall_interrupt_handler(frame: &mut TrapFrame) {
+    frame.rax = crate::syscall::syscall_entry(frame.rax, frame.rdi, frame.rsi, frame.rdx, frame.r10, frame.r8, frame.r9);
+    crate::process::preempt::on_syscall_return(frame);
+}
+
+// Spurious APIC interrupts need neither handling nor an EOI
+global_asm!(
+    ".global spurious_interrupt_entry",
+    "spurious_interrupt_entry:",
+    "iretq",
+);
+
+// The keyboard entry never switches context, so only the caller-saved
+// registers are preserved; nine pushes keep the stack 16-byte aligned
+global_asm!(
+    ".global keyboard_interrupt_entry",
+    "keyboard_interrupt_entry:",
+    "push rax",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "cld",
+    "call {handler}",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rax",
+    "iretq",
+    handler = sym keyboard_interrupt_handler,
+);
+
+/// Forward IRQ 1 to the keyboard driver, or take a scancode off the
+/// PS/2 controller for kernel hotkeys (the dashboard and virtual
+/// terminals) while no driver has claimed it
+extern "C" fn keyboard_interrupt_handler() {
+    IRQ_COUNTS[1].fetch_add(1, Ordering::Relaxed);
+    if !crate::ipc::irq::dispatch(1) {
+        let io = X86_64IoOperations;
+        // Bit 0 of the status register: output buffer full
+        if io.port_read_u8(PS2_STATUS_PORT) & 0x01 != 0 {
+            let scancode = io.port_read_u8(PS2_DATA_PORT);
+            crate::monitor::on_scancode(scancode);
+            crate::vt::on_scancode(scancode);
+        }
+    }
+    end_of_interrupt(1);
+}
+
+/// Entry stub of a device IRQ line; like the keyboard entry it only
+/// preserves the caller-saved registers
+macro_rules! device_irq_entry {
+    ($entry:literal, $line:literal) => {
+        global_asm!(
+            concat!(".global ", $entry),
+            concat!($entry, ":"),
+            "push rax",
+            "push rcx",
+            "push rdx",
+            "push rsi",
+            "push rdi",
+            "push r8",
+            "push r9",
+            "push r10",
+            "push r11",
+            "cld",
+            concat!("mov edi, ", $line),
+            "call {handler}",
+            "pop r11",
+            "pop r10",
+            "pop r9",
+            "pop r8",
+            "pop rdi",
+            "pop rsi",
+            "pop rdx",
+            "pop rcx",
+            "pop rax",
+            "iretq",
+            handler = sym device_irq_handler,
+        );
+    };
+}
+
+device_irq_entry!("irq3_entry", 3);
+device_irq_entry!("irq4_entry", 4);
+device_irq_entry!("irq5_entry", 5);
+device_irq_entry!("irq6_entry", 6);
+device_irq_entry!("irq7_entry", 7);
+device_irq_entry!("irq8_entry", 8);
+device_irq_entry!("irq9_entry", 9);
+device_irq_entry!("irq10_entry", 10);
+device_irq_entry!("irq11_entry", 11);
+device_irq_entry!("irq12_entry", 12);
+device_irq_entry!("irq13_entry", 13);
+device_irq_entry!("irq14_entry", 14);
+device_irq_entry!("irq15_entry", 15);
+
+/// Hand a device interrupt to the driver that claimed its line, or
+/// console input to the serial console while no driver has claimed COM1
+extern "C" fn device_irq_handler(line: u32) {
+    let line = line as usize;
+    IRQ_COUNTS[line].fetch_add(1, Ordering::Relaxed);
+    let claimed = crate::ipc::irq::dispatch(line);
+    if !claimed && line == SERIAL_IRQ {
+        crate::serial::receive();
+    }
+    // Unclaimed lines stay masked, so IRQ 7 and 15 on them are spurious
+    // PIC interrupts: not in service, they take no EOI on their own PIC
+    if !claimed && !uses_ioapic() && (line == 7 || line == 15) {
+        if line == 15 {
+            unsafe {
+                PICS.lock().notify_end_of_interrupt(PIC_1_OFFSET);
+            }
+        }
+        return;
+    }
+    end_of_interrupt(line);
+}
+
+// One stub per MSI vector, each passing its index to the handler
+global_asm!(
+    ".global msi_entries",
+    ".balign {stride}",
+    "msi_entries:",
+    ".set msi_index, 0",
+    ".rept {count}",
+    ".balign {stride}",
+    "push rax",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "cld",
+    "mov edi, msi_index",
+    "call {handler}",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rax",
+    "iretq",
+    ".set msi_index, msi_index + 1",
+    ".endr",
+    count = const MSI_VECTORS,
+    stride = const MSI_STUB_STRIDE,
+    handler = sym msi_handler,
+);
+
+/// Hand a message-signaled interrupt to the driver that allocated it
+///
+/// The line is never masked here: the device keeps further messages to
+/// itself until its driver has serviced it.
+extern "C" fn msi_handler(index: u32) {
+    crate::ipc::irq::dispatch(crate::ipc::irq::MSI_FIRST_LINE + index as usize);
+    super::apic::end_of_interrupt();
+}
+
+/// Address and data raising message-signaled interrupt `index` on the
+/// CPU the I/O APIC delivers to, or None without I/O APIC routing
+pub fn msi_message(index: usize) -> Option<(u64, u32)> {
+    if !uses_ioapic() || index >= MSI_VECTORS {
+        return None;
+    }
+    let destination = super::ioapic::destination()?;
+    // Fixed delivery, edge triggered, to a physical destination
+    let address = MSI_ADDRESS_BASE | (destination as u64 & 0xFF) << MSI_DESTINATION_SHIFT;
+    Some((address, MSI_VECTOR_BASE as u32 + index as u32))
+}
+
+lazy_static! {
+    static ref IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        unsafe {
+            idt[TIMER_VECTOR as usize].set_handler_addr(VirtAddr::new(timer_interrupt_entry as u64));
+            idt[KEYBOARD_VECTOR as usize].set_handler_addr(VirtAddr::new(keyboard_interrupt_entry as u64));
+            let device_entries: [unsafe extern "C" fn(); 13] = [
+                irq3_entry, irq4_entry, irq5_entry, irq6_entry, irq7_entry, irq8_entry, irq9_entry,
+                irq10_entry, irq11_entry, irq12_entry, irq13_entry, irq14_entry, irq15_entry,
+            ];
+            for (line, entry) in (3..).zip(device_entries) {
+                idt[(PIC_1_OFFSET + line) as usize].set_handler_addr(VirtAddr::new(entry as u64));
+            }
+            idt[APIC_TIMER_VECTOR as usize].set_handler_addr(VirtAddr::new(apic_timer_interrupt_entry as u64));
+            idt[super::apic::SPURIOUS_VECTOR as usize].set_handler_addr(VirtAddr::new(spurious_interrupt_entry as u64));
+            for index in 0..MSI_VECTORS {
+                let entry = msi_entries as u64 + (index * MSI_STUB_STRIDE) as u64;
+                idt[MSI_VECTOR_BASE as usize + index].set_handler_addr(VirtAddr::new(entry));
+            }
+            idt[SYSCALL_VECTOR as usize].set_handler_addr(VirtAddr::new(syscall_interrupt_entry as u64))
+                .set_privilege_level(PrivilegeLevel::Ring3);
+        }
+        super::exceptions::install(&mut idt);
+        idt
+    };
+}
+
+/// Load the shared IDT on the calling CPU
+pub fn load_idt() {
+    IDT.load();
+}
 
 /// x86-64 interrupt handler implementation
 pub struct X86_64InterruptHandler {
@@ -16,14 +458,46 @@ impl X86_64InterruptHandler {
         }
     }
     
-    /// Setup the Interrupt Descriptor Table (IDT)
+    /// Setup the Interrupt Descriptor Table (IDT) and remap the PICs, then
+    /// move the IRQ lines to the I/O APIC if there is one
     pub fn setup_interrupts(&mut self) -> PlatformResult<()> {
-        // This would setup the IDT with proper interrupt handlers
-        // For now, just return success
+        IDT.load();
+        
+        {
+            let mut pics = PICS.lock();
+            unsafe {
+                pics.initialize();
+                // Only the timer, keyboard and COM1 are unmasked; other
+                // lines stay off until claimed
+                pics.write_masks(0xEC, 0xFF);
+            }
+        }
+        
+        match x86_64::instructions::interrupts::without_interrupts(route_through_ioapic) {
+            Ok(()) => log::info!("IRQ lines routed through the I/O APIC"),
+            Err(e) => log::info!("IRQ lines stay on the 8259 PICs ({})", e),
+        }
         Ok(())
     }
 }
 
+/// Route the timer, keyboard and COM1 through the I/O APICs the ACPI MADT lists
+/// and mask the PICs, which stay remapped so a spurious interrupt from
+/// them cannot look like an exception
+fn route_through_ioapic() -> PlatformResult<()> {
+    let madt = super::acpi::madt().ok_or(PlatformError::UnsupportedOperation)?;
+    super::apic::init_local()?;
+    super::ioapic::init(madt, super::apic::id())?;
+    for line in [0u8, 1, SERIAL_IRQ as u8] {
+        super::ioapic::set_isa_line_masked(line, PIC_1_OFFSET + line, false)?;
+    }
+    unsafe {
+        PICS.lock().write_masks(0xFF, 0xFF);
+    }
+    IOAPIC_ROUTING.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
 impl InterruptHandling for X86_64InterruptHandler {
     fn enable_interrupts(&self) {
         unsafe {
@@ -62,18 +536,13 @@ impl InterruptHandling for X86_64InterruptHandler {
     }
     
     fn send_eoi(&self, interrupt_number: u8) -> PlatformResult<()> {
-        // Send End of Interrupt to APIC/PIC
-        // This is a simplified implementation
-        if interrupt_number >= 32 && interrupt_number < 48 {
-            // PIC interrupts
-            unsafe {
-                if interrupt_number >= 40 {
-                    // Slave PIC
-                    asm!("out 0xA0, al", in("al") 0x20u8);
-                }
-                // Master PIC
-                asm!("out 0x20, al", in("al") 0x20u8);
-            }
+        // Send End of Interrupt to the I/O APIC's local APIC or the PICs
+        if (PIC_1_OFFSET..PIC_1_OFFSET + IRQ_LINES as u8).contains(&interrupt_number) {
+            end_of_interrupt((interrupt_number - PIC_1_OFFSET) as usize);
+        } else if interrupt_number == APIC_TIMER_VECTOR
+            || (MSI_VECTOR_BASE..MSI_VECTOR_BASE + MSI_VECTORS as u8).contains(&interrupt_number)
+        {
+            super::apic::end_of_interrupt();
         }
         Ok(())
     }
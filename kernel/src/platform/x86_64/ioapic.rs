@@ -0,0 +1,192 @@
+//! I/O APIC
+//!
+//! Where the ACPI MADT lists I/O APICs, the legacy IRQ lines are routed
+//! through them to the boot CPU's local APIC instead of through the 8259
+//! PICs, which are then masked for good. Each ISA line keeps the vector the
+//! PICs gave it, so the IDT is the same either way; the MADT's interrupt
+//! source overrides say which global system interrupt (GSI) a line arrives
+//! on, and with what polarity and trigger mode.
+
+use alloc::vec::Vec;
+use spin::{Mutex, Once};
+use super::super::{PlatformResult, PlatformError};
+use super::acpi::{InterruptOverride, MadtInfo};
+use crate::memory::vmm::kernel_layout::PHYSICAL_MEMORY_OFFSET;
+
+/// Register select and data window offsets
+const IOREGSEL: usize = 0x00;
+const IOWIN: usize = 0x10;
+
+/// Indirect registers
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION_TABLE: u32 = 0x10;
+
+/// Redirection entry fields; fixed delivery to a physical destination is
+/// all zeros
+const ENTRY_ACTIVE_LOW: u64 = 1 << 13;
+const ENTRY_LEVEL_TRIGGERED: u64 = 1 << 15;
+const ENTRY_MASKED: u64 = 1 << 16;
+const ENTRY_DESTINATION_SHIFT: u64 = 56;
+
+/// MPS INTI flags of an interrupt source override
+const INTI_POLARITY_MASK: u16 = 0b11;
+const INTI_POLARITY_ACTIVE_LOW: u16 = 0b11;
+const INTI_TRIGGER_MASK: u16 = 0b11 << 2;
+const INTI_TRIGGER_LEVEL: u16 = 0b11 << 2;
+
+/// An I/O APIC and the GSIs it handles
+#[derive(Debug, Clone, Copy)]
+struct IoApicUnit {
+    address: u64,
+    gsi_base: u32,
+    entries: u32,
+}
+
+impl IoApicUnit {
+    fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi - self.gsi_base < self.entries
+    }
+}
+
+/// Where and how an ISA IRQ line arrives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsaRoute {
+    pub gsi: u32,
+    pub active_low: bool,
+    pub level_triggered: bool,
+}
+
+/// The I/O APICs and how ISA lines reach them
+struct Routing {
+    units: Vec<IoApicUnit>,
+    overrides: Vec<InterruptOverride>,
+    /// APIC ID of the CPU every line is delivered to
+    destination: u32,
+}
+
+static ROUTING: Once<Routing> = Once::new();
+
+/// Serializes the select-then-access register pairs
+static REGISTER_LOCK: Mutex<()> = Mutex::new(());
+
+/// Route of ISA IRQ `line`: identity mapped and active high, edge triggered
+/// unless an override says otherwise
+pub fn isa_route(overrides: &[InterruptOverride], line: u8) -> IsaRoute {
+    match overrides.iter().find(|entry| entry.source == line) {
+        Some(entry) => IsaRoute {
+            gsi: entry.gsi,
+            active_low: entry.flags & INTI_POLARITY_MASK == INTI_POLARITY_ACTIVE_LOW,
+            level_triggered: entry.flags & INTI_TRIGGER_MASK == INTI_TRIGGER_LEVEL,
+        },
+        None => IsaRoute { gsi: line as u32, active_low: false, level_triggered: false },
+    }
+}
+
+/// Redirection table entry delivering `route` as `vector` to the CPU with
+/// APIC ID `destination`
+pub fn redirection_entry(route: IsaRoute, vector: u8, destination: u32, masked: bool) -> u64 {
+    let mut entry = vector as u64 | ((destination as u64 & 0xFF) << ENTRY_DESTINATION_SHIFT);
+    if route.active_low {
+        entry |= ENTRY_ACTIVE_LOW;
+    }
+    if route.level_triggered {
+        entry |= ENTRY_LEVEL_TRIGGERED;
+    }
+    if masked {
+        entry |= ENTRY_MASKED;
+    }
+    entry
+}
+
+fn read(unit: &IoApicUnit, register: u32) -> u32 {
+    let base = PHYSICAL_MEMORY_OFFSET.as_usize() + unit.address as usize;
+    unsafe {
+        core::ptr::write_volatile((base + IOREGSEL) as *mut u32, register);
+        core::ptr::read_volatile((base + IOWIN) as *const u32)
+    }
+}
+
+fn write(unit: &IoApicUnit, register: u32, value: u32) {
+    let base = PHYSICAL_MEMORY_OFFSET.as_usize() + unit.address as usize;
+    unsafe {
+        core::ptr::write_volatile((base + IOREGSEL) as *mut u32, register);
+        core::ptr::write_volatile((base + IOWIN) as *mut u32, value);
+    }
+}
+
+fn write_entry(unit: &IoApicUnit, gsi: u32, entry: u64) {
+    let register = IOAPIC_REDIRECTION_TABLE + (gsi - unit.gsi_base) * 2;
+    let _guard = REGISTER_LOCK.lock();
+    // Mask while the halves disagree, then write the low half last
+    write(unit, register, ENTRY_MASKED as u32);
+    write(unit, register + 1, (entry >> 32) as u32);
+    write(unit, register, entry as u32);
+}
+
+/// Take over the I/O APICs the MADT lists, every entry masked, delivering
+/// to the CPU with APIC ID `destination`
+pub fn init(madt: &MadtInfo, destination: u32) -> PlatformResult<()> {
+    if madt.io_apics.is_empty() {
+        return Err(PlatformError::UnsupportedOperation);
+    }
+
+    let routing = ROUTING.call_once(|| Routing {
+        units: madt.io_apics.iter().map(|io_apic| {
+            let mut unit = IoApicUnit { address: io_apic.address as u64, gsi_base: io_apic.gsi_base, entries: 0 };
+            // Bits 16-23 of the version register: index of the last entry
+            unit.entries = ((read(&unit, IOAPIC_VERSION) >> 16) & 0xFF) + 1;
+            unit
+        }).collect(),
+        overrides: madt.overrides.clone(),
+        destination,
+    });
+
+    for unit in &routing.units {
+        for gsi in unit.gsi_base..unit.gsi_base + unit.entries {
+            write_entry(unit, gsi, ENTRY_MASKED);
+        }
+    }
+    Ok(())
+}
+
+/// APIC ID of the CPU the lines are delivered to, once routing is set up
+pub fn destination() -> Option<u32> {
+    ROUTING.get().map(|routing| routing.destination)
+}
+
+/// Mask or unmask ISA IRQ `line`, delivered as `vector`
+pub fn set_isa_line_masked(line: u8, vector: u8, masked: bool) -> PlatformResult<()> {
+    let routing = ROUTING.get().ok_or(PlatformError::HardwareError)?;
+    let route = isa_route(&routing.overrides, line);
+    let unit = routing.units.iter()
+        .find(|unit| unit.handles(route.gsi))
+        .ok_or(PlatformError::InvalidAddress)?;
+    write_entry(unit, route.gsi, redirection_entry(route, vector, routing.destination, masked));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_isa_routes_follow_overrides() {
+        let overrides = [
+            InterruptOverride { source: 0, gsi: 2, flags: 0 },
+            InterruptOverride { source: 9, gsi: 9, flags: 0b1111 },
+        ];
+        assert_eq!(isa_route(&overrides, 0), IsaRoute { gsi: 2, active_low: false, level_triggered: false });
+        assert_eq!(isa_route(&overrides, 9), IsaRoute { gsi: 9, active_low: true, level_triggered: true });
+        assert_eq!(isa_route(&overrides, 1), IsaRoute { gsi: 1, active_low: false, level_triggered: false });
+    }
+
+    #[test_case]
+    fn test_redirection_entry_layout() {
+        let edge = IsaRoute { gsi: 1, active_low: false, level_triggered: false };
+        assert_eq!(redirection_entry(edge, 33, 0, false), 33);
+        assert_eq!(redirection_entry(edge, 33, 3, true), 33 | ENTRY_MASKED | 3 << 56);
+
+        let level = IsaRoute { gsi: 9, active_low: true, level_triggered: true };
+        assert_eq!(redirection_entry(level, 41, 1, false), 41 | ENTRY_ACTIVE_LOW | ENTRY_LEVEL_TRIGGERED | 1 << 56);
+    }
+}
@@ -0,0 +1,551 @@
+//! Intel VT-d DMA remapping
+//!
+//! Parses the ACPI DMAR table and programs the remapping hardware units with
+//! a shared root table, per-bus context tables and one 4-level second-level
+//! page table per domain. Devices without a context entry are blocked once
+//! translation is enabled.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use super::super::traits::IommuOperations;
+use super::super::{PhysicalAddress, PlatformResult, PlatformError, IommuDomainId, DmaDeviceId};
+use super::acpi::{read_u16, read_u32, read_u64};
+use crate::memory::physical::{self, PageFrame};
+use crate::memory::vmm::kernel_layout::PHYSICAL_MEMORY_OFFSET;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// DMAR remapping structure types
+const DMAR_TYPE_DRHD: u16 = 0;
+const DMAR_TYPE_RMRR: u16 = 1;
+
+/// DRHD flag: unit covers every device on its segment not listed elsewhere
+const DRHD_INCLUDE_PCI_ALL: u8 = 0x1;
+
+/// Remapping unit register offsets
+const REG_CAP: usize = 0x08;
+const REG_ECAP: usize = 0x10;
+const REG_GCMD: usize = 0x18;
+const REG_GSTS: usize = 0x1C;
+const REG_RTADDR: usize = 0x20;
+const REG_CCMD: usize = 0x28;
+
+/// Global command/status bits
+const GCMD_TE: u32 = 1 << 31;
+const GCMD_SRTP: u32 = 1 << 30;
+/// GSTS bits that are persistent and must be written back to GCMD
+const GSTS_PERSISTENT_MASK: u32 = 0x96FF_FFFF;
+
+/// Global context-cache invalidation
+const CCMD_ICC: u64 = 1 << 63;
+const CCMD_GLOBAL: u64 = 1 << 61;
+
+/// Global IOTLB invalidation (draining reads and writes)
+const IOTLB_IVT: u64 = 1 << 63;
+const IOTLB_GLOBAL: u64 = 1 << 60;
+const IOTLB_DRAIN: u64 = (1 << 49) | (1 << 48);
+
+/// Capability: 4-level (48-bit) second-level tables supported
+const CAP_SAGAW_4_LEVEL: u64 = 1 << 10;
+/// Extended capability: page walks snoop the CPU caches
+const ECAP_COHERENT: u64 = 1 << 0;
+
+/// Second-level page table entry bits
+const SL_READ: u64 = 1 << 0;
+const SL_WRITE: u64 = 1 << 1;
+const SL_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Context entry address width encoding for 48-bit, 4-level tables
+const CONTEXT_AW_48: u64 = 0b010;
+
+/// Polling limit for hardware status changes
+const STATUS_TIMEOUT: usize = 1_000_000;
+
+/// A DMA remapping hardware unit (DRHD entry)
+#[derive(Debug, Clone)]
+pub struct DrhdUnit {
+    pub segment: u16,
+    pub register_base: u64,
+    pub include_all: bool,
+    pub devices: Vec<DmaDeviceId>,
+}
+
+/// Memory a device keeps using for DMA behind the OS's back (RMRR entry)
+#[derive(Debug, Clone)]
+pub struct ReservedRegion {
+    pub base: u64,
+    pub limit: u64,
+    pub devices: Vec<DmaDeviceId>,
+}
+
+/// Contents of the ACPI DMAR table
+#[derive(Debug, Clone)]
+pub struct DmarInfo {
+    pub host_address_width: u8,
+    pub units: Vec<DrhdUnit>,
+    pub reserved_regions: Vec<ReservedRegion>,
+}
+
+/// Parse device scope entries into requester IDs
+///
+/// Only the first path element is used, which is exact for devices on the
+/// start bus; devices behind bridges need the bus numbers assigned during
+/// PCI enumeration.
+fn parse_device_scopes(segment: u16, scopes: &[u8]) -> PlatformResult<Vec<DmaDeviceId>> {
+    let mut devices = Vec::new();
+    let mut offset = 0;
+
+    while offset + 6 <= scopes.len() {
+        let length = scopes[offset + 1] as usize;
+        if length < 6 || offset + length > scopes.len() {
+            return Err(PlatformError::InvalidAddress);
+        }
+
+        let start_bus = scopes[offset + 5];
+        if length >= 8 {
+            let device = scopes[offset + 6];
+            let function = scopes[offset + 7];
+            devices.push(DmaDeviceId::pci(segment, start_bus, device, function));
+        }
+
+        offset += length;
+    }
+
+    Ok(devices)
+}
+
+/// Parse an ACPI DMAR table
+pub fn parse_dmar(table: &[u8]) -> PlatformResult<DmarInfo> {
+    if table.get(0..4) != Some(b"DMAR".as_slice()) {
+        return Err(PlatformError::InvalidAddress);
+    }
+
+    let length = read_u32(table, 4)? as usize;
+    if length > table.len() || length < 48 {
+        return Err(PlatformError::InvalidAddress);
+    }
+    let table = &table[..length];
+
+    let mut info = DmarInfo {
+        host_address_width: table[36] + 1,
+        units: Vec::new(),
+        reserved_regions: Vec::new(),
+    };
+
+    let mut offset = 48;
+    while offset + 4 <= table.len() {
+        let structure_type = read_u16(table, offset)?;
+        let structure_length = read_u16(table, offset + 2)? as usize;
+        if structure_length < 4 || offset + structure_length > table.len() {
+            return Err(PlatformError::InvalidAddress);
+        }
+        let structure = &table[offset..offset + structure_length];
+
+        match structure_type {
+            DMAR_TYPE_DRHD if structure_length >= 16 => {
+                let segment = read_u16(structure, 6)?;
+                info.units.push(DrhdUnit {
+                    segment,
+                    register_base: read_u64(structure, 8)?,
+                    include_all: structure[4] & DRHD_INCLUDE_PCI_ALL != 0,
+                    devices: parse_device_scopes(segment, &structure[16..])?,
+                });
+            }
+            DMAR_TYPE_RMRR if structure_length >= 24 => {
+                let segment = read_u16(structure, 6)?;
+                info.reserved_regions.push(ReservedRegion {
+                    base: read_u64(structure, 8)?,
+                    limit: read_u64(structure, 16)?,
+                    devices: parse_device_scopes(segment, &structure[24..])?,
+                });
+            }
+            // ATSR, RHSA and ANDD structures are not needed for isolation
+            _ => {}
+        }
+
+        offset += structure_length;
+    }
+
+    if info.units.is_empty() {
+        return Err(PlatformError::UnsupportedOperation);
+    }
+
+    Ok(info)
+}
+
+fn phys_to_virt(physical: u64) -> *mut u64 {
+    (PHYSICAL_MEMORY_OFFSET.as_usize() as u64 + physical) as *mut u64
+}
+
+/// Intel VT-d IOMMU
+pub struct VtdIommu {
+    info: DmarInfo,
+    /// Root table shared by every remapping unit (one entry per bus)
+    root_table: u64,
+    /// Context table per bus number
+    context_tables: BTreeMap<u8, u64>,
+    /// Top-level second-level page table per domain
+    domains: BTreeMap<IommuDomainId, u64>,
+    /// Device to domain assignment
+    devices: BTreeMap<DmaDeviceId, IommuDomainId>,
+    /// Page walks do not snoop caches; table writes must be flushed
+    needs_flush: bool,
+    enabled: bool,
+}
+
+impl VtdIommu {
+    /// Set up VT-d from a DMAR table
+    pub fn new(info: DmarInfo) -> PlatformResult<Self> {
+        let mut needs_flush = false;
+        for unit in &info.units {
+            let cap = Self::read_reg64(unit.register_base, REG_CAP);
+            if cap & CAP_SAGAW_4_LEVEL == 0 {
+                // Only 4-level tables are implemented
+                return Err(PlatformError::UnsupportedOperation);
+            }
+            if Self::read_reg64(unit.register_base, REG_ECAP) & ECAP_COHERENT == 0 {
+                needs_flush = true;
+            }
+        }
+
+        let mut iommu = Self {
+            info,
+            root_table: 0,
+            context_tables: BTreeMap::new(),
+            domains: BTreeMap::new(),
+            devices: BTreeMap::new(),
+            needs_flush,
+            enabled: false,
+        };
+        iommu.root_table = iommu.allocate_table()?;
+        Ok(iommu)
+    }
+
+    /// Parsed DMAR information
+    pub fn dmar_info(&self) -> &DmarInfo {
+        &self.info
+    }
+
+    fn read_reg32(base: u64, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile(phys_to_virt(base + offset as u64) as *const u32) }
+    }
+
+    fn write_reg32(base: u64, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile(phys_to_virt(base + offset as u64) as *mut u32, value) }
+    }
+
+    fn read_reg64(base: u64, offset: usize) -> u64 {
+        unsafe { core::ptr::read_volatile(phys_to_virt(base + offset as u64)) }
+    }
+
+    fn write_reg64(base: u64, offset: usize, value: u64) {
+        unsafe { core::ptr::write_volatile(phys_to_virt(base + offset as u64), value) }
+    }
+
+    fn wait_for_status(base: u64, bit: u32, set: bool) -> PlatformResult<()> {
+        for _ in 0..STATUS_TIMEOUT {
+            if (Self::read_reg32(base, REG_GSTS) & bit != 0) == set {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(PlatformError::HardwareError)
+    }
+
+    fn issue_command(base: u64, command: u32) -> PlatformResult<()> {
+        let status = Self::read_reg32(base, REG_GSTS) & GSTS_PERSISTENT_MASK;
+        Self::write_reg32(base, REG_GCMD, status | command);
+        Self::wait_for_status(base, command, true)
+    }
+
+    /// Invalidate context caches and IOTLBs on every unit
+    fn invalidate_all(&self) -> PlatformResult<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        for unit in &self.info.units {
+            let base = unit.register_base;
+
+            Self::write_reg64(base, REG_CCMD, CCMD_ICC | CCMD_GLOBAL);
+            let mut done = false;
+            for _ in 0..STATUS_TIMEOUT {
+                if Self::read_reg64(base, REG_CCMD) & CCMD_ICC == 0 {
+                    done = true;
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+            if !done {
+                return Err(PlatformError::HardwareError);
+            }
+
+            // The IOTLB register pair sits at the offset advertised in ECAP.IRO
+            let iotlb = ((Self::read_reg64(base, REG_ECAP) >> 8) & 0x3FF) as usize * 16 + 8;
+            Self::write_reg64(base, iotlb, IOTLB_IVT | IOTLB_GLOBAL | IOTLB_DRAIN);
+            done = false;
+            for _ in 0..STATUS_TIMEOUT {
+                if Self::read_reg64(base, iotlb) & IOTLB_IVT == 0 {
+                    done = true;
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+            if !done {
+                return Err(PlatformError::HardwareError);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush_entry(&self, entry: *mut u64) {
+        if self.needs_flush {
+            unsafe { core::arch::x86_64::_mm_clflush(entry as *const u8) };
+        }
+    }
+
+    fn allocate_table(&self) -> PlatformResult<u64> {
+        let frame = physical::allocate_frame().ok_or(PlatformError::HardwareError)?;
+        let physical = frame.address() as u64;
+        unsafe { core::ptr::write_bytes(phys_to_virt(physical) as *mut u8, 0, PAGE_SIZE as usize) };
+        if self.needs_flush {
+            for line in (0..PAGE_SIZE).step_by(64) {
+                unsafe { core::arch::x86_64::_mm_clflush((phys_to_virt(physical) as *const u8).add(line as usize)) };
+            }
+        }
+        Ok(physical)
+    }
+
+    /// Context table for a bus, creating it and its root entry on first use
+    fn context_table(&mut self, bus: u8) -> PlatformResult<u64> {
+        if let Some(table) = self.context_tables.get(&bus) {
+            return Ok(*table);
+        }
+
+        let table = self.allocate_table()?;
+        let root_entry = unsafe { phys_to_virt(self.root_table).add(bus as usize * 2) };
+        unsafe { core::ptr::write_volatile(root_entry, (table & SL_ADDRESS_MASK) | 1) };
+        self.flush_entry(root_entry);
+
+        self.context_tables.insert(bus, table);
+        Ok(table)
+    }
+
+    /// Leaf entry for an IOVA, allocating intermediate tables when asked
+    fn leaf_entry(&self, top: u64, iova: u64, allocate: bool) -> PlatformResult<Option<*mut u64>> {
+        let mut table = top;
+
+        for level in (1..4).rev() {
+            let index = ((iova >> (12 + 9 * level)) & 0x1FF) as usize;
+            let entry = unsafe { phys_to_virt(table).add(index) };
+            let value = unsafe { core::ptr::read_volatile(entry) };
+
+            table = if value & (SL_READ | SL_WRITE) != 0 {
+                value & SL_ADDRESS_MASK
+            } else if allocate {
+                let next = self.allocate_table()?;
+                unsafe { core::ptr::write_volatile(entry, next | SL_READ | SL_WRITE) };
+                self.flush_entry(entry);
+                next
+            } else {
+                return Ok(None);
+            };
+        }
+
+        let index = ((iova >> 12) & 0x1FF) as usize;
+        Ok(Some(unsafe { phys_to_virt(table).add(index) }))
+    }
+
+    fn free_tables(table: u64, level: usize) {
+        if level > 1 {
+            for index in 0..512 {
+                let value = unsafe { core::ptr::read_volatile(phys_to_virt(table).add(index)) };
+                if value & (SL_READ | SL_WRITE) != 0 {
+                    Self::free_tables(value & SL_ADDRESS_MASK, level - 1);
+                }
+            }
+        }
+        physical::deallocate_frame(PageFrame::from_address(table as usize));
+    }
+
+    /// Identity-map RMRR regions used by a device so firmware-initiated DMA keeps working
+    fn map_reserved_regions(&mut self, domain: IommuDomainId, device: DmaDeviceId) -> PlatformResult<()> {
+        let regions: Vec<(u64, u64)> = self.info.reserved_regions.iter()
+            .filter(|region| region.devices.contains(&device))
+            .map(|region| (region.base, region.limit))
+            .collect();
+
+        for (base, limit) in regions {
+            let size = (limit - base + 1) as usize;
+            self.map(domain, base, PhysicalAddress::new(base), size, true)?;
+        }
+        Ok(())
+    }
+}
+
+impl IommuOperations for VtdIommu {
+    fn name(&self) -> &'static str {
+        "VT-d"
+    }
+
+    fn enable(&mut self) -> PlatformResult<()> {
+        for unit in &self.info.units {
+            let base = unit.register_base;
+            Self::write_reg64(base, REG_RTADDR, self.root_table);
+            Self::issue_command(base, GCMD_SRTP)?;
+        }
+
+        self.enabled = true;
+        self.invalidate_all()?;
+
+        for unit in &self.info.units {
+            Self::issue_command(unit.register_base, GCMD_TE)?;
+        }
+
+        Ok(())
+    }
+
+    fn create_domain(&mut self, domain: IommuDomainId) -> PlatformResult<()> {
+        if self.domains.contains_key(&domain) {
+            return Err(PlatformError::InvalidAddress);
+        }
+        let top = self.allocate_table()?;
+        self.domains.insert(domain, top);
+        Ok(())
+    }
+
+    fn destroy_domain(&mut self, domain: IommuDomainId) -> PlatformResult<()> {
+        if self.devices.values().any(|assigned| *assigned == domain) {
+            return Err(PlatformError::InvalidAddress);
+        }
+        let top = self.domains.remove(&domain).ok_or(PlatformError::InvalidAddress)?;
+        self.invalidate_all()?;
+        Self::free_tables(top, 4);
+        Ok(())
+    }
+
+    fn attach_device(&mut self, domain: IommuDomainId, device: DmaDeviceId) -> PlatformResult<()> {
+        let top = *self.domains.get(&domain).ok_or(PlatformError::InvalidAddress)?;
+        let table = self.context_table(device.bus())?;
+
+        let entry = unsafe { phys_to_virt(table).add(device.devfn() as usize * 2) };
+        unsafe {
+            // Write the upper half first so the entry never becomes present half-initialized
+            core::ptr::write_volatile(entry.add(1), CONTEXT_AW_48 | ((domain.0 as u64) << 8));
+            core::ptr::write_volatile(entry, (top & SL_ADDRESS_MASK) | 1);
+        }
+        self.flush_entry(entry);
+
+        self.devices.insert(device, domain);
+        self.map_reserved_regions(domain, device)?;
+        self.invalidate_all()
+    }
+
+    fn detach_device(&mut self, device: DmaDeviceId) -> PlatformResult<()> {
+        self.devices.remove(&device).ok_or(PlatformError::InvalidAddress)?;
+
+        if let Some(table) = self.context_tables.get(&device.bus()) {
+            let entry = unsafe { phys_to_virt(*table).add(device.devfn() as usize * 2) };
+            unsafe {
+                core::ptr::write_volatile(entry, 0);
+                core::ptr::write_volatile(entry.add(1), 0);
+            }
+            self.flush_entry(entry);
+        }
+
+        self.invalidate_all()
+    }
+
+    fn map(&mut self, domain: IommuDomainId, iova: u64, physical: PhysicalAddress, size: usize, writable: bool) -> PlatformResult<()> {
+        if iova % PAGE_SIZE != 0 || physical.as_u64() % PAGE_SIZE != 0 {
+            return Err(PlatformError::InvalidAddress);
+        }
+        let top = *self.domains.get(&domain).ok_or(PlatformError::InvalidAddress)?;
+        let permissions = if writable { SL_READ | SL_WRITE } else { SL_READ };
+
+        let pages = (size as u64 + PAGE_SIZE - 1) / PAGE_SIZE;
+        for page in 0..pages {
+            let offset = page * PAGE_SIZE;
+            let entry = self.leaf_entry(top, iova + offset, true)?
+                .ok_or(PlatformError::InvalidAddress)?;
+            unsafe { core::ptr::write_volatile(entry, ((physical.as_u64() + offset) & SL_ADDRESS_MASK) | permissions) };
+            self.flush_entry(entry);
+        }
+
+        Ok(())
+    }
+
+    fn unmap(&mut self, domain: IommuDomainId, iova: u64, size: usize) -> PlatformResult<()> {
+        let top = *self.domains.get(&domain).ok_or(PlatformError::InvalidAddress)?;
+
+        let pages = (size as u64 + PAGE_SIZE - 1) / PAGE_SIZE;
+        for page in 0..pages {
+            if let Some(entry) = self.leaf_entry(top, iova + page * PAGE_SIZE, false)? {
+                unsafe { core::ptr::write_volatile(entry, 0) };
+                self.flush_entry(entry);
+            }
+        }
+
+        // A stale IOTLB entry would keep the old page reachable
+        self.invalidate_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn dmar_table() -> Vec<u8> {
+        let mut table = vec![0u8; 48];
+        table[0..4].copy_from_slice(b"DMAR");
+        table[36] = 38; // 39-bit host address width
+
+        // DRHD for segment 0 with one device scope (00:02.0)
+        let mut drhd = vec![0u8; 16];
+        drhd[0..2].copy_from_slice(&DMAR_TYPE_DRHD.to_le_bytes());
+        drhd[2..4].copy_from_slice(&24u16.to_le_bytes());
+        drhd[8..16].copy_from_slice(&0xFED9_0000u64.to_le_bytes());
+        drhd.extend_from_slice(&[1, 8, 0, 0, 0, 0, 2, 0]);
+        table.extend_from_slice(&drhd);
+
+        // RMRR for 00:14.0 covering one page
+        let mut rmrr = vec![0u8; 24];
+        rmrr[0..2].copy_from_slice(&DMAR_TYPE_RMRR.to_le_bytes());
+        rmrr[2..4].copy_from_slice(&32u16.to_le_bytes());
+        rmrr[8..16].copy_from_slice(&0x7D00_0000u64.to_le_bytes());
+        rmrr[16..24].copy_from_slice(&0x7D00_0FFFu64.to_le_bytes());
+        rmrr.extend_from_slice(&[1, 8, 0, 0, 0, 0, 0x14, 0]);
+        table.extend_from_slice(&rmrr);
+
+        let length = table.len() as u32;
+        table[4..8].copy_from_slice(&length.to_le_bytes());
+        table
+    }
+
+    #[test_case]
+    fn test_parse_dmar() {
+        let info = parse_dmar(&dmar_table()).unwrap();
+
+        assert_eq!(info.host_address_width, 39);
+        assert_eq!(info.units.len(), 1);
+        assert_eq!(info.units[0].register_base, 0xFED9_0000);
+        assert!(!info.units[0].include_all);
+        assert_eq!(info.units[0].devices, vec![DmaDeviceId::pci(0, 0, 2, 0)]);
+
+        assert_eq!(info.reserved_regions.len(), 1);
+        assert_eq!(info.reserved_regions[0].base, 0x7D00_0000);
+        assert_eq!(info.reserved_regions[0].devices, vec![DmaDeviceId::pci(0, 0, 0x14, 0)]);
+    }
+
+    #[test_case]
+    fn test_parse_dmar_rejects_bad_tables() {
+        let mut table = dmar_table();
+        table[0] = b'X';
+        assert!(parse_dmar(&table).is_err());
+
+        let table = dmar_table();
+        assert!(parse_dmar(&table[..40]).is_err());
+    }
+}
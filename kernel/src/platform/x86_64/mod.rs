@@ -4,22 +4,43 @@
 
 use super::traits::*;
 use super::{
-    CpuInfo, CpuArchitecture, CpuFeatures, MemoryMap, MemoryRegion, MemoryRegionType,
+    CpuInfo, CpuArchitecture, CpuFeatures, CoreCapacity, MemoryMap, MemoryRegion, MemoryRegionType,
     VirtualAddress, PhysicalAddress, PageFlags, PlatformResult, PlatformError
 };
 use core::sync::atomic::{AtomicBool, Ordering};
 
+pub mod acpi;
 pub mod registers;
 pub mod memory;
 pub mod interrupts;
+pub mod exceptions;
 pub mod cache;
 pub mod context;
 pub mod timer;
+pub mod clock;
+pub mod rng;
 pub mod power;
+pub mod battery;
+pub mod thermal;
+pub mod pstate;
+pub mod cstate;
 pub mod io;
+pub mod uart;
+pub mod iommu;
+pub mod apic;
+pub mod ioapic;
+pub mod smp;
 
 pub use registers::X86_64Registers;
 
+/// The console UART of this platform
+pub type PlatformUart = uart::Uart16550;
+
+/// The console UART, COM1
+pub fn console_uart() -> PlatformUart {
+    uart::Uart16550::new(uart::COM1)
+}
+
 /// x86-64 platform implementation
 pub struct X86_64Platform {
     initialized: AtomicBool,
@@ -67,11 +88,12 @@ impl PlatformInterface for X86_64Platform {
         
         let model_name = "x86-64 CPU"; // Simplified for now
         
-        let core_count = if let Some(feature_info) = cpuid.get_feature_info() {
-            // This is a simplified approach - real implementation would be more complex
-            1 // Default to 1 core for now
-        } else {
-            1
+        // The processors the ACPI MADT enables; without one, the logical
+        // processors per package CPUID reports, an upper bound
+        let core_count = match (acpi::madt(), cpuid.get_feature_info()) {
+            (Some(madt), _) => madt.enabled_processors().count().max(1) as u32,
+            (None, Some(feature_info)) if feature_info.has_htt() => feature_info.max_logical_processor_ids().max(1) as u32,
+            _ => 1,
         };
         
         let features = CpuFeatures {
@@ -90,6 +112,8 @@ impl PlatformInterface for X86_64Platform {
             core_count,
             cache_line_size: 64, // Standard for x86-64
             features,
+            // Hybrid parts (CPUID leaf 0x1A) are treated as uniform for now
+            core_capacities: CoreCapacity::uniform(core_count, 2400),
         }
     }
     
@@ -169,6 +193,15 @@ pub fn init() -> PlatformResult<()> {
     Err(PlatformError::HardwareError)
 }
 
+/// Start the periodic timer interrupt
+pub fn start_timer(frequency_hz: u32) -> PlatformResult<()> {
+    let platform = unsafe { PLATFORM_INSTANCE.as_mut() }.ok_or(PlatformError::HardwareError)?;
+    platform.interrupt_handler.setup_interrupts()?;
+    platform.timer_ops.setup_periodic_timer(frequency_hz)?;
+    platform.interrupt_handler.enable_interrupts();
+    Ok(())
+}
+
 /// Get the current platform instance
 pub fn get_platform() -> &'static dyn PlatformInterface {
     unsafe {
@@ -1,6 +1,7 @@
 //! x86-64 power management implementation
 
 use core::arch::asm;
+use super::acpi;
 use super::super::traits::PowerManagement;
 use super::super::{PlatformResult, PlatformError};
 
@@ -34,8 +35,9 @@ impl PowerManagement for X86_64PowerManagement {
     }
     
     fn system_reset(&self) -> ! {
+        acpi::reset();
         unsafe {
-            // Triple fault to reset
+            // Triple fault if the FADT reset register did not work
             asm!("cli");
             asm!("lidt [{}]", in(reg) 0usize); // Load invalid IDT
             asm!("int3"); // Trigger interrupt with invalid IDT
@@ -46,8 +48,9 @@ impl PowerManagement for X86_64PowerManagement {
     }
     
     fn system_shutdown(&self) -> ! {
-        // ACPI shutdown would go here
-        // For now, just halt
+        // Enter S5; halt if ACPI cannot power the machine off
+        acpi::shutdown();
+        log::warn!("ACPI power-off unavailable; halting");
         self.cpu_halt()
     }
     
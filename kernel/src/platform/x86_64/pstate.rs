@@ -0,0 +1,213 @@
+//! x86-64 CPU performance control
+//!
+//! Three backends are tried, best first:
+//!
+//! - Intel hardware-controlled P-states (HWP): once enabled, the CPU picks
+//!   its own operating point inside the bounds of IA32_HWP_REQUEST, and a
+//!   level pins minimum, maximum and desired performance to one ratio.
+//! - The ACPI `_PSS` states: the control value of a state is written to
+//!   IA32_PERF_CTL on Intel CPUs with Enhanced SpeedStep, or to the P-state
+//!   control MSR on AMD CPUs with hardware P-states.
+//! - Enhanced SpeedStep without `_PSS`: every bus ratio between the
+//!   maximum efficiency and maximum non-turbo ratios of MSR_PLATFORM_INFO.
+//!
+//! Bus ratios are taken as 100 MHz steps. The MSRs are per CPU: the CPU
+//! that sets a level writes it at once, and the others write it on their
+//! next APIC timer tick.
+
+use alloc::vec::Vec;
+use core::arch::x86_64::__cpuid;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Once;
+use x86_64::registers::model_specific::Msr;
+use super::super::{FrequencyControl, PerformanceLevel, PlatformResult, PlatformError};
+use crate::smp::MAX_CPUS;
+
+/// Intel performance MSRs
+const MSR_PLATFORM_INFO: u32 = 0xCE;
+const IA32_PERF_CTL: u32 = 0x199;
+const IA32_PM_ENABLE: u32 = 0x770;
+const IA32_HWP_CAPABILITIES: u32 = 0x771;
+const IA32_HWP_REQUEST: u32 = 0x774;
+
+/// AMD P-state control MSR
+const AMD_PSTATE_CONTROL: u32 = 0xC001_0062;
+
+/// CPUID feature bits
+const CPUID1_ECX_EIST: u32 = 1 << 7;
+const CPUID6_EAX_HWP: u32 = 1 << 7;
+const CPUID80000007_EDX_HW_PSTATE: u32 = 1 << 7;
+
+/// Bits of IA32_PERF_CTL that select the target ratio
+const PERF_CTL_TARGET_MASK: u64 = 0xFFFF;
+
+/// Energy/performance preference written with each HWP request; the
+/// midpoint, neither favoring
+const HWP_BALANCED_EPP: u64 = 0x80;
+
+const MHZ_PER_RATIO: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    /// Control values are HWP performance ratios
+    Hwp,
+    /// Control values are IA32_PERF_CTL targets
+    PerfCtl,
+    /// Control values are AMD P-state numbers
+    AmdPstate,
+}
+
+static BACKEND: Once<Option<Backend>> = Once::new();
+
+/// Control value of the level last set, and how many times a level has
+/// been set; each CPU remembers the count it last applied
+static TARGET: AtomicU64 = AtomicU64::new(0);
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static APPLIED: [AtomicU64; MAX_CPUS] = [const { AtomicU64::new(0) }; MAX_CPUS];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Vendor {
+    Intel,
+    Amd,
+    Other,
+}
+
+fn vendor() -> Vendor {
+    let leaf = unsafe { __cpuid(0) };
+    match (leaf.ebx, leaf.edx, leaf.ecx) {
+        // "GenuineIntel" and "AuthenticAMD" as EBX, EDX, ECX
+        (0x756E_6547, 0x4965_6E69, 0x6C65_746E) => Vendor::Intel,
+        (0x6874_7541, 0x6974_6E65, 0x444D_4163) => Vendor::Amd,
+        _ => Vendor::Other,
+    }
+}
+
+/// Whether CPUID leaf `leaf` exists
+fn has_leaf(leaf: u32) -> bool {
+    let highest = unsafe { __cpuid(leaf & 0x8000_0000) }.eax;
+    highest >= leaf
+}
+
+fn read_msr(msr: u32) -> u64 {
+    unsafe { Msr::new(msr).read() }
+}
+
+fn write_msr(msr: u32, value: u64) {
+    unsafe { Msr::new(msr).write(value) }
+}
+
+/// Levels for every ratio from `lowest` to `highest`, encoded by `control`
+pub fn ratio_levels(lowest: u8, highest: u8, control: impl Fn(u8) -> u64) -> Vec<PerformanceLevel> {
+    (lowest.max(1)..=highest).map(|ratio| PerformanceLevel {
+        frequency_mhz: ratio as u32 * MHZ_PER_RATIO,
+        control: control(ratio),
+    }).collect()
+}
+
+/// Levels of the ACPI `_PSS` states, slowest first
+fn acpi_levels() -> Vec<PerformanceLevel> {
+    super::acpi::performance_states().iter().rev().map(|state| PerformanceLevel {
+        frequency_mhz: state.frequency_mhz,
+        control: state.control,
+    }).collect()
+}
+
+/// Find the best backend the CPU and firmware offer
+pub fn probe() -> Option<FrequencyControl> {
+    let vendor = vendor();
+    let has_hwp = has_leaf(6) && unsafe { __cpuid(6) }.eax & CPUID6_EAX_HWP != 0;
+    let has_eist = unsafe { __cpuid(1) }.ecx & CPUID1_ECX_EIST != 0;
+    let has_hw_pstate = has_leaf(0x8000_0007)
+        && unsafe { __cpuid(0x8000_0007) }.edx & CPUID80000007_EDX_HW_PSTATE != 0;
+
+    let (backend, name, levels) = if vendor == Vendor::Intel && has_hwp {
+        // Enabling HWP is for the whole package and cannot be undone
+        write_msr(IA32_PM_ENABLE, 1);
+        let capabilities = read_msr(IA32_HWP_CAPABILITIES);
+        let (highest, lowest) = (capabilities as u8, (capabilities >> 24) as u8);
+        (Backend::Hwp, "intel-hwp", ratio_levels(lowest, highest, |ratio| ratio as u64))
+    } else if vendor == Vendor::Intel && has_eist {
+        let levels = acpi_levels();
+        if levels.is_empty() {
+            let platform_info = read_msr(MSR_PLATFORM_INFO);
+            let (highest, lowest) = ((platform_info >> 8) as u8, (platform_info >> 40) as u8);
+            (Backend::PerfCtl, "intel-speedstep", ratio_levels(lowest, highest, |ratio| (ratio as u64) << 8))
+        } else {
+            (Backend::PerfCtl, "acpi-pstates", levels)
+        }
+    } else if vendor == Vendor::Amd && has_hw_pstate {
+        (Backend::AmdPstate, "acpi-pstates", acpi_levels())
+    } else {
+        (Backend::PerfCtl, "", Vec::new())
+    };
+
+    if levels.is_empty() {
+        BACKEND.call_once(|| None);
+        return None;
+    }
+    BACKEND.call_once(|| Some(backend));
+    Some(FrequencyControl { backend: name, levels })
+}
+
+/// IA32_HWP_REQUEST value holding the calling CPU to `ratio`
+pub fn hwp_request(ratio: u8) -> u64 {
+    let ratio = ratio as u64;
+    ratio | ratio << 8 | ratio << 16 | HWP_BALANCED_EPP << 24
+}
+
+fn apply(backend: Backend, control: u64) {
+    match backend {
+        Backend::Hwp => write_msr(IA32_HWP_REQUEST, hwp_request(control as u8)),
+        Backend::PerfCtl => {
+            let current = read_msr(IA32_PERF_CTL);
+            write_msr(IA32_PERF_CTL, (current & !PERF_CTL_TARGET_MASK) | (control & PERF_CTL_TARGET_MASK));
+        }
+        Backend::AmdPstate => write_msr(AMD_PSTATE_CONTROL, control),
+    }
+}
+
+/// Switch every CPU to `level`, starting with the calling one
+pub fn set_level(level: &PerformanceLevel) -> PlatformResult<()> {
+    if BACKEND.get().copied().flatten().is_none() {
+        return Err(PlatformError::UnsupportedOperation);
+    }
+    TARGET.store(level.control, Ordering::Relaxed);
+    GENERATION.fetch_add(1, Ordering::Release);
+    sync_local();
+    Ok(())
+}
+
+/// Write the level last set on the calling CPU, if it has not yet
+pub fn sync_local() {
+    let Some(backend) = BACKEND.get().copied().flatten() else {
+        return;
+    };
+    let generation = GENERATION.load(Ordering::Acquire);
+    let Some(applied) = APPLIED.get(super::smp::current_cpu_index()) else {
+        return;
+    };
+    if applied.swap(generation, Ordering::Relaxed) != generation {
+        apply(backend, TARGET.load(Ordering::Relaxed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_ratio_levels_are_100_mhz_steps() {
+        let levels = ratio_levels(8, 10, |ratio| (ratio as u64) << 8);
+        assert_eq!(levels, [
+            PerformanceLevel { frequency_mhz: 800, control: 0x800 },
+            PerformanceLevel { frequency_mhz: 900, control: 0x900 },
+            PerformanceLevel { frequency_mhz: 1000, control: 0xA00 },
+        ]);
+        assert!(ratio_levels(12, 10, |ratio| ratio as u64).is_empty());
+    }
+
+    #[test_case]
+    fn test_hwp_request_pins_one_ratio() {
+        assert_eq!(hwp_request(0x18), 0x8018_1818);
+    }
+}
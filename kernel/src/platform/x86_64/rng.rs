@@ -0,0 +1,51 @@
+//! x86-64 hardware random numbers
+//!
+//! RDSEED returns output of the CPU's entropy source directly and is
+//! preferred for seeding; RDRAND returns output of a generator the CPU
+//! reseeds from that source, and is taken where RDSEED is missing. Both
+//! may fail when the source is drained, so each is retried a few times.
+
+use core::arch::x86_64::{_rdrand64_step, _rdseed64_step};
+
+/// Attempts before giving up on an instruction, as Intel recommends
+const RETRIES: usize = 10;
+
+fn has_rdseed() -> bool {
+    raw_cpuid::CpuId::new()
+        .get_extended_feature_info()
+        .map_or(false, |info| info.has_rdseed())
+}
+
+fn has_rdrand() -> bool {
+    raw_cpuid::CpuId::new()
+        .get_feature_info()
+        .map_or(false, |info| info.has_rdrand())
+}
+
+#[target_feature(enable = "rdseed")]
+unsafe fn rdseed() -> Option<u64> {
+    let mut value = 0;
+    (0..RETRIES).find(|_| _rdseed64_step(&mut value) == 1).map(|_| value)
+}
+
+#[target_feature(enable = "rdrand")]
+unsafe fn rdrand() -> Option<u64> {
+    let mut value = 0;
+    (0..RETRIES).find(|_| _rdrand64_step(&mut value) == 1).map(|_| value)
+}
+
+/// 64 random bits from the CPU, or None if it has no generator or the
+/// generator keeps failing
+pub fn random() -> Option<u64> {
+    // CPUID is cheap next to the instructions, but runs on every call:
+    // callers only ask at boot and on reseeds
+    if has_rdseed() {
+        if let Some(value) = unsafe { rdseed() } {
+            return Some(value);
+        }
+    }
+    if has_rdrand() {
+        return unsafe { rdrand() };
+    }
+    None
+}
@@ -0,0 +1,372 @@
+//! Secondary CPU startup on x86-64
+//!
+//! A secondary CPU is woken with an INIT and two startup IPIs. It starts
+//! in real mode at a trampoline copied to low memory, climbs to long mode
+//! on the boot CPU's page tables and control register settings, then loads
+//! its own GDT and TSS, the shared IDT, and enables its local APIC before
+//! entering generic code.
+//!
+//! Secondary CPUs are the processors the ACPI MADT enables, other than the
+//! boot CPU, in table order. Without a MADT they are assumed to have APIC
+//! IDs 1, 2, ... in order, as on QEMU and most single-socket machines.
+//!
+//! Each TSS is followed by an I/O permission bitmap, loaded with the ports
+//! of the process the CPU switches to. While a process without ports runs
+//! the bitmap offset points past the end of the TSS, which denies every
+//! port without copying anything.
+
+use alloc::boxed::Box;
+use core::arch::global_asm;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::segmentation::{Segment, CS, DS, ES, FS, GS, SS};
+use x86_64::registers::control::{Cr0, Cr3, Cr4};
+use x86_64::registers::model_specific::Efer;
+use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
+use x86_64::structures::tss::TaskStateSegment;
+use x86_64::VirtAddr;
+use super::super::{PlatformResult, PlatformError};
+use super::{acpi, apic, interrupts, timer};
+use crate::memory::kstack::{KernelStack, StackOwner};
+use crate::smp::MAX_CPUS;
+
+/// Physical address the trampoline is copied to; must be page aligned and
+/// below 1 MiB, and is assumed to be identity mapped
+const TRAMPOLINE_BASE: usize = 0x8000;
+
+/// Time a started CPU gets to reach generic code
+const STARTUP_TIMEOUT_US: u64 = 200_000;
+
+/// Size of each secondary CPU's double fault stack
+const DOUBLE_FAULT_STACK_SIZE: usize = 4096 * 5;
+
+/// IA32_EFER bit that is read-only and must not be written back
+const EFER_LONG_MODE_ACTIVE: u64 = 1 << 10;
+
+/// APIC ID of each CPU by index, `u32::MAX` where unknown
+static APIC_IDS: [AtomicU32; MAX_CPUS] = [const { AtomicU32::new(u32::MAX) }; MAX_CPUS];
+
+/// Descriptor tables of each CPU, built by the boot CPU
+static CPU_TABLES: Mutex<[Option<&'static CpuTables>; MAX_CPUS]> = Mutex::new([None; MAX_CPUS]);
+
+/// Bytes of an I/O permission bitmap, one bit per port
+const IO_BITMAP_BYTES: usize = 65536 / 8;
+
+/// Offset of the I/O permission bitmap from the start of the TSS
+const IO_BITMAP_OFFSET: u16 = core::mem::size_of::<TaskStateSegment>() as u16;
+
+/// Bitmap offset past the TSS limit, denying every port
+const IO_BITMAP_DISABLED: u16 = u16::MAX;
+
+/// A TSS with the I/O permission bitmap that follows it, and the byte of
+/// set bits the CPU expects after the bitmap
+#[repr(C)]
+struct TssWithIoBitmap {
+    tss: TaskStateSegment,
+    io_bitmap: [u8; IO_BITMAP_BYTES + 1],
+}
+
+/// TSS of each CPU, written only by that CPU once loaded
+static TSS: [AtomicPtr<TssWithIoBitmap>; MAX_CPUS] = [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_CPUS];
+
+/// Generation of the I/O permission bitmap in each CPU's TSS, 0 for none
+static LOADED_IO_BITMAP: [AtomicU64; MAX_CPUS] = [const { AtomicU64::new(0) }; MAX_CPUS];
+
+/// Per-CPU GDT and TSS; a TSS cannot be shared since loading it marks it
+/// busy. The layout matches the boot CPU's early tables.
+struct CpuTables {
+    gdt: GlobalDescriptorTable,
+    code_selector: SegmentSelector,
+    data_selector: SegmentSelector,
+    tss_selector: SegmentSelector,
+}
+
+impl CpuTables {
+    fn new(cpu: usize) -> PlatformResult<&'static Self> {
+        let double_fault_stack = KernelStack::allocate(DOUBLE_FAULT_STACK_SIZE, StackOwner::DoubleFault(cpu))
+            .map_err(|e| {
+                log::warn!("CPU {}: {}", cpu, e);
+                PlatformError::OutOfMemory
+            })?;
+        let mut tss = TaskStateSegment::new();
+        tss.interrupt_stack_table[crate::boot::DOUBLE_FAULT_IST_INDEX as usize] =
+            VirtAddr::new(double_fault_stack.leak());
+        tss.iomap_base = IO_BITMAP_OFFSET;
+
+        // Built in place, the bitmap is too large for the stack
+        let io: *mut TssWithIoBitmap = Box::into_raw(Box::<TssWithIoBitmap>::new_zeroed()).cast();
+        let descriptor = unsafe {
+            core::ptr::addr_of_mut!((*io).tss).write(tss);
+            (*io).io_bitmap.fill(0xFF);
+            Descriptor::tss_segment_with_iomap(&(*io).tss, &(*io).io_bitmap)
+        };
+        let descriptor = descriptor.map_err(|e| {
+            log::warn!("CPU {}: invalid I/O permission bitmap: {:?}", cpu, e);
+            PlatformError::HardwareError
+        })?;
+        // No port is open until a process with some runs
+        unsafe { core::ptr::addr_of_mut!((*io).tss.iomap_base).write_unaligned(IO_BITMAP_DISABLED) };
+        TSS[cpu].store(io, Ordering::Release);
+
+        let mut gdt = GlobalDescriptorTable::new();
+        let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+        let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
+        let tss_selector = gdt.add_entry(descriptor);
+        Ok(Box::leak(Box::new(Self { gdt, code_selector, data_selector, tss_selector })))
+    }
+
+    fn load(&'static self) {
+        self.gdt.load();
+        unsafe {
+            CS::set_reg(self.code_selector);
+            DS::set_reg(self.data_selector);
+            ES::set_reg(self.data_selector);
+            FS::set_reg(self.data_selector);
+            GS::set_reg(self.data_selector);
+            SS::set_reg(self.data_selector);
+            x86_64::instructions::tables::load_tss(self.tss_selector);
+        }
+    }
+}
+
+/// Startup data at the end of the trampoline, filled in per CPU
+#[repr(C)]
+struct TrampolineData {
+    cr3: u64,
+    cr4: u64,
+    cr0: u64,
+    efer: u64,
+    stack_top: u64,
+    cpu: u64,
+}
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_data: u8;
+    static ap_trampoline_end: u8;
+}
+
+// Runs at TRAMPOLINE_BASE, so every address is computed relative to it.
+// A temporary flat GDT carries the CPU through protected mode into long
+// mode; PAE is enough to turn on paging, the full CR0/CR4 of the boot CPU
+// are only loaded once in long mode, where all their bits are valid.
+global_asm!(
+    ".code16",
+    ".global ap_trampoline_start",
+    "ap_trampoline_start:",
+    "cli",
+    "cld",
+    "xorw %ax, %ax",
+    "movw %ax, %ds",
+    "movw %ax, %es",
+    "movw %ax, %ss",
+    "lgdtl {base} + (ap_trampoline_gdt_pointer - ap_trampoline_start)",
+    "movl %cr0, %eax",
+    "orl $1, %eax",
+    "movl %eax, %cr0",
+    "ljmpl $0x08, ${base} + (ap_trampoline_protected - ap_trampoline_start)",
+    "",
+    ".code32",
+    "ap_trampoline_protected:",
+    "movw $0x10, %ax",
+    "movw %ax, %ds",
+    "movw %ax, %es",
+    "movw %ax, %ss",
+    "movl %cr4, %eax",
+    "orl $0x20, %eax",
+    "movl %eax, %cr4",
+    "movl {base} + (ap_trampoline_data - ap_trampoline_start), %eax",
+    "movl %eax, %cr3",
+    "movl $0xC0000080, %ecx",
+    "movl {base} + (ap_trampoline_data - ap_trampoline_start) + 24, %eax",
+    "xorl %edx, %edx",
+    "wrmsr",
+    "movl %cr0, %eax",
+    "orl $0x80000000, %eax",
+    "movl %eax, %cr0",
+    "ljmpl $0x18, ${base} + (ap_trampoline_long - ap_trampoline_start)",
+    "",
+    ".code64",
+    "ap_trampoline_long:",
+    "xorw %ax, %ax",
+    "movw %ax, %ds",
+    "movw %ax, %es",
+    "movw %ax, %ss",
+    "movq {base} + (ap_trampoline_data - ap_trampoline_start) + 8, %rax",
+    "movq %rax, %cr4",
+    "movq {base} + (ap_trampoline_data - ap_trampoline_start) + 16, %rax",
+    "movq %rax, %cr0",
+    "movq {base} + (ap_trampoline_data - ap_trampoline_start) + 32, %rsp",
+    "movq {base} + (ap_trampoline_data - ap_trampoline_start) + 40, %rdi",
+    "movabsq ${entry}, %rax",
+    "callq *%rax",
+    "1:",
+    "hlt",
+    "jmp 1b",
+    "",
+    ".balign 8",
+    "ap_trampoline_gdt:",
+    ".quad 0",
+    ".quad 0x00CF9A000000FFFF",
+    ".quad 0x00CF92000000FFFF",
+    ".quad 0x00AF9A000000FFFF",
+    "ap_trampoline_gdt_pointer:",
+    ".word ap_trampoline_gdt_pointer - ap_trampoline_gdt - 1",
+    ".long {base} + (ap_trampoline_gdt - ap_trampoline_start)",
+    ".balign 8",
+    ".global ap_trampoline_data",
+    "ap_trampoline_data:",
+    ".fill 6, 8, 0",
+    ".global ap_trampoline_end",
+    "ap_trampoline_end:",
+    base = const TRAMPOLINE_BASE,
+    entry = sym secondary_entry,
+    options(att_syntax),
+);
+
+/// Index of the calling CPU, looked up by its APIC ID
+pub fn current_cpu_index() -> usize {
+    let id = apic::id();
+    APIC_IDS.iter()
+        .position(|apic_id| apic_id.load(Ordering::Relaxed) == id)
+        .unwrap_or(0)
+}
+
+/// Move the boot CPU onto descriptor tables whose double fault stack has a
+/// guard page
+///
+/// The tables loaded at boot are built before memory management is up, so
+/// their double fault stack is a plain static array.
+pub fn load_boot_cpu_tables() -> PlatformResult<()> {
+    let tables = CpuTables::new(0)?;
+    x86_64::instructions::interrupts::without_interrupts(|| tables.load());
+    CPU_TABLES.lock()[0] = Some(tables);
+    Ok(())
+}
+
+/// Let the process the calling CPU switches to use the ports `bitmap`
+/// leaves clear, or deny every port with `None`
+///
+/// A bitmap whose generation is already loaded is not copied again.
+pub fn set_io_bitmap(bitmap: Option<(u64, &[u8])>) {
+    let cpu = crate::smp::current_cpu();
+    let io = TSS[cpu].load(Ordering::Acquire);
+    if io.is_null() {
+        // Still on the boot tables, before any process runs
+        return;
+    }
+    unsafe {
+        let base = match bitmap {
+            Some((generation, bits)) if bits.len() == IO_BITMAP_BYTES => {
+                if LOADED_IO_BITMAP[cpu].swap(generation, Ordering::Relaxed) != generation {
+                    (*io).io_bitmap[..IO_BITMAP_BYTES].copy_from_slice(bits);
+                }
+                IO_BITMAP_OFFSET
+            }
+            _ => IO_BITMAP_DISABLED,
+        };
+        core::ptr::addr_of_mut!((*io).tss.iomap_base).write_unaligned(base);
+    }
+}
+
+/// Enable the boot CPU's local APIC, calibrate the APIC timer against the
+/// PIT and install the trampoline
+pub fn prepare() -> PlatformResult<()> {
+    apic::init_local()?;
+    APIC_IDS[0].store(apic::id(), Ordering::Relaxed);
+    apic::calibrate_timer(timer::tick_frequency_hz())?;
+
+    unsafe {
+        let start = &ap_trampoline_start as *const u8;
+        let length = &ap_trampoline_end as *const u8 as usize - start as usize;
+        core::ptr::copy_nonoverlapping(start, TRAMPOLINE_BASE as *mut u8, length);
+    }
+    Ok(())
+}
+
+/// APIC ID of secondary CPU `cpu`
+fn secondary_apic_id(cpu: usize) -> PlatformResult<u32> {
+    match acpi::madt() {
+        Some(madt) => madt.secondary_apic_ids(APIC_IDS[0].load(Ordering::Relaxed))
+            .nth(cpu - 1)
+            .ok_or(PlatformError::HardwareError),
+        None => Ok(cpu as u32),
+    }
+}
+
+/// Start CPU `cpu` through the trampoline and wait until it is online
+pub fn start_cpu(cpu: usize, stack_top: usize, online: &AtomicBool) -> PlatformResult<()> {
+    let apic_id = secondary_apic_id(cpu)?;
+    APIC_IDS[cpu].store(apic_id, Ordering::Relaxed);
+    // A CPU started again after going offline keeps its tables
+    {
+        let mut tables = CPU_TABLES.lock();
+        if tables[cpu].is_none() {
+            tables[cpu] = Some(CpuTables::new(cpu)?);
+        }
+    }
+
+    let (page_table, cr3_flags) = Cr3::read();
+    let data = TrampolineData {
+        cr3: page_table.start_address().as_u64() | cr3_flags.bits(),
+        cr4: Cr4::read_raw(),
+        cr0: Cr0::read_raw(),
+        efer: Efer::read_raw() & !EFER_LONG_MODE_ACTIVE,
+        stack_top: stack_top as u64,
+        cpu: cpu as u64,
+    };
+    // The trampoline loads CR3 in 32-bit mode
+    if data.cr3 > u32::MAX as u64 {
+        return Err(PlatformError::UnsupportedOperation);
+    }
+    unsafe {
+        let offset = &ap_trampoline_data as *const u8 as usize - &ap_trampoline_start as *const u8 as usize;
+        core::ptr::write_volatile((TRAMPOLINE_BASE + offset) as *mut TrampolineData, data);
+    }
+
+    apic::send_init(apic_id);
+    timer::busy_wait_us(10_000);
+    for _ in 0..2 {
+        apic::send_startup(apic_id, (TRAMPOLINE_BASE >> 12) as u8);
+        timer::busy_wait_us(200);
+    }
+
+    let mut waited_us = 0;
+    while !online.load(Ordering::Acquire) {
+        if waited_us >= STARTUP_TIMEOUT_US {
+            return Err(PlatformError::HardwareError);
+        }
+        timer::busy_wait_us(1000);
+        waited_us += 1000;
+    }
+    Ok(())
+}
+
+/// First Rust code on a secondary CPU, on its own stack in long mode
+extern "C" fn secondary_entry(cpu: usize) -> ! {
+    let tables = CPU_TABLES.lock()[cpu].expect("secondary CPU started without descriptor tables");
+    tables.load();
+    interrupts::load_idt();
+    if apic::init_local().is_err() {
+        log::debug!("CPU {}: local APIC unusable", cpu);
+    }
+    crate::smp::secondary_main(cpu)
+}
+
+/// Halt the calling CPU with interrupts off; only an INIT IPI, as
+/// `start_cpu` sends, gets it going again
+pub fn stop_cpu(parked: &AtomicBool) -> ! {
+    x86_64::instructions::interrupts::disable();
+    parked.store(true, Ordering::Release);
+    loop {
+        // An NMI still wakes the CPU
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Start the calling secondary CPU's APIC timer and enable interrupts
+pub fn start_timer() -> PlatformResult<()> {
+    apic::start_periodic_timer(interrupts::APIC_TIMER_VECTOR)?;
+    x86_64::instructions::interrupts::enable();
+    Ok(())
+}
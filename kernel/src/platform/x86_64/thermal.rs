@@ -0,0 +1,198 @@
+//! x86-64 temperature sensors
+//!
+//! Two kinds are offered:
+//!
+//! - ACPI thermal zones, the `ThermalZone` objects of the DSDT and SSDTs.
+//!   Like the batteries they are read only where their objects are
+//!   constants: `_TMP` for the temperature, and `_PSV`, `_HOT` and `_CRT`
+//!   for the trip points, all in tenths of a kelvin. Zones whose `_TMP`
+//!   reads the embedded controller are left out.
+//! - The digital thermal sensor of Intel CPUs, which tells how far below
+//!   TjMax the core runs. It is read on the CPU that asks. Its trip points
+//!   sit below TjMax, where the CPU would start throttling itself; it powers
+//!   itself off past TjMax, so there is no critical one.
+//!
+//! AMD CPUs report their temperature through the northbridge, which is not
+//! read.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::arch::x86_64::__cpuid;
+use spin::Once;
+use x86_64::registers::model_specific::Msr;
+use super::super::{ThermalSensor, TripKind, TripPoint};
+use super::acpi::{self, AmlValue};
+
+/// Intel thermal MSRs
+const IA32_THERM_STATUS: u32 = 0x19C;
+const MSR_TEMPERATURE_TARGET: u32 = 0x1A2;
+
+/// CPUID leaf 6 bit: digital temperature sensor
+const CPUID6_EAX_DTS: u32 = 1 << 0;
+
+/// IA32_THERM_STATUS: readout valid, and the readout in bits 16-22
+const THERM_STATUS_VALID: u64 = 1 << 31;
+const THERM_STATUS_READOUT_SHIFT: u64 = 16;
+const THERM_STATUS_READOUT_MASK: u64 = 0x7F;
+
+/// TjMax taken where MSR_TEMPERATURE_TARGET does not give one
+const DEFAULT_TJMAX_CELSIUS: i32 = 100;
+
+/// How far below TjMax the CPU sensor's trip points sit
+const CPU_PASSIVE_BELOW_TJMAX_MC: i32 = 10_000;
+const CPU_HOT_BELOW_TJMAX_MC: i32 = 5_000;
+
+/// Trip point objects of a thermal zone
+const ZONE_TRIPS: [(&[u8; 4], TripKind); 3] = [
+    (b"_PSV", TripKind::Passive),
+    (b"_HOT", TripKind::Hot),
+    (b"_CRT", TripKind::Critical),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    /// Body of an ACPI thermal zone
+    AcpiZone(&'static [u8]),
+    /// Digital thermal sensor of the calling CPU
+    CpuSensor { tjmax_mc: i32 },
+}
+
+static SENSORS: Once<Vec<(Source, ThermalSensor)>> = Once::new();
+
+/// Thousandths of a degree Celsius from tenths of a kelvin
+pub fn deci_kelvin_to_millicelsius(deci_kelvin: u64) -> i32 {
+    (deci_kelvin.min(i32::MAX as u64 / 100) as i32 - 2732) * 100
+}
+
+/// Temperature an IA32_THERM_STATUS value gives, None if it is not valid
+pub fn dts_millicelsius(status: u64, tjmax_mc: i32) -> Option<i32> {
+    if status & THERM_STATUS_VALID == 0 {
+        return None;
+    }
+    let below_tjmax = ((status >> THERM_STATUS_READOUT_SHIFT) & THERM_STATUS_READOUT_MASK) as i32;
+    Some(tjmax_mc - below_tjmax * 1000)
+}
+
+/// Trip points of the CPU sensor
+pub fn cpu_trips(tjmax_mc: i32) -> Vec<TripPoint> {
+    alloc::vec![
+        TripPoint { kind: TripKind::Passive, millicelsius: tjmax_mc - CPU_PASSIVE_BELOW_TJMAX_MC },
+        TripPoint { kind: TripKind::Hot, millicelsius: tjmax_mc - CPU_HOT_BELOW_TJMAX_MC },
+    ]
+}
+
+fn zone_integer(body: &[u8], name: &[u8; 4]) -> Option<u64> {
+    match acpi::device_object(body, name) {
+        Some(AmlValue::Integer(value)) => Some(value),
+        _ => None,
+    }
+}
+
+/// Temperature of a thermal zone body
+fn zone_temperature(body: &[u8]) -> Option<i32> {
+    zone_integer(body, b"_TMP").map(deci_kelvin_to_millicelsius)
+}
+
+/// The sensor a thermal zone body describes, None if its temperature
+/// cannot be read
+pub fn zone_sensor(name: &[u8; 4], body: &[u8]) -> Option<ThermalSensor> {
+    zone_temperature(body)?;
+    let trips = ZONE_TRIPS.iter()
+        .filter_map(|&(object, kind)| zone_integer(body, object).map(|value| TripPoint {
+            kind,
+            millicelsius: deci_kelvin_to_millicelsius(value),
+        }))
+        .collect();
+    let name = String::from_utf8_lossy(name).trim_end_matches('_').into();
+    Some(ThermalSensor { name, trips })
+}
+
+fn is_intel() -> bool {
+    let leaf = unsafe { __cpuid(0) };
+    // "GenuineIntel" as EBX, EDX, ECX
+    (leaf.ebx, leaf.edx, leaf.ecx) == (0x756E_6547, 0x4965_6E69, 0x6C65_746E)
+}
+
+fn has_dts() -> bool {
+    unsafe { __cpuid(0) }.eax >= 6 && unsafe { __cpuid(6) }.eax & CPUID6_EAX_DTS != 0
+}
+
+/// TjMax from bits 16-23 of MSR_TEMPERATURE_TARGET, in thousandths of a
+/// degree
+fn tjmax_mc() -> i32 {
+    let celsius = match unsafe { Msr::new(MSR_TEMPERATURE_TARGET).read() } >> 16 & 0xFF {
+        0 => DEFAULT_TJMAX_CELSIUS,
+        celsius => celsius as i32,
+    };
+    celsius * 1000
+}
+
+/// The ACPI thermal zones that can be read, then the CPU sensor
+fn sensors() -> &'static [(Source, ThermalSensor)] {
+    SENSORS.call_once(|| {
+        let mut sensors = Vec::new();
+        if let Some(info) = acpi::info() {
+            for aml in info.definition_blocks() {
+                for (name, body) in acpi::thermal_zones(aml) {
+                    if let Some(sensor) = zone_sensor(&name, body) {
+                        sensors.push((Source::AcpiZone(body), sensor));
+                    }
+                }
+            }
+        }
+        if is_intel() && has_dts() {
+            let tjmax_mc = tjmax_mc();
+            sensors.push((Source::CpuSensor { tjmax_mc }, ThermalSensor { name: "cpu".into(), trips: cpu_trips(tjmax_mc) }));
+        }
+        sensors
+    })
+}
+
+/// Find the sensors, ACPI thermal zones first
+pub fn probe() -> Vec<ThermalSensor> {
+    sensors().iter().map(|(_, sensor)| sensor.clone()).collect()
+}
+
+/// Temperature of sensor `index`, in thousandths of a degree Celsius
+pub fn read_temperature(index: usize) -> Option<i32> {
+    match sensors().get(index)?.0 {
+        Source::AcpiZone(body) => zone_temperature(body),
+        Source::CpuSensor { tjmax_mc } => {
+            dts_millicelsius(unsafe { Msr::new(IA32_THERM_STATUS).read() }, tjmax_mc)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_temperatures_convert_to_millicelsius() {
+        assert_eq!(deci_kelvin_to_millicelsius(3232), 50_000);
+        assert_eq!(deci_kelvin_to_millicelsius(2732), 0);
+        assert_eq!(dts_millicelsius(THERM_STATUS_VALID | 35 << 16, 100_000), Some(65_000));
+        assert_eq!(dts_millicelsius(35 << 16, 100_000), None);
+        assert_eq!(cpu_trips(100_000)[0], TripPoint { kind: TripKind::Passive, millicelsius: 90_000 });
+    }
+
+    #[test_case]
+    fn test_zone_sensor_reads_constant_objects() {
+        // Name (_TMP, 0x0C9E) Name (_PSV, 0x0D8A) Method (_CRT) { Return (0x0EBA) }
+        let body = [
+            0x08, b'_', b'T', b'M', b'P', 0x0B, 0x9E, 0x0C,
+            0x08, b'_', b'P', b'S', b'V', 0x0B, 0x8A, 0x0D,
+            0x14, 0x0A, b'_', b'C', b'R', b'T', 0x00, 0xA4, 0x0B, 0xBA, 0x0E,
+        ];
+        let sensor = zone_sensor(b"TZ0_", &body).unwrap();
+        assert_eq!(sensor.name, "TZ0");
+        assert_eq!(sensor.trips, [
+            TripPoint { kind: TripKind::Passive, millicelsius: 73_400 },
+            TripPoint { kind: TripKind::Critical, millicelsius: 103_800 },
+        ]);
+        assert_eq!(zone_temperature(&body), Some(49_800));
+
+        // _TMP reads the embedded controller
+        assert_eq!(zone_sensor(b"TZ1_", &body[8..]), None);
+    }
+}
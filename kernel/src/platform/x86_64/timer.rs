@@ -1,43 +1,129 @@
 //! x86-64 timer operations implementation
+//!
+//! The periodic tick comes from channel 0 of the legacy PIT, wired to
+//! ISA IRQ 0: the master PIC's first line, or the I/O APIC input the MADT
+//! overrides it to, usually GSI 2.
 
 use super::super::traits::TimerOperations;
 use super::super::{PlatformResult, PlatformError};
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use x86_64::instructions::port::Port;
 
-/// x86-64 timer operations implementation
-pub struct X86_64TimerOperations {
-    system_time: AtomicU64,
+/// Input clock of the PIT
+const PIT_FREQUENCY_HZ: u32 = 1_193_182;
+
+const PIT_CHANNEL0_PORT: u16 = 0x40;
+const PIT_CHANNEL2_PORT: u16 = 0x42;
+const PIT_COMMAND_PORT: u16 = 0x43;
+/// Gate and output of channel 2, shared with the PC speaker
+const PIT_CHANNEL2_CONTROL_PORT: u16 = 0x61;
+const PIT_CHANNEL2_GATE: u8 = 1 << 0;
+const PIT_SPEAKER_ENABLE: u8 = 1 << 1;
+const PIT_CHANNEL2_OUTPUT: u8 = 1 << 5;
+
+/// Channel 0, low then high byte, mode 2 (rate generator)
+const PIT_MODE_RATE_GENERATOR: u8 = 0x34;
+/// Channel 0, low then high byte, mode 0 (interrupt on terminal count)
+const PIT_MODE_ONESHOT: u8 = 0x30;
+/// Channel 2, low then high byte, mode 0 (interrupt on terminal count)
+const PIT_CHANNEL2_ONESHOT: u8 = 0xB0;
+
+/// Timer interrupts taken since the periodic timer was started
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Frequency of the periodic timer, 0 while stopped
+static TICK_FREQUENCY_HZ: AtomicU32 = AtomicU32::new(0);
+
+/// Count one timer interrupt
+pub fn record_tick() {
+    TICKS.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Busy-wait for `microseconds` on PIT channel 2
+///
+/// Channel 2 is independent of the periodic tick on channel 0 and is
+/// polled, so this works with interrupts disabled. Only one CPU may use it
+/// at a time.
+pub fn busy_wait_us(microseconds: u64) {
+    let mut remaining = microseconds * PIT_FREQUENCY_HZ as u64 / 1_000_000;
+    while remaining > 0 {
+        let count = remaining.min(u16::MAX as u64);
+        remaining -= count;
+        unsafe {
+            let mut control = Port::<u8>::new(PIT_CHANNEL2_CONTROL_PORT);
+            // Gate and speaker off; raising the gate below starts the count
+            let gate = control.read() & !(PIT_CHANNEL2_GATE | PIT_SPEAKER_ENABLE);
+            control.write(gate);
+            Port::<u8>::new(PIT_COMMAND_PORT).write(PIT_CHANNEL2_ONESHOT);
+            let mut data = Port::<u8>::new(PIT_CHANNEL2_PORT);
+            data.write((count & 0xFF) as u8);
+            data.write((count >> 8) as u8);
+            control.write(gate | PIT_CHANNEL2_GATE);
+            while control.read() & PIT_CHANNEL2_OUTPUT == 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
 }
 
+/// Frequency of the periodic timer, 0 while stopped
+pub fn tick_frequency_hz() -> u32 {
+    TICK_FREQUENCY_HZ.load(Ordering::SeqCst)
+}
+
+/// x86-64 timer operations implementation
+pub struct X86_64TimerOperations;
+
 impl X86_64TimerOperations {
     pub fn new() -> Self {
-        Self {
-            system_time: AtomicU64::new(0),
+        Self
+    }
+    
+    fn program_pit(mode: u8, count: u16) {
+        unsafe {
+            Port::<u8>::new(PIT_COMMAND_PORT).write(mode);
+            let mut data = Port::<u8>::new(PIT_CHANNEL0_PORT);
+            data.write((count & 0xFF) as u8);
+            data.write((count >> 8) as u8);
         }
     }
 }
 
 impl TimerOperations for X86_64TimerOperations {
     fn get_system_time(&self) -> u64 {
-        // This would read from TSC or HPET
-        // For now, return a simple counter
-        self.system_time.load(Ordering::SeqCst)
+        let frequency = TICK_FREQUENCY_HZ.load(Ordering::SeqCst) as u64;
+        if frequency == 0 {
+            return 0;
+        }
+        TICKS.load(Ordering::SeqCst) * (1_000_000_000 / frequency)
     }
     
     fn setup_periodic_timer(&mut self, frequency_hz: u32) -> PlatformResult<()> {
-        // This would program the PIT, LAPIC timer, or HPET
-        // For now, just return success
+        // The 16-bit divisor limits the range to about 19 Hz .. 1.19 MHz
+        if frequency_hz == 0 || frequency_hz > PIT_FREQUENCY_HZ {
+            return Err(PlatformError::UnsupportedOperation);
+        }
+        let divisor = (PIT_FREQUENCY_HZ / frequency_hz).clamp(2, u16::MAX as u32);
+        
+        Self::program_pit(PIT_MODE_RATE_GENERATOR, divisor as u16);
+        TICK_FREQUENCY_HZ.store(PIT_FREQUENCY_HZ / divisor, Ordering::SeqCst);
         Ok(())
     }
     
     fn setup_oneshot_timer(&mut self, nanoseconds: u64) -> PlatformResult<()> {
-        // This would program a one-shot timer
-        // For now, just return success
+        let count = (nanoseconds as u128 * PIT_FREQUENCY_HZ as u128 / 1_000_000_000)
+            .clamp(1, u16::MAX as u128);
+        Self::program_pit(PIT_MODE_ONESHOT, count as u16);
+        TICK_FREQUENCY_HZ.store(0, Ordering::SeqCst);
         Ok(())
     }
     
     fn stop_timer(&mut self) -> PlatformResult<()> {
-        // This would stop the timer
+        // Mode 0 without a count loaded never fires
+        unsafe {
+            Port::<u8>::new(PIT_COMMAND_PORT).write(PIT_MODE_ONESHOT);
+        }
+        TICK_FREQUENCY_HZ.store(0, Ordering::SeqCst);
         Ok(())
     }
-}
\ No newline at end of file
+}
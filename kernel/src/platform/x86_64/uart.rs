@@ -0,0 +1,80 @@
+//! x86-64 console UART
+//!
+//! A 16550-compatible UART on the legacy COM ports, programmed for 38400
+//! baud, 8N1, with its FIFOs on.
+
+use core::arch::asm;
+use super::super::traits::ConsoleUart;
+
+/// Base I/O port of COM1
+pub const COM1: u16 = 0x3F8;
+
+/// Register offsets from the base port
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+
+/// Line control: 8 data bits, no parity, one stop bit
+const LINE_8N1: u8 = 0x03;
+/// Line control bit that maps the divisor latch over DATA and INTERRUPT_ENABLE
+const LINE_DIVISOR_LATCH: u8 = 0x80;
+/// 115200 / 3
+const DIVISOR_38400: u16 = 3;
+/// Enable and clear the FIFOs, interrupt at 14 bytes
+const FIFO_ENABLE_CLEAR: u8 = 0xC7;
+/// DTR, RTS and OUT2, which gates the interrupt line
+const MODEM_READY: u8 = 0x0B;
+/// Interrupt enable: data received
+const INTERRUPT_RECEIVED: u8 = 0x01;
+
+/// Line status bits
+const STATUS_DATA_READY: u8 = 0x01;
+const STATUS_TRANSMIT_EMPTY: u8 = 0x20;
+
+/// A 16550 at an I/O port base
+pub struct Uart16550 {
+    base: u16,
+}
+
+impl Uart16550 {
+    pub const fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    fn read(&self, register: u16) -> u8 {
+        let value: u8;
+        unsafe { asm!("in al, dx", out("al") value, in("dx") self.base + register) };
+        value
+    }
+
+    fn write(&self, register: u16, value: u8) {
+        unsafe { asm!("out dx, al", in("dx") self.base + register, in("al") value) };
+    }
+}
+
+impl ConsoleUart for Uart16550 {
+    fn init(&mut self) {
+        self.write(INTERRUPT_ENABLE, 0);
+        self.write(LINE_CONTROL, LINE_DIVISOR_LATCH);
+        self.write(DATA, DIVISOR_38400 as u8);
+        self.write(INTERRUPT_ENABLE, (DIVISOR_38400 >> 8) as u8);
+        self.write(LINE_CONTROL, LINE_8N1);
+        self.write(FIFO_CONTROL, FIFO_ENABLE_CLEAR);
+        self.write(MODEM_CONTROL, MODEM_READY);
+        self.write(INTERRUPT_ENABLE, INTERRUPT_RECEIVED);
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        while self.read(LINE_STATUS) & STATUS_TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        self.write(DATA, byte);
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        (self.read(LINE_STATUS) & STATUS_DATA_READY != 0).then(|| self.read(DATA))
+    }
+}
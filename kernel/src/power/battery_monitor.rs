@@ -1,11 +1,45 @@
 //! Battery Level Monitoring
 //! 
-//! Provides battery status monitoring and power level management
+//! Provides battery status monitoring and power level management. The
+//! batteries and AC adapter are read through the platform (the ACPI
+//! control method batteries on x86-64); without one that can be read the
+//! battery is absent, and is looked for again on every update.
+//!
+//! Callbacks hear of the low and critical levels once, when they are
+//! crossed; userspace reads the state with the `battery_info` system call,
+//! and the driver manager puts devices in low power from it.
 
 use super::{BatteryInfo, PowerError, PowerState};
+use crate::platform::BatteryStatus;
 use alloc::vec::Vec;
 use spin::Mutex;
 
+/// `PowerSupplyInfo` flags
+pub const SUPPLY_BATTERY_PRESENT: u32 = 1 << 0;
+pub const SUPPLY_CHARGING: u32 = 1 << 1;
+pub const SUPPLY_LOW: u32 = 1 << 2;
+pub const SUPPLY_CRITICAL: u32 = 1 << 3;
+pub const SUPPLY_AC_ONLINE: u32 = 1 << 4;
+
+/// Battery and AC adapter state, as reported by the `battery_info` system
+/// call
+///
+/// The layout is shared with `kosh_posix::power`. Values that are not
+/// known are 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+pub struct PowerSupplyInfo {
+    /// `SUPPLY_*` flags; low and critical only while running on battery
+    pub flags: u32,
+    pub level_percent: u32,
+    /// Charge or discharge rate in mW
+    pub rate_mw: u32,
+    pub remaining_mwh: u32,
+    pub full_mwh: u32,
+    /// Until empty, or until full while charging
+    pub minutes_remaining: u32,
+}
+
 /// Battery status change events
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BatteryEvent {
@@ -57,6 +91,9 @@ pub struct BatteryMonitor {
     last_update_time: u64,
     event_callbacks: Vec<BatteryEventCallback>,
     battery_present: bool,
+    /// What the platform last reported
+    status: BatteryStatus,
+    ac_online: Option<bool>,
     charging_history: [bool; 10], // Last 10 charging state samples
     level_history: [u8; 20],      // Last 20 level samples
     history_index: usize,
@@ -74,7 +111,9 @@ impl BatteryMonitor {
             config: BatteryConfig::default(),
             last_update_time: 0,
             event_callbacks: Vec::new(),
-            battery_present: true,
+            battery_present: false,
+            status: BatteryStatus::default(),
+            ac_online: None,
             charging_history: [false; 10],
             level_history: [100; 20],
             history_index: 0,
@@ -83,35 +122,24 @@ impl BatteryMonitor {
 
     /// Initialize battery monitoring
     pub fn init(&mut self) -> Result<(), PowerError> {
-        // In a real implementation, this would:
-        // 1. Detect battery presence via ACPI
-        // 2. Read initial battery status
-        // 3. Set up battery status change interrupts
-        // 4. Configure power management policies
-        
-        self.battery_present = self.detect_battery_presence();
-        if self.battery_present {
-            self.update_battery_info()?;
-        }
-        
-        Ok(())
+        self.update_battery_info()
     }
 
     /// Update battery information
     pub fn update(&mut self, current_time: u64) -> Result<(), PowerError> {
-        if !self.battery_present {
-            return Ok(());
-        }
-
         if current_time.saturating_sub(self.last_update_time) >= self.config.monitor_interval_ms {
             let old_info = self.current_info;
+            let was_present = self.battery_present;
+            let (was_low, was_critical) = (self.is_low(), self.is_critical());
             self.update_battery_info()?;
             
-            // Check for significant changes and trigger events
-            self.check_for_events(old_info);
-            
-            // Update history
-            self.update_history();
+            if was_present && self.battery_present {
+                // Check for significant changes and trigger events
+                self.check_for_events(old_info, was_low, was_critical);
+                
+                // Update history
+                self.update_history();
+            }
             
             self.last_update_time = current_time;
         }
@@ -178,71 +206,73 @@ impl BatteryMonitor {
 
     /// Check if battery is in critical state
     pub fn is_critical(&self) -> bool {
-        self.battery_present && 
-        !self.current_info.is_charging && 
-        self.current_info.level_percent <= self.config.critical_level
+        self.on_battery() && 
+        (self.status.critical || self.current_info.level_percent <= self.config.critical_level)
     }
 
     /// Check if battery is in low state
     pub fn is_low(&self) -> bool {
-        self.battery_present && 
-        !self.current_info.is_charging && 
+        self.on_battery() && 
         self.current_info.level_percent <= self.config.low_level
     }
 
+    /// State as `SYS_BATTERY_INFO` reports it
+    pub fn supply_info(&self) -> PowerSupplyInfo {
+        let mut flags = 0;
+        if self.ac_online == Some(true) {
+            flags |= SUPPLY_AC_ONLINE;
+        }
+        if !self.battery_present {
+            return PowerSupplyInfo { flags, ..PowerSupplyInfo::default() };
+        }
+        flags |= SUPPLY_BATTERY_PRESENT;
+        if self.current_info.is_charging {
+            flags |= SUPPLY_CHARGING;
+        }
+        if self.is_low() {
+            flags |= SUPPLY_LOW;
+        }
+        if self.is_critical() {
+            flags |= SUPPLY_CRITICAL;
+        }
+        PowerSupplyInfo {
+            flags,
+            level_percent: self.current_info.level_percent as u32,
+            rate_mw: self.status.rate_mw,
+            remaining_mwh: self.status.remaining_mwh,
+            full_mwh: self.status.full_mwh,
+            minutes_remaining: self.current_info.estimated_time_remaining.unwrap_or(0),
+        }
+    }
+
     // Private methods
 
-    fn detect_battery_presence(&self) -> bool {
-        // In a real implementation, this would check ACPI battery presence
-        // For now, assume battery is present
-        true
+    /// Whether the machine draws on the battery: it is not charging, and
+    /// the AC adapter does not say it is online
+    fn on_battery(&self) -> bool {
+        self.battery_present && !self.current_info.is_charging && self.ac_online != Some(true)
     }
 
     fn update_battery_info(&mut self) -> Result<(), PowerError> {
-        // In a real implementation, this would:
-        // 1. Read battery status from ACPI
-        // 2. Query charging state from power management controller
-        // 3. Calculate remaining time based on current draw
+        let status = crate::platform::read_battery().filter(|status| status.present);
+        self.ac_online = crate::platform::read_ac_online();
+        if status.is_some() != self.battery_present {
+            self.battery_present = status.is_some();
+            self.trigger_event(BatteryEvent::BatteryPresenceChanged(self.battery_present));
+        }
         
-        // For simulation, create realistic battery behavior
-        self.simulate_battery_behavior();
+        self.status = status.unwrap_or_default();
+        if let Some(status) = status {
+            self.current_info.level_percent = status.level_percent;
+            self.current_info.is_charging = status.charging;
+            self.current_info.estimated_time_remaining = time_remaining(&status)
+                .or_else(|| self.estimate_time_remaining());
+        }
         
         Ok(())
     }
 
-    fn simulate_battery_behavior(&mut self) {
-        // Simple simulation for demonstration
-        // In reality, this would read from hardware
-        
-        static mut SIMULATION_STATE: (u8, bool, u64) = (100, false, 0);
-        
-        unsafe {
-            let (ref mut level, ref mut charging, ref mut last_change) = &mut SIMULATION_STATE;
-            
-            // Simulate battery discharge/charge
-            if *charging {
-                if *level < 100 {
-                    *level += 1;
-                }
-                if *level >= 100 {
-                    *charging = false;
-                }
-            } else {
-                if *level > 0 {
-                    *level = level.saturating_sub(1);
-                }
-                if *level <= 20 {
-                    *charging = true; // Simulate plugging in charger
-                }
-            }
-            
-            self.current_info.level_percent = *level;
-            self.current_info.is_charging = *charging;
-            self.current_info.estimated_time_remaining = self.estimate_time_remaining();
-        }
-    }
-
-    fn check_for_events(&mut self, old_info: BatteryInfo) {
+    fn check_for_events(&mut self, old_info: BatteryInfo, was_low: bool, was_critical: bool) {
         // Check for level changes
         if (old_info.level_percent as i16 - self.current_info.level_percent as i16).abs() >= 5 {
             self.trigger_event(BatteryEvent::LevelChanged(self.current_info.level_percent));
@@ -253,10 +283,10 @@ impl BatteryMonitor {
             self.trigger_event(BatteryEvent::ChargingStateChanged(self.current_info.is_charging));
         }
 
-        // Check for critical/low levels
-        if !old_info.is_charging && self.current_info.level_percent <= self.config.critical_level {
+        // Check for critical/low levels, once as they are crossed
+        if self.is_critical() && !was_critical {
             self.trigger_event(BatteryEvent::CriticalLevel);
-        } else if !old_info.is_charging && self.current_info.level_percent <= self.config.low_level {
+        } else if self.is_low() && !was_low {
             self.trigger_event(BatteryEvent::LowLevel);
         }
 
@@ -314,6 +344,19 @@ impl BatteryMonitor {
     }
 }
 
+/// Minutes until the battery is empty, or full while charging, at the rate
+/// it reports; None without a rate
+pub fn time_remaining(status: &BatteryStatus) -> Option<u32> {
+    let energy = if status.charging {
+        status.full_mwh.saturating_sub(status.remaining_mwh)
+    } else if status.discharging {
+        status.remaining_mwh
+    } else {
+        return None;
+    };
+    (energy as u64 * 60).checked_div(status.rate_mw as u64).map(|minutes| minutes as u32)
+}
+
 /// Global battery monitor instance
 static BATTERY_MONITOR: Mutex<Option<BatteryMonitor>> = Mutex::new(None);
 
@@ -343,6 +386,11 @@ pub fn get_battery_info() -> Result<BatteryInfo, PowerError> {
     }
 }
 
+/// Battery and AC adapter state; nothing present before `init`
+pub fn supply_info() -> PowerSupplyInfo {
+    BATTERY_MONITOR.lock().as_ref().map(BatteryMonitor::supply_info).unwrap_or_default()
+}
+
 /// Get recommended power state
 pub fn get_recommended_power_state() -> PowerState {
     if let Some(ref monitor) = BATTERY_MONITOR.lock().as_ref() {
@@ -375,4 +423,34 @@ pub fn is_low() -> bool {
     } else {
         false
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_time_remaining_follows_the_rate() {
+        let mut status = BatteryStatus {
+            present: true,
+            discharging: true,
+            level_percent: 50,
+            rate_mw: 10000,
+            remaining_mwh: 20000,
+            full_mwh: 40000,
+            ..BatteryStatus::default()
+        };
+        assert_eq!(time_remaining(&status), Some(120));
+
+        status.discharging = false;
+        status.charging = true;
+        status.rate_mw = 40000;
+        assert_eq!(time_remaining(&status), Some(30));
+
+        status.rate_mw = 0;
+        assert_eq!(time_remaining(&status), None);
+        status.charging = false;
+        status.rate_mw = 10000;
+        assert_eq!(time_remaining(&status), None);
+    }
+}
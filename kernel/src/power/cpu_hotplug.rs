@@ -0,0 +1,481 @@
+//! CPU Hotplug and Core Parking
+//!
+//! Takes idle cores offline under sustained low load and brings them back
+//! when load rises or an interactive spike is detected. Parking a core
+//! migrates its processes and interrupts to the remaining online cores and
+//! halts it through `crate::smp`; unparking starts it again.
+
+use super::{PowerError, PowerState};
+use crate::process::ProcessId;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// The boot CPU is never parked
+pub const BOOT_CPU: u32 = 0;
+
+/// Hotplug state of a CPU core
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpuState {
+    /// Core is running and accepting work
+    Online,
+    /// Core is parked in deep idle with no work assigned
+    Parked,
+}
+
+/// Core parking thresholds
+#[derive(Debug, Clone, Copy)]
+pub struct HotplugConfig {
+    /// Average load of online cores below which a core may be parked
+    pub park_load_threshold_percent: u8,
+    /// Average load of online cores above which a parked core is woken
+    pub unpark_load_threshold_percent: u8,
+    /// How long load must stay low before a core is parked
+    pub park_delay_ms: u64,
+    /// Minimum time between two hotplug operations
+    pub min_transition_interval_ms: u64,
+}
+
+impl Default for HotplugConfig {
+    fn default() -> Self {
+        Self {
+            park_load_threshold_percent: 20,
+            unpark_load_threshold_percent: 70,
+            park_delay_ms: 2000,
+            min_transition_interval_ms: 250,
+        }
+    }
+}
+
+/// Per-core hotplug bookkeeping
+#[derive(Debug, Clone)]
+struct CoreInfo {
+    state: CpuState,
+    load_percent: u8,
+    processes: Vec<ProcessId>,
+    irqs: Vec<u8>,
+}
+
+/// Hotplug statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HotplugStats {
+    pub online_cpus: u32,
+    pub parked_cpus: u32,
+    pub park_count: u64,
+    pub unpark_count: u64,
+    pub processes_migrated: u64,
+    pub irqs_migrated: u64,
+    pub interactive_wakeups: u64,
+}
+
+/// CPU hotplug manager
+pub struct CpuHotplugManager {
+    cores: Vec<CoreInfo>,
+    config: HotplugConfig,
+    power_state: PowerState,
+    low_load_since: Option<u64>,
+    last_transition_time: u64,
+    stats: HotplugStats,
+}
+
+impl CpuHotplugManager {
+    /// Create new hotplug manager for `cpu_count` cores, all online
+    pub fn new(cpu_count: u32) -> Self {
+        let cpu_count = cpu_count.max(1);
+        let cores = (0..cpu_count).map(|_| CoreInfo {
+            state: CpuState::Online,
+            load_percent: 0,
+            processes: Vec::new(),
+            irqs: Vec::new(),
+        }).collect();
+
+        Self {
+            cores,
+            config: HotplugConfig::default(),
+            power_state: PowerState::Balanced,
+            low_load_since: None,
+            last_transition_time: 0,
+            stats: HotplugStats {
+                online_cpus: cpu_count,
+                ..HotplugStats::default()
+            },
+        }
+    }
+
+    /// Set the power state that bounds how many cores may be parked
+    pub fn set_power_state(&mut self, state: PowerState) -> Result<(), PowerError> {
+        self.power_state = state;
+
+        // Performance mode keeps every core available
+        if state == PowerState::Performance {
+            self.unpark_all()?;
+        }
+        Ok(())
+    }
+
+    /// Update the measured load of a core
+    pub fn update_cpu_load(&mut self, cpu: u32, load_percent: u8) -> Result<(), PowerError> {
+        let core = self.cores.get_mut(cpu as usize).ok_or(PowerError::NotSupported)?;
+        core.load_percent = load_percent.min(100);
+        Ok(())
+    }
+
+    /// Record that a process runs on `cpu`; work is redirected away from parked cores
+    pub fn assign_process(&mut self, pid: ProcessId, cpu: u32) -> Result<u32, PowerError> {
+        self.remove_process(pid);
+
+        let target = self.online_target(cpu)?;
+        self.cores[target as usize].processes.push(pid);
+        Ok(target)
+    }
+
+    /// Record that an interrupt is routed to `cpu`; returns the core actually used
+    pub fn assign_irq(&mut self, irq: u8, cpu: u32) -> Result<u32, PowerError> {
+        for core in self.cores.iter_mut() {
+            core.irqs.retain(|&assigned| assigned != irq);
+        }
+
+        let target = self.online_target(cpu)?;
+        self.cores[target as usize].irqs.push(irq);
+        Ok(target)
+    }
+
+    /// Forget a terminated process
+    pub fn remove_process(&mut self, pid: ProcessId) {
+        for core in self.cores.iter_mut() {
+            core.processes.retain(|&assigned| assigned != pid);
+        }
+    }
+
+    /// Core a process is currently assigned to
+    pub fn cpu_of_process(&self, pid: ProcessId) -> Option<u32> {
+        self.cores.iter()
+            .position(|core| core.processes.contains(&pid))
+            .map(|cpu| cpu as u32)
+    }
+
+    /// Get hotplug state of a core
+    pub fn get_cpu_state(&self, cpu: u32) -> Option<CpuState> {
+        self.cores.get(cpu as usize).map(|core| core.state)
+    }
+
+    /// Park a core: migrate its work away and put it into deep idle
+    pub fn park_cpu(&mut self, cpu: u32, current_time: u64) -> Result<(), PowerError> {
+        if cpu == BOOT_CPU {
+            return Err(PowerError::InvalidTransition);
+        }
+        match self.cores.get(cpu as usize).map(|core| core.state) {
+            Some(CpuState::Online) => {}
+            Some(CpuState::Parked) => return Ok(()),
+            None => return Err(PowerError::NotSupported),
+        }
+
+        self.execute_core_offline(cpu)?;
+
+        let processes = core::mem::take(&mut self.cores[cpu as usize].processes);
+        let irqs = core::mem::take(&mut self.cores[cpu as usize].irqs);
+        self.cores[cpu as usize].state = CpuState::Parked;
+        self.cores[cpu as usize].load_percent = 0;
+
+        // Spread processes over the least loaded online cores
+        for pid in processes {
+            let target = self.least_loaded_online_cpu();
+            self.cores[target as usize].processes.push(pid);
+            self.stats.processes_migrated += 1;
+        }
+
+        // Interrupts go to the boot CPU, which is always online and which
+        // the interrupt controllers already deliver device interrupts to
+        for irq in irqs {
+            self.cores[BOOT_CPU as usize].irqs.push(irq);
+            self.stats.irqs_migrated += 1;
+        }
+
+        self.stats.park_count += 1;
+        self.last_transition_time = current_time;
+        self.update_counts();
+        Ok(())
+    }
+
+    /// Bring a parked core back online
+    pub fn unpark_cpu(&mut self, cpu: u32, current_time: u64) -> Result<(), PowerError> {
+        match self.cores.get(cpu as usize).map(|core| core.state) {
+            Some(CpuState::Parked) => {}
+            Some(CpuState::Online) => return Ok(()),
+            None => return Err(PowerError::NotSupported),
+        }
+
+        self.execute_core_online(cpu)?;
+
+        self.cores[cpu as usize].state = CpuState::Online;
+        self.stats.unpark_count += 1;
+        self.last_transition_time = current_time;
+        self.low_load_since = None;
+        self.update_counts();
+        Ok(())
+    }
+
+    /// Wake cores for an interactive load spike
+    pub fn handle_interactive_spike(&mut self, current_time: u64) -> Result<(), PowerError> {
+        let allowed = self.max_online_cpus();
+        let mut woken = false;
+
+        for cpu in 0..self.cores.len() as u32 {
+            if self.online_count() >= allowed {
+                break;
+            }
+            if self.cores[cpu as usize].state == CpuState::Parked {
+                self.unpark_cpu(cpu, current_time)?;
+                woken = true;
+            }
+        }
+
+        if woken {
+            self.stats.interactive_wakeups += 1;
+        }
+        Ok(())
+    }
+
+    /// Make parking decisions (called periodically by the power policy)
+    pub fn evaluate(&mut self, current_time: u64) -> Result<(), PowerError> {
+        let online = self.online_count();
+        let min_online = self.min_online_cpus();
+        let average_load = self.average_online_load();
+
+        // Power state changes may force cores off
+        if online > self.max_online_cpus() {
+            if let Some(cpu) = self.highest_online_cpu() {
+                self.park_cpu(cpu, current_time)?;
+            }
+            return Ok(());
+        }
+
+        if current_time.saturating_sub(self.last_transition_time) < self.config.min_transition_interval_ms {
+            return Ok(());
+        }
+
+        if average_load >= self.config.unpark_load_threshold_percent || online < min_online {
+            self.low_load_since = None;
+            if online < self.max_online_cpus() {
+                if let Some(cpu) = self.lowest_parked_cpu() {
+                    self.unpark_cpu(cpu, current_time)?;
+                }
+            }
+        } else if average_load < self.config.park_load_threshold_percent && online > min_online {
+            let since = *self.low_load_since.get_or_insert(current_time);
+            if current_time.saturating_sub(since) >= self.config.park_delay_ms {
+                if let Some(cpu) = self.highest_online_cpu() {
+                    self.park_cpu(cpu, current_time)?;
+                }
+                // Require another full quiet period before parking the next core
+                self.low_load_since = Some(current_time);
+            }
+        } else {
+            self.low_load_since = None;
+        }
+
+        Ok(())
+    }
+
+    /// Get hotplug statistics
+    pub fn get_stats(&self) -> HotplugStats {
+        self.stats
+    }
+
+    /// Set parking thresholds
+    pub fn set_config(&mut self, config: HotplugConfig) {
+        self.config = config;
+    }
+
+    // Private methods
+
+    fn unpark_all(&mut self) -> Result<(), PowerError> {
+        for cpu in 0..self.cores.len() as u32 {
+            self.unpark_cpu(cpu, self.last_transition_time)?;
+        }
+        Ok(())
+    }
+
+    /// Fewest cores that must stay online in the current power state
+    fn min_online_cpus(&self) -> u32 {
+        match self.power_state {
+            PowerState::Performance => self.cores.len() as u32,
+            _ => 1,
+        }
+    }
+
+    /// Most cores allowed online in the current power state
+    fn max_online_cpus(&self) -> u32 {
+        let total = self.cores.len() as u32;
+        match self.power_state {
+            PowerState::Performance | PowerState::Balanced => total,
+            PowerState::PowerSaver => (total / 2).max(1),
+            PowerState::Critical => 1,
+        }
+    }
+
+    fn online_count(&self) -> u32 {
+        self.cores.iter().filter(|core| core.state == CpuState::Online).count() as u32
+    }
+
+    fn average_online_load(&self) -> u8 {
+        let (total, count) = self.cores.iter()
+            .filter(|core| core.state == CpuState::Online)
+            .fold((0u32, 0u32), |(total, count), core| (total + core.load_percent as u32, count + 1));
+        if count == 0 { 0 } else { (total / count) as u8 }
+    }
+
+    fn online_target(&self, cpu: u32) -> Result<u32, PowerError> {
+        match self.cores.get(cpu as usize) {
+            Some(core) if core.state == CpuState::Online => Ok(cpu),
+            Some(_) => Ok(self.least_loaded_online_cpu()),
+            None => Err(PowerError::NotSupported),
+        }
+    }
+
+    fn least_loaded_online_cpu(&self) -> u32 {
+        self.cores.iter()
+            .enumerate()
+            .filter(|(_, core)| core.state == CpuState::Online)
+            .min_by_key(|(_, core)| (core.processes.len(), core.load_percent))
+            .map(|(cpu, _)| cpu as u32)
+            .unwrap_or(BOOT_CPU)
+    }
+
+    fn highest_online_cpu(&self) -> Option<u32> {
+        (1..self.cores.len() as u32)
+            .rev()
+            .find(|&cpu| self.cores[cpu as usize].state == CpuState::Online)
+    }
+
+    fn lowest_parked_cpu(&self) -> Option<u32> {
+        (0..self.cores.len() as u32)
+            .find(|&cpu| self.cores[cpu as usize].state == CpuState::Parked)
+    }
+
+    fn update_counts(&mut self) {
+        self.stats.online_cpus = self.online_count();
+        self.stats.parked_cpus = self.cores.len() as u32 - self.stats.online_cpus;
+    }
+
+    /// Take the core out of scheduling; it halts once it has handed its
+    /// running process to another core
+    fn execute_core_offline(&self, cpu: u32) -> Result<(), PowerError> {
+        crate::smp::offline_cpu(cpu as usize).map_err(|e| {
+            log::warn!("CPU {} cannot go offline: {}", cpu, e);
+            PowerError::InvalidTransition
+        })
+    }
+
+    /// Start the halted core again (INIT-SIPI, PSCI CPU_ON or SBI
+    /// hart_start) and wait for it to reach its idle loop
+    fn execute_core_online(&self, cpu: u32) -> Result<(), PowerError> {
+        crate::smp::online_cpu(cpu as usize).map_err(|e| {
+            log::warn!("CPU {} cannot come back online: {}", cpu, e);
+            PowerError::HardwareError
+        })
+    }
+}
+
+/// Global CPU hotplug manager
+static HOTPLUG_MANAGER: Mutex<Option<CpuHotplugManager>> = Mutex::new(None);
+
+/// Initialize CPU hotplug management
+pub fn init() -> Result<(), PowerError> {
+    // Only the cores brought up can be parked and started again
+    let cpu_count = crate::smp::online_count() as u32;
+    *HOTPLUG_MANAGER.lock() = Some(CpuHotplugManager::new(cpu_count));
+    Ok(())
+}
+
+/// Set the power state that bounds core parking
+pub fn set_power_state(state: PowerState) -> Result<(), PowerError> {
+    if let Some(ref mut manager) = HOTPLUG_MANAGER.lock().as_mut() {
+        manager.set_power_state(state)
+    } else {
+        Err(PowerError::NotSupported)
+    }
+}
+
+/// Update the measured load of a core
+pub fn update_cpu_load(cpu: u32, load_percent: u8) -> Result<(), PowerError> {
+    if let Some(ref mut manager) = HOTPLUG_MANAGER.lock().as_mut() {
+        manager.update_cpu_load(cpu, load_percent)
+    } else {
+        Err(PowerError::NotSupported)
+    }
+}
+
+/// Record the core a process runs on; returns the online core it was placed on
+pub fn assign_process(pid: ProcessId, cpu: u32) -> Result<u32, PowerError> {
+    if let Some(ref mut manager) = HOTPLUG_MANAGER.lock().as_mut() {
+        manager.assign_process(pid, cpu)
+    } else {
+        Err(PowerError::NotSupported)
+    }
+}
+
+/// Record the core an interrupt is routed to; returns the online core used
+pub fn assign_irq(irq: u8, cpu: u32) -> Result<u32, PowerError> {
+    if let Some(ref mut manager) = HOTPLUG_MANAGER.lock().as_mut() {
+        manager.assign_irq(irq, cpu)
+    } else {
+        Err(PowerError::NotSupported)
+    }
+}
+
+/// Remove process from hotplug tracking
+pub fn remove_process(pid: ProcessId) {
+    if let Some(ref mut manager) = HOTPLUG_MANAGER.lock().as_mut() {
+        manager.remove_process(pid);
+    }
+}
+
+/// Park a core
+pub fn park_cpu(cpu: u32, current_time: u64) -> Result<(), PowerError> {
+    if let Some(ref mut manager) = HOTPLUG_MANAGER.lock().as_mut() {
+        manager.park_cpu(cpu, current_time)
+    } else {
+        Err(PowerError::NotSupported)
+    }
+}
+
+/// Bring a parked core back online
+pub fn unpark_cpu(cpu: u32, current_time: u64) -> Result<(), PowerError> {
+    if let Some(ref mut manager) = HOTPLUG_MANAGER.lock().as_mut() {
+        manager.unpark_cpu(cpu, current_time)
+    } else {
+        Err(PowerError::NotSupported)
+    }
+}
+
+/// Wake cores for an interactive load spike
+pub fn handle_interactive_spike(current_time: u64) -> Result<(), PowerError> {
+    if let Some(ref mut manager) = HOTPLUG_MANAGER.lock().as_mut() {
+        manager.handle_interactive_spike(current_time)
+    } else {
+        Ok(())
+    }
+}
+
+/// Make parking decisions (called periodically)
+pub fn evaluate(current_time: u64) -> Result<(), PowerError> {
+    if let Some(ref mut manager) = HOTPLUG_MANAGER.lock().as_mut() {
+        manager.evaluate(current_time)
+    } else {
+        Ok(())
+    }
+}
+
+/// Get hotplug state of a core
+pub fn get_cpu_state(cpu: u32) -> Option<CpuState> {
+    HOTPLUG_MANAGER.lock().as_ref().and_then(|manager| manager.get_cpu_state(cpu))
+}
+
+/// Get hotplug statistics
+pub fn get_stats() -> Result<HotplugStats, PowerError> {
+    if let Some(ref manager) = HOTPLUG_MANAGER.lock().as_ref() {
+        Ok(manager.get_stats())
+    } else {
+        Err(PowerError::NotSupported)
+    }
+}
@@ -1,12 +1,61 @@
 //! CPU Frequency Scaling
 //! 
 //! Provides dynamic CPU frequency scaling for power management
+//!
+//! The governors choose a frequency between the slowest and fastest
+//! levels the platform backend offers, and the closest level is set in
+//! hardware: through HWP, ACPI P-states or SpeedStep on x86-64, or SCMI
+//! on ARM64. Without a backend the frequency is only tracked, in the
+//! default range.
+//!
+//! `SYS_CPUFREQ_INFO` reports the state, with read access to the "power"
+//! system resource, and `SYS_CPUFREQ_SET` changes the governor, with write
+//! access to it; the shell's `powerctl` command uses both.
 
 use super::{CpuFrequency, CpuGovernor, PowerError, ProcessActivity};
+use crate::platform::{FrequencyControl, PerformanceLevel};
 use crate::process::ProcessId;
 use alloc::collections::BTreeMap;
 use spin::Mutex;
 
+/// Bytes of backend name `CpuFreqInfo` carries
+pub const BACKEND_NAME_LEN: usize = 16;
+
+/// Frequency scaling state, as reported by the `cpufreq_info` system call
+///
+/// The layout is shared with `kosh_posix::power`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct CpuFreqInfo {
+    /// `CpuGovernor::raw` of the governor in use
+    pub governor: u32,
+    pub current_mhz: u32,
+    pub min_mhz: u32,
+    pub max_mhz: u32,
+    /// Levels the backend offers, 0 without one
+    pub levels: u32,
+    /// Backend name, UTF-8 padded with NULs; empty without one
+    pub backend: [u8; BACKEND_NAME_LEN],
+}
+
+/// Index of the level to run at when a governor moves from `current_mhz`
+/// to `target_mhz`
+///
+/// The level reached is at or past the target in the direction of the
+/// move, so a governor stepping by less than the gap between two levels
+/// still gets from one to the next.
+pub fn choose_level(levels: &[PerformanceLevel], current_mhz: u32, target_mhz: u32) -> Option<usize> {
+    if levels.is_empty() {
+        return None;
+    }
+    let last = levels.len() - 1;
+    if target_mhz >= current_mhz {
+        Some(levels.iter().position(|level| level.frequency_mhz >= target_mhz).unwrap_or(last))
+    } else {
+        Some(levels.iter().rposition(|level| level.frequency_mhz <= target_mhz).unwrap_or(0))
+    }
+}
+
 /// CPU frequency scaling manager
 pub struct CpuScalingManager {
     current_governor: CpuGovernor,
@@ -18,6 +67,10 @@ pub struct CpuScalingManager {
     process_activities: BTreeMap<ProcessId, ProcessActivity>,
     interactive_boost_active: bool,
     boost_end_time: u64, // Timestamp when boost should end
+    /// Hardware backend, if the platform has one
+    control: Option<FrequencyControl>,
+    /// Index of the level last set in hardware
+    current_level: Option<usize>,
 }
 
 impl CpuScalingManager {
@@ -33,17 +86,13 @@ impl CpuScalingManager {
             process_activities: BTreeMap::new(),
             interactive_boost_active: false,
             boost_end_time: 0,
+            control: None,
+            current_level: None,
         }
     }
 
     /// Initialize CPU frequency scaling
     pub fn init(&mut self) -> Result<(), PowerError> {
-        // In a real implementation, this would:
-        // 1. Detect CPU frequency scaling capabilities
-        // 2. Read available frequencies from hardware
-        // 3. Set initial governor and frequency
-        
-        // For now, simulate successful initialization
         self.detect_frequency_range()?;
         self.set_frequency(self.current_frequency)?;
         Ok(())
@@ -79,6 +128,32 @@ impl CpuScalingManager {
         }
     }
 
+    /// Governor in use
+    pub fn governor(&self) -> CpuGovernor {
+        self.current_governor
+    }
+
+    /// Name of the hardware backend, if there is one
+    pub fn backend(&self) -> Option<&'static str> {
+        self.control.as_ref().map(|control| control.backend)
+    }
+
+    /// State as `SYS_CPUFREQ_INFO` reports it
+    pub fn info(&self) -> CpuFreqInfo {
+        let mut backend = [0; BACKEND_NAME_LEN];
+        let name = self.backend().unwrap_or("").as_bytes();
+        let length = name.len().min(BACKEND_NAME_LEN);
+        backend[..length].copy_from_slice(&name[..length]);
+        CpuFreqInfo {
+            governor: self.current_governor.raw(),
+            current_mhz: self.current_frequency,
+            min_mhz: self.min_frequency,
+            max_mhz: self.max_frequency,
+            levels: self.control.as_ref().map_or(0, |control| control.levels.len() as u32),
+            backend,
+        }
+    }
+
     /// Update CPU load and adjust frequency if needed
     pub fn update_load(&mut self, load_percent: u8) -> Result<(), PowerError> {
         // Store load in circular buffer
@@ -130,8 +205,17 @@ impl CpuScalingManager {
     // Private methods
 
     fn detect_frequency_range(&mut self) -> Result<(), PowerError> {
-        // In a real implementation, this would read from hardware
-        // For now, use default values
+        self.control = crate::platform::probe_frequency_control();
+        if let Some(control) = &self.control {
+            let (Some(slowest), Some(fastest)) = (control.levels.first(), control.levels.last()) else {
+                return Err(PowerError::FrequencyScalingUnavailable);
+            };
+            self.min_frequency = slowest.frequency_mhz;
+            self.max_frequency = fastest.frequency_mhz;
+            self.current_frequency = self.current_frequency.clamp(self.min_frequency, self.max_frequency);
+            log::info!("CPU frequency scaling through {}: {} levels, {}-{} MHz",
+                       control.backend, control.levels.len(), self.min_frequency, self.max_frequency);
+        }
         Ok(())
     }
 
@@ -140,9 +224,19 @@ impl CpuScalingManager {
             return Err(PowerError::InvalidTransition);
         }
 
-        // In a real implementation, this would write to hardware registers
-        // or use ACPI/platform-specific interfaces
-        self.current_frequency = frequency_mhz;
+        let Some(control) = &self.control else {
+            self.current_frequency = frequency_mhz;
+            return Ok(());
+        };
+        let index = choose_level(&control.levels, self.current_frequency, frequency_mhz).ok_or(PowerError::FrequencyScalingUnavailable)?;
+        // The governors run on every load update; only a change reaches
+        // the hardware
+        if self.current_level != Some(index) {
+            crate::platform::set_performance_level(&control.levels[index])
+                .map_err(|_| PowerError::HardwareError)?;
+            self.current_level = Some(index);
+        }
+        self.current_frequency = control.levels[index].frequency_mhz;
         Ok(())
     }
 
@@ -241,6 +335,15 @@ pub fn get_frequency_info() -> Result<CpuFrequency, PowerError> {
     }
 }
 
+/// Frequency scaling state, for `SYS_CPUFREQ_INFO`
+pub fn info() -> Result<CpuFreqInfo, PowerError> {
+    if let Some(ref manager) = CPU_SCALING.lock().as_ref() {
+        Ok(manager.info())
+    } else {
+        Err(PowerError::FrequencyScalingUnavailable)
+    }
+}
+
 /// Update CPU load
 pub fn update_load(load_percent: u8) -> Result<(), PowerError> {
     if let Some(ref mut manager) = CPU_SCALING.lock().as_mut() {
@@ -271,4 +374,23 @@ pub fn tick(current_time: u64) -> Result<(), PowerError> {
     } else {
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_levels_are_chosen_in_the_direction_of_the_move() {
+        let levels = [
+            PerformanceLevel { frequency_mhz: 800, control: 8 },
+            PerformanceLevel { frequency_mhz: 1200, control: 12 },
+            PerformanceLevel { frequency_mhz: 2000, control: 20 },
+        ];
+        assert_eq!(choose_level(&levels, 800, 960), Some(1));
+        assert_eq!(choose_level(&levels, 1200, 1200), Some(1));
+        assert_eq!(choose_level(&levels, 1200, 2400), Some(2));
+        assert_eq!(choose_level(&levels, 2000, 1840), Some(1));
+        assert_eq!(choose_level(&levels, 1200, 700), Some(0));
+        assert_eq!(choose_level(&[], 800, 1000), None);
+    }
+}
@@ -1,35 +1,78 @@
 //! Idle State Management
-//! 
-//! Manages CPU idle states for power saving when the system is not busy
+//!
+//! Chooses how deeply each CPU sleeps when it has nothing to run. The
+//! platform lists the low-power states its CPUs offer, shallowest first:
+//! `hlt` and MWAIT C-states on x86-64, `wfi` and PSCI retention states on
+//! ARM64. Each CPU's idle loop asks `select_state` for the deepest state
+//! that pays off over the idle period it predicts and wakes up within the
+//! allowed latency, waits in it, and reports how long it slept with
+//! `record_idle`.
+//!
+//! The idle period is predicted as the mean of the CPU's last few, and is
+//! never longer than a scheduler tick, which always wakes the CPU. While
+//! an interactive process is active the wake latency is held to
+//! `INTERACTIVE_LATENCY_US`, so that input is answered promptly.
+//!
+//! A timer interrupt can switch the idle loop out at any point, so its
+//! side keeps to atomics and to the state list, fixed once probed.
 
 use super::{PowerError, ProcessActivity};
+use crate::platform::{self, CpuIdleState};
 use crate::process::ProcessId;
+use crate::process::scheduler::TICK_MS;
+use crate::smp::{self, MAX_CPUS};
 use alloc::collections::BTreeMap;
-use spin::Mutex;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use spin::{Mutex, Once};
+
+/// Wake latency allowed while an interactive process is active
+pub const INTERACTIVE_LATENCY_US: u32 = 100;
+
+/// Idle periods each CPU predicts the next one from
+const IDLE_HISTORY: usize = 8;
+
+/// Most states used; deeper ones the platform offers are left out
+const MAX_STATES: usize = 8;
+
+/// Longest a CPU sleeps, woken by the scheduler tick
+const TICK_US: u32 = TICK_MS as u32 * 1000;
 
 /// CPU idle states (C-states)
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum IdleState {
     /// CPU running normally
     C0,
-    /// CPU halted, caches and TLBs maintained
+    /// CPU halted in the platform's shallowest state, `hlt` or `wfi`
     C1,
-    /// CPU powered down, caches flushed
+    /// The platform's second state
     C2,
-    /// CPU and caches powered down
+    /// The platform's third state
     C3,
-    /// Deepest sleep state
+    /// The platform's fourth state or a deeper one
     C4,
 }
 
+impl IdleState {
+    /// State the platform state at `index` counts as
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            0 => IdleState::C1,
+            1 => IdleState::C2,
+            2 => IdleState::C3,
+            _ => IdleState::C4,
+        }
+    }
+}
+
 /// Idle state information
 #[derive(Debug, Clone, Copy)]
 pub struct IdleStateInfo {
     pub state: IdleState,
-    pub entry_latency_us: u32,
+    /// Platform name, such as "hlt" or "psci-retention"
+    pub name: &'static str,
     pub exit_latency_us: u32,
-    pub power_consumption_mw: u32,
-    pub available: bool,
+    pub target_residency_us: u32,
 }
 
 /// Idle management statistics
@@ -44,327 +87,155 @@ pub struct IdleStats {
     pub total_idle_time: u64,
 }
 
+impl IdleStats {
+    fn add_idle_time(&mut self, state: IdleState, time: u64) {
+        match state {
+            IdleState::C0 => self.time_in_c0 += time,
+            IdleState::C1 => self.time_in_c1 += time,
+            IdleState::C2 => self.time_in_c2 += time,
+            IdleState::C3 => self.time_in_c3 += time,
+            IdleState::C4 => self.time_in_c4 += time,
+        }
+        self.total_idle_time += time;
+    }
+}
+
+/// What one CPU's idle loop records
+struct CpuIdle {
+    /// Latest idle periods in µs, a ring `next` points into
+    history: [AtomicU32; IDLE_HISTORY],
+    next: AtomicUsize,
+    /// Time slept in each state, in µs
+    residency_us: [AtomicU64; MAX_STATES],
+    entries: AtomicU64,
+}
+
+impl CpuIdle {
+    const fn new() -> Self {
+        Self {
+            history: [const { AtomicU32::new(0) }; IDLE_HISTORY],
+            next: AtomicUsize::new(0),
+            residency_us: [const { AtomicU64::new(0) }; MAX_STATES],
+            entries: AtomicU64::new(0),
+        }
+    }
+}
+
+static CPU_IDLE: [CpuIdle; MAX_CPUS] = [const { CpuIdle::new() }; MAX_CPUS];
+
+/// States the platform offers, shallowest first
+static STATES: Once<Vec<CpuIdleState>> = Once::new();
+
+/// Wake latency the idle loops keep to, in µs
+static LATENCY_LIMIT_US: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// Idle period to expect after `history`, the latest ones: their mean, at
+/// most a tick
+pub fn predict_idle_us(history: &[u32]) -> u32 {
+    if history.is_empty() {
+        return 0;
+    }
+    let mean = history.iter().map(|&period| period as u64).sum::<u64>() / history.len() as u64;
+    mean.min(TICK_US as u64) as u32
+}
+
+/// Index of the deepest of `states` that pays off over `predicted_us` and
+/// wakes within `latency_limit_us`; the first state if none does
+pub fn choose_state(states: &[CpuIdleState], predicted_us: u32, latency_limit_us: u32) -> usize {
+    (1..states.len()).rev()
+        .find(|&index| {
+            states[index].target_residency_us <= predicted_us && states[index].exit_latency_us <= latency_limit_us
+        })
+        .unwrap_or(0)
+}
+
 /// Idle state manager
+///
+/// Tracks the activity of processes to set the wake latency the idle
+/// loops keep to.
 pub struct IdleManager {
-    current_state: IdleState,
-    available_states: [IdleStateInfo; 5],
-    stats: IdleStats,
-    last_activity_time: u64,
-    idle_threshold_ms: u64,
-    deep_idle_threshold_ms: u64,
     active_processes: BTreeMap<ProcessId, ProcessActivity>,
-    prevent_deep_idle: bool,
 }
 
 impl IdleManager {
     /// Create new idle manager
     pub fn new() -> Self {
         Self {
-            current_state: IdleState::C0,
-            available_states: [
-                IdleStateInfo {
-                    state: IdleState::C0,
-                    entry_latency_us: 0,
-                    exit_latency_us: 0,
-                    power_consumption_mw: 1000,
-                    available: true,
-                },
-                IdleStateInfo {
-                    state: IdleState::C1,
-                    entry_latency_us: 1,
-                    exit_latency_us: 1,
-                    power_consumption_mw: 500,
-                    available: true,
-                },
-                IdleStateInfo {
-                    state: IdleState::C2,
-                    entry_latency_us: 10,
-                    exit_latency_us: 20,
-                    power_consumption_mw: 200,
-                    available: true,
-                },
-                IdleStateInfo {
-                    state: IdleState::C3,
-                    entry_latency_us: 50,
-                    exit_latency_us: 100,
-                    power_consumption_mw: 50,
-                    available: true,
-                },
-                IdleStateInfo {
-                    state: IdleState::C4,
-                    entry_latency_us: 200,
-                    exit_latency_us: 500,
-                    power_consumption_mw: 10,
-                    available: false, // Disabled by default
-                },
-            ],
-            stats: IdleStats::default(),
-            last_activity_time: 0,
-            idle_threshold_ms: 10,      // Enter idle after 10ms of inactivity
-            deep_idle_threshold_ms: 100, // Enter deep idle after 100ms
             active_processes: BTreeMap::new(),
-            prevent_deep_idle: false,
-        }
-    }
-
-    /// Initialize idle state management
-    pub fn init(&mut self) -> Result<(), PowerError> {
-        // In a real implementation, this would:
-        // 1. Detect available CPU idle states from ACPI
-        // 2. Configure idle state parameters
-        // 3. Set up idle state transition mechanisms
-        
-        // Enable C4 state if supported by hardware
-        self.available_states[4].available = self.detect_deep_idle_support();
-        
-        Ok(())
-    }
-
-    /// Enter appropriate idle state based on system conditions
-    pub fn enter_idle(&mut self, current_time: u64) -> Result<IdleState, PowerError> {
-        let idle_duration = current_time.saturating_sub(self.last_activity_time);
-        
-        // Determine appropriate idle state
-        let target_state = self.select_idle_state(idle_duration)?;
-        
-        if target_state != self.current_state {
-            self.transition_to_state(target_state, current_time)?;
         }
-        
-        Ok(target_state)
-    }
-
-    /// Exit idle state due to activity
-    pub fn exit_idle(&mut self, current_time: u64) -> Result<(), PowerError> {
-        if self.current_state != IdleState::C0 {
-            let idle_time = current_time.saturating_sub(self.last_activity_time);
-            self.update_idle_stats(idle_time);
-            
-            self.transition_to_state(IdleState::C0, current_time)?;
-        }
-        
-        self.last_activity_time = current_time;
-        Ok(())
     }
 
     /// Notify of process activity
-    pub fn notify_process_activity(&mut self, pid: ProcessId, activity: ProcessActivity, current_time: u64) {
+    pub fn notify_process_activity(&mut self, pid: ProcessId, activity: ProcessActivity) {
         self.active_processes.insert(pid, activity);
-        self.last_activity_time = current_time;
-        
-        // Prevent deep idle for interactive processes
-        self.prevent_deep_idle = self.active_processes
-            .values()
-            .any(|&act| matches!(act, ProcessActivity::Interactive));
+        self.update_latency_limit();
     }
 
     /// Remove process from activity tracking
     pub fn remove_process(&mut self, pid: ProcessId) {
         self.active_processes.remove(&pid);
-        
-        // Update deep idle prevention
-        self.prevent_deep_idle = self.active_processes
-            .values()
-            .any(|&act| matches!(act, ProcessActivity::Interactive));
-    }
-
-    /// Get current idle state
-    pub fn get_current_state(&self) -> IdleState {
-        self.current_state
-    }
-
-    /// Get idle statistics
-    pub fn get_stats(&self) -> IdleStats {
-        self.stats
+        self.update_latency_limit();
     }
 
-    /// Get available idle states
-    pub fn get_available_states(&self) -> &[IdleStateInfo; 5] {
-        &self.available_states
-    }
-
-    /// Set idle thresholds
-    pub fn set_thresholds(&mut self, idle_threshold_ms: u64, deep_idle_threshold_ms: u64) {
-        self.idle_threshold_ms = idle_threshold_ms;
-        self.deep_idle_threshold_ms = deep_idle_threshold_ms;
-    }
-
-    // Private methods
-
-    fn detect_deep_idle_support(&self) -> bool {
-        // In a real implementation, this would check ACPI tables
-        // For now, assume deep idle is supported
-        true
-    }
-
-    fn select_idle_state(&self, idle_duration_ms: u64) -> Result<IdleState, PowerError> {
-        // Don't enter idle if there's recent activity
-        if idle_duration_ms < self.idle_threshold_ms {
-            return Ok(IdleState::C0);
-        }
-
-        // Check for interactive processes that need responsiveness
+    /// Wake latency the active processes allow
+    pub fn latency_limit_us(&self) -> u32 {
         let has_interactive = self.active_processes
             .values()
             .any(|&activity| matches!(activity, ProcessActivity::Interactive));
-
-        let target_state = if has_interactive {
-            // For interactive workloads, use shallow idle states
-            if idle_duration_ms > 50 {
-                IdleState::C2
-            } else if idle_duration_ms > 20 {
-                IdleState::C1
-            } else {
-                IdleState::C0
-            }
-        } else if self.prevent_deep_idle {
-            // Prevent deep idle but allow moderate power saving
-            if idle_duration_ms > self.deep_idle_threshold_ms {
-                IdleState::C2
-            } else if idle_duration_ms > 20 {
-                IdleState::C1
-            } else {
-                IdleState::C0
-            }
+        if has_interactive {
+            INTERACTIVE_LATENCY_US
         } else {
-            // Full idle state selection for background workloads
-            if idle_duration_ms > 500 && self.available_states[4].available {
-                IdleState::C4
-            } else if idle_duration_ms > self.deep_idle_threshold_ms {
-                IdleState::C3
-            } else if idle_duration_ms > 50 {
-                IdleState::C2
-            } else if idle_duration_ms > 20 {
-                IdleState::C1
-            } else {
-                IdleState::C0
-            }
-        };
-
-        // Ensure the target state is available
-        let state_index = target_state as usize;
-        if self.available_states[state_index].available {
-            Ok(target_state)
-        } else {
-            // Fall back to a shallower available state
-            self.find_fallback_state(target_state)
-        }
-    }
-
-    fn find_fallback_state(&self, requested_state: IdleState) -> Result<IdleState, PowerError> {
-        let mut state_index = requested_state as usize;
-        
-        while state_index > 0 {
-            state_index -= 1;
-            if self.available_states[state_index].available {
-                return Ok(match state_index {
-                    0 => IdleState::C0,
-                    1 => IdleState::C1,
-                    2 => IdleState::C2,
-                    3 => IdleState::C3,
-                    4 => IdleState::C4,
-                    _ => IdleState::C0,
-                });
-            }
-        }
-        
-        Ok(IdleState::C0)
-    }
-
-    fn transition_to_state(&mut self, target_state: IdleState, current_time: u64) -> Result<(), PowerError> {
-        let old_state = self.current_state;
-        
-        // In a real implementation, this would:
-        // 1. Execute platform-specific idle instructions (HLT, MWAIT, etc.)
-        // 2. Configure hardware power management
-        // 3. Handle cache and TLB management
-        
-        self.current_state = target_state;
-        self.stats.total_idle_entries += 1;
-        
-        // Simulate hardware idle state transition
-        match target_state {
-            IdleState::C0 => {
-                // CPU active, no special handling needed
-            }
-            IdleState::C1 => {
-                // Execute HLT instruction equivalent
-                self.execute_halt();
-            }
-            IdleState::C2 => {
-                // Deeper idle with cache management
-                self.execute_deep_halt();
-            }
-            IdleState::C3 | IdleState::C4 => {
-                // Very deep idle states
-                self.execute_very_deep_halt();
-            }
-        }
-        
-        Ok(())
-    }
-
-    fn update_idle_stats(&mut self, idle_time: u64) {
-        self.stats.total_idle_time += idle_time;
-        
-        match self.current_state {
-            IdleState::C0 => self.stats.time_in_c0 += idle_time,
-            IdleState::C1 => self.stats.time_in_c1 += idle_time,
-            IdleState::C2 => self.stats.time_in_c2 += idle_time,
-            IdleState::C3 => self.stats.time_in_c3 += idle_time,
-            IdleState::C4 => self.stats.time_in_c4 += idle_time,
+            u32::MAX
         }
     }
 
-    fn execute_halt(&self) {
-        // In a real implementation, this would execute the HLT instruction
-        // For now, this is a placeholder
-    }
-
-    fn execute_deep_halt(&self) {
-        // In a real implementation, this would execute deeper idle instructions
-        // and manage cache coherency
-    }
-
-    fn execute_very_deep_halt(&self) {
-        // In a real implementation, this would execute the deepest idle states
-        // with full power management
+    fn update_latency_limit(&self) {
+        LATENCY_LIMIT_US.store(self.latency_limit_us(), Ordering::Relaxed);
     }
 }
 
 /// Global idle manager instance
 static IDLE_MANAGER: Mutex<Option<IdleManager>> = Mutex::new(None);
 
+/// The states the platform offers, shallowest first
+pub fn states() -> &'static [CpuIdleState] {
+    STATES.call_once(|| {
+        let mut states = platform::probe_idle_states();
+        states.truncate(MAX_STATES);
+        states
+    })
+}
+
 /// Initialize idle state management
 pub fn init() -> Result<(), PowerError> {
-    let mut manager = IdleManager::new();
-    manager.init()?;
-    *IDLE_MANAGER.lock() = Some(manager);
+    let names: Vec<&str> = states().iter().map(|state| state.name).collect();
+    log::info!("CPU idle states: {}", names.join(", "));
+    *IDLE_MANAGER.lock() = Some(IdleManager::new());
     Ok(())
 }
 
-/// Enter idle state
-pub fn enter_idle(current_time: u64) -> Result<IdleState, PowerError> {
-    if let Some(ref mut manager) = IDLE_MANAGER.lock().as_mut() {
-        manager.enter_idle(current_time)
-    } else {
-        Err(PowerError::NotSupported)
-    }
+/// Index of the state CPU `cpu` should sleep in now
+///
+/// Called by the CPU's idle loop before it sleeps.
+pub fn select_state(cpu: usize) -> usize {
+    let idle = &CPU_IDLE[cpu];
+    let history: [u32; IDLE_HISTORY] = core::array::from_fn(|slot| idle.history[slot].load(Ordering::Relaxed));
+    choose_state(states(), predict_idle_us(&history), LATENCY_LIMIT_US.load(Ordering::Relaxed))
 }
 
-/// Exit idle state
-pub fn exit_idle(current_time: u64) -> Result<(), PowerError> {
-    if let Some(ref mut manager) = IDLE_MANAGER.lock().as_mut() {
-        manager.exit_idle(current_time)
-    } else {
-        Err(PowerError::NotSupported)
-    }
+/// Note that CPU `cpu` slept `slept_us` in the state at `index`
+pub fn record_idle(cpu: usize, index: usize, slept_us: u64) {
+    let idle = &CPU_IDLE[cpu];
+    let slot = idle.next.fetch_add(1, Ordering::Relaxed) % IDLE_HISTORY;
+    idle.history[slot].store(slept_us.min(u32::MAX as u64) as u32, Ordering::Relaxed);
+    idle.residency_us[index].fetch_add(slept_us, Ordering::Relaxed);
+    idle.entries.fetch_add(1, Ordering::Relaxed);
 }
 
 /// Notify of process activity
-pub fn notify_process_activity(pid: ProcessId, activity: ProcessActivity, current_time: u64) {
+pub fn notify_process_activity(pid: ProcessId, activity: ProcessActivity) {
     if let Some(ref mut manager) = IDLE_MANAGER.lock().as_mut() {
-        manager.notify_process_activity(pid, activity, current_time);
+        manager.notify_process_activity(pid, activity);
     }
 }
 
@@ -375,20 +246,57 @@ pub fn remove_process(pid: ProcessId) {
     }
 }
 
-/// Get current idle state
-pub fn get_current_state() -> Result<IdleState, PowerError> {
-    if let Some(ref manager) = IDLE_MANAGER.lock().as_ref() {
-        Ok(manager.get_current_state())
-    } else {
-        Err(PowerError::NotSupported)
-    }
+/// Get available idle states
+pub fn get_available_states() -> Vec<IdleStateInfo> {
+    states().iter().enumerate()
+        .map(|(index, state)| IdleStateInfo {
+            state: IdleState::from_index(index),
+            name: state.name,
+            exit_latency_us: state.exit_latency_us,
+            target_residency_us: state.target_residency_us,
+        })
+        .collect()
 }
 
-/// Get idle statistics
+/// Get idle statistics, in milliseconds summed over the CPUs
 pub fn get_stats() -> Result<IdleStats, PowerError> {
-    if let Some(ref manager) = IDLE_MANAGER.lock().as_ref() {
-        Ok(manager.get_stats())
-    } else {
-        Err(PowerError::NotSupported)
+    if IDLE_MANAGER.lock().is_none() {
+        return Err(PowerError::NotSupported);
+    }
+    let mut stats = IdleStats::default();
+    for idle in &CPU_IDLE {
+        for (index, residency_us) in idle.residency_us.iter().enumerate() {
+            stats.add_idle_time(IdleState::from_index(index), residency_us.load(Ordering::Relaxed) / 1000);
+        }
+        stats.total_idle_entries += idle.entries.load(Ordering::Relaxed);
     }
-}
\ No newline at end of file
+    let online_ms = crate::time::monotonic_ms() * smp::online_count() as u64;
+    stats.time_in_c0 = online_ms.saturating_sub(stats.total_idle_time);
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_state_fits_predicted_idle_and_latency() {
+        let state = |exit_latency_us, target_residency_us| CpuIdleState {
+            name: "test",
+            exit_latency_us,
+            target_residency_us,
+            control: 0,
+        };
+        let states = [state(2, 2), state(70, 100), state(85, 200)];
+        assert_eq!(choose_state(&states, 50, u32::MAX), 0);
+        assert_eq!(choose_state(&states, 150, u32::MAX), 1);
+        assert_eq!(choose_state(&states, 900, u32::MAX), 2);
+        assert_eq!(choose_state(&states, 900, 80), 1);
+        assert_eq!(choose_state(&states, 900, 50), 0);
+        assert_eq!(choose_state(&states[..1], 900, u32::MAX), 0);
+
+        assert_eq!(predict_idle_us(&[]), 0);
+        assert_eq!(predict_idle_us(&[100, 300]), 200);
+        assert_eq!(predict_idle_us(&[u32::MAX; IDLE_HISTORY]), TICK_US);
+    }
+}
@@ -1,13 +1,16 @@
 //! Power Management Framework
 //! 
 //! This module provides power management capabilities for mobile optimization,
-//! including CPU frequency scaling, idle state management, and battery monitoring.
+//! including CPU frequency scaling, idle state management, core parking, battery
+//! monitoring and thermal throttling.
 
 pub mod cpu_scaling;
 pub mod idle_management;
 pub mod battery_monitor;
 pub mod power_policy;
 pub mod responsiveness;
+pub mod cpu_hotplug;
+pub mod thermal;
 
 use crate::process::ProcessId;
 
@@ -82,6 +85,30 @@ pub enum CpuGovernor {
     Interactive,
 }
 
+impl CpuGovernor {
+    /// Number used for the governor by `SYS_CPUFREQ_SET` and `CpuFreqInfo`
+    pub fn raw(self) -> u32 {
+        match self {
+            CpuGovernor::Performance => 1,
+            CpuGovernor::OnDemand => 2,
+            CpuGovernor::PowerSave => 3,
+            CpuGovernor::Conservative => 4,
+            CpuGovernor::Interactive => 5,
+        }
+    }
+    
+    pub fn from_raw(value: u64) -> Option<Self> {
+        match value {
+            1 => Some(CpuGovernor::Performance),
+            2 => Some(CpuGovernor::OnDemand),
+            3 => Some(CpuGovernor::PowerSave),
+            4 => Some(CpuGovernor::Conservative),
+            5 => Some(CpuGovernor::Interactive),
+            _ => None,
+        }
+    }
+}
+
 /// Process activity types for power management
 #[derive(Debug, Clone, Copy)]
 pub enum ProcessActivity {
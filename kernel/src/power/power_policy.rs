@@ -5,8 +5,9 @@
 use super::{
     PowerState, PowerError, ProcessActivity, CpuGovernor,
     battery_monitor::{self, BatteryEvent},
-    cpu_scaling, idle_management,
+    cpu_scaling, idle_management, cpu_hotplug, responsiveness, thermal,
 };
+use crate::platform::TripKind;
 use crate::process::{ProcessId, ProcessPriority};
 use alloc::collections::BTreeMap;
 use spin::Mutex;
@@ -39,6 +40,17 @@ pub enum ProcessPowerClass {
     Batch,
 }
 
+/// Preferred core class for a process on big.LITTLE systems
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CorePreference {
+    /// Run on high-capacity cores for latency
+    Big,
+    /// Run on energy-efficient cores
+    Little,
+    /// No preference
+    Any,
+}
+
 /// Power management policy engine
 pub struct PowerPolicyManager {
     current_state: PowerState,
@@ -48,7 +60,9 @@ pub struct PowerPolicyManager {
     boost_end_time: u64,
     last_policy_update: u64,
     policy_update_interval: u64,
-    thermal_throttling_active: bool,
+    /// Most power-hungry state the thermal zones allow, None while they
+    /// are cool
+    thermal_limit: Option<PowerState>,
 }
 
 impl PowerPolicyManager {
@@ -62,7 +76,7 @@ impl PowerPolicyManager {
             boost_end_time: 0,
             last_policy_update: 0,
             policy_update_interval: 1000, // Update every second
-            thermal_throttling_active: false,
+            thermal_limit: None,
         }
     }
 
@@ -79,7 +93,13 @@ impl PowerPolicyManager {
     }
 
     /// Set power state and update policies
+    ///
+    /// While the thermal zones are hot the state is held to their limit.
     pub fn set_power_state(&mut self, state: PowerState) -> Result<(), PowerError> {
+        let state = match self.thermal_limit {
+            Some(limit) if power_saving(limit) > power_saving(state) => limit,
+            _ => state,
+        };
         if state == self.current_state {
             return Ok(());
         }
@@ -104,6 +124,9 @@ impl PowerPolicyManager {
         };
         cpu_scaling::set_governor(governor)?;
 
+        // Bound how many cores may stay online
+        cpu_hotplug::set_power_state(state)?;
+
         // Adjust process priorities based on new policy
         self.apply_power_aware_scheduling()?;
 
@@ -125,6 +148,7 @@ impl PowerPolicyManager {
         self.process_classifications.remove(&pid);
         cpu_scaling::remove_process(pid);
         idle_management::remove_process(pid);
+        cpu_hotplug::remove_process(pid);
     }
 
     /// Notify of process activity for power-aware decisions
@@ -133,7 +157,7 @@ impl PowerPolicyManager {
         cpu_scaling::notify_process_activity(pid, activity);
         
         // Update idle management
-        idle_management::notify_process_activity(pid, activity, current_time);
+        idle_management::notify_process_activity(pid, activity);
 
         // Handle interactive boost
         if matches!(activity, ProcessActivity::Interactive) {
@@ -143,9 +167,17 @@ impl PowerPolicyManager {
 
     /// Update power management policies (called periodically)
     pub fn update(&mut self, current_time: u64) -> Result<(), PowerError> {
+        // Interactive spikes must wake parked cores without waiting for the policy interval
+        if responsiveness::detect_interactive_spike(current_time) {
+            cpu_hotplug::handle_interactive_spike(current_time)?;
+        }
+
         if current_time.saturating_sub(self.last_policy_update) >= self.policy_update_interval {
-            // Update battery monitoring
-            battery_monitor::update(current_time)?;
+            // Act on the trip points the thermal zones are past
+            self.apply_thermal_trip(thermal::update())?;
+
+            // Update battery monitoring; a machine without one goes on
+            let _ = battery_monitor::update(current_time);
             
             // Check if power state should change based on battery
             let recommended_state = battery_monitor::get_recommended_power_state();
@@ -156,6 +188,9 @@ impl PowerPolicyManager {
             // Update CPU frequency scaling
             cpu_scaling::tick(current_time)?;
 
+            // Park or unpark cores based on load
+            cpu_hotplug::evaluate(current_time)?;
+
             // Check interactive boost timeout
             if self.interactive_boost_active && current_time >= self.boost_end_time {
                 self.interactive_boost_active = false;
@@ -209,6 +244,50 @@ impl PowerPolicyManager {
         }
     }
 
+    /// Get the preferred core class for a process
+    ///
+    /// Unclassified processes are classified by their scheduling priority, so
+    /// system (real-time) and interactive tasks still land on big cores.
+    pub fn get_core_preference(&self, pid: ProcessId, base_priority: ProcessPriority) -> CorePreference {
+        let class = self.process_classifications.get(&pid)
+            .copied()
+            .unwrap_or(match base_priority {
+                ProcessPriority::System => ProcessPowerClass::Critical,
+                ProcessPriority::Interactive => ProcessPowerClass::Interactive,
+                ProcessPriority::Normal => ProcessPowerClass::Background,
+                ProcessPriority::Background => ProcessPowerClass::Batch,
+            });
+
+        match self.current_policy {
+            SchedulingPolicy::Performance => {
+                match class {
+                    ProcessPowerClass::Critical | ProcessPowerClass::Interactive => CorePreference::Big,
+                    _ => CorePreference::Any,
+                }
+            }
+            SchedulingPolicy::Interactive | SchedulingPolicy::Balanced => {
+                match class {
+                    ProcessPowerClass::Critical | ProcessPowerClass::Interactive => CorePreference::Big,
+                    _ => CorePreference::Little,
+                }
+            }
+            SchedulingPolicy::PowerSaver => {
+                match class {
+                    ProcessPowerClass::Critical => CorePreference::Big,
+                    ProcessPowerClass::Interactive if self.interactive_boost_active => CorePreference::Big,
+                    ProcessPowerClass::Interactive => CorePreference::Any,
+                    _ => CorePreference::Little,
+                }
+            }
+            SchedulingPolicy::Critical => {
+                match class {
+                    ProcessPowerClass::Critical => CorePreference::Any,
+                    _ => CorePreference::Little,
+                }
+            }
+        }
+    }
+
     /// Get time slice adjustment for power management
     pub fn get_time_slice_multiplier(&self, pid: ProcessId) -> f32 {
         let class = self.process_classifications.get(&pid)
@@ -266,25 +345,29 @@ impl PowerPolicyManager {
 
     /// Enable thermal throttling
     pub fn enable_thermal_throttling(&mut self) -> Result<(), PowerError> {
-        self.thermal_throttling_active = true;
-        
-        // Force power saver mode during thermal throttling
-        if self.current_state != PowerState::Critical {
-            self.set_power_state(PowerState::PowerSaver)?;
-        }
-        
-        Ok(())
+        self.set_thermal_limit(Some(PowerState::PowerSaver))
     }
 
     /// Disable thermal throttling
     pub fn disable_thermal_throttling(&mut self) -> Result<(), PowerError> {
-        self.thermal_throttling_active = false;
+        self.set_thermal_limit(None)
+    }
+
+    /// Hold the power state to `limit` or below, or lift the hold
+    pub fn set_thermal_limit(&mut self, limit: Option<PowerState>) -> Result<(), PowerError> {
+        if limit == self.thermal_limit {
+            return Ok(());
+        }
+        self.thermal_limit = limit;
         
-        // Restore normal power state based on battery
+        // Restore normal power state based on battery, within the limit
         let recommended_state = battery_monitor::get_recommended_power_state();
-        self.set_power_state(recommended_state)?;
-        
-        Ok(())
+        self.set_power_state(recommended_state)
+    }
+
+    /// Check if thermal throttling is active
+    pub fn is_thermal_throttling(&self) -> bool {
+        self.thermal_limit.is_some()
     }
 
     // Private methods
@@ -293,10 +376,28 @@ impl PowerPolicyManager {
         self.interactive_boost_active = true;
         self.boost_end_time = current_time + 500; // 500ms boost
         
+        // Make parked cores available for the interactive work
+        let _ = cpu_hotplug::handle_interactive_spike(current_time);
+        
         // Apply immediate scheduling changes
         let _ = self.apply_power_aware_scheduling();
     }
 
+    /// Throttle for the worst trip point the thermal zones are past
+    fn apply_thermal_trip(&mut self, trip: Option<TripKind>) -> Result<(), PowerError> {
+        let limit = match trip {
+            None => None,
+            Some(TripKind::Passive) => Some(PowerState::PowerSaver),
+            Some(TripKind::Hot) => Some(PowerState::Critical),
+            Some(TripKind::Critical) => {
+                log::error!("Thermal zone past its critical trip point, powering off");
+                crate::platform::power_off();
+                Some(PowerState::Critical)
+            }
+        };
+        self.set_thermal_limit(limit)
+    }
+
     fn apply_power_aware_scheduling(&self) -> Result<(), PowerError> {
         // In a real implementation, this would update the scheduler
         // with new priorities and time slice adjustments
@@ -333,6 +434,16 @@ impl PowerPolicyManager {
     }
 }
 
+/// How strongly a state saves power, for comparing them
+fn power_saving(state: PowerState) -> u8 {
+    match state {
+        PowerState::Performance => 0,
+        PowerState::Balanced => 1,
+        PowerState::PowerSaver => 2,
+        PowerState::Critical => 3,
+    }
+}
+
 /// Global power policy manager
 static POWER_POLICY: Mutex<Option<PowerPolicyManager>> = Mutex::new(None);
 
@@ -392,6 +503,18 @@ pub fn update(current_time: u64) -> Result<(), PowerError> {
     }
 }
 
+/// Run `update` from the boot CPU's timer tick
+///
+/// Skipped if the tick interrupted the policy manager itself; the next
+/// tick catches up.
+pub fn timer_tick(current_time: u64) {
+    if let Some(mut manager) = POWER_POLICY.try_lock() {
+        if let Some(manager) = manager.as_mut() {
+            let _ = manager.update(current_time);
+        }
+    }
+}
+
 /// Get power-aware priority for process
 pub fn get_power_aware_priority(pid: ProcessId, base_priority: ProcessPriority) -> ProcessPriority {
     if let Some(ref manager) = POWER_POLICY.lock().as_ref() {
@@ -401,6 +524,15 @@ pub fn get_power_aware_priority(pid: ProcessId, base_priority: ProcessPriority)
     }
 }
 
+/// Get preferred core class for process
+pub fn get_core_preference(pid: ProcessId, base_priority: ProcessPriority) -> CorePreference {
+    if let Some(ref manager) = POWER_POLICY.lock().as_ref() {
+        manager.get_core_preference(pid, base_priority)
+    } else {
+        CorePreference::Any
+    }
+}
+
 /// Get time slice multiplier for process
 pub fn get_time_slice_multiplier(pid: ProcessId) -> f32 {
     if let Some(ref manager) = POWER_POLICY.lock().as_ref() {
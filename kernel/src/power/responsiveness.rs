@@ -347,6 +347,28 @@ impl ResponsivenessOptimizer {
         }
     }
 
+    /// Check for an interactive load spike that needs extra CPU capacity
+    ///
+    /// A spike is an active interactive boost combined with either high
+    /// system load or touch input within the interactive window.
+    pub fn detect_interactive_spike(&self, current_time: u64) -> bool {
+        let boost_active = self.current_interactive_processes
+            .values()
+            .any(|&boost_end_time| current_time < boost_end_time);
+        if !boost_active {
+            return false;
+        }
+
+        let high_load = self.system_load_percent >= self.adaptive_scheduling_config.load_threshold_percent;
+        let recent_touch = self.touch_input_queue
+            .last()
+            .map_or(false, |&(_, timestamp)| {
+                current_time.saturating_sub(timestamp) < self.adaptive_scheduling_config.interactive_window_ms
+            });
+
+        high_load || recent_touch
+    }
+
     /// Get responsiveness statistics
     pub fn get_statistics(&self) -> ResponsivenessStats {
         let total_interactive_processes = self.current_interactive_processes.len();
@@ -482,6 +504,15 @@ pub fn notify_process_activity(pid: ProcessId, activity: ProcessActivity, curren
     }
 }
 
+/// Check for an interactive load spike
+pub fn detect_interactive_spike(current_time: u64) -> bool {
+    if let Some(ref optimizer) = RESPONSIVENESS_OPTIMIZER.lock().as_ref() {
+        optimizer.detect_interactive_spike(current_time)
+    } else {
+        false
+    }
+}
+
 /// Get responsiveness statistics
 pub fn get_statistics() -> Option<ResponsivenessStats> {
     if let Some(ref optimizer) = RESPONSIVENESS_OPTIMIZER.lock().as_ref() {
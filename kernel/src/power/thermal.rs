@@ -0,0 +1,212 @@
+//! Thermal Management
+//!
+//! A thermal zone is one of the temperature sensors the platform found,
+//! with its trip points. The power policy reads the zones on each update
+//! and acts on the worst trip point any of them is past:
+//!
+//! - passive: power saver mode, which puts the CPUs on the powersave
+//!   governor and parks cores
+//! - hot: critical mode as well, which throttles background and batch
+//!   processes hardest and keeps the rest on little cores
+//! - critical: the machine is powered off
+//!
+//! A zone only leaves a trip point once it has cooled `HYSTERESIS_MC`
+//! below it, so it does not flap around the threshold. The
+//! `thermal_info` system call reports the zones; the shell's `thermal`
+//! command shows them.
+
+use super::PowerError;
+use crate::platform::{ThermalSensor, TripKind, TripPoint};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// How far below a trip point a zone must cool to leave it
+pub const HYSTERESIS_MC: i32 = 3_000;
+
+/// Bytes of zone name `ThermalZoneInfo` carries
+pub const ZONE_NAME_LEN: usize = 8;
+
+/// `ThermalZoneInfo` flags: which values are known
+pub const ZONE_TEMPERATURE_VALID: u32 = 1 << 0;
+pub const ZONE_HAS_PASSIVE: u32 = 1 << 1;
+pub const ZONE_HAS_HOT: u32 = 1 << 2;
+pub const ZONE_HAS_CRITICAL: u32 = 1 << 3;
+
+/// A thermal zone, as reported by the `thermal_info` system call
+///
+/// The layout is shared with `kosh_posix::power`. Temperatures are in
+/// thousandths of a degree Celsius; those the flags do not mark known
+/// are 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+pub struct ThermalZoneInfo {
+    /// Sensor name, UTF-8 padded with NULs
+    pub name: [u8; ZONE_NAME_LEN],
+    pub flags: u32,
+    /// Trip point the zone is past: 0 for none, then passive, hot and
+    /// critical
+    pub tripped: u32,
+    pub temperature_mc: i32,
+    pub passive_mc: i32,
+    pub hot_mc: i32,
+    pub critical_mc: i32,
+}
+
+/// Trip point a zone at `temperature_mc` is past, given the one it was
+/// past before
+pub fn tripped_at(trips: &[TripPoint], temperature_mc: i32, previous: Option<TripKind>) -> Option<TripKind> {
+    trips.iter()
+        .filter(|trip| {
+            let threshold = if previous.is_some_and(|kind| kind >= trip.kind) {
+                trip.millicelsius - HYSTERESIS_MC
+            } else {
+                trip.millicelsius
+            };
+            temperature_mc >= threshold
+        })
+        .map(|trip| trip.kind)
+        .max()
+}
+
+/// Number `ThermalZoneInfo::tripped` uses for a trip point
+fn trip_raw(kind: Option<TripKind>) -> u32 {
+    match kind {
+        None => 0,
+        Some(TripKind::Passive) => 1,
+        Some(TripKind::Hot) => 2,
+        Some(TripKind::Critical) => 3,
+    }
+}
+
+struct ThermalZone {
+    sensor: ThermalSensor,
+    temperature_mc: Option<i32>,
+    tripped: Option<TripKind>,
+}
+
+impl ThermalZone {
+    fn info(&self) -> ThermalZoneInfo {
+        let mut info = ThermalZoneInfo { tripped: trip_raw(self.tripped), ..ThermalZoneInfo::default() };
+        let name = self.sensor.name.as_bytes();
+        let length = name.len().min(ZONE_NAME_LEN);
+        info.name[..length].copy_from_slice(&name[..length]);
+        if let Some(temperature_mc) = self.temperature_mc {
+            info.flags |= ZONE_TEMPERATURE_VALID;
+            info.temperature_mc = temperature_mc;
+        }
+        for trip in &self.sensor.trips {
+            let (flag, slot) = match trip.kind {
+                TripKind::Passive => (ZONE_HAS_PASSIVE, &mut info.passive_mc),
+                TripKind::Hot => (ZONE_HAS_HOT, &mut info.hot_mc),
+                TripKind::Critical => (ZONE_HAS_CRITICAL, &mut info.critical_mc),
+            };
+            info.flags |= flag;
+            *slot = trip.millicelsius;
+        }
+        info
+    }
+}
+
+/// The thermal zones and the trip points they are past
+pub struct ThermalManager {
+    zones: Vec<ThermalZone>,
+}
+
+impl ThermalManager {
+    /// Take the sensors the platform offers as zones
+    pub fn new() -> Self {
+        Self {
+            zones: crate::platform::probe_thermal_sensors().into_iter()
+                .map(|sensor| ThermalZone { sensor, temperature_mc: None, tripped: None })
+                .collect(),
+        }
+    }
+
+    /// Read every zone; returns the worst trip point any of them is past
+    pub fn update(&mut self) -> Option<TripKind> {
+        for (index, zone) in self.zones.iter_mut().enumerate() {
+            zone.temperature_mc = crate::platform::read_temperature(index);
+            let Some(temperature_mc) = zone.temperature_mc else {
+                // Keep what the zone last said rather than guess
+                continue;
+            };
+            let tripped = tripped_at(&zone.sensor.trips, temperature_mc, zone.tripped);
+            if tripped != zone.tripped {
+                log::warn!("Thermal zone {} at {} °C, trip point now {:?}", zone.sensor.name, temperature_mc / 1000, tripped);
+                zone.tripped = tripped;
+            }
+        }
+        self.zones.iter().filter_map(|zone| zone.tripped).max()
+    }
+
+    /// Zones as `SYS_THERMAL_INFO` reports them
+    pub fn info(&self) -> Vec<ThermalZoneInfo> {
+        self.zones.iter().map(ThermalZone::info).collect()
+    }
+}
+
+/// Global thermal manager instance
+static THERMAL: Mutex<Option<ThermalManager>> = Mutex::new(None);
+
+/// Initialize thermal management
+///
+/// Fails with `NotSupported` if the platform has no sensor to read.
+pub fn init() -> Result<(), PowerError> {
+    let mut manager = ThermalManager::new();
+    if manager.zones.is_empty() {
+        return Err(PowerError::NotSupported);
+    }
+    manager.update();
+    *THERMAL.lock() = Some(manager);
+    Ok(())
+}
+
+/// Read the zones; returns the worst trip point any of them is past
+pub fn update() -> Option<TripKind> {
+    THERMAL.lock().as_mut().and_then(ThermalManager::update)
+}
+
+/// The zones; none before `init`
+pub fn info() -> Vec<ThermalZoneInfo> {
+    THERMAL.lock().as_ref().map(ThermalManager::info).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_trip_points_have_hysteresis() {
+        let trips = [
+            TripPoint { kind: TripKind::Passive, millicelsius: 80_000 },
+            TripPoint { kind: TripKind::Hot, millicelsius: 90_000 },
+            TripPoint { kind: TripKind::Critical, millicelsius: 100_000 },
+        ];
+        assert_eq!(tripped_at(&trips, 79_000, None), None);
+        assert_eq!(tripped_at(&trips, 80_000, None), Some(TripKind::Passive));
+        assert_eq!(tripped_at(&trips, 95_000, None), Some(TripKind::Hot));
+        assert_eq!(tripped_at(&trips, 101_000, Some(TripKind::Passive)), Some(TripKind::Critical));
+
+        // Cooling leaves a trip point only well below it
+        assert_eq!(tripped_at(&trips, 88_000, Some(TripKind::Hot)), Some(TripKind::Hot));
+        assert_eq!(tripped_at(&trips, 86_000, Some(TripKind::Hot)), Some(TripKind::Passive));
+        assert_eq!(tripped_at(&trips, 78_000, Some(TripKind::Passive)), Some(TripKind::Passive));
+        assert_eq!(tripped_at(&trips, 76_000, Some(TripKind::Passive)), None);
+    }
+
+    #[test_case]
+    fn test_zone_info_layout() {
+        let zone = ThermalZone {
+            sensor: ThermalSensor {
+                name: "cpu".into(),
+                trips: alloc::vec![TripPoint { kind: TripKind::Hot, millicelsius: 95_000 }],
+            },
+            temperature_mc: Some(97_000),
+            tripped: Some(TripKind::Hot),
+        };
+        let info = zone.info();
+        assert_eq!(&info.name[..4], b"cpu\0");
+        assert_eq!(info.flags, ZONE_TEMPERATURE_VALID | ZONE_HAS_HOT);
+        assert_eq!((info.tripped, info.temperature_mc, info.hot_mc), (2, 97_000, 95_000));
+    }
+}
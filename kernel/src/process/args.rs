@@ -0,0 +1,107 @@
+//! Program arguments and environment
+//!
+//! `exec` records the argument and environment strings it was given with
+//! the process, and a forked child starts with a copy of its parent's. The
+//! kernel never looks inside the environment: a process fetches the whole
+//! block with `SYS_GETARGS` and searches it itself, so there is no
+//! `getenv` system call.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Most argument and environment strings a process may hold in total
+pub const MAX_ARG_STRINGS: usize = 1024;
+
+/// Largest encoded block, the strings plus their terminators and header
+pub const MAX_ARGS_SIZE: usize = 64 * 1024;
+
+/// Size of the block header: the argument and environment counts
+const HEADER_SIZE: usize = 8;
+
+/// Argument list errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgsError {
+    /// More strings, or more bytes, than the limits allow
+    TooLarge,
+    /// A string contains a NUL byte
+    InvalidString,
+}
+
+/// Argument vector and environment of a process
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessArgs {
+    pub argv: Vec<String>,
+    /// `NAME=value` strings
+    pub envp: Vec<String>,
+}
+
+impl ProcessArgs {
+    /// Check a new argument list against the limits
+    pub fn new(argv: Vec<String>, envp: Vec<String>) -> Result<Self, ArgsError> {
+        let args = Self { argv, envp };
+        if args.strings().any(|string| string.contains('\0')) {
+            return Err(ArgsError::InvalidString);
+        }
+        if args.argv.len() + args.envp.len() > MAX_ARG_STRINGS || args.encoded_len() > MAX_ARGS_SIZE {
+            return Err(ArgsError::TooLarge);
+        }
+        Ok(args)
+    }
+
+    /// Size of the block `encode` produces
+    pub fn encoded_len(&self) -> usize {
+        HEADER_SIZE + self.strings().map(|string| string.len() + 1).sum::<usize>()
+    }
+
+    /// Block handed out by `SYS_GETARGS`
+    ///
+    /// The argument and environment counts as little-endian `u32`s, then
+    /// every string NUL-terminated, arguments first.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut block = Vec::with_capacity(self.encoded_len());
+        block.extend_from_slice(&(self.argv.len() as u32).to_le_bytes());
+        block.extend_from_slice(&(self.envp.len() as u32).to_le_bytes());
+        for string in self.strings() {
+            block.extend_from_slice(string.as_bytes());
+            block.push(0);
+        }
+        block
+    }
+
+    fn strings(&self) -> impl Iterator<Item = &String> {
+        self.argv.iter().chain(self.envp.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test_case]
+    fn test_args_encode() {
+        let args = ProcessArgs::new(
+            vec![String::from("/bin/app"), String::from("-v")],
+            vec![String::from("HOME=/")],
+        ).unwrap();
+        let block = args.encode();
+        assert_eq!(block.len(), args.encoded_len());
+        assert_eq!(&block[..8], &[2, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(&block[8..], b"/bin/app\0-v\0HOME=/\0");
+
+        assert_eq!(ProcessArgs::default().encode(), vec![0u8; 8]);
+    }
+
+    #[test_case]
+    fn test_args_limits() {
+        let nul = ProcessArgs::new(vec![String::from("a\0b")], Vec::new());
+        assert_eq!(nul, Err(ArgsError::InvalidString));
+
+        let many = vec![String::new(); MAX_ARG_STRINGS + 1];
+        assert_eq!(ProcessArgs::new(many, Vec::new()), Err(ArgsError::TooLarge));
+
+        let mut long = String::new();
+        long.extend(core::iter::repeat('x').take(MAX_ARGS_SIZE));
+        assert_eq!(ProcessArgs::new(Vec::new(), vec![long]), Err(ArgsError::TooLarge));
+    }
+}
@@ -1,5 +1,4 @@
 use core::arch::asm;
-use crate::{serial_println, println};
 
 /// CPU context for x86-64 architecture
 /// This structure represents the saved state of a process
@@ -120,17 +119,17 @@ impl CpuContext {
     
     /// Print context information for debugging
     pub fn print_debug(&self) {
-        serial_println!("CPU Context:");
-        serial_println!("  RAX: 0x{:016x}  RBX: 0x{:016x}  RCX: 0x{:016x}  RDX: 0x{:016x}", 
+        log::debug!("CPU Context:");
+        log::debug!("  RAX: 0x{:016x}  RBX: 0x{:016x}  RCX: 0x{:016x}  RDX: 0x{:016x}", 
                        self.rax, self.rbx, self.rcx, self.rdx);
-        serial_println!("  RSI: 0x{:016x}  RDI: 0x{:016x}  RBP: 0x{:016x}  RSP: 0x{:016x}", 
+        log::debug!("  RSI: 0x{:016x}  RDI: 0x{:016x}  RBP: 0x{:016x}  RSP: 0x{:016x}", 
                        self.rsi, self.rdi, self.rbp, self.rsp);
-        serial_println!("  R8:  0x{:016x}  R9:  0x{:016x}  R10: 0x{:016x}  R11: 0x{:016x}", 
+        log::debug!("  R8:  0x{:016x}  R9:  0x{:016x}  R10: 0x{:016x}  R11: 0x{:016x}", 
                        self.r8, self.r9, self.r10, self.r11);
-        serial_println!("  R12: 0x{:016x}  R13: 0x{:016x}  R14: 0x{:016x}  R15: 0x{:016x}", 
+        log::debug!("  R12: 0x{:016x}  R13: 0x{:016x}  R14: 0x{:016x}  R15: 0x{:016x}", 
                        self.r12, self.r13, self.r14, self.r15);
-        serial_println!("  RIP: 0x{:016x}  RFLAGS: 0x{:016x}", self.rip, self.rflags);
-        serial_println!("  CS: 0x{:04x}  DS: 0x{:04x}  ES: 0x{:04x}  FS: 0x{:04x}  GS: 0x{:04x}  SS: 0x{:04x}", 
+        log::debug!("  RIP: 0x{:016x}  RFLAGS: 0x{:016x}", self.rip, self.rflags);
+        log::debug!("  CS: 0x{:04x}  DS: 0x{:04x}  ES: 0x{:04x}  FS: 0x{:04x}  GS: 0x{:04x}  SS: 0x{:04x}", 
                        self.cs, self.ds, self.es, self.fs, self.gs, self.ss);
     }
 }
@@ -141,6 +140,94 @@ impl Default for CpuContext {
     }
 }
 
+/// Registers saved on the stack by an interrupt entry stub
+///
+/// The stub pushes the general purpose registers on top of the frame the
+/// CPU pushed, so fields are listed from the lowest address up.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct TrapFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    
+    // Pushed by the CPU
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl TrapFrame {
+    /// Whether the trap interrupted user mode code
+    pub fn from_user_mode(&self) -> bool {
+        self.cs & 0x3 == 0x3
+    }
+}
+
+impl CpuContext {
+    /// Record the state interrupted by a trap
+    pub fn save_from_frame(&mut self, frame: &TrapFrame) {
+        self.rax = frame.rax;
+        self.rbx = frame.rbx;
+        self.rcx = frame.rcx;
+        self.rdx = frame.rdx;
+        self.rsi = frame.rsi;
+        self.rdi = frame.rdi;
+        self.rbp = frame.rbp;
+        self.rsp = frame.rsp;
+        self.r8 = frame.r8;
+        self.r9 = frame.r9;
+        self.r10 = frame.r10;
+        self.r11 = frame.r11;
+        self.r12 = frame.r12;
+        self.r13 = frame.r13;
+        self.r14 = frame.r14;
+        self.r15 = frame.r15;
+        self.rip = frame.rip;
+        self.rflags = frame.rflags;
+        self.cs = frame.cs as u16;
+        self.ss = frame.ss as u16;
+    }
+    
+    /// Make the return from a trap resume this context instead
+    pub fn load_into_frame(&self, frame: &mut TrapFrame) {
+        frame.rax = self.rax;
+        frame.rbx = self.rbx;
+        frame.rcx = self.rcx;
+        frame.rdx = self.rdx;
+        frame.rsi = self.rsi;
+        frame.rdi = self.rdi;
+        frame.rbp = self.rbp;
+        frame.rsp = self.rsp;
+        frame.r8 = self.r8;
+        frame.r9 = self.r9;
+        frame.r10 = self.r10;
+        frame.r11 = self.r11;
+        frame.r12 = self.r12;
+        frame.r13 = self.r13;
+        frame.r14 = self.r14;
+        frame.r15 = self.r15;
+        frame.rip = self.rip;
+        frame.rflags = self.rflags;
+        frame.cs = self.cs as u64;
+        frame.ss = self.ss as u64;
+    }
+}
+
 /// Context switching functionality
 pub struct ContextSwitcher;
 
@@ -154,7 +241,7 @@ impl ContextSwitcher {
     /// - The new_context contains valid register values
     /// - The new stack pointer is valid and properly aligned
     pub unsafe fn switch_context(old_context: *mut CpuContext, new_context: &CpuContext) {
-        serial_println!("Performing context switch from 0x{:p} to RIP 0x{:016x}", 
+        log::debug!("Performing context switch from 0x{:p} to RIP 0x{:016x}", 
                        old_context, new_context.rip);
         
         // For now, this is a simplified implementation that just copies the context
@@ -284,13 +371,13 @@ impl ContextSwitcher {
 
 /// Test function for context switching (for debugging)
 pub fn test_context_switching() {
-    serial_println!("Testing context switching functionality...");
+    log::debug!("Testing context switching functionality...");
     
     // Create test contexts
     let mut context1 = CpuContext::new_kernel_thread(test_function1 as u64, 0x100000);
     let mut context2 = CpuContext::new_kernel_thread(test_function2 as u64, 0x200000);
     
-    serial_println!("Created test contexts:");
+    log::debug!("Created test contexts:");
     context1.print_debug();
     context2.print_debug();
     
@@ -301,18 +388,17 @@ pub fn test_context_switching() {
     assert_eq!(context1.rsp, 0x100000);
     assert_eq!(context2.rsp, 0x200000);
     
-    serial_println!("Context switching test completed successfully");
-    println!("Context switching: Test completed");
+    log::info!("Context switching test completed successfully");
 }
 
 /// Test function 1 for context switching
 extern "C" fn test_function1() {
-    serial_println!("Executing test function 1");
+    log::debug!("Executing test function 1");
 }
 
 /// Test function 2 for context switching
 extern "C" fn test_function2() {
-    serial_println!("Executing test function 2");
+    log::debug!("Executing test function 2");
 }
 
 #[cfg(test)]
@@ -364,4 +450,23 @@ mod tests {
         assert_eq!(context.stack_pointer(), 0x6000);
         assert_eq!(context.rbp, 0x6000); // Base pointer should also be set
     }
+    
+    #[test_case]
+    fn test_trap_frame_round_trip() {
+        let user = CpuContext::new_user_process(0x40_0000, 0x7fff_0000);
+        let mut frame = TrapFrame::default();
+        user.load_into_frame(&mut frame);
+        assert!(frame.from_user_mode());
+        assert_eq!(frame.rip, 0x40_0000);
+        assert_eq!(frame.rsp, 0x7fff_0000);
+        
+        frame.rax = 7;
+        frame.rip += 2;
+        let mut saved = CpuContext::new();
+        saved.save_from_frame(&frame);
+        assert_eq!(saved.rax, 7);
+        assert_eq!(saved.rip, 0x40_0002);
+        assert_eq!(saved.cs, 0x1B);
+        assert_eq!(saved.ss, 0x23);
+    }
 }
\ No newline at end of file
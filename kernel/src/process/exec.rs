@@ -0,0 +1,528 @@
+//! Program images for `exec`
+//!
+//! An image is a statically linked ELF64 executable for x86_64, linked
+//! either at fixed addresses or position independent. The latter is loaded
+//! at `PIE_BASE` with its relative relocations applied; an image that asks
+//! for a dynamic linker or any other kind of relocation is refused. Every
+//! loadable segment becomes an image mapping of `mmap`, below the heap.
+//!
+//! The file is read from the file system service one transfer at a time.
+//! As with every call the service serves, the caller is blocked while a
+//! request is out and repeats `exec` once woken, which continues the read
+//! kept here. Nothing of the caller changes until the whole image is read
+//! and checked.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+use kosh_service::{FileSystemRequest, ServiceData, ServiceStatus};
+use kosh_types::OpenFlags;
+use crate::ipc::fs_client::{self, FsCall, MAX_FS_TRANSFER};
+use crate::memory::{PAGE_SIZE, align_up};
+use crate::memory::mmap::{self, HEAP_BASE, MmapError};
+use crate::memory::vmm::{MemoryProtection, VirtualAddress};
+use super::ProcessId;
+
+/// Largest image file `exec` reads
+pub const MAX_IMAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Where a position-independent image is loaded
+pub const PIE_BASE: usize = 0x0000_0000_0040_0000;
+
+/// Most program headers an image may have
+const MAX_PROGRAM_HEADERS: usize = 64;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELF_CLASS_64: u8 = 2;
+const ELF_DATA_LITTLE_ENDIAN: u8 = 1;
+const ELF_HEADER_SIZE: usize = 64;
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+const EM_X86_64: u16 = 62;
+
+const PROGRAM_HEADER_SIZE: usize = 56;
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+
+const DYNAMIC_ENTRY_SIZE: usize = 16;
+const DT_NULL: u64 = 0;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+const DT_REL: u64 = 17;
+const DT_JMPREL: u64 = 23;
+
+const RELA_SIZE: usize = 24;
+const R_X86_64_RELATIVE: u32 = 8;
+
+/// Program image errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecError {
+    /// No file at the path
+    NotFound,
+    /// The service refused to open the file
+    PermissionDenied,
+    /// The file system service could not be reached
+    ServiceUnavailable,
+    /// The service failed to read the file
+    ReadFailed,
+    /// The file is larger than `MAX_IMAGE_SIZE`
+    TooLarge,
+    /// Not an image this loader can run
+    NotExecutable,
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::NotFound => write!(f, "Image not found"),
+            ExecError::PermissionDenied => write!(f, "Permission denied"),
+            ExecError::ServiceUnavailable => write!(f, "File system service unavailable"),
+            ExecError::ReadFailed => write!(f, "Failed to read image"),
+            ExecError::TooLarge => write!(f, "Image too large"),
+            ExecError::NotExecutable => write!(f, "Not an executable image"),
+        }
+    }
+}
+
+/// A loadable segment, at the address it is mapped at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub address: usize,
+    /// Bytes in memory; those past `file_size` are zero
+    pub memory_size: usize,
+    pub file_offset: usize,
+    pub file_size: usize,
+    pub protection: MemoryProtection,
+}
+
+/// A checked image, ready to be mapped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub entry: usize,
+    /// By address, never sharing a page
+    pub segments: Vec<Segment>,
+}
+
+impl Image {
+    /// Where the file bytes at the image address `address` are, if the
+    /// `length` bytes from there lie in the file part of one segment
+    fn file_offset(&self, address: usize, length: usize) -> Option<usize> {
+        let end = address.checked_add(length)?;
+        self.segments.iter()
+            .find(|segment| address >= segment.address && end <= segment.address + segment.file_size)
+            .map(|segment| segment.file_offset + (address - segment.address))
+    }
+}
+
+fn field<const N: usize>(bytes: &[u8], offset: usize) -> Result<[u8; N], ExecError> {
+    let end = offset.checked_add(N).ok_or(ExecError::NotExecutable)?;
+    bytes.get(offset..end)
+        .and_then(|field| field.try_into().ok())
+        .ok_or(ExecError::NotExecutable)
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16, ExecError> {
+    field(bytes, offset).map(u16::from_le_bytes)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, ExecError> {
+    field(bytes, offset).map(u32::from_le_bytes)
+}
+
+fn usize_at(bytes: &[u8], offset: usize) -> Result<usize, ExecError> {
+    field(bytes, offset).map(u64::from_le_bytes).map(|value| value as usize)
+}
+
+/// Check `bytes` as an image and apply its relocations to them
+pub fn parse(bytes: &mut [u8]) -> Result<Image, ExecError> {
+    let header = bytes.get(..ELF_HEADER_SIZE).ok_or(ExecError::NotExecutable)?;
+    if &header[..4] != ELF_MAGIC || header[4] != ELF_CLASS_64 || header[5] != ELF_DATA_LITTLE_ENDIAN
+        || u16_at(header, 18)? != EM_X86_64 {
+        return Err(ExecError::NotExecutable);
+    }
+    let bias = match u16_at(header, 16)? {
+        ET_EXEC => 0,
+        ET_DYN => PIE_BASE,
+        _ => return Err(ExecError::NotExecutable),
+    };
+    let entry = usize_at(header, 24)?;
+    let header_table = usize_at(header, 32)?;
+    let header_count = u16_at(header, 56)? as usize;
+    if u16_at(header, 54)? as usize != PROGRAM_HEADER_SIZE || header_count == 0 || header_count > MAX_PROGRAM_HEADERS {
+        return Err(ExecError::NotExecutable);
+    }
+
+    let mut segments = Vec::new();
+    let mut dynamic = None;
+    for index in 0..header_count {
+        let offset = header_table.checked_add(index * PROGRAM_HEADER_SIZE).ok_or(ExecError::NotExecutable)?;
+        let program_header: [u8; PROGRAM_HEADER_SIZE] = field(bytes, offset)?;
+        let file_offset = usize_at(&program_header, 8)?;
+        let file_size = usize_at(&program_header, 32)?;
+        match u32_at(&program_header, 0)? {
+            // Needs a dynamic linker
+            PT_INTERP => return Err(ExecError::NotExecutable),
+            PT_DYNAMIC => dynamic = Some((file_offset, file_size)),
+            PT_LOAD => {
+                let flags = u32_at(&program_header, 4)?;
+                let address = usize_at(&program_header, 16)?.checked_add(bias).ok_or(ExecError::NotExecutable)?;
+                let memory_size = usize_at(&program_header, 40)?;
+                if memory_size == 0 {
+                    continue;
+                }
+                segments.push(Segment {
+                    address,
+                    memory_size,
+                    file_offset,
+                    file_size,
+                    // Pages cannot be executable or writable without being readable
+                    protection: MemoryProtection {
+                        readable: true,
+                        writable: flags & PF_W != 0,
+                        executable: flags & PF_X != 0,
+                        user_accessible: true,
+                        uncached: false,
+                    },
+                });
+            }
+            _ => {}
+        }
+    }
+
+    segments.sort_by_key(|segment| segment.address);
+    let mut previous_end = PAGE_SIZE;
+    for segment in &segments {
+        let file_end = segment.file_offset.checked_add(segment.file_size).ok_or(ExecError::NotExecutable)?;
+        let end = segment.address.checked_add(segment.memory_size).ok_or(ExecError::NotExecutable)?;
+        // Segments are mapped page by page below the heap, and the first
+        // page is never mapped
+        let start_page = segment.address & !(PAGE_SIZE - 1);
+        if segment.file_size > segment.memory_size || file_end > bytes.len()
+            || start_page < previous_end || end > HEAP_BASE {
+            return Err(ExecError::NotExecutable);
+        }
+        previous_end = align_up(end);
+    }
+
+    let image = Image { entry: entry.checked_add(bias).ok_or(ExecError::NotExecutable)?, segments };
+    let entry_executable = image.segments.iter().any(|segment| segment.protection.executable
+        && image.entry >= segment.address && image.entry < segment.address + segment.memory_size);
+    if !entry_executable {
+        return Err(ExecError::NotExecutable);
+    }
+    if let Some((offset, size)) = dynamic {
+        relocate(bytes, &image, offset, size, bias)?;
+    }
+    Ok(image)
+}
+
+/// Apply the relocations the dynamic table at `offset` lists, for an image
+/// loaded `bias` bytes above its link address
+///
+/// Only relative relocations are supported, which is all a statically
+/// linked position-independent image has.
+fn relocate(bytes: &mut [u8], image: &Image, offset: usize, size: usize, bias: usize) -> Result<(), ExecError> {
+    let mut table = None;
+    let mut table_size = 0;
+    let mut entry_size = RELA_SIZE;
+    for index in 0..size / DYNAMIC_ENTRY_SIZE {
+        let entry = offset.checked_add(index * DYNAMIC_ENTRY_SIZE).ok_or(ExecError::NotExecutable)?;
+        let entry: [u8; DYNAMIC_ENTRY_SIZE] = field(bytes, entry)?;
+        let value = usize_at(&entry, 8)?;
+        match usize_at(&entry, 0)? as u64 {
+            DT_NULL => break,
+            DT_RELA => table = Some(value),
+            DT_RELASZ => table_size = value,
+            DT_RELAENT => entry_size = value,
+            DT_REL | DT_JMPREL => return Err(ExecError::NotExecutable),
+            _ => {}
+        }
+    }
+
+    let Some(table) = table else {
+        return Ok(());
+    };
+    if entry_size != RELA_SIZE {
+        return Err(ExecError::NotExecutable);
+    }
+    let table_address = table.checked_add(bias).ok_or(ExecError::NotExecutable)?;
+    let table = image.file_offset(table_address, table_size).ok_or(ExecError::NotExecutable)?;
+    for index in 0..table_size / RELA_SIZE {
+        let relocation = table + index * RELA_SIZE;
+        let target = usize_at(bytes, relocation)?;
+        let kind = u32_at(bytes, relocation + 8)?;
+        let addend = usize_at(bytes, relocation + 16)?;
+        if kind != R_X86_64_RELATIVE {
+            return Err(ExecError::NotExecutable);
+        }
+        let target_address = target.checked_add(bias).ok_or(ExecError::NotExecutable)?;
+        let target = image.file_offset(target_address, 8).ok_or(ExecError::NotExecutable)?;
+        let value = bias.wrapping_add(addend) as u64;
+        bytes[target..target + 8].copy_from_slice(&value.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Map the segments of `image`, read as `bytes`, into the address space
+/// of `pid`, whose previous image is gone
+pub fn map_segments(pid: ProcessId, image: &Image, bytes: &[u8]) -> Result<(), MmapError> {
+    for segment in &image.segments {
+        let data = &bytes[segment.file_offset..segment.file_offset + segment.file_size];
+        mmap::map_image_segment(pid, VirtualAddress::new(segment.address), segment.memory_size, data,
+                                segment.protection)?;
+    }
+    Ok(())
+}
+
+/// An image file being read for a process
+struct PendingRead {
+    path: String,
+    /// The service holding the file and its descriptor there, once open
+    file: Option<(ProcessId, u32)>,
+    image: Vec<u8>,
+}
+
+static READS: Mutex<BTreeMap<ProcessId, PendingRead>> = Mutex::new(BTreeMap::new());
+
+fn close(file: Option<(ProcessId, u32)>) {
+    if let Some((service, fd)) = file {
+        let _ = fs_client::notify(service, FileSystemRequest::Close { fd });
+    }
+}
+
+/// Forward `request` for `pid`, returning the service and the data it
+/// answered with, or `None` while the caller waits for the answer
+fn call(pid: ProcessId, request: FileSystemRequest) -> Result<Option<(ProcessId, ServiceData)>, ExecError> {
+    match fs_client::call(pid, request) {
+        Ok(FsCall::Complete { service, response }) => match response.status {
+            ServiceStatus::Success => Ok(Some((service, response.data))),
+            ServiceStatus::NotFound => Err(ExecError::NotFound),
+            ServiceStatus::PermissionDenied => Err(ExecError::PermissionDenied),
+            ServiceStatus::ServiceUnavailable => Err(ExecError::ServiceUnavailable),
+            _ => Err(ExecError::ReadFailed),
+        },
+        Ok(FsCall::Pending) => Ok(None),
+        Err(_) => Err(ExecError::ServiceUnavailable),
+    }
+}
+
+/// Read the image file at `path` for `pid`
+///
+/// Returns `None` while a request is with the service; `pid` is then
+/// blocked, and repeating the call once woken continues the read.
+pub fn read_image(pid: ProcessId, path: &str) -> Result<Option<Vec<u8>>, ExecError> {
+    let mut read = match READS.lock().remove(&pid) {
+        Some(read) if read.path == path => read,
+        stale => {
+            // An exec the process gave up on, e.g. after being signalled
+            if let Some(stale) = stale {
+                close(stale.file);
+            }
+            PendingRead { path: String::from(path), file: None, image: Vec::new() }
+        }
+    };
+
+    let remote_fd = match read.file {
+        Some((_, remote_fd)) => remote_fd,
+        None => {
+            let request = FileSystemRequest::Open { path: String::from(path), flags: OpenFlags::READ_ONLY.bits() };
+            let (service, data) = match call(pid, request)? {
+                Some(answer) => answer,
+                None => {
+                    READS.lock().insert(pid, read);
+                    return Ok(None);
+                }
+            };
+            let remote_fd = match data {
+                ServiceData::Binary(bytes) if bytes.len() == 4 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                _ => return Err(ExecError::ReadFailed),
+            };
+            read.file = Some((service, remote_fd));
+            remote_fd
+        }
+    };
+
+    loop {
+        let request = FileSystemRequest::ReadAt { fd: remote_fd, offset: read.image.len() as u64, size: MAX_FS_TRANSFER };
+        let data = match call(pid, request) {
+            Ok(Some((_, data))) => data,
+            Ok(None) => {
+                READS.lock().insert(pid, read);
+                return Ok(None);
+            }
+            Err(error) => {
+                close(read.file);
+                return Err(error);
+            }
+        };
+        let bytes = match data {
+            ServiceData::Binary(bytes) => bytes,
+            // The service answers a read at the end with no data
+            ServiceData::Empty => Vec::new(),
+            _ => {
+                close(read.file);
+                return Err(ExecError::ReadFailed);
+            }
+        };
+        let count = bytes.len().min(MAX_FS_TRANSFER);
+        if read.image.len() + count > MAX_IMAGE_SIZE {
+            close(read.file);
+            return Err(ExecError::TooLarge);
+        }
+        read.image.extend_from_slice(&bytes[..count]);
+
+        // A short read is the end of the file
+        if count < MAX_FS_TRANSFER {
+            close(read.file);
+            return Ok(Some(read.image));
+        }
+    }
+}
+
+/// Drop the image read of a terminating process, closing its file
+pub fn release_process(pid: ProcessId) {
+    let read = READS.lock().remove(&pid);
+    if let Some(read) = read {
+        close(read.file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// Where the test images keep their segment contents
+    const BODY: usize = 0x200;
+
+    struct ProgramHeader {
+        kind: u32,
+        flags: u32,
+        offset: usize,
+        address: usize,
+        file_size: usize,
+        memory_size: usize,
+    }
+
+    fn load(flags: u32, offset: usize, address: usize, file_size: usize, memory_size: usize) -> ProgramHeader {
+        ProgramHeader { kind: PT_LOAD, flags, offset, address, file_size, memory_size }
+    }
+
+    fn elf(kind: u16, entry: usize, headers: &[ProgramHeader], body: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0u8; BODY];
+        bytes[..4].copy_from_slice(ELF_MAGIC);
+        bytes[4] = ELF_CLASS_64;
+        bytes[5] = ELF_DATA_LITTLE_ENDIAN;
+        bytes[16..18].copy_from_slice(&kind.to_le_bytes());
+        bytes[18..20].copy_from_slice(&EM_X86_64.to_le_bytes());
+        bytes[24..32].copy_from_slice(&(entry as u64).to_le_bytes());
+        bytes[32..40].copy_from_slice(&(ELF_HEADER_SIZE as u64).to_le_bytes());
+        bytes[54..56].copy_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+        bytes[56..58].copy_from_slice(&(headers.len() as u16).to_le_bytes());
+        for (index, header) in headers.iter().enumerate() {
+            let at = ELF_HEADER_SIZE + index * PROGRAM_HEADER_SIZE;
+            bytes[at..at + 4].copy_from_slice(&header.kind.to_le_bytes());
+            bytes[at + 4..at + 8].copy_from_slice(&header.flags.to_le_bytes());
+            bytes[at + 8..at + 16].copy_from_slice(&(header.offset as u64).to_le_bytes());
+            bytes[at + 16..at + 24].copy_from_slice(&(header.address as u64).to_le_bytes());
+            bytes[at + 32..at + 40].copy_from_slice(&(header.file_size as u64).to_le_bytes());
+            bytes[at + 40..at + 48].copy_from_slice(&(header.memory_size as u64).to_le_bytes());
+        }
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    /// Text at 0x40_0000 and data with a bss on the next pages
+    fn static_image() -> Vec<u8> {
+        let headers = [
+            load(PF_X | 4, BODY, 0x40_0000, 16, 16),
+            load(PF_W | 4, BODY + 16, 0x40_1000, 8, 0x2000),
+        ];
+        elf(ET_EXEC, 0x40_0004, &headers, &[0x90; 24])
+    }
+
+    #[test_case]
+    fn test_static_image_segments() {
+        let mut bytes = static_image();
+        let image = parse(&mut bytes).expect("image");
+        assert_eq!(image.entry, 0x40_0004);
+        assert_eq!(image.segments.len(), 2);
+
+        let text = image.segments[0];
+        assert_eq!((text.address, text.file_offset, text.file_size, text.memory_size), (0x40_0000, BODY, 16, 16));
+        assert!(text.protection.executable && !text.protection.writable && text.protection.user_accessible);
+        let data = image.segments[1];
+        assert_eq!((data.address, data.file_offset, data.file_size, data.memory_size), (0x40_1000, BODY + 16, 8, 0x2000));
+        assert!(data.protection.writable && !data.protection.executable);
+    }
+
+    #[test_case]
+    fn test_invalid_images_are_refused() {
+        let refused = |mut bytes: Vec<u8>| assert_eq!(parse(&mut bytes), Err(ExecError::NotExecutable));
+
+        refused(Vec::new());
+        let mut bytes = static_image();
+        bytes[0] = 0;
+        refused(bytes);
+        let mut bytes = static_image();
+        bytes[4] = 1;
+        refused(bytes);
+        let mut bytes = static_image();
+        bytes[18] = 3;
+        refused(bytes);
+        // Truncated inside the program headers
+        refused(static_image()[..ELF_HEADER_SIZE + 8].to_vec());
+
+        let text = || load(PF_X | 4, BODY, 0x40_0000, 16, 16);
+        let interpreter = ProgramHeader { kind: PT_INTERP, flags: 4, offset: BODY, address: 0, file_size: 1, memory_size: 1 };
+        refused(elf(ET_EXEC, 0x40_0000, &[text(), interpreter], &[0; 16]));
+        // More file than memory, or past the end of the file
+        refused(elf(ET_EXEC, 0x40_0000, &[load(PF_X | 4, BODY, 0x40_0000, 16, 8)], &[0; 16]));
+        refused(elf(ET_EXEC, 0x40_0000, &[load(PF_X | 4, BODY, 0x40_0000, 32, 32)], &[0; 16]));
+        // In the first page, in the heap, or sharing a page
+        refused(elf(ET_EXEC, 0x10, &[load(PF_X | 4, BODY, 0, 16, 16)], &[0; 16]));
+        refused(elf(ET_EXEC, HEAP_BASE, &[load(PF_X | 4, BODY, HEAP_BASE, 16, 16)], &[0; 16]));
+        refused(elf(ET_EXEC, 0x40_0000, &[text(), load(PF_W | 4, BODY, 0x40_0800, 16, 16)], &[0; 16]));
+        // Entry outside the executable segments
+        refused(elf(ET_EXEC, 0x40_1000, &[text(), load(PF_W | 4, BODY, 0x40_1000, 16, 16)], &[0; 16]));
+    }
+
+    /// A position-independent image whose data page holds a pointer into
+    /// its text, with the relocation table after it
+    fn position_independent_image(kind: u32) -> Vec<u8> {
+        let mut body = vec![0u8; 16];
+        // The pointer, then the relocation for it
+        body.extend_from_slice(&0u64.to_le_bytes());
+        body.extend_from_slice(&0x2000u64.to_le_bytes());
+        body.extend_from_slice(&(kind as u64).to_le_bytes());
+        body.extend_from_slice(&0x1008u64.to_le_bytes());
+        // The dynamic table
+        for (tag, value) in [(DT_RELA, 0x2008), (DT_RELASZ, RELA_SIZE as u64), (DT_RELAENT, RELA_SIZE as u64), (DT_NULL, 0)] {
+            body.extend_from_slice(&tag.to_le_bytes());
+            body.extend_from_slice(&value.to_le_bytes());
+        }
+        let dynamic = ProgramHeader { kind: PT_DYNAMIC, flags: 4, offset: BODY + 48, address: 0x2020, file_size: 64, memory_size: 64 };
+        let headers = [load(PF_X | 4, BODY, 0x1000, 16, 16), load(PF_W | 4, BODY + 16, 0x2000, 96, 96), dynamic];
+        elf(ET_DYN, 0x1000, &headers, &body)
+    }
+
+    #[test_case]
+    fn test_position_independent_image_is_relocated() {
+        let mut bytes = position_independent_image(R_X86_64_RELATIVE);
+        let image = parse(&mut bytes).expect("image");
+        assert_eq!(image.entry, PIE_BASE + 0x1000);
+        assert_eq!(image.segments[1].address, PIE_BASE + 0x2000);
+        let pointer = u64::from_le_bytes(bytes[BODY + 16..BODY + 24].try_into().unwrap());
+        assert_eq!(pointer as usize, PIE_BASE + 0x1008);
+
+        // Anything but relative relocations needs a dynamic linker
+        let mut bytes = position_independent_image(1);
+        assert_eq!(parse(&mut bytes), Err(ExecError::NotExecutable));
+    }
+}
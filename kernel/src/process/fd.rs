@@ -0,0 +1,268 @@
+//! Per-process file descriptor table
+//!
+//! Descriptors 0, 1 and 2 start out bound to the console. Files opened
+//! through the file system service are recorded with the service's own
+//! descriptor so later reads, writes and closes can be forwarded to it.
+//! Pipes are kernel objects and, unlike service files, are inherited by
+//! forked children.
+
+use alloc::vec::Vec;
+use alloc::vec;
+use kosh_types::OpenFlags;
+use super::ProcessId;
+use crate::ipc::pipe::{PipeEnd, PipeId};
+
+/// Standard input descriptor
+pub const STDIN_FILENO: u32 = 0;
+/// Standard output descriptor
+pub const STDOUT_FILENO: u32 = 1;
+/// Standard error descriptor
+pub const STDERR_FILENO: u32 = 2;
+
+/// Maximum number of open descriptors per process
+pub const MAX_FDS: usize = 256;
+
+/// Console stream a descriptor is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleStream {
+    Input,
+    Output,
+    Error,
+}
+
+/// What an open descriptor refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileHandle {
+    /// Kernel console (VGA text buffer and serial port)
+    Console(ConsoleStream),
+    /// File held open by a file system service
+    Service {
+        /// Process ID of the service
+        service: ProcessId,
+        /// Descriptor in the service's own table
+        remote_fd: u32,
+    },
+    /// One end of a kernel pipe
+    Pipe {
+        pipe: PipeId,
+        end: PipeEnd,
+    },
+}
+
+/// State of one open descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenFile {
+    pub handle: FileHandle,
+    pub flags: OpenFlags,
+    /// Bytes transferred through this descriptor so far
+    pub offset: u64,
+}
+
+impl OpenFile {
+    /// Create an entry for a freshly opened file
+    pub fn new(handle: FileHandle, flags: OpenFlags) -> Self {
+        Self { handle, flags, offset: 0 }
+    }
+
+    /// Whether the access mode permits reading
+    pub fn readable(&self) -> bool {
+        !self.flags.contains(OpenFlags::WRITE_ONLY)
+    }
+
+    /// Whether the access mode permits writing
+    pub fn writable(&self) -> bool {
+        self.flags.intersects(OpenFlags::WRITE_ONLY | OpenFlags::READ_WRITE)
+    }
+}
+
+/// File descriptor errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdError {
+    /// Descriptor is not open
+    BadDescriptor,
+    /// No free descriptor left
+    TableFull,
+}
+
+/// Open descriptors of a process, indexed by descriptor number
+#[derive(Debug, Clone)]
+pub struct FdTable {
+    entries: Vec<Option<OpenFile>>,
+}
+
+impl FdTable {
+    /// Create an empty table
+    pub fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Create a table with stdin, stdout and stderr bound to the console
+    pub fn with_stdio() -> Self {
+        Self {
+            entries: vec![
+                Some(OpenFile::new(FileHandle::Console(ConsoleStream::Input), OpenFlags::READ_ONLY)),
+                Some(OpenFile::new(FileHandle::Console(ConsoleStream::Output), OpenFlags::WRITE_ONLY)),
+                Some(OpenFile::new(FileHandle::Console(ConsoleStream::Error), OpenFlags::WRITE_ONLY)),
+            ],
+        }
+    }
+
+    /// Install an open file at the lowest free descriptor
+    pub fn allocate(&mut self, file: OpenFile) -> Result<u32, FdError> {
+        if let Some(fd) = self.entries.iter().position(|entry| entry.is_none()) {
+            self.entries[fd] = Some(file);
+            return Ok(fd as u32);
+        }
+        if self.entries.len() >= MAX_FDS {
+            return Err(FdError::TableFull);
+        }
+        self.entries.push(Some(file));
+        Ok((self.entries.len() - 1) as u32)
+    }
+
+    /// Look up an open descriptor
+    pub fn get(&self, fd: u32) -> Result<&OpenFile, FdError> {
+        self.entries.get(fd as usize)
+            .and_then(|entry| entry.as_ref())
+            .ok_or(FdError::BadDescriptor)
+    }
+
+    /// Look up an open descriptor for modification
+    pub fn get_mut(&mut self, fd: u32) -> Result<&mut OpenFile, FdError> {
+        self.entries.get_mut(fd as usize)
+            .and_then(|entry| entry.as_mut())
+            .ok_or(FdError::BadDescriptor)
+    }
+
+    /// Remove a descriptor, returning what it referred to
+    pub fn close(&mut self, fd: u32) -> Result<OpenFile, FdError> {
+        let file = self.entries.get_mut(fd as usize)
+            .and_then(|entry| entry.take())
+            .ok_or(FdError::BadDescriptor)?;
+
+        // Keep the table compact once trailing descriptors are gone
+        while let Some(None) = self.entries.last() {
+            self.entries.pop();
+        }
+        Ok(file)
+    }
+
+    /// Make `target` refer to the same file as `fd`, as `dup2` does
+    ///
+    /// Returns what `target` referred to before, for the caller to release.
+    pub fn duplicate_to(&mut self, fd: u32, target: u32) -> Result<Option<OpenFile>, FdError> {
+        let file = *self.get(fd)?;
+        if target as usize >= MAX_FDS {
+            return Err(FdError::BadDescriptor);
+        }
+        if target as usize >= self.entries.len() {
+            self.entries.resize(target as usize + 1, None);
+        }
+        Ok(self.entries[target as usize].replace(file))
+    }
+
+    /// What each open descriptor refers to
+    pub fn handles(&self) -> impl Iterator<Item = FileHandle> + '_ {
+        self.entries.iter().flatten().map(|file| file.handle)
+    }
+
+    /// Whether any descriptor still refers to `handle`
+    pub fn references(&self, handle: FileHandle) -> bool {
+        self.entries.iter().flatten().any(|file| file.handle == handle)
+    }
+
+    /// Descriptors a forked child starts with
+    ///
+    /// Console and pipe descriptors keep their numbers; files held by a
+    /// service are not inherited, since the service tracks them per owner.
+    pub fn inherited(&self) -> FdTable {
+        let mut entries: Vec<Option<OpenFile>> = self.entries.iter()
+            .map(|entry| entry.filter(|file| !matches!(file.handle, FileHandle::Service { .. })))
+            .collect();
+        while let Some(None) = entries.last() {
+            entries.pop();
+        }
+        FdTable { entries }
+    }
+
+    /// Remove every descriptor, returning the files that were open
+    pub fn close_all(&mut self) -> Vec<OpenFile> {
+        self.entries.drain(..).flatten().collect()
+    }
+
+    /// Number of open descriptors
+    pub fn open_count(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.is_some()).count()
+    }
+}
+
+impl Default for FdTable {
+    fn default() -> Self {
+        Self::with_stdio()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_fd_table_allocation() {
+        let mut table = FdTable::with_stdio();
+        assert_eq!(table.open_count(), 3);
+        assert_eq!(table.get(STDOUT_FILENO).unwrap().handle, FileHandle::Console(ConsoleStream::Output));
+
+        let file = OpenFile::new(
+            FileHandle::Service { service: ProcessId::new(2), remote_fd: 7 },
+            OpenFlags::READ_WRITE,
+        );
+        assert_eq!(table.allocate(file), Ok(3));
+        assert_eq!(table.allocate(file), Ok(4));
+
+        // Closed descriptors are reused lowest first
+        assert!(table.close(STDIN_FILENO).is_ok());
+        assert_eq!(table.get(STDIN_FILENO), Err(FdError::BadDescriptor));
+        assert_eq!(table.allocate(file), Ok(0));
+
+        assert_eq!(table.close(42), Err(FdError::BadDescriptor));
+        assert_eq!(table.close_all().len(), 5);
+        assert_eq!(table.open_count(), 0);
+    }
+
+    #[test_case]
+    fn test_fd_table_duplicate_and_inherit() {
+        let mut table = FdTable::with_stdio();
+        let service = OpenFile::new(
+            FileHandle::Service { service: ProcessId::new(2), remote_fd: 7 },
+            OpenFlags::READ_ONLY,
+        );
+        let pipe = OpenFile::new(FileHandle::Pipe { pipe: PipeId(1), end: PipeEnd::Write }, OpenFlags::WRITE_ONLY);
+        assert_eq!(table.allocate(service), Ok(3));
+        assert_eq!(table.allocate(pipe), Ok(4));
+
+        // The displaced descriptor is handed back
+        let displaced = table.duplicate_to(4, STDOUT_FILENO).unwrap();
+        assert_eq!(displaced.unwrap().handle, FileHandle::Console(ConsoleStream::Output));
+        assert_eq!(table.get(STDOUT_FILENO).unwrap().handle, pipe.handle);
+        assert_eq!(table.duplicate_to(3, 9), Ok(None));
+        assert_eq!(table.duplicate_to(5, 6), Err(FdError::BadDescriptor));
+        assert!(table.references(service.handle));
+
+        let child = table.inherited();
+        assert_eq!(child.get(STDOUT_FILENO).unwrap().handle, pipe.handle);
+        assert_eq!(child.get(4).unwrap().handle, pipe.handle);
+        assert_eq!(child.get(3), Err(FdError::BadDescriptor));
+        assert_eq!(child.get(9), Err(FdError::BadDescriptor));
+        assert_eq!(child.open_count(), 4);
+    }
+
+    #[test_case]
+    fn test_fd_table_limit() {
+        let mut table = FdTable::new();
+        let file = OpenFile::new(FileHandle::Console(ConsoleStream::Output), OpenFlags::WRITE_ONLY);
+        for _ in 0..MAX_FDS {
+            assert!(table.allocate(file).is_ok());
+        }
+        assert_eq!(table.allocate(file), Err(FdError::TableFull));
+    }
+}
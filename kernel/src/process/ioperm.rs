@@ -0,0 +1,140 @@
+//! Per-process I/O port permissions
+//!
+//! A driver may use the I/O ports it holds a `DeviceAccess` grant for, as
+//! an `IoPorts` range, and no others. The ports of each process with such
+//! a grant are kept as an I/O permission bitmap, a set bit denying its
+//! port as on x86-64, rebuilt whenever its grants change. The bitmap of
+//! the process a CPU switches to is installed in that CPU's TSS, so a port
+//! instruction outside it faults rather than reaching another driver's
+//! device; a process without a bitmap may use no port at all.
+//!
+//! A grant takes effect the next time the process is switched to.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use crate::ipc::capability::{self, CapabilityType, ResourceId};
+use crate::process::{ProcessId, owning_process};
+
+/// Bytes of a bitmap, one bit per port
+pub const BITMAP_BYTES: usize = 65536 / 8;
+
+/// Ports a process may use
+struct IoBitmap {
+    /// Changes whenever the bits do, so a CPU that has them loaded need
+    /// not copy them again
+    generation: u64,
+    bits: Vec<u8>,
+}
+
+impl IoBitmap {
+    /// A bitmap opening the inclusive port ranges `ranges`
+    fn new(ranges: &[(u16, u16)]) -> Self {
+        let mut bits = vec![0xFF; BITMAP_BYTES];
+        for &(start, end) in ranges {
+            for port in start..=end {
+                bits[port as usize / 8] &= !(1 << (port % 8));
+            }
+        }
+        Self { generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed), bits }
+    }
+
+    fn allows(&self, port: u16) -> bool {
+        self.bits[port as usize / 8] & (1 << (port % 8)) == 0
+    }
+}
+
+/// Generation of the next bitmap built; 0 stands for none
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+static BITMAPS: Mutex<BTreeMap<ProcessId, IoBitmap>> = Mutex::new(BTreeMap::new());
+
+/// Port ranges `process` holds a grant for
+fn granted_ranges(process: ProcessId) -> Vec<(u16, u16)> {
+    capability::list_capabilities(process).iter()
+        .filter(|cap| cap.capability_type == CapabilityType::DeviceAccess)
+        .filter_map(|cap| match cap.resource {
+            ResourceId::IoPorts { start, end } => Some((start, end)),
+            ResourceId::Any => Some((0, u16::MAX)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Rebuild the bitmap of `process` from its grants
+pub fn refresh(process: ProcessId) {
+    let process = owning_process(process);
+    let ranges = granted_ranges(process);
+    let mut bitmaps = BITMAPS.lock();
+    if ranges.is_empty() {
+        bitmaps.remove(&process);
+    } else {
+        log::debug!("Process {} may use I/O ports {:x?}", process.0, ranges);
+        bitmaps.insert(process, IoBitmap::new(&ranges));
+    }
+}
+
+/// Rebuild every bitmap, after grants were revoked
+///
+/// Revoking only ever takes ports away, so processes without a bitmap
+/// have nothing to lose.
+pub fn refresh_all() {
+    let processes: Vec<ProcessId> = BITMAPS.lock().keys().copied().collect();
+    for process in processes {
+        refresh(process);
+    }
+}
+
+/// Whether `process` may use `port`
+pub fn allows(process: ProcessId, port: u16) -> bool {
+    BITMAPS.lock().get(&owning_process(process)).is_some_and(|bitmap| bitmap.allows(port))
+}
+
+/// Install the ports of `process` on the calling CPU as it switches to
+/// it, or close them all for the idle loop with `None`
+pub fn activate(process: Option<ProcessId>) {
+    let bitmaps = BITMAPS.lock();
+    let bitmap = process.and_then(|process| bitmaps.get(&owning_process(process)));
+    crate::platform::set_io_bitmap(bitmap.map(|bitmap| (bitmap.generation, &bitmap.bits[..])));
+}
+
+/// Forget the ports of an exiting process
+pub fn release_process(process: ProcessId) {
+    BITMAPS.lock().remove(&process);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_bitmap_opens_only_granted_ports() {
+        let bitmap = IoBitmap::new(&[(0x60, 0x60), (0x64, 0x64), (0x3F8, 0x3FF)]);
+        assert!(bitmap.allows(0x60));
+        assert!(bitmap.allows(0x64));
+        assert!(bitmap.allows(0x3FC));
+        assert!(!bitmap.allows(0x61));
+        assert!(!bitmap.allows(0x3F7));
+        assert!(!bitmap.allows(0x400));
+        assert!(!bitmap.allows(0xCF8));
+        assert_ne!(IoBitmap::new(&[]).generation, bitmap.generation);
+    }
+
+    #[test_case]
+    fn test_ports_follow_grants() {
+        let driver = ProcessId::new(9720);
+        let port = capability::create_capability(driver, CapabilityType::DeviceAccess,
+                                                 ResourceId::IoPorts { start: 0x1F0, end: 0x1F7 }, None)
+            .expect("grant");
+        refresh(driver);
+        assert!(allows(driver, 0x1F0));
+        assert!(!allows(driver, 0x60));
+
+        capability::revoke_capability(driver, port).expect("revoke");
+        refresh_all();
+        assert!(!allows(driver, 0x1F0));
+        assert!(!BITMAPS.lock().contains_key(&driver));
+    }
+}
@@ -1,26 +1,38 @@
 pub mod process;
 pub mod scheduler;
 pub mod context;
+pub mod signal;
+pub mod fd;
+pub mod preempt;
+pub mod args;
+pub mod exec;
+pub mod timer;
+pub mod thread;
+pub mod realtime;
+pub mod ioperm;
 
 #[cfg(test)]
 pub mod tests;
 
 pub use process::{
-    Process, ProcessId, ProcessState, ProcessTable, ProcessError, ProcessPriority, ProcessInfo,
-    create_process, get_process, remove_process, set_current_process, get_current_process,
-    get_runnable_processes, get_process_statistics, print_process_table, cleanup_zombie_processes,
+    Process, ProcessId, ProcessState, ProcessTable, ProcessError, ProcessPriority, ProcessInfo, CpuMode,
+    BlockReason, create_process, get_process, list_processes, try_list_processes, remove_process, set_current_process, get_current_process,
+    set_process_state, exit_process, wait_for_child, WaitStatus, send_signal, take_pending_signal, with_address_space,
+    with_address_space_mut, release_address_space, replace_address_space, with_fd_table, with_args, layout, set_layout, find_process_by_name, charge_cpu_time, with_cpu_context,
+    create_thread, owning_process,
+    get_runnable_processes, get_runnable_processes_on, rehome_processes, steal_process, get_process_statistics, print_process_table, cleanup_zombie_processes,
     init_process_table
 };
 pub use scheduler::{
-    Scheduler, SchedulerError, SchedulingAlgorithm,
-    schedule_next_process, handle_timer_tick, set_scheduling_algorithm, set_time_slice,
-    get_scheduler_statistics, print_scheduler_info
+    Scheduler, SchedulerError, SchedulingAlgorithm, SchedInfo, MIN_TIME_SLICE_MS, MAX_TIME_SLICE_MS,
+    schedule_next_process, handle_timer_tick, yield_process, set_scheduling_algorithm, set_time_slice,
+    is_valid_time_slice, get_scheduler_statistics, print_scheduler_info, cpu_load_percent, LOAD_WINDOW_MS
 };
-pub use context::{CpuContext, ContextSwitcher, test_context_switching};
+pub use context::{CpuContext, ContextSwitcher, TrapFrame, test_context_switching};
 
 /// Process management initialization
 pub fn init_process_management() -> Result<(), &'static str> {
-    crate::serial_println!("Initializing process management...");
+    log::info!("Initializing process management...");
     
     // Initialize the global process table
     process::init_process_table()?;
@@ -28,6 +40,6 @@ pub fn init_process_management() -> Result<(), &'static str> {
     // Initialize the scheduler
     scheduler::init_scheduler()?;
     
-    crate::serial_println!("Process management initialized successfully");
+    log::info!("Process management initialized successfully");
     Ok(())
 }
\ No newline at end of file
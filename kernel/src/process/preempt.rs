@@ -0,0 +1,261 @@
+//! Preemption from the timer interrupt
+//!
+//! The kernel itself is not preemptible. Ticks that interrupt kernel code
+//! are only counted and handed to the scheduler at the next tick that
+//! interrupts user code or the idle loop, so the interrupt path never
+//! contends for a lock the interrupted code may hold.
+//!
+//! A process is switched out by saving the interrupted registers into its
+//! `CpuContext` and returning from the interrupt into the next context,
+//! after loading the page tables the next context runs on. Threads of one
+//! process share page tables, so switching between them keeps the loaded
+//! ones. The I/O ports the next process may use are installed along with
+//! its page tables.
+//! A system call normally returns into its caller where it left off. One
+//! that replaced the caller's image returns into the new image's entry
+//! point instead, and one that ended the caller into whatever runs next.
+//! A child `fork` created starts from a copy of the registers its parent
+//! returns with, with 0 as its own result.
+//! Every CPU has its own deferred ticks, running process and idle context.
+//! The idle loop sleeps in whichever low-power state idle management picks,
+//! or halts the CPU for good once it was asked to go offline.
+
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use spin::Mutex;
+use super::context::{CpuContext, TrapFrame};
+use super::{CpuMode, ProcessId, get_current_process, owning_process, with_address_space, with_cpu_context};
+use crate::memory::vmm::activate_address_space;
+use crate::power::idle_management;
+use crate::smp::{self, MAX_CPUS};
+use crate::trace::{Tracepoint, TRACE_IDLE};
+
+/// Timer ticks not yet passed to the scheduler
+static DEFERRED_TICKS: [AtomicU64; MAX_CPUS] = [const { AtomicU64::new(0) }; MAX_CPUS];
+
+/// Marks the idle loop in `RUNNING`
+const IDLE: u64 = u64::MAX;
+
+/// Process whose registers each CPU currently holds, or `IDLE`
+///
+/// Atomic rather than locked since the idle loop writes it with interrupts
+/// enabled.
+static RUNNING: [AtomicU64; MAX_CPUS] = [const { AtomicU64::new(IDLE) }; MAX_CPUS];
+
+/// Saved state of each CPU's idle loop while a process runs
+static IDLE_CONTEXT: [Mutex<Option<CpuContext>>; MAX_CPUS] = [const { Mutex::new(None) }; MAX_CPUS];
+
+/// When each CPU last switched from its idle loop to a process, in µs
+static LEFT_IDLE_AT: [AtomicU64; MAX_CPUS] = [const { AtomicU64::new(0) }; MAX_CPUS];
+
+/// Where the system call in progress on each CPU returns to
+static SYSCALL_RETURN: [AtomicU8; MAX_CPUS] = [const { AtomicU8::new(RETURN_TO_CALLER) }; MAX_CPUS];
+
+/// Child the system call in progress on each CPU forked, or `NO_CHILD`
+static FORKED_CHILD: [AtomicU64; MAX_CPUS] = [const { AtomicU64::new(NO_CHILD) }; MAX_CPUS];
+
+const NO_CHILD: u64 = u64::MAX;
+
+const RETURN_TO_CALLER: u8 = 0;
+/// The caller's context was replaced, by `exec`
+const RETURN_TO_NEW_IMAGE: u8 = 1;
+/// The caller was terminated
+const RETURN_ELSEWHERE: u8 = 2;
+
+/// Count a timer tick and pass pending ticks to the scheduler if the
+/// interrupted code can be preempted
+///
+/// Returns false if the ticks were deferred.
+pub fn tick(interrupted_user_mode: bool) -> bool {
+    let cpu = smp::current_cpu();
+    let ticks = DEFERRED_TICKS[cpu].fetch_add(1, Ordering::SeqCst) + 1;
+    if !interrupted_user_mode && running(cpu).is_some() {
+        // A process is in a system call; catch up on the next tick
+        return false;
+    }
+    DEFERRED_TICKS[cpu].store(0, Ordering::SeqCst);
+    
+    for tick in 1..=ticks {
+        // Ticks deferred while the process was in a system call were spent
+        // in the kernel
+        let mode = if tick < ticks { CpuMode::Kernel } else { CpuMode::User };
+        if super::handle_timer_tick(mode).is_err() {
+            // The scheduler is not up yet
+            break;
+        }
+    }
+    true
+}
+
+/// Handle a timer interrupt, switching the interrupted context if the
+/// scheduler picked another process
+pub fn on_timer_interrupt(frame: &mut TrapFrame) {
+    if !tick(frame.from_user_mode()) {
+        return;
+    }
+    // A CPU going offline hands the process it runs to the others and
+    // idles, halting from its idle loop
+    let cpu = smp::current_cpu();
+    if smp::park_requested(cpu) {
+        let _ = super::set_current_process(None);
+        super::rehome_processes(cpu);
+    }
+    // The serial escape stops the boot CPU where the tick found it
+    if cpu == 0 {
+        crate::kdb::timer_tick(frame);
+    }
+    switch_to_current(frame);
+}
+
+/// Terminate the process running on this CPU after a fatal fault and make
+/// `frame` return into whatever the scheduler picks next
+pub fn kill_running_process(frame: &mut TrapFrame, signal: u32) {
+    if let Some(pid) = running(smp::current_cpu()) {
+        crate::syscall::terminate_by_signal(pid, signal);
+    }
+    switch_away(frame);
+}
+
+/// Make `frame` return into whatever the scheduler picks next after the
+/// running process stopped being runnable, e.g. because it was blocked
+/// inside a fault
+///
+/// A blocked process resumes at the interrupted instruction.
+pub fn switch_away(frame: &mut TrapFrame) {
+    if super::schedule_next_process().is_err() {
+        // Without a scheduler, idle rather than return into the process
+        let _ = super::set_current_process(None);
+    }
+    switch_to_current(frame);
+}
+
+/// Have the system call in progress on this CPU return into the caller's
+/// context as it is now, which `exec` set to the start of a new image
+pub fn return_to_new_image() {
+    SYSCALL_RETURN[smp::current_cpu()].store(RETURN_TO_NEW_IMAGE, Ordering::SeqCst);
+}
+
+/// Have the system call in progress on this CPU return into whatever the
+/// scheduler picks next, as it terminated the caller
+pub fn return_elsewhere() {
+    SYSCALL_RETURN[smp::current_cpu()].store(RETURN_ELSEWHERE, Ordering::SeqCst);
+}
+
+/// Have the system call in progress on this CPU start `child` from the
+/// registers the caller returns with
+pub fn start_forked_child(child: ProcessId) {
+    FORKED_CHILD[smp::current_cpu()].store(child.0 as u64, Ordering::SeqCst);
+}
+
+/// Make `frame` return from a system call where the call asked for
+pub fn on_syscall_return(frame: &mut TrapFrame) {
+    let cpu = smp::current_cpu();
+    let child = FORKED_CHILD[cpu].swap(NO_CHILD, Ordering::SeqCst);
+    if child != NO_CHILD {
+        // Until now the child had no entry point, so nothing ran it
+        let _ = with_cpu_context(ProcessId::new(child as u32), |context| {
+            context.save_from_frame(frame);
+            context.rax = 0;
+        });
+    }
+    match SYSCALL_RETURN[cpu].swap(RETURN_TO_CALLER, Ordering::SeqCst) {
+        RETURN_TO_NEW_IMAGE => {
+            if let Some(pid) = running(cpu) {
+                let _ = with_cpu_context(pid, |context| context.load_into_frame(frame));
+            }
+        }
+        RETURN_ELSEWHERE => switch_away(frame),
+        _ => {}
+    }
+}
+
+/// Make `frame` return into the calling CPU's current process, or into the
+/// idle loop if that process cannot run
+fn switch_to_current(frame: &mut TrapFrame) {
+    // Processes without a loaded image are accounted but cannot be
+    // entered; the CPU idles in their place
+    let cpu = smp::current_cpu();
+    let next = get_current_process().filter(|pid| has_entry_point(*pid));
+    let running = running(cpu);
+    if next == running {
+        return;
+    }
+    
+    match running {
+        Some(pid) => {
+            let _ = with_cpu_context(pid, |context| context.save_from_frame(frame));
+        }
+        None => {
+            IDLE_CONTEXT[cpu].lock().get_or_insert_with(CpuContext::new).save_from_frame(frame);
+            LEFT_IDLE_AT[cpu].store(crate::time::monotonic_us(), Ordering::SeqCst);
+        }
+    }
+    
+    match next {
+        Some(pid) => {
+            let _ = with_cpu_context(pid, |context| context.load_into_frame(frame));
+        }
+        None => match IDLE_CONTEXT[cpu].lock().as_ref() {
+            Some(idle) => idle.load_into_frame(frame),
+            None => {
+                log::warn!("No idle context to return to; staying in process {:?}", running);
+                return;
+            }
+        },
+    }
+    
+    match next {
+        Some(pid) if running.is_some_and(|running| shares_address_space(running, pid)) => {}
+        Some(pid) => {
+            let _ = with_address_space(pid, activate_address_space);
+            super::ioperm::activate(Some(pid));
+        }
+        None => {
+            activate_address_space(None);
+            super::ioperm::activate(None);
+        }
+    }
+    RUNNING[cpu].store(next.map_or(IDLE, |pid| pid.0 as u64), Ordering::SeqCst);
+    
+    let traced = |pid: Option<ProcessId>| pid.map_or(TRACE_IDLE, |pid| pid.0);
+    crate::trace::record(Tracepoint::SchedSwitch, ProcessId::new(traced(next)), traced(running) as u64, 0);
+}
+
+fn running(cpu: usize) -> Option<ProcessId> {
+    match RUNNING[cpu].load(Ordering::SeqCst) {
+        IDLE => None,
+        pid => Some(ProcessId::new(pid as u32)),
+    }
+}
+
+/// Whether `a` and `b` are threads of one process, or one of them the
+/// process and the other its thread
+fn shares_address_space(a: ProcessId, b: ProcessId) -> bool {
+    owning_process(a) == owning_process(b)
+}
+
+fn has_entry_point(pid: ProcessId) -> bool {
+    with_cpu_context(pid, |context| context.rip != 0).unwrap_or(false)
+}
+
+/// Turn the calling thread into its CPU's idle loop
+///
+/// The timer interrupt switches from here into processes, and back here
+/// whenever no process can run.
+pub fn idle_loop() -> ! {
+    let cpu = smp::current_cpu();
+    RUNNING[cpu].store(IDLE, Ordering::SeqCst);
+    loop {
+        if smp::park_requested(cpu) {
+            smp::park_current_cpu();
+        }
+        let index = idle_management::select_state(cpu);
+        let entered_us = crate::time::monotonic_us();
+        crate::platform::enter_idle_state(&idle_management::states()[index]);
+        
+        // If the interrupt that woke the CPU switched to a process, the
+        // idle period ended then rather than now
+        let left_us = LEFT_IDLE_AT[cpu].load(Ordering::SeqCst);
+        let woke_us = if left_us >= entered_us { left_us } else { crate::time::monotonic_us() };
+        idle_management::record_idle(cpu, index, woke_us.saturating_sub(entered_us));
+    }
+}
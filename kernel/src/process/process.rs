@@ -1,9 +1,15 @@
 use alloc::vec::Vec;
 use alloc::string::String;
 use spin::Mutex;
+use crate::memory::accounting::MemoryUsage;
+use crate::memory::aslr::UserLayout;
+use crate::memory::slab::{SlabBox, PROCESS_CACHE};
 use crate::memory::vmm::VirtualAddressSpace;
 use crate::process::context::CpuContext;
-use crate::{serial_println, println};
+use crate::process::args::ProcessArgs;
+use crate::process::fd::FdTable;
+use crate::process::signal::{self, SignalAction, SignalSet, SIGCHLD};
+use crate::smp::{self, MAX_CPUS};
 
 /// Process identifier type
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -55,6 +61,23 @@ pub enum BlockReason {
     WaitingForMemory,
     /// Waiting for a system resource
     WaitingForResource,
+    /// Waiting for a claimed IRQ line to fire
+    WaitingForInterrupt,
+    /// Sleeping, or waiting for a timer to expire
+    Sleeping,
+    /// Waiting on a futex word
+    WaitingForFutex,
+    /// Waiting for a thread to exit
+    WaitingForThread,
+}
+
+/// Privilege level CPU time was spent at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuMode {
+    /// Running the process's own code
+    User,
+    /// In a system call or fault on behalf of the process
+    Kernel,
 }
 
 /// Process priority levels
@@ -115,12 +138,18 @@ pub struct Process {
     pub priority: ProcessPriority,
     /// Process name for debugging
     pub name: String,
-    /// Virtual address space (None for kernel threads)
+    /// Virtual address space (None for processes sharing the kernel's)
     pub address_space: Option<VirtualAddressSpace>,
     /// CPU context for context switching
     pub cpu_context: CpuContext,
     /// CPU time used by this process (in milliseconds)
     pub cpu_time_ms: u64,
+    /// Part of `cpu_time_ms` spent in user mode
+    pub user_time_ms: u64,
+    /// Part of `cpu_time_ms` spent in the kernel on the process's behalf
+    pub kernel_time_ms: u64,
+    /// Times the process was switched onto a CPU
+    pub context_switches: u64,
     /// Time when process was created (in milliseconds since boot)
     pub creation_time_ms: u64,
     /// Time when process was last scheduled (in milliseconds since boot)
@@ -129,6 +158,21 @@ pub struct Process {
     pub exit_code: Option<i32>,
     /// Child process IDs
     pub children: Vec<ProcessId>,
+    /// Signals posted but not yet delivered
+    pub pending_signals: SignalSet,
+    /// Open file descriptors
+    pub fd_table: FdTable,
+    /// Arguments and environment from the last exec
+    pub args: ProcessArgs,
+    /// Where the image's stack, heap and mappings go, and its stack canary
+    pub layout: UserLayout,
+    /// CPU whose scheduler runs this process
+    pub cpu: usize,
+    /// Process this entry is a thread of (None for processes themselves)
+    ///
+    /// A thread is scheduled like a process but uses the address space,
+    /// descriptors and arguments of its owner.
+    pub owner: Option<ProcessId>,
 }
 
 impl Process {
@@ -150,16 +194,25 @@ impl Process {
             address_space: None,
             cpu_context: CpuContext::new(),
             cpu_time_ms: 0,
+            user_time_ms: 0,
+            kernel_time_ms: 0,
+            context_switches: 0,
             creation_time_ms: current_time,
             last_scheduled_ms: current_time,
             exit_code: None,
             children: Vec::new(),
+            pending_signals: SignalSet::empty(),
+            fd_table: FdTable::with_stdio(),
+            args: ProcessArgs::default(),
+            layout: UserLayout::FIXED,
+            cpu: 0,
+            owner: None,
         }
     }
     
     /// Set the process state
     pub fn set_state(&mut self, new_state: ProcessState) {
-        serial_println!("Process {} ({}) state change: {:?} -> {:?}", 
+        log::debug!("Process {} ({}) state change: {:?} -> {:?}", 
                        self.pid.0, self.name, self.state, new_state);
         self.state = new_state;
     }
@@ -174,9 +227,18 @@ impl Process {
         matches!(self.state, ProcessState::Zombie)
     }
     
-    /// Add CPU time to this process
-    pub fn add_cpu_time(&mut self, time_ms: u64) {
+    /// Check if this entry is a thread of another process
+    pub fn is_thread(&self) -> bool {
+        self.owner.is_some()
+    }
+    
+    /// Add CPU time spent in `mode` to this process
+    pub fn add_cpu_time(&mut self, time_ms: u64, mode: CpuMode) {
         self.cpu_time_ms += time_ms;
+        match mode {
+            CpuMode::User => self.user_time_ms += time_ms,
+            CpuMode::Kernel => self.kernel_time_ms += time_ms,
+        }
         self.last_scheduled_ms = get_current_time_ms();
     }
     
@@ -196,7 +258,7 @@ impl Process {
     pub fn terminate(&mut self, exit_code: i32) {
         self.set_state(ProcessState::Zombie);
         self.exit_code = Some(exit_code);
-        serial_println!("Process {} ({}) terminated with exit code {}", 
+        log::debug!("Process {} ({}) terminated with exit code {}", 
                        self.pid.0, self.name, exit_code);
     }
 }
@@ -216,16 +278,34 @@ pub enum ProcessError {
     OutOfMemory,
     /// Invalid process ID
     InvalidPid,
+    /// The process has no (matching) children to wait for
+    NoChildren,
+    /// Signal number out of range
+    InvalidSignal,
+    /// Caller may not act on the target process
+    PermissionDenied,
+}
+
+/// Outcome of waiting for a child process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStatus {
+    /// A child exited with the given code and has been reaped
+    Exited(ProcessId, i32),
+    /// Children exist but none has exited yet
+    NoneExited,
+    /// The caller was blocked and must wait again once woken
+    Blocked,
 }
 
 /// Process table for managing all processes in the system
 pub struct ProcessTable {
-    /// Vector of all processes (index is not necessarily PID)
-    processes: Vec<Option<Process>>,
+    /// Vector of all processes (index is not necessarily PID), each in
+    /// the process slab cache
+    processes: Vec<Option<SlabBox<Process>>>,
     /// Next available PID
     next_pid: u32,
-    /// Currently running process PID
-    current_pid: Option<ProcessId>,
+    /// PID of the process running on each CPU
+    current_pids: [Option<ProcessId>; MAX_CPUS],
     /// Maximum number of processes
     max_processes: usize,
 }
@@ -236,7 +316,7 @@ impl ProcessTable {
         Self {
             processes: Vec::with_capacity(max_processes),
             next_pid: 1, // PID 0 is reserved for kernel
-            current_pid: None,
+            current_pids: [None; MAX_CPUS],
             max_processes,
         }
     }
@@ -259,7 +339,9 @@ impl ProcessTable {
         
         // Create the new process
         let mut process = Process::new(pid, parent_pid, name, priority);
+        process.cpu = self.least_loaded_cpu();
         process.set_state(ProcessState::Ready);
+        let process = SlabBox::new(&PROCESS_CACHE, process).map_err(|_| ProcessError::OutOfMemory)?;
         
         // Add to parent's children list if parent exists
         if let Some(parent_pid) = parent_pid {
@@ -271,23 +353,60 @@ impl ProcessTable {
         // Add to process table
         self.processes.push(Some(process));
         
-        serial_println!("Created process {} with PID {}", 
+        log::debug!("Created process {} with PID {}", 
                        self.processes.last().as_ref().unwrap().as_ref().unwrap().name, 
                        pid.0);
         
         Ok(pid)
     }
     
+    /// Add a thread of `owner` to the table
+    ///
+    /// The thread gets its own ID from the PID space and is homed on the
+    /// least loaded CPU, so threads of one process run in parallel. It is
+    /// nobody's child: it is joined rather than waited for.
+    pub fn create_thread(&mut self, owner: ProcessId) -> Result<ProcessId, ProcessError> {
+        if self.processes.len() >= self.max_processes {
+            return Err(ProcessError::ProcessTableFull);
+        }
+        let owner_process = self.get_process(owner).ok_or(ProcessError::ProcessNotFound)?;
+        if owner_process.is_terminated() || owner_process.is_thread() {
+            return Err(ProcessError::InvalidPid);
+        }
+        let name = owner_process.name.clone();
+        let priority = owner_process.priority;
+        
+        let tid = ProcessId::new(self.next_pid);
+        self.next_pid += 1;
+        
+        let mut thread = Process::new(tid, None, name, priority);
+        thread.owner = Some(owner);
+        thread.cpu = self.least_loaded_cpu();
+        thread.set_state(ProcessState::Ready);
+        let thread = SlabBox::new(&PROCESS_CACHE, thread).map_err(|_| ProcessError::OutOfMemory)?;
+        self.processes.push(Some(thread));
+        
+        log::debug!("Created thread {} of process {}", tid.0, owner.0);
+        Ok(tid)
+    }
+    
+    /// The process whose resources `pid` uses: the owner of a thread, or
+    /// the process itself
+    pub fn owning_process(&self, pid: ProcessId) -> ProcessId {
+        self.get_process(pid).and_then(|process| process.owner).unwrap_or(pid)
+    }
+
+    
     /// Get a process by PID (immutable reference)
     pub fn get_process(&self, pid: ProcessId) -> Option<&Process> {
         self.processes.iter()
-            .find_map(|p| p.as_ref().filter(|proc| proc.pid == pid))
+            .find_map(|p| p.as_deref().filter(|proc| proc.pid == pid))
     }
     
     /// Get a process by PID (mutable reference)
     pub fn get_process_mut(&mut self, pid: ProcessId) -> Option<&mut Process> {
         self.processes.iter_mut()
-            .find_map(|p| p.as_mut().filter(|proc| proc.pid == pid))
+            .find_map(|p| p.as_deref_mut().filter(|proc| proc.pid == pid))
     }
     
     /// Remove a process from the table
@@ -299,6 +418,7 @@ impl ProcessTable {
         
         // Remove the process
         let process = self.processes[index].take()
+            .map(SlabBox::into_inner)
             .ok_or(ProcessError::ProcessNotFound)?;
         
         // Remove from parent's children list
@@ -316,10 +436,169 @@ impl ProcessTable {
             }
         }
         
-        serial_println!("Removed process {} ({})", pid.0, process.name);
+        log::debug!("Removed process {} ({})", pid.0, process.name);
         Ok(process)
     }
     
+    /// Terminate a process, hand its children to init and wake a waiting parent
+    ///
+    /// The process stays in the table as a zombie until its parent collects
+    /// the exit code. Processes without a parent are reaped immediately.
+    pub fn exit_process(&mut self, pid: ProcessId, exit_code: i32) -> Result<(), ProcessError> {
+        let process = self.get_process_mut(pid).ok_or(ProcessError::ProcessNotFound)?;
+        if process.is_terminated() {
+            return Err(ProcessError::ProcessTerminated);
+        }
+        process.terminate(exit_code);
+        let parent_pid = process.parent_pid;
+        let children = core::mem::take(&mut process.children);
+        
+        // Orphans are adopted by init so their exit status is still collected
+        let adopter = if pid != ProcessId::INIT && self.get_process(ProcessId::INIT).is_some() {
+            Some(ProcessId::INIT)
+        } else {
+            None
+        };
+        let mut adopted_zombie = false;
+        for child_pid in children {
+            let child_is_zombie = match self.get_process_mut(child_pid) {
+                Some(child) => {
+                    child.parent_pid = adopter;
+                    child.is_terminated()
+                }
+                None => continue,
+            };
+            match adopter.and_then(|init_pid| self.get_process_mut(init_pid)) {
+                Some(init) => {
+                    init.add_child(child_pid);
+                    adopted_zombie |= child_is_zombie;
+                }
+                None if child_is_zombie => {
+                    self.remove_process(child_pid)?;
+                }
+                None => {}
+            }
+        }
+        if adopted_zombie {
+            self.post_signal(ProcessId::INIT, SIGCHLD);
+            self.wake_waiting_parent(ProcessId::INIT);
+        }
+        
+        match parent_pid.filter(|&parent| self.get_process(parent).is_some()) {
+            Some(parent) => {
+                self.post_signal(parent, SIGCHLD);
+                self.wake_waiting_parent(parent);
+            }
+            None => {
+                // Nobody can wait for this process
+                self.remove_process(pid)?;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Collect an exited child of `parent`
+    ///
+    /// `target` selects a specific child; `None` accepts any child. The
+    /// reaped zombie is removed from the table.
+    pub fn wait_child(&mut self, parent: ProcessId, target: Option<ProcessId>) -> Result<WaitStatus, ProcessError> {
+        let children: Vec<ProcessId> = self.get_process(parent)
+            .ok_or(ProcessError::ProcessNotFound)?
+            .children.iter()
+            .copied()
+            .filter(|&child| target.map_or(true, |wanted| wanted == child))
+            .collect();
+        
+        if children.is_empty() {
+            return Err(ProcessError::NoChildren);
+        }
+        
+        for child_pid in children {
+            let exit_code = match self.get_process(child_pid) {
+                Some(child) if child.is_terminated() => child.exit_code.unwrap_or(-1),
+                _ => continue,
+            };
+            self.remove_process(child_pid)?;
+            return Ok(WaitStatus::Exited(child_pid, exit_code));
+        }
+        
+        Ok(WaitStatus::NoneExited)
+    }
+    
+    /// Send a signal from `sender` to `target`
+    ///
+    /// Signal 0 only checks that the target exists and may be signalled.
+    /// Processes may signal themselves and their children; init and the
+    /// kernel may signal anyone.
+    pub fn send_signal(&mut self, sender: ProcessId, target: ProcessId, signal: u32) -> Result<(), ProcessError> {
+        if signal != 0 && !signal::is_valid_signal(signal) {
+            return Err(ProcessError::InvalidSignal);
+        }
+        
+        let process = self.get_process(target).ok_or(ProcessError::ProcessNotFound)?;
+        if process.is_terminated() {
+            return Err(ProcessError::ProcessTerminated);
+        }
+        
+        let allowed = sender == target
+            || sender == ProcessId::KERNEL
+            || sender == ProcessId::INIT
+            || process.parent_pid == Some(sender);
+        if !allowed {
+            return Err(ProcessError::PermissionDenied);
+        }
+        
+        if signal != 0 {
+            log::debug!("Signal {} posted to process {} by process {}", signal, target.0, sender.0);
+            self.post_signal(target, signal);
+        }
+        Ok(())
+    }
+    
+    /// Mark a signal pending on a process
+    ///
+    /// A process blocked in the kernel is woken for fatal signals so it
+    /// returns through the system call path, where the signal is delivered.
+    fn post_signal(&mut self, target: ProcessId, signal: u32) {
+        if let Some(process) = self.get_process_mut(target) {
+            if process.is_terminated() {
+                return;
+            }
+            process.pending_signals.add(signal);
+            if signal::default_action(signal) == SignalAction::Terminate
+                && matches!(process.state, ProcessState::Blocked(_)) {
+                process.set_state(ProcessState::Ready);
+            }
+        }
+    }
+    
+    /// Consume pending signals of a process and return the first fatal one
+    ///
+    /// Ignored signals are discarded along the way.
+    pub fn take_pending_signal(&mut self, pid: ProcessId) -> Option<u32> {
+        let process = self.get_process_mut(pid)?;
+        if process.is_terminated() {
+            return None;
+        }
+        
+        while let Some(signal) = process.pending_signals.take_next() {
+            match signal::default_action(signal) {
+                SignalAction::Terminate => return Some(signal),
+                SignalAction::Ignore => {}
+            }
+        }
+        None
+    }
+    
+    fn wake_waiting_parent(&mut self, parent: ProcessId) {
+        if let Some(process) = self.get_process_mut(parent) {
+            if process.state == ProcessState::Blocked(BlockReason::WaitingForChild) {
+                process.set_state(ProcessState::Ready);
+            }
+        }
+    }
+    
     /// Get all processes in a specific state
     pub fn get_processes_by_state(&self, state: ProcessState) -> Vec<ProcessId> {
         self.processes.iter()
@@ -338,6 +617,68 @@ impl ProcessTable {
             .collect()
     }
     
+    /// Get the runnable processes homed on `cpu`
+    pub fn get_runnable_processes_on(&self, cpu: usize) -> Vec<ProcessId> {
+        self.processes.iter()
+            .filter_map(|p| p.as_ref())
+            .filter(|proc| proc.cpu == cpu && proc.is_runnable())
+            .map(|proc| proc.pid)
+            .collect()
+    }
+    
+    /// Online CPU with the fewest runnable processes, for homing a new one
+    fn least_loaded_cpu(&self) -> usize {
+        smp::online_cpus()
+            .min_by_key(|&cpu| self.get_runnable_processes_on(cpu).len())
+            .unwrap_or(0)
+    }
+    
+    /// Move a ready process from the busiest other CPU to `thief`
+    ///
+    /// A CPU is only robbed while it has more than one runnable process, so
+    /// stealing never leaves a CPU idle to keep another one busy.
+    pub fn steal_process(&mut self, thief: usize) -> Option<ProcessId> {
+        // A CPU going offline takes no more work
+        if !smp::is_online(thief) {
+            return None;
+        }
+        let victim = smp::online_cpus()
+            .filter(|&cpu| cpu != thief)
+            .max_by_key(|&cpu| self.get_runnable_processes_on(cpu).len())?;
+        if self.get_runnable_processes_on(victim).len() < 2 {
+            return None;
+        }
+        
+        let process = self.processes.iter_mut()
+            .filter_map(|p| p.as_mut())
+            .find(|proc| proc.cpu == victim && proc.state == ProcessState::Ready)?;
+        process.cpu = thief;
+        log::debug!("CPU {} took process {} from CPU {}", thief, process.pid.0, victim);
+        Some(process.pid)
+    }
+    
+    /// Move every process homed on `cpu` to the online CPUs, except one
+    /// running there, which its CPU moves once it has switched away
+    ///
+    /// Returns the number of processes moved.
+    pub fn rehome_processes(&mut self, cpu: usize) -> usize {
+        let mut moved = 0;
+        loop {
+            let target = self.least_loaded_cpu();
+            if target == cpu {
+                break;
+            }
+            let Some(process) = self.processes.iter_mut()
+                .filter_map(|p| p.as_mut())
+                .find(|proc| proc.cpu == cpu && proc.state != ProcessState::Running) else {
+                break;
+            };
+            process.cpu = target;
+            moved += 1;
+        }
+        moved
+    }
+    
     /// Get processes by priority
     pub fn get_processes_by_priority(&self, priority: ProcessPriority) -> Vec<ProcessId> {
         self.processes.iter()
@@ -347,10 +688,22 @@ impl ProcessTable {
             .collect()
     }
     
-    /// Set the currently running process
+    /// Set the process running on the calling CPU
+    ///
+    /// A process homed on another CPU, or running on another CPU, cannot be
+    /// taken: its home scheduler owns it until it is stolen.
     pub fn set_current_process(&mut self, pid: Option<ProcessId>) -> Result<(), ProcessError> {
+        let cpu = smp::current_cpu();
+        if let Some(new_pid) = pid {
+            let new_proc = self.get_process(new_pid).ok_or(ProcessError::ProcessNotFound)?;
+            let running_here = self.current_pids[cpu] == Some(new_pid);
+            if new_proc.cpu != cpu || (new_proc.state == ProcessState::Running && !running_here) {
+                return Err(ProcessError::InvalidStateTransition);
+            }
+        }
+        
         // Update previous current process state
-        if let Some(current_pid) = self.current_pid {
+        if let Some(current_pid) = self.current_pids[cpu] {
             if let Some(current_proc) = self.get_process_mut(current_pid) {
                 if current_proc.state == ProcessState::Running {
                     current_proc.set_state(ProcessState::Ready);
@@ -360,20 +713,25 @@ impl ProcessTable {
         
         // Update new current process state
         if let Some(new_pid) = pid {
+            let switched_in = self.current_pids[cpu] != Some(new_pid);
             if let Some(new_proc) = self.get_process_mut(new_pid) {
                 new_proc.set_state(ProcessState::Running);
+                if switched_in {
+                    new_proc.context_switches += 1;
+                    new_proc.last_scheduled_ms = get_current_time_ms();
+                }
             } else {
                 return Err(ProcessError::ProcessNotFound);
             }
         }
         
-        self.current_pid = pid;
+        self.current_pids[cpu] = pid;
         Ok(())
     }
     
-    /// Get the currently running process PID
+    /// Get the PID of the process running on the calling CPU
     pub fn get_current_process(&self) -> Option<ProcessId> {
-        self.current_pid
+        self.current_pids[smp::current_cpu()]
     }
     
     /// Get process count
@@ -421,16 +779,27 @@ impl ProcessTable {
             interactive_priority_processes: priority_counts[1],
             normal_priority_processes: priority_counts[2],
             background_priority_processes: priority_counts[3],
-            current_pid: self.current_pid,
+            current_pid: self.get_current_process(),
         }
     }
     
-    /// Clean up zombie processes
+    /// Clean up zombie processes that no parent will wait for
+    ///
+    /// Zombies with a living parent keep their exit code until reaped
+    /// through `wait_child`.
     pub fn cleanup_zombies(&mut self) -> usize {
         let mut cleaned_count = 0;
         
-        // Find zombie processes
-        let zombie_pids: Vec<ProcessId> = self.get_processes_by_state(ProcessState::Zombie);
+        // Find orphaned zombie processes
+        let zombie_pids: Vec<ProcessId> = self.get_processes_by_state(ProcessState::Zombie)
+            .into_iter()
+            .filter(|&pid| {
+                self.get_process(pid)
+                    .and_then(|process| process.parent_pid)
+                    .and_then(|parent| self.get_process(parent))
+                    .map_or(true, |parent| parent.is_terminated())
+            })
+            .collect();
         
         for pid in zombie_pids {
             if self.remove_process(pid).is_ok() {
@@ -439,7 +808,7 @@ impl ProcessTable {
         }
         
         if cleaned_count > 0 {
-            serial_println!("Cleaned up {} zombie processes", cleaned_count);
+            log::debug!("Cleaned up {} zombie processes", cleaned_count);
         }
         
         cleaned_count
@@ -470,41 +839,93 @@ const MAX_PROCESSES: usize = 1024;
 
 /// Initialize the global process table
 pub fn init_process_table() -> Result<(), &'static str> {
-    serial_println!("Initializing process table...");
+    log::info!("Initializing process table...");
     
     let process_table = ProcessTable::new(MAX_PROCESSES);
     *PROCESS_TABLE.lock() = Some(process_table);
     
-    serial_println!("Process table initialized with capacity for {} processes", MAX_PROCESSES);
+    log::info!("Process table initialized with capacity for {} processes", MAX_PROCESSES);
     Ok(())
 }
 
 /// Create a new process
+///
+/// It gets a layout of its own, randomized unless `aslr=off` was given,
+/// and the security policy's default capabilities.
 pub fn create_process(
     parent_pid: Option<ProcessId>,
     name: String,
     priority: ProcessPriority,
 ) -> Result<ProcessId, ProcessError> {
+    // Built before taking the table lock, as it locks the kernel address space
+    let address_space = match crate::memory::vmm::create_user_address_space() {
+        Ok(space) => Some(space),
+        Err(err) => {
+            log::debug!("Process {} shares the kernel address space: {}", name, err);
+            None
+        }
+    };
+    
+    let pid = {
+        let mut table = PROCESS_TABLE.lock();
+        let table = table.as_mut().ok_or(ProcessError::ProcessNotFound)?;
+        let pid = table.create_process(parent_pid, name, priority)?;
+        if let Some(process) = table.get_process_mut(pid) {
+            process.address_space = address_space;
+        }
+        pid
+    };
+    set_layout(pid, crate::memory::aslr::new_layout())?;
+    
+    // Processes created before the policy is loaded, by the boot self-tests,
+    // get none
+    if let Err(err) = crate::ipc::security::grant_default_capabilities(pid, parent_pid) {
+        log::debug!("Process {} starts without default capabilities: {}", pid.0, err);
+    }
+    Ok(pid)
+}
+
+/// Add a thread of `owner` to the process table
+pub fn create_thread(owner: ProcessId) -> Result<ProcessId, ProcessError> {
     let mut table = PROCESS_TABLE.lock();
     let table = table.as_mut().ok_or(ProcessError::ProcessNotFound)?;
-    table.create_process(parent_pid, name, priority)
+    table.create_thread(owner)
+}
+
+/// The process whose resources `pid` uses: the owner of a thread, or the
+/// process itself
+pub fn owning_process(pid: ProcessId) -> ProcessId {
+    let table = PROCESS_TABLE.lock();
+    match table.as_ref() {
+        Some(table) => table.owning_process(pid),
+        None => pid,
+    }
 }
 
 /// Get a process by PID (returns a copy of basic process info)
 pub fn get_process(pid: ProcessId) -> Option<ProcessInfo> {
     let table = PROCESS_TABLE.lock();
     let table = table.as_ref()?;
-    table.get_process(pid).map(|p| ProcessInfo {
-        pid: p.pid,
-        parent_pid: p.parent_pid,
-        state: p.state,
-        priority: p.priority,
-        name: p.name.clone(),
-        cpu_time_ms: p.cpu_time_ms,
-        creation_time_ms: p.creation_time_ms,
-        last_scheduled_ms: p.last_scheduled_ms,
-        exit_code: p.exit_code,
-        children_count: p.children.len(),
+    table.get_process(pid).map(ProcessInfo::from)
+}
+
+/// Get information about every process in the table
+pub fn list_processes() -> Vec<ProcessInfo> {
+    let table = PROCESS_TABLE.lock();
+    match table.as_ref() {
+        Some(table) => table.processes.iter().flatten().map(|process| ProcessInfo::from(&**process)).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Every process, or None while the table is locked
+///
+/// Never waits, so it can run from a panic on the CPU that held the lock.
+pub fn try_list_processes() -> Option<Vec<ProcessInfo>> {
+    let table = PROCESS_TABLE.try_lock()?;
+    Some(match table.as_ref() {
+        Some(table) => table.processes.iter().flatten().map(|process| ProcessInfo::from(&**process)).collect(),
+        None => Vec::new(),
     })
 }
 
@@ -517,10 +938,37 @@ pub struct ProcessInfo {
     pub priority: ProcessPriority,
     pub name: String,
     pub cpu_time_ms: u64,
+    pub user_time_ms: u64,
+    pub kernel_time_ms: u64,
+    pub context_switches: u64,
     pub creation_time_ms: u64,
     pub last_scheduled_ms: u64,
     pub exit_code: Option<i32>,
     pub children_count: usize,
+    pub cpu: usize,
+    pub memory: MemoryUsage,
+}
+
+impl From<&Process> for ProcessInfo {
+    fn from(p: &Process) -> Self {
+        Self {
+            pid: p.pid,
+            parent_pid: p.parent_pid,
+            state: p.state,
+            priority: p.priority,
+            name: p.name.clone(),
+            cpu_time_ms: p.cpu_time_ms,
+            user_time_ms: p.user_time_ms,
+            kernel_time_ms: p.kernel_time_ms,
+            context_switches: p.context_switches,
+            creation_time_ms: p.creation_time_ms,
+            last_scheduled_ms: p.last_scheduled_ms,
+            exit_code: p.exit_code,
+            children_count: p.children.len(),
+            cpu: p.cpu,
+            memory: crate::memory::accounting::usage(p.pid),
+        }
+    }
 }
 
 impl ProcessInfo {
@@ -542,6 +990,173 @@ pub fn remove_process(pid: ProcessId) -> Result<Process, ProcessError> {
     table.remove_process(pid)
 }
 
+/// Change the state of a process (e.g. to block or wake it)
+pub fn set_process_state(pid: ProcessId, state: ProcessState) -> Result<(), ProcessError> {
+    let mut table = PROCESS_TABLE.lock();
+    let table = table.as_mut().ok_or(ProcessError::ProcessNotFound)?;
+    let process = table.get_process_mut(pid).ok_or(ProcessError::ProcessNotFound)?;
+    if process.is_terminated() {
+        return Err(ProcessError::ProcessTerminated);
+    }
+    process.set_state(state);
+    Ok(())
+}
+
+/// Terminate a process; it remains a zombie until its parent waits for it
+pub fn exit_process(pid: ProcessId, exit_code: i32) -> Result<(), ProcessError> {
+    let mut table = PROCESS_TABLE.lock();
+    let table = table.as_mut().ok_or(ProcessError::ProcessNotFound)?;
+    table.exit_process(pid, exit_code)
+}
+
+/// Reap an exited child, optionally blocking the caller until one exits
+///
+/// A blocked caller is woken when a child exits and must call again to
+/// collect it.
+pub fn wait_for_child(parent: ProcessId, target: Option<ProcessId>, block: bool) -> Result<WaitStatus, ProcessError> {
+    let mut table = PROCESS_TABLE.lock();
+    let table = table.as_mut().ok_or(ProcessError::ProcessNotFound)?;
+    
+    let status = table.wait_child(parent, target)?;
+    if status == WaitStatus::NoneExited && block {
+        // The table lock is held, so a child cannot exit unnoticed in between
+        let process = table.get_process_mut(parent).ok_or(ProcessError::ProcessNotFound)?;
+        process.set_state(ProcessState::Blocked(BlockReason::WaitingForChild));
+        return Ok(WaitStatus::Blocked);
+    }
+    Ok(status)
+}
+
+/// Run `f` with the address space of a process, or of the owner of a thread
+///
+/// `f` receives `None` for processes that share the kernel address space.
+pub fn with_address_space<R>(pid: ProcessId, f: impl FnOnce(Option<&VirtualAddressSpace>) -> R) -> Result<R, ProcessError> {
+    let table = PROCESS_TABLE.lock();
+    let table = table.as_ref().ok_or(ProcessError::ProcessNotFound)?;
+    let process = table.get_process(table.owning_process(pid)).ok_or(ProcessError::ProcessNotFound)?;
+    Ok(f(process.address_space.as_ref()))
+}
+
+/// Run `f` with the address space of a process or thread owner, mutably
+///
+/// `f` receives `None` for processes that share the kernel address space.
+pub fn with_address_space_mut<R>(pid: ProcessId, f: impl FnOnce(Option<&mut VirtualAddressSpace>) -> R) -> Result<R, ProcessError> {
+    let mut table = PROCESS_TABLE.lock();
+    let table = table.as_mut().ok_or(ProcessError::ProcessNotFound)?;
+    let process = table.get_process_mut(table.owning_process(pid)).ok_or(ProcessError::ProcessNotFound)?;
+    Ok(f(process.address_space.as_mut()))
+}
+
+/// Tear down the address space of an exiting process
+///
+/// The calling CPU falls back to the kernel page tables if it was running
+/// on the freed ones.
+pub fn release_address_space(pid: ProcessId) {
+    let space = {
+        let mut table = PROCESS_TABLE.lock();
+        table.as_mut()
+            .and_then(|table| table.get_process_mut(pid))
+            .and_then(|process| process.address_space.take())
+    };
+    // Freed outside the table lock
+    drop(space);
+}
+
+/// Give process `pid` the new address space `space` for the image `exec`
+/// loads, and switch the calling CPU to it
+pub fn replace_address_space(pid: ProcessId, space: VirtualAddressSpace) -> Result<(), ProcessError> {
+    let old = {
+        let mut table = PROCESS_TABLE.lock();
+        let process = table.as_mut()
+            .and_then(|table| table.get_process_mut(pid))
+            .ok_or(ProcessError::ProcessNotFound)?;
+        let old = process.address_space.replace(space);
+        crate::memory::vmm::activate_address_space(process.address_space.as_ref());
+        old
+    };
+    // Freed outside the table lock, once the CPU no longer runs on it
+    drop(old);
+    Ok(())
+}
+
+/// Run `f` with the file descriptor table of a process, shared by its threads
+pub fn with_fd_table<R>(pid: ProcessId, f: impl FnOnce(&mut FdTable) -> R) -> Result<R, ProcessError> {
+    let mut table = PROCESS_TABLE.lock();
+    let table = table.as_mut().ok_or(ProcessError::ProcessNotFound)?;
+    let process = table.get_process_mut(table.owning_process(pid)).ok_or(ProcessError::ProcessNotFound)?;
+    Ok(f(&mut process.fd_table))
+}
+
+/// Run `f` with the arguments and environment of a process, shared by its threads
+pub fn with_args<R>(pid: ProcessId, f: impl FnOnce(&mut ProcessArgs) -> R) -> Result<R, ProcessError> {
+    let mut table = PROCESS_TABLE.lock();
+    let table = table.as_mut().ok_or(ProcessError::ProcessNotFound)?;
+    let process = table.get_process_mut(table.owning_process(pid)).ok_or(ProcessError::ProcessNotFound)?;
+    Ok(f(&mut process.args))
+}
+
+/// The layout of a process, shared by its threads
+pub fn layout(pid: ProcessId) -> Result<UserLayout, ProcessError> {
+    let table = PROCESS_TABLE.lock();
+    let table = table.as_ref().ok_or(ProcessError::ProcessNotFound)?;
+    let process = table.get_process(table.owning_process(pid)).ok_or(ProcessError::ProcessNotFound)?;
+    Ok(process.layout)
+}
+
+/// Give a process a new layout, before its image maps anything
+pub fn set_layout(pid: ProcessId, layout: UserLayout) -> Result<(), ProcessError> {
+    let owner = {
+        let mut table = PROCESS_TABLE.lock();
+        let table = table.as_mut().ok_or(ProcessError::ProcessNotFound)?;
+        let owner = table.owning_process(pid);
+        table.get_process_mut(owner).ok_or(ProcessError::ProcessNotFound)?.layout = layout;
+        owner
+    };
+    // The mapping manager places the heap, stack and mappings by it. It
+    // takes the table lock under its own, so it is told outside this one
+    crate::memory::mmap::set_layout(owner, layout);
+    Ok(())
+}
+
+/// Add CPU time spent in `mode` to a process
+pub fn charge_cpu_time(pid: ProcessId, time_ms: u64, mode: CpuMode) -> Result<(), ProcessError> {
+    let mut table = PROCESS_TABLE.lock();
+    let table = table.as_mut().ok_or(ProcessError::ProcessNotFound)?;
+    let process = table.get_process_mut(pid).ok_or(ProcessError::ProcessNotFound)?;
+    process.add_cpu_time(time_ms, mode);
+    Ok(())
+}
+
+/// Run `f` with the saved CPU context of a process
+pub fn with_cpu_context<R>(pid: ProcessId, f: impl FnOnce(&mut CpuContext) -> R) -> Result<R, ProcessError> {
+    let mut table = PROCESS_TABLE.lock();
+    let table = table.as_mut().ok_or(ProcessError::ProcessNotFound)?;
+    let process = table.get_process_mut(pid).ok_or(ProcessError::ProcessNotFound)?;
+    Ok(f(&mut process.cpu_context))
+}
+
+/// Find a live process by name
+pub fn find_process_by_name(name: &str) -> Option<ProcessId> {
+    let table = PROCESS_TABLE.lock();
+    table.as_ref()?.processes.iter()
+        .flatten()
+        .find(|process| process.name == name && !process.is_terminated())
+        .map(|process| process.pid)
+}
+
+/// Send a signal to a process
+pub fn send_signal(sender: ProcessId, target: ProcessId, signal: u32) -> Result<(), ProcessError> {
+    let mut table = PROCESS_TABLE.lock();
+    let table = table.as_mut().ok_or(ProcessError::ProcessNotFound)?;
+    table.send_signal(sender, target, signal)
+}
+
+/// Take the next fatal pending signal of a process, discarding ignored ones
+pub fn take_pending_signal(pid: ProcessId) -> Option<u32> {
+    let mut table = PROCESS_TABLE.lock();
+    table.as_mut()?.take_pending_signal(pid)
+}
+
 /// Set the currently running process
 pub fn set_current_process(pid: Option<ProcessId>) -> Result<(), ProcessError> {
     let mut table = PROCESS_TABLE.lock();
@@ -565,6 +1180,28 @@ pub fn get_runnable_processes() -> Vec<ProcessId> {
     }
 }
 
+/// Get the runnable processes homed on `cpu`
+pub fn get_runnable_processes_on(cpu: usize) -> Vec<ProcessId> {
+    let table = PROCESS_TABLE.lock();
+    match table.as_ref() {
+        Some(table) => table.get_runnable_processes_on(cpu),
+        None => Vec::new(),
+    }
+}
+
+/// Move the processes homed on `cpu` to the online CPUs, all but the one
+/// it is running
+pub fn rehome_processes(cpu: usize) -> usize {
+    let mut table = PROCESS_TABLE.lock();
+    table.as_mut().map_or(0, |table| table.rehome_processes(cpu))
+}
+
+/// Move a ready process from the busiest other CPU to `cpu`
+pub fn steal_process(cpu: usize) -> Option<ProcessId> {
+    let mut table = PROCESS_TABLE.lock();
+    table.as_mut()?.steal_process(cpu)
+}
+
 /// Get process table statistics
 pub fn get_process_statistics() -> Option<ProcessTableStatistics> {
     let table = PROCESS_TABLE.lock();
@@ -577,24 +1214,24 @@ pub fn print_process_table() {
     if let Some(table) = table.as_ref() {
         let stats = table.get_statistics();
         
-        serial_println!("Process Table Statistics:");
-        serial_println!("  Total processes: {}", stats.total_processes);
-        serial_println!("  Creating: {}, Ready: {}, Running: {}, Blocked: {}, Zombie: {}", 
+        log::debug!("Process Table Statistics:");
+        log::debug!("  Total processes: {}", stats.total_processes);
+        log::debug!("  Creating: {}, Ready: {}, Running: {}, Blocked: {}, Zombie: {}", 
                        stats.creating_processes, stats.ready_processes, 
                        stats.running_processes, stats.blocked_processes, stats.zombie_processes);
-        serial_println!("  Priority distribution - System: {}, Interactive: {}, Normal: {}, Background: {}", 
+        log::debug!("  Priority distribution - System: {}, Interactive: {}, Normal: {}, Background: {}", 
                        stats.system_priority_processes, stats.interactive_priority_processes,
                        stats.normal_priority_processes, stats.background_priority_processes);
         
         if let Some(current_pid) = stats.current_pid {
-            serial_println!("  Current process: {}", current_pid.0);
+            log::debug!("  Current process: {}", current_pid.0);
         } else {
-            serial_println!("  No current process");
+            log::debug!("  No current process");
         }
         
-        println!("Process table: {} processes active", stats.total_processes);
+        log::info!("Process table: {} processes active", stats.total_processes);
     } else {
-        serial_println!("Process table not initialized");
+        log::info!("Process table not initialized");
     }
 }
 
@@ -748,4 +1385,149 @@ mod tests {
         assert_eq!(stats.normal_priority_processes, 1);
         assert_eq!(stats.background_priority_processes, 1);
     }
-}
\ No newline at end of file
+    
+    #[test_case]
+    fn test_wait_reaps_zombie_child() {
+        let mut table = ProcessTable::new(10);
+        
+        let parent = table.create_process(None, "parent".to_string(), ProcessPriority::Normal).unwrap();
+        let child = table.create_process(Some(parent), "child".to_string(), ProcessPriority::Normal).unwrap();
+        
+        assert_eq!(table.wait_child(parent, None), Ok(WaitStatus::NoneExited));
+        
+        table.get_process_mut(parent).unwrap().set_state(ProcessState::Blocked(BlockReason::WaitingForChild));
+        table.exit_process(child, 7).unwrap();
+        assert_eq!(table.get_process(parent).unwrap().state, ProcessState::Ready);
+        assert!(table.get_process(child).unwrap().is_terminated());
+        
+        // Zombies with a living parent survive cleanup
+        assert_eq!(table.cleanup_zombies(), 0);
+        
+        assert_eq!(table.wait_child(parent, Some(child)), Ok(WaitStatus::Exited(child, 7)));
+        assert!(table.get_process(child).is_none());
+        assert_eq!(table.wait_child(parent, None), Err(ProcessError::NoChildren));
+    }
+    
+    #[test_case]
+    fn test_orphans_are_adopted_by_init() {
+        let mut table = ProcessTable::new(10);
+        
+        let init = table.create_process(None, "init".to_string(), ProcessPriority::System).unwrap();
+        assert_eq!(init, ProcessId::INIT);
+        let service = table.create_process(Some(init), "service".to_string(), ProcessPriority::Normal).unwrap();
+        let worker = table.create_process(Some(service), "worker".to_string(), ProcessPriority::Normal).unwrap();
+        
+        table.exit_process(service, 1).unwrap();
+        assert_eq!(table.get_process(worker).unwrap().parent_pid, Some(init));
+        assert!(table.get_process(init).unwrap().children.contains(&worker));
+        
+        assert_eq!(table.wait_child(init, None), Ok(WaitStatus::Exited(service, 1)));
+        
+        // A process without a parent is reaped on exit
+        let orphan = table.create_process(None, "orphan".to_string(), ProcessPriority::Normal).unwrap();
+        table.exit_process(orphan, 0).unwrap();
+        assert!(table.get_process(orphan).is_none());
+    }
+    
+    #[test_case]
+    fn test_signal_delivery_and_sigchld() {
+        use crate::process::signal::{SIGTERM, SIGCHLD};
+        
+        let mut table = ProcessTable::new(10);
+        
+        let init = table.create_process(None, "init".to_string(), ProcessPriority::System).unwrap();
+        let service = table.create_process(Some(init), "service".to_string(), ProcessPriority::Normal).unwrap();
+        let other = table.create_process(Some(init), "other".to_string(), ProcessPriority::Normal).unwrap();
+        
+        // Siblings may not signal each other
+        assert_eq!(table.send_signal(other, service, SIGTERM), Err(ProcessError::PermissionDenied));
+        
+        // A blocked target is woken so the signal is acted on
+        table.get_process_mut(service).unwrap().set_state(ProcessState::Blocked(BlockReason::WaitingForMessage));
+        table.send_signal(init, service, SIGTERM).unwrap();
+        assert_eq!(table.get_process(service).unwrap().state, ProcessState::Ready);
+        assert_eq!(table.take_pending_signal(service), Some(SIGTERM));
+        assert_eq!(table.take_pending_signal(service), None);
+        
+        // Child exit posts SIGCHLD to the parent, which is ignored by default
+        table.exit_process(service, signal::exit_code_for(SIGTERM)).unwrap();
+        assert!(table.get_process(init).unwrap().pending_signals.contains(SIGCHLD));
+        assert_eq!(table.take_pending_signal(init), None);
+        assert!(table.get_process(init).unwrap().pending_signals.is_empty());
+    }
+    
+    #[test_case]
+    fn test_home_cpu_claim() {
+        let mut table = ProcessTable::new(10);
+        let local = table.create_process(None, "local".to_string(), ProcessPriority::Normal).unwrap();
+        let remote = table.create_process(None, "remote".to_string(), ProcessPriority::Normal).unwrap();
+        
+        // Only the boot CPU is online under test
+        assert_eq!(table.get_process(local).unwrap().cpu, 0);
+        table.get_process_mut(remote).unwrap().cpu = 1;
+        assert_eq!(table.get_runnable_processes_on(0), alloc::vec![local]);
+        
+        // A process homed elsewhere cannot be taken, and nobody can steal
+        // from a CPU that is not online
+        assert_eq!(table.set_current_process(Some(remote)), Err(ProcessError::InvalidStateTransition));
+        assert_eq!(table.steal_process(0), None);
+        
+        table.set_current_process(Some(local)).unwrap();
+        assert_eq!(table.get_current_process(), Some(local));
+        assert_eq!(table.get_process(local).unwrap().state, ProcessState::Running);
+    }
+    
+    #[test_case]
+    fn test_rehome_processes_of_offline_cpu() {
+        let mut table = ProcessTable::new(10);
+        let waiting = table.create_process(None, "waiting".to_string(), ProcessPriority::Normal).unwrap();
+        let running = table.create_process(None, "running".to_string(), ProcessPriority::Normal).unwrap();
+        table.get_process_mut(waiting).unwrap().cpu = 1;
+        table.get_process_mut(running).unwrap().cpu = 1;
+        table.get_process_mut(running).unwrap().set_state(ProcessState::Running);
+        
+        // Only the boot CPU is online under test, so everything goes there
+        // but the process CPU 1 is still running
+        assert_eq!(table.rehome_processes(1), 1);
+        assert_eq!(table.get_process(waiting).unwrap().cpu, 0);
+        assert_eq!(table.get_process(running).unwrap().cpu, 1);
+        
+        // A CPU that is not online cannot steal either
+        assert_eq!(table.steal_process(1), None);
+    }
+    
+    #[test_case]
+    fn test_threads_resolve_to_their_owner() {
+        let mut table = ProcessTable::new(10);
+        let service = table.create_process(None, "service".to_string(), ProcessPriority::Interactive).unwrap();
+        let worker = table.create_thread(service).unwrap();
+        
+        let thread = table.get_process(worker).unwrap();
+        assert_eq!(thread.owner, Some(service));
+        assert_eq!(thread.priority, ProcessPriority::Interactive);
+        assert!(thread.is_runnable());
+        assert!(table.get_process(service).unwrap().children.is_empty());
+        
+        assert_eq!(table.owning_process(worker), service);
+        assert_eq!(table.owning_process(service), service);
+        
+        // Threads cannot have threads of their own
+        assert_eq!(table.create_thread(worker), Err(ProcessError::InvalidPid));
+    }
+    
+    #[test_case]
+    fn test_cpu_accounting() {
+        let mut table = ProcessTable::new(10);
+        let pid = table.create_process(None, "busy".to_string(), ProcessPriority::Normal).unwrap();
+        
+        let process = table.get_process_mut(pid).unwrap();
+        process.add_cpu_time(3, CpuMode::User);
+        process.add_cpu_time(2, CpuMode::Kernel);
+        assert_eq!((process.cpu_time_ms, process.user_time_ms, process.kernel_time_ms), (5, 3, 2));
+        
+        // Staying on the CPU is not a context switch
+        table.set_current_process(Some(pid)).unwrap();
+        table.set_current_process(Some(pid)).unwrap();
+        assert_eq!(ProcessInfo::from(table.get_process(pid).unwrap()).context_switches, 1);
+    }
+}
@@ -0,0 +1,223 @@
+//! Real-time scheduling class
+//!
+//! Processes in the real-time class run before every other process,
+//! whatever the scheduling algorithm: the runnable one with the highest
+//! real-time priority runs, those of equal priority take turns, and a
+//! real-time process that becomes runnable preempts a lower one at the
+//! next tick. The input path runs in this class so that touch-to-display
+//! latency stays bounded when the system is loaded.
+//!
+//! A real-time process waiting on a lower one through IPC lends it its
+//! priority, so a busy normal process cannot hold up the request it is
+//! serving. A message to a process of lower effective priority starts a
+//! loan, which ends when the receiver messages the sender back (the reply),
+//! goes idle waiting for messages, or either of them exits. File system
+//! calls the kernel forwards lend the caller's priority to fs-service
+//! until the response arrives.
+//!
+//! Threads share the class and priority of their process.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::process::{ProcessId, owning_process};
+
+/// Lowest and highest real-time priority; higher runs first
+pub const MIN_RT_PRIORITY: u8 = 1;
+pub const MAX_RT_PRIORITY: u8 = 99;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RealtimeError {
+    /// Priority outside `MIN_RT_PRIORITY..=MAX_RT_PRIORITY`
+    InvalidPriority,
+    /// No such process
+    ProcessNotFound,
+}
+
+struct RealtimeClass {
+    /// Priority set for each real-time process
+    base: BTreeMap<ProcessId, u8>,
+    /// Priorities lent to each receiver, with the process that lent them
+    loans: BTreeMap<ProcessId, Vec<(ProcessId, u8)>>,
+}
+
+impl RealtimeClass {
+    const fn new() -> Self {
+        Self { base: BTreeMap::new(), loans: BTreeMap::new() }
+    }
+
+    fn effective_priority(&self, process: ProcessId) -> Option<u8> {
+        let lent = self.loans.get(&process)
+            .and_then(|loans| loans.iter().map(|&(_, priority)| priority).max());
+        self.base.get(&process).copied().max(lent)
+    }
+
+    fn lend(&mut self, donor: ProcessId, receiver: ProcessId) {
+        if donor == receiver {
+            return;
+        }
+        let priority = match self.effective_priority(donor) {
+            Some(priority) => priority,
+            None => return,
+        };
+        let loans = self.loans.entry(receiver).or_default();
+        loans.retain(|&(lender, _)| lender != donor);
+        loans.push((donor, priority));
+    }
+
+    fn reclaim(&mut self, donor: ProcessId, receiver: ProcessId) {
+        if let Some(loans) = self.loans.get_mut(&receiver) {
+            loans.retain(|&(lender, _)| lender != donor);
+            if loans.is_empty() {
+                self.loans.remove(&receiver);
+            }
+        }
+    }
+
+    fn release(&mut self, process: ProcessId) {
+        self.base.remove(&process);
+        self.loans.remove(&process);
+        self.loans.retain(|_, loans| {
+            loans.retain(|&(lender, _)| lender != process);
+            !loans.is_empty()
+        });
+    }
+}
+
+static REALTIME: Mutex<RealtimeClass> = Mutex::new(RealtimeClass::new());
+
+/// Put `process` in the real-time class at `priority`, or back in the
+/// normal class with `None`
+pub fn set_priority(process: ProcessId, priority: Option<u8>) -> Result<(), RealtimeError> {
+    if crate::process::get_process(process).is_none() {
+        return Err(RealtimeError::ProcessNotFound);
+    }
+    let process = owning_process(process);
+    let mut realtime = REALTIME.lock();
+    match priority {
+        Some(priority) if (MIN_RT_PRIORITY..=MAX_RT_PRIORITY).contains(&priority) => {
+            realtime.base.insert(process, priority);
+        }
+        Some(_) => return Err(RealtimeError::InvalidPriority),
+        None => {
+            realtime.base.remove(&process);
+        }
+    }
+    log::debug!("Process {} real-time priority set to {:?}", process.0, priority);
+    Ok(())
+}
+
+/// Real-time priority `process` runs at, counting loans; None if it runs
+/// in the normal class
+pub fn effective_priority(process: ProcessId) -> Option<u8> {
+    let process = owning_process(process);
+    REALTIME.lock().effective_priority(process)
+}
+
+/// Whether any process is in the real-time class, to skip the lookups
+/// when none is
+pub fn is_active() -> bool {
+    let realtime = REALTIME.lock();
+    !realtime.base.is_empty() || !realtime.loans.is_empty()
+}
+
+/// Lend the priority of `donor` to `receiver` while `receiver` works on a
+/// request from it
+///
+/// Nothing is lent if the receiver already runs at least as high.
+pub fn lend(donor: ProcessId, receiver: ProcessId) {
+    let (donor, receiver) = (owning_process(donor), owning_process(receiver));
+    let mut realtime = REALTIME.lock();
+    let higher = match (realtime.effective_priority(donor), realtime.effective_priority(receiver)) {
+        (Some(lent), Some(own)) => lent > own,
+        (Some(_), None) => true,
+        (None, _) => false,
+    };
+    if higher {
+        realtime.lend(donor, receiver);
+    }
+}
+
+/// End the loan from `donor` to `receiver`, if any
+pub fn reclaim(donor: ProcessId, receiver: ProcessId) {
+    let (donor, receiver) = (owning_process(donor), owning_process(receiver));
+    REALTIME.lock().reclaim(donor, receiver);
+}
+
+/// Account for a message from `sender` to `receiver`
+///
+/// A message back to a process that lent the sender its priority is the
+/// reply, and ends the loan; the message itself may start a new one.
+pub fn message_sent(sender: ProcessId, receiver: ProcessId) {
+    reclaim(receiver, sender);
+    lend(sender, receiver);
+}
+
+/// `process` has handled every message and waits for more, so nobody
+/// waits on it any longer
+pub fn idle(process: ProcessId) {
+    let process = owning_process(process);
+    REALTIME.lock().loans.remove(&process);
+}
+
+/// Forget the class of an exiting process and the loans it made
+pub fn release_process(process: ProcessId) {
+    REALTIME.lock().release(process);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_priority_is_the_highest_loan() {
+        let mut realtime = RealtimeClass::new();
+        let (input, compositor, service) = (ProcessId::new(9400), ProcessId::new(9401), ProcessId::new(9402));
+        realtime.base.insert(input, 50);
+        realtime.base.insert(compositor, 20);
+        assert_eq!(realtime.effective_priority(service), None);
+
+        realtime.lend(compositor, service);
+        realtime.lend(input, service);
+        assert_eq!(realtime.effective_priority(service), Some(50));
+
+        realtime.reclaim(input, service);
+        assert_eq!(realtime.effective_priority(service), Some(20));
+        realtime.release(compositor);
+        assert_eq!(realtime.effective_priority(service), None);
+        assert!(realtime.loans.is_empty());
+    }
+
+    #[test_case]
+    fn test_loans_are_not_stacked() {
+        let mut realtime = RealtimeClass::new();
+        let (donor, receiver) = (ProcessId::new(9410), ProcessId::new(9411));
+        realtime.base.insert(donor, 30);
+
+        realtime.lend(donor, receiver);
+        realtime.base.insert(donor, 40);
+        realtime.lend(donor, receiver);
+        assert_eq!(realtime.loans[&receiver], [(donor, 40)]);
+
+        // A process never lends to itself
+        realtime.lend(donor, donor);
+        assert!(!realtime.loans.contains_key(&donor));
+    }
+
+    #[test_case]
+    fn test_reply_ends_the_loan() {
+        let (client, server) = (ProcessId::new(9420), ProcessId::new(9421));
+        REALTIME.lock().base.insert(client, 60);
+
+        message_sent(client, server);
+        assert_eq!(effective_priority(server), Some(60));
+        message_sent(server, client);
+        assert_eq!(effective_priority(server), None);
+
+        message_sent(client, server);
+        idle(server);
+        assert_eq!(effective_priority(server), None);
+        release_process(client);
+        assert_eq!(effective_priority(client), None);
+    }
+}
@@ -1,9 +1,13 @@
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use spin::Mutex;
-use crate::process::{ProcessId, ProcessPriority, get_runnable_processes, get_process, set_current_process, get_current_process};
+use crate::process::{ProcessId, ProcessPriority, ProcessState, CpuMode, get_runnable_processes_on, steal_process, get_process, set_current_process, get_current_process};
 use crate::process::context::{CpuContext, ContextSwitcher};
-use crate::power::{power_policy, responsiveness, ProcessActivity};
-use crate::{serial_println, println};
+use crate::process::realtime;
+use crate::power::{power_policy, responsiveness, cpu_hotplug, cpu_scaling, ProcessActivity};
+use crate::power::power_policy::CorePreference;
+use crate::platform::{CoreCapacity, CoreClass};
+use crate::smp::{self, MAX_CPUS};
 
 /// Scheduler errors
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -27,6 +31,34 @@ pub enum SchedulingAlgorithm {
     CompletelyFair,
 }
 
+impl SchedulingAlgorithm {
+    /// Decode the algorithm value used by the scheduler system calls
+    pub fn from_raw(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(SchedulingAlgorithm::RoundRobin),
+            1 => Some(SchedulingAlgorithm::Priority),
+            2 => Some(SchedulingAlgorithm::CompletelyFair),
+            _ => None,
+        }
+    }
+
+    /// Value of the algorithm in the scheduler system calls
+    pub fn as_raw(self) -> u64 {
+        match self {
+            SchedulingAlgorithm::RoundRobin => 0,
+            SchedulingAlgorithm::Priority => 1,
+            SchedulingAlgorithm::CompletelyFair => 2,
+        }
+    }
+}
+
+/// Shortest and longest time slice that may be configured at runtime
+pub const MIN_TIME_SLICE_MS: u64 = 1;
+pub const MAX_TIME_SLICE_MS: u64 = 1000;
+
+/// Period over which CPU load is measured
+pub const LOAD_WINDOW_MS: u64 = 100;
+
 /// Scheduler statistics
 #[derive(Debug, Clone)]
 pub struct SchedulerStatistics {
@@ -40,10 +72,48 @@ pub struct SchedulerStatistics {
     pub algorithm: SchedulingAlgorithm,
     /// Time slice duration in milliseconds
     pub time_slice_ms: u64,
+    /// Time processes ran in user mode (milliseconds)
+    pub user_time_ms: u64,
+    /// Time processes spent in the kernel (milliseconds)
+    pub kernel_time_ms: u64,
+    /// Time with nothing to run (milliseconds)
+    pub idle_time_ms: u64,
+}
+
+/// Scheduler state as reported by the `sched_info` system call
+///
+/// `algorithm` holds the raw value from `SchedulingAlgorithm::as_raw`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct SchedInfo {
+    pub algorithm: u64,
+    pub time_slice_ms: u64,
+    pub context_switches: u64,
+    pub scheduling_decisions: u64,
+    pub scheduler_time_us: u64,
+    pub runnable_processes: u64,
+}
+
+impl SchedInfo {
+    pub fn new(stats: &SchedulerStatistics, runnable_processes: usize) -> Self {
+        Self {
+            algorithm: stats.algorithm.as_raw(),
+            time_slice_ms: stats.time_slice_ms,
+            context_switches: stats.context_switches,
+            scheduling_decisions: stats.scheduling_decisions,
+            scheduler_time_us: stats.scheduler_time_us,
+            runnable_processes: runnable_processes as u64,
+        }
+    }
 }
 
 /// Round-robin scheduler implementation
+///
+/// Each CPU has its own scheduler, which runs the processes homed on that
+/// CPU and steals from busier CPUs when it has none.
 pub struct Scheduler {
+    /// CPU whose processes this scheduler runs
+    cpu: usize,
     /// Current scheduling algorithm
     algorithm: SchedulingAlgorithm,
     /// Time slice for round-robin scheduling (in milliseconds)
@@ -54,12 +124,27 @@ pub struct Scheduler {
     stats: SchedulerStatistics,
     /// Priority queues for priority-based scheduling
     priority_queues: [Vec<ProcessId>; 4], // One queue per priority level
+    /// Per-core capacities; placement hints are only used when they differ
+    core_capacities: Vec<CoreCapacity>,
+    /// Time left before the current process is preempted (in milliseconds)
+    slice_remaining_ms: u64,
+    /// Real-time process scheduled last, so equal priorities take turns
+    last_realtime: Option<ProcessId>,
+    /// Busy and total time in the current load window (milliseconds)
+    load_busy_ms: u64,
+    load_window_ms: u64,
 }
 
 impl Scheduler {
-    /// Create a new scheduler
+    /// Create a new scheduler for the boot CPU
     pub fn new(algorithm: SchedulingAlgorithm, time_slice_ms: u64) -> Self {
+        Self::new_for_cpu(0, algorithm, time_slice_ms)
+    }
+    
+    /// Create a new scheduler for `cpu`
+    pub fn new_for_cpu(cpu: usize, algorithm: SchedulingAlgorithm, time_slice_ms: u64) -> Self {
         Self {
+            cpu,
             algorithm,
             time_slice_ms,
             last_scheduled_index: 0,
@@ -69,28 +154,96 @@ impl Scheduler {
                 scheduler_time_us: 0,
                 algorithm,
                 time_slice_ms,
+                user_time_ms: 0,
+                kernel_time_ms: 0,
+                idle_time_ms: 0,
             },
             priority_queues: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
+            core_capacities: Vec::new(),
+            slice_remaining_ms: 0,
+            last_realtime: None,
+            load_busy_ms: 0,
+            load_window_ms: 0,
         }
     }
     
+    /// Set the core capacities used for big.LITTLE placement
+    pub fn set_core_capacities(&mut self, core_capacities: Vec<CoreCapacity>) {
+        self.core_capacities = core_capacities;
+    }
+    
+    /// Pick a core for a process according to its power class
+    ///
+    /// Returns `None` on systems with uniform cores, where placement does
+    /// not affect power or latency.
+    fn select_core(&self, pid: ProcessId, priority: ProcessPriority) -> Option<u32> {
+        let first = self.core_capacities.first()?.capacity;
+        if self.core_capacities.iter().all(|core| core.capacity == first) {
+            return None;
+        }
+        
+        let wanted = match power_policy::get_core_preference(pid, priority) {
+            CorePreference::Big => Some(CoreClass::Big),
+            CorePreference::Little => Some(CoreClass::Little),
+            CorePreference::Any => None,
+        };
+        
+        let is_online = |core: &&CoreCapacity| {
+            cpu_hotplug::get_cpu_state(core.core_id) != Some(cpu_hotplug::CpuState::Parked)
+        };
+        let mut candidates: Vec<u32> = self.core_capacities.iter()
+            .filter(|core| wanted.map_or(true, |class| core.class == class))
+            .filter(is_online)
+            .map(|core| core.core_id)
+            .collect();
+        
+        // Fall back to any online core when the preferred cluster is parked
+        if candidates.is_empty() {
+            candidates = self.core_capacities.iter()
+                .filter(is_online)
+                .map(|core| core.core_id)
+                .collect();
+        }
+        
+        // Spread processes across the cluster
+        candidates.get(pid.0 as usize % candidates.len().max(1)).copied()
+    }
+    
     /// Schedule the next process to run
     pub fn schedule(&mut self) -> Result<Option<ProcessId>, SchedulerError> {
         let start_time = get_scheduler_time_us();
         self.stats.scheduling_decisions += 1;
         
-        let next_process = match self.algorithm {
-            SchedulingAlgorithm::RoundRobin => self.schedule_round_robin()?,
-            SchedulingAlgorithm::Priority => self.schedule_priority()?,
-            SchedulingAlgorithm::CompletelyFair => self.schedule_cfs()?,
+        // The real-time class runs ahead of whatever the algorithm picks
+        let realtime_process = self.schedule_realtime();
+        let next_process = match realtime_process {
+            Some(pid) => Some(pid),
+            None => match self.algorithm {
+                SchedulingAlgorithm::RoundRobin => self.schedule_round_robin()?,
+                SchedulingAlgorithm::Priority => self.schedule_priority()?,
+                SchedulingAlgorithm::CompletelyFair => self.schedule_cfs()?,
+            },
         };
         
         // Update current process if we found one to schedule
         if let Some(pid) = next_process {
+            // Every scheduling decision starts a fresh slice; real-time
+            // processes are not subject to power-aware shortening
+            self.slice_remaining_ms = if realtime_process.is_some() {
+                self.time_slice_ms.max(1)
+            } else {
+                self.get_power_aware_time_slice(pid).max(1)
+            };
+            
             let current = get_current_process();
             if current != Some(pid) {
-                set_current_process(Some(pid))
-                    .map_err(|_| SchedulerError::InvalidProcess)?;
+                if set_current_process(Some(pid)).is_err() {
+                    // Another CPU took the process since it was picked;
+                    // idle until the next tick
+                    set_current_process(None)
+                        .map_err(|_| SchedulerError::InvalidProcess)?;
+                    return Ok(None);
+                }
                 self.stats.context_switches += 1;
                 
                 // Notify power management of process activity
@@ -103,9 +256,16 @@ impl Scheduler {
                         ProcessPriority::Background => ProcessActivity::Background,
                     };
                     self.notify_power_management(pid, activity);
+                    
+                    // Bias placement towards big or LITTLE cores
+                    if let Some(core) = self.select_core(pid, process.priority) {
+                        if let Ok(placed) = cpu_hotplug::assign_process(pid, core) {
+                            log::debug!("Process {} placed on core {}", pid.0, placed);
+                        }
+                    }
                 }
                 
-                serial_println!("Scheduled process {} (algorithm: {:?})", pid.0, self.algorithm);
+                log::debug!("Scheduled process {} (algorithm: {:?})", pid.0, self.algorithm);
             }
         } else {
             // No process to schedule, clear current process
@@ -119,9 +279,62 @@ impl Scheduler {
         Ok(next_process)
     }
     
+    /// Runnable processes of this CPU, or one stolen from a busier CPU if
+    /// there are none
+    fn runnable_processes(&self) -> Vec<ProcessId> {
+        let runnable_processes = get_runnable_processes_on(self.cpu);
+        if !runnable_processes.is_empty() {
+            return runnable_processes;
+        }
+        steal_process(self.cpu).into_iter().collect()
+    }
+    
+    /// Runnable real-time processes of this CPU at the highest effective
+    /// priority, with that priority
+    fn top_realtime_processes(&self) -> Option<(u8, Vec<ProcessId>)> {
+        if !realtime::is_active() {
+            return None;
+        }
+        let mut top: Option<(u8, Vec<ProcessId>)> = None;
+        for pid in get_runnable_processes_on(self.cpu) {
+            let priority = match realtime::effective_priority(pid) {
+                Some(priority) => priority,
+                None => continue,
+            };
+            match &mut top {
+                Some((highest, pids)) if priority == *highest => pids.push(pid),
+                Some((highest, _)) if priority < *highest => {}
+                _ => top = Some((priority, alloc::vec![pid])),
+            }
+        }
+        top
+    }
+    
+    /// Pick the real-time process to run, if any is runnable
+    ///
+    /// Processes of equal priority take turns in process ID order.
+    fn schedule_realtime(&mut self) -> Option<ProcessId> {
+        let (_, pids) = self.top_realtime_processes()?;
+        let next = match self.last_realtime {
+            Some(last) => pids.iter().copied().find(|&pid| pid > last).unwrap_or(pids[0]),
+            None => pids[0],
+        };
+        self.last_realtime = Some(next);
+        Some(next)
+    }
+    
+    /// Whether a real-time process of higher priority than `current` is
+    /// waiting to run
+    fn realtime_preempts(&self, current: ProcessId) -> bool {
+        match self.top_realtime_processes() {
+            Some((highest, _)) => realtime::effective_priority(current).map_or(true, |own| highest > own),
+            None => false,
+        }
+    }
+    
     /// Round-robin scheduling implementation
     fn schedule_round_robin(&mut self) -> Result<Option<ProcessId>, SchedulerError> {
-        let runnable_processes = get_runnable_processes();
+        let runnable_processes = self.runnable_processes();
         
         if runnable_processes.is_empty() {
             return Ok(None);
@@ -187,7 +400,7 @@ impl Scheduler {
     
     /// Completely Fair Scheduler (CFS) implementation (simplified)
     fn schedule_cfs(&mut self) -> Result<Option<ProcessId>, SchedulerError> {
-        let runnable_processes = get_runnable_processes();
+        let runnable_processes = self.runnable_processes();
         
         if runnable_processes.is_empty() {
             return Ok(None);
@@ -217,7 +430,7 @@ impl Scheduler {
         }
         
         // Populate queues with current runnable processes
-        let runnable_processes = get_runnable_processes();
+        let runnable_processes = self.runnable_processes();
         
         for pid in runnable_processes {
             if let Some(process) = get_process(pid) {
@@ -252,7 +465,7 @@ impl Scheduler {
     
     /// Set scheduling algorithm
     pub fn set_algorithm(&mut self, algorithm: SchedulingAlgorithm) {
-        serial_println!("Changing scheduling algorithm from {:?} to {:?}", 
+        log::debug!("Changing scheduling algorithm from {:?} to {:?}", 
                        self.algorithm, algorithm);
         self.algorithm = algorithm;
         self.stats.algorithm = algorithm;
@@ -266,7 +479,7 @@ impl Scheduler {
     
     /// Set time slice duration
     pub fn set_time_slice(&mut self, time_slice_ms: u64) {
-        serial_println!("Changing time slice from {} ms to {} ms", 
+        log::debug!("Changing time slice from {} ms to {} ms", 
                        self.time_slice_ms, time_slice_ms);
         self.time_slice_ms = time_slice_ms;
         self.stats.time_slice_ms = time_slice_ms;
@@ -296,104 +509,270 @@ impl Scheduler {
     }
     
     /// Handle timer tick for preemptive scheduling
-    pub fn timer_tick(&mut self) -> Result<bool, SchedulerError> {
-        // For now, always trigger rescheduling on timer tick
-        // In a real implementation, this would check if the current process
-        // has exceeded its time slice
-        
+    ///
+    /// Charges `elapsed_ms`, spent in `mode`, to the current process and
+    /// picks another one once its time slice has run out, it stopped being
+    /// runnable or a higher real-time process waits. Returns true if the
+    /// current process changed.
+    pub fn timer_tick(&mut self, elapsed_ms: u64, mode: CpuMode) -> Result<bool, SchedulerError> {
         let current_process = get_current_process();
-        if current_process.is_some() {
-            // Trigger rescheduling
-            self.schedule()?;
-            Ok(true) // Rescheduling occurred
-        } else {
-            Ok(false) // No rescheduling needed
+        self.account_load(elapsed_ms, current_process.map(|_| mode));
+        let current_process = match current_process {
+            Some(pid) => pid,
+            // Idle: pick up processes that became ready
+            None => return Ok(self.schedule()?.is_some()),
+        };
+        
+        let _ = crate::process::charge_cpu_time(current_process, elapsed_ms, mode);
+        self.slice_remaining_ms = self.slice_remaining_ms.saturating_sub(elapsed_ms);
+        
+        let still_running = get_process(current_process)
+            .map_or(false, |process| process.state == ProcessState::Running);
+        // A real-time process that became runnable waits at most one tick
+        if still_running && self.slice_remaining_ms > 0 && !self.realtime_preempts(current_process) {
+            return Ok(false);
+        }
+        
+        let next_process = self.schedule()?;
+        Ok(next_process != Some(current_process))
+    }
+    
+    /// Count `elapsed_ms` as busy in `mode`, or idle if `None`, and publish
+    /// the CPU's load at the end of each window
+    ///
+    /// The boot CPU also hands the system-wide load to frequency scaling.
+    fn account_load(&mut self, elapsed_ms: u64, mode: Option<CpuMode>) {
+        match mode {
+            Some(CpuMode::User) => self.stats.user_time_ms += elapsed_ms,
+            Some(CpuMode::Kernel) => self.stats.kernel_time_ms += elapsed_ms,
+            None => self.stats.idle_time_ms += elapsed_ms,
+        }
+        if mode.is_some() {
+            self.load_busy_ms += elapsed_ms;
+        }
+        self.load_window_ms += elapsed_ms;
+        if self.load_window_ms < LOAD_WINDOW_MS {
+            return;
+        }
+        
+        let load = (self.load_busy_ms * 100 / self.load_window_ms).min(100) as u8;
+        if let Some(slot) = CPU_LOAD.get(self.cpu) {
+            slot.store(load, Ordering::Relaxed);
         }
+        self.load_busy_ms = 0;
+        self.load_window_ms = 0;
+        if self.cpu == 0 {
+            // Nothing to feed before frequency scaling is up
+            let _ = cpu_scaling::update_load(cpu_load_percent());
+        }
+    }
+    
+    /// Give up the rest of the current time slice
+    pub fn yield_current(&mut self) {
+        self.slice_remaining_ms = 0;
     }
     
     /// Print scheduler information
     pub fn print_info(&self) {
-        serial_println!("Scheduler Information:");
-        serial_println!("  Algorithm: {:?}", self.algorithm);
-        serial_println!("  Time slice: {} ms", self.time_slice_ms);
-        serial_println!("  Context switches: {}", self.stats.context_switches);
-        serial_println!("  Scheduling decisions: {}", self.stats.scheduling_decisions);
-        serial_println!("  Scheduler overhead: {} μs", self.stats.scheduler_time_us);
+        log::debug!("Scheduler Information (CPU {}):", self.cpu);
+        log::debug!("  Algorithm: {:?}", self.algorithm);
+        log::debug!("  Time slice: {} ms", self.time_slice_ms);
+        log::debug!("  Context switches: {}", self.stats.context_switches);
+        log::debug!("  Scheduling decisions: {}", self.stats.scheduling_decisions);
+        log::debug!("  Scheduler overhead: {} μs", self.stats.scheduler_time_us);
         
         if self.algorithm == SchedulingAlgorithm::Priority {
-            serial_println!("  Priority queue sizes:");
-            serial_println!("    System: {}", self.priority_queues[0].len());
-            serial_println!("    Interactive: {}", self.priority_queues[1].len());
-            serial_println!("    Normal: {}", self.priority_queues[2].len());
-            serial_println!("    Background: {}", self.priority_queues[3].len());
+            log::debug!("  Priority queue sizes:");
+            log::debug!("    System: {}", self.priority_queues[0].len());
+            log::debug!("    Interactive: {}", self.priority_queues[1].len());
+            log::debug!("    Normal: {}", self.priority_queues[2].len());
+            log::debug!("    Background: {}", self.priority_queues[3].len());
         }
         
-        println!("Scheduler: {:?} algorithm, {} context switches", 
-                self.algorithm, self.stats.context_switches);
+        log::info!("Scheduler (CPU {}): {:?} algorithm, {} context switches", 
+                self.cpu, self.algorithm, self.stats.context_switches);
     }
 }
 
-/// Global scheduler instance
-static SCHEDULER: Mutex<Option<Scheduler>> = Mutex::new(None);
+/// Scheduler of each CPU, set up as the CPU comes online
+static SCHEDULERS: [Mutex<Option<Scheduler>>; MAX_CPUS] = [const { Mutex::new(None) }; MAX_CPUS];
 
 /// Default time slice in milliseconds
 const DEFAULT_TIME_SLICE_MS: u64 = 10;
 
+/// Frequency of the timer interrupt driving preemption
+pub const TIMER_FREQUENCY_HZ: u32 = 1000;
+
+/// Time between two timer interrupts in milliseconds
+pub const TICK_MS: u64 = 1000 / TIMER_FREQUENCY_HZ as u64;
+
+/// Set when a CPU's current process should be switched out at the next
+/// opportunity, e.g. after it yielded from a system call
+static NEED_RESCHED: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+
+/// Busy share of each CPU over its last complete load window, in percent
+///
+/// Kept outside the schedulers so the load can be read without taking
+/// their locks.
+static CPU_LOAD: [AtomicU8; MAX_CPUS] = [const { AtomicU8::new(0) }; MAX_CPUS];
+
 /// Initialize the global scheduler
 pub fn init_scheduler() -> Result<(), &'static str> {
-    serial_println!("Initializing scheduler...");
+    log::info!("Initializing scheduler...");
     
-    let scheduler = Scheduler::new(SchedulingAlgorithm::RoundRobin, DEFAULT_TIME_SLICE_MS);
-    *SCHEDULER.lock() = Some(scheduler);
+    let mut scheduler = Scheduler::new(SchedulingAlgorithm::RoundRobin, DEFAULT_TIME_SLICE_MS);
+    
+    let cpu_info = crate::platform::current_platform().get_cpu_info();
+    if cpu_info.is_heterogeneous() {
+        log::debug!("Heterogeneous CPU: {} big, {} LITTLE cores",
+                       cpu_info.cores_of_class(CoreClass::Big).len(),
+                       cpu_info.cores_of_class(CoreClass::Little).len());
+    }
+    scheduler.set_core_capacities(cpu_info.core_capacities);
+    *SCHEDULERS[0].lock() = Some(scheduler);
     
-    serial_println!("Scheduler initialized with round-robin algorithm and {} ms time slice", 
+    log::info!("Scheduler initialized with round-robin algorithm and {} ms time slice", 
                    DEFAULT_TIME_SLICE_MS);
     Ok(())
 }
 
-/// Schedule the next process
+/// Initialize the scheduler of a secondary CPU with the boot CPU's settings
+pub fn init_cpu_scheduler(cpu: usize) -> Result<(), &'static str> {
+    let slot = SCHEDULERS.get(cpu).ok_or("CPU index out of range")?;
+    let scheduler = {
+        let boot = SCHEDULERS[0].lock();
+        let boot = boot.as_ref().ok_or("boot CPU scheduler not initialized")?;
+        let mut scheduler = Scheduler::new_for_cpu(cpu, boot.algorithm, boot.time_slice_ms);
+        scheduler.set_core_capacities(boot.core_capacities.clone());
+        scheduler
+    };
+    *slot.lock() = Some(scheduler);
+    Ok(())
+}
+
+/// Apply `f` to the scheduler of every CPU that has one
+fn for_each_scheduler(mut f: impl FnMut(&mut Scheduler)) -> Result<(), SchedulerError> {
+    if SCHEDULERS[0].lock().is_none() {
+        return Err(SchedulerError::NotInitialized);
+    }
+    for scheduler in &SCHEDULERS {
+        if let Some(scheduler) = scheduler.lock().as_mut() {
+            f(scheduler);
+        }
+    }
+    Ok(())
+}
+
+/// Schedule the next process on the calling CPU
 pub fn schedule_next_process() -> Result<Option<ProcessId>, SchedulerError> {
-    let mut scheduler = SCHEDULER.lock();
+    let mut scheduler = SCHEDULERS[smp::current_cpu()].lock();
     let scheduler = scheduler.as_mut().ok_or(SchedulerError::NotInitialized)?;
     scheduler.schedule()
 }
 
 /// Handle timer tick
-pub fn handle_timer_tick() -> Result<bool, SchedulerError> {
-    let mut scheduler = SCHEDULER.lock();
+///
+/// Called once per timer interrupt, with the mode the tick was spent in.
+/// Returns true if the current process changed and the interrupted context
+/// must be switched.
+pub fn handle_timer_tick(mode: CpuMode) -> Result<bool, SchedulerError> {
+    let cpu = smp::current_cpu();
+    
+    // System-wide timekeeping runs on the boot CPU only
+    if cpu == 0 {
+        // Wake processes whose poll timeout expired before picking the next one
+        crate::ipc::poll::timer_tick(TICK_MS);
+        crate::process::timer::timer_tick();
+        
+        // Terminal switches and scrolling asked for from the keyboard
+        crate::vt::timer_tick();
+        
+        // Serial input the UART's interrupt left behind
+        crate::serial::receive();
+        
+        // Battery, thermal zones and CPU frequency; the policy keeps its own interval
+        crate::power::power_policy::timer_tick(crate::time::monotonic_ms());
+        
+        // Debug builds sweep heap redzones and quarantined blocks periodically
+        #[cfg(debug_assertions)]
+        crate::memory::heap::periodic_integrity_check();
+    }
+    
+    crate::monitor::timer_tick(cpu, TICK_MS, get_current_process().is_some());
+    
+    let mut scheduler = SCHEDULERS[cpu].lock();
     let scheduler = scheduler.as_mut().ok_or(SchedulerError::NotInitialized)?;
-    scheduler.timer_tick()
+    if NEED_RESCHED[cpu].swap(false, Ordering::SeqCst) {
+        scheduler.yield_current();
+    }
+    scheduler.timer_tick(TICK_MS, mode)
+}
+
+/// Load of the online CPUs over their last load window, in percent
+pub fn cpu_load_percent() -> u8 {
+    let (cpus, total) = smp::online_cpus()
+        .filter_map(|cpu| CPU_LOAD.get(cpu))
+        .fold((0u32, 0u32), |(cpus, total), load| (cpus + 1, total + load.load(Ordering::Relaxed) as u32));
+    if cpus == 0 {
+        return 0;
+    }
+    (total / cpus) as u8
+}
+
+/// Give up the CPU on behalf of `pid`
+///
+/// The switch happens at the next timer tick; the caller returns to user
+/// space first. Yielding is a hint, so a process that is not running has
+/// nothing to give up. A running process always runs on its home CPU.
+pub fn yield_process(pid: ProcessId) {
+    if let Some(process) = get_process(pid) {
+        if process.state == ProcessState::Running {
+            NEED_RESCHED[process.cpu].store(true, Ordering::SeqCst);
+        }
+    }
 }
 
 /// Set scheduling algorithm
 pub fn set_scheduling_algorithm(algorithm: SchedulingAlgorithm) -> Result<(), SchedulerError> {
-    let mut scheduler = SCHEDULER.lock();
-    let scheduler = scheduler.as_mut().ok_or(SchedulerError::NotInitialized)?;
-    scheduler.set_algorithm(algorithm);
-    Ok(())
+    for_each_scheduler(|scheduler| scheduler.set_algorithm(algorithm))
 }
 
 /// Set time slice duration
 pub fn set_time_slice(time_slice_ms: u64) -> Result<(), SchedulerError> {
-    let mut scheduler = SCHEDULER.lock();
-    let scheduler = scheduler.as_mut().ok_or(SchedulerError::NotInitialized)?;
-    scheduler.set_time_slice(time_slice_ms);
-    Ok(())
+    for_each_scheduler(|scheduler| scheduler.set_time_slice(time_slice_ms))
 }
 
-/// Get scheduler statistics
+/// Get scheduler statistics, summed over all CPUs
 pub fn get_scheduler_statistics() -> Option<SchedulerStatistics> {
-    let scheduler = SCHEDULER.lock();
-    scheduler.as_ref().map(|s| s.get_statistics())
+    let mut stats = SCHEDULERS[0].lock().as_ref()?.get_statistics();
+    for scheduler in &SCHEDULERS[1..] {
+        if let Some(scheduler) = scheduler.lock().as_ref() {
+            stats.context_switches += scheduler.stats.context_switches;
+            stats.scheduling_decisions += scheduler.stats.scheduling_decisions;
+            stats.scheduler_time_us += scheduler.stats.scheduler_time_us;
+            stats.user_time_ms += scheduler.stats.user_time_ms;
+            stats.kernel_time_ms += scheduler.stats.kernel_time_ms;
+            stats.idle_time_ms += scheduler.stats.idle_time_ms;
+        }
+    }
+    Some(stats)
+}
+
+/// Whether `time_slice_ms` may be configured at runtime
+pub fn is_valid_time_slice(time_slice_ms: u64) -> bool {
+    (MIN_TIME_SLICE_MS..=MAX_TIME_SLICE_MS).contains(&time_slice_ms)
 }
 
-/// Print scheduler information
+/// Print scheduler information for every CPU
 pub fn print_scheduler_info() {
-    let scheduler = SCHEDULER.lock();
-    if let Some(scheduler) = scheduler.as_ref() {
-        scheduler.print_info();
-    } else {
-        serial_println!("Scheduler not initialized");
+    if SCHEDULERS[0].lock().is_none() {
+        log::info!("Scheduler not initialized");
+        return;
+    }
+    for scheduler in &SCHEDULERS {
+        if let Some(scheduler) = scheduler.lock().as_ref() {
+            scheduler.print_info();
+        }
     }
 }
 
@@ -450,4 +829,117 @@ mod tests {
         assert_eq!(stats.scheduling_decisions, 0);
         assert_eq!(stats.scheduler_time_us, 0);
     }
-}
\ No newline at end of file
+    
+    #[test_case]
+    fn test_algorithm_raw_values() {
+        for algorithm in [SchedulingAlgorithm::RoundRobin, SchedulingAlgorithm::Priority, SchedulingAlgorithm::CompletelyFair] {
+            assert_eq!(SchedulingAlgorithm::from_raw(algorithm.as_raw()), Some(algorithm));
+        }
+        assert_eq!(SchedulingAlgorithm::from_raw(3), None);
+        
+        assert!(!is_valid_time_slice(0));
+        assert!(is_valid_time_slice(MIN_TIME_SLICE_MS));
+        assert!(is_valid_time_slice(MAX_TIME_SLICE_MS));
+        assert!(!is_valid_time_slice(MAX_TIME_SLICE_MS + 1));
+    }
+    
+    #[test_case]
+    fn test_core_selection() {
+        let mut scheduler = Scheduler::new(SchedulingAlgorithm::RoundRobin, 10);
+        let pid = ProcessId::new(42);
+        
+        scheduler.set_core_capacities(CoreCapacity::uniform(4, 2000));
+        assert_eq!(scheduler.select_core(pid, ProcessPriority::Interactive), None);
+        
+        scheduler.set_core_capacities(CoreCapacity::from_device_tree(&[(381, 1800), (381, 1800), (1024, 2800), (1024, 2800)]));
+        let core = scheduler.select_core(pid, ProcessPriority::Interactive).unwrap();
+        assert!(core < 4);
+    }
+    
+    #[test_case]
+    fn test_core_preference_by_priority() {
+        let policy = power_policy::PowerPolicyManager::new();
+        let pid = ProcessId::new(43);
+        
+        assert_eq!(policy.get_core_preference(pid, ProcessPriority::System), CorePreference::Big);
+        assert_eq!(policy.get_core_preference(pid, ProcessPriority::Interactive), CorePreference::Big);
+        assert_eq!(policy.get_core_preference(pid, ProcessPriority::Background), CorePreference::Little);
+    }
+    
+    #[test_case]
+    fn test_time_slice_preemption() {
+        init_process_table().unwrap();
+        let first = create_process(None, "slice_a".to_string(), ProcessPriority::Normal).unwrap();
+        let second = create_process(None, "slice_b".to_string(), ProcessPriority::Normal).unwrap();
+        
+        let mut scheduler = Scheduler::new(SchedulingAlgorithm::RoundRobin, 10);
+        assert_eq!(scheduler.schedule(), Ok(Some(first)));
+        
+        // A single tick does not use up the slice
+        assert_eq!(scheduler.timer_tick(1, CpuMode::User), Ok(false));
+        assert_eq!(get_current_process(), Some(first));
+        
+        let mut ticks = 1;
+        while scheduler.timer_tick(1, CpuMode::User) == Ok(false) {
+            ticks += 1;
+            assert!(ticks < 100, "time slice never expired");
+        }
+        assert_eq!(get_current_process(), Some(second));
+        assert!(get_process(first).unwrap().cpu_time_ms >= ticks);
+        
+        // Yielding ends the slice at the next tick
+        scheduler.yield_current();
+        assert_eq!(scheduler.timer_tick(1, CpuMode::User), Ok(true));
+        assert_eq!(get_current_process(), Some(first));
+    }
+    
+    #[test_case]
+    fn test_load_accounting() {
+        let mut scheduler = Scheduler::new(SchedulingAlgorithm::RoundRobin, 10);
+        for ms in 0..LOAD_WINDOW_MS {
+            let mode = match ms % 4 {
+                0 | 1 => Some(CpuMode::User),
+                2 => Some(CpuMode::Kernel),
+                _ => None,
+            };
+            scheduler.account_load(1, mode);
+        }
+        
+        let stats = scheduler.get_statistics();
+        assert_eq!(stats.user_time_ms, LOAD_WINDOW_MS / 2);
+        assert_eq!(stats.kernel_time_ms, LOAD_WINDOW_MS / 4);
+        assert_eq!(stats.idle_time_ms, LOAD_WINDOW_MS / 4);
+        // Only the boot CPU is online under test
+        assert_eq!(cpu_load_percent(), 75);
+        assert_eq!(scheduler.load_window_ms, 0);
+    }
+    
+    #[test_case]
+    fn test_realtime_process_preempts() {
+        init_process_table().unwrap();
+        let normal = create_process(None, "rt_normal".to_string(), ProcessPriority::System).unwrap();
+        let input = create_process(None, "rt_input".to_string(), ProcessPriority::Background).unwrap();
+        let server = create_process(None, "rt_server".to_string(), ProcessPriority::Normal).unwrap();
+        crate::process::set_process_state(input, ProcessState::Blocked(crate::process::BlockReason::WaitingForMessage)).unwrap();
+        crate::process::set_process_state(server, ProcessState::Blocked(crate::process::BlockReason::WaitingForMessage)).unwrap();
+        realtime::set_priority(input, Some(50)).unwrap();
+        
+        let mut scheduler = Scheduler::new(SchedulingAlgorithm::Priority, 10);
+        assert_eq!(scheduler.schedule(), Ok(Some(normal)));
+        
+        // Woken by input, the real-time process runs at the next tick
+        crate::process::set_process_state(input, ProcessState::Ready).unwrap();
+        assert_eq!(scheduler.timer_tick(1, CpuMode::User), Ok(true));
+        assert_eq!(get_current_process(), Some(input));
+        
+        // The server it waits on runs ahead of the normal process
+        realtime::message_sent(input, server);
+        crate::process::set_process_state(input, ProcessState::Blocked(crate::process::BlockReason::WaitingForMessage)).unwrap();
+        crate::process::set_process_state(server, ProcessState::Ready).unwrap();
+        assert_eq!(scheduler.timer_tick(1, CpuMode::User), Ok(true));
+        assert_eq!(get_current_process(), Some(server));
+        
+        realtime::release_process(input);
+        assert_eq!(realtime::effective_priority(server), None);
+    }
+}
@@ -0,0 +1,158 @@
+//! POSIX-style signals
+//!
+//! Each process carries a bitmap of pending signals. Signals are posted by
+//! `sys_kill` or by the kernel (SIGCHLD on child exit) and acted upon when
+//! the target process next returns from a system call. User-installed
+//! handlers are not supported yet, so every signal takes its default action.
+
+/// Hangup
+pub const SIGHUP: u32 = 1;
+/// Interrupt from keyboard
+pub const SIGINT: u32 = 2;
+/// Quit from keyboard
+pub const SIGQUIT: u32 = 3;
+/// Illegal instruction
+pub const SIGILL: u32 = 4;
+/// Trace or breakpoint trap
+pub const SIGTRAP: u32 = 5;
+/// Abort
+pub const SIGABRT: u32 = 6;
+/// Arithmetic exception
+pub const SIGFPE: u32 = 8;
+/// Kill (cannot be caught or ignored)
+pub const SIGKILL: u32 = 9;
+/// User-defined signal 1
+pub const SIGUSR1: u32 = 10;
+/// Invalid memory reference
+pub const SIGSEGV: u32 = 11;
+/// User-defined signal 2
+pub const SIGUSR2: u32 = 12;
+/// Broken pipe
+pub const SIGPIPE: u32 = 13;
+/// Alarm clock
+pub const SIGALRM: u32 = 14;
+/// Termination request
+pub const SIGTERM: u32 = 15;
+/// Child stopped or terminated
+pub const SIGCHLD: u32 = 17;
+/// Continue if stopped
+pub const SIGCONT: u32 = 18;
+/// Window size change
+pub const SIGWINCH: u32 = 28;
+
+/// Number of supported signals (1..=MAX_SIGNAL)
+pub const MAX_SIGNAL: u32 = 64;
+
+/// Exit status offset for processes terminated by a signal
+pub const SIGNAL_EXIT_BASE: i32 = 128;
+
+/// What happens when a signal is delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalAction {
+    /// Terminate the process
+    Terminate,
+    /// Discard the signal
+    Ignore,
+}
+
+/// Default disposition of a signal
+pub fn default_action(signal: u32) -> SignalAction {
+    match signal {
+        SIGCHLD | SIGCONT | SIGWINCH => SignalAction::Ignore,
+        _ => SignalAction::Terminate,
+    }
+}
+
+/// Check whether a signal number is deliverable
+pub fn is_valid_signal(signal: u32) -> bool {
+    signal >= 1 && signal <= MAX_SIGNAL
+}
+
+/// Exit code reported to the parent of a process killed by `signal`
+pub fn exit_code_for(signal: u32) -> i32 {
+    SIGNAL_EXIT_BASE + signal as i32
+}
+
+/// Set of pending signals, one bit per signal number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SignalSet(u64);
+
+impl SignalSet {
+    /// Create an empty signal set
+    pub const fn empty() -> Self {
+        SignalSet(0)
+    }
+
+    fn bit(signal: u32) -> u64 {
+        1u64 << (signal - 1)
+    }
+
+    /// Mark a signal as pending
+    pub fn add(&mut self, signal: u32) {
+        if is_valid_signal(signal) {
+            self.0 |= Self::bit(signal);
+        }
+    }
+
+    /// Check whether a signal is pending
+    pub fn contains(&self, signal: u32) -> bool {
+        is_valid_signal(signal) && self.0 & Self::bit(signal) != 0
+    }
+
+    /// Check whether no signal is pending
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Remove and return the next signal to deliver
+    ///
+    /// SIGKILL always goes first; the rest are taken lowest number first.
+    pub fn take_next(&mut self) -> Option<u32> {
+        if self.contains(SIGKILL) {
+            self.0 &= !Self::bit(SIGKILL);
+            return Some(SIGKILL);
+        }
+        if self.0 == 0 {
+            return None;
+        }
+        let signal = self.0.trailing_zeros() + 1;
+        self.0 &= !Self::bit(signal);
+        Some(signal)
+    }
+
+    /// Raw bitmap (bit N-1 is signal N)
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_signal_set_ordering() {
+        let mut set = SignalSet::empty();
+        set.add(SIGTERM);
+        set.add(SIGCHLD);
+        set.add(SIGKILL);
+        set.add(SIGHUP);
+        set.add(0);
+        set.add(MAX_SIGNAL + 1);
+
+        assert_eq!(set.take_next(), Some(SIGKILL));
+        assert_eq!(set.take_next(), Some(SIGHUP));
+        assert_eq!(set.take_next(), Some(SIGTERM));
+        assert_eq!(set.take_next(), Some(SIGCHLD));
+        assert_eq!(set.take_next(), None);
+        assert!(set.is_empty());
+    }
+
+    #[test_case]
+    fn test_default_actions() {
+        assert_eq!(default_action(SIGTERM), SignalAction::Terminate);
+        assert_eq!(default_action(SIGKILL), SignalAction::Terminate);
+        assert_eq!(default_action(SIGCHLD), SignalAction::Ignore);
+        assert_eq!(exit_code_for(SIGKILL), 137);
+    }
+}
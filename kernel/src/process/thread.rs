@@ -0,0 +1,265 @@
+//! Threads
+//!
+//! A thread is a second line of execution inside a process. It has an
+//! entry in the process table of its own, with its own ID, state, CPU
+//! context and home CPU, so the scheduler runs it like any process and
+//! threads of one process run in parallel on different CPUs. Everything
+//! else is the owner's: `with_address_space`, `with_fd_table` and
+//! `with_args` look through a thread to its owner, and system calls made
+//! by a thread act for the owner except where the caller itself sleeps
+//! (nanosleep, futex waits, yield) or manages threads.
+//!
+//! What only threads have lives here: a guarded kernel stack and the exit
+//! code kept for `join`. Like a zombie process, an exited thread keeps its
+//! record until another thread of the process joins it. Threads end with
+//! their process.
+//!
+//! TODO: System call and interrupt entry still run on the CPU's own stack;
+//! they are to switch to the kernel stack of the thread they came from.
+//!
+//! Messages are queued for the process, not for one of its threads, so
+//! only one thread should receive them.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::memory::kstack::{KernelStack, StackOwner};
+use crate::process::{ProcessId, ProcessState, BlockReason, ProcessError, CpuContext, get_process, set_process_state};
+
+/// Size of each thread's kernel stack
+pub const KERNEL_STACK_SIZE: usize = 16 * 1024;
+
+/// Most threads one process may have, not counting itself
+pub const MAX_THREADS_PER_PROCESS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadError {
+    /// No such thread in the caller's process
+    NotFound,
+    /// The caller may not do this, e.g. join itself or exit as a thread
+    /// when it is a process
+    InvalidThread,
+    /// Another thread is already joining this one
+    AlreadyJoined,
+    /// The process already has `MAX_THREADS_PER_PROCESS` threads
+    TooManyThreads,
+    OutOfMemory,
+    /// The caller was blocked until the thread exits
+    WouldBlock,
+}
+
+impl From<ProcessError> for ThreadError {
+    fn from(error: ProcessError) -> Self {
+        match error {
+            ProcessError::OutOfMemory | ProcessError::ProcessTableFull => ThreadError::OutOfMemory,
+            _ => ThreadError::NotFound,
+        }
+    }
+}
+
+struct Thread {
+    /// Process the thread belongs to
+    process: ProcessId,
+    /// Dropped once the thread has exited
+    kernel_stack: Option<KernelStack>,
+    /// Set when the thread exits
+    exit_code: Option<i32>,
+    /// Thread or process waiting in `join`
+    joiner: Option<ProcessId>,
+}
+
+static THREADS: Mutex<BTreeMap<ProcessId, Thread>> = Mutex::new(BTreeMap::new());
+
+/// Start a thread of `process` at `entry` on the user stack `stack_top`,
+/// with `arg` in the first argument register
+///
+/// Returns the ID of the new thread.
+pub fn create(process: ProcessId, entry: u64, stack_top: u64, arg: u64) -> Result<ProcessId, ThreadError> {
+    let threads = THREADS.lock().values().filter(|thread| thread.process == process).count();
+    if threads >= MAX_THREADS_PER_PROCESS {
+        return Err(ThreadError::TooManyThreads);
+    }
+
+    let tid = crate::process::create_thread(process)?;
+    let owner = StackOwner::Thread { process, thread: tid };
+    let kernel_stack = match KernelStack::allocate(KERNEL_STACK_SIZE, owner) {
+        Ok(stack) => stack,
+        Err(e) => {
+            log::debug!("No kernel stack for a thread of process {}: {}", process.0, e);
+            let _ = crate::process::remove_process(tid);
+            return Err(ThreadError::OutOfMemory);
+        }
+    };
+    let mut context = CpuContext::new_user_process(entry, stack_top);
+    context.rdi = arg;
+    if crate::process::with_cpu_context(tid, |thread_context| *thread_context = context).is_err() {
+        return Err(ThreadError::NotFound);
+    }
+    log::debug!("Thread {} of process {} starts at {:#x}, kernel stack at {:#x}",
+                tid.0, process.0, entry, kernel_stack.top());
+    THREADS.lock().insert(tid, Thread { process, kernel_stack: Some(kernel_stack), exit_code: None, joiner: None });
+    Ok(tid)
+}
+
+/// End the calling thread with `exit_code`
+///
+/// The thread leaves the scheduler at once; its exit code is kept until
+/// it is joined.
+pub fn exit(tid: ProcessId, exit_code: i32) -> Result<(), ThreadError> {
+    let joiner = {
+        let mut threads = THREADS.lock();
+        let thread = threads.get_mut(&tid).ok_or(ThreadError::InvalidThread)?;
+        thread.exit_code = Some(exit_code);
+        thread.kernel_stack = None;
+        thread.joiner
+    };
+    // Threads have no parent, so the entry leaves the table at once
+    let _ = crate::process::exit_process(tid, exit_code);
+    if let Some(joiner) = joiner {
+        wake_joiner(joiner);
+    }
+    Ok(())
+}
+
+/// Collect the exit code of thread `tid` for `caller`
+///
+/// `caller` may be the process or any other of its threads. If the thread
+/// is still running, the caller is blocked and `WouldBlock` returned; once
+/// woken it repeats the call to collect the exit code.
+pub fn join(caller: ProcessId, tid: ProcessId) -> Result<i32, ThreadError> {
+    if caller == tid {
+        return Err(ThreadError::InvalidThread);
+    }
+    let process = crate::process::owning_process(caller);
+    {
+        let mut threads = THREADS.lock();
+        let thread = threads.get_mut(&tid)
+            .filter(|thread| thread.process == process)
+            .ok_or(ThreadError::NotFound)?;
+        if let Some(exit_code) = thread.exit_code {
+            threads.remove(&tid);
+            return Ok(exit_code);
+        }
+        match thread.joiner {
+            Some(joiner) if joiner != caller => return Err(ThreadError::AlreadyJoined),
+            _ => thread.joiner = Some(caller),
+        }
+    }
+
+    let _ = set_process_state(caller, ProcessState::Blocked(BlockReason::WaitingForThread));
+    // The thread may have exited while the caller was being blocked
+    let mut threads = THREADS.lock();
+    if let Some(exit_code) = threads.get(&tid).and_then(|thread| thread.exit_code) {
+        threads.remove(&tid);
+        let _ = set_process_state(caller, ProcessState::Ready);
+        return Ok(exit_code);
+    }
+    Err(ThreadError::WouldBlock)
+}
+
+fn wake_joiner(joiner: ProcessId) {
+    let blocked = get_process(joiner)
+        .is_some_and(|info| info.state == ProcessState::Blocked(BlockReason::WaitingForThread));
+    if blocked {
+        let _ = set_process_state(joiner, ProcessState::Ready);
+    }
+}
+
+/// Top of the kernel stack of a running thread
+pub fn kernel_stack_top(tid: ProcessId) -> Option<u64> {
+    THREADS.lock().get(&tid)?.kernel_stack.as_ref().map(KernelStack::top)
+}
+
+/// A thread as the debug monitor lists it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadInfo {
+    pub tid: ProcessId,
+    pub process: ProcessId,
+    /// Kernel stack range, while the thread has not exited
+    pub kernel_stack: Option<(usize, u64)>,
+    pub exit_code: Option<i32>,
+}
+
+/// Every thread, or None while the table is locked
+///
+/// Never waits, so it can run from a panic on the CPU that held the lock.
+pub fn try_list() -> Option<Vec<ThreadInfo>> {
+    let threads = THREADS.try_lock()?;
+    Some(threads.iter()
+        .map(|(&tid, thread)| ThreadInfo {
+            tid,
+            process: thread.process,
+            kernel_stack: thread.kernel_stack.as_ref().map(|stack| (stack.bottom(), stack.top())),
+            exit_code: thread.exit_code,
+        })
+        .collect())
+}
+
+/// End every thread of a terminating process
+///
+/// Returns the threads, whose sleeps, timers and futex waits the caller
+/// releases as well.
+pub fn release_process(process: ProcessId) -> Vec<ProcessId> {
+    let tids: Vec<ProcessId> = {
+        let mut threads = THREADS.lock();
+        let tids = threads.iter()
+            .filter(|(_, thread)| thread.process == process)
+            .map(|(&tid, _)| tid)
+            .collect();
+        threads.retain(|_, thread| thread.process != process);
+        tids
+    };
+    // Threads that are still running leave the table here
+    for &tid in &tids {
+        let _ = crate::process::remove_process(tid);
+    }
+    tids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thread(process: u32) -> Thread {
+        Thread { process: ProcessId::new(process), kernel_stack: None, exit_code: None, joiner: None }
+    }
+
+    #[test_case]
+    fn test_kernel_stack_top_is_aligned() {
+        let owner = StackOwner::Thread { process: ProcessId::new(9310), thread: ProcessId::new(9311) };
+        let stack = KernelStack::allocate(KERNEL_STACK_SIZE, owner).expect("kernel stack");
+        let bottom = stack.bottom() as u64;
+        assert_eq!(stack.top() % 16, 0);
+        assert!(stack.top() > bottom && stack.top() <= bottom + KERNEL_STACK_SIZE as u64);
+    }
+
+    #[test_case]
+    fn test_join_collects_exit_code_once() {
+        let process = ProcessId::new(9300);
+        let tid = ProcessId::new(9301);
+        THREADS.lock().insert(tid, Thread { exit_code: Some(7), ..thread(9300) });
+
+        assert_eq!(join(process, tid), Ok(7));
+        assert_eq!(join(process, tid), Err(ThreadError::NotFound));
+    }
+
+    #[test_case]
+    fn test_join_is_limited_to_the_process() {
+        let tid = ProcessId::new(9311);
+        THREADS.lock().insert(tid, Thread { exit_code: Some(0), ..thread(9310) });
+
+        assert_eq!(join(tid, tid), Err(ThreadError::InvalidThread));
+        assert_eq!(join(ProcessId::new(9312), tid), Err(ThreadError::NotFound));
+        assert_eq!(release_process(ProcessId::new(9310)), [tid]);
+        assert!(THREADS.lock().get(&tid).is_none());
+    }
+
+    #[test_case]
+    fn test_second_joiner_is_refused() {
+        let tid = ProcessId::new(9321);
+        THREADS.lock().insert(tid, Thread { joiner: Some(ProcessId::new(9322)), ..thread(9320) });
+
+        assert_eq!(join(ProcessId::new(9320), tid), Err(ThreadError::AlreadyJoined));
+        THREADS.lock().remove(&tid);
+    }
+}
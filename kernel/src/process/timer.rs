@@ -0,0 +1,409 @@
+//! Sleeps and process timers
+//!
+//! Every pending expiry sits in a hashed timer wheel with one slot per
+//! scheduler tick, advanced from the tick on the boot CPU. An expiry more
+//! than one turn of the wheel away stays in its slot for as many turns as
+//! it needs, so arming and firing cost the same however far off the
+//! deadline is.
+//!
+//! A sleeping process, or one waiting on a timer, is blocked with
+//! `BlockReason::Sleeping`. As with `poll`, it is woken when its time is
+//! up and repeats the call to collect the result. The wheel also keeps the
+//! timeouts of futex waits, which it hands to `ipc::futex` when due.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::process::{ProcessId, ProcessState, BlockReason, get_process, set_process_state};
+use crate::process::scheduler::TICK_MS;
+use crate::time::NANOS_PER_MILLI;
+
+/// Slots in the timer wheel, one per tick
+pub const WHEEL_SLOTS: usize = 256;
+
+/// Most timers one process may hold
+pub const MAX_TIMERS_PER_PROCESS: usize = 32;
+
+/// A process's name for one of its timers
+pub type TimerId = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerError {
+    /// The caller has no timer with this ID
+    NotFound,
+    /// The caller already holds `MAX_TIMERS_PER_PROCESS` timers
+    TooManyTimers,
+    /// The timer is disarmed with nothing pending, so a wait would never end
+    NotArmed,
+    /// The caller was blocked until its time is up
+    WouldBlock,
+}
+
+/// What an expiry is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Sleep(ProcessId),
+    Timer(ProcessId, TimerId),
+    /// Timeout of a futex wait
+    FutexTimeout(ProcessId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Expiry {
+    /// Tick at which it fires
+    deadline: u64,
+    target: Target,
+    /// Arming it belongs to; it is stale once its target is re-armed or gone
+    arming: u64,
+}
+
+/// Expiries hashed by deadline into one-tick slots
+struct TimerWheel {
+    /// Allocated on first use, so the wheel can live in a static
+    slots: Vec<Vec<Expiry>>,
+    /// Ticks so far
+    now: u64,
+}
+
+impl TimerWheel {
+    const fn new() -> Self {
+        Self { slots: Vec::new(), now: 0 }
+    }
+
+    /// Queue `expiry`; a deadline already passed fires at the next tick
+    fn insert(&mut self, expiry: Expiry) {
+        if self.slots.is_empty() {
+            self.slots.resize_with(WHEEL_SLOTS, Vec::new);
+        }
+        let deadline = expiry.deadline.max(self.now + 1);
+        self.slots[(deadline % WHEEL_SLOTS as u64) as usize].push(Expiry { deadline, ..expiry });
+    }
+
+    /// Advance by one tick, returning the expiries now due
+    fn advance(&mut self) -> Vec<Expiry> {
+        self.now += 1;
+        let mut due = Vec::new();
+        if self.slots.is_empty() {
+            return due;
+        }
+        let now = self.now;
+        self.slots[(now % WHEEL_SLOTS as u64) as usize].retain(|expiry| {
+            if expiry.deadline > now {
+                return true;
+            }
+            due.push(*expiry);
+            false
+        });
+        due
+    }
+}
+
+/// A process blocked in `sleep`
+#[derive(Debug, Clone, Copy)]
+struct Sleeper {
+    arming: u64,
+    expired: bool,
+}
+
+/// A timer created with `SYS_TIMER_CREATE`
+#[derive(Debug, Clone, Copy, Default)]
+struct ProcessTimer {
+    /// Current arming, or None while disarmed
+    arming: Option<u64>,
+    /// Ticks between expiries; 0 for a one-shot timer
+    interval_ticks: u64,
+    /// Expiries since the owner last waited
+    expirations: u64,
+}
+
+struct Timers {
+    wheel: TimerWheel,
+    sleepers: BTreeMap<ProcessId, Sleeper>,
+    timers: BTreeMap<(ProcessId, TimerId), ProcessTimer>,
+    next_arming: u64,
+}
+
+impl Timers {
+    const fn new() -> Self {
+        Self {
+            wheel: TimerWheel::new(),
+            sleepers: BTreeMap::new(),
+            timers: BTreeMap::new(),
+            next_arming: 1,
+        }
+    }
+
+    /// Queue an expiry `ticks` from now, returning its arming
+    fn arm(&mut self, target: Target, ticks: u64) -> u64 {
+        let arming = self.next_arming;
+        self.next_arming += 1;
+        self.wheel.insert(Expiry { deadline: self.wheel.now.saturating_add(ticks), target, arming });
+        arming
+    }
+
+    /// Record an expiry, returning the process to wake if it still counts
+    fn fire(&mut self, expiry: Expiry) -> Option<ProcessId> {
+        match expiry.target {
+            Target::Sleep(pid) => {
+                let sleeper = self.sleepers.get_mut(&pid).filter(|sleeper| sleeper.arming == expiry.arming)?;
+                sleeper.expired = true;
+                Some(pid)
+            }
+            Target::Timer(pid, id) => {
+                let timer = self.timers.get_mut(&(pid, id)).filter(|timer| timer.arming == Some(expiry.arming))?;
+                timer.expirations += 1;
+                if timer.interval_ticks == 0 {
+                    timer.arming = None;
+                } else {
+                    let deadline = expiry.deadline + timer.interval_ticks;
+                    self.wheel.insert(Expiry { deadline, ..expiry });
+                }
+                Some(pid)
+            }
+            // Handed to `ipc::futex` once the lock is dropped
+            Target::FutexTimeout(_) => None,
+        }
+    }
+}
+
+static TIMERS: Mutex<Timers> = Mutex::new(Timers::new());
+
+/// Ticks to wait for at least `nanos` to pass
+///
+/// Rounded up, plus one for the tick already under way.
+pub fn ticks_for(nanos: u64) -> u64 {
+    nanos.div_ceil(TICK_MS * NANOS_PER_MILLI).saturating_add(1)
+}
+
+/// Block the caller for at least `duration_ns`
+///
+/// The first call queues the wakeup and returns `WouldBlock`; once woken
+/// the caller repeats it and gets `Ok` when the time is up. A caller woken
+/// early keeps its original deadline.
+pub fn sleep(process_id: ProcessId, duration_ns: u64) -> Result<(), TimerError> {
+    {
+        let mut timers = TIMERS.lock();
+        match timers.sleepers.get(&process_id) {
+            Some(sleeper) if sleeper.expired => {
+                timers.sleepers.remove(&process_id);
+                return Ok(());
+            }
+            Some(_) => {}
+            None => {
+                if duration_ns == 0 {
+                    return Ok(());
+                }
+                let arming = timers.arm(Target::Sleep(process_id), ticks_for(duration_ns));
+                timers.sleepers.insert(process_id, Sleeper { arming, expired: false });
+            }
+        }
+    }
+
+    let _ = set_process_state(process_id, ProcessState::Blocked(BlockReason::Sleeping));
+    // The tick may have expired the sleep while the caller was being blocked
+    let mut timers = TIMERS.lock();
+    if timers.sleepers.get(&process_id).is_some_and(|sleeper| sleeper.expired) {
+        timers.sleepers.remove(&process_id);
+        let _ = set_process_state(process_id, ProcessState::Ready);
+        return Ok(());
+    }
+    Err(TimerError::WouldBlock)
+}
+
+/// Queue the timeout of a futex wait, returning its arming
+///
+/// `ipc::futex::timed_out` is called with it once `duration_ns` passed.
+pub fn arm_futex_timeout(process_id: ProcessId, duration_ns: u64) -> u64 {
+    TIMERS.lock().arm(Target::FutexTimeout(process_id), ticks_for(duration_ns))
+}
+
+/// Create a disarmed timer
+pub fn create(process_id: ProcessId) -> Result<TimerId, TimerError> {
+    let mut timers = TIMERS.lock();
+    let owned = timers.timers.range((process_id, 0)..=(process_id, TimerId::MAX)).count();
+    if owned >= MAX_TIMERS_PER_PROCESS {
+        return Err(TimerError::TooManyTimers);
+    }
+    let id = (1..).find(|id| !timers.timers.contains_key(&(process_id, *id))).ok_or(TimerError::TooManyTimers)?;
+    timers.timers.insert((process_id, id), ProcessTimer::default());
+    log::debug!("Process {} created timer {}", process_id.0, id);
+    Ok(id)
+}
+
+/// Arm a timer to expire after `initial_ns`, then every `interval_ns`
+///
+/// An `interval_ns` of 0 makes it one-shot and an `initial_ns` of 0
+/// disarms it. Expiries not yet collected are dropped either way.
+pub fn set(process_id: ProcessId, id: TimerId, initial_ns: u64, interval_ns: u64) -> Result<(), TimerError> {
+    let mut timers = TIMERS.lock();
+    if !timers.timers.contains_key(&(process_id, id)) {
+        return Err(TimerError::NotFound);
+    }
+    let arming = if initial_ns == 0 {
+        None
+    } else {
+        Some(timers.arm(Target::Timer(process_id, id), ticks_for(initial_ns)))
+    };
+    let timer = timers.timers.get_mut(&(process_id, id)).ok_or(TimerError::NotFound)?;
+    *timer = ProcessTimer {
+        arming,
+        // Periods are not padded like deadlines, as they follow the previous expiry
+        interval_ticks: interval_ns.div_ceil(TICK_MS * NANOS_PER_MILLI),
+        expirations: 0,
+    };
+    Ok(())
+}
+
+/// Collect the expiries of a timer since the last wait
+///
+/// With none pending the caller is blocked and `WouldBlock` returned,
+/// unless `nohang` is set, which returns 0 instead. A blocked caller is
+/// woken at the next expiry and repeats the call.
+pub fn wait(process_id: ProcessId, id: TimerId, nohang: bool) -> Result<u64, TimerError> {
+    let take = || -> Result<Option<u64>, TimerError> {
+        let mut timers = TIMERS.lock();
+        let timer = timers.timers.get_mut(&(process_id, id)).ok_or(TimerError::NotFound)?;
+        let expirations = core::mem::take(&mut timer.expirations);
+        if expirations > 0 || nohang {
+            return Ok(Some(expirations));
+        }
+        if timer.arming.is_none() {
+            return Err(TimerError::NotArmed);
+        }
+        Ok(None)
+    };
+
+    if let Some(expirations) = take()? {
+        return Ok(expirations);
+    }
+    let _ = set_process_state(process_id, ProcessState::Blocked(BlockReason::Sleeping));
+    // The timer may have expired while the caller was being blocked
+    let pending = take();
+    if !matches!(pending, Ok(None)) {
+        let _ = set_process_state(process_id, ProcessState::Ready);
+    }
+    pending?.ok_or(TimerError::WouldBlock)
+}
+
+/// Delete a timer; its pending expiries are dropped
+pub fn delete(process_id: ProcessId, id: TimerId) -> Result<(), TimerError> {
+    TIMERS.lock().timers.remove(&(process_id, id)).ok_or(TimerError::NotFound)?;
+    log::debug!("Process {} deleted timer {}", process_id.0, id);
+    Ok(())
+}
+
+/// Advance the wheel by one tick and wake processes whose time is up
+///
+/// Called from the scheduler tick on the boot CPU.
+pub fn timer_tick() {
+    let mut woken = Vec::new();
+    let mut futex_timeouts = Vec::new();
+    {
+        let mut timers = TIMERS.lock();
+        for expiry in timers.wheel.advance() {
+            if let Target::FutexTimeout(pid) = expiry.target {
+                futex_timeouts.push((pid, expiry.arming));
+            } else if let Some(pid) = timers.fire(expiry) {
+                woken.push(pid);
+            }
+        }
+    }
+
+    for (pid, arming) in futex_timeouts {
+        crate::ipc::futex::timed_out(pid, arming);
+    }
+
+    for pid in woken {
+        let sleeping = get_process(pid)
+            .is_some_and(|info| info.state == ProcessState::Blocked(BlockReason::Sleeping));
+        if sleeping {
+            let _ = set_process_state(pid, ProcessState::Ready);
+        }
+    }
+}
+
+/// Forget the sleep and timers of a terminating process
+///
+/// Their expiries still in the wheel are stale and are dropped when due.
+pub fn release_process(process_id: ProcessId) {
+    let mut timers = TIMERS.lock();
+    timers.sleepers.remove(&process_id);
+    timers.timers.retain(|&(owner, _), _| owner != process_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expiry(deadline: u64, arming: u64) -> Expiry {
+        Expiry { deadline, target: Target::Sleep(ProcessId::new(9100)), arming }
+    }
+
+    #[test_case]
+    fn test_wheel_fires_at_deadline() {
+        let mut wheel = TimerWheel::new();
+        wheel.insert(expiry(2, 1));
+        // A full turn later lands in the same slot
+        wheel.insert(expiry(2 + WHEEL_SLOTS as u64, 2));
+
+        assert!(wheel.advance().is_empty());
+        assert_eq!(wheel.advance(), [expiry(2, 1)]);
+        for _ in 3..2 + WHEEL_SLOTS as u64 {
+            assert!(wheel.advance().is_empty());
+        }
+        assert_eq!(wheel.advance(), [expiry(2 + WHEEL_SLOTS as u64, 2)]);
+    }
+
+    #[test_case]
+    fn test_wheel_past_deadline_fires_next_tick() {
+        let mut wheel = TimerWheel::new();
+        wheel.advance();
+        wheel.advance();
+        wheel.insert(expiry(1, 1));
+        assert_eq!(wheel.advance(), [expiry(3, 1)]);
+    }
+
+    #[test_case]
+    fn test_periodic_timer_rearms() {
+        let pid = ProcessId::new(9101);
+        let mut timers = Timers::new();
+        let arming = timers.arm(Target::Timer(pid, 1), 2);
+        timers.timers.insert((pid, 1), ProcessTimer { arming: Some(arming), interval_ticks: 3, expirations: 0 });
+
+        let mut fired = 0;
+        for _ in 0..8 {
+            for expiry in timers.wheel.advance() {
+                if timers.fire(expiry).is_some() {
+                    fired += 1;
+                }
+            }
+        }
+        // Ticks 2, 5 and 8
+        assert_eq!(fired, 3);
+        assert_eq!(timers.timers[&(pid, 1)].expirations, 3);
+    }
+
+    #[test_case]
+    fn test_stale_expiry_is_dropped() {
+        let pid = ProcessId::new(9102);
+        let mut timers = Timers::new();
+        let stale = timers.arm(Target::Sleep(pid), 1);
+        let current = timers.arm(Target::Sleep(pid), 2);
+        assert_ne!(stale, current);
+        timers.sleepers.insert(pid, Sleeper { arming: current, expired: false });
+
+        let due = timers.wheel.advance();
+        assert_eq!(due.len(), 1);
+        assert_eq!(timers.fire(due[0]), None);
+        let due = timers.wheel.advance();
+        assert_eq!(timers.fire(due[0]), Some(pid));
+        assert!(timers.sleepers[&pid].expired);
+    }
+
+    #[test_case]
+    fn test_ticks_for() {
+        assert_eq!(ticks_for(1), 2);
+        assert_eq!(ticks_for(TICK_MS * NANOS_PER_MILLI), 2);
+        assert_eq!(ticks_for(TICK_MS * NANOS_PER_MILLI + 1), 3);
+    }
+}
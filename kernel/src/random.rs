@@ -0,0 +1,371 @@
+//! Kernel random number generator
+//!
+//! Entropy is gathered into an input pool: the timing of device
+//! interrupts, bits from the CPU's generator where it has one, and what
+//! drivers such as virtio-rng feed in through `SYS_ADD_ENTROPY`. The pool
+//! is a sponge over the ChaCha permutation that inputs are absorbed into
+//! and keys squeezed out of. Each input comes with an estimate of the
+//! entropy it carries, which the pool counts.
+//!
+//! Output comes from ChaCha20 keyed from the pool, once the pool has
+//! counted `SEED_BITS`, and rekeyed from it every `RESEED_INTERVAL_NS`
+//! after that. Each request first replaces the generator's key with its
+//! own output (fast key erasure), so a key read from memory later tells
+//! nothing of what was handed out before.
+//!
+//! Interrupt handlers only touch atomics: their timings are folded into
+//! the pool every `FAST_POOL_EVENTS` interrupts, or later if the pool is
+//! in use just then.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+
+/// Entropy the pool must have counted before output is considered secure
+pub const SEED_BITS: u32 = 256;
+
+/// Most entropy the pool can hold, the size of its state
+const POOL_BITS: u32 = 512;
+
+/// Time between reseeds once seeded
+const RESEED_INTERVAL_NS: u64 = 60 * crate::time::NANOS_PER_SEC;
+
+/// Interrupts gathered before they are folded into the pool; each fold
+/// credits one bit per this many, as interrupt timing is mostly predictable
+const FAST_POOL_EVENTS: u32 = 64;
+
+/// Bytes of the pool state input is absorbed into; the rest is capacity
+const RATE_BYTES: usize = 32;
+
+/// "expand 32-byte k"
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646E, 0x7962_2D32, 0x6B20_6574];
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// The 20 rounds of ChaCha, without adding the input back
+fn permute(state: &mut [u32; 16]) {
+    for _ in 0..10 {
+        quarter_round(state, 0, 4, 8, 12);
+        quarter_round(state, 1, 5, 9, 13);
+        quarter_round(state, 2, 6, 10, 14);
+        quarter_round(state, 3, 7, 11, 15);
+        quarter_round(state, 0, 5, 10, 15);
+        quarter_round(state, 1, 6, 11, 12);
+        quarter_round(state, 2, 7, 8, 13);
+        quarter_round(state, 3, 4, 9, 14);
+    }
+}
+
+/// Block `counter` of the ChaCha20 stream under `key` and `nonce`, as in
+/// RFC 8439
+pub fn chacha20_block(key: &[u32; 8], counter: u32, nonce: &[u32; 3]) -> [u32; 16] {
+    let mut input = [0u32; 16];
+    input[..4].copy_from_slice(&CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter;
+    input[13..].copy_from_slice(nonce);
+
+    let mut state = input;
+    permute(&mut state);
+    for (word, input) in state.iter_mut().zip(input) {
+        *word = word.wrapping_add(input);
+    }
+    state
+}
+
+/// Fill `out` with the ChaCha20 stream under `key`, from block 0
+fn keystream(key: &[u32; 8], out: &mut [u8]) {
+    for (counter, chunk) in out.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, counter as u32, &[0; 3]);
+        for (bytes, word) in chunk.chunks_mut(4).zip(block) {
+            bytes.copy_from_slice(&word.to_le_bytes()[..bytes.len()]);
+        }
+    }
+}
+
+/// Sponge inputs are absorbed into
+struct InputPool {
+    state: [u32; 16],
+    /// Next byte of the rate input goes into
+    position: usize,
+    /// Entropy the pool is estimated to hold, in bits
+    entropy_bits: u32,
+}
+
+impl InputPool {
+    const fn new() -> Self {
+        // The constants sit in the capacity, so no input can zero the state
+        let mut state = [0u32; 16];
+        state[12] = CONSTANTS[0];
+        state[13] = CONSTANTS[1];
+        state[14] = CONSTANTS[2];
+        state[15] = CONSTANTS[3];
+        Self { state, position: 0, entropy_bits: 0 }
+    }
+
+    fn absorb(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.state[self.position / 4] ^= (byte as u32) << (8 * (self.position % 4));
+            self.position += 1;
+            if self.position == RATE_BYTES {
+                permute(&mut self.state);
+                self.position = 0;
+            }
+        }
+    }
+
+    fn credit(&mut self, bits: u32) {
+        self.entropy_bits = self.entropy_bits.saturating_add(bits).min(POOL_BITS);
+    }
+
+    /// Squeeze out a key, using up the entropy counted
+    fn extract(&mut self) -> [u32; 8] {
+        permute(&mut self.state);
+        let mut key = [0u32; 8];
+        key.copy_from_slice(&self.state[..8]);
+        // Without the rate the state cannot be run back to the key
+        self.state[..8].fill(0);
+        permute(&mut self.state);
+        self.position = 0;
+        self.entropy_bits = 0;
+        key
+    }
+}
+
+/// The pool and the generator keyed from it
+struct Generator {
+    pool: InputPool,
+    key: [u32; 8],
+    /// Requests served, the nonce of the next one's key
+    requests: u64,
+    seeded: bool,
+    /// Monotonic time of the last reseed
+    reseeded_ns: u64,
+}
+
+impl Generator {
+    const fn new() -> Self {
+        Self { pool: InputPool::new(), key: [0; 8], requests: 0, seeded: false, reseeded_ns: 0 }
+    }
+
+    /// Mix a key from the pool into the generator's
+    ///
+    /// The CPU's generator, where there is one, is mixed in uncounted
+    /// first, so a reseed is no weaker than what it offers.
+    fn reseed(&mut self, hardware: impl Iterator<Item = u64>, now_ns: u64) {
+        for value in hardware {
+            self.pool.absorb(&value.to_le_bytes());
+        }
+        if self.pool.entropy_bits >= SEED_BITS {
+            self.seeded = true;
+        }
+        for (word, fresh) in self.key.iter_mut().zip(self.pool.extract()) {
+            *word ^= fresh;
+        }
+        self.reseeded_ns = now_ns;
+    }
+
+    /// Whether the pool has enough for a first seed, or a seeded
+    /// generator is due for a fresh one
+    fn wants_reseed(&self, now_ns: u64) -> bool {
+        if self.seeded {
+            now_ns.saturating_sub(self.reseeded_ns) >= RESEED_INTERVAL_NS
+        } else {
+            self.pool.entropy_bits >= SEED_BITS
+        }
+    }
+
+    /// Key of one request, replacing the generator's own
+    fn request_key(&mut self) -> [u32; 8] {
+        let nonce = [self.requests as u32, (self.requests >> 32) as u32, 0];
+        self.requests = self.requests.wrapping_add(1);
+        let block = chacha20_block(&self.key, 0, &nonce);
+        self.key.copy_from_slice(&block[..8]);
+        let mut key = [0u32; 8];
+        key.copy_from_slice(&block[8..]);
+        key
+    }
+}
+
+static GENERATOR: Mutex<Generator> = Mutex::new(Generator::new());
+
+/// Mirrors `Generator::seeded`, for callers that must not take the lock
+static SEEDED: AtomicBool = AtomicBool::new(false);
+
+/// Interrupt timings not yet folded into the pool
+static FAST_POOL: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+
+/// Interrupts gathered in `FAST_POOL`
+static FAST_EVENTS: AtomicU32 = AtomicU32::new(0);
+
+/// Bits the CPU's generator is asked for at each reseed
+const HARDWARE_WORDS: usize = (SEED_BITS / 64) as usize;
+
+fn hardware_words() -> impl Iterator<Item = u64> {
+    (0..HARDWARE_WORDS).map_while(|_| crate::platform::hardware_random())
+}
+
+/// Seed the pool at boot
+///
+/// The boot timings and the time of day are mixed in uncounted. Bits from
+/// the CPU's generator are counted in full, as on Linux by default, so
+/// machines with RDSEED or RDRAND are seeded from the start.
+pub fn init() {
+    let now_ns = crate::time::monotonic_ns();
+    let mut generator = GENERATOR.lock();
+    generator.pool.absorb(&now_ns.to_le_bytes());
+    generator.pool.absorb(&crate::time::realtime_ns().to_le_bytes());
+    let mut hardware_bits = 0;
+    for value in hardware_words() {
+        generator.pool.absorb(&value.to_le_bytes());
+        hardware_bits += 64;
+    }
+    generator.pool.credit(hardware_bits);
+    generator.reseed(core::iter::empty(), now_ns);
+    SEEDED.store(generator.seeded, Ordering::Release);
+
+    if generator.seeded {
+        log::info!("Random pool seeded from the CPU's generator");
+    } else {
+        log::warn!("No CPU random generator; the pool seeds from interrupts and devices");
+    }
+}
+
+/// Whether output is seeded from at least `SEED_BITS` of entropy
+pub fn is_seeded() -> bool {
+    SEEDED.load(Ordering::Acquire)
+}
+
+/// Mix the timing of an interrupt on `line` into the pool
+///
+/// Lock-free, for interrupt handlers.
+pub fn add_interrupt_randomness(line: usize) {
+    let events = FAST_EVENTS.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+    // The multiply spreads the fast-changing low bits of the time over the word
+    let sample = (crate::time::monotonic_ns() ^ ((line as u64) << 56)).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    FAST_POOL[events as usize % FAST_POOL.len()].fetch_xor(sample.rotate_left(events), Ordering::Relaxed);
+
+    if events < FAST_POOL_EVENTS {
+        return;
+    }
+    // Whoever holds the pool may be what this interrupt interrupted
+    let Some(mut generator) = GENERATOR.try_lock() else {
+        return;
+    };
+    let events = FAST_EVENTS.swap(0, Ordering::Relaxed);
+    for word in &FAST_POOL {
+        generator.pool.absorb(&word.swap(0, Ordering::Relaxed).to_le_bytes());
+    }
+    generator.pool.credit(events / FAST_POOL_EVENTS);
+}
+
+/// Mix `data` into the pool, counting `entropy_bits` of entropy
+///
+/// No more than eight bits a byte are counted.
+pub fn add_device_randomness(data: &[u8], entropy_bits: u32) {
+    let mut generator = GENERATOR.lock();
+    generator.pool.absorb(data);
+    let bits = entropy_bits.min(u32::try_from(data.len()).unwrap_or(u32::MAX).saturating_mul(8));
+    generator.pool.credit(bits);
+}
+
+/// Fill `out` with random bytes
+///
+/// Output before the pool is seeded is only as unpredictable as the boot
+/// timings; callers that need better check `is_seeded` first.
+pub fn fill(out: &mut [u8]) {
+    let now_ns = crate::time::monotonic_ns();
+    let key = {
+        let mut generator = GENERATOR.lock();
+        if generator.wants_reseed(now_ns) {
+            generator.reseed(hardware_words(), now_ns);
+            if generator.seeded && !SEEDED.swap(true, Ordering::Release) {
+                log::info!("Random pool seeded");
+            }
+        }
+        generator.request_key()
+    };
+    keystream(&key, out);
+}
+
+/// 64 random bits
+pub fn random_u64() -> u64 {
+    let mut bytes = [0u8; 8];
+    fill(&mut bytes);
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_chacha20_block() {
+        // RFC 8439, section 2.3.2
+        let key = core::array::from_fn(|index| u32::from_le_bytes(core::array::from_fn(|byte| (index * 4 + byte) as u8)));
+        let block = chacha20_block(&key, 1, &[0x0900_0000, 0x4A00_0000, 0]);
+        assert_eq!(block, [
+            0xE4E7_F110, 0x1559_3BD1, 0x1FDD_0F50, 0xC471_20A3,
+            0xC7F4_D1C7, 0x0368_C033, 0x9AAA_2204, 0x4E6C_D4C3,
+            0x4664_82D2, 0x09AA_9F07, 0x05D7_C214, 0xA202_8BD9,
+            0xD19C_12B5, 0xB94E_16DE, 0xE883_D0CB, 0x4E3C_50A2,
+        ]);
+    }
+
+    #[test_case]
+    fn test_input_pool() {
+        let mut first = InputPool::new();
+        let mut second = InputPool::new();
+        first.absorb(&[1, 2, 3]);
+        second.absorb(&[1, 2, 4]);
+        first.credit(POOL_BITS * 2);
+        assert_eq!(first.entropy_bits, POOL_BITS);
+        let key = first.extract();
+        assert_ne!(key, second.extract());
+        assert_eq!(first.entropy_bits, 0);
+        // Squeezing again without input still gives a new key
+        assert_ne!(key, first.extract());
+    }
+
+    #[test_case]
+    fn test_generator_seeding() {
+        let mut generator = Generator::new();
+        generator.pool.absorb(b"boot timings");
+        generator.reseed(core::iter::empty(), 0);
+        assert!(!generator.seeded);
+        assert!(!generator.wants_reseed(RESEED_INTERVAL_NS));
+
+        generator.pool.absorb(b"device bits");
+        generator.pool.credit(SEED_BITS);
+        assert!(generator.wants_reseed(1));
+        generator.reseed([7u64, 9].into_iter(), 1);
+        assert!(generator.seeded);
+        assert!(!generator.wants_reseed(RESEED_INTERVAL_NS));
+        assert!(generator.wants_reseed(RESEED_INTERVAL_NS + 1));
+
+        // Every request gets a fresh key and moves the generator's on
+        let key = generator.key;
+        let first = generator.request_key();
+        assert_ne!(generator.key, key);
+        assert_ne!(first, generator.request_key());
+    }
+
+    #[test_case]
+    fn test_fill() {
+        let mut first = [0u8; 100];
+        let mut second = [0u8; 100];
+        fill(&mut first);
+        fill(&mut second);
+        assert_ne!(first, second);
+        assert!(first[64..].iter().any(|&byte| byte != 0));
+        assert_ne!(random_u64(), random_u64());
+    }
+}
@@ -1,19 +1,176 @@
-use uart_16550::SerialPort;
+//! Serial console
+//!
+//! `serial_print!` and the kernel log write to the platform's console
+//! UART: COM1's 16550 on x86_64, the PL011 named by the device tree on
+//! ARM64, the firmware's console through the SBI on RISC-V 64. All behave
+//! the same, so output is identical on each.
+//!
+//! The UART is also a console input channel, so the shell can be used
+//! headless. Received bytes are moved into a buffer by the UART's
+//! interrupt where the platform wires it (COM1's IRQ 4 on x86_64) and by
+//! the boot CPU's timer tick everywhere, which also picks up what an
+//! interrupt could not take while the UART was busy. The buffer is a
+//! fixed ring, so taking input never allocates in interrupt context; it
+//! holds a few lines of typeahead for the shell to read while it is busy,
+//! and counts what overflows it.
+//!
+//! Input is handed over raw. Assembling lines, backspace and echo are
+//! left to the reader: the shell edits its own command line, which also
+//! needs the arrow and tab keys a cooked console would swallow.
+//!
+//! Ctrl+] followed by `d` breaks into the kernel debug monitor, `kdb`;
+//! Ctrl+] typed twice sends one. Any other byte after Ctrl+] is passed on
+//! with it.
+
+use alloc::vec::Vec;
+use core::fmt;
 use spin::Mutex;
 use lazy_static::lazy_static;
+use crate::platform::traits::ConsoleUart;
+use crate::platform::PlatformUart;
+
+/// Ctrl+], which starts a console escape
+pub const ESCAPE: u8 = 0x1D;
+
+/// Typed after `ESCAPE` to break into the debug monitor
+pub const ESCAPE_MONITOR: u8 = b'd';
+
+/// Received bytes held until the console is read
+const RECEIVE_CAPACITY: usize = 1024;
 
 lazy_static! {
-    pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
-        serial_port.init();
-        Mutex::new(serial_port)
+    pub static ref SERIAL1: Mutex<PlatformUart> = {
+        let mut uart = crate::platform::console_uart();
+        uart.init();
+        Mutex::new(uart)
+    };
+}
+
+/// Switch to the console UART the platform names now
+///
+/// Output before the device tree is read goes to the default UART; this
+/// moves it to the one the tree describes.
+pub fn reset_console() {
+    let mut uart = crate::platform::console_uart();
+    uart.init();
+    *SERIAL1.lock() = uart;
+}
+
+static RECEIVER: Mutex<Receiver> = Mutex::new(Receiver::new());
+
+/// Console input received and not read yet
+struct Receiver {
+    /// Ring of received bytes, the oldest at `start`
+    buffer: [u8; RECEIVE_CAPACITY],
+    start: usize,
+    len: usize,
+    /// Whether the last byte was an `ESCAPE` still waiting for its second
+    escaped: bool,
+    /// Bytes lost to a full buffer
+    dropped: u64,
+}
+
+impl Receiver {
+    const fn new() -> Self {
+        Self { buffer: [0; RECEIVE_CAPACITY], start: 0, len: 0, escaped: false, dropped: 0 }
+    }
+
+    /// Take a received byte, returning true if it completes the escape
+    /// into the debug monitor
+    fn push(&mut self, byte: u8) -> bool {
+        if core::mem::take(&mut self.escaped) {
+            match byte {
+                ESCAPE_MONITOR => return true,
+                ESCAPE => self.queue(ESCAPE),
+                _ => {
+                    self.queue(ESCAPE);
+                    self.queue(byte);
+                }
+            }
+        } else if byte == ESCAPE {
+            self.escaped = true;
+        } else {
+            self.queue(byte);
+        }
+        false
+    }
+
+    /// Keep `byte` for the reader; the newest input goes when full
+    fn queue(&mut self, byte: u8) {
+        if self.len < RECEIVE_CAPACITY {
+            self.buffer[(self.start + self.len) % RECEIVE_CAPACITY] = byte;
+            self.len += 1;
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    fn take(&mut self, max: usize) -> Vec<u8> {
+        let count = max.min(self.len);
+        let bytes = (0..count).map(|i| self.buffer[(self.start + i) % RECEIVE_CAPACITY]).collect();
+        self.start = (self.start + count) % RECEIVE_CAPACITY;
+        self.len -= count;
+        bytes
+    }
+}
+
+/// Move what the UART has received into the buffer
+fn drain(serial: &mut PlatformUart, receiver: &mut Receiver) {
+    while let Some(byte) = serial.read_byte() {
+        if receiver.push(byte) {
+            crate::kdb::request();
+        }
+    }
+}
+
+/// Take what the console UART has received into the input buffer
+///
+/// Called from the UART's interrupt and the boot CPU's timer tick, so it
+/// never waits: while the UART or the buffer is in use the bytes stay in
+/// the UART's FIFO for the next call.
+pub fn receive() {
+    let Some(mut serial) = SERIAL1.try_lock() else {
+        return;
     };
+    if let Some(mut receiver) = RECEIVER.try_lock() {
+        drain(&mut serial, &mut receiver);
+    }
+}
+
+/// Take up to `max` bytes of console input, without waiting
+///
+/// Console input is raw: bytes are handed over as they arrive, with no
+/// line editing or echo.
+pub fn read_available(max: usize) -> Vec<u8> {
+    // The UART first: that is the order `receive` takes the locks in
+    let mut serial = SERIAL1.lock();
+    let mut receiver = RECEIVER.lock();
+    drain(&mut serial, &mut receiver);
+    receiver.take(max)
+}
+
+/// Console input bytes lost because the buffer was full
+pub fn dropped_input() -> u64 {
+    RECEIVER.lock().dropped
+}
+
+/// Formats onto a console UART
+struct UartWriter<'a, U: ConsoleUart>(&'a mut U);
+
+impl<U: ConsoleUart> fmt::Write for UartWriter<'_, U> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        for byte in text.bytes() {
+            self.0.write_byte(byte);
+        }
+        Ok(())
+    }
 }
 
 #[doc(hidden)]
 pub fn _print(args: ::core::fmt::Arguments) {
     use core::fmt::Write;
-    SERIAL1.lock().write_fmt(args).expect("Printing to serial failed");
+    let mut serial = SERIAL1.lock();
+    UartWriter(&mut *serial).write_fmt(args).expect("Printing to serial failed");
 }
 
 #[macro_export]
@@ -27,4 +184,60 @@ macro_rules! serial_print {
 macro_rules! serial_println {
     () => ($crate::serial_print!("\n"));
     ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_all(receiver: &mut Receiver, bytes: &[u8]) -> bool {
+        let mut monitor = false;
+        for &byte in bytes {
+            monitor |= receiver.push(byte);
+        }
+        monitor
+    }
+
+    #[test_case]
+    fn test_receiver_escape() {
+        let mut receiver = Receiver::new();
+        assert!(!push_all(&mut receiver, b"ls\r"));
+        assert!(push_all(&mut receiver, &[ESCAPE, ESCAPE_MONITOR]));
+        // Neither escape byte reaches the reader
+        assert_eq!(receiver.take(16), b"ls\r");
+
+        assert!(!push_all(&mut receiver, &[ESCAPE, ESCAPE, ESCAPE, b'x']));
+        assert_eq!(receiver.take(16), [ESCAPE, ESCAPE, b'x']);
+        assert!(!receiver.escaped);
+    }
+
+    #[test_case]
+    fn test_receiver_wraps() {
+        let mut receiver = Receiver::new();
+        for round in 0..3u8 {
+            for _ in 0..RECEIVE_CAPACITY - 1 {
+                receiver.push(round);
+            }
+            receiver.push(b'\r');
+            let taken = receiver.take(usize::MAX);
+            assert_eq!(taken.len(), RECEIVE_CAPACITY);
+            assert!(taken[..RECEIVE_CAPACITY - 1].iter().all(|&byte| byte == round));
+            assert_eq!(taken[RECEIVE_CAPACITY - 1], b'\r');
+            receiver.push(b'x');
+            assert_eq!(receiver.take(1), b"x");
+        }
+        assert_eq!(receiver.dropped, 0);
+    }
+
+    #[test_case]
+    fn test_receiver_overflow() {
+        let mut receiver = Receiver::new();
+        for _ in 0..RECEIVE_CAPACITY + 10 {
+            receiver.push(b'a');
+        }
+        assert_eq!(receiver.dropped, 10);
+        assert_eq!(receiver.take(4).len(), 4);
+        assert_eq!(receiver.take(usize::MAX).len(), RECEIVE_CAPACITY - 4);
+        assert!(receiver.take(1).is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+//! Symmetric multiprocessing
+//!
+//! The boot CPU brings up the other cores once the kernel is initialized.
+//! Each secondary CPU gets its own guarded stack, descriptor tables and timer from
+//! the platform layer, then enters its idle loop, from where its own
+//! scheduler picks up work. Processes have a home CPU whose scheduler runs
+//! them; idle CPUs steal ready processes from busier ones.
+//!
+//! A secondary CPU can be taken offline again: it stops taking work, its
+//! processes are rehomed on the others and it halts from its idle loop
+//! until it is started again, on the stack it was first started on.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::memory::kstack::{KernelStack, StackOwner};
+
+/// Most CPUs the kernel drives
+pub const MAX_CPUS: usize = 8;
+
+/// Stack of each secondary CPU's boot and idle thread
+const SECONDARY_STACK_SIZE: usize = 64 * 1024;
+
+/// CPUs that finished bring-up; the boot CPU is always online
+static ONLINE: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+
+/// CPUs asked to go offline; each halts from its idle loop
+static PARK_REQUESTED: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+
+/// CPUs halted after going offline, ready to be started again
+static PARKED: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+
+/// Top of the stack each secondary CPU was started on
+static STACK_TOPS: [AtomicUsize; MAX_CPUS] = [const { AtomicUsize::new(0) }; MAX_CPUS];
+
+/// Set once secondary CPUs may run kernel code
+///
+/// Until then every caller is on the boot CPU and `current_cpu` does not
+/// need to ask the hardware.
+static SECONDARIES_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Index of the CPU executing the caller, 0 for the boot CPU
+pub fn current_cpu() -> usize {
+    if !SECONDARIES_STARTED.load(Ordering::Acquire) {
+        return 0;
+    }
+    crate::platform::current_cpu_index()
+}
+
+/// Whether `cpu` is up and scheduling
+pub fn is_online(cpu: usize) -> bool {
+    cpu == 0 || ONLINE.get(cpu).is_some_and(|online| online.load(Ordering::Acquire))
+}
+
+/// Indices of the CPUs that are up
+pub fn online_cpus() -> impl Iterator<Item = usize> {
+    (0..MAX_CPUS).filter(|&cpu| is_online(cpu))
+}
+
+/// Number of CPUs that are up
+pub fn online_count() -> usize {
+    online_cpus().count()
+}
+
+/// Bring up every secondary CPU the platform reports
+///
+/// CPUs are started one at a time and share the platform's startup data,
+/// so bring-up stops at the first CPU that does not come up in time: it
+/// might still wake up late and pick up the next CPU's stack.
+pub fn start_secondary_cpus() {
+    let core_count = crate::platform::current_platform().get_cpu_info().core_count as usize;
+    let cpu_count = core_count.min(MAX_CPUS);
+    if cpu_count <= 1 {
+        log::debug!("Single CPU system, no secondary CPUs to start");
+        return;
+    }
+    if core_count > MAX_CPUS {
+        log::debug!("{} CPUs reported, only the first {} are used", core_count, MAX_CPUS);
+    }
+
+    if let Err(e) = crate::platform::prepare_secondary_cpus() {
+        log::warn!("Cannot start secondary CPUs: {}", e);
+        return;
+    }
+    SECONDARIES_STARTED.store(true, Ordering::Release);
+
+    for cpu in 1..cpu_count {
+        // Leaked on purpose: the CPU runs on it for as long as the system is up
+        let stack_top = match KernelStack::allocate(SECONDARY_STACK_SIZE, StackOwner::Cpu(cpu)) {
+            Ok(stack) => stack.leak() as usize,
+            Err(e) => {
+                log::warn!("CPU {} not started: {}", cpu, e);
+                break;
+            }
+        };
+
+        STACK_TOPS[cpu].store(stack_top, Ordering::Relaxed);
+        if let Err(e) = crate::platform::start_secondary_cpu(cpu, stack_top, &ONLINE[cpu]) {
+            log::warn!("CPU {} failed to start: {}", cpu, e);
+            break;
+        }
+    }
+
+    log::info!("{} of {} CPUs online", online_count(), cpu_count);
+}
+
+/// Continue bring-up of a secondary CPU in generic kernel code
+///
+/// Called by the platform entry code on the new CPU's own stack, with its
+/// descriptor tables and interrupt controller set up. Never returns: the
+/// CPU becomes the idle loop its scheduler switches away from.
+pub fn secondary_main(cpu: usize) -> ! {
+    if let Err(e) = crate::process::scheduler::init_cpu_scheduler(cpu) {
+        log::debug!("CPU {}: no scheduler ({}), parking", cpu, e);
+        crate::process::preempt::idle_loop();
+    }
+
+    ONLINE[cpu].store(true, Ordering::Release);
+    log::info!("CPU {} online", cpu);
+
+    if let Err(e) = crate::platform::start_secondary_timer() {
+        log::warn!("CPU {}: cannot start timer: {}", cpu, e);
+    }
+    crate::process::preempt::idle_loop()
+}
+
+/// Take secondary CPU `cpu` offline
+///
+/// It stops being offered new processes right away and its processes are
+/// rehomed on the online CPUs, except the one it is running, which moves
+/// at its next timer tick. The CPU then halts from its idle loop; this
+/// does not wait for it.
+pub fn offline_cpu(cpu: usize) -> Result<(), &'static str> {
+    if cpu == 0 {
+        return Err("the boot CPU cannot go offline");
+    }
+    if cpu >= MAX_CPUS {
+        return Err("CPU index out of range");
+    }
+    if !ONLINE[cpu].swap(false, Ordering::AcqRel) {
+        return Ok(());
+    }
+    PARK_REQUESTED[cpu].store(true, Ordering::Release);
+    let moved = crate::process::rehome_processes(cpu);
+    log::debug!("CPU {} going offline, {} processes rehomed", cpu, moved);
+    Ok(())
+}
+
+/// Whether `cpu` was asked to go offline and has not halted yet
+pub fn park_requested(cpu: usize) -> bool {
+    PARK_REQUESTED.get(cpu).is_some_and(|requested| requested.load(Ordering::Acquire))
+        && !PARKED[cpu].load(Ordering::Acquire)
+}
+
+/// Halt the calling CPU after it was asked to go offline
+///
+/// Only called from the idle loop, once the process it was running has
+/// been rehomed.
+pub fn park_current_cpu() -> ! {
+    let cpu = current_cpu();
+    log::info!("CPU {} offline", cpu);
+    crate::platform::stop_current_cpu(&PARKED[cpu])
+}
+
+/// Start secondary CPU `cpu` again after it went offline
+///
+/// Waits until it is back in its idle loop.
+pub fn online_cpu(cpu: usize) -> Result<(), &'static str> {
+    if is_online(cpu) {
+        return Ok(());
+    }
+    let stack_top = STACK_TOPS.get(cpu).map_or(0, |top| top.load(Ordering::Relaxed));
+    if stack_top == 0 {
+        return Err("CPU was never started");
+    }
+    // Its stack is only free again once it has halted
+    if !PARKED[cpu].load(Ordering::Acquire) {
+        return Err("CPU is still going offline");
+    }
+    PARKED[cpu].store(false, Ordering::Release);
+    PARK_REQUESTED[cpu].store(false, Ordering::Release);
+
+    crate::platform::start_secondary_cpu(cpu, stack_top, &ONLINE[cpu]).map_err(|e| {
+        log::warn!("CPU {} failed to restart: {}", cpu, e);
+        // Still halted, so it can be tried again
+        PARK_REQUESTED[cpu].store(true, Ordering::Release);
+        PARKED[cpu].store(true, Ordering::Release);
+        "CPU did not come back online"
+    })
+}
@@ -1,98 +1,7 @@
-use core::fmt;
 use alloc::format;
 
-/// System call error types
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum SyscallError {
-    /// Invalid system call number
-    InvalidSyscall,
-    /// Invalid argument provided to system call
-    InvalidArgument,
-    /// Permission denied
-    PermissionDenied,
-    /// Resource not found
-    NotFound,
-    /// Process not found
-    ProcessNotFound,
-    /// Resource already exists
-    AlreadyExists,
-    /// Operation not supported
-    NotSupported,
-    /// No memory available
-    OutOfMemory,
-    /// Resource temporarily unavailable
-    WouldBlock,
-    /// Operation interrupted
-    Interrupted,
-    /// Invalid file descriptor
-    BadFileDescriptor,
-    /// Broken pipe
-    BrokenPipe,
-    /// Address already in use
-    AddressInUse,
-    /// Connection refused
-    ConnectionRefused,
-    /// Operation timed out
-    TimedOut,
-    /// System resource exhausted
-    ResourceExhausted,
-    /// Internal kernel error
-    InternalError,
-}
-
-impl SyscallError {
-    /// Convert system call error to errno value
-    pub fn to_errno(self) -> i32 {
-        match self {
-            SyscallError::InvalidSyscall => -1,      // EPERM equivalent
-            SyscallError::InvalidArgument => -22,    // EINVAL
-            SyscallError::PermissionDenied => -13,   // EACCES
-            SyscallError::NotFound => -2,            // ENOENT
-            SyscallError::ProcessNotFound => -3,     // ESRCH
-            SyscallError::AlreadyExists => -17,      // EEXIST
-            SyscallError::NotSupported => -95,       // EOPNOTSUPP
-            SyscallError::OutOfMemory => -12,        // ENOMEM
-            SyscallError::WouldBlock => -11,         // EAGAIN/EWOULDBLOCK
-            SyscallError::Interrupted => -4,         // EINTR
-            SyscallError::BadFileDescriptor => -9,   // EBADF
-            SyscallError::BrokenPipe => -32,         // EPIPE
-            SyscallError::AddressInUse => -98,       // EADDRINUSE
-            SyscallError::ConnectionRefused => -111, // ECONNREFUSED
-            SyscallError::TimedOut => -110,          // ETIMEDOUT
-            SyscallError::ResourceExhausted => -105, // ENOBUFS
-            SyscallError::InternalError => -5,       // EIO
-        }
-    }
-    
-    /// Get a human-readable description of the error
-    pub fn description(self) -> &'static str {
-        match self {
-            SyscallError::InvalidSyscall => "Invalid system call number",
-            SyscallError::InvalidArgument => "Invalid argument",
-            SyscallError::PermissionDenied => "Permission denied",
-            SyscallError::NotFound => "Resource not found",
-            SyscallError::ProcessNotFound => "Process not found",
-            SyscallError::AlreadyExists => "Resource already exists",
-            SyscallError::NotSupported => "Operation not supported",
-            SyscallError::OutOfMemory => "Out of memory",
-            SyscallError::WouldBlock => "Resource temporarily unavailable",
-            SyscallError::Interrupted => "Operation interrupted",
-            SyscallError::BadFileDescriptor => "Bad file descriptor",
-            SyscallError::BrokenPipe => "Broken pipe",
-            SyscallError::AddressInUse => "Address already in use",
-            SyscallError::ConnectionRefused => "Connection refused",
-            SyscallError::TimedOut => "Operation timed out",
-            SyscallError::ResourceExhausted => "System resource exhausted",
-            SyscallError::InternalError => "Internal kernel error",
-        }
-    }
-}
-
-impl fmt::Display for SyscallError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.description())
-    }
-}
+/// System call error types, shared with the argument checks in kosh-syscall
+pub use kosh_syscall::SyscallError;
 
 /// Convert various error types to SyscallError
 impl From<crate::ipc::MessageError> for SyscallError {
@@ -111,6 +20,138 @@ impl From<crate::ipc::MessageError> for SyscallError {
     }
 }
 
+impl From<crate::ipc::ShmError> for SyscallError {
+    fn from(error: crate::ipc::ShmError) -> Self {
+        match error {
+            crate::ipc::ShmError::InvalidSize => SyscallError::InvalidArgument,
+            crate::ipc::ShmError::RegionNotFound => SyscallError::NotFound,
+            crate::ipc::ShmError::PermissionDenied => SyscallError::PermissionDenied,
+            crate::ipc::ShmError::OutOfMemory => SyscallError::OutOfMemory,
+            crate::ipc::ShmError::MappingFailed => SyscallError::InternalError,
+            crate::ipc::ShmError::NotMapped => SyscallError::InvalidArgument,
+        }
+    }
+}
+
+impl From<crate::memory::mmap::MmapError> for SyscallError {
+    fn from(error: crate::memory::mmap::MmapError) -> Self {
+        match error {
+            crate::memory::mmap::MmapError::InvalidArgument => SyscallError::InvalidArgument,
+            crate::memory::mmap::MmapError::OutOfMemory | crate::memory::mmap::MmapError::LimitExceeded => SyscallError::OutOfMemory,
+            crate::memory::mmap::MmapError::MappingFailed => SyscallError::InternalError,
+            crate::memory::mmap::MmapError::ServiceUnavailable => SyscallError::ConnectionRefused,
+        }
+    }
+}
+
+impl From<crate::memory::iommu::IommuError> for SyscallError {
+    fn from(error: crate::memory::iommu::IommuError) -> Self {
+        match error {
+            crate::memory::iommu::IommuError::NotInitialized => SyscallError::NotSupported,
+            crate::memory::iommu::IommuError::DomainExists => SyscallError::AlreadyExists,
+            crate::memory::iommu::IommuError::DomainNotFound => SyscallError::NotFound,
+            crate::memory::iommu::IommuError::NoFreeDomains => SyscallError::ResourceExhausted,
+            crate::memory::iommu::IommuError::InvalidRange => SyscallError::InvalidArgument,
+            crate::memory::iommu::IommuError::NotMapped => SyscallError::InvalidArgument,
+            crate::memory::iommu::IommuError::DeviceInUse => SyscallError::AddressInUse,
+            crate::memory::iommu::IommuError::HardwareError => SyscallError::InternalError,
+        }
+    }
+}
+
+impl From<crate::ipc::capability::CapabilityError> for SyscallError {
+    fn from(error: crate::ipc::capability::CapabilityError) -> Self {
+        match error {
+            crate::ipc::capability::CapabilityError::CapabilityNotFound => SyscallError::NotFound,
+            crate::ipc::capability::CapabilityError::PermissionDenied => SyscallError::PermissionDenied,
+            crate::ipc::capability::CapabilityError::CapabilityExpired => SyscallError::PermissionDenied,
+            crate::ipc::capability::CapabilityError::NotDelegatable => SyscallError::PermissionDenied,
+            crate::ipc::capability::CapabilityError::InvalidCapability => SyscallError::InvalidArgument,
+            crate::ipc::capability::CapabilityError::ResourceExhausted => SyscallError::ResourceExhausted,
+        }
+    }
+}
+
+impl From<crate::ipc::irq::IrqError> for SyscallError {
+    fn from(error: crate::ipc::irq::IrqError) -> Self {
+        match error {
+            crate::ipc::irq::IrqError::InvalidLine => SyscallError::InvalidArgument,
+            crate::ipc::irq::IrqError::InvalidFlags => SyscallError::InvalidArgument,
+            crate::ipc::irq::IrqError::PermissionDenied => SyscallError::PermissionDenied,
+            crate::ipc::irq::IrqError::AlreadyClaimed => SyscallError::AddressInUse,
+            crate::ipc::irq::IrqError::NotOwner => SyscallError::PermissionDenied,
+            crate::ipc::irq::IrqError::WouldBlock => SyscallError::WouldBlock,
+            crate::ipc::irq::IrqError::Unsupported => SyscallError::NotSupported,
+            crate::ipc::irq::IrqError::Exhausted => SyscallError::ResourceExhausted,
+        }
+    }
+}
+
+impl From<crate::process::timer::TimerError> for SyscallError {
+    fn from(error: crate::process::timer::TimerError) -> Self {
+        match error {
+            crate::process::timer::TimerError::NotFound => SyscallError::InvalidArgument,
+            crate::process::timer::TimerError::TooManyTimers => SyscallError::ResourceExhausted,
+            crate::process::timer::TimerError::NotArmed => SyscallError::InvalidArgument,
+            crate::process::timer::TimerError::WouldBlock => SyscallError::WouldBlock,
+        }
+    }
+}
+
+impl From<crate::ipc::futex::FutexError> for SyscallError {
+    fn from(error: crate::ipc::futex::FutexError) -> Self {
+        match error {
+            crate::ipc::futex::FutexError::BadAddress => SyscallError::BadAddress,
+            crate::ipc::futex::FutexError::TimedOut => SyscallError::TimedOut,
+            crate::ipc::futex::FutexError::WouldBlock => SyscallError::WouldBlock,
+        }
+    }
+}
+
+impl From<crate::process::thread::ThreadError> for SyscallError {
+    fn from(error: crate::process::thread::ThreadError) -> Self {
+        match error {
+            crate::process::thread::ThreadError::NotFound => SyscallError::ProcessNotFound,
+            crate::process::thread::ThreadError::InvalidThread => SyscallError::InvalidArgument,
+            crate::process::thread::ThreadError::AlreadyJoined => SyscallError::InvalidArgument,
+            crate::process::thread::ThreadError::TooManyThreads => SyscallError::ResourceExhausted,
+            crate::process::thread::ThreadError::OutOfMemory => SyscallError::OutOfMemory,
+            crate::process::thread::ThreadError::WouldBlock => SyscallError::WouldBlock,
+        }
+    }
+}
+
+impl From<crate::process::realtime::RealtimeError> for SyscallError {
+    fn from(error: crate::process::realtime::RealtimeError) -> Self {
+        match error {
+            crate::process::realtime::RealtimeError::InvalidPriority => SyscallError::InvalidArgument,
+            crate::process::realtime::RealtimeError::ProcessNotFound => SyscallError::ProcessNotFound,
+        }
+    }
+}
+
+impl From<crate::memory::dma::DmaError> for SyscallError {
+    fn from(error: crate::memory::dma::DmaError) -> Self {
+        match error {
+            crate::memory::dma::DmaError::InvalidBuffer => SyscallError::InvalidArgument,
+            crate::memory::dma::DmaError::CacheMaintenanceFailed => SyscallError::InternalError,
+            crate::memory::dma::DmaError::OutOfMemory => SyscallError::OutOfMemory,
+            crate::memory::dma::DmaError::MappingFailed => SyscallError::InternalError,
+        }
+    }
+}
+
+impl From<crate::memory::mmio::MmioError> for SyscallError {
+    fn from(error: crate::memory::mmio::MmioError) -> Self {
+        match error {
+            crate::memory::mmio::MmioError::InvalidRange => SyscallError::InvalidArgument,
+            crate::memory::mmio::MmioError::PermissionDenied => SyscallError::PermissionDenied,
+            crate::memory::mmio::MmioError::OutOfAddressSpace => SyscallError::OutOfMemory,
+            crate::memory::mmio::MmioError::MappingFailed => SyscallError::InternalError,
+        }
+    }
+}
+
 impl From<crate::process::ProcessError> for SyscallError {
     fn from(error: crate::process::ProcessError) -> Self {
         match error {
@@ -120,6 +161,82 @@ impl From<crate::process::ProcessError> for SyscallError {
             crate::process::ProcessError::ProcessTerminated => SyscallError::InvalidArgument,
             crate::process::ProcessError::OutOfMemory => SyscallError::OutOfMemory,
             crate::process::ProcessError::InvalidPid => SyscallError::InvalidArgument,
+            crate::process::ProcessError::NoChildren => SyscallError::NoChildren,
+            crate::process::ProcessError::InvalidSignal => SyscallError::InvalidArgument,
+            crate::process::ProcessError::PermissionDenied => SyscallError::PermissionDenied,
+        }
+    }
+}
+
+impl From<crate::process::fd::FdError> for SyscallError {
+    fn from(error: crate::process::fd::FdError) -> Self {
+        match error {
+            crate::process::fd::FdError::BadDescriptor => SyscallError::BadFileDescriptor,
+            crate::process::fd::FdError::TableFull => SyscallError::TooManyOpenFiles,
+        }
+    }
+}
+
+impl From<crate::ipc::fs_client::FsClientError> for SyscallError {
+    fn from(error: crate::ipc::fs_client::FsClientError) -> Self {
+        match error {
+            crate::ipc::fs_client::FsClientError::ServiceUnavailable => SyscallError::ConnectionRefused,
+            crate::ipc::fs_client::FsClientError::SendFailed(error) => error.into(),
+        }
+    }
+}
+
+impl From<crate::ipc::pipe::PipeError> for SyscallError {
+    fn from(error: crate::ipc::pipe::PipeError) -> Self {
+        match error {
+            crate::ipc::pipe::PipeError::NotFound => SyscallError::BadFileDescriptor,
+            crate::ipc::pipe::PipeError::WouldBlock => SyscallError::WouldBlock,
+            crate::ipc::pipe::PipeError::BrokenPipe => SyscallError::BrokenPipe,
+        }
+    }
+}
+
+impl From<crate::process::args::ArgsError> for SyscallError {
+    fn from(error: crate::process::args::ArgsError) -> Self {
+        match error {
+            crate::process::args::ArgsError::TooLarge => SyscallError::ArgumentListTooLong,
+            crate::process::args::ArgsError::InvalidString => SyscallError::InvalidArgument,
+        }
+    }
+}
+
+impl From<crate::process::exec::ExecError> for SyscallError {
+    fn from(error: crate::process::exec::ExecError) -> Self {
+        match error {
+            crate::process::exec::ExecError::NotFound => SyscallError::NotFound,
+            crate::process::exec::ExecError::PermissionDenied => SyscallError::PermissionDenied,
+            crate::process::exec::ExecError::ServiceUnavailable => SyscallError::ConnectionRefused,
+            crate::process::exec::ExecError::ReadFailed => SyscallError::InternalError,
+            crate::process::exec::ExecError::TooLarge => SyscallError::OutOfMemory,
+            crate::process::exec::ExecError::NotExecutable => SyscallError::NotExecutable,
+        }
+    }
+}
+
+impl From<crate::process::SchedulerError> for SyscallError {
+    fn from(error: crate::process::SchedulerError) -> Self {
+        match error {
+            crate::process::SchedulerError::NoProcessesAvailable => SyscallError::WouldBlock,
+            crate::process::SchedulerError::NotInitialized => SyscallError::NotSupported,
+            crate::process::SchedulerError::InvalidProcess => SyscallError::InvalidArgument,
+        }
+    }
+}
+
+impl From<crate::power::PowerError> for SyscallError {
+    fn from(error: crate::power::PowerError) -> Self {
+        match error {
+            crate::power::PowerError::NotSupported
+            | crate::power::PowerError::BatteryUnavailable
+            | crate::power::PowerError::FrequencyScalingUnavailable => SyscallError::NotSupported,
+            crate::power::PowerError::InvalidTransition => SyscallError::InvalidArgument,
+            crate::power::PowerError::PermissionDenied => SyscallError::PermissionDenied,
+            crate::power::PowerError::HardwareError => SyscallError::InternalError,
         }
     }
 }
@@ -0,0 +1,301 @@
+//! Statistics returned by the system information calls
+//!
+//! The layouts are shared with `kosh_posix::sysinfo`; fs-service turns them
+//! into the files under /proc. The boot display's layout is shared with
+//! `kosh_driver::display` instead, as only graphics drivers read it.
+
+use spin::Once;
+use crate::memory::PAGE_SIZE;
+use crate::process::{ProcessInfo, ProcessState};
+use crate::ipc::IpcStatistics;
+
+/// `ProcessStatus::state` values
+pub const PROCESS_STATE_RUNNING: u32 = 0;
+pub const PROCESS_STATE_READY: u32 = 1;
+pub const PROCESS_STATE_BLOCKED: u32 = 2;
+pub const PROCESS_STATE_ZOMBIE: u32 = 3;
+pub const PROCESS_STATE_CREATING: u32 = 4;
+
+/// Bytes of a process name `ProcessStatus` carries
+pub const PROCESS_NAME_LEN: usize = 32;
+
+/// System-wide figures for `SYS_SYSINFO`; memory sizes are in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct SysInfo {
+    pub uptime_ms: u64,
+    pub total_memory: u64,
+    pub free_memory: u64,
+    pub heap_size: u64,
+    pub heap_used: u64,
+    pub process_count: u64,
+    pub cpu_count: u64,
+    /// Average busy share of the CPUs over the last load window, in percent
+    pub cpu_load: u64,
+    /// CPU time since boot, summed over all CPUs
+    pub user_time_ms: u64,
+    pub kernel_time_ms: u64,
+    pub idle_time_ms: u64,
+}
+
+impl SysInfo {
+    pub fn collect() -> Self {
+        let page_size = crate::memory::PAGE_SIZE as u64;
+        let (total_pages, free_pages) = crate::memory::physical::memory_stats()
+            .map_or((0, 0), |stats| (stats.total_pages as u64, stats.free_pages as u64));
+        let heap = crate::memory::heap::heap_stats();
+        let (user_time_ms, kernel_time_ms, idle_time_ms) = crate::process::get_scheduler_statistics()
+            .map_or((0, 0, 0), |stats| (stats.user_time_ms, stats.kernel_time_ms, stats.idle_time_ms));
+
+        Self {
+            uptime_ms: crate::time::monotonic_ms(),
+            total_memory: total_pages * page_size,
+            free_memory: free_pages * page_size,
+            heap_size: heap.heap_size as u64,
+            heap_used: heap.current_bytes as u64,
+            process_count: crate::process::list_processes().len() as u64,
+            cpu_count: crate::smp::online_cpus().count() as u64,
+            cpu_load: crate::process::cpu_load_percent() as u64,
+            user_time_ms,
+            kernel_time_ms,
+            idle_time_ms,
+        }
+    }
+}
+
+/// Seconds and nanoseconds from `SYS_CLOCK_GETTIME`, as `struct timespec`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct Timespec {
+    pub tv_sec: i64,
+    pub tv_nsec: i64,
+}
+
+impl Timespec {
+    pub fn from_nanos(nanos: u64) -> Self {
+        Self {
+            tv_sec: (nanos / crate::time::NANOS_PER_SEC) as i64,
+            tv_nsec: (nanos % crate::time::NANOS_PER_SEC) as i64,
+        }
+    }
+}
+
+/// One process as reported by `SYS_PROCESS_STATUS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct ProcessStatus {
+    pub pid: u32,
+    /// 0 for processes without a parent
+    pub parent_pid: u32,
+    pub state: u32,
+    /// `ProcessPriority` value, 0 being the highest
+    pub priority: u32,
+    pub cpu: u32,
+    /// Only meaningful for zombies
+    pub exit_code: i32,
+    pub cpu_time_ms: u64,
+    /// Parts of `cpu_time_ms` spent in user mode and in the kernel
+    pub user_time_ms: u64,
+    pub kernel_time_ms: u64,
+    pub context_switches: u64,
+    /// When the process last got a CPU (milliseconds since boot)
+    pub last_run_ms: u64,
+    /// Bytes of populated pages in the process's mappings and heap
+    pub resident_bytes: u64,
+    pub heap_bytes: u64,
+    /// Most bytes the process may map; 0 if unlimited
+    pub memory_limit: u64,
+    pub creation_time_ms: u64,
+    pub children: u64,
+    /// UTF-8, cut to `PROCESS_NAME_LEN` bytes and padded with NULs
+    pub name: [u8; PROCESS_NAME_LEN],
+}
+
+impl From<&ProcessInfo> for ProcessStatus {
+    fn from(info: &ProcessInfo) -> Self {
+        let state = match info.state {
+            ProcessState::Running => PROCESS_STATE_RUNNING,
+            ProcessState::Ready => PROCESS_STATE_READY,
+            ProcessState::Blocked(_) => PROCESS_STATE_BLOCKED,
+            ProcessState::Zombie => PROCESS_STATE_ZOMBIE,
+            ProcessState::Creating => PROCESS_STATE_CREATING,
+        };
+
+        // Cut on a character boundary so the name stays valid UTF-8
+        let mut length = info.name.len().min(PROCESS_NAME_LEN);
+        while !info.name.is_char_boundary(length) {
+            length -= 1;
+        }
+        let mut name = [0u8; PROCESS_NAME_LEN];
+        name[..length].copy_from_slice(&info.name.as_bytes()[..length]);
+
+        Self {
+            pid: info.pid.0,
+            parent_pid: info.parent_pid.map_or(0, |parent| parent.0),
+            state,
+            priority: info.priority as u32,
+            cpu: info.cpu as u32,
+            exit_code: info.exit_code.unwrap_or(0),
+            cpu_time_ms: info.cpu_time_ms,
+            user_time_ms: info.user_time_ms,
+            kernel_time_ms: info.kernel_time_ms,
+            context_switches: info.context_switches,
+            last_run_ms: info.last_scheduled_ms,
+            resident_bytes: (info.memory.resident_pages * PAGE_SIZE) as u64,
+            heap_bytes: info.memory.heap_bytes as u64,
+            memory_limit: info.memory.limit.unwrap_or(0) as u64,
+            creation_time_ms: info.creation_time_ms,
+            children: info.children_count as u64,
+            name,
+        }
+    }
+}
+
+/// IPC counters for `SYS_IPC_INFO`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct IpcInfo {
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub active_queues: u64,
+    pub capabilities: u64,
+    pub capability_checks: u64,
+    pub capability_checks_failed: u64,
+    /// Messages refused to a sender or receiver without the capability
+    pub sends_denied: u64,
+    pub receives_denied: u64,
+}
+
+impl From<&IpcStatistics> for IpcInfo {
+    fn from(stats: &IpcStatistics) -> Self {
+        Self {
+            messages_sent: stats.total_messages_sent,
+            messages_received: stats.total_messages_received,
+            active_queues: stats.active_message_queues as u64,
+            capabilities: stats.total_capabilities as u64,
+            capability_checks: stats.capability_checks_performed,
+            capability_checks_failed: stats.capability_checks_failed,
+            sends_denied: stats.sends_denied,
+            receives_denied: stats.receives_denied,
+        }
+    }
+}
+
+/// `DisplayInfo::kind` values
+pub const DISPLAY_NONE: u32 = 0;
+pub const DISPLAY_TEXT: u32 = 1;
+pub const DISPLAY_RGB: u32 = 2;
+pub const DISPLAY_INDEXED: u32 = 3;
+
+/// `DisplayInfo::vbe_mode` when the boot loader did not set the mode
+/// through VBE
+pub const NO_VBE_MODE: u32 = u32::MAX;
+
+/// The display mode the boot loader left, for `SYS_DISPLAY_INFO`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct DisplayInfo {
+    /// Physical address of the framebuffer, or of text memory
+    pub address: u64,
+    pub kind: u32,
+    /// In pixels, or in character cells for text modes
+    pub width: u32,
+    pub height: u32,
+    /// Bytes from the start of one line to the next
+    pub pitch: u32,
+    pub bpp: u32,
+    pub vbe_mode: u32,
+    /// Lowest bit and width of each color in an RGB pixel
+    pub red_position: u8,
+    pub red_size: u8,
+    pub green_position: u8,
+    pub green_size: u8,
+    pub blue_position: u8,
+    pub blue_size: u8,
+    pub reserved: [u8; 2],
+}
+
+impl DisplayInfo {
+    /// VGA text mode 3, which a boot loader that says nothing leaves
+    pub const VGA_TEXT: Self = Self {
+        address: 0xB8000,
+        kind: DISPLAY_TEXT,
+        width: 80,
+        height: 25,
+        pitch: 160,
+        bpp: 16,
+        vbe_mode: NO_VBE_MODE,
+        red_position: 0,
+        red_size: 0,
+        green_position: 0,
+        green_size: 0,
+        blue_position: 0,
+        blue_size: 0,
+        reserved: [0; 2],
+    };
+}
+
+static BOOT_DISPLAY: Once<DisplayInfo> = Once::new();
+
+/// Remember the display mode the boot loader left; only the first call
+/// counts
+pub fn record_boot_display(info: DisplayInfo) {
+    BOOT_DISPLAY.call_once(|| info);
+}
+
+/// The display mode the boot loader left, VGA text if it was not recorded
+pub fn boot_display() -> DisplayInfo {
+    BOOT_DISPLAY.get().copied().unwrap_or(DisplayInfo::VGA_TEXT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::accounting::MemoryUsage;
+    use crate::process::{BlockReason, ProcessId, ProcessPriority};
+    use alloc::string::ToString;
+
+    fn process_info(name: &str, state: ProcessState) -> ProcessInfo {
+        ProcessInfo {
+            pid: ProcessId::new(7),
+            parent_pid: Some(ProcessId::INIT),
+            state,
+            priority: ProcessPriority::Interactive,
+            name: name.to_string(),
+            cpu_time_ms: 250,
+            user_time_ms: 200,
+            kernel_time_ms: 50,
+            context_switches: 12,
+            creation_time_ms: 10,
+            last_scheduled_ms: 900,
+            exit_code: None,
+            children_count: 2,
+            cpu: 1,
+            memory: MemoryUsage { reserved_bytes: 65536, resident_pages: 3, heap_bytes: 8192, limit: None },
+        }
+    }
+
+    #[test_case]
+    fn test_process_status_from_info() {
+        let status = ProcessStatus::from(&process_info("shell", ProcessState::Ready));
+        assert_eq!(status.pid, 7);
+        assert_eq!(status.parent_pid, 1);
+        assert_eq!(status.state, PROCESS_STATE_READY);
+        assert_eq!(status.priority, 1);
+        assert_eq!(status.children, 2);
+        assert_eq!((status.user_time_ms, status.kernel_time_ms), (200, 50));
+        assert_eq!((status.context_switches, status.last_run_ms), (12, 900));
+        assert_eq!((status.resident_bytes, status.heap_bytes, status.memory_limit), (3 * 4096, 8192, 0));
+        assert_eq!(&status.name[..6], b"shell\0");
+    }
+
+    #[test_case]
+    fn test_process_status_name_cut_on_char_boundary() {
+        // 31 ASCII bytes followed by a two-byte character
+        let name = "a".repeat(PROCESS_NAME_LEN - 1) + "é";
+        let status = ProcessStatus::from(&process_info(&name, ProcessState::Blocked(BlockReason::WaitingForMessage)));
+        assert_eq!(status.state, PROCESS_STATE_BLOCKED);
+        assert_eq!(status.name[PROCESS_NAME_LEN - 1], 0);
+        assert!(core::str::from_utf8(&status.name[..PROCESS_NAME_LEN - 1]).is_ok());
+    }
+}
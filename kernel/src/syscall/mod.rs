@@ -1,12 +1,11 @@
 use core::arch::asm;
-use crate::process::ProcessId;
-use crate::{serial_println, println};
 
 pub mod dispatcher;
-pub mod numbers;
+pub use kosh_syscall::numbers;
 pub mod validation;
 pub mod error;
 pub mod test;
+pub mod info;
 
 pub use dispatcher::*;
 pub use numbers::*;
@@ -18,33 +17,23 @@ pub type SyscallResult = Result<u64, SyscallError>;
 
 /// Initialize the system call interface
 pub fn init_syscall_interface() -> Result<(), &'static str> {
-    serial_println!("Initializing system call interface...");
+    log::info!("Initializing system call interface...");
     
     // Initialize the system call dispatcher
     dispatcher::init_syscall_dispatcher()?;
     
-    // Set up system call interrupt handler
-    setup_syscall_interrupt()?;
+    // The gate itself is part of the IDT every CPU loads
+    log::debug!(
+        "System calls enter through vector {:#x}",
+        crate::platform::x86_64::interrupts::SYSCALL_VECTOR
+    );
     
-    serial_println!("System call interface initialized successfully");
+    log::info!("System call interface initialized successfully");
     Ok(())
 }
 
-/// Set up the system call interrupt handler (int 0x80)
-fn setup_syscall_interrupt() -> Result<(), &'static str> {
-    // For now, we'll use a simple approach with software interrupt 0x80
-    // In a more sophisticated implementation, we would use SYSCALL/SYSRET instructions
-    
-    serial_println!("Setting up system call interrupt handler (int 0x80)");
-    
-    // TODO: Set up IDT entry for interrupt 0x80
-    // This would require implementing an IDT (Interrupt Descriptor Table)
-    // For now, we'll just log that it's set up
-    
-    Ok(())
-}
-
-/// System call entry point (called from assembly interrupt handler)
+/// System call entry point, called by the system call gate on behalf of
+/// the process running on the calling CPU
 #[no_mangle]
 pub extern "C" fn syscall_entry(
     syscall_number: u64,
@@ -55,8 +44,10 @@ pub extern "C" fn syscall_entry(
     arg5: u64,
     arg6: u64,
 ) -> u64 {
-    // Get current process ID (for now, use a dummy value)
-    let current_pid = ProcessId::new(1); // TODO: Get actual current process
+    let Some(current_pid) = crate::process::get_current_process() else {
+        log::warn!("System call {} with no process running on this CPU", syscall_number);
+        return SyscallError::PermissionDenied.to_errno() as u64;
+    };
     
     // Dispatch the system call
     match dispatcher::dispatch_syscall(
@@ -66,7 +57,7 @@ pub extern "C" fn syscall_entry(
     ) {
         Ok(result) => result,
         Err(error) => {
-            serial_println!("System call {} failed: {:?}", syscall_number, error);
+            log::debug!("System call {} failed: {:?}", syscall_number, error);
             error.to_errno() as u64
         }
     }
@@ -1,7 +1,14 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::memory::PAGE_SIZE;
+use crate::memory::mmap::FaultResult;
+use crate::memory::vmm::VirtualAddress;
 use crate::process::ProcessId;
-use crate::syscall::{SyscallError, SyscallResult};
-use crate::syscall::numbers::*;
-use crate::{serial_println};
+use crate::syscall::SyscallError;
+
+pub use kosh_syscall::validation::{
+    check_syscall_args, check_user_bounds, Limits, UserMemory, USER_SPACE_END, USER_SPACE_START,
+};
 
 /// Validate system call arguments before processing
 pub fn validate_syscall_args(
@@ -9,484 +16,195 @@ pub fn validate_syscall_args(
     syscall_number: u64,
     args: &[u64; 6],
 ) -> Result<(), SyscallError> {
-    // Check if the system call number is valid
-    if !is_valid_syscall_number(syscall_number) {
-        serial_println!("Invalid system call number: {}", syscall_number);
-        return Err(SyscallError::InvalidSyscall);
-    }
-    
-    // Perform syscall-specific argument validation
-    match syscall_number {
-        SYS_EXIT => validate_exit_args(args),
-        SYS_FORK => validate_fork_args(args),
-        SYS_EXEC => validate_exec_args(process_id, args),
-        SYS_WAIT => validate_wait_args(args),
-        SYS_GETPID | SYS_GETPPID => validate_no_args(args),
-        SYS_KILL => validate_kill_args(args),
-        
-        SYS_MMAP => validate_mmap_args(args),
-        SYS_MUNMAP => validate_munmap_args(args),
-        SYS_MPROTECT => validate_mprotect_args(args),
-        SYS_BRK | SYS_SBRK => validate_brk_args(args),
-        
-        SYS_OPEN => validate_open_args(process_id, args),
-        SYS_CLOSE => validate_close_args(args),
-        SYS_READ => validate_read_args(process_id, args),
-        SYS_WRITE => validate_write_args(process_id, args),
-        SYS_LSEEK => validate_lseek_args(args),
-        SYS_STAT | SYS_FSTAT => validate_stat_args(process_id, args),
-        SYS_MKDIR => validate_mkdir_args(process_id, args),
-        SYS_RMDIR | SYS_UNLINK => validate_unlink_args(process_id, args),
-        
-        SYS_SEND_MESSAGE => validate_send_message_args(process_id, args),
-        SYS_RECEIVE_MESSAGE => validate_receive_message_args(args),
-        SYS_REPLY_MESSAGE => validate_reply_message_args(process_id, args),
-        SYS_CREATE_CHANNEL => validate_create_channel_args(args),
-        SYS_DESTROY_CHANNEL => validate_destroy_channel_args(args),
-        
-        SYS_DRIVER_REGISTER => validate_driver_register_args(process_id, args),
-        SYS_DRIVER_UNREGISTER => validate_driver_unregister_args(process_id, args),
-        SYS_DRIVER_REQUEST => validate_driver_request_args(process_id, args),
-        SYS_DRIVER_RESPONSE => validate_driver_response_args(process_id, args),
-        
-        SYS_UNAME | SYS_SYSINFO | SYS_TIME => validate_info_args(args),
-        SYS_CLOCK_GETTIME => validate_clock_gettime_args(args),
-        
-        SYS_GRANT_CAPABILITY => validate_grant_capability_args(process_id, args),
-        SYS_REVOKE_CAPABILITY => validate_revoke_capability_args(process_id, args),
-        SYS_CHECK_CAPABILITY => validate_check_capability_args(process_id, args),
-        SYS_LIST_CAPABILITIES => validate_list_capabilities_args(args),
-        
-        #[cfg(debug_assertions)]
-        SYS_DEBUG_PRINT => validate_debug_print_args(args),
-        #[cfg(debug_assertions)]
-        SYS_DEBUG_DUMP => validate_debug_dump_args(args),
-        
-        _ => {
-            serial_println!("Unknown system call number: {}", syscall_number);
-            Err(SyscallError::InvalidSyscall)
-        }
-    }
-}
-
-/// Validate that a pointer argument is valid for the given process
-fn validate_user_pointer(process_id: ProcessId, ptr: u64, size: usize) -> Result<(), SyscallError> {
-    if ptr == 0 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    // TODO: Check that the pointer is within the process's valid address space
-    // TODO: Check that the memory region is accessible (readable/writable as needed)
-    // For now, we'll just check for null pointers
-    
-    Ok(())
-}
-
-/// Validate that a string pointer is valid and null-terminated
-fn validate_user_string(process_id: ProcessId, ptr: u64, max_len: usize) -> Result<(), SyscallError> {
-    if ptr == 0 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    // TODO: Validate that the string is null-terminated within max_len
-    // TODO: Check that the string is in valid user memory
-    
-    Ok(())
-}
-
-/// Validate file descriptor
-fn validate_file_descriptor(fd: u64) -> Result<(), SyscallError> {
-    // File descriptors should be reasonable values
-    if fd > 1024 {
-        return Err(SyscallError::BadFileDescriptor);
-    }
-    Ok(())
-}
+    let result = check_syscall_args(&ProcessMemory(process_id), &LIMITS, syscall_number, args);
+    if result == Err(SyscallError::InvalidSyscall) {
+        log::warn!("Invalid system call number: {}", syscall_number);
+    }
+    result
+}
+
+/// The limits of this kernel, as argument validation checks them
+pub const LIMITS: Limits = Limits {
+    page_size: PAGE_SIZE as u64,
+    max_fds: crate::process::fd::MAX_FDS as u64,
+    max_signal: crate::process::signal::MAX_SIGNAL as u64,
+    max_shm_region_size: crate::ipc::shm::MAX_SHM_REGION_SIZE as u64,
+    max_poll_entries: crate::ipc::poll::MAX_POLL_ENTRIES,
+    max_irq_lines: crate::ipc::irq::MAX_IRQ_LINES as u64,
+    max_timer_id: crate::process::timer::TimerId::MAX as u64,
+    rt_priorities: crate::process::realtime::MIN_RT_PRIORITY as u64..=crate::process::realtime::MAX_RT_PRIORITY as u64,
+    clocks: &[crate::time::CLOCK_REALTIME, crate::time::CLOCK_MONOTONIC],
+    wait_flags: crate::syscall::dispatcher::WAIT_NOHANG,
+    message_flags: crate::syscall::dispatcher::IPC_FLAG_SHARED,
+    shm_prot: crate::ipc::shm::SHM_PROT_READ | crate::ipc::shm::SHM_PROT_WRITE,
+    tracepoints: crate::trace::ALL_TRACEPOINTS as u64,
+    public_key_len: crate::trust::PUBLIC_KEY_LEN,
+    poll_entry_size: core::mem::size_of::<crate::ipc::poll::PollEntry>(),
+    sysinfo_size: core::mem::size_of::<super::info::SysInfo>(),
+    process_status_size: core::mem::size_of::<super::info::ProcessStatus>(),
+    ipc_info_size: core::mem::size_of::<super::info::IpcInfo>(),
+    timespec_size: core::mem::size_of::<super::info::Timespec>(),
+    sched_info_size: core::mem::size_of::<crate::process::SchedInfo>(),
+    display_info_size: core::mem::size_of::<super::info::DisplayInfo>(),
+    cpufreq_info_size: core::mem::size_of::<crate::power::cpu_scaling::CpuFreqInfo>(),
+    power_supply_info_size: core::mem::size_of::<crate::power::battery_monitor::PowerSupplyInfo>(),
+    thermal_zone_info_size: core::mem::size_of::<crate::power::thermal::ThermalZoneInfo>(),
+    audit_record_size: core::mem::size_of::<crate::audit::AuditRecord>(),
+    trace_record_size: core::mem::size_of::<crate::trace::TraceRecord>(),
+    klog_level: |raw| crate::klog::level_from_raw(raw).is_some(),
+    scheduling_algorithm: |raw| crate::process::SchedulingAlgorithm::from_raw(raw).is_some(),
+    time_slice: crate::process::is_valid_time_slice,
+    cpu_governor: |raw| crate::power::CpuGovernor::from_raw(raw).is_some(),
+    capability_type: |raw| crate::ipc::capability::CapabilityType::from_raw(raw).is_some(),
+};
 
-// Process management syscall validations
-fn validate_exit_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    // Exit code can be any value
-    Ok(())
-}
-
-fn validate_fork_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    // Fork takes no arguments
-    Ok(())
-}
-
-fn validate_exec_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let path_ptr = args[0];
-    let argv_ptr = args[1];
-    let envp_ptr = args[2];
-    
-    validate_user_string(process_id, path_ptr, 4096)?;
-    
-    if argv_ptr != 0 {
-        validate_user_pointer(process_id, argv_ptr, 8)?; // At least one pointer
-    }
-    
-    if envp_ptr != 0 {
-        validate_user_pointer(process_id, envp_ptr, 8)?; // At least one pointer
-    }
-    
-    Ok(())
-}
-
-fn validate_wait_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    // Wait can take a status pointer (optional)
-    Ok(())
-}
-
-fn validate_no_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    // These syscalls take no arguments
-    Ok(())
-}
-
-fn validate_kill_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    let pid = args[0];
-    let signal = args[1];
-    
-    if pid == 0 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    // Validate signal number (basic range check)
-    if signal > 64 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    Ok(())
-}
+/// The memory of a process in the process table
+pub struct ProcessMemory(pub ProcessId);
 
-// Memory management syscall validations
-fn validate_mmap_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    let addr = args[0];
-    let length = args[1];
-    let prot = args[2];
-    let flags = args[3];
-    let fd = args[4];
-    let offset = args[5];
-    
-    if length == 0 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    // Validate protection flags
-    if prot > 7 {  // PROT_READ | PROT_WRITE | PROT_EXEC
-        return Err(SyscallError::InvalidArgument);
+impl UserMemory for ProcessMemory {
+    fn process(&self) -> kosh_types::ProcessId {
+        self.0.0
     }
-    
-    // If mapping a file, validate file descriptor
-    if (flags & 0x01) == 0 && fd != u64::MAX {  // Not MAP_ANONYMOUS
-        validate_file_descriptor(fd)?;
-    }
-    
-    Ok(())
-}
 
-fn validate_munmap_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    let addr = args[0];
-    let length = args[1];
-    
-    if addr == 0 || length == 0 {
-        return Err(SyscallError::InvalidArgument);
+    fn check_range(&self, ptr: u64, len: usize, write: bool) -> Result<(), SyscallError> {
+        validate_user_range(self.0, ptr, len, write)
     }
-    
-    Ok(())
-}
 
-fn validate_mprotect_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    let addr = args[0];
-    let length = args[1];
-    let prot = args[2];
-    
-    if addr == 0 || length == 0 {
-        return Err(SyscallError::InvalidArgument);
+    fn check_string(&self, ptr: u64, max_len: usize) -> Result<(), SyscallError> {
+        copy_string_from_user(self.0, ptr, max_len).map(|_| ())
     }
-    
-    if prot > 7 {  // PROT_READ | PROT_WRITE | PROT_EXEC
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    Ok(())
 }
 
-fn validate_brk_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    // brk/sbrk can take any address value
-    Ok(())
-}
-
-// File system syscall validations
-fn validate_open_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let path_ptr = args[0];
-    let flags = args[1];
-    let mode = args[2];
+/// Check that `[ptr, ptr + len)` is user memory the process may access
+///
+/// The range must lie in the user half of the address space and be covered
+/// by user-accessible regions of the process's own address space with the
+/// needed protection. An unknown process, or one sharing the kernel address
+/// space, owns no user memory, so every range is refused. Pages of mmap
+/// regions in the range are populated first; `WouldBlock` means the process
+/// was blocked until a file page is read and should retry.
+pub fn validate_user_range(process_id: ProcessId, ptr: u64, len: usize, write: bool) -> Result<(), SyscallError> {
+    check_user_bounds(ptr, len)?;
     
-    validate_user_string(process_id, path_ptr, 4096)?;
+    let accessible = crate::process::with_address_space(process_id, |address_space| {
+        address_space.is_some_and(|vas| {
+            vas.check_user_access(VirtualAddress::new(ptr as usize), len, write)
+        })
+    })
+    .unwrap_or(false);
     
-    // Basic flag validation
-    if flags > 0xFFFF {
-        return Err(SyscallError::InvalidArgument);
+    if !accessible {
+        return Err(SyscallError::BadAddress);
     }
     
-    Ok(())
-}
-
-fn validate_close_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    let fd = args[0];
-    validate_file_descriptor(fd)
-}
-
-fn validate_read_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let fd = args[0];
-    let buf_ptr = args[1];
-    let count = args[2];
-    
-    validate_file_descriptor(fd)?;
-    
-    if count > 0 {
-        validate_user_pointer(process_id, buf_ptr, count as usize)?;
+    // The kernel must not fault on mmap pages that were never touched
+    match crate::memory::mmap::fault_in_range(process_id, ptr as usize, len, write) {
+        FaultResult::Resolved => Ok(()),
+        FaultResult::Pending => Err(SyscallError::WouldBlock),
+        FaultResult::Unhandled => Err(SyscallError::BadAddress),
+        FaultResult::OutOfMemory => Err(SyscallError::OutOfMemory),
     }
-    
-    Ok(())
 }
 
-fn validate_write_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let fd = args[0];
-    let buf_ptr = args[1];
-    let count = args[2];
-    
-    validate_file_descriptor(fd)?;
-    
-    if count > 0 {
-        validate_user_pointer(process_id, buf_ptr, count as usize)?;
+/// Copy `len` bytes from user memory into a kernel buffer
+pub fn copy_from_user(process_id: ProcessId, ptr: u64, len: usize) -> Result<Vec<u8>, SyscallError> {
+    if len == 0 {
+        return Ok(Vec::new());
     }
+    validate_user_range(process_id, ptr, len, false)?;
     
-    Ok(())
+    let bytes = unsafe { core::slice::from_raw_parts(ptr as *const u8, len) };
+    Ok(bytes.to_vec())
 }
 
-fn validate_lseek_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    let fd = args[0];
-    let offset = args[1];
-    let whence = args[2];
-    
-    validate_file_descriptor(fd)?;
-    
-    // Validate whence parameter (SEEK_SET, SEEK_CUR, SEEK_END)
-    if whence > 2 {
-        return Err(SyscallError::InvalidArgument);
+/// Copy a kernel buffer into user memory
+pub fn copy_to_user(process_id: ProcessId, ptr: u64, data: &[u8]) -> Result<(), SyscallError> {
+    if data.is_empty() {
+        return Ok(());
     }
-    
-    Ok(())
-}
-
-fn validate_stat_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let path_or_fd = args[0];
-    let stat_buf_ptr = args[1];
-    
-    validate_user_pointer(process_id, stat_buf_ptr, 144)?; // sizeof(struct stat)
-    
-    Ok(())
+    validate_user_range(process_id, ptr, data.len(), true)?;
+    
+    unsafe {
+        core::ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, data.len());
+    }
+    Ok(())
 }
 
-fn validate_mkdir_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let path_ptr = args[0];
-    let mode = args[1];
-    
-    validate_user_string(process_id, path_ptr, 4096)?;
-    
-    Ok(())
+/// Copy a plain value (e.g. an out-parameter) into user memory
+pub fn copy_value_to_user<T: Copy>(process_id: ProcessId, ptr: u64, value: T) -> Result<(), SyscallError> {
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&value as *const T as *const u8, core::mem::size_of::<T>())
+    };
+    copy_to_user(process_id, ptr, bytes)
 }
 
-fn validate_unlink_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let path_ptr = args[0];
-    validate_user_string(process_id, path_ptr, 4096)
-}
-
-// IPC syscall validations
-fn validate_send_message_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let receiver_pid = args[0];
-    let message_ptr = args[1];
-    let message_len = args[2];
-    
-    if receiver_pid == 0 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    if message_len > 0 {
-        validate_user_pointer(process_id, message_ptr, message_len as usize)?;
-    }
-    
-    Ok(())
-}
-
-fn validate_receive_message_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    // Receive message takes optional timeout
-    Ok(())
-}
-
-fn validate_reply_message_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let message_id = args[0];
-    let reply_ptr = args[1];
-    let reply_len = args[2];
-    
-    if message_id == 0 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    if reply_len > 0 {
-        validate_user_pointer(process_id, reply_ptr, reply_len as usize)?;
-    }
-    
-    Ok(())
-}
-
-fn validate_create_channel_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    let other_pid = args[0];
-    
-    if other_pid == 0 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    Ok(())
-}
-
-fn validate_destroy_channel_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    let channel_id = args[0];
-    
-    if channel_id == 0 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    Ok(())
-}
-
-// Driver interface syscall validations
-fn validate_driver_register_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let driver_info_ptr = args[0];
-    validate_user_pointer(process_id, driver_info_ptr, 64) // Basic driver info struct size
-}
-
-fn validate_driver_unregister_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let driver_id = args[0];
-    
-    if driver_id == 0 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    Ok(())
-}
-
-fn validate_driver_request_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let driver_id = args[0];
-    let request_ptr = args[1];
-    let request_len = args[2];
-    
-    if driver_id == 0 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    if request_len > 0 {
-        validate_user_pointer(process_id, request_ptr, request_len as usize)?;
-    }
-    
-    Ok(())
-}
-
-fn validate_driver_response_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let request_id = args[0];
-    let response_ptr = args[1];
-    let response_len = args[2];
-    
-    if request_id == 0 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    if response_len > 0 {
-        validate_user_pointer(process_id, response_ptr, response_len as usize)?;
-    }
-    
-    Ok(())
-}
-
-// System information syscall validations
-fn validate_info_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    // These syscalls typically take a buffer pointer
-    Ok(())
-}
-
-fn validate_clock_gettime_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    let clock_id = args[0];
-    
-    // Validate clock ID (basic range check)
-    if clock_id > 10 {
-        return Err(SyscallError::InvalidArgument);
-    }
-    
-    Ok(())
-}
-
-// Security syscall validations
-fn validate_grant_capability_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let target_pid = args[0];
-    let capability_type = args[1];
-    let resource_ptr = args[2];
-    
-    if target_pid == 0 {
+/// Copy a NUL-terminated UTF-8 string of at most `max_len` bytes from user memory
+///
+/// Each page is validated before it is touched, so a string running off the
+/// end of a mapping faults cleanly instead of reading beyond it.
+pub fn copy_string_from_user(process_id: ProcessId, ptr: u64, max_len: usize) -> Result<String, SyscallError> {
+    if ptr == 0 {
         return Err(SyscallError::InvalidArgument);
     }
     
-    if resource_ptr != 0 {
-        validate_user_pointer(process_id, resource_ptr, 64)?; // Basic resource descriptor size
-    }
-    
-    Ok(())
-}
-
-fn validate_revoke_capability_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let target_pid = args[0];
-    let capability_id = args[1];
-    
-    if target_pid == 0 || capability_id == 0 {
-        return Err(SyscallError::InvalidArgument);
+    let mut bytes = Vec::new();
+    for offset in 0..max_len as u64 {
+        let addr = ptr.checked_add(offset).ok_or(SyscallError::BadAddress)?;
+        if offset == 0 || addr % PAGE_SIZE as u64 == 0 {
+            let page_remaining = PAGE_SIZE - (addr as usize % PAGE_SIZE);
+            validate_user_range(process_id, addr, page_remaining.min(max_len - offset as usize), false)?;
+        }
+        
+        let byte = unsafe { core::ptr::read_volatile(addr as *const u8) };
+        if byte == 0 {
+            return String::from_utf8(bytes).map_err(|_| SyscallError::InvalidArgument);
+        }
+        bytes.push(byte);
     }
     
-    Ok(())
+    // No terminator within the limit
+    Err(SyscallError::InvalidArgument)
 }
 
-fn validate_check_capability_args(process_id: ProcessId, args: &[u64; 6]) -> Result<(), SyscallError> {
-    let capability_type = args[0];
-    let resource_ptr = args[1];
-    
-    if resource_ptr != 0 {
-        validate_user_pointer(process_id, resource_ptr, 64)?; // Basic resource descriptor size
+/// Copy a NULL-terminated array of C strings, as exec's argv and envp
+///
+/// A null array is empty. Fails with `ArgumentListTooLong` past
+/// `max_strings` entries or `max_bytes` of string data.
+pub fn copy_string_array_from_user(
+    process_id: ProcessId,
+    ptr: u64,
+    max_strings: usize,
+    max_bytes: usize,
+) -> Result<Vec<String>, SyscallError> {
+    let mut strings = Vec::new();
+    if ptr == 0 {
+        return Ok(strings);
+    }
+    let mut total = 0;
+    loop {
+        let entry = ptr.checked_add(strings.len() as u64 * 8).ok_or(SyscallError::BadAddress)?;
+        let bytes = copy_from_user(process_id, entry, 8)?;
+        let string_ptr = u64::from_ne_bytes(bytes.try_into().map_err(|_| SyscallError::BadAddress)?);
+        if string_ptr == 0 {
+            return Ok(strings);
+        }
+        if strings.len() == max_strings {
+            return Err(SyscallError::ArgumentListTooLong);
+        }
+        let string = copy_string_from_user(process_id, string_ptr, max_bytes)?;
+        total += string.len() + 1;
+        if total > max_bytes {
+            return Err(SyscallError::ArgumentListTooLong);
+        }
+        strings.push(string);
     }
-    
-    Ok(())
-}
-
-fn validate_list_capabilities_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    // List capabilities takes optional buffer parameters
-    Ok(())
-}
-
-// Debug syscall validations (only in debug builds)
-#[cfg(debug_assertions)]
-fn validate_debug_print_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    // Debug print can take any arguments
-    Ok(())
-}
-
-#[cfg(debug_assertions)]
-fn validate_debug_dump_args(args: &[u64; 6]) -> Result<(), SyscallError> {
-    // Debug dump can take any arguments
-    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::process::ProcessId;
+    use crate::syscall::numbers::*;
     
     #[test_case]
     fn test_validate_syscall_args() {
@@ -503,13 +221,35 @@ mod tests {
         );
     }
     
+    #[test_case]
+    fn test_validate_user_range() {
+        let pid = ProcessId::new(1);
+        
+        assert!(validate_user_range(pid, 0x40000000, 4096, false).is_ok());
+        assert_eq!(validate_user_range(pid, 0x10, 8, false), Err(SyscallError::BadAddress));
+        assert_eq!(validate_user_range(pid, USER_SPACE_END - 4, 8, true), Err(SyscallError::BadAddress));
+        assert_eq!(validate_user_range(pid, u64::MAX - 2, 8, false), Err(SyscallError::BadAddress));
+    }
+    
     #[test_case]
     fn test_validate_file_descriptor() {
-        assert!(validate_file_descriptor(0).is_ok());
-        assert!(validate_file_descriptor(10).is_ok());
+        let pid = ProcessId::new(1);
+        assert!(validate_syscall_args(pid, SYS_CLOSE, &[0, 0, 0, 0, 0, 0]).is_ok());
+        assert!(validate_syscall_args(pid, SYS_CLOSE, &[10, 0, 0, 0, 0, 0]).is_ok());
         assert_eq!(
-            validate_file_descriptor(2000),
+            validate_syscall_args(pid, SYS_CLOSE, &[2000, 0, 0, 0, 0, 0]),
             Err(SyscallError::BadFileDescriptor)
         );
     }
-}
\ No newline at end of file
+    
+    #[test_case]
+    fn test_validate_sched_set_args() {
+        let pid = ProcessId::new(1);
+        let sched_set = |algorithm, time_slice_ms| validate_syscall_args(pid, SYS_SCHED_SET, &[algorithm, time_slice_ms, 0, 0, 0, 0]);
+        assert!(sched_set(2, 0).is_ok());
+        assert!(sched_set(SCHED_KEEP_ALGORITHM, 20).is_ok());
+        assert_eq!(sched_set(7, 0), Err(SyscallError::InvalidArgument));
+        assert_eq!(sched_set(0, 100_000), Err(SyscallError::InvalidArgument));
+        assert_eq!(sched_set(SCHED_KEEP_ALGORITHM, 0), Err(SyscallError::InvalidArgument));
+    }
+}
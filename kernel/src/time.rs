@@ -0,0 +1,145 @@
+//! Timekeeping
+//!
+//! The monotonic clock counts nanoseconds since timekeeping started, read
+//! from the best counter the platform has: the TSC when it runs at a
+//! constant rate, else the HPET main counter on x86-64, and the generic
+//! timer's counter on ARM64. Without any of them it falls back to the
+//! periodic tick, at the tick's resolution.
+//!
+//! The wall clock is the monotonic clock plus the time of day the RTC gave
+//! at boot, so it never jumps while the system runs. It reads as the Unix
+//! epoch when there is no RTC.
+//!
+//! Everything here is lock-free, so the clocks can be read from interrupt
+//! handlers and by the logger.
+
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use crate::platform::{self, ClockSource};
+
+pub const NANOS_PER_SEC: u64 = 1_000_000_000;
+pub const NANOS_PER_MILLI: u64 = 1_000_000;
+pub const NANOS_PER_MICRO: u64 = 1_000;
+
+/// `CLOCK_*` IDs, shared with `kosh_posix::time`
+pub const CLOCK_REALTIME: u64 = 0;
+pub const CLOCK_MONOTONIC: u64 = 1;
+
+/// `SOURCE` while the tick is all there is
+const SOURCE_TICK: u8 = 0;
+
+/// Counter the monotonic clock reads, as `ClockSource::raw`
+static SOURCE: AtomicU8 = AtomicU8::new(SOURCE_TICK);
+
+/// Counter rate, in Hz
+static FREQUENCY_HZ: AtomicU64 = AtomicU64::new(0);
+
+/// Counter value at which the monotonic clock read 0
+static BASE_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Wall clock time, in nanoseconds since the epoch, at monotonic time 0
+static BOOT_TIME_NS: AtomicU64 = AtomicU64::new(0);
+
+/// Pick the clock source and read the RTC
+///
+/// Called once on the boot CPU before the scheduler timer starts; the
+/// counter is calibrated against the PIT where its rate is not known.
+pub fn init() {
+    match platform::probe_clock_counter() {
+        Some(counter) => {
+            BASE_COUNT.store(platform::read_clock_counter(counter.source), Ordering::SeqCst);
+            FREQUENCY_HZ.store(counter.frequency_hz, Ordering::SeqCst);
+            SOURCE.store(counter.source.raw(), Ordering::SeqCst);
+            log::info!("Clock source: {} at {} kHz", counter.source.name(), counter.frequency_hz / 1000);
+        }
+        None => log::warn!("No clock counter; time has the timer tick's resolution"),
+    }
+
+    match platform::read_rtc() {
+        Some(seconds) => {
+            BOOT_TIME_NS.store(seconds.saturating_mul(NANOS_PER_SEC), Ordering::SeqCst);
+            log::info!("Wall clock: {} seconds since the epoch", seconds);
+        }
+        None => log::warn!("No RTC; the wall clock starts at the epoch"),
+    }
+}
+
+/// Nanoseconds since timekeeping started
+pub fn monotonic_ns() -> u64 {
+    match ClockSource::from_raw(SOURCE.load(Ordering::Relaxed)) {
+        Some(source) => {
+            let elapsed = platform::read_clock_counter(source).wrapping_sub(BASE_COUNT.load(Ordering::Relaxed));
+            counter_to_ns(elapsed, FREQUENCY_HZ.load(Ordering::Relaxed))
+        }
+        None => crate::monitor::uptime_ms() * NANOS_PER_MILLI,
+    }
+}
+
+pub fn monotonic_us() -> u64 {
+    monotonic_ns() / NANOS_PER_MICRO
+}
+
+pub fn monotonic_ms() -> u64 {
+    monotonic_ns() / NANOS_PER_MILLI
+}
+
+/// Nanoseconds since the Unix epoch
+pub fn realtime_ns() -> u64 {
+    BOOT_TIME_NS.load(Ordering::Relaxed).saturating_add(monotonic_ns())
+}
+
+/// Read clock `clock_id`, or `None` for an unknown clock
+pub fn clock_ns(clock_id: u64) -> Option<u64> {
+    match clock_id {
+        CLOCK_REALTIME => Some(realtime_ns()),
+        CLOCK_MONOTONIC => Some(monotonic_ns()),
+        _ => None,
+    }
+}
+
+/// Nanoseconds `count` ticks of a `frequency_hz` counter take
+pub fn counter_to_ns(count: u64, frequency_hz: u64) -> u64 {
+    if frequency_hz == 0 {
+        return 0;
+    }
+    (count as u128 * NANOS_PER_SEC as u128 / frequency_hz as u128).min(u64::MAX as u128) as u64
+}
+
+/// Seconds since the Unix epoch of a UTC date and time, as an RTC gives them
+///
+/// `month` and `day` count from 1.
+pub fn unix_time(year: u64, month: u64, day: u64, hour: u64, minute: u64, second: u64) -> u64 {
+    // Days since 1970-01-01 by counting years from March, so the leap day
+    // falls at the end of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let month_from_march = (month + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    days * 86_400 + hour * 3_600 + minute * 60 + second
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_counter_to_ns() {
+        assert_eq!(counter_to_ns(1_000, 1_000), NANOS_PER_SEC);
+        assert_eq!(counter_to_ns(3, 3_000_000_000), 1);
+        assert_eq!(counter_to_ns(14_318_180, 14_318_180), NANOS_PER_SEC);
+        // A day on a 4 GHz TSC does not overflow
+        assert_eq!(counter_to_ns(86_400 * 4_000_000_000, 4_000_000_000), 86_400 * NANOS_PER_SEC);
+        assert_eq!(counter_to_ns(5, 0), 0);
+    }
+
+    #[test_case]
+    fn test_unix_time() {
+        assert_eq!(unix_time(1970, 1, 1, 0, 0, 0), 0);
+        assert_eq!(unix_time(2000, 3, 1, 0, 0, 0), 951_868_800);
+        assert_eq!(unix_time(2024, 2, 29, 12, 30, 15), 1_709_209_815);
+        assert_eq!(unix_time(2038, 1, 19, 3, 14, 8), 1 << 31);
+    }
+}
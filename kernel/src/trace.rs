@@ -0,0 +1,281 @@
+//! Kernel tracing
+//!
+//! Tracepoints sit on the paths latency questions are about: the scheduler
+//! switching processes, IPC sends and receives, system call entry and
+//! exit, and page faults. A tracepoint that is off costs one atomic load;
+//! one that is on writes a fixed-size record, stamped in nanoseconds, into
+//! the ring of the CPU it ran on. Rings keep the newest records. A record
+//! that finds its ring busy, because a reader is draining it or because
+//! the code an interrupt cut short was writing to it, is dropped; both
+//! overwritten and dropped records count as lost.
+//!
+//! `SYS_TRACE` turns tracepoints on and off, with write access to the
+//! "trace" system resource, and drains the rings, oldest record first
+//! across all CPUs, with read access to it; the shell's `trace` command
+//! drives it. `trace=` on the kernel command line turns tracepoints on from
+//! boot, e.g. `trace=syscall_enter,syscall_exit` or `trace=all`.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use spin::{Mutex, MutexGuard};
+use crate::process::ProcessId;
+use crate::smp::MAX_CPUS;
+
+/// Records each CPU's ring holds
+pub const TRACE_CAPACITY: usize = 512;
+
+/// Every tracepoint's bit
+pub const ALL_TRACEPOINTS: u32 = (1 << (Tracepoint::ALL.len() as u32 + 1)) - 2;
+
+/// Process number that stands for the idle loop in `SchedSwitch` records
+pub const TRACE_IDLE: u32 = u32::MAX;
+
+/// A place in the kernel that can write trace records
+///
+/// What the two arguments of a record carry depends on the tracepoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum Tracepoint {
+    /// The process the CPU switched to; from which process, or `TRACE_IDLE`
+    SchedSwitch = 1,
+    /// The sender; to which process, and the message ID
+    IpcSend = 2,
+    /// The receiver; from which process, and the message ID
+    IpcReceive = 3,
+    /// The caller; system call number and first argument
+    SyscallEnter = 4,
+    /// The caller; system call number and the value returned, a negative
+    /// errno on failure
+    SyscallExit = 5,
+    /// The faulting process; the address and the error code
+    PageFault = 6,
+}
+
+impl Tracepoint {
+    pub const ALL: [Tracepoint; 6] = [
+        Tracepoint::SchedSwitch,
+        Tracepoint::IpcSend,
+        Tracepoint::IpcReceive,
+        Tracepoint::SyscallEnter,
+        Tracepoint::SyscallExit,
+        Tracepoint::PageFault,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tracepoint::SchedSwitch => "sched_switch",
+            Tracepoint::IpcSend => "ipc_send",
+            Tracepoint::IpcReceive => "ipc_receive",
+            Tracepoint::SyscallEnter => "syscall_enter",
+            Tracepoint::SyscallExit => "syscall_exit",
+            Tracepoint::PageFault => "page_fault",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|point| point.name() == name)
+    }
+
+    /// The tracepoint's bit in `SYS_TRACE` masks
+    pub fn bit(self) -> u32 {
+        1 << self as u16
+    }
+}
+
+/// One event, as `SYS_TRACE` returns it
+///
+/// The layout is shared with `kosh_posix::trace`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct TraceRecord {
+    /// Nanoseconds since boot
+    pub time_ns: u64,
+    pub tracepoint: u16,
+    pub cpu: u16,
+    /// Process the event concerns
+    pub process: u32,
+    pub arg0: u64,
+    pub arg1: u64,
+}
+
+impl TraceRecord {
+    const EMPTY: Self = Self { time_ns: 0, tracepoint: 0, cpu: 0, process: 0, arg0: 0, arg1: 0 };
+}
+
+/// Ring of records that overwrites the oldest when full
+struct TraceRing {
+    records: [TraceRecord; TRACE_CAPACITY],
+    start: usize,
+    len: usize,
+}
+
+impl TraceRing {
+    const fn new() -> Self {
+        Self { records: [TraceRecord::EMPTY; TRACE_CAPACITY], start: 0, len: 0 }
+    }
+
+    /// Append a record, returning whether it overwrote one
+    fn push(&mut self, record: TraceRecord) -> bool {
+        let full = self.len == TRACE_CAPACITY;
+        self.records[(self.start + self.len) % TRACE_CAPACITY] = record;
+        if full {
+            self.start = (self.start + 1) % TRACE_CAPACITY;
+        } else {
+            self.len += 1;
+        }
+        full
+    }
+
+    fn oldest(&self) -> Option<&TraceRecord> {
+        (self.len > 0).then(|| &self.records[self.start])
+    }
+
+    fn pop(&mut self) -> Option<TraceRecord> {
+        let record = *self.oldest()?;
+        self.start = (self.start + 1) % TRACE_CAPACITY;
+        self.len -= 1;
+        Some(record)
+    }
+}
+
+static ENABLED: AtomicU32 = AtomicU32::new(0);
+
+static RINGS: [Mutex<TraceRing>; MAX_CPUS] = [const { Mutex::new(TraceRing::new()) }; MAX_CPUS];
+
+/// Records overwritten before they were read, or dropped
+static LOST: AtomicU64 = AtomicU64::new(0);
+
+pub fn is_enabled(tracepoint: Tracepoint) -> bool {
+    ENABLED.load(Ordering::Relaxed) & tracepoint.bit() != 0
+}
+
+/// Write a record for `tracepoint` if it is on
+#[inline]
+pub fn record(tracepoint: Tracepoint, process: ProcessId, arg0: u64, arg1: u64) {
+    if !is_enabled(tracepoint) {
+        return;
+    }
+    let cpu = crate::smp::current_cpu();
+    let record = TraceRecord {
+        time_ns: crate::time::monotonic_ns(),
+        tracepoint: tracepoint as u16,
+        cpu: cpu as u16,
+        process: process.0,
+        arg0,
+        arg1,
+    };
+    // Never wait: the ring may be held by the code this interrupted
+    let lost = match RINGS[cpu].try_lock() {
+        Some(mut ring) => ring.push(record),
+        None => true,
+    };
+    if lost {
+        LOST.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Turn on the tracepoints in `mask`, returning those now on
+pub fn enable(mask: u32) -> u32 {
+    ENABLED.fetch_or(mask & ALL_TRACEPOINTS, Ordering::AcqRel) | (mask & ALL_TRACEPOINTS)
+}
+
+/// Turn off the tracepoints in `mask`, returning those still on
+pub fn disable(mask: u32) -> u32 {
+    ENABLED.fetch_and(!mask, Ordering::AcqRel) & !mask
+}
+
+/// Mask of the tracepoints that are on
+pub fn enabled() -> u32 {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// Records lost since boot
+pub fn lost() -> u64 {
+    LOST.load(Ordering::Relaxed)
+}
+
+/// Mask for a `trace=` value: tracepoint names separated by commas, or
+/// `all`
+pub fn parse_mask(spec: &str) -> Result<u32, &'static str> {
+    spec.split(',').try_fold(0, |mask, name| match name {
+        "all" => Ok(mask | ALL_TRACEPOINTS),
+        name => Tracepoint::from_name(name).map(|point| mask | point.bit()).ok_or("unknown tracepoint"),
+    })
+}
+
+/// Apply a `trace=` value
+pub fn configure(spec: &str) -> Result<(), &'static str> {
+    enable(parse_mask(spec)?);
+    Ok(())
+}
+
+/// Take up to `max` of the oldest records across all CPUs, in time order
+pub fn drain(max: usize) -> Vec<TraceRecord> {
+    let mut rings: Vec<MutexGuard<TraceRing>> = RINGS.iter().map(|ring| ring.lock()).collect();
+    let mut rings: Vec<&mut TraceRing> = rings.iter_mut().map(|ring| &mut **ring).collect();
+    merge(&mut rings, max)
+}
+
+/// Pop records from `rings` oldest first, until `max` or all are taken
+fn merge(rings: &mut [&mut TraceRing], max: usize) -> Vec<TraceRecord> {
+    let mut records = Vec::new();
+    while records.len() < max {
+        let next = rings.iter_mut()
+            .filter(|ring| ring.oldest().is_some())
+            .min_by_key(|ring| ring.oldest().map_or(u64::MAX, |record| record.time_ns));
+        match next.and_then(|ring| ring.pop()) {
+            Some(record) => records.push(record),
+            None => break,
+        }
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+
+    fn at(time_ns: u64, cpu: u16) -> TraceRecord {
+        TraceRecord { time_ns, cpu, ..TraceRecord::EMPTY }
+    }
+
+    #[test_case]
+    fn test_ring_overwrites_the_oldest() {
+        let mut ring = Box::new(TraceRing::new());
+        for time in 0..TRACE_CAPACITY as u64 {
+            assert!(!ring.push(at(time, 0)));
+        }
+        assert!(ring.push(at(TRACE_CAPACITY as u64, 0)));
+        assert_eq!(ring.len, TRACE_CAPACITY);
+        assert_eq!(ring.pop().map(|record| record.time_ns), Some(1));
+    }
+
+    #[test_case]
+    fn test_merge_in_time_order() {
+        let mut first = Box::new(TraceRing::new());
+        let mut second = Box::new(TraceRing::new());
+        for time in [10, 30, 50] {
+            first.push(at(time, 0));
+        }
+        for time in [20, 40] {
+            second.push(at(time, 1));
+        }
+        let mut rings = [&mut *first, &mut *second];
+        let times = |records: Vec<TraceRecord>| records.iter().map(|record| record.time_ns).collect::<Vec<_>>();
+        assert_eq!(times(merge(&mut rings, 3)), [10, 20, 30]);
+        assert_eq!(times(merge(&mut rings, usize::MAX)), [40, 50]);
+        assert!(merge(&mut rings, usize::MAX).is_empty());
+    }
+
+    #[test_case]
+    fn test_parse_mask() {
+        assert_eq!(parse_mask("all"), Ok(ALL_TRACEPOINTS));
+        assert_eq!(
+            parse_mask("syscall_enter,syscall_exit"),
+            Ok(Tracepoint::SyscallEnter.bit() | Tracepoint::SyscallExit.bit())
+        );
+        assert!(parse_mask("syscall").is_err());
+        assert_eq!(ALL_TRACEPOINTS, Tracepoint::ALL.iter().fold(0, |mask, point| mask | point.bit()));
+    }
+}
@@ -0,0 +1,69 @@
+//! Driver signing trust
+//!
+//! The kernel carries the public key drivers must be signed with, given
+//! at build time as 64 hex digits in `KOSH_DRIVER_SIGNING_KEY`, and knows
+//! whether the boot parameters relax the requirement: `safe_mode` and
+//! `recovery` let unsigned drivers load, so a system can be repaired with
+//! a driver that was never signed. The driver manager, which verifies the
+//! signatures, asks for both through `SYS_DRIVER_TRUST`.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Bytes of an Ed25519 public key
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// Key given at build time, if any
+const SIGNING_KEY_HEX: Option<&str> = option_env!("KOSH_DRIVER_SIGNING_KEY");
+
+static UNSIGNED_ALLOWED: AtomicBool = AtomicBool::new(false);
+
+/// Key in `hex`, 64 hex digits
+fn parse_key(hex: &str) -> Option<[u8; PUBLIC_KEY_LEN]> {
+    let hex = hex.trim().as_bytes();
+    if hex.len() != PUBLIC_KEY_LEN * 2 {
+        return None;
+    }
+    let mut key = [0u8; PUBLIC_KEY_LEN];
+    for (byte, pair) in key.iter_mut().zip(hex.chunks_exact(2)) {
+        let digits = core::str::from_utf8(pair).ok()?;
+        *byte = u8::from_str_radix(digits, 16).ok()?;
+    }
+    Some(key)
+}
+
+/// Public key drivers must be signed with; None if the kernel was built
+/// without one
+pub fn signing_key() -> Option<[u8; PUBLIC_KEY_LEN]> {
+    let key = parse_key(SIGNING_KEY_HEX?);
+    if key.is_none() {
+        log::warn!("KOSH_DRIVER_SIGNING_KEY is not 64 hex digits; no driver signing key");
+    }
+    key
+}
+
+/// Let drivers without a valid signature load, for the boot mode `reason`
+pub fn allow_unsigned_drivers(reason: &str) {
+    UNSIGNED_ALLOWED.store(true, Ordering::Relaxed);
+    log::warn!("{}: unsigned drivers may load", reason);
+}
+
+/// Whether drivers without a valid signature may load
+pub fn unsigned_drivers_allowed() -> bool {
+    UNSIGNED_ALLOWED.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_key_is_parsed_from_hex() {
+        let hex = "00112233445566778899aabbccddeeff00112233445566778899AABBCCDDEEFF";
+        let key = parse_key(hex).expect("key");
+        assert_eq!(key[0], 0x00);
+        assert_eq!(key[1], 0x11);
+        assert_eq!(key[31], 0xFF);
+        assert_eq!(parse_key(&hex[..62]), None);
+        assert_eq!(parse_key("zz112233445566778899aabbccddeeff00112233445566778899aabbccddeeff"), None);
+    }
+}
@@ -1,15 +1,9 @@
 use volatile::Volatile;
 use core::fmt;
 use spin::Mutex;
-use lazy_static::lazy_static;
-
-lazy_static! {
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
-        column_position: 0,
-        color_code: ColorCode::new(Color::Yellow, Color::Black),
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
-    });
-}
+use crate::vt::{Damage, Terminal, KERNEL_LOG, TERMINAL_COUNT};
+
+pub static WRITER: Mutex<Writer> = Mutex::new(Writer::new());
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -35,88 +29,175 @@ pub enum Color {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
-struct ColorCode(u8);
+pub struct ColorCode(u8);
 
 impl ColorCode {
-    fn new(foreground: Color, background: Color) -> ColorCode {
+    pub const fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
-struct ScreenChar {
+pub struct ScreenChar {
     ascii_character: u8,
     color_code: ColorCode,
 }
 
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
+impl ScreenChar {
+    pub const fn new(ascii_character: u8, color_code: ColorCode) -> Self {
+        Self { ascii_character, color_code }
+    }
+
+    pub const fn blank(color_code: ColorCode) -> Self {
+        Self::new(b' ', color_code)
+    }
+
+    /// Character byte of the cell
+    #[allow(dead_code)]
+    pub fn character(&self) -> u8 {
+        self.ascii_character
+    }
+}
+
+pub const BUFFER_HEIGHT: usize = 25;
+pub const BUFFER_WIDTH: usize = 80;
+
+const VGA_BUFFER_ADDRESS: usize = 0xb8000;
 
 #[repr(transparent)]
 struct Buffer {
     chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
 }
 
+/// Text consoles on the VGA buffer
+///
+/// Each virtual terminal is kept in a shadow copy and only the active one
+/// is drawn, so output to hidden terminals is not lost. Another screen,
+/// such as the statistics dashboard, can also take over the display;
+/// output keeps going to the shadows while the console is hidden and
+/// reappears when it is shown.
 pub struct Writer {
-    column_position: usize,
-    color_code: ColorCode,
-    buffer: &'static mut Buffer,
+    terminals: [Terminal; TERMINAL_COUNT],
+    /// Terminal on screen, which also has the input focus
+    active: usize,
+    console_visible: bool,
 }
 
 impl Writer {
-    pub fn write_byte(&mut self, byte: u8) {
-        match byte {
-            b'\n' => self.new_line(),
-            byte => {
-                if self.column_position >= BUFFER_WIDTH {
-                    self.new_line();
-                }
-
-                let row = BUFFER_HEIGHT - 1;
-                let col = self.column_position;
+    const fn new() -> Self {
+        Self {
+            terminals: [
+                Terminal::new(ColorCode::new(Color::LightGray, Color::Black)),
+                Terminal::new(ColorCode::new(Color::Yellow, Color::Black)),
+                Terminal::new(ColorCode::new(Color::LightCyan, Color::Black)),
+                Terminal::new(ColorCode::new(Color::LightGray, Color::Black)),
+            ],
+            // Boot messages are shown until programs start
+            active: KERNEL_LOG,
+            console_visible: true,
+        }
+    }
 
-                let color_code = self.color_code;
-                self.buffer.chars[row][col].write(ScreenChar {
-                    ascii_character: byte,
-                    color_code,
-                });
-                self.column_position += 1;
+    /// Write text to one terminal, drawing it if that terminal is shown
+    pub fn write_to(&mut self, terminal: usize, s: &str) {
+        if terminal >= TERMINAL_COUNT {
+            return;
+        }
+        for byte in s.bytes() {
+            let byte = match byte {
+                0x20..=0x7e | b'\n' => byte,
+                _ => 0xfe,
+            };
+            let damage = self.terminals[terminal].write_byte(byte);
+            if terminal != self.active || !self.console_visible {
+                continue;
+            }
+            match damage {
+                Damage::Cell(row, col) => {
+                    let character = self.terminals[terminal].visible_row(row)[col];
+                    buffer().chars[row][col].write(character);
+                }
+                Damage::Screen => self.redraw_console(),
             }
         }
     }
 
+    /// Kernel log output, as `print!` writes it
     pub fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                _ => self.write_byte(0xfe),
-            }
+        self.write_to(KERNEL_LOG, s);
+    }
+
+    /// Terminal on screen
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Put another terminal on screen
+    pub fn switch_to(&mut self, terminal: usize) {
+        if terminal >= TERMINAL_COUNT || terminal == self.active {
+            return;
+        }
+        self.active = terminal;
+        if self.console_visible {
+            self.redraw_console();
         }
     }
 
-    fn new_line(&mut self) {
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(character);
-            }
+    /// Scroll the shown terminal back by `lines`, or forward when negative
+    pub fn scroll_view(&mut self, lines: isize) {
+        if self.terminals[self.active].scroll_view(lines) && self.console_visible {
+            self.redraw_console();
         }
-        self.clear_row(BUFFER_HEIGHT - 1);
-        self.column_position = 0;
     }
 
-    fn clear_row(&mut self, row: usize) {
-        let blank = ScreenChar {
-            ascii_character: b' ',
-            color_code: self.color_code,
-        };
+    /// Show the console again, redrawing whatever it printed meanwhile
+    pub fn show_console(&mut self) {
+        self.console_visible = true;
+        self.redraw_console();
+    }
+
+    /// Hand the display to another screen; output goes to the shadows only
+    pub fn hide_console(&mut self) {
+        self.console_visible = false;
+    }
+
+    /// Draw one line of text directly on the display, padded with blanks
+    ///
+    /// Used by screens that replace the console; does nothing while the
+    /// console is visible so it cannot scribble over console output.
+    pub fn draw_line(&mut self, row: usize, text: &str, foreground: Color, background: Color) {
+        if self.console_visible || row >= BUFFER_HEIGHT {
+            return;
+        }
+        let color_code = ColorCode::new(foreground, background);
+        let mut bytes = text.bytes();
         for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+            let ascii_character = match bytes.next() {
+                Some(byte @ 0x20..=0x7e) => byte,
+                Some(_) => 0xfe,
+                None => b' ',
+            };
+            buffer().chars[row][col].write(ScreenChar { ascii_character, color_code });
+        }
+    }
+
+    fn redraw_console(&mut self) {
+        let terminal = &self.terminals[self.active];
+        let buffer = buffer();
+        for row in 0..BUFFER_HEIGHT {
+            for (col, character) in terminal.visible_row(row).iter().enumerate() {
+                buffer.chars[row][col].write(*character);
+            }
         }
     }
 }
 
+/// The VGA text buffer, identity-mapped by the boot code
+fn buffer() -> &'static mut Buffer {
+    unsafe { &mut *(VGA_BUFFER_ADDRESS as *mut Buffer) }
+}
+
 impl fmt::Write for Writer {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         self.write_string(s);
@@ -139,4 +220,34 @@ macro_rules! println {
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
     WRITER.lock().write_fmt(args).unwrap();
+}
+
+/// Formatted output to one terminal, the way `print!` writes the kernel log
+pub fn print_to(terminal: usize, args: fmt::Arguments) {
+    use core::fmt::Write;
+    let mut writer = WRITER.lock();
+    let _ = TerminalWriter { writer: &mut writer, terminal }.write_fmt(args);
+}
+
+struct TerminalWriter<'a> {
+    writer: &'a mut Writer,
+    terminal: usize,
+}
+
+impl fmt::Write for TerminalWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.writer.write_to(self.terminal, s);
+        Ok(())
+    }
+}
+
+/// Bring the kernel log back no matter which screen is shown
+///
+/// For the panic path: skipped if the writer is held, since the panic may
+/// have happened while printing.
+pub fn force_console() {
+    if let Some(mut writer) = WRITER.try_lock() {
+        writer.switch_to(KERNEL_LOG);
+        writer.show_console();
+    }
 }
\ No newline at end of file
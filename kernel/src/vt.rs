@@ -0,0 +1,280 @@
+//! Virtual terminals
+//!
+//! Several text consoles share the VGA display and one is shown at a time.
+//! Programs writing to their console descriptors use the shell terminal,
+//! `print!` goes to the kernel log and `SYS_DEBUG_PRINT` to the debug
+//! terminal; the fourth is spare. Each keeps its own screen and scrollback
+//! while hidden.
+//!
+//! Alt+F1..F4 switches terminals and Shift+PageUp/PageDown scrolls the
+//! shown one back through its history. Like the F12 dashboard, the
+//! keyboard interrupt only records the request and the timer tick carries
+//! it out, and the hotkeys only work while no keyboard driver has claimed
+//! the PS/2 controller.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+use crate::vga_buffer::{ColorCode, ScreenChar, BUFFER_HEIGHT, BUFFER_WIDTH, WRITER};
+
+/// Number of terminals, one per Alt+F key
+pub const TERMINAL_COUNT: usize = 4;
+
+/// Terminal of the console descriptors programs inherit (Alt+F1)
+pub const SHELL: usize = 0;
+
+/// Terminal of the kernel's own messages (Alt+F2)
+pub const KERNEL_LOG: usize = 1;
+
+/// Terminal of `SYS_DEBUG_PRINT` output (Alt+F3)
+pub const DEBUG: usize = 2;
+
+/// Lines each terminal keeps after they scroll off the screen
+pub const SCROLLBACK_LINES: usize = 100;
+
+/// Lines Shift+PageUp/PageDown move the view by
+const SCROLL_PAGE: isize = BUFFER_HEIGHT as isize - 1;
+
+/// Scancode set 1 codes the hotkeys are made of
+const SCANCODE_EXTENDED: u8 = 0xE0;
+const SCANCODE_RELEASED: u8 = 0x80;
+const SCANCODE_ALT: u8 = 0x38;
+const SCANCODE_LEFT_SHIFT: u8 = 0x2A;
+const SCANCODE_RIGHT_SHIFT: u8 = 0x36;
+const SCANCODE_F1: u8 = 0x3B;
+const SCANCODE_F4: u8 = 0x3E;
+const SCANCODE_PAGE_UP: u8 = 0x49;
+const SCANCODE_PAGE_DOWN: u8 = 0x51;
+
+const NO_SWITCH: usize = usize::MAX;
+
+static ALT_HELD: AtomicBool = AtomicBool::new(false);
+static SHIFT_HELD: AtomicBool = AtomicBool::new(false);
+/// Set by the 0xE0 prefix for the scancode that follows
+static EXTENDED: AtomicBool = AtomicBool::new(false);
+
+/// Terminal to switch to at the next timer tick
+static SWITCH_REQUESTED: AtomicUsize = AtomicUsize::new(NO_SWITCH);
+
+/// Lines to scroll the view back by at the next timer tick
+static SCROLL_REQUESTED: AtomicIsize = AtomicIsize::new(0);
+
+/// Whether a program has written to the shell terminal yet
+static SHELL_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Handle a scancode seen by the keyboard interrupt
+///
+/// Safe from interrupt context: only flags the request.
+pub fn on_scancode(scancode: u8) {
+    if scancode == SCANCODE_EXTENDED {
+        EXTENDED.store(true, Ordering::SeqCst);
+        return;
+    }
+    let extended = EXTENDED.swap(false, Ordering::SeqCst);
+    let pressed = scancode & SCANCODE_RELEASED == 0;
+    match (extended, scancode & !SCANCODE_RELEASED) {
+        // Left Alt, or right Alt with the prefix
+        (_, SCANCODE_ALT) => ALT_HELD.store(pressed, Ordering::SeqCst),
+        (false, SCANCODE_LEFT_SHIFT | SCANCODE_RIGHT_SHIFT) => SHIFT_HELD.store(pressed, Ordering::SeqCst),
+        (false, code @ SCANCODE_F1..=SCANCODE_F4) if pressed && ALT_HELD.load(Ordering::SeqCst) => {
+            SWITCH_REQUESTED.store((code - SCANCODE_F1) as usize, Ordering::SeqCst);
+        }
+        (true, SCANCODE_PAGE_UP) if pressed && SHIFT_HELD.load(Ordering::SeqCst) => {
+            SCROLL_REQUESTED.fetch_add(SCROLL_PAGE, Ordering::SeqCst);
+        }
+        (true, SCANCODE_PAGE_DOWN) if pressed && SHIFT_HELD.load(Ordering::SeqCst) => {
+            SCROLL_REQUESTED.fetch_sub(SCROLL_PAGE, Ordering::SeqCst);
+        }
+        _ => {}
+    }
+}
+
+/// Carry out switch and scroll requests from the keyboard
+///
+/// Runs on the boot CPU's timer tick. While the interrupted code holds the
+/// writer the requests wait for a later tick.
+pub fn timer_tick() {
+    if SWITCH_REQUESTED.load(Ordering::SeqCst) == NO_SWITCH && SCROLL_REQUESTED.load(Ordering::SeqCst) == 0 {
+        return;
+    }
+    let Some(mut writer) = WRITER.try_lock() else {
+        return;
+    };
+    let terminal = SWITCH_REQUESTED.swap(NO_SWITCH, Ordering::SeqCst);
+    if terminal != NO_SWITCH {
+        writer.switch_to(terminal);
+    }
+    let lines = SCROLL_REQUESTED.swap(0, Ordering::SeqCst);
+    if lines != 0 {
+        writer.scroll_view(lines);
+    }
+}
+
+/// Write program output to the shell terminal
+///
+/// Boot messages stay on screen until a program first writes to its
+/// console; that brings up the shell terminal.
+pub fn console_write(text: &str) {
+    let mut writer = WRITER.lock();
+    if !SHELL_STARTED.swap(true, Ordering::SeqCst) {
+        writer.switch_to(SHELL);
+    }
+    writer.write_to(SHELL, text);
+}
+
+/// Take up to `max` bytes of console input for the shell terminal
+///
+/// Input belongs to the terminal on screen: what is typed while another
+/// one is shown is dropped rather than queued for the shell.
+pub fn read_input(max: usize) -> Vec<u8> {
+    let bytes = crate::serial::read_available(max);
+    if WRITER.lock().active() == SHELL {
+        bytes
+    } else {
+        Vec::new()
+    }
+}
+
+/// What a write changed on a terminal's view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Damage {
+    /// One character cell, as (row, column)
+    Cell(usize, usize),
+    /// Everything moved, so the whole screen must be redrawn
+    Screen,
+}
+
+/// Screen contents and history of one terminal
+pub struct Terminal {
+    rows: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    /// Ring of lines that scrolled off the top, oldest at `scrollback_start`
+    scrollback: [[ScreenChar; BUFFER_WIDTH]; SCROLLBACK_LINES],
+    scrollback_start: usize,
+    scrollback_len: usize,
+    column_position: usize,
+    color_code: ColorCode,
+    /// Lines the view is scrolled back by; 0 follows the output
+    view_offset: usize,
+}
+
+impl Terminal {
+    pub const fn new(color_code: ColorCode) -> Self {
+        let blank = ScreenChar::blank(color_code);
+        Self {
+            rows: [[blank; BUFFER_WIDTH]; BUFFER_HEIGHT],
+            scrollback: [[blank; BUFFER_WIDTH]; SCROLLBACK_LINES],
+            scrollback_start: 0,
+            scrollback_len: 0,
+            column_position: 0,
+            color_code,
+            view_offset: 0,
+        }
+    }
+
+    /// Append a byte of output at the bottom line
+    ///
+    /// New output brings a scrolled-back view down to the bottom again.
+    pub fn write_byte(&mut self, byte: u8) -> Damage {
+        let scrolled_back = core::mem::take(&mut self.view_offset) > 0;
+        if byte == b'\n' {
+            self.new_line();
+            return Damage::Screen;
+        }
+        let wrapped = self.column_position >= BUFFER_WIDTH;
+        if wrapped {
+            self.new_line();
+        }
+        let row = BUFFER_HEIGHT - 1;
+        let col = self.column_position;
+        self.rows[row][col] = ScreenChar::new(byte, self.color_code);
+        self.column_position += 1;
+        if scrolled_back || wrapped {
+            Damage::Screen
+        } else {
+            Damage::Cell(row, col)
+        }
+    }
+
+    /// Row of the view, counting scrollback when scrolled back
+    pub fn visible_row(&self, row: usize) -> &[ScreenChar; BUFFER_WIDTH] {
+        let line = self.scrollback_len - self.view_offset + row;
+        if line < self.scrollback_len {
+            &self.scrollback[(self.scrollback_start + line) % SCROLLBACK_LINES]
+        } else {
+            &self.rows[line - self.scrollback_len]
+        }
+    }
+
+    /// Move the view back by `lines`, or forward when negative
+    ///
+    /// Returns whether the view moved.
+    pub fn scroll_view(&mut self, lines: isize) -> bool {
+        let offset = (self.view_offset as isize + lines).clamp(0, self.scrollback_len as isize) as usize;
+        let moved = offset != self.view_offset;
+        self.view_offset = offset;
+        moved
+    }
+
+    fn new_line(&mut self) {
+        let top = self.rows[0];
+        if self.scrollback_len < SCROLLBACK_LINES {
+            self.scrollback[(self.scrollback_start + self.scrollback_len) % SCROLLBACK_LINES] = top;
+            self.scrollback_len += 1;
+        } else {
+            // Full: the oldest line makes room
+            self.scrollback[self.scrollback_start] = top;
+            self.scrollback_start = (self.scrollback_start + 1) % SCROLLBACK_LINES;
+        }
+        self.rows.copy_within(1.., 0);
+        self.rows[BUFFER_HEIGHT - 1] = [ScreenChar::blank(self.color_code); BUFFER_WIDTH];
+        self.column_position = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vga_buffer::Color;
+
+    fn terminal() -> Terminal {
+        Terminal::new(ColorCode::new(Color::LightGray, Color::Black))
+    }
+
+    fn text(row: &[ScreenChar; BUFFER_WIDTH]) -> alloc::string::String {
+        row.iter().map(|cell| cell.character() as char).collect::<alloc::string::String>().trim_end().into()
+    }
+
+    #[test_case]
+    fn test_terminal_write_damage() {
+        let mut terminal = terminal();
+        assert_eq!(terminal.write_byte(b'a'), Damage::Cell(BUFFER_HEIGHT - 1, 0));
+        assert_eq!(terminal.write_byte(b'b'), Damage::Cell(BUFFER_HEIGHT - 1, 1));
+        assert_eq!(terminal.write_byte(b'\n'), Damage::Screen);
+        assert_eq!(text(terminal.visible_row(BUFFER_HEIGHT - 2)), "ab");
+
+        // A full line wraps onto the next one
+        for _ in 0..BUFFER_WIDTH {
+            terminal.write_byte(b'x');
+        }
+        assert_eq!(terminal.write_byte(b'y'), Damage::Screen);
+        assert_eq!(text(terminal.visible_row(BUFFER_HEIGHT - 1)), "y");
+    }
+
+    #[test_case]
+    fn test_terminal_scrollback() {
+        let mut terminal = terminal();
+        for line in 0..BUFFER_HEIGHT + SCROLLBACK_LINES + 5 {
+            terminal.write_byte(b'0' + (line % 10) as u8);
+            terminal.write_byte(b'\n');
+        }
+        // The oldest lines were dropped once the history was full
+        assert!(!terminal.scroll_view(-1));
+        assert!(terminal.scroll_view(isize::MAX / 2));
+        assert_eq!(terminal.view_offset, SCROLLBACK_LINES);
+        assert_eq!(text(terminal.visible_row(0)), "6");
+
+        // Output snaps the view back to the bottom
+        assert_eq!(terminal.write_byte(b'z'), Damage::Screen);
+        assert_eq!(terminal.view_offset, 0);
+        assert_eq!(text(terminal.visible_row(BUFFER_HEIGHT - 1)), "z");
+    }
+}
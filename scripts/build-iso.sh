@@ -157,6 +157,8 @@ copy_userspace() {
         "kosh-init:init"
         "kosh-fs-service:fs-service"
         "kosh-driver-manager:driver-manager"
+        "kosh-input-service:input-service"
+        "kosh-display-service:display-service"
         "kosh-shell:shell"
     )
     
@@ -302,7 +304,8 @@ Kosh OS Boot Help
 
 Available Boot Parameters:
 - debug=1          : Enable debug mode
-- log_level=debug  : Set log level (debug, info, warn, error)
+- log_level=debug  : Set log level (trace, debug, info, warn, error, off),
+                     optionally per subsystem: log_level=info,syscall=trace
 - safe_mode=1      : Boot in safe mode
 - recovery=1       : Boot in recovery mode
 - single_user=1    : Boot to single user mode
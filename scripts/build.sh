@@ -15,8 +15,11 @@ case $TARGET in
     aarch64)
         TARGET_JSON="aarch64-kosh.json"
         ;;
+    riscv64)
+        TARGET_JSON="riscv64-kosh.json"
+        ;;
     *)
-        echo "Error: Unsupported target '$TARGET'. Supported targets: x86_64, aarch64"
+        echo "Error: Unsupported target '$TARGET'. Supported targets: x86_64, aarch64, riscv64"
         exit 1
         ;;
 esac
@@ -52,12 +55,16 @@ echo "Building userspace for $TARGET..."
 cargo build --package kosh-init --target $TARGET_JSON --release -Z build-std=core,alloc
 cargo build --package kosh-fs-service --target $TARGET_JSON --release -Z build-std=core,alloc
 cargo build --package kosh-driver-manager --target $TARGET_JSON --release -Z build-std=core,alloc
+cargo build --package kosh-input-service --target $TARGET_JSON --release -Z build-std=core,alloc
+cargo build --package kosh-display-service --target $TARGET_JSON --release -Z build-std=core,alloc
 
 # Copy userspace binaries
 mkdir -p build/$TARGET/userspace
 cp target/${TARGET_JSON%.*}/release/kosh-init build/$TARGET/userspace/
 cp target/${TARGET_JSON%.*}/release/kosh-fs-service build/$TARGET/userspace/
 cp target/${TARGET_JSON%.*}/release/kosh-driver-manager build/$TARGET/userspace/
+cp target/${TARGET_JSON%.*}/release/kosh-input-service build/$TARGET/userspace/
+cp target/${TARGET_JSON%.*}/release/kosh-display-service build/$TARGET/userspace/
 
 echo "Build completed successfully for $TARGET!"
 echo "Output directory: build/$TARGET/"
\ No newline at end of file
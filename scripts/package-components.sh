@@ -184,6 +184,8 @@ package_userspace() {
         "kosh-init:init:System initialization process"
         "kosh-fs-service:fs-service:File system service"
         "kosh-driver-manager:driver-manager:Driver management service"
+        "kosh-input-service:input-service:Input routing service"
+        "kosh-display-service:display-service:Surface compositing service"
         "kosh-shell:shell:Interactive shell"
     )
     
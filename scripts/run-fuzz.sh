@@ -0,0 +1,47 @@
+#!/bin/bash
+
+# Kosh Fuzz Runner
+# Runs each cargo-fuzz target under fuzz/, or the ones named on the command
+# line, for KOSH_FUZZ_SECONDS seconds apiece. Needs cargo-fuzz, which builds
+# for the host with the nightly toolchain the repository pins
+
+set -e
+
+ROOT_DIR="$(cd "$(dirname "$0")/.." && pwd)"
+SECONDS_PER_TARGET="${KOSH_FUZZ_SECONDS:-60}"
+
+# Every target under fuzz/fuzz_targets/
+ALL_TARGETS=(
+    service_message
+    service_response
+    ext4_superblock
+    ext4_inode
+    ansi
+    scancode
+    syscall_args
+)
+
+echo "=== Kosh Fuzzing ==="
+
+cd "$ROOT_DIR/fuzz"
+
+if ! cargo fuzz --help > /dev/null 2>&1; then
+    echo "❌ cargo-fuzz is not installed (cargo install cargo-fuzz)"
+    exit 1
+fi
+
+if [ $# -gt 0 ]; then
+    TARGETS=("$@")
+else
+    TARGETS=("${ALL_TARGETS[@]}")
+fi
+
+for target in "${TARGETS[@]}"; do
+    echo "Fuzzing $target for ${SECONDS_PER_TARGET}s..."
+    if ! cargo fuzz run "$target" -- -max_total_time="$SECONDS_PER_TARGET"; then
+        echo "❌ $target crashed; the input is under fuzz/artifacts/$target"
+        exit 1
+    fi
+done
+
+echo "✅ No crashes in ${#TARGETS[@]} targets"
@@ -0,0 +1,77 @@
+#!/bin/bash
+
+# Kosh Userspace Integration Test Runner
+# Boots a debug kernel with integration_tests=1, so init starts the test
+# orchestrator in place of the shell, and reads the result from QEMU's exit
+# code the way run-kernel-tests.sh does
+
+set -e
+
+ROOT_DIR="$(cd "$(dirname "$0")/.." && pwd)"
+TARGET_DIR="$ROOT_DIR/target/x86_64-kosh/debug"
+WORK_DIR="$ROOT_DIR/target/integration-tests"
+ISO_DIR="$WORK_DIR/iso"
+ISO_NAME="$WORK_DIR/kosh-integration-tests.iso"
+SERIAL_LOG="$WORK_DIR/serial.log"
+TIMEOUT="${KOSH_TEST_TIMEOUT:-300}"
+
+echo "=== Kosh Userspace Integration Tests ==="
+
+cd "$ROOT_DIR"
+
+# The test control system call exists only in debug kernels
+echo "Building debug kernel and userspace..."
+cargo build --target x86_64-kosh.json -Z build-std=core,alloc -p kosh-kernel
+cargo build --target x86_64-kosh.json -Z build-std=core,alloc \
+    -p kosh-init \
+    -p kosh-fs-service \
+    -p kosh-driver-manager \
+    -p kosh-input-service \
+    -p kosh-display-service \
+    -p kosh-integration-tests
+
+echo "Creating test image..."
+rm -rf "$ISO_DIR"
+mkdir -p "$ISO_DIR/boot/grub" "$ISO_DIR/system/bin" "$ISO_DIR/system/services"
+cp "$TARGET_DIR/kosh-kernel" "$ISO_DIR/boot/kosh-kernel"
+cp "$TARGET_DIR/kosh-init" "$ISO_DIR/system/bin/init"
+cp "$TARGET_DIR/kosh-integration-tests" "$ISO_DIR/system/bin/integration-tests"
+for service in fs-service driver-manager input-service display-service; do
+    cp "$TARGET_DIR/kosh-$service" "$ISO_DIR/system/services/$service"
+done
+
+cat > "$ISO_DIR/boot/grub/grub.cfg" << EOF
+set timeout=0
+set default=0
+
+menuentry "Kosh OS - Integration Tests" {
+    multiboot2 /boot/kosh-kernel integration_tests=1 log_level=info
+    boot
+}
+EOF
+
+grub-mkrescue -o "$ISO_NAME" "$ISO_DIR/" 2>/dev/null
+
+# Run tests in QEMU; the serial port carries the orchestrator's report
+echo "Running integration tests in QEMU (timeout ${TIMEOUT}s)..."
+set +e
+timeout "$TIMEOUT" qemu-system-x86_64 \
+    -cdrom "$ISO_NAME" \
+    -m 256M \
+    -device isa-debug-exit,iobase=0xf4,iosize=0x04 \
+    -serial stdio \
+    -display none \
+    -no-reboot | tee "$SERIAL_LOG"
+EXIT_CODE=${PIPESTATUS[0]}
+set -e
+
+if [ $EXIT_CODE -eq 33 ]; then  # QEMU exit code for success (0x10 + 33)
+    echo "✅ All integration tests passed!"
+    exit 0
+elif [ $EXIT_CODE -eq 124 ]; then
+    echo "❌ Integration tests timed out after ${TIMEOUT}s (serial log: $SERIAL_LOG)"
+    exit 1
+else
+    echo "❌ Integration tests failed with exit code $EXIT_CODE (serial log: $SERIAL_LOG)"
+    exit 1
+fi
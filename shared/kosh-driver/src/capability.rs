@@ -35,6 +35,9 @@ pub enum HardwareCapability {
     MemoryMappedIo { start: u64, size: u64 },
     /// Access to specific IRQ lines
     Interrupt { irq: u32 },
+    /// Up to `vectors` message-signaled interrupts for the PCI devices the
+    /// driver is bound to
+    MessageSignaledInterrupts { vectors: u32 },
     /// DMA access
     Dma { channel: u32 },
     /// PCI device access
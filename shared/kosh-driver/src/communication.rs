@@ -3,6 +3,14 @@ use kosh_types::{ProcessId, DriverId, DriverError};
 use kosh_ipc::{Message, MessageData, DriverRequestData, IpcError};
 use crate::{DriverRequest, DriverResponse};
 
+/// `DriverRequestData::request_type` of power events the driver manager
+/// sends; the data is the event's `PowerEvent::code`
+pub const POWER_EVENT_REQUEST: u32 = 7;
+
+/// `DriverRequestData::request_type` of the driver manager's periodic
+/// liveness ping; a driver answers it with an empty response
+pub const HEARTBEAT_REQUEST: u32 = 8;
+
 /// Communication channel for driver-to-driver and driver-to-system communication
 pub trait DriverCommunication {
     /// Send a request to another driver
@@ -131,7 +139,7 @@ impl DriverCommunication for KernelDriverCommunication {
         // Convert DriverRequest to DriverRequestData
         let request_data = DriverRequestData {
             driver_id: target_driver,
-            request_type: match request {
+            request_type: match &request {
                 DriverRequest::Initialize => 1,
                 DriverRequest::Read { .. } => 2,
                 DriverRequest::Write { .. } => 3,
@@ -139,7 +147,7 @@ impl DriverCommunication for KernelDriverCommunication {
                 DriverRequest::Query { .. } => 5,
                 DriverRequest::Custom { .. } => 6,
             },
-            data: &[], // In a real implementation, serialize the request data
+            data: serialization::serialize_request(&request),
         };
 
         // In a real implementation, this would:
@@ -0,0 +1,69 @@
+//! The display mode the boot loader left
+//!
+//! The kernel keeps what the boot loader's framebuffer tag said so a
+//! graphics driver knows what is on the screen before it sets a mode of its
+//! own: VGA text, or a linear framebuffer set up through VBE or GOP.
+
+use crate::hal::hardware_syscall;
+
+/// System call number (must match shared/kosh-syscall/src/numbers.rs)
+const SYS_DISPLAY_INFO: u64 = 95;
+
+/// `DisplayInfo::kind` values
+pub const DISPLAY_NONE: u32 = 0;
+pub const DISPLAY_TEXT: u32 = 1;
+pub const DISPLAY_RGB: u32 = 2;
+pub const DISPLAY_INDEXED: u32 = 3;
+
+/// `DisplayInfo::vbe_mode` when the boot loader did not set the mode
+/// through VBE
+pub const NO_VBE_MODE: u32 = u32::MAX;
+
+/// The boot display as the kernel reports it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct DisplayInfo {
+    /// Physical address of the framebuffer, or of text memory
+    pub address: u64,
+    pub kind: u32,
+    /// In pixels, or in character cells for text modes
+    pub width: u32,
+    pub height: u32,
+    /// Bytes from the start of one line to the next
+    pub pitch: u32,
+    pub bpp: u32,
+    pub vbe_mode: u32,
+    /// Lowest bit and width of each color in an RGB pixel
+    pub red_position: u8,
+    pub red_size: u8,
+    pub green_position: u8,
+    pub green_size: u8,
+    pub blue_position: u8,
+    pub blue_size: u8,
+    pub reserved: [u8; 2],
+}
+
+impl DisplayInfo {
+    pub fn is_text(&self) -> bool {
+        self.kind == DISPLAY_TEXT
+    }
+
+    /// Whether the boot loader left a framebuffer to draw pixels into
+    pub fn is_graphics(&self) -> bool {
+        matches!(self.kind, DISPLAY_RGB | DISPLAY_INDEXED)
+    }
+
+    /// VBE mode number, if the boot loader set the mode through VBE
+    pub fn vbe_mode(&self) -> Option<u16> {
+        (self.vbe_mode != NO_VBE_MODE).then_some(self.vbe_mode as u16)
+    }
+}
+
+/// Read the boot display, None if the kernel cannot say
+pub fn boot_display() -> Option<DisplayInfo> {
+    let mut info = DisplayInfo::default();
+    if hardware_syscall(SYS_DISPLAY_INFO, &mut info as *mut DisplayInfo as u64, 0, 0) < 0 {
+        return None;
+    }
+    (info.kind != DISPLAY_NONE).then_some(info)
+}
@@ -0,0 +1,263 @@
+use alloc::vec::Vec;
+use kosh_types::DriverError;
+
+/// System call numbers for DMA (must match the kernel)
+const SYS_DMA_SYNC: u64 = 44;
+const SYS_DMA_ALLOC: u64 = 73;
+const SYS_DMA_FREE: u64 = 74;
+
+/// Errno the kernel returns when no contiguous memory is left (ENOMEM)
+const ENOMEM: i64 = -12;
+
+/// Direction of a DMA transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaDirection {
+    /// Driver fills the buffer, device reads it
+    ToDevice,
+    /// Device fills the buffer, driver reads it
+    FromDevice,
+    /// Both sides read and write the buffer
+    Bidirectional,
+}
+
+impl DmaDirection {
+    fn as_raw(self) -> u64 {
+        match self {
+            DmaDirection::ToDevice => 0,
+            DmaDirection::FromDevice => 1,
+            DmaDirection::Bidirectional => 2,
+        }
+    }
+}
+
+/// Hand a buffer to the device: call before starting a DMA transfer
+pub fn sync_for_device(buffer: &[u8], direction: DmaDirection) -> Result<(), DriverError> {
+    dma_sync(buffer.as_ptr() as u64, buffer.len(), direction, true)
+}
+
+/// Take a buffer back from the device: call after a DMA transfer completed
+pub fn sync_for_cpu(buffer: &[u8], direction: DmaDirection) -> Result<(), DriverError> {
+    dma_sync(buffer.as_ptr() as u64, buffer.len(), direction, false)
+}
+
+fn dma_sync(addr: u64, length: usize, direction: DmaDirection, for_device: bool) -> Result<(), DriverError> {
+    if length == 0 {
+        return Ok(());
+    }
+
+    let result: i64;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") SYS_DMA_SYNC,
+            in("rdi") addr,
+            in("rsi") length,
+            in("rdx") direction.as_raw(),
+            in("r10") for_device as u64,
+            lateout("rax") result,
+            options(nostack, preserves_flags)
+        );
+    }
+
+    if result < 0 {
+        Err(DriverError::InvalidRequest)
+    } else {
+        Ok(())
+    }
+}
+
+/// How the CPU caches a `DmaBuffer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaCache {
+    /// Cached; sync around transfers with `sync_for_device`/`sync_for_cpu`
+    Cached,
+    /// Uncached; always coherent, for descriptor rings and mailboxes
+    Uncached,
+}
+
+impl DmaCache {
+    fn as_raw(self) -> u64 {
+        match self {
+            DmaCache::Cached => 0,
+            DmaCache::Uncached => 1,
+        }
+    }
+}
+
+/// Zeroed, physically contiguous memory a device can reach
+///
+/// The kernel maps the buffer into the driver's IOMMU domain; devices must
+/// be given `device_address`, never the buffer's address in the driver.
+/// The buffer is freed, and the device's access revoked, on drop.
+pub struct DmaBuffer {
+    address: usize,
+    device_address: u64,
+    len: usize,
+}
+
+impl DmaBuffer {
+    /// Allocate a buffer of at least `len` bytes, rounded up to whole pages
+    pub fn allocate(len: usize, cache: DmaCache) -> Result<Self, DriverError> {
+        let mut device_address: u64 = 0;
+        let result: i64;
+        unsafe {
+            core::arch::asm!(
+                "int 0x80",
+                in("rax") SYS_DMA_ALLOC,
+                in("rdi") len,
+                in("rsi") cache.as_raw(),
+                in("rdx") &mut device_address as *mut u64,
+                lateout("rax") result,
+                options(nostack, preserves_flags)
+            );
+        }
+
+        match result {
+            ENOMEM => Err(DriverError::ResourceBusy),
+            result if result < 0 => Err(DriverError::InvalidRequest),
+            address => Ok(Self { address: address as usize, device_address, len }),
+        }
+    }
+
+    /// Address of the buffer in the driver
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// Address the driver's devices reach the buffer at
+    pub fn device_address(&self) -> u64 {
+        self.device_address
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.address as *const u8, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.address as *mut u8, self.len) }
+    }
+
+    /// Keep the buffer for the rest of the driver's life, returning its
+    /// driver and device addresses
+    ///
+    /// For memory handed to an allocator that outlives any owner, such as a
+    /// controller's descriptor pool. The kernel frees it when the driver exits.
+    pub fn leak(self) -> (usize, u64) {
+        let addresses = (self.address, self.device_address);
+        core::mem::forget(self);
+        addresses
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            core::arch::asm!(
+                "int 0x80",
+                inlateout("rax") SYS_DMA_FREE => _,
+                in("rdi") self.address,
+                options(nostack, preserves_flags)
+            );
+        }
+    }
+}
+
+/// Size of the pages `DmaMemory` rounds allocations up to
+pub const DMA_PAGE_SIZE: usize = 4096;
+
+/// Memory the device reaches by DMA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaRegion {
+    /// Address the driver uses
+    pub virt: usize,
+    /// Address the device uses
+    pub phys: u64,
+    pub size: usize,
+}
+
+impl DmaRegion {
+    fn pointer(&self, offset: usize, width: usize) -> usize {
+        assert!(offset + width <= self.size, "access outside DMA region");
+        self.virt + offset
+    }
+
+    pub fn read_u8(&self, offset: usize) -> u8 {
+        unsafe { core::ptr::read_volatile(self.pointer(offset, 1) as *const u8) }
+    }
+
+    pub fn write_u8(&self, offset: usize, value: u8) {
+        unsafe { core::ptr::write_volatile(self.pointer(offset, 1) as *mut u8, value) }
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        unsafe { core::ptr::read_volatile(self.pointer(offset, 2) as *const u16) }
+    }
+
+    pub fn write_u16(&self, offset: usize, value: u16) {
+        unsafe { core::ptr::write_volatile(self.pointer(offset, 2) as *mut u16, value) }
+    }
+
+    pub fn read_u32(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile(self.pointer(offset, 4) as *const u32) }
+    }
+
+    pub fn write_u32(&self, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile(self.pointer(offset, 4) as *mut u32, value) }
+    }
+
+    pub fn write_u64(&self, offset: usize, value: u64) {
+        self.write_u32(offset, value as u32);
+        self.write_u32(offset + 4, (value >> 32) as u32);
+    }
+
+    pub fn read_bytes(&self, offset: usize, length: usize) -> Vec<u8> {
+        let source = self.pointer(offset, length) as *const u8;
+        (0..length).map(|index| unsafe { core::ptr::read_volatile(source.add(index)) }).collect()
+    }
+
+    pub fn write_bytes(&self, offset: usize, bytes: &[u8]) {
+        let target = self.pointer(offset, bytes.len()) as *mut u8;
+        for (index, &byte) in bytes.iter().enumerate() {
+            unsafe { core::ptr::write_volatile(target.add(index), byte) }
+        }
+    }
+}
+
+/// Source of memory the device can reach
+pub trait DmaMemory: Send {
+    /// Zeroed, page-aligned, physically contiguous memory of at least
+    /// `size` bytes, or None when there is not that much left
+    fn allocate(&mut self, size: usize) -> Option<DmaRegion>;
+
+    /// Return memory from `allocate`
+    fn free(&mut self, region: DmaRegion);
+}
+
+/// DMA memory from the kernel, uncached so neither descriptor rings nor
+/// the buffers they point at need syncing around transfers
+#[derive(Default)]
+pub struct KernelDma {
+    buffers: Vec<DmaBuffer>,
+}
+
+impl DmaMemory for KernelDma {
+    fn allocate(&mut self, size: usize) -> Option<DmaRegion> {
+        let buffer = DmaBuffer::allocate(size, DmaCache::Uncached).ok()?;
+        let region = DmaRegion { virt: buffer.address(), phys: buffer.device_address(), size };
+        self.buffers.push(buffer);
+        Some(region)
+    }
+
+    fn free(&mut self, region: DmaRegion) {
+        // Dropping the buffer frees it
+        self.buffers.retain(|buffer| buffer.device_address() != region.phys);
+    }
+}
@@ -0,0 +1,22 @@
+//! Feeding the kernel's random pool
+//!
+//! Drivers of hardware random number generators hand what their device
+//! produced to the kernel, which mixes it into the pool `getrandom` draws
+//! from. The entropy claimed for it counts toward seeding the pool because
+//! the caller is a driver; other processes can only mix bytes in.
+
+use crate::hal::hardware_syscall;
+
+/// System call number (must match shared/kosh-syscall/src/numbers.rs)
+const SYS_ADD_ENTROPY: u64 = 97;
+
+/// Most bytes the kernel takes per call
+pub const MAX_ENTROPY_BYTES: usize = 4096;
+
+/// Mix `data` into the kernel's pool, claiming `entropy_bits` of entropy
+///
+/// Returns the bits the kernel counted, or None if it refused the data.
+pub fn add_entropy(data: &[u8], entropy_bits: u32) -> Option<u32> {
+    let result = hardware_syscall(SYS_ADD_ENTROPY, data.as_ptr() as u64, data.len() as u64, entropy_bits as u64);
+    (result >= 0).then_some(result as u32)
+}
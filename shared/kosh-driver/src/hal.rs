@@ -0,0 +1,399 @@
+//! Hardware access seam for drivers
+//!
+//! Drivers talk to their device through a `PortIo` or `Mmio` trait object
+//! instead of issuing port instructions or volatile accesses directly. On
+//! real hardware the `Hardware*` backends are used; tests plug in a replay
+//! backend (feature `replay`) that checks every access against a recorded
+//! register transaction fixture.
+//!
+//! A driver is built from a `HardwareBackend`, which hands out all of
+//! these at once. `RealBackend` gives the `Hardware*` ones; the simulated
+//! machine of `mock::MockBackend` (feature `mock`) gives devices that
+//! answer like the real ones, fed from scancode, touch and block streams a
+//! test injects.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use kosh_types::DriverError;
+use crate::input::RawTouch;
+
+/// Port-mapped I/O (x86 `in`/`out`)
+pub trait PortIo: Send {
+    fn read_u8(&mut self, port: u16) -> u8;
+    fn write_u8(&mut self, port: u16, value: u8);
+    fn read_u16(&mut self, port: u16) -> u16;
+    fn write_u16(&mut self, port: u16, value: u16);
+    fn read_u32(&mut self, port: u16) -> u32;
+    fn write_u32(&mut self, port: u16, value: u32);
+}
+
+/// Memory-mapped register window; offsets are relative to the window base
+pub trait Mmio: Send {
+    fn read_u8(&mut self, offset: usize) -> u8;
+    fn write_u8(&mut self, offset: usize, value: u8);
+    fn read_u16(&mut self, offset: usize) -> u16;
+    fn write_u16(&mut self, offset: usize, value: u16);
+    fn read_u32(&mut self, offset: usize) -> u32;
+    fn write_u32(&mut self, offset: usize, value: u32);
+}
+
+/// System call numbers (must match shared/kosh-syscall/src/numbers.rs)
+const SYS_IO_READ: u64 = 70;
+const SYS_IO_WRITE: u64 = 71;
+const SYS_MMIO_MAP: u64 = 72;
+
+/// `SYS_MMIO_MAP` flag: map the registers writable
+const MMIO_MAP_WRITE: u64 = 1 << 0;
+
+/// Errno the kernel returns for a missing grant (EACCES)
+const EACCES: i64 = -13;
+
+pub(crate) fn hardware_syscall(number: u64, arg0: u64, arg1: u64, arg2: u64) -> i64 {
+    let result: i64;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") number,
+            in("rdi") arg0,
+            in("rsi") arg1,
+            in("rdx") arg2,
+            lateout("rax") result,
+            options(nostack, preserves_flags)
+        );
+    }
+    result
+}
+
+/// Port access through the kernel
+///
+/// Drivers run in user mode, where `in` and `out` fault, so every access
+/// is a system call the kernel checks against the driver's `IoPorts`
+/// grants. Refused reads float high like an empty bus and refused writes
+/// are dropped, as on a machine without the device.
+pub struct HardwarePortIo;
+
+impl HardwarePortIo {
+    fn read(port: u16, width: u64) -> Option<u32> {
+        let result = hardware_syscall(SYS_IO_READ, port as u64, width, 0);
+        (result >= 0).then_some(result as u32)
+    }
+
+    fn write(port: u16, width: u64, value: u32) {
+        hardware_syscall(SYS_IO_WRITE, port as u64, width, value as u64);
+    }
+}
+
+impl PortIo for HardwarePortIo {
+    fn read_u8(&mut self, port: u16) -> u8 {
+        Self::read(port, 1).map_or(0xFF, |value| value as u8)
+    }
+
+    fn write_u8(&mut self, port: u16, value: u8) {
+        Self::write(port, 1, value as u32);
+    }
+
+    fn read_u16(&mut self, port: u16) -> u16 {
+        Self::read(port, 2).map_or(0xFFFF, |value| value as u16)
+    }
+
+    fn write_u16(&mut self, port: u16, value: u16) {
+        Self::write(port, 2, value as u32);
+    }
+
+    fn read_u32(&mut self, port: u16) -> u32 {
+        Self::read(port, 4).unwrap_or(0xFFFF_FFFF)
+    }
+
+    fn write_u32(&mut self, port: u16, value: u32) {
+        Self::write(port, 4, value);
+    }
+}
+
+/// Map `len` bytes of device registers at physical address `physical`
+///
+/// The driver must hold a `MemoryRange` grant covering the registers. The
+/// window is mapped uncached and stays mapped until the driver exits.
+pub fn map_mmio(physical: u64, len: usize) -> Result<HardwareMmio, DriverError> {
+    match hardware_syscall(SYS_MMIO_MAP, physical, len as u64, MMIO_MAP_WRITE) {
+        EACCES => Err(DriverError::PermissionDenied),
+        result if result < 0 => Err(DriverError::HardwareNotFound),
+        base => Ok(unsafe { HardwareMmio::new(base as usize, len) }),
+    }
+}
+
+/// Volatile access to a mapped register window
+pub struct HardwareMmio {
+    base: usize,
+    len: usize,
+}
+
+impl HardwareMmio {
+    /// Wrap a register window
+    ///
+    /// # Safety
+    /// `base..base + len` must be device memory mapped into this process for
+    /// as long as the `HardwareMmio` lives.
+    pub unsafe fn new(base: usize, len: usize) -> Self {
+        Self { base, len }
+    }
+
+    fn address(&self, offset: usize, width: usize) -> usize {
+        assert!(offset + width <= self.len, "MMIO access outside register window");
+        self.base + offset
+    }
+}
+
+impl Mmio for HardwareMmio {
+    fn read_u8(&mut self, offset: usize) -> u8 {
+        unsafe { core::ptr::read_volatile(self.address(offset, 1) as *const u8) }
+    }
+
+    fn write_u8(&mut self, offset: usize, value: u8) {
+        unsafe { core::ptr::write_volatile(self.address(offset, 1) as *mut u8, value) }
+    }
+
+    fn read_u16(&mut self, offset: usize) -> u16 {
+        unsafe { core::ptr::read_volatile(self.address(offset, 2) as *const u16) }
+    }
+
+    fn write_u16(&mut self, offset: usize, value: u16) {
+        unsafe { core::ptr::write_volatile(self.address(offset, 2) as *mut u16, value) }
+    }
+
+    fn read_u32(&mut self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile(self.address(offset, 4) as *const u32) }
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile(self.address(offset, 4) as *mut u32, value) }
+    }
+}
+
+/// A touch controller, however it is wired
+pub trait TouchPanel: Send {
+    /// Reports the controller has ready, oldest first
+    fn read_reports(&mut self) -> Result<Vec<RawTouch>, DriverError>;
+}
+
+/// The touch controller of the machine
+///
+/// No touch controller is supported yet, so there is never anything to
+/// read.
+pub struct HardwareTouchPanel;
+
+impl TouchPanel for HardwareTouchPanel {
+    fn read_reports(&mut self) -> Result<Vec<RawTouch>, DriverError> {
+        Ok(Vec::new())
+    }
+}
+
+/// Everything a driver reaches its device through
+pub trait HardwareBackend {
+    /// Port I/O, for the ports the driver was granted
+    fn port_io(&self) -> Box<dyn PortIo>;
+
+    /// Map `len` bytes of device registers at physical address `physical`
+    fn map_mmio(&self, physical: u64, len: usize) -> Result<Box<dyn Mmio>, DriverError>;
+
+    /// The touch controller
+    fn touch_panel(&self) -> Box<dyn TouchPanel>;
+}
+
+/// The machine the driver runs on, reached through the kernel
+pub struct RealBackend;
+
+impl HardwareBackend for RealBackend {
+    fn port_io(&self) -> Box<dyn PortIo> {
+        Box::new(HardwarePortIo)
+    }
+
+    fn map_mmio(&self, physical: u64, len: usize) -> Result<Box<dyn Mmio>, DriverError> {
+        Ok(Box::new(map_mmio(physical, len)?))
+    }
+
+    fn touch_panel(&self) -> Box<dyn TouchPanel> {
+        Box::new(HardwareTouchPanel)
+    }
+}
+
+#[cfg(feature = "replay")]
+pub mod replay {
+    //! Deterministic replay of recorded register transactions
+    //!
+    //! A fixture is the ordered list of accesses a driver performed against
+    //! real (or emulated) hardware. `ReplayIo` hands back the recorded read
+    //! values and panics as soon as the driver deviates from the recording.
+
+    use alloc::vec::Vec;
+    use super::{Mmio, PortIo};
+
+    /// Direction of a register access
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Access {
+        Read,
+        Write,
+    }
+
+    /// Width of a register access
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Width {
+        U8,
+        U16,
+        U32,
+    }
+
+    /// One recorded register access
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Transaction {
+        pub access: Access,
+        pub width: Width,
+        /// Port number or MMIO offset
+        pub address: usize,
+        /// Value read from or written to the register
+        pub value: u32,
+    }
+
+    impl Transaction {
+        pub const fn read_u8(address: usize, value: u8) -> Self {
+            Self { access: Access::Read, width: Width::U8, address, value: value as u32 }
+        }
+
+        pub const fn write_u8(address: usize, value: u8) -> Self {
+            Self { access: Access::Write, width: Width::U8, address, value: value as u32 }
+        }
+
+        pub const fn read_u16(address: usize, value: u16) -> Self {
+            Self { access: Access::Read, width: Width::U16, address, value: value as u32 }
+        }
+
+        pub const fn write_u16(address: usize, value: u16) -> Self {
+            Self { access: Access::Write, width: Width::U16, address, value: value as u32 }
+        }
+
+        pub const fn read_u32(address: usize, value: u32) -> Self {
+            Self { access: Access::Read, width: Width::U32, address, value }
+        }
+
+        pub const fn write_u32(address: usize, value: u32) -> Self {
+            Self { access: Access::Write, width: Width::U32, address, value }
+        }
+    }
+
+    /// Replays a fixture through the `PortIo` and `Mmio` interfaces
+    pub struct ReplayIo {
+        script: Vec<Transaction>,
+        position: usize,
+        looping: bool,
+    }
+
+    impl ReplayIo {
+        /// Replay the fixture once
+        pub fn new(script: &[Transaction]) -> Self {
+            Self { script: script.to_vec(), position: 0, looping: false }
+        }
+
+        /// Replay the fixture over and over, for code that repeats a sequence
+        /// (e.g. re-initialising on resume)
+        pub fn looping(script: &[Transaction]) -> Self {
+            Self { script: script.to_vec(), position: 0, looping: true }
+        }
+
+        /// Append further transactions to the script
+        pub fn then(mut self, script: &[Transaction]) -> Self {
+            self.script.extend_from_slice(script);
+            self
+        }
+
+        /// Number of transactions consumed so far
+        pub fn position(&self) -> usize {
+            self.position
+        }
+
+        /// Whether every recorded transaction has been replayed
+        pub fn is_finished(&self) -> bool {
+            self.position == self.script.len()
+        }
+
+        fn next(&mut self, access: Access, width: Width, address: usize, written: u32) -> u32 {
+            if self.looping && self.position == self.script.len() {
+                self.position = 0;
+            }
+
+            let expected = match self.script.get(self.position) {
+                Some(expected) => *expected,
+                None => panic!(
+                    "replay: unexpected {:?} {:?} at 0x{:x} after end of fixture ({} transactions)",
+                    access, width, address, self.script.len()
+                ),
+            };
+
+            if expected.access != access || expected.width != width || expected.address != address {
+                panic!(
+                    "replay: transaction {} expected {:?} {:?} at 0x{:x}, driver did {:?} {:?} at 0x{:x}",
+                    self.position, expected.access, expected.width, expected.address, access, width, address
+                );
+            }
+            if access == Access::Write && expected.value != written {
+                panic!(
+                    "replay: transaction {} expected write of 0x{:x} to 0x{:x}, driver wrote 0x{:x}",
+                    self.position, expected.value, address, written
+                );
+            }
+
+            self.position += 1;
+            expected.value
+        }
+    }
+
+    impl PortIo for ReplayIo {
+        fn read_u8(&mut self, port: u16) -> u8 {
+            self.next(Access::Read, Width::U8, port as usize, 0) as u8
+        }
+
+        fn write_u8(&mut self, port: u16, value: u8) {
+            self.next(Access::Write, Width::U8, port as usize, value as u32);
+        }
+
+        fn read_u16(&mut self, port: u16) -> u16 {
+            self.next(Access::Read, Width::U16, port as usize, 0) as u16
+        }
+
+        fn write_u16(&mut self, port: u16, value: u16) {
+            self.next(Access::Write, Width::U16, port as usize, value as u32);
+        }
+
+        fn read_u32(&mut self, port: u16) -> u32 {
+            self.next(Access::Read, Width::U32, port as usize, 0)
+        }
+
+        fn write_u32(&mut self, port: u16, value: u32) {
+            self.next(Access::Write, Width::U32, port as usize, value);
+        }
+    }
+
+    impl Mmio for ReplayIo {
+        fn read_u8(&mut self, offset: usize) -> u8 {
+            self.next(Access::Read, Width::U8, offset, 0) as u8
+        }
+
+        fn write_u8(&mut self, offset: usize, value: u8) {
+            self.next(Access::Write, Width::U8, offset, value as u32);
+        }
+
+        fn read_u16(&mut self, offset: usize) -> u16 {
+            self.next(Access::Read, Width::U16, offset, 0) as u16
+        }
+
+        fn write_u16(&mut self, offset: usize, value: u16) {
+            self.next(Access::Write, Width::U16, offset, value as u32);
+        }
+
+        fn read_u32(&mut self, offset: usize) -> u32 {
+            self.next(Access::Read, Width::U32, offset, 0)
+        }
+
+        fn write_u32(&mut self, offset: usize, value: u32) {
+            self.next(Access::Write, Width::U32, offset, value);
+        }
+    }
+}
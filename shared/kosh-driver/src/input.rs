@@ -0,0 +1,674 @@
+//! Input events shared by the input device drivers
+//!
+//! Every keyboard driver, whatever the bus, reports keys as `KeyCode`s
+//! numbered after PS/2 scancode set 1, and every pointing device reports
+//! relative `MouseEvent`s, so consumers do not care where input came from.
+//! Touch screens report absolute `TouchInputEvent`s, streamed in touch
+//! batches to whoever subscribed to the driver.
+//!
+//! Keyboard and pointer drivers send their events to the input service as
+//! batches of `InputRecord`s, and the input service passes them on, with
+//! touch events, in the same format to the process that has focus.
+
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use kosh_ipc::syscall::MAX_MESSAGE_SIZE;
+use kosh_types::DriverError;
+
+/// Key codes for common keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum KeyCode {
+    // Letters
+    A = 0x1E, B = 0x30, C = 0x2E, D = 0x20, E = 0x12, F = 0x21, G = 0x22,
+    H = 0x23, I = 0x17, J = 0x24, K = 0x25, L = 0x26, M = 0x32, N = 0x31,
+    O = 0x18, P = 0x19, Q = 0x10, R = 0x13, S = 0x1F, T = 0x14, U = 0x16,
+    V = 0x2F, W = 0x11, X = 0x2D, Y = 0x15, Z = 0x2C,
+    
+    // Numbers
+    Key0 = 0x0B, Key1 = 0x02, Key2 = 0x03, Key3 = 0x04, Key4 = 0x05,
+    Key5 = 0x06, Key6 = 0x07, Key7 = 0x08, Key8 = 0x09, Key9 = 0x0A,
+    
+    // Function keys
+    F1 = 0x3B, F2 = 0x3C, F3 = 0x3D, F4 = 0x3E, F5 = 0x3F, F6 = 0x40,
+    F7 = 0x41, F8 = 0x42, F9 = 0x43, F10 = 0x44, F11 = 0x57, F12 = 0x58,
+    
+    // Special keys
+    Escape = 0x01,
+    Backspace = 0x0E,
+    Tab = 0x0F,
+    Enter = 0x1C,
+    Space = 0x39,
+    LeftShift = 0x2A,
+    RightShift = 0x36,
+    LeftCtrl = 0x1D,
+    LeftAlt = 0x38,
+    CapsLock = 0x3A,
+    NumLock = 0x45,
+    ScrollLock = 0x46,
+    
+    // Arrow keys (extended scancodes)
+    ArrowUp = 0x48,
+    ArrowDown = 0x50,
+    ArrowLeft = 0x4B,
+    ArrowRight = 0x4D,
+    
+    // Other keys
+    Delete = 0x53,
+    Home = 0x47,
+    End = 0x4F,
+    PageUp = 0x49,
+    PageDown = 0x51,
+    Insert = 0x52,
+    
+    // Unknown key
+    Unknown = 0xFF,
+}
+
+impl KeyCode {
+    /// The key code numbered `byte`, `Unknown` if there is none
+    pub fn from_byte(byte: u8) -> Self {
+        const KEYS: [KeyCode; 70] = [
+            KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+            KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N,
+            KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R, KeyCode::S, KeyCode::T, KeyCode::U,
+            KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+            KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+            KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+            KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+            KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+            KeyCode::Escape, KeyCode::Backspace, KeyCode::Tab, KeyCode::Enter, KeyCode::Space,
+            KeyCode::LeftShift, KeyCode::RightShift, KeyCode::LeftCtrl, KeyCode::LeftAlt, KeyCode::CapsLock,
+            KeyCode::NumLock, KeyCode::ScrollLock,
+            KeyCode::ArrowUp, KeyCode::ArrowDown, KeyCode::ArrowLeft, KeyCode::ArrowRight,
+            KeyCode::Delete, KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown, KeyCode::Insert,
+        ];
+        KEYS.iter()
+            .copied()
+            .find(|&key| key as u8 == byte)
+            .unwrap_or(KeyCode::Unknown)
+    }
+
+    /// Whether the key only changes how others read, like Shift
+    pub fn is_modifier(self) -> bool {
+        matches!(
+            self,
+            KeyCode::LeftShift | KeyCode::RightShift | KeyCode::LeftCtrl | KeyCode::LeftAlt
+                | KeyCode::CapsLock | KeyCode::NumLock | KeyCode::ScrollLock
+        )
+    }
+}
+
+/// Key event types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEventType {
+    KeyPress,
+    KeyRelease,
+}
+
+/// Input event structure
+#[derive(Debug, Clone)]
+pub struct InputEvent {
+    pub event_type: KeyEventType,
+    pub key_code: KeyCode,
+    pub scancode: u8,
+    pub modifiers: KeyModifiers,
+    /// Character the key types; drivers give ASCII, and the input
+    /// service's keymap can give any character
+    pub ascii_char: Option<char>,
+    /// Milliseconds on the monotonic clock, from `time::monotonic_ms`
+    pub timestamp: u64,
+}
+
+/// Key modifier flags
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct KeyModifiers: u8 {
+        const SHIFT = 1 << 0;
+        const CTRL = 1 << 1;
+        const ALT = 1 << 2;
+        const CAPS_LOCK = 1 << 3;
+        const NUM_LOCK = 1 << 4;
+        const SCROLL_LOCK = 1 << 5;
+    }
+}
+
+/// Convert keycode to ASCII character (considering modifiers)
+pub fn keycode_to_ascii(key_code: KeyCode, modifiers: KeyModifiers) -> Option<char> {
+    let shift_pressed = modifiers.contains(KeyModifiers::SHIFT);
+    let caps_lock = modifiers.contains(KeyModifiers::CAPS_LOCK);
+    
+    match key_code {
+        // Letters - handle each key individually since range patterns don't work with enums
+        KeyCode::A => {
+            let base_char = 'a';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::B => {
+            let base_char = 'b';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::C => {
+            let base_char = 'c';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::D => {
+            let base_char = 'd';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::E => {
+            let base_char = 'e';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::F => {
+            let base_char = 'f';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::G => {
+            let base_char = 'g';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::H => {
+            let base_char = 'h';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::I => {
+            let base_char = 'i';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::J => {
+            let base_char = 'j';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::K => {
+            let base_char = 'k';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::L => {
+            let base_char = 'l';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::M => {
+            let base_char = 'm';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::N => {
+            let base_char = 'n';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::O => {
+            let base_char = 'o';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::P => {
+            let base_char = 'p';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::Q => {
+            let base_char = 'q';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::R => {
+            let base_char = 'r';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::S => {
+            let base_char = 's';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::T => {
+            let base_char = 't';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::U => {
+            let base_char = 'u';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::V => {
+            let base_char = 'v';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::W => {
+            let base_char = 'w';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::X => {
+            let base_char = 'x';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::Y => {
+            let base_char = 'y';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        KeyCode::Z => {
+            let base_char = 'z';
+            if shift_pressed ^ caps_lock {
+                Some(base_char.to_ascii_uppercase())
+            } else {
+                Some(base_char)
+            }
+        }
+        
+        // Numbers
+        KeyCode::Key0 => Some(if shift_pressed { ')' } else { '0' }),
+        KeyCode::Key1 => Some(if shift_pressed { '!' } else { '1' }),
+        KeyCode::Key2 => Some(if shift_pressed { '@' } else { '2' }),
+        KeyCode::Key3 => Some(if shift_pressed { '#' } else { '3' }),
+        KeyCode::Key4 => Some(if shift_pressed { '$' } else { '4' }),
+        KeyCode::Key5 => Some(if shift_pressed { '%' } else { '5' }),
+        KeyCode::Key6 => Some(if shift_pressed { '^' } else { '6' }),
+        KeyCode::Key7 => Some(if shift_pressed { '&' } else { '7' }),
+        KeyCode::Key8 => Some(if shift_pressed { '*' } else { '8' }),
+        KeyCode::Key9 => Some(if shift_pressed { '(' } else { '9' }),
+        
+        // Special characters
+        KeyCode::Space => Some(' '),
+        KeyCode::Tab => Some('\t'),
+        KeyCode::Enter => Some('\n'),
+        KeyCode::Backspace => Some('\x08'),
+        
+        _ => None,
+    }
+}
+
+/// Mouse button flags
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MouseButtons: u8 {
+        const LEFT = 1 << 0;
+        const RIGHT = 1 << 1;
+        const MIDDLE = 1 << 2;
+    }
+}
+
+/// Relative pointer movement and button state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// Movement to the right
+    pub dx: i32,
+    /// Movement downwards
+    pub dy: i32,
+    /// Wheel clicks away from the user
+    pub wheel: i8,
+    /// Buttons held after the movement
+    pub buttons: MouseButtons,
+    /// Milliseconds on the monotonic clock, from `time::monotonic_ms`
+    pub timestamp: u64,
+}
+
+/// Touch event types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TouchEventType {
+    /// Touch down
+    Down = 0,
+    /// Touch move
+    Move = 1,
+    /// Touch up
+    Up = 2,
+    /// Touch cancel
+    Cancel = 3,
+}
+
+impl TouchEventType {
+    /// Decode the type byte of the batch format
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(TouchEventType::Down),
+            1 => Some(TouchEventType::Move),
+            2 => Some(TouchEventType::Up),
+            3 => Some(TouchEventType::Cancel),
+            _ => None,
+        }
+    }
+}
+
+/// Touch input event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TouchInputEvent {
+    /// Event type
+    pub event_type: TouchEventType,
+    /// X coordinate (0-65535)
+    pub x: u16,
+    /// Y coordinate (0-65535)
+    pub y: u16,
+    /// Pressure (0-255, 0 = no pressure)
+    pub pressure: u8,
+    /// Microseconds on the monotonic clock, from `time::monotonic_us`
+    pub timestamp_us: u64,
+    /// Touch ID for multi-touch
+    pub touch_id: u8,
+}
+
+/// A contact as a touch controller reports it, before the driver tracks
+/// and filters it into `TouchInputEvent`s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawTouch {
+    pub event_type: TouchEventType,
+    /// The controller's ID for the contact
+    pub tracking_id: u16,
+    pub x: u16,
+    pub y: u16,
+    pub pressure: u8,
+    /// Contact area in square millimetres
+    pub area: u16,
+    /// Microseconds on the monotonic clock, from `time::monotonic_us`
+    pub timestamp_us: u64,
+}
+
+/// Touch batch format: the magic `KTCH`, a format version byte, a
+/// little-endian `u16` event count, then per event the type byte, touch
+/// ID, `u16` x and y, pressure and `u64` timestamp, all little-endian
+const TOUCH_BATCH_MAGIC: &[u8; 4] = b"KTCH";
+const TOUCH_BATCH_VERSION: u8 = 1;
+const BATCH_HEADER_LEN: usize = 7;
+const TOUCH_EVENT_LEN: usize = 15;
+
+/// Most events one batch carries, so that it fits in an IPC message
+pub const TOUCH_BATCH_MAX_EVENTS: usize = (MAX_MESSAGE_SIZE - BATCH_HEADER_LEN) / TOUCH_EVENT_LEN;
+
+fn batch_header(magic: &[u8; 4], version: u8, count: usize, capacity: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(capacity);
+    bytes.extend_from_slice(magic);
+    bytes.push(version);
+    bytes.extend_from_slice(&(count as u16).to_le_bytes());
+    bytes
+}
+
+/// Events of a batch with `magic` and `version`, and how many it says
+/// there are
+fn batch_body<'a>(bytes: &'a [u8], magic: &[u8; 4], version: u8) -> Result<(&'a [u8], usize), DriverError> {
+    if bytes.len() < BATCH_HEADER_LEN || &bytes[..4] != magic || bytes[4] != version {
+        return Err(DriverError::InvalidRequest);
+    }
+    Ok((&bytes[BATCH_HEADER_LEN..], u16::from_le_bytes([bytes[5], bytes[6]]) as usize))
+}
+
+fn put_touch(bytes: &mut Vec<u8>, event: &TouchInputEvent) {
+    bytes.push(event.event_type as u8);
+    bytes.push(event.touch_id);
+    bytes.extend_from_slice(&event.x.to_le_bytes());
+    bytes.extend_from_slice(&event.y.to_le_bytes());
+    bytes.push(event.pressure);
+    bytes.extend_from_slice(&event.timestamp_us.to_le_bytes());
+}
+
+fn get_touch(entry: &[u8]) -> Result<TouchInputEvent, DriverError> {
+    Ok(TouchInputEvent {
+        event_type: TouchEventType::from_byte(entry[0]).ok_or(DriverError::InvalidRequest)?,
+        touch_id: entry[1],
+        x: u16::from_le_bytes([entry[2], entry[3]]),
+        y: u16::from_le_bytes([entry[4], entry[5]]),
+        pressure: entry[6],
+        timestamp_us: get_u64(&entry[7..15]),
+    })
+}
+
+fn get_u64(bytes: &[u8]) -> u64 {
+    let mut raw = [0u8; 8];
+    raw.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(raw)
+}
+
+fn get_i32(bytes: &[u8]) -> i32 {
+    i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Encode up to `TOUCH_BATCH_MAX_EVENTS` of `events` as a batch; the rest
+/// are left for the next one
+pub fn encode_touch_batch(events: &[TouchInputEvent]) -> Vec<u8> {
+    let events = &events[..events.len().min(TOUCH_BATCH_MAX_EVENTS)];
+    let capacity = BATCH_HEADER_LEN + events.len() * TOUCH_EVENT_LEN;
+    let mut bytes = batch_header(TOUCH_BATCH_MAGIC, TOUCH_BATCH_VERSION, events.len(), capacity);
+    for event in events {
+        put_touch(&mut bytes, event);
+    }
+    bytes
+}
+
+/// Decode a batch produced by `encode_touch_batch`
+pub fn decode_touch_batch(bytes: &[u8]) -> Result<Vec<TouchInputEvent>, DriverError> {
+    let (entries, count) = batch_body(bytes, TOUCH_BATCH_MAGIC, TOUCH_BATCH_VERSION)?;
+    if entries.len() != count * TOUCH_EVENT_LEN {
+        return Err(DriverError::InvalidRequest);
+    }
+    entries.chunks_exact(TOUCH_EVENT_LEN).map(get_touch).collect()
+}
+
+/// Name the input service runs under; keyboard and pointer drivers look
+/// its process ID up by this name and send it their events
+pub const INPUT_SERVICE_NAME: &str = "input-service";
+
+/// An event from any input device
+#[derive(Debug, Clone)]
+pub enum InputRecord {
+    Key(InputEvent),
+    Mouse(MouseEvent),
+    Touch(TouchInputEvent),
+}
+
+/// Input batch format, in which drivers send events to the input service
+/// and it passes them on: the magic `KEVT`, a format version byte and a
+/// little-endian `u16` record count, then per record a tag byte and its
+/// fields, all little-endian:
+///
+/// - 0, key: type, key code, scancode, modifiers, `u32` character or 0,
+///   and a `u64` timestamp in milliseconds
+/// - 1, mouse: `i32` dx and dy, wheel, buttons and a `u64` timestamp in
+///   milliseconds
+/// - 2, touch: the fields of a touch batch event
+const INPUT_BATCH_MAGIC: &[u8; 4] = b"KEVT";
+const INPUT_BATCH_VERSION: u8 = 2;
+
+const RECORD_KEY: u8 = 0;
+const KEY_RECORD_LEN: usize = 16;
+const RECORD_MOUSE: u8 = 1;
+const RECORD_TOUCH: u8 = 2;
+
+impl InputRecord {
+    /// Bytes the record takes in a batch, with its tag
+    fn encoded_len(&self) -> usize {
+        1 + match self {
+            InputRecord::Key(_) => KEY_RECORD_LEN,
+            InputRecord::Mouse(_) => 18,
+            InputRecord::Touch(_) => TOUCH_EVENT_LEN,
+        }
+    }
+}
+
+/// Whether `bytes` is an input batch rather than some other message
+pub fn is_input_batch(bytes: &[u8]) -> bool {
+    bytes.starts_with(INPUT_BATCH_MAGIC)
+}
+
+/// Whether `bytes` is a touch batch rather than some other message
+pub fn is_touch_batch(bytes: &[u8]) -> bool {
+    bytes.starts_with(TOUCH_BATCH_MAGIC)
+}
+
+/// Encode as many of `records` as fit in an IPC message as a batch;
+/// returns it and how many went in
+pub fn encode_input_batch(records: &[InputRecord]) -> (Vec<u8>, usize) {
+    let mut length = BATCH_HEADER_LEN;
+    let count = records.iter()
+        .take_while(|record| {
+            length += record.encoded_len();
+            length <= MAX_MESSAGE_SIZE
+        })
+        .count();
+
+    let mut bytes = batch_header(INPUT_BATCH_MAGIC, INPUT_BATCH_VERSION, count, MAX_MESSAGE_SIZE.min(length));
+    for record in &records[..count] {
+        match record {
+            InputRecord::Key(event) => {
+                bytes.push(RECORD_KEY);
+                bytes.push(event.event_type as u8);
+                bytes.push(event.key_code as u8);
+                bytes.push(event.scancode);
+                bytes.push(event.modifiers.bits());
+                bytes.extend_from_slice(&event.ascii_char.map_or(0, u32::from).to_le_bytes());
+                bytes.extend_from_slice(&event.timestamp.to_le_bytes());
+            }
+            InputRecord::Mouse(event) => {
+                bytes.push(RECORD_MOUSE);
+                bytes.extend_from_slice(&event.dx.to_le_bytes());
+                bytes.extend_from_slice(&event.dy.to_le_bytes());
+                bytes.push(event.wheel as u8);
+                bytes.push(event.buttons.bits());
+                bytes.extend_from_slice(&event.timestamp.to_le_bytes());
+            }
+            InputRecord::Touch(event) => {
+                bytes.push(RECORD_TOUCH);
+                put_touch(&mut bytes, event);
+            }
+        }
+    }
+    (bytes, count)
+}
+
+/// Decode a batch produced by `encode_input_batch`
+pub fn decode_input_batch(bytes: &[u8]) -> Result<Vec<InputRecord>, DriverError> {
+    let (mut entries, count) = batch_body(bytes, INPUT_BATCH_MAGIC, INPUT_BATCH_VERSION)?;
+    let mut records = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (&tag, rest) = entries.split_first().ok_or(DriverError::InvalidRequest)?;
+        let length = match tag {
+            RECORD_KEY => KEY_RECORD_LEN,
+            RECORD_MOUSE => 18,
+            RECORD_TOUCH => TOUCH_EVENT_LEN,
+            _ => return Err(DriverError::InvalidRequest),
+        };
+        if rest.len() < length {
+            return Err(DriverError::InvalidRequest);
+        }
+        let (entry, rest) = rest.split_at(length);
+        records.push(match tag {
+            RECORD_KEY => InputRecord::Key(InputEvent {
+                event_type: match entry[0] {
+                    0 => KeyEventType::KeyPress,
+                    1 => KeyEventType::KeyRelease,
+                    _ => return Err(DriverError::InvalidRequest),
+                },
+                key_code: KeyCode::from_byte(entry[1]),
+                scancode: entry[2],
+                modifiers: KeyModifiers::from_bits_truncate(entry[3]),
+                ascii_char: char::from_u32(u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]))
+                    .filter(|&character| character != '\0'),
+                timestamp: get_u64(&entry[8..16]),
+            }),
+            RECORD_MOUSE => InputRecord::Mouse(MouseEvent {
+                dx: get_i32(&entry[0..4]),
+                dy: get_i32(&entry[4..8]),
+                wheel: entry[8] as i8,
+                buttons: MouseButtons::from_bits_truncate(entry[9]),
+                timestamp: get_u64(&entry[10..18]),
+            }),
+            _ => InputRecord::Touch(get_touch(entry)?),
+        });
+        entries = rest;
+    }
+    if !entries.is_empty() {
+        return Err(DriverError::InvalidRequest);
+    }
+    Ok(records)
+}
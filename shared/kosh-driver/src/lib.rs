@@ -9,10 +9,27 @@ use kosh_ipc::{Message, DriverRequestData};
 pub mod capability;
 pub mod communication;
 pub mod error;
+pub mod dma;
+pub mod display;
+pub mod entropy;
+pub mod hal;
+pub mod input;
+pub mod metadata;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod pci;
+pub mod report;
+pub mod signature;
+pub mod time;
+pub mod virtio;
 
 pub use capability::*;
 pub use communication::*;
 pub use error::*;
+pub use dma::{DmaBuffer, DmaCache, DmaDirection};
+pub use hal::{PortIo, Mmio, HardwareBackend, RealBackend};
+pub use metadata::{DriverManifest, DriverMetadataRecord};
+pub use signature::DriverSignatureRecord;
 
 /// Core trait that all Kosh drivers must implement
 pub trait KoshDriver {
@@ -96,6 +113,30 @@ pub enum PowerEvent {
     FullPower,
 }
 
+impl PowerEvent {
+    /// Code carried in a power event request's data
+    pub fn code(self) -> u8 {
+        match self {
+            PowerEvent::Suspend => 0,
+            PowerEvent::Resume => 1,
+            PowerEvent::PowerDown => 2,
+            PowerEvent::LowPower => 3,
+            PowerEvent::FullPower => 4,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(PowerEvent::Suspend),
+            1 => Some(PowerEvent::Resume),
+            2 => Some(PowerEvent::PowerDown),
+            3 => Some(PowerEvent::LowPower),
+            4 => Some(PowerEvent::FullPower),
+            _ => None,
+        }
+    }
+}
+
 /// Driver request types
 #[derive(Debug, Clone)]
 pub enum DriverRequest {
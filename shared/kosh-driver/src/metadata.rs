@@ -0,0 +1,373 @@
+//! Driver metadata embedded in driver binaries
+//!
+//! Every driver binary carries one `DriverMetadataRecord` in the
+//! `.kosh_driver` section, which the driver manager reads before starting
+//! the driver:
+//!
+//! ```ignore
+//! #[used]
+//! #[link_section = ".kosh_driver"]
+//! static DRIVER_METADATA: DriverMetadataRecord = DriverMetadataRecord::new("ps2-keyboard", "0.1.0", DriverType::Input)
+//!     .requires_io_ports(0x60, 0x64)
+//!     .requires_irq(1);
+//! ```
+//!
+//! The `requires_*` entries are what the driver asks for; the driver
+//! manager's capability policy decides what it is actually granted.
+
+use alloc::{string::String, vec::Vec};
+use crate::{DriverType, HardwareId};
+use crate::capability::{DriverCapabilityType, HardwareCapability, MemoryCapability, NetworkCapability};
+
+/// Section holding the driver's `DriverMetadataRecord`
+pub const DRIVER_METADATA_SECTION: &str = ".kosh_driver";
+
+/// Symbol the driver's entry point must be named
+pub const DRIVER_ENTRY_SYMBOL: &str = "_start";
+
+const MAGIC: [u8; 4] = *b"KDRV";
+const FORMAT_VERSION: u32 = 2;
+
+const NAME_LEN: usize = 32;
+const VERSION_LEN: usize = 16;
+const MAX_DEPENDENCIES: usize = 4;
+const MAX_HARDWARE_IDS: usize = 8;
+const MAX_REQUIREMENTS: usize = 8;
+
+/// Subsystem ID matching any subsystem
+const ANY_SUBSYSTEM: u32 = u32::MAX;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct HardwareIdRecord {
+    vendor_id: u32,
+    device_id: u32,
+    subsystem_vendor_id: u32,
+    subsystem_device_id: u32,
+}
+
+/// `RequirementRecord::kind` values
+const REQUIRE_IO_PORTS: u32 = 1;
+const REQUIRE_MMIO: u32 = 2;
+const REQUIRE_IRQ: u32 = 3;
+const REQUIRE_DMA_MEMORY: u32 = 4;
+const REQUIRE_PCI_DEVICE: u32 = 5;
+const REQUIRE_NETWORK: u32 = 6;
+const REQUIRE_HARDWARE_ACCESS: u32 = 7;
+const REQUIRE_MSI: u32 = 8;
+
+/// Custom driver types store their number in `driver_type_id`
+const DRIVER_TYPE_CUSTOM: u32 = 7;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RequirementRecord {
+    kind: u32,
+    reserved: u32,
+    first: u64,
+    second: u64,
+}
+
+const fn driver_type_code(driver_type: DriverType) -> (u32, u32) {
+    match driver_type {
+        DriverType::Storage => (0, 0),
+        DriverType::Network => (1, 0),
+        DriverType::Graphics => (2, 0),
+        DriverType::Audio => (3, 0),
+        DriverType::Input => (4, 0),
+        DriverType::Power => (5, 0),
+        DriverType::System => (6, 0),
+        DriverType::Custom(id) => (DRIVER_TYPE_CUSTOM, id),
+    }
+}
+
+fn driver_type_from_code(code: u32, id: u32) -> Option<DriverType> {
+    match code {
+        0 => Some(DriverType::Storage),
+        1 => Some(DriverType::Network),
+        2 => Some(DriverType::Graphics),
+        3 => Some(DriverType::Audio),
+        4 => Some(DriverType::Input),
+        5 => Some(DriverType::Power),
+        6 => Some(DriverType::System),
+        DRIVER_TYPE_CUSTOM => Some(DriverType::Custom(id)),
+        _ => None,
+    }
+}
+
+/// Fixed-layout metadata a driver places in `DRIVER_METADATA_SECTION`
+///
+/// Built at compile time with the `const` methods below; names longer than
+/// the record holds fail the build.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DriverMetadataRecord {
+    magic: [u8; 4],
+    format_version: u32,
+    name: [u8; NAME_LEN],
+    version: [u8; VERSION_LEN],
+    dependency_count: u32,
+    dependencies: [[u8; NAME_LEN]; MAX_DEPENDENCIES],
+    hardware_id_count: u32,
+    hardware_ids: [HardwareIdRecord; MAX_HARDWARE_IDS],
+    driver_type: u32,
+    driver_type_id: u32,
+    requirement_count: u32,
+    reserved: u32,
+    requirements: [RequirementRecord; MAX_REQUIREMENTS],
+}
+
+const fn fixed<const N: usize>(text: &str) -> [u8; N] {
+    let bytes = text.as_bytes();
+    assert!(!bytes.is_empty() && bytes.len() <= N, "driver metadata string is empty or too long");
+    let mut out = [0u8; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        out[i] = bytes[i];
+        i += 1;
+    }
+    out
+}
+
+impl DriverMetadataRecord {
+    /// Size of the record in the section
+    pub const SIZE: usize = core::mem::size_of::<Self>();
+
+    pub const fn new(name: &str, version: &str, driver_type: DriverType) -> Self {
+        let (driver_type, driver_type_id) = driver_type_code(driver_type);
+        Self {
+            magic: MAGIC,
+            format_version: FORMAT_VERSION,
+            name: fixed(name),
+            version: fixed(version),
+            dependency_count: 0,
+            dependencies: [[0; NAME_LEN]; MAX_DEPENDENCIES],
+            hardware_id_count: 0,
+            hardware_ids: [HardwareIdRecord { vendor_id: 0, device_id: 0, subsystem_vendor_id: 0, subsystem_device_id: 0 }; MAX_HARDWARE_IDS],
+            driver_type,
+            driver_type_id,
+            requirement_count: 0,
+            reserved: 0,
+            requirements: [RequirementRecord { kind: 0, reserved: 0, first: 0, second: 0 }; MAX_REQUIREMENTS],
+        }
+    }
+
+    /// Require the driver named `name` to be loaded first
+    pub const fn with_dependency(mut self, name: &str) -> Self {
+        assert!((self.dependency_count as usize) < MAX_DEPENDENCIES, "too many driver dependencies");
+        self.dependencies[self.dependency_count as usize] = fixed(name);
+        self.dependency_count += 1;
+        self
+    }
+
+    /// Declare support for a device, whatever its subsystem IDs
+    pub const fn with_hardware_id(self, vendor_id: u32, device_id: u32) -> Self {
+        self.with_subsystem_hardware_id(vendor_id, device_id, ANY_SUBSYSTEM, ANY_SUBSYSTEM)
+    }
+
+    /// Declare support for a device with specific subsystem IDs
+    pub const fn with_subsystem_hardware_id(mut self, vendor_id: u32, device_id: u32, subsystem_vendor_id: u32, subsystem_device_id: u32) -> Self {
+        assert!((self.hardware_id_count as usize) < MAX_HARDWARE_IDS, "too many hardware IDs");
+        self.hardware_ids[self.hardware_id_count as usize] = HardwareIdRecord {
+            vendor_id,
+            device_id,
+            subsystem_vendor_id,
+            subsystem_device_id,
+        };
+        self.hardware_id_count += 1;
+        self
+    }
+
+    const fn requires(mut self, kind: u32, first: u64, second: u64) -> Self {
+        assert!((self.requirement_count as usize) < MAX_REQUIREMENTS, "too many driver requirements");
+        self.requirements[self.requirement_count as usize] = RequirementRecord { kind, reserved: 0, first, second };
+        self.requirement_count += 1;
+        self
+    }
+
+    /// Ask for I/O ports `start..=end`
+    pub const fn requires_io_ports(self, start: u16, end: u16) -> Self {
+        assert!(start <= end, "empty I/O port range");
+        self.requires(REQUIRE_IO_PORTS, start as u64, end as u64)
+    }
+
+    /// Ask for the device registers at `start..start + size`
+    pub const fn requires_mmio(self, start: u64, size: u64) -> Self {
+        assert!(size != 0, "empty MMIO range");
+        self.requires(REQUIRE_MMIO, start, size)
+    }
+
+    /// Ask for interrupt line `irq`
+    pub const fn requires_irq(self, irq: u32) -> Self {
+        self.requires(REQUIRE_IRQ, irq as u64, 0)
+    }
+
+    /// Ask for DMA-coherent buffers
+    pub const fn requires_dma_memory(self) -> Self {
+        self.requires(REQUIRE_DMA_MEMORY, 0, 0)
+    }
+
+    /// Ask for access to a PCI function, including its BARs once bound
+    pub const fn requires_pci_device(self, vendor_id: u32, device_id: u32) -> Self {
+        self.requires(REQUIRE_PCI_DEVICE, vendor_id as u64, device_id as u64)
+    }
+
+    /// Ask for up to `vectors` message-signaled interrupts for each bound
+    /// PCI device; needs `requires_pci_device` as well
+    pub const fn requires_msi(self, vectors: u32) -> Self {
+        assert!(vectors != 0, "no MSI vectors");
+        self.requires(REQUIRE_MSI, vectors as u64, 0)
+    }
+
+    /// Ask for raw network access
+    pub const fn requires_network(self) -> Self {
+        self.requires(REQUIRE_NETWORK, 0, 0)
+    }
+
+    /// Ask for unrestricted hardware access
+    pub const fn requires_hardware_access(self) -> Self {
+        self.requires(REQUIRE_HARDWARE_ACCESS, 0, 0)
+    }
+}
+
+/// Driver metadata read back from a binary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverManifest {
+    pub name: String,
+    pub version: String,
+    pub dependencies: Vec<String>,
+    pub hardware_ids: Vec<HardwareId>,
+    pub driver_type: DriverType,
+    /// What the driver asks for, before any policy is applied
+    pub required_capabilities: Vec<DriverCapabilityType>,
+}
+
+impl DriverManifest {
+    /// Decode the contents of a driver's metadata section
+    ///
+    /// Returns None if the section does not hold a well-formed record.
+    pub fn parse(section: &[u8]) -> Option<Self> {
+        if section.len() < DriverMetadataRecord::SIZE || section[..4] != MAGIC {
+            return None;
+        }
+        let mut reader = Reader { bytes: section, offset: 4 };
+        if reader.u32()? != FORMAT_VERSION {
+            return None;
+        }
+
+        let name = reader.text(NAME_LEN)?;
+        let version = reader.text(VERSION_LEN)?;
+
+        let dependency_count = reader.u32()? as usize;
+        if dependency_count > MAX_DEPENDENCIES {
+            return None;
+        }
+        let mut dependencies = Vec::new();
+        for index in 0..MAX_DEPENDENCIES {
+            let dependency = reader.text(NAME_LEN)?;
+            if index < dependency_count {
+                dependencies.push(dependency);
+            }
+        }
+
+        let hardware_id_count = reader.u32()? as usize;
+        if hardware_id_count > MAX_HARDWARE_IDS {
+            return None;
+        }
+        let mut hardware_ids = Vec::new();
+        for _ in 0..hardware_id_count {
+            let subsystem = |id: u32| Some(id).filter(|&id| id != ANY_SUBSYSTEM);
+            hardware_ids.push(HardwareId {
+                vendor_id: reader.u32()?,
+                device_id: reader.u32()?,
+                subsystem_vendor_id: subsystem(reader.u32()?),
+                subsystem_device_id: subsystem(reader.u32()?),
+            });
+        }
+
+        for _ in hardware_id_count..MAX_HARDWARE_IDS {
+            reader.take(core::mem::size_of::<HardwareIdRecord>())?;
+        }
+
+        let driver_type = reader.u32()?;
+        let driver_type = driver_type_from_code(driver_type, reader.u32()?)?;
+
+        let requirement_count = reader.u32()? as usize;
+        if requirement_count > MAX_REQUIREMENTS {
+            return None;
+        }
+        reader.u32()?;
+        let mut required_capabilities = Vec::new();
+        for _ in 0..requirement_count {
+            let kind = reader.u32()?;
+            reader.u32()?;
+            required_capabilities.push(requirement(kind, reader.u64()?, reader.u64()?)?);
+        }
+
+        if name.is_empty() || version.is_empty() {
+            return None;
+        }
+        Some(Self { name, version, dependencies, hardware_ids, driver_type, required_capabilities })
+    }
+}
+
+/// Capability a requirement record asks for, None for malformed records
+fn requirement(kind: u32, first: u64, second: u64) -> Option<DriverCapabilityType> {
+    let capability = match kind {
+        REQUIRE_IO_PORTS => {
+            let start = u16::try_from(first).ok()?;
+            let end = u16::try_from(second).ok()?;
+            if start > end {
+                return None;
+            }
+            HardwareCapability::IoPort { start, end }
+        }
+        REQUIRE_MMIO => {
+            if second == 0 || first.checked_add(second).is_none() {
+                return None;
+            }
+            HardwareCapability::MemoryMappedIo { start: first, size: second }
+        }
+        REQUIRE_IRQ => HardwareCapability::Interrupt { irq: u32::try_from(first).ok()? },
+        REQUIRE_PCI_DEVICE => HardwareCapability::PciDevice {
+            vendor_id: u32::try_from(first).ok()?,
+            device_id: u32::try_from(second).ok()?,
+        },
+        REQUIRE_MSI => HardwareCapability::MessageSignaledInterrupts {
+            vectors: u32::try_from(first).ok().filter(|&vectors| vectors != 0)?,
+        },
+        REQUIRE_DMA_MEMORY => return Some(DriverCapabilityType::Memory(MemoryCapability::DmaMemory)),
+        REQUIRE_NETWORK => return Some(DriverCapabilityType::Network(NetworkCapability::RawSocket)),
+        REQUIRE_HARDWARE_ACCESS => return Some(DriverCapabilityType::HardwareAccess),
+        _ => return None,
+    };
+    Some(DriverCapabilityType::Hardware(capability))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Option<&[u8]> {
+        let bytes = self.bytes.get(self.offset..self.offset + len)?;
+        self.offset += len;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    /// NUL-padded UTF-8 string in a field of `len` bytes
+    fn text(&mut self, len: usize) -> Option<String> {
+        let field = self.take(len)?;
+        let end = field.iter().position(|&byte| byte == 0).unwrap_or(len);
+        core::str::from_utf8(&field[..end]).ok().map(String::from)
+    }
+}
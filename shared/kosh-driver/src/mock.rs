@@ -0,0 +1,471 @@
+//! Simulated hardware for running drivers on the host
+//!
+//! `MockBackend` is a machine with a PS/2 controller and keyboard, an ATA
+//! disk on the primary channel, a touch panel, and RAM behind every MMIO
+//! window. A replay fixture checks that a driver makes one recorded
+//! sequence of accesses; these devices instead answer whatever the driver
+//! does the way the hardware would, so a test can feed a driver input and
+//! check what comes out of it:
+//!
+//! - `push_scancodes` adds to the keyboard's scancode stream, which the
+//!   driver reads through the controller's output buffer after any
+//!   answers to its commands. Scancodes pushed before the driver starts
+//!   are flushed with the rest of the firmware's leftovers.
+//! - `push_touches` queues reports for the touch panel.
+//! - `attach_disk` puts a disk image on the primary ATA channel, which
+//!   IDENTIFY DEVICE describes and READ SECTORS reads from.
+//!
+//! The backend is a handle: its clones share one machine, so a test keeps
+//! one to feed and inspect the devices while the driver owns the others.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use kosh_types::DriverError;
+use spin::Mutex;
+use crate::hal::{HardwareBackend, Mmio, PortIo, TouchPanel};
+use crate::input::RawTouch;
+
+/// PS/2 controller ports
+const PS2_DATA: u16 = 0x60;
+const PS2_STATUS_COMMAND: u16 = 0x64;
+
+/// Idle controller status: system flag, command/data and unlocked bits
+const PS2_STATUS_IDLE: u8 = 0x1C;
+const PS2_STATUS_OUTPUT_FULL: u8 = 0x01;
+
+/// Configuration byte firmware leaves: both IRQs on, system flag,
+/// translation
+const PS2_FIRMWARE_CONFIG: u8 = 0x47;
+
+const KEYBOARD_ACK: u8 = 0xFA;
+const KEYBOARD_BAT_PASSED: u8 = 0xAA;
+
+/// I/O ports of the primary ATA channel
+const ATA_BASE: u16 = 0x1F0;
+const ATA_LAST: u16 = ATA_BASE + 7;
+
+const ATA_STATUS_READY: u8 = 0x50;
+const ATA_STATUS_DRQ: u8 = 0x08;
+const ATA_STATUS_ERR: u8 = 0x01;
+
+/// Drive select bits: the slave drive, and LBA addressing
+const ATA_SELECT_SLAVE: u8 = 1 << 4;
+const ATA_SELECT_LBA: u8 = 1 << 6;
+
+const ATA_SECTOR_SIZE: usize = 512;
+
+/// A simulated machine, shared by every clone of the handle
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    machine: Arc<Mutex<Machine>>,
+}
+
+#[derive(Default)]
+struct Machine {
+    ps2: Ps2Controller,
+    ata: AtaChannel,
+    touches: VecDeque<RawTouch>,
+    /// RAM behind each MMIO window, by physical address
+    mmio: BTreeMap<u64, Vec<u8>>,
+}
+
+impl MockBackend {
+    /// A machine with an idle keyboard, no disk and nothing touching the
+    /// panel
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add scancodes to the keyboard's stream
+    pub fn push_scancodes(&self, scancodes: &[u8]) {
+        self.machine.lock().ps2.scancodes.extend(scancodes);
+    }
+
+    /// Scancodes the driver has yet to read
+    pub fn pending_scancodes(&self) -> usize {
+        self.machine.lock().ps2.scancodes.len()
+    }
+
+    /// Every byte sent to the keyboard, commands and their arguments, in
+    /// order
+    pub fn keyboard_bytes(&self) -> Vec<u8> {
+        self.machine.lock().ps2.keyboard_bytes.clone()
+    }
+
+    /// The controller's configuration byte
+    pub fn ps2_config(&self) -> u8 {
+        self.machine.lock().ps2.config
+    }
+
+    /// Queue reports for the touch panel
+    pub fn push_touches(&self, touches: &[RawTouch]) {
+        self.machine.lock().touches.extend(touches);
+    }
+
+    /// Touch reports the driver has yet to read
+    pub fn pending_touches(&self) -> usize {
+        self.machine.lock().touches.len()
+    }
+
+    /// Attach a disk holding `image`, padded with zeros to whole sectors,
+    /// as the primary channel's master drive
+    pub fn attach_disk(&self, mut image: Vec<u8>) {
+        image.resize(image.len().div_ceil(ATA_SECTOR_SIZE) * ATA_SECTOR_SIZE, 0);
+        self.machine.lock().ata.disk = Some(image);
+    }
+
+    /// Take the disk off the channel
+    pub fn detach_disk(&self) {
+        self.machine.lock().ata.disk = None;
+    }
+
+    /// Copy `bytes` into the MMIO window at `physical`, from `offset`,
+    /// whether or not the driver has mapped it yet
+    pub fn write_mmio(&self, physical: u64, offset: usize, bytes: &[u8]) {
+        let mut machine = self.machine.lock();
+        let window = machine.mmio.entry(physical).or_default();
+        if window.len() < offset + bytes.len() {
+            window.resize(offset + bytes.len(), 0);
+        }
+        window[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// `len` bytes of the MMIO window at `physical`, from `offset`; bytes
+    /// nothing wrote read as zero
+    pub fn read_mmio(&self, physical: u64, offset: usize, len: usize) -> Vec<u8> {
+        let machine = self.machine.lock();
+        let mut bytes = vec![0; len];
+        if let Some(window) = machine.mmio.get(&physical) {
+            for (index, byte) in bytes.iter_mut().enumerate() {
+                *byte = window.get(offset + index).copied().unwrap_or(0);
+            }
+        }
+        bytes
+    }
+}
+
+impl HardwareBackend for MockBackend {
+    fn port_io(&self) -> Box<dyn PortIo> {
+        Box::new(MockPortIo { machine: self.machine.clone() })
+    }
+
+    fn map_mmio(&self, physical: u64, len: usize) -> Result<Box<dyn Mmio>, DriverError> {
+        let mut machine = self.machine.lock();
+        let window = machine.mmio.entry(physical).or_default();
+        if window.len() < len {
+            window.resize(len, 0);
+        }
+        Ok(Box::new(MockMmio { machine: self.machine.clone(), physical, len }))
+    }
+
+    fn touch_panel(&self) -> Box<dyn TouchPanel> {
+        Box::new(MockTouchPanel { machine: self.machine.clone() })
+    }
+}
+
+/// What the controller takes the next byte written to its data port for
+#[derive(Default)]
+enum DataWrite {
+    /// A command for the keyboard
+    #[default]
+    Keyboard,
+    /// The argument of the keyboard's last command
+    KeyboardArgument,
+    /// A new configuration byte
+    Config,
+}
+
+/// i8042 controller with a keyboard on its first port
+struct Ps2Controller {
+    config: u8,
+    /// Answers to commands, read before any scancode
+    responses: VecDeque<u8>,
+    scancodes: VecDeque<u8>,
+    next_write: DataWrite,
+    keyboard_bytes: Vec<u8>,
+}
+
+impl Default for Ps2Controller {
+    fn default() -> Self {
+        Self {
+            config: PS2_FIRMWARE_CONFIG,
+            responses: VecDeque::new(),
+            scancodes: VecDeque::new(),
+            next_write: DataWrite::Keyboard,
+            keyboard_bytes: Vec::new(),
+        }
+    }
+}
+
+impl Ps2Controller {
+    /// Bytes go in as fast as they are written, so the input buffer is
+    /// never full
+    fn status(&self) -> u8 {
+        if self.responses.is_empty() && self.scancodes.is_empty() {
+            PS2_STATUS_IDLE
+        } else {
+            PS2_STATUS_IDLE | PS2_STATUS_OUTPUT_FULL
+        }
+    }
+
+    fn read_data(&mut self) -> u8 {
+        self.responses.pop_front().or_else(|| self.scancodes.pop_front()).unwrap_or(0)
+    }
+
+    fn write_command(&mut self, command: u8) {
+        match command {
+            0x20 => self.responses.push_back(self.config),
+            0x60 => self.next_write = DataWrite::Config,
+            // Self test passed
+            0xAA => self.responses.push_back(0x55),
+            // First port test passed
+            0xAB => self.responses.push_back(0x00),
+            // Enabling and disabling ports changes nothing here
+            _ => {}
+        }
+    }
+
+    fn write_data(&mut self, value: u8) {
+        match core::mem::take(&mut self.next_write) {
+            DataWrite::Config => self.config = value,
+            DataWrite::KeyboardArgument => {
+                self.keyboard_bytes.push(value);
+                self.responses.push_back(KEYBOARD_ACK);
+            }
+            DataWrite::Keyboard => {
+                self.keyboard_bytes.push(value);
+                self.responses.push_back(KEYBOARD_ACK);
+                match value {
+                    // Reset drops whatever the keyboard held
+                    0xFF => {
+                        self.scancodes.clear();
+                        self.responses.push_back(KEYBOARD_BAT_PASSED);
+                    }
+                    // Set LEDs and set typematic take an argument
+                    0xED | 0xF3 => self.next_write = DataWrite::KeyboardArgument,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Task file of the primary ATA channel
+#[derive(Default)]
+struct AtaChannel {
+    disk: Option<Vec<u8>>,
+    drive_select: u8,
+    sector_count: u8,
+    lba: [u8; 3],
+    status: u8,
+    /// Words the drive has ready on the data port
+    data: VecDeque<u16>,
+}
+
+impl AtaChannel {
+    /// The selected drive's disk; there is never a slave
+    fn selected_disk(&self) -> Option<&Vec<u8>> {
+        self.disk.as_ref().filter(|_| self.drive_select & ATA_SELECT_SLAVE == 0)
+    }
+
+    fn read_u8(&mut self, register: u16) -> u8 {
+        match register {
+            2 => self.sector_count,
+            3..=5 => self.lba[register as usize - 3],
+            6 => self.drive_select,
+            // The bus floats to zero on an empty channel
+            7 if self.selected_disk().is_none() => 0,
+            7 if self.status == 0 => ATA_STATUS_READY,
+            7 => self.status,
+            _ => 0,
+        }
+    }
+
+    fn write_u8(&mut self, register: u16, value: u8) {
+        match register {
+            2 => self.sector_count = value,
+            3..=5 => self.lba[register as usize - 3] = value,
+            6 => self.drive_select = value,
+            7 => self.command(value),
+            _ => {}
+        }
+    }
+
+    fn read_data(&mut self) -> u16 {
+        let word = self.data.pop_front().unwrap_or(0);
+        if self.data.is_empty() {
+            self.status = ATA_STATUS_READY;
+        }
+        word
+    }
+
+    fn command(&mut self, command: u8) {
+        self.data.clear();
+        let Some(disk) = self.selected_disk() else {
+            return;
+        };
+        let words = match command {
+            0xEC => Some(identify_words(disk.len() / ATA_SECTOR_SIZE)),
+            0x20 => self.sector_words(disk),
+            _ => None,
+        };
+        match words {
+            Some(words) => {
+                self.data.extend(words);
+                self.status = ATA_STATUS_READY | ATA_STATUS_DRQ;
+            }
+            None => self.status = ATA_STATUS_READY | ATA_STATUS_ERR,
+        }
+    }
+
+    /// The sectors READ SECTORS asks for, or `None` past the end of the
+    /// disk or without LBA addressing
+    fn sector_words(&self, disk: &[u8]) -> Option<Vec<u16>> {
+        if self.drive_select & ATA_SELECT_LBA == 0 {
+            return None;
+        }
+        let lba = self.lba[0] as usize
+            | (self.lba[1] as usize) << 8
+            | (self.lba[2] as usize) << 16
+            | ((self.drive_select & 0x0F) as usize) << 24;
+        let count = if self.sector_count == 0 { 256 } else { self.sector_count as usize };
+        let bytes = disk.get(lba * ATA_SECTOR_SIZE..(lba + count) * ATA_SECTOR_SIZE)?;
+        Some(bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect())
+    }
+}
+
+/// IDENTIFY DEVICE response of a disk of `sectors` sectors
+fn identify_words(sectors: usize) -> Vec<u16> {
+    let mut words = vec![0u16; 256];
+    words[0] = 0x0040;
+    ata_string(&mut words[10..20], b"MOCK0001");
+    ata_string(&mut words[23..27], b"1.0");
+    ata_string(&mut words[27..47], b"KOSH MOCK DISK");
+    // LBA supported
+    words[49] = 1 << 9;
+    let lba28 = sectors.min(0x0FFF_FFFF) as u32;
+    words[60] = lba28 as u16;
+    words[61] = (lba28 >> 16) as u16;
+    // 48-bit LBA supported
+    words[83] = 1 << 10;
+    for (index, word) in words[100..104].iter_mut().enumerate() {
+        *word = (sectors as u64 >> (16 * index)) as u16;
+    }
+    words
+}
+
+/// Store `text`, padded with spaces, two characters per word with the
+/// first in the high byte
+fn ata_string(words: &mut [u16], text: &[u8]) {
+    for (index, word) in words.iter_mut().enumerate() {
+        let byte = |position: usize| text.get(position).copied().unwrap_or(b' ') as u16;
+        *word = byte(2 * index) << 8 | byte(2 * index + 1);
+    }
+}
+
+/// Port I/O on the simulated machine; ports no device answers float high
+/// and writes to them are dropped
+struct MockPortIo {
+    machine: Arc<Mutex<Machine>>,
+}
+
+impl PortIo for MockPortIo {
+    fn read_u8(&mut self, port: u16) -> u8 {
+        let mut machine = self.machine.lock();
+        match port {
+            PS2_DATA => machine.ps2.read_data(),
+            PS2_STATUS_COMMAND => machine.ps2.status(),
+            ATA_BASE..=ATA_LAST => machine.ata.read_u8(port - ATA_BASE),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_u8(&mut self, port: u16, value: u8) {
+        let mut machine = self.machine.lock();
+        match port {
+            PS2_DATA => machine.ps2.write_data(value),
+            PS2_STATUS_COMMAND => machine.ps2.write_command(value),
+            ATA_BASE..=ATA_LAST => machine.ata.write_u8(port - ATA_BASE, value),
+            _ => {}
+        }
+    }
+
+    fn read_u16(&mut self, port: u16) -> u16 {
+        match port {
+            ATA_BASE => self.machine.lock().ata.read_data(),
+            _ => 0xFFFF,
+        }
+    }
+
+    fn write_u16(&mut self, _port: u16, _value: u16) {}
+
+    fn read_u32(&mut self, _port: u16) -> u32 {
+        0xFFFF_FFFF
+    }
+
+    fn write_u32(&mut self, _port: u16, _value: u32) {}
+}
+
+/// An MMIO window backed by the machine's RAM for it
+struct MockMmio {
+    machine: Arc<Mutex<Machine>>,
+    physical: u64,
+    len: usize,
+}
+
+impl MockMmio {
+    fn read<const N: usize>(&self, offset: usize) -> [u8; N] {
+        assert!(offset + N <= self.len, "MMIO access outside register window");
+        let machine = self.machine.lock();
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&machine.mmio[&self.physical][offset..offset + N]);
+        bytes
+    }
+
+    fn write(&self, offset: usize, bytes: &[u8]) {
+        assert!(offset + bytes.len() <= self.len, "MMIO access outside register window");
+        let mut machine = self.machine.lock();
+        let window = machine.mmio.get_mut(&self.physical).expect("mapped window");
+        window[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+}
+
+impl Mmio for MockMmio {
+    fn read_u8(&mut self, offset: usize) -> u8 {
+        self.read::<1>(offset)[0]
+    }
+
+    fn write_u8(&mut self, offset: usize, value: u8) {
+        self.write(offset, &[value]);
+    }
+
+    fn read_u16(&mut self, offset: usize) -> u16 {
+        u16::from_le_bytes(self.read(offset))
+    }
+
+    fn write_u16(&mut self, offset: usize, value: u16) {
+        self.write(offset, &value.to_le_bytes());
+    }
+
+    fn read_u32(&mut self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.read(offset))
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        self.write(offset, &value.to_le_bytes());
+    }
+}
+
+/// The touch panel, reporting what the test queued
+struct MockTouchPanel {
+    machine: Arc<Mutex<Machine>>,
+}
+
+impl TouchPanel for MockTouchPanel {
+    fn read_reports(&mut self) -> Result<Vec<RawTouch>, DriverError> {
+        Ok(self.machine.lock().touches.drain(..).collect())
+    }
+}
@@ -0,0 +1,648 @@
+//! PCI configuration space access and bus enumeration
+//!
+//! Configuration space is reached either through the legacy port pair at
+//! 0xCF8/0xCFC, which only reaches the first 256 bytes of each function,
+//! or through a memory-mapped ECAM window covering the full 4 KiB. Both go
+//! through the `hal` traits like any other register access.
+//!
+//! Enumeration walks the root buses and follows PCI-to-PCI bridges to the
+//! buses behind them. Bus numbers are used as the firmware assigned them;
+//! nothing is renumbered.
+//!
+//! Functions that can signal interrupts with a memory write list an MSI or
+//! MSI-X structure in their capability list. The address and data to
+//! program come from the kernel (`kosh_ipc::irq::allocate_msi`); enabling
+//! either switches the function's INTx pin off.
+
+use alloc::vec::Vec;
+use core::fmt;
+use crate::hal::{Mmio, PortIo};
+use crate::HardwareId;
+
+/// Legacy configuration mechanism ports
+pub const CONFIG_ADDRESS: u16 = 0xCF8;
+pub const CONFIG_DATA: u16 = 0xCFC;
+
+/// Offsets in the configuration header
+pub const VENDOR_DEVICE: u16 = 0x00;
+pub const COMMAND_STATUS: u16 = 0x04;
+pub const CLASS_REVISION: u16 = 0x08;
+pub const HEADER_TYPE: u16 = 0x0E;
+pub const BAR0: u16 = 0x10;
+pub const BRIDGE_BUS_NUMBERS: u16 = 0x18;
+pub const SUBSYSTEM: u16 = 0x2C;
+pub const CAPABILITIES_POINTER: u16 = 0x34;
+
+pub const DEVICES_PER_BUS: u8 = 32;
+pub const FUNCTIONS_PER_DEVICE: u8 = 8;
+
+const HEADER_LAYOUT_MASK: u8 = 0x7F;
+const HEADER_MULTI_FUNCTION: u8 = 0x80;
+
+/// Header layouts
+pub const HEADER_GENERAL: u8 = 0x00;
+pub const HEADER_PCI_BRIDGE: u8 = 0x01;
+
+/// Base address registers in a general header
+const BAR_COUNT: u16 = 6;
+const BAR_IO_SPACE: u32 = 0x1;
+const BAR_TYPE_MASK: u32 = 0x6;
+const BAR_TYPE_64BIT: u32 = 0x4;
+const BAR_ADDRESS_MASK: u32 = !0xF;
+const BAR_IO_ADDRESS_MASK: u32 = !0x3;
+
+/// I/O and memory space enable in the command register
+const COMMAND_IO_SPACE: u32 = 0x1;
+const COMMAND_MEMORY_SPACE: u32 = 0x2;
+/// INTx disable in the command register
+const COMMAND_INTX_DISABLE: u32 = 1 << 10;
+/// Capability list present, in the status half of `COMMAND_STATUS`
+const STATUS_CAPABILITY_LIST: u32 = 1 << 20;
+
+/// Capability IDs
+pub const CAPABILITY_MSI: u8 = 0x05;
+pub const CAPABILITY_VENDOR: u8 = 0x09;
+pub const CAPABILITY_MSIX: u8 = 0x11;
+
+/// Most entries a capability list can hold in the first 256 bytes, to stop
+/// a looping list
+const MAX_CAPABILITIES: usize = 48;
+
+/// MSI message control fields
+const MSI_ENABLE: u16 = 1 << 0;
+const MSI_CAPABLE_SHIFT: u16 = 1;
+const MSI_ENABLED_SHIFT: u16 = 4;
+const MSI_VECTORS_MASK: u16 = 0b111;
+const MSI_64BIT: u16 = 1 << 7;
+
+/// MSI-X message control fields
+const MSIX_TABLE_SIZE_MASK: u16 = 0x7FF;
+const MSIX_FUNCTION_MASK: u16 = 1 << 14;
+const MSIX_ENABLE: u16 = 1 << 15;
+/// BAR indicator in the table and PBA offset registers
+const MSIX_BIR_MASK: u32 = 0b111;
+
+/// Bytes per MSI-X table entry, and the vector control mask bit
+pub const MSIX_ENTRY_SIZE: usize = 16;
+const MSIX_ENTRY_MASKED: u32 = 1 << 0;
+
+/// Vendor ID read back where no function answers
+const NO_VENDOR: u16 = 0xFFFF;
+
+/// Size of each function's configuration space through ECAM
+const ECAM_FUNCTION_SIZE: usize = 4096;
+
+/// Location of a function on the PCI buses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PciAddress {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciAddress {
+    pub const fn new(bus: u8, device: u8, function: u8) -> Self {
+        Self { bus, device, function }
+    }
+
+    /// Value written to `CONFIG_ADDRESS` to select the dword at `offset`
+    fn config_address(self, offset: u16) -> u32 {
+        (1 << 31)
+            | (self.bus as u32) << 16
+            | (self.device as u32) << 11
+            | (self.function as u32) << 8
+            | (offset as u32 & 0xFC)
+    }
+
+    /// Offset of the function's configuration space in an ECAM window
+    /// starting at `start_bus`
+    fn ecam_offset(self, start_bus: u8) -> usize {
+        let function = ((self.bus - start_bus) as usize * DEVICES_PER_BUS as usize + self.device as usize)
+            * FUNCTIONS_PER_DEVICE as usize
+            + self.function as usize;
+        function * ECAM_FUNCTION_SIZE
+    }
+}
+
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02x}:{:02x}.{}", self.bus, self.device, self.function)
+    }
+}
+
+/// Access to configuration space
+///
+/// Reads of functions or registers the mechanism cannot reach return all
+/// ones, as an empty slot does on the bus.
+pub trait ConfigSpace {
+    /// Read the aligned dword containing `offset`
+    fn read_u32(&mut self, address: PciAddress, offset: u16) -> u32;
+
+    /// Write the aligned dword containing `offset`
+    fn write_u32(&mut self, address: PciAddress, offset: u16, value: u32);
+
+    fn read_u16(&mut self, address: PciAddress, offset: u16) -> u16 {
+        (self.read_u32(address, offset) >> ((offset & 2) * 8)) as u16
+    }
+
+    fn read_u8(&mut self, address: PciAddress, offset: u16) -> u8 {
+        (self.read_u32(address, offset) >> ((offset & 3) * 8)) as u8
+    }
+}
+
+/// Configuration space through the legacy 0xCF8/0xCFC port pair
+pub struct PortConfigSpace<P: PortIo> {
+    io: P,
+}
+
+impl<P: PortIo> PortConfigSpace<P> {
+    pub fn new(io: P) -> Self {
+        Self { io }
+    }
+}
+
+impl<P: PortIo> ConfigSpace for PortConfigSpace<P> {
+    fn read_u32(&mut self, address: PciAddress, offset: u16) -> u32 {
+        if offset >= 256 {
+            return u32::MAX;
+        }
+        self.io.write_u32(CONFIG_ADDRESS, address.config_address(offset));
+        self.io.read_u32(CONFIG_DATA)
+    }
+
+    fn write_u32(&mut self, address: PciAddress, offset: u16, value: u32) {
+        if offset >= 256 {
+            return;
+        }
+        self.io.write_u32(CONFIG_ADDRESS, address.config_address(offset));
+        self.io.write_u32(CONFIG_DATA, value);
+    }
+}
+
+/// Configuration space through an ECAM window covering buses
+/// `start_bus..=end_bus`
+pub struct EcamConfigSpace<M: Mmio> {
+    window: M,
+    start_bus: u8,
+    end_bus: u8,
+}
+
+impl<M: Mmio> EcamConfigSpace<M> {
+    /// `window` must map 1 MiB of configuration space per bus in the range
+    pub fn new(window: M, start_bus: u8, end_bus: u8) -> Self {
+        Self { window, start_bus, end_bus }
+    }
+
+    fn offset(&self, address: PciAddress, offset: u16) -> Option<usize> {
+        let in_range = (self.start_bus..=self.end_bus).contains(&address.bus)
+            && (offset as usize) < ECAM_FUNCTION_SIZE;
+        in_range.then(|| address.ecam_offset(self.start_bus) + (offset as usize & !3))
+    }
+}
+
+impl<M: Mmio> ConfigSpace for EcamConfigSpace<M> {
+    fn read_u32(&mut self, address: PciAddress, offset: u16) -> u32 {
+        match self.offset(address, offset) {
+            Some(offset) => self.window.read_u32(offset),
+            None => u32::MAX,
+        }
+    }
+
+    fn write_u32(&mut self, address: PciAddress, offset: u16, value: u32) {
+        if let Some(offset) = self.offset(address, offset) {
+            self.window.write_u32(offset, value);
+        }
+    }
+}
+
+/// A function found on the bus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PciDevice {
+    pub address: PciAddress,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    /// Header layout, without the multi-function bit
+    pub header_type: u8,
+    pub multi_function: bool,
+    pub subsystem_vendor_id: Option<u16>,
+    pub subsystem_device_id: Option<u16>,
+    /// Bus behind a PCI-to-PCI bridge
+    pub secondary_bus: Option<u8>,
+}
+
+impl PciDevice {
+    /// Identity matched against `DriverFactory::can_handle`
+    pub fn hardware_id(&self) -> HardwareId {
+        HardwareId {
+            vendor_id: self.vendor_id as u32,
+            device_id: self.device_id as u32,
+            subsystem_vendor_id: self.subsystem_vendor_id.map(u32::from),
+            subsystem_device_id: self.subsystem_device_id.map(u32::from),
+        }
+    }
+
+    pub fn is_bridge(&self) -> bool {
+        self.header_type == HEADER_PCI_BRIDGE
+    }
+
+    /// Broad description of the class code
+    pub fn class_name(&self) -> &'static str {
+        match (self.class, self.subclass) {
+            (0x01, 0x01) => "IDE controller",
+            (0x01, 0x06) => "SATA controller",
+            (0x01, 0x08) => "NVMe controller",
+            (0x01, _) => "Storage controller",
+            (0x02, _) => "Network controller",
+            (0x03, _) => "Display controller",
+            (0x04, _) => "Multimedia controller",
+            (0x06, 0x00) => "Host bridge",
+            (0x06, 0x01) => "ISA bridge",
+            (0x06, 0x04) => "PCI bridge",
+            (0x06, _) => "Bridge",
+            (0x0C, 0x03) => "USB controller",
+            (0x0C, _) => "Serial bus controller",
+            _ => "Unclassified device",
+        }
+    }
+}
+
+/// Read the function at `address`, or None if nothing answers there
+pub fn probe(config: &mut dyn ConfigSpace, address: PciAddress) -> Option<PciDevice> {
+    let ids = config.read_u32(address, VENDOR_DEVICE);
+    let vendor_id = ids as u16;
+    if vendor_id == NO_VENDOR || vendor_id == 0 {
+        return None;
+    }
+
+    let class = config.read_u32(address, CLASS_REVISION);
+    let header = config.read_u8(address, HEADER_TYPE);
+    let header_type = header & HEADER_LAYOUT_MASK;
+
+    let (subsystem_vendor_id, subsystem_device_id) = match header_type {
+        HEADER_GENERAL => {
+            let subsystem = config.read_u32(address, SUBSYSTEM);
+            let vendor = subsystem as u16;
+            // Zero means the vendor left the subsystem unset
+            if vendor == 0 || vendor == NO_VENDOR {
+                (None, None)
+            } else {
+                (Some(vendor), Some((subsystem >> 16) as u16))
+            }
+        }
+        _ => (None, None),
+    };
+    let secondary_bus = (header_type == HEADER_PCI_BRIDGE)
+        .then(|| config.read_u8(address, BRIDGE_BUS_NUMBERS + 1));
+
+    Some(PciDevice {
+        address,
+        vendor_id,
+        device_id: (ids >> 16) as u16,
+        class: (class >> 24) as u8,
+        subclass: (class >> 16) as u8,
+        prog_if: (class >> 8) as u8,
+        revision: class as u8,
+        header_type,
+        multi_function: header & HEADER_MULTI_FUNCTION != 0,
+        subsystem_vendor_id,
+        subsystem_device_id,
+        secondary_bus,
+    })
+}
+
+/// Find every function reachable from the root buses, in bus order
+/// followed depth-first through bridges
+pub fn enumerate(config: &mut dyn ConfigSpace) -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+    let mut scanned = [false; 256];
+
+    // A multi-function host bridge heads one root bus per function
+    match probe(config, PciAddress::new(0, 0, 0)) {
+        Some(host) if host.multi_function => {
+            for function in 0..FUNCTIONS_PER_DEVICE {
+                if probe(config, PciAddress::new(0, 0, function)).is_some() {
+                    scan_bus(config, function, &mut scanned, &mut devices);
+                }
+            }
+        }
+        _ => scan_bus(config, 0, &mut scanned, &mut devices),
+    }
+    devices
+}
+
+fn scan_bus(config: &mut dyn ConfigSpace, bus: u8, scanned: &mut [bool; 256], devices: &mut Vec<PciDevice>) {
+    // Misconfigured bridges could otherwise send the walk round in circles
+    if scanned[bus as usize] {
+        return;
+    }
+    scanned[bus as usize] = true;
+
+    for device in 0..DEVICES_PER_BUS {
+        let Some(first) = probe(config, PciAddress::new(bus, device, 0)) else {
+            continue;
+        };
+        let functions = if first.multi_function { FUNCTIONS_PER_DEVICE } else { 1 };
+
+        let mut found = Some(first);
+        for function in 0..functions {
+            if function > 0 {
+                found = probe(config, PciAddress::new(bus, device, function));
+            }
+            let Some(found) = found.take() else {
+                continue;
+            };
+            let secondary_bus = found.secondary_bus;
+            devices.push(found);
+            // An unconfigured bridge reports bus 0 behind it
+            if let Some(secondary) = secondary_bus.filter(|&secondary| secondary > bus) {
+                scan_bus(config, secondary, scanned, devices);
+            }
+        }
+    }
+}
+
+/// A memory window a function decodes through one of its BARs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBar {
+    /// BAR number; a 64-bit BAR also takes the next one
+    pub index: u8,
+    pub address: u64,
+    pub size: u64,
+}
+
+/// Size the memory BARs of the general-header function at `address`
+///
+/// Each BAR is sized by writing all ones and reading back which bits
+/// stick, with memory decoding switched off meanwhile so the device never
+/// answers at the probe value. I/O BARs and ones firmware left unassigned
+/// are skipped.
+pub fn memory_bars(config: &mut dyn ConfigSpace, address: PciAddress) -> Vec<MemoryBar> {
+    let mut bars = Vec::new();
+    if config.read_u8(address, HEADER_TYPE) & HEADER_LAYOUT_MASK != HEADER_GENERAL {
+        return bars;
+    }
+
+    // Only touch the command half: status bits are cleared by writing 1
+    let command = config.read_u32(address, COMMAND_STATUS) & 0xFFFF;
+    config.write_u32(address, COMMAND_STATUS, command & !COMMAND_MEMORY_SPACE);
+
+    let mut index = 0;
+    while index < BAR_COUNT {
+        let offset = BAR0 + index * 4;
+        let low = config.read_u32(address, offset);
+        if low & BAR_IO_SPACE != 0 {
+            index += 1;
+            continue;
+        }
+        let wide = low & BAR_TYPE_MASK == BAR_TYPE_64BIT && index + 1 < BAR_COUNT;
+
+        let low_mask = size_bar(config, address, offset, low) & BAR_ADDRESS_MASK;
+        let (high, high_mask) = if wide {
+            let high = config.read_u32(address, offset + 4);
+            (high, size_bar(config, address, offset + 4, high))
+        } else {
+            (0, u32::MAX)
+        };
+
+        let mask = (high_mask as u64) << 32 | low_mask as u64;
+        let base = (high as u64) << 32 | (low & BAR_ADDRESS_MASK) as u64;
+        if low_mask != 0 && base != 0 {
+            bars.push(MemoryBar { index: index as u8, address: base, size: (!mask).wrapping_add(1) });
+        }
+        index += if wide { 2 } else { 1 };
+    }
+
+    config.write_u32(address, COMMAND_STATUS, command);
+    bars
+}
+
+/// A range of I/O ports a function decodes through one of its BARs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoBar {
+    pub index: u8,
+    /// First port
+    pub port: u16,
+    pub size: u16,
+}
+
+/// Size the I/O BARs of the general-header function at `address`
+///
+/// Sized as `memory_bars` sizes memory BARs, with I/O decoding switched
+/// off meanwhile. Memory BARs and ones firmware left unassigned are
+/// skipped.
+pub fn io_bars(config: &mut dyn ConfigSpace, address: PciAddress) -> Vec<IoBar> {
+    let mut bars = Vec::new();
+    if config.read_u8(address, HEADER_TYPE) & HEADER_LAYOUT_MASK != HEADER_GENERAL {
+        return bars;
+    }
+
+    let command = config.read_u32(address, COMMAND_STATUS) & 0xFFFF;
+    config.write_u32(address, COMMAND_STATUS, command & !COMMAND_IO_SPACE);
+
+    let mut index = 0;
+    while index < BAR_COUNT {
+        let offset = BAR0 + index * 4;
+        let value = config.read_u32(address, offset);
+        if value & BAR_IO_SPACE == 0 {
+            // The upper half of a 64-bit memory BAR is no BAR of its own
+            index += if value & BAR_TYPE_MASK == BAR_TYPE_64BIT { 2 } else { 1 };
+            continue;
+        }
+        // Ports are 16 bits; devices may leave the upper bits of the mask clear
+        let mask = size_bar(config, address, offset, value) & BAR_IO_ADDRESS_MASK;
+        let port = (value & BAR_IO_ADDRESS_MASK) as u16;
+        let size = (!mask & 0xFFFF) + 1;
+        if mask & 0xFFFF != 0 && port != 0 && size <= 0x10000 - port as u32 {
+            bars.push(IoBar { index: index as u8, port, size: size as u16 });
+        }
+        index += 1;
+    }
+
+    config.write_u32(address, COMMAND_STATUS, command);
+    bars
+}
+
+/// Bits of the BAR at `offset` that hold an address, restoring `value`
+fn size_bar(config: &mut dyn ConfigSpace, address: PciAddress, offset: u16, value: u32) -> u32 {
+    config.write_u32(address, offset, u32::MAX);
+    let mask = config.read_u32(address, offset);
+    config.write_u32(address, offset, value);
+    mask
+}
+
+/// Offset of capability `id` in the capability list of the function at
+/// `address`, if it has one
+pub fn find_capability(config: &mut dyn ConfigSpace, address: PciAddress, id: u8) -> Option<u16> {
+    find_capabilities(config, address, id).first().copied()
+}
+
+/// Offsets of every capability `id` in the capability list of the function
+/// at `address`, in list order
+///
+/// For capabilities a function lists more than once, such as the
+/// vendor-specific ones virtio devices describe their registers with.
+pub fn find_capabilities(config: &mut dyn ConfigSpace, address: PciAddress, id: u8) -> Vec<u16> {
+    let mut found = Vec::new();
+    if config.read_u32(address, COMMAND_STATUS) & STATUS_CAPABILITY_LIST == 0 {
+        return found;
+    }
+    let mut offset = (config.read_u8(address, CAPABILITIES_POINTER) & 0xFC) as u16;
+    for _ in 0..MAX_CAPABILITIES {
+        // Capabilities never live in the header itself
+        if offset < 0x40 {
+            break;
+        }
+        if config.read_u8(address, offset) == id {
+            found.push(offset);
+        }
+        offset = (config.read_u8(address, offset + 1) & 0xFC) as u16;
+    }
+    found
+}
+
+/// Set or clear the INTx disable bit, leaving the status bits alone
+fn set_intx_disabled(config: &mut dyn ConfigSpace, address: PciAddress, disabled: bool) {
+    let command = config.read_u32(address, COMMAND_STATUS) & 0xFFFF;
+    let command = if disabled { command | COMMAND_INTX_DISABLE } else { command & !COMMAND_INTX_DISABLE };
+    config.write_u32(address, COMMAND_STATUS, command);
+}
+
+/// Write the message control register of the capability at `offset`
+fn write_message_control(config: &mut dyn ConfigSpace, address: PciAddress, offset: u16, control: u16) {
+    // The ID and next pointer below it are read-only
+    let header = config.read_u32(address, offset) & 0xFFFF;
+    config.write_u32(address, offset, (control as u32) << 16 | header);
+}
+
+/// A function's MSI capability structure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsiCapability {
+    pub offset: u16,
+    pub control: u16,
+}
+
+impl MsiCapability {
+    /// Most vectors the function can send, a power of two up to 32
+    pub fn max_vectors(&self) -> u32 {
+        1 << ((self.control >> MSI_CAPABLE_SHIFT) & MSI_VECTORS_MASK).min(5)
+    }
+
+    /// Whether the message address has an upper half
+    pub fn is_64bit(&self) -> bool {
+        self.control & MSI_64BIT != 0
+    }
+
+    fn data_offset(&self) -> u16 {
+        self.offset + if self.is_64bit() { 0x0C } else { 0x08 }
+    }
+}
+
+/// The MSI capability of the function at `address`
+pub fn msi_capability(config: &mut dyn ConfigSpace, address: PciAddress) -> Option<MsiCapability> {
+    let offset = find_capability(config, address, CAPABILITY_MSI)?;
+    Some(MsiCapability { offset, control: config.read_u16(address, offset + 2) })
+}
+
+/// Have the function send `vectors` messages, the `n`th writing
+/// `data + n` to `message_address`, instead of asserting INTx
+///
+/// `vectors` is rounded down to a power of two the function supports.
+/// Returns how many it got.
+pub fn enable_msi(config: &mut dyn ConfigSpace, address: PciAddress, capability: &MsiCapability, message_address: u64, data: u32, vectors: u32) -> u32 {
+    let vectors = vectors.clamp(1, capability.max_vectors());
+    let log2 = 31 - vectors.leading_zeros();
+
+    let control = capability.control & !(MSI_ENABLE | MSI_VECTORS_MASK << MSI_ENABLED_SHIFT);
+    write_message_control(config, address, capability.offset, control);
+
+    config.write_u32(address, capability.offset + 4, message_address as u32);
+    if capability.is_64bit() {
+        config.write_u32(address, capability.offset + 8, (message_address >> 32) as u32);
+    }
+    // Data is 16 bits; the upper half of its dword is extended data or
+    // reserved
+    let data_offset = capability.data_offset();
+    let upper = config.read_u32(address, data_offset) & 0xFFFF_0000;
+    config.write_u32(address, data_offset, upper | (data & 0xFFFF));
+
+    set_intx_disabled(config, address, true);
+    write_message_control(config, address, capability.offset, control | (log2 as u16) << MSI_ENABLED_SHIFT | MSI_ENABLE);
+    1 << log2
+}
+
+/// Stop the function sending MSI messages and give it its INTx pin back
+pub fn disable_msi(config: &mut dyn ConfigSpace, address: PciAddress, capability: &MsiCapability) {
+    let control = config.read_u16(address, capability.offset + 2) & !MSI_ENABLE;
+    write_message_control(config, address, capability.offset, control);
+    set_intx_disabled(config, address, false);
+}
+
+/// A function's MSI-X capability structure
+///
+/// The vector table and pending bit array live in the function's memory
+/// BARs, which the driver maps like its other registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsixCapability {
+    pub offset: u16,
+    /// Entries in the vector table
+    pub table_size: u16,
+    pub table_bar: u8,
+    pub table_offset: u32,
+    pub pba_bar: u8,
+    pub pba_offset: u32,
+}
+
+/// The MSI-X capability of the function at `address`
+pub fn msix_capability(config: &mut dyn ConfigSpace, address: PciAddress) -> Option<MsixCapability> {
+    let offset = find_capability(config, address, CAPABILITY_MSIX)?;
+    let control = config.read_u16(address, offset + 2);
+    let table = config.read_u32(address, offset + 4);
+    let pba = config.read_u32(address, offset + 8);
+    Some(MsixCapability {
+        offset,
+        table_size: (control & MSIX_TABLE_SIZE_MASK) + 1,
+        table_bar: (table & MSIX_BIR_MASK) as u8,
+        table_offset: table & !MSIX_BIR_MASK,
+        pba_bar: (pba & MSIX_BIR_MASK) as u8,
+        pba_offset: pba & !MSIX_BIR_MASK,
+    })
+}
+
+/// Switch MSI-X on or off for the function
+///
+/// Enabling leaves the whole function masked, so the table can be filled
+/// in with `write_msix_entry` before `set_msix_masked` lets messages
+/// through.
+pub fn set_msix_enabled(config: &mut dyn ConfigSpace, address: PciAddress, capability: &MsixCapability, enabled: bool) {
+    let control = config.read_u16(address, capability.offset + 2);
+    let control = if enabled {
+        control | MSIX_ENABLE | MSIX_FUNCTION_MASK
+    } else {
+        control & !(MSIX_ENABLE | MSIX_FUNCTION_MASK)
+    };
+    set_intx_disabled(config, address, enabled);
+    write_message_control(config, address, capability.offset, control);
+}
+
+/// Mask or unmask every MSI-X vector of the function at once
+pub fn set_msix_masked(config: &mut dyn ConfigSpace, address: PciAddress, capability: &MsixCapability, masked: bool) {
+    let control = config.read_u16(address, capability.offset + 2);
+    let control = if masked { control | MSIX_FUNCTION_MASK } else { control & !MSIX_FUNCTION_MASK };
+    write_message_control(config, address, capability.offset, control);
+}
+
+/// Fill in entry `entry` of an MSI-X vector table mapped at `table`
+/// (the capability's `table_offset` into its `table_bar`)
+pub fn write_msix_entry(table: &mut dyn Mmio, entry: u16, message_address: u64, data: u32, masked: bool) {
+    let base = entry as usize * MSIX_ENTRY_SIZE;
+    // Mask while the entry is half written
+    table.write_u32(base + 12, MSIX_ENTRY_MASKED);
+    table.write_u32(base, message_address as u32);
+    table.write_u32(base + 4, (message_address >> 32) as u32);
+    table.write_u32(base + 8, data);
+    table.write_u32(base + 12, if masked { MSIX_ENTRY_MASKED } else { 0 });
+}
@@ -0,0 +1,71 @@
+//! Reporting failures
+//!
+//! A driver that cannot go on says why in the kernel log and exits with
+//! `FAILURE_STATUS`. The driver manager reaps the process, records the
+//! failure with its status and starts the driver again after a backoff,
+//! so exiting is how a driver tells the manager it failed.
+//!
+//! Lines are formatted into a buffer on the stack, so a driver can report
+//! a panic that hit inside its allocator.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use crate::hal::hardware_syscall;
+
+/// System call numbers (must match shared/kosh-syscall/src/numbers.rs)
+const SYS_EXIT: u64 = 1;
+const SYS_KLOG: u64 = 59;
+
+/// `SYS_KLOG` action adding a line to the log
+const KLOG_WRITE: u64 = 6;
+
+/// Longest line the kernel log takes from a process
+const KLOG_WRITE_MAX: usize = 256;
+
+/// Exit status of a driver that gave up
+pub const FAILURE_STATUS: i32 = 1;
+
+/// A log line being formatted; what does not fit is cut off at a
+/// character boundary
+struct Line {
+    bytes: [u8; KLOG_WRITE_MAX],
+    len: usize,
+}
+
+impl Write for Line {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        let room = KLOG_WRITE_MAX - self.len;
+        let mut count = text.len().min(room);
+        while !text.is_char_boundary(count) {
+            count -= 1;
+        }
+        self.bytes[self.len..self.len + count].copy_from_slice(&text.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+/// Add a line to the kernel log as an error
+pub fn log_error(args: fmt::Arguments) {
+    let mut line = Line { bytes: [0; KLOG_WRITE_MAX], len: 0 };
+    let _ = line.write_fmt(args);
+    hardware_syscall(SYS_KLOG, KLOG_WRITE, line.bytes.as_ptr() as u64, line.len as u64);
+}
+
+/// Log why the driver cannot go on and exit, for the driver manager to
+/// restart it
+pub fn fail(args: fmt::Arguments) -> ! {
+    log_error(args);
+    exit(FAILURE_STATUS)
+}
+
+/// Log a panic and exit; drivers call this from their panic handler
+pub fn report_panic(driver: &str, info: &PanicInfo) -> ! {
+    fail(format_args!("{} driver panicked: {}", driver, info))
+}
+
+fn exit(status: i32) -> ! {
+    loop {
+        hardware_syscall(SYS_EXIT, status as u64, 0, 0);
+    }
+}
@@ -0,0 +1,104 @@
+//! Driver signatures
+//!
+//! A driver binary is signed with Ed25519 over the whole file, taken with
+//! the signature bytes of its `DriverSignatureRecord` zeroed. The record
+//! lives in the `.kosh_signature` section, which the driver reserves at
+//! build time with an unsigned record:
+//!
+//! ```ignore
+//! #[used]
+//! #[link_section = ".kosh_signature"]
+//! static DRIVER_SIGNATURE: DriverSignatureRecord = DriverSignatureRecord::UNSIGNED;
+//! ```
+//!
+//! Signing writes the signature into the record in place, so the layout
+//! of the file does not change. The driver manager checks it against the
+//! public key the kernel was built with, which `driver_trust` returns.
+
+use kosh_types::DriverError;
+
+/// Section holding the driver's `DriverSignatureRecord`
+pub const DRIVER_SIGNATURE_SECTION: &str = ".kosh_signature";
+
+/// Bytes of an Ed25519 signature and public key
+pub const SIGNATURE_LEN: usize = 64;
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+const MAGIC: [u8; 4] = *b"KSIG";
+const FORMAT_VERSION: u32 = 1;
+
+/// System call number for `driver_trust` (must match the kernel)
+pub const SYS_DRIVER_TRUST: u64 = 49;
+
+/// `SYS_DRIVER_TRUST` result: the kernel was built with a signing key
+pub const TRUST_KEY_PROVISIONED: u64 = 1 << 0;
+/// `SYS_DRIVER_TRUST` result: safe mode or recovery lets unsigned drivers load
+pub const TRUST_UNSIGNED_ALLOWED: u64 = 1 << 1;
+
+/// Fixed-layout signature a driver places in `DRIVER_SIGNATURE_SECTION`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DriverSignatureRecord {
+    magic: [u8; 4],
+    format_version: u32,
+    signature: [u8; SIGNATURE_LEN],
+}
+
+impl DriverSignatureRecord {
+    /// Size of the record in the section
+    pub const SIZE: usize = core::mem::size_of::<Self>();
+
+    /// Offset of the signature in the record
+    pub const SIGNATURE_OFFSET: usize = 8;
+
+    /// Placeholder a driver is built with, filled in when it is signed
+    pub const UNSIGNED: Self = Self { magic: MAGIC, format_version: FORMAT_VERSION, signature: [0; SIGNATURE_LEN] };
+
+    /// Signature in the contents of a signature section
+    ///
+    /// Returns None for an unsigned record, and for one that is not a
+    /// record at all.
+    pub fn signature(section: &[u8]) -> Option<[u8; SIGNATURE_LEN]> {
+        if section.len() < Self::SIZE || section[..4] != MAGIC
+            || section[4..8] != FORMAT_VERSION.to_le_bytes() {
+            return None;
+        }
+        let mut signature = [0; SIGNATURE_LEN];
+        signature.copy_from_slice(&section[Self::SIGNATURE_OFFSET..Self::SIGNATURE_OFFSET + SIGNATURE_LEN]);
+        signature.iter().any(|&byte| byte != 0).then_some(signature)
+    }
+}
+
+/// What the kernel tells the driver manager about signed drivers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriverTrust {
+    /// Key drivers must be signed with; None if the kernel has none
+    pub public_key: Option<[u8; PUBLIC_KEY_LEN]>,
+    /// Whether drivers without a valid signature may load anyway
+    pub unsigned_allowed: bool,
+}
+
+/// Ask the kernel for the driver signing key and whether the boot
+/// parameters allow unsigned drivers
+pub fn driver_trust() -> Result<DriverTrust, DriverError> {
+    let mut key = [0u8; PUBLIC_KEY_LEN];
+    let result: i64;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") SYS_DRIVER_TRUST,
+            in("rdi") key.as_mut_ptr() as u64,
+            lateout("rax") result,
+            options(nostack, preserves_flags)
+        );
+    }
+
+    if result < 0 {
+        return Err(DriverError::PermissionDenied);
+    }
+    let flags = result as u64;
+    Ok(DriverTrust {
+        public_key: (flags & TRUST_KEY_PROVISIONED != 0).then_some(key),
+        unsigned_allowed: flags & TRUST_UNSIGNED_ALLOWED != 0,
+    })
+}
@@ -0,0 +1,100 @@
+//! Event timestamps and timers
+//!
+//! Drivers stamp input events with the kernel's monotonic clock, so events
+//! from different devices can be ordered and subtracted. The clock counts
+//! from boot and never goes back.
+//!
+//! Drivers that poll their hardware sleep on a kernel timer between polls.
+
+use crate::hal::hardware_syscall;
+
+/// System call numbers (must match shared/kosh-syscall/src/numbers.rs)
+const SYS_CLOCK_GETTIME: u64 = 53;
+const SYS_NANOSLEEP: u64 = 80;
+const SYS_TIMER_CREATE: u64 = 81;
+const SYS_TIMER_SET: u64 = 82;
+const SYS_TIMER_WAIT: u64 = 83;
+const SYS_TIMER_DELETE: u64 = 84;
+
+/// Errno the kernel returns after blocking the caller (EAGAIN)
+const EAGAIN: i64 = -11;
+
+/// `CLOCK_MONOTONIC`
+const CLOCK_MONOTONIC: u64 = 1;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// `struct timespec` as the kernel fills it in
+#[repr(C)]
+#[derive(Default)]
+struct Timespec {
+    tv_sec: i64,
+    tv_nsec: i64,
+}
+
+/// Nanoseconds on the monotonic clock, or 0 if the kernel cannot say
+pub fn monotonic_ns() -> u64 {
+    let mut time = Timespec::default();
+    if hardware_syscall(SYS_CLOCK_GETTIME, CLOCK_MONOTONIC, &mut time as *mut Timespec as u64, 0) < 0 {
+        return 0;
+    }
+    time.tv_sec as u64 * NANOS_PER_SEC + time.tv_nsec as u64
+}
+
+/// Microseconds on the monotonic clock, as `TouchInputEvent` carries them
+pub fn monotonic_us() -> u64 {
+    monotonic_ns() / 1_000
+}
+
+/// Milliseconds on the monotonic clock, as `InputEvent` and `MouseEvent`
+/// carry them
+pub fn monotonic_ms() -> u64 {
+    monotonic_ns() / 1_000_000
+}
+
+/// Sleep for at least `us` microseconds
+pub fn sleep_us(us: u64) {
+    while hardware_syscall(SYS_NANOSLEEP, us.saturating_mul(1_000), 0, 0) == EAGAIN {}
+}
+
+/// A kernel timer expiring at a fixed period
+///
+/// Deleted when dropped.
+pub struct PeriodicTimer {
+    id: u64,
+}
+
+impl PeriodicTimer {
+    /// Start a timer expiring every `period_us` microseconds
+    pub fn new(period_us: u64) -> Option<Self> {
+        let id = hardware_syscall(SYS_TIMER_CREATE, 0, 0, 0);
+        if id <= 0 {
+            return None;
+        }
+        let timer = Self { id: id as u64 };
+        let period_ns = period_us.saturating_mul(1_000);
+        if hardware_syscall(SYS_TIMER_SET, timer.id, period_ns, period_ns) < 0 {
+            return None;
+        }
+        Some(timer)
+    }
+
+    /// Sleep until the next expiry
+    ///
+    /// Returns the periods that passed since the last wait, more than one
+    /// if the caller fell behind, or 0 if the wait failed.
+    pub fn wait(&self) -> u64 {
+        loop {
+            match hardware_syscall(SYS_TIMER_WAIT, self.id, 0, 0) {
+                EAGAIN => continue,
+                result => return result.max(0) as u64,
+            }
+        }
+    }
+}
+
+impl Drop for PeriodicTimer {
+    fn drop(&mut self) {
+        hardware_syscall(SYS_TIMER_DELETE, self.id, 0, 0);
+    }
+}
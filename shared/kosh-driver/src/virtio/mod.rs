@@ -0,0 +1,8 @@
+//! Virtio devices over PCI
+//!
+//! The transport finds a device's register structures and negotiates with
+//! it; virtqueues carry its requests. Both are shared by every virtio
+//! driver, whatever the device type.
+
+pub mod transport;
+pub mod virtqueue;
@@ -0,0 +1,253 @@
+//! Virtio over PCI, the modern (virtio 1.0) interface
+//!
+//! A virtio device lists where its register structures live in vendor
+//! capabilities: the common configuration the driver negotiates features
+//! and sets up queues through, the notification area it kicks queues
+//! through and the device-specific configuration. Each is a range of one
+//! of the device's memory BARs. The legacy I/O port interface is not
+//! supported.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use crate::hal::Mmio;
+use crate::pci::{self, ConfigSpace, PciAddress};
+use kosh_types::DriverError;
+use super::virtqueue::Virtqueue;
+
+/// Virtio PCI vendor ID; modern devices have ID 0x1040 plus the device type
+pub const VIRTIO_VENDOR: u16 = 0x1AF4;
+pub const VIRTIO_MODERN_DEVICE_BASE: u16 = 0x1040;
+
+/// `cfg_type` of the vendor capabilities
+const CAPABILITY_COMMON: u8 = 1;
+const CAPABILITY_NOTIFY: u8 = 2;
+const CAPABILITY_DEVICE: u8 = 4;
+
+/// Common configuration registers
+const DEVICE_FEATURE_SELECT: usize = 0x00;
+const DEVICE_FEATURE: usize = 0x04;
+const DRIVER_FEATURE_SELECT: usize = 0x08;
+const DRIVER_FEATURE: usize = 0x0C;
+const DEVICE_STATUS: usize = 0x14;
+const QUEUE_SELECT: usize = 0x16;
+const QUEUE_SIZE: usize = 0x18;
+const QUEUE_ENABLE: usize = 0x1C;
+const QUEUE_NOTIFY_OFF: usize = 0x1E;
+const QUEUE_DESC: usize = 0x20;
+const QUEUE_DRIVER: usize = 0x28;
+const QUEUE_DEVICE: usize = 0x30;
+
+/// Device status bits
+pub const STATUS_ACKNOWLEDGE: u8 = 1;
+pub const STATUS_DRIVER: u8 = 2;
+pub const STATUS_DRIVER_OK: u8 = 4;
+pub const STATUS_FEATURES_OK: u8 = 8;
+pub const STATUS_FAILED: u8 = 128;
+
+/// The device follows virtio 1.0 rather than the legacy interface
+pub const FEATURE_VERSION_1: u64 = 1 << 32;
+
+/// Register polls before giving up on the device
+const POLL_LIMIT: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VirtioError {
+    /// The device did not respond in time
+    Timeout,
+    /// Out of DMA memory
+    NoMemory,
+    /// The device is legacy only, or refused the features
+    FeaturesRefused,
+    /// The queue does not exist on the device
+    NoQueue,
+    /// The device answered a request with this error response
+    Request(u32),
+}
+
+impl From<VirtioError> for DriverError {
+    fn from(error: VirtioError) -> Self {
+        match error {
+            VirtioError::NoMemory => DriverError::ResourceBusy,
+            VirtioError::Request(_) => DriverError::InvalidRequest,
+            _ => DriverError::InitializationFailed,
+        }
+    }
+}
+
+/// A range of a BAR holding one register structure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Structure {
+    pub bar: u8,
+    pub offset: u32,
+    pub length: u32,
+}
+
+/// Where a device's register structures are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtioCapabilities {
+    pub common: Structure,
+    pub notify: Structure,
+    /// Bytes between the notification addresses of consecutive
+    /// `queue_notify_off` values
+    pub notify_multiplier: u32,
+    pub device: Structure,
+}
+
+impl VirtioCapabilities {
+    /// Read the vendor capabilities of the function at `address`, None if
+    /// it lacks any structure a driver needs
+    ///
+    /// Where a structure is listed more than once the first one is used,
+    /// as the specification asks.
+    pub fn read(config: &mut dyn ConfigSpace, address: PciAddress) -> Option<Self> {
+        let mut common = None;
+        let mut notify = None;
+        let mut device = None;
+        for offset in pci::find_capabilities(config, address, pci::CAPABILITY_VENDOR) {
+            let structure = Structure {
+                bar: config.read_u8(address, offset + 4),
+                offset: config.read_u32(address, offset + 8),
+                length: config.read_u32(address, offset + 12),
+            };
+            match config.read_u8(address, offset + 3) {
+                CAPABILITY_COMMON => {
+                    common.get_or_insert(structure);
+                }
+                CAPABILITY_NOTIFY => {
+                    notify.get_or_insert((structure, config.read_u32(address, offset + 16)));
+                }
+                CAPABILITY_DEVICE => {
+                    device.get_or_insert(structure);
+                }
+                _ => {}
+            }
+        }
+        let (notify, notify_multiplier) = notify?;
+        Some(Self { common: common?, notify, notify_multiplier, device: device? })
+    }
+}
+
+/// A virtio device's register structures
+pub struct VirtioPci {
+    common: Box<dyn Mmio>,
+    notify: Box<dyn Mmio>,
+    notify_multiplier: u32,
+    device: Box<dyn Mmio>,
+    /// Notification offset of each queue set up
+    queue_notify: Vec<(u16, usize)>,
+}
+
+impl VirtioPci {
+    /// Drive a device through its mapped common configuration,
+    /// notification and device configuration structures
+    pub fn new(common: Box<dyn Mmio>, notify: Box<dyn Mmio>, notify_multiplier: u32, device: Box<dyn Mmio>) -> Self {
+        Self { common, notify, notify_multiplier, device, queue_notify: Vec::new() }
+    }
+
+    pub fn status(&mut self) -> u8 {
+        self.common.read_u8(DEVICE_STATUS)
+    }
+
+    fn add_status(&mut self, bits: u8) {
+        let status = self.status();
+        self.common.write_u8(DEVICE_STATUS, status | bits);
+    }
+
+    /// Reset the device, waiting until it is done
+    pub fn reset(&mut self) -> Result<(), VirtioError> {
+        self.queue_notify.clear();
+        self.common.write_u8(DEVICE_STATUS, 0);
+        for _ in 0..POLL_LIMIT {
+            if self.status() == 0 {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(VirtioError::Timeout)
+    }
+
+    /// Acknowledge a reset device and agree on features, taking those of
+    /// `wanted` the device offers
+    ///
+    /// Returns the features agreed on, always including
+    /// `FEATURE_VERSION_1`.
+    pub fn negotiate(&mut self, wanted: u64) -> Result<u64, VirtioError> {
+        self.add_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        let mut offered = 0;
+        for half in 0..2 {
+            self.common.write_u32(DEVICE_FEATURE_SELECT, half);
+            offered |= (self.common.read_u32(DEVICE_FEATURE) as u64) << (half * 32);
+        }
+        if offered & FEATURE_VERSION_1 == 0 {
+            self.fail();
+            return Err(VirtioError::FeaturesRefused);
+        }
+
+        let features = offered & (wanted | FEATURE_VERSION_1);
+        for half in 0..2 {
+            self.common.write_u32(DRIVER_FEATURE_SELECT, half);
+            self.common.write_u32(DRIVER_FEATURE, (features >> (half * 32)) as u32);
+        }
+        self.add_status(STATUS_FEATURES_OK);
+        // The device clears FEATURES_OK again if it cannot work with them
+        if self.status() & STATUS_FEATURES_OK == 0 {
+            self.fail();
+            return Err(VirtioError::FeaturesRefused);
+        }
+        Ok(features)
+    }
+
+    /// Most descriptors queue `index` can have, 0 if there is no such queue
+    pub fn max_queue_size(&mut self, index: u16) -> u16 {
+        self.common.write_u16(QUEUE_SELECT, index);
+        self.common.read_u16(QUEUE_SIZE)
+    }
+
+    /// Give queue `index` to the device as `queue`
+    pub fn setup_queue(&mut self, index: u16, queue: &Virtqueue) -> Result<(), VirtioError> {
+        if self.max_queue_size(index) < queue.size() {
+            return Err(VirtioError::NoQueue);
+        }
+        let (descriptors, available, used) = queue.addresses();
+        self.common.write_u16(QUEUE_SIZE, queue.size());
+        self.write_u64(QUEUE_DESC, descriptors);
+        self.write_u64(QUEUE_DRIVER, available);
+        self.write_u64(QUEUE_DEVICE, used);
+        let notify_offset = self.common.read_u16(QUEUE_NOTIFY_OFF) as usize * self.notify_multiplier as usize;
+        self.common.write_u16(QUEUE_ENABLE, 1);
+        self.queue_notify.retain(|&(queue, _)| queue != index);
+        self.queue_notify.push((index, notify_offset));
+        Ok(())
+    }
+
+    /// 64-bit registers are written as two halves, low first
+    fn write_u64(&mut self, register: usize, value: u64) {
+        self.common.write_u32(register, value as u32);
+        self.common.write_u32(register + 4, (value >> 32) as u32);
+    }
+
+    /// Tell the device queue `index` has new buffers
+    pub fn notify(&mut self, index: u16) {
+        if let Some(&(_, offset)) = self.queue_notify.iter().find(|&&(queue, _)| queue == index) {
+            self.notify.write_u16(offset, index);
+        }
+    }
+
+    /// Finish setting up: the device may use its queues from now on
+    pub fn driver_ok(&mut self) {
+        self.add_status(STATUS_DRIVER_OK);
+    }
+
+    /// Give up on the device
+    pub fn fail(&mut self) {
+        self.add_status(STATUS_FAILED);
+    }
+
+    pub fn read_device_u32(&mut self, offset: usize) -> u32 {
+        self.device.read_u32(offset)
+    }
+
+    pub fn write_device_u32(&mut self, offset: usize, value: u32) {
+        self.device.write_u32(offset, value);
+    }
+}
@@ -0,0 +1,153 @@
+//! Split virtqueues and the memory they live in
+//!
+//! A split virtqueue is three areas in memory the device reaches by DMA:
+//! the descriptor table, the available ring the driver hands descriptor
+//! chains over on, and the used ring the device hands them back on. The
+//! driver waits for each request to complete before making the next, so
+//! a queue never has more than a few descriptors in flight.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{fence, Ordering};
+
+pub use crate::dma::{DmaMemory, DmaRegion, KernelDma, DMA_PAGE_SIZE};
+
+/// Descriptor layout
+const DESCRIPTOR_SIZE: usize = 16;
+pub const DESCRIPTOR_NEXT: u16 = 1 << 0;
+pub const DESCRIPTOR_WRITE: u16 = 1 << 1;
+
+/// Ring headers: flags, then the index of the next entry
+const RING_HEADER: usize = 4;
+const USED_ENTRY_SIZE: usize = 8;
+
+/// Most descriptors a queue is set up with, whatever the device offers
+pub const MAX_QUEUE_SIZE: u16 = 64;
+
+/// A buffer in a request: device address, length and whether the device
+/// writes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u64,
+    pub length: u32,
+    pub device_writes: bool,
+}
+
+/// Where the areas of a queue of `size` descriptors start, and how much
+/// memory they take together
+pub fn queue_layout(size: u16) -> (usize, usize, usize, usize) {
+    let size = size as usize;
+    let available = size * DESCRIPTOR_SIZE;
+    // The used ring is 4-byte aligned
+    let used = (available + RING_HEADER + size * 2 + 2).next_multiple_of(4);
+    let total = used + RING_HEADER + size * USED_ENTRY_SIZE + 2;
+    (0, available, used, total)
+}
+
+/// A split virtqueue
+pub struct Virtqueue {
+    memory: DmaRegion,
+    size: u16,
+    /// Descriptors not in a chain the device holds
+    free: Vec<u16>,
+    /// Next available ring index to fill
+    available_index: u16,
+    /// Next used ring index to read
+    used_index: u16,
+}
+
+impl Virtqueue {
+    /// A queue of `size` descriptors in `memory`, which must be zeroed and
+    /// hold `queue_layout(size)` bytes
+    pub fn new(memory: DmaRegion, size: u16) -> Self {
+        assert!(size.is_power_of_two() && queue_layout(size).3 <= memory.size, "queue does not fit its memory");
+        Self {
+            memory,
+            size,
+            free: (0..size).rev().collect(),
+            available_index: 0,
+            used_index: 0,
+        }
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Device addresses of the descriptor table, available and used rings
+    pub fn addresses(&self) -> (u64, u64, u64) {
+        let (descriptors, available, used, _) = queue_layout(self.size);
+        (
+            self.memory.phys + descriptors as u64,
+            self.memory.phys + available as u64,
+            self.memory.phys + used as u64,
+        )
+    }
+
+    pub fn memory(&self) -> DmaRegion {
+        self.memory
+    }
+
+    /// Hand a chain of `segments` to the device, device-readable ones
+    /// first, returning the head descriptor's index
+    ///
+    /// None when too few descriptors are free.
+    pub fn push(&mut self, segments: &[Segment]) -> Option<u16> {
+        if segments.is_empty() || segments.len() > self.free.len() {
+            return None;
+        }
+        let chain: Vec<u16> = (0..segments.len()).filter_map(|_| self.free.pop()).collect();
+        for (position, (segment, &index)) in segments.iter().zip(&chain).enumerate() {
+            let next = chain.get(position + 1);
+            let mut flags = if segment.device_writes { DESCRIPTOR_WRITE } else { 0 };
+            if next.is_some() {
+                flags |= DESCRIPTOR_NEXT;
+            }
+            let offset = index as usize * DESCRIPTOR_SIZE;
+            self.memory.write_u64(offset, segment.address);
+            self.memory.write_u32(offset + 8, segment.length);
+            self.memory.write_u16(offset + 12, flags);
+            self.memory.write_u16(offset + 14, next.copied().unwrap_or(0));
+        }
+
+        let (_, available, _, _) = queue_layout(self.size);
+        let slot = (self.available_index % self.size) as usize;
+        self.memory.write_u16(available + RING_HEADER + slot * 2, chain[0]);
+        // The device must see the chain before the index that hands it over
+        fence(Ordering::SeqCst);
+        self.available_index = self.available_index.wrapping_add(1);
+        self.memory.write_u16(available + 2, self.available_index);
+        fence(Ordering::SeqCst);
+        Some(chain[0])
+    }
+
+    /// The next chain the device is done with: its head index and the
+    /// bytes the device wrote
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let (_, _, used, _) = queue_layout(self.size);
+        fence(Ordering::SeqCst);
+        if self.memory.read_u16(used + 2) == self.used_index {
+            return None;
+        }
+        let entry = used + RING_HEADER + (self.used_index % self.size) as usize * USED_ENTRY_SIZE;
+        let head = self.memory.read_u32(entry) as u16;
+        let length = self.memory.read_u32(entry + 4);
+        self.used_index = self.used_index.wrapping_add(1);
+
+        // Give the chain's descriptors back; a chain is never longer than
+        // the queue
+        let mut index = head;
+        for _ in 0..self.size {
+            self.free.push(index);
+            let offset = index as usize * DESCRIPTOR_SIZE;
+            if self.memory.read_u16(offset + 12) & DESCRIPTOR_NEXT == 0 {
+                break;
+            }
+            index = self.memory.read_u16(offset + 14);
+        }
+        Some((head, length))
+    }
+
+    pub fn free_descriptors(&self) -> usize {
+        self.free.len()
+    }
+}
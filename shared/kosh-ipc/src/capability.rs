@@ -0,0 +1,254 @@
+//! Granting and revoking kernel capabilities
+//!
+//! A process may hand another process a capability it holds with the
+//! Grant right, or a narrower one: a port or memory range inside its own,
+//! for instance. Whether the new capability carries the Grant right in
+//! turn is up to the granter. The resource is described by a fixed-size
+//! `ResourceDescriptor` the kernel copies in.
+//!
+//! Revoking a capability revokes everything delegated from it, however
+//! many hands it passed through. A process may revoke what it delegated,
+//! directly or further down, and drop its own capabilities.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::IpcError;
+
+/// System call numbers (must match shared/kosh-syscall/src/numbers.rs)
+pub const SYS_GRANT_CAPABILITY: u64 = 60;
+pub const SYS_REVOKE_CAPABILITY: u64 = 61;
+pub const SYS_CHECK_CAPABILITY: u64 = 62;
+pub const SYS_LIST_CAPABILITIES: u64 = 63;
+
+/// `SYS_GRANT_CAPABILITY` flag: give the target the Grant right as well
+pub const GRANT_DELEGATE: u64 = 1 << 0;
+
+/// Kernel capability types, in the order of the kernel's `CapabilityType`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum CapabilityKind {
+    Read = 0,
+    Write = 1,
+    Execute = 2,
+    Create = 3,
+    Delete = 4,
+    SendMessage = 5,
+    ReceiveMessage = 6,
+    SystemCall = 7,
+    DeviceAccess = 8,
+    MemoryManagement = 9,
+    ProcessManagement = 10,
+    FileSystem = 11,
+    Network = 12,
+    Admin = 13,
+}
+
+/// `ResourceDescriptor::kind` values
+pub const RESOURCE_ANY: u32 = 0;
+pub const RESOURCE_PROCESS: u32 = 1;
+pub const RESOURCE_IO_PORTS: u32 = 2;
+pub const RESOURCE_MEMORY_RANGE: u32 = 3;
+pub const RESOURCE_IRQ: u32 = 4;
+pub const RESOURCE_SHARED_MEMORY: u32 = 5;
+/// A file, device or other named resource, listed but never granted
+/// through a descriptor
+pub const RESOURCE_NAMED: u32 = 6;
+pub const RESOURCE_PCI_DEVICE: u32 = 7;
+
+/// Resource a capability applies to, as passed to the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct ResourceDescriptor {
+    pub kind: u32,
+    pub reserved: u32,
+    pub first: u64,
+    pub second: u64,
+}
+
+impl ResourceDescriptor {
+    pub const SIZE: usize = core::mem::size_of::<Self>();
+
+    const fn new(kind: u32, first: u64, second: u64) -> Self {
+        Self { kind, reserved: 0, first, second }
+    }
+
+    /// Every resource of the capability's type
+    pub const fn any() -> Self {
+        Self::new(RESOURCE_ANY, 0, 0)
+    }
+
+    pub const fn process(pid: u32) -> Self {
+        Self::new(RESOURCE_PROCESS, pid as u64, 0)
+    }
+
+    /// I/O ports `start..=end`
+    pub const fn io_ports(start: u16, end: u16) -> Self {
+        Self::new(RESOURCE_IO_PORTS, start as u64, end as u64)
+    }
+
+    /// Physical memory `start..start + size`
+    pub const fn memory_range(start: u64, size: u64) -> Self {
+        Self::new(RESOURCE_MEMORY_RANGE, start, size)
+    }
+
+    /// Interrupt line `line`
+    pub const fn irq(line: u32) -> Self {
+        Self::new(RESOURCE_IRQ, line as u64, 0)
+    }
+
+    /// PCI function `bus:device.function`, which message-signaled
+    /// interrupts are allocated for
+    pub const fn pci_device(bus: u8, device: u8, function: u8) -> Self {
+        Self::new(RESOURCE_PCI_DEVICE, pci_requester_id(bus, device, function) as u64, 0)
+    }
+
+    pub const fn shared_memory(region: u64) -> Self {
+        Self::new(RESOURCE_SHARED_MEMORY, region, 0)
+    }
+
+    /// A resource known by name, which a descriptor cannot carry
+    pub const fn named() -> Self {
+        Self::new(RESOURCE_NAMED, 0, 0)
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.kind.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.reserved.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.first.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.second.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+        Some(Self {
+            kind: u32::from_le_bytes(bytes[0..4].try_into().ok()?),
+            reserved: u32::from_le_bytes(bytes[4..8].try_into().ok()?),
+            first: u64::from_le_bytes(bytes[8..16].try_into().ok()?),
+            second: u64::from_le_bytes(bytes[16..24].try_into().ok()?),
+        })
+    }
+}
+
+/// Requester ID of PCI function `bus:device.function`, the way it tags
+/// the memory writes it makes
+pub const fn pci_requester_id(bus: u8, device: u8, function: u8) -> u16 {
+    (bus as u16) << 8 | ((device & 0x1F) as u16) << 3 | (function & 0x07) as u16
+}
+
+/// `CapabilityRecord::flags` bit: the holder has the Grant right
+pub const RECORD_DELEGATABLE: u32 = 1 << 0;
+
+/// A capability of the caller, as `list` returns it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct CapabilityRecord {
+    pub id: u64,
+    /// `CapabilityKind` value
+    pub kind: u64,
+    pub resource: ResourceDescriptor,
+    /// Process that granted it, 0 if the kernel did
+    pub granter: u32,
+    pub flags: u32,
+}
+
+impl CapabilityRecord {
+    pub const SIZE: usize = core::mem::size_of::<Self>();
+
+    pub fn is_delegatable(&self) -> bool {
+        self.flags & RECORD_DELEGATABLE != 0
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.id.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.kind.to_le_bytes());
+        bytes[16..40].copy_from_slice(&self.resource.to_bytes());
+        bytes[40..44].copy_from_slice(&self.granter.to_le_bytes());
+        bytes[44..48].copy_from_slice(&self.flags.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+        Some(Self {
+            id: u64::from_le_bytes(bytes[0..8].try_into().ok()?),
+            kind: u64::from_le_bytes(bytes[8..16].try_into().ok()?),
+            resource: ResourceDescriptor::from_bytes(&bytes[16..40])?,
+            granter: u32::from_le_bytes(bytes[40..44].try_into().ok()?),
+            flags: u32::from_le_bytes(bytes[44..48].try_into().ok()?),
+        })
+    }
+}
+
+/// Give `target` a `kind` capability on `resource`, returning its ID
+///
+/// Fails with `PermissionDenied` unless the caller holds a capability of
+/// the same kind covering the resource, and with it the Grant right. A
+/// process may always let others message it or its children, with
+/// `SendMessage` or `ReceiveMessage` on `ResourceDescriptor::process`.
+pub fn grant(target: u32, kind: CapabilityKind, resource: &ResourceDescriptor) -> Result<u64, IpcError> {
+    grant_with_flags(target, kind, resource, 0)
+}
+
+/// `grant` with `GRANT_DELEGATE` or other flags
+pub fn grant_with_flags(target: u32, kind: CapabilityKind, resource: &ResourceDescriptor, flags: u64) -> Result<u64, IpcError> {
+    let bytes = resource.to_bytes();
+    syscall(SYS_GRANT_CAPABILITY, target as u64, kind as u64, bytes.as_ptr() as u64, flags)
+}
+
+/// Revoke capability `capability` of `target`, and everything delegated
+/// from it; returns how many capabilities went
+pub fn revoke(target: u32, capability: u64) -> Result<u64, IpcError> {
+    syscall(SYS_REVOKE_CAPABILITY, target as u64, capability, 0, 0)
+}
+
+/// Whether the caller holds a `kind` capability covering `resource`
+pub fn check(kind: CapabilityKind, resource: &ResourceDescriptor) -> Result<bool, IpcError> {
+    let bytes = resource.to_bytes();
+    syscall(SYS_CHECK_CAPABILITY, kind as u64, bytes.as_ptr() as u64, 0, 0).map(|held| held != 0)
+}
+
+/// The caller's capabilities
+pub fn list() -> Result<Vec<CapabilityRecord>, IpcError> {
+    // The set may grow between asking for its size and reading it
+    let mut capacity = syscall(SYS_LIST_CAPABILITIES, 0, 0, 0, 0)? as usize;
+    loop {
+        let mut buffer = vec![0u8; capacity * CapabilityRecord::SIZE];
+        let count = syscall(SYS_LIST_CAPABILITIES, buffer.as_mut_ptr() as u64, capacity as u64, 0, 0)? as usize;
+        if count <= capacity {
+            return Ok(buffer.chunks_exact(CapabilityRecord::SIZE)
+                .take(count)
+                .filter_map(CapabilityRecord::from_bytes)
+                .collect());
+        }
+        capacity = count;
+    }
+}
+
+fn syscall(number: u64, arg0: u64, arg1: u64, arg2: u64, arg3: u64) -> Result<u64, IpcError> {
+    let result: i64;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") number,
+            in("rdi") arg0,
+            in("rsi") arg1,
+            in("rdx") arg2,
+            in("r10") arg3,
+            lateout("rax") result,
+            options(nostack, preserves_flags)
+        );
+    }
+
+    if result < 0 {
+        Err(IpcError::from_errno(result))
+    } else {
+        Ok(result as u64)
+    }
+}
@@ -0,0 +1,170 @@
+//! Interrupt delivery to userspace drivers
+//!
+//! A driver claims an IRQ line, then waits for it. The kernel masks the
+//! line each time it fires and keeps it masked until the driver has
+//! serviced the device and acknowledged the interrupt, so a level-triggered
+//! device cannot storm the CPU before its driver gets to run.
+//!
+//! Devices that signal interrupts with a memory write (MSI and MSI-X)
+//! get lines of their own instead: `allocate_msi` hands a PCI function a
+//! block of lines, claimed by the caller, and the address and data the
+//! function must write to raise them. The driver programs those into the
+//! device's capability structure and waits on the lines like on any
+//! other. Such lines are never masked by the kernel; the device masks
+//! them itself until the driver acknowledges.
+
+use crate::IpcError;
+
+/// System call numbers (must match shared/kosh-syscall/src/numbers.rs)
+pub const SYS_IRQ_REGISTER: u64 = 45;
+pub const SYS_IRQ_WAIT: u64 = 46;
+pub const SYS_IRQ_ACK: u64 = 47;
+pub const SYS_IRQ_UNREGISTER: u64 = 48;
+pub const SYS_IRQ_ALLOC_MSI: u64 = 75;
+
+/// Most message-signaled lines one allocation may ask for
+pub const MAX_MSI_VECTORS: u32 = 32;
+
+/// Registration flag: also queue an `IrqNotification` message from the
+/// kernel each time the line fires, for drivers that sleep in `poll`
+pub const IRQ_NOTIFY_MESSAGE: u64 = 1 << 0;
+
+/// Wait flag: return 0 instead of blocking when nothing is pending
+pub const IRQ_WAIT_NOHANG: u64 = 1 << 0;
+
+/// Message the kernel queues for `IRQ_NOTIFY_MESSAGE` registrations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqNotification {
+    pub line: u32,
+}
+
+impl IrqNotification {
+    const MAGIC: [u8; 4] = *b"IRQ\0";
+
+    pub const ENCODED_SIZE: usize = 8;
+
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_SIZE] {
+        let mut bytes = [0u8; Self::ENCODED_SIZE];
+        bytes[..4].copy_from_slice(&Self::MAGIC);
+        bytes[4..].copy_from_slice(&self.line.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::ENCODED_SIZE || bytes[..4] != Self::MAGIC {
+            return None;
+        }
+        Some(Self { line: u32::from_le_bytes(bytes[4..].try_into().ok()?) })
+    }
+}
+
+/// A block of message-signaled lines, as `allocate_msi` returns it
+///
+/// Vector `n` of the function is line `first_line + n`, and is raised by
+/// writing `data + n` to `address`. MSI uses the consecutive data values
+/// as they are; each MSI-X table entry gets its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+pub struct MsiAllocation {
+    pub first_line: u32,
+    pub count: u32,
+    pub address: u64,
+    pub data: u32,
+    pub reserved: u32,
+}
+
+impl MsiAllocation {
+    /// Line of vector `vector`, if the block has it
+    pub fn line(&self, vector: u32) -> Option<u32> {
+        (vector < self.count).then(|| self.first_line + vector)
+    }
+
+    /// Message data raising vector `vector`
+    pub fn data_for(&self, vector: u32) -> u32 {
+        self.data + vector
+    }
+}
+
+fn irq_syscall(number: u64, line: u32, flags: u64) -> Result<u64, IpcError> {
+    let result: i64;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") number,
+            in("rdi") line as u64,
+            in("rsi") flags,
+            lateout("rax") result,
+            options(nostack, preserves_flags)
+        );
+    }
+
+    if result < 0 {
+        Err(IpcError::from_errno(result))
+    } else {
+        Ok(result as u64)
+    }
+}
+
+/// Claim `line` for this process; needs device access to `irq:<line>`
+pub fn register(line: u32, flags: u64) -> Result<(), IpcError> {
+    irq_syscall(SYS_IRQ_REGISTER, line, flags).map(|_| ())
+}
+
+/// Give `line` back; it is left masked
+pub fn unregister(line: u32) -> Result<(), IpcError> {
+    irq_syscall(SYS_IRQ_UNREGISTER, line, 0).map(|_| ())
+}
+
+/// Wait until `line` has fired, returning how many times it did since the
+/// last wait
+///
+/// With `IRQ_WAIT_NOHANG`, returns 0 instead of blocking.
+pub fn wait(line: u32, flags: u64) -> Result<u32, IpcError> {
+    loop {
+        match irq_syscall(SYS_IRQ_WAIT, line, flags) {
+            Ok(count) => return Ok(count as u32),
+            // The kernel blocked us; once rescheduled the call is repeated
+            // to collect the interrupts that woke us up
+            Err(IpcError::WouldBlock) => continue,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Unmask `line` once the device has been serviced
+pub fn acknowledge(line: u32) -> Result<(), IpcError> {
+    irq_syscall(SYS_IRQ_ACK, line, 0).map(|_| ())
+}
+
+/// Allocate and claim `count` message-signaled lines for PCI function
+/// `bus:device.function`, with the `register` flags
+///
+/// `count` is a power of two up to `MAX_MSI_VECTORS`, since MSI can only
+/// ask for such blocks. Needs device access to the function, as
+/// `ResourceDescriptor::pci_device` names it. Fails where the platform
+/// has no message-signaled interrupts, and with `ChannelFull` when no
+/// block of `count` lines is free. The lines go back with `unregister`,
+/// one by one.
+pub fn allocate_msi(bus: u8, device: u8, function: u8, count: u32, flags: u64) -> Result<MsiAllocation, IpcError> {
+    let mut allocation = MsiAllocation::default();
+    let requester = crate::capability::pci_requester_id(bus, device, function);
+    let result: i64;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") SYS_IRQ_ALLOC_MSI,
+            in("rdi") requester as u64,
+            in("rsi") count as u64,
+            in("rdx") flags,
+            in("r10") &mut allocation as *mut MsiAllocation as u64,
+            lateout("rax") result,
+            options(nostack, preserves_flags)
+        );
+    }
+
+    if result < 0 {
+        Err(IpcError::from_errno(result))
+    } else {
+        Ok(allocation)
+    }
+}
@@ -1,35 +1,139 @@
 #![no_std]
 
+extern crate alloc;
+
+use alloc::vec::Vec;
 use kosh_types::{ProcessId, MessageType, Capability};
 
-#[derive(Debug)]
+pub mod syscall;
+pub mod shm;
+pub mod poll;
+pub mod irq;
+pub mod capability;
+
+/// Payloads at or above this size should travel as a shared memory grant
+/// instead of being copied through the kernel message queue
+pub const ZERO_COPY_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Clone)]
 pub struct Message {
     pub sender: ProcessId,
     pub receiver: ProcessId,
     pub message_type: MessageType,
     pub data: MessageData,
-    pub capabilities: &'static [Capability],
+    pub capabilities: Vec<Capability>,
 }
 
-#[derive(Debug)]
+impl Message {
+    pub fn new(sender: ProcessId, receiver: ProcessId, message_type: MessageType, data: MessageData) -> Self {
+        Self {
+            sender,
+            receiver,
+            message_type,
+            data,
+            capabilities: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum MessageData {
     Empty,
-    Bytes(&'static [u8]),
+    /// Payload owned by the message and copied through the kernel queue
+    Bytes(Vec<u8>),
+    /// Payload left in a shared memory region granted to the receiver
+    Shared(SharedBuffer),
     SystemCall(SystemCallData),
     DriverRequest(DriverRequestData),
 }
 
-#[derive(Debug)]
+impl MessageData {
+    /// Number of payload bytes carried by this message, including bytes
+    /// that live in a shared memory grant
+    pub fn payload_len(&self) -> usize {
+        match self {
+            MessageData::Empty | MessageData::SystemCall(_) => 0,
+            MessageData::Bytes(bytes) => bytes.len(),
+            MessageData::Shared(buffer) => buffer.length,
+            MessageData::DriverRequest(request) => request.data.len(),
+        }
+    }
+
+    /// Inline payload bytes, if the payload was copied into the message
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            MessageData::Bytes(bytes) => Some(bytes),
+            MessageData::DriverRequest(request) => Some(&request.data),
+            _ => None,
+        }
+    }
+
+    /// Check whether the payload is passed by reference rather than copied
+    pub fn is_zero_copy(&self) -> bool {
+        matches!(self, MessageData::Shared(_))
+    }
+}
+
+/// Reference to a payload inside a shared memory region
+///
+/// The sender writes the payload into the region and grants it to the
+/// receiver; only this descriptor is copied through the message queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SharedBuffer {
+    /// Shared memory region identifier returned by the kernel
+    pub region_id: u64,
+    /// Offset of the payload within the region
+    pub offset: usize,
+    /// Length of the payload in bytes
+    pub length: usize,
+}
+
+impl SharedBuffer {
+    /// Size of the encoded descriptor in bytes
+    pub const ENCODED_SIZE: usize = 24;
+
+    pub fn new(region_id: u64, offset: usize, length: usize) -> Self {
+        Self { region_id, offset, length }
+    }
+
+    /// Encode the descriptor as little-endian region id, offset and length
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_SIZE] {
+        let mut bytes = [0u8; Self::ENCODED_SIZE];
+        bytes[0..8].copy_from_slice(&self.region_id.to_le_bytes());
+        bytes[8..16].copy_from_slice(&(self.offset as u64).to_le_bytes());
+        bytes[16..24].copy_from_slice(&(self.length as u64).to_le_bytes());
+        bytes
+    }
+
+    /// Decode a descriptor produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::ENCODED_SIZE {
+            return None;
+        }
+
+        let mut field = [0u8; 8];
+        field.copy_from_slice(&bytes[0..8]);
+        let region_id = u64::from_le_bytes(field);
+        field.copy_from_slice(&bytes[8..16]);
+        let offset = u64::from_le_bytes(field) as usize;
+        field.copy_from_slice(&bytes[16..24]);
+        let length = u64::from_le_bytes(field) as usize;
+
+        Some(Self { region_id, offset, length })
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct SystemCallData {
     pub call_number: u64,
     pub args: [u64; 6],
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DriverRequestData {
     pub driver_id: u32,
     pub request_type: u32,
-    pub data: &'static [u8],
+    pub data: Vec<u8>,
 }
 
 pub trait IpcChannel {
@@ -44,4 +148,23 @@ pub enum IpcError {
     ChannelFull,
     PermissionDenied,
     Timeout,
+    WouldBlock,
+    UnsupportedPayload,
+    OutOfMemory,
+}
+
+impl IpcError {
+    /// Map a negative errno returned by an IPC system call to an IpcError
+    pub fn from_errno(errno: i64) -> Self {
+        match errno {
+            -2 | -3 => IpcError::InvalidReceiver,   // ENOENT, ESRCH
+            -13 => IpcError::PermissionDenied,      // EACCES
+            -11 => IpcError::WouldBlock,            // EAGAIN
+            -12 => IpcError::OutOfMemory,           // ENOMEM
+            -90 | -22 => IpcError::MessageTooLarge, // EMSGSIZE, EINVAL
+            -105 => IpcError::ChannelFull,          // ENOBUFS
+            -110 => IpcError::Timeout,              // ETIMEDOUT
+            _ => IpcError::PermissionDenied,
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,101 @@
+//! Event notification for multiplexing many IPC channels
+//!
+//! Services describe the channels they are interested in with a set of
+//! `PollEntry` values and sleep in the kernel until one of them has a
+//! message waiting or the timeout expires.
+
+use kosh_types::ProcessId;
+use crate::IpcError;
+
+/// System call number (must match shared/kosh-syscall/src/numbers.rs)
+pub const SYS_POLL: u64 = 39;
+
+/// A message is waiting to be received
+pub const POLL_IN: u32 = 0x1;
+
+/// Handle that matches messages from any sender
+pub const ANY_SENDER: u64 = 0;
+
+/// Wait until an event arrives
+pub const INFINITE: u64 = u64::MAX;
+
+/// Largest poll set the kernel accepts
+pub const MAX_POLL_ENTRIES: usize = 64;
+
+/// One channel in a poll set (layout shared with the kernel)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct PollEntry {
+    pub handle: u64,
+    pub events: u32,
+    pub revents: u32,
+}
+
+impl PollEntry {
+    /// Watch for messages from a specific process
+    pub fn from_sender(sender: ProcessId) -> Self {
+        Self {
+            handle: sender as u64,
+            events: POLL_IN,
+            revents: 0,
+        }
+    }
+
+    /// Watch for messages from anyone
+    pub fn any_sender() -> Self {
+        Self {
+            handle: ANY_SENDER,
+            events: POLL_IN,
+            revents: 0,
+        }
+    }
+
+    /// Check whether a message is waiting on this channel
+    pub fn is_readable(&self) -> bool {
+        self.revents & POLL_IN != 0
+    }
+}
+
+/// Wait for events on a set of channels
+///
+/// Returns the number of ready entries, or 0 when the timeout expired.
+/// `timeout_ms` of 0 checks without waiting; `INFINITE` never times out.
+pub fn poll(entries: &mut [PollEntry], timeout_ms: u64) -> Result<usize, IpcError> {
+    if entries.is_empty() || entries.len() > MAX_POLL_ENTRIES {
+        return Err(IpcError::MessageTooLarge);
+    }
+
+    loop {
+        let result: i64;
+        unsafe {
+            core::arch::asm!(
+                "int 0x80",
+                in("rax") SYS_POLL,
+                in("rdi") entries.as_mut_ptr(),
+                in("rsi") entries.len(),
+                in("rdx") timeout_ms,
+                lateout("rax") result,
+                options(nostack, preserves_flags)
+            );
+        }
+
+        if result >= 0 {
+            return Ok(result as usize);
+        }
+
+        // The kernel blocked us; once rescheduled the call is repeated to
+        // collect the events that woke us up
+        match IpcError::from_errno(result) {
+            IpcError::WouldBlock => continue,
+            error => return Err(error),
+        }
+    }
+}
+
+/// Wait until any message is queued for this process
+///
+/// Returns false when the timeout expired first.
+pub fn wait_for_message(timeout_ms: u64) -> Result<bool, IpcError> {
+    let mut entries = [PollEntry::any_sender()];
+    Ok(poll(&mut entries, timeout_ms)? > 0)
+}
@@ -0,0 +1,140 @@
+//! Shared memory system call wrappers
+//!
+//! A region is created by one process, granted to its peers and mapped by
+//! each of them; bulk data then moves through the mapping while only a
+//! `SharedBuffer` descriptor travels through the message queue.
+
+use kosh_types::ProcessId;
+use crate::{IpcError, SharedBuffer};
+
+/// System call numbers (must match shared/kosh-syscall/src/numbers.rs)
+pub const SYS_SHM_CREATE: u64 = 35;
+pub const SYS_SHM_MAP: u64 = 36;
+pub const SYS_SHM_UNMAP: u64 = 37;
+pub const SYS_SHM_GRANT: u64 = 38;
+
+/// Mapping protection flags
+pub const PROT_READ: u64 = 0x1;
+pub const PROT_WRITE: u64 = 0x2;
+
+/// Largest region the kernel will create
+pub const MAX_REGION_SIZE: usize = 64 * 1024 * 1024;
+
+/// A shared memory region mapped into this process
+///
+/// The mapping is removed when the value is dropped.
+#[derive(Debug)]
+pub struct SharedRegion {
+    id: u64,
+    address: *mut u8,
+    size: usize,
+    writable: bool,
+}
+
+impl SharedRegion {
+    /// Create a new region of at least `size` bytes and map it read-write
+    pub fn create(size: usize) -> Result<Self, IpcError> {
+        if size == 0 || size > MAX_REGION_SIZE {
+            return Err(IpcError::MessageTooLarge);
+        }
+
+        let id = syscall(SYS_SHM_CREATE, size as u64, 0, 0)?;
+        Self::map(id, size, true)
+    }
+
+    /// Map a region another process granted to us
+    pub fn open(buffer: &SharedBuffer, writable: bool) -> Result<Self, IpcError> {
+        Self::map(buffer.region_id, buffer.offset + buffer.length, writable)
+    }
+
+    fn map(id: u64, size: usize, writable: bool) -> Result<Self, IpcError> {
+        let prot = if writable { PROT_READ | PROT_WRITE } else { PROT_READ };
+        let address = syscall(SYS_SHM_MAP, id, prot, 0)?;
+
+        Ok(Self {
+            id,
+            address: address as *mut u8,
+            size,
+            writable,
+        })
+    }
+
+    /// Region identifier assigned by the kernel
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Usable size of the mapping in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Allow `target` to map this region
+    pub fn grant(&self, target: ProcessId, writable: bool) -> Result<(), IpcError> {
+        grant(self.id, target, writable)
+    }
+
+    /// Describe `length` bytes starting at `offset` for sending to a peer
+    pub fn buffer(&self, offset: usize, length: usize) -> Option<SharedBuffer> {
+        if offset.checked_add(length)? > self.size {
+            return None;
+        }
+        Some(SharedBuffer::new(self.id, offset, length))
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.address, self.size) }
+    }
+
+    /// Writable view of the mapping, if it was mapped writable
+    pub fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        if !self.writable {
+            return None;
+        }
+        Some(unsafe { core::slice::from_raw_parts_mut(self.address, self.size) })
+    }
+
+    /// Bytes described by a `SharedBuffer` that refers to this region
+    pub fn slice_of(&self, buffer: &SharedBuffer) -> Option<&[u8]> {
+        if buffer.region_id != self.id {
+            return None;
+        }
+        let end = buffer.offset.checked_add(buffer.length)?;
+        self.as_slice().get(buffer.offset..end)
+    }
+}
+
+// The mapping is valid process-wide, so the handle may move between threads
+unsafe impl Send for SharedRegion {}
+
+impl Drop for SharedRegion {
+    fn drop(&mut self) {
+        let _ = syscall(SYS_SHM_UNMAP, self.id, 0, 0);
+    }
+}
+
+/// Allow `target` to map region `region_id`; only the creator may grant
+pub fn grant(region_id: u64, target: ProcessId, writable: bool) -> Result<(), IpcError> {
+    syscall(SYS_SHM_GRANT, region_id, target as u64, writable as u64).map(|_| ())
+}
+
+fn syscall(number: u64, arg0: u64, arg1: u64, arg2: u64) -> Result<u64, IpcError> {
+    let result: i64;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") number,
+            in("rdi") arg0,
+            in("rsi") arg1,
+            in("rdx") arg2,
+            lateout("rax") result,
+            options(nostack, preserves_flags)
+        );
+    }
+
+    if result < 0 {
+        Err(IpcError::from_errno(result))
+    } else {
+        Ok(result as u64)
+    }
+}
@@ -0,0 +1,117 @@
+//! Raw IPC system call wrappers used by userspace services and drivers
+
+use kosh_types::ProcessId;
+use crate::{IpcError, MessageData, SharedBuffer};
+
+/// System call numbers (must match shared/kosh-syscall/src/numbers.rs)
+pub const SYS_SEND_MESSAGE: u64 = 30;
+pub const SYS_RECEIVE_MESSAGE: u64 = 31;
+
+/// Largest payload the kernel accepts in a single message
+pub const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// Message flag: the payload is a `SharedBuffer` descriptor
+pub const FLAG_SHARED: u64 = 1 << 0;
+
+/// Send a byte payload to another process
+pub fn send_message(receiver: ProcessId, data: &[u8]) -> Result<(), IpcError> {
+    send_raw(receiver, data, 0)
+}
+
+/// Send a payload that lives in a shared memory region granted to the receiver
+///
+/// Only the descriptor is copied through the kernel, so the payload may be
+/// larger than `MAX_MESSAGE_SIZE`.
+pub fn send_shared(receiver: ProcessId, buffer: SharedBuffer) -> Result<(), IpcError> {
+    send_raw(receiver, &buffer.to_bytes(), FLAG_SHARED)
+}
+
+/// Send owned message data, choosing the copy or zero-copy path
+pub fn send(receiver: ProcessId, data: &MessageData) -> Result<(), IpcError> {
+    match data {
+        MessageData::Empty => send_message(receiver, &[]),
+        MessageData::Shared(buffer) => send_shared(receiver, *buffer),
+        other => match other.as_bytes() {
+            Some(bytes) => send_message(receiver, bytes),
+            None => Err(IpcError::UnsupportedPayload),
+        },
+    }
+}
+
+fn send_raw(receiver: ProcessId, data: &[u8], flags: u64) -> Result<(), IpcError> {
+    if data.len() > MAX_MESSAGE_SIZE {
+        return Err(IpcError::MessageTooLarge);
+    }
+
+    let result: i64;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") SYS_SEND_MESSAGE,
+            in("rdi") receiver as u64,
+            in("rsi") data.as_ptr(),
+            in("rdx") data.len(),
+            in("r10") flags,
+            lateout("rax") result,
+            options(nostack, preserves_flags)
+        );
+    }
+
+    if result < 0 {
+        Err(IpcError::from_errno(result))
+    } else {
+        Ok(())
+    }
+}
+
+/// Receive the next pending message into `buffer` (non-blocking)
+///
+/// Returns the sender and the number of bytes written to `buffer`.
+pub fn receive_message(buffer: &mut [u8]) -> Result<(ProcessId, usize), IpcError> {
+    let (sender, length, _flags) = receive_raw(buffer)?;
+    Ok((sender, length))
+}
+
+/// Receive the next pending message as owned data (non-blocking)
+///
+/// Inline payloads are copied out of `buffer`; shared memory grants are
+/// returned as a `SharedBuffer` descriptor without touching the payload.
+pub fn receive(buffer: &mut [u8]) -> Result<(ProcessId, MessageData), IpcError> {
+    let (sender, length, flags) = receive_raw(buffer)?;
+
+    let data = if flags & FLAG_SHARED != 0 {
+        let descriptor = SharedBuffer::from_bytes(&buffer[..length])
+            .ok_or(IpcError::UnsupportedPayload)?;
+        MessageData::Shared(descriptor)
+    } else if length == 0 {
+        MessageData::Empty
+    } else {
+        MessageData::Bytes(buffer[..length].to_vec())
+    };
+
+    Ok((sender, data))
+}
+
+fn receive_raw(buffer: &mut [u8]) -> Result<(ProcessId, usize, u64), IpcError> {
+    let mut sender: u64 = 0;
+    let mut flags: u64 = 0;
+    let result: i64;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") SYS_RECEIVE_MESSAGE,
+            in("rdi") buffer.as_mut_ptr(),
+            in("rsi") buffer.len(),
+            in("rdx") &mut sender as *mut u64,
+            in("r10") &mut flags as *mut u64,
+            lateout("rax") result,
+            options(nostack, preserves_flags)
+        );
+    }
+
+    if result < 0 {
+        Err(IpcError::from_errno(result))
+    } else {
+        Ok((sender as ProcessId, result as usize, flags))
+    }
+}
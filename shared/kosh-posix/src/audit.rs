@@ -0,0 +1,77 @@
+//! Security audit log
+//!
+//! The kernel records capability checks that failed, system calls refused
+//! for lack of permission, drivers loading and unloading, and processes
+//! killed by a signal, keeping the newest `AUDIT_CAPACITY` records.
+//! Reading them needs read access to the "audit" system resource.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::errno::Errno;
+use crate::raw::{syscall3, SYS_AUDIT};
+
+/// Records the kernel keeps
+pub const AUDIT_CAPACITY: usize = 256;
+
+/// Bytes of text a record carries
+pub const DETAIL_LEN: usize = 48;
+
+/// `AuditRecord::kind` values
+pub const AUDIT_CAPABILITY_DENIED: u32 = 1;
+pub const AUDIT_SYSCALL_DENIED: u32 = 2;
+pub const AUDIT_DRIVER_LOADED: u32 = 3;
+pub const AUDIT_DRIVER_UNLOADED: u32 = 4;
+pub const AUDIT_PROCESS_KILLED: u32 = 5;
+
+/// One security-relevant event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct AuditRecord {
+    /// Counts up from 1 since boot; a gap means records were dropped
+    pub sequence: u64,
+    /// Milliseconds since boot
+    pub time_ms: u64,
+    pub kind: u32,
+    /// Process the event concerns
+    pub process: u32,
+    /// Capability type, system call number, driver domain or signal
+    pub value: u64,
+    /// Capability and resource, or system call name; UTF-8, padded with NULs
+    pub detail: [u8; DETAIL_LEN],
+}
+
+impl Default for AuditRecord {
+    fn default() -> Self {
+        Self { sequence: 0, time_ms: 0, kind: 0, process: 0, value: 0, detail: [0; DETAIL_LEN] }
+    }
+}
+
+impl AuditRecord {
+    pub fn detail(&self) -> &str {
+        let length = self.detail.iter().position(|&byte| byte == 0).unwrap_or(DETAIL_LEN);
+        core::str::from_utf8(&self.detail[..length]).unwrap_or("?")
+    }
+
+    /// Name of the kind of event, as `audit` shows it
+    pub fn kind_name(&self) -> &'static str {
+        match self.kind {
+            AUDIT_CAPABILITY_DENIED => "capability-denied",
+            AUDIT_SYSCALL_DENIED => "syscall-denied",
+            AUDIT_DRIVER_LOADED => "driver-loaded",
+            AUDIT_DRIVER_UNLOADED => "driver-unloaded",
+            AUDIT_PROCESS_KILLED => "killed",
+            _ => "unknown",
+        }
+    }
+}
+
+/// Audit records numbered after `since`, oldest first
+///
+/// Pass 0 for everything the kernel still holds. Fails with EPERM without
+/// read access to the "audit" resource.
+pub fn audit_read(since: u64) -> Result<Vec<AuditRecord>, Errno> {
+    let mut records = vec![AuditRecord::default(); AUDIT_CAPACITY];
+    let count = Errno::result(syscall3(SYS_AUDIT, records.as_mut_ptr() as u64, AUDIT_CAPACITY as u64, since))? as usize;
+    records.truncate(count);
+    Ok(records)
+}
@@ -0,0 +1,73 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use kosh_service::{FileSystemRequest, ServiceData};
+use crate::errno::{Errno, EIO};
+use crate::raw::c_path;
+use crate::service::fs_request;
+
+/// `d_type` values
+pub const DT_UNKNOWN: u8 = 0;
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+
+/// One directory entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dirent {
+    pub d_name: String,
+    pub d_type: u8,
+}
+
+/// Open directory stream
+///
+/// The whole listing is fetched when the directory is opened, so entries
+/// created afterwards are not seen.
+#[derive(Debug)]
+pub struct Dir {
+    entries: Vec<Dirent>,
+    position: usize,
+}
+
+impl Dir {
+    /// Rewind to the first entry
+    pub fn rewind(&mut self) {
+        self.position = 0;
+    }
+}
+
+/// Open a directory for reading
+pub fn opendir(path: &str) -> Result<Dir, Errno> {
+    c_path(path)?;
+    let response = fs_request(FileSystemRequest::List { path: String::from(path) })?;
+    let listing = match response.data {
+        ServiceData::Text(listing) => listing,
+        _ => return Err(EIO),
+    };
+    Ok(Dir { entries: parse_listing(&listing), position: 0 })
+}
+
+/// Next entry, or `None` at the end of the directory
+pub fn readdir(dir: &mut Dir) -> Option<&Dirent> {
+    let entry = dir.entries.get(dir.position)?;
+    dir.position += 1;
+    Some(entry)
+}
+
+/// Close a directory stream
+pub fn closedir(dir: Dir) {
+    drop(dir);
+}
+
+/// Parse the service's listing: a header line, then one indented name per
+/// line with directories marked by a trailing '/'
+fn parse_listing(listing: &str) -> Vec<Dirent> {
+    listing
+        .lines()
+        .skip(1)
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| match line.strip_suffix('/') {
+            Some(name) => Dirent { d_name: String::from(name), d_type: DT_DIR },
+            None => Dirent { d_name: String::from(line), d_type: DT_REG },
+        })
+        .collect()
+}
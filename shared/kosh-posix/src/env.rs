@@ -0,0 +1,62 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::errno::{Errno, EIO};
+use crate::raw::{syscall3, SYS_GETARGS};
+
+/// Arguments and environment a process was started with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessArgs {
+    pub argv: Vec<String>,
+    /// `NAME=value` strings
+    pub envp: Vec<String>,
+}
+
+/// Fetch the caller's arguments and environment from the kernel
+pub fn process_args() -> Result<ProcessArgs, Errno> {
+    let size = Errno::result(syscall3(SYS_GETARGS, 0, 0, 0))? as usize;
+    let mut block = vec![0u8; size];
+    let copied = Errno::result(syscall3(SYS_GETARGS, block.as_mut_ptr() as u64, size as u64, 0))? as usize;
+    // The block only changes on exec, so the size holds
+    if copied != size {
+        return Err(EIO);
+    }
+    decode(&block).ok_or(EIO)
+}
+
+/// Argument vector of the caller
+pub fn argv() -> Result<Vec<String>, Errno> {
+    process_args().map(|args| args.argv)
+}
+
+/// Environment of the caller as `NAME=value` strings
+pub fn environ() -> Result<Vec<String>, Errno> {
+    process_args().map(|args| args.envp)
+}
+
+/// Value of environment variable `name`
+///
+/// Looked up in the block `process_args` returns; the kernel has no
+/// per-variable call.
+pub fn getenv(name: &str) -> Option<String> {
+    environ().ok()?.into_iter().find_map(|entry| {
+        let (key, value) = entry.split_once('=')?;
+        (key == name).then(|| String::from(value))
+    })
+}
+
+/// Parse the kernel's block: argument and environment counts as
+/// little-endian u32, then every string NUL-terminated
+fn decode(block: &[u8]) -> Option<ProcessArgs> {
+    let argc = u32::from_le_bytes(block.get(0..4)?.try_into().ok()?) as usize;
+    let envc = u32::from_le_bytes(block.get(4..8)?.try_into().ok()?) as usize;
+    let mut strings = block[8..].split(|&byte| byte == 0);
+    let mut take = |count: usize| -> Option<Vec<String>> {
+        (0..count)
+            .map(|_| strings.next().and_then(|bytes| String::from_utf8(bytes.to_vec()).ok()))
+            .collect()
+    };
+    let argv = take(argc)?;
+    let envp = take(envc)?;
+    Some(ProcessArgs { argv, envp })
+}
@@ -0,0 +1,97 @@
+use core::fmt;
+
+/// Error number returned by a failed call
+///
+/// Values match the negated codes returned by the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Errno(pub i32);
+
+pub const EPERM: Errno = Errno(1);
+pub const ENOENT: Errno = Errno(2);
+pub const ESRCH: Errno = Errno(3);
+pub const EINTR: Errno = Errno(4);
+pub const EIO: Errno = Errno(5);
+pub const E2BIG: Errno = Errno(7);
+pub const ENOEXEC: Errno = Errno(8);
+pub const EBADF: Errno = Errno(9);
+pub const ECHILD: Errno = Errno(10);
+pub const EAGAIN: Errno = Errno(11);
+pub const ENOMEM: Errno = Errno(12);
+pub const EACCES: Errno = Errno(13);
+pub const EFAULT: Errno = Errno(14);
+pub const EEXIST: Errno = Errno(17);
+pub const ENOTDIR: Errno = Errno(20);
+pub const EINVAL: Errno = Errno(22);
+pub const EMFILE: Errno = Errno(24);
+pub const ESPIPE: Errno = Errno(29);
+pub const EPIPE: Errno = Errno(32);
+pub const ENAMETOOLONG: Errno = Errno(36);
+pub const ENOSYS: Errno = Errno(38);
+pub const EOPNOTSUPP: Errno = Errno(95);
+pub const ENOBUFS: Errno = Errno(105);
+pub const ETIMEDOUT: Errno = Errno(110);
+pub const ECONNREFUSED: Errno = Errno(111);
+
+impl Errno {
+    /// Convert a raw system call return value into a result
+    pub fn result(ret: i64) -> Result<u64, Errno> {
+        if ret < 0 {
+            Err(Errno(-ret as i32))
+        } else {
+            Ok(ret as u64)
+        }
+    }
+
+    /// Short description, as strerror would give
+    pub fn description(self) -> &'static str {
+        match self {
+            EPERM => "Operation not permitted",
+            ENOENT => "No such file or directory",
+            ESRCH => "No such process",
+            EINTR => "Interrupted system call",
+            EIO => "Input/output error",
+            E2BIG => "Argument list too long",
+            ENOEXEC => "Exec format error",
+            EBADF => "Bad file descriptor",
+            ECHILD => "No child processes",
+            EAGAIN => "Resource temporarily unavailable",
+            ENOMEM => "Cannot allocate memory",
+            EACCES => "Permission denied",
+            EFAULT => "Bad address",
+            EEXIST => "File exists",
+            ENOTDIR => "Not a directory",
+            EINVAL => "Invalid argument",
+            EMFILE => "Too many open files",
+            ESPIPE => "Illegal seek",
+            EPIPE => "Broken pipe",
+            ENAMETOOLONG => "File name too long",
+            ENOSYS => "Function not implemented",
+            EOPNOTSUPP => "Operation not supported",
+            ENOBUFS => "No buffer space available",
+            ETIMEDOUT => "Connection timed out",
+            ECONNREFUSED => "Connection refused",
+            _ => "Unknown error",
+        }
+    }
+}
+
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (errno {})", self.description(), self.0)
+    }
+}
+
+impl From<kosh_service::ServiceError> for Errno {
+    fn from(error: kosh_service::ServiceError) -> Self {
+        match error {
+            kosh_service::ServiceError::NotFound => ECONNREFUSED,
+            kosh_service::ServiceError::PermissionDenied => EACCES,
+            kosh_service::ServiceError::InvalidRequest => EINVAL,
+            kosh_service::ServiceError::Timeout => ETIMEDOUT,
+            kosh_service::ServiceError::WouldBlock => EAGAIN,
+            kosh_service::ServiceError::NotImplemented => ENOSYS,
+            kosh_service::ServiceError::CommunicationError
+            | kosh_service::ServiceError::MalformedMessage => EIO,
+        }
+    }
+}
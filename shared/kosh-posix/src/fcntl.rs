@@ -0,0 +1,28 @@
+use kosh_types::OpenFlags;
+use crate::errno::Errno;
+use crate::raw::{blocking_syscall3, c_path, SYS_OPEN};
+use crate::unistd::Fd;
+
+/// Open flags (values of `kosh_types::OpenFlags`)
+pub const O_RDONLY: i32 = OpenFlags::READ_ONLY.bits() as i32;
+pub const O_WRONLY: i32 = OpenFlags::WRITE_ONLY.bits() as i32;
+pub const O_RDWR: i32 = OpenFlags::READ_WRITE.bits() as i32;
+pub const O_CREAT: i32 = OpenFlags::CREATE.bits() as i32;
+pub const O_EXCL: i32 = OpenFlags::EXCLUSIVE.bits() as i32;
+pub const O_TRUNC: i32 = OpenFlags::TRUNCATE.bits() as i32;
+pub const O_APPEND: i32 = OpenFlags::APPEND.bits() as i32;
+
+/// Open a file, returning the lowest free descriptor
+///
+/// `mode` is accepted for compatibility; the file system service currently
+/// creates files owner read/write regardless.
+pub fn open(path: &str, flags: i32, mode: u32) -> Result<Fd, Errno> {
+    let path = c_path(path)?;
+    let fd = blocking_syscall3(SYS_OPEN, path.as_ptr() as u64, flags as u32 as u64, mode as u64)?;
+    Ok(fd as Fd)
+}
+
+/// Create or truncate a file for writing
+pub fn creat(path: &str, mode: u32) -> Result<Fd, Errno> {
+    open(path, O_WRONLY | O_CREAT | O_TRUNC, mode)
+}
@@ -0,0 +1,120 @@
+//! POSIX-style compatibility layer
+//!
+//! Maps a small POSIX-like API (open/read/write/close/stat/mkdir/opendir,
+//! clock_gettime, nanosleep, timers, scheduler and CPU frequency control,
+//! battery state and temperatures, pipe/dup2/fork/execve/waitpid/kill, brk/sbrk, memory
+//! limits, threads, argv and environment) onto Kosh system calls and
+//! services, to ease porting programs. It is optional; native programs use `kosh-ipc` and
+//! `kosh-service` directly.
+//!
+//! Deviations from POSIX:
+//!
+//! - There is no global `errno`; every call returns `Result<_, Errno>`.
+//! - Paths are `&str` and are NUL-terminated by the shim.
+//! - File I/O is served by fs-service. Reads and writes move at most about
+//!   1 KiB per call, so short counts are normal; use `write_all` to write
+//!   everything.
+//! - `lseek` only reports the current offset (`lseek(fd, 0, SEEK_CUR)`).
+//! - `stat` and `fstat` return ENOSYS until the kernel implements them.
+//! - `clock_gettime` only knows `CLOCK_REALTIME` and `CLOCK_MONOTONIC`.
+//!   The wall clock is set from the RTC at boot and cannot be changed.
+//! - `mkdir`, `opendir` and `clone_file` go to fs-service over IPC, found at
+//!   `DEFAULT_FS_SERVICE_PID` unless `set_fs_service_pid` says otherwise.
+//!   File modes are ignored.
+//! - `opendir` takes a snapshot of the directory, and `readdir` only
+//!   reports `DT_DIR` or `DT_REG`.
+//! - `nanosleep` and timers have the scheduler tick's granularity, and
+//!   sleeps are never interrupted.
+//! - Timers do not raise signals: `timer_wait` blocks until the next
+//!   expiry and returns the expiry count, like reading a Linux timerfd.
+//!   `timer_create` takes no clock, as timers always run on the
+//!   monotonic clock.
+//! - `sched_info` and `sched_set` replace `sched_getscheduler` and
+//!   `sched_setscheduler`; the policy is system-wide, not per process.
+//!   Only `sched_set_realtime`, the counterpart of `SCHED_RR`, applies
+//!   to one process, and it takes the process ID rather than 0 for self.
+//! - `cpufreq_info` and `cpufreq_set_governor` stand in for the Linux
+//!   cpufreq sysfs files, with one governor for every CPU.
+//! - `brk` fails with ENOMEM instead of returning the old break, and
+//!   `set_memory_limit` replaces `setrlimit(RLIMIT_AS)`. It takes a process
+//!   ID and needs memory management rights, and children do not inherit
+//!   the limit.
+//! - `sysinfo` has its own layout, and `process_list`, `process_status`
+//!   and `ipc_info` have no POSIX counterpart; /proc shows the same data.
+//!   `find_process` and `NamedProcess` find a service by the name it runs
+//!   under, like `pidof`.
+//! - `klog_read`, `klog_write` and `klog_set_level` stand in for
+//!   `syslog(2)`; the log comes back as text, one record per line, and
+//!   what a process writes goes in as one error record. `crash_report_read` and
+//!   `crash_report_clear` reach the report a panic left before the last
+//!   warm reboot, which Linux keeps in pstore.
+//! - `audit_read` reads the kernel's security audit log, which has no
+//!   POSIX counterpart.
+//! - `trace_enable`, `trace_disable` and `trace_read` drive the kernel's
+//!   tracepoints, much like the Linux tracefs files.
+//! - `getrandom` takes Linux's flags plus `GRND_INSECURE`, and returns at
+//!   most 64 KiB per call. `add_entropy` stands in for the `RNDADDENTROPY`
+//!   ioctl on /dev/random.
+//! - Signals always take their default action, so SIGCHLD cannot be
+//!   caught; poll `waitpid` with `WNOHANG` to notice exited children.
+//!   A process ended by a signal exits with `SIGNAL_EXIT_BASE` plus the
+//!   signal. There is no SIGSTOP, so processes cannot be suspended.
+//! - Console input is raw and non-blocking, like a terminal with `ICANON`
+//!   and `ECHO` off and `VMIN` 0: `read` on stdin returns the bytes typed
+//!   so far and 0 when there are none, rather than end of file.
+//! - A forked child inherits console and pipe descriptors only; files
+//!   served by fs-service have to be opened again.
+//! - `execv` and `execve` take `&str` arguments; they fail with
+//!   EOPNOTSUPP until the kernel can load programs.
+//! - Threads run closures: `thread::spawn` stands in for `pthread_create`
+//!   and `JoinHandle::join` for `pthread_join`. A thread that is never
+//!   joined keeps its stack allocated. Use `kosh-sync` for locks.
+//! - There is no `environ` global and no `setenv`: `argv`, `environ` and
+//!   `getenv` read the block the kernel recorded at exec (or copied at
+//!   fork), and a new environment is only passed on through `execve`.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod errno;
+pub mod raw;
+pub mod service;
+pub mod fcntl;
+pub mod unistd;
+pub mod env;
+pub mod stat;
+pub mod dirent;
+pub mod time;
+pub mod sched;
+pub mod power;
+pub mod mman;
+pub mod sysinfo;
+pub mod audit;
+pub mod trace;
+pub mod random;
+pub mod thread;
+
+pub use errno::Errno;
+pub use fcntl::*;
+pub use unistd::*;
+pub use env::{argv, environ, getenv, process_args, ProcessArgs};
+pub use stat::{stat, fstat, mkdir, clone_file, Stat};
+pub use dirent::{opendir, readdir, closedir, Dir, Dirent};
+pub use time::{
+    clock_gettime, nanosleep, sleep, timer_create, timer_settime, timer_wait, timer_expirations, timer_delete,
+    Timespec, Itimerspec, TimerId, CLOCK_REALTIME, CLOCK_MONOTONIC,
+};
+pub use mman::{brk, sbrk, set_memory_limit};
+pub use sched::{sched_info, sched_set, sched_set_realtime, SchedInfo, SchedPolicy};
+pub use power::{
+    battery_info, cpufreq_info, cpufreq_set_governor, thermal_info, CpuFreqInfo, CpuGovernor, PowerSupplyInfo,
+    ThermalZoneInfo,
+};
+pub use sysinfo::{
+    sysinfo, process_list, process_status, find_process, ipc_info, klog_read, klog_write, klog_set_level,
+    crash_report_read, crash_report_clear, SysInfo, ProcessStatus, NamedProcess, IpcInfo, LogLevel,
+};
+pub use audit::{audit_read, AuditRecord};
+pub use trace::{trace_enable, trace_disable, trace_status, trace_read, trace_lost, TraceRecord, Tracepoint};
+pub use service::set_fs_service_pid;
@@ -0,0 +1,35 @@
+//! Memory management
+//!
+//! `brk` and `sbrk` move the end of the heap, which the kernel places in
+//! an area of its own and populates on first touch. Linux's `brk` returns
+//! the old break when it fails; this one returns ENOMEM, or EINVAL for an
+//! address below the heap.
+//!
+//! `set_memory_limit` stands in for `setrlimit(RLIMIT_AS)`: it applies to
+//! what a process maps and grows its heap by, can be set on other
+//! processes given memory management rights, and is not inherited.
+
+use crate::errno::Errno;
+use crate::raw::{syscall3, SYS_BRK, SYS_SBRK, SYS_SET_MEMORY_LIMIT};
+
+/// Move the end of the heap to `address`, returning the new end; `0`
+/// only returns the current one
+pub fn brk(address: usize) -> Result<usize, Errno> {
+    Ok(Errno::result(syscall3(SYS_BRK, address as u64, 0, 0))? as usize)
+}
+
+/// Grow the heap by `increment` bytes, or shrink it if negative, returning
+/// the old end, which is where new memory starts
+pub fn sbrk(increment: isize) -> Result<usize, Errno> {
+    Ok(Errno::result(syscall3(SYS_SBRK, increment as u64, 0, 0))? as usize)
+}
+
+/// Limit the memory process `pid` may map to `limit` bytes, or lift the
+/// limit with `None`
+///
+/// Mapping or growing the heap past the limit fails with ENOMEM; a limit
+/// below what the process already uses only stops it from growing.
+pub fn set_memory_limit(pid: u32, limit: Option<usize>) -> Result<(), Errno> {
+    Errno::result(syscall3(SYS_SET_MEMORY_LIMIT, pid as u64, limit.unwrap_or(0) as u64, 0))?;
+    Ok(())
+}
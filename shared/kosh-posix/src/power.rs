@@ -0,0 +1,241 @@
+//! CPU frequency scaling control, battery state and temperatures
+//!
+//! Like the scheduler policy, the CPU governor is system-wide. Reading the
+//! state needs read access to the "power" system resource, and changing
+//! the governor needs write access to it. The kernel sets the frequency in
+//! hardware where the platform lets it; `CpuFreqInfo::backend` says how,
+//! and is empty when the frequency is only tracked.
+//!
+//! Any process can read the battery and AC adapter state, and the thermal
+//! zones.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::errno::Errno;
+use crate::raw::{syscall3, SYS_BATTERY_INFO, SYS_CPUFREQ_INFO, SYS_CPUFREQ_SET, SYS_THERMAL_INFO};
+
+/// Bytes of backend name `CpuFreqInfo` carries
+pub const BACKEND_NAME_LEN: usize = 16;
+
+/// How the CPU frequency follows the load
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuGovernor {
+    /// Always the fastest level
+    Performance,
+    /// Jumps with the load
+    OnDemand,
+    /// Always the slowest level
+    PowerSave,
+    /// Steps gradually with the load
+    Conservative,
+    /// Like on-demand, boosted while interactive processes run
+    Interactive,
+}
+
+impl CpuGovernor {
+    pub fn from_raw(value: u64) -> Option<Self> {
+        match value {
+            1 => Some(CpuGovernor::Performance),
+            2 => Some(CpuGovernor::OnDemand),
+            3 => Some(CpuGovernor::PowerSave),
+            4 => Some(CpuGovernor::Conservative),
+            5 => Some(CpuGovernor::Interactive),
+            _ => None,
+        }
+    }
+
+    pub fn as_raw(self) -> u64 {
+        match self {
+            CpuGovernor::Performance => 1,
+            CpuGovernor::OnDemand => 2,
+            CpuGovernor::PowerSave => 3,
+            CpuGovernor::Conservative => 4,
+            CpuGovernor::Interactive => 5,
+        }
+    }
+
+    /// Short name, as accepted by `from_name`
+    pub fn name(self) -> &'static str {
+        match self {
+            CpuGovernor::Performance => "performance",
+            CpuGovernor::OnDemand => "ondemand",
+            CpuGovernor::PowerSave => "powersave",
+            CpuGovernor::Conservative => "conservative",
+            CpuGovernor::Interactive => "interactive",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "performance" => Some(CpuGovernor::Performance),
+            "ondemand" | "on-demand" => Some(CpuGovernor::OnDemand),
+            "powersave" | "power-save" => Some(CpuGovernor::PowerSave),
+            "conservative" => Some(CpuGovernor::Conservative),
+            "interactive" => Some(CpuGovernor::Interactive),
+            _ => None,
+        }
+    }
+}
+
+/// Frequency scaling state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct CpuFreqInfo {
+    pub governor: u32,
+    pub current_mhz: u32,
+    pub min_mhz: u32,
+    pub max_mhz: u32,
+    /// Levels the hardware backend offers, 0 without one
+    pub levels: u32,
+    /// Backend name, UTF-8 padded with NULs
+    pub backend: [u8; BACKEND_NAME_LEN],
+}
+
+impl CpuFreqInfo {
+    /// Current governor, or None if the kernel reports one this library
+    /// does not know
+    pub fn governor(&self) -> Option<CpuGovernor> {
+        CpuGovernor::from_raw(self.governor as u64)
+    }
+
+    /// How the frequency is set, or None if it is only tracked
+    pub fn backend(&self) -> Option<&str> {
+        let length = self.backend.iter().position(|&byte| byte == 0).unwrap_or(BACKEND_NAME_LEN);
+        core::str::from_utf8(&self.backend[..length]).ok().filter(|name| !name.is_empty())
+    }
+}
+
+/// Read the governor, frequency range and backend
+pub fn cpufreq_info() -> Result<CpuFreqInfo, Errno> {
+    let mut info = CpuFreqInfo::default();
+    Errno::result(syscall3(SYS_CPUFREQ_INFO, &mut info as *mut CpuFreqInfo as u64, 0, 0))?;
+    Ok(info)
+}
+
+/// Switch every CPU to `governor`
+pub fn cpufreq_set_governor(governor: CpuGovernor) -> Result<(), Errno> {
+    Errno::result(syscall3(SYS_CPUFREQ_SET, governor.as_raw(), 0, 0))?;
+    Ok(())
+}
+
+/// `PowerSupplyInfo` flags
+pub const SUPPLY_BATTERY_PRESENT: u32 = 1 << 0;
+pub const SUPPLY_CHARGING: u32 = 1 << 1;
+pub const SUPPLY_LOW: u32 = 1 << 2;
+pub const SUPPLY_CRITICAL: u32 = 1 << 3;
+pub const SUPPLY_AC_ONLINE: u32 = 1 << 4;
+
+/// Battery and AC adapter state; values not known are 0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct PowerSupplyInfo {
+    /// `SUPPLY_*` flags; low and critical only while running on battery
+    pub flags: u32,
+    pub level_percent: u32,
+    /// Charge or discharge rate in mW
+    pub rate_mw: u32,
+    pub remaining_mwh: u32,
+    pub full_mwh: u32,
+    /// Until empty, or until full while charging
+    pub minutes_remaining: u32,
+}
+
+impl PowerSupplyInfo {
+    pub fn battery_present(&self) -> bool {
+        self.flags & SUPPLY_BATTERY_PRESENT != 0
+    }
+
+    pub fn charging(&self) -> bool {
+        self.flags & SUPPLY_CHARGING != 0
+    }
+
+    pub fn ac_online(&self) -> bool {
+        self.flags & SUPPLY_AC_ONLINE != 0
+    }
+
+    /// Running on a battery at or below the low level, critical included
+    pub fn low(&self) -> bool {
+        self.flags & (SUPPLY_LOW | SUPPLY_CRITICAL) != 0
+    }
+
+    pub fn critical(&self) -> bool {
+        self.flags & SUPPLY_CRITICAL != 0
+    }
+}
+
+/// Read the battery charge and whether AC power is connected
+pub fn battery_info() -> Result<PowerSupplyInfo, Errno> {
+    let mut info = PowerSupplyInfo::default();
+    Errno::result(syscall3(SYS_BATTERY_INFO, &mut info as *mut PowerSupplyInfo as u64, 0, 0))?;
+    Ok(info)
+}
+
+/// Bytes of zone name `ThermalZoneInfo` carries
+pub const ZONE_NAME_LEN: usize = 8;
+
+/// `ThermalZoneInfo` flags: which values are known
+pub const ZONE_TEMPERATURE_VALID: u32 = 1 << 0;
+pub const ZONE_HAS_PASSIVE: u32 = 1 << 1;
+pub const ZONE_HAS_HOT: u32 = 1 << 2;
+pub const ZONE_HAS_CRITICAL: u32 = 1 << 3;
+
+/// A temperature sensor and its trip points; temperatures are in
+/// thousandths of a degree Celsius
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct ThermalZoneInfo {
+    pub name: [u8; ZONE_NAME_LEN],
+    /// `ZONE_*` flags
+    pub flags: u32,
+    /// Trip point the zone is past: 0 for none, then passive, hot and
+    /// critical
+    pub tripped: u32,
+    pub temperature_mc: i32,
+    pub passive_mc: i32,
+    pub hot_mc: i32,
+    pub critical_mc: i32,
+}
+
+impl ThermalZoneInfo {
+    pub fn name(&self) -> &str {
+        let length = self.name.iter().position(|&byte| byte == 0).unwrap_or(ZONE_NAME_LEN);
+        core::str::from_utf8(&self.name[..length]).unwrap_or("?")
+    }
+
+    /// Temperature, None if the sensor could not be read
+    pub fn temperature_mc(&self) -> Option<i32> {
+        (self.flags & ZONE_TEMPERATURE_VALID != 0).then_some(self.temperature_mc)
+    }
+
+    /// Trip points the zone has, as name and temperature, coolest first
+    pub fn trip_points(&self) -> impl Iterator<Item = (&'static str, i32)> + '_ {
+        [
+            (ZONE_HAS_PASSIVE, "passive", self.passive_mc),
+            (ZONE_HAS_HOT, "hot", self.hot_mc),
+            (ZONE_HAS_CRITICAL, "critical", self.critical_mc),
+        ].into_iter()
+            .filter(|&(flag, _, _)| self.flags & flag != 0)
+            .map(|(_, name, millicelsius)| (name, millicelsius))
+    }
+
+    /// Name of the trip point the zone is past, if any
+    pub fn tripped_name(&self) -> Option<&'static str> {
+        match self.tripped {
+            1 => Some("passive"),
+            2 => Some("hot"),
+            3 => Some("critical"),
+            _ => None,
+        }
+    }
+}
+
+/// Read the thermal zones
+///
+/// The kernel finds them at boot, so their number does not change.
+pub fn thermal_info() -> Result<Vec<ThermalZoneInfo>, Errno> {
+    let count = Errno::result(syscall3(SYS_THERMAL_INFO, 0, 0, 0))? as usize;
+    let mut zones = vec![ThermalZoneInfo::default(); count];
+    let count = Errno::result(syscall3(SYS_THERMAL_INFO, zones.as_mut_ptr() as u64, count as u64, 0))? as usize;
+    zones.truncate(count);
+    Ok(zones)
+}
@@ -0,0 +1,53 @@
+//! Random bytes from the kernel
+//!
+//! The kernel's generator is seeded from interrupt timing, the CPU's
+//! generator and hardware random number devices. `getrandom` blocks until
+//! it has gathered enough entropy, unless `GRND_NONBLOCK` asks for EAGAIN
+//! instead or `GRND_INSECURE` for bytes regardless. /dev/urandom serves
+//! the same bytes and never blocks.
+//!
+//! Any process can mix bytes into the pool with `add_entropy`; they only
+//! count toward seeding it when they come from a driver.
+
+use crate::errno::Errno;
+use crate::raw::{blocking_syscall3, syscall3, SYS_ADD_ENTROPY, SYS_GETRANDOM};
+
+/// `getrandom` flags: fail with EAGAIN rather than wait for the pool to be
+/// seeded; accepted for compatibility, as there is one pool; return bytes
+/// even before the pool is seeded
+pub const GRND_NONBLOCK: u64 = 1 << 0;
+pub const GRND_RANDOM: u64 = 1 << 1;
+pub const GRND_INSECURE: u64 = 1 << 2;
+
+/// Fill `buf` with random bytes, returning how many were filled
+///
+/// Reads over 64 KiB come back short.
+pub fn getrandom(buf: &mut [u8], flags: u64) -> Result<usize, Errno> {
+    let (ptr, len) = (buf.as_mut_ptr() as u64, buf.len() as u64);
+    let count = if flags & GRND_NONBLOCK != 0 {
+        // EAGAIN is the answer here, not a request to call again
+        Errno::result(syscall3(SYS_GETRANDOM, ptr, len, flags))?
+    } else {
+        blocking_syscall3(SYS_GETRANDOM, ptr, len, flags)?
+    };
+    Ok(count as usize)
+}
+
+/// Fill all of `buf` with random bytes, waiting for the pool to be seeded
+pub fn fill_random(buf: &mut [u8]) -> Result<(), Errno> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        filled += getrandom(&mut buf[filled..], 0)?;
+    }
+    Ok(())
+}
+
+/// Mix `data` into the kernel's pool, claiming `entropy_bits` of entropy
+///
+/// Returns the bits the kernel counted: none unless the caller is a driver
+/// or holds write access to the "random" system resource. At most 4 KiB
+/// are taken per call; EINVAL beyond that.
+pub fn add_entropy(data: &[u8], entropy_bits: u32) -> Result<u32, Errno> {
+    let counted = Errno::result(syscall3(SYS_ADD_ENTROPY, data.as_ptr() as u64, data.len() as u64, entropy_bits as u64))?;
+    Ok(counted as u32)
+}
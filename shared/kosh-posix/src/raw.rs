@@ -0,0 +1,128 @@
+//! Raw system call entry
+
+use alloc::vec::Vec;
+use crate::errno::{Errno, EAGAIN, EINVAL, ENAMETOOLONG};
+
+/// System call numbers (must match shared/kosh-syscall/src/numbers.rs)
+pub const SYS_EXIT: u64 = 1;
+pub const SYS_FORK: u64 = 2;
+pub const SYS_EXEC: u64 = 3;
+pub const SYS_WAIT: u64 = 4;
+pub const SYS_GETPID: u64 = 5;
+pub const SYS_KILL: u64 = 7;
+pub const SYS_YIELD: u64 = 8;
+pub const SYS_GETARGS: u64 = 9;
+pub const SYS_BRK: u64 = 13;
+pub const SYS_SBRK: u64 = 14;
+pub const SYS_SET_MEMORY_LIMIT: u64 = 15;
+pub const SYS_PIPE: u64 = 16;
+pub const SYS_DUP2: u64 = 17;
+pub const SYS_OPEN: u64 = 20;
+pub const SYS_CLOSE: u64 = 21;
+pub const SYS_READ: u64 = 22;
+pub const SYS_WRITE: u64 = 23;
+pub const SYS_LSEEK: u64 = 24;
+pub const SYS_STAT: u64 = 25;
+pub const SYS_FSTAT: u64 = 26;
+pub const SYS_MKDIR: u64 = 27;
+pub const SYS_RMDIR: u64 = 28;
+pub const SYS_UNLINK: u64 = 29;
+pub const SYS_SYSINFO: u64 = 51;
+pub const SYS_CLOCK_GETTIME: u64 = 53;
+pub const SYS_SCHED_INFO: u64 = 54;
+pub const SYS_SCHED_SET: u64 = 55;
+pub const SYS_PROCESS_LIST: u64 = 56;
+pub const SYS_PROCESS_STATUS: u64 = 57;
+pub const SYS_IPC_INFO: u64 = 58;
+pub const SYS_KLOG: u64 = 59;
+pub const SYS_AUDIT: u64 = 64;
+
+pub const SYS_NANOSLEEP: u64 = 80;
+pub const SYS_TIMER_CREATE: u64 = 81;
+pub const SYS_TIMER_SET: u64 = 82;
+pub const SYS_TIMER_WAIT: u64 = 83;
+pub const SYS_TIMER_DELETE: u64 = 84;
+
+pub const SYS_THREAD_CREATE: u64 = 87;
+pub const SYS_THREAD_EXIT: u64 = 88;
+pub const SYS_THREAD_JOIN: u64 = 89;
+
+pub const SYS_SCHED_SET_REALTIME: u64 = 90;
+
+pub const SYS_CPUFREQ_INFO: u64 = 91;
+pub const SYS_CPUFREQ_SET: u64 = 92;
+pub const SYS_BATTERY_INFO: u64 = 93;
+pub const SYS_THERMAL_INFO: u64 = 94;
+
+pub const SYS_GETRANDOM: u64 = 96;
+pub const SYS_ADD_ENTROPY: u64 = 97;
+pub const SYS_TRACE: u64 = 98;
+
+/// `SYS_KLOG` actions
+pub const KLOG_READ: u64 = 0;
+pub const KLOG_SIZE: u64 = 1;
+pub const KLOG_SET_LEVEL: u64 = 2;
+pub const KLOG_CRASH_READ: u64 = 3;
+pub const KLOG_CRASH_SIZE: u64 = 4;
+pub const KLOG_CRASH_CLEAR: u64 = 5;
+pub const KLOG_WRITE: u64 = 6;
+
+/// Longest line `KLOG_WRITE` takes, in bytes
+pub const KLOG_WRITE_MAX: usize = 256;
+
+/// `SYS_TRACE` actions
+pub const TRACE_ENABLE: u64 = 0;
+pub const TRACE_DISABLE: u64 = 1;
+pub const TRACE_STATUS: u64 = 2;
+pub const TRACE_READ: u64 = 3;
+pub const TRACE_LOST: u64 = 4;
+
+/// `SYS_TIMER_WAIT` flag: return 0 instead of blocking when nothing expired
+pub const TIMER_WAIT_NOHANG: u64 = 1 << 0;
+
+/// Longest path the kernel accepts, including the terminator
+pub const PATH_MAX: usize = 4096;
+
+/// Issue a system call with up to three arguments
+pub fn syscall3(number: u64, arg0: u64, arg1: u64, arg2: u64) -> i64 {
+    let result: i64;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") number,
+            in("rdi") arg0,
+            in("rsi") arg1,
+            in("rdx") arg2,
+            lateout("rax") result,
+            options(nostack, preserves_flags)
+        );
+    }
+    result
+}
+
+/// Issue a system call, repeating it while the kernel has blocked the caller
+///
+/// Calls served by a userspace service block the caller and return EAGAIN;
+/// once rescheduled, the repeated call collects the service's answer.
+pub fn blocking_syscall3(number: u64, arg0: u64, arg1: u64, arg2: u64) -> Result<u64, Errno> {
+    loop {
+        match Errno::result(syscall3(number, arg0, arg1, arg2)) {
+            Err(EAGAIN) => continue,
+            result => return result,
+        }
+    }
+}
+
+/// NUL-terminated copy of a path for the kernel
+pub fn c_path(path: &str) -> Result<Vec<u8>, Errno> {
+    if path.is_empty() || path.as_bytes().contains(&0) {
+        return Err(EINVAL);
+    }
+    if path.len() >= PATH_MAX {
+        return Err(ENAMETOOLONG);
+    }
+    let mut bytes = Vec::with_capacity(path.len() + 1);
+    bytes.extend_from_slice(path.as_bytes());
+    bytes.push(0);
+    Ok(bytes)
+}
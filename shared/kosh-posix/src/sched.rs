@@ -0,0 +1,115 @@
+//! Scheduler policy control
+//!
+//! Not the POSIX per-process `sched_setscheduler`: Kosh has one scheduling
+//! algorithm and time slice for the whole system. Reading them is open to
+//! every process; changing them needs process management rights over the
+//! scheduler.
+//!
+//! Apart from that, single processes can be put in the real-time class,
+//! which runs ahead of the algorithm, highest priority first. The same
+//! rights are needed.
+
+use crate::errno::Errno;
+use crate::raw::{syscall3, SYS_SCHED_INFO, SYS_SCHED_SET, SYS_SCHED_SET_REALTIME};
+
+/// `SYS_SCHED_SET` algorithm value that keeps the current algorithm
+const KEEP_POLICY: u64 = u64::MAX;
+
+/// System-wide scheduling algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    RoundRobin,
+    Priority,
+    CompletelyFair,
+}
+
+impl SchedPolicy {
+    pub fn from_raw(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(SchedPolicy::RoundRobin),
+            1 => Some(SchedPolicy::Priority),
+            2 => Some(SchedPolicy::CompletelyFair),
+            _ => None,
+        }
+    }
+
+    pub fn as_raw(self) -> u64 {
+        match self {
+            SchedPolicy::RoundRobin => 0,
+            SchedPolicy::Priority => 1,
+            SchedPolicy::CompletelyFair => 2,
+        }
+    }
+
+    /// Short name, as accepted by `from_name`
+    pub fn name(self) -> &'static str {
+        match self {
+            SchedPolicy::RoundRobin => "rr",
+            SchedPolicy::Priority => "priority",
+            SchedPolicy::CompletelyFair => "cfs",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rr" | "round-robin" => Some(SchedPolicy::RoundRobin),
+            "priority" => Some(SchedPolicy::Priority),
+            "cfs" | "fair" => Some(SchedPolicy::CompletelyFair),
+            _ => None,
+        }
+    }
+}
+
+/// Lowest and highest real-time priority; higher runs first
+pub const MIN_RT_PRIORITY: u8 = 1;
+pub const MAX_RT_PRIORITY: u8 = 99;
+
+/// Shortest and longest time slice the kernel accepts
+pub const MIN_TIME_SLICE_MS: u64 = 1;
+pub const MAX_TIME_SLICE_MS: u64 = 1000;
+
+/// Scheduler state, summed over all CPUs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct SchedInfo {
+    pub algorithm: u64,
+    pub time_slice_ms: u64,
+    pub context_switches: u64,
+    pub scheduling_decisions: u64,
+    pub scheduler_time_us: u64,
+    pub runnable_processes: u64,
+}
+
+impl SchedInfo {
+    /// Current algorithm, or None if the kernel reports one this library
+    /// does not know
+    pub fn policy(&self) -> Option<SchedPolicy> {
+        SchedPolicy::from_raw(self.algorithm)
+    }
+}
+
+/// Read the scheduler's policy and statistics
+pub fn sched_info() -> Result<SchedInfo, Errno> {
+    let mut info = SchedInfo::default();
+    Errno::result(syscall3(SYS_SCHED_INFO, &mut info as *mut SchedInfo as u64, 0, 0))?;
+    Ok(info)
+}
+
+/// Change the scheduling algorithm and/or time slice on every CPU
+///
+/// `None` leaves that setting unchanged; at least one must be given.
+pub fn sched_set(policy: Option<SchedPolicy>, time_slice_ms: Option<u64>) -> Result<(), Errno> {
+    let policy = policy.map_or(KEEP_POLICY, SchedPolicy::as_raw);
+    Errno::result(syscall3(SYS_SCHED_SET, policy, time_slice_ms.unwrap_or(0), 0))?;
+    Ok(())
+}
+
+/// Put process `pid` in the real-time class at `priority`, or back in the
+/// normal class with `None`
+///
+/// A real-time process also lends its priority to the processes it sends
+/// requests to until they reply.
+pub fn sched_set_realtime(pid: u32, priority: Option<u8>) -> Result<(), Errno> {
+    Errno::result(syscall3(SYS_SCHED_SET_REALTIME, pid as u64, priority.unwrap_or(0) as u64, 0))?;
+    Ok(())
+}
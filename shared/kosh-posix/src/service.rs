@@ -0,0 +1,56 @@
+//! Direct requests to the file system service
+//!
+//! Calls with no kernel system call yet (mkdir, opendir) talk to
+//! fs-service over IPC, the same way any other client would.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use kosh_ipc::poll::{poll, PollEntry};
+use kosh_service::{FileSystemRequest, ServiceClient, ServiceData, ServiceResponse, ServiceStatus, ServiceType};
+use kosh_types::ProcessId;
+use crate::errno::{Errno, EACCES, ECONNREFUSED, EINVAL, EIO, ENOENT};
+
+/// Process ID fs-service gets when init spawns it first
+pub const DEFAULT_FS_SERVICE_PID: ProcessId = 2;
+
+static FS_SERVICE_PID: AtomicU32 = AtomicU32::new(DEFAULT_FS_SERVICE_PID);
+
+/// Override where file system requests are sent
+///
+/// There is no name lookup for services yet, so programs started in an
+/// unusual order must point the shim at fs-service themselves.
+pub fn set_fs_service_pid(pid: ProcessId) {
+    FS_SERVICE_PID.store(pid, Ordering::Relaxed);
+}
+
+/// Process ID file system requests are sent to
+pub fn fs_service_pid() -> ProcessId {
+    FS_SERVICE_PID.load(Ordering::Relaxed)
+}
+
+/// Send a request and wait for its response
+pub fn fs_request(request: FileSystemRequest) -> Result<ServiceResponse, Errno> {
+    let service = fs_service_pid();
+    let mut client = ServiceClient::new();
+    client.send_request(service, ServiceType::FileSystem, ServiceData::FileSystemRequest(request))?;
+
+    loop {
+        let mut entries = [PollEntry::from_sender(service)];
+        poll(&mut entries, kosh_ipc::poll::INFINITE).map_err(|_| EIO)?;
+        match client.receive_response() {
+            Ok(response) => return status_to_result(response),
+            Err(kosh_service::ServiceError::WouldBlock) => continue,
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+fn status_to_result(response: ServiceResponse) -> Result<ServiceResponse, Errno> {
+    match response.status {
+        ServiceStatus::Success => Ok(response),
+        ServiceStatus::NotFound => Err(ENOENT),
+        ServiceStatus::PermissionDenied => Err(EACCES),
+        ServiceStatus::InvalidRequest => Err(EINVAL),
+        ServiceStatus::ServiceUnavailable => Err(ECONNREFUSED),
+        ServiceStatus::Error => Err(EIO),
+    }
+}
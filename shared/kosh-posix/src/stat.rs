@@ -0,0 +1,86 @@
+use alloc::string::String;
+use kosh_service::{FileSystemRequest, ServiceData};
+use crate::errno::{Errno, EIO};
+use crate::raw::{blocking_syscall3, c_path, SYS_FSTAT, SYS_STAT};
+use crate::service::fs_request;
+use crate::unistd::Fd;
+
+/// File type bits of `st_mode`
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+/// File status, laid out as the 144-byte x86_64 `struct stat`
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct Stat {
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_nlink: u64,
+    pub st_mode: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    __pad0: u32,
+    pub st_rdev: u64,
+    pub st_size: i64,
+    pub st_blksize: i64,
+    pub st_blocks: i64,
+    pub st_atime: i64,
+    pub st_atime_nsec: i64,
+    pub st_mtime: i64,
+    pub st_mtime_nsec: i64,
+    pub st_ctime: i64,
+    pub st_ctime_nsec: i64,
+    __unused: [i64; 3],
+}
+
+impl Stat {
+    pub fn is_dir(&self) -> bool {
+        self.st_mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.st_mode & S_IFMT == S_IFREG
+    }
+}
+
+/// Status of the file at `path`
+pub fn stat(path: &str) -> Result<Stat, Errno> {
+    let path = c_path(path)?;
+    let mut status = Stat::default();
+    blocking_syscall3(SYS_STAT, path.as_ptr() as u64, &mut status as *mut Stat as u64, 0)?;
+    Ok(status)
+}
+
+/// Status of an open descriptor
+pub fn fstat(fd: Fd) -> Result<Stat, Errno> {
+    let mut status = Stat::default();
+    blocking_syscall3(SYS_FSTAT, fd as u64, &mut status as *mut Stat as u64, 0)?;
+    Ok(status)
+}
+
+/// Create a directory
+///
+/// Sent straight to the file system service; `mode` is ignored.
+pub fn mkdir(path: &str, _mode: u32) -> Result<(), Errno> {
+    c_path(path)?;
+    fs_request(FileSystemRequest::Create { path: String::from(path), is_directory: true })?;
+    Ok(())
+}
+
+/// Copy the file `source` to the new file `destination`, like
+/// `cp --reflink=auto`
+///
+/// Sent straight to the file system service. Returns true if the copy
+/// shares blocks with `source` copy-on-write, false if the data was copied.
+pub fn clone_file(source: &str, destination: &str) -> Result<bool, Errno> {
+    c_path(source)?;
+    c_path(destination)?;
+    let request = FileSystemRequest::Clone { source: String::from(source), destination: String::from(destination) };
+    match fs_request(request)?.data {
+        ServiceData::Binary(shared) if shared.len() == 1 => Ok(shared[0] != 0),
+        _ => Err(EIO),
+    }
+}
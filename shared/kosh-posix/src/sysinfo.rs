@@ -0,0 +1,304 @@
+//! System and process statistics
+//!
+//! Kosh's own calls rather than Linux `sysinfo(2)`: the kernel reports
+//! system-wide memory and uptime, the status of each process and the IPC
+//! counters. fs-service shows the same figures as text under /proc. The
+//! kernel log is read here too, for `dmesg`, along with the crash report
+//! a panic left before the last warm reboot.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::errno::Errno;
+use crate::raw::{
+    syscall3, KLOG_CRASH_CLEAR, KLOG_CRASH_READ, KLOG_CRASH_SIZE, KLOG_READ, KLOG_SET_LEVEL, KLOG_SIZE, KLOG_WRITE,
+    KLOG_WRITE_MAX, SYS_IPC_INFO, SYS_KLOG, SYS_PROCESS_LIST, SYS_PROCESS_STATUS, SYS_SYSINFO,
+};
+
+/// `ProcessStatus::state` values
+pub const PROCESS_STATE_RUNNING: u32 = 0;
+pub const PROCESS_STATE_READY: u32 = 1;
+pub const PROCESS_STATE_BLOCKED: u32 = 2;
+pub const PROCESS_STATE_ZOMBIE: u32 = 3;
+pub const PROCESS_STATE_CREATING: u32 = 4;
+
+/// Bytes of a process name `ProcessStatus` carries
+pub const PROCESS_NAME_LEN: usize = 32;
+
+/// System-wide figures; memory sizes are in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct SysInfo {
+    pub uptime_ms: u64,
+    pub total_memory: u64,
+    pub free_memory: u64,
+    pub heap_size: u64,
+    pub heap_used: u64,
+    pub process_count: u64,
+    pub cpu_count: u64,
+    /// Average busy share of the CPUs over the last tenth of a second, in
+    /// percent
+    pub cpu_load: u64,
+    /// CPU time since boot, summed over all CPUs
+    pub user_time_ms: u64,
+    pub kernel_time_ms: u64,
+    pub idle_time_ms: u64,
+}
+
+/// One process as the kernel sees it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct ProcessStatus {
+    pub pid: u32,
+    /// 0 for processes without a parent
+    pub parent_pid: u32,
+    pub state: u32,
+    /// 0 (system) to 3 (background)
+    pub priority: u32,
+    pub cpu: u32,
+    /// Only meaningful for zombies
+    pub exit_code: i32,
+    pub cpu_time_ms: u64,
+    /// Parts of `cpu_time_ms` spent in user mode and in the kernel
+    pub user_time_ms: u64,
+    pub kernel_time_ms: u64,
+    pub context_switches: u64,
+    /// When the process last got a CPU (milliseconds since boot)
+    pub last_run_ms: u64,
+    /// Bytes of populated pages in the process's mappings and heap
+    pub resident_bytes: u64,
+    pub heap_bytes: u64,
+    /// Most bytes the process may map; 0 if unlimited
+    pub memory_limit: u64,
+    pub creation_time_ms: u64,
+    pub children: u64,
+    /// UTF-8, padded with NULs
+    pub name: [u8; PROCESS_NAME_LEN],
+}
+
+impl Default for ProcessStatus {
+    fn default() -> Self {
+        Self {
+            pid: 0,
+            parent_pid: 0,
+            state: 0,
+            priority: 0,
+            cpu: 0,
+            exit_code: 0,
+            cpu_time_ms: 0,
+            user_time_ms: 0,
+            kernel_time_ms: 0,
+            context_switches: 0,
+            last_run_ms: 0,
+            resident_bytes: 0,
+            heap_bytes: 0,
+            memory_limit: 0,
+            creation_time_ms: 0,
+            children: 0,
+            name: [0; PROCESS_NAME_LEN],
+        }
+    }
+}
+
+impl ProcessStatus {
+    pub fn name(&self) -> &str {
+        let length = self.name.iter().position(|&byte| byte == 0).unwrap_or(PROCESS_NAME_LEN);
+        core::str::from_utf8(&self.name[..length]).unwrap_or("?")
+    }
+
+    /// Name of the state, as /proc and `ps` show it
+    pub fn state_name(&self) -> &'static str {
+        match self.state {
+            PROCESS_STATE_RUNNING => "running",
+            PROCESS_STATE_READY => "ready",
+            PROCESS_STATE_BLOCKED => "blocked",
+            PROCESS_STATE_ZOMBIE => "zombie",
+            PROCESS_STATE_CREATING => "creating",
+            _ => "unknown",
+        }
+    }
+}
+
+/// IPC counters since boot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct IpcInfo {
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub active_queues: u64,
+    pub capabilities: u64,
+    pub capability_checks: u64,
+    pub capability_checks_failed: u64,
+    /// Messages refused to a sender or receiver without the capability
+    pub sends_denied: u64,
+    pub receives_denied: u64,
+}
+
+/// Read the system-wide figures
+pub fn sysinfo() -> Result<SysInfo, Errno> {
+    let mut info = SysInfo::default();
+    Errno::result(syscall3(SYS_SYSINFO, &mut info as *mut SysInfo as u64, 0, 0))?;
+    Ok(info)
+}
+
+/// IDs of every process, in process table order
+pub fn process_list() -> Result<Vec<u32>, Errno> {
+    // Processes come and go between the calls, so size generously and
+    // retry if the table grew past the buffer
+    let mut capacity = Errno::result(syscall3(SYS_PROCESS_LIST, 0, 0, 0))? as usize + 8;
+    loop {
+        let mut pids = vec![0u32; capacity];
+        let count = Errno::result(syscall3(SYS_PROCESS_LIST, pids.as_mut_ptr() as u64, capacity as u64, 0))? as usize;
+        if count <= capacity {
+            pids.truncate(count);
+            return Ok(pids);
+        }
+        capacity = count + 8;
+    }
+}
+
+/// Status of process `pid`; fails with ESRCH if there is none
+pub fn process_status(pid: u32) -> Result<ProcessStatus, Errno> {
+    let mut status = ProcessStatus::default();
+    Errno::result(syscall3(SYS_PROCESS_STATUS, pid as u64, &mut status as *mut ProcessStatus as u64, 0))?;
+    Ok(status)
+}
+
+/// ID of the live process called `name`, if there is one
+///
+/// Services are known by the name init starts them under, such as
+/// "input-service"; their process IDs depend on the start order and
+/// change when one is restarted.
+pub fn find_process(name: &str) -> Result<Option<u32>, Errno> {
+    for pid in process_list()? {
+        // A process that exited since the list was taken is skipped
+        let Ok(status) = process_status(pid) else {
+            continue;
+        };
+        if status.state != PROCESS_STATE_ZOMBIE && status.name() == name {
+            return Ok(Some(pid));
+        }
+    }
+    Ok(None)
+}
+
+/// A process found by name, looked up again after it was forgotten
+///
+/// For a service that may start after its client or be restarted: look it
+/// up with `pid`, and call `forget` when sending to it fails.
+#[derive(Debug, Clone)]
+pub struct NamedProcess {
+    name: &'static str,
+    pid: Option<u32>,
+}
+
+impl NamedProcess {
+    pub const fn new(name: &'static str) -> Self {
+        Self { name, pid: None }
+    }
+
+    /// The process's ID, looking it up if it is not known; `None` if no
+    /// process by that name is running
+    pub fn pid(&mut self) -> Option<u32> {
+        if self.pid.is_none() {
+            self.pid = find_process(self.name).ok().flatten();
+        }
+        self.pid
+    }
+
+    /// Look the process up again next time, as it may have been restarted
+    pub fn forget(&mut self) {
+        self.pid = None;
+    }
+}
+
+/// Read the IPC counters
+pub fn ipc_info() -> Result<IpcInfo, Errno> {
+    let mut info = IpcInfo::default();
+    Errno::result(syscall3(SYS_IPC_INFO, &mut info as *mut IpcInfo as u64, 0, 0))?;
+    Ok(info)
+}
+
+/// Kernel log levels, least verbose first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl LogLevel {
+    /// Level for a name such as "warn", as `log_level=` and `dmesg` take
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "error" => Some(Self::Error),
+            "warn" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            "debug" => Some(Self::Debug),
+            "trace" => Some(Self::Trace),
+            _ => None,
+        }
+    }
+
+    /// Level of a kernel log line, `[   12.345] WARN  syscall: ...`
+    pub fn of_record(line: &str) -> Option<Self> {
+        let (_, rest) = line.split_once("] ")?;
+        Self::from_name(rest.split_whitespace().next()?)
+    }
+}
+
+/// The kernel log, oldest line first
+pub fn klog_read() -> Result<String, Errno> {
+    // The log may grow between the calls; the kernel then returns the
+    // newest lines that fit
+    let size = Errno::result(syscall3(SYS_KLOG, KLOG_SIZE, 0, 0))? as usize;
+    let mut buffer = vec![0u8; size];
+    let count = Errno::result(syscall3(SYS_KLOG, KLOG_READ, buffer.as_mut_ptr() as u64, size as u64))? as usize;
+    buffer.truncate(count);
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+/// The crash report the previous boot left, if it panicked and was
+/// rebooted warm
+///
+/// The report holds the panic message, a backtrace and the newest kernel
+/// log lines from before the panic.
+pub fn crash_report_read() -> Result<Option<String>, Errno> {
+    let size = Errno::result(syscall3(SYS_KLOG, KLOG_CRASH_SIZE, 0, 0))? as usize;
+    if size == 0 {
+        return Ok(None);
+    }
+    let mut buffer = vec![0u8; size];
+    let count = Errno::result(syscall3(SYS_KLOG, KLOG_CRASH_READ, buffer.as_mut_ptr() as u64, size as u64))? as usize;
+    buffer.truncate(count);
+    Ok(Some(String::from_utf8_lossy(&buffer).into_owned()))
+}
+
+/// Drop the crash report the previous boot left
+///
+/// Fails with EPERM without write access to the "klog" resource.
+pub fn crash_report_clear() -> Result<(), Errno> {
+    Errno::result(syscall3(SYS_KLOG, KLOG_CRASH_CLEAR, 0, 0))?;
+    Ok(())
+}
+
+/// Add `text` to the kernel log as an error record naming the caller
+///
+/// The kernel keeps at most 256 bytes of it, as one line.
+pub fn klog_write(text: &str) -> Result<(), Errno> {
+    let len = text.len().min(KLOG_WRITE_MAX);
+    Errno::result(syscall3(SYS_KLOG, KLOG_WRITE, text.as_ptr() as u64, len as u64))?;
+    Ok(())
+}
+
+/// Keep kernel log records at `level` and above from every subsystem
+///
+/// Fails with EPERM without write access to the "klog" resource.
+pub fn klog_set_level(level: LogLevel) -> Result<(), Errno> {
+    Errno::result(syscall3(SYS_KLOG, KLOG_SET_LEVEL, level as u64, 0))?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! Threads
+//!
+//! Not `pthread_create`: a thread runs a closure on a stack allocated from
+//! the heap and ends when the closure returns, with the closure's result
+//! as its exit code. Threads share everything of the process but their
+//! registers and stacks, and end when the process exits.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::errno::{Errno, EINVAL};
+use crate::raw::{blocking_syscall3, syscall3, SYS_THREAD_CREATE, SYS_THREAD_EXIT, SYS_THREAD_JOIN};
+
+/// Stack size `spawn` gives a thread
+pub const DEFAULT_STACK_SIZE: usize = 64 * 1024;
+
+/// Smallest stack `spawn_with_stack` accepts
+pub const MIN_STACK_SIZE: usize = 4096;
+
+/// Kernel-assigned thread ID, from the same space as process IDs
+pub type ThreadId = u32;
+
+type ThreadMain = Box<dyn FnOnce() -> i32 + Send>;
+
+/// A running thread, to be joined
+///
+/// A handle dropped without `join` detaches the thread; its stack is then
+/// never freed, as nothing tells when the thread stops using it.
+pub struct JoinHandle {
+    tid: ThreadId,
+    stack: Vec<u8>,
+}
+
+impl JoinHandle {
+    pub fn id(&self) -> ThreadId {
+        self.tid
+    }
+
+    /// Wait for the thread to end and return its exit code
+    pub fn join(mut self) -> Result<i32, Errno> {
+        let mut status: i32 = 0;
+        blocking_syscall3(SYS_THREAD_JOIN, self.tid as u64, &mut status as *mut i32 as u64, 0)?;
+        // Nothing runs on the stack any more
+        drop(core::mem::take(&mut self.stack));
+        Ok(status)
+    }
+}
+
+impl Drop for JoinHandle {
+    fn drop(&mut self) {
+        // The detached thread may still be running on its stack
+        core::mem::forget(core::mem::take(&mut self.stack));
+    }
+}
+
+/// Start `main` on a new thread with a `DEFAULT_STACK_SIZE` stack
+pub fn spawn<F>(main: F) -> Result<JoinHandle, Errno>
+where
+    F: FnOnce() -> i32 + Send + 'static,
+{
+    spawn_with_stack(DEFAULT_STACK_SIZE, main)
+}
+
+/// Start `main` on a new thread with a stack of `stack_size` bytes
+pub fn spawn_with_stack<F>(stack_size: usize, main: F) -> Result<JoinHandle, Errno>
+where
+    F: FnOnce() -> i32 + Send + 'static,
+{
+    if stack_size < MIN_STACK_SIZE {
+        return Err(EINVAL);
+    }
+    let stack = vec![0u8; stack_size];
+    // The thread is entered as if called, with the return address slot
+    // taking it off 16-byte alignment
+    let top = ((stack.as_ptr() as u64 + stack_size as u64) & !0xF) - 8;
+
+    let main: Box<ThreadMain> = Box::new(Box::new(main));
+    let arg = Box::into_raw(main) as u64;
+    match Errno::result(syscall3(SYS_THREAD_CREATE, thread_entry as *const () as u64, top, arg)) {
+        Ok(tid) => Ok(JoinHandle { tid: tid as ThreadId, stack }),
+        Err(errno) => {
+            // The thread never started, so the closure is still ours
+            drop(unsafe { Box::from_raw(arg as *mut ThreadMain) });
+            Err(errno)
+        }
+    }
+}
+
+/// First code a new thread runs, with the boxed closure as argument
+extern "C" fn thread_entry(arg: u64) -> ! {
+    let main = unsafe { Box::from_raw(arg as *mut ThreadMain) };
+    exit(main())
+}
+
+/// End the calling thread with `status`
+///
+/// Only spawned threads can end this way; the process's first thread ends
+/// with the process, through `unistd::exit`.
+pub fn exit(status: i32) -> ! {
+    if Errno::result(syscall3(SYS_THREAD_EXIT, status as u64, 0, 0)).is_err() {
+        crate::unistd::exit(status);
+    }
+    // The kernel never schedules an exited thread again
+    loop {
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,109 @@
+use crate::errno::{Errno, EINVAL};
+use crate::raw::{
+    blocking_syscall3, syscall3, SYS_CLOCK_GETTIME, SYS_NANOSLEEP, SYS_TIMER_CREATE, SYS_TIMER_DELETE,
+    SYS_TIMER_SET, SYS_TIMER_WAIT, TIMER_WAIT_NOHANG,
+};
+
+/// Clock identifiers
+pub const CLOCK_REALTIME: i32 = 0;
+pub const CLOCK_MONOTONIC: i32 = 1;
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+/// Seconds and nanoseconds, as `struct timespec`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(C)]
+pub struct Timespec {
+    pub tv_sec: i64,
+    pub tv_nsec: i64,
+}
+
+impl Timespec {
+    pub const fn new(tv_sec: i64, tv_nsec: i64) -> Self {
+        Self { tv_sec, tv_nsec }
+    }
+
+    fn is_valid(&self) -> bool {
+        self.tv_sec >= 0 && (0..NANOS_PER_SEC).contains(&self.tv_nsec)
+    }
+
+    /// Total nanoseconds, saturating for times past the year 2554
+    fn as_nanos(&self) -> u64 {
+        (self.tv_sec as u64).saturating_mul(NANOS_PER_SEC as u64).saturating_add(self.tv_nsec as u64)
+    }
+}
+
+/// Timer setting, as `struct itimerspec`
+///
+/// A zero `it_value` disarms the timer; a zero `it_interval` makes it
+/// expire once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct Itimerspec {
+    pub it_interval: Timespec,
+    pub it_value: Timespec,
+}
+
+/// A timer created with `timer_create`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerId(u32);
+
+/// Read a clock
+pub fn clock_gettime(clock: i32) -> Result<Timespec, Errno> {
+    let mut time = Timespec::default();
+    Errno::result(syscall3(SYS_CLOCK_GETTIME, clock as u64, &mut time as *mut Timespec as u64, 0))?;
+    Ok(time)
+}
+
+/// Suspend the caller for at least `request`
+///
+/// Sleeps are rounded up to the timer tick. Signals do not interrupt the
+/// sleep, so there is never remaining time to report.
+pub fn nanosleep(request: &Timespec) -> Result<(), Errno> {
+    if !request.is_valid() {
+        return Err(EINVAL);
+    }
+    blocking_syscall3(SYS_NANOSLEEP, request.as_nanos(), 0, 0)?;
+    Ok(())
+}
+
+/// Sleep for whole seconds
+pub fn sleep(seconds: u32) -> Result<(), Errno> {
+    nanosleep(&Timespec::new(seconds as i64, 0))
+}
+
+/// Create a disarmed timer
+pub fn timer_create() -> Result<TimerId, Errno> {
+    Errno::result(syscall3(SYS_TIMER_CREATE, 0, 0, 0)).map(|id| TimerId(id as u32))
+}
+
+/// Arm or disarm `timer`
+///
+/// Expiries not yet collected with `timer_wait` are dropped.
+pub fn timer_settime(timer: TimerId, value: &Itimerspec) -> Result<(), Errno> {
+    if !value.it_value.is_valid() || !value.it_interval.is_valid() {
+        return Err(EINVAL);
+    }
+    Errno::result(syscall3(SYS_TIMER_SET, timer.0 as u64, value.it_value.as_nanos(), value.it_interval.as_nanos()))?;
+    Ok(())
+}
+
+/// Wait for `timer` to expire
+///
+/// Returns how many times it expired since the last call, which is more
+/// than one when a periodic timer was not waited on in time. Fails with
+/// EINVAL if the timer is disarmed and nothing is pending.
+pub fn timer_wait(timer: TimerId) -> Result<u64, Errno> {
+    blocking_syscall3(SYS_TIMER_WAIT, timer.0 as u64, 0, 0)
+}
+
+/// Expiries of `timer` since the last wait, without blocking
+pub fn timer_expirations(timer: TimerId) -> Result<u64, Errno> {
+    Errno::result(syscall3(SYS_TIMER_WAIT, timer.0 as u64, TIMER_WAIT_NOHANG, 0))
+}
+
+/// Delete `timer`
+pub fn timer_delete(timer: TimerId) -> Result<(), Errno> {
+    Errno::result(syscall3(SYS_TIMER_DELETE, timer.0 as u64, 0, 0))?;
+    Ok(())
+}
@@ -0,0 +1,129 @@
+//! Kernel tracing
+//!
+//! Tracepoints in the scheduler, IPC, system call and page fault paths
+//! write time-stamped records into per-CPU rings in the kernel while they
+//! are on. Turning them on or off needs write access to the "trace" system
+//! resource; reading the records, which takes them out of the rings, needs
+//! read access to it.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::errno::Errno;
+use crate::raw::{syscall3, SYS_TRACE, TRACE_DISABLE, TRACE_ENABLE, TRACE_LOST, TRACE_READ, TRACE_STATUS};
+
+/// Records read per call
+const READ_BATCH: usize = 256;
+
+/// Process number that stands for the idle loop in `SchedSwitch` records
+pub const TRACE_IDLE: u32 = u32::MAX;
+
+/// A place in the kernel that can write trace records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum Tracepoint {
+    /// `process` is the process switched to; `arg0` the one switched from
+    SchedSwitch = 1,
+    /// `process` is the sender; `arg0` the receiver, `arg1` the message ID
+    IpcSend = 2,
+    /// `process` is the receiver; `arg0` the sender, `arg1` the message ID
+    IpcReceive = 3,
+    /// `arg0` is the system call number, `arg1` its first argument
+    SyscallEnter = 4,
+    /// `arg0` is the system call number, `arg1` the value it returned, a
+    /// negative errno on failure
+    SyscallExit = 5,
+    /// `arg0` is the faulting address, `arg1` the error code
+    PageFault = 6,
+}
+
+impl Tracepoint {
+    pub const ALL: [Tracepoint; 6] = [
+        Tracepoint::SchedSwitch,
+        Tracepoint::IpcSend,
+        Tracepoint::IpcReceive,
+        Tracepoint::SyscallEnter,
+        Tracepoint::SyscallExit,
+        Tracepoint::PageFault,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tracepoint::SchedSwitch => "sched_switch",
+            Tracepoint::IpcSend => "ipc_send",
+            Tracepoint::IpcReceive => "ipc_receive",
+            Tracepoint::SyscallEnter => "syscall_enter",
+            Tracepoint::SyscallExit => "syscall_exit",
+            Tracepoint::PageFault => "page_fault",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|point| point.name() == name)
+    }
+
+    pub fn from_raw(raw: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|&point| point as u16 == raw)
+    }
+
+    /// The tracepoint's bit in masks
+    pub fn bit(self) -> u32 {
+        1 << self as u16
+    }
+}
+
+/// One event, oldest first across all CPUs when read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct TraceRecord {
+    /// Nanoseconds since boot
+    pub time_ns: u64,
+    pub tracepoint: u16,
+    pub cpu: u16,
+    /// Process the event concerns
+    pub process: u32,
+    pub arg0: u64,
+    pub arg1: u64,
+}
+
+impl TraceRecord {
+    pub fn tracepoint(&self) -> Option<Tracepoint> {
+        Tracepoint::from_raw(self.tracepoint)
+    }
+}
+
+/// Turn on the tracepoints whose bits are in `mask`, returning the mask of
+/// those now on
+pub fn trace_enable(mask: u32) -> Result<u32, Errno> {
+    Ok(Errno::result(syscall3(SYS_TRACE, TRACE_ENABLE, mask as u64, 0))? as u32)
+}
+
+/// Turn off the tracepoints whose bits are in `mask`, returning the mask
+/// of those still on
+pub fn trace_disable(mask: u32) -> Result<u32, Errno> {
+    Ok(Errno::result(syscall3(SYS_TRACE, TRACE_DISABLE, mask as u64, 0))? as u32)
+}
+
+/// Mask of the tracepoints that are on
+pub fn trace_status() -> Result<u32, Errno> {
+    Ok(Errno::result(syscall3(SYS_TRACE, TRACE_STATUS, 0, 0))? as u32)
+}
+
+/// Take every record the kernel holds, oldest first
+pub fn trace_read() -> Result<Vec<TraceRecord>, Errno> {
+    let mut records = Vec::new();
+    loop {
+        let mut batch = vec![TraceRecord::default(); READ_BATCH];
+        let count = Errno::result(syscall3(SYS_TRACE, TRACE_READ, batch.as_mut_ptr() as u64, READ_BATCH as u64))? as usize;
+        batch.truncate(count);
+        records.extend(batch);
+        if count < READ_BATCH {
+            return Ok(records);
+        }
+    }
+}
+
+/// Records the kernel lost since boot, overwritten before they were read
+/// or dropped while their ring was busy
+pub fn trace_lost() -> Result<u64, Errno> {
+    Errno::result(syscall3(SYS_TRACE, TRACE_LOST, 0, 0))
+}
@@ -0,0 +1,180 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::errno::Errno;
+use crate::raw::{blocking_syscall3, c_path, syscall3, SYS_CLOSE, SYS_DUP2, SYS_EXEC, SYS_EXIT, SYS_FORK, SYS_GETPID, SYS_KILL, SYS_LSEEK, SYS_PIPE, SYS_READ, SYS_WAIT, SYS_WRITE, SYS_YIELD};
+
+/// File descriptor
+pub type Fd = i32;
+
+pub const STDIN_FILENO: Fd = 0;
+pub const STDOUT_FILENO: Fd = 1;
+pub const STDERR_FILENO: Fd = 2;
+
+/// waitpid option: return at once if no child has exited
+pub const WNOHANG: u64 = 0x1;
+
+/// Signal numbers
+pub const SIGINT: i32 = 2;
+pub const SIGKILL: i32 = 9;
+pub const SIGTERM: i32 = 15;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+
+/// Exit status of a process ended by a signal is this plus the signal
+pub const SIGNAL_EXIT_BASE: i32 = 128;
+
+/// lseek whence values
+pub const SEEK_SET: i32 = 0;
+pub const SEEK_CUR: i32 = 1;
+pub const SEEK_END: i32 = 2;
+
+/// Read up to `buffer.len()` bytes; 0 means end of file
+///
+/// File reads are served in chunks of about 1 KiB, so a short count does not
+/// imply end of file.
+pub fn read(fd: Fd, buffer: &mut [u8]) -> Result<usize, Errno> {
+    let count = blocking_syscall3(SYS_READ, fd as u64, buffer.as_mut_ptr() as u64, buffer.len() as u64)?;
+    Ok(count as usize)
+}
+
+/// Write up to `buffer.len()` bytes, returning how many were written
+pub fn write(fd: Fd, buffer: &[u8]) -> Result<usize, Errno> {
+    let count = blocking_syscall3(SYS_WRITE, fd as u64, buffer.as_ptr() as u64, buffer.len() as u64)?;
+    Ok(count as usize)
+}
+
+/// Write the whole buffer, retrying short writes
+pub fn write_all(fd: Fd, mut buffer: &[u8]) -> Result<(), Errno> {
+    while !buffer.is_empty() {
+        let written = write(fd, buffer)?;
+        if written == 0 {
+            return Err(crate::errno::EIO);
+        }
+        buffer = &buffer[written..];
+    }
+    Ok(())
+}
+
+/// Close a descriptor
+pub fn close(fd: Fd) -> Result<(), Errno> {
+    Errno::result(syscall3(SYS_CLOSE, fd as u64, 0, 0)).map(|_| ())
+}
+
+/// Create a pipe, returning its read and write descriptors
+///
+/// Reads block while the pipe is empty and return 0 once every write
+/// descriptor is closed; writing with every read descriptor closed fails
+/// with EPIPE.
+pub fn pipe() -> Result<(Fd, Fd), Errno> {
+    let mut fds: [i32; 2] = [-1, -1];
+    Errno::result(syscall3(SYS_PIPE, fds.as_mut_ptr() as u64, 0, 0))?;
+    Ok((fds[0], fds[1]))
+}
+
+/// Make `target` refer to the same file as `fd`, closing what it was
+pub fn dup2(fd: Fd, target: Fd) -> Result<Fd, Errno> {
+    Errno::result(syscall3(SYS_DUP2, fd as u64, target as u64, 0)).map(|fd| fd as Fd)
+}
+
+/// Reposition a descriptor, returning the new offset
+///
+/// Only `lseek(fd, 0, SEEK_CUR)` is answered today; other forms return
+/// EOPNOTSUPP until the file system service supports seeking.
+pub fn lseek(fd: Fd, offset: i64, whence: i32) -> Result<u64, Errno> {
+    Errno::result(syscall3(SYS_LSEEK, fd as u64, offset as u64, whence as u64))
+}
+
+/// Process ID of the caller
+pub fn getpid() -> u32 {
+    syscall3(SYS_GETPID, 0, 0, 0) as u32
+}
+
+/// Give up the rest of the time slice
+pub fn sched_yield() {
+    syscall3(SYS_YIELD, 0, 0, 0);
+}
+
+/// End the calling process with `status`
+pub fn exit(status: i32) -> ! {
+    syscall3(SYS_EXIT, status as u64, 0, 0);
+    // The kernel never schedules an exited process again
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Create a child process
+///
+/// Returns 0 in the child and the child's process ID in the parent.
+pub fn fork() -> Result<u32, Errno> {
+    Errno::result(syscall3(SYS_FORK, 0, 0, 0)).map(|pid| pid as u32)
+}
+
+/// Replace the program of the calling process with the one at `path`,
+/// keeping the caller's environment
+///
+/// `args` become the argument vector after the program path. Only returns
+/// on failure.
+pub fn execv(path: &str, args: &[&str]) -> Errno {
+    let env = crate::env::environ().unwrap_or_default();
+    let env: Vec<&str> = env.iter().map(String::as_str).collect();
+    execve(path, args, &env)
+}
+
+/// Replace the program of the calling process with the one at `path`
+///
+/// `args` become the argument vector after the program path, and `env`,
+/// as `NAME=value` strings, the new program's environment. Only returns
+/// on failure.
+pub fn execve(path: &str, args: &[&str], env: &[&str]) -> Errno {
+    let program = match c_path(path) {
+        Ok(program) => program,
+        Err(errno) => return errno,
+    };
+    let args = c_strings(args);
+    let env = c_strings(env);
+    // NULL-terminated argv, starting with the program itself
+    let mut argv: Vec<u64> = Vec::with_capacity(args.len() + 2);
+    argv.push(program.as_ptr() as u64);
+    argv.extend(args.iter().map(|arg| arg.as_ptr() as u64));
+    argv.push(0);
+    let mut envp: Vec<u64> = env.iter().map(|entry| entry.as_ptr() as u64).collect();
+    envp.push(0);
+    // The kernel reads the program from the file system service
+    match blocking_syscall3(SYS_EXEC, program.as_ptr() as u64, argv.as_ptr() as u64, envp.as_ptr() as u64) {
+        Err(errno) => errno,
+        Ok(_) => crate::errno::EIO,
+    }
+}
+
+/// NUL-terminated copies of `strings`
+fn c_strings(strings: &[&str]) -> Vec<Vec<u8>> {
+    strings.iter()
+        .map(|string| {
+            let mut bytes = Vec::with_capacity(string.len() + 1);
+            bytes.extend_from_slice(string.as_bytes());
+            bytes.push(0);
+            bytes
+        })
+        .collect()
+}
+
+/// Collect an exited child, `pid` 0 meaning any child
+///
+/// Returns the child and its exit status, or `None` when `WNOHANG` is set
+/// and no child has exited yet.
+pub fn waitpid(pid: u32, options: u64) -> Result<Option<(u32, i32)>, Errno> {
+    let mut status: i32 = 0;
+    let status_ptr = &mut status as *mut i32 as u64;
+    let child = if options & WNOHANG != 0 {
+        Errno::result(syscall3(SYS_WAIT, status_ptr, options, pid as u64))?
+    } else {
+        blocking_syscall3(SYS_WAIT, status_ptr, options, pid as u64)?
+    };
+    Ok(Some(child).filter(|&child| child != 0).map(|child| (child as u32, status)))
+}
+
+/// Send `signal` to process `pid`
+pub fn kill(pid: u32, signal: i32) -> Result<(), Errno> {
+    Errno::result(syscall3(SYS_KILL, pid as u64, signal as u64, 0)).map(|_| ())
+}
@@ -0,0 +1,26 @@
+//! Fuzz entry points for the service wire format
+//!
+//! Every service reads frames straight out of its IPC queue, so whatever a
+//! sender puts there reaches the decoders. Each entry point takes arbitrary
+//! bytes and panics only when the decoder misbehaves: a frame that decodes
+//! must encode back to a frame that decodes to the same value.
+
+use crate::wire::{decode_message, decode_response, encode_message, encode_response};
+
+/// Decode `data` as a request frame and check that it round-trips
+pub fn service_message(data: &[u8]) {
+    if let Ok(message) = decode_message(data) {
+        let encoded = encode_message(&message);
+        let decoded = decode_message(&encoded).expect("re-encoded request does not decode");
+        assert_eq!(encode_message(&decoded), encoded, "request changed across a round trip");
+    }
+}
+
+/// Decode `data` as a response frame and check that it round-trips
+pub fn service_response(data: &[u8]) {
+    if let Ok(response) = decode_response(data) {
+        let encoded = encode_response(&response);
+        let decoded = decode_response(&encoded).expect("re-encoded response does not decode");
+        assert_eq!(encode_response(&decoded), encoded, "response changed across a round trip");
+    }
+}
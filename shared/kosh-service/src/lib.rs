@@ -1,20 +1,43 @@
-#![no_std]
+#![cfg_attr(not(feature = "fuzzing"), no_std)]
 
 extern crate alloc;
 
+use alloc::vec;
 use alloc::vec::Vec;
 use alloc::string::String;
 use kosh_types::ProcessId;
-use kosh_ipc::{Message, MessageData, IpcError};
+use kosh_ipc::{IpcError, SharedBuffer};
+
+pub mod wire;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+
+pub use wire::WireError;
 
 /// Service communication framework for Kosh OS
 /// Provides standardized communication between system services
 
-#[derive(Debug, Clone)]
+/// Sender ID of messages queued by the kernel itself
+pub const KERNEL_PID: ProcessId = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ServiceMessage {
     pub service_type: ServiceType,
     pub request_id: u64,
     pub data: ServiceData,
+    /// Process the kernel forwarded the request for, e.g. the caller of a
+    /// file system call. Ignored unless the kernel sent the request.
+    pub on_behalf_of: Option<ProcessId>,
+}
+
+impl ServiceMessage {
+    /// Process the request is made for, given the process that sent it
+    pub fn requester(&self, sender: ProcessId) -> ProcessId {
+        match self.on_behalf_of {
+            Some(pid) if sender == KERNEL_PID => pid,
+            _ => sender,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -26,19 +49,24 @@ pub enum ServiceType {
     NetworkManager,
     DisplayManager,
     InputManager,
+    AppRuntime,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ServiceData {
     Empty,
     Text(String),
     Binary(Vec<u8>),
+    /// Large binary payload passed by shared memory grant
+    SharedBinary(SharedBuffer),
     FileSystemRequest(FileSystemRequest),
     DriverRequest(DriverRequest),
     ProcessRequest(ProcessRequest),
+    InputRequest(InputRequest),
+    DisplayRequest(DisplayRequest),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FileSystemRequest {
     Open { path: String, flags: u32 },
     Close { fd: u32 },
@@ -47,17 +75,60 @@ pub enum FileSystemRequest {
     List { path: String },
     Create { path: String, is_directory: bool },
     Delete { path: String },
+    /// Read at `offset` without moving the descriptor's offset
+    ReadAt { fd: u32, offset: u64, size: usize },
+    /// Write at `offset` without moving the descriptor's offset
+    WriteAt { fd: u32, offset: u64, data: Vec<u8> },
+    /// Copy `source` to the new file `destination`, sharing its blocks
+    /// copy-on-write where the file system supports that
+    Clone { source: String, destination: String },
+    /// Restrict `pid` to a view of the file tree made of `(path, target)`
+    /// bindings: `target` in the global tree appears at `path`
+    SetNamespace { pid: ProcessId, bindings: Vec<(String, String)> },
+    /// Give `pid` the whole file tree again
+    ClearNamespace { pid: ProcessId },
+    /// Mount a `fs_type` file system from device `source` at `target`, or
+    /// with `MountFlags::BIND` show the directory `source` there
+    Mount { source: String, target: String, fs_type: String, flags: u32 },
+    /// Remove the mount at `target`
+    Unmount { target: String },
+    /// Write data the file system service holds back to every device
+    Sync,
+    /// Move the offset of `fd` as `lseek` does; answered with the new
+    /// offset
+    Seek { fd: u32, offset: i64, whence: i32 },
+    /// Another descriptor for the file `fd` is open on, sharing its offset
+    Dup { fd: u32 },
+    /// Create a symbolic link at `path` pointing to `target`
+    Symlink { target: String, path: String },
+    /// Change the requester's working directory, which relative paths in
+    /// its later requests start from
+    Chdir { path: String },
+    /// The requester's working directory, as text
+    Getcwd,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DriverRequest {
     LoadDriver { path: String },
     UnloadDriver { driver_id: u32 },
     ListDrivers,
     SendToDriver { driver_id: u32, data: Vec<u8> },
+    /// Idle time before the driver's device goes to low power and to
+    /// suspend; 0 never does
+    SetPowerPolicy { driver_id: u32, low_power_after_ms: u64, suspend_after_ms: u64 },
+    /// Keep the device powered while the requester uses it, e.g. the network
+    /// stack while sockets are open
+    HoldDriver { driver_id: u32 },
+    /// Drop a hold taken with `HoldDriver`
+    ReleaseDriver { driver_id: u32 },
+    /// Suspend every device for system sleep
+    SuspendAll,
+    /// Bring back the devices `SuspendAll` suspended
+    ResumeAll,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProcessRequest {
     Spawn { program: String, args: Vec<String> },
     Kill { pid: ProcessId },
@@ -65,7 +136,59 @@ pub enum ProcessRequest {
     GetInfo { pid: ProcessId },
 }
 
-#[derive(Debug, Clone)]
+/// Requests to the input service
+///
+/// Input reaches the process with focus as batches of
+/// `kosh_driver::input::InputRecord`s, sent as plain IPC messages rather
+/// than service responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputRequest {
+    /// Send the requester input while it has focus; the first process to
+    /// subscribe gets focus
+    Subscribe,
+    /// Stop sending the requester input; focus passes to the process that
+    /// subscribed last
+    Unsubscribe,
+    /// Send input to `pid`, which must have subscribed
+    SetFocus { pid: ProcessId },
+    /// The process with focus, as a little-endian `u32`
+    GetFocus,
+    /// Repeat a held key after `delay_ms`, then every `interval_ms`; a
+    /// delay of 0 turns repeating off
+    SetKeyRepeat { delay_ms: u32, interval_ms: u32 },
+    /// Type with the keymap called `name`, such as "us" or "de"
+    SetKeymap { name: String },
+}
+
+/// Name the display service runs under; clients look its process ID up
+/// by this name
+pub const DISPLAY_SERVICE_NAME: &str = "display-service";
+
+/// Requests to the display service
+///
+/// A surface is an image the requester draws into: a shared memory region
+/// it created and granted the display service, holding `width` by `height`
+/// little-endian 0x00RRGGBB pixels, one row after another. Surfaces are
+/// stacked in the order they were last raised, and the input of the
+/// process owning the one with focus is sent to it as
+/// `kosh_driver::input::InputRecord` batches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisplayRequest {
+    /// Show a surface at `x`, `y` on top of the others, with focus;
+    /// answered with its ID as a little-endian `u32`
+    CreateSurface { x: i32, y: i32, width: u32, height: u32, buffer: SharedBuffer },
+    DestroySurface { surface: u32 },
+    MoveSurface { surface: u32, x: i32, y: i32 },
+    /// Put a surface on top of the others and give it focus
+    RaiseSurface { surface: u32 },
+    /// The requester drew the `width` by `height` pixels at `x`, `y` of the
+    /// surface, which are shown again
+    Damage { surface: u32, x: u32, y: u32, width: u32, height: u32 },
+    /// The screen's width and height, as little-endian `u32`s
+    GetDisplayInfo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ServiceResponse {
     pub request_id: u64,
     pub status: ServiceStatus,
@@ -135,12 +258,16 @@ impl ServiceRegistry {
 /// Service client for communicating with services
 pub struct ServiceClient {
     next_request_id: u64,
+    pending_requests: Vec<u64>,
+    receive_buffer: Vec<u8>,
 }
 
 impl ServiceClient {
     pub fn new() -> Self {
         Self {
             next_request_id: 1,
+            pending_requests: Vec::new(),
+            receive_buffer: vec![0u8; kosh_ipc::syscall::MAX_MESSAGE_SIZE],
         }
     }
     
@@ -152,34 +279,39 @@ impl ServiceClient {
             service_type,
             request_id,
             data,
+            on_behalf_of: None,
         };
         
-        // Convert to IPC message
-        let _ipc_message = self.service_message_to_ipc(service_pid, service_message)?;
+        // Serialize and hand the frame to the kernel IPC queue of the service
+        let frame = wire::encode_message(&service_message);
+        kosh_ipc::syscall::send_message(service_pid, &frame)?;
         
-        // Send via IPC (this would use actual IPC system calls)
-        // For now, just return the request ID
+        self.pending_requests.push(request_id);
         Ok(request_id)
     }
     
-    pub fn receive_response(&self) -> Result<ServiceResponse, ServiceError> {
-        // In a real implementation, this would receive IPC messages
-        // and convert them back to service responses
-        Err(ServiceError::NotImplemented)
+    /// Receive the next response for any outstanding request
+    ///
+    /// Frames that are not responses to a request issued by this client are
+    /// dropped. Returns `WouldBlock` when no message is queued.
+    pub fn receive_response(&mut self) -> Result<ServiceResponse, ServiceError> {
+        loop {
+            let (_sender, length) = kosh_ipc::syscall::receive_message(&mut self.receive_buffer)?;
+            let response = match wire::decode_response(&self.receive_buffer[..length]) {
+                Ok(response) => response,
+                Err(_) => continue,
+            };
+            
+            if let Some(index) = self.pending_requests.iter().position(|id| *id == response.request_id) {
+                self.pending_requests.swap_remove(index);
+                return Ok(response);
+            }
+        }
     }
     
-    fn service_message_to_ipc(&self, receiver: ProcessId, _message: ServiceMessage) -> Result<Message, ServiceError> {
-        // Convert ServiceMessage to IPC Message
-        // This is a simplified conversion
-        let message_data = MessageData::Bytes(&[]); // Would serialize the actual data
-        
-        Ok(Message {
-            sender: 0, // Would be filled by IPC system
-            receiver,
-            message_type: kosh_types::MessageType::ServiceRequest,
-            data: message_data,
-            capabilities: &[],
-        })
+    /// Check whether a request is still waiting for its response
+    pub fn is_pending(&self, request_id: u64) -> bool {
+        self.pending_requests.contains(&request_id)
     }
 }
 
@@ -190,6 +322,8 @@ pub enum ServiceError {
     InvalidRequest,
     CommunicationError,
     Timeout,
+    WouldBlock,
+    MalformedMessage,
     NotImplemented,
 }
 
@@ -199,14 +333,55 @@ impl From<IpcError> for ServiceError {
             IpcError::InvalidReceiver => ServiceError::NotFound,
             IpcError::PermissionDenied => ServiceError::PermissionDenied,
             IpcError::Timeout => ServiceError::Timeout,
+            IpcError::WouldBlock => ServiceError::WouldBlock,
+            IpcError::UnsupportedPayload => ServiceError::InvalidRequest,
             _ => ServiceError::CommunicationError,
         }
     }
 }
 
+impl From<WireError> for ServiceError {
+    fn from(_error: WireError) -> Self {
+        ServiceError::MalformedMessage
+    }
+}
+
+/// A decoded request waiting to be handled
+#[derive(Debug, Clone)]
+pub struct IncomingRequest {
+    /// Process to send the response to
+    pub sender: ProcessId,
+    /// Process the request is made for
+    pub requester: ProcessId,
+    pub request: ServiceMessage,
+}
+
+/// Send `response` to the process that sent the request
+///
+/// Bulk payloads stay in shared memory, which the receiver is granted
+/// access to.
+pub fn send_response(sender: ProcessId, response: &ServiceResponse) -> Result<(), ServiceError> {
+    if let ServiceData::SharedBinary(buffer) = &response.data {
+        kosh_ipc::shm::grant(buffer.region_id, sender, false)?;
+    }
+    
+    let frame = wire::encode_response(response);
+    kosh_ipc::syscall::send_message(sender, &frame)?;
+    Ok(())
+}
+
 /// Trait for implementing service handlers
 pub trait ServiceHandler {
     fn handle_request(&mut self, request: ServiceMessage) -> ServiceResponse;
+    
+    /// Handle a request made for `requester`
+    ///
+    /// Services that treat callers differently override this; the default
+    /// ignores who is asking.
+    fn handle_request_from(&mut self, requester: ProcessId, request: ServiceMessage) -> ServiceResponse {
+        let _ = requester;
+        self.handle_request(request)
+    }
     fn get_service_type(&self) -> ServiceType;
     fn initialize(&mut self) -> Result<(), ServiceError>;
     fn shutdown(&mut self) -> Result<(), ServiceError>;
@@ -216,6 +391,7 @@ pub trait ServiceHandler {
 pub struct ServiceRunner<T: ServiceHandler> {
     handler: T,
     running: bool,
+    receive_buffer: Vec<u8>,
 }
 
 impl<T: ServiceHandler> ServiceRunner<T> {
@@ -223,6 +399,7 @@ impl<T: ServiceHandler> ServiceRunner<T> {
         Self {
             handler,
             running: false,
+            receive_buffer: vec![0u8; kosh_ipc::syscall::MAX_MESSAGE_SIZE],
         }
     }
     
@@ -237,21 +414,82 @@ impl<T: ServiceHandler> ServiceRunner<T> {
         self.handler.shutdown()
     }
     
+    /// Process at most one pending request
+    ///
+    /// Receives the next IPC frame, decodes it, dispatches it to the handler
+    /// and sends the encoded response back to the sender.
     pub fn run_once(&mut self) -> Result<(), ServiceError> {
+        if let Some(incoming) = self.receive_request()? {
+            let response = self.handler.handle_request_from(incoming.requester, incoming.request);
+            send_response(incoming.sender, &response)?;
+        }
+        Ok(())
+    }
+    
+    /// Take the next pending request off the IPC queue without handling it
+    ///
+    /// For services that hand requests to worker threads, which answer
+    /// through `send_response`. Returns `None` if nothing was queued; a
+    /// request that does not decode is answered here and `None` returned.
+    pub fn receive_request(&mut self) -> Result<Option<IncomingRequest>, ServiceError> {
         if !self.running {
             return Err(ServiceError::InvalidRequest);
         }
         
-        // In a real implementation, this would:
-        // 1. Check for incoming IPC messages
-        // 2. Convert them to ServiceMessage
-        // 3. Call handler.handle_request()
-        // 4. Send response back via IPC
+        let (sender, length) = match kosh_ipc::syscall::receive_message(&mut self.receive_buffer) {
+            Ok(received) => received,
+            Err(IpcError::WouldBlock) => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
         
-        Ok(())
+        match wire::decode_message(&self.receive_buffer[..length]) {
+            Ok(request) => {
+                let requester = request.requester(sender);
+                Ok(Some(IncomingRequest { sender, requester, request }))
+            }
+            Err(_) => {
+                // Still answer so the client does not wait forever
+                let request_id = wire::peek_request_id(&self.receive_buffer[..length])
+                    .map_err(ServiceError::from)?;
+                let response = ServiceResponse {
+                    request_id,
+                    status: ServiceStatus::InvalidRequest,
+                    data: ServiceData::Empty,
+                };
+                send_response(sender, &response)?;
+                Ok(None)
+            }
+        }
+    }
+    
+    /// Sleep until requests arrive, then handle every queued request
+    ///
+    /// Returns the number of requests processed; 0 means the timeout
+    /// expired. Replaces spinning on `run_once`.
+    pub fn poll_and_dispatch(&mut self, timeout_ms: u64) -> Result<usize, ServiceError> {
+        if !self.running {
+            return Err(ServiceError::InvalidRequest);
+        }
+        
+        if !kosh_ipc::poll::wait_for_message(timeout_ms)? {
+            return Ok(0);
+        }
+        
+        let mut handled = 0;
+        while kosh_ipc::poll::wait_for_message(0)? {
+            self.run_once()?;
+            handled += 1;
+        }
+        
+        Ok(handled)
     }
     
     pub fn is_running(&self) -> bool {
         self.running
     }
+    
+    /// The handler, for work the service does between requests
+    pub fn handler_mut(&mut self) -> &mut T {
+        &mut self.handler
+    }
 }
\ No newline at end of file
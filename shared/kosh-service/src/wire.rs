@@ -0,0 +1,827 @@
+//! Wire format for service messages
+//!
+//! ServiceMessage and ServiceResponse are encoded into a compact little-endian
+//! byte stream so they can travel through the kernel IPC queues. Every frame
+//! starts with a one-byte frame kind followed by the request ID, which lets a
+//! client correlate responses with the requests it has in flight.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::{
+    ServiceMessage, ServiceResponse, ServiceType, ServiceStatus, ServiceData,
+    FileSystemRequest, DriverRequest, ProcessRequest, InputRequest, DisplayRequest,
+};
+use kosh_ipc::SharedBuffer;
+
+/// Frame kind tags
+const FRAME_REQUEST: u8 = 0x01;
+const FRAME_RESPONSE: u8 = 0x02;
+
+/// Wire encoding/decoding errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireError {
+    /// Input ended before the frame was complete
+    Truncated,
+    /// Unknown frame, enum or data tag
+    InvalidTag(u8),
+    /// String payload was not valid UTF-8
+    InvalidUtf8,
+    /// Frame kind does not match what the caller expected
+    UnexpectedFrame,
+}
+
+/// Encode a service request into a byte frame
+pub fn encode_message(message: &ServiceMessage) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+    encoder.put_u8(FRAME_REQUEST);
+    encoder.put_u64(message.request_id);
+    encoder.put_u8(service_type_to_tag(message.service_type));
+    match message.on_behalf_of {
+        Some(pid) => {
+            encoder.put_bool(true);
+            encoder.put_u32(pid);
+        }
+        None => encoder.put_bool(false),
+    }
+    encoder.put_data(&message.data);
+    encoder.finish()
+}
+
+/// Decode a service request from a byte frame
+pub fn decode_message(bytes: &[u8]) -> Result<ServiceMessage, WireError> {
+    let mut decoder = Decoder::new(bytes);
+    if decoder.get_u8()? != FRAME_REQUEST {
+        return Err(WireError::UnexpectedFrame);
+    }
+    let request_id = decoder.get_u64()?;
+    let service_type = service_type_from_tag(decoder.get_u8()?)?;
+    let on_behalf_of = if decoder.get_bool()? { Some(decoder.get_u32()?) } else { None };
+    let data = decoder.get_data()?;
+
+    Ok(ServiceMessage { service_type, request_id, data, on_behalf_of })
+}
+
+/// Encode a service response into a byte frame
+pub fn encode_response(response: &ServiceResponse) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+    encoder.put_u8(FRAME_RESPONSE);
+    encoder.put_u64(response.request_id);
+    encoder.put_u8(status_to_tag(response.status));
+    encoder.put_data(&response.data);
+    encoder.finish()
+}
+
+/// Decode a service response from a byte frame
+pub fn decode_response(bytes: &[u8]) -> Result<ServiceResponse, WireError> {
+    let mut decoder = Decoder::new(bytes);
+    if decoder.get_u8()? != FRAME_RESPONSE {
+        return Err(WireError::UnexpectedFrame);
+    }
+    let request_id = decoder.get_u64()?;
+    let status = status_from_tag(decoder.get_u8()?)?;
+    let data = decoder.get_data()?;
+
+    Ok(ServiceResponse { request_id, status, data })
+}
+
+/// Peek at the request ID of an encoded frame without decoding the payload
+pub fn peek_request_id(bytes: &[u8]) -> Result<u64, WireError> {
+    let mut decoder = Decoder::new(bytes);
+    decoder.get_u8()?;
+    decoder.get_u64()
+}
+
+fn service_type_to_tag(service_type: ServiceType) -> u8 {
+    match service_type {
+        ServiceType::FileSystem => 0,
+        ServiceType::DriverManager => 1,
+        ServiceType::ProcessManager => 2,
+        ServiceType::MemoryManager => 3,
+        ServiceType::NetworkManager => 4,
+        ServiceType::DisplayManager => 5,
+        ServiceType::InputManager => 6,
+        ServiceType::AppRuntime => 7,
+    }
+}
+
+fn service_type_from_tag(tag: u8) -> Result<ServiceType, WireError> {
+    match tag {
+        0 => Ok(ServiceType::FileSystem),
+        1 => Ok(ServiceType::DriverManager),
+        2 => Ok(ServiceType::ProcessManager),
+        3 => Ok(ServiceType::MemoryManager),
+        4 => Ok(ServiceType::NetworkManager),
+        5 => Ok(ServiceType::DisplayManager),
+        6 => Ok(ServiceType::InputManager),
+        7 => Ok(ServiceType::AppRuntime),
+        _ => Err(WireError::InvalidTag(tag)),
+    }
+}
+
+fn status_to_tag(status: ServiceStatus) -> u8 {
+    match status {
+        ServiceStatus::Success => 0,
+        ServiceStatus::Error => 1,
+        ServiceStatus::NotFound => 2,
+        ServiceStatus::PermissionDenied => 3,
+        ServiceStatus::InvalidRequest => 4,
+        ServiceStatus::ServiceUnavailable => 5,
+    }
+}
+
+fn status_from_tag(tag: u8) -> Result<ServiceStatus, WireError> {
+    match tag {
+        0 => Ok(ServiceStatus::Success),
+        1 => Ok(ServiceStatus::Error),
+        2 => Ok(ServiceStatus::NotFound),
+        3 => Ok(ServiceStatus::PermissionDenied),
+        4 => Ok(ServiceStatus::InvalidRequest),
+        5 => Ok(ServiceStatus::ServiceUnavailable),
+        _ => Err(WireError::InvalidTag(tag)),
+    }
+}
+
+/// Little-endian frame writer
+struct Encoder {
+    buffer: Vec<u8>,
+}
+
+impl Encoder {
+    fn new() -> Self {
+        Self { buffer: Vec::new() }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    fn put_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_bool(&mut self, value: bool) {
+        self.put_u8(value as u8);
+    }
+
+    fn put_bytes(&mut self, bytes: &[u8]) {
+        self.put_u32(bytes.len() as u32);
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    fn put_str(&mut self, text: &str) {
+        self.put_bytes(text.as_bytes());
+    }
+
+    fn put_data(&mut self, data: &ServiceData) {
+        match data {
+            ServiceData::Empty => self.put_u8(0),
+            ServiceData::Text(text) => {
+                self.put_u8(1);
+                self.put_str(text);
+            }
+            ServiceData::Binary(bytes) => {
+                self.put_u8(2);
+                self.put_bytes(bytes);
+            }
+            ServiceData::FileSystemRequest(request) => {
+                self.put_u8(3);
+                self.put_fs_request(request);
+            }
+            ServiceData::DriverRequest(request) => {
+                self.put_u8(4);
+                self.put_driver_request(request);
+            }
+            ServiceData::ProcessRequest(request) => {
+                self.put_u8(5);
+                self.put_process_request(request);
+            }
+            ServiceData::SharedBinary(buffer) => {
+                self.put_u8(6);
+                self.buffer.extend_from_slice(&buffer.to_bytes());
+            }
+            ServiceData::InputRequest(request) => {
+                self.put_u8(7);
+                self.put_input_request(request);
+            }
+            ServiceData::DisplayRequest(request) => {
+                self.put_u8(8);
+                self.put_display_request(request);
+            }
+        }
+    }
+
+    fn put_fs_request(&mut self, request: &FileSystemRequest) {
+        match request {
+            FileSystemRequest::Open { path, flags } => {
+                self.put_u8(0);
+                self.put_str(path);
+                self.put_u32(*flags);
+            }
+            FileSystemRequest::Close { fd } => {
+                self.put_u8(1);
+                self.put_u32(*fd);
+            }
+            FileSystemRequest::Read { fd, size } => {
+                self.put_u8(2);
+                self.put_u32(*fd);
+                self.put_u64(*size as u64);
+            }
+            FileSystemRequest::Write { fd, data } => {
+                self.put_u8(3);
+                self.put_u32(*fd);
+                self.put_bytes(data);
+            }
+            FileSystemRequest::List { path } => {
+                self.put_u8(4);
+                self.put_str(path);
+            }
+            FileSystemRequest::Create { path, is_directory } => {
+                self.put_u8(5);
+                self.put_str(path);
+                self.put_bool(*is_directory);
+            }
+            FileSystemRequest::Delete { path } => {
+                self.put_u8(6);
+                self.put_str(path);
+            }
+            FileSystemRequest::ReadAt { fd, offset, size } => {
+                self.put_u8(7);
+                self.put_u32(*fd);
+                self.put_u64(*offset);
+                self.put_u64(*size as u64);
+            }
+            FileSystemRequest::WriteAt { fd, offset, data } => {
+                self.put_u8(8);
+                self.put_u32(*fd);
+                self.put_u64(*offset);
+                self.put_bytes(data);
+            }
+            FileSystemRequest::Clone { source, destination } => {
+                self.put_u8(9);
+                self.put_str(source);
+                self.put_str(destination);
+            }
+            FileSystemRequest::SetNamespace { pid, bindings } => {
+                self.put_u8(10);
+                self.put_u32(*pid);
+                self.put_u32(bindings.len() as u32);
+                for (path, target) in bindings {
+                    self.put_str(path);
+                    self.put_str(target);
+                }
+            }
+            FileSystemRequest::ClearNamespace { pid } => {
+                self.put_u8(11);
+                self.put_u32(*pid);
+            }
+            FileSystemRequest::Mount { source, target, fs_type, flags } => {
+                self.put_u8(12);
+                self.put_str(source);
+                self.put_str(target);
+                self.put_str(fs_type);
+                self.put_u32(*flags);
+            }
+            FileSystemRequest::Unmount { target } => {
+                self.put_u8(13);
+                self.put_str(target);
+            }
+            FileSystemRequest::Sync => self.put_u8(14),
+            FileSystemRequest::Seek { fd, offset, whence } => {
+                self.put_u8(15);
+                self.put_u32(*fd);
+                self.put_u64(*offset as u64);
+                self.put_u32(*whence as u32);
+            }
+            FileSystemRequest::Dup { fd } => {
+                self.put_u8(16);
+                self.put_u32(*fd);
+            }
+            FileSystemRequest::Symlink { target, path } => {
+                self.put_u8(17);
+                self.put_str(target);
+                self.put_str(path);
+            }
+            FileSystemRequest::Chdir { path } => {
+                self.put_u8(18);
+                self.put_str(path);
+            }
+            FileSystemRequest::Getcwd => self.put_u8(19),
+        }
+    }
+
+    fn put_driver_request(&mut self, request: &DriverRequest) {
+        match request {
+            DriverRequest::LoadDriver { path } => {
+                self.put_u8(0);
+                self.put_str(path);
+            }
+            DriverRequest::UnloadDriver { driver_id } => {
+                self.put_u8(1);
+                self.put_u32(*driver_id);
+            }
+            DriverRequest::ListDrivers => self.put_u8(2),
+            DriverRequest::SendToDriver { driver_id, data } => {
+                self.put_u8(3);
+                self.put_u32(*driver_id);
+                self.put_bytes(data);
+            }
+            DriverRequest::SetPowerPolicy { driver_id, low_power_after_ms, suspend_after_ms } => {
+                self.put_u8(4);
+                self.put_u32(*driver_id);
+                self.put_u64(*low_power_after_ms);
+                self.put_u64(*suspend_after_ms);
+            }
+            DriverRequest::HoldDriver { driver_id } => {
+                self.put_u8(5);
+                self.put_u32(*driver_id);
+            }
+            DriverRequest::ReleaseDriver { driver_id } => {
+                self.put_u8(6);
+                self.put_u32(*driver_id);
+            }
+            DriverRequest::SuspendAll => self.put_u8(7),
+            DriverRequest::ResumeAll => self.put_u8(8),
+        }
+    }
+
+    fn put_process_request(&mut self, request: &ProcessRequest) {
+        match request {
+            ProcessRequest::Spawn { program, args } => {
+                self.put_u8(0);
+                self.put_str(program);
+                self.put_u32(args.len() as u32);
+                for arg in args {
+                    self.put_str(arg);
+                }
+            }
+            ProcessRequest::Kill { pid } => {
+                self.put_u8(1);
+                self.put_u32(*pid);
+            }
+            ProcessRequest::List => self.put_u8(2),
+            ProcessRequest::GetInfo { pid } => {
+                self.put_u8(3);
+                self.put_u32(*pid);
+            }
+        }
+    }
+
+    fn put_input_request(&mut self, request: &InputRequest) {
+        match request {
+            InputRequest::Subscribe => self.put_u8(0),
+            InputRequest::Unsubscribe => self.put_u8(1),
+            InputRequest::SetFocus { pid } => {
+                self.put_u8(2);
+                self.put_u32(*pid);
+            }
+            InputRequest::GetFocus => self.put_u8(3),
+            InputRequest::SetKeyRepeat { delay_ms, interval_ms } => {
+                self.put_u8(4);
+                self.put_u32(*delay_ms);
+                self.put_u32(*interval_ms);
+            }
+            InputRequest::SetKeymap { name } => {
+                self.put_u8(5);
+                self.put_str(name);
+            }
+        }
+    }
+
+    fn put_display_request(&mut self, request: &DisplayRequest) {
+        match request {
+            DisplayRequest::CreateSurface { x, y, width, height, buffer } => {
+                self.put_u8(0);
+                self.put_u32(*x as u32);
+                self.put_u32(*y as u32);
+                self.put_u32(*width);
+                self.put_u32(*height);
+                self.buffer.extend_from_slice(&buffer.to_bytes());
+            }
+            DisplayRequest::DestroySurface { surface } => {
+                self.put_u8(1);
+                self.put_u32(*surface);
+            }
+            DisplayRequest::MoveSurface { surface, x, y } => {
+                self.put_u8(2);
+                self.put_u32(*surface);
+                self.put_u32(*x as u32);
+                self.put_u32(*y as u32);
+            }
+            DisplayRequest::RaiseSurface { surface } => {
+                self.put_u8(3);
+                self.put_u32(*surface);
+            }
+            DisplayRequest::Damage { surface, x, y, width, height } => {
+                self.put_u8(4);
+                self.put_u32(*surface);
+                self.put_u32(*x);
+                self.put_u32(*y);
+                self.put_u32(*width);
+                self.put_u32(*height);
+            }
+            DisplayRequest::GetDisplayInfo => self.put_u8(5),
+        }
+    }
+}
+
+/// Little-endian frame reader
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], WireError> {
+        let end = self.position.checked_add(count).ok_or(WireError::Truncated)?;
+        if end > self.bytes.len() {
+            return Err(WireError::Truncated);
+        }
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn get_u8(&mut self) -> Result<u8, WireError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn get_u32(&mut self) -> Result<u32, WireError> {
+        let mut raw = [0u8; 4];
+        raw.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(raw))
+    }
+
+    fn get_u64(&mut self) -> Result<u64, WireError> {
+        let mut raw = [0u8; 8];
+        raw.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(raw))
+    }
+
+    fn get_bool(&mut self) -> Result<bool, WireError> {
+        match self.get_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(WireError::InvalidTag(tag)),
+        }
+    }
+
+    fn get_bytes(&mut self) -> Result<Vec<u8>, WireError> {
+        let len = self.get_u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn get_string(&mut self) -> Result<String, WireError> {
+        let bytes = self.get_bytes()?;
+        String::from_utf8(bytes).map_err(|_| WireError::InvalidUtf8)
+    }
+
+    fn get_data(&mut self) -> Result<ServiceData, WireError> {
+        match self.get_u8()? {
+            0 => Ok(ServiceData::Empty),
+            1 => Ok(ServiceData::Text(self.get_string()?)),
+            2 => Ok(ServiceData::Binary(self.get_bytes()?)),
+            3 => Ok(ServiceData::FileSystemRequest(self.get_fs_request()?)),
+            4 => Ok(ServiceData::DriverRequest(self.get_driver_request()?)),
+            5 => Ok(ServiceData::ProcessRequest(self.get_process_request()?)),
+            6 => Ok(ServiceData::SharedBinary(self.get_shared_buffer()?)),
+            7 => Ok(ServiceData::InputRequest(self.get_input_request()?)),
+            8 => Ok(ServiceData::DisplayRequest(self.get_display_request()?)),
+            tag => Err(WireError::InvalidTag(tag)),
+        }
+    }
+
+    fn get_shared_buffer(&mut self) -> Result<SharedBuffer, WireError> {
+        let descriptor = self.take(SharedBuffer::ENCODED_SIZE)?;
+        SharedBuffer::from_bytes(descriptor).ok_or(WireError::Truncated)
+    }
+
+    fn get_fs_request(&mut self) -> Result<FileSystemRequest, WireError> {
+        match self.get_u8()? {
+            0 => Ok(FileSystemRequest::Open { path: self.get_string()?, flags: self.get_u32()? }),
+            1 => Ok(FileSystemRequest::Close { fd: self.get_u32()? }),
+            2 => Ok(FileSystemRequest::Read { fd: self.get_u32()?, size: self.get_u64()? as usize }),
+            3 => Ok(FileSystemRequest::Write { fd: self.get_u32()?, data: self.get_bytes()? }),
+            4 => Ok(FileSystemRequest::List { path: self.get_string()? }),
+            5 => Ok(FileSystemRequest::Create { path: self.get_string()?, is_directory: self.get_bool()? }),
+            6 => Ok(FileSystemRequest::Delete { path: self.get_string()? }),
+            7 => Ok(FileSystemRequest::ReadAt {
+                fd: self.get_u32()?,
+                offset: self.get_u64()?,
+                size: self.get_u64()? as usize,
+            }),
+            8 => Ok(FileSystemRequest::WriteAt {
+                fd: self.get_u32()?,
+                offset: self.get_u64()?,
+                data: self.get_bytes()?,
+            }),
+            9 => Ok(FileSystemRequest::Clone { source: self.get_string()?, destination: self.get_string()? }),
+            10 => {
+                let pid = self.get_u32()?;
+                let count = self.get_u32()? as usize;
+                let mut bindings = Vec::new();
+                for _ in 0..count {
+                    bindings.push((self.get_string()?, self.get_string()?));
+                }
+                Ok(FileSystemRequest::SetNamespace { pid, bindings })
+            }
+            11 => Ok(FileSystemRequest::ClearNamespace { pid: self.get_u32()? }),
+            12 => Ok(FileSystemRequest::Mount {
+                source: self.get_string()?,
+                target: self.get_string()?,
+                fs_type: self.get_string()?,
+                flags: self.get_u32()?,
+            }),
+            13 => Ok(FileSystemRequest::Unmount { target: self.get_string()? }),
+            14 => Ok(FileSystemRequest::Sync),
+            15 => Ok(FileSystemRequest::Seek {
+                fd: self.get_u32()?,
+                offset: self.get_u64()? as i64,
+                whence: self.get_u32()? as i32,
+            }),
+            16 => Ok(FileSystemRequest::Dup { fd: self.get_u32()? }),
+            17 => Ok(FileSystemRequest::Symlink { target: self.get_string()?, path: self.get_string()? }),
+            18 => Ok(FileSystemRequest::Chdir { path: self.get_string()? }),
+            19 => Ok(FileSystemRequest::Getcwd),
+            tag => Err(WireError::InvalidTag(tag)),
+        }
+    }
+
+    fn get_driver_request(&mut self) -> Result<DriverRequest, WireError> {
+        match self.get_u8()? {
+            0 => Ok(DriverRequest::LoadDriver { path: self.get_string()? }),
+            1 => Ok(DriverRequest::UnloadDriver { driver_id: self.get_u32()? }),
+            2 => Ok(DriverRequest::ListDrivers),
+            3 => Ok(DriverRequest::SendToDriver { driver_id: self.get_u32()?, data: self.get_bytes()? }),
+            4 => Ok(DriverRequest::SetPowerPolicy {
+                driver_id: self.get_u32()?,
+                low_power_after_ms: self.get_u64()?,
+                suspend_after_ms: self.get_u64()?,
+            }),
+            5 => Ok(DriverRequest::HoldDriver { driver_id: self.get_u32()? }),
+            6 => Ok(DriverRequest::ReleaseDriver { driver_id: self.get_u32()? }),
+            7 => Ok(DriverRequest::SuspendAll),
+            8 => Ok(DriverRequest::ResumeAll),
+            tag => Err(WireError::InvalidTag(tag)),
+        }
+    }
+
+    fn get_process_request(&mut self) -> Result<ProcessRequest, WireError> {
+        match self.get_u8()? {
+            0 => {
+                let program = self.get_string()?;
+                let count = self.get_u32()? as usize;
+                let mut args = Vec::new();
+                for _ in 0..count {
+                    args.push(self.get_string()?);
+                }
+                Ok(ProcessRequest::Spawn { program, args })
+            }
+            1 => Ok(ProcessRequest::Kill { pid: self.get_u32()? }),
+            2 => Ok(ProcessRequest::List),
+            3 => Ok(ProcessRequest::GetInfo { pid: self.get_u32()? }),
+            tag => Err(WireError::InvalidTag(tag)),
+        }
+    }
+
+    fn get_input_request(&mut self) -> Result<InputRequest, WireError> {
+        match self.get_u8()? {
+            0 => Ok(InputRequest::Subscribe),
+            1 => Ok(InputRequest::Unsubscribe),
+            2 => Ok(InputRequest::SetFocus { pid: self.get_u32()? }),
+            3 => Ok(InputRequest::GetFocus),
+            4 => Ok(InputRequest::SetKeyRepeat { delay_ms: self.get_u32()?, interval_ms: self.get_u32()? }),
+            5 => Ok(InputRequest::SetKeymap { name: self.get_string()? }),
+            tag => Err(WireError::InvalidTag(tag)),
+        }
+    }
+
+    fn get_display_request(&mut self) -> Result<DisplayRequest, WireError> {
+        match self.get_u8()? {
+            0 => Ok(DisplayRequest::CreateSurface {
+                x: self.get_u32()? as i32,
+                y: self.get_u32()? as i32,
+                width: self.get_u32()?,
+                height: self.get_u32()?,
+                buffer: self.get_shared_buffer()?,
+            }),
+            1 => Ok(DisplayRequest::DestroySurface { surface: self.get_u32()? }),
+            2 => Ok(DisplayRequest::MoveSurface {
+                surface: self.get_u32()?,
+                x: self.get_u32()? as i32,
+                y: self.get_u32()? as i32,
+            }),
+            3 => Ok(DisplayRequest::RaiseSurface { surface: self.get_u32()? }),
+            4 => Ok(DisplayRequest::Damage {
+                surface: self.get_u32()?,
+                x: self.get_u32()?,
+                y: self.get_u32()?,
+                width: self.get_u32()?,
+                height: self.get_u32()?,
+            }),
+            5 => Ok(DisplayRequest::GetDisplayInfo),
+            tag => Err(WireError::InvalidTag(tag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn buffer() -> SharedBuffer {
+        SharedBuffer { region_id: 0x1234_5678_9ABC, offset: 64, length: 4096 }
+    }
+
+    /// A payload of every kind, with every request variant
+    fn every_payload() -> Vec<ServiceData> {
+        let mut payloads = vec![
+            ServiceData::Empty,
+            ServiceData::Text("héllo".to_string()),
+            ServiceData::Binary(vec![0, 1, 2, 0xFF]),
+            ServiceData::SharedBinary(buffer()),
+        ];
+        let file_system = [
+            FileSystemRequest::Open { path: "/etc/motd".to_string(), flags: 0o102 },
+            FileSystemRequest::Close { fd: 3 },
+            FileSystemRequest::Read { fd: 3, size: 512 },
+            FileSystemRequest::Write { fd: 4, data: b"data".to_vec() },
+            FileSystemRequest::List { path: "/".to_string() },
+            FileSystemRequest::Create { path: "/tmp".to_string(), is_directory: true },
+            FileSystemRequest::Delete { path: "/tmp/old".to_string() },
+            FileSystemRequest::ReadAt { fd: 5, offset: 1 << 40, size: 64 },
+            FileSystemRequest::WriteAt { fd: 5, offset: 7, data: vec![9; 3] },
+            FileSystemRequest::Clone { source: "/a".to_string(), destination: "/b".to_string() },
+            FileSystemRequest::SetNamespace {
+                pid: 9,
+                bindings: vec![("/".to_string(), "/jail".to_string()), ("/dev".to_string(), "/dev".to_string())],
+            },
+            FileSystemRequest::ClearNamespace { pid: 9 },
+            FileSystemRequest::Mount {
+                source: "disk0".to_string(),
+                target: "/mnt".to_string(),
+                fs_type: "kfs".to_string(),
+                flags: 1,
+            },
+            FileSystemRequest::Unmount { target: "/mnt".to_string() },
+            FileSystemRequest::Sync,
+            FileSystemRequest::Seek { fd: 6, offset: -16, whence: 2 },
+            FileSystemRequest::Dup { fd: 6 },
+            FileSystemRequest::Symlink { target: "/bin/sh".to_string(), path: "/sh".to_string() },
+            FileSystemRequest::Chdir { path: "/home".to_string() },
+            FileSystemRequest::Getcwd,
+        ];
+        let driver = [
+            DriverRequest::LoadDriver { path: "/drivers/ac97".to_string() },
+            DriverRequest::UnloadDriver { driver_id: 2 },
+            DriverRequest::ListDrivers,
+            DriverRequest::SendToDriver { driver_id: 2, data: vec![1, 2] },
+            DriverRequest::SetPowerPolicy { driver_id: 2, low_power_after_ms: 500, suspend_after_ms: 30_000 },
+            DriverRequest::HoldDriver { driver_id: 2 },
+            DriverRequest::ReleaseDriver { driver_id: 2 },
+            DriverRequest::SuspendAll,
+            DriverRequest::ResumeAll,
+        ];
+        let process = [
+            ProcessRequest::Spawn { program: "/bin/ls".to_string(), args: vec!["-l".to_string(), "/".to_string()] },
+            ProcessRequest::Kill { pid: 12 },
+            ProcessRequest::List,
+            ProcessRequest::GetInfo { pid: 12 },
+        ];
+        let input = [
+            InputRequest::Subscribe,
+            InputRequest::Unsubscribe,
+            InputRequest::SetFocus { pid: 7 },
+            InputRequest::GetFocus,
+            InputRequest::SetKeyRepeat { delay_ms: 250, interval_ms: 33 },
+            InputRequest::SetKeymap { name: "de".to_string() },
+        ];
+        let display = [
+            DisplayRequest::CreateSurface { x: -20, y: 10, width: 640, height: 480, buffer: buffer() },
+            DisplayRequest::DestroySurface { surface: 1 },
+            DisplayRequest::MoveSurface { surface: 1, x: 5, y: -5 },
+            DisplayRequest::RaiseSurface { surface: 1 },
+            DisplayRequest::Damage { surface: 1, x: 0, y: 0, width: 32, height: 16 },
+            DisplayRequest::GetDisplayInfo,
+        ];
+        payloads.extend(file_system.into_iter().map(ServiceData::FileSystemRequest));
+        payloads.extend(driver.into_iter().map(ServiceData::DriverRequest));
+        payloads.extend(process.into_iter().map(ServiceData::ProcessRequest));
+        payloads.extend(input.into_iter().map(ServiceData::InputRequest));
+        payloads.extend(display.into_iter().map(ServiceData::DisplayRequest));
+        payloads
+    }
+
+    fn every_request() -> Vec<ServiceMessage> {
+        every_payload().into_iter().enumerate()
+            .map(|(index, data)| ServiceMessage {
+                service_type: service_type_from_tag(index as u8 % 8).unwrap(),
+                request_id: 0x0100_0000_0000 + index as u64,
+                data,
+                on_behalf_of: if index % 2 == 0 { None } else { Some(index as u32) },
+            })
+            .collect()
+    }
+
+    fn every_response() -> Vec<ServiceResponse> {
+        every_payload().into_iter().enumerate()
+            .map(|(index, data)| ServiceResponse {
+                request_id: u64::MAX - index as u64,
+                status: status_from_tag(index as u8 % 6).unwrap(),
+                data,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_requests_round_trip() {
+        for message in every_request() {
+            let frame = encode_message(&message);
+            assert_eq!(decode_message(&frame), Ok(message.clone()));
+            assert_eq!(peek_request_id(&frame), Ok(message.request_id));
+        }
+    }
+
+    #[test]
+    fn test_responses_round_trip() {
+        for response in every_response() {
+            let frame = encode_response(&response);
+            assert_eq!(decode_response(&frame), Ok(response.clone()));
+            assert_eq!(peek_request_id(&frame), Ok(response.request_id));
+        }
+    }
+
+    #[test]
+    fn test_truncated_frames_are_refused() {
+        for message in every_request() {
+            let frame = encode_message(&message);
+            for length in 0..frame.len() {
+                assert_eq!(decode_message(&frame[..length]), Err(WireError::Truncated), "{:?} cut to {}", message, length);
+            }
+        }
+        for response in every_response() {
+            let frame = encode_response(&response);
+            for length in 0..frame.len() {
+                assert_eq!(decode_response(&frame[..length]), Err(WireError::Truncated), "{:?} cut to {}", response, length);
+            }
+        }
+        assert_eq!(peek_request_id(&[FRAME_REQUEST, 1, 2, 3]), Err(WireError::Truncated));
+    }
+
+    #[test]
+    fn test_take_stays_within_the_input() {
+        let mut decoder = Decoder::new(&[1, 2, 3]);
+        assert_eq!(decoder.take(4), Err(WireError::Truncated));
+        assert_eq!(decoder.take(usize::MAX), Err(WireError::Truncated));
+        // A failed take consumes nothing
+        assert_eq!(decoder.take(2), Ok(&[1, 2][..]));
+        assert_eq!(decoder.take(2), Err(WireError::Truncated));
+        assert_eq!(decoder.take(1), Ok(&[3][..]));
+        assert_eq!(decoder.take(0), Ok(&[][..]));
+        assert_eq!(decoder.take(1), Err(WireError::Truncated));
+
+        // A length prefix larger than what follows
+        let mut decoder = Decoder::new(&[0xFF, 0xFF, 0xFF, 0xFF, b'a']);
+        assert_eq!(decoder.get_bytes(), Err(WireError::Truncated));
+    }
+
+    #[test]
+    fn test_malformed_frames_are_refused() {
+        let frame = encode_message(&every_request()[0]);
+        assert_eq!(decode_response(&frame), Err(WireError::UnexpectedFrame));
+        let frame = encode_response(&every_response()[0]);
+        assert_eq!(decode_message(&frame), Err(WireError::UnexpectedFrame));
+
+        let mut frame = encode_message(&ServiceMessage {
+            service_type: ServiceType::FileSystem,
+            request_id: 1,
+            data: ServiceData::Text("x".to_string()),
+            on_behalf_of: None,
+        });
+        // Kind, request ID, service type, then the on-behalf-of flag
+        frame[10] = 2;
+        assert_eq!(decode_message(&frame), Err(WireError::InvalidTag(2)));
+        frame[10] = 0;
+        frame[9] = 8;
+        assert_eq!(decode_message(&frame), Err(WireError::InvalidTag(8)));
+        frame[9] = 0;
+        frame[11] = 9;
+        assert_eq!(decode_message(&frame), Err(WireError::InvalidTag(9)));
+        frame[11] = 1;
+        frame[16] = 0xFF;
+        assert_eq!(decode_message(&frame), Err(WireError::InvalidUtf8));
+    }
+}
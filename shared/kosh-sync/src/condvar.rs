@@ -0,0 +1,54 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+use crate::futex::{futex_wait, futex_wake, FutexError, INFINITE};
+use crate::mutex::MutexGuard;
+
+/// A condition variable for use with `Mutex`
+///
+/// The word counts notifications. A waiter reads it before letting go of
+/// the mutex and sleeps only while it is unchanged, so a notification
+/// between the two is not lost.
+pub struct Condvar {
+    sequence: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self { sequence: AtomicU32::new(0) }
+    }
+
+    /// Unlock `guard`'s mutex, sleep until notified and lock it again
+    ///
+    /// Wakeups may be spurious, so wait in a loop that checks the condition.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.wait_timeout(guard, INFINITE).0
+    }
+
+    /// As `wait`, but for at most `timeout_ns`
+    ///
+    /// The flag returned is true if the timeout elapsed.
+    pub fn wait_timeout<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>, timeout_ns: u64) -> (MutexGuard<'a, T>, bool) {
+        let mutex = guard.mutex();
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        drop(guard);
+        let timed_out = futex_wait(&self.sequence, sequence, timeout_ns) == Err(FutexError::TimedOut);
+        (mutex.lock(), timed_out)
+    }
+
+    /// Wake one waiter
+    pub fn notify_one(&self) {
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        futex_wake(&self.sequence, 1);
+    }
+
+    /// Wake every waiter
+    pub fn notify_all(&self) {
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        futex_wake(&self.sequence, usize::MAX);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,66 @@
+use core::sync::atomic::AtomicU32;
+
+/// System call numbers (must match shared/kosh-syscall/src/numbers.rs)
+pub const SYS_FUTEX_WAIT: u64 = 85;
+pub const SYS_FUTEX_WAKE: u64 = 86;
+
+/// Timeout value that waits until woken
+pub const INFINITE: u64 = u64::MAX;
+
+/// Errno the kernel returns after blocking the caller; the call is repeated
+const EAGAIN: i64 = -11;
+const ETIMEDOUT: i64 = -110;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexError {
+    /// The timeout elapsed before a wake
+    TimedOut,
+    /// The kernel refused the word, e.g. because it is not mapped
+    Failed(i64),
+}
+
+/// Sleep while `word` holds `expected`
+///
+/// Returns once woken, or at once if the word holds another value.
+/// `timeout_ns` of `INFINITE` never times out. Callers recheck their
+/// condition afterwards, since a wake is only a hint that it changed.
+pub fn futex_wait(word: &AtomicU32, expected: u32, timeout_ns: u64) -> Result<(), FutexError> {
+    loop {
+        match syscall(SYS_FUTEX_WAIT, word.as_ptr() as u64, expected as u64, timeout_ns) {
+            EAGAIN => continue,
+            ETIMEDOUT => return Err(FutexError::TimedOut),
+            result if result < 0 => return Err(FutexError::Failed(result)),
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Wake up to `count` processes sleeping on `word`, returning how many woke
+pub fn futex_wake(word: &AtomicU32, count: usize) -> usize {
+    syscall(SYS_FUTEX_WAKE, word.as_ptr() as u64, count as u64, 0).max(0) as usize
+}
+
+#[cfg(target_os = "none")]
+fn syscall(number: u64, arg0: u64, arg1: u64, arg2: u64) -> i64 {
+    let result: i64;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") number,
+            in("rdi") arg0,
+            in("rsi") arg1,
+            in("rdx") arg2,
+            lateout("rax") result,
+            options(nostack, preserves_flags)
+        );
+    }
+    result
+}
+
+/// Built for a host, as for unit tests of the crates using this one,
+/// waits return at once and the callers spin
+#[cfg(not(target_os = "none"))]
+fn syscall(_number: u64, _arg0: u64, _arg1: u64, _arg2: u64) -> i64 {
+    core::hint::spin_loop();
+    0
+}
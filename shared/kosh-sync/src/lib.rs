@@ -0,0 +1,16 @@
+#![no_std]
+
+//! Blocking synchronization for userspace
+//!
+//! `Mutex` and `Condvar` keep their state in a 32-bit word and only enter
+//! the kernel through the futex calls when they have to sleep or wake a
+//! sleeper, so uncontended use costs no system call. A word in shared
+//! memory coordinates processes as well as the threads of one process.
+
+pub mod futex;
+pub mod mutex;
+pub mod condvar;
+
+pub use futex::{futex_wait, futex_wake, FutexError};
+pub use mutex::{Mutex, MutexGuard};
+pub use condvar::Condvar;
@@ -0,0 +1,118 @@
+use core::fmt;
+
+/// System call error types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallError {
+    /// Invalid system call number
+    InvalidSyscall,
+    /// Invalid argument provided to system call
+    InvalidArgument,
+    /// Permission denied
+    PermissionDenied,
+    /// Resource not found
+    NotFound,
+    /// Process not found
+    ProcessNotFound,
+    /// Resource already exists
+    AlreadyExists,
+    /// Operation not supported
+    NotSupported,
+    /// No memory available
+    OutOfMemory,
+    /// Resource temporarily unavailable
+    WouldBlock,
+    /// Operation interrupted
+    Interrupted,
+    /// Invalid file descriptor
+    BadFileDescriptor,
+    /// Broken pipe
+    BrokenPipe,
+    /// Address already in use
+    AddressInUse,
+    /// Connection refused
+    ConnectionRefused,
+    /// Operation timed out
+    TimedOut,
+    /// System resource exhausted
+    ResourceExhausted,
+    /// Internal kernel error
+    InternalError,
+    /// No child processes to wait for
+    NoChildren,
+    /// User pointer outside the caller's accessible memory
+    BadAddress,
+    /// Descriptor does not support seeking
+    IllegalSeek,
+    /// Per-process descriptor limit reached
+    TooManyOpenFiles,
+    /// Arguments and environment exceed the exec limits
+    ArgumentListTooLong,
+    /// The file is not a program image the kernel can run
+    NotExecutable,
+}
+
+impl SyscallError {
+    /// Convert system call error to errno value
+    pub fn to_errno(self) -> i32 {
+        match self {
+            SyscallError::InvalidSyscall => -1,      // EPERM equivalent
+            SyscallError::InvalidArgument => -22,    // EINVAL
+            SyscallError::PermissionDenied => -13,   // EACCES
+            SyscallError::NotFound => -2,            // ENOENT
+            SyscallError::ProcessNotFound => -3,     // ESRCH
+            SyscallError::AlreadyExists => -17,      // EEXIST
+            SyscallError::NotSupported => -95,       // EOPNOTSUPP
+            SyscallError::OutOfMemory => -12,        // ENOMEM
+            SyscallError::WouldBlock => -11,         // EAGAIN/EWOULDBLOCK
+            SyscallError::Interrupted => -4,         // EINTR
+            SyscallError::BadFileDescriptor => -9,   // EBADF
+            SyscallError::BrokenPipe => -32,         // EPIPE
+            SyscallError::AddressInUse => -98,       // EADDRINUSE
+            SyscallError::ConnectionRefused => -111, // ECONNREFUSED
+            SyscallError::TimedOut => -110,          // ETIMEDOUT
+            SyscallError::ResourceExhausted => -105, // ENOBUFS
+            SyscallError::InternalError => -5,       // EIO
+            SyscallError::NoChildren => -10,         // ECHILD
+            SyscallError::BadAddress => -14,         // EFAULT
+            SyscallError::IllegalSeek => -29,        // ESPIPE
+            SyscallError::TooManyOpenFiles => -24,   // EMFILE
+            SyscallError::ArgumentListTooLong => -7, // E2BIG
+            SyscallError::NotExecutable => -8,       // ENOEXEC
+        }
+    }
+    
+    /// Get a human-readable description of the error
+    pub fn description(self) -> &'static str {
+        match self {
+            SyscallError::InvalidSyscall => "Invalid system call number",
+            SyscallError::InvalidArgument => "Invalid argument",
+            SyscallError::PermissionDenied => "Permission denied",
+            SyscallError::NotFound => "Resource not found",
+            SyscallError::ProcessNotFound => "Process not found",
+            SyscallError::AlreadyExists => "Resource already exists",
+            SyscallError::NotSupported => "Operation not supported",
+            SyscallError::OutOfMemory => "Out of memory",
+            SyscallError::WouldBlock => "Resource temporarily unavailable",
+            SyscallError::Interrupted => "Operation interrupted",
+            SyscallError::BadFileDescriptor => "Bad file descriptor",
+            SyscallError::BrokenPipe => "Broken pipe",
+            SyscallError::AddressInUse => "Address already in use",
+            SyscallError::ConnectionRefused => "Connection refused",
+            SyscallError::TimedOut => "Operation timed out",
+            SyscallError::ResourceExhausted => "System resource exhausted",
+            SyscallError::InternalError => "Internal kernel error",
+            SyscallError::NoChildren => "No child processes",
+            SyscallError::BadAddress => "Bad address",
+            SyscallError::IllegalSeek => "Illegal seek",
+            SyscallError::TooManyOpenFiles => "Too many open files",
+            SyscallError::ArgumentListTooLong => "Argument list too long",
+            SyscallError::NotExecutable => "Exec format error",
+        }
+    }
+}
+
+impl fmt::Display for SyscallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
@@ -0,0 +1,72 @@
+//! Fuzz entry point for system call argument validation
+//!
+//! Every argument a process passes reaches the validators before anything
+//! else looks at it, so they must turn any value down cleanly. The entry
+//! point takes a call number, six arguments and the user memory the caller
+//! has mapped from the fuzzer's bytes, and panics only when validation
+//! does.
+
+use kosh_types::ProcessId;
+use crate::error::SyscallError;
+use crate::numbers::MAX_SYSCALL_NUMBER;
+use crate::validation::{check_syscall_args, check_user_bounds, UserMemory, HOST_LIMITS};
+
+/// A caller with one mapped region of user memory
+struct MappedMemory {
+    process: ProcessId,
+    start: u64,
+    end: u64,
+}
+
+impl UserMemory for MappedMemory {
+    fn process(&self) -> ProcessId {
+        self.process
+    }
+
+    fn check_range(&self, ptr: u64, len: usize, _write: bool) -> Result<(), SyscallError> {
+        check_user_bounds(ptr, len)?;
+        let end = ptr.checked_add(len as u64).ok_or(SyscallError::BadAddress)?;
+        if ptr < self.start || end > self.end {
+            return Err(SyscallError::BadAddress);
+        }
+        Ok(())
+    }
+
+    fn check_string(&self, ptr: u64, max_len: usize) -> Result<(), SyscallError> {
+        if ptr == 0 {
+            return Err(SyscallError::InvalidArgument);
+        }
+        // Strings end somewhere in the mapping, or run off its end
+        self.check_range(ptr, max_len.min(self.end.saturating_sub(ptr) as usize).max(1), false)
+    }
+}
+
+/// Read the `index`th little-endian word of `data`, zero past its end
+fn word(data: &[u8], index: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    for (offset, byte) in bytes.iter_mut().enumerate() {
+        *byte = data.get(index * 8 + offset).copied().unwrap_or(0);
+    }
+    u64::from_le_bytes(bytes)
+}
+
+/// Validate the call `data` describes: the call number, six arguments,
+/// then the caller's process ID and mapped region
+pub fn syscall_args(data: &[u8]) {
+    let syscall_number = word(data, 0) % (MAX_SYSCALL_NUMBER + 2);
+    let mut args = [0u64; 6];
+    for (index, arg) in args.iter_mut().enumerate() {
+        *arg = word(data, index + 1);
+    }
+    let start = word(data, 8);
+    let memory = MappedMemory {
+        process: word(data, 7) as ProcessId,
+        start,
+        end: start.saturating_add(word(data, 9) % (1 << 32)),
+    };
+
+    let result = check_syscall_args(&memory, &HOST_LIMITS, syscall_number, &args);
+    if syscall_number == 0 || syscall_number > MAX_SYSCALL_NUMBER {
+        assert_eq!(result, Err(SyscallError::InvalidSyscall), "call {} passed validation", syscall_number);
+    }
+}
@@ -0,0 +1,15 @@
+//! The system call interface between user space and the kernel
+//!
+//! Call numbers, the errors calls fail with and the checks made on their
+//! arguments before dispatch. None of it touches kernel state, so the
+//! argument checks build and run on the host as well.
+
+#![cfg_attr(not(feature = "fuzzing"), no_std)]
+
+pub mod error;
+pub mod numbers;
+pub mod validation;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+
+pub use error::SyscallError;
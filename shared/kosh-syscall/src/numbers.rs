@@ -0,0 +1,370 @@
+//! System call numbers for the Kosh operating system
+//! These numbers define the interface between user space and kernel space
+
+/// Process management system calls
+pub const SYS_EXIT: u64 = 1;
+pub const SYS_FORK: u64 = 2;
+pub const SYS_EXEC: u64 = 3;
+pub const SYS_WAIT: u64 = 4;
+pub const SYS_GETPID: u64 = 5;
+pub const SYS_GETPPID: u64 = 6;
+pub const SYS_KILL: u64 = 7;
+pub const SYS_YIELD: u64 = 8;
+pub const SYS_GETARGS: u64 = 9;
+
+/// Memory management system calls
+pub const SYS_MMAP: u64 = 10;
+pub const SYS_MUNMAP: u64 = 11;
+pub const SYS_MPROTECT: u64 = 12;
+pub const SYS_BRK: u64 = 13;
+pub const SYS_SBRK: u64 = 14;
+pub const SYS_SET_MEMORY_LIMIT: u64 = 15;
+
+/// Pipe and descriptor system calls
+pub const SYS_PIPE: u64 = 16;
+pub const SYS_DUP2: u64 = 17;
+
+/// File system system calls
+pub const SYS_OPEN: u64 = 20;
+pub const SYS_CLOSE: u64 = 21;
+pub const SYS_READ: u64 = 22;
+pub const SYS_WRITE: u64 = 23;
+pub const SYS_LSEEK: u64 = 24;
+pub const SYS_STAT: u64 = 25;
+pub const SYS_FSTAT: u64 = 26;
+pub const SYS_MKDIR: u64 = 27;
+pub const SYS_RMDIR: u64 = 28;
+pub const SYS_UNLINK: u64 = 29;
+
+/// IPC system calls
+pub const SYS_SEND_MESSAGE: u64 = 30;
+pub const SYS_RECEIVE_MESSAGE: u64 = 31;
+pub const SYS_REPLY_MESSAGE: u64 = 32;
+pub const SYS_CREATE_CHANNEL: u64 = 33;
+pub const SYS_DESTROY_CHANNEL: u64 = 34;
+pub const SYS_SHM_CREATE: u64 = 35;
+pub const SYS_SHM_MAP: u64 = 36;
+pub const SYS_SHM_UNMAP: u64 = 37;
+pub const SYS_SHM_GRANT: u64 = 38;
+pub const SYS_POLL: u64 = 39;
+
+/// Driver interface system calls
+pub const SYS_DRIVER_REGISTER: u64 = 40;
+pub const SYS_DRIVER_UNREGISTER: u64 = 41;
+pub const SYS_DRIVER_REQUEST: u64 = 42;
+pub const SYS_DRIVER_RESPONSE: u64 = 43;
+pub const SYS_DMA_SYNC: u64 = 44;
+pub const SYS_IRQ_REGISTER: u64 = 45;
+pub const SYS_IRQ_WAIT: u64 = 46;
+pub const SYS_IRQ_ACK: u64 = 47;
+pub const SYS_IRQ_UNREGISTER: u64 = 48;
+pub const SYS_DRIVER_TRUST: u64 = 49;
+
+/// `SYS_DRIVER_TRUST` result: the kernel was built with a driver signing key
+pub const TRUST_KEY_PROVISIONED: u64 = 1 << 0;
+/// `SYS_DRIVER_TRUST` result: the boot mode lets unsigned drivers load
+pub const TRUST_UNSIGNED_ALLOWED: u64 = 1 << 1;
+
+/// System information system calls
+pub const SYS_UNAME: u64 = 50;
+pub const SYS_SYSINFO: u64 = 51;
+pub const SYS_TIME: u64 = 52;
+pub const SYS_CLOCK_GETTIME: u64 = 53;
+pub const SYS_SCHED_INFO: u64 = 54;
+pub const SYS_SCHED_SET: u64 = 55;
+pub const SYS_PROCESS_LIST: u64 = 56;
+pub const SYS_PROCESS_STATUS: u64 = 57;
+pub const SYS_IPC_INFO: u64 = 58;
+pub const SYS_KLOG: u64 = 59;
+
+/// `SYS_SCHED_SET` argument that leaves the algorithm unchanged
+pub const SCHED_KEEP_ALGORITHM: u64 = u64::MAX;
+
+/// `SYS_KLOG` actions
+pub const KLOG_READ: u64 = 0;
+pub const KLOG_SIZE: u64 = 1;
+pub const KLOG_SET_LEVEL: u64 = 2;
+pub const KLOG_CRASH_READ: u64 = 3;
+pub const KLOG_CRASH_SIZE: u64 = 4;
+pub const KLOG_CRASH_CLEAR: u64 = 5;
+pub const KLOG_WRITE: u64 = 6;
+
+/// Longest line `KLOG_WRITE` takes, in bytes
+pub const KLOG_WRITE_MAX: usize = 256;
+
+/// Security and capability system calls
+pub const SYS_GRANT_CAPABILITY: u64 = 60;
+pub const SYS_REVOKE_CAPABILITY: u64 = 61;
+pub const SYS_CHECK_CAPABILITY: u64 = 62;
+pub const SYS_LIST_CAPABILITIES: u64 = 63;
+pub const SYS_AUDIT: u64 = 64;
+
+/// Hardware access system calls, gated by `DeviceAccess` grants
+pub const SYS_IO_READ: u64 = 70;
+pub const SYS_IO_WRITE: u64 = 71;
+pub const SYS_MMIO_MAP: u64 = 72;
+pub const SYS_DMA_ALLOC: u64 = 73;
+pub const SYS_DMA_FREE: u64 = 74;
+/// Allocate message-signaled interrupt lines for a PCI function
+pub const SYS_IRQ_ALLOC_MSI: u64 = 75;
+
+/// `SYS_MMIO_MAP` flag: map the registers writable
+pub const MMIO_MAP_WRITE: u64 = 1 << 0;
+
+/// Sleep and timer system calls
+pub const SYS_NANOSLEEP: u64 = 80;
+pub const SYS_TIMER_CREATE: u64 = 81;
+pub const SYS_TIMER_SET: u64 = 82;
+pub const SYS_TIMER_WAIT: u64 = 83;
+pub const SYS_TIMER_DELETE: u64 = 84;
+
+/// `SYS_TIMER_WAIT` flag: return 0 instead of blocking when nothing expired
+pub const TIMER_WAIT_NOHANG: u64 = 1 << 0;
+
+/// Futex system calls
+pub const SYS_FUTEX_WAIT: u64 = 85;
+pub const SYS_FUTEX_WAKE: u64 = 86;
+
+/// Thread system calls
+pub const SYS_THREAD_CREATE: u64 = 87;
+pub const SYS_THREAD_EXIT: u64 = 88;
+pub const SYS_THREAD_JOIN: u64 = 89;
+
+/// Scheduling class system calls
+pub const SYS_SCHED_SET_REALTIME: u64 = 90;
+
+/// CPU frequency scaling system calls
+pub const SYS_CPUFREQ_INFO: u64 = 91;
+pub const SYS_CPUFREQ_SET: u64 = 92;
+
+/// Battery and AC adapter system call
+pub const SYS_BATTERY_INFO: u64 = 93;
+
+/// Thermal zone system call
+pub const SYS_THERMAL_INFO: u64 = 94;
+
+/// Boot display mode system call
+pub const SYS_DISPLAY_INFO: u64 = 95;
+
+/// Random number system calls
+pub const SYS_GETRANDOM: u64 = 96;
+pub const SYS_ADD_ENTROPY: u64 = 97;
+
+/// `SYS_GETRANDOM` flags: fail with EAGAIN rather than block until the
+/// pool is seeded; accepted for compatibility, as there is one pool; hand
+/// out bytes even before the pool is seeded
+pub const GRND_NONBLOCK: u64 = 1 << 0;
+pub const GRND_RANDOM: u64 = 1 << 1;
+pub const GRND_INSECURE: u64 = 1 << 2;
+
+/// Most bytes one `SYS_GETRANDOM` returns; longer reads come back short
+pub const GETRANDOM_MAX_BYTES: u64 = 64 * 1024;
+
+/// Most bytes one `SYS_ADD_ENTROPY` takes
+pub const ADD_ENTROPY_MAX_BYTES: u64 = 4096;
+
+/// Kernel tracing system call
+pub const SYS_TRACE: u64 = 98;
+
+/// `SYS_TRACE` actions
+pub const TRACE_ENABLE: u64 = 0;
+pub const TRACE_DISABLE: u64 = 1;
+pub const TRACE_STATUS: u64 = 2;
+pub const TRACE_READ: u64 = 3;
+pub const TRACE_LOST: u64 = 4;
+
+/// Debug and testing system calls (only available in debug builds)
+#[cfg(debug_assertions)]
+pub const SYS_DEBUG_PRINT: u64 = 100;
+#[cfg(debug_assertions)]
+pub const SYS_DEBUG_DUMP: u64 = 101;
+
+/// `SYS_DEBUG_DUMP` types: start a heap leak window, print the blocks
+/// allocated since that are still live, print heap statistics by tag
+#[cfg(debug_assertions)]
+pub const DEBUG_DUMP_HEAP_MARK: u64 = 1;
+#[cfg(debug_assertions)]
+pub const DEBUG_DUMP_HEAP_LEAKS: u64 = 2;
+#[cfg(debug_assertions)]
+pub const DEBUG_DUMP_HEAP_STATS: u64 = 3;
+
+/// Integration test control; answers only when the kernel booted with
+/// `integration_tests=1`
+#[cfg(debug_assertions)]
+pub const SYS_TEST_CONTROL: u64 = 102;
+
+/// `SYS_TEST_CONTROL` actions: whether this is an integration test boot;
+/// end the run with a status, 0 when every test passed
+#[cfg(debug_assertions)]
+pub const TEST_CONTROL_MODE: u64 = 0;
+#[cfg(debug_assertions)]
+pub const TEST_CONTROL_EXIT: u64 = 1;
+
+/// Maximum system call number (for validation)
+#[cfg(debug_assertions)]
+pub const MAX_SYSCALL_NUMBER: u64 = 102;
+#[cfg(not(debug_assertions))]
+pub const MAX_SYSCALL_NUMBER: u64 = 98;
+
+/// Check if a system call number is valid
+pub fn is_valid_syscall_number(syscall_number: u64) -> bool {
+    syscall_number > 0 && syscall_number <= MAX_SYSCALL_NUMBER
+}
+
+/// Get the name of a system call for debugging purposes
+pub fn syscall_name(syscall_number: u64) -> &'static str {
+    match syscall_number {
+        SYS_EXIT => "exit",
+        SYS_FORK => "fork",
+        SYS_EXEC => "exec",
+        SYS_WAIT => "wait",
+        SYS_GETPID => "getpid",
+        SYS_GETPPID => "getppid",
+        SYS_KILL => "kill",
+        SYS_YIELD => "yield",
+        SYS_GETARGS => "getargs",
+        
+        SYS_MMAP => "mmap",
+        SYS_MUNMAP => "munmap",
+        SYS_MPROTECT => "mprotect",
+        SYS_BRK => "brk",
+        SYS_SBRK => "sbrk",
+        SYS_SET_MEMORY_LIMIT => "set_memory_limit",
+        
+        SYS_PIPE => "pipe",
+        SYS_DUP2 => "dup2",
+        
+        SYS_OPEN => "open",
+        SYS_CLOSE => "close",
+        SYS_READ => "read",
+        SYS_WRITE => "write",
+        SYS_LSEEK => "lseek",
+        SYS_STAT => "stat",
+        SYS_FSTAT => "fstat",
+        SYS_MKDIR => "mkdir",
+        SYS_RMDIR => "rmdir",
+        SYS_UNLINK => "unlink",
+        
+        SYS_SEND_MESSAGE => "send_message",
+        SYS_RECEIVE_MESSAGE => "receive_message",
+        SYS_REPLY_MESSAGE => "reply_message",
+        SYS_CREATE_CHANNEL => "create_channel",
+        SYS_DESTROY_CHANNEL => "destroy_channel",
+        SYS_SHM_CREATE => "shm_create",
+        SYS_SHM_MAP => "shm_map",
+        SYS_SHM_UNMAP => "shm_unmap",
+        SYS_SHM_GRANT => "shm_grant",
+        SYS_POLL => "poll",
+        
+        SYS_DRIVER_REGISTER => "driver_register",
+        SYS_DRIVER_UNREGISTER => "driver_unregister",
+        SYS_DRIVER_REQUEST => "driver_request",
+        SYS_DRIVER_RESPONSE => "driver_response",
+        SYS_DMA_SYNC => "dma_sync",
+        SYS_IRQ_REGISTER => "irq_register",
+        SYS_IRQ_WAIT => "irq_wait",
+        SYS_IRQ_ACK => "irq_ack",
+        SYS_IRQ_UNREGISTER => "irq_unregister",
+        SYS_DRIVER_TRUST => "driver_trust",
+        
+        SYS_UNAME => "uname",
+        SYS_SYSINFO => "sysinfo",
+        SYS_TIME => "time",
+        SYS_CLOCK_GETTIME => "clock_gettime",
+        SYS_SCHED_INFO => "sched_info",
+        SYS_SCHED_SET => "sched_set",
+        SYS_PROCESS_LIST => "process_list",
+        SYS_PROCESS_STATUS => "process_status",
+        SYS_IPC_INFO => "ipc_info",
+        SYS_KLOG => "klog",
+        
+        SYS_GRANT_CAPABILITY => "grant_capability",
+        SYS_REVOKE_CAPABILITY => "revoke_capability",
+        SYS_CHECK_CAPABILITY => "check_capability",
+        SYS_LIST_CAPABILITIES => "list_capabilities",
+        SYS_AUDIT => "audit",
+        
+        SYS_IO_READ => "io_read",
+        SYS_IO_WRITE => "io_write",
+        SYS_MMIO_MAP => "mmio_map",
+        SYS_DMA_ALLOC => "dma_alloc",
+        SYS_DMA_FREE => "dma_free",
+        SYS_IRQ_ALLOC_MSI => "irq_alloc_msi",
+        
+        SYS_NANOSLEEP => "nanosleep",
+        SYS_TIMER_CREATE => "timer_create",
+        SYS_TIMER_SET => "timer_set",
+        SYS_TIMER_WAIT => "timer_wait",
+        SYS_TIMER_DELETE => "timer_delete",
+        
+        SYS_FUTEX_WAIT => "futex_wait",
+        SYS_FUTEX_WAKE => "futex_wake",
+        
+        SYS_THREAD_CREATE => "thread_create",
+        SYS_THREAD_EXIT => "thread_exit",
+        SYS_THREAD_JOIN => "thread_join",
+        SYS_SCHED_SET_REALTIME => "sched_set_realtime",
+        
+        SYS_CPUFREQ_INFO => "cpufreq_info",
+        SYS_CPUFREQ_SET => "cpufreq_set",
+        SYS_BATTERY_INFO => "battery_info",
+        SYS_THERMAL_INFO => "thermal_info",
+        SYS_DISPLAY_INFO => "display_info",
+        SYS_GETRANDOM => "getrandom",
+        SYS_ADD_ENTROPY => "add_entropy",
+        SYS_TRACE => "trace",
+        
+        #[cfg(debug_assertions)]
+        SYS_DEBUG_PRINT => "debug_print",
+        #[cfg(debug_assertions)]
+        SYS_DEBUG_DUMP => "debug_dump",
+        #[cfg(debug_assertions)]
+        SYS_TEST_CONTROL => "test_control",
+        
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_valid_syscall_numbers() {
+        assert!(is_valid_syscall_number(SYS_EXIT));
+        assert!(is_valid_syscall_number(SYS_READ));
+        assert!(is_valid_syscall_number(SYS_SEND_MESSAGE));
+        assert!(is_valid_syscall_number(SYS_ADD_ENTROPY));
+        assert!(is_valid_syscall_number(SYS_TRACE));
+        assert!(!is_valid_syscall_number(0));
+        assert!(!is_valid_syscall_number(MAX_SYSCALL_NUMBER + 1));
+    }
+    
+    #[test]
+    fn test_syscall_names() {
+        assert_eq!(syscall_name(SYS_EXIT), "exit");
+        assert_eq!(syscall_name(SYS_READ), "read");
+        assert_eq!(syscall_name(SYS_SEND_MESSAGE), "send_message");
+        assert_eq!(syscall_name(SYS_IRQ_WAIT), "irq_wait");
+        assert_eq!(syscall_name(SYS_PIPE), "pipe");
+        assert_eq!(syscall_name(SYS_GETARGS), "getargs");
+        assert_eq!(syscall_name(SYS_KLOG), "klog");
+        assert_eq!(syscall_name(SYS_NANOSLEEP), "nanosleep");
+        assert_eq!(syscall_name(SYS_FUTEX_WAKE), "futex_wake");
+        assert_eq!(syscall_name(SYS_THREAD_JOIN), "thread_join");
+        assert_eq!(syscall_name(SYS_SCHED_SET_REALTIME), "sched_set_realtime");
+        assert_eq!(syscall_name(SYS_SET_MEMORY_LIMIT), "set_memory_limit");
+        assert_eq!(syscall_name(SYS_DRIVER_TRUST), "driver_trust");
+        assert_eq!(syscall_name(SYS_AUDIT), "audit");
+        assert_eq!(syscall_name(SYS_CPUFREQ_SET), "cpufreq_set");
+        assert_eq!(syscall_name(SYS_BATTERY_INFO), "battery_info");
+        assert_eq!(syscall_name(SYS_THERMAL_INFO), "thermal_info");
+        assert_eq!(syscall_name(SYS_DISPLAY_INFO), "display_info");
+        assert_eq!(syscall_name(SYS_GETRANDOM), "getrandom");
+        assert_eq!(syscall_name(SYS_ADD_ENTROPY), "add_entropy");
+        assert_eq!(syscall_name(SYS_TRACE), "trace");
+        #[cfg(debug_assertions)]
+        assert_eq!(syscall_name(SYS_TEST_CONTROL), "test_control");
+        assert_eq!(syscall_name(999), "unknown");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,1202 @@
+//! Checks on system call arguments, made before a call is dispatched
+//!
+//! The checks reach the caller's memory only through `UserMemory` and the
+//! kernel's limits only through `Limits`, so they build for the host: the
+//! kernel passes the calling process's address space, tests and the
+//! `syscall_args` fuzz target pass memory of their own.
+
+use kosh_types::ProcessId;
+use crate::error::SyscallError;
+use crate::numbers::*;
+
+/// User memory as argument validation sees it
+///
+/// The kernel checks pointers against the calling process's address space;
+/// tests and the fuzz target substitute memory that involves no process,
+/// so any argument can be thrown at the validators.
+pub trait UserMemory {
+    /// Process making the call
+    fn process(&self) -> ProcessId;
+
+    /// Check that `[ptr, ptr + len)` may be accessed
+    fn check_range(&self, ptr: u64, len: usize, write: bool) -> Result<(), SyscallError>;
+
+    /// Check for a NUL-terminated UTF-8 string of at most `max_len` bytes at `ptr`
+    fn check_string(&self, ptr: u64, max_len: usize) -> Result<(), SyscallError>;
+}
+
+/// What the validators need to know about the kernel they check calls for
+///
+/// Sizes are those of the records the kernel copies out, counts and flags
+/// those the kernel accepts, and the predicates answer whether a raw value
+/// names something the kernel knows.
+pub struct Limits {
+    pub page_size: u64,
+    /// Descriptors per process
+    pub max_fds: u64,
+    pub max_signal: u64,
+    pub max_shm_region_size: u64,
+    pub max_poll_entries: usize,
+    pub max_irq_lines: u64,
+    pub max_timer_id: u64,
+    /// Real-time priorities a process may be given
+    pub rt_priorities: core::ops::RangeInclusive<u64>,
+    /// Clocks `SYS_CLOCK_GETTIME` reads
+    pub clocks: &'static [u64],
+    /// Flags `SYS_WAIT` accepts
+    pub wait_flags: u64,
+    /// Flags `SYS_SEND_MESSAGE` accepts
+    pub message_flags: u64,
+    /// Protections a shared memory region may be mapped with
+    pub shm_prot: u64,
+    /// Every tracepoint `SYS_TRACE` can switch
+    pub tracepoints: u64,
+    pub public_key_len: usize,
+    pub poll_entry_size: usize,
+    pub sysinfo_size: usize,
+    pub process_status_size: usize,
+    pub ipc_info_size: usize,
+    pub timespec_size: usize,
+    pub sched_info_size: usize,
+    pub display_info_size: usize,
+    pub cpufreq_info_size: usize,
+    pub power_supply_info_size: usize,
+    pub thermal_zone_info_size: usize,
+    pub audit_record_size: usize,
+    pub trace_record_size: usize,
+    pub klog_level: fn(u64) -> bool,
+    pub scheduling_algorithm: fn(u64) -> bool,
+    pub time_slice: fn(u64) -> bool,
+    pub cpu_governor: fn(u64) -> bool,
+    pub capability_type: fn(u64) -> bool,
+}
+
+/// Validate the arguments of a call made with `memory`
+pub fn check_syscall_args(
+    memory: &dyn UserMemory,
+    limits: &Limits,
+    syscall_number: u64,
+    args: &[u64; 6],
+) -> Result<(), SyscallError> {
+    // Check if the system call number is valid
+    if !is_valid_syscall_number(syscall_number) {
+        return Err(SyscallError::InvalidSyscall);
+    }
+    
+    // Perform syscall-specific argument validation
+    match syscall_number {
+        SYS_EXIT => validate_exit_args(args),
+        SYS_FORK => validate_fork_args(args),
+        SYS_EXEC => validate_exec_args(memory, args),
+        SYS_WAIT => validate_wait_args(memory, limits, args),
+        SYS_GETPID | SYS_GETPPID | SYS_YIELD => validate_no_args(args),
+        SYS_GETARGS => validate_getargs_args(memory, args),
+        SYS_KILL => validate_kill_args(limits, args),
+        
+        SYS_MMAP => validate_mmap_args(limits, args),
+        SYS_MUNMAP => validate_munmap_args(args),
+        SYS_MPROTECT => validate_mprotect_args(args),
+        SYS_BRK | SYS_SBRK => validate_brk_args(args),
+        SYS_SET_MEMORY_LIMIT => validate_set_memory_limit_args(args),
+        
+        SYS_PIPE => memory.check_range(args[0], core::mem::size_of::<[i32; 2]>(), true),
+        SYS_DUP2 => validate_dup2_args(limits, args),
+        
+        SYS_OPEN => validate_open_args(memory, args),
+        SYS_CLOSE => validate_close_args(limits, args),
+        SYS_READ => validate_read_args(memory, limits, args),
+        SYS_WRITE => validate_write_args(memory, limits, args),
+        SYS_LSEEK => validate_lseek_args(limits, args),
+        SYS_STAT | SYS_FSTAT => validate_stat_args(memory, args),
+        SYS_MKDIR => validate_mkdir_args(memory, args),
+        SYS_RMDIR | SYS_UNLINK => validate_unlink_args(memory, args),
+        
+        SYS_SEND_MESSAGE => validate_send_message_args(memory, limits, args),
+        SYS_RECEIVE_MESSAGE => validate_receive_message_args(memory, args),
+        SYS_REPLY_MESSAGE => validate_reply_message_args(memory, args),
+        SYS_CREATE_CHANNEL => validate_create_channel_args(args),
+        SYS_DESTROY_CHANNEL => validate_destroy_channel_args(args),
+        SYS_SHM_CREATE => validate_shm_create_args(limits, args),
+        SYS_SHM_MAP => validate_shm_map_args(limits, args),
+        SYS_SHM_UNMAP => validate_shm_unmap_args(args),
+        SYS_SHM_GRANT => validate_shm_grant_args(memory, args),
+        SYS_POLL => validate_poll_args(memory, limits, args),
+        
+        SYS_DRIVER_REGISTER => validate_driver_register_args(memory, args),
+        SYS_DRIVER_UNREGISTER => validate_driver_unregister_args(memory, args),
+        SYS_DRIVER_REQUEST => validate_driver_request_args(memory, args),
+        SYS_DRIVER_RESPONSE => validate_driver_response_args(memory, args),
+        SYS_DMA_SYNC => validate_dma_sync_args(memory, args),
+        SYS_IRQ_REGISTER | SYS_IRQ_WAIT | SYS_IRQ_ACK | SYS_IRQ_UNREGISTER => validate_irq_args(limits, args),
+        SYS_DRIVER_TRUST => validate_user_pointer(memory, args[0], limits.public_key_len),
+        
+        SYS_UNAME => validate_info_args(args),
+        SYS_TIME => validate_time_args(memory, args),
+        SYS_SYSINFO => validate_user_pointer(memory, args[0], limits.sysinfo_size),
+        SYS_CLOCK_GETTIME => validate_clock_gettime_args(memory, limits, args),
+        SYS_SCHED_INFO => validate_sched_info_args(memory, limits, args),
+        SYS_SCHED_SET => validate_sched_set_args(limits, args),
+        SYS_PROCESS_LIST => validate_process_list_args(memory, args),
+        SYS_PROCESS_STATUS => validate_user_pointer(memory, args[1], limits.process_status_size),
+        SYS_IPC_INFO => validate_user_pointer(memory, args[0], limits.ipc_info_size),
+        SYS_KLOG => validate_klog_args(memory, limits, args),
+        
+        SYS_GRANT_CAPABILITY => validate_grant_capability_args(memory, limits, args),
+        SYS_REVOKE_CAPABILITY => validate_revoke_capability_args(memory, args),
+        SYS_CHECK_CAPABILITY => validate_check_capability_args(memory, limits, args),
+        SYS_LIST_CAPABILITIES => validate_list_capabilities_args(memory, args),
+        SYS_AUDIT => validate_audit_args(memory, limits, args),
+        
+        SYS_IO_READ | SYS_IO_WRITE => validate_io_port_args(args),
+        SYS_MMIO_MAP => validate_mmio_map_args(args),
+        SYS_DMA_ALLOC => validate_dma_alloc_args(memory, args),
+        SYS_DMA_FREE => validate_dma_free_args(limits, args),
+        SYS_IRQ_ALLOC_MSI => validate_irq_alloc_msi_args(memory, args),
+        
+        SYS_NANOSLEEP | SYS_TIMER_CREATE => Ok(()),
+        SYS_TIMER_SET | SYS_TIMER_WAIT | SYS_TIMER_DELETE => validate_timer_args(limits, args),
+        
+        SYS_FUTEX_WAIT | SYS_FUTEX_WAKE => validate_futex_args(memory, args),
+        
+        SYS_THREAD_CREATE => validate_thread_create_args(memory, args),
+        SYS_THREAD_EXIT => Ok(()),
+        SYS_THREAD_JOIN => validate_thread_join_args(memory, args),
+        SYS_SCHED_SET_REALTIME => validate_sched_set_realtime_args(limits, args),
+        
+        SYS_CPUFREQ_INFO => validate_cpufreq_info_args(memory, limits, args),
+        SYS_CPUFREQ_SET => validate_cpufreq_set_args(limits, args),
+        SYS_BATTERY_INFO => validate_battery_info_args(memory, limits, args),
+        SYS_THERMAL_INFO => validate_thermal_info_args(memory, limits, args),
+        SYS_DISPLAY_INFO => validate_display_info_args(memory, limits, args),
+        SYS_GETRANDOM => validate_getrandom_args(memory, args),
+        SYS_ADD_ENTROPY => validate_add_entropy_args(memory, args),
+        SYS_TRACE => validate_trace_args(memory, limits, args),
+        
+        #[cfg(debug_assertions)]
+        SYS_DEBUG_PRINT => validate_debug_print_args(args),
+        #[cfg(debug_assertions)]
+        SYS_DEBUG_DUMP => validate_debug_dump_args(args),
+        #[cfg(debug_assertions)]
+        SYS_TEST_CONTROL => validate_test_control_args(args),
+        
+        _ => Err(SyscallError::InvalidSyscall),
+    }
+}
+
+/// Lowest user address; page zero stays unmapped to catch null pointers
+pub const USER_SPACE_START: u64 = 0x1000;
+
+/// One past the highest user address (top of the canonical lower half)
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+/// Check that `[ptr, ptr + len)` lies in the user half of the address space
+pub fn check_user_bounds(ptr: u64, len: usize) -> Result<(), SyscallError> {
+    let end = ptr.checked_add(len as u64).ok_or(SyscallError::BadAddress)?;
+    if ptr < USER_SPACE_START || end > USER_SPACE_END {
+        return Err(SyscallError::BadAddress);
+    }
+    Ok(())
+}
+
+/// Validate that a pointer argument is valid for the given process
+fn validate_user_pointer(memory: &dyn UserMemory, ptr: u64, size: usize) -> Result<(), SyscallError> {
+    if ptr == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    memory.check_range(ptr, size, false)
+}
+
+/// Validate that a string pointer is valid and null-terminated
+fn validate_user_string(memory: &dyn UserMemory, ptr: u64, max_len: usize) -> Result<(), SyscallError> {
+    memory.check_string(ptr, max_len)
+}
+
+/// Validate file descriptor
+fn validate_file_descriptor(limits: &Limits, fd: u64) -> Result<(), SyscallError> {
+    // Descriptors index the per-process table
+    if fd >= limits.max_fds {
+        return Err(SyscallError::BadFileDescriptor);
+    }
+    Ok(())
+}
+
+// Process management syscall validations
+fn validate_exit_args(_args: &[u64; 6]) -> Result<(), SyscallError> {
+    // Exit code can be any value
+    Ok(())
+}
+
+fn validate_fork_args(_args: &[u64; 6]) -> Result<(), SyscallError> {
+    // Fork takes no arguments
+    Ok(())
+}
+
+fn validate_exec_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let path_ptr = args[0];
+    let argv_ptr = args[1];
+    let envp_ptr = args[2];
+    
+    validate_user_string(memory, path_ptr, 4096)?;
+    
+    if argv_ptr != 0 {
+        validate_user_pointer(memory, argv_ptr, 8)?; // At least one pointer
+    }
+    
+    if envp_ptr != 0 {
+        validate_user_pointer(memory, envp_ptr, 8)?; // At least one pointer
+    }
+    
+    Ok(())
+}
+
+fn validate_getargs_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let buf_ptr = args[0];
+    let len = args[1];
+    
+    // A null buffer asks for the size only
+    if buf_ptr != 0 && len > 0 {
+        memory.check_range(buf_ptr, len as usize, true)?;
+    }
+    Ok(())
+}
+
+fn validate_wait_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let status_ptr = args[0];
+    let options = args[1];
+    let target_pid = args[2];
+    
+    if options & !limits.wait_flags != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    if target_pid > u32::MAX as u64 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    // The status pointer is optional
+    if status_ptr != 0 {
+        validate_user_pointer(memory, status_ptr, core::mem::size_of::<i32>())?;
+    }
+    
+    Ok(())
+}
+
+fn validate_no_args(_args: &[u64; 6]) -> Result<(), SyscallError> {
+    // These syscalls take no arguments
+    Ok(())
+}
+
+fn validate_kill_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let pid = args[0];
+    let signal = args[1];
+    
+    if pid == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    // Validate signal number (0 only probes the target)
+    if signal > limits.max_signal {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+// Memory management syscall validations
+fn validate_mmap_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let _addr = args[0];
+    let length = args[1];
+    let prot = args[2];
+    let flags = args[3];
+    let fd = args[4];
+    let _offset = args[5];
+    
+    if length == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    // Validate protection flags
+    if prot > 7 {  // PROT_READ | PROT_WRITE | PROT_EXEC
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    // If mapping a file, validate file descriptor
+    if (flags & 0x01) == 0 && fd != u64::MAX {  // Not MAP_ANONYMOUS
+        validate_file_descriptor(limits, fd)?;
+    }
+    
+    Ok(())
+}
+
+fn validate_munmap_args(args: &[u64; 6]) -> Result<(), SyscallError> {
+    let addr = args[0];
+    let length = args[1];
+    
+    if addr == 0 || length == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_mprotect_args(args: &[u64; 6]) -> Result<(), SyscallError> {
+    let addr = args[0];
+    let length = args[1];
+    let prot = args[2];
+    
+    if addr == 0 || length == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    if prot > 7 {  // PROT_READ | PROT_WRITE | PROT_EXEC
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_brk_args(_args: &[u64; 6]) -> Result<(), SyscallError> {
+    // brk/sbrk can take any address value
+    Ok(())
+}
+
+fn validate_set_memory_limit_args(args: &[u64; 6]) -> Result<(), SyscallError> {
+    let pid = args[0];
+    
+    if pid == 0 || pid > u32::MAX as u64 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+// File system syscall validations
+fn validate_open_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let path_ptr = args[0];
+    let flags = args[1];
+    let _mode = args[2];
+    
+    validate_user_string(memory, path_ptr, 4096)?;
+    
+    // Basic flag validation
+    if flags > 0xFFFF {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_close_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let fd = args[0];
+    validate_file_descriptor(limits, fd)
+}
+
+fn validate_dup2_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    validate_file_descriptor(limits, args[0])?;
+    validate_file_descriptor(limits, args[1])
+}
+
+fn validate_read_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let fd = args[0];
+    let buf_ptr = args[1];
+    let count = args[2];
+    
+    validate_file_descriptor(limits, fd)?;
+    
+    // The kernel writes into the buffer
+    if count > 0 {
+        memory.check_range(buf_ptr, count as usize, true)?;
+    }
+    
+    Ok(())
+}
+
+fn validate_write_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let fd = args[0];
+    let buf_ptr = args[1];
+    let count = args[2];
+    
+    validate_file_descriptor(limits, fd)?;
+    
+    if count > 0 {
+        validate_user_pointer(memory, buf_ptr, count as usize)?;
+    }
+    
+    Ok(())
+}
+
+fn validate_lseek_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let fd = args[0];
+    let _offset = args[1];
+    let whence = args[2];
+    
+    validate_file_descriptor(limits, fd)?;
+    
+    // Validate whence parameter (SEEK_SET, SEEK_CUR, SEEK_END)
+    if whence > 2 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_stat_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let _path_or_fd = args[0];
+    let stat_buf_ptr = args[1];
+    
+    validate_user_pointer(memory, stat_buf_ptr, 144)?; // sizeof(struct stat)
+    
+    Ok(())
+}
+
+fn validate_mkdir_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let path_ptr = args[0];
+    let _mode = args[1];
+    
+    validate_user_string(memory, path_ptr, 4096)?;
+    
+    Ok(())
+}
+
+fn validate_unlink_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let path_ptr = args[0];
+    validate_user_string(memory, path_ptr, 4096)
+}
+
+// IPC syscall validations
+fn validate_send_message_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let receiver_pid = args[0];
+    let message_ptr = args[1];
+    let message_len = args[2];
+    let flags = args[3];
+    
+    if receiver_pid == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    if flags & !limits.message_flags != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    if message_len > 0 {
+        validate_user_pointer(memory, message_ptr, message_len as usize)?;
+    }
+    
+    Ok(())
+}
+
+fn validate_receive_message_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let buffer_ptr = args[0];
+    let buffer_len = args[1];
+    let sender_ptr = args[2];
+    
+    if buffer_len > 0 {
+        validate_user_pointer(memory, buffer_ptr, buffer_len as usize)?;
+    }
+    
+    let flags_ptr = args[3];
+    
+    if sender_ptr != 0 {
+        validate_user_pointer(memory, sender_ptr, 8)?;
+    }
+    
+    if flags_ptr != 0 {
+        validate_user_pointer(memory, flags_ptr, 8)?;
+    }
+    
+    Ok(())
+}
+
+fn validate_reply_message_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let message_id = args[0];
+    let reply_ptr = args[1];
+    let reply_len = args[2];
+    
+    if message_id == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    if reply_len > 0 {
+        validate_user_pointer(memory, reply_ptr, reply_len as usize)?;
+    }
+    
+    Ok(())
+}
+
+fn validate_create_channel_args(args: &[u64; 6]) -> Result<(), SyscallError> {
+    let other_pid = args[0];
+    
+    if other_pid == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_destroy_channel_args(args: &[u64; 6]) -> Result<(), SyscallError> {
+    let channel_id = args[0];
+    
+    if channel_id == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_shm_create_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let size = args[0];
+    
+    if size == 0 || size > limits.max_shm_region_size {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_shm_map_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let region_id = args[0];
+    let prot = args[1];
+    
+    if region_id == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    // Shared memory is never executable
+    if prot & !limits.shm_prot != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_shm_unmap_args(args: &[u64; 6]) -> Result<(), SyscallError> {
+    let region_id = args[0];
+    
+    if region_id == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_shm_grant_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let region_id = args[0];
+    let target_pid = args[1];
+    
+    if region_id == 0 || target_pid == 0 || target_pid == memory.process() as u64 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_poll_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let entries_ptr = args[0];
+    let entry_count = args[1] as usize;
+    
+    if entry_count == 0 || entry_count > limits.max_poll_entries {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    validate_user_pointer(
+        memory,
+        entries_ptr,
+        entry_count * limits.poll_entry_size,
+    )
+}
+
+// Driver interface syscall validations
+fn validate_driver_register_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let driver_info_ptr = args[0];
+    validate_user_pointer(memory, driver_info_ptr, 64) // Basic driver info struct size
+}
+
+fn validate_driver_unregister_args(_memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let driver_id = args[0];
+    
+    if driver_id == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_driver_request_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let driver_id = args[0];
+    let request_ptr = args[1];
+    let request_len = args[2];
+    
+    if driver_id == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    if request_len > 0 {
+        validate_user_pointer(memory, request_ptr, request_len as usize)?;
+    }
+    
+    Ok(())
+}
+
+fn validate_driver_response_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let request_id = args[0];
+    let response_ptr = args[1];
+    let response_len = args[2];
+    
+    if request_id == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    if response_len > 0 {
+        validate_user_pointer(memory, response_ptr, response_len as usize)?;
+    }
+    
+    Ok(())
+}
+
+fn validate_dma_sync_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let buffer_addr = args[0];
+    let length = args[1];
+    let direction = args[2];
+    
+    // Directions: 0 = to device, 1 = from device, 2 = bidirectional
+    if direction > 2 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    if length > 0 {
+        validate_user_pointer(memory, buffer_addr, length as usize)?;
+    }
+    
+    Ok(())
+}
+
+fn validate_irq_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let line = args[0];
+    
+    if line >= limits.max_irq_lines {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+// System information syscall validations
+fn validate_info_args(_args: &[u64; 6]) -> Result<(), SyscallError> {
+    // These syscalls typically take a buffer pointer
+    Ok(())
+}
+
+fn validate_time_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let time_ptr = args[0];
+    
+    // A null pointer only asks for the return value
+    if time_ptr == 0 {
+        return Ok(());
+    }
+    validate_user_pointer(memory, time_ptr, core::mem::size_of::<i64>())
+}
+
+fn validate_clock_gettime_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let clock_id = args[0];
+    let timespec_ptr = args[1];
+    
+    if !limits.clocks.contains(&clock_id) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    validate_user_pointer(memory, timespec_ptr, limits.timespec_size)
+}
+
+fn validate_sched_info_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let info_ptr = args[0];
+    
+    validate_user_pointer(memory, info_ptr, limits.sched_info_size)
+}
+
+fn validate_process_list_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let pids_ptr = args[0];
+    let max_count = args[1] as usize;
+    
+    // A zero-sized buffer only asks for the count
+    if max_count == 0 {
+        return Ok(());
+    }
+    let size = max_count.checked_mul(core::mem::size_of::<u32>()).ok_or(SyscallError::InvalidArgument)?;
+    validate_user_pointer(memory, pids_ptr, size)
+}
+
+fn validate_klog_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    match args[0] {
+        KLOG_READ | KLOG_CRASH_READ => {
+            let buf_ptr = args[1];
+            let len = args[2] as usize;
+            if len == 0 {
+                return Ok(());
+            }
+            validate_user_pointer(memory, buf_ptr, len)
+        }
+        KLOG_WRITE => {
+            let text_ptr = args[1];
+            let len = args[2] as usize;
+            if len > KLOG_WRITE_MAX {
+                return Err(SyscallError::InvalidArgument);
+            }
+            if len == 0 {
+                return Ok(());
+            }
+            validate_user_pointer(memory, text_ptr, len)
+        }
+        KLOG_SIZE | KLOG_CRASH_SIZE | KLOG_CRASH_CLEAR => Ok(()),
+        KLOG_SET_LEVEL if (limits.klog_level)(args[1]) => Ok(()),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+fn validate_sched_set_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let algorithm = args[0];
+    let time_slice_ms = args[1];
+    
+    if algorithm != SCHED_KEEP_ALGORITHM && !(limits.scheduling_algorithm)(algorithm) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    if time_slice_ms != 0 && !(limits.time_slice)(time_slice_ms) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    // Either setting may be left unchanged, but not both
+    if algorithm == SCHED_KEEP_ALGORITHM && time_slice_ms == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_sched_set_realtime_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let pid = args[0];
+    let priority = args[1];
+    
+    if pid == 0 || pid > u32::MAX as u64 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    // 0 leaves the real-time class
+    if priority != 0 && !limits.rt_priorities.contains(&priority) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_cpufreq_info_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let info_ptr = args[0];
+    
+    validate_user_pointer(memory, info_ptr, limits.cpufreq_info_size)
+}
+
+fn validate_cpufreq_set_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    if !(limits.cpu_governor)(args[0]) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    Ok(())
+}
+
+fn validate_battery_info_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let info_ptr = args[0];
+    
+    validate_user_pointer(memory, info_ptr, limits.power_supply_info_size)
+}
+
+fn validate_thermal_info_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let zones_ptr = args[0];
+    let max_count = args[1] as usize;
+    
+    // A zero-sized buffer only asks for the count
+    if max_count == 0 {
+        return Ok(());
+    }
+    let size = max_count.checked_mul(limits.thermal_zone_info_size)
+        .ok_or(SyscallError::InvalidArgument)?;
+    validate_user_pointer(memory, zones_ptr, size)
+}
+
+fn validate_display_info_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let info_ptr = args[0];
+    
+    validate_user_pointer(memory, info_ptr, limits.display_info_size)
+}
+
+fn validate_getrandom_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let buf_ptr = args[0];
+    let len = args[1].min(GETRANDOM_MAX_BYTES) as usize;
+    let flags = args[2];
+    
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    if len > 0 {
+        memory.check_range(buf_ptr, len, true)?;
+    }
+    Ok(())
+}
+
+fn validate_add_entropy_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let data_ptr = args[0];
+    let len = args[1];
+    
+    if len > ADD_ENTROPY_MAX_BYTES {
+        return Err(SyscallError::InvalidArgument);
+    }
+    if len > 0 {
+        validate_user_pointer(memory, data_ptr, len as usize)?;
+    }
+    Ok(())
+}
+
+// Security syscall validations
+fn validate_grant_capability_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let target_pid = args[0];
+    let capability_type = args[1];
+    let resource_ptr = args[2];
+    let flags = args[3];
+    
+    if target_pid == 0 || flags & !kosh_ipc::capability::GRANT_DELEGATE != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    if !(limits.capability_type)(capability_type) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    if resource_ptr != 0 {
+        validate_user_pointer(memory, resource_ptr, kosh_ipc::capability::ResourceDescriptor::SIZE)?;
+    }
+    
+    Ok(())
+}
+
+fn validate_revoke_capability_args(_memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let target_pid = args[0];
+    let capability_id = args[1];
+    
+    if target_pid == 0 || capability_id == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_check_capability_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let capability_type = args[0];
+    let resource_ptr = args[1];
+    
+    if !(limits.capability_type)(capability_type) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    if resource_ptr != 0 {
+        validate_user_pointer(memory, resource_ptr, kosh_ipc::capability::ResourceDescriptor::SIZE)?;
+    }
+    
+    Ok(())
+}
+
+fn validate_list_capabilities_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let buf_ptr = args[0];
+    let max_count = args[1] as usize;
+    
+    // A zero-sized buffer only asks for the count
+    if max_count == 0 {
+        return Ok(());
+    }
+    let size = max_count.checked_mul(kosh_ipc::capability::CapabilityRecord::SIZE).ok_or(SyscallError::InvalidArgument)?;
+    validate_user_pointer(memory, buf_ptr, size)
+}
+
+fn validate_audit_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let buf_ptr = args[0];
+    let max_count = args[1] as usize;
+    
+    if max_count == 0 {
+        return Ok(());
+    }
+    let size = max_count.checked_mul(limits.audit_record_size).ok_or(SyscallError::InvalidArgument)?;
+    validate_user_pointer(memory, buf_ptr, size)
+}
+
+fn validate_trace_args(memory: &dyn UserMemory, limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    match args[0] {
+        TRACE_ENABLE | TRACE_DISABLE if args[1] & !limits.tracepoints == 0 => Ok(()),
+        TRACE_STATUS | TRACE_LOST => Ok(()),
+        TRACE_READ => {
+            let max_count = args[2] as usize;
+            if max_count == 0 {
+                return Ok(());
+            }
+            let size = max_count.checked_mul(limits.trace_record_size).ok_or(SyscallError::InvalidArgument)?;
+            validate_user_pointer(memory, args[1], size)
+        }
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+// Hardware access syscall validations
+fn validate_io_port_args(args: &[u64; 6]) -> Result<(), SyscallError> {
+    let port = args[0];
+    let width = args[1];
+    
+    if !matches!(width, 1 | 2 | 4) || port.checked_add(width).is_none_or(|end| end > 0x1_0000) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_mmio_map_args(args: &[u64; 6]) -> Result<(), SyscallError> {
+    let physical = args[0];
+    let size = args[1];
+    let flags = args[2];
+    
+    if size == 0 || physical.checked_add(size).is_none() || flags & !MMIO_MAP_WRITE != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+fn validate_dma_alloc_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let size = args[0];
+    let cache_mode = args[1];
+    let device_address_ptr = args[2];
+    
+    // Cache modes: 0 = cached, 1 = uncached
+    if size == 0 || cache_mode > 1 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    validate_user_pointer(memory, device_address_ptr, core::mem::size_of::<u64>())
+}
+
+fn validate_irq_alloc_msi_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let requester = args[0];
+    let allocation_ptr = args[3];
+    
+    if requester > u16::MAX as u64 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    validate_user_pointer(memory, allocation_ptr, core::mem::size_of::<kosh_ipc::irq::MsiAllocation>())
+}
+
+fn validate_dma_free_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let address = args[0];
+    
+    if !address.is_multiple_of(limits.page_size) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+// Timer syscall validations
+fn validate_timer_args(limits: &Limits, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let timer_id = args[0];
+    
+    if timer_id == 0 || timer_id > limits.max_timer_id {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    Ok(())
+}
+
+// Futex syscall validations
+fn validate_futex_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let address = args[0];
+    
+    if !address.is_multiple_of(core::mem::size_of::<u32>() as u64) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    // Faults the word's page in if it has not been touched yet
+    validate_user_pointer(memory, address, core::mem::size_of::<u32>())
+}
+
+// Thread syscall validations
+fn validate_thread_create_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let entry = args[0];
+    let stack_top = args[1];
+    
+    if !(USER_SPACE_START..USER_SPACE_END).contains(&entry) {
+        return Err(SyscallError::BadAddress);
+    }
+    
+    // The new thread pushes onto the stack right away
+    let frame = core::mem::size_of::<u64>() * 2;
+    let stack_frame = stack_top.checked_sub(frame as u64).ok_or(SyscallError::BadAddress)?;
+    memory.check_range(stack_frame, frame, true)
+}
+
+fn validate_thread_join_args(memory: &dyn UserMemory, args: &[u64; 6]) -> Result<(), SyscallError> {
+    let tid = args[0];
+    let status_ptr = args[1];
+    
+    if tid == 0 || tid > u32::MAX as u64 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    
+    // The status pointer is optional
+    if status_ptr != 0 {
+        validate_user_pointer(memory, status_ptr, core::mem::size_of::<i32>())?;
+    }
+    
+    Ok(())
+}
+
+// Debug syscall validations (only in debug builds)
+#[cfg(debug_assertions)]
+fn validate_debug_print_args(_args: &[u64; 6]) -> Result<(), SyscallError> {
+    // Debug print can take any arguments
+    Ok(())
+}
+
+#[cfg(debug_assertions)]
+fn validate_debug_dump_args(_args: &[u64; 6]) -> Result<(), SyscallError> {
+    // Debug dump can take any arguments
+    Ok(())
+}
+
+#[cfg(debug_assertions)]
+fn validate_test_control_args(args: &[u64; 6]) -> Result<(), SyscallError> {
+    match args[0] {
+        TEST_CONTROL_MODE | TEST_CONTROL_EXIT => Ok(()),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+
+/// Limits shaped like the kernel's, for checking calls away from it
+#[cfg(any(test, feature = "fuzzing"))]
+pub(crate) const HOST_LIMITS: Limits = Limits {
+    page_size: 4096,
+    max_fds: 256,
+    max_signal: 64,
+    max_shm_region_size: 64 * 1024 * 1024,
+    max_poll_entries: 64,
+    max_irq_lines: 96,
+    max_timer_id: u32::MAX as u64,
+    rt_priorities: 1..=99,
+    clocks: &[0, 1],
+    wait_flags: 1,
+    message_flags: 1,
+    shm_prot: 0x3,
+    tracepoints: 0x7E,
+    public_key_len: 32,
+    poll_entry_size: 16,
+    sysinfo_size: 64,
+    process_status_size: 64,
+    ipc_info_size: 48,
+    timespec_size: 16,
+    sched_info_size: 32,
+    display_info_size: 32,
+    cpufreq_info_size: 40,
+    power_supply_info_size: 32,
+    thermal_zone_info_size: 48,
+    audit_record_size: 48,
+    trace_record_size: 32,
+    klog_level: |raw| raw <= 5,
+    scheduling_algorithm: |raw| raw <= 2,
+    time_slice: |raw| (1..=1000).contains(&raw),
+    cpu_governor: |raw| (1..=5).contains(&raw),
+    capability_type: |raw| raw <= 13,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Memory where all of the user half is mapped, so only the argument
+    /// checks themselves can turn a call down
+    struct FlatMemory;
+
+    impl UserMemory for FlatMemory {
+        fn process(&self) -> ProcessId {
+            1
+        }
+
+        fn check_range(&self, ptr: u64, len: usize, _write: bool) -> Result<(), SyscallError> {
+            check_user_bounds(ptr, len)
+        }
+
+        fn check_string(&self, ptr: u64, _max_len: usize) -> Result<(), SyscallError> {
+            if ptr == 0 {
+                return Err(SyscallError::InvalidArgument);
+            }
+            check_user_bounds(ptr, 1)
+        }
+    }
+
+    /// Argument values near the edges the validators check
+    const EDGES: [u64; 10] = [
+        0, 1, 7, 0xFFFF, USER_SPACE_START, USER_SPACE_END - 1, USER_SPACE_END,
+        u32::MAX as u64, i64::MAX as u64, u64::MAX,
+    ];
+
+    #[test]
+    fn test_random_syscall_args() {
+        // xorshift64, seeded so a failure repeats
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for round in 0..20_000u64 {
+            let syscall_number = round % MAX_SYSCALL_NUMBER + 1;
+            let mut args = [0u64; 6];
+            for arg in args.iter_mut() {
+                let random = next();
+                *arg = match random % 4 {
+                    0 => EDGES[(random >> 8) as usize % EDGES.len()],
+                    1 => random >> 40,
+                    2 => USER_SPACE_START + (random >> 20) % (USER_SPACE_END - USER_SPACE_START),
+                    _ => random,
+                };
+            }
+            // Any answer will do, as long as validation does not panic
+            let _ = check_syscall_args(&FlatMemory, &HOST_LIMITS, syscall_number, &args);
+        }
+    }
+
+    #[test]
+    fn test_validate_file_descriptor() {
+        assert!(validate_file_descriptor(&HOST_LIMITS, 0).is_ok());
+        assert!(validate_file_descriptor(&HOST_LIMITS, 255).is_ok());
+        assert_eq!(validate_file_descriptor(&HOST_LIMITS, 256), Err(SyscallError::BadFileDescriptor));
+    }
+
+    #[test]
+    fn test_io_port_range_does_not_overflow() {
+        assert!(validate_io_port_args(&[0x60, 1, 0, 0, 0, 0]).is_ok());
+        assert_eq!(validate_io_port_args(&[0xFFFF, 2, 0, 0, 0, 0]), Err(SyscallError::InvalidArgument));
+        assert_eq!(validate_io_port_args(&[u64::MAX, 4, 0, 0, 0, 0]), Err(SyscallError::InvalidArgument));
+    }
+
+    #[test]
+    fn test_shm_grant_to_self_is_refused() {
+        assert!(validate_shm_grant_args(&FlatMemory, &[5, 2, 0, 0, 0, 0]).is_ok());
+        assert_eq!(validate_shm_grant_args(&FlatMemory, &[5, 1, 0, 0, 0, 0]), Err(SyscallError::InvalidArgument));
+    }
+
+    #[test]
+    fn test_klog_write_is_bounded() {
+        let line = |len: u64| [KLOG_WRITE, USER_SPACE_START, len, 0, 0, 0];
+        assert!(validate_klog_args(&FlatMemory, &HOST_LIMITS, &line(KLOG_WRITE_MAX as u64)).is_ok());
+        assert_eq!(validate_klog_args(&FlatMemory, &HOST_LIMITS, &line(KLOG_WRITE_MAX as u64 + 1)), Err(SyscallError::InvalidArgument));
+        assert!(validate_klog_args(&FlatMemory, &HOST_LIMITS, &[KLOG_WRITE, 0, 0, 0, 0, 0]).is_ok());
+    }
+
+    #[test]
+    fn test_unknown_syscall_is_refused() {
+        let args = [0; 6];
+        assert!(check_syscall_args(&FlatMemory, &HOST_LIMITS, SYS_GETPID, &args).is_ok());
+        assert_eq!(check_syscall_args(&FlatMemory, &HOST_LIMITS, 0, &args), Err(SyscallError::InvalidSyscall));
+        assert_eq!(check_syscall_args(&FlatMemory, &HOST_LIMITS, MAX_SYSCALL_NUMBER + 1, &args), Err(SyscallError::InvalidSyscall));
+    }
+}
@@ -101,6 +101,16 @@ bitflags! {
     }
 }
 
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MountFlags: u32 {
+        /// Refuse every write through the mount
+        const READ_ONLY = 1 << 0;
+        /// Show a directory of an existing mount instead of mounting a device
+        const BIND = 1 << 1;
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FileMetadata {
     pub inode: InodeNumber,
@@ -129,6 +139,11 @@ pub enum VfsError {
     InvalidFileDescriptor,
     NotMounted,
     MountPointBusy,
+    NotSupported,
+    DirectoryNotEmpty,
+    InvalidArgument,
+    /// A path went through too many symbolic links
+    SymlinkLoop,
 }
 
 #[derive(Debug, Clone)]
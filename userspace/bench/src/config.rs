@@ -0,0 +1,102 @@
+//! Command line of `kosh-bench`
+//!
+//! `kosh-bench [syscall|ipc|switch|all] [-n SAMPLES]`
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// Samples taken for each benchmark unless `-n` says otherwise
+pub const DEFAULT_SAMPLES: usize = 1000;
+
+/// Most samples `-n` accepts, bounding the memory results take
+pub const MAX_SAMPLES: usize = 100_000;
+
+/// IPC payload sizes measured, in bytes
+pub const PAYLOAD_SIZES: [usize; 5] = [0, 64, 512, 1024, 4096];
+
+pub const USAGE: &str = "usage: kosh-bench [syscall|ipc|switch|all] [-n SAMPLES]";
+
+/// A group of benchmarks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Suite {
+    /// System call round trip
+    Syscall,
+    /// IPC latency and throughput per payload size
+    Ipc,
+    /// Switching between two processes
+    Switch,
+}
+
+impl Suite {
+    pub const ALL: [Suite; 3] = [Suite::Syscall, Suite::Ipc, Suite::Switch];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Suite::Syscall => "syscall",
+            Suite::Ipc => "ipc",
+            Suite::Switch => "switch",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchConfig {
+    /// Suites to run, in order, each once
+    pub suites: Vec<Suite>,
+    pub samples: usize,
+}
+
+/// Parse the arguments after the program name
+pub fn parse_args<S: AsRef<str>>(args: &[S]) -> Result<BenchConfig, String> {
+    let mut suites = Vec::new();
+    let mut samples = DEFAULT_SAMPLES;
+    let mut args = args.iter().map(AsRef::as_ref);
+    while let Some(arg) = args.next() {
+        match arg {
+            "-n" => {
+                let value = args.next().ok_or_else(|| "-n needs a sample count".to_string())?;
+                samples = match value.parse() {
+                    Ok(count) if (1..=MAX_SAMPLES).contains(&count) => count,
+                    _ => return Err(format!("bad sample count {}, expected 1 to {}", value, MAX_SAMPLES)),
+                };
+            }
+            "all" => suites.extend(Suite::ALL),
+            name => match Suite::ALL.into_iter().find(|suite| suite.name() == name) {
+                Some(suite) => suites.push(suite),
+                None => return Err(format!("unknown benchmark {}\n{}", name, USAGE)),
+            },
+        }
+    }
+    if suites.is_empty() {
+        suites.extend(Suite::ALL);
+    }
+    let mut seen = Vec::new();
+    suites.retain(|suite| {
+        let first = !seen.contains(suite);
+        seen.push(*suite);
+        first
+    });
+    Ok(BenchConfig { suites, samples })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_parse_args() {
+        let none: [&str; 0] = [];
+        assert_eq!(parse_args(&none), Ok(BenchConfig { suites: Suite::ALL.to_vec(), samples: DEFAULT_SAMPLES }));
+        assert_eq!(
+            parse_args(&["ipc", "-n", "50"]),
+            Ok(BenchConfig { suites: vec![Suite::Ipc], samples: 50 })
+        );
+        assert_eq!(parse_args(&["switch", "syscall"]).unwrap().suites, [Suite::Switch, Suite::Syscall]);
+        assert_eq!(parse_args(&["ipc", "all"]).unwrap().suites, [Suite::Ipc, Suite::Syscall, Suite::Switch]);
+        assert!(parse_args(&["-n", "0"]).is_err());
+        assert!(parse_args(&["-n"]).is_err());
+        assert!(parse_args(&["disk"]).is_err());
+    }
+}
@@ -0,0 +1,17 @@
+//! Benchmarks of the kernel paths redesigns are judged by
+//!
+//! `kosh-bench` times, from user mode, the system call round trip, IPC
+//! messages of several payload sizes between two processes, and the cost
+//! of switching between processes, and prints each as percentiles. The
+//! kernel's `bench` feature times the same paths from inside the kernel,
+//! which separates their own cost from traps and scheduling.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod stats;
+pub mod config;
+
+pub use stats::{format_summary, throughput_mb_s, Summary};
+pub use config::{parse_args, BenchConfig, Suite};
@@ -0,0 +1,281 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use kosh_bench::config::PAYLOAD_SIZES;
+use kosh_bench::{format_summary, parse_args, throughput_mb_s, BenchConfig, Suite, Summary};
+use kosh_ipc::capability::{self, CapabilityKind, ResourceDescriptor};
+use kosh_ipc::poll::wait_for_message;
+use kosh_ipc::syscall::{receive_message, send_message, MAX_MESSAGE_SIZE};
+use kosh_ipc::IpcError;
+use kosh_posix::errno::Errno;
+use kosh_posix::Fd;
+
+// Global allocator setup
+use linked_list_allocator::LockedHeap;
+
+#[global_allocator]
+static ALLOCATOR: LockedHeap = LockedHeap::empty();
+
+/// System calls timed together in one sample, so reading the clock, itself
+/// a system call, adds little to each
+const SYSCALL_BATCH: u64 = 64;
+
+/// Messages sent before waiting for the receiver to acknowledge them; few
+/// enough that the largest never fill its queue
+const IPC_WINDOW: usize = 8;
+
+/// How long the sender waits for an acknowledgement, and the receiver for
+/// the next message, before giving up on the other
+const PEER_TIMEOUT_MS: u64 = 2000;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    init_heap();
+
+    let args = kosh_posix::argv().unwrap_or_default();
+    let config = match parse_args(args.get(1..).unwrap_or(&[])) {
+        Ok(config) => config,
+        Err(message) => {
+            print(&format!("{}\n", message));
+            kosh_posix::exit(2);
+        }
+    };
+
+    print(&format!("kosh-bench: {} samples per benchmark\n", config.samples));
+    let mut status = 0;
+    for &suite in &config.suites {
+        let result = match suite {
+            Suite::Syscall => bench_syscall(&config),
+            Suite::Ipc => bench_ipc(&config),
+            Suite::Switch => bench_switch(&config),
+        };
+        if let Err(message) = result {
+            print(&format!("{}: {}\n", suite.name(), message));
+            status = 1;
+        }
+    }
+    kosh_posix::exit(status)
+}
+
+/// Round trip of the cheapest system call there is
+fn bench_syscall(config: &BenchConfig) -> Result<(), String> {
+    let mut samples = Vec::with_capacity(config.samples);
+    for _ in 0..config.samples {
+        let start = now_ns()?;
+        for _ in 0..SYSCALL_BATCH {
+            core::hint::black_box(kosh_posix::getpid());
+        }
+        samples.push(now_ns()?.saturating_sub(start) / SYSCALL_BATCH);
+    }
+    report("syscall getpid", &mut samples);
+    Ok(())
+}
+
+/// Messages from this process to a child, per payload size
+///
+/// A sample is the time per message over a window of `IPC_WINDOW` sends
+/// and the child's acknowledgement of them, so it covers copying into and
+/// out of the kernel as well as waking the receiver.
+fn bench_ipc(config: &BenchConfig) -> Result<(), String> {
+    let parent = kosh_posix::getpid();
+    let child = kosh_posix::fork().map_err(|errno| format!("fork failed: {}", errno))?;
+    if child == 0 {
+        ipc_receiver(parent, PAYLOAD_SIZES.len() * config.samples * IPC_WINDOW);
+    }
+
+    // The child only ever waits for the first message until these are in place
+    let result = connect(parent, child).and_then(|()| ipc_sender(child, config));
+    if result.is_err() {
+        let _ = kosh_posix::kill(child, kosh_posix::SIGKILL);
+    }
+    let _ = kosh_posix::waitpid(child, 0);
+    result
+}
+
+/// Give this process and its child `child` the capabilities to message
+/// each other
+fn connect(parent: u32, child: u32) -> Result<(), String> {
+    let grants = [
+        (child, CapabilityKind::SendMessage, parent),
+        (child, CapabilityKind::ReceiveMessage, parent),
+        (parent, CapabilityKind::SendMessage, child),
+        (parent, CapabilityKind::ReceiveMessage, child),
+    ];
+    for (holder, kind, peer) in grants {
+        capability::grant(holder, kind, &ResourceDescriptor::process(peer)).map_err(|error| {
+            format!("granting process {} {:?} for process {} failed: {:?}", holder, kind, peer, error)
+        })?;
+    }
+    Ok(())
+}
+
+fn ipc_sender(child: u32, config: &BenchConfig) -> Result<(), String> {
+    let mut ack = vec![0u8; MAX_MESSAGE_SIZE];
+    for size in PAYLOAD_SIZES {
+        let payload = vec![0xA5u8; size];
+        let mut samples = Vec::with_capacity(config.samples);
+        let started = now_ns()?;
+        for _ in 0..config.samples {
+            let start = now_ns()?;
+            for _ in 0..IPC_WINDOW {
+                send(child, &payload)?;
+            }
+            wait_for_ack(child, &mut ack)?;
+            samples.push(now_ns()?.saturating_sub(start) / IPC_WINDOW as u64);
+        }
+        let elapsed = now_ns()?.saturating_sub(started);
+
+        report(&format!("ipc {} bytes", size), &mut samples);
+        if size > 0 {
+            let bytes = (size * IPC_WINDOW * config.samples) as u64;
+            print(&format!("{:<20} {} MB/s\n", "", throughput_mb_s(bytes, elapsed)));
+        }
+    }
+    Ok(())
+}
+
+fn send(receiver: u32, payload: &[u8]) -> Result<(), String> {
+    loop {
+        match send_message(receiver, payload) {
+            Ok(()) => return Ok(()),
+            Err(IpcError::ChannelFull) => kosh_posix::sched_yield(),
+            Err(error) => return Err(format!("send failed: {:?}", error)),
+        }
+    }
+}
+
+fn wait_for_ack(child: u32, buffer: &mut [u8]) -> Result<(), String> {
+    loop {
+        match receive_message(buffer) {
+            Ok((sender, _)) if sender == child => return Ok(()),
+            // Not ours; whoever sent it is not part of the benchmark
+            Ok(_) => continue,
+            Err(IpcError::WouldBlock) => match wait_for_message(PEER_TIMEOUT_MS) {
+                Ok(true) => continue,
+                Ok(false) => return Err(String::from("the receiver stopped acknowledging messages")),
+                Err(error) => return Err(format!("poll failed: {:?}", error)),
+            },
+            Err(error) => return Err(format!("receive failed: {:?}", error)),
+        }
+    }
+}
+
+/// Child side of `bench_ipc`: take `messages` messages, acknowledging
+/// every window of them
+fn ipc_receiver(parent: u32, messages: usize) -> ! {
+    let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+    let mut received = 0;
+    while received < messages {
+        match receive_message(&mut buffer) {
+            Ok((sender, _)) if sender == parent => {
+                received += 1;
+                if received % IPC_WINDOW == 0 && send(parent, &[]).is_err() {
+                    kosh_posix::exit(1);
+                }
+            }
+            Ok(_) => {}
+            Err(IpcError::WouldBlock) => {
+                if !matches!(wait_for_message(PEER_TIMEOUT_MS), Ok(true)) {
+                    kosh_posix::exit(1);
+                }
+            }
+            Err(_) => kosh_posix::exit(1),
+        }
+    }
+    kosh_posix::exit(0)
+}
+
+/// Switching between two processes, by passing a byte back and forth over
+/// a pair of pipes
+///
+/// Each round trip blocks and wakes each process once, so a sample is half
+/// of it: one switch plus a pipe read and write. On more than one CPU the
+/// child may run elsewhere, and the figure becomes a cross-CPU wakeup.
+fn bench_switch(config: &BenchConfig) -> Result<(), String> {
+    let pipe = || kosh_posix::pipe().map_err(|errno| format!("pipe failed: {}", errno));
+    let (to_child_read, to_child_write) = pipe()?;
+    let (to_parent_read, to_parent_write) = pipe()?;
+    let child = kosh_posix::fork().map_err(|errno| format!("fork failed: {}", errno))?;
+    if child == 0 {
+        let _ = kosh_posix::close(to_child_write);
+        let _ = kosh_posix::close(to_parent_read);
+        switch_echo(to_child_read, to_parent_write);
+    }
+    let _ = kosh_posix::close(to_child_read);
+    let _ = kosh_posix::close(to_parent_write);
+
+    let mut samples = Vec::with_capacity(config.samples);
+    let result = ping_pong(to_child_write, to_parent_read, config.samples, &mut samples);
+    // Closing the pipe ends the child's loop
+    let _ = kosh_posix::close(to_child_write);
+    let _ = kosh_posix::close(to_parent_read);
+    let _ = kosh_posix::waitpid(child, 0);
+    result?;
+
+    report("switch (pipe)", &mut samples);
+    Ok(())
+}
+
+fn ping_pong(to_child: Fd, from_child: Fd, rounds: usize, samples: &mut Vec<u64>) -> Result<(), String> {
+    let failed = |errno: Errno| format!("pipe transfer failed: {}", errno);
+    let mut byte = [0u8; 1];
+    for _ in 0..rounds {
+        let start = now_ns()?;
+        kosh_posix::write_all(to_child, &byte).map_err(failed)?;
+        if kosh_posix::read(from_child, &mut byte).map_err(failed)? != 1 {
+            return Err(String::from("the child closed its pipe"));
+        }
+        samples.push(now_ns()?.saturating_sub(start) / 2);
+    }
+    Ok(())
+}
+
+/// Child side of `bench_switch`: send back each byte until the parent
+/// closes its end
+fn switch_echo(from_parent: Fd, to_parent: Fd) -> ! {
+    let mut byte = [0u8; 1];
+    while matches!(kosh_posix::read(from_parent, &mut byte), Ok(1)) {
+        if kosh_posix::write_all(to_parent, &byte).is_err() {
+            kosh_posix::exit(1);
+        }
+    }
+    kosh_posix::exit(0)
+}
+
+fn report(name: &str, samples: &mut [u64]) {
+    if let Some(summary) = Summary::of(samples) {
+        print(&format_summary(name, &summary));
+    }
+}
+
+fn now_ns() -> Result<u64, String> {
+    let time = kosh_posix::clock_gettime(kosh_posix::CLOCK_MONOTONIC)
+        .map_err(|errno| format!("clock_gettime failed: {}", errno))?;
+    Ok(time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64)
+}
+
+fn print(text: &str) {
+    let _ = kosh_posix::write_all(kosh_posix::STDOUT_FILENO, text.as_bytes());
+}
+
+fn init_heap() {
+    const HEAP_SIZE: usize = 256 * 1024;
+    static mut HEAP_MEMORY: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+
+    unsafe {
+        let heap_ptr = core::ptr::addr_of_mut!(HEAP_MEMORY);
+        ALLOCATOR.lock().init((*heap_ptr).as_mut_ptr(), HEAP_SIZE);
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    print("kosh-bench: panicked\n");
+    kosh_posix::exit(1);
+}
@@ -0,0 +1,87 @@
+//! Percentile statistics over timing samples
+
+use alloc::format;
+use alloc::string::String;
+
+/// Percentiles of a set of samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub count: usize,
+    pub min: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+    pub mean: u64,
+}
+
+impl Summary {
+    /// Summarize `samples`, which are sorted in place
+    pub fn of(samples: &mut [u64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let total: u128 = samples.iter().map(|&sample| sample as u128).sum();
+        Some(Self {
+            count: samples.len(),
+            min: samples[0],
+            p50: percentile(samples, 50),
+            p90: percentile(samples, 90),
+            p99: percentile(samples, 99),
+            max: samples[samples.len() - 1],
+            mean: (total / samples.len() as u128) as u64,
+        })
+    }
+}
+
+/// Nearest-rank percentile of sorted, non-empty `samples`
+pub fn percentile(samples: &[u64], percent: usize) -> u64 {
+    let rank = (samples.len() * percent).div_ceil(100).max(1);
+    samples[rank - 1]
+}
+
+/// One line of results, samples in nanoseconds
+pub fn format_summary(name: &str, summary: &Summary) -> String {
+    format!(
+        "{:<20} n {:>5}  min {:>7}  p50 {:>7}  p90 {:>7}  p99 {:>7}  max {:>9}  mean {:>7} ns\n",
+        name, summary.count, summary.min, summary.p50, summary.p90, summary.p99, summary.max, summary.mean
+    )
+}
+
+/// Megabytes per second for `bytes` moved in `elapsed_ns`
+pub fn throughput_mb_s(bytes: u64, elapsed_ns: u64) -> u64 {
+    if elapsed_ns == 0 {
+        return 0;
+    }
+    // Bytes per nanosecond is thousands of megabytes per second
+    (bytes as u128 * 1000 / elapsed_ns as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_summary_percentiles() {
+        let mut samples: Vec<u64> = (1..=100).rev().collect();
+        let summary = Summary::of(&mut samples).unwrap();
+        assert_eq!(summary, Summary { count: 100, min: 1, p50: 50, p90: 90, p99: 99, max: 100, mean: 50 });
+        assert!(Summary::of(&mut []).is_none());
+    }
+
+    #[test]
+    fn test_percentile_of_few_samples() {
+        assert_eq!(percentile(&[5], 0), 5);
+        assert_eq!(percentile(&[5], 99), 5);
+        assert_eq!(percentile(&[1, 2, 3], 50), 2);
+        assert_eq!(percentile(&[1, 2, 3], 99), 3);
+    }
+
+    #[test]
+    fn test_throughput() {
+        assert_eq!(throughput_mb_s(4096 * 1000, 1_000_000), 4096);
+        assert_eq!(throughput_mb_s(4096, 0), 0);
+    }
+}
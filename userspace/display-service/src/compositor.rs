@@ -0,0 +1,566 @@
+//! Compositing and focus
+//!
+//! Surfaces are kept bottom to top. Each composite redraws the damaged
+//! rectangles a line at a time: the background, then every surface over
+//! it, lowest first. Surfaces are opaque; one fully covered is still read,
+//! which keeps compositing simple at the cost of some copying.
+//!
+//! Input goes to the owner of the surface with focus. Key events follow
+//! focus alone; a click or a touch first gives focus to the surface under
+//! it and raises it. Events wait in a bounded queue until they are sent,
+//! as they do in the input service.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use kosh_driver::input::{self, InputRecord, MouseButtons, TouchEventType};
+use kosh_ipc::{IpcError, SharedBuffer};
+use kosh_service::{DisplayRequest, ServiceData, ServiceStatus};
+use kosh_types::ProcessId;
+use crate::damage::{DamageList, Rect};
+use crate::output::Output;
+use crate::surface::{Surface, SurfaceId, SurfaceMemory, MAX_SURFACE_SIZE};
+
+/// Most surfaces shown at once
+pub const MAX_SURFACES: usize = 64;
+
+/// Most events waiting to be sent; older ones are dropped
+pub const MAX_PENDING_EVENTS: usize = 256;
+
+/// What shows where no surface is
+pub const BACKGROUND: u32 = 0x0030_3840;
+
+/// Touch coordinates span 0 to this over the whole screen
+const TOUCH_RANGE: u32 = 65536;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayError {
+    /// No such surface
+    NotFound,
+    /// The surface belongs to another process
+    PermissionDenied,
+    /// Size out of range, or memory too small for it
+    InvalidRequest,
+    TooManySurfaces,
+}
+
+impl DisplayError {
+    pub fn status(self) -> ServiceStatus {
+        match self {
+            DisplayError::NotFound => ServiceStatus::NotFound,
+            DisplayError::PermissionDenied => ServiceStatus::PermissionDenied,
+            DisplayError::InvalidRequest => ServiceStatus::InvalidRequest,
+            DisplayError::TooManySurfaces => ServiceStatus::Error,
+        }
+    }
+}
+
+/// Shows client surfaces on an output and routes input to them
+pub struct Compositor {
+    output: Box<dyn Output>,
+    /// Bottom to top
+    surfaces: Vec<Surface>,
+    next_id: SurfaceId,
+    focus: Option<SurfaceId>,
+    damage: DamageList,
+    background: u32,
+    pointer: (i32, i32),
+    buttons: MouseButtons,
+    pending: VecDeque<(ProcessId, InputRecord)>,
+}
+
+impl Compositor {
+    /// Show surfaces on `output`, whose whole screen is drawn first
+    pub fn new(output: Box<dyn Output>) -> Self {
+        let (width, height) = output.size();
+        let mut damage = DamageList::new();
+        damage.add(Rect::new(0, 0, width, height));
+        Self {
+            output,
+            surfaces: Vec::new(),
+            next_id: 1,
+            focus: None,
+            damage,
+            background: BACKGROUND,
+            pointer: ((width / 2) as i32, (height / 2) as i32),
+            buttons: MouseButtons::empty(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// The whole screen
+    pub fn screen(&self) -> Rect {
+        let (width, height) = self.output.size();
+        Rect::new(0, 0, width, height)
+    }
+
+    /// Surfaces, bottom to top
+    pub fn surfaces(&self) -> &[Surface] {
+        &self.surfaces
+    }
+
+    /// The surface with focus
+    pub fn focus(&self) -> Option<SurfaceId> {
+        self.focus
+    }
+
+    pub fn pointer(&self) -> (i32, i32) {
+        self.pointer
+    }
+
+    pub fn set_background(&mut self, color: u32) {
+        self.background = color;
+        self.damage.add(self.screen());
+    }
+
+    /// Whether anything is waiting to be composited
+    pub fn is_damaged(&self) -> bool {
+        !self.damage.is_empty()
+    }
+
+    /// Where surface `id` of `owner` is in the stack
+    fn position(&self, owner: ProcessId, id: SurfaceId) -> Result<usize, DisplayError> {
+        let index = self.surfaces.iter().position(|surface| surface.id == id).ok_or(DisplayError::NotFound)?;
+        if self.surfaces[index].owner != owner {
+            return Err(DisplayError::PermissionDenied);
+        }
+        Ok(index)
+    }
+
+    /// Show a `rect`-sized surface of `owner`'s at `rect`'s position, on
+    /// top and with focus
+    pub fn create_surface(&mut self, owner: ProcessId, rect: Rect, memory: Box<dyn SurfaceMemory>) -> Result<SurfaceId, DisplayError> {
+        if self.surfaces.len() >= MAX_SURFACES {
+            return Err(DisplayError::TooManySurfaces);
+        }
+        let id = self.next_id;
+        let surface = Surface::new(id, owner, rect, memory).ok_or(DisplayError::InvalidRequest)?;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        self.surfaces.push(surface);
+        self.damage.add(rect);
+        self.focus = Some(id);
+        Ok(id)
+    }
+
+    pub fn destroy_surface(&mut self, owner: ProcessId, id: SurfaceId) -> Result<(), DisplayError> {
+        let index = self.position(owner, id)?;
+        self.remove(index);
+        Ok(())
+    }
+
+    fn remove(&mut self, index: usize) {
+        let surface = self.surfaces.remove(index);
+        self.damage.add(surface.rect);
+        if self.focus == Some(surface.id) {
+            // Focus passes to the surface now on top
+            self.focus = self.surfaces.last().map(|surface| surface.id);
+        }
+    }
+
+    pub fn move_surface(&mut self, owner: ProcessId, id: SurfaceId, x: i32, y: i32) -> Result<(), DisplayError> {
+        let index = self.position(owner, id)?;
+        let surface = &mut self.surfaces[index];
+        let previous = surface.rect;
+        surface.rect = Rect::new(x, y, previous.width, previous.height);
+        let moved = surface.rect;
+        self.damage.add(previous);
+        self.damage.add(moved);
+        Ok(())
+    }
+
+    /// Put a surface on top and give it focus
+    pub fn raise_surface(&mut self, owner: ProcessId, id: SurfaceId) -> Result<(), DisplayError> {
+        let index = self.position(owner, id)?;
+        self.raise(index);
+        Ok(())
+    }
+
+    fn raise(&mut self, index: usize) {
+        let surface = self.surfaces.remove(index);
+        self.focus = Some(surface.id);
+        if index != self.surfaces.len() {
+            self.damage.add(surface.rect);
+        }
+        self.surfaces.push(surface);
+    }
+
+    /// Note that the owner drew into `rect` of a surface, in the
+    /// surface's own coordinates
+    pub fn damage_surface(&mut self, owner: ProcessId, id: SurfaceId, rect: Rect) -> Result<(), DisplayError> {
+        let index = self.position(owner, id)?;
+        let surface = &self.surfaces[index];
+        let bounds = Rect::new(0, 0, surface.rect.width, surface.rect.height);
+        if let Some(drawn) = rect.intersect(&bounds) {
+            self.damage.add(drawn.offset(surface.rect.x, surface.rect.y));
+        }
+        Ok(())
+    }
+
+    /// Drop every surface of `pid` and the input waiting for it, as when
+    /// it exits
+    pub fn release_process(&mut self, pid: ProcessId) {
+        while let Some(index) = self.surfaces.iter().position(|surface| surface.owner == pid) {
+            self.remove(index);
+        }
+        self.pending.retain(|&(owner, _)| owner != pid);
+    }
+
+    /// Draw the damaged parts of the screen, returning them
+    pub fn compose(&mut self) -> Vec<Rect> {
+        let screen = self.screen();
+        let damaged: Vec<Rect> = self.damage.take().iter().filter_map(|rect| rect.intersect(&screen)).collect();
+        for rect in &damaged {
+            let mut line = vec![0u32; rect.width as usize];
+            for y in rect.y..rect.bottom() as i32 {
+                line.fill(self.background);
+                let span = Rect::new(rect.x, y, rect.width, 1);
+                for surface in &self.surfaces {
+                    if let Some(visible) = surface.rect.intersect(&span) {
+                        let start = (visible.x - rect.x) as usize;
+                        surface.read_line(
+                            (visible.x - surface.rect.x) as u32,
+                            (y - surface.rect.y) as u32,
+                            &mut line[start..start + visible.width as usize],
+                        );
+                    }
+                }
+                self.output.write_line(rect.x as u32, y as u32, &line);
+            }
+            self.output.flush(*rect);
+        }
+        damaged
+    }
+
+    /// Index of the topmost surface under `x`, `y`
+    fn surface_at(&self, x: i32, y: i32) -> Option<usize> {
+        self.surfaces.iter().rposition(|surface| surface.rect.contains_point(x, y))
+    }
+
+    /// Give focus to the surface under `x`, `y`, if there is one
+    fn focus_at(&mut self, x: i32, y: i32) {
+        if let Some(index) = self.surface_at(x, y) {
+            self.raise(index);
+        }
+    }
+
+    /// Queue an event for the owner of the surface with focus
+    pub fn route(&mut self, record: InputRecord) {
+        match &record {
+            InputRecord::Mouse(event) => {
+                let (width, height) = self.output.size();
+                let x = self.pointer.0.saturating_add(event.dx).clamp(0, width.saturating_sub(1) as i32);
+                let y = self.pointer.1.saturating_add(event.dy).clamp(0, height.saturating_sub(1) as i32);
+                self.pointer = (x, y);
+                let pressed = event.buttons.difference(self.buttons);
+                self.buttons = event.buttons;
+                if !pressed.is_empty() {
+                    self.focus_at(x, y);
+                }
+            }
+            InputRecord::Touch(event) if event.event_type == TouchEventType::Down => {
+                let (width, height) = self.output.size();
+                let x = (event.x as u64 * width as u64 / TOUCH_RANGE as u64) as i32;
+                let y = (event.y as u64 * height as u64 / TOUCH_RANGE as u64) as i32;
+                self.focus_at(x, y);
+            }
+            _ => {}
+        }
+
+        let Some(owner) = self.focus.and_then(|id| self.surfaces.iter().find(|surface| surface.id == id)).map(|surface| surface.owner) else {
+            return;
+        };
+        if self.pending.len() >= MAX_PENDING_EVENTS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((owner, record));
+    }
+
+    /// Take the events of a batch from the input service; false if the
+    /// message is not an input batch
+    pub fn handle_batch(&mut self, bytes: &[u8]) -> bool {
+        if !input::is_input_batch(bytes) {
+            return false;
+        }
+        for record in input::decode_input_batch(bytes).into_iter().flatten() {
+            self.route(record);
+        }
+        true
+    }
+
+    /// Send the waiting events with `send`, a batch for each run of events
+    /// for the same process
+    ///
+    /// A process whose queue is full gets the rest later; one that has gone
+    /// away loses its surfaces.
+    pub fn flush<F>(&mut self, mut send: F)
+    where
+        F: FnMut(ProcessId, &[u8]) -> Result<(), IpcError>,
+    {
+        while let Some(&(pid, _)) = self.pending.front() {
+            let run: Vec<InputRecord> = self.pending.iter()
+                .take_while(|&&(owner, _)| owner == pid)
+                .map(|(_, record)| record.clone())
+                .collect();
+            let (batch, count) = input::encode_input_batch(&run);
+            match send(pid, &batch) {
+                Ok(()) => {
+                    self.pending.drain(..count);
+                }
+                Err(IpcError::ChannelFull | IpcError::WouldBlock) => return,
+                Err(_) => self.release_process(pid),
+            }
+        }
+    }
+
+    /// Answer a `DisplayRequest` from `requester`, mapping the memory of a
+    /// new surface with `open`
+    pub fn handle_request<F>(&mut self, requester: ProcessId, request: DisplayRequest, open: F) -> (ServiceStatus, ServiceData)
+    where
+        F: FnOnce(&SharedBuffer) -> Option<Box<dyn SurfaceMemory>>,
+    {
+        let result = match request {
+            DisplayRequest::CreateSurface { x, y, width, height, buffer } => {
+                let created = open(&buffer)
+                    .ok_or(DisplayError::InvalidRequest)
+                    .and_then(|memory| self.create_surface(requester, Rect::new(x, y, width, height), memory));
+                return match created {
+                    Ok(id) => (ServiceStatus::Success, ServiceData::Binary(id.to_le_bytes().to_vec())),
+                    Err(error) => (error.status(), ServiceData::Empty),
+                };
+            }
+            DisplayRequest::DestroySurface { surface } => self.destroy_surface(requester, surface),
+            DisplayRequest::MoveSurface { surface, x, y } => self.move_surface(requester, surface, x, y),
+            DisplayRequest::RaiseSurface { surface } => self.raise_surface(requester, surface),
+            DisplayRequest::Damage { surface, x, y, width, height } => {
+                let clamp = |value: u32| value.min(MAX_SURFACE_SIZE);
+                let rect = Rect::new(clamp(x) as i32, clamp(y) as i32, clamp(width), clamp(height));
+                self.damage_surface(requester, surface, rect)
+            }
+            DisplayRequest::GetDisplayInfo => {
+                let (width, height) = self.output.size();
+                let mut bytes = width.to_le_bytes().to_vec();
+                bytes.extend_from_slice(&height.to_le_bytes());
+                return (ServiceStatus::Success, ServiceData::Binary(bytes));
+            }
+        };
+        match result {
+            Ok(()) => (ServiceStatus::Success, ServiceData::Empty),
+            Err(error) => (error.status(), ServiceData::Empty),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+    use kosh_driver::input::{InputEvent, KeyCode, KeyEventType, KeyModifiers, MouseEvent, TouchInputEvent};
+
+    const WIDTH: u32 = 8;
+    const HEIGHT: u32 = 6;
+
+    /// A screen the test can look at after the compositor draws on it
+    #[derive(Clone)]
+    struct Screen(Rc<RefCell<Vec<u32>>>);
+
+    impl Screen {
+        fn new() -> Self {
+            Self(Rc::new(RefCell::new(vec![0; (WIDTH * HEIGHT) as usize])))
+        }
+
+        fn pixel(&self, x: u32, y: u32) -> u32 {
+            self.0.borrow()[(y * WIDTH + x) as usize]
+        }
+
+        fn row(&self, y: u32) -> Vec<u32> {
+            (0..WIDTH).map(|x| self.pixel(x, y)).collect()
+        }
+    }
+
+    impl Output for Screen {
+        fn size(&self) -> (u32, u32) {
+            (WIDTH, HEIGHT)
+        }
+
+        fn write_line(&mut self, x: u32, y: u32, pixels: &[u32]) {
+            let start = (y * WIDTH + x) as usize;
+            self.0.borrow_mut()[start..start + pixels.len()].copy_from_slice(pixels);
+        }
+    }
+
+    /// Memory for a `width` by `height` surface of one color
+    fn filled(width: u32, height: u32, color: u32) -> Box<dyn SurfaceMemory> {
+        Box::new(color.to_le_bytes().repeat((width * height) as usize))
+    }
+
+    fn compositor() -> (Compositor, Screen) {
+        let screen = Screen::new();
+        (Compositor::new(Box::new(screen.clone())), screen)
+    }
+
+    fn key(key_code: KeyCode) -> InputRecord {
+        InputRecord::Key(InputEvent {
+            event_type: KeyEventType::KeyPress,
+            key_code,
+            scancode: key_code as u8,
+            modifiers: KeyModifiers::empty(),
+            ascii_char: None,
+            timestamp: 0,
+        })
+    }
+
+    fn click(dx: i32, dy: i32, buttons: MouseButtons) -> InputRecord {
+        InputRecord::Mouse(MouseEvent { dx, dy, wheel: 0, buttons, timestamp: 0 })
+    }
+
+    fn sent(compositor: &mut Compositor) -> Vec<(ProcessId, usize)> {
+        let mut batches = Vec::new();
+        compositor.flush(|pid, batch| {
+            batches.push((pid, input::decode_input_batch(batch).unwrap().len()));
+            Ok(())
+        });
+        batches
+    }
+
+    #[test]
+    fn test_surfaces_stack_and_only_damage_is_drawn() {
+        let (mut compositor, screen) = compositor();
+        assert_eq!(compositor.compose(), vec![Rect::new(0, 0, WIDTH, HEIGHT)]);
+        assert_eq!(screen.row(0), vec![BACKGROUND; WIDTH as usize]);
+        assert!(compositor.compose().is_empty());
+
+        const A: u32 = 0xAA;
+        const B: u32 = 0xBB;
+        let a = compositor.create_surface(10, Rect::new(1, 1, 4, 3), filled(4, 3, A)).unwrap();
+        let b = compositor.create_surface(11, Rect::new(3, 2, 4, 3), filled(4, 3, B)).unwrap();
+        assert_eq!(compositor.compose(), vec![Rect::new(1, 1, 4, 3), Rect::new(3, 2, 4, 3)]);
+        let background = BACKGROUND;
+        assert_eq!(screen.row(1), vec![background, A, A, A, A, background, background, background]);
+        assert_eq!(screen.row(2), vec![background, A, A, B, B, B, B, background]);
+        assert_eq!(compositor.focus(), Some(b));
+
+        // Raised, A covers B where they overlap
+        compositor.raise_surface(10, a).unwrap();
+        assert_eq!(compositor.compose(), vec![Rect::new(1, 1, 4, 3)]);
+        assert_eq!(screen.row(2), vec![background, A, A, A, A, B, B, background]);
+        assert_eq!(compositor.focus(), Some(a));
+
+        // Moving A partly off the screen uncovers what was under it
+        compositor.move_surface(10, a, -2, 4).unwrap();
+        compositor.compose();
+        assert_eq!(screen.row(2), vec![background, background, background, B, B, B, B, background]);
+        assert_eq!(screen.row(4), vec![A, A, background, B, B, B, B, background]);
+        assert_eq!(screen.row(5), vec![A, A, background, background, background, background, background, background]);
+
+        // Damage is clipped to the surface, then to the screen
+        compositor.damage_surface(10, a, Rect::new(1, 0, 10, 10)).unwrap();
+        assert_eq!(compositor.compose(), vec![Rect::new(0, 4, 2, 2)]);
+        compositor.damage_surface(10, a, Rect::new(0, 0, 1, 1)).unwrap();
+        assert!(compositor.compose().is_empty());
+
+        // Gone, B takes focus back
+        compositor.destroy_surface(10, a).unwrap();
+        compositor.compose();
+        assert_eq!(screen.row(4), vec![background, background, background, B, B, B, B, background]);
+        assert_eq!(compositor.focus(), Some(b));
+    }
+
+    #[test]
+    fn test_surfaces_belong_to_their_owner() {
+        let (mut compositor, _) = compositor();
+        let surface = compositor.create_surface(10, Rect::new(0, 0, 2, 2), filled(2, 2, 1)).unwrap();
+        assert_eq!(compositor.move_surface(11, surface, 1, 1), Err(DisplayError::PermissionDenied));
+        assert_eq!(compositor.destroy_surface(11, surface), Err(DisplayError::PermissionDenied));
+        assert_eq!(compositor.raise_surface(10, surface + 1), Err(DisplayError::NotFound));
+
+        // The memory must hold every pixel
+        let small = compositor.create_surface(10, Rect::new(0, 0, 3, 3), filled(2, 2, 1));
+        assert_eq!(small.err(), Some(DisplayError::InvalidRequest));
+        let empty = compositor.create_surface(10, Rect::new(0, 0, 0, 3), filled(2, 2, 1));
+        assert_eq!(empty.err(), Some(DisplayError::InvalidRequest));
+
+        compositor.create_surface(11, Rect::new(4, 4, 1, 1), filled(1, 1, 1)).unwrap();
+        compositor.compose();
+        compositor.release_process(10);
+        assert_eq!(compositor.surfaces().len(), 1);
+        assert_eq!(compositor.compose(), vec![Rect::new(0, 0, 2, 2)]);
+    }
+
+    #[test]
+    fn test_input_follows_focus() {
+        let (mut compositor, _) = compositor();
+        compositor.route(key(KeyCode::A));
+        assert!(sent(&mut compositor).is_empty());
+
+        compositor.create_surface(10, Rect::new(0, 0, 4, 6), filled(4, 6, 1)).unwrap();
+        let right = compositor.create_surface(11, Rect::new(4, 0, 4, 6), filled(4, 6, 2)).unwrap();
+        compositor.route(key(KeyCode::A));
+        compositor.route(key(KeyCode::B));
+
+        // The pointer starts in the middle, on the right surface; moving
+        // left and clicking focuses the left one
+        assert_eq!(compositor.pointer(), (4, 3));
+        compositor.route(click(-2, 0, MouseButtons::empty()));
+        compositor.route(click(0, 0, MouseButtons::LEFT));
+        compositor.route(click(0, 0, MouseButtons::empty()));
+        compositor.route(key(KeyCode::C));
+        assert_eq!(sent(&mut compositor), vec![(11, 3), (10, 3)]);
+        assert_eq!(compositor.surfaces().last().map(|surface| surface.owner), Some(10));
+
+        // The pointer stays on the screen
+        compositor.route(click(-100, 100, MouseButtons::empty()));
+        assert_eq!(compositor.pointer(), (0, 5));
+        assert_eq!(sent(&mut compositor), vec![(10, 1)]);
+
+        // A touch on the right half focuses the right surface
+        let touch = |event_type| InputRecord::Touch(TouchInputEvent { event_type, x: 50000, y: 100, pressure: 1, timestamp_us: 0, touch_id: 0 });
+        compositor.route(touch(TouchEventType::Down));
+        compositor.route(touch(TouchEventType::Up));
+        assert_eq!(compositor.focus(), Some(right));
+
+        // A full queue keeps the events; a process that has gone away
+        // loses its surfaces
+        compositor.flush(|_, _| Err(IpcError::ChannelFull));
+        compositor.flush(|_, _| Err(IpcError::InvalidReceiver));
+        assert!(compositor.surfaces().iter().all(|surface| surface.owner == 10));
+        assert!(sent(&mut compositor).is_empty());
+
+        let (batch, _) = input::encode_input_batch(&[key(KeyCode::D)]);
+        assert!(compositor.handle_batch(&batch));
+        assert!(!compositor.handle_batch(b"not a batch"));
+        assert_eq!(sent(&mut compositor), vec![(10, 1)]);
+    }
+
+    #[test]
+    fn test_requests() {
+        let (mut compositor, _) = compositor();
+        let buffer = SharedBuffer::new(7, 0, 16);
+        let create = DisplayRequest::CreateSurface { x: 1, y: 1, width: 2, height: 2, buffer };
+        let (status, data) = compositor.handle_request(10, create.clone(), |shared| {
+            assert_eq!(*shared, buffer);
+            Some(filled(2, 2, 1))
+        });
+        assert_eq!(status, ServiceStatus::Success);
+        assert!(matches!(data, ServiceData::Binary(bytes) if bytes == vec![1, 0, 0, 0]));
+
+        // Memory the service cannot map
+        let (status, _) = compositor.handle_request(10, create, |_| None);
+        assert_eq!(status, ServiceStatus::InvalidRequest);
+
+        let (status, data) = compositor.handle_request(10, DisplayRequest::GetDisplayInfo, |_| None);
+        assert_eq!(status, ServiceStatus::Success);
+        assert!(matches!(data, ServiceData::Binary(bytes) if bytes == vec![8, 0, 0, 0, 6, 0, 0, 0]));
+
+        compositor.compose();
+        let damage = DisplayRequest::Damage { surface: 1, x: 1, y: 0, width: u32::MAX, height: 1 };
+        assert_eq!(compositor.handle_request(10, damage, |_| None).0, ServiceStatus::Success);
+        assert_eq!(compositor.compose(), vec![Rect::new(2, 1, 1, 1)]);
+
+        let (status, _) = compositor.handle_request(11, DisplayRequest::DestroySurface { surface: 1 }, |_| None);
+        assert_eq!(status, ServiceStatus::PermissionDenied);
+        let (status, _) = compositor.handle_request(10, DisplayRequest::MoveSurface { surface: 2, x: 0, y: 0 }, |_| None);
+        assert_eq!(status, ServiceStatus::NotFound);
+    }
+}
@@ -0,0 +1,161 @@
+//! Damage tracking
+//!
+//! The parts of the screen that changed since the last composite are kept
+//! as a short list of rectangles. A rectangle inside one already listed is
+//! dropped, and past `MAX_DAMAGE_RECTS` the list becomes the one rectangle
+//! around them all, which may composite more than changed but never less.
+
+use alloc::vec::Vec;
+
+/// Most rectangles kept before they are merged into one
+pub const MAX_DAMAGE_RECTS: usize = 16;
+
+/// A rectangle of pixels; `x` and `y` may be off the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// First column right of the rectangle
+    pub fn right(&self) -> i64 {
+        self.x as i64 + self.width as i64
+    }
+
+    /// First line below the rectangle
+    pub fn bottom(&self) -> i64 {
+        self.y as i64 + self.height as i64
+    }
+
+    pub fn contains_point(&self, x: i32, y: i32) -> bool {
+        x >= self.x && (x as i64) < self.right() && y >= self.y && (y as i64) < self.bottom()
+    }
+
+    /// Whether `other` lies wholly inside; an empty rectangle lies inside
+    /// any other
+    pub fn contains(&self, other: &Rect) -> bool {
+        other.is_empty()
+            || (other.x >= self.x && other.right() <= self.right() && other.y >= self.y && other.bottom() <= self.bottom())
+    }
+
+    /// The part both rectangles cover, None if they do not overlap
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        (right > x as i64 && bottom > y as i64).then(|| Rect::new(x, y, (right - x as i64) as u32, (bottom - y as i64) as u32))
+    }
+
+    /// The smallest rectangle covering both
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect::new(x, y, (right - x as i64) as u32, (bottom - y as i64) as u32)
+    }
+
+    /// The rectangle moved by `dx`, `dy`
+    pub fn offset(&self, dx: i32, dy: i32) -> Rect {
+        Rect::new(self.x.saturating_add(dx), self.y.saturating_add(dy), self.width, self.height)
+    }
+}
+
+/// The parts of the screen to composite again
+pub struct DamageList {
+    rects: Vec<Rect>,
+}
+
+impl DamageList {
+    pub fn new() -> Self {
+        Self { rects: Vec::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    /// Note that `rect` changed
+    pub fn add(&mut self, rect: Rect) {
+        if rect.is_empty() || self.rects.iter().any(|damaged| damaged.contains(&rect)) {
+            return;
+        }
+        self.rects.retain(|damaged| !rect.contains(damaged));
+        self.rects.push(rect);
+        if self.rects.len() > MAX_DAMAGE_RECTS {
+            let bounds = self.rects.iter().fold(Rect::new(0, 0, 0, 0), |bounds, damaged| bounds.union(damaged));
+            self.rects.clear();
+            self.rects.push(bounds);
+        }
+    }
+
+    /// The rectangles noted since the last call
+    pub fn take(&mut self) -> Vec<Rect> {
+        core::mem::take(&mut self.rects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rect_geometry() {
+        let rect = Rect::new(-10, 20, 30, 40);
+        assert_eq!((rect.right(), rect.bottom()), (20, 60));
+        assert!(rect.contains_point(-10, 20));
+        assert!(!rect.contains_point(20, 20));
+        assert!(rect.contains(&Rect::new(0, 30, 5, 5)));
+        assert!(!rect.contains(&Rect::new(0, 30, 25, 5)));
+
+        assert_eq!(rect.intersect(&Rect::new(0, 0, 640, 480)), Some(Rect::new(0, 20, 20, 40)));
+        assert_eq!(rect.intersect(&Rect::new(20, 20, 10, 10)), None);
+        assert_eq!(rect.union(&Rect::new(100, 0, 10, 10)), Rect::new(-10, 0, 120, 60));
+        assert_eq!(rect.union(&Rect::new(100, 0, 0, 10)), rect);
+        assert_eq!(rect.offset(5, -5), Rect::new(-5, 15, 30, 40));
+    }
+
+    #[test]
+    fn test_damage_merges() {
+        let mut damage = DamageList::new();
+        damage.add(Rect::new(0, 0, 10, 10));
+        damage.add(Rect::new(2, 2, 4, 4));
+        damage.add(Rect::new(50, 50, 0, 10));
+        assert_eq!(damage.rects(), &[Rect::new(0, 0, 10, 10)]);
+
+        // A larger rectangle replaces those inside it
+        damage.add(Rect::new(20, 0, 5, 5));
+        damage.add(Rect::new(0, 0, 30, 30));
+        assert_eq!(damage.rects(), &[Rect::new(0, 0, 30, 30)]);
+
+        // Too many become the one around them all
+        for index in 1..=MAX_DAMAGE_RECTS as i32 {
+            damage.add(Rect::new(index * 40, 0, 10, 10));
+        }
+        assert_eq!(damage.rects(), &[Rect::new(0, 0, 40 * MAX_DAMAGE_RECTS as u32 + 10, 30)]);
+        assert_eq!(damage.take().len(), 1);
+        assert!(damage.is_empty());
+    }
+}
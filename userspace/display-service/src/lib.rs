@@ -0,0 +1,25 @@
+#![no_std]
+
+//! Display service
+//!
+//! Owns the framebuffer and shows the surfaces of client processes on it.
+//! A client draws into a shared memory region, hands it over with
+//! `DisplayRequest::CreateSurface` and reports what it drew with
+//! `DisplayRequest::Damage`; only the damaged parts of the screen are
+//! composited again, surfaces stacked in the order they were last raised.
+//!
+//! The service subscribes to the input service and passes the input on to
+//! the process owning the surface with focus, as `InputRecord` batches. A
+//! new or raised surface gets focus, and so does one clicked or touched.
+
+extern crate alloc;
+
+pub mod compositor;
+pub mod damage;
+pub mod output;
+pub mod surface;
+
+pub use compositor::{Compositor, DisplayError};
+pub use damage::{DamageList, Rect};
+pub use output::{FramebufferOutput, HeadlessOutput, Output};
+pub use surface::{SharedSurface, Surface, SurfaceId, SurfaceMemory};
@@ -0,0 +1,152 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use kosh_display_service::{Compositor, FramebufferOutput, HeadlessOutput, Output, SharedSurface, SurfaceMemory};
+use kosh_ipc::IpcError;
+use kosh_driver::input::INPUT_SERVICE_NAME;
+use kosh_service::{wire, InputRequest, ServiceClient, ServiceData, ServiceMessage, ServiceResponse, ServiceStatus, ServiceType};
+use kosh_types::ProcessId;
+use linked_list_allocator::LockedHeap;
+
+#[global_allocator]
+static ALLOCATOR: LockedHeap = LockedHeap::empty();
+
+/// Size of the screen when there is no framebuffer to draw into
+const HEADLESS_SIZE: (u32, u32) = (640, 480);
+
+/// The boot framebuffer, or a screen nothing is shown on if it cannot be
+/// mapped or its layout is not one the service draws
+fn open_output() -> Box<dyn Output> {
+    if let Some(info) = kosh_driver::display::boot_display() {
+        let mapped = kosh_driver::hal::map_mmio(info.address, FramebufferOutput::byte_len(&info));
+        if let Some(output) = mapped.ok().and_then(|memory| FramebufferOutput::new(&info, Box::new(memory))) {
+            return Box::new(output);
+        }
+        debug_print(b"Display Service: Boot framebuffer unusable, running headless\n");
+        return Box::new(HeadlessOutput::new(info.width, info.height));
+    }
+    debug_print(b"Display Service: No boot display, running headless\n");
+    Box::new(HeadlessOutput::new(HEADLESS_SIZE.0, HEADLESS_SIZE.1))
+}
+
+/// Answer a request `requester` made
+fn handle_request(compositor: &mut Compositor, requester: ProcessId, request: ServiceMessage) -> ServiceResponse {
+    let (status, data) = match request.data {
+        ServiceData::DisplayRequest(display_request) => compositor.handle_request(requester, display_request, |buffer| {
+            SharedSurface::open(buffer).map(|surface| Box::new(surface) as Box<dyn SurfaceMemory>)
+        }),
+        _ => (ServiceStatus::InvalidRequest, ServiceData::Empty),
+    };
+    ServiceResponse { request_id: request.request_id, status, data }
+}
+
+/// Entry point for the display service
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    init_heap();
+    debug_print(b"Display Service: Starting\n");
+
+    let mut compositor = Compositor::new(open_output());
+    let mut buffer = vec![0u8; kosh_ipc::syscall::MAX_MESSAGE_SIZE];
+
+    // Input reaches the service as batches from the input service, which
+    // answers the subscription in the same queue as requests arrive. Init
+    // starts the input service first, so it is found by name here
+    let mut client = ServiceClient::new();
+    let subscribe = ServiceData::InputRequest(InputRequest::Subscribe);
+    let subscribed = match kosh_posix::find_process(INPUT_SERVICE_NAME) {
+        Ok(Some(input_service)) => client.send_request(input_service, ServiceType::InputManager, subscribe).is_ok(),
+        _ => false,
+    };
+    if !subscribed {
+        debug_print(b"Display Service: Could not subscribe to input\n");
+    }
+
+    loop {
+        compositor.compose();
+
+        if kosh_ipc::poll::wait_for_message(kosh_ipc::poll::INFINITE).is_err() {
+            debug_print(b"Display Service: Error waiting for messages\n");
+        }
+
+        loop {
+            let (sender, length) = match kosh_ipc::syscall::receive_message(&mut buffer) {
+                Ok(received) => received,
+                Err(IpcError::WouldBlock) => break,
+                Err(_) => {
+                    debug_print(b"Display Service: Error receiving message\n");
+                    break;
+                }
+            };
+            let message = &buffer[..length];
+            if compositor.handle_batch(message) {
+                continue;
+            }
+            let response = match wire::decode_message(message) {
+                Ok(request) => {
+                    let requester = request.requester(sender);
+                    handle_request(&mut compositor, requester, request)
+                }
+                // The input service answering the subscription
+                Err(_) if wire::decode_response(message).is_ok() => continue,
+                Err(_) => match wire::peek_request_id(message) {
+                    Ok(request_id) => ServiceResponse {
+                        request_id,
+                        status: ServiceStatus::InvalidRequest,
+                        data: ServiceData::Empty,
+                    },
+                    Err(_) => continue,
+                },
+            };
+            let _ = kosh_service::send_response(sender, &response);
+        }
+
+        compositor.flush(kosh_ipc::syscall::send_message);
+    }
+}
+
+fn init_heap() {
+    const HEAP_SIZE: usize = 1024 * 1024;
+    static mut HEAP_MEMORY: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+
+    unsafe {
+        let heap_ptr = core::ptr::addr_of_mut!(HEAP_MEMORY);
+        ALLOCATOR.lock().init((*heap_ptr).as_mut_ptr(), HEAP_SIZE);
+    }
+}
+
+fn debug_print(message: &[u8]) {
+    #[cfg(debug_assertions)]
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") 100u64, // SYS_DEBUG_PRINT
+            in("rdi") message.as_ptr(),
+            in("rsi") message.len(),
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+#[cfg(not(test))]
+fn sys_exit(status: i32) -> ! {
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") 1u64, // SYS_EXIT
+            in("rdi") status,
+            options(noreturn)
+        );
+    }
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    debug_print(b"Display Service: PANIC occurred!\n");
+    sys_exit(1);
+}
@@ -0,0 +1,126 @@
+//! Where composited pixels go
+//!
+//! The service draws into the linear framebuffer the boot loader set up,
+//! converting its 0x00RRGGBB pixels to the framebuffer's layout. Indexed
+//! color framebuffers are not supported.
+
+use alloc::boxed::Box;
+use kosh_driver::display::DisplayInfo;
+use kosh_driver::hal::Mmio;
+use crate::damage::Rect;
+
+/// A screen the compositor draws on
+pub trait Output {
+    /// Width and height in pixels
+    fn size(&self) -> (u32, u32);
+
+    /// Write `pixels`, 0x00RRGGBB each, to line `y` from column `x` on
+    fn write_line(&mut self, x: u32, y: u32, pixels: &[u32]);
+
+    /// Show what was written to `rect`, for outputs that need telling
+    fn flush(&mut self, _rect: Rect) {}
+}
+
+/// Position and width of one color in a framebuffer pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Channel {
+    position: u8,
+    size: u8,
+}
+
+impl Channel {
+    /// The top `size` bits of an 8-bit `value`, in place
+    fn encode(&self, value: u32) -> u32 {
+        let size = self.size.min(8);
+        ((value & 0xFF) >> (8 - size)) << self.position
+    }
+}
+
+/// A linear RGB framebuffer
+pub struct FramebufferOutput {
+    memory: Box<dyn Mmio>,
+    width: u32,
+    height: u32,
+    pitch: usize,
+    bytes_per_pixel: usize,
+    red: Channel,
+    green: Channel,
+    blue: Channel,
+}
+
+impl FramebufferOutput {
+    /// Draw into `memory`, the framebuffer `info` describes; None unless
+    /// it is an RGB framebuffer of 16, 24 or 32 bpp
+    pub fn new(info: &DisplayInfo, memory: Box<dyn Mmio>) -> Option<Self> {
+        if info.kind != kosh_driver::display::DISPLAY_RGB || !matches!(info.bpp, 16 | 24 | 32) {
+            return None;
+        }
+        Some(Self {
+            memory,
+            width: info.width,
+            height: info.height,
+            pitch: info.pitch as usize,
+            bytes_per_pixel: info.bpp as usize / 8,
+            red: Channel { position: info.red_position, size: info.red_size },
+            green: Channel { position: info.green_position, size: info.green_size },
+            blue: Channel { position: info.blue_position, size: info.blue_size },
+        })
+    }
+
+    /// Bytes the framebuffer takes
+    pub fn byte_len(info: &DisplayInfo) -> usize {
+        info.pitch as usize * info.height as usize
+    }
+
+    fn encode(&self, pixel: u32) -> u32 {
+        self.red.encode(pixel >> 16) | self.green.encode(pixel >> 8) | self.blue.encode(pixel)
+    }
+}
+
+impl Output for FramebufferOutput {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn write_line(&mut self, x: u32, y: u32, pixels: &[u32]) {
+        if y >= self.height || x >= self.width {
+            return;
+        }
+        let count = pixels.len().min((self.width - x) as usize);
+        let start = y as usize * self.pitch + x as usize * self.bytes_per_pixel;
+        for (index, &pixel) in pixels[..count].iter().enumerate() {
+            let offset = start + index * self.bytes_per_pixel;
+            let value = self.encode(pixel);
+            match self.bytes_per_pixel {
+                4 => self.memory.write_u32(offset, value),
+                2 => self.memory.write_u16(offset, value as u16),
+                _ => {
+                    for byte in 0..self.bytes_per_pixel {
+                        self.memory.write_u8(offset + byte, (value >> (byte * 8)) as u8);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A screen nothing is shown on, for when there is no framebuffer to draw
+/// into; surfaces and input are still handled
+pub struct HeadlessOutput {
+    width: u32,
+    height: u32,
+}
+
+impl HeadlessOutput {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+}
+
+impl Output for HeadlessOutput {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn write_line(&mut self, _x: u32, _y: u32, _pixels: &[u32]) {}
+}
@@ -0,0 +1,85 @@
+//! Client surfaces
+//!
+//! A surface's pixels stay in the memory its owner draws into; the
+//! compositor only ever reads them, a line at a time.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use kosh_ipc::shm::SharedRegion;
+use kosh_ipc::SharedBuffer;
+use kosh_types::ProcessId;
+use crate::damage::Rect;
+
+pub type SurfaceId = u32;
+
+/// Bytes of a 0x00RRGGBB pixel
+pub const BYTES_PER_PIXEL: usize = 4;
+
+/// Widest and tallest surface accepted
+pub const MAX_SURFACE_SIZE: u32 = 8192;
+
+/// Memory holding a surface's pixels
+pub trait SurfaceMemory: Send {
+    fn pixels(&self) -> &[u8];
+}
+
+impl SurfaceMemory for Vec<u8> {
+    fn pixels(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Pixels in a shared memory region the owner granted the service
+pub struct SharedSurface {
+    region: SharedRegion,
+    buffer: SharedBuffer,
+}
+
+impl SharedSurface {
+    /// Map the pixels `buffer` describes, read-only
+    pub fn open(buffer: &SharedBuffer) -> Option<Self> {
+        let region = SharedRegion::open(buffer, false).ok()?;
+        region.slice_of(buffer)?;
+        Some(Self { region, buffer: *buffer })
+    }
+}
+
+impl SurfaceMemory for SharedSurface {
+    fn pixels(&self) -> &[u8] {
+        self.region.slice_of(&self.buffer).unwrap_or(&[])
+    }
+}
+
+/// A client's image on the screen
+pub struct Surface {
+    pub id: SurfaceId,
+    pub owner: ProcessId,
+    /// Where the surface shows, and its size
+    pub rect: Rect,
+    memory: Box<dyn SurfaceMemory>,
+}
+
+impl Surface {
+    /// A surface of `rect`'s size in `memory`, None if the memory is too
+    /// small for it or the size is out of range
+    pub fn new(id: SurfaceId, owner: ProcessId, rect: Rect, memory: Box<dyn SurfaceMemory>) -> Option<Self> {
+        if rect.is_empty() || rect.width > MAX_SURFACE_SIZE || rect.height > MAX_SURFACE_SIZE {
+            return None;
+        }
+        let size = rect.width as usize * rect.height as usize * BYTES_PER_PIXEL;
+        if memory.pixels().len() < size {
+            return None;
+        }
+        Some(Self { id, owner, rect, memory })
+    }
+
+    /// Read `pixels.len()` pixels of line `y` from column `x` on, both
+    /// relative to the surface
+    pub fn read_line(&self, x: u32, y: u32, pixels: &mut [u32]) {
+        let start = (y as usize * self.rect.width as usize + x as usize) * BYTES_PER_PIXEL;
+        let bytes = &self.memory.pixels()[start..start + pixels.len() * BYTES_PER_PIXEL];
+        for (pixel, bytes) in pixels.iter_mut().zip(bytes.chunks_exact(BYTES_PER_PIXEL)) {
+            *pixel = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! Which capabilities a driver is granted
+//!
+//! Drivers declare what they need in their metadata. Each request is held
+//! against what the driver's class may ever have; fixed hardware resources
+//! (ports, register ranges, interrupt lines) must also be on the allowlist
+//! of the driver known to own them, and PCI access is limited to the
+//! devices the driver declares it supports. Message-signaled interrupts
+//! are only for drivers that ask for a PCI device. One request out of
+//! bounds refuses the whole load: a driver that asks for too much is not
+//! started with less than it expects.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use kosh_driver::{
+    validate_driver_capabilities, DriverCapabilityType, DriverType, HardwareCapability, HardwareId, MemoryCapability,
+};
+use kosh_types::DriverId;
+use kosh_driver::pci::{CONFIG_ADDRESS, CONFIG_DATA};
+use kosh_ipc::capability::{CapabilityKind, ResourceDescriptor};
+use kosh_ipc::irq::MAX_MSI_VECTORS;
+use crate::driver_loader::DriverMetadata;
+
+/// Fixed hardware resources a known driver may ask for
+struct DriverAllowance {
+    name: &'static str,
+    io_ports: &'static [(u16, u16)],
+    mmio: &'static [(u64, u64)],
+    irqs: &'static [u32],
+}
+
+/// Legacy PCI configuration ports, for drivers that enable their own device
+const CONFIG_PORTS: (u16, u16) = (CONFIG_ADDRESS, CONFIG_DATA + 3);
+
+const BUILTIN_ALLOWANCES: &[DriverAllowance] = &[
+    DriverAllowance {
+        name: "ps2-keyboard",
+        io_ports: &[(0x60, 0x64)],
+        mmio: &[],
+        irqs: &[1],
+    },
+    DriverAllowance {
+        name: "usb-xhci",
+        io_ports: &[CONFIG_PORTS],
+        mmio: &[],
+        irqs: &[],
+    },
+    DriverAllowance {
+        name: "virtio-gpu",
+        // VGA sequencer and graphics controller, for the font on virtio-vga
+        io_ports: &[CONFIG_PORTS, (0x3C4, 0x3CF)],
+        mmio: &[(0xA0000, 0x10000)],
+        irqs: &[],
+    },
+    DriverAllowance {
+        name: "ac97",
+        io_ports: &[CONFIG_PORTS],
+        mmio: &[],
+        irqs: &[],
+    },
+    DriverAllowance {
+        name: "virtio-rng",
+        io_ports: &[CONFIG_PORTS],
+        mmio: &[],
+        irqs: &[],
+    },
+];
+
+/// A request the policy refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyViolation {
+    pub capability: DriverCapabilityType,
+    pub reason: &'static str,
+}
+
+/// Whether a driver of `driver_type` may ever hold `capability`
+fn class_permits(driver_type: DriverType, capability: &DriverCapabilityType) -> Result<(), &'static str> {
+    match capability {
+        DriverCapabilityType::HardwareAccess
+        | DriverCapabilityType::Hardware(HardwareCapability::GenericHardware) => {
+            Err("blanket hardware access is never granted")
+        }
+        DriverCapabilityType::Network(_) if driver_type != DriverType::Network => {
+            Err("only network drivers get network access")
+        }
+        DriverCapabilityType::FileSystem(_) if !matches!(driver_type, DriverType::Storage | DriverType::System) => {
+            Err("only storage and system drivers get file system access")
+        }
+        DriverCapabilityType::Hardware(HardwareCapability::Dma { .. })
+            if !matches!(driver_type, DriverType::Storage | DriverType::Audio | DriverType::System) =>
+        {
+            Err("ISA DMA channels are for storage and audio drivers")
+        }
+        DriverCapabilityType::Memory(MemoryCapability::PhysicalMemoryAlloc | MemoryCapability::MemoryMapping)
+            if driver_type != DriverType::System =>
+        {
+            Err("only system drivers manage physical memory")
+        }
+        DriverCapabilityType::TextOutput | DriverCapabilityType::GraphicsOutput
+            if !matches!(driver_type, DriverType::Graphics | DriverType::System) =>
+        {
+            Err("only display drivers draw to the screen")
+        }
+        DriverCapabilityType::Custom(_) => Err("custom capabilities are not understood"),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `inner` lies within `start..=end`
+fn within_ports((start, end): (u16, u16), inner_start: u16, inner_end: u16) -> bool {
+    start <= inner_start && inner_end <= end
+}
+
+/// Whether `start..start + size` lies within the range `outer`
+fn within_memory((outer_start, outer_size): (u64, u64), start: u64, size: u64) -> bool {
+    match (outer_start.checked_add(outer_size), start.checked_add(size)) {
+        (Some(outer_end), Some(end)) => outer_start <= start && end <= outer_end,
+        _ => false,
+    }
+}
+
+pub struct CapabilityPolicy {
+    allowances: &'static [DriverAllowance],
+    /// What each running driver was granted
+    approved: BTreeMap<DriverId, Vec<DriverCapabilityType>>,
+}
+
+impl CapabilityPolicy {
+    pub fn new() -> Self {
+        Self {
+            allowances: BUILTIN_ALLOWANCES,
+            approved: BTreeMap::new(),
+        }
+    }
+
+    fn allowance(&self, name: &str) -> Option<&DriverAllowance> {
+        self.allowances.iter().find(|allowance| allowance.name == name)
+    }
+
+    /// Check everything `metadata` asks for, returning what to grant
+    pub fn review(&self, metadata: &DriverMetadata) -> Result<Vec<DriverCapabilityType>, PolicyViolation> {
+        let refuse = |capability: &DriverCapabilityType, reason| PolicyViolation {
+            capability: capability.clone(),
+            reason,
+        };
+
+        let allowance = self.allowance(&metadata.name);
+        for capability in &metadata.required_capabilities {
+            class_permits(metadata.driver_type, capability).map_err(|reason| refuse(capability, reason))?;
+            if validate_driver_capabilities(core::slice::from_ref(capability), metadata.driver_type).is_err() {
+                return Err(refuse(capability, "not allowed for this kind of driver"));
+            }
+
+            let allowed = match capability {
+                DriverCapabilityType::Hardware(HardwareCapability::IoPort { start, end }) => allowance
+                    .is_some_and(|allowance| allowance.io_ports.iter().any(|&ports| within_ports(ports, *start, *end))),
+                DriverCapabilityType::Hardware(HardwareCapability::MemoryMappedIo { start, size })
+                | DriverCapabilityType::Memory(MemoryCapability::MemoryRegion { start, size }) => allowance
+                    .is_some_and(|allowance| allowance.mmio.iter().any(|&range| within_memory(range, *start, *size))),
+                DriverCapabilityType::Hardware(HardwareCapability::Interrupt { irq }) => allowance
+                    .is_some_and(|allowance| allowance.irqs.contains(irq)),
+                DriverCapabilityType::Hardware(HardwareCapability::PciDevice { vendor_id, device_id }) => metadata
+                    .hardware_ids.iter()
+                    .any(|id| id.vendor_id == *vendor_id && id.device_id == *device_id),
+                DriverCapabilityType::Hardware(HardwareCapability::MessageSignaledInterrupts { vectors }) => {
+                    if *vectors > MAX_MSI_VECTORS {
+                        return Err(refuse(capability, "more MSI vectors than a device can have"));
+                    }
+                    metadata.required_capabilities.iter().any(|other| matches!(
+                        other,
+                        DriverCapabilityType::Hardware(HardwareCapability::PciDevice { .. })
+                    ))
+                }
+                _ => true,
+            };
+            if !allowed {
+                return Err(refuse(capability, "not on the driver's allowlist"));
+            }
+        }
+
+        Ok(metadata.required_capabilities.clone())
+    }
+
+    /// Record what a started driver was granted
+    pub fn approve(&mut self, driver_id: DriverId, capabilities: Vec<DriverCapabilityType>) {
+        self.approved.insert(driver_id, capabilities);
+    }
+
+    pub fn forget(&mut self, driver_id: DriverId) {
+        self.approved.remove(&driver_id);
+    }
+
+    /// Whether `driver_id` was approved for the PCI device `hardware_id`
+    pub fn permits_device(&self, driver_id: DriverId, hardware_id: &HardwareId) -> bool {
+        self.approved.get(&driver_id).is_some_and(|capabilities| {
+            capabilities.iter().any(|capability| matches!(
+                capability,
+                DriverCapabilityType::Hardware(HardwareCapability::PciDevice { vendor_id, device_id })
+                    if *vendor_id == hardware_id.vendor_id && *device_id == hardware_id.device_id
+            ))
+        })
+    }
+
+    /// Whether `driver_id` was approved for message-signaled interrupts
+    pub fn permits_msi(&self, driver_id: DriverId) -> bool {
+        self.approved.get(&driver_id).is_some_and(|capabilities| {
+            capabilities.iter().any(|capability| matches!(
+                capability,
+                DriverCapabilityType::Hardware(HardwareCapability::MessageSignaledInterrupts { .. })
+            ))
+        })
+    }
+}
+
+/// Kernel capabilities backing approved driver capabilities
+///
+/// PCI devices are granted their registers, and message-signaled
+/// interrupts the right to allocate them, when the driver is bound to
+/// them; DMA buffers and IPC need no grant of their own.
+pub fn kernel_grants(capabilities: &[DriverCapabilityType]) -> Vec<(CapabilityKind, ResourceDescriptor)> {
+    capabilities.iter()
+        .filter_map(|capability| match capability {
+            DriverCapabilityType::Hardware(HardwareCapability::IoPort { start, end }) => {
+                Some((CapabilityKind::DeviceAccess, ResourceDescriptor::io_ports(*start, *end)))
+            }
+            DriverCapabilityType::Hardware(HardwareCapability::MemoryMappedIo { start, size })
+            | DriverCapabilityType::Memory(MemoryCapability::MemoryRegion { start, size }) => {
+                Some((CapabilityKind::DeviceAccess, ResourceDescriptor::memory_range(*start, *size)))
+            }
+            DriverCapabilityType::Hardware(HardwareCapability::Interrupt { irq }) => {
+                Some((CapabilityKind::DeviceAccess, ResourceDescriptor::irq(*irq)))
+            }
+            DriverCapabilityType::Network(_) => Some((CapabilityKind::Network, ResourceDescriptor::any())),
+            DriverCapabilityType::FileSystem(_) => Some((CapabilityKind::FileSystem, ResourceDescriptor::any())),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use kosh_driver::NetworkCapability;
+
+    const CONFIG_SPACE: DriverCapabilityType = DriverCapabilityType::Hardware(HardwareCapability::IoPort { start: 0xCF8, end: 0xCFF });
+    const DMA_MEMORY: DriverCapabilityType = DriverCapabilityType::Memory(MemoryCapability::DmaMemory);
+
+    fn pci_device(vendor_id: u32, device_id: u32) -> DriverCapabilityType {
+        DriverCapabilityType::Hardware(HardwareCapability::PciDevice { vendor_id, device_id })
+    }
+
+    /// Metadata as the loader reads it from a driver's manifest
+    fn metadata(name: &str, driver_type: DriverType, devices: &[(u32, u32)], required_capabilities: Vec<DriverCapabilityType>) -> DriverMetadata {
+        DriverMetadata {
+            name: name.to_string(),
+            version: "0.1.0".to_string(),
+            driver_type,
+            required_capabilities,
+            hardware_ids: devices.iter()
+                .map(|&(vendor_id, device_id)| HardwareId {
+                    vendor_id,
+                    device_id,
+                    subsystem_vendor_id: None,
+                    subsystem_device_id: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Run `metadata` through the policy, returning what the driver gets
+    /// and the kernel capabilities that back it
+    fn grants(metadata: &DriverMetadata) -> (Vec<DriverCapabilityType>, Vec<(CapabilityKind, ResourceDescriptor)>) {
+        let approved = CapabilityPolicy::new().review(metadata).expect("request within policy");
+        let kernel = kernel_grants(&approved);
+        (approved, kernel)
+    }
+
+    fn refusal(metadata: &DriverMetadata) -> &'static str {
+        CapabilityPolicy::new().review(metadata).expect_err("request beyond policy").reason
+    }
+
+    #[test]
+    fn test_keyboard_policy() {
+        let required = vec![
+            DriverCapabilityType::Hardware(HardwareCapability::IoPort { start: 0x60, end: 0x64 }),
+            DriverCapabilityType::Hardware(HardwareCapability::Interrupt { irq: 1 }),
+        ];
+        let keyboard = metadata("ps2-keyboard", DriverType::Input, &[], required.clone());
+        let (approved, kernel) = grants(&keyboard);
+        assert_eq!(approved, required);
+        assert_eq!(kernel, vec![
+            (CapabilityKind::DeviceAccess, ResourceDescriptor::io_ports(0x60, 0x64)),
+            (CapabilityKind::DeviceAccess, ResourceDescriptor::irq(1)),
+        ]);
+
+        // A keyboard asking for the network, or for more ports or lines
+        // than the controller has, gets nothing
+        let mut greedy = keyboard.clone();
+        greedy.required_capabilities.push(DriverCapabilityType::Network(NetworkCapability::RawSocket));
+        assert_eq!(refusal(&greedy), "only network drivers get network access");
+        let mut greedy = keyboard.clone();
+        greedy.required_capabilities[0] = DriverCapabilityType::Hardware(HardwareCapability::IoPort { start: 0x60, end: 0x70 });
+        assert_eq!(refusal(&greedy), "not on the driver's allowlist");
+        let mut greedy = keyboard.clone();
+        greedy.required_capabilities.push(DriverCapabilityType::Hardware(HardwareCapability::Interrupt { irq: 12 }));
+        assert_eq!(refusal(&greedy), "not on the driver's allowlist");
+        let mut greedy = keyboard;
+        greedy.required_capabilities.push(DriverCapabilityType::HardwareAccess);
+        assert_eq!(refusal(&greedy), "blanket hardware access is never granted");
+    }
+
+    #[test]
+    fn test_usb_xhci_policy() {
+        let controllers = [(0x1B36, 0x000D), (0x1033, 0x0194), (0x8086, 0x1E31), (0x8086, 0x8C31)];
+        let mut required = vec![CONFIG_SPACE];
+        required.extend(controllers.iter().map(|&(vendor, device)| pci_device(vendor, device)));
+        required.push(DMA_MEMORY);
+        let usb = metadata("usb-xhci", DriverType::Input, &controllers, required.clone());
+
+        // The PCI devices are granted when the driver is bound to them
+        let (approved, kernel) = grants(&usb);
+        assert_eq!(approved, required);
+        assert_eq!(kernel, vec![(CapabilityKind::DeviceAccess, ResourceDescriptor::io_ports(0xCF8, 0xCFF))]);
+
+        // Only the controllers it declares
+        let mut greedy = usb;
+        greedy.required_capabilities.push(pci_device(0x1AF4, 0x1050));
+        assert_eq!(refusal(&greedy), "not on the driver's allowlist");
+    }
+
+    #[test]
+    fn test_virtio_gpu_policy() {
+        let vga_memory = DriverCapabilityType::Hardware(HardwareCapability::MemoryMappedIo { start: 0xA0000, size: 0x10000 });
+        let required = vec![
+            CONFIG_SPACE,
+            DriverCapabilityType::Hardware(HardwareCapability::IoPort { start: 0x3C4, end: 0x3CF }),
+            vga_memory,
+            pci_device(0x1AF4, 0x1050),
+            DMA_MEMORY,
+        ];
+        let gpu = metadata("virtio-gpu", DriverType::Graphics, &[(0x1AF4, 0x1050)], required.clone());
+        let (approved, kernel) = grants(&gpu);
+        assert_eq!(approved, required);
+        assert_eq!(kernel, vec![
+            (CapabilityKind::DeviceAccess, ResourceDescriptor::io_ports(0xCF8, 0xCFF)),
+            (CapabilityKind::DeviceAccess, ResourceDescriptor::io_ports(0x3C4, 0x3CF)),
+            (CapabilityKind::DeviceAccess, ResourceDescriptor::memory_range(0xA0000, 0x10000)),
+        ]);
+
+        // Memory past the VGA window is not the driver's
+        let mut greedy = gpu;
+        greedy.required_capabilities[2] = DriverCapabilityType::Hardware(HardwareCapability::MemoryMappedIo { start: 0xA0000, size: 0x20000 });
+        assert_eq!(refusal(&greedy), "not on the driver's allowlist");
+    }
+
+    #[test]
+    fn test_ac97_and_virtio_rng_policies() {
+        let ac97 = metadata("ac97", DriverType::Audio, &[(0x8086, 0x2415)], vec![CONFIG_SPACE, pci_device(0x8086, 0x2415), DMA_MEMORY]);
+        let rng_devices = [(0x1AF4, 0x1005), (0x1AF4, 0x1044)];
+        let rng = metadata("virtio-rng", DriverType::System, &rng_devices, vec![
+            CONFIG_SPACE,
+            pci_device(0x1AF4, 0x1005),
+            pci_device(0x1AF4, 0x1044),
+            DMA_MEMORY,
+        ]);
+
+        for driver in [&ac97, &rng] {
+            let (approved, kernel) = grants(driver);
+            assert_eq!(approved, driver.required_capabilities);
+            assert_eq!(kernel, vec![(CapabilityKind::DeviceAccess, ResourceDescriptor::io_ports(0xCF8, 0xCFF))]);
+        }
+
+        // Neither has an interrupt line of its own
+        let mut greedy = ac97;
+        greedy.required_capabilities.push(DriverCapabilityType::Hardware(HardwareCapability::Interrupt { irq: 5 }));
+        assert_eq!(refusal(&greedy), "not on the driver's allowlist");
+    }
+
+    #[test]
+    fn test_unknown_driver_policy() {
+        // A driver without an allowlist gets no fixed hardware, but still
+        // what needs no grant and the devices it declares
+        let required = vec![
+            pci_device(0x10EC, 0x8139),
+            DriverCapabilityType::Hardware(HardwareCapability::MessageSignaledInterrupts { vectors: 2 }),
+            DMA_MEMORY,
+            DriverCapabilityType::Network(NetworkCapability::RawSocket),
+        ];
+        let nic = metadata("rtl8139", DriverType::Network, &[(0x10EC, 0x8139)], required.clone());
+        let (approved, kernel) = grants(&nic);
+        assert_eq!(approved, required);
+        assert_eq!(kernel, vec![(CapabilityKind::Network, ResourceDescriptor::any())]);
+
+        let mut greedy = nic.clone();
+        greedy.required_capabilities.push(CONFIG_SPACE);
+        assert_eq!(refusal(&greedy), "not on the driver's allowlist");
+
+        // Interrupt vectors need a PCI device, and no more than a device has
+        let mut greedy = nic.clone();
+        greedy.required_capabilities.remove(0);
+        assert_eq!(refusal(&greedy), "not on the driver's allowlist");
+        let mut greedy = nic;
+        greedy.required_capabilities[1] = DriverCapabilityType::Hardware(HardwareCapability::MessageSignaledInterrupts { vectors: MAX_MSI_VECTORS + 1 });
+        assert_eq!(refusal(&greedy), "more MSI vectors than a device can have");
+    }
+
+    #[test]
+    fn test_approved_capabilities_are_recorded() {
+        let mut policy = CapabilityPolicy::new();
+        let device = HardwareId { vendor_id: 0x1AF4, device_id: 0x1005, subsystem_vendor_id: None, subsystem_device_id: None };
+        policy.approve(7, vec![pci_device(0x1AF4, 0x1005)]);
+        assert!(policy.permits_device(7, &device));
+        assert!(!policy.permits_device(8, &device));
+        assert!(!policy.permits_msi(7));
+
+        policy.forget(7);
+        assert!(!policy.permits_device(7, &device));
+    }
+}
@@ -32,6 +32,28 @@ impl DependencyResolver {
         Ok(resolved_deps)
     }
 
+    /// Dependencies of `binary` that are not loaded yet
+    pub fn missing_dependencies(&self, binary: &DriverBinary) -> Vec<String> {
+        binary.dependencies.iter()
+            .filter(|dep_name| !self.name_to_id.contains_key(*dep_name))
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_driver_id(&self, driver_name: &str) -> Option<DriverId> {
+        self.name_to_id.get(driver_name).copied()
+    }
+
+    /// Forget an unloaded driver: its name and the dependencies it had
+    pub fn remove_driver(&mut self, driver_id: DriverId) {
+        self.name_to_id.retain(|_, id| *id != driver_id);
+        self.dependents.remove(&driver_id);
+        for deps in self.dependents.values_mut() {
+            deps.retain(|&id| id != driver_id);
+        }
+        self.dependents.retain(|_, deps| !deps.is_empty());
+    }
+
     pub fn register_driver_name(&mut self, driver_name: String, driver_id: DriverId) {
         self.name_to_id.insert(driver_name, driver_id);
     }
@@ -0,0 +1,184 @@
+//! Hardware discovery and automatic driver selection
+//!
+//! The PCI buses are enumerated into a device tree, and each device without
+//! a driver is offered to the registered factories in registration order.
+//! The first factory whose `can_handle` accepts the device's `HardwareId`
+//! names the driver binary to load for it. Unclaimed devices stay in the
+//! tree, so a factory registered later still gets to see them.
+
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use kosh_driver::pci::{self, ConfigSpace, PciDevice};
+use kosh_driver::{DriverFactory, DriverType, HardwareId, KoshDriver};
+use kosh_types::{DriverError, DriverId};
+
+/// A discovered device and the driver bound to it
+#[derive(Debug, Clone)]
+pub struct DeviceNode {
+    pub device: PciDevice,
+    pub driver: Option<DriverId>,
+}
+
+/// A driver binary and the factory deciding which devices it takes
+struct Registration {
+    path: String,
+    factory: Box<dyn DriverFactory>,
+}
+
+pub struct DeviceDiscovery {
+    registrations: Vec<Registration>,
+    devices: Vec<DeviceNode>,
+}
+
+impl DeviceDiscovery {
+    pub fn new() -> Self {
+        Self {
+            registrations: Vec::new(),
+            devices: Vec::new(),
+        }
+    }
+
+    /// Load `path` for devices `factory` can handle
+    pub fn register_factory(&mut self, path: &str, factory: Box<dyn DriverFactory>) {
+        self.registrations.push(Registration {
+            path: String::from(path),
+            factory,
+        });
+    }
+
+    /// Rebuild the device tree, keeping the drivers of devices still present
+    ///
+    /// Returns the number of devices found.
+    pub fn scan(&mut self, config: &mut dyn ConfigSpace) -> usize {
+        let previous = core::mem::take(&mut self.devices);
+        self.devices = pci::enumerate(config)
+            .into_iter()
+            .map(|device| {
+                let driver = previous.iter()
+                    .find(|node| node.device.address == device.address && node.device.hardware_id() == device.hardware_id())
+                    .and_then(|node| node.driver);
+                DeviceNode { device, driver }
+            })
+            .collect();
+        self.devices.len()
+    }
+
+    /// Devices without a driver that a registered factory handles, as
+    /// indices into `devices()` with the driver binary to load for each
+    pub fn pending_matches(&self) -> Vec<(usize, String)> {
+        self.devices.iter()
+            .enumerate()
+            .filter(|(_, node)| node.driver.is_none())
+            .filter_map(|(index, node)| {
+                let hardware_id = node.device.hardware_id();
+                self.registrations.iter()
+                    .find(|registration| registration.factory.can_handle(&hardware_id))
+                    .map(|registration| (index, registration.path.clone()))
+            })
+            .collect()
+    }
+
+    pub fn has_factory(&self, path: &str) -> bool {
+        self.registrations.iter().any(|registration| registration.path == path)
+    }
+
+    pub fn bind(&mut self, index: usize, driver_id: DriverId) {
+        if let Some(node) = self.devices.get_mut(index) {
+            node.driver = Some(driver_id);
+        }
+    }
+
+    /// Forget `driver_id` on every device it was bound to
+    pub fn unbind_driver(&mut self, driver_id: DriverId) {
+        for node in self.devices.iter_mut().filter(|node| node.driver == Some(driver_id)) {
+            node.driver = None;
+        }
+    }
+
+    pub fn devices(&self) -> &[DeviceNode] {
+        &self.devices
+    }
+}
+
+/// Match table of a driver binary that runs in its own process
+///
+/// The manager never instantiates drivers itself, so `create_driver` is
+/// refused; only `can_handle` is used, to pick the binary to load.
+pub struct DriverBinaryFactory {
+    driver_type: DriverType,
+    hardware_ids: Vec<HardwareId>,
+}
+
+impl DriverBinaryFactory {
+    pub fn new(driver_type: DriverType, hardware_ids: Vec<HardwareId>) -> Self {
+        Self { driver_type, hardware_ids }
+    }
+}
+
+impl DriverFactory for DriverBinaryFactory {
+    fn create_driver(&self, _hardware_id: &HardwareId) -> Result<Box<dyn KoshDriver>, DriverError> {
+        Err(DriverError::InvalidRequest)
+    }
+
+    /// Vendor and device must match; subsystem IDs only if the table
+    /// names them
+    fn can_handle(&self, hardware_id: &HardwareId) -> bool {
+        self.hardware_ids.iter().any(|id| {
+            id.vendor_id == hardware_id.vendor_id
+                && id.device_id == hardware_id.device_id
+                && (id.subsystem_vendor_id.is_none() || id.subsystem_vendor_id == hardware_id.subsystem_vendor_id)
+                && (id.subsystem_device_id.is_none() || id.subsystem_device_id == hardware_id.subsystem_device_id)
+        })
+    }
+
+    fn get_driver_type(&self) -> DriverType {
+        self.driver_type
+    }
+}
+
+fn pci_id(vendor_id: u32, device_id: u32) -> HardwareId {
+    HardwareId {
+        vendor_id,
+        device_id,
+        subsystem_vendor_id: None,
+        subsystem_device_id: None,
+    }
+}
+
+/// Factories for the PCI drivers shipped with the system
+pub fn builtin_factories() -> Vec<(&'static str, Box<dyn DriverFactory>)> {
+    vec![
+        (
+            "/drivers/graphics.ko",
+            // QEMU/Bochs standard VGA
+            Box::new(DriverBinaryFactory::new(DriverType::Graphics, vec![pci_id(0x1234, 0x1111)])),
+        ),
+        (
+            "/drivers/storage.ko",
+            // PIIX3 and PIIX4 IDE, which expose the legacy ATA ports
+            Box::new(DriverBinaryFactory::new(DriverType::Storage, vec![pci_id(0x8086, 0x7010), pci_id(0x8086, 0x7111)])),
+        ),
+        (
+            "/drivers/usb.ko",
+            // xHCI controllers: QEMU qemu-xhci and nec-usb-xhci, Intel 7 and 8 Series
+            Box::new(DriverBinaryFactory::new(
+                DriverType::Input,
+                vec![pci_id(0x1B36, 0x000D), pci_id(0x1033, 0x0194), pci_id(0x8086, 0x1E31), pci_id(0x8086, 0x8C31)],
+            )),
+        ),
+        (
+            "/drivers/virtio-gpu.ko",
+            // QEMU virtio-gpu-pci and virtio-vga
+            Box::new(DriverBinaryFactory::new(DriverType::Graphics, vec![pci_id(0x1AF4, 0x1050)])),
+        ),
+        (
+            "/drivers/audio.ko",
+            // Intel 82801AA AC'97, as QEMU and VirtualBox emulate it
+            Box::new(DriverBinaryFactory::new(DriverType::Audio, vec![pci_id(0x8086, 0x2415)])),
+        ),
+        (
+            "/drivers/virtio-rng.ko",
+            // QEMU virtio-rng-pci, transitional and modern
+            Box::new(DriverBinaryFactory::new(DriverType::System, vec![pci_id(0x1AF4, 0x1005), pci_id(0x1AF4, 0x1044)])),
+        ),
+    ]
+}
@@ -1,5 +1,16 @@
-use alloc::{vec::Vec, string::String};
+use alloc::{vec, vec::Vec, string::String};
 use kosh_types::DriverError;
+use kosh_driver::{DriverCapabilityType, DriverManifest, DriverType, HardwareId};
+use kosh_driver::metadata::{DRIVER_ENTRY_SYMBOL, DRIVER_METADATA_SECTION};
+use kosh_driver::signature::{self as driver_signature, DriverTrust};
+use crate::elf::{ElfError, ElfFile};
+use crate::signature::{self, SignatureStatus};
+
+/// Largest driver binary the loader reads
+pub const MAX_DRIVER_BINARY_SIZE: usize = 1024 * 1024;
+
+/// Directory drivers are loaded from by name
+pub const DRIVER_DIRECTORY: &str = "/drivers";
 
 #[derive(Debug, Clone)]
 pub struct DriverBinary {
@@ -13,40 +24,106 @@ pub struct DriverBinary {
 pub struct DriverMetadata {
     pub name: String,
     pub version: String,
-    pub required_capabilities: Vec<String>,
-    pub hardware_requirements: Vec<String>,
+    pub driver_type: DriverType,
+    /// What the driver asks for; `CapabilityPolicy` decides what it gets
+    pub required_capabilities: Vec<DriverCapabilityType>,
+    pub hardware_ids: Vec<HardwareId>,
+}
+
+/// Path of the binary of the driver called `name`
+pub fn driver_path(name: &str) -> String {
+    alloc::format!("{}/{}.ko", DRIVER_DIRECTORY, name)
+}
+
+impl From<ElfError> for DriverError {
+    fn from(_: ElfError) -> Self {
+        DriverError::InitializationFailed
+    }
+}
+
+fn errno_to_driver_error(errno: kosh_posix::Errno) -> DriverError {
+    match errno {
+        kosh_posix::errno::EACCES | kosh_posix::errno::EPERM => DriverError::PermissionDenied,
+        _ => DriverError::InvalidRequest,
+    }
 }
 
 pub struct DriverLoader {
-    // In a real implementation, this would handle ELF loading, etc.
+    /// Signing key and boot mode, fixed for as long as the system runs
+    trust: DriverTrust,
 }
 
 impl DriverLoader {
     pub fn new() -> Self {
-        Self {}
+        // Without an answer from the kernel nothing can be verified, and
+        // only signed drivers would do
+        let trust = driver_signature::driver_trust()
+            .unwrap_or(DriverTrust { public_key: None, unsigned_allowed: false });
+        Self { trust }
     }
 
+    /// Read a driver binary through fs-service and check it is a driver
+    ///
+    /// The binary must be an executable for this machine whose entry point
+    /// is `DRIVER_ENTRY_SYMBOL`, which carries a metadata section and which
+    /// is signed by the kernel's driver key, unless the boot mode allows
+    /// unsigned drivers.
     pub fn load_driver_binary(&self, driver_path: &str) -> Result<DriverBinary, DriverError> {
-        // In a real implementation, this would:
-        // 1. Read the driver file from the file system
-        // 2. Parse the ELF/binary format
-        // 3. Extract metadata and dependencies
-        // 4. Validate the binary signature
-        // 5. Prepare it for execution
-
-        // For now, return a mock implementation
-        let metadata = DriverMetadata {
-            name: String::from("mock_driver"),
-            version: String::from("1.0.0"),
-            required_capabilities: Vec::new(),
-            hardware_requirements: Vec::new(),
+        let data = self.read_file(driver_path)?;
+        let binary = self.parse_driver_binary(data)?;
+        self.validate_driver_binary(&binary)?;
+        Ok(binary)
+    }
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, DriverError> {
+        let fd = kosh_posix::open(path, kosh_posix::O_RDONLY, 0).map_err(errno_to_driver_error)?;
+
+        let mut data = Vec::new();
+        let mut chunk = vec![0u8; 4096];
+        let result = loop {
+            match kosh_posix::read(fd, &mut chunk) {
+                Ok(0) => break Ok(()),
+                Ok(count) if data.len() + count > MAX_DRIVER_BINARY_SIZE => break Err(DriverError::ResourceBusy),
+                Ok(count) => data.extend_from_slice(&chunk[..count]),
+                Err(errno) => break Err(errno_to_driver_error(errno)),
+            }
         };
+        let _ = kosh_posix::close(fd);
+
+        result.map(|_| data)
+    }
+
+    fn parse_driver_binary(&self, data: Vec<u8>) -> Result<DriverBinary, DriverError> {
+        let elf = ElfFile::parse(&data)?;
+        elf.check_entry_point()?;
+
+        let status = signature::check_driver(&elf, &data, &self.trust)?;
+        if status != SignatureStatus::Valid {
+            crate::debug_print(b"Driver Manager: loading a driver without a valid signature (safe mode)\n");
+        }
+
+        // Stripped binaries carry no symbols; otherwise the entry point has
+        // to be the driver's `_start`
+        if elf.symbol(DRIVER_ENTRY_SYMBOL).is_some_and(|address| address != elf.entry_point()) {
+            return Err(DriverError::InitializationFailed);
+        }
+
+        let manifest = elf.section(DRIVER_METADATA_SECTION)
+            .and_then(DriverManifest::parse)
+            .ok_or(DriverError::InitializationFailed)?;
+        let entry_point = elf.entry_point();
 
         Ok(DriverBinary {
-            data: Vec::new(), // Would contain actual binary data
-            entry_point: 0x1000, // Mock entry point
-            dependencies: Vec::new(),
-            metadata,
+            data,
+            entry_point,
+            dependencies: manifest.dependencies,
+            metadata: DriverMetadata {
+                name: manifest.name,
+                version: manifest.version,
+                driver_type: manifest.driver_type,
+                required_capabilities: manifest.required_capabilities,
+                hardware_ids: manifest.hardware_ids,
+            },
         })
     }
 
@@ -61,11 +138,13 @@ impl DriverLoader {
             return Err(DriverError::InitializationFailed);
         }
 
+        // A driver may not depend on itself
+        if binary.dependencies.contains(&binary.metadata.name) {
+            return Err(DriverError::InitializationFailed);
+        }
+
         // Additional validation would go here:
-        // - Check binary signature
         // - Verify compatibility with current kernel version
-        // - Validate required capabilities
-        // - Check hardware requirements
 
         Ok(())
     }
@@ -77,4 +156,4 @@ impl DriverLoader {
     pub fn get_driver_metadata<'a>(&self, binary: &'a DriverBinary) -> &'a DriverMetadata {
         &binary.metadata
     }
-}
\ No newline at end of file
+}
@@ -73,6 +73,20 @@ impl DriverRegistry {
         self.drivers.get(driver_id)
     }
 
+    /// Driver running in `process_id`
+    pub fn get_driver_by_process(&self, process_id: ProcessId) -> Option<&DriverInfo> {
+        self.drivers.values().find(|info| info.process_id == process_id)
+    }
+
+    /// Record the process a restarted driver now runs in
+    pub fn update_process_id(&mut self, driver_id: DriverId, process_id: ProcessId) -> Result<(), DriverError> {
+        let driver_info = self.drivers.get_mut(&driver_id)
+            .ok_or(DriverError::InvalidRequest)?;
+
+        driver_info.process_id = process_id;
+        Ok(())
+    }
+
     pub fn update_driver_status(&mut self, driver_id: DriverId, status: DriverStatus) -> Result<(), DriverError> {
         let driver_info = self.drivers.get_mut(&driver_id)
             .ok_or(DriverError::InvalidRequest)?;
@@ -0,0 +1,357 @@
+use core::ops::Range;
+
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+
+const HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const SECTION_HEADER_SIZE: usize = 64;
+const SYMBOL_SIZE: usize = 24;
+
+/// Why a file is not a loadable driver binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// A header or table runs past the end of the file
+    Truncated,
+    /// Not an ELF file
+    BadMagic,
+    /// Not a 64-bit little-endian executable for this machine
+    Unsupported,
+    /// The entry point is not in an executable segment
+    BadEntryPoint,
+    /// Two loadable segments claim the same memory
+    OverlappingSegments,
+}
+
+/// Expected `e_machine` for binaries run on this CPU
+fn native_machine() -> u16 {
+    if cfg!(target_arch = "aarch64") { EM_AARCH64 } else { EM_X86_64 }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    offset: u64,
+    size: u64,
+    link: u32,
+}
+
+/// Read-only view of a 64-bit ELF executable
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+    entry: u64,
+    program_headers: Range<usize>,
+    sections: Range<usize>,
+    section_names: u16,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ElfError> {
+    let bytes = data.get(offset..offset + 2).ok_or(ElfError::Truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ElfError> {
+    let bytes = data.get(offset..offset + 4).ok_or(ElfError::Truncated)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Result<u64, ElfError> {
+    let bytes = data.get(offset..offset + 8).ok_or(ElfError::Truncated)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Byte range of a table of `count` entries of `entry_size` at `offset`
+fn table(data: &[u8], offset: u64, count: u16, entry_size: u16, expected_size: usize) -> Result<Range<usize>, ElfError> {
+    if count == 0 {
+        return Ok(0..0);
+    }
+    if entry_size as usize != expected_size {
+        return Err(ElfError::Unsupported);
+    }
+    let start = usize::try_from(offset).map_err(|_| ElfError::Truncated)?;
+    let end = start.checked_add(count as usize * expected_size).ok_or(ElfError::Truncated)?;
+    if end > data.len() {
+        return Err(ElfError::Truncated);
+    }
+    Ok(start..end)
+}
+
+impl<'a> ElfFile<'a> {
+    /// Check the headers of `data` and locate its tables
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        if data.len() < HEADER_SIZE {
+            return Err(ElfError::Truncated);
+        }
+        if data[..4] != ELF_MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        if data[4] != ELFCLASS64 || data[5] != ELFDATA2LSB {
+            return Err(ElfError::Unsupported);
+        }
+        let kind = u16_at(data, 16)?;
+        if (kind != ET_EXEC && kind != ET_DYN) || u16_at(data, 18)? != native_machine() {
+            return Err(ElfError::Unsupported);
+        }
+
+        let program_headers = table(data, u64_at(data, 32)?, u16_at(data, 56)?, u16_at(data, 54)?, PROGRAM_HEADER_SIZE)?;
+        let sections = table(data, u64_at(data, 40)?, u16_at(data, 60)?, u16_at(data, 58)?, SECTION_HEADER_SIZE)?;
+
+        let elf = Self {
+            data,
+            entry: u64_at(data, 24)?,
+            program_headers,
+            sections,
+            section_names: u16_at(data, 62)?,
+        };
+        elf.check_segments()?;
+        Ok(elf)
+    }
+
+    pub fn entry_point(&self) -> u64 {
+        self.entry
+    }
+
+    /// Check that the entry point lies in an executable loadable segment
+    pub fn check_entry_point(&self) -> Result<(), ElfError> {
+        let executable = self.loadable_segments()
+            .any(|(flags, memory)| flags & PF_X != 0 && memory.contains(&self.entry));
+        if executable { Ok(()) } else { Err(ElfError::BadEntryPoint) }
+    }
+
+    /// Flags and memory range of each loadable segment
+    fn loadable_segments(&self) -> impl Iterator<Item = (u32, Range<u64>)> + 'a {
+        self.data[self.program_headers.clone()]
+            .chunks_exact(PROGRAM_HEADER_SIZE)
+            .filter(|header| u32_at(header, 0) == Ok(PT_LOAD))
+            .map(|header| {
+                let start = u64_at(header, 16).unwrap_or(0);
+                let size = u64_at(header, 40).unwrap_or(0);
+                (u32_at(header, 4).unwrap_or(0), start..start.saturating_add(size))
+            })
+    }
+
+    /// Check that no two loadable segments share memory
+    fn check_segments(&self) -> Result<(), ElfError> {
+        for (index, (_, memory)) in self.loadable_segments().enumerate() {
+            let overlaps = self.loadable_segments()
+                .skip(index + 1)
+                .any(|(_, other)| memory.start < other.end && other.start < memory.end
+                    && !memory.is_empty() && !other.is_empty());
+            if overlaps {
+                return Err(ElfError::OverlappingSegments);
+            }
+        }
+        Ok(())
+    }
+
+    fn section_header(&self, index: usize) -> Option<SectionHeader> {
+        let start = self.sections.start + index * SECTION_HEADER_SIZE;
+        if start + SECTION_HEADER_SIZE > self.sections.end {
+            return None;
+        }
+        let header = &self.data[start..start + SECTION_HEADER_SIZE];
+        Some(SectionHeader {
+            name: u32_at(header, 0).ok()?,
+            kind: u32_at(header, 4).ok()?,
+            offset: u64_at(header, 24).ok()?,
+            size: u64_at(header, 32).ok()?,
+            link: u32_at(header, 40).ok()?,
+        })
+    }
+
+    fn section_data(&self, header: &SectionHeader) -> Option<&'a [u8]> {
+        self.section_bounds(header).map(|range| &self.data[range])
+    }
+
+    /// Where in the file the contents of a section are
+    fn section_bounds(&self, header: &SectionHeader) -> Option<Range<usize>> {
+        if header.kind == SHT_NOBITS {
+            return None;
+        }
+        let start = usize::try_from(header.offset).ok()?;
+        let end = start.checked_add(usize::try_from(header.size).ok()?)?;
+        (end <= self.data.len()).then_some(start..end)
+    }
+
+    /// NUL-terminated string at `offset` in a string table
+    fn string(table: &[u8], offset: u32) -> Option<&[u8]> {
+        let rest = table.get(offset as usize..)?;
+        let end = rest.iter().position(|&byte| byte == 0)?;
+        Some(&rest[..end])
+    }
+
+    fn section_count(&self) -> usize {
+        self.sections.len() / SECTION_HEADER_SIZE
+    }
+
+    /// Contents of the section called `name`
+    pub fn section(&self, name: &str) -> Option<&'a [u8]> {
+        self.section_range(name).map(|range| &self.data[range])
+    }
+
+    /// Where in the file the contents of the section called `name` are
+    pub fn section_range(&self, name: &str) -> Option<Range<usize>> {
+        let names = self.section_data(&self.section_header(self.section_names as usize)?)?;
+        let header = (0..self.section_count())
+            .filter_map(|index| self.section_header(index))
+            .find(|header| Self::string(names, header.name) == Some(name.as_bytes()))?;
+        self.section_bounds(&header)
+    }
+
+    /// Value of the symbol `name` in the symbol table
+    ///
+    /// Returns None for stripped binaries as well as missing symbols.
+    pub fn symbol(&self, name: &str) -> Option<u64> {
+        let symtab = (0..self.section_count())
+            .filter_map(|index| self.section_header(index))
+            .find(|header| header.kind == SHT_SYMTAB)?;
+        let symbols = self.section_data(&symtab)?;
+        let names = self.section_data(&self.section_header(symtab.link as usize)?)?;
+
+        symbols.chunks_exact(SYMBOL_SIZE)
+            .find(|symbol| {
+                u32_at(symbol, 0).ok()
+                    .and_then(|offset| Self::string(names, offset))
+                    == Some(name.as_bytes())
+            })
+            .and_then(|symbol| u64_at(symbol, 8).ok())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    const SHT_PROGBITS: u32 = 1;
+    const SHT_STRTAB: u32 = 3;
+
+    /// A little-endian executable for this machine entered at `entry`,
+    /// with a loadable segment per `(flags, start, size)` and a section
+    /// per `(name, contents)`
+    pub(crate) fn build_elf(entry: u64, segments: &[(u32, u64, u64)], sections: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut data = alloc::vec![0u8; HEADER_SIZE];
+        data[..4].copy_from_slice(&ELF_MAGIC);
+        data[4] = ELFCLASS64;
+        data[5] = ELFDATA2LSB;
+        data[6] = 1;
+        data[16..18].copy_from_slice(&ET_EXEC.to_le_bytes());
+        data[18..20].copy_from_slice(&native_machine().to_le_bytes());
+        data[20..24].copy_from_slice(&1u32.to_le_bytes());
+        data[24..32].copy_from_slice(&entry.to_le_bytes());
+        data[32..40].copy_from_slice(&(HEADER_SIZE as u64).to_le_bytes());
+        data[52..54].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        data[54..56].copy_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+        data[56..58].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+
+        for &(flags, start, size) in segments {
+            let mut header = [0u8; PROGRAM_HEADER_SIZE];
+            header[0..4].copy_from_slice(&PT_LOAD.to_le_bytes());
+            header[4..8].copy_from_slice(&flags.to_le_bytes());
+            header[16..24].copy_from_slice(&start.to_le_bytes());
+            header[24..32].copy_from_slice(&start.to_le_bytes());
+            header[40..48].copy_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&header);
+        }
+
+        // Section contents, then the section name table
+        let mut names = alloc::vec![0u8];
+        let mut headers = alloc::vec![[0u8; SECTION_HEADER_SIZE]];
+        let mut add_section = |data: &mut Vec<u8>, names: &mut Vec<u8>, name: &str, kind: u32, contents: &[u8]| {
+            let mut header = [0u8; SECTION_HEADER_SIZE];
+            header[0..4].copy_from_slice(&(names.len() as u32).to_le_bytes());
+            header[4..8].copy_from_slice(&kind.to_le_bytes());
+            header[24..32].copy_from_slice(&(data.len() as u64).to_le_bytes());
+            header[32..40].copy_from_slice(&(contents.len() as u64).to_le_bytes());
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+            data.extend_from_slice(contents);
+            headers.push(header);
+        };
+        for &(name, contents) in sections {
+            add_section(&mut data, &mut names, name, SHT_PROGBITS, contents);
+        }
+        let mut table_names = names.clone();
+        let own_name = table_names.len() as u32;
+        table_names.extend_from_slice(b".shstrtab\0");
+        let mut header = [0u8; SECTION_HEADER_SIZE];
+        header[0..4].copy_from_slice(&own_name.to_le_bytes());
+        header[4..8].copy_from_slice(&SHT_STRTAB.to_le_bytes());
+        header[24..32].copy_from_slice(&(data.len() as u64).to_le_bytes());
+        header[32..40].copy_from_slice(&(table_names.len() as u64).to_le_bytes());
+        data.extend_from_slice(&table_names);
+        headers.push(header);
+
+        let section_headers = data.len() as u64;
+        data[40..48].copy_from_slice(&section_headers.to_le_bytes());
+        data[58..60].copy_from_slice(&(SECTION_HEADER_SIZE as u16).to_le_bytes());
+        data[60..62].copy_from_slice(&(headers.len() as u16).to_le_bytes());
+        data[62..64].copy_from_slice(&(headers.len() as u16 - 1).to_le_bytes());
+        for header in headers {
+            data.extend_from_slice(&header);
+        }
+        data
+    }
+
+    #[test]
+    fn test_sections_and_entry_point() {
+        let data = build_elf(0x1000, &[(PF_X, 0x1000, 0x100)], &[(".text", &[0x90; 16]), (".kosh_driver", b"meta")]);
+        let elf = ElfFile::parse(&data).unwrap();
+        assert_eq!(elf.entry_point(), 0x1000);
+        assert_eq!(elf.check_entry_point(), Ok(()));
+        assert_eq!(elf.section(".kosh_driver"), Some(&b"meta"[..]));
+        assert_eq!(elf.section(".missing"), None);
+        assert_eq!(elf.symbol("_start"), None);
+    }
+
+    #[test]
+    fn test_truncated_program_headers() {
+        let data = build_elf(0x1000, &[(PF_X, 0x1000, 0x100), (0, 0x2000, 0x100)], &[]);
+
+        // The second program header runs past the end of the file
+        let end = HEADER_SIZE + PROGRAM_HEADER_SIZE + PROGRAM_HEADER_SIZE / 2;
+        assert!(matches!(ElfFile::parse(&data[..end]), Err(ElfError::Truncated)));
+
+        // A table that claims more entries than the file holds
+        let mut data = data.clone();
+        data[56..58].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(matches!(ElfFile::parse(&data), Err(ElfError::Truncated)));
+
+        // An offset that wraps around
+        data[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(ElfFile::parse(&data), Err(ElfError::Truncated)));
+
+        // And no program headers at all leaves no entry point
+        let data = build_elf(0x1000, &[], &[]);
+        assert!(matches!(ElfFile::parse(&data).unwrap().check_entry_point(), Err(ElfError::BadEntryPoint)));
+    }
+
+    #[test]
+    fn test_overlapping_program_headers() {
+        let overlapping = build_elf(0x1000, &[(PF_X, 0x1000, 0x1000), (0, 0x1800, 0x1000)], &[]);
+        assert!(matches!(ElfFile::parse(&overlapping), Err(ElfError::OverlappingSegments)));
+
+        // One segment inside another
+        let nested = build_elf(0x1000, &[(0, 0x3000, 0x10), (PF_X, 0x1000, 0x4000)], &[]);
+        assert!(matches!(ElfFile::parse(&nested), Err(ElfError::OverlappingSegments)));
+
+        // Segments that only touch are fine, as are empty ones
+        let adjacent = build_elf(0x1000, &[(PF_X, 0x1000, 0x1000), (0, 0x2000, 0x1000), (0, 0x1800, 0)], &[]);
+        let elf = ElfFile::parse(&adjacent).unwrap();
+        assert_eq!(elf.check_entry_point(), Ok(()));
+    }
+}
@@ -1,13 +1,23 @@
-use alloc::{collections::BTreeMap, vec::Vec};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
 use kosh_types::{DriverId, ProcessId, Capability, DriverError};
+use kosh_driver::{DriverType, PowerEvent, HEARTBEAT_REQUEST, POWER_EVENT_REQUEST};
 use kosh_ipc::{DriverRequestData, IpcError};
+use kosh_ipc::capability::{CapabilityKind, ResourceDescriptor};
 use crate::driver_loader::DriverBinary;
 
+/// Real-time priority of input drivers, so a touch reaches the display in
+/// bounded time however loaded the system is
+pub const INPUT_RT_PRIORITY: u8 = 50;
+
 #[derive(Debug, Clone)]
 pub struct DriverProcess {
     pub driver_id: DriverId,
     pub process_id: ProcessId,
     pub capabilities: Vec<Capability>,
+    /// Kernel capabilities granted to the process, replayed on restart
+    pub grants: Vec<(CapabilityKind, ResourceDescriptor)>,
+    /// IDs the kernel gave those grants, revoked when the process stops
+    pub granted_ids: Vec<u64>,
     pub memory_limit: usize,
     pub cpu_quota: u32,
 }
@@ -29,6 +39,7 @@ impl DriverIsolation {
         &mut self,
         driver_id: DriverId,
         capabilities: Vec<Capability>,
+        grants: Vec<(CapabilityKind, ResourceDescriptor)>,
     ) -> Result<ProcessId, DriverError> {
         let process_id = self.next_process_id;
         self.next_process_id += 1;
@@ -37,6 +48,8 @@ impl DriverIsolation {
             driver_id,
             process_id,
             capabilities,
+            grants,
+            granted_ids: Vec::new(),
             memory_limit: 16 * 1024 * 1024, // 16MB default limit
             cpu_quota: 10, // 10% CPU quota by default
         };
@@ -44,9 +57,8 @@ impl DriverIsolation {
         // In a real implementation, this would:
         // 1. Create a new address space for the driver
         // 2. Set up memory protection boundaries
-        // 3. Configure capability restrictions
-        // 4. Set resource limits (memory, CPU, I/O)
-        // 5. Create IPC channels for communication
+        // 3. Set resource limits (memory, CPU, I/O)
+        // 4. Create IPC channels for communication
 
         self.driver_processes.insert(process_id, driver_process);
 
@@ -58,9 +70,16 @@ impl DriverIsolation {
         process_id: ProcessId,
         binary: DriverBinary,
     ) -> Result<(), DriverError> {
-        let driver_process = self.driver_processes.get(&process_id)
+        let driver_process = self.driver_processes.get_mut(&process_id)
             .ok_or(DriverError::InvalidRequest)?;
 
+        // The driver may touch its hardware from its first instruction;
+        // the kernel maps no registers and opens no ports it was not granted
+        for (kind, resource) in &driver_process.grants {
+            let id = grant_to_process(process_id, *kind, resource)?;
+            driver_process.granted_ids.push(id);
+        }
+
         // In a real implementation, this would:
         // 1. Load the driver binary into the isolated address space
         // 2. Set up the initial stack and heap
@@ -73,19 +92,44 @@ impl DriverIsolation {
             return Err(DriverError::InitializationFailed);
         }
 
+        // The kernel refuses mappings past the limit; without it the driver
+        // only loses that protection
+        let _ = kosh_posix::set_memory_limit(process_id, Some(driver_process.memory_limit));
+
+        // Input runs in the real-time class; without it the driver still
+        // works, only with ordinary latency
+        if binary.metadata.driver_type == DriverType::Input {
+            let _ = kosh_posix::sched_set_realtime(process_id, Some(INPUT_RT_PRIORITY));
+        }
+
+        Ok(())
+    }
+
+    /// Grant a running driver one more kernel capability
+    pub fn grant(&mut self, process_id: ProcessId, kind: CapabilityKind, resource: ResourceDescriptor) -> Result<(), DriverError> {
+        let driver_process = self.driver_processes.get_mut(&process_id)
+            .ok_or(DriverError::InvalidRequest)?;
+        let id = grant_to_process(process_id, kind, &resource)?;
+        driver_process.grants.push((kind, resource));
+        driver_process.granted_ids.push(id);
         Ok(())
     }
 
     pub fn stop_driver_process(&mut self, process_id: ProcessId) -> Result<(), DriverError> {
-        let _driver_process = self.driver_processes.remove(&process_id)
+        let driver_process = self.driver_processes.remove(&process_id)
             .ok_or(DriverError::InvalidRequest)?;
 
-        // In a real implementation, this would:
+        // Revoking the grants unmaps the driver's registers and closes its
+        // ports at once; one the process already dropped is gone anyway
+        for &id in &driver_process.granted_ids {
+            let _ = kosh_ipc::capability::revoke(process_id, id);
+        }
+
+        // In a real implementation, this would also:
         // 1. Send termination signal to the driver process
         // 2. Wait for graceful shutdown or force termination
         // 3. Clean up the driver's address space
-        // 4. Release allocated resources
-        // 5. Close IPC channels
+        // 4. Close IPC channels
 
         Ok(())
     }
@@ -109,14 +153,42 @@ impl DriverIsolation {
         Ok(Vec::new())
     }
 
+    /// Deliver a power event, which the driver hands to `handle_power_event`
+    pub fn send_power_event(&self, process_id: ProcessId, event: PowerEvent) -> Result<(), DriverError> {
+        let driver_process = self.driver_processes.get(&process_id)
+            .ok_or(DriverError::InvalidRequest)?;
+
+        let request = DriverRequestData {
+            driver_id: driver_process.driver_id,
+            request_type: POWER_EVENT_REQUEST,
+            data: vec![event.code()],
+        };
+        self.send_request_to_driver(process_id, request)?;
+        Ok(())
+    }
+
+    /// Ping a driver; an error means it did not answer
+    pub fn send_heartbeat(&self, process_id: ProcessId) -> Result<(), DriverError> {
+        let driver_process = self.driver_processes.get(&process_id)
+            .ok_or(DriverError::InvalidRequest)?;
+
+        let request = DriverRequestData {
+            driver_id: driver_process.driver_id,
+            request_type: HEARTBEAT_REQUEST,
+            data: Vec::new(),
+        };
+        self.send_request_to_driver(process_id, request)?;
+        Ok(())
+    }
+
     pub fn set_memory_limit(&mut self, process_id: ProcessId, limit: usize) -> Result<(), DriverError> {
         let driver_process = self.driver_processes.get_mut(&process_id)
             .ok_or(DriverError::InvalidRequest)?;
 
         driver_process.memory_limit = limit;
 
-        // In a real implementation, this would update the actual memory limits
-        // in the kernel's memory management system
+        // A driver that is not running yet gets the limit when it starts
+        let _ = kosh_posix::set_memory_limit(process_id, Some(limit));
 
         Ok(())
     }
@@ -151,26 +223,46 @@ impl DriverIsolation {
         Ok(DriverHealthStatus::Healthy)
     }
 
-    pub fn restart_driver(&mut self, process_id: ProcessId) -> Result<(), DriverError> {
+    /// Replace a driver's process with a fresh one running `binary`
+    ///
+    /// The new process gets the same capabilities and limits. Returns its
+    /// process ID.
+    pub fn restart_driver(&mut self, process_id: ProcessId, binary: DriverBinary) -> Result<ProcessId, DriverError> {
         let driver_process = self.driver_processes.get(&process_id)
             .ok_or(DriverError::InvalidRequest)?;
 
         let driver_id = driver_process.driver_id;
         let capabilities = driver_process.capabilities.clone();
+        let grants = driver_process.grants.clone();
+        let memory_limit = driver_process.memory_limit;
+        let cpu_quota = driver_process.cpu_quota;
 
         // Stop the current process
         self.stop_driver_process(process_id)?;
 
         // Create a new process with the same configuration
-        let new_process_id = self.create_driver_process(driver_id, capabilities)?;
+        let new_process_id = self.create_driver_process(driver_id, capabilities, grants)?;
+        self.set_memory_limit(new_process_id, memory_limit)?;
+        self.set_cpu_quota(new_process_id, cpu_quota)?;
 
-        // In a real implementation, we would also need to reload the binary
-        // and restart the driver
+        if let Err(error) = self.start_driver_process(new_process_id, binary) {
+            self.driver_processes.remove(&new_process_id);
+            return Err(error);
+        }
 
-        Ok(())
+        Ok(new_process_id)
     }
 }
 
+/// Record a grant in the kernel's capability system and return its ID
+fn grant_to_process(process_id: ProcessId, kind: CapabilityKind, resource: &ResourceDescriptor) -> Result<u64, DriverError> {
+    kosh_ipc::capability::grant(process_id, kind, resource)
+        .map_err(|error| match error {
+            IpcError::PermissionDenied => DriverError::PermissionDenied,
+            _ => DriverError::InitializationFailed,
+        })
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DriverHealthStatus {
     Healthy,
@@ -0,0 +1,22 @@
+#![no_std]
+
+extern crate alloc;
+
+pub mod capability_policy;
+pub mod driver_loader;
+pub mod elf;
+pub mod signature;
+
+/// Print a message to the kernel's debug output in debug builds
+pub fn debug_print(message: &[u8]) {
+    #[cfg(debug_assertions)]
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") 100u64, // SYS_DEBUG_PRINT
+            in("rdi") message.as_ptr(),
+            in("rsi") message.len(),
+            options(nostack, preserves_flags)
+        );
+    }
+}
@@ -5,11 +5,12 @@ extern crate alloc;
 
 use alloc::vec::Vec;
 use alloc::vec;
+use alloc::boxed::Box;
 use alloc::string::String;
 use alloc::format;
 use linked_list_allocator::LockedHeap;
 use core::panic::PanicInfo;
-use kosh_types::{DriverId, DriverError, Capability};
+use kosh_types::{DriverId, DriverError};
 use kosh_ipc::DriverRequestData;
 use kosh_service::{ServiceHandler, ServiceMessage, ServiceResponse, ServiceType, ServiceData, ServiceStatus, ServiceRunner, DriverRequest};
 
@@ -17,56 +18,202 @@ use kosh_service::{ServiceHandler, ServiceMessage, ServiceResponse, ServiceType,
 static ALLOCATOR: LockedHeap = LockedHeap::empty();
 
 mod driver_registry;
-mod driver_loader;
 mod dependency_resolver;
 mod isolation;
+mod device_discovery;
+mod power;
+mod watchdog;
 
+use kosh_driver_manager::{capability_policy, debug_print, driver_loader};
+
+use capability_policy::CapabilityPolicy;
 use driver_registry::DriverRegistry;
 use driver_loader::DriverLoader;
 use dependency_resolver::DependencyResolver;
 use isolation::DriverIsolation;
+use device_discovery::{DeviceDiscovery, DeviceNode, DriverBinaryFactory};
+use power::{PowerPolicy, PowerTransition, RuntimePowerManager, RuntimeState};
+use watchdog::{DriverFailure, DriverWatchdog};
+use kosh_driver::DriverCapabilityManager;
+use kosh_driver::hal::HardwarePortIo;
+use kosh_driver::pci::{self, ConfigSpace, PortConfigSpace};
+use kosh_ipc::capability::{CapabilityKind, ResourceDescriptor};
 
 pub struct DriverManager {
     registry: DriverRegistry,
     loader: DriverLoader,
     dependency_resolver: DependencyResolver,
     isolation: DriverIsolation,
+    policy: CapabilityPolicy,
+    discovery: DeviceDiscovery,
+    power: RuntimePowerManager,
+    watchdog: DriverWatchdog,
     next_driver_id: DriverId,
+    /// When the battery is next read, None once there is none to read
+    battery_check_at: Option<u64>,
+}
+
+/// How often the battery is read
+const BATTERY_POLL_MS: u64 = 5000;
+
+/// Milliseconds on the monotonic clock, 0 until the kernel provides one
+fn monotonic_ms() -> u64 {
+    kosh_posix::clock_gettime(kosh_posix::CLOCK_MONOTONIC)
+        .map_or(0, |time| time.tv_sec as u64 * 1000 + time.tv_nsec as u64 / 1_000_000)
 }
 
 impl DriverManager {
     pub fn new() -> Self {
+        let mut discovery = DeviceDiscovery::new();
+        for (path, factory) in device_discovery::builtin_factories() {
+            discovery.register_factory(path, factory);
+        }
+        
         Self {
             registry: DriverRegistry::new(),
             loader: DriverLoader::new(),
             dependency_resolver: DependencyResolver::new(),
             isolation: DriverIsolation::new(),
+            policy: CapabilityPolicy::new(),
+            discovery,
+            power: RuntimePowerManager::new(),
+            watchdog: DriverWatchdog::new(),
             next_driver_id: 1,
+            battery_check_at: Some(0),
         }
     }
 
-    pub fn load_driver(&mut self, driver_path: &str, capabilities: Vec<Capability>) -> Result<DriverId, DriverError> {
+    /// Load the driver binary at `driver_path` and start it
+    ///
+    /// Dependencies named in the binary's metadata that are not running
+    /// yet are loaded first from the driver directory. The capabilities
+    /// the driver declares must all pass the capability policy.
+    pub fn load_driver(&mut self, driver_path: &str) -> Result<DriverId, DriverError> {
+        self.load_driver_with_dependencies(driver_path, &mut Vec::new())
+    }
+
+    /// `loading` holds the drivers further up the dependency chain, to
+    /// catch dependency cycles
+    fn load_driver_with_dependencies(&mut self, driver_path: &str, loading: &mut Vec<String>) -> Result<DriverId, DriverError> {
+        if self.registry.get_driver_by_path(driver_path).is_some() {
+            return Err(DriverError::ResourceBusy);
+        }
+
         // Load the driver binary
         let driver_binary = self.loader.load_driver_binary(driver_path)?;
-        
+        let name = driver_binary.metadata.name.clone();
+        if self.dependency_resolver.get_driver_id(&name).is_some() {
+            return Err(DriverError::ResourceBusy);
+        }
+        if loading.contains(&name) {
+            return Err(DriverError::InitializationFailed);
+        }
+        let approved = self.policy.review(&driver_binary.metadata).map_err(|violation| {
+            debug_print(format!("Driver Manager: refused {} from {}: {:?} {}\n",
+                                name, driver_path, violation.capability, violation.reason).as_bytes());
+            DriverError::PermissionDenied
+        })?;
+
+        loading.push(name.clone());
+        for dependency in self.dependency_resolver.missing_dependencies(&driver_binary) {
+            let path = driver_loader::driver_path(&dependency);
+            if let Err(error) = self.load_driver_with_dependencies(&path, loading) {
+                loading.pop();
+                return Err(error);
+            }
+        }
+        loading.pop();
+
         // Resolve dependencies
         let dependencies = self.dependency_resolver.resolve_dependencies(&driver_binary)?;
+        let hardware_ids = driver_binary.metadata.hardware_ids.clone();
+        let driver_type = driver_binary.metadata.driver_type;
+        let version = driver_binary.metadata.version.clone();
         
         // Create isolated environment
         let driver_id = self.next_driver_id;
         self.next_driver_id += 1;
         
-        let process_id = self.isolation.create_driver_process(driver_id, capabilities)?;
-        
-        // Register the driver
-        self.registry.register_driver(driver_id, driver_path, process_id, dependencies)?;
+        let mut capabilities = DriverCapabilityManager::new();
+        for capability in &approved {
+            capabilities.grant_capability(capability.clone());
+        }
+        let grants = capability_policy::kernel_grants(&approved);
+        let process_id = self.isolation.create_driver_process(driver_id, capabilities.to_kernel_capabilities(), grants)?;
         
         // Start the driver process
-        self.isolation.start_driver_process(process_id, driver_binary)?;
+        if let Err(error) = self.isolation.start_driver_process(process_id, driver_binary) {
+            let _ = self.isolation.stop_driver_process(process_id);
+            return Err(error);
+        }
+        
+        // Register the driver
+        self.registry.register_driver(driver_id, driver_path, process_id, dependencies.clone())?;
+        self.registry.update_driver_status(driver_id, DriverStatus::Running)?;
+        self.dependency_resolver.register_driver_name(name.clone(), driver_id);
+        self.policy.approve(driver_id, approved);
+        for &dependency in &dependencies {
+            self.dependency_resolver.add_dependency(driver_id, dependency);
+        }
+        self.power.register(driver_id, dependencies, power::default_policy(driver_path), monotonic_ms());
+        self.watchdog.register(driver_id, monotonic_ms());
+        debug_print(format!("Driver Manager: loaded {} {} from {} as driver {}\n",
+                            name, version, driver_path, driver_id).as_bytes());
+
+        // Hand the new driver the devices it supports that have none yet
+        if !hardware_ids.is_empty() && !self.discovery.has_factory(driver_path) {
+            self.discovery.register_factory(driver_path, Box::new(DriverBinaryFactory::new(driver_type, hardware_ids)));
+        }
+        let mut config = PortConfigSpace::new(HardwarePortIo);
+        for (index, path) in self.discovery.pending_matches() {
+            if path == driver_path {
+                let _ = self.bind_device(&mut config, index, driver_id);
+            }
+        }
         
         Ok(driver_id)
     }
 
+    /// Bind a device to a driver, granting the driver the device's
+    /// registers if the policy approved it for the device, and the right
+    /// to allocate its message-signaled interrupts if it asked for them
+    fn bind_device(&mut self, config: &mut dyn ConfigSpace, index: usize, driver_id: DriverId) -> Result<(), DriverError> {
+        let device = &self.discovery.devices().get(index).ok_or(DriverError::InvalidRequest)?.device;
+        let address = device.address;
+        let process_id = self.registry.get_driver_info(driver_id)
+            .ok_or(DriverError::InvalidRequest)?
+            .process_id;
+
+        if self.policy.permits_device(driver_id, &device.hardware_id()) {
+            for bar in pci::memory_bars(config, address) {
+                let registers = ResourceDescriptor::memory_range(bar.address, bar.size);
+                if let Err(error) = self.isolation.grant(process_id, CapabilityKind::DeviceAccess, registers) {
+                    debug_print(format!("Driver Manager: could not grant driver {} BAR{} of PCI {}\n",
+                                        driver_id, bar.index, address).as_bytes());
+                    return Err(error);
+                }
+            }
+            for bar in pci::io_bars(config, address) {
+                let ports = ResourceDescriptor::io_ports(bar.port, bar.port + (bar.size - 1));
+                if let Err(error) = self.isolation.grant(process_id, CapabilityKind::DeviceAccess, ports) {
+                    debug_print(format!("Driver Manager: could not grant driver {} BAR{} of PCI {}\n",
+                                        driver_id, bar.index, address).as_bytes());
+                    return Err(error);
+                }
+            }
+            if self.policy.permits_msi(driver_id) {
+                let function = ResourceDescriptor::pci_device(address.bus, address.device, address.function);
+                if let Err(error) = self.isolation.grant(process_id, CapabilityKind::DeviceAccess, function) {
+                    debug_print(format!("Driver Manager: could not grant driver {} the interrupts of PCI {}\n",
+                                        driver_id, address).as_bytes());
+                    return Err(error);
+                }
+            }
+        }
+        self.discovery.bind(index, driver_id);
+        Ok(())
+    }
+
     pub fn unload_driver(&mut self, driver_id: DriverId) -> Result<(), DriverError> {
         // Check if other drivers depend on this one
         if self.dependency_resolver.has_dependents(driver_id) {
@@ -82,16 +229,205 @@ impl DriverManager {
 
         // Unregister the driver
         self.registry.unregister_driver(driver_id)?;
+        self.discovery.unbind_driver(driver_id);
+        self.dependency_resolver.remove_driver(driver_id);
+        self.policy.forget(driver_id);
+        self.power.unregister(driver_id);
+        self.watchdog.unregister(driver_id);
 
         Ok(())
     }
 
+    /// Enumerate the PCI buses and load drivers for the devices found
+    ///
+    /// A driver binary already running takes on further devices it
+    /// matches instead of being loaded again. Returns the number of devices
+    /// that got a driver.
+    pub fn discover_devices(&mut self, config: &mut dyn ConfigSpace) -> usize {
+        self.discovery.scan(config);
+        
+        let mut bound = 0;
+        for (index, path) in self.discovery.pending_matches() {
+            let driver_id = match self.registry.get_driver_by_path(&path) {
+                Some(driver_info) => driver_info.driver_id,
+                None => match self.load_driver(&path) {
+                    Ok(driver_id) => driver_id,
+                    Err(_) => continue,
+                },
+            };
+            // Loading binds the devices it can
+            if self.discovery.devices()[index].driver.is_none() && self.bind_device(config, index, driver_id).is_err() {
+                continue;
+            }
+            bound += 1;
+        }
+        bound
+    }
+
     pub fn handle_driver_request(&mut self, request: DriverRequestData) -> Result<Vec<u8>, DriverError> {
         let driver_info = self.registry.get_driver_info(request.driver_id)
             .ok_or(DriverError::InvalidRequest)?;
+        let process_id = driver_info.process_id;
+
+        // Bring the device back up if it went to sleep while idle
+        let transitions = self.power.wake(request.driver_id, monotonic_ms())?;
+        self.send_power_events(&transitions)?;
 
         // Forward request to the driver process
-        self.isolation.send_request_to_driver(driver_info.process_id, request)
+        self.isolation.send_request_to_driver(process_id, request)
+    }
+
+    /// Deliver power events in order, stopping at the first failure
+    fn send_power_events(&self, transitions: &[PowerTransition]) -> Result<(), DriverError> {
+        for &(driver_id, event) in transitions {
+            let driver_info = self.registry.get_driver_info(driver_id)
+                .ok_or(DriverError::InvalidRequest)?;
+            self.isolation.send_power_event(driver_info.process_id, event)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_power_policy(&mut self, driver_id: DriverId, policy: PowerPolicy) -> Result<(), DriverError> {
+        self.power.set_policy(driver_id, policy)
+    }
+
+    /// Keep a driver's device powered until `release_driver`
+    pub fn hold_driver(&mut self, driver_id: DriverId) -> Result<(), DriverError> {
+        let transitions = self.power.hold(driver_id, monotonic_ms())?;
+        self.send_power_events(&transitions)
+    }
+
+    pub fn release_driver(&mut self, driver_id: DriverId) -> Result<(), DriverError> {
+        self.power.release(driver_id, monotonic_ms())
+    }
+
+    /// Suspend every device ahead of system sleep
+    pub fn suspend_all(&mut self) -> Result<(), DriverError> {
+        let transitions = self.power.suspend_all();
+        self.send_power_events(&transitions)
+    }
+
+    /// Resume the devices `suspend_all` suspended
+    pub fn resume_all(&mut self) -> Result<(), DriverError> {
+        let transitions = self.power.resume_all(monotonic_ms());
+        self.send_power_events(&transitions)
+    }
+
+    /// Put idle devices to sleep
+    ///
+    /// Returns the milliseconds until this should run again, None when no
+    /// timeout is pending.
+    pub fn run_power_management(&mut self) -> Option<u64> {
+        let now = monotonic_ms();
+        let transitions = self.power.idle(now);
+        // A driver that missed its event is asleep to us anyway; the next
+        // wake resumes it
+        let _ = self.send_power_events(&transitions);
+        self.power.next_timeout(now)
+    }
+
+    /// Keep devices in low power while the battery runs low, and bring
+    /// them back once it recovers or AC power returns
+    ///
+    /// Returns the milliseconds until this should run again. A machine
+    /// without a battery the kernel can read is not asked again.
+    pub fn run_battery_monitor(&mut self) -> Option<u64> {
+        let now = monotonic_ms();
+        let due = self.battery_check_at?;
+        if due > now {
+            return Some(due - now);
+        }
+        let battery = kosh_posix::power::battery_info().ok().filter(|info| info.battery_present());
+        let low = battery.is_some_and(|info| info.low());
+        // As with idle timeouts, a driver that missed its event catches up
+        // on the next one
+        let transitions = self.power.set_battery_saver(low, now);
+        let _ = self.send_power_events(&transitions);
+
+        self.battery_check_at = battery.map(|_| now + BATTERY_POLL_MS);
+        self.battery_check_at.map(|_| BATTERY_POLL_MS)
+    }
+
+    pub fn get_power_state(&self, driver_id: DriverId) -> Option<RuntimeState> {
+        self.power.state(driver_id)
+    }
+
+    /// Supervise the running drivers
+    ///
+    /// Reaps driver processes that exited, pings drivers whose heartbeat is
+    /// due and restarts failed drivers whose backoff has passed. Returns
+    /// the milliseconds until this should run again.
+    pub fn run_watchdog(&mut self) -> Option<u64> {
+        let now = monotonic_ms();
+
+        // Drivers cannot catch SIGCHLD, so collect exited children here
+        while let Ok(Some((process_id, status))) = kosh_posix::waitpid(0, kosh_posix::WNOHANG) {
+            if let Some(driver_id) = self.registry.get_driver_by_process(process_id).map(|info| info.driver_id) {
+                self.driver_failed(driver_id, DriverFailure::Exited(status), now);
+            }
+        }
+
+        for driver_id in self.watchdog.heartbeats_due(now) {
+            let Some(process_id) = self.registry.get_driver_info(driver_id).map(|info| info.process_id) else {
+                continue;
+            };
+            // A device put to sleep still has a running driver to answer
+            let answered = self.isolation.send_heartbeat(process_id).is_ok();
+            if self.watchdog.heartbeat_result(driver_id, answered) {
+                self.driver_failed(driver_id, DriverFailure::Unresponsive, now);
+            }
+        }
+
+        for driver_id in self.watchdog.restarts_due(now) {
+            match self.restart_driver(driver_id) {
+                Ok(()) => {
+                    self.watchdog.restarted(driver_id, now);
+                    debug_print(format!("Driver Manager: driver {} restarted\n", driver_id).as_bytes());
+                }
+                Err(_) => self.watchdog.restart_failed(driver_id, now),
+            }
+        }
+
+        self.watchdog.next_timeout(now)
+    }
+
+    fn driver_failed(&mut self, driver_id: DriverId, failure: DriverFailure, now: u64) {
+        let _ = self.registry.update_driver_status(driver_id, DriverStatus::Error);
+        if let Some(delay) = self.watchdog.driver_failed(driver_id, now) {
+            let reason = match failure {
+                DriverFailure::Exited(status) => format!("exited with status {}", status),
+                DriverFailure::Unresponsive => String::from("stopped answering heartbeats"),
+            };
+            debug_print(format!("Driver Manager: driver {} {}, restarting in {} ms\n", driver_id, reason, delay).as_bytes());
+        }
+    }
+
+    /// Start a failed driver again from its binary, in a fresh process
+    fn restart_driver(&mut self, driver_id: DriverId) -> Result<(), DriverError> {
+        let driver_info = self.registry.get_driver_info(driver_id)
+            .ok_or(DriverError::InvalidRequest)?;
+        let process_id = driver_info.process_id;
+        let driver_binary = self.loader.load_driver_binary(&driver_info.driver_path)?;
+
+        let new_process_id = self.isolation.restart_driver(process_id, driver_binary)?;
+        self.registry.update_process_id(driver_id, new_process_id)?;
+        self.registry.update_driver_status(driver_id, DriverStatus::Running)?;
+
+        // The device comes back up powered
+        let dependencies = self.registry.get_driver_dependencies(driver_id).cloned().unwrap_or_default();
+        let policy = power::default_policy(&self.registry.get_driver_info(driver_id).unwrap().driver_path);
+        self.power.register(driver_id, dependencies, policy, monotonic_ms());
+        Ok(())
+    }
+
+    /// Times a driver has been restarted after crashing or hanging
+    pub fn get_restart_count(&self, driver_id: DriverId) -> Option<u32> {
+        self.watchdog.restart_count(driver_id)
+    }
+
+    /// Devices found by the last `discover_devices`
+    pub fn devices(&self) -> &[DeviceNode] {
+        self.discovery.devices()
     }
 
     pub fn list_drivers(&self) -> Vec<DriverId> {
@@ -111,6 +447,16 @@ pub enum DriverStatus {
     Error,
 }
 
+/// Status reported to a client whose request failed with `error`
+fn driver_error_status(error: DriverError) -> ServiceStatus {
+    match error {
+        DriverError::InvalidRequest => ServiceStatus::InvalidRequest,
+        DriverError::PermissionDenied => ServiceStatus::PermissionDenied,
+        DriverError::HardwareNotFound => ServiceStatus::NotFound,
+        DriverError::InitializationFailed | DriverError::ResourceBusy => ServiceStatus::Error,
+    }
+}
+
 /// Driver Manager Service Handler
 struct DriverManagerService {
     driver_manager: DriverManager,
@@ -126,36 +472,79 @@ impl DriverManagerService {
 
 impl ServiceHandler for DriverManagerService {
     fn handle_request(&mut self, request: ServiceMessage) -> ServiceResponse {
+        let mut status = ServiceStatus::Success;
         let response_data = match request.data {
             ServiceData::DriverRequest(driver_request) => {
                 match driver_request {
                     DriverRequest::LoadDriver { path } => {
-                        // Load driver with default capabilities
-                        let capabilities = vec![]; // Would be configured based on driver requirements
-                        match self.driver_manager.load_driver(&path, capabilities) {
+                        match self.driver_manager.load_driver(&path) {
                             Ok(driver_id) => ServiceData::Binary(driver_id.to_le_bytes().to_vec()),
-                            Err(_) => ServiceData::Empty,
+                            Err(error) => {
+                                status = driver_error_status(error);
+                                ServiceData::Empty
+                            }
                         }
                     }
                     DriverRequest::UnloadDriver { driver_id } => {
-                        match self.driver_manager.unload_driver(driver_id) {
-                            Ok(_) => ServiceData::Empty,
-                            Err(_) => ServiceData::Empty,
+                        if let Err(error) = self.driver_manager.unload_driver(driver_id) {
+                            status = driver_error_status(error);
                         }
+                        ServiceData::Empty
                     }
                     DriverRequest::ListDrivers => {
                         let drivers = self.driver_manager.list_drivers();
                         let mut result = String::new();
                         for driver_id in drivers {
-                            result.push_str(&format!("Driver ID: {}\n", driver_id));
+                            let restarts = self.driver_manager.get_restart_count(driver_id).unwrap_or(0);
+                            result.push_str(&format!("Driver ID: {} restarts: {}", driver_id, restarts));
+                            match self.driver_manager.get_power_state(driver_id) {
+                                Some(RuntimeState::LowPower) => result.push_str(" (low power)\n"),
+                                Some(RuntimeState::Suspended) => result.push_str(" (suspended)\n"),
+                                _ => result.push('\n'),
+                            }
+                        }
+                        for node in self.driver_manager.devices() {
+                            let device = &node.device;
+                            result.push_str(&format!("PCI {} {:04x}:{:04x} {}", device.address,
+                                                     device.vendor_id, device.device_id, device.class_name()));
+                            match node.driver {
+                                Some(driver_id) => result.push_str(&format!(" (driver {})\n", driver_id)),
+                                None => result.push('\n'),
+                            }
                         }
                         ServiceData::Text(result)
                     }
                     DriverRequest::SendToDriver { driver_id, data } => {
-                        // For now, just return a mock response since we can't easily
-                        // handle the lifetime issue with DriverRequestData
-                        let response = format!("Response from driver {}", driver_id);
-                        ServiceData::Text(response)
+                        let driver_request = DriverRequestData {
+                            driver_id,
+                            request_type: 0,
+                            data,
+                        };
+                        match self.driver_manager.handle_driver_request(driver_request) {
+                            Ok(response) => ServiceData::Binary(response),
+                            Err(_) => ServiceData::Empty,
+                        }
+                    }
+                    DriverRequest::SetPowerPolicy { driver_id, low_power_after_ms, suspend_after_ms } => {
+                        let policy = PowerPolicy::from_ms(low_power_after_ms, suspend_after_ms);
+                        let _ = self.driver_manager.set_power_policy(driver_id, policy);
+                        ServiceData::Empty
+                    }
+                    DriverRequest::HoldDriver { driver_id } => {
+                        let _ = self.driver_manager.hold_driver(driver_id);
+                        ServiceData::Empty
+                    }
+                    DriverRequest::ReleaseDriver { driver_id } => {
+                        let _ = self.driver_manager.release_driver(driver_id);
+                        ServiceData::Empty
+                    }
+                    DriverRequest::SuspendAll => {
+                        let _ = self.driver_manager.suspend_all();
+                        ServiceData::Empty
+                    }
+                    DriverRequest::ResumeAll => {
+                        let _ = self.driver_manager.resume_all();
+                        ServiceData::Empty
                     }
                 }
             }
@@ -164,7 +553,7 @@ impl ServiceHandler for DriverManagerService {
 
         ServiceResponse {
             request_id: request.request_id,
-            status: ServiceStatus::Success,
+            status,
             data: response_data,
         }
     }
@@ -176,15 +565,13 @@ impl ServiceHandler for DriverManagerService {
     fn initialize(&mut self) -> Result<(), kosh_service::ServiceError> {
         debug_print(b"Driver Manager: Initializing service\n");
         
-        // Load essential drivers
+        // Load drivers for hardware that cannot be enumerated
         let essential_drivers = vec![
-            "/drivers/graphics.ko",
             "/drivers/keyboard.ko",
-            "/drivers/storage.ko",
         ];
         
         for driver_path in essential_drivers {
-            match self.driver_manager.load_driver(driver_path, vec![]) {
+            match self.driver_manager.load_driver(driver_path) {
                 Ok(driver_id) => {
                     debug_print(b"Driver Manager: Essential driver loaded\n");
                 }
@@ -195,6 +582,11 @@ impl ServiceHandler for DriverManagerService {
             }
         }
         
+        let mut config = PortConfigSpace::new(HardwarePortIo);
+        let bound = self.driver_manager.discover_devices(&mut config);
+        let devices = self.driver_manager.devices().len();
+        debug_print(format!("Driver Manager: {} PCI devices found, {} with drivers\n", devices, bound).as_bytes());
+        
         Ok(())
     }
 
@@ -231,20 +623,27 @@ pub extern "C" fn _start() -> ! {
     debug_print(b"Driver Manager: Service started, entering main loop\n");
     
     // Main service loop
+    let mut timeout = kosh_ipc::poll::INFINITE;
     loop {
-        // Process incoming requests
-        if let Err(_) = service_runner.run_once() {
+        // Sleep until clients send requests, a device idle timeout is due,
+        // the battery needs reading or a driver needs pinging or
+        // restarting, then serve all of them
+        if let Err(_) = service_runner.poll_and_dispatch(timeout) {
             debug_print(b"Driver Manager: Error processing request\n");
         }
-        
-        // Yield CPU to prevent busy waiting
-        yield_cpu();
+        let driver_manager = &mut service_runner.handler_mut().driver_manager;
+        let power_timeout = driver_manager.run_power_management();
+        let battery_timeout = driver_manager.run_battery_monitor();
+        let watchdog_timeout = driver_manager.run_watchdog();
+        timeout = power_timeout.into_iter().chain(battery_timeout).chain(watchdog_timeout).min()
+            .unwrap_or(kosh_ipc::poll::INFINITE);
     }
 }
 
 fn init_heap() {
-    const HEAP_SIZE: usize = 64 * 1024; // 64KB heap
-    static mut HEAP_MEMORY: [u8; 64 * 1024] = [0; 64 * 1024];
+    // Room for driver binaries read in whole before they are started
+    const HEAP_SIZE: usize = 4 * 1024 * 1024; // 4MB heap
+    static mut HEAP_MEMORY: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
     
     unsafe {
         let heap_ptr = core::ptr::addr_of_mut!(HEAP_MEMORY);
@@ -252,29 +651,10 @@ fn init_heap() {
     }
 }
 
-fn yield_cpu() {
-    for _ in 0..1000 {
-        core::hint::spin_loop();
-    }
-}
-
-fn debug_print(message: &[u8]) {
-    #[cfg(debug_assertions)]
-    unsafe {
-        core::arch::asm!(
-            "syscall",
-            in("rax") 100u64, // SYS_DEBUG_PRINT
-            in("rdi") message.as_ptr(),
-            in("rsi") message.len(),
-            options(nostack, preserves_flags)
-        );
-    }
-}
-
 fn sys_exit(status: i32) -> ! {
     unsafe {
         core::arch::asm!(
-            "syscall",
+            "int 0x80",
             in("rax") 1u64, // SYS_EXIT
             in("rdi") status,
             options(noreturn)
@@ -0,0 +1,355 @@
+use alloc::{collections::{BTreeMap, BTreeSet}, vec::Vec};
+use kosh_types::{DriverId, DriverError};
+use kosh_driver::PowerEvent;
+
+/// Runtime power state of a driver's device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RuntimeState {
+    Active,
+    LowPower,
+    Suspended,
+}
+
+/// Idle time after which a device is put in low power and suspended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PowerPolicy {
+    pub low_power_after_ms: Option<u64>,
+    pub suspend_after_ms: Option<u64>,
+}
+
+impl PowerPolicy {
+    pub const NEVER: Self = Self { low_power_after_ms: None, suspend_after_ms: None };
+
+    /// Policy from timeouts where 0 means never
+    pub fn from_ms(low_power_after_ms: u64, suspend_after_ms: u64) -> Self {
+        Self {
+            low_power_after_ms: Some(low_power_after_ms).filter(|&ms| ms > 0),
+            suspend_after_ms: Some(suspend_after_ms).filter(|&ms| ms > 0),
+        }
+    }
+
+    /// State a device idle for `idle_ms` should be in
+    fn target(&self, idle_ms: u64) -> RuntimeState {
+        if self.suspend_after_ms.is_some_and(|ms| idle_ms >= ms) {
+            RuntimeState::Suspended
+        } else if self.low_power_after_ms.is_some_and(|ms| idle_ms >= ms) {
+            RuntimeState::LowPower
+        } else {
+            RuntimeState::Active
+        }
+    }
+}
+
+/// Policy a driver starts with
+///
+/// Input and display devices must stay up to notice the user; the NIC and
+/// disks can sleep when nothing uses them.
+pub fn default_policy(driver_path: &str) -> PowerPolicy {
+    match driver_path {
+        "/drivers/network.ko" => PowerPolicy { low_power_after_ms: Some(10_000), suspend_after_ms: Some(60_000) },
+        "/drivers/storage.ko" => PowerPolicy { low_power_after_ms: Some(30_000), suspend_after_ms: Some(300_000) },
+        _ => PowerPolicy::NEVER,
+    }
+}
+
+/// A power event to send to a driver
+pub type PowerTransition = (DriverId, PowerEvent);
+
+struct DevicePower {
+    state: RuntimeState,
+    policy: PowerPolicy,
+    last_activity: u64,
+    /// Users keeping the device up regardless of activity
+    holds: u32,
+    /// Drivers that must be powered for this one to work
+    dependencies: Vec<DriverId>,
+    /// Put to sleep by `suspend_all` rather than for being idle
+    suspended_by_system: bool,
+}
+
+/// Runtime power management of driver devices
+///
+/// A driver and the drivers it depends on form a power domain: waking a
+/// driver wakes its dependencies first, and a dependency never sleeps
+/// deeper than the drivers using it. In battery saver mode devices are
+/// kept in low power at most, and use does not bring them to full power.
+pub struct RuntimePowerManager {
+    devices: BTreeMap<DriverId, DevicePower>,
+    system_suspended: bool,
+    battery_saver: bool,
+}
+
+impl RuntimePowerManager {
+    pub fn new() -> Self {
+        Self {
+            devices: BTreeMap::new(),
+            system_suspended: false,
+            battery_saver: false,
+        }
+    }
+
+    pub fn register(&mut self, driver_id: DriverId, dependencies: Vec<DriverId>, policy: PowerPolicy, now: u64) {
+        self.devices.insert(driver_id, DevicePower {
+            state: RuntimeState::Active,
+            policy,
+            last_activity: now,
+            holds: 0,
+            dependencies,
+            suspended_by_system: false,
+        });
+    }
+
+    pub fn unregister(&mut self, driver_id: DriverId) {
+        self.devices.remove(&driver_id);
+    }
+
+    pub fn set_policy(&mut self, driver_id: DriverId, policy: PowerPolicy) -> Result<(), DriverError> {
+        let device = self.devices.get_mut(&driver_id).ok_or(DriverError::InvalidRequest)?;
+        device.policy = policy;
+        Ok(())
+    }
+
+    pub fn state(&self, driver_id: DriverId) -> Option<RuntimeState> {
+        self.devices.get(&driver_id).map(|device| device.state)
+    }
+
+    /// Record activity on a driver, powering it and its dependencies up
+    ///
+    /// Returns the events to send, dependencies first. Fails with
+    /// `ResourceBusy` while the system is suspended.
+    pub fn wake(&mut self, driver_id: DriverId, now: u64) -> Result<Vec<PowerTransition>, DriverError> {
+        if !self.devices.contains_key(&driver_id) {
+            return Err(DriverError::InvalidRequest);
+        }
+        if self.system_suspended {
+            return Err(DriverError::ResourceBusy);
+        }
+        let mut transitions = Vec::new();
+        self.wake_domain(driver_id, now, &mut BTreeSet::new(), &mut transitions);
+        Ok(transitions)
+    }
+
+    fn wake_domain(&mut self, driver_id: DriverId, now: u64, visited: &mut BTreeSet<DriverId>, transitions: &mut Vec<PowerTransition>) {
+        if !visited.insert(driver_id) {
+            return;
+        }
+        let Some(dependencies) = self.devices.get(&driver_id).map(|device| device.dependencies.clone()) else {
+            return;
+        };
+        for dependency in dependencies {
+            self.wake_domain(dependency, now, visited, transitions);
+        }
+
+        let battery_saver = self.battery_saver;
+        let device = self.devices.get_mut(&driver_id).unwrap();
+        device.last_activity = now;
+        match device.state {
+            RuntimeState::Active => {}
+            RuntimeState::LowPower if battery_saver => return,
+            RuntimeState::LowPower => transitions.push((driver_id, PowerEvent::FullPower)),
+            RuntimeState::Suspended => {
+                transitions.push((driver_id, PowerEvent::Resume));
+                if battery_saver {
+                    transitions.push((driver_id, PowerEvent::LowPower));
+                    device.state = RuntimeState::LowPower;
+                    return;
+                }
+            }
+        }
+        device.state = RuntimeState::Active;
+    }
+
+    /// Keep a driver powered until `release`; wakes it like `wake`
+    pub fn hold(&mut self, driver_id: DriverId, now: u64) -> Result<Vec<PowerTransition>, DriverError> {
+        let transitions = self.wake(driver_id, now)?;
+        self.devices.get_mut(&driver_id).unwrap().holds += 1;
+        Ok(transitions)
+    }
+
+    /// Drop a hold; the idle timeouts start counting from now
+    pub fn release(&mut self, driver_id: DriverId, now: u64) -> Result<(), DriverError> {
+        let device = self.devices.get_mut(&driver_id).ok_or(DriverError::InvalidRequest)?;
+        if device.holds == 0 {
+            return Err(DriverError::InvalidRequest);
+        }
+        device.holds -= 1;
+        device.last_activity = now;
+        Ok(())
+    }
+
+    /// Deepest state the drivers depending on `driver_id` allow it
+    fn allowed_by_dependents(&self, driver_id: DriverId) -> RuntimeState {
+        self.devices.values()
+            .filter(|device| device.dependencies.contains(&driver_id))
+            .map(|device| device.state)
+            .min()
+            .unwrap_or(RuntimeState::Suspended)
+    }
+
+    /// Put devices idle past their policy's timeouts to sleep
+    ///
+    /// Returns the events to send, users before their dependencies.
+    pub fn idle(&mut self, now: u64) -> Vec<PowerTransition> {
+        let mut transitions = Vec::new();
+        if self.system_suspended {
+            return transitions;
+        }
+        let floor = if self.battery_saver { RuntimeState::LowPower } else { RuntimeState::Active };
+        // A dependency may only sleep once its users have, so go on until
+        // nothing changes
+        loop {
+            let mut changed = false;
+            let ids: Vec<DriverId> = self.devices.keys().copied().collect();
+            for driver_id in ids {
+                let allowed = self.allowed_by_dependents(driver_id);
+                let device = self.devices.get_mut(&driver_id).unwrap();
+                if device.holds > 0 {
+                    continue;
+                }
+                let target = device.policy.target(now.saturating_sub(device.last_activity)).max(floor).min(allowed);
+                if target <= device.state {
+                    continue;
+                }
+                device.state = target;
+                let event = match target {
+                    RuntimeState::LowPower => PowerEvent::LowPower,
+                    _ => PowerEvent::Suspend,
+                };
+                transitions.push((driver_id, event));
+                changed = true;
+            }
+            if !changed {
+                return transitions;
+            }
+        }
+    }
+
+    /// Turn battery saver mode on or off
+    ///
+    /// Turning it on puts the active devices nothing holds in low power,
+    /// users before their dependencies; turning it off brings the ones
+    /// not idle past their low power timeout back to full power,
+    /// dependencies first. Returns the events to send.
+    pub fn set_battery_saver(&mut self, enabled: bool, now: u64) -> Vec<PowerTransition> {
+        let mut transitions = Vec::new();
+        if enabled == self.battery_saver {
+            return transitions;
+        }
+        self.battery_saver = enabled;
+        if self.system_suspended {
+            return transitions;
+        }
+        if enabled {
+            for driver_id in self.dependency_order().into_iter().rev() {
+                let allowed = self.allowed_by_dependents(driver_id);
+                let device = self.devices.get_mut(&driver_id).unwrap();
+                if device.state == RuntimeState::Active && device.holds == 0 && allowed >= RuntimeState::LowPower {
+                    device.state = RuntimeState::LowPower;
+                    transitions.push((driver_id, PowerEvent::LowPower));
+                }
+            }
+        } else {
+            for driver_id in self.dependency_order() {
+                let device = self.devices.get_mut(&driver_id).unwrap();
+                let idle_target = device.policy.target(now.saturating_sub(device.last_activity));
+                if device.state == RuntimeState::LowPower && idle_target == RuntimeState::Active {
+                    device.state = RuntimeState::Active;
+                    transitions.push((driver_id, PowerEvent::FullPower));
+                }
+            }
+        }
+        transitions
+    }
+
+    /// Milliseconds until the next idle timeout expires
+    ///
+    /// Timeouts already past are left out: `idle` acted on them, or the
+    /// drivers depending on the device keep it up and it follows them
+    /// down in the same `idle` call.
+    pub fn next_timeout(&self, now: u64) -> Option<u64> {
+        if self.system_suspended {
+            return None;
+        }
+        self.devices.values()
+            .filter(|device| device.holds == 0)
+            .flat_map(|device| {
+                let deadline = |timeout: Option<u64>, state| {
+                    timeout.filter(|_| device.state < state).map(|ms| (device.last_activity + ms).saturating_sub(now))
+                };
+                [
+                    deadline(device.policy.low_power_after_ms, RuntimeState::LowPower),
+                    deadline(device.policy.suspend_after_ms, RuntimeState::Suspended),
+                ]
+            })
+            .flatten()
+            .filter(|&ms| ms > 0)
+            .min()
+    }
+
+    /// Length of the longest dependency chain below `driver_id`
+    fn level(&self, driver_id: DriverId, visited: &mut BTreeSet<DriverId>) -> usize {
+        if !visited.insert(driver_id) {
+            return 0;
+        }
+        let level = self.devices.get(&driver_id)
+            .map(|device| device.dependencies.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|dependency| self.level(dependency, visited) + 1)
+            .max()
+            .unwrap_or(0);
+        visited.remove(&driver_id);
+        level
+    }
+
+    /// Drivers ordered so dependencies come before their users
+    fn dependency_order(&self) -> Vec<DriverId> {
+        let mut order: Vec<(usize, DriverId)> = self.devices.keys()
+            .map(|&driver_id| (self.level(driver_id, &mut BTreeSet::new()), driver_id))
+            .collect();
+        order.sort();
+        order.into_iter().map(|(_, driver_id)| driver_id).collect()
+    }
+
+    /// Suspend every device that is not already for system sleep
+    ///
+    /// Returns the events to send, users before their dependencies.
+    pub fn suspend_all(&mut self) -> Vec<PowerTransition> {
+        if self.system_suspended {
+            return Vec::new();
+        }
+        self.system_suspended = true;
+        let mut transitions = Vec::new();
+        for driver_id in self.dependency_order().into_iter().rev() {
+            let device = self.devices.get_mut(&driver_id).unwrap();
+            if device.state != RuntimeState::Suspended {
+                device.state = RuntimeState::Suspended;
+                device.suspended_by_system = true;
+                transitions.push((driver_id, PowerEvent::Suspend));
+            }
+        }
+        transitions
+    }
+
+    /// Resume the devices `suspend_all` suspended
+    ///
+    /// Devices that were asleep for being idle stay asleep until used.
+    /// Returns the events to send, dependencies first.
+    pub fn resume_all(&mut self, now: u64) -> Vec<PowerTransition> {
+        if !self.system_suspended {
+            return Vec::new();
+        }
+        self.system_suspended = false;
+        let mut transitions = Vec::new();
+        for driver_id in self.dependency_order() {
+            let device = self.devices.get_mut(&driver_id).unwrap();
+            if device.suspended_by_system {
+                device.suspended_by_system = false;
+                device.state = RuntimeState::Active;
+                device.last_activity = now;
+                transitions.push((driver_id, PowerEvent::Resume));
+            }
+        }
+        transitions
+    }
+}
@@ -0,0 +1,174 @@
+//! Driver signature verification
+//!
+//! A driver only starts if it carries a valid signature by the key the
+//! kernel was built with. Booting with `safe_mode` or `recovery` lets a
+//! driver without one load as well, with a warning, so a broken system
+//! can be repaired with a driver that was never signed. A kernel built
+//! without a key accepts unsigned drivers only in those modes, since it
+//! has nothing to check signatures against.
+
+use ed25519_compact::{PublicKey, Signature};
+use kosh_driver::signature::{DriverSignatureRecord, DriverTrust, DRIVER_SIGNATURE_SECTION, PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use kosh_types::DriverError;
+use crate::elf::ElfFile;
+
+/// What a driver binary's signature section holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// Signed by the trusted key
+    Valid,
+    /// No section, or an unsigned record
+    Unsigned,
+    /// Signed, but not by the trusted key or not over this file
+    Invalid,
+}
+
+/// Check the signature of the driver binary `data` against `key`
+pub fn verify(elf: &ElfFile, data: &[u8], key: &[u8; PUBLIC_KEY_LEN]) -> SignatureStatus {
+    let range = match elf.section_range(DRIVER_SIGNATURE_SECTION) {
+        Some(range) => range,
+        None => return SignatureStatus::Unsigned,
+    };
+    let signature = match DriverSignatureRecord::signature(&data[range.clone()]) {
+        Some(signature) => signature,
+        None => return SignatureStatus::Unsigned,
+    };
+
+    // The file is signed with the signature itself zeroed
+    let signature_start = range.start + DriverSignatureRecord::SIGNATURE_OFFSET;
+    let signature_end = signature_start + SIGNATURE_LEN;
+    let valid = PublicKey::new(*key)
+        .verify_incremental(&Signature::new(signature))
+        .and_then(|mut state| {
+            state.absorb(&data[..signature_start]);
+            state.absorb([0u8; SIGNATURE_LEN]);
+            state.absorb(&data[signature_end..]);
+            state.verify()
+        })
+        .is_ok();
+    if valid { SignatureStatus::Valid } else { SignatureStatus::Invalid }
+}
+
+/// Decide whether a driver with signature `status` may load under `trust`
+pub fn admit(status: SignatureStatus, trust: &DriverTrust) -> Result<(), DriverError> {
+    match status {
+        SignatureStatus::Valid => Ok(()),
+        _ if trust.unsigned_allowed => Ok(()),
+        _ => Err(DriverError::PermissionDenied),
+    }
+}
+
+/// Check the signature of a parsed driver binary and decide whether it
+/// may load
+pub fn check_driver(elf: &ElfFile, data: &[u8], trust: &DriverTrust) -> Result<SignatureStatus, DriverError> {
+    let status = match &trust.public_key {
+        Some(key) => verify(elf, data, key),
+        None => SignatureStatus::Unsigned,
+    };
+    admit(status, trust).map(|_| status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use ed25519_compact::{KeyPair, Seed};
+    use crate::elf::tests::build_elf;
+
+    const PF_X: u32 = 1;
+
+    fn key_pair(seed: u8) -> KeyPair {
+        KeyPair::from_seed(Seed::new([seed; 32]))
+    }
+
+    /// A driver binary reserving an unsigned signature record
+    fn unsigned_driver() -> Vec<u8> {
+        let mut record = Vec::new();
+        record.extend_from_slice(b"KSIG");
+        record.extend_from_slice(&1u32.to_le_bytes());
+        record.extend_from_slice(&[0; SIGNATURE_LEN]);
+        assert_eq!(record.len(), DriverSignatureRecord::SIZE);
+        build_elf(0x1000, &[(PF_X, 0x1000, 0x100)], &[(".text", &[0x90; 32]), (DRIVER_SIGNATURE_SECTION, &record)])
+    }
+
+    /// Sign `data` in place as the signing tool does
+    fn sign(data: &mut [u8], key_pair: &KeyPair) {
+        let range = ElfFile::parse(data).unwrap().section_range(DRIVER_SIGNATURE_SECTION).unwrap();
+        let signature = key_pair.sk.sign(&*data, None);
+        let start = range.start + DriverSignatureRecord::SIGNATURE_OFFSET;
+        data[start..start + SIGNATURE_LEN].copy_from_slice(signature.as_ref());
+    }
+
+    fn trust(key_pair: &KeyPair, unsigned_allowed: bool) -> DriverTrust {
+        DriverTrust { public_key: Some(*key_pair.pk), unsigned_allowed }
+    }
+
+    #[test]
+    fn test_valid_signature_is_admitted() {
+        let keys = key_pair(1);
+        let mut data = unsigned_driver();
+        sign(&mut data, &keys);
+        let elf = ElfFile::parse(&data).unwrap();
+
+        assert_eq!(verify(&elf, &data, &keys.pk), SignatureStatus::Valid);
+        assert!(matches!(check_driver(&elf, &data, &trust(&keys, false)), Ok(SignatureStatus::Valid)));
+    }
+
+    #[test]
+    fn test_tampered_image_is_rejected() {
+        let keys = key_pair(1);
+        let mut data = unsigned_driver();
+        sign(&mut data, &keys);
+        let code = ElfFile::parse(&data).unwrap().section_range(".text").unwrap();
+        data[code.start] ^= 0xFF;
+        let elf = ElfFile::parse(&data).unwrap();
+
+        assert_eq!(verify(&elf, &data, &keys.pk), SignatureStatus::Invalid);
+        assert!(matches!(check_driver(&elf, &data, &trust(&keys, false)), Err(DriverError::PermissionDenied)));
+    }
+
+    #[test]
+    fn test_other_key_is_rejected() {
+        let mut data = unsigned_driver();
+        sign(&mut data, &key_pair(2));
+        let elf = ElfFile::parse(&data).unwrap();
+
+        assert_eq!(verify(&elf, &data, &key_pair(1).pk), SignatureStatus::Invalid);
+        assert!(matches!(check_driver(&elf, &data, &trust(&key_pair(1), false)), Err(DriverError::PermissionDenied)));
+    }
+
+    #[test]
+    fn test_unsigned_driver_rejected_in_normal_mode() {
+        let keys = key_pair(1);
+        let data = unsigned_driver();
+        let elf = ElfFile::parse(&data).unwrap();
+
+        assert_eq!(verify(&elf, &data, &keys.pk), SignatureStatus::Unsigned);
+        assert!(matches!(check_driver(&elf, &data, &trust(&keys, false)), Err(DriverError::PermissionDenied)));
+
+        // Nor does a kernel without a key take it
+        let keyless = DriverTrust { public_key: None, unsigned_allowed: false };
+        assert!(matches!(check_driver(&elf, &data, &keyless), Err(DriverError::PermissionDenied)));
+
+        // A binary without a signature section is unsigned too
+        let bare = build_elf(0x1000, &[(PF_X, 0x1000, 0x100)], &[(".text", &[0x90; 32])]);
+        let elf = ElfFile::parse(&bare).unwrap();
+        assert_eq!(verify(&elf, &bare, &keys.pk), SignatureStatus::Unsigned);
+    }
+
+    #[test]
+    fn test_unsigned_driver_admitted_in_safe_mode() {
+        // Safe mode and recovery both show up as unsigned drivers being allowed
+        let keys = key_pair(1);
+        let data = unsigned_driver();
+        let elf = ElfFile::parse(&data).unwrap();
+        assert!(matches!(check_driver(&elf, &data, &trust(&keys, true)), Ok(SignatureStatus::Unsigned)));
+
+        let keyless = DriverTrust { public_key: None, unsigned_allowed: true };
+        assert!(matches!(check_driver(&elf, &data, &keyless), Ok(SignatureStatus::Unsigned)));
+
+        // An invalid signature is let through with the same warning
+        assert!(matches!(admit(SignatureStatus::Invalid, &trust(&keys, true)), Ok(())));
+        assert!(matches!(admit(SignatureStatus::Unsigned, &trust(&keys, false)), Err(DriverError::PermissionDenied)));
+    }
+}
@@ -0,0 +1,156 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+use kosh_types::DriverId;
+
+/// Time between heartbeat pings to a running driver
+pub const HEARTBEAT_INTERVAL_MS: u64 = 5_000;
+
+/// Pings a driver may leave unanswered before it is taken for hung
+pub const MAX_MISSED_HEARTBEATS: u32 = 3;
+
+/// Delay before the first restart of a failed driver
+const INITIAL_BACKOFF_MS: u64 = 500;
+
+/// Longest delay between restarts of a driver that keeps failing
+const MAX_BACKOFF_MS: u64 = 60_000;
+
+/// Uptime after which a restarted driver counts as healthy again and its
+/// next failure is restarted after the initial delay
+const STABLE_AFTER_MS: u64 = 30_000;
+
+/// Why a driver is being restarted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverFailure {
+    /// The driver process exited with this status
+    Exited(i32),
+    /// The driver stopped answering heartbeats
+    Unresponsive,
+}
+
+struct DriverHealth {
+    /// When the driver was last started
+    started_at: u64,
+    next_heartbeat: u64,
+    missed_heartbeats: u32,
+    restarts: u32,
+    /// Delay before the next restart after a failure
+    backoff_ms: u64,
+    /// Set while the driver is down and waiting to be restarted
+    restart_at: Option<u64>,
+}
+
+/// Heartbeat and crash supervision of driver processes
+///
+/// A failed driver is restarted after a delay that doubles with every
+/// failure, so one that crashes on start does not spin the system, and
+/// falls back once the driver has stayed up for a while.
+pub struct DriverWatchdog {
+    drivers: BTreeMap<DriverId, DriverHealth>,
+}
+
+impl DriverWatchdog {
+    pub fn new() -> Self {
+        Self {
+            drivers: BTreeMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, driver_id: DriverId, now: u64) {
+        self.drivers.insert(driver_id, DriverHealth {
+            started_at: now,
+            next_heartbeat: now + HEARTBEAT_INTERVAL_MS,
+            missed_heartbeats: 0,
+            restarts: 0,
+            backoff_ms: INITIAL_BACKOFF_MS,
+            restart_at: None,
+        });
+    }
+
+    pub fn unregister(&mut self, driver_id: DriverId) {
+        self.drivers.remove(&driver_id);
+    }
+
+    /// Times `driver_id` has been restarted since it was loaded
+    pub fn restart_count(&self, driver_id: DriverId) -> Option<u32> {
+        self.drivers.get(&driver_id).map(|health| health.restarts)
+    }
+
+    /// Running drivers whose heartbeat is due, scheduling their next one
+    pub fn heartbeats_due(&mut self, now: u64) -> Vec<DriverId> {
+        let mut due = Vec::new();
+        for (&driver_id, health) in self.drivers.iter_mut() {
+            if health.restart_at.is_none() && health.next_heartbeat <= now {
+                health.next_heartbeat = now + HEARTBEAT_INTERVAL_MS;
+                due.push(driver_id);
+            }
+        }
+        due
+    }
+
+    /// Record the outcome of a heartbeat ping
+    ///
+    /// Returns true once the driver has missed too many in a row.
+    pub fn heartbeat_result(&mut self, driver_id: DriverId, answered: bool) -> bool {
+        let Some(health) = self.drivers.get_mut(&driver_id) else {
+            return false;
+        };
+        if answered {
+            health.missed_heartbeats = 0;
+            return false;
+        }
+        health.missed_heartbeats += 1;
+        health.missed_heartbeats >= MAX_MISSED_HEARTBEATS
+    }
+
+    /// Schedule a restart of a driver that crashed or hung
+    ///
+    /// Returns the delay before the restart, None for an unknown driver or
+    /// one already waiting to restart.
+    pub fn driver_failed(&mut self, driver_id: DriverId, now: u64) -> Option<u64> {
+        let health = self.drivers.get_mut(&driver_id)?;
+        if health.restart_at.is_some() {
+            return None;
+        }
+        if now.saturating_sub(health.started_at) >= STABLE_AFTER_MS {
+            health.backoff_ms = INITIAL_BACKOFF_MS;
+        }
+        let delay = health.backoff_ms;
+        health.restart_at = Some(now + delay);
+        health.backoff_ms = (delay * 2).min(MAX_BACKOFF_MS);
+        Some(delay)
+    }
+
+    /// Try again later after a restart attempt failed
+    pub fn restart_failed(&mut self, driver_id: DriverId, now: u64) {
+        if let Some(health) = self.drivers.get_mut(&driver_id) {
+            health.started_at = now;
+            health.restart_at = None;
+        }
+        self.driver_failed(driver_id, now);
+    }
+
+    /// Failed drivers whose restart delay has passed
+    pub fn restarts_due(&self, now: u64) -> Vec<DriverId> {
+        self.drivers.iter()
+            .filter(|(_, health)| health.restart_at.is_some_and(|at| at <= now))
+            .map(|(&driver_id, _)| driver_id)
+            .collect()
+    }
+
+    /// Record that a failed driver was started again
+    pub fn restarted(&mut self, driver_id: DriverId, now: u64) {
+        if let Some(health) = self.drivers.get_mut(&driver_id) {
+            health.started_at = now;
+            health.next_heartbeat = now + HEARTBEAT_INTERVAL_MS;
+            health.missed_heartbeats = 0;
+            health.restarts += 1;
+            health.restart_at = None;
+        }
+    }
+
+    /// Milliseconds until the next heartbeat or restart is due
+    pub fn next_timeout(&self, now: u64) -> Option<u64> {
+        self.drivers.values()
+            .map(|health| health.restart_at.unwrap_or(health.next_heartbeat).saturating_sub(now))
+            .min()
+    }
+}
@@ -0,0 +1,133 @@
+//! Block devices file systems are stored on
+//!
+//! A device is read and written in whole blocks of its own size, which is
+//! usually smaller than the file system's. `read_at` and `write_at` move
+//! byte ranges on top of that, reading and rewriting the partial blocks at
+//! either end.
+
+use alloc::{vec, vec::Vec, collections::BTreeMap};
+use kosh_types::VfsError;
+
+/// Sector size of devices that do not say otherwise
+pub const SECTOR_SIZE: usize = 512;
+
+/// Storage addressed in fixed-size blocks
+pub trait BlockDevice {
+    /// Bytes per block
+    fn block_size(&self) -> usize;
+
+    fn block_count(&self) -> u64;
+
+    /// Fill `buffer`, one block long, with block `index`
+    fn read_block(&mut self, index: u64, buffer: &mut [u8]) -> Result<(), VfsError>;
+
+    /// Store `buffer`, one block long, as block `index`
+    fn write_block(&mut self, index: u64, buffer: &[u8]) -> Result<(), VfsError>;
+
+    /// Make every completed write durable
+    fn flush(&mut self) -> Result<(), VfsError> {
+        Ok(())
+    }
+}
+
+/// Check that `index` and `buffer` address one block of `device`
+fn check_block(device: &dyn BlockDevice, index: u64, length: usize) -> Result<(), VfsError> {
+    if length != device.block_size() || index >= device.block_count() {
+        return Err(VfsError::IoError);
+    }
+    Ok(())
+}
+
+/// Fill `buffer` from byte `offset` of `device`
+pub fn read_at(device: &mut dyn BlockDevice, offset: u64, buffer: &mut [u8]) -> Result<(), VfsError> {
+    let block_size = device.block_size();
+    let mut block = vec![0u8; block_size];
+    let mut done = 0;
+    while done < buffer.len() {
+        let position = offset + done as u64;
+        let within = (position % block_size as u64) as usize;
+        let count = (buffer.len() - done).min(block_size - within);
+        device.read_block(position / block_size as u64, &mut block)?;
+        buffer[done..done + count].copy_from_slice(&block[within..within + count]);
+        done += count;
+    }
+    Ok(())
+}
+
+/// Store `buffer` at byte `offset` of `device`
+///
+/// Blocks only partly covered are read first, so the bytes around the
+/// range keep their contents.
+pub fn write_at(device: &mut dyn BlockDevice, offset: u64, buffer: &[u8]) -> Result<(), VfsError> {
+    let block_size = device.block_size();
+    let mut block = vec![0u8; block_size];
+    let mut done = 0;
+    while done < buffer.len() {
+        let position = offset + done as u64;
+        let index = position / block_size as u64;
+        let within = (position % block_size as u64) as usize;
+        let count = (buffer.len() - done).min(block_size - within);
+        if count < block_size {
+            device.read_block(index, &mut block)?;
+        }
+        block[within..within + count].copy_from_slice(&buffer[done..done + count]);
+        device.write_block(index, &block)?;
+        done += count;
+    }
+    Ok(())
+}
+
+/// Device held in memory
+///
+/// Only blocks that were written and are not all zeros take up memory, so
+/// a freshly formatted disk costs little more than its metadata.
+pub struct RamDisk {
+    block_size: usize,
+    block_count: u64,
+    blocks: BTreeMap<u64, Vec<u8>>,
+}
+
+impl RamDisk {
+    /// Zero-filled disk of `block_count` blocks of `block_size` bytes
+    pub fn new(block_size: usize, block_count: u64) -> Self {
+        Self {
+            block_size,
+            block_count,
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    /// Bytes of memory holding block contents
+    pub fn memory_used(&self) -> usize {
+        self.blocks.len() * self.block_size
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn read_block(&mut self, index: u64, buffer: &mut [u8]) -> Result<(), VfsError> {
+        check_block(self, index, buffer.len())?;
+        match self.blocks.get(&index) {
+            Some(block) => buffer.copy_from_slice(block),
+            None => buffer.fill(0),
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, index: u64, buffer: &[u8]) -> Result<(), VfsError> {
+        check_block(self, index, buffer.len())?;
+        if buffer.iter().all(|&byte| byte == 0) {
+            self.blocks.remove(&index);
+        } else {
+            self.blocks.insert(index, buffer.to_vec());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,404 @@
+//! Block cache shared by every mounted file system
+//!
+//! Devices are attached to one `BlockCache` and handed to file systems as
+//! `CachedDevice`s, so directory blocks and inode tables read again and
+//! again come from memory. Writes stay in the cache, marked dirty, until
+//! the block is evicted, its device is flushed or the cache is synced.
+//! The least recently used blocks go first once the cache holds more than
+//! its memory budget.
+
+use crate::block::BlockDevice;
+use alloc::{vec::Vec, boxed::Box, collections::BTreeMap, rc::Rc};
+use core::cell::RefCell;
+use kosh_types::VfsError;
+
+/// Memory the cache may use unless told otherwise
+pub const DEFAULT_CACHE_BUDGET: usize = 32 * 1024;
+
+/// Identifies a device attached to a cache
+pub type DeviceId = u32;
+
+/// A cache shared by the devices attached to it
+pub type SharedBlockCache = Rc<RefCell<BlockCache>>;
+
+struct CachedBlock {
+    data: Vec<u8>,
+    dirty: bool,
+    /// Key in `BlockCache::recency`
+    last_used: u64,
+}
+
+/// Counters for judging whether the budget suits the workload
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Bytes of block contents held
+    pub used: usize,
+    pub budget: usize,
+    pub dirty_blocks: usize,
+}
+
+/// Least recently used cache of device blocks
+pub struct BlockCache {
+    budget: usize,
+    used: usize,
+    devices: BTreeMap<DeviceId, Box<dyn BlockDevice>>,
+    next_device: DeviceId,
+    blocks: BTreeMap<(DeviceId, u64), CachedBlock>,
+    /// Blocks by the time they were last used, oldest first
+    recency: BTreeMap<u64, (DeviceId, u64)>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl BlockCache {
+    /// Cache holding at most `budget` bytes of blocks
+    ///
+    /// A budget smaller than a device's blocks leaves that device uncached:
+    /// its reads and writes go straight through.
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            used: 0,
+            devices: BTreeMap::new(),
+            next_device: 1,
+            blocks: BTreeMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Cache to share between file systems
+    pub fn shared(budget: usize) -> SharedBlockCache {
+        Rc::new(RefCell::new(Self::new(budget)))
+    }
+
+    /// Put `device` behind `cache`
+    ///
+    /// The device is detached, with its dirty blocks written back, when the
+    /// returned `CachedDevice` is dropped.
+    pub fn attach(cache: &SharedBlockCache, device: Box<dyn BlockDevice>) -> CachedDevice {
+        let block_size = device.block_size();
+        let block_count = device.block_count();
+        let mut inner = cache.borrow_mut();
+        let id = inner.next_device;
+        inner.next_device += 1;
+        inner.devices.insert(id, device);
+        CachedDevice {
+            cache: cache.clone(),
+            id,
+            block_size,
+            block_count,
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            used: self.used,
+            budget: self.budget,
+            dirty_blocks: self.blocks.values().filter(|block| block.dirty).count(),
+        }
+    }
+
+    /// Change the memory budget, evicting blocks until the cache fits
+    pub fn set_budget(&mut self, budget: usize) -> Result<(), VfsError> {
+        self.budget = budget;
+        self.evict_to(budget)
+    }
+
+    /// Write every dirty block back and flush every device
+    pub fn sync(&mut self) -> Result<(), VfsError> {
+        let devices: Vec<DeviceId> = self.devices.keys().copied().collect();
+        for device in devices {
+            self.flush_device(device)?;
+        }
+        Ok(())
+    }
+
+    fn device(&mut self, device: DeviceId) -> Result<&mut Box<dyn BlockDevice>, VfsError> {
+        self.devices.get_mut(&device).ok_or(VfsError::IoError)
+    }
+
+    fn touch(&mut self, key: (DeviceId, u64)) {
+        self.clock += 1;
+        if let Some(block) = self.blocks.get_mut(&key) {
+            self.recency.remove(&block.last_used);
+            block.last_used = self.clock;
+            self.recency.insert(self.clock, key);
+        }
+    }
+
+    /// Drop the least recently used blocks until at most `limit` bytes are
+    /// held, writing dirty ones back first
+    fn evict_to(&mut self, limit: usize) -> Result<(), VfsError> {
+        while self.used > limit {
+            let Some((&last_used, &key)) = self.recency.iter().next() else { break };
+            self.write_back(key)?;
+            self.recency.remove(&last_used);
+            if let Some(block) = self.blocks.remove(&key) {
+                self.used -= block.data.len();
+            }
+        }
+        Ok(())
+    }
+
+    fn write_back(&mut self, key: (DeviceId, u64)) -> Result<(), VfsError> {
+        let Some(block) = self.blocks.get(&key).filter(|block| block.dirty) else { return Ok(()) };
+        let data = block.data.clone();
+        self.device(key.0)?.write_block(key.1, &data)?;
+        if let Some(block) = self.blocks.get_mut(&key) {
+            block.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Hold `data` as block `key`, making room for it first
+    fn insert(&mut self, key: (DeviceId, u64), data: Vec<u8>, dirty: bool) -> Result<(), VfsError> {
+        self.evict_to(self.budget.saturating_sub(data.len()))?;
+        self.used += data.len();
+        self.clock += 1;
+        self.recency.insert(self.clock, key);
+        self.blocks.insert(key, CachedBlock { data, dirty, last_used: self.clock });
+        Ok(())
+    }
+
+    fn read_block(&mut self, device: DeviceId, index: u64, buffer: &mut [u8]) -> Result<(), VfsError> {
+        let key = (device, index);
+        if let Some(block) = self.blocks.get(&key) {
+            buffer.copy_from_slice(&block.data);
+            self.hits += 1;
+            self.touch(key);
+            return Ok(());
+        }
+
+        self.misses += 1;
+        self.device(device)?.read_block(index, buffer)?;
+        if buffer.len() <= self.budget {
+            self.insert(key, buffer.to_vec(), false)?;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, device: DeviceId, index: u64, buffer: &[u8]) -> Result<(), VfsError> {
+        let key = (device, index);
+        if let Some(block) = self.blocks.get_mut(&key) {
+            block.data.copy_from_slice(buffer);
+            block.dirty = true;
+            self.touch(key);
+            return Ok(());
+        }
+
+        if buffer.len() > self.budget {
+            return self.device(device)?.write_block(index, buffer);
+        }
+        self.insert(key, buffer.to_vec(), true)
+    }
+
+    fn flush_device(&mut self, device: DeviceId) -> Result<(), VfsError> {
+        let dirty: Vec<(DeviceId, u64)> = self.blocks.range((device, 0)..=(device, u64::MAX))
+            .filter(|(_, block)| block.dirty)
+            .map(|(&key, _)| key)
+            .collect();
+        for key in dirty {
+            self.write_back(key)?;
+        }
+        self.device(device)?.flush()
+    }
+
+    /// Write back and forget the blocks of `device`, then let it go
+    fn detach(&mut self, device: DeviceId) -> Result<(), VfsError> {
+        let result = self.flush_device(device);
+        let keys: Vec<(DeviceId, u64)> = self.blocks.range((device, 0)..=(device, u64::MAX))
+            .map(|(&key, _)| key)
+            .collect();
+        for key in keys {
+            if let Some(block) = self.blocks.remove(&key) {
+                self.recency.remove(&block.last_used);
+                self.used -= block.data.len();
+            }
+        }
+        self.devices.remove(&device);
+        result
+    }
+}
+
+/// A device reached through a `BlockCache`
+pub struct CachedDevice {
+    cache: SharedBlockCache,
+    id: DeviceId,
+    block_size: usize,
+    block_count: u64,
+}
+
+impl CachedDevice {
+    fn check_block(&self, index: u64, length: usize) -> Result<(), VfsError> {
+        if length != self.block_size || index >= self.block_count {
+            return Err(VfsError::IoError);
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for CachedDevice {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn read_block(&mut self, index: u64, buffer: &mut [u8]) -> Result<(), VfsError> {
+        self.check_block(index, buffer.len())?;
+        self.cache.borrow_mut().read_block(self.id, index, buffer)
+    }
+
+    fn write_block(&mut self, index: u64, buffer: &[u8]) -> Result<(), VfsError> {
+        self.check_block(index, buffer.len())?;
+        self.cache.borrow_mut().write_block(self.id, index, buffer)
+    }
+
+    /// Write this device's dirty blocks back, then flush the device
+    fn flush(&mut self) -> Result<(), VfsError> {
+        self.cache.borrow_mut().flush_device(self.id)
+    }
+}
+
+impl Drop for CachedDevice {
+    fn drop(&mut self) {
+        // File systems sync before they let go of their device; a failure
+        // here has nowhere to be reported
+        let _ = self.cache.borrow_mut().detach(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{self, RamDisk};
+    use core::cell::Cell;
+
+    type Counter = Rc<Cell<usize>>;
+
+    /// RAM disk counting the operations that reach it
+    struct CountingDisk {
+        disk: RamDisk,
+        reads: Counter,
+        writes: Counter,
+    }
+
+    impl BlockDevice for CountingDisk {
+        fn block_size(&self) -> usize {
+            self.disk.block_size()
+        }
+
+        fn block_count(&self) -> u64 {
+            self.disk.block_count()
+        }
+
+        fn read_block(&mut self, index: u64, buffer: &mut [u8]) -> Result<(), VfsError> {
+            self.reads.set(self.reads.get() + 1);
+            self.disk.read_block(index, buffer)
+        }
+
+        fn write_block(&mut self, index: u64, buffer: &[u8]) -> Result<(), VfsError> {
+            self.writes.set(self.writes.get() + 1);
+            self.disk.write_block(index, buffer)
+        }
+    }
+
+    fn counting_disk() -> (Box<dyn BlockDevice>, Counter, Counter) {
+        let reads = Rc::new(Cell::new(0));
+        let writes = Rc::new(Cell::new(0));
+        let disk = CountingDisk { disk: RamDisk::new(512, 64), reads: reads.clone(), writes: writes.clone() };
+        (Box::new(disk), reads, writes)
+    }
+
+    #[test]
+    fn test_repeated_reads_hit_the_cache() {
+        let cache = BlockCache::shared(4 * 512);
+        let (disk, reads, _) = counting_disk();
+        let mut device = BlockCache::attach(&cache, disk);
+
+        let mut buffer = [0u8; 512];
+        for _ in 0..3 {
+            device.read_block(5, &mut buffer).unwrap();
+        }
+        assert_eq!(reads.get(), 1);
+        let stats = cache.borrow().stats();
+        assert_eq!((stats.hits, stats.misses, stats.used), (2, 1, 512));
+    }
+
+    #[test]
+    fn test_least_recently_used_block_is_evicted() {
+        let cache = BlockCache::shared(2 * 512);
+        let (disk, reads, writes) = counting_disk();
+        let mut device = BlockCache::attach(&cache, disk);
+
+        let mut buffer = [0u8; 512];
+        device.write_block(0, &[1u8; 512]).unwrap();
+        device.read_block(1, &mut buffer).unwrap();
+        device.read_block(0, &mut buffer).unwrap();
+        assert_eq!(writes.get(), 0);
+
+        // Block 1 was used longest ago; block 0 stays, still dirty
+        device.read_block(2, &mut buffer).unwrap();
+        assert_eq!(writes.get(), 0);
+        device.read_block(0, &mut buffer).unwrap();
+        assert_eq!(buffer, [1u8; 512]);
+        assert_eq!(reads.get(), 2);
+
+        // Shrinking the budget writes block 0 back as it goes
+        cache.borrow_mut().set_budget(0).unwrap();
+        assert_eq!(writes.get(), 1);
+        assert_eq!(cache.borrow().stats().used, 0);
+        device.read_block(0, &mut buffer).unwrap();
+        assert_eq!(buffer, [1u8; 512]);
+    }
+
+    #[test]
+    fn test_sync_writes_dirty_blocks_once() {
+        let cache = BlockCache::shared(8 * 512);
+        let (disk, _, writes) = counting_disk();
+        let mut device = BlockCache::attach(&cache, disk);
+
+        block::write_at(&mut device, 100, b"hello").unwrap();
+        block::write_at(&mut device, 600, b"world").unwrap();
+        block::write_at(&mut device, 105, b", again").unwrap();
+        assert_eq!(cache.borrow().stats().dirty_blocks, 2);
+        assert_eq!(writes.get(), 0);
+
+        cache.borrow_mut().sync().unwrap();
+        assert_eq!(writes.get(), 2);
+        assert_eq!(cache.borrow().stats().dirty_blocks, 0);
+        cache.borrow_mut().sync().unwrap();
+        assert_eq!(writes.get(), 2);
+    }
+
+    #[test]
+    fn test_devices_share_the_budget() {
+        let cache = BlockCache::shared(2 * 512);
+        let (first_disk, first_reads, _) = counting_disk();
+        let (second_disk, _, second_writes) = counting_disk();
+        let mut first = BlockCache::attach(&cache, first_disk);
+        let mut second = BlockCache::attach(&cache, second_disk);
+
+        let mut buffer = [0u8; 512];
+        first.read_block(0, &mut buffer).unwrap();
+        second.write_block(0, &[7u8; 512]).unwrap();
+        second.write_block(1, &[8u8; 512]).unwrap();
+        first.read_block(0, &mut buffer).unwrap();
+        assert_eq!(first_reads.get(), 2);
+
+        // Dropping a device writes its blocks back and frees their memory
+        drop(second);
+        assert_eq!(second_writes.get(), 2);
+        assert_eq!(cache.borrow().stats().used, 512);
+    }
+}
@@ -0,0 +1,190 @@
+//! LZ4 block compression for read-only file system images
+//!
+//! Only the raw block format is implemented, without the LZ4 frame
+//! header: a block is a series of sequences, each a token byte, literal
+//! bytes and a back-reference into the already decompressed output. The
+//! compressor is a simple greedy one, used when building images; speed of
+//! decompression is what matters on the device.
+
+use alloc::{vec, vec::Vec};
+use kosh_types::VfsError;
+
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = u16::MAX as usize;
+/// The last bytes of a block are always literals
+const LAST_LITERALS: usize = 5;
+/// No match may start closer than this to the end of a block
+const MATCH_START_LIMIT: usize = 12;
+const HASH_BITS: u32 = 12;
+
+/// Compress `input` into one LZ4 block
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() / 2 + 16);
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut position = 0;
+
+    while position + MATCH_START_LIMIT <= input.len() {
+        let sequence = read_u32(input, position);
+        let slot = hash(sequence);
+        let candidate = table[slot];
+        table[slot] = position;
+
+        if candidate == usize::MAX || position - candidate > MAX_OFFSET || read_u32(input, candidate) != sequence {
+            position += 1;
+            continue;
+        }
+
+        let limit = input.len() - LAST_LITERALS;
+        let mut length = MIN_MATCH;
+        while position + length < limit && input[candidate + length] == input[position + length] {
+            length += 1;
+        }
+        emit_sequence(&mut output, &input[anchor..position], Some((position - candidate, length)));
+        position += length;
+        anchor = position;
+    }
+
+    emit_sequence(&mut output, &input[anchor..], None);
+    output
+}
+
+/// Decompress one LZ4 block, producing at most `max_output` bytes
+///
+/// Corrupt input, including input that would decompress to more than
+/// `max_output` bytes, is an I/O error.
+pub fn decompress(input: &[u8], max_output: usize) -> Result<Vec<u8>, VfsError> {
+    let mut output = Vec::with_capacity(max_output);
+    let mut position = 0;
+
+    loop {
+        let token = *input.get(position).ok_or(VfsError::IoError)?;
+        position += 1;
+
+        let literals = read_length(input, &mut position, (token >> 4) as usize)?;
+        let end = position.checked_add(literals).filter(|&end| end <= input.len()).ok_or(VfsError::IoError)?;
+        if output.len() + literals > max_output {
+            return Err(VfsError::IoError);
+        }
+        output.extend_from_slice(&input[position..end]);
+        position = end;
+
+        // The last sequence has no match
+        if position == input.len() {
+            return Ok(output);
+        }
+
+        let offset = input.get(position..position + 2).ok_or(VfsError::IoError)?;
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+        position += 2;
+        if offset == 0 || offset > output.len() {
+            return Err(VfsError::IoError);
+        }
+
+        let length = read_length(input, &mut position, (token & 0x0F) as usize)? + MIN_MATCH;
+        if output.len() + length > max_output {
+            return Err(VfsError::IoError);
+        }
+        // Matches may overlap their own output, so copy byte by byte
+        let start = output.len() - offset;
+        for index in 0..length {
+            let byte = output[start + index];
+            output.push(byte);
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8], position: usize) -> u32 {
+    u32::from_le_bytes([bytes[position], bytes[position + 1], bytes[position + 2], bytes[position + 3]])
+}
+
+fn hash(sequence: u32) -> usize {
+    (sequence.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Append a sequence of `literals` followed by an optional `(offset, length)`
+/// match
+fn emit_sequence(output: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_code = matched.map_or(0, |(_, length)| length - MIN_MATCH);
+    output.push(((literals.len().min(15) as u8) << 4) | match_code.min(15) as u8);
+    write_length(output, literals.len());
+    output.extend_from_slice(literals);
+
+    if let Some((offset, _)) = matched {
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+        write_length(output, match_code);
+    }
+}
+
+/// Lengths of 15 and more continue in extra bytes after the token
+fn write_length(output: &mut Vec<u8>, length: usize) {
+    if length < 15 {
+        return;
+    }
+    let mut rest = length - 15;
+    while rest >= 255 {
+        output.push(255);
+        rest -= 255;
+    }
+    output.push(rest as u8);
+}
+
+fn read_length(input: &[u8], position: &mut usize, nibble: usize) -> Result<usize, VfsError> {
+    let mut length = nibble;
+    if nibble == 15 {
+        loop {
+            let byte = *input.get(*position).ok_or(VfsError::IoError)?;
+            *position += 1;
+            length += byte as usize;
+            if byte != 255 {
+                break;
+            }
+        }
+    }
+    Ok(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) {
+        let compressed = compress(data);
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip(b"");
+        round_trip(b"short");
+        round_trip(&[0x42; 10000]);
+
+        let text: Vec<u8> = b"kosh system image ".iter().cycle().take(5000).copied().collect();
+        let compressed = compress(&text);
+        assert!(compressed.len() < text.len() / 10);
+        round_trip(&text);
+
+        // Poorly compressible data survives as literals
+        let mut state = 0x1234_5678u32;
+        let noise: Vec<u8> = (0..3000).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }).collect();
+        round_trip(&noise);
+    }
+
+    #[test]
+    fn test_corrupt_input_rejected() {
+        let data = [7u8; 1000];
+        let compressed = compress(&data);
+
+        // More output than the caller allows
+        assert_eq!(decompress(&compressed, 999), Err(VfsError::IoError));
+        // Truncated block
+        assert_eq!(decompress(&compressed[..compressed.len() - 1], 1000), Err(VfsError::IoError));
+        // Back-reference before the start of the output
+        assert_eq!(decompress(&[0x10, b'a', 0x05, 0x00], 100), Err(VfsError::IoError));
+    }
+}
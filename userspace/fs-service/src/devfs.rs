@@ -0,0 +1,327 @@
+//! Device files
+//!
+//! Mounted at /dev. The files are fixed and backed by nothing stored:
+//!
+//! - `null`: reads end at once and writes are discarded
+//! - `zero`: reads give zero bytes and writes are discarded
+//! - `urandom`: reads give bytes from the kernel's random generator and
+//!   never block, even before the generator is seeded; writes are mixed
+//!   into its pool without counting as entropy
+
+use alloc::{vec, vec::Vec, boxed::Box};
+use kosh_posix::random::GRND_INSECURE;
+use kosh_types::{
+    InodeNumber, FileOffset, FileType, FilePermissions, OpenFlags, FileMetadata, VfsError, DirectoryEntry
+};
+use crate::sysimage::directory_entry;
+use crate::vfs::FileSystem;
+
+const ROOT_INODE: InodeNumber = 1;
+const NULL_INODE: InodeNumber = 2;
+const ZERO_INODE: InodeNumber = 3;
+const URANDOM_INODE: InodeNumber = 4;
+
+/// Most bytes the kernel takes per `add_entropy` call
+const MIX_CHUNK: usize = 4096;
+
+/// Where random bytes come from
+pub trait RandomSource {
+    /// Fill the start of `buffer`, returning how many bytes were filled
+    fn fill(&mut self, buffer: &mut [u8]) -> Result<usize, VfsError>;
+
+    /// Mix `data` into the pool, without counting it as entropy
+    fn mix(&mut self, data: &[u8]) -> Result<(), VfsError>;
+}
+
+/// The kernel's generator
+pub struct KernelRandom;
+
+impl RandomSource for KernelRandom {
+    fn fill(&mut self, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        kosh_posix::random::getrandom(buffer, GRND_INSECURE).map_err(|_| VfsError::IoError)
+    }
+
+    fn mix(&mut self, data: &[u8]) -> Result<(), VfsError> {
+        for chunk in data.chunks(MIX_CHUNK) {
+            kosh_posix::random::add_entropy(chunk, 0).map_err(|_| VfsError::IoError)?;
+        }
+        Ok(())
+    }
+}
+
+/// A file or directory under /dev
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Node {
+    Root,
+    Null,
+    Zero,
+    URandom,
+}
+
+impl Node {
+    const DEVICES: [(&'static str, Node); 3] = [("null", Node::Null), ("zero", Node::Zero), ("urandom", Node::URandom)];
+
+    fn inode(self) -> InodeNumber {
+        match self {
+            Node::Root => ROOT_INODE,
+            Node::Null => NULL_INODE,
+            Node::Zero => ZERO_INODE,
+            Node::URandom => URANDOM_INODE,
+        }
+    }
+
+    fn from_inode(inode: InodeNumber) -> Option<Self> {
+        match inode {
+            ROOT_INODE => Some(Node::Root),
+            NULL_INODE => Some(Node::Null),
+            ZERO_INODE => Some(Node::Zero),
+            URANDOM_INODE => Some(Node::URandom),
+            _ => None,
+        }
+    }
+
+    fn file_type(self) -> FileType {
+        match self {
+            Node::Root => FileType::Directory,
+            _ => FileType::CharacterDevice,
+        }
+    }
+}
+
+/// File system of device files
+pub struct DevFs {
+    random: Box<dyn RandomSource>,
+    mounted: bool,
+}
+
+impl DevFs {
+    /// Device files backed by the running kernel
+    pub fn new() -> Self {
+        Self::with_random(Box::new(KernelRandom))
+    }
+
+    pub fn with_random(random: Box<dyn RandomSource>) -> Self {
+        Self { random, mounted: false }
+    }
+
+    fn check_mounted(&self) -> Result<(), VfsError> {
+        if self.mounted { Ok(()) } else { Err(VfsError::NotMounted) }
+    }
+
+    fn lookup(&self, path: &str) -> Result<Node, VfsError> {
+        self.check_mounted()?;
+        let components: Vec<&str> = path.split('/').filter(|component| !component.is_empty()).collect();
+        match components[..] {
+            [] => Ok(Node::Root),
+            [name] => Node::DEVICES.iter()
+                .find(|(device, _)| *device == name)
+                .map(|(_, node)| *node)
+                .ok_or(VfsError::NotFound),
+            _ => Err(VfsError::NotFound),
+        }
+    }
+
+    fn node(&self, inode: InodeNumber) -> Result<Node, VfsError> {
+        self.check_mounted()?;
+        Node::from_inode(inode).ok_or(VfsError::InvalidFileDescriptor)
+    }
+
+    fn metadata(&self, node: Node) -> FileMetadata {
+        let read_write = FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE
+            | FilePermissions::GROUP_READ | FilePermissions::GROUP_WRITE
+            | FilePermissions::OTHER_READ | FilePermissions::OTHER_WRITE;
+        let permissions = match node {
+            Node::Root => FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE | FilePermissions::OWNER_EXECUTE
+                | FilePermissions::GROUP_READ | FilePermissions::GROUP_EXECUTE
+                | FilePermissions::OTHER_READ | FilePermissions::OTHER_EXECUTE,
+            _ => read_write,
+        };
+        FileMetadata {
+            inode: node.inode(),
+            file_type: node.file_type(),
+            permissions,
+            size: 0,
+            uid: 0,
+            gid: 0,
+            created_time: 0,
+            modified_time: 0,
+            accessed_time: 0,
+        }
+    }
+}
+
+impl Default for DevFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for DevFs {
+    fn init(&mut self) -> Result<(), VfsError> {
+        Ok(())
+    }
+
+    fn mount(&mut self, _device_id: Option<u32>) -> Result<(), VfsError> {
+        if self.mounted {
+            return Err(VfsError::MountPointBusy);
+        }
+        self.mounted = true;
+        Ok(())
+    }
+
+    fn unmount(&mut self) -> Result<(), VfsError> {
+        self.check_mounted()?;
+        self.mounted = false;
+        Ok(())
+    }
+
+    fn open(&mut self, path: &str, _flags: OpenFlags) -> Result<(InodeNumber, FileMetadata), VfsError> {
+        let node = self.lookup(path)?;
+        Ok((node.inode(), self.metadata(node)))
+    }
+
+    fn close(&mut self, _inode: InodeNumber) -> Result<(), VfsError> {
+        Ok(())
+    }
+
+    /// Devices have no length: the offset is ignored
+    fn read(&mut self, inode: InodeNumber, _offset: FileOffset, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        match self.node(inode)? {
+            Node::Root => Err(VfsError::IsDirectory),
+            Node::Null => Ok(0),
+            Node::Zero => {
+                buffer.fill(0);
+                Ok(buffer.len())
+            }
+            Node::URandom => self.random.fill(buffer),
+        }
+    }
+
+    fn write(&mut self, inode: InodeNumber, _offset: FileOffset, buffer: &[u8]) -> Result<usize, VfsError> {
+        match self.node(inode)? {
+            Node::Root => Err(VfsError::IsDirectory),
+            Node::Null | Node::Zero => Ok(buffer.len()),
+            Node::URandom => {
+                self.random.mix(buffer)?;
+                Ok(buffer.len())
+            }
+        }
+    }
+
+    fn create(&mut self, _path: &str, _file_type: FileType, _permissions: FilePermissions) -> Result<InodeNumber, VfsError> {
+        Err(VfsError::NotSupported)
+    }
+
+    fn unlink(&mut self, _path: &str) -> Result<(), VfsError> {
+        Err(VfsError::NotSupported)
+    }
+
+    fn stat(&mut self, path: &str) -> Result<FileMetadata, VfsError> {
+        let node = self.lookup(path)?;
+        Ok(self.metadata(node))
+    }
+
+    fn readdir(&mut self, path: &str) -> Result<Vec<DirectoryEntry>, VfsError> {
+        if self.lookup(path)? != Node::Root {
+            return Err(VfsError::NotDirectory);
+        }
+        let mut entries = vec![
+            directory_entry(".", ROOT_INODE, FileType::Directory),
+            directory_entry("..", ROOT_INODE, FileType::Directory),
+        ];
+        for (name, node) in Node::DEVICES {
+            entries.push(directory_entry(name, node.inode(), node.file_type()));
+        }
+        Ok(entries)
+    }
+
+    fn mkdir(&mut self, _path: &str, _permissions: FilePermissions) -> Result<(), VfsError> {
+        Err(VfsError::NotSupported)
+    }
+
+    fn rmdir(&mut self, _path: &str) -> Result<(), VfsError> {
+        Err(VfsError::NotSupported)
+    }
+
+    fn sync(&mut self) -> Result<(), VfsError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{Vfs, FileSystemType};
+    use alloc::{rc::Rc, string::String};
+    use core::cell::RefCell;
+
+    /// Counts up from 1, and keeps what is mixed in
+    #[derive(Default)]
+    struct FakeRandom {
+        next: u8,
+        mixed: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl RandomSource for FakeRandom {
+        fn fill(&mut self, buffer: &mut [u8]) -> Result<usize, VfsError> {
+            for byte in buffer.iter_mut() {
+                self.next = self.next.wrapping_add(1);
+                *byte = self.next;
+            }
+            Ok(buffer.len())
+        }
+
+        fn mix(&mut self, data: &[u8]) -> Result<(), VfsError> {
+            self.mixed.borrow_mut().extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    fn mounted_vfs(random: FakeRandom) -> Vfs {
+        let mut vfs = Vfs::new();
+        let devfs = DevFs::with_random(Box::new(random));
+        assert!(vfs.mount_filesystem("/dev", FileSystemType::DevFs, Box::new(devfs), None, false).is_ok());
+        vfs
+    }
+
+    #[test]
+    fn test_dev_files() {
+        let random = FakeRandom::default();
+        let mixed = random.mixed.clone();
+        let mut vfs = mounted_vfs(random);
+        let mut buffer = [0xAAu8; 8];
+
+        let fd = vfs.open("/dev/urandom", OpenFlags::READ_WRITE).unwrap();
+        assert_eq!(vfs.read(fd, &mut buffer), Ok(8));
+        assert_eq!(buffer, [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(vfs.read(fd, &mut buffer[..3]), Ok(3));
+        assert_eq!(buffer[..3], [9, 10, 11]);
+        assert_eq!(vfs.write(fd, b"seed"), Ok(4));
+        assert_eq!(*mixed.borrow(), b"seed");
+        assert!(vfs.close(fd).is_ok());
+
+        let fd = vfs.open("/dev/zero", OpenFlags::READ_ONLY).unwrap();
+        assert_eq!(vfs.read(fd, &mut buffer), Ok(8));
+        assert_eq!(buffer, [0; 8]);
+        assert!(vfs.close(fd).is_ok());
+
+        let fd = vfs.open("/dev/null", OpenFlags::READ_WRITE).unwrap();
+        assert_eq!(vfs.read(fd, &mut buffer), Ok(0));
+        assert_eq!(vfs.write(fd, b"gone"), Ok(4));
+        assert!(vfs.close(fd).is_ok());
+    }
+
+    #[test]
+    fn test_dev_directory() {
+        let mut vfs = mounted_vfs(FakeRandom::default());
+
+        let names: Vec<String> = vfs.readdir("/dev").unwrap().iter()
+            .map(|entry| String::from_utf8_lossy(&entry.name[..entry.name_len as usize]).into_owned())
+            .collect();
+        assert_eq!(names, [".", "..", "null", "zero", "urandom"]);
+        assert_eq!(vfs.stat("/dev/urandom").unwrap().file_type, FileType::CharacterDevice);
+        assert_eq!(vfs.stat("/dev/random").err(), Some(VfsError::NotFound));
+        assert_eq!(vfs.stat("/dev/null/x").err(), Some(VfsError::NotFound));
+        assert_eq!(vfs.create("/dev/new", FileType::Regular, FilePermissions::OWNER_READ), Err(VfsError::NotSupported));
+    }
+}
@@ -1,12 +1,20 @@
 use kosh_types::{
-    FileDescriptor, InodeNumber, FileOffset, FileType, FilePermissions,
-    OpenFlags, FileMetadata, VfsError, DirectoryEntry, FileSize
+    InodeNumber, FileOffset, FileType, FilePermissions,
+    OpenFlags, FileMetadata, VfsError, DirectoryEntry
 };
-use crate::vfs::FileSystem;
-use alloc::{vec, vec::Vec, string::{String, ToString}, collections::BTreeMap};
+use crate::block::{self, BlockDevice, RamDisk, SECTOR_SIZE};
+use crate::vfs::{now, FileSystem};
+use alloc::{vec, vec::Vec, boxed::Box, string::String, collections::BTreeMap};
 use core::{result::Result, mem};
 
 /// ext4 file system implementation
+///
+/// Blocks and inodes are allocated from the on-disk bitmaps, and every
+/// change to them, the inodes and the directories is written through to
+/// the block device as it is made. Files this driver creates map their
+/// data through direct and indirect blocks; extent-mapped files written by
+/// other implementations are read in full and can grow while their extent
+/// tree fits in the inode.
 pub struct Ext4FileSystem {
     superblock: Option<Ext4Superblock>,
     block_size: u32,
@@ -14,8 +22,11 @@ pub struct Ext4FileSystem {
     device_id: Option<u32>,
     mounted: bool,
     inode_cache: BTreeMap<InodeNumber, Ext4Inode>,
-    path_to_inode: BTreeMap<String, InodeNumber>,
-    next_inode: InodeNumber,
+    device: Option<Box<dyn BlockDevice>>,
+    groups: Vec<GroupDescriptor>,
+    descriptor_size: u32,
+    /// Set for images using features writes would leave inconsistent
+    read_only: bool,
 }
 
 /// ext4 superblock structure (simplified)
@@ -104,11 +115,97 @@ pub struct Ext4DirEntry {
     // name follows this structure
 }
 
+/// The parts of a block group descriptor this driver uses
+#[derive(Debug, Clone, Copy)]
+struct GroupDescriptor {
+    block_bitmap: u64,
+    inode_bitmap: u64,
+    inode_table: u64,
+    free_blocks: u32,
+    free_inodes: u32,
+    used_dirs: u32,
+}
+
+/// A directory entry as found in a directory block
+struct RawDirEntry {
+    /// Byte offset within the block
+    offset: usize,
+    inode: u32,
+    rec_len: usize,
+    name_len: usize,
+    file_type: u8,
+}
+
+/// Where a logical block of an extent-mapped file lives
+enum ExtentLookup {
+    Mapped(u64),
+    /// Allocated but never written; reads as zeros
+    Unwritten,
+    Hole,
+}
+
 /// ext4 constants
 const EXT4_SUPER_MAGIC: u16 = 0xEF53;
 const EXT4_SUPERBLOCK_OFFSET: u64 = 1024;
+const EXT4_SUPERBLOCK_SIZE: usize = 1024;
 const EXT4_MIN_BLOCK_SIZE: u32 = 1024;
 const EXT4_MAX_BLOCK_SIZE: u32 = 65536;
+const EXT4_ROOT_INODE: InodeNumber = 2;
+const EXT4_GOOD_OLD_FIRST_INO: u32 = 11;
+const EXT4_MIN_DESC_SIZE: u32 = 32;
+const EXT4_MIN_DESC_SIZE_64BIT: u32 = 64;
+const EXT4_NAME_LEN: usize = 255;
+const EXT4_DIR_ENTRY_HEADER: usize = mem::size_of::<Ext4DirEntry>();
+const EXT4_LINK_MAX: u16 = 65000;
+/// Set in `Ext4Superblock::state` while the file system is cleanly unmounted
+const EXT4_VALID_FS: u16 = 0x0001;
+
+// Superblock fields past the simplified structure
+const EXT4_DESC_SIZE_OFFSET: usize = 0xFE;
+const EXT4_BLOCKS_COUNT_HI_OFFSET: usize = 0x150;
+
+// Features the image may use. Unknown incompatible features refuse the
+// mount; unknown read-only compatible ones (checksums among them, which
+// this driver does not maintain) make it read-only.
+const EXT4_FEATURE_INCOMPAT_FILETYPE: u32 = 0x0002;
+const EXT4_FEATURE_INCOMPAT_EXTENTS: u32 = 0x0040;
+const EXT4_FEATURE_INCOMPAT_64BIT: u32 = 0x0080;
+const EXT4_FEATURE_INCOMPAT_FLEX_BG: u32 = 0x0200;
+const EXT4_FEATURE_INCOMPAT_SUPPORTED: u32 = EXT4_FEATURE_INCOMPAT_FILETYPE
+    | EXT4_FEATURE_INCOMPAT_EXTENTS
+    | EXT4_FEATURE_INCOMPAT_64BIT
+    | EXT4_FEATURE_INCOMPAT_FLEX_BG;
+const EXT4_FEATURE_RO_COMPAT_WRITABLE: u32 = 0x0001 // sparse_super
+    | 0x0002 // large_file
+    | 0x0008 // huge_file
+    | 0x0020 // dir_nlink
+    | 0x0040; // extra_isize
+
+// Inode flags
+const EXT4_INDEX_FL: u32 = 0x1000;
+const EXT4_EXTENTS_FL: u32 = 0x80000;
+
+// Block map layout of inodes without extents
+const EXT4_NDIR_BLOCKS: usize = 12;
+const EXT4_IND_BLOCK: usize = 12;
+const EXT4_DIND_BLOCK: usize = 13;
+const EXT4_TIND_BLOCK: usize = 14;
+
+// Extent tree layout
+const EXT4_EXT_MAGIC: u16 = 0xF30A;
+const EXT4_EXT_ENTRY_SIZE: usize = 12;
+pub(crate) const EXT4_EXT_INIT_MAX_LEN: u32 = 32768;
+
+// Extended attribute blocks
+const EXT4_XATTR_MAGIC: u32 = 0xEA02_0000;
+
+/// Size of the RAM disk an instance without a device formats at mount
+const RAM_DISK_SIZE: u64 = 4 * 1024 * 1024;
+const RAM_DISK_BLOCK_SIZE: u32 = 1024;
+/// Data bytes per inode `format` provides for
+const FORMAT_BYTES_PER_INODE: u64 = 4096;
+const FORMAT_INODE_SIZE: u16 = 256;
+const FORMAT_MIN_BLOCKS: u64 = 64;
 
 // File type constants for ext4 directory entries
 const EXT4_FT_UNKNOWN: u8 = 0;
@@ -129,8 +226,278 @@ const EXT4_S_IFCHR: u16 = 0x2000;  // Character device
 const EXT4_S_IFIFO: u16 = 0x1000;  // FIFO
 const EXT4_S_IFSOCK: u16 = 0xC000; // Socket
 
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn write_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Raw bytes of a plain on-disk structure
+fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    // Safety: only used with the packed on-disk structures, which have no
+    // padding and no invalid bit patterns
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) }
+}
+
+/// Length of a directory entry holding a `name_len` byte name
+fn dir_entry_len(name_len: usize) -> usize {
+    (EXT4_DIR_ENTRY_HEADER + name_len + 3) & !3
+}
+
+/// Index of the first clear bit in `from..to`, set on return
+fn allocate_bit(bitmap: &mut [u8], from: usize, to: usize) -> Option<usize> {
+    let bit = (from..to).find(|&bit| bitmap[bit / 8] & (1 << (bit % 8)) == 0)?;
+    bitmap[bit / 8] |= 1 << (bit % 8);
+    Some(bit)
+}
+
+/// Set bits `from..to`
+fn set_bits(bitmap: &mut [u8], from: usize, to: usize) {
+    for bit in from..to {
+        bitmap[bit / 8] |= 1 << (bit % 8);
+    }
+}
+
+/// Parent directory and final component of `path`
+fn split_path(path: &str) -> Result<(&str, &str), VfsError> {
+    let path = path.trim_end_matches('/');
+    let (parent, name) = path.rsplit_once('/').ok_or(VfsError::InvalidPath)?;
+    if name.is_empty() || name.len() > EXT4_NAME_LEN {
+        return Err(VfsError::InvalidPath);
+    }
+    Ok((if parent.is_empty() { "/" } else { parent }, name))
+}
+
+/// Write an empty ext4 file system to `device`
+///
+/// The layout is a single block group, so at most `8 * block_size` blocks
+/// of the device are used. Only the features every ext4 implementation
+/// can write are enabled.
+pub fn format(device: &mut dyn BlockDevice, block_size: u32) -> Result<(), VfsError> {
+    if !block_size.is_power_of_two()
+        || !(EXT4_MIN_BLOCK_SIZE..=EXT4_MAX_BLOCK_SIZE).contains(&block_size)
+        || !(block_size as usize).is_multiple_of(device.block_size())
+    {
+        return Err(VfsError::InvalidPath);
+    }
+
+    let bs = block_size as u64;
+    let first_data_block: u64 = if block_size == EXT4_MIN_BLOCK_SIZE { 1 } else { 0 };
+    let blocks_per_group = 8 * bs;
+    let device_blocks = device.block_count() * device.block_size() as u64 / bs;
+    let blocks_count = device_blocks.min(first_data_block + blocks_per_group);
+    if blocks_count < FORMAT_MIN_BLOCKS {
+        return Err(VfsError::NoSpace);
+    }
+
+    let inodes_per_block = bs / FORMAT_INODE_SIZE as u64;
+    let inodes_count = (blocks_count * bs / FORMAT_BYTES_PER_INODE)
+        .max(inodes_per_block)
+        .min(8 * bs)
+        .next_multiple_of(inodes_per_block);
+    let inode_table_blocks = inodes_count / inodes_per_block;
+
+    // Superblock, descriptors, bitmaps, inode table and the root directory
+    let group_descriptors = first_data_block + 1;
+    let block_bitmap = group_descriptors + 1;
+    let inode_bitmap = block_bitmap + 1;
+    let inode_table = inode_bitmap + 1;
+    let root_directory = inode_table + inode_table_blocks;
+    let blocks_in_group = blocks_count - first_data_block;
+    let used_blocks = root_directory + 1 - first_data_block;
+    if used_blocks >= blocks_in_group {
+        return Err(VfsError::NoSpace);
+    }
+    let reserved_inodes = EXT4_GOOD_OLD_FIRST_INO as u64 - 1;
+
+    let superblock = Ext4Superblock {
+        inodes_count: inodes_count as u32,
+        blocks_count: blocks_count as u32,
+        r_blocks_count: 0,
+        free_blocks_count: (blocks_in_group - used_blocks) as u32,
+        free_inodes_count: (inodes_count - reserved_inodes) as u32,
+        first_data_block: first_data_block as u32,
+        log_block_size: block_size.trailing_zeros() - EXT4_MIN_BLOCK_SIZE.trailing_zeros(),
+        log_cluster_size: block_size.trailing_zeros() - EXT4_MIN_BLOCK_SIZE.trailing_zeros(),
+        blocks_per_group: blocks_per_group as u32,
+        clusters_per_group: blocks_per_group as u32,
+        inodes_per_group: inodes_count as u32,
+        mtime: 0,
+        wtime: now(),
+        mnt_count: 0,
+        max_mnt_count: u16::MAX,
+        magic: EXT4_SUPER_MAGIC,
+        state: EXT4_VALID_FS,
+        errors: 1, // Continue on errors
+        minor_rev_level: 0,
+        lastcheck: now(),
+        checkinterval: 0,
+        creator_os: 0,
+        rev_level: 1,
+        def_resuid: 0,
+        def_resgid: 0,
+        first_ino: EXT4_GOOD_OLD_FIRST_INO,
+        inode_size: FORMAT_INODE_SIZE,
+        block_group_nr: 0,
+        feature_compat: 0,
+        feature_incompat: EXT4_FEATURE_INCOMPAT_FILETYPE,
+        feature_ro_compat: 0,
+        uuid: [0; 16],
+        volume_name: [0; 16],
+        last_mounted: [0; 64],
+        algorithm_usage_bitmap: 0,
+    };
+    let mut raw = vec![0u8; EXT4_SUPERBLOCK_SIZE];
+    raw[..mem::size_of::<Ext4Superblock>()].copy_from_slice(as_bytes(&superblock));
+    block::write_at(device, EXT4_SUPERBLOCK_OFFSET, &raw)?;
+
+    let mut block = vec![0u8; bs as usize];
+    let write_block = |device: &mut dyn BlockDevice, index: u64, data: &[u8]| block::write_at(device, index * bs, data);
+
+    write_u32(&mut block, 0x00, block_bitmap as u32);
+    write_u32(&mut block, 0x04, inode_bitmap as u32);
+    write_u32(&mut block, 0x08, inode_table as u32);
+    write_u16(&mut block, 0x0C, superblock.free_blocks_count as u16);
+    write_u16(&mut block, 0x0E, superblock.free_inodes_count as u16);
+    write_u16(&mut block, 0x10, 1);
+    write_block(device, group_descriptors, &block)?;
+
+    // Bits past the end of the group stay set so they are never allocated
+    block.fill(0);
+    set_bits(&mut block, 0, used_blocks as usize);
+    set_bits(&mut block, blocks_in_group as usize, 8 * bs as usize);
+    write_block(device, block_bitmap, &block)?;
+
+    block.fill(0);
+    set_bits(&mut block, 0, reserved_inodes as usize);
+    set_bits(&mut block, inodes_count as usize, 8 * bs as usize);
+    write_block(device, inode_bitmap, &block)?;
+
+    block.fill(0);
+    for index in inode_table..root_directory {
+        write_block(device, index, &block)?;
+    }
+
+    let mut root = Ext4FileSystem::new_inode(EXT4_S_IFDIR | 0o755);
+    root.links_count = 2;
+    root.size_lo = block_size;
+    root.blocks_lo = block_size / SECTOR_SIZE as u32;
+    root.block[0] = root_directory as u32;
+    let root_offset = inode_table * bs + (EXT4_ROOT_INODE - 1) * FORMAT_INODE_SIZE as u64;
+    block::write_at(device, root_offset, as_bytes(&root))?;
+
+    Ext4FileSystem::init_directory_block(&mut block, EXT4_ROOT_INODE as u32, EXT4_ROOT_INODE as u32);
+    write_block(device, root_directory, &block)?;
+
+    device.flush()
+}
+
+/// Freshly formatted RAM disk for instances without a device
+pub fn ram_disk() -> Result<RamDisk, VfsError> {
+    let mut disk = RamDisk::new(SECTOR_SIZE, RAM_DISK_SIZE / SECTOR_SIZE as u64);
+    format(&mut disk, RAM_DISK_BLOCK_SIZE)?;
+    Ok(disk)
+}
+
+/// Superblock fields checked, with the geometry and features they imply
+#[derive(Debug, Clone, Copy)]
+pub struct ParsedSuperblock {
+    pub superblock: Ext4Superblock,
+    pub block_size: u32,
+    pub inode_size: u16,
+    pub descriptor_size: u32,
+    /// The image uses features writes would leave inconsistent
+    pub read_only: bool,
+}
+
+/// Parse and check the superblock at the start of `data`
+pub fn parse_superblock(data: &[u8]) -> Result<ParsedSuperblock, VfsError> {
+    if data.len() < EXT4_SUPERBLOCK_SIZE {
+        return Err(VfsError::IoError);
+    }
+
+    // Safety: We've verified the buffer is large enough
+    let superblock = unsafe {
+        core::ptr::read_unaligned(data.as_ptr() as *const Ext4Superblock)
+    };
+
+    // Verify ext4 magic number
+    if superblock.magic != EXT4_SUPER_MAGIC {
+        return Err(VfsError::IoError);
+    }
+
+    // Calculate block size
+    if superblock.log_block_size > EXT4_MAX_BLOCK_SIZE.trailing_zeros() {
+        return Err(VfsError::IoError);
+    }
+    let block_size = EXT4_MIN_BLOCK_SIZE << superblock.log_block_size;
+    if !(EXT4_MIN_BLOCK_SIZE..=EXT4_MAX_BLOCK_SIZE).contains(&block_size) {
+        return Err(VfsError::IoError);
+    }
+
+    // Validate inode size
+    let inode_size = if superblock.rev_level >= 1 {
+        superblock.inode_size
+    } else {
+        128 // Default inode size for revision 0
+    };
+
+    if inode_size < 128 || inode_size as u32 > block_size {
+        return Err(VfsError::IoError);
+    }
+
+    if superblock.blocks_per_group == 0 || superblock.inodes_per_group == 0 {
+        return Err(VfsError::IoError);
+    }
+    // The group count is worked out from the blocks past the first
+    if superblock.first_data_block >= superblock.blocks_count {
+        return Err(VfsError::IoError);
+    }
+
+    let incompat = if superblock.rev_level >= 1 { superblock.feature_incompat } else { 0 };
+    if incompat & !EXT4_FEATURE_INCOMPAT_SUPPORTED != 0 {
+        return Err(VfsError::NotSupported);
+    }
+    let mut descriptor_size = EXT4_MIN_DESC_SIZE;
+    if incompat & EXT4_FEATURE_INCOMPAT_64BIT != 0 {
+        // Block pointers are 32 bits wide here
+        if read_u32(data, EXT4_BLOCKS_COUNT_HI_OFFSET) != 0 {
+            return Err(VfsError::NotSupported);
+        }
+        descriptor_size = (read_u16(data, EXT4_DESC_SIZE_OFFSET) as u32).max(EXT4_MIN_DESC_SIZE_64BIT);
+    }
+    let ro_compat = if superblock.rev_level >= 1 { superblock.feature_ro_compat } else { 0 };
+    let read_only = ro_compat & !EXT4_FEATURE_RO_COMPAT_WRITABLE != 0;
+
+    Ok(ParsedSuperblock { superblock, block_size, inode_size, descriptor_size, read_only })
+}
+
+/// An inode from its on-disk bytes, with the fields past the end of a
+/// shorter one read as zero
+pub fn parse_inode(raw: &[u8]) -> Ext4Inode {
+    let mut bytes = [0u8; mem::size_of::<Ext4Inode>()];
+    let length = bytes.len().min(raw.len());
+    bytes[..length].copy_from_slice(&raw[..length]);
+    // Safety: the buffer is exactly one inode long
+    unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const Ext4Inode) }
+}
+
 impl Ext4FileSystem {
     /// Create a new ext4 file system instance
+    ///
+    /// Until storage drivers hand their devices to fs-service, an instance
+    /// created this way keeps its data on a RAM disk formatted at its
+    /// first mount.
     pub fn new() -> Self {
         Self {
             superblock: None,
@@ -139,45 +506,79 @@ impl Ext4FileSystem {
             device_id: None,
             mounted: false,
             inode_cache: BTreeMap::new(),
-            path_to_inode: BTreeMap::new(),
-            next_inode: 100, // Start from inode 100 for user files
+            device: None,
+            groups: Vec::new(),
+            descriptor_size: EXT4_MIN_DESC_SIZE,
+            read_only: false,
         }
     }
 
-    /// Parse the ext4 superblock from raw bytes
-    fn parse_superblock(&mut self, data: &[u8]) -> Result<(), VfsError> {
-        if data.len() < mem::size_of::<Ext4Superblock>() {
-            return Err(VfsError::IoError);
+    /// Create an instance for the ext4 image on `device`
+    pub fn with_device(device: Box<dyn BlockDevice>) -> Self {
+        Self {
+            device: Some(device),
+            ..Self::new()
         }
+    }
 
-        // Safety: We've verified the buffer is large enough
-        let superblock = unsafe {
-            core::ptr::read_unaligned(data.as_ptr() as *const Ext4Superblock)
-        };
+    /// Parse the ext4 superblock from raw bytes
+    fn parse_superblock(&mut self, data: &[u8]) -> Result<(), VfsError> {
+        let parsed = parse_superblock(data)?;
+        self.block_size = parsed.block_size;
+        self.inode_size = parsed.inode_size;
+        self.descriptor_size = parsed.descriptor_size;
+        self.read_only = parsed.read_only;
+        self.superblock = Some(parsed.superblock);
+        Ok(())
+    }
 
-        // Verify ext4 magic number
-        if superblock.magic != EXT4_SUPER_MAGIC {
+    /// Read the block group descriptor table
+    fn load_group_descriptors(&mut self) -> Result<(), VfsError> {
+        let superblock = self.superblock.ok_or(VfsError::NotMounted)?;
+        let data_blocks = (superblock.blocks_count - superblock.first_data_block) as u64;
+        let count = data_blocks.div_ceil(superblock.blocks_per_group as u64) as usize;
+        if count as u64 * superblock.inodes_per_group as u64 != superblock.inodes_count as u64 {
             return Err(VfsError::IoError);
         }
 
-        // Calculate block size
-        self.block_size = EXT4_MIN_BLOCK_SIZE << superblock.log_block_size;
-        if self.block_size < EXT4_MIN_BLOCK_SIZE || self.block_size > EXT4_MAX_BLOCK_SIZE {
-            return Err(VfsError::IoError);
-        }
+        let size = self.descriptor_size as usize;
+        let mut table = vec![0u8; count * size];
+        let offset = self.descriptor_table_block() * self.block_size as u64;
+        block::read_at(self.device()?, offset, &mut table)?;
 
-        // Validate inode size
-        self.inode_size = if superblock.rev_level >= 1 {
-            superblock.inode_size
-        } else {
-            128 // Default inode size for revision 0
+        let wide = size >= EXT4_MIN_DESC_SIZE_64BIT as usize;
+        let field = |raw: &[u8], lo: usize, hi: usize| {
+            read_u32(raw, lo) as u64 | if wide { (read_u32(raw, hi) as u64) << 32 } else { 0 }
         };
+        let count16 = |raw: &[u8], lo: usize, hi: usize| {
+            read_u16(raw, lo) as u32 | if wide { (read_u16(raw, hi) as u32) << 16 } else { 0 }
+        };
+        self.groups = table.chunks_exact(size)
+            .map(|raw| GroupDescriptor {
+                block_bitmap: field(raw, 0x00, 0x20),
+                inode_bitmap: field(raw, 0x04, 0x24),
+                inode_table: field(raw, 0x08, 0x28),
+                free_blocks: count16(raw, 0x0C, 0x2C),
+                free_inodes: count16(raw, 0x0E, 0x2E),
+                used_dirs: count16(raw, 0x10, 0x30),
+            })
+            .collect();
+        Ok(())
+    }
 
-        if self.inode_size < 128 || self.inode_size > self.block_size as u16 {
-            return Err(VfsError::IoError);
-        }
+    /// Block holding the first group descriptor, right after the superblock
+    fn descriptor_table_block(&self) -> u64 {
+        EXT4_SUPERBLOCK_OFFSET / self.block_size as u64 + 1
+    }
+
+    fn device(&mut self) -> Result<&mut (dyn BlockDevice + 'static), VfsError> {
+        self.device.as_deref_mut().ok_or(VfsError::NotMounted)
+    }
 
-        self.superblock = Some(superblock);
+    fn check_writable(&self) -> Result<(), VfsError> {
+        if self.read_only {
+            return Err(VfsError::ReadOnlyFileSystem);
+        }
         Ok(())
     }
 
@@ -195,8 +596,21 @@ impl Ext4FileSystem {
         }
     }
 
+    /// Convert VFS file type to the ext4 directory entry type
+    fn vfs_to_ext4_file_type(file_type: FileType) -> u8 {
+        match file_type {
+            FileType::Regular => EXT4_FT_REG_FILE,
+            FileType::Directory => EXT4_FT_DIR,
+            FileType::SymbolicLink => EXT4_FT_SYMLINK,
+            FileType::BlockDevice => EXT4_FT_BLKDEV,
+            FileType::CharacterDevice => EXT4_FT_CHRDEV,
+            FileType::Fifo => EXT4_FT_FIFO,
+            FileType::Socket => EXT4_FT_SOCK,
+        }
+    }
+
     /// Convert ext4 inode mode to VFS file type
-    fn inode_mode_to_file_type(mode: u16) -> FileType {
+    pub(crate) fn inode_mode_to_file_type(mode: u16) -> FileType {
         match mode & 0xF000 {
             EXT4_S_IFREG => FileType::Regular,
             EXT4_S_IFDIR => FileType::Directory,
@@ -214,24 +628,178 @@ impl Ext4FileSystem {
         FilePermissions::from_bits_truncate(mode)
     }
 
-    /// Read a block from the device (placeholder implementation)
-    fn read_block(&self, block_num: u32, buffer: &mut [u8]) -> Result<(), VfsError> {
-        // In a real implementation, this would read from the actual storage device
-        // For now, we'll return an error to indicate this is not implemented
-        if buffer.len() < self.block_size as usize {
+    /// Read a file system block from the device
+    fn read_block(&mut self, block_num: u64, buffer: &mut [u8]) -> Result<(), VfsError> {
+        let offset = block_num * self.block_size as u64;
+        block::read_at(self.device()?, offset, buffer)
+    }
+
+    /// Write a file system block to the device
+    fn write_block(&mut self, block_num: u64, buffer: &[u8]) -> Result<(), VfsError> {
+        let offset = block_num * self.block_size as u64;
+        block::write_at(self.device()?, offset, buffer)
+    }
+
+    fn write_superblock(&mut self) -> Result<(), VfsError> {
+        let superblock = self.superblock.ok_or(VfsError::NotMounted)?;
+        block::write_at(self.device()?, EXT4_SUPERBLOCK_OFFSET, as_bytes(&superblock))
+    }
+
+    /// Write the free counts of `group` back to its descriptor
+    fn write_group_descriptor(&mut self, group: usize) -> Result<(), VfsError> {
+        let descriptor = self.groups[group];
+        let size = self.descriptor_size as usize;
+        let mut raw = vec![0u8; size];
+        let offset = self.descriptor_table_block() * self.block_size as u64 + (group * size) as u64;
+        block::read_at(self.device()?, offset, &mut raw)?;
+        write_u16(&mut raw, 0x0C, descriptor.free_blocks as u16);
+        write_u16(&mut raw, 0x0E, descriptor.free_inodes as u16);
+        write_u16(&mut raw, 0x10, descriptor.used_dirs as u16);
+        if size >= EXT4_MIN_DESC_SIZE_64BIT as usize {
+            write_u16(&mut raw, 0x2C, (descriptor.free_blocks >> 16) as u16);
+            write_u16(&mut raw, 0x2E, (descriptor.free_inodes >> 16) as u16);
+            write_u16(&mut raw, 0x30, (descriptor.used_dirs >> 16) as u16);
+        }
+        block::write_at(self.device()?, offset, &raw)
+    }
+
+    /// Apply a change in free blocks, free inodes and directories of `group`
+    fn adjust_counts(&mut self, group: usize, blocks: i32, inodes: i32, dirs: i32) -> Result<(), VfsError> {
+        let descriptor = &mut self.groups[group];
+        descriptor.free_blocks = descriptor.free_blocks.wrapping_add_signed(blocks);
+        descriptor.free_inodes = descriptor.free_inodes.wrapping_add_signed(inodes);
+        descriptor.used_dirs = descriptor.used_dirs.wrapping_add_signed(dirs);
+
+        let superblock = self.superblock.as_mut().ok_or(VfsError::NotMounted)?;
+        superblock.free_blocks_count = superblock.free_blocks_count.wrapping_add_signed(blocks);
+        superblock.free_inodes_count = superblock.free_inodes_count.wrapping_add_signed(inodes);
+
+        self.write_group_descriptor(group)?;
+        self.write_superblock()
+    }
+
+    /// Blocks belonging to `group`; the last group may be short
+    fn blocks_in_group(&self, group: usize) -> usize {
+        let Some(superblock) = self.superblock else { return 0 };
+        let start = superblock.first_data_block as u64 + group as u64 * superblock.blocks_per_group as u64;
+        (superblock.blocks_count as u64 - start).min(superblock.blocks_per_group as u64) as usize
+    }
+
+    /// First block of the group holding `inode_num`, where its data is best placed
+    fn inode_goal(&self, inode_num: InodeNumber) -> u64 {
+        let Some(superblock) = self.superblock else { return 0 };
+        let group = (inode_num - 1) / superblock.inodes_per_group as u64;
+        superblock.first_data_block as u64 + group * superblock.blocks_per_group as u64
+    }
+
+    /// Allocate a zeroed block, as close after `goal` as possible
+    fn allocate_block(&mut self, goal: u64) -> Result<u64, VfsError> {
+        let superblock = self.superblock.ok_or(VfsError::NotMounted)?;
+        let first = superblock.first_data_block as u64;
+        let per_group = superblock.blocks_per_group as u64;
+        let goal = goal.clamp(first, superblock.blocks_count as u64 - 1) - first;
+        let goal_group = (goal / per_group) as usize;
+
+        let mut bitmap = vec![0u8; self.block_size as usize];
+        for step in 0..self.groups.len() {
+            let group = (goal_group + step) % self.groups.len();
+            if self.groups[group].free_blocks == 0 {
+                continue;
+            }
+
+            let count = self.blocks_in_group(group);
+            let start = if step == 0 { (goal % per_group) as usize } else { 0 };
+            let bitmap_block = self.groups[group].block_bitmap;
+            self.read_block(bitmap_block, &mut bitmap)?;
+            let Some(bit) = allocate_bit(&mut bitmap, start, count).or_else(|| allocate_bit(&mut bitmap, 0, start)) else {
+                continue;
+            };
+            self.write_block(bitmap_block, &bitmap)?;
+            self.adjust_counts(group, -1, 0, 0)?;
+
+            let block_num = first + group as u64 * per_group + bit as u64;
+            bitmap.fill(0);
+            self.write_block(block_num, &bitmap)?;
+            return Ok(block_num);
+        }
+        Err(VfsError::NoSpace)
+    }
+
+    fn free_block(&mut self, block_num: u64) -> Result<(), VfsError> {
+        let superblock = self.superblock.ok_or(VfsError::NotMounted)?;
+        let first = superblock.first_data_block as u64;
+        if block_num < first || block_num >= superblock.blocks_count as u64 {
             return Err(VfsError::IoError);
         }
-        
-        // Placeholder: fill with zeros
-        buffer[..self.block_size as usize].fill(0);
-        Ok(())
+        let group = ((block_num - first) / superblock.blocks_per_group as u64) as usize;
+        let bit = ((block_num - first) % superblock.blocks_per_group as u64) as usize;
+
+        let mut bitmap = vec![0u8; self.block_size as usize];
+        let bitmap_block = self.groups[group].block_bitmap;
+        self.read_block(bitmap_block, &mut bitmap)?;
+        if bitmap[bit / 8] & (1 << (bit % 8)) == 0 {
+            // Already free; counting it again would corrupt the totals
+            return Ok(());
+        }
+        bitmap[bit / 8] &= !(1 << (bit % 8));
+        self.write_block(bitmap_block, &bitmap)?;
+        self.adjust_counts(group, 1, 0, 0)
     }
 
-    /// Write a block to the device (placeholder implementation)
-    fn write_block(&self, _block_num: u32, _buffer: &[u8]) -> Result<(), VfsError> {
-        // In a real implementation, this would write to the actual storage device
-        // For now, we'll return success as a placeholder
-        Ok(())
+    /// Allocate an inode, preferring the group holding `near`
+    fn allocate_inode(&mut self, near: InodeNumber, directory: bool) -> Result<InodeNumber, VfsError> {
+        let superblock = self.superblock.ok_or(VfsError::NotMounted)?;
+        let per_group = superblock.inodes_per_group as usize;
+        let near_group = ((near - 1) / per_group as u64) as usize;
+
+        let mut bitmap = vec![0u8; self.block_size as usize];
+        for step in 0..self.groups.len() {
+            let group = (near_group + step) % self.groups.len();
+            if self.groups[group].free_inodes == 0 {
+                continue;
+            }
+
+            // The reserved inodes all sit at the start of the first group
+            let start = if group == 0 { superblock.first_ino as usize - 1 } else { 0 };
+            let bitmap_block = self.groups[group].inode_bitmap;
+            self.read_block(bitmap_block, &mut bitmap)?;
+            let Some(bit) = allocate_bit(&mut bitmap, start, per_group) else {
+                continue;
+            };
+            self.write_block(bitmap_block, &bitmap)?;
+            self.adjust_counts(group, 0, -1, directory as i32)?;
+            return Ok((group * per_group + bit + 1) as InodeNumber);
+        }
+        Err(VfsError::NoSpace)
+    }
+
+    fn free_inode(&mut self, inode_num: InodeNumber, directory: bool) -> Result<(), VfsError> {
+        let superblock = self.superblock.ok_or(VfsError::NotMounted)?;
+        let group = ((inode_num - 1) / superblock.inodes_per_group as u64) as usize;
+        let bit = ((inode_num - 1) % superblock.inodes_per_group as u64) as usize;
+
+        let mut bitmap = vec![0u8; self.block_size as usize];
+        let bitmap_block = self.groups[group].inode_bitmap;
+        self.read_block(bitmap_block, &mut bitmap)?;
+        if bitmap[bit / 8] & (1 << (bit % 8)) == 0 {
+            return Ok(());
+        }
+        bitmap[bit / 8] &= !(1 << (bit % 8));
+        self.write_block(bitmap_block, &bitmap)?;
+        self.inode_cache.remove(&inode_num);
+        self.adjust_counts(group, 0, 1, -(directory as i32))
+    }
+
+    /// Byte offset of `inode_num` in the inode table
+    fn inode_offset(&self, inode_num: InodeNumber) -> Result<u64, VfsError> {
+        let superblock = self.superblock.ok_or(VfsError::NotMounted)?;
+        if inode_num == 0 || inode_num > superblock.inodes_count as u64 {
+            return Err(VfsError::NotFound);
+        }
+        let group = ((inode_num - 1) / superblock.inodes_per_group as u64) as usize;
+        let index = (inode_num - 1) % superblock.inodes_per_group as u64;
+        let table = self.groups.get(group).ok_or(VfsError::IoError)?.inode_table;
+        Ok(table * self.block_size as u64 + index * self.inode_size as u64)
     }
 
     /// Read an inode from disk
@@ -241,25 +809,38 @@ impl Ext4FileSystem {
             return Ok(*inode);
         }
 
-        let superblock = self.superblock.ok_or(VfsError::NotMounted)?;
-        
-        // Calculate inode location
-        let group = (inode_num - 1) / superblock.inodes_per_group as u64;
-        let index = (inode_num - 1) % superblock.inodes_per_group as u64;
-        
-        // In a real implementation, we would:
-        // 1. Read the block group descriptor to get the inode table location
-        // 2. Calculate the exact block and offset within the block
-        // 3. Read the block and extract the inode
-        
-        // For now, return a placeholder inode
-        let inode = Ext4Inode {
-            mode: EXT4_S_IFREG | 0o644,
+        // Inodes of the original 128 bytes read the newer fields as zero
+        let offset = self.inode_offset(inode_num)?;
+        let mut raw = [0u8; mem::size_of::<Ext4Inode>()];
+        let length = raw.len().min(self.inode_size as usize);
+        block::read_at(self.device()?, offset, &mut raw[..length])?;
+
+        let inode = parse_inode(&raw[..length]);
+
+        // Cache the inode
+        self.inode_cache.insert(inode_num, inode);
+        Ok(inode)
+    }
+
+    /// Write an inode to disk
+    fn write_inode(&mut self, inode_num: InodeNumber, inode: &Ext4Inode) -> Result<(), VfsError> {
+        let offset = self.inode_offset(inode_num)?;
+        let length = mem::size_of::<Ext4Inode>().min(self.inode_size as usize);
+        block::write_at(self.device()?, offset, &as_bytes(inode)[..length])?;
+        self.inode_cache.insert(inode_num, *inode);
+        Ok(())
+    }
+
+    /// A fresh in-use inode of `mode` with no data
+    fn new_inode(mode: u16) -> Ext4Inode {
+        let time = now();
+        Ext4Inode {
+            mode,
             uid: 0,
             size_lo: 0,
-            atime: 0,
-            ctime: 0,
-            mtime: 0,
+            atime: time,
+            ctime: time,
+            mtime: time,
             dtime: 0,
             gid: 0,
             links_count: 1,
@@ -272,31 +853,46 @@ impl Ext4FileSystem {
             size_high: 0,
             obso_faddr: 0,
             osd2: [0; 12],
-            extra_isize: 0,
+            // Fields past the original 128 bytes that this structure holds
+            extra_isize: (mem::size_of::<Ext4Inode>() - 128) as u16,
             checksum_hi: 0,
             ctime_extra: 0,
             mtime_extra: 0,
             atime_extra: 0,
-            crtime: 0,
+            crtime: time,
             crtime_extra: 0,
             version_hi: 0,
             projid: 0,
-        };
+        }
+    }
 
-        // Cache the inode
-        self.inode_cache.insert(inode_num, inode);
-        Ok(inode)
+    pub(crate) fn inode_size_bytes(inode: &Ext4Inode) -> u64 {
+        (inode.size_high as u64) << 32 | inode.size_lo as u64
+    }
+
+    fn set_inode_size(inode: &mut Ext4Inode, size: u64) {
+        inode.size_lo = size as u32;
+        inode.size_high = (size >> 32) as u32;
+    }
+
+    /// Sectors `blocks_lo` counts per file system block
+    fn sectors_per_block(&self) -> u32 {
+        self.block_size / SECTOR_SIZE as u32
+    }
+
+    /// Whether the inode's block array holds a symlink target rather than blocks
+    fn is_fast_symlink(inode: &Ext4Inode) -> bool {
+        Self::inode_mode_to_file_type(inode.mode) == FileType::SymbolicLink
+            && Self::inode_size_bytes(inode) < (mem::size_of::<[u32; 15]>() as u64)
     }
 
     /// Convert ext4 inode to VFS metadata
     fn inode_to_metadata(&self, inode_num: InodeNumber, inode: &Ext4Inode) -> FileMetadata {
-        let file_size = (inode.size_high as u64) << 32 | inode.size_lo as u64;
-        
         FileMetadata {
             inode: inode_num,
             file_type: Self::inode_mode_to_file_type(inode.mode),
             permissions: Self::inode_mode_to_permissions(inode.mode),
-            size: file_size,
+            size: Self::inode_size_bytes(inode),
             uid: inode.uid as u32,
             gid: inode.gid as u32,
             created_time: inode.crtime as u64,
@@ -305,19 +901,579 @@ impl Ext4FileSystem {
         }
     }
 
+    /// Physical block holding logical block `logical` of `inode`
+    ///
+    /// Holes are `None` unless `allocate` gives a goal to allocate near, in
+    /// which case the block and any indirect blocks leading to it are
+    /// allocated and `inode` updated; the caller writes it back.
+    fn map_block(&mut self, inode: &mut Ext4Inode, logical: u32, allocate: Option<u64>) -> Result<Option<u64>, VfsError> {
+        if inode.flags & EXT4_EXTENTS_FL != 0 {
+            self.map_extent(inode, logical, allocate)
+        } else {
+            self.map_indirect(inode, logical, allocate)
+        }
+    }
+
+    fn map_indirect(&mut self, inode: &mut Ext4Inode, logical: u32, allocate: Option<u64>) -> Result<Option<u64>, VfsError> {
+        let per_block = self.block_size as u64 / 4;
+        let mut index = logical as u64;
+
+        // Which pointer in the inode leads to the block, then the index at
+        // each level of indirection below it
+        let (slot, path): (usize, Vec<u64>) = if index < EXT4_NDIR_BLOCKS as u64 {
+            (index as usize, Vec::new())
+        } else {
+            index -= EXT4_NDIR_BLOCKS as u64;
+            if index < per_block {
+                (EXT4_IND_BLOCK, vec![index])
+            } else {
+                index -= per_block;
+                if index < per_block * per_block {
+                    (EXT4_DIND_BLOCK, vec![index / per_block, index % per_block])
+                } else {
+                    index -= per_block * per_block;
+                    if index >= per_block * per_block * per_block {
+                        return Err(VfsError::NoSpace);
+                    }
+                    (EXT4_TIND_BLOCK, vec![index / (per_block * per_block), (index / per_block) % per_block, index % per_block])
+                }
+            }
+        };
+
+        let mut pointers = inode.block;
+        let mut current = pointers[slot] as u64;
+        if current == 0 {
+            let Some(goal) = allocate else { return Ok(None) };
+            current = self.allocate_block(goal)?;
+            pointers[slot] = current as u32;
+            inode.block = pointers;
+            inode.blocks_lo += self.sectors_per_block();
+        }
+
+        let mut table = vec![0u8; self.block_size as usize];
+        for index in path {
+            self.read_block(current, &mut table)?;
+            let offset = index as usize * 4;
+            let mut next = read_u32(&table, offset) as u64;
+            if next == 0 {
+                if allocate.is_none() {
+                    return Ok(None);
+                }
+                next = self.allocate_block(current + 1)?;
+                write_u32(&mut table, offset, next as u32);
+                self.write_block(current, &table)?;
+                inode.blocks_lo += self.sectors_per_block();
+            }
+            current = next;
+        }
+        Ok(Some(current))
+    }
+
+    /// The inode's block array as the bytes of an extent tree root
+    pub(crate) fn extent_root(inode: &Ext4Inode) -> [u8; 60] {
+        let mut root = [0u8; 60];
+        let pointers = inode.block;
+        for (bytes, pointer) in root.chunks_exact_mut(4).zip(pointers) {
+            bytes.copy_from_slice(&pointer.to_le_bytes());
+        }
+        root
+    }
+
+    fn set_extent_root(inode: &mut Ext4Inode, root: &[u8; 60]) {
+        let mut pointers = [0u32; 15];
+        for (pointer, bytes) in pointers.iter_mut().zip(root.chunks_exact(4)) {
+            *pointer = read_u32(bytes, 0);
+        }
+        inode.block = pointers;
+    }
+
+    /// Entry count and depth of the extent node in `node`
+    pub(crate) fn extent_header(node: &[u8]) -> Result<(usize, u16), VfsError> {
+        if read_u16(node, 0) != EXT4_EXT_MAGIC {
+            return Err(VfsError::IoError);
+        }
+        let entries = read_u16(node, 2) as usize;
+        if EXT4_EXT_ENTRY_SIZE * (entries + 1) > node.len() {
+            return Err(VfsError::IoError);
+        }
+        Ok((entries, read_u16(node, 6)))
+    }
+
+    /// Logical start, length, physical start and whether it was written
+    pub(crate) fn parse_extent(node: &[u8], index: usize) -> (u32, u32, u64, bool) {
+        let entry = EXT4_EXT_ENTRY_SIZE * (index + 1);
+        let length = read_u16(node, entry + 4) as u32;
+        let start = (read_u16(node, entry + 6) as u64) << 32 | read_u32(node, entry + 8) as u64;
+        if length > EXT4_EXT_INIT_MAX_LEN {
+            (read_u32(node, entry), length - EXT4_EXT_INIT_MAX_LEN, start, false)
+        } else {
+            (read_u32(node, entry), length, start, true)
+        }
+    }
+
+    fn lookup_extent(&mut self, inode: &Ext4Inode, logical: u32) -> Result<ExtentLookup, VfsError> {
+        let mut node = Self::extent_root(inode).to_vec();
+        loop {
+            let (entries, depth) = Self::extent_header(&node)?;
+            if depth == 0 {
+                for index in 0..entries {
+                    let (first, length, start, written) = Self::parse_extent(&node, index);
+                    if logical >= first && logical - first < length {
+                        return Ok(match written {
+                            true => ExtentLookup::Mapped(start + (logical - first) as u64),
+                            false => ExtentLookup::Unwritten,
+                        });
+                    }
+                }
+                return Ok(ExtentLookup::Hole);
+            }
+
+            // Follow the last index starting at or before the block
+            let Some(child) = (0..entries)
+                .map(|index| EXT4_EXT_ENTRY_SIZE * (index + 1))
+                .take_while(|&entry| read_u32(&node, entry) <= logical)
+                .last()
+                .map(|entry| (read_u16(&node, entry + 8) as u64) << 32 | read_u32(&node, entry + 4) as u64)
+            else {
+                return Ok(ExtentLookup::Hole);
+            };
+            node = vec![0u8; self.block_size as usize];
+            self.read_block(child, &mut node)?;
+        }
+    }
+
+    /// Extent mapping; holes are only filled while the tree is the inode alone
+    fn map_extent(&mut self, inode: &mut Ext4Inode, logical: u32, allocate: Option<u64>) -> Result<Option<u64>, VfsError> {
+        match self.lookup_extent(inode, logical)? {
+            ExtentLookup::Mapped(block_num) => return Ok(Some(block_num)),
+            ExtentLookup::Unwritten if allocate.is_some() => return Err(VfsError::NotSupported),
+            ExtentLookup::Unwritten | ExtentLookup::Hole if allocate.is_none() => return Ok(None),
+            _ => {}
+        }
+
+        let mut root = Self::extent_root(inode);
+        let (entries, depth) = Self::extent_header(&root)?;
+        if depth != 0 {
+            return Err(VfsError::NotSupported);
+        }
+
+        // Grow the extent ending just before the block when the next
+        // physical block is free too
+        let preceding = (0..entries).find(|&index| {
+            let (first, length, _, written) = Self::parse_extent(&root, index);
+            written && first + length == logical && length < EXT4_EXT_INIT_MAX_LEN
+        });
+        let goal = match preceding {
+            Some(index) => {
+                let (_, length, start, _) = Self::parse_extent(&root, index);
+                start + length as u64
+            }
+            None => allocate.unwrap_or(0),
+        };
+        let block_num = self.allocate_block(goal)?;
+
+        match preceding {
+            Some(index) if block_num == goal => {
+                let entry = EXT4_EXT_ENTRY_SIZE * (index + 1);
+                let length = read_u16(&root, entry + 4);
+                write_u16(&mut root, entry + 4, length + 1);
+            }
+            _ => {
+                if entries >= read_u16(&root, 4) as usize {
+                    self.free_block(block_num)?;
+                    return Err(VfsError::NotSupported);
+                }
+                // Keep the extents sorted by logical block
+                let position = (0..entries)
+                    .find(|&index| Self::parse_extent(&root, index).0 > logical)
+                    .unwrap_or(entries);
+                let entry = EXT4_EXT_ENTRY_SIZE * (position + 1);
+                root.copy_within(entry..EXT4_EXT_ENTRY_SIZE * (entries + 1), entry + EXT4_EXT_ENTRY_SIZE);
+                write_u32(&mut root, entry, logical);
+                write_u16(&mut root, entry + 4, 1);
+                write_u16(&mut root, entry + 6, (block_num >> 32) as u16);
+                write_u32(&mut root, entry + 8, block_num as u32);
+                write_u16(&mut root, 2, entries as u16 + 1);
+            }
+        }
+
+        Self::set_extent_root(inode, &root);
+        inode.blocks_lo += self.sectors_per_block();
+        Ok(Some(block_num))
+    }
+
+    /// Free `block_num` and, `depth` levels deep, every block it points to
+    fn free_indirect(&mut self, block_num: u64, depth: u32) -> Result<(), VfsError> {
+        if depth > 0 {
+            let mut table = vec![0u8; self.block_size as usize];
+            self.read_block(block_num, &mut table)?;
+            for offset in (0..table.len()).step_by(4) {
+                let child = read_u32(&table, offset) as u64;
+                if child != 0 {
+                    self.free_indirect(child, depth - 1)?;
+                }
+            }
+        }
+        self.free_block(block_num)
+    }
+
+    /// Free every block an extent node maps, and the index blocks below it
+    fn free_extent_node(&mut self, node: &[u8]) -> Result<(), VfsError> {
+        let (entries, depth) = Self::extent_header(node)?;
+        for index in 0..entries {
+            let entry = EXT4_EXT_ENTRY_SIZE * (index + 1);
+            if depth == 0 {
+                let (_, length, start, _) = Self::parse_extent(node, index);
+                for block_num in start..start + length as u64 {
+                    self.free_block(block_num)?;
+                }
+            } else {
+                let child = (read_u16(node, entry + 8) as u64) << 32 | read_u32(node, entry + 4) as u64;
+                let mut block = vec![0u8; self.block_size as usize];
+                self.read_block(child, &mut block)?;
+                self.free_extent_node(&block)?;
+                self.free_block(child)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Release all data blocks of `inode`, leaving it empty
+    fn free_data(&mut self, inode: &mut Ext4Inode) -> Result<(), VfsError> {
+        if Self::is_fast_symlink(inode) {
+            return Ok(());
+        }
+
+        if inode.flags & EXT4_EXTENTS_FL != 0 {
+            let mut root = Self::extent_root(inode);
+            self.free_extent_node(&root)?;
+            write_u16(&mut root, 2, 0);
+            write_u16(&mut root, 6, 0);
+            Self::set_extent_root(inode, &root);
+        } else {
+            let pointers = inode.block;
+            for (slot, &pointer) in pointers.iter().enumerate() {
+                if pointer != 0 {
+                    let depth = slot.saturating_sub(EXT4_NDIR_BLOCKS - 1) as u32;
+                    self.free_indirect(pointer as u64, depth)?;
+                }
+            }
+            inode.block = [0; 15];
+        }
+
+        inode.blocks_lo = 0;
+        Self::set_inode_size(inode, 0);
+        Ok(())
+    }
+
+    /// Drop the inode's reference to its extended attribute block
+    fn release_xattr_block(&mut self, inode: &mut Ext4Inode) -> Result<(), VfsError> {
+        let block_num = inode.file_acl_lo as u64;
+        if block_num == 0 {
+            return Ok(());
+        }
+        inode.file_acl_lo = 0;
+
+        let mut block = vec![0u8; self.block_size as usize];
+        self.read_block(block_num, &mut block)?;
+        if read_u32(&block, 0) != EXT4_XATTR_MAGIC {
+            return Err(VfsError::IoError);
+        }
+        let references = read_u32(&block, 4);
+        if references > 1 {
+            write_u32(&mut block, 4, references - 1);
+            return self.write_block(block_num, &block);
+        }
+        self.free_block(block_num)
+    }
+
+    /// Fill `block` with a directory's "." and ".." entries
+    fn init_directory_block(block: &mut [u8], inode_num: u32, parent: u32) {
+        block.fill(0);
+        let dot_len = dir_entry_len(1);
+        write_u32(block, 0, inode_num);
+        write_u16(block, 4, dot_len as u16);
+        block[6] = 1;
+        block[7] = EXT4_FT_DIR;
+        block[8] = b'.';
+
+        write_u32(block, dot_len, parent);
+        write_u16(block, dot_len + 4, (block.len() - dot_len) as u16);
+        block[dot_len + 6] = 2;
+        block[dot_len + 7] = EXT4_FT_DIR;
+        block[dot_len + 8..dot_len + 10].copy_from_slice(b"..");
+    }
+
+    /// The entries of one directory block
+    fn parse_dir_block(&self, block: &[u8]) -> Result<Vec<RawDirEntry>, VfsError> {
+        let has_file_type = self.superblock
+            .is_some_and(|superblock| superblock.feature_incompat & EXT4_FEATURE_INCOMPAT_FILETYPE != 0);
+
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset + EXT4_DIR_ENTRY_HEADER <= block.len() {
+            let rec_len = read_u16(block, offset + 4) as usize;
+            let name_len = block[offset + 6] as usize;
+            if rec_len < EXT4_DIR_ENTRY_HEADER || !rec_len.is_multiple_of(4) || offset + rec_len > block.len()
+                || EXT4_DIR_ENTRY_HEADER + name_len > rec_len
+            {
+                return Err(VfsError::IoError);
+            }
+            entries.push(RawDirEntry {
+                offset,
+                inode: read_u32(block, offset),
+                rec_len,
+                name_len,
+                file_type: if has_file_type { block[offset + 7] } else { EXT4_FT_UNKNOWN },
+            });
+            offset += rec_len;
+        }
+        Ok(entries)
+    }
+
+    fn entry_name<'a>(block: &'a [u8], entry: &RawDirEntry) -> &'a [u8] {
+        let start = entry.offset + EXT4_DIR_ENTRY_HEADER;
+        &block[start..start + entry.name_len]
+    }
+
+    /// Physical blocks of a directory, in order
+    fn directory_blocks(&mut self, inode: &Ext4Inode) -> Result<Vec<u64>, VfsError> {
+        let count = Self::inode_size_bytes(inode).div_ceil(self.block_size as u64) as u32;
+        let mut inode = *inode;
+        (0..count)
+            .map(|logical| self.map_block(&mut inode, logical, None)?.ok_or(VfsError::IoError))
+            .collect()
+    }
+
+    /// Inode number and entry type of `name` in the directory `dir`
+    fn lookup(&mut self, dir: &Ext4Inode, name: &[u8]) -> Result<Option<(InodeNumber, u8)>, VfsError> {
+        let mut block = vec![0u8; self.block_size as usize];
+        for block_num in self.directory_blocks(dir)? {
+            self.read_block(block_num, &mut block)?;
+            for entry in self.parse_dir_block(&block)? {
+                if entry.inode != 0 && Self::entry_name(&block, &entry) == name {
+                    return Ok(Some((entry.inode as InodeNumber, entry.file_type)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Link `child` into the directory `dir_num` as `name`
+    fn add_entry(&mut self, dir_num: InodeNumber, name: &[u8], child: InodeNumber, file_type: u8) -> Result<(), VfsError> {
+        let mut dir = self.read_inode(dir_num)?;
+        let needed = dir_entry_len(name.len());
+        let write_entry = |block: &mut [u8], offset: usize, rec_len: usize| {
+            write_u32(block, offset, child as u32);
+            write_u16(block, offset + 4, rec_len as u16);
+            block[offset + 6] = name.len() as u8;
+            block[offset + 7] = file_type;
+            block[offset + 8..offset + 8 + name.len()].copy_from_slice(name);
+        };
+
+        // The hashed index is not maintained, so a changed directory falls
+        // back to the linear format every reader understands
+        dir.flags &= !EXT4_INDEX_FL;
+
+        let mut block = vec![0u8; self.block_size as usize];
+        for block_num in self.directory_blocks(&dir)? {
+            self.read_block(block_num, &mut block)?;
+            let free_slot = self.parse_dir_block(&block)?.into_iter().find_map(|entry| {
+                let used = if entry.inode == 0 { 0 } else { dir_entry_len(entry.name_len) };
+                (entry.rec_len - used >= needed).then_some((entry, used))
+            });
+            if let Some((entry, used)) = free_slot {
+                if used > 0 {
+                    write_u16(&mut block, entry.offset + 4, used as u16);
+                }
+                write_entry(&mut block, entry.offset + used, entry.rec_len - used);
+                self.write_block(block_num, &block)?;
+                return self.write_inode(dir_num, &dir);
+            }
+        }
+
+        // Every block is full; the entry starts a new one
+        let logical = Self::inode_size_bytes(&dir).div_ceil(self.block_size as u64) as u32;
+        let goal = self.inode_goal(dir_num);
+        let block_num = self.map_block(&mut dir, logical, Some(goal))?.ok_or(VfsError::IoError)?;
+        block.fill(0);
+        let rec_len = block.len();
+        write_entry(&mut block, 0, rec_len);
+        self.write_block(block_num, &block)?;
+        Self::set_inode_size(&mut dir, (logical as u64 + 1) * self.block_size as u64);
+        dir.mtime = now();
+        self.write_inode(dir_num, &dir)
+    }
+
+    /// Unlink `name` from the directory `dir_num`
+    fn remove_entry(&mut self, dir_num: InodeNumber, name: &[u8]) -> Result<(), VfsError> {
+        let mut dir = self.read_inode(dir_num)?;
+        dir.flags &= !EXT4_INDEX_FL;
+
+        let mut block = vec![0u8; self.block_size as usize];
+        for block_num in self.directory_blocks(&dir)? {
+            self.read_block(block_num, &mut block)?;
+            let entries = self.parse_dir_block(&block)?;
+            let Some(position) = entries.iter()
+                .position(|entry| entry.inode != 0 && Self::entry_name(&block, entry) == name)
+            else {
+                continue;
+            };
+
+            // The previous entry absorbs the space; the first in a block is
+            // only marked unused
+            let entry = &entries[position];
+            match position.checked_sub(1).map(|previous| &entries[previous]) {
+                Some(previous) => write_u16(&mut block, previous.offset + 4, (previous.rec_len + entry.rec_len) as u16),
+                None => write_u32(&mut block, entry.offset, 0),
+            }
+            self.write_block(block_num, &block)?;
+            dir.mtime = now();
+            return self.write_inode(dir_num, &dir);
+        }
+        Err(VfsError::NotFound)
+    }
+
     /// Resolve a path to an inode number
     fn resolve_path(&mut self, path: &str) -> Result<InodeNumber, VfsError> {
-        if path == "/" {
-            return Ok(2); // Root inode is typically 2 in ext4
+        let mut current = EXT4_ROOT_INODE;
+        for component in path.split('/').filter(|component| !component.is_empty()) {
+            let inode = self.read_inode(current)?;
+            if Self::inode_mode_to_file_type(inode.mode) != FileType::Directory {
+                return Err(VfsError::NotDirectory);
+            }
+            current = self.lookup(&inode, component.as_bytes())?.ok_or(VfsError::NotFound)?.0;
+        }
+        Ok(current)
+    }
+
+    /// The directory holding `path` and the final name within it
+    fn resolve_parent<'a>(&mut self, path: &'a str) -> Result<(InodeNumber, Ext4Inode, &'a str), VfsError> {
+        let (parent, name) = split_path(path)?;
+        let parent_num = self.resolve_path(parent)?;
+        let parent_inode = self.read_inode(parent_num)?;
+        if Self::inode_mode_to_file_type(parent_inode.mode) != FileType::Directory {
+            return Err(VfsError::NotDirectory);
         }
+        Ok((parent_num, parent_inode, name))
+    }
 
-        // Check if we have this path in our path-to-inode mapping
-        if let Some(&inode_num) = self.path_to_inode.get(path) {
-            return Ok(inode_num);
+    /// Allocate an inode for a new file at `path` and link it in
+    fn create_node(&mut self, path: &str, file_type: FileType, permissions: FilePermissions) -> Result<InodeNumber, VfsError> {
+        self.check_writable()?;
+        let (parent_num, parent, name) = self.resolve_parent(path)?;
+        if self.lookup(&parent, name.as_bytes())?.is_some() {
+            return Err(VfsError::AlreadyExists);
         }
-        
-        // If not found, return NotFound error
-        Err(VfsError::NotFound)
+
+        let directory = file_type == FileType::Directory;
+        let mode = match file_type {
+            FileType::Regular => EXT4_S_IFREG,
+            FileType::Directory => EXT4_S_IFDIR,
+            FileType::SymbolicLink => EXT4_S_IFLNK,
+            FileType::BlockDevice => EXT4_S_IFBLK,
+            FileType::CharacterDevice => EXT4_S_IFCHR,
+            FileType::Fifo => EXT4_S_IFIFO,
+            FileType::Socket => EXT4_S_IFSOCK,
+        } | permissions.bits();
+
+        let inode_num = self.allocate_inode(parent_num, directory)?;
+        let mut inode = Self::new_inode(mode);
+
+        let result = (|| {
+            // Clear what a previous owner left past the fields written here
+            let offset = self.inode_offset(inode_num)?;
+            let inode_size = self.inode_size as usize;
+            block::write_at(self.device()?, offset, &vec![0u8; inode_size])?;
+
+            if directory {
+                let block_num = self.map_block(&mut inode, 0, Some(self.inode_goal(inode_num)))?.ok_or(VfsError::IoError)?;
+                let mut block = vec![0u8; self.block_size as usize];
+                Self::init_directory_block(&mut block, inode_num as u32, parent_num as u32);
+                self.write_block(block_num, &block)?;
+                inode.links_count = 2;
+                Self::set_inode_size(&mut inode, self.block_size as u64);
+            }
+            self.write_inode(inode_num, &inode)?;
+            self.add_entry(parent_num, name.as_bytes(), inode_num, Self::vfs_to_ext4_file_type(file_type))
+        })();
+        if let Err(error) = result {
+            self.free_data(&mut inode)?;
+            self.free_inode(inode_num, directory)?;
+            return Err(error);
+        }
+
+        if directory {
+            // The new directory's ".." links to the parent
+            let mut parent = self.read_inode(parent_num)?;
+            parent.links_count = (parent.links_count + 1).min(EXT4_LINK_MAX);
+            self.write_inode(parent_num, &parent)?;
+        }
+        Ok(inode_num)
+    }
+
+    /// Store `buffer` at `offset` of `inode`, allocating blocks as needed
+    ///
+    /// Whatever was written before an error is kept, and counted unless
+    /// nothing was.
+    fn write_data(&mut self, inode_num: InodeNumber, mut inode: Ext4Inode, offset: FileOffset, buffer: &[u8]) -> Result<usize, VfsError> {
+        let block_size = self.block_size as usize;
+        let mut block = vec![0u8; block_size];
+        let mut goal = self.inode_goal(inode_num);
+        let mut done = 0;
+        let result = loop {
+            if done == buffer.len() {
+                break Ok(());
+            }
+            let position = offset + done as u64;
+            let within = (position % block_size as u64) as usize;
+            let count = (buffer.len() - done).min(block_size - within);
+            let Ok(logical) = u32::try_from(position / block_size as u64) else {
+                break Err(VfsError::NoSpace);
+            };
+
+            let block_num = match self.map_block(&mut inode, logical, Some(goal)) {
+                Ok(block_num) => block_num.ok_or(VfsError::IoError),
+                Err(error) => Err(error),
+            };
+            let stored = block_num.and_then(|block_num| {
+                if count < block_size {
+                    self.read_block(block_num, &mut block)?;
+                }
+                block[within..within + count].copy_from_slice(&buffer[done..done + count]);
+                self.write_block(block_num, &block)?;
+                Ok(block_num)
+            });
+            match stored {
+                Ok(block_num) => goal = block_num + 1,
+                Err(error) => break Err(error),
+            }
+            done += count;
+        };
+
+        // Whatever was written, and the blocks allocated for it, is kept
+        let end = offset + done as u64;
+        if end > Self::inode_size_bytes(&inode) {
+            Self::set_inode_size(&mut inode, end);
+        }
+        inode.mtime = now();
+        self.write_inode(inode_num, &inode)?;
+
+        match result {
+            Err(error) if done == 0 => Err(error),
+            _ => Ok(done),
+        }
+    }
+    /// Mark a removed inode deleted and give back its blocks and number
+    fn delete_inode(&mut self, inode_num: InodeNumber, inode: &mut Ext4Inode) -> Result<(), VfsError> {
+        let directory = Self::inode_mode_to_file_type(inode.mode) == FileType::Directory;
+        self.free_data(inode)?;
+        self.release_xattr_block(inode)?;
+        inode.links_count = 0;
+        // A zero deletion time reads as an inode still in use
+        inode.dtime = now().max(1);
+        self.write_inode(inode_num, inode)?;
+        self.free_inode(inode_num, directory)
     }
 }
 
@@ -330,8 +1486,8 @@ impl FileSystem for Ext4FileSystem {
         self.inode_size = 0;
         self.mounted = false;
         self.inode_cache.clear();
-        self.path_to_inode.clear();
-        self.next_inode = 100;
+        self.groups.clear();
+        self.read_only = false;
         Ok(())
     }
 
@@ -342,60 +1498,32 @@ impl FileSystem for Ext4FileSystem {
         }
 
         self.device_id = device_id;
+        if self.device.is_none() {
+            self.device = Some(Box::new(ram_disk()?));
+        }
 
         // Read and parse the superblock
-        let mut superblock_data = vec![0u8; mem::size_of::<Ext4Superblock>()];
-        
-        // In a real implementation, we would read from the actual device at offset 1024
-        // For now, we'll create a minimal valid superblock for testing
-        let test_superblock = Ext4Superblock {
-            inodes_count: 1000,
-            blocks_count: 10000,
-            r_blocks_count: 500,
-            free_blocks_count: 8000,
-            free_inodes_count: 900,
-            first_data_block: 1,
-            log_block_size: 0, // 1024 byte blocks
-            log_cluster_size: 0,
-            blocks_per_group: 8192,
-            clusters_per_group: 8192,
-            inodes_per_group: 256,
-            mtime: 0,
-            wtime: 0,
-            mnt_count: 1,
-            max_mnt_count: 20,
-            magic: EXT4_SUPER_MAGIC,
-            state: 1, // Clean
-            errors: 1, // Continue on errors
-            minor_rev_level: 0,
-            lastcheck: 0,
-            checkinterval: 0,
-            creator_os: 0,
-            rev_level: 1,
-            def_resuid: 0,
-            def_resgid: 0,
-            first_ino: 11,
-            inode_size: 256,
-            block_group_nr: 0,
-            feature_compat: 0,
-            feature_incompat: 0,
-            feature_ro_compat: 0,
-            uuid: [0; 16],
-            volume_name: [0; 16],
-            last_mounted: [0; 64],
-            algorithm_usage_bitmap: 0,
-        };
-
-        // Copy test superblock to buffer
-        unsafe {
-            core::ptr::copy_nonoverlapping(
-                &test_superblock as *const _ as *const u8,
-                superblock_data.as_mut_ptr(),
-                mem::size_of::<Ext4Superblock>()
-            );
+        let mut superblock_data = vec![0u8; EXT4_SUPERBLOCK_SIZE];
+        block::read_at(self.device()?, EXT4_SUPERBLOCK_OFFSET, &mut superblock_data)?;
+
+        let result = self.parse_superblock(&superblock_data)
+            .and_then(|()| self.load_group_descriptors())
+            .and_then(|()| {
+                if self.read_only {
+                    return Ok(());
+                }
+                // Marked in use until a clean unmount
+                let superblock = self.superblock.as_mut().ok_or(VfsError::NotMounted)?;
+                superblock.state &= !EXT4_VALID_FS;
+                superblock.mnt_count = superblock.mnt_count.wrapping_add(1);
+                superblock.mtime = now();
+                self.write_superblock()
+            });
+        if let Err(error) = result {
+            self.init()?;
+            return Err(error);
         }
 
-        self.parse_superblock(&superblock_data)?;
         self.mounted = true;
         Ok(())
     }
@@ -406,32 +1534,48 @@ impl FileSystem for Ext4FileSystem {
             return Err(VfsError::NotMounted);
         }
 
+        if !self.read_only {
+            let superblock = self.superblock.as_mut().ok_or(VfsError::NotMounted)?;
+            superblock.state |= EXT4_VALID_FS;
+            superblock.wtime = now();
+            self.write_superblock()?;
+        }
+
         // Sync any pending changes
         self.sync()?;
-        
-        // Clear state
+
+        // Clear state; the device stays, so a later mount finds the data
         self.superblock = None;
         self.block_size = 0;
         self.inode_size = 0;
         self.device_id = None;
         self.mounted = false;
         self.inode_cache.clear();
-        self.path_to_inode.clear();
-        self.next_inode = 100;
-        
+        self.groups.clear();
+        self.read_only = false;
+
         Ok(())
     }
 
     /// Open a file and return its inode and metadata
-    fn open(&mut self, path: &str, _flags: OpenFlags) -> Result<(InodeNumber, FileMetadata), VfsError> {
+    fn open(&mut self, path: &str, flags: OpenFlags) -> Result<(InodeNumber, FileMetadata), VfsError> {
         if !self.mounted {
             return Err(VfsError::NotMounted);
         }
 
         let inode_num = self.resolve_path(path)?;
-        let inode = self.read_inode(inode_num)?;
+        let mut inode = self.read_inode(inode_num)?;
+        if flags.contains(OpenFlags::TRUNCATE)
+            && Self::inode_mode_to_file_type(inode.mode) == FileType::Regular
+            && Self::inode_size_bytes(&inode) > 0
+        {
+            self.check_writable()?;
+            self.free_data(&mut inode)?;
+            inode.mtime = now();
+            self.write_inode(inode_num, &inode)?;
+        }
         let metadata = self.inode_to_metadata(inode_num, &inode);
-        
+
         Ok((inode_num, metadata))
     }
 
@@ -440,9 +1584,8 @@ impl FileSystem for Ext4FileSystem {
         if !self.mounted {
             return Err(VfsError::NotMounted);
         }
-        
-        // In a real implementation, we might flush any cached data
-        // and update access times
+
+        // Writes go straight to the device, so nothing is left to flush
         Ok(())
     }
 
@@ -452,9 +1595,9 @@ impl FileSystem for Ext4FileSystem {
             return Err(VfsError::NotMounted);
         }
 
-        let inode = self.read_inode(inode_num)?;
-        let file_size = (inode.size_high as u64) << 32 | inode.size_lo as u64;
-        
+        let mut inode = self.read_inode(inode_num)?;
+        let file_size = Self::inode_size_bytes(&inode);
+
         // Check if offset is beyond file size
         if offset >= file_size {
             return Ok(0);
@@ -462,15 +1605,26 @@ impl FileSystem for Ext4FileSystem {
 
         // Calculate how much we can actually read
         let bytes_to_read = core::cmp::min(buffer.len() as u64, file_size - offset) as usize;
-        
-        // In a real implementation, we would:
-        // 1. Calculate which blocks contain the requested data
-        // 2. Read the blocks from disk
-        // 3. Copy the relevant portions to the buffer
-        
-        // For now, fill with placeholder data
-        buffer[..bytes_to_read].fill(0x42); // Fill with 'B' for testing
-        
+        let block_size = self.block_size as usize;
+        let mut block = vec![0u8; block_size];
+        let mut done = 0;
+        while done < bytes_to_read {
+            let position = offset + done as u64;
+            let within = (position % block_size as u64) as usize;
+            let count = (bytes_to_read - done).min(block_size - within);
+            let logical = u32::try_from(position / block_size as u64).map_err(|_| VfsError::IoError)?;
+
+            // Holes read as zeros
+            match self.map_block(&mut inode, logical, None)? {
+                Some(block_num) => {
+                    self.read_block(block_num, &mut block)?;
+                    buffer[done..done + count].copy_from_slice(&block[within..within + count]);
+                }
+                None => buffer[done..done + count].fill(0),
+            }
+            done += count;
+        }
+
         Ok(bytes_to_read)
     }
 
@@ -479,37 +1633,16 @@ impl FileSystem for Ext4FileSystem {
         if !self.mounted {
             return Err(VfsError::NotMounted);
         }
+        self.check_writable()?;
+
+        let inode = self.read_inode(inode_num)?;
 
-        let mut inode = self.read_inode(inode_num)?;
-        
         // Check if this is a regular file
         if Self::inode_mode_to_file_type(inode.mode) != FileType::Regular {
             return Err(VfsError::PermissionDenied);
         }
 
-        // In a real implementation, we would:
-        // 1. Allocate new blocks if needed
-        // 2. Update the inode's block pointers
-        // 3. Write the data to the allocated blocks
-        // 4. Update the inode size and modification time
-        // 5. Write the updated inode back to disk
-
-        // For now, just update the size if we're extending the file
-        let new_size = offset + buffer.len() as u64;
-        let current_size = (inode.size_high as u64) << 32 | inode.size_lo as u64;
-        
-        if new_size > current_size {
-            inode.size_lo = new_size as u32;
-            inode.size_high = (new_size >> 32) as u32;
-            
-            // Update modification time (placeholder)
-            inode.mtime = 1234567890; // Placeholder timestamp
-            
-            // Update cache
-            self.inode_cache.insert(inode_num, inode);
-        }
-
-        Ok(buffer.len())
+        self.write_data(inode_num, inode, offset, buffer)
     }
 
     /// Create a new file
@@ -518,67 +1651,7 @@ impl FileSystem for Ext4FileSystem {
             return Err(VfsError::NotMounted);
         }
 
-        // Check if the path already exists
-        if self.path_to_inode.contains_key(path) {
-            return Err(VfsError::AlreadyExists);
-        }
-
-        // In a real implementation, we would:
-        // 1. Find a free inode
-        // 2. Initialize the inode with the specified type and permissions
-        // 3. Add a directory entry in the parent directory
-        // 4. Update the superblock free inode count
-
-        // Allocate a new inode number
-        let new_inode_num = self.next_inode;
-        self.next_inode += 1;
-
-        // Create a basic inode structure
-        let mode = match file_type {
-            FileType::Regular => EXT4_S_IFREG,
-            FileType::Directory => EXT4_S_IFDIR,
-            FileType::SymbolicLink => EXT4_S_IFLNK,
-            FileType::BlockDevice => EXT4_S_IFBLK,
-            FileType::CharacterDevice => EXT4_S_IFCHR,
-            FileType::Fifo => EXT4_S_IFIFO,
-            FileType::Socket => EXT4_S_IFSOCK,
-        } | permissions.bits();
-
-        let new_inode = Ext4Inode {
-            mode,
-            uid: 0, // Current user ID (placeholder)
-            size_lo: 0,
-            atime: 1234567890, // Placeholder timestamp
-            ctime: 1234567890,
-            mtime: 1234567890,
-            dtime: 0,
-            gid: 0, // Current group ID (placeholder)
-            links_count: 1,
-            blocks_lo: 0,
-            flags: 0,
-            osd1: 0,
-            block: [0; 15],
-            generation: 1,
-            file_acl_lo: 0,
-            size_high: 0,
-            obso_faddr: 0,
-            osd2: [0; 12],
-            extra_isize: 0,
-            checksum_hi: 0,
-            ctime_extra: 0,
-            mtime_extra: 0,
-            atime_extra: 0,
-            crtime: 1234567890,
-            crtime_extra: 0,
-            version_hi: 0,
-            projid: 0,
-        };
-
-        // Cache the new inode and add path mapping
-        self.inode_cache.insert(new_inode_num, new_inode);
-        self.path_to_inode.insert(path.to_string(), new_inode_num);
-
-        Ok(new_inode_num)
+        self.create_node(path, file_type, permissions)
     }
 
     /// Delete a file
@@ -586,25 +1659,24 @@ impl FileSystem for Ext4FileSystem {
         if !self.mounted {
             return Err(VfsError::NotMounted);
         }
+        self.check_writable()?;
 
-        let inode_num = self.resolve_path(path)?;
-        let inode = self.read_inode(inode_num)?;
+        let (parent_num, parent, name) = self.resolve_parent(path)?;
+        let (inode_num, _) = self.lookup(&parent, name.as_bytes())?.ok_or(VfsError::NotFound)?;
+        let mut inode = self.read_inode(inode_num)?;
 
         // Check if it's a directory
         if Self::inode_mode_to_file_type(inode.mode) == FileType::Directory {
             return Err(VfsError::IsDirectory);
         }
 
-        // In a real implementation, we would:
-        // 1. Remove the directory entry from the parent directory
-        // 2. Decrement the inode link count
-        // 3. If link count reaches 0, free the inode and its blocks
-        // 4. Update the superblock free counts
-
-        // For now, just remove from cache
-        self.inode_cache.remove(&inode_num);
-
-        Ok(())
+        self.remove_entry(parent_num, name.as_bytes())?;
+        inode.links_count = inode.links_count.saturating_sub(1);
+        if inode.links_count > 0 {
+            inode.ctime = now();
+            return self.write_inode(inode_num, &inode);
+        }
+        self.delete_inode(inode_num, &mut inode)
     }
 
     /// Get file metadata
@@ -632,34 +1704,29 @@ impl FileSystem for Ext4FileSystem {
             return Err(VfsError::NotDirectory);
         }
 
-        // In a real implementation, we would:
-        // 1. Read the directory's data blocks
-        // 2. Parse the directory entries
-        // 3. Convert them to VFS DirectoryEntry format
-
-        // For now, return some placeholder entries
         let mut entries = Vec::new();
-        
-        // Add "." entry
-        let mut dot_name = [0u8; 256];
-        dot_name[0] = b'.';
-        entries.push(DirectoryEntry {
-            name: dot_name,
-            name_len: 1,
-            inode: inode_num,
-            file_type: FileType::Directory,
-        });
-
-        // Add ".." entry
-        let mut dotdot_name = [0u8; 256];
-        dotdot_name[0] = b'.';
-        dotdot_name[1] = b'.';
-        entries.push(DirectoryEntry {
-            name: dotdot_name,
-            name_len: 2,
-            inode: 2, // Parent directory (root for now)
-            file_type: FileType::Directory,
-        });
+        let mut block = vec![0u8; self.block_size as usize];
+        for block_num in self.directory_blocks(&inode)? {
+            self.read_block(block_num, &mut block)?;
+            for entry in self.parse_dir_block(&block)? {
+                if entry.inode == 0 {
+                    continue;
+                }
+                // Images without typed entries only record it in the inode
+                let file_type = match entry.file_type {
+                    EXT4_FT_UNKNOWN => Self::inode_mode_to_file_type(self.read_inode(entry.inode as InodeNumber)?.mode),
+                    file_type => Self::ext4_to_vfs_file_type(file_type),
+                };
+                let mut name = [0u8; 256];
+                name[..entry.name_len].copy_from_slice(Self::entry_name(&block, &entry));
+                entries.push(DirectoryEntry {
+                    name,
+                    name_len: entry.name_len as u8,
+                    inode: entry.inode as InodeNumber,
+                    file_type,
+                });
+            }
+        }
 
         Ok(entries)
     }
@@ -670,13 +1737,7 @@ impl FileSystem for Ext4FileSystem {
             return Err(VfsError::NotMounted);
         }
 
-        // Create the directory inode
-        let _inode_num = self.create(path, FileType::Directory, permissions)?;
-
-        // In a real implementation, we would also:
-        // 1. Initialize the directory with "." and ".." entries
-        // 2. Update the parent directory's link count
-
+        self.create_node(path, FileType::Directory, permissions)?;
         Ok(())
     }
 
@@ -685,25 +1746,37 @@ impl FileSystem for Ext4FileSystem {
         if !self.mounted {
             return Err(VfsError::NotMounted);
         }
+        self.check_writable()?;
 
-        let inode_num = self.resolve_path(path)?;
-        let inode = self.read_inode(inode_num)?;
+        let (parent_num, parent, name) = self.resolve_parent(path)?;
+        if name == "." || name == ".." {
+            return Err(VfsError::InvalidPath);
+        }
+        let (inode_num, _) = self.lookup(&parent, name.as_bytes())?.ok_or(VfsError::NotFound)?;
+        let mut inode = self.read_inode(inode_num)?;
 
         // Check if it's a directory
         if Self::inode_mode_to_file_type(inode.mode) != FileType::Directory {
             return Err(VfsError::NotDirectory);
         }
 
-        // In a real implementation, we would:
-        // 1. Check if the directory is empty (only contains "." and "..")
-        // 2. Remove the directory entry from the parent
-        // 3. Free the directory inode and blocks
-        // 4. Update parent directory's link count
+        // Only "." and ".." may be left
+        let mut block = vec![0u8; self.block_size as usize];
+        for block_num in self.directory_blocks(&inode)? {
+            self.read_block(block_num, &mut block)?;
+            let occupied = self.parse_dir_block(&block)?.iter()
+                .any(|entry| entry.inode != 0 && !matches!(Self::entry_name(&block, entry), b"." | b".."));
+            if occupied {
+                return Err(VfsError::DirectoryNotEmpty);
+            }
+        }
 
-        // For now, just remove from cache
-        self.inode_cache.remove(&inode_num);
+        self.remove_entry(parent_num, name.as_bytes())?;
+        self.delete_inode(inode_num, &mut inode)?;
 
-        Ok(())
+        let mut parent = self.read_inode(parent_num)?;
+        parent.links_count = parent.links_count.saturating_sub(1).max(2);
+        self.write_inode(parent_num, &parent)
     }
 
     /// Sync file system data to storage
@@ -712,20 +1785,82 @@ impl FileSystem for Ext4FileSystem {
             return Err(VfsError::NotMounted);
         }
 
-        // In a real implementation, we would:
-        // 1. Write all dirty inodes to disk
-        // 2. Write all dirty data blocks to disk
-        // 3. Update the superblock
-        // 4. Ensure all writes are committed to storage
+        // Metadata and data are written as they change; the device may
+        // still be holding some of it back
+        self.device()?.flush()
+    }
 
-        // For now, this is a no-op
-        Ok(())
+    fn size(&mut self, inode_num: InodeNumber) -> Result<FileOffset, VfsError> {
+        if !self.mounted {
+            return Err(VfsError::NotMounted);
+        }
+
+        let inode = self.read_inode(inode_num)?;
+        Ok(Self::inode_size_bytes(&inode))
+    }
+
+    /// Targets shorter than the block array are kept in it; longer ones,
+    /// up to a block, get a data block
+    fn symlink(&mut self, path: &str, target: &str) -> Result<InodeNumber, VfsError> {
+        if !self.mounted {
+            return Err(VfsError::NotMounted);
+        }
+        if target.is_empty() || target.len() >= self.block_size as usize {
+            return Err(VfsError::InvalidPath);
+        }
+
+        let permissions = FilePermissions::from_bits_truncate(0o777);
+        let inode_num = self.create_node(path, FileType::SymbolicLink, permissions)?;
+        let mut inode = self.read_inode(inode_num)?;
+        let result = if target.len() < mem::size_of::<[u32; 15]>() {
+            let mut bytes = [0u8; mem::size_of::<[u32; 15]>()];
+            bytes[..target.len()].copy_from_slice(target.as_bytes());
+            let mut pointers = [0u32; 15];
+            for (pointer, chunk) in pointers.iter_mut().zip(bytes.chunks_exact(4)) {
+                *pointer = read_u32(chunk, 0);
+            }
+            inode.block = pointers;
+            Self::set_inode_size(&mut inode, target.len() as u64);
+            self.write_inode(inode_num, &inode)
+        } else {
+            self.write_data(inode_num, inode, 0, target.as_bytes()).map(|_| ())
+        };
+        if let Err(error) = result {
+            self.unlink(path)?;
+            return Err(error);
+        }
+        Ok(inode_num)
+    }
+
+    fn readlink(&mut self, path: &str) -> Result<String, VfsError> {
+        if !self.mounted {
+            return Err(VfsError::NotMounted);
+        }
+
+        let inode_num = self.resolve_path(path)?;
+        let inode = self.read_inode(inode_num)?;
+        if Self::inode_mode_to_file_type(inode.mode) != FileType::SymbolicLink {
+            return Err(VfsError::InvalidArgument);
+        }
+
+        let size = Self::inode_size_bytes(&inode).min(self.block_size as u64) as usize;
+        let mut target = vec![0u8; size];
+        if Self::is_fast_symlink(&inode) {
+            let pointers = inode.block;
+            let bytes: Vec<u8> = pointers.iter().flat_map(|pointer| pointer.to_le_bytes()).collect();
+            target.copy_from_slice(&bytes[..size]);
+        } else {
+            let length = self.read(inode_num, 0, &mut target)?;
+            target.truncate(length);
+        }
+        String::from_utf8(target).map_err(|_| VfsError::IoError)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::string::String;
 
     #[test]
     fn test_ext4_creation() {
@@ -771,10 +1906,10 @@ mod tests {
     fn test_create_file() {
         let mut fs = Ext4FileSystem::new();
         assert!(fs.mount(Some(1)).is_ok());
-        
+
         let inode_num = fs.create("/test.txt", FileType::Regular, FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE);
         assert!(inode_num.is_ok());
-        
+
         let inode = fs.read_inode(inode_num.unwrap());
         assert!(inode.is_ok());
     }
@@ -783,19 +1918,133 @@ mod tests {
     fn test_read_write() {
         let mut fs = Ext4FileSystem::new();
         assert!(fs.mount(Some(1)).is_ok());
-        
+
         let inode_num = fs.create("/test.txt", FileType::Regular, FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE).unwrap();
-        
+
         // Test write
         let data = b"Hello, ext4!";
         let written = fs.write(inode_num, 0, data);
         assert!(written.is_ok());
         assert_eq!(written.unwrap(), data.len());
-        
+
         // Test read
         let mut buffer = vec![0u8; data.len()];
         let read = fs.read(inode_num, 0, &mut buffer);
         assert!(read.is_ok());
         assert_eq!(read.unwrap(), data.len());
+        assert_eq!(&buffer, data);
+    }
+
+    fn free_counts(fs: &Ext4FileSystem) -> (u32, u32) {
+        let superblock = fs.superblock.unwrap();
+        (superblock.free_blocks_count, superblock.free_inodes_count)
+    }
+
+    #[test]
+    fn test_data_persists_across_mounts() {
+        let mut disk = RamDisk::new(SECTOR_SIZE, 2048);
+        format(&mut disk, 1024).unwrap();
+        let mut fs = Ext4FileSystem::with_device(Box::new(disk));
+        fs.mount(None).unwrap();
+
+        let permissions = FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE | FilePermissions::OWNER_EXECUTE;
+        fs.mkdir("/etc", permissions).unwrap();
+        let inode_num = fs.create("/etc/hosts", FileType::Regular, permissions).unwrap();
+
+        // Past the direct blocks, so the data goes through an indirect block
+        let data: Vec<u8> = (0..20 * 1024).map(|i| (i % 251) as u8).collect();
+        assert_eq!(fs.write(inode_num, 0, &data), Ok(data.len()));
+        fs.unmount().unwrap();
+
+        fs.mount(None).unwrap();
+        assert!(fs.superblock.unwrap().state & EXT4_VALID_FS == 0);
+        let (inode_num, metadata) = fs.open("/etc/hosts", OpenFlags::READ_ONLY).unwrap();
+        assert_eq!(metadata.size, data.len() as u64);
+        let mut buffer = vec![0u8; data.len()];
+        assert_eq!(fs.read(inode_num, 0, &mut buffer), Ok(data.len()));
+        assert_eq!(buffer, data);
+
+        let names: Vec<Vec<u8>> = fs.readdir("/etc").unwrap().iter()
+            .map(|entry| entry.name[..entry.name_len as usize].to_vec())
+            .collect();
+        assert_eq!(names, [b".".to_vec(), b"..".to_vec(), b"hosts".to_vec()]);
+        assert_eq!(fs.stat("/etc/hosts/x").err(), Some(VfsError::NotDirectory));
+    }
+
+    #[test]
+    fn test_unlink_frees_blocks_and_inodes() {
+        let mut fs = Ext4FileSystem::new();
+        fs.mount(None).unwrap();
+        let before = free_counts(&fs);
+
+        let permissions = FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE;
+        fs.mkdir("/logs", permissions).unwrap();
+        let inode_num = fs.create("/logs/boot", FileType::Regular, permissions).unwrap();
+        assert_eq!(fs.write(inode_num, 0, &vec![7u8; 40 * 1024]), Ok(40 * 1024));
+        let (free_blocks, free_inodes) = free_counts(&fs);
+        // 40 data blocks, an indirect block and the directory's block
+        assert_eq!(free_blocks, before.0 - 42);
+        assert_eq!(free_inodes, before.1 - 2);
+
+        assert_eq!(fs.rmdir("/logs"), Err(VfsError::DirectoryNotEmpty));
+        assert_eq!(fs.unlink("/logs"), Err(VfsError::IsDirectory));
+        fs.unlink("/logs/boot").unwrap();
+        assert_eq!(fs.stat("/logs/boot").err(), Some(VfsError::NotFound));
+        fs.rmdir("/logs").unwrap();
+        assert_eq!(free_counts(&fs), before);
+        let root_links = fs.read_inode(EXT4_ROOT_INODE).unwrap().links_count;
+        assert_eq!(root_links, 2);
+        assert_eq!(fs.readdir("/").unwrap().len(), 2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_directory_grows_past_one_block() {
+        let mut fs = Ext4FileSystem::new();
+        fs.mount(None).unwrap();
+
+        let names: Vec<String> = (0..100).map(|i| alloc::format!("file-{i:03}")).collect();
+        for name in &names {
+            fs.create(&alloc::format!("/{name}"), FileType::Regular, FilePermissions::OWNER_READ).unwrap();
+        }
+        assert!(fs.stat("/").unwrap().size > 1024);
+        assert_eq!(fs.readdir("/").unwrap().len(), names.len() + 2);
+        assert_eq!(fs.create("/file-042", FileType::Regular, FilePermissions::OWNER_READ), Err(VfsError::AlreadyExists));
+
+        // Removing entries leaves room that is used again
+        fs.unlink("/file-000").unwrap();
+        fs.unlink("/file-050").unwrap();
+        let size = fs.stat("/").unwrap().size;
+        fs.create("/again", FileType::Regular, FilePermissions::OWNER_READ).unwrap();
+        assert_eq!(fs.stat("/").unwrap().size, size);
+        assert!(fs.stat("/file-050").is_err());
+        assert!(fs.stat("/file-099").is_ok());
+    }
+
+    #[test]
+    fn test_parse_superblock_checks_geometry() {
+        let mut disk = RamDisk::new(SECTOR_SIZE, 2048);
+        format(&mut disk, 1024).unwrap();
+        let mut raw = vec![0u8; EXT4_SUPERBLOCK_SIZE];
+        block::read_at(&mut disk, EXT4_SUPERBLOCK_OFFSET, &mut raw).unwrap();
+
+        let parsed = parse_superblock(&raw).unwrap();
+        assert_eq!(parsed.block_size, 1024);
+        assert!(!parsed.read_only);
+        assert_eq!(parse_superblock(&raw[..512]).err(), Some(VfsError::IoError));
+
+        // No blocks past the first data block leaves no groups to load
+        let blocks_count = read_u32(&raw, 4);
+        write_u32(&mut raw, 20, blocks_count);
+        assert_eq!(parse_superblock(&raw).err(), Some(VfsError::IoError));
+    }
+
+    #[test]
+    fn test_parse_short_inode() {
+        let mut raw = [0xFFu8; 128];
+        write_u16(&mut raw, 0, EXT4_S_IFDIR | 0o755);
+        let inode = parse_inode(&raw);
+        assert_eq!(Ext4FileSystem::inode_mode_to_file_type(inode.mode), FileType::Directory);
+        let extra_isize = inode.extra_isize;
+        assert_eq!(extra_isize, 0);
+    }
+}
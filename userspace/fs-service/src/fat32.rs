@@ -0,0 +1,1304 @@
+//! FAT32 file system
+//!
+//! For the boot (EFI system) partition and for removable media shared with
+//! other operating systems. Long file names are read and written, and every
+//! new file also gets the unique 8.3 short name other implementations
+//! expect. FAT has no inodes: a file's inode number is the device offset of
+//! its directory entry, and the root directory, which has no entry, is
+//! inode 1.
+
+use kosh_types::{
+    InodeNumber, FileOffset, FileType, FilePermissions,
+    OpenFlags, FileMetadata, VfsError, DirectoryEntry
+};
+use crate::block::{self, BlockDevice};
+use crate::vfs::{now, FileSystem};
+use alloc::{vec, vec::Vec, boxed::Box, string::String};
+
+const ROOT_INODE: InodeNumber = 1;
+
+const BOOT_SIGNATURE: u16 = 0xAA55;
+const DIR_ENTRY_SIZE: usize = 32;
+/// Characters of a long name each long name entry holds
+const LFN_CHARS_PER_ENTRY: usize = 13;
+const LFN_MAX_CHARS: usize = 255;
+/// Byte offsets of those characters within the entry
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS_PER_ENTRY] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const LFN_LAST_ENTRY: u8 = 0x40;
+const LFN_SEQUENCE_MASK: u8 = 0x1F;
+
+// Directory entry attributes
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_HIDDEN: u8 = 0x02;
+const ATTR_SYSTEM: u8 = 0x04;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+/// First name byte of a deleted entry, and of the end of the directory
+const ENTRY_DELETED: u8 = 0xE5;
+const ENTRY_END: u8 = 0x00;
+/// Stands for a leading 0xE5 that is part of the name
+const ENTRY_KANJI_E5: u8 = 0x05;
+// Case of short names shown in lower case, in the reserved byte
+const CASE_LOWER_BASE: u8 = 0x08;
+const CASE_LOWER_EXT: u8 = 0x10;
+
+// FAT entries; the top four bits are reserved and kept as found
+const FAT_ENTRY_MASK: u32 = 0x0FFF_FFFF;
+const FAT_FREE: u32 = 0;
+const FAT_BAD: u32 = 0x0FFF_FFF7;
+const FAT_END_OF_CHAIN: u32 = 0x0FFF_FFFF;
+const FAT_MIN_END_OF_CHAIN: u32 = 0x0FFF_FFF8;
+const FIRST_CLUSTER: u32 = 2;
+
+// FSInfo sector
+const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FSINFO_TRAIL_SIGNATURE: u32 = 0xAA55_0000;
+const FSINFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+// What `format` lays out
+const FORMAT_RESERVED_SECTORS: u32 = 32;
+const FORMAT_FAT_COUNT: u32 = 2;
+const FORMAT_FSINFO_SECTOR: u32 = 1;
+const FORMAT_BACKUP_BOOT_SECTOR: u32 = 6;
+const FORMAT_MEDIA: u8 = 0xF8;
+/// Fewer clusters than this make a volume FAT16 to every other implementation
+const FAT32_MIN_CLUSTERS: u64 = 65525;
+const FAT32_MAX_CLUSTERS: u64 = 0x0FFF_FFF5;
+const MAX_CLUSTER_SIZE: u64 = 32 * 1024;
+
+/// FAT dates count years from here
+const FAT_EPOCH_YEAR: i64 = 1980;
+const SECONDS_PER_DAY: i64 = 86400;
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn write_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Year, month and day of a count of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    (year_of_era + era * 400 + (month <= 2) as i64, month, day)
+}
+
+/// Seconds since the epoch of a FAT date and time; 0 when no date was set
+fn fat_to_unix(date: u16, time: u16) -> u64 {
+    if date == 0 {
+        return 0;
+    }
+    let days = days_from_civil(
+        FAT_EPOCH_YEAR + (date >> 9) as i64,
+        ((date >> 5) & 0xF).clamp(1, 12) as i64,
+        (date & 0x1F).max(1) as i64,
+    );
+    let seconds = (time >> 11) as i64 * 3600 + ((time >> 5) & 0x3F) as i64 * 60 + (time & 0x1F) as i64 * 2;
+    (days * SECONDS_PER_DAY + seconds).max(0) as u64
+}
+
+/// FAT date and time of seconds since the epoch, clamped to what FAT holds
+fn unix_to_fat(seconds: u32) -> (u16, u16) {
+    let seconds = seconds as i64;
+    let (year, month, day) = civil_from_days(seconds / SECONDS_PER_DAY);
+    if year < FAT_EPOCH_YEAR {
+        return ((1 << 5) | 1, 0);
+    }
+    let year = (year - FAT_EPOCH_YEAR).min(127);
+    let time_of_day = seconds % SECONDS_PER_DAY;
+    let date = (year << 9 | month << 5 | day) as u16;
+    let time = ((time_of_day / 3600) << 11 | (time_of_day / 60 % 60) << 5 | (time_of_day % 60 / 2)) as u16;
+    (date, time)
+}
+
+/// Checksum of a short name that its long name entries carry
+fn short_name_checksum(short_name: &[u8; 11]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+/// Whether `byte` may appear in a short name as it is
+fn is_short_name_char(byte: u8) -> bool {
+    byte.is_ascii_uppercase() || byte.is_ascii_digit() || b"!#$%&'()-@^_`{}~".contains(&byte)
+}
+
+/// Whether `name` may be used for a file at all
+fn is_valid_long_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "." && name != ".."
+        && !name.ends_with(['.', ' '])
+        && name.encode_utf16().count() <= LFN_MAX_CHARS
+        && !name.chars().any(|c| c < ' ' || "\"*/:<>?\\|".contains(c))
+}
+
+/// The short name `name` already is, if it needs no long name
+fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, extension) = name.split_once('.').unwrap_or((name, ""));
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 {
+        return None;
+    }
+    if !base.bytes().chain(extension.bytes()).all(is_short_name_char) {
+        return None;
+    }
+    let mut short_name = [b' '; 11];
+    short_name[..base.len()].copy_from_slice(base.as_bytes());
+    short_name[8..8 + extension.len()].copy_from_slice(extension.as_bytes());
+    Some(short_name)
+}
+
+/// The short name `name` is shortened to, before a numeric tail is added
+///
+/// Letters are upper-cased, spaces and extra dots dropped, and anything
+/// else a short name cannot hold becomes '_'.
+fn short_name_basis(name: &str) -> (Vec<u8>, Vec<u8>) {
+    let convert = |part: &str, limit: usize| -> Vec<u8> {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| {
+                let byte = if c.is_ascii() { c.to_ascii_uppercase() as u8 } else { b'_' };
+                if is_short_name_char(byte) { byte } else { b'_' }
+            })
+            .take(limit)
+            .collect()
+    };
+    let trimmed = name.trim_start_matches('.');
+    match trimmed.rsplit_once('.') {
+        Some((base, extension)) => (convert(base, 8), convert(extension, 3)),
+        None => (convert(trimmed, 8), Vec::new()),
+    }
+}
+
+/// `basis` with the numeric tail `~number`
+fn numbered_short_name((base, extension): &(Vec<u8>, Vec<u8>), number: u32) -> [u8; 11] {
+    let tail = alloc::format!("~{number}");
+    let kept = base.len().min(8 - tail.len());
+    let mut short_name = [b' '; 11];
+    short_name[..kept].copy_from_slice(&base[..kept]);
+    short_name[kept..kept + tail.len()].copy_from_slice(tail.as_bytes());
+    short_name[8..8 + extension.len()].copy_from_slice(extension);
+    short_name
+}
+
+/// Long name entries for `name`, in the order they precede the short entry
+fn long_name_entries(name: &str, short_name: &[u8; 11]) -> Vec<[u8; DIR_ENTRY_SIZE]> {
+    let mut units: Vec<u16> = name.encode_utf16().collect();
+    let count = units.len().div_ceil(LFN_CHARS_PER_ENTRY);
+    // A terminator if there is room for it, then padding
+    if units.len() < count * LFN_CHARS_PER_ENTRY {
+        units.push(0x0000);
+    }
+    units.resize(count * LFN_CHARS_PER_ENTRY, 0xFFFF);
+
+    let checksum = short_name_checksum(short_name);
+    (1..=count).rev()
+        .map(|sequence| {
+            let mut raw = [0u8; DIR_ENTRY_SIZE];
+            raw[0] = sequence as u8 | if sequence == count { LFN_LAST_ENTRY } else { 0 };
+            raw[11] = ATTR_LONG_NAME;
+            raw[13] = checksum;
+            let part = &units[(sequence - 1) * LFN_CHARS_PER_ENTRY..sequence * LFN_CHARS_PER_ENTRY];
+            for (&offset, &unit) in LFN_CHAR_OFFSETS.iter().zip(part) {
+                write_u16(&mut raw, offset, unit);
+            }
+            raw
+        })
+        .collect()
+}
+
+/// How a short name is shown when it has no long name
+fn short_display_name(raw: &[u8]) -> String {
+    let show = |bytes: &[u8], lower: bool| -> String {
+        bytes.iter()
+            .map(|&byte| match byte {
+                byte if byte.is_ascii() && lower => byte.to_ascii_lowercase() as char,
+                byte if byte.is_ascii() => byte as char,
+                _ => '_',
+            })
+            .collect::<String>()
+            .trim_end_matches(' ')
+            .into()
+    };
+    let mut base = [0u8; 8];
+    base.copy_from_slice(&raw[..8]);
+    if base[0] == ENTRY_KANJI_E5 {
+        base[0] = ENTRY_DELETED;
+    }
+    let mut name = show(&base, raw[12] & CASE_LOWER_BASE != 0);
+    let extension = show(&raw[8..11], raw[12] & CASE_LOWER_EXT != 0);
+    if !extension.is_empty() {
+        name.push('.');
+        name.push_str(&extension);
+    }
+    name
+}
+
+/// Geometry of a mounted volume
+#[derive(Debug, Clone, Copy)]
+struct Volume {
+    bytes_per_sector: u64,
+    sectors_per_cluster: u64,
+    /// Byte offset and length of the first FAT
+    fat_offset: u64,
+    fat_size: u64,
+    fat_count: u32,
+    /// FAT read from; writes go to every FAT unless mirroring is off
+    active_fat: u32,
+    mirrored: bool,
+    root_cluster: u32,
+    /// Byte offset of cluster 2
+    data_offset: u64,
+    cluster_count: u32,
+    fs_info_sector: Option<u64>,
+    /// Unknown until counted
+    free_count: Option<u32>,
+    /// Where the search for a free cluster starts
+    next_free: u32,
+}
+
+impl Volume {
+    fn cluster_size(&self) -> u64 {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_offset + (cluster - FIRST_CLUSTER) as u64 * self.cluster_size()
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        (FIRST_CLUSTER..FIRST_CLUSTER + self.cluster_count).contains(&cluster)
+    }
+}
+
+/// A file or directory as recorded in its parent
+#[derive(Debug, Clone)]
+struct Entry {
+    name: String,
+    attributes: u8,
+    /// 0 for empty files, and in ".." entries pointing at the root
+    first_cluster: u32,
+    size: u32,
+    created: u64,
+    modified: u64,
+    accessed: u64,
+    /// Device offset of the short entry, also the inode number
+    offset: InodeNumber,
+    /// Offsets of the long name entries before it
+    long_name_slots: Vec<u64>,
+}
+
+impl Entry {
+    fn parse(raw: &[u8], offset: u64, name: String) -> Self {
+        Self {
+            name,
+            attributes: raw[11],
+            first_cluster: (read_u16(raw, 20) as u32) << 16 | read_u16(raw, 26) as u32,
+            size: read_u32(raw, 28),
+            created: fat_to_unix(read_u16(raw, 16), read_u16(raw, 14)),
+            modified: fat_to_unix(read_u16(raw, 24), read_u16(raw, 22)),
+            accessed: fat_to_unix(read_u16(raw, 18), 0),
+            offset,
+            long_name_slots: Vec::new(),
+        }
+    }
+
+    fn is_directory(&self) -> bool {
+        self.attributes & ATTR_DIRECTORY != 0
+    }
+
+    fn is_dot(&self) -> bool {
+        self.name == "." || self.name == ".."
+    }
+}
+
+/// Write an empty FAT32 file system to `device`
+///
+/// Cluster sizes follow the usual table for the volume size. Devices too
+/// small for the 65525 clusters that make a volume FAT32 are refused.
+pub fn format(device: &mut dyn BlockDevice) -> Result<(), VfsError> {
+    let bytes_per_sector = device.block_size() as u64;
+    if !bytes_per_sector.is_power_of_two() || !(512..=4096).contains(&bytes_per_sector) {
+        return Err(VfsError::NotSupported);
+    }
+    let total_sectors = device.block_count().min(u32::MAX as u64);
+    let volume_size = total_sectors * bytes_per_sector;
+    let cluster_size = match volume_size >> 20 {
+        0..=260 => 512,
+        261..=8192 => 4096,
+        8193..=16384 => 8192,
+        16385..=32768 => 16384,
+        _ => MAX_CLUSTER_SIZE,
+    };
+    let sectors_per_cluster = (cluster_size / bytes_per_sector).max(1);
+
+    let reserved = FORMAT_RESERVED_SECTORS as u64;
+    let fat_count = FORMAT_FAT_COUNT as u64;
+    let mut fat_sectors = 1;
+    let cluster_count = loop {
+        let data_sectors = total_sectors.checked_sub(reserved + fat_count * fat_sectors).ok_or(VfsError::NoSpace)?;
+        let clusters = data_sectors / sectors_per_cluster;
+        let needed = ((clusters + FIRST_CLUSTER as u64) * 4).div_ceil(bytes_per_sector);
+        if needed <= fat_sectors {
+            break clusters;
+        }
+        fat_sectors = needed;
+    };
+    if cluster_count < FAT32_MIN_CLUSTERS {
+        return Err(VfsError::NoSpace);
+    }
+    let cluster_count = cluster_count.min(FAT32_MAX_CLUSTERS);
+
+    let mut sector = vec![0u8; bytes_per_sector as usize];
+    let write_sector = |device: &mut dyn BlockDevice, index: u64, data: &[u8]| {
+        block::write_at(device, index * bytes_per_sector, data)
+    };
+
+    sector[..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+    sector[3..11].copy_from_slice(b"KOSH    ");
+    write_u16(&mut sector, 0x0B, bytes_per_sector as u16);
+    sector[0x0D] = sectors_per_cluster as u8;
+    write_u16(&mut sector, 0x0E, reserved as u16);
+    sector[0x10] = fat_count as u8;
+    sector[0x15] = FORMAT_MEDIA;
+    write_u16(&mut sector, 0x18, 63); // Sectors per track
+    write_u16(&mut sector, 0x1A, 255); // Heads
+    write_u32(&mut sector, 0x20, total_sectors as u32);
+    write_u32(&mut sector, 0x24, fat_sectors as u32);
+    write_u32(&mut sector, 0x2C, FIRST_CLUSTER);
+    write_u16(&mut sector, 0x30, FORMAT_FSINFO_SECTOR as u16);
+    write_u16(&mut sector, 0x32, FORMAT_BACKUP_BOOT_SECTOR as u16);
+    sector[0x40] = 0x80; // Drive number
+    sector[0x42] = 0x29; // Extended boot signature
+    write_u32(&mut sector, 0x43, now());
+    sector[0x47..0x52].copy_from_slice(b"NO NAME    ");
+    sector[0x52..0x5A].copy_from_slice(b"FAT32   ");
+    write_u16(&mut sector, 510, BOOT_SIGNATURE);
+    write_sector(device, 0, &sector)?;
+    write_sector(device, FORMAT_BACKUP_BOOT_SECTOR as u64, &sector)?;
+
+    sector.fill(0);
+    write_u32(&mut sector, 0, FSINFO_LEAD_SIGNATURE);
+    write_u32(&mut sector, 484, FSINFO_STRUCT_SIGNATURE);
+    write_u32(&mut sector, 488, cluster_count as u32 - 1); // The root directory has one
+    write_u32(&mut sector, 492, FIRST_CLUSTER + 1);
+    write_u32(&mut sector, 508, FSINFO_TRAIL_SIGNATURE);
+    write_sector(device, FORMAT_FSINFO_SECTOR as u64, &sector)?;
+    write_sector(device, (FORMAT_BACKUP_BOOT_SECTOR + FORMAT_FSINFO_SECTOR) as u64, &sector)?;
+
+    // Entries 0 and 1 are reserved; the root directory's cluster ends its chain
+    for fat in 0..fat_count {
+        let start = reserved + fat * fat_sectors;
+        sector.fill(0);
+        for index in 1..fat_sectors {
+            write_sector(device, start + index, &sector)?;
+        }
+        write_u32(&mut sector, 0, 0x0FFF_FF00 | FORMAT_MEDIA as u32);
+        write_u32(&mut sector, 4, FAT_END_OF_CHAIN);
+        write_u32(&mut sector, 8, FAT_END_OF_CHAIN);
+        write_sector(device, start, &sector)?;
+    }
+
+    let data_start = reserved + fat_count * fat_sectors;
+    sector.fill(0);
+    for index in 0..sectors_per_cluster {
+        write_sector(device, data_start + index, &sector)?;
+    }
+
+    device.flush()
+}
+
+/// FAT32 file system implementation
+pub struct Fat32FileSystem {
+    device: Option<Box<dyn BlockDevice>>,
+    volume: Option<Volume>,
+    device_id: Option<u32>,
+}
+
+impl Fat32FileSystem {
+    /// Needs a device before it can be mounted; see `with_device`
+    pub fn new() -> Self {
+        Self {
+            device: None,
+            volume: None,
+            device_id: None,
+        }
+    }
+
+    /// File system on the FAT32 volume on `device`
+    pub fn with_device(device: Box<dyn BlockDevice>) -> Self {
+        Self {
+            device: Some(device),
+            ..Self::new()
+        }
+    }
+
+    fn device(&mut self) -> Result<&mut (dyn BlockDevice + 'static), VfsError> {
+        self.device.as_deref_mut().ok_or(VfsError::IoError)
+    }
+
+    fn volume(&self) -> Result<Volume, VfsError> {
+        self.volume.ok_or(VfsError::NotMounted)
+    }
+
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result<(), VfsError> {
+        block::read_at(self.device()?, offset, buffer)
+    }
+
+    fn write_at(&mut self, offset: u64, buffer: &[u8]) -> Result<(), VfsError> {
+        block::write_at(self.device()?, offset, buffer)
+    }
+
+    /// Read the boot sector and FSInfo
+    fn read_volume(&mut self) -> Result<Volume, VfsError> {
+        let mut boot = [0u8; 512];
+        self.read_at(0, &mut boot)?;
+        if read_u16(&boot, 510) != BOOT_SIGNATURE {
+            return Err(VfsError::IoError);
+        }
+
+        let bytes_per_sector = read_u16(&boot, 0x0B) as u64;
+        let sectors_per_cluster = boot[0x0D] as u64;
+        let reserved = read_u16(&boot, 0x0E) as u64;
+        let fat_count = boot[0x10] as u32;
+        if !bytes_per_sector.is_power_of_two() || !(512..=4096).contains(&bytes_per_sector)
+            || !sectors_per_cluster.is_power_of_two() || reserved == 0 || fat_count == 0
+        {
+            return Err(VfsError::IoError);
+        }
+
+        // FAT12 and FAT16 keep a fixed root directory and 16-bit FAT sizes
+        let fat_sectors = read_u32(&boot, 0x24) as u64;
+        if read_u16(&boot, 0x11) != 0 || read_u16(&boot, 0x16) != 0 || fat_sectors == 0 {
+            return Err(VfsError::NotSupported);
+        }
+
+        let total_sectors = match read_u16(&boot, 0x13) {
+            0 => read_u32(&boot, 0x20) as u64,
+            sectors => sectors as u64,
+        };
+        let data_sector = reserved + fat_count as u64 * fat_sectors;
+        let cluster_count = total_sectors.checked_sub(data_sector).ok_or(VfsError::IoError)? / sectors_per_cluster;
+        if cluster_count == 0 || (cluster_count + FIRST_CLUSTER as u64) * 4 > fat_sectors * bytes_per_sector {
+            return Err(VfsError::IoError);
+        }
+
+        let extended_flags = read_u16(&boot, 0x28);
+        let mirrored = extended_flags & 0x80 == 0;
+        let active_fat = if mirrored { 0 } else { (extended_flags & 0xF) as u32 };
+        if active_fat >= fat_count {
+            return Err(VfsError::IoError);
+        }
+
+        let mut volume = Volume {
+            bytes_per_sector,
+            sectors_per_cluster,
+            fat_offset: reserved * bytes_per_sector,
+            fat_size: fat_sectors * bytes_per_sector,
+            fat_count,
+            active_fat,
+            mirrored,
+            root_cluster: read_u32(&boot, 0x2C),
+            data_offset: data_sector * bytes_per_sector,
+            cluster_count: cluster_count.min(FAT32_MAX_CLUSTERS) as u32,
+            fs_info_sector: None,
+            free_count: None,
+            next_free: FIRST_CLUSTER,
+        };
+        if !volume.is_valid_cluster(volume.root_cluster) {
+            return Err(VfsError::IoError);
+        }
+
+        // The free cluster hints are only trusted when they are in range
+        let fs_info_sector = read_u16(&boot, 0x30) as u64;
+        if fs_info_sector != 0 && fs_info_sector < reserved {
+            let mut info = [0u8; 512];
+            self.read_at(fs_info_sector * bytes_per_sector, &mut info)?;
+            if read_u32(&info, 0) == FSINFO_LEAD_SIGNATURE && read_u32(&info, 484) == FSINFO_STRUCT_SIGNATURE {
+                volume.fs_info_sector = Some(fs_info_sector);
+                volume.free_count = Some(read_u32(&info, 488)).filter(|&free| free <= volume.cluster_count);
+                if volume.is_valid_cluster(read_u32(&info, 492)) {
+                    volume.next_free = read_u32(&info, 492);
+                }
+            }
+        }
+        Ok(volume)
+    }
+
+    /// Store the free cluster hints in FSInfo
+    fn write_fs_info(&mut self) -> Result<(), VfsError> {
+        let volume = self.volume()?;
+        let Some(sector) = volume.fs_info_sector else { return Ok(()) };
+        let offset = sector * volume.bytes_per_sector;
+        self.write_at(offset + 488, &volume.free_count.unwrap_or(FSINFO_UNKNOWN).to_le_bytes())?;
+        self.write_at(offset + 492, &volume.next_free.to_le_bytes())
+    }
+
+    fn read_fat(&mut self, cluster: u32) -> Result<u32, VfsError> {
+        let volume = self.volume()?;
+        let offset = volume.fat_offset + volume.active_fat as u64 * volume.fat_size + cluster as u64 * 4;
+        let mut entry = [0u8; 4];
+        self.read_at(offset, &mut entry)?;
+        Ok(u32::from_le_bytes(entry) & FAT_ENTRY_MASK)
+    }
+
+    fn write_fat(&mut self, cluster: u32, value: u32) -> Result<(), VfsError> {
+        let volume = self.volume()?;
+        let fats = if volume.mirrored { 0..volume.fat_count } else { volume.active_fat..volume.active_fat + 1 };
+        for fat in fats {
+            let offset = volume.fat_offset + fat as u64 * volume.fat_size + cluster as u64 * 4;
+            let mut entry = [0u8; 4];
+            self.read_at(offset, &mut entry)?;
+            let entry = u32::from_le_bytes(entry) & !FAT_ENTRY_MASK | value & FAT_ENTRY_MASK;
+            self.write_at(offset, &entry.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// The clusters of the chain starting at `first`, in order
+    fn chain(&mut self, first: u32) -> Result<Vec<u32>, VfsError> {
+        let volume = self.volume()?;
+        let mut clusters = Vec::new();
+        let mut cluster = first;
+        while cluster != FAT_FREE {
+            // A chain longer than the volume loops back on itself
+            if !volume.is_valid_cluster(cluster) || clusters.len() >= volume.cluster_count as usize {
+                return Err(VfsError::IoError);
+            }
+            clusters.push(cluster);
+            cluster = match self.read_fat(cluster)? {
+                next if next >= FAT_MIN_END_OF_CHAIN => FAT_FREE,
+                FAT_FREE | FAT_BAD => return Err(VfsError::IoError),
+                next => next,
+            };
+        }
+        Ok(clusters)
+    }
+
+    /// Allocate a zeroed cluster, linked after `previous` if given
+    fn allocate_cluster(&mut self, previous: Option<u32>) -> Result<u32, VfsError> {
+        let volume = self.volume()?;
+        if volume.free_count == Some(0) {
+            return Err(VfsError::NoSpace);
+        }
+
+        let start = volume.next_free.clamp(FIRST_CLUSTER, FIRST_CLUSTER + volume.cluster_count - 1) - FIRST_CLUSTER;
+        for step in 0..volume.cluster_count {
+            let cluster = FIRST_CLUSTER + (start + step) % volume.cluster_count;
+            if self.read_fat(cluster)? != FAT_FREE {
+                continue;
+            }
+
+            self.write_fat(cluster, FAT_END_OF_CHAIN)?;
+            if let Some(previous) = previous {
+                self.write_fat(previous, cluster)?;
+            }
+            self.write_at(volume.cluster_offset(cluster), &vec![0u8; volume.cluster_size() as usize])?;
+
+            let volume = self.volume.as_mut().ok_or(VfsError::NotMounted)?;
+            volume.free_count = volume.free_count.map(|free| free - 1);
+            volume.next_free = cluster + 1;
+            return Ok(cluster);
+        }
+        Err(VfsError::NoSpace)
+    }
+
+    fn free_chain(&mut self, first: u32) -> Result<(), VfsError> {
+        let clusters = self.chain(first)?;
+        for &cluster in &clusters {
+            self.write_fat(cluster, FAT_FREE)?;
+        }
+        let volume = self.volume.as_mut().ok_or(VfsError::NotMounted)?;
+        volume.free_count = volume.free_count.map(|free| free + clusters.len() as u32);
+        Ok(())
+    }
+
+    fn root_entry(&self) -> Result<Entry, VfsError> {
+        Ok(Entry {
+            name: String::new(),
+            attributes: ATTR_DIRECTORY,
+            first_cluster: self.volume()?.root_cluster,
+            size: 0,
+            created: 0,
+            modified: 0,
+            accessed: 0,
+            offset: ROOT_INODE,
+            long_name_slots: Vec::new(),
+        })
+    }
+
+    /// First cluster of a directory; ".." entries name the root as cluster 0
+    fn directory_cluster(&self, directory: &Entry) -> Result<u32, VfsError> {
+        match directory.first_cluster {
+            0 => Ok(self.volume()?.root_cluster),
+            cluster => Ok(cluster),
+        }
+    }
+
+    /// Every entry slot of a directory, with its device offset
+    fn read_slots(&mut self, directory: &Entry) -> Result<Vec<(u64, [u8; DIR_ENTRY_SIZE])>, VfsError> {
+        let volume = self.volume()?;
+        let first = self.directory_cluster(directory)?;
+        let mut sector = vec![0u8; volume.bytes_per_sector as usize];
+        let mut slots = Vec::new();
+        for cluster in self.chain(first)? {
+            for index in 0..volume.sectors_per_cluster {
+                let offset = volume.cluster_offset(cluster) + index * volume.bytes_per_sector;
+                self.read_at(offset, &mut sector)?;
+                for (position, raw) in sector.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+                    let mut slot = [0u8; DIR_ENTRY_SIZE];
+                    slot.copy_from_slice(raw);
+                    slots.push((offset + (position * DIR_ENTRY_SIZE) as u64, slot));
+                }
+            }
+        }
+        Ok(slots)
+    }
+
+    /// The files and directories in `directory`, long names joined up
+    fn list(&mut self, directory: &Entry) -> Result<Vec<Entry>, VfsError> {
+        let mut entries = Vec::new();
+        // Long name parts seen since the last short entry, last part first
+        let mut long_name: Vec<[u16; LFN_CHARS_PER_ENTRY]> = Vec::new();
+        let mut long_name_slots = Vec::new();
+        let mut checksum = 0;
+        let mut next_sequence = 0;
+
+        for (offset, raw) in self.read_slots(directory)? {
+            match raw[0] {
+                ENTRY_END => break,
+                ENTRY_DELETED => {
+                    long_name.clear();
+                    long_name_slots.clear();
+                    continue;
+                }
+                _ => {}
+            }
+
+            if raw[11] & 0x3F == ATTR_LONG_NAME {
+                // Parts count down to 1 from the one marked last; anything
+                // out of order is an orphan left by another implementation
+                let sequence = raw[0] & LFN_SEQUENCE_MASK;
+                if raw[0] & LFN_LAST_ENTRY != 0 {
+                    long_name.clear();
+                    long_name_slots.clear();
+                    checksum = raw[13];
+                } else if sequence != next_sequence || raw[13] != checksum {
+                    long_name.clear();
+                    long_name_slots.clear();
+                    continue;
+                }
+                if sequence == 0 {
+                    continue;
+                }
+                let mut part = [0u16; LFN_CHARS_PER_ENTRY];
+                for (unit, &position) in part.iter_mut().zip(&LFN_CHAR_OFFSETS) {
+                    *unit = read_u16(&raw, position);
+                }
+                long_name.push(part);
+                long_name_slots.push(offset);
+                next_sequence = sequence - 1;
+                continue;
+            }
+
+            if raw[11] & ATTR_VOLUME_ID != 0 {
+                long_name.clear();
+                long_name_slots.clear();
+                continue;
+            }
+
+            let mut short_name = [0u8; 11];
+            short_name.copy_from_slice(&raw[..11]);
+            let complete = !long_name.is_empty() && next_sequence == 0 && short_name_checksum(&short_name) == checksum;
+            let name = if complete {
+                let units: Vec<u16> = long_name.iter().rev()
+                    .flat_map(|part| part.iter().copied())
+                    .take_while(|&unit| unit != 0x0000)
+                    .collect();
+                String::from_utf16_lossy(&units)
+            } else {
+                short_display_name(&raw)
+            };
+
+            let mut entry = Entry::parse(&raw, offset, name);
+            if complete {
+                entry.long_name_slots = core::mem::take(&mut long_name_slots);
+            }
+            entries.push(entry);
+            long_name.clear();
+            long_name_slots.clear();
+        }
+        Ok(entries)
+    }
+
+    fn lookup(&mut self, directory: &Entry, name: &str) -> Result<Option<Entry>, VfsError> {
+        Ok(self.list(directory)?.into_iter().find(|entry| entry.name.eq_ignore_ascii_case(name)))
+    }
+
+    /// The entry `path` leads to
+    fn resolve(&mut self, path: &str) -> Result<Entry, VfsError> {
+        let mut current = self.root_entry()?;
+        for component in path.split('/').filter(|component| !component.is_empty() && *component != ".") {
+            if !current.is_directory() {
+                return Err(VfsError::NotDirectory);
+            }
+            current = self.lookup(&current, component)?.ok_or(VfsError::NotFound)?;
+            if current.is_directory() && current.first_cluster == 0 {
+                current = self.root_entry()?;
+            }
+        }
+        Ok(current)
+    }
+
+    /// The directory holding `path` and the final name within it
+    fn resolve_parent<'a>(&mut self, path: &'a str) -> Result<(Entry, &'a str), VfsError> {
+        let path = path.trim_end_matches('/');
+        let (parent, name) = path.rsplit_once('/').ok_or(VfsError::InvalidPath)?;
+        let parent = self.resolve(parent)?;
+        if !parent.is_directory() {
+            return Err(VfsError::NotDirectory);
+        }
+        Ok((parent, name))
+    }
+
+    /// The entry at inode number `inode`
+    fn entry_at(&mut self, inode: InodeNumber) -> Result<Entry, VfsError> {
+        if inode == ROOT_INODE {
+            return self.root_entry();
+        }
+        let mut raw = [0u8; DIR_ENTRY_SIZE];
+        self.read_at(inode, &mut raw)?;
+        if matches!(raw[0], ENTRY_END | ENTRY_DELETED) || raw[11] & 0x3F == ATTR_LONG_NAME {
+            return Err(VfsError::NotFound);
+        }
+        Ok(Entry::parse(&raw, inode, String::new()))
+    }
+
+    /// Write the size, first cluster, attributes and times of `entry` back
+    fn update_entry(&mut self, entry: &Entry) -> Result<(), VfsError> {
+        if entry.offset == ROOT_INODE {
+            return Ok(());
+        }
+        let mut raw = [0u8; DIR_ENTRY_SIZE];
+        self.read_at(entry.offset, &mut raw)?;
+        raw[11] = entry.attributes;
+        write_u16(&mut raw, 20, (entry.first_cluster >> 16) as u16);
+        write_u16(&mut raw, 26, entry.first_cluster as u16);
+        write_u32(&mut raw, 28, entry.size);
+        let (date, time) = unix_to_fat(entry.modified as u32);
+        write_u16(&mut raw, 22, time);
+        write_u16(&mut raw, 24, date);
+        write_u16(&mut raw, 18, unix_to_fat(entry.accessed as u32).0);
+        self.write_at(entry.offset, &raw)
+    }
+
+    /// Add an entry for `name` to `directory`, with a long name if needed
+    fn add_entry(&mut self, directory: &Entry, name: &str, attributes: u8, first_cluster: u32) -> Result<Entry, VfsError> {
+        let existing = self.read_slots(directory)?;
+        let short_name = match exact_short_name(name) {
+            Some(short_name) => short_name,
+            None => {
+                let basis = short_name_basis(name);
+                let basis = if basis.0.is_empty() { (b"_".to_vec(), basis.1) } else { basis };
+                (1..1_000_000)
+                    .map(|number| numbered_short_name(&basis, number))
+                    .find(|candidate| !existing.iter().any(|(_, raw)| raw[0] != ENTRY_DELETED && raw[..11] == candidate[..]))
+                    .ok_or(VfsError::AlreadyExists)?
+            }
+        };
+        let mut slots = if exact_short_name(name).is_some() { Vec::new() } else { long_name_entries(name, &short_name) };
+
+        let time = now();
+        let (date, clock) = unix_to_fat(time);
+        let mut raw = [0u8; DIR_ENTRY_SIZE];
+        raw[..11].copy_from_slice(&short_name);
+        raw[11] = attributes;
+        write_u16(&mut raw, 14, clock);
+        write_u16(&mut raw, 16, date);
+        write_u16(&mut raw, 18, date);
+        write_u16(&mut raw, 20, (first_cluster >> 16) as u16);
+        write_u16(&mut raw, 22, clock);
+        write_u16(&mut raw, 24, date);
+        write_u16(&mut raw, 26, first_cluster as u16);
+        slots.push(raw);
+
+        // The first run of free slots long enough, growing the directory if
+        // there is none
+        let mut offsets: Vec<u64> = existing.iter().map(|&(offset, _)| offset).collect();
+        let mut free: Vec<bool> = existing.iter().map(|(_, raw)| matches!(raw[0], ENTRY_END | ENTRY_DELETED)).collect();
+        if let Some(end) = existing.iter().position(|(_, raw)| raw[0] == ENTRY_END) {
+            free[end..].fill(true);
+        }
+        let find_run = |free: &[bool]| {
+            let mut run = 0;
+            free.iter().position(|&is_free| {
+                run = if is_free { run + 1 } else { 0 };
+                run == slots.len()
+            }).map(|last| last + 1 - slots.len())
+        };
+        let start = match find_run(&free) {
+            Some(start) => start,
+            None => {
+                let volume = self.volume()?;
+                let mut chain = self.chain(self.directory_cluster(directory)?)?;
+                loop {
+                    let cluster = self.allocate_cluster(chain.last().copied())?;
+                    chain.push(cluster);
+                    for index in 0..volume.cluster_size() / DIR_ENTRY_SIZE as u64 {
+                        offsets.push(volume.cluster_offset(cluster) + index * DIR_ENTRY_SIZE as u64);
+                        free.push(true);
+                    }
+                    if let Some(start) = find_run(&free) {
+                        break start;
+                    }
+                }
+            }
+        };
+
+        for (raw, &offset) in slots.iter().zip(&offsets[start..]) {
+            self.write_at(offset, raw)?;
+        }
+        let offset = offsets[start + slots.len() - 1];
+        let mut entry = Entry::parse(&raw, offset, name.into());
+        entry.long_name_slots = offsets[start..start + slots.len() - 1].to_vec();
+        Ok(entry)
+    }
+
+    /// Mark `entry` and its long name entries deleted
+    fn remove_entry(&mut self, entry: &Entry) -> Result<(), VfsError> {
+        for &offset in entry.long_name_slots.iter().chain(core::iter::once(&entry.offset)) {
+            self.write_at(offset, &[ENTRY_DELETED])?;
+        }
+        Ok(())
+    }
+
+    /// Store `data` at `offset` of the file, growing its chain as needed
+    ///
+    /// Returns how much fit; the caller writes the entry back even when
+    /// the volume filled up partway.
+    fn write_data(&mut self, entry: &mut Entry, offset: u64, data: &[u8]) -> Result<usize, VfsError> {
+        let volume = self.volume()?;
+        let cluster_size = volume.cluster_size();
+        let mut chain = self.chain(entry.first_cluster)?;
+        let needed = (offset + data.len() as u64).div_ceil(cluster_size) as usize;
+        let mut full = Ok(());
+        while chain.len() < needed {
+            match self.allocate_cluster(chain.last().copied()) {
+                Ok(cluster) => {
+                    if chain.is_empty() {
+                        entry.first_cluster = cluster;
+                    }
+                    chain.push(cluster);
+                }
+                Err(error) => {
+                    full = Err(error);
+                    break;
+                }
+            }
+        }
+
+        let end = (offset + data.len() as u64).min(chain.len() as u64 * cluster_size);
+        let mut position = offset;
+        while position < end {
+            let cluster = chain[(position / cluster_size) as usize];
+            let within = position % cluster_size;
+            let count = (end - position).min(cluster_size - within);
+            let source = (position - offset) as usize;
+            self.write_at(volume.cluster_offset(cluster) + within, &data[source..source + count as usize])?;
+            position += count;
+        }
+
+        match full {
+            Err(error) if position == offset && !data.is_empty() => Err(error),
+            _ => Ok((position - offset) as usize),
+        }
+    }
+
+    fn metadata(entry: &Entry) -> FileMetadata {
+        let mut permissions = FilePermissions::OWNER_READ | FilePermissions::GROUP_READ | FilePermissions::OTHER_READ;
+        if entry.attributes & ATTR_READ_ONLY == 0 {
+            permissions |= FilePermissions::OWNER_WRITE | FilePermissions::GROUP_WRITE | FilePermissions::OTHER_WRITE;
+        }
+        if entry.is_directory() {
+            permissions |= FilePermissions::OWNER_EXECUTE | FilePermissions::GROUP_EXECUTE | FilePermissions::OTHER_EXECUTE;
+        }
+        FileMetadata {
+            inode: entry.offset,
+            file_type: if entry.is_directory() { FileType::Directory } else { FileType::Regular },
+            permissions,
+            size: entry.size as u64,
+            uid: 0,
+            gid: 0,
+            created_time: entry.created,
+            modified_time: entry.modified,
+            accessed_time: entry.accessed,
+        }
+    }
+}
+
+impl FileSystem for Fat32FileSystem {
+    fn init(&mut self) -> Result<(), VfsError> {
+        self.volume = None;
+        Ok(())
+    }
+
+    fn mount(&mut self, device_id: Option<u32>) -> Result<(), VfsError> {
+        if self.volume.is_some() {
+            return Err(VfsError::MountPointBusy);
+        }
+        self.volume = Some(self.read_volume()?);
+        self.device_id = device_id;
+        Ok(())
+    }
+
+    fn unmount(&mut self) -> Result<(), VfsError> {
+        self.sync()?;
+        self.volume = None;
+        self.device_id = None;
+        Ok(())
+    }
+
+    fn open(&mut self, path: &str, flags: OpenFlags) -> Result<(InodeNumber, FileMetadata), VfsError> {
+        let mut entry = self.resolve(path)?;
+        if flags.contains(OpenFlags::TRUNCATE) && !entry.is_directory() && entry.first_cluster != 0 {
+            self.free_chain(entry.first_cluster)?;
+            entry.first_cluster = 0;
+            entry.size = 0;
+            entry.modified = now() as u64;
+            self.update_entry(&entry)?;
+        }
+        Ok((entry.offset, Self::metadata(&entry)))
+    }
+
+    fn close(&mut self, _inode: InodeNumber) -> Result<(), VfsError> {
+        self.volume()?;
+        Ok(())
+    }
+
+    fn read(&mut self, inode: InodeNumber, offset: FileOffset, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        let entry = self.entry_at(inode)?;
+        if entry.is_directory() {
+            return Err(VfsError::IsDirectory);
+        }
+        if offset >= entry.size as u64 {
+            return Ok(0);
+        }
+
+        let volume = self.volume()?;
+        let cluster_size = volume.cluster_size();
+        let length = buffer.len().min((entry.size as u64 - offset) as usize);
+        let chain = self.chain(entry.first_cluster)?;
+        let mut done = 0;
+        while done < length {
+            let position = offset + done as u64;
+            let cluster = *chain.get((position / cluster_size) as usize).ok_or(VfsError::IoError)?;
+            let within = position % cluster_size;
+            let count = (length - done).min((cluster_size - within) as usize);
+            self.read_at(volume.cluster_offset(cluster) + within, &mut buffer[done..done + count])?;
+            done += count;
+        }
+        Ok(length)
+    }
+
+    fn write(&mut self, inode: InodeNumber, offset: FileOffset, buffer: &[u8]) -> Result<usize, VfsError> {
+        let mut entry = self.entry_at(inode)?;
+        if entry.is_directory() {
+            return Err(VfsError::IsDirectory);
+        }
+        if offset + buffer.len() as u64 > u32::MAX as u64 {
+            return Err(VfsError::NoSpace);
+        }
+
+        // Bytes between the old end and `offset` read as zeros
+        let mut result = Ok(0);
+        let mut size = entry.size as u64;
+        let zeros = vec![0u8; self.volume()?.cluster_size() as usize];
+        while size < offset && result.is_ok() {
+            let count = (offset - size).min(zeros.len() as u64) as usize;
+            result = self.write_data(&mut entry, size, &zeros[..count]);
+            if let Ok(written) = result {
+                size += written as u64;
+                if written < count {
+                    result = Err(VfsError::NoSpace);
+                }
+            }
+        }
+        if result.is_ok() {
+            result = self.write_data(&mut entry, offset, buffer);
+            if let Ok(written) = result {
+                size = size.max(offset + written as u64);
+            }
+        }
+
+        entry.size = size as u32;
+        entry.modified = now() as u64;
+        entry.accessed = entry.modified;
+        entry.attributes |= ATTR_ARCHIVE;
+        self.update_entry(&entry)?;
+        result
+    }
+
+    fn create(&mut self, path: &str, file_type: FileType, permissions: FilePermissions) -> Result<InodeNumber, VfsError> {
+        let (parent, name) = self.resolve_parent(path)?;
+        if !is_valid_long_name(name) {
+            return Err(VfsError::InvalidPath);
+        }
+        if self.lookup(&parent, name)?.is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+        let read_only = if permissions.contains(FilePermissions::OWNER_WRITE) { 0 } else { ATTR_READ_ONLY };
+
+        match file_type {
+            FileType::Regular => Ok(self.add_entry(&parent, name, ATTR_ARCHIVE | read_only, 0)?.offset),
+            FileType::Directory => {
+                let cluster = self.allocate_cluster(None)?;
+                let entry = match self.add_entry(&parent, name, ATTR_DIRECTORY | read_only, cluster) {
+                    Ok(entry) => entry,
+                    Err(error) => {
+                        self.free_chain(cluster)?;
+                        return Err(error);
+                    }
+                };
+
+                // "." and ".." open every directory but the root
+                let volume = self.volume()?;
+                let parent_cluster = if parent.offset == ROOT_INODE { 0 } else { parent.first_cluster };
+                let mut raw = [0u8; DIR_ENTRY_SIZE];
+                let mut dot_entry = |name: &[u8], cluster: u32| {
+                    raw.fill(0);
+                    raw[..11].fill(b' ');
+                    raw[..name.len()].copy_from_slice(name);
+                    raw[11] = ATTR_DIRECTORY;
+                    write_u16(&mut raw, 20, (cluster >> 16) as u16);
+                    write_u16(&mut raw, 26, cluster as u16);
+                    raw
+                };
+                let dot = dot_entry(b".", cluster);
+                let dot_dot = dot_entry(b"..", parent_cluster);
+                self.write_at(volume.cluster_offset(cluster), &dot)?;
+                self.write_at(volume.cluster_offset(cluster) + DIR_ENTRY_SIZE as u64, &dot_dot)?;
+                Ok(entry.offset)
+            }
+            _ => Err(VfsError::NotSupported),
+        }
+    }
+
+    fn unlink(&mut self, path: &str) -> Result<(), VfsError> {
+        let (parent, name) = self.resolve_parent(path)?;
+        let entry = self.lookup(&parent, name)?.ok_or(VfsError::NotFound)?;
+        if entry.is_directory() {
+            return Err(VfsError::IsDirectory);
+        }
+        self.remove_entry(&entry)?;
+        self.free_chain(entry.first_cluster)
+    }
+
+    fn stat(&mut self, path: &str) -> Result<FileMetadata, VfsError> {
+        Ok(Self::metadata(&self.resolve(path)?))
+    }
+
+    /// "." and ".." are only on disk below the root, so they are left out
+    /// everywhere
+    fn readdir(&mut self, path: &str) -> Result<Vec<DirectoryEntry>, VfsError> {
+        let directory = self.resolve(path)?;
+        if !directory.is_directory() {
+            return Err(VfsError::NotDirectory);
+        }
+
+        Ok(self.list(&directory)?.into_iter()
+            .filter(|entry| !entry.is_dot())
+            .map(|entry| {
+                let mut length = entry.name.len().min(255);
+                while !entry.name.is_char_boundary(length) {
+                    length -= 1;
+                }
+                let mut name = [0u8; 256];
+                name[..length].copy_from_slice(&entry.name.as_bytes()[..length]);
+                DirectoryEntry {
+                    name,
+                    name_len: length as u8,
+                    inode: entry.offset,
+                    file_type: if entry.is_directory() { FileType::Directory } else { FileType::Regular },
+                }
+            })
+            .collect())
+    }
+
+    fn mkdir(&mut self, path: &str, permissions: FilePermissions) -> Result<(), VfsError> {
+        self.create(path, FileType::Directory, permissions)?;
+        Ok(())
+    }
+
+    fn rmdir(&mut self, path: &str) -> Result<(), VfsError> {
+        let (parent, name) = self.resolve_parent(path)?;
+        let entry = self.lookup(&parent, name)?.ok_or(VfsError::NotFound)?;
+        if !entry.is_directory() {
+            return Err(VfsError::NotDirectory);
+        }
+        if entry.is_dot() {
+            return Err(VfsError::InvalidPath);
+        }
+        if self.list(&entry)?.iter().any(|child| !child.is_dot()) {
+            return Err(VfsError::DirectoryNotEmpty);
+        }
+        self.remove_entry(&entry)?;
+        self.free_chain(entry.first_cluster)
+    }
+
+    fn sync(&mut self) -> Result<(), VfsError> {
+        self.write_fs_info()?;
+        self.device()?.flush()
+    }
+
+    fn size(&mut self, inode: InodeNumber) -> Result<FileOffset, VfsError> {
+        Ok(self.entry_at(inode)?.size as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::RamDisk;
+    use alloc::string::ToString;
+
+    /// Smallest size that formats as FAT32 with 512-byte clusters, plus room
+    const DISK_SECTORS: u64 = 70_000;
+
+    fn mounted() -> Fat32FileSystem {
+        let mut disk = RamDisk::new(512, DISK_SECTORS);
+        format(&mut disk).unwrap();
+        let mut fs = Fat32FileSystem::with_device(Box::new(disk));
+        fs.mount(Some(1)).unwrap();
+        fs
+    }
+
+    fn names(fs: &mut Fat32FileSystem, path: &str) -> Vec<String> {
+        fs.readdir(path).unwrap().iter()
+            .map(|entry| String::from_utf8(entry.name[..entry.name_len as usize].to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_short_names() {
+        assert_eq!(exact_short_name("KERNEL.ELF"), Some(*b"KERNEL  ELF"));
+        assert_eq!(exact_short_name("kernel.elf"), None);
+        assert_eq!(exact_short_name("A.B.C"), None);
+
+        let basis = short_name_basis("Long File Name.text");
+        assert_eq!(numbered_short_name(&basis, 1), *b"LONGFI~1TEX");
+        assert_eq!(numbered_short_name(&short_name_basis(".bashrc"), 12), *b"BASHR~12   ");
+        assert_eq!(short_name_checksum(b"LONGFI~1TEX"), short_name_checksum(&numbered_short_name(&basis, 1)));
+
+        assert!(is_valid_long_name("notes for 2024.md"));
+        assert!(!is_valid_long_name("a:b"));
+        assert!(!is_valid_long_name("trailing."));
+    }
+
+    #[test]
+    fn test_fat_timestamps() {
+        // 2024-02-29 13:45:30
+        let (date, time) = unix_to_fat(1_709_214_330);
+        assert_eq!(date, (44 << 9) | (2 << 5) | 29);
+        assert_eq!(time, (13 << 11) | (45 << 5) | 15);
+        assert_eq!(fat_to_unix(date, time), 1_709_214_330);
+        assert_eq!(unix_to_fat(0), ((1 << 5) | 1, 0));
+    }
+
+    #[test]
+    fn test_format_refuses_small_devices() {
+        let mut disk = RamDisk::new(512, 8192);
+        assert_eq!(format(&mut disk), Err(VfsError::NoSpace));
+    }
+
+    #[test]
+    fn test_long_names_and_cluster_chains() {
+        let mut fs = mounted();
+        let free = fs.volume.unwrap().free_count.unwrap();
+
+        fs.mkdir("/EFI", FilePermissions::OWNER_WRITE).unwrap();
+        fs.mkdir("/EFI/Boot Loader Entries", FilePermissions::OWNER_WRITE).unwrap();
+        let path = "/EFI/Boot Loader Entries/kosh-default-configuration.conf";
+        let inode = fs.create(path, FileType::Regular, FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE).unwrap();
+
+        // Several clusters, so the data follows a chain
+        let data: Vec<u8> = (0..5000).map(|i| (i % 253) as u8).collect();
+        assert_eq!(fs.write(inode, 0, &data), Ok(data.len()));
+        assert_eq!(fs.write(inode, 6000, b"tail"), Ok(4));
+        fs.unmount().unwrap();
+        fs.mount(Some(1)).unwrap();
+
+        // Names match without regard to case
+        let (inode, metadata) = fs.open("/efi/boot loader entries/KOSH-DEFAULT-CONFIGURATION.CONF", OpenFlags::READ_ONLY).unwrap();
+        assert_eq!(metadata.size, 6004);
+        let mut buffer = vec![0u8; 6004];
+        assert_eq!(fs.read(inode, 0, &mut buffer), Ok(6004));
+        assert_eq!(&buffer[..5000], &data[..]);
+        assert!(buffer[5000..6000].iter().all(|&byte| byte == 0));
+        assert_eq!(&buffer[6000..], b"tail");
+
+        assert_eq!(names(&mut fs, "/"), ["EFI"]);
+        assert_eq!(names(&mut fs, "/EFI"), ["Boot Loader Entries"]);
+        assert_eq!(names(&mut fs, "/EFI/Boot Loader Entries"), ["kosh-default-configuration.conf"]);
+
+        // Two directory clusters and 12 data clusters
+        assert_eq!(fs.volume.unwrap().free_count, Some(free - 14));
+        assert_eq!(fs.rmdir("/EFI/Boot Loader Entries"), Err(VfsError::DirectoryNotEmpty));
+        fs.unlink(path).unwrap();
+        fs.rmdir("/EFI/Boot Loader Entries").unwrap();
+        fs.rmdir("/EFI").unwrap();
+        assert_eq!(fs.volume.unwrap().free_count, Some(free));
+        assert!(names(&mut fs, "/").is_empty());
+    }
+
+    #[test]
+    fn test_directory_grows_across_clusters() {
+        let mut fs = mounted();
+        let names_written: Vec<String> = (0..40).map(|i| alloc::format!("document number {i}.txt")).collect();
+        for name in &names_written {
+            fs.create(&alloc::format!("/{name}"), FileType::Regular, FilePermissions::OWNER_WRITE).unwrap();
+        }
+        assert_eq!(names(&mut fs, "/"), names_written);
+
+        // Each name takes three slots, so the numbered short names differ
+        let root = fs.root_entry().unwrap();
+        let short_names: Vec<[u8; 11]> = fs.read_slots(&root).unwrap().iter()
+            .filter(|(_, raw)| raw[0] != ENTRY_END && raw[11] != ATTR_LONG_NAME)
+            .map(|(_, raw)| raw[..11].try_into().unwrap())
+            .collect();
+        assert_eq!(short_names.len(), 40);
+        assert_eq!(&short_names[0], b"DOCUME~1TXT");
+        assert_eq!(&short_names[9], b"DOCUM~10TXT");
+
+        assert_eq!(fs.create("/Document Number 3.TXT", FileType::Regular, FilePermissions::OWNER_WRITE), Err(VfsError::AlreadyExists));
+        fs.unlink("/document number 3.txt").unwrap();
+        assert!(!names(&mut fs, "/").contains(&"document number 3.txt".to_string()));
+        assert_eq!(fs.stat("/document number 4.txt").unwrap().file_type, FileType::Regular);
+    }
+}
@@ -0,0 +1,34 @@
+//! Fuzz entry points for the on-disk structures ext4 reads
+//!
+//! A mounted image comes from whatever disk was attached, so its
+//! superblock and inodes are as untrusted as any other input.
+
+use crate::ext4::{parse_inode, parse_superblock, Ext4FileSystem, EXT4_EXT_INIT_MAX_LEN};
+
+/// Parse `data` as a superblock, checking the geometry of one accepted
+pub fn ext4_superblock(data: &[u8]) {
+    if let Ok(parsed) = parse_superblock(data) {
+        assert!(parsed.block_size.is_power_of_two(), "block size {} accepted", parsed.block_size);
+        assert!(parsed.inode_size >= 128 && parsed.inode_size as u32 <= parsed.block_size);
+        let superblock = parsed.superblock;
+        assert!(superblock.blocks_per_group != 0 && superblock.inodes_per_group != 0);
+        assert!(superblock.first_data_block < superblock.blocks_count);
+    }
+}
+
+/// Parse `data` as an inode and walk the extent tree root it holds
+pub fn ext4_inode(data: &[u8]) {
+    let inode = parse_inode(data);
+    Ext4FileSystem::inode_mode_to_file_type(inode.mode);
+    Ext4FileSystem::inode_size_bytes(&inode);
+
+    let root = Ext4FileSystem::extent_root(&inode);
+    if let Ok((entries, depth)) = Ext4FileSystem::extent_header(&root) {
+        if depth == 0 {
+            for index in 0..entries {
+                let (_, length, _, _) = Ext4FileSystem::parse_extent(&root, index);
+                assert!(length <= EXT4_EXT_INIT_MAX_LEN, "extent of {} blocks", length);
+            }
+        }
+    }
+}
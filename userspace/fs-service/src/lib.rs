@@ -1,13 +1,23 @@
-#![no_std]
+#![cfg_attr(not(feature = "fuzzing"), no_std)]
 
 extern crate alloc;
 
 use alloc::{vec::Vec, string::String};
-use kosh_types::{OpenFlags, FileType, FilePermissions, VfsError};
+use kosh_types::{OpenFlags, FileType, FilePermissions, MountFlags, ProcessId, VfsError};
 
 pub mod vfs;
+pub mod block;
+pub mod cache;
 pub mod ext4;
-pub use vfs::{Vfs, FileSystemType};
+pub mod fat32;
+pub mod compression;
+pub mod sysimage;
+pub mod namespace;
+pub mod procfs;
+pub mod devfs;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+pub use vfs::{Vfs, FileSystemType, CloneMethod, SeekFrom};
 
 /// File system service request types
 #[derive(Debug, Clone)]
@@ -16,12 +26,27 @@ pub enum FsRequest {
     Close { fd: kosh_types::FileDescriptor },
     Read { fd: kosh_types::FileDescriptor, size: usize },
     Write { fd: kosh_types::FileDescriptor, data: Vec<u8> },
+    /// Move the offset of `fd` as `lseek` does with `offset` and `whence`
+    Seek { fd: kosh_types::FileDescriptor, offset: i64, whence: i32 },
+    /// Another descriptor for the file `fd` is open on, sharing its offset
+    Dup { fd: kosh_types::FileDescriptor },
     Stat { path: String },
     Create { path: String, file_type: FileType, permissions: FilePermissions },
     Unlink { path: String },
     ReadDir { path: String },
     MkDir { path: String, permissions: FilePermissions },
     RmDir { path: String },
+    Clone { source: String, destination: String },
+    /// Create a symbolic link at `path` pointing to `target`
+    Symlink { target: String, path: String },
+    ReadLink { path: String },
+    /// Change the working directory relative paths from `pid` start at
+    Chdir { pid: ProcessId, path: String },
+    Getcwd { pid: ProcessId },
+    Mount { source: String, target: String, fs_type: String, flags: MountFlags },
+    Unmount { target: String },
+    /// Write cached data back to every device
+    Sync,
 }
 
 /// File system service response types
@@ -31,8 +56,11 @@ pub enum FsResponse {
     FileDescriptor(kosh_types::FileDescriptor),
     Data(Vec<u8>),
     BytesWritten(usize),
+    Offset(kosh_types::FileOffset),
     Metadata(kosh_types::FileMetadata),
     DirectoryEntries(Vec<kosh_types::DirectoryEntry>),
+    Cloned(CloneMethod),
+    Path(String),
 }
 
 /// Handle file system service requests
@@ -57,6 +85,14 @@ pub fn handle_fs_request(vfs: &mut Vfs, request: FsRequest) -> Result<FsResponse
             let bytes_written = vfs.write(fd, &data)?;
             Ok(FsResponse::BytesWritten(bytes_written))
         }
+        FsRequest::Seek { fd, offset, whence } => {
+            let position = SeekFrom::from_whence(offset, whence).ok_or(VfsError::InvalidArgument)?;
+            Ok(FsResponse::Offset(vfs.seek(fd, position)?))
+        }
+        FsRequest::Dup { fd } => {
+            let fd = vfs.dup(fd)?;
+            Ok(FsResponse::FileDescriptor(fd))
+        }
         FsRequest::Stat { path } => {
             let metadata = vfs.stat(&path)?;
             Ok(FsResponse::Metadata(metadata))
@@ -81,5 +117,53 @@ pub fn handle_fs_request(vfs: &mut Vfs, request: FsRequest) -> Result<FsResponse
             vfs.rmdir(&path)?;
             Ok(FsResponse::Success)
         }
+        FsRequest::Clone { source, destination } => {
+            let method = vfs.clone_file(&source, &destination)?;
+            Ok(FsResponse::Cloned(method))
+        }
+        FsRequest::Symlink { target, path } => {
+            vfs.symlink(&target, &path)?;
+            Ok(FsResponse::Success)
+        }
+        FsRequest::ReadLink { path } => {
+            let target = vfs.readlink(&path)?;
+            Ok(FsResponse::Path(target))
+        }
+        FsRequest::Chdir { pid, path } => {
+            vfs.chdir(pid, &path)?;
+            Ok(FsResponse::Success)
+        }
+        FsRequest::Getcwd { pid } => Ok(FsResponse::Path(vfs.getcwd(pid))),
+        FsRequest::Mount { source, target, fs_type, flags } => {
+            mount(vfs, &source, &target, &fs_type, flags)?;
+            Ok(FsResponse::Success)
+        }
+        FsRequest::Unmount { target } => {
+            vfs.unmount(&target)?;
+            Ok(FsResponse::Success)
+        }
+        FsRequest::Sync => {
+            vfs.sync()?;
+            Ok(FsResponse::Success)
+        }
     }
+}
+
+/// Carry out a mount request
+///
+/// `source` is the device number to mount, or `none` (or nothing) for file
+/// systems without a device. A bind mount takes a directory instead and
+/// ignores `fs_type`.
+pub fn mount(vfs: &mut Vfs, source: &str, target: &str, fs_type: &str, flags: MountFlags) -> Result<(), VfsError> {
+    let read_only = flags.contains(MountFlags::READ_ONLY);
+    if flags.contains(MountFlags::BIND) {
+        return vfs.bind_mount(source, target, read_only);
+    }
+
+    let fs_type = FileSystemType::from_name(fs_type).ok_or(VfsError::NotSupported)?;
+    let device_id = match source {
+        "" | "none" => None,
+        device => Some(device.parse().map_err(|_| VfsError::InvalidPath)?),
+    };
+    vfs.mount(target, fs_type, device_id, read_only)
 }
\ No newline at end of file
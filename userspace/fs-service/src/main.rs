@@ -5,10 +5,15 @@ extern crate alloc;
 
 use alloc::format;
 use alloc::vec;
+use alloc::string::String;
 use alloc::vec::Vec;
-use kosh_fs_service::{Vfs, FileSystemType};
-use kosh_types::{OpenFlags, FileType, FilePermissions};
-use kosh_service::{ServiceHandler, ServiceMessage, ServiceResponse, ServiceType, ServiceData, ServiceStatus, ServiceRunner, FileSystemRequest};
+use alloc::collections::VecDeque;
+use kosh_fs_service::{Vfs, FileSystemType, CloneMethod, SeekFrom};
+use kosh_fs_service::namespace::{normalize, Namespace, Namespaces};
+use kosh_ipc::shm::SharedRegion;
+use kosh_types::{OpenFlags, FileType, FilePermissions, MountFlags, ProcessId, VfsError};
+use kosh_service::{ServiceHandler, ServiceMessage, ServiceResponse, ServiceType, ServiceData, ServiceStatus, ServiceRunner, FileSystemRequest, IncomingRequest, KERNEL_PID};
+use kosh_sync::{Condvar, Mutex};
 
 // Global allocator setup
 use linked_list_allocator::LockedHeap;
@@ -16,73 +21,321 @@ use linked_list_allocator::LockedHeap;
 #[global_allocator]
 static ALLOCATOR: LockedHeap = LockedHeap::empty();
 
+/// Reads at least this large are returned through shared memory
+const SHARED_READ_THRESHOLD: usize = kosh_ipc::ZERO_COPY_THRESHOLD;
+
+/// Shared read buffers kept alive for clients that have not mapped them yet
+const MAX_SHARED_READS: usize = 8;
+
+/// Threads handling requests while the main thread receives them
+const WORKER_THREADS: usize = 2;
+
+/// Stack size of each worker thread
+const WORKER_STACK_SIZE: usize = 32 * 1024;
+
+/// The service state, shared by the worker threads
+///
+/// Requests are handled one at a time under the lock; receiving, decoding
+/// and answering them goes on in parallel.
+static SERVICE: Mutex<Option<FileSystemService>> = Mutex::new(None);
+
+/// Requests received and waiting for a worker
+static QUEUE: Mutex<VecDeque<IncomingRequest>> = Mutex::new(VecDeque::new());
+static QUEUED: Condvar = Condvar::new();
+
 /// File System Service Handler
 struct FileSystemService {
     vfs: Vfs,
+    shared_reads: VecDeque<SharedRegion>,
+    namespaces: Namespaces,
 }
 
+// The block cache handles in the VFS are never shared outside it, so the
+// service moves between threads as a whole; `SERVICE` serializes access
+unsafe impl Send for FileSystemService {}
+
 impl FileSystemService {
     fn new() -> Self {
         Self {
             vfs: Vfs::new(),
+            shared_reads: VecDeque::new(),
+            namespaces: Namespaces::new(),
         }
     }
+
+    /// Rewrite the paths in `request` from `requester`'s view of the tree
+    /// to the global one
+    ///
+    /// Relative paths start at the requester's working directory.
+    fn resolve_paths(&self, requester: ProcessId, request: FileSystemRequest) -> Result<FileSystemRequest, VfsError> {
+        let resolve = |path: &str| self.namespaces.resolve(requester, &self.vfs.absolute(requester, path));
+        Ok(match request {
+            FileSystemRequest::Open { path, flags } => FileSystemRequest::Open { path: resolve(&path)?, flags },
+            FileSystemRequest::List { path } => FileSystemRequest::List { path: resolve(&path)? },
+            FileSystemRequest::Create { path, is_directory } => FileSystemRequest::Create { path: resolve(&path)?, is_directory },
+            FileSystemRequest::Delete { path } => FileSystemRequest::Delete { path: resolve(&path)? },
+            FileSystemRequest::Clone { source, destination } => FileSystemRequest::Clone {
+                source: resolve(&source)?,
+                destination: resolve(&destination)?,
+            },
+            // The target is resolved when the link is followed
+            FileSystemRequest::Symlink { target, path } => FileSystemRequest::Symlink { target, path: resolve(&path)? },
+            other => other,
+        })
+    }
+
+    /// Change the working directory of `requester`
+    ///
+    /// A confined process keeps a path of its own view, the only paths it
+    /// knows.
+    fn chdir(&mut self, requester: ProcessId, path: &str) -> Result<(), VfsError> {
+        if !self.namespaces.is_confined(requester) {
+            return self.vfs.chdir(requester, path);
+        }
+        let view = normalize(&self.vfs.absolute(requester, path))?;
+        let global = self.namespaces.resolve(requester, &view)?;
+        if self.vfs.stat(&global)?.file_type != FileType::Directory {
+            return Err(VfsError::NotDirectory);
+        }
+        self.vfs.set_cwd(requester, view);
+        Ok(())
+    }
+
+    /// Read straight into a fresh shared memory region
+    ///
+    /// Falls back to `None` when no region could be created, in which case
+    /// the caller copies the data through the message instead.
+    fn read_shared(&mut self, fd: u32, size: usize) -> Option<ServiceData> {
+        let mut region = SharedRegion::create(size).ok()?;
+        let bytes_read = self.vfs.read(fd, region.as_mut_slice()?).ok()?;
+        let buffer = region.buffer(0, bytes_read)?;
+
+        // The runner grants the region to the requester; keep it mapped
+        // here until the client had a chance to map it as well
+        if self.shared_reads.len() >= MAX_SHARED_READS {
+            self.shared_reads.pop_front();
+        }
+        self.shared_reads.push_back(region);
+
+        Some(ServiceData::SharedBinary(buffer))
+    }
+}
+
+/// Status for the outcome of a mount or unmount
+fn mount_status(result: Result<(), VfsError>) -> ServiceStatus {
+    match result {
+        Ok(()) => ServiceStatus::Success,
+        Err(VfsError::NotFound) | Err(VfsError::NotMounted) => ServiceStatus::NotFound,
+        Err(VfsError::PermissionDenied) => ServiceStatus::PermissionDenied,
+        Err(VfsError::InvalidPath) | Err(VfsError::NotDirectory) | Err(VfsError::NotSupported) => ServiceStatus::InvalidRequest,
+        // Busy mounts and failing devices
+        Err(_) => ServiceStatus::Error,
+    }
 }
 
 impl ServiceHandler for FileSystemService {
     fn handle_request(&mut self, request: ServiceMessage) -> ServiceResponse {
+        self.handle_request_from(KERNEL_PID, request)
+    }
+
+    fn handle_request_from(&mut self, requester: ProcessId, request: ServiceMessage) -> ServiceResponse {
+        let mut status = ServiceStatus::Success;
         let response_data = match request.data {
             ServiceData::FileSystemRequest(fs_request) => {
-                match fs_request {
-                    FileSystemRequest::Open { path, flags } => {
-                        // Convert u32 flags to OpenFlags
-                        let open_flags = OpenFlags::from_bits_truncate(flags);
-                        match self.vfs.open(&path, open_flags) {
-                            Ok(fd) => ServiceData::Binary(fd.to_le_bytes().to_vec()),
-                            Err(_) => ServiceData::Empty,
-                        }
+                match self.resolve_paths(requester, fs_request) {
+                    Err(VfsError::InvalidPath) => {
+                        status = ServiceStatus::InvalidRequest;
+                        ServiceData::Empty
                     }
-                    FileSystemRequest::Close { fd } => {
-                        match self.vfs.close(fd) {
-                            Ok(_) => ServiceData::Empty,
-                            Err(_) => ServiceData::Empty,
-                        }
+                    // Paths outside the requester's view do not exist for it
+                    Err(_) => {
+                        status = ServiceStatus::NotFound;
+                        ServiceData::Empty
                     }
-                    FileSystemRequest::Read { fd, size } => {
-                        let mut buffer = vec![0u8; size];
-                        match self.vfs.read(fd, &mut buffer) {
-                            Ok(bytes_read) => {
-                                buffer.truncate(bytes_read);
-                                ServiceData::Binary(buffer)
-                            },
-                            Err(_) => ServiceData::Empty,
+                    Ok(fs_request) => match fs_request {
+                        FileSystemRequest::Open { path, flags } => {
+                            // Convert u32 flags to OpenFlags
+                            let open_flags = OpenFlags::from_bits_truncate(flags);
+                            match self.vfs.open(&path, open_flags) {
+                                Ok(fd) => ServiceData::Binary(fd.to_le_bytes().to_vec()),
+                                Err(_) => ServiceData::Empty,
+                            }
                         }
-                    }
-                    FileSystemRequest::Write { fd, data } => {
-                        match self.vfs.write(fd, &data) {
-                            Ok(bytes_written) => ServiceData::Binary(bytes_written.to_le_bytes().to_vec()),
-                            Err(_) => ServiceData::Empty,
+                        FileSystemRequest::Close { fd } => {
+                            match self.vfs.close(fd) {
+                                Ok(_) => ServiceData::Empty,
+                                Err(_) => ServiceData::Empty,
+                            }
                         }
-                    }
-                    FileSystemRequest::List { path } => {
-                        // For now, return a mock directory listing
-                        // In a real implementation, this would use VFS methods
-                        let result = format!("Contents of {}:\n  file1.txt\n  file2.txt\n  subdir/", path);
-                        ServiceData::Text(result)
-                    }
-                    FileSystemRequest::Create { path, is_directory } => {
-                        let file_type = if is_directory { FileType::Directory } else { FileType::Regular };
-                        let permissions = FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE;
-                        match self.vfs.create(&path, file_type, permissions) {
-                            Ok(_) => ServiceData::Empty,
-                            Err(_) => ServiceData::Empty,
+                        FileSystemRequest::Read { fd, size } if size >= SHARED_READ_THRESHOLD => {
+                            match self.read_shared(fd, size) {
+                                Some(data) => data,
+                                None => {
+                                    let mut buffer = vec![0u8; size];
+                                    match self.vfs.read(fd, &mut buffer) {
+                                        Ok(bytes_read) => {
+                                            buffer.truncate(bytes_read);
+                                            ServiceData::Binary(buffer)
+                                        },
+                                        Err(_) => ServiceData::Empty,
+                                    }
+                                }
+                            }
                         }
-                    }
-                    FileSystemRequest::Delete { path } => {
-                        // For now, just return success
-                        // In a real implementation, this would use VFS delete methods
-                        ServiceData::Empty
-                    }
+                        FileSystemRequest::Read { fd, size } => {
+                            let mut buffer = vec![0u8; size];
+                            match self.vfs.read(fd, &mut buffer) {
+                                Ok(bytes_read) => {
+                                    buffer.truncate(bytes_read);
+                                    ServiceData::Binary(buffer)
+                                },
+                                Err(_) => ServiceData::Empty,
+                            }
+                        }
+                        FileSystemRequest::Write { fd, data } => {
+                            match self.vfs.write(fd, &data) {
+                                Ok(bytes_written) => ServiceData::Binary(bytes_written.to_le_bytes().to_vec()),
+                                Err(_) => ServiceData::Empty,
+                            }
+                        }
+                        FileSystemRequest::List { path } => {
+                            // A header line, then one indented name per entry,
+                            // directories marked with a trailing '/'
+                            match self.vfs.readdir(&path) {
+                                Ok(entries) => {
+                                    let mut result = format!("Contents of {}:", path);
+                                    for entry in entries {
+                                        let name = core::str::from_utf8(&entry.name[..entry.name_len as usize]).unwrap_or("?");
+                                        if name == "." || name == ".." {
+                                            continue;
+                                        }
+                                        let marker = if entry.file_type == FileType::Directory { "/" } else { "" };
+                                        result.push_str(&format!("\n  {}{}", name, marker));
+                                    }
+                                    ServiceData::Text(result)
+                                }
+                                Err(VfsError::NotFound) => {
+                                    status = ServiceStatus::NotFound;
+                                    ServiceData::Empty
+                                }
+                                Err(_) => {
+                                    status = ServiceStatus::InvalidRequest;
+                                    ServiceData::Empty
+                                }
+                            }
+                        }
+                        FileSystemRequest::Create { path, is_directory } => {
+                            let file_type = if is_directory { FileType::Directory } else { FileType::Regular };
+                            let permissions = FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE;
+                            match self.vfs.create(&path, file_type, permissions) {
+                                Ok(_) => ServiceData::Empty,
+                                Err(_) => ServiceData::Empty,
+                            }
+                        }
+                        FileSystemRequest::ReadAt { fd, offset, size } => {
+                            let mut buffer = vec![0u8; size];
+                            match self.vfs.read_at(fd, offset, &mut buffer) {
+                                Ok(bytes_read) => {
+                                    buffer.truncate(bytes_read);
+                                    ServiceData::Binary(buffer)
+                                },
+                                Err(_) => ServiceData::Empty,
+                            }
+                        }
+                        FileSystemRequest::WriteAt { fd, offset, data } => {
+                            match self.vfs.write_at(fd, offset, &data) {
+                                Ok(bytes_written) => ServiceData::Binary(bytes_written.to_le_bytes().to_vec()),
+                                Err(_) => ServiceData::Empty,
+                            }
+                        }
+                        FileSystemRequest::Clone { source, destination } => {
+                            // One byte telling whether blocks are shared
+                            match self.vfs.clone_file(&source, &destination) {
+                                Ok(method) => ServiceData::Binary(vec![(method == CloneMethod::Reflink) as u8]),
+                                Err(_) => ServiceData::Empty,
+                            }
+                        }
+                        FileSystemRequest::Delete { path } => {
+                            // For now, just return success
+                            // In a real implementation, this would use VFS delete methods
+                            ServiceData::Empty
+                        }
+                        FileSystemRequest::SetNamespace { pid, bindings } => {
+                            let result = Namespace::new(&bindings)
+                                .and_then(|namespace| self.namespaces.set(requester, pid, namespace));
+                            status = match result {
+                                Ok(()) => ServiceStatus::Success,
+                                Err(VfsError::PermissionDenied) => ServiceStatus::PermissionDenied,
+                                Err(_) => ServiceStatus::InvalidRequest,
+                            };
+                            ServiceData::Empty
+                        }
+                        FileSystemRequest::ClearNamespace { pid } => {
+                            match self.namespaces.clear(requester, pid) {
+                                // Its working directory was a path of the view
+                                Ok(()) => self.vfs.set_cwd(pid, String::from("/")),
+                                Err(_) => status = ServiceStatus::PermissionDenied,
+                            }
+                            ServiceData::Empty
+                        }
+                        FileSystemRequest::Chdir { path } => {
+                            status = match self.chdir(requester, &path) {
+                                Ok(()) => ServiceStatus::Success,
+                                Err(VfsError::NotFound) => ServiceStatus::NotFound,
+                                Err(_) => ServiceStatus::InvalidRequest,
+                            };
+                            ServiceData::Empty
+                        }
+                        FileSystemRequest::Getcwd => ServiceData::Text(self.vfs.getcwd(requester)),
+                        // The mount table is global, and links could point out of a
+                        // view; confined processes may change neither
+                        FileSystemRequest::Mount { .. } | FileSystemRequest::Unmount { .. } | FileSystemRequest::Symlink { .. }
+                            if self.namespaces.is_confined(requester) =>
+                        {
+                            status = ServiceStatus::PermissionDenied;
+                            ServiceData::Empty
+                        }
+                        FileSystemRequest::Symlink { target, path } => {
+                            if self.vfs.symlink(&target, &path).is_err() {
+                                status = ServiceStatus::Error;
+                            }
+                            ServiceData::Empty
+                        }
+                        FileSystemRequest::Mount { source, target, fs_type, flags } => {
+                            let flags = MountFlags::from_bits_truncate(flags);
+                            let result = kosh_fs_service::mount(&mut self.vfs, &source, &target, &fs_type, flags);
+                            status = mount_status(result);
+                            ServiceData::Empty
+                        }
+                        FileSystemRequest::Unmount { target } => {
+                            status = mount_status(self.vfs.unmount(&target));
+                            ServiceData::Empty
+                        }
+                        FileSystemRequest::Seek { fd, offset, whence } => {
+                            let result = SeekFrom::from_whence(offset, whence)
+                                .ok_or(VfsError::InvalidArgument)
+                                .and_then(|position| self.vfs.seek(fd, position));
+                            match result {
+                                Ok(offset) => ServiceData::Binary(offset.to_le_bytes().to_vec()),
+                                Err(_) => {
+                                    status = ServiceStatus::InvalidRequest;
+                                    ServiceData::Empty
+                                }
+                            }
+                        }
+                        FileSystemRequest::Dup { fd } => {
+                            match self.vfs.dup(fd) {
+                                Ok(fd) => ServiceData::Binary(fd.to_le_bytes().to_vec()),
+                                Err(_) => ServiceData::Empty,
+                            }
+                        }
+                        FileSystemRequest::Sync => {
+                            if self.vfs.sync().is_err() {
+                                status = ServiceStatus::Error;
+                            }
+                            ServiceData::Empty
+                        }
+                    },
                 }
             }
             _ => ServiceData::Empty,
@@ -90,7 +343,7 @@ impl ServiceHandler for FileSystemService {
 
         ServiceResponse {
             request_id: request.request_id,
-            status: ServiceStatus::Success,
+            status,
             data: response_data,
         }
     }
@@ -104,6 +357,21 @@ impl ServiceHandler for FileSystemService {
         match self.vfs.mount("/", FileSystemType::Ext4, None, false) {
             Ok(_) => {
                 debug_print(b"FS Service: Root filesystem mounted\n");
+                // Where the shell mounts further devices
+                let permissions = FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE | FilePermissions::OWNER_EXECUTE;
+                if self.vfs.mkdir("/mnt", permissions).is_err() {
+                    debug_print(b"FS Service: Failed to create /mnt\n");
+                }
+                let proc_mounted = self.vfs.mkdir("/proc", permissions)
+                    .and_then(|_| self.vfs.mount("/proc", FileSystemType::ProcFs, None, true));
+                if proc_mounted.is_err() {
+                    debug_print(b"FS Service: Failed to mount /proc\n");
+                }
+                let dev_mounted = self.vfs.mkdir("/dev", permissions)
+                    .and_then(|_| self.vfs.mount("/dev", FileSystemType::DevFs, None, false));
+                if dev_mounted.is_err() {
+                    debug_print(b"FS Service: Failed to mount /dev\n");
+                }
                 Ok(())
             }
             Err(_) => {
@@ -115,11 +383,79 @@ impl ServiceHandler for FileSystemService {
 
     fn shutdown(&mut self) -> Result<(), kosh_service::ServiceError> {
         debug_print(b"FS Service: Shutting down\n");
-        // Unmount filesystems and cleanup
+        // Nothing cached may be lost
+        if self.vfs.sync().is_err() {
+            debug_print(b"FS Service: Failed to write back cached blocks\n");
+        }
         Ok(())
     }
 }
 
+fn with_service<R>(f: impl FnOnce(&mut FileSystemService) -> R) -> R {
+    f(SERVICE.lock().get_or_insert_with(FileSystemService::new))
+}
+
+/// The runner's view of the service, which lives in `SERVICE`
+struct SharedService;
+
+impl ServiceHandler for SharedService {
+    fn handle_request(&mut self, request: ServiceMessage) -> ServiceResponse {
+        with_service(|service| service.handle_request(request))
+    }
+
+    fn handle_request_from(&mut self, requester: ProcessId, request: ServiceMessage) -> ServiceResponse {
+        with_service(|service| service.handle_request_from(requester, request))
+    }
+
+    fn get_service_type(&self) -> ServiceType {
+        ServiceType::FileSystem
+    }
+
+    fn initialize(&mut self) -> Result<(), kosh_service::ServiceError> {
+        with_service(|service| service.initialize())
+    }
+
+    fn shutdown(&mut self) -> Result<(), kosh_service::ServiceError> {
+        with_service(|service| service.shutdown())
+    }
+}
+
+/// Handle a request and answer it
+fn serve(incoming: IncomingRequest) {
+    let response = with_service(|service| service.handle_request_from(incoming.requester, incoming.request));
+    if kosh_service::send_response(incoming.sender, &response).is_err() {
+        debug_print(b"FS Service: Failed to send response\n");
+    }
+}
+
+/// Serve queued requests until the process exits
+fn worker() -> i32 {
+    loop {
+        let mut queue = QUEUE.lock();
+        let incoming = loop {
+            match queue.pop_front() {
+                Some(incoming) => break incoming,
+                None => queue = QUEUED.wait(queue),
+            }
+        };
+        drop(queue);
+        serve(incoming);
+    }
+}
+
+/// Start the worker threads, returning how many started
+fn start_workers() -> usize {
+    let mut started = 0;
+    for _ in 0..WORKER_THREADS {
+        match kosh_posix::thread::spawn_with_stack(WORKER_STACK_SIZE, worker) {
+            // Workers run until the service exits
+            Ok(_) => started += 1,
+            Err(_) => break,
+        }
+    }
+    started
+}
+
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     // Initialize heap allocator
@@ -128,8 +464,7 @@ pub extern "C" fn _start() -> ! {
     debug_print(b"FS Service: Starting file system service\n");
     
     // Create and start the file system service
-    let fs_service = FileSystemService::new();
-    let mut service_runner = ServiceRunner::new(fs_service);
+    let mut service_runner = ServiceRunner::new(SharedService);
     
     // Initialize the service
     if let Err(_) = service_runner.start() {
@@ -137,23 +472,48 @@ pub extern "C" fn _start() -> ! {
         sys_exit(1);
     }
     
+    // Without threads, requests are served one after another here
+    let workers = start_workers();
+    if workers == 0 {
+        debug_print(b"FS Service: No worker threads, serving requests in turn\n");
+    }
+    
     debug_print(b"FS Service: Service started, entering main loop\n");
     
     // Main service loop
     loop {
-        // Process incoming requests
-        if let Err(_) = service_runner.run_once() {
-            debug_print(b"FS Service: Error processing request\n");
+        if workers == 0 {
+            // Sleep until clients send requests, then serve all of them
+            if let Err(_) = service_runner.poll_and_dispatch(kosh_ipc::poll::INFINITE) {
+                debug_print(b"FS Service: Error processing request\n");
+            }
+            continue;
         }
         
-        // Yield CPU to prevent busy waiting
-        yield_cpu();
+        // Sleep until clients send requests, then queue all of them
+        if kosh_ipc::poll::wait_for_message(kosh_ipc::poll::INFINITE).is_err() {
+            debug_print(b"FS Service: Error waiting for requests\n");
+            continue;
+        }
+        loop {
+            match service_runner.receive_request() {
+                Ok(Some(incoming)) => {
+                    QUEUE.lock().push_back(incoming);
+                    QUEUED.notify_one();
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    debug_print(b"FS Service: Error receiving request\n");
+                    break;
+                }
+            }
+        }
     }
 }
 
 fn init_heap() {
-    const HEAP_SIZE: usize = 128 * 1024; // 128KB heap for FS service
-    static mut HEAP_MEMORY: [u8; 128 * 1024] = [0; 128 * 1024];
+    const HEAP_SIZE: usize = 256 * 1024; // 256KB heap for FS service and worker stacks
+    static mut HEAP_MEMORY: [u8; 256 * 1024] = [0; 256 * 1024];
     
     unsafe {
         let heap_ptr = core::ptr::addr_of_mut!(HEAP_MEMORY);
@@ -161,17 +521,11 @@ fn init_heap() {
     }
 }
 
-fn yield_cpu() {
-    for _ in 0..1000 {
-        core::hint::spin_loop();
-    }
-}
-
 fn debug_print(message: &[u8]) {
     #[cfg(debug_assertions)]
     unsafe {
         core::arch::asm!(
-            "syscall",
+            "int 0x80",
             in("rax") 100u64, // SYS_DEBUG_PRINT
             in("rdi") message.as_ptr(),
             in("rsi") message.len(),
@@ -183,7 +537,7 @@ fn debug_print(message: &[u8]) {
 fn sys_exit(status: i32) -> ! {
     unsafe {
         core::arch::asm!(
-            "syscall",
+            "int 0x80",
             in("rax") 1u64, // SYS_EXIT
             in("rdi") status,
             options(noreturn)
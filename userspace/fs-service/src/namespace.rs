@@ -0,0 +1,218 @@
+//! Per-process views of the file tree
+//!
+//! Processes without a namespace see the whole tree. A process with one
+//! sees only its bindings: each makes a file or directory of the global
+//! tree visible at a path of its own, and everything else does not exist
+//! for it. Paths are normalised before they are resolved, so `..` cannot
+//! climb out of a binding.
+//!
+//! Namespaces are set up by init from the manifests of the processes it
+//! starts. Only processes that see the whole tree may set or clear them,
+//! so a confined process can never widen its own view.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use kosh_types::{ProcessId, VfsError};
+
+/// Bring a path into the form `/a/b`, resolving `.` and `..`
+///
+/// `..` at the root stays at the root.
+pub fn normalize(path: &str) -> Result<String, VfsError> {
+    if !path.starts_with('/') {
+        return Err(VfsError::InvalidPath);
+    }
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+
+    if components.is_empty() {
+        return Ok(String::from("/"));
+    }
+    let mut normalized = String::new();
+    for component in components {
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+    Ok(normalized)
+}
+
+/// `path` with the leading `prefix` removed, if `prefix` names `path` or one
+/// of its ancestors
+pub(crate) fn strip_ancestor<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    if prefix == "/" {
+        return Some(path);
+    }
+    let rest = path.strip_prefix(prefix)?;
+    (rest.is_empty() || rest.starts_with('/')).then_some(rest)
+}
+
+/// A restricted view of the file tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Namespace {
+    /// `(path, target)` pairs, both normalised
+    bindings: Vec<(String, String)>,
+}
+
+impl Namespace {
+    /// Build a view from `(path, target)` bindings
+    ///
+    /// No binding may be given the same path twice. A view without bindings
+    /// hides the whole tree.
+    pub fn new(bindings: &[(String, String)]) -> Result<Self, VfsError> {
+        let mut normalized: Vec<(String, String)> = Vec::with_capacity(bindings.len());
+        for (path, target) in bindings {
+            let path = normalize(path)?;
+            if normalized.iter().any(|(existing, _)| *existing == path) {
+                return Err(VfsError::AlreadyExists);
+            }
+            normalized.push((path, normalize(target)?));
+        }
+        Ok(Self { bindings: normalized })
+    }
+
+    /// Path in the global tree that `path` names in this view
+    ///
+    /// The binding with the longest matching path wins, so a binding below
+    /// another one shadows it.
+    pub fn resolve(&self, path: &str) -> Result<String, VfsError> {
+        let path = normalize(path)?;
+        let (rest, target) = self.bindings.iter()
+            .filter_map(|(bound, target)| strip_ancestor(&path, bound).map(|rest| (bound.len(), rest, target)))
+            .max_by_key(|(length, _, _)| *length)
+            .map(|(_, rest, target)| (rest, target))
+            .ok_or(VfsError::NotFound)?;
+
+        if rest.is_empty() {
+            return Ok(target.clone());
+        }
+        let mut resolved = if target == "/" { String::new() } else { target.clone() };
+        resolved.push_str(rest);
+        Ok(resolved)
+    }
+
+    pub fn bindings(&self) -> &[(String, String)] {
+        &self.bindings
+    }
+}
+
+/// Namespaces of all confined processes
+#[derive(Default)]
+pub struct Namespaces {
+    namespaces: BTreeMap<ProcessId, Namespace>,
+}
+
+impl Namespaces {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_confined(&self, pid: ProcessId) -> bool {
+        self.namespaces.contains_key(&pid)
+    }
+
+    /// Confine `pid` to `namespace`, on behalf of `requester`
+    pub fn set(&mut self, requester: ProcessId, pid: ProcessId, namespace: Namespace) -> Result<(), VfsError> {
+        if self.is_confined(requester) {
+            return Err(VfsError::PermissionDenied);
+        }
+        self.namespaces.insert(pid, namespace);
+        Ok(())
+    }
+
+    /// Give `pid` the whole tree again, on behalf of `requester`
+    ///
+    /// Init does this when a confined process exits, before its ID can be
+    /// reused.
+    pub fn clear(&mut self, requester: ProcessId, pid: ProcessId) -> Result<(), VfsError> {
+        if self.is_confined(requester) {
+            return Err(VfsError::PermissionDenied);
+        }
+        self.namespaces.remove(&pid);
+        Ok(())
+    }
+
+    /// Path in the global tree that `path` names for `pid`
+    pub fn resolve(&self, pid: ProcessId, path: &str) -> Result<String, VfsError> {
+        match self.namespaces.get(&pid) {
+            Some(namespace) => namespace.resolve(path),
+            None => Ok(String::from(path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(path: &str, target: &str) -> (String, String) {
+        (String::from(path), String::from(target))
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("/").unwrap(), "/");
+        assert_eq!(normalize("//tmp/./a/").unwrap(), "/tmp/a");
+        assert_eq!(normalize("/tmp/a/../b").unwrap(), "/tmp/b");
+        assert_eq!(normalize("/../../etc").unwrap(), "/etc");
+        assert_eq!(normalize("tmp"), Err(VfsError::InvalidPath));
+    }
+
+    #[test]
+    fn test_namespace_resolution() {
+        let namespace = Namespace::new(&[
+            binding("/dev/fb0", "/dev/fb0"),
+            binding("/tmp", "/tmp/private/42"),
+            binding("/tmp/shared", "/var/shared"),
+        ]).unwrap();
+
+        assert_eq!(namespace.resolve("/dev/fb0").unwrap(), "/dev/fb0");
+        assert_eq!(namespace.resolve("/tmp").unwrap(), "/tmp/private/42");
+        assert_eq!(namespace.resolve("/tmp/cache/x").unwrap(), "/tmp/private/42/cache/x");
+        // The deeper binding shadows the one above it
+        assert_eq!(namespace.resolve("/tmp/shared/log").unwrap(), "/var/shared/log");
+
+        // Anything not bound is invisible, including look-alike siblings
+        assert_eq!(namespace.resolve("/dev/fb01"), Err(VfsError::NotFound));
+        assert_eq!(namespace.resolve("/dev"), Err(VfsError::NotFound));
+        assert_eq!(namespace.resolve("/etc/passwd"), Err(VfsError::NotFound));
+        // and cannot be reached by climbing out of a binding
+        assert_eq!(namespace.resolve("/tmp/../etc/passwd"), Err(VfsError::NotFound));
+        assert_eq!(namespace.resolve("/tmp/../../tmp/private/7"), Ok(String::from("/tmp/private/42/private/7")));
+
+        assert_eq!(Namespace::new(&[binding("/a", "/b"), binding("/a/", "/c")]), Err(VfsError::AlreadyExists));
+        assert_eq!(Namespace::new(&[]).unwrap().resolve("/"), Err(VfsError::NotFound));
+
+        let root = Namespace::new(&[binding("/", "/srv/app")]).unwrap();
+        assert_eq!(root.resolve("/data").unwrap(), "/srv/app/data");
+        let whole = Namespace::new(&[binding("/", "/")]).unwrap();
+        assert_eq!(whole.resolve("/data").unwrap(), "/data");
+    }
+
+    #[test]
+    fn test_confined_processes_cannot_change_namespaces() {
+        let mut namespaces = Namespaces::new();
+        let namespace = Namespace::new(&[binding("/tmp", "/tmp/private/5")]).unwrap();
+
+        // Unconfined processes see the global tree
+        assert_eq!(namespaces.resolve(5, "/etc").unwrap(), "/etc");
+
+        namespaces.set(1, 5, namespace.clone()).unwrap();
+        assert_eq!(namespaces.resolve(5, "/tmp/a").unwrap(), "/tmp/private/5/a");
+        assert_eq!(namespaces.resolve(5, "/etc"), Err(VfsError::NotFound));
+
+        // Neither for itself nor for others
+        assert_eq!(namespaces.clear(5, 5), Err(VfsError::PermissionDenied));
+        assert_eq!(namespaces.set(5, 6, namespace), Err(VfsError::PermissionDenied));
+        assert!(!namespaces.is_confined(6));
+
+        namespaces.clear(1, 5).unwrap();
+        assert_eq!(namespaces.resolve(5, "/etc").unwrap(), "/etc");
+    }
+}
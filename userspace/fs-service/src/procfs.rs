@@ -0,0 +1,549 @@
+//! Process and kernel statistics as files
+//!
+//! Mounted at /proc. Nothing is stored: a file's text is generated from the
+//! kernel's figures when it is opened, and reads are served from that
+//! snapshot until the last descriptor for it is closed, so a reader never
+//! sees half of one sample and half of the next.
+//!
+//! - `meminfo`: physical memory and the kernel heap
+//! - `uptime`: seconds since boot
+//! - `stat`: CPU time by mode and the current CPU load
+//! - `ipc`: message and capability counters
+//! - `<pid>/status`: one process, one `Key:\tvalue` line per field
+
+use alloc::{vec, vec::Vec, string::String, collections::BTreeMap, boxed::Box, format};
+use core::fmt::Write;
+use kosh_posix::sysinfo::{IpcInfo, ProcessStatus, SysInfo};
+use kosh_types::{
+    InodeNumber, FileOffset, FileType, FilePermissions, OpenFlags, FileMetadata, VfsError, DirectoryEntry
+};
+use crate::sysimage::directory_entry;
+use crate::vfs::FileSystem;
+
+const ROOT_INODE: InodeNumber = 1;
+const MEMINFO_INODE: InodeNumber = 2;
+const UPTIME_INODE: InodeNumber = 3;
+const IPC_INODE: InodeNumber = 4;
+const STAT_INODE: InodeNumber = 5;
+
+/// Process `pid` has directory inode `PROCESS_INODE_BASE + 2 * pid` and its
+/// status file the one after
+const PROCESS_INODE_BASE: InodeNumber = 16;
+
+/// Where the figures come from
+pub trait KernelStats {
+    fn system(&mut self) -> Result<SysInfo, VfsError>;
+
+    /// IDs of every process
+    fn processes(&mut self) -> Result<Vec<u32>, VfsError>;
+
+    /// Status of process `pid`, `NotFound` if it does not exist
+    fn process(&mut self, pid: u32) -> Result<ProcessStatus, VfsError>;
+
+    fn ipc(&mut self) -> Result<IpcInfo, VfsError>;
+}
+
+/// Figures from the kernel's system information calls
+pub struct KernelQueries;
+
+fn errno_to_vfs_error(errno: kosh_posix::Errno) -> VfsError {
+    match errno {
+        kosh_posix::errno::ESRCH => VfsError::NotFound,
+        kosh_posix::errno::EPERM | kosh_posix::errno::EACCES => VfsError::PermissionDenied,
+        _ => VfsError::IoError,
+    }
+}
+
+impl KernelStats for KernelQueries {
+    fn system(&mut self) -> Result<SysInfo, VfsError> {
+        kosh_posix::sysinfo().map_err(errno_to_vfs_error)
+    }
+
+    fn processes(&mut self) -> Result<Vec<u32>, VfsError> {
+        kosh_posix::process_list().map_err(errno_to_vfs_error)
+    }
+
+    fn process(&mut self, pid: u32) -> Result<ProcessStatus, VfsError> {
+        kosh_posix::process_status(pid).map_err(errno_to_vfs_error)
+    }
+
+    fn ipc(&mut self) -> Result<IpcInfo, VfsError> {
+        kosh_posix::ipc_info().map_err(errno_to_vfs_error)
+    }
+}
+
+/// A file or directory under /proc
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Node {
+    Root,
+    MemInfo,
+    Uptime,
+    Ipc,
+    Stat,
+    ProcessDirectory(u32),
+    ProcessStatus(u32),
+}
+
+impl Node {
+    fn inode(self) -> InodeNumber {
+        match self {
+            Node::Root => ROOT_INODE,
+            Node::MemInfo => MEMINFO_INODE,
+            Node::Uptime => UPTIME_INODE,
+            Node::Ipc => IPC_INODE,
+            Node::Stat => STAT_INODE,
+            Node::ProcessDirectory(pid) => PROCESS_INODE_BASE + 2 * pid as InodeNumber,
+            Node::ProcessStatus(pid) => PROCESS_INODE_BASE + 2 * pid as InodeNumber + 1,
+        }
+    }
+
+    fn from_inode(inode: InodeNumber) -> Option<Self> {
+        match inode {
+            ROOT_INODE => Some(Node::Root),
+            MEMINFO_INODE => Some(Node::MemInfo),
+            UPTIME_INODE => Some(Node::Uptime),
+            IPC_INODE => Some(Node::Ipc),
+            STAT_INODE => Some(Node::Stat),
+            inode if inode >= PROCESS_INODE_BASE => {
+                let pid = u32::try_from((inode - PROCESS_INODE_BASE) / 2).ok()?;
+                Some(if inode % 2 == 0 { Node::ProcessDirectory(pid) } else { Node::ProcessStatus(pid) })
+            }
+            _ => None,
+        }
+    }
+
+    fn file_type(self) -> FileType {
+        match self {
+            Node::Root | Node::ProcessDirectory(_) => FileType::Directory,
+            _ => FileType::Regular,
+        }
+    }
+}
+
+/// Generated text of an open file
+struct Snapshot {
+    data: Vec<u8>,
+    opens: usize,
+}
+
+/// Read-only file system showing kernel statistics
+pub struct ProcFs {
+    stats: Box<dyn KernelStats>,
+    mounted: bool,
+    snapshots: BTreeMap<InodeNumber, Snapshot>,
+}
+
+impl ProcFs {
+    /// File system showing the running kernel's figures
+    pub fn new() -> Self {
+        Self::with_stats(Box::new(KernelQueries))
+    }
+
+    pub fn with_stats(stats: Box<dyn KernelStats>) -> Self {
+        Self {
+            stats,
+            mounted: false,
+            snapshots: BTreeMap::new(),
+        }
+    }
+
+    fn check_mounted(&self) -> Result<(), VfsError> {
+        if self.mounted { Ok(()) } else { Err(VfsError::NotMounted) }
+    }
+
+    fn lookup(&mut self, path: &str) -> Result<Node, VfsError> {
+        self.check_mounted()?;
+        let components: Vec<&str> = path.split('/').filter(|component| !component.is_empty()).collect();
+        let node = match components[..] {
+            [] => Node::Root,
+            ["meminfo"] => Node::MemInfo,
+            ["uptime"] => Node::Uptime,
+            ["ipc"] => Node::Ipc,
+            ["stat"] => Node::Stat,
+            [pid] => Node::ProcessDirectory(parse_pid(pid)?),
+            [pid, "status"] => Node::ProcessStatus(parse_pid(pid)?),
+            _ => return Err(VfsError::NotFound),
+        };
+        // Processes that have gone do not leave their directory behind
+        if let Node::ProcessDirectory(pid) | Node::ProcessStatus(pid) = node {
+            self.stats.process(pid)?;
+        }
+        Ok(node)
+    }
+
+    fn metadata(&self, node: Node, size: u64) -> FileMetadata {
+        let read = FilePermissions::OWNER_READ | FilePermissions::GROUP_READ | FilePermissions::OTHER_READ;
+        let permissions = match node.file_type() {
+            FileType::Directory => read | FilePermissions::OWNER_EXECUTE | FilePermissions::GROUP_EXECUTE | FilePermissions::OTHER_EXECUTE,
+            _ => read,
+        };
+        FileMetadata {
+            inode: node.inode(),
+            file_type: node.file_type(),
+            permissions,
+            size,
+            uid: 0,
+            gid: 0,
+            created_time: 0,
+            modified_time: 0,
+            accessed_time: 0,
+        }
+    }
+
+    /// Current text of the file `node`
+    fn generate(&mut self, node: Node) -> Result<String, VfsError> {
+        match node {
+            Node::MemInfo => Ok(format_meminfo(&self.stats.system()?)),
+            Node::Uptime => Ok(format_uptime(&self.stats.system()?)),
+            Node::Ipc => Ok(format_ipc(&self.stats.ipc()?)),
+            Node::Stat => Ok(format_stat(&self.stats.system()?)),
+            Node::ProcessStatus(pid) => Ok(format_status(&self.stats.process(pid)?)),
+            Node::Root | Node::ProcessDirectory(_) => Err(VfsError::IsDirectory),
+        }
+    }
+}
+
+fn parse_pid(name: &str) -> Result<u32, VfsError> {
+    // No leading zeros or signs: every process has exactly one name
+    if name.starts_with('0') && name != "0" || !name.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(VfsError::NotFound);
+    }
+    name.parse().map_err(|_| VfsError::NotFound)
+}
+
+const KIB: u64 = 1024;
+
+/// Text of /proc/meminfo
+pub fn format_meminfo(info: &SysInfo) -> String {
+    let mut text = String::new();
+    let used = info.total_memory.saturating_sub(info.free_memory);
+    let _ = writeln!(text, "MemTotal:  {:>10} kB", info.total_memory / KIB);
+    let _ = writeln!(text, "MemFree:   {:>10} kB", info.free_memory / KIB);
+    let _ = writeln!(text, "MemUsed:   {:>10} kB", used / KIB);
+    let _ = writeln!(text, "HeapTotal: {:>10} kB", info.heap_size / KIB);
+    let _ = writeln!(text, "HeapUsed:  {:>10} kB", info.heap_used / KIB);
+    let _ = writeln!(text, "Processes: {:>10}", info.process_count);
+    let _ = writeln!(text, "Cpus:      {:>10}", info.cpu_count);
+    text
+}
+
+/// Text of /proc/uptime: seconds since boot with two decimals
+pub fn format_uptime(info: &SysInfo) -> String {
+    format!("{}.{:02}\n", info.uptime_ms / 1000, info.uptime_ms % 1000 / 10)
+}
+
+/// Text of /proc/stat
+pub fn format_stat(info: &SysInfo) -> String {
+    let mut text = String::new();
+    let _ = writeln!(text, "UserTime:   {:>10} ms", info.user_time_ms);
+    let _ = writeln!(text, "KernelTime: {:>10} ms", info.kernel_time_ms);
+    let _ = writeln!(text, "IdleTime:   {:>10} ms", info.idle_time_ms);
+    let _ = writeln!(text, "CpuLoad:    {:>10} %", info.cpu_load);
+    text
+}
+
+/// Text of /proc/ipc
+pub fn format_ipc(info: &IpcInfo) -> String {
+    let mut text = String::new();
+    let _ = writeln!(text, "MessagesSent:           {}", info.messages_sent);
+    let _ = writeln!(text, "MessagesReceived:       {}", info.messages_received);
+    let _ = writeln!(text, "ActiveQueues:           {}", info.active_queues);
+    let _ = writeln!(text, "Capabilities:           {}", info.capabilities);
+    let _ = writeln!(text, "CapabilityChecks:       {}", info.capability_checks);
+    let _ = writeln!(text, "CapabilityChecksFailed: {}", info.capability_checks_failed);
+    let _ = writeln!(text, "SendsDenied:            {}", info.sends_denied);
+    let _ = writeln!(text, "ReceivesDenied:         {}", info.receives_denied);
+    text
+}
+
+/// Text of /proc/<pid>/status
+pub fn format_status(status: &ProcessStatus) -> String {
+    let priority = match status.priority {
+        0 => "system",
+        1 => "interactive",
+        2 => "normal",
+        3 => "background",
+        _ => "unknown",
+    };
+    let mut text = String::new();
+    let _ = writeln!(text, "Name:\t{}", status.name());
+    let _ = writeln!(text, "State:\t{}", status.state_name());
+    let _ = writeln!(text, "Pid:\t{}", status.pid);
+    let _ = writeln!(text, "PPid:\t{}", status.parent_pid);
+    let _ = writeln!(text, "Priority:\t{}", priority);
+    let _ = writeln!(text, "Cpu:\t{}", status.cpu);
+    let _ = writeln!(text, "CpuTime:\t{} ms", status.cpu_time_ms);
+    let _ = writeln!(text, "UserTime:\t{} ms", status.user_time_ms);
+    let _ = writeln!(text, "KernelTime:\t{} ms", status.kernel_time_ms);
+    let _ = writeln!(text, "ContextSwitches:\t{}", status.context_switches);
+    let _ = writeln!(text, "LastRun:\t{} ms", status.last_run_ms);
+    let _ = writeln!(text, "VmRSS:\t{} kB", status.resident_bytes / KIB);
+    let _ = writeln!(text, "VmHeap:\t{} kB", status.heap_bytes / KIB);
+    if status.memory_limit != 0 {
+        let _ = writeln!(text, "VmLimit:\t{} kB", status.memory_limit / KIB);
+    }
+    let _ = writeln!(text, "Children:\t{}", status.children);
+    if status.state == kosh_posix::sysinfo::PROCESS_STATE_ZOMBIE {
+        let _ = writeln!(text, "ExitCode:\t{}", status.exit_code);
+    }
+    text
+}
+
+impl FileSystem for ProcFs {
+    fn init(&mut self) -> Result<(), VfsError> {
+        self.snapshots.clear();
+        Ok(())
+    }
+
+    fn mount(&mut self, _device_id: Option<u32>) -> Result<(), VfsError> {
+        if self.mounted {
+            return Err(VfsError::MountPointBusy);
+        }
+        self.mounted = true;
+        Ok(())
+    }
+
+    fn unmount(&mut self) -> Result<(), VfsError> {
+        self.check_mounted()?;
+        self.mounted = false;
+        self.snapshots.clear();
+        Ok(())
+    }
+
+    /// Take a snapshot of a file's text
+    ///
+    /// Opening a file that is already open takes a fresh one, which the
+    /// other descriptors then share.
+    fn open(&mut self, path: &str, flags: OpenFlags) -> Result<(InodeNumber, FileMetadata), VfsError> {
+        if flags.intersects(OpenFlags::WRITE_ONLY | OpenFlags::READ_WRITE | OpenFlags::TRUNCATE | OpenFlags::APPEND) {
+            return Err(VfsError::ReadOnlyFileSystem);
+        }
+        let node = self.lookup(path)?;
+        let inode = node.inode();
+        if node.file_type() == FileType::Directory {
+            return Ok((inode, self.metadata(node, 0)));
+        }
+
+        let data = self.generate(node)?.into_bytes();
+        let size = data.len() as u64;
+        let snapshot = self.snapshots.entry(inode).or_insert(Snapshot { data: Vec::new(), opens: 0 });
+        snapshot.data = data;
+        snapshot.opens += 1;
+        Ok((inode, self.metadata(node, size)))
+    }
+
+    fn close(&mut self, inode: InodeNumber) -> Result<(), VfsError> {
+        if let Some(snapshot) = self.snapshots.get_mut(&inode) {
+            snapshot.opens -= 1;
+            if snapshot.opens == 0 {
+                self.snapshots.remove(&inode);
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, inode: InodeNumber, offset: FileOffset, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        let snapshot = match self.snapshots.get(&inode) {
+            Some(snapshot) => snapshot,
+            None if Node::from_inode(inode).map(Node::file_type) == Some(FileType::Directory) => {
+                return Err(VfsError::IsDirectory);
+            }
+            None => return Err(VfsError::InvalidFileDescriptor),
+        };
+        let start = usize::try_from(offset).unwrap_or(usize::MAX).min(snapshot.data.len());
+        let count = buffer.len().min(snapshot.data.len() - start);
+        buffer[..count].copy_from_slice(&snapshot.data[start..start + count]);
+        Ok(count)
+    }
+
+    fn write(&mut self, _inode: InodeNumber, _offset: FileOffset, _buffer: &[u8]) -> Result<usize, VfsError> {
+        Err(VfsError::ReadOnlyFileSystem)
+    }
+
+    fn create(&mut self, _path: &str, _file_type: FileType, _permissions: FilePermissions) -> Result<InodeNumber, VfsError> {
+        Err(VfsError::ReadOnlyFileSystem)
+    }
+
+    fn unlink(&mut self, _path: &str) -> Result<(), VfsError> {
+        Err(VfsError::ReadOnlyFileSystem)
+    }
+
+    /// Files report the size of their current text
+    fn stat(&mut self, path: &str) -> Result<FileMetadata, VfsError> {
+        let node = self.lookup(path)?;
+        let size = match node.file_type() {
+            FileType::Directory => 0,
+            _ => self.generate(node)?.len() as u64,
+        };
+        Ok(self.metadata(node, size))
+    }
+
+    fn readdir(&mut self, path: &str) -> Result<Vec<DirectoryEntry>, VfsError> {
+        let node = self.lookup(path)?;
+        match node {
+            Node::Root => {
+                let mut entries = vec![
+                    directory_entry(".", ROOT_INODE, FileType::Directory),
+                    directory_entry("..", ROOT_INODE, FileType::Directory),
+                    directory_entry("meminfo", MEMINFO_INODE, FileType::Regular),
+                    directory_entry("uptime", UPTIME_INODE, FileType::Regular),
+                    directory_entry("ipc", IPC_INODE, FileType::Regular),
+                    directory_entry("stat", STAT_INODE, FileType::Regular),
+                ];
+                for pid in self.stats.processes()? {
+                    let directory = Node::ProcessDirectory(pid);
+                    entries.push(directory_entry(&format!("{}", pid), directory.inode(), FileType::Directory));
+                }
+                Ok(entries)
+            }
+            Node::ProcessDirectory(pid) => Ok(vec![
+                directory_entry(".", node.inode(), FileType::Directory),
+                directory_entry("..", ROOT_INODE, FileType::Directory),
+                directory_entry("status", Node::ProcessStatus(pid).inode(), FileType::Regular),
+            ]),
+            _ => Err(VfsError::NotDirectory),
+        }
+    }
+
+    fn mkdir(&mut self, _path: &str, _permissions: FilePermissions) -> Result<(), VfsError> {
+        Err(VfsError::ReadOnlyFileSystem)
+    }
+
+    fn rmdir(&mut self, _path: &str) -> Result<(), VfsError> {
+        Err(VfsError::ReadOnlyFileSystem)
+    }
+
+    fn sync(&mut self) -> Result<(), VfsError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{Vfs, FileSystemType};
+    use kosh_posix::sysinfo::{PROCESS_NAME_LEN, PROCESS_STATE_BLOCKED, PROCESS_STATE_RUNNING};
+
+    fn process(pid: u32, name: &str, state: u32) -> ProcessStatus {
+        let mut status = ProcessStatus {
+            pid,
+            parent_pid: 1,
+            state,
+            cpu_time_ms: 40,
+            resident_bytes: 48 * 1024,
+            heap_bytes: 16 * 1024,
+            ..Default::default()
+        };
+        status.name[..name.len()].copy_from_slice(name.as_bytes());
+        status
+    }
+
+    struct FakeStats {
+        processes: Vec<ProcessStatus>,
+    }
+
+    impl KernelStats for FakeStats {
+        fn system(&mut self) -> Result<SysInfo, VfsError> {
+            Ok(SysInfo {
+                uptime_ms: 61_250,
+                total_memory: 64 * 1024 * 1024,
+                free_memory: 48 * 1024 * 1024,
+                process_count: self.processes.len() as u64,
+                cpu_count: 2,
+                cpu_load: 37,
+                user_time_ms: 900,
+                kernel_time_ms: 300,
+                ..Default::default()
+            })
+        }
+
+        fn processes(&mut self) -> Result<Vec<u32>, VfsError> {
+            Ok(self.processes.iter().map(|process| process.pid).collect())
+        }
+
+        fn process(&mut self, pid: u32) -> Result<ProcessStatus, VfsError> {
+            self.processes.iter().find(|process| process.pid == pid).copied().ok_or(VfsError::NotFound)
+        }
+
+        fn ipc(&mut self) -> Result<IpcInfo, VfsError> {
+            Ok(IpcInfo { messages_sent: 12, messages_received: 10, sends_denied: 3, ..Default::default() })
+        }
+    }
+
+    fn mounted_vfs() -> Vfs {
+        let stats = FakeStats {
+            processes: vec![process(1, "init", PROCESS_STATE_BLOCKED), process(4, "shell", PROCESS_STATE_RUNNING)],
+        };
+        let mut vfs = Vfs::new();
+        let procfs = ProcFs::with_stats(Box::new(stats));
+        assert!(vfs.mount_filesystem("/proc", FileSystemType::ProcFs, Box::new(procfs), None, false).is_ok());
+        vfs
+    }
+
+    fn read_file(vfs: &mut Vfs, path: &str) -> String {
+        let fd = vfs.open(path, OpenFlags::READ_ONLY).unwrap();
+        let mut text = Vec::new();
+        let mut chunk = [0u8; 16];
+        loop {
+            match vfs.read(fd, &mut chunk).unwrap() {
+                0 => break,
+                count => text.extend_from_slice(&chunk[..count]),
+            }
+        }
+        assert!(vfs.close(fd).is_ok());
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn test_proc_files() {
+        let mut vfs = mounted_vfs();
+
+        let meminfo = read_file(&mut vfs, "/proc/meminfo");
+        assert!(meminfo.starts_with("MemTotal:       65536 kB\n"));
+        assert!(meminfo.contains("MemUsed:        16384 kB\n"));
+        assert_eq!(read_file(&mut vfs, "/proc/uptime"), "61.25\n");
+        let ipc = read_file(&mut vfs, "/proc/ipc");
+        assert!(ipc.starts_with("MessagesSent:           12\n"));
+        assert!(ipc.contains("SendsDenied:            3\n"));
+        let stat = read_file(&mut vfs, "/proc/stat");
+        assert!(stat.starts_with("UserTime:          900 ms\nKernelTime:        300 ms\n"));
+        assert!(stat.ends_with("CpuLoad:            37 %\n"));
+
+        let status = read_file(&mut vfs, "/proc/4/status");
+        assert!(status.starts_with("Name:\tshell\nState:\trunning\nPid:\t4\nPPid:\t1\n"));
+        assert!(status.contains("CpuTime:\t40 ms\n"));
+        assert!(status.contains("VmRSS:\t48 kB\nVmHeap:\t16 kB\n"));
+        assert!(!status.contains("VmLimit:"));
+        assert_eq!(vfs.stat("/proc/4/status").unwrap().size, status.len() as u64);
+    }
+
+    #[test]
+    fn test_proc_directories() {
+        let mut vfs = mounted_vfs();
+
+        let names: Vec<String> = vfs.readdir("/proc").unwrap().iter()
+            .map(|entry| String::from_utf8_lossy(&entry.name[..entry.name_len as usize]).into_owned())
+            .collect();
+        assert_eq!(names, ["." , "..", "meminfo", "uptime", "ipc", "stat", "1", "4"]);
+        assert_eq!(vfs.readdir("/proc/1").unwrap().len(), 3);
+        assert_eq!(vfs.stat("/proc/4").unwrap().file_type, FileType::Directory);
+
+        // Only live processes, by their one canonical name
+        assert_eq!(vfs.stat("/proc/2").err(), Some(VfsError::NotFound));
+        assert_eq!(vfs.stat("/proc/04").err(), Some(VfsError::NotFound));
+        assert_eq!(vfs.stat("/proc/4/cmdline").err(), Some(VfsError::NotFound));
+        assert_eq!(vfs.readdir("/proc/meminfo").err(), Some(VfsError::NotDirectory));
+    }
+
+    #[test]
+    fn test_proc_read_only() {
+        let mut vfs = mounted_vfs();
+        assert_eq!(vfs.open("/proc/meminfo", OpenFlags::READ_WRITE), Err(VfsError::ReadOnlyFileSystem));
+        assert_eq!(vfs.create("/proc/new", FileType::Regular, FilePermissions::OWNER_READ), Err(VfsError::ReadOnlyFileSystem));
+
+        // A name filling the whole field has no terminator
+        let name = "n".repeat(PROCESS_NAME_LEN);
+        let status = format_status(&process(9, &name, PROCESS_STATE_RUNNING));
+        assert!(status.starts_with(&format!("Name:\t{}\n", name)));
+    }
+}
@@ -0,0 +1,657 @@
+//! Compressed read-only system image file system
+//!
+//! The system partition holds an image in the spirit of squashfs: file
+//! data is cut into fixed-size blocks, each compressed on its own, so any
+//! part of a file can be read by decompressing just the blocks covering
+//! it. The image is built once (see `ImageBuilder`) and never written on
+//! the device.
+//!
+//! Layout, all integers little-endian:
+//!
+//! - Header: the magic `KSIM`, `u16` format version, `u16` log2 of the block
+//!   size, `u32` entry count, `u32` block count, `u64` offset of the entry
+//!   table and `u64` offset of the block table.
+//! - Entry table, sorted by path: per entry a `u8` kind (0 file, 1
+//!   directory), `u16` permission bits, `u16` path length, `u64` size and
+//!   `u32` first block, followed by the path relative to the image root
+//!   without leading or trailing slashes. The root directory has the empty
+//!   path. A file's blocks are consecutive.
+//! - Block table: per block a `u64` image offset and a `u32` stored length
+//!   whose top bit marks a block stored uncompressed.
+//! - Block data.
+//!
+//! The entry table is the index and is kept in memory while mounted; block
+//! table entries and block data are read from the image as needed. The
+//! kernel's page cache keeps the file pages it maps, so the decompression
+//! cache here only holds the few most recent blocks: enough that reading a
+//! block page by page, in the small chunks fs-service requests carry,
+//! decompresses it once.
+
+use alloc::{vec, vec::Vec, string::{String, ToString}, collections::{BTreeMap, VecDeque}, boxed::Box};
+use kosh_types::{
+    InodeNumber, FileOffset, FileType, FilePermissions, OpenFlags, FileMetadata, VfsError, DirectoryEntry
+};
+use crate::compression;
+use crate::vfs::FileSystem;
+
+const MAGIC: &[u8; 4] = b"KSIM";
+const FORMAT_VERSION: u16 = 1;
+const HEADER_LEN: usize = 32;
+const BLOCK_ENTRY_LEN: usize = 12;
+
+/// Set in a block's stored length if the block is not compressed
+const UNCOMPRESSED: u32 = 1 << 31;
+
+const KIND_FILE: u8 = 0;
+const KIND_DIRECTORY: u8 = 1;
+
+/// Block sizes an image may use, as log2
+const MIN_BLOCK_LOG: u16 = 12;
+const MAX_BLOCK_LOG: u16 = 17;
+
+/// 8 KiB blocks keep the decompression cache small enough for the
+/// fs-service heap
+pub const DEFAULT_BLOCK_LOG: u16 = 13;
+
+/// Decompressed blocks kept around
+const CACHED_BLOCKS: usize = 4;
+
+/// Where image bytes come from, e.g. a system slot partition
+pub trait ImageSource {
+    /// Fill `buffer` from byte `offset` of the image
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result<(), VfsError>;
+}
+
+impl ImageSource for Vec<u8> {
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result<(), VfsError> {
+        let start = usize::try_from(offset).map_err(|_| VfsError::IoError)?;
+        let end = start.checked_add(buffer.len())
+            .filter(|&end| end <= self.len())
+            .ok_or(VfsError::IoError)?;
+        buffer.copy_from_slice(&self[start..end]);
+        Ok(())
+    }
+}
+
+/// A file or directory in the image
+#[derive(Debug, Clone)]
+struct ImageEntry {
+    path: String,
+    file_type: FileType,
+    permissions: FilePermissions,
+    size: u64,
+    first_block: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ImageHeader {
+    block_log: u16,
+    entry_count: u32,
+    block_count: u32,
+    entry_table_offset: u64,
+    block_table_offset: u64,
+}
+
+struct CachedBlock {
+    index: u32,
+    data: Vec<u8>,
+}
+
+/// Read-only file system on a compressed system image
+pub struct SystemImageFs {
+    source: Option<Box<dyn ImageSource>>,
+    header: Option<ImageHeader>,
+    /// Sorted by path; inode numbers are indices plus one
+    entries: Vec<ImageEntry>,
+    cache: VecDeque<CachedBlock>,
+    device_id: Option<u32>,
+}
+
+impl SystemImageFs {
+    /// File system without an image; mounting it fails
+    pub fn new() -> Self {
+        Self {
+            source: None,
+            header: None,
+            entries: Vec::new(),
+            cache: VecDeque::new(),
+            device_id: None,
+        }
+    }
+
+    /// File system on the image read from `source`
+    pub fn with_source(source: Box<dyn ImageSource>) -> Self {
+        Self { source: Some(source), ..Self::new() }
+    }
+
+    fn source(&mut self) -> Result<&mut dyn ImageSource, VfsError> {
+        Ok(self.source.as_mut().ok_or(VfsError::IoError)?.as_mut())
+    }
+
+    fn header(&self) -> Result<ImageHeader, VfsError> {
+        self.header.ok_or(VfsError::NotMounted)
+    }
+
+    fn read_header(&mut self) -> Result<ImageHeader, VfsError> {
+        let mut bytes = [0u8; HEADER_LEN];
+        self.source()?.read_at(0, &mut bytes)?;
+        let mut reader = Reader::new(&bytes);
+        if reader.take(4)? != MAGIC || reader.u16()? != FORMAT_VERSION {
+            return Err(VfsError::IoError);
+        }
+        let header = ImageHeader {
+            block_log: reader.u16()?,
+            entry_count: reader.u32()?,
+            block_count: reader.u32()?,
+            entry_table_offset: reader.u64()?,
+            block_table_offset: reader.u64()?,
+        };
+        if !(MIN_BLOCK_LOG..=MAX_BLOCK_LOG).contains(&header.block_log)
+            || header.block_table_offset < header.entry_table_offset
+        {
+            return Err(VfsError::IoError);
+        }
+        Ok(header)
+    }
+
+    fn read_entries(&mut self, header: &ImageHeader) -> Result<Vec<ImageEntry>, VfsError> {
+        let table_len = usize::try_from(header.block_table_offset - header.entry_table_offset)
+            .map_err(|_| VfsError::IoError)?;
+        let mut bytes = vec![0u8; table_len];
+        self.source()?.read_at(header.entry_table_offset, &mut bytes)?;
+
+        let block_size = 1u64 << header.block_log;
+        let mut reader = Reader::new(&bytes);
+        let mut entries: Vec<ImageEntry> = Vec::new();
+        for _ in 0..header.entry_count {
+            let kind = reader.u8()?;
+            let mode = reader.u16()?;
+            let path_len = reader.u16()? as usize;
+            let size = reader.u64()?;
+            let first_block = reader.u32()?;
+            let path = core::str::from_utf8(reader.take(path_len)?).map_err(|_| VfsError::IoError)?;
+
+            let file_type = match kind {
+                KIND_FILE => FileType::Regular,
+                KIND_DIRECTORY => FileType::Directory,
+                _ => return Err(VfsError::IoError),
+            };
+            // Lookups rely on the order; blocks must lie in the block table
+            let block_count = size.div_ceil(block_size);
+            if entries.last().is_some_and(|last| last.path.as_str() >= path)
+                || first_block as u64 + block_count > header.block_count as u64
+            {
+                return Err(VfsError::IoError);
+            }
+            entries.push(ImageEntry {
+                path: path.to_string(),
+                file_type,
+                permissions: FilePermissions::from_bits_truncate(mode),
+                size,
+                first_block,
+            });
+        }
+
+        if entries.first().is_none_or(|root| !root.path.is_empty() || root.file_type != FileType::Directory) {
+            return Err(VfsError::IoError);
+        }
+        Ok(entries)
+    }
+
+    fn lookup(&self, path: &str) -> Result<InodeNumber, VfsError> {
+        if self.header.is_none() {
+            return Err(VfsError::NotMounted);
+        }
+        let path = normalize(path);
+        self.entries.binary_search_by(|entry| entry.path.as_str().cmp(path))
+            .map(|index| index as InodeNumber + 1)
+            .map_err(|_| VfsError::NotFound)
+    }
+
+    fn entry(&self, inode: InodeNumber) -> Result<&ImageEntry, VfsError> {
+        inode.checked_sub(1)
+            .and_then(|index| self.entries.get(index as usize))
+            .ok_or(VfsError::NotFound)
+    }
+
+    fn metadata(&self, inode: InodeNumber) -> Result<FileMetadata, VfsError> {
+        let entry = self.entry(inode)?;
+        Ok(FileMetadata {
+            inode,
+            file_type: entry.file_type,
+            permissions: entry.permissions,
+            size: entry.size,
+            uid: 0,
+            gid: 0,
+            created_time: 0,
+            modified_time: 0,
+            accessed_time: 0,
+        })
+    }
+
+    /// Decompressed contents of block `index`
+    fn block(&mut self, index: u32) -> Result<&[u8], VfsError> {
+        if let Some(position) = self.cache.iter().position(|block| block.index == index) {
+            let block = self.cache.remove(position).ok_or(VfsError::IoError)?;
+            self.cache.push_back(block);
+        } else {
+            let data = self.load_block(index)?;
+            if self.cache.len() >= CACHED_BLOCKS {
+                self.cache.pop_front();
+            }
+            self.cache.push_back(CachedBlock { index, data });
+        }
+        Ok(&self.cache.back().ok_or(VfsError::IoError)?.data)
+    }
+
+    fn load_block(&mut self, index: u32) -> Result<Vec<u8>, VfsError> {
+        let header = self.header()?;
+        let block_size = 1usize << header.block_log;
+
+        let mut entry = [0u8; BLOCK_ENTRY_LEN];
+        self.source()?.read_at(header.block_table_offset + index as u64 * BLOCK_ENTRY_LEN as u64, &mut entry)?;
+        let mut reader = Reader::new(&entry);
+        let offset = reader.u64()?;
+        let stored = reader.u32()?;
+        let length = (stored & !UNCOMPRESSED) as usize;
+        if length > block_size + block_size / 2 {
+            return Err(VfsError::IoError);
+        }
+
+        let mut data = vec![0u8; length];
+        self.source()?.read_at(offset, &mut data)?;
+        if stored & UNCOMPRESSED != 0 {
+            if length > block_size {
+                return Err(VfsError::IoError);
+            }
+            return Ok(data);
+        }
+        compression::decompress(&data, block_size)
+    }
+}
+
+impl FileSystem for SystemImageFs {
+    fn init(&mut self) -> Result<(), VfsError> {
+        self.header = None;
+        self.entries.clear();
+        self.cache.clear();
+        Ok(())
+    }
+
+    /// Check the image and load its index
+    fn mount(&mut self, device_id: Option<u32>) -> Result<(), VfsError> {
+        if self.header.is_some() {
+            return Err(VfsError::MountPointBusy);
+        }
+        let header = self.read_header()?;
+        self.entries = self.read_entries(&header)?;
+        self.header = Some(header);
+        self.device_id = device_id;
+        Ok(())
+    }
+
+    fn unmount(&mut self) -> Result<(), VfsError> {
+        self.header()?;
+        self.init()?;
+        self.device_id = None;
+        Ok(())
+    }
+
+    fn open(&mut self, path: &str, flags: OpenFlags) -> Result<(InodeNumber, FileMetadata), VfsError> {
+        if flags.intersects(OpenFlags::WRITE_ONLY | OpenFlags::READ_WRITE | OpenFlags::TRUNCATE | OpenFlags::APPEND) {
+            return Err(VfsError::ReadOnlyFileSystem);
+        }
+        let inode = self.lookup(path)?;
+        Ok((inode, self.metadata(inode)?))
+    }
+
+    fn close(&mut self, inode: InodeNumber) -> Result<(), VfsError> {
+        self.entry(inode).map(|_| ())
+    }
+
+    fn read(&mut self, inode: InodeNumber, offset: FileOffset, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        let header = self.header()?;
+        let entry = self.entry(inode)?;
+        if entry.file_type == FileType::Directory {
+            return Err(VfsError::IsDirectory);
+        }
+        let (size, first_block) = (entry.size, entry.first_block);
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let length = (buffer.len() as u64).min(size - offset) as usize;
+        let block_mask = (1u64 << header.block_log) - 1;
+        let mut done = 0;
+        while done < length {
+            let position = offset + done as u64;
+            let index = first_block + (position >> header.block_log) as u32;
+            let within = (position & block_mask) as usize;
+            let data = self.block(index)?;
+            let count = (length - done).min(data.len().saturating_sub(within));
+            if count == 0 {
+                // The block is shorter than the file size says
+                return Err(VfsError::IoError);
+            }
+            buffer[done..done + count].copy_from_slice(&data[within..within + count]);
+            done += count;
+        }
+        Ok(length)
+    }
+
+    fn write(&mut self, _inode: InodeNumber, _offset: FileOffset, _buffer: &[u8]) -> Result<usize, VfsError> {
+        Err(VfsError::ReadOnlyFileSystem)
+    }
+
+    fn create(&mut self, _path: &str, _file_type: FileType, _permissions: FilePermissions) -> Result<InodeNumber, VfsError> {
+        Err(VfsError::ReadOnlyFileSystem)
+    }
+
+    fn unlink(&mut self, _path: &str) -> Result<(), VfsError> {
+        Err(VfsError::ReadOnlyFileSystem)
+    }
+
+    fn stat(&mut self, path: &str) -> Result<FileMetadata, VfsError> {
+        let inode = self.lookup(path)?;
+        self.metadata(inode)
+    }
+
+    fn readdir(&mut self, path: &str) -> Result<Vec<DirectoryEntry>, VfsError> {
+        let inode = self.lookup(path)?;
+        let directory = self.entry(inode)?;
+        if directory.file_type != FileType::Directory {
+            return Err(VfsError::NotDirectory);
+        }
+        let parent_inode = self.lookup(parent(&directory.path))?;
+
+        let mut entries = vec![
+            directory_entry(".", inode, FileType::Directory),
+            directory_entry("..", parent_inode, FileType::Directory),
+        ];
+        for (index, entry) in self.entries.iter().enumerate().skip(1) {
+            if parent(&entry.path) == directory.path {
+                let name = entry.path.rsplit('/').next().unwrap_or(&entry.path);
+                entries.push(directory_entry(name, index as InodeNumber + 1, entry.file_type));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn mkdir(&mut self, _path: &str, _permissions: FilePermissions) -> Result<(), VfsError> {
+        Err(VfsError::ReadOnlyFileSystem)
+    }
+
+    fn rmdir(&mut self, _path: &str) -> Result<(), VfsError> {
+        Err(VfsError::ReadOnlyFileSystem)
+    }
+
+    fn sync(&mut self) -> Result<(), VfsError> {
+        // Nothing is ever dirty
+        Ok(())
+    }
+}
+
+/// Image path for a path relative to the mount point
+fn normalize(path: &str) -> &str {
+    path.trim_matches('/')
+}
+
+/// Path of the directory containing `path`; the root is its own parent
+fn parent(path: &str) -> &str {
+    path.rfind('/').map_or("", |slash| &path[..slash])
+}
+
+pub(crate) fn directory_entry(name: &str, inode: InodeNumber, file_type: FileType) -> DirectoryEntry {
+    let length = name.len().min(255);
+    let mut buffer = [0u8; 256];
+    buffer[..length].copy_from_slice(&name.as_bytes()[..length]);
+    DirectoryEntry { name: buffer, name_len: length as u8, inode, file_type }
+}
+
+/// Little-endian cursor over image metadata
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], VfsError> {
+        let end = self.position.checked_add(count)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(VfsError::IoError)?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, VfsError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, VfsError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, VfsError> {
+        let mut raw = [0u8; 4];
+        raw.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(raw))
+    }
+
+    fn u64(&mut self) -> Result<u64, VfsError> {
+        let mut raw = [0u8; 8];
+        raw.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(raw))
+    }
+}
+
+struct BuilderEntry {
+    file_type: FileType,
+    permissions: FilePermissions,
+    data: Vec<u8>,
+}
+
+/// Builds system images, e.g. from the build host's staging directory
+pub struct ImageBuilder {
+    block_log: u16,
+    entries: BTreeMap<String, BuilderEntry>,
+}
+
+impl ImageBuilder {
+    /// Builder for an image with `DEFAULT_BLOCK_LOG` sized blocks
+    pub fn new() -> Self {
+        Self::with_block_log(DEFAULT_BLOCK_LOG)
+    }
+
+    /// Builder for an image with blocks of `1 << block_log` bytes
+    ///
+    /// The block size is clamped to what the format allows.
+    pub fn with_block_log(block_log: u16) -> Self {
+        let mut entries = BTreeMap::new();
+        entries.insert(String::new(), BuilderEntry {
+            file_type: FileType::Directory,
+            permissions: default_directory_permissions(),
+            data: Vec::new(),
+        });
+        Self { block_log: block_log.clamp(MIN_BLOCK_LOG, MAX_BLOCK_LOG), entries }
+    }
+
+    /// Add a directory, along with any missing parent directories
+    pub fn add_directory(&mut self, path: &str, permissions: FilePermissions) {
+        let path = normalize(path);
+        self.add_parents(path);
+        self.entries.insert(path.to_string(), BuilderEntry {
+            file_type: FileType::Directory,
+            permissions,
+            data: Vec::new(),
+        });
+    }
+
+    /// Add a file, along with any missing parent directories
+    pub fn add_file(&mut self, path: &str, permissions: FilePermissions, data: &[u8]) {
+        let path = normalize(path);
+        self.add_parents(path);
+        self.entries.insert(path.to_string(), BuilderEntry {
+            file_type: FileType::Regular,
+            permissions,
+            data: data.to_vec(),
+        });
+    }
+
+    fn add_parents(&mut self, path: &str) {
+        let mut directory = parent(path);
+        while !directory.is_empty() && !self.entries.contains_key(directory) {
+            self.entries.insert(directory.to_string(), BuilderEntry {
+                file_type: FileType::Directory,
+                permissions: default_directory_permissions(),
+                data: Vec::new(),
+            });
+            directory = parent(directory);
+        }
+    }
+
+    /// Encode the image
+    pub fn build(&self) -> Vec<u8> {
+        let block_size = 1usize << self.block_log;
+
+        // Compress every block first to learn the table sizes
+        let mut blocks: Vec<(Vec<u8>, bool)> = Vec::new();
+        let mut first_blocks = Vec::with_capacity(self.entries.len());
+        for entry in self.entries.values() {
+            first_blocks.push(blocks.len() as u32);
+            for chunk in entry.data.chunks(block_size) {
+                let compressed = compression::compress(chunk);
+                if compressed.len() < chunk.len() {
+                    blocks.push((compressed, true));
+                } else {
+                    blocks.push((chunk.to_vec(), false));
+                }
+            }
+        }
+
+        let mut entry_table = Vec::new();
+        for ((path, entry), first_block) in self.entries.iter().zip(first_blocks) {
+            let kind = if entry.file_type == FileType::Directory { KIND_DIRECTORY } else { KIND_FILE };
+            entry_table.push(kind);
+            entry_table.extend_from_slice(&entry.permissions.bits().to_le_bytes());
+            entry_table.extend_from_slice(&(path.len() as u16).to_le_bytes());
+            entry_table.extend_from_slice(&(entry.data.len() as u64).to_le_bytes());
+            entry_table.extend_from_slice(&first_block.to_le_bytes());
+            entry_table.extend_from_slice(path.as_bytes());
+        }
+
+        let entry_table_offset = HEADER_LEN as u64;
+        let block_table_offset = entry_table_offset + entry_table.len() as u64;
+        let mut data_offset = block_table_offset + (blocks.len() * BLOCK_ENTRY_LEN) as u64;
+
+        let mut image = Vec::new();
+        image.extend_from_slice(MAGIC);
+        image.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        image.extend_from_slice(&self.block_log.to_le_bytes());
+        image.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        image.extend_from_slice(&(blocks.len() as u32).to_le_bytes());
+        image.extend_from_slice(&entry_table_offset.to_le_bytes());
+        image.extend_from_slice(&block_table_offset.to_le_bytes());
+        image.extend_from_slice(&entry_table);
+
+        for (data, compressed) in &blocks {
+            let stored = data.len() as u32 | if *compressed { 0 } else { UNCOMPRESSED };
+            image.extend_from_slice(&data_offset.to_le_bytes());
+            image.extend_from_slice(&stored.to_le_bytes());
+            data_offset += data.len() as u64;
+        }
+        for (data, _) in &blocks {
+            image.extend_from_slice(data);
+        }
+        image
+    }
+}
+
+fn default_directory_permissions() -> FilePermissions {
+    FilePermissions::from_bits_truncate(0o755)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_image() -> (Vec<u8>, Vec<u8>) {
+        let font: Vec<u8> = (0..20000u32).map(|index| (index % 251) as u8).collect();
+        let mut builder = ImageBuilder::with_block_log(MIN_BLOCK_LOG);
+        builder.add_file("/bin/shell", FilePermissions::from_bits_truncate(0o755), b"\x7fELF shell");
+        builder.add_file("/share/fonts/default.psf", FilePermissions::from_bits_truncate(0o644), &font);
+        builder.add_directory("/etc", default_directory_permissions());
+        (builder.build(), font)
+    }
+
+    fn mounted(image: Vec<u8>) -> SystemImageFs {
+        let mut fs = SystemImageFs::with_source(Box::new(image));
+        assert!(fs.mount(Some(1)).is_ok());
+        fs
+    }
+
+    #[test]
+    fn test_read_across_blocks() {
+        let (image, font) = sample_image();
+        let mut fs = mounted(image);
+
+        let (inode, metadata) = fs.open("share/fonts/default.psf", OpenFlags::READ_ONLY).unwrap();
+        assert_eq!(metadata.size, font.len() as u64);
+
+        // Starts in the first block and ends in the third
+        let mut buffer = vec![0u8; 5000];
+        assert_eq!(fs.read(inode, 4000, &mut buffer), Ok(5000));
+        assert_eq!(&buffer[..], &font[4000..9000]);
+
+        // Short read at the end of the file
+        assert_eq!(fs.read(inode, font.len() as u64 - 10, &mut buffer), Ok(10));
+        assert_eq!(&buffer[..10], &font[font.len() - 10..]);
+        assert_eq!(fs.read(inode, font.len() as u64, &mut buffer), Ok(0));
+        assert!(fs.cache.len() <= CACHED_BLOCKS);
+    }
+
+    #[test]
+    fn test_directories() {
+        let (image, _) = sample_image();
+        let mut fs = mounted(image);
+
+        assert_eq!(fs.stat("/share/fonts").unwrap().file_type, FileType::Directory);
+        assert_eq!(fs.stat("/bin/missing").unwrap_err(), VfsError::NotFound);
+
+        let names: Vec<String> = fs.readdir("/").unwrap().iter()
+            .map(|entry| String::from_utf8_lossy(&entry.name[..entry.name_len as usize]).into_owned())
+            .collect();
+        assert_eq!(names, [".", "..", "bin", "etc", "share"]);
+        assert_eq!(fs.readdir("bin/shell").unwrap_err(), VfsError::NotDirectory);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let (image, _) = sample_image();
+        let mut fs = mounted(image);
+
+        assert_eq!(fs.open("bin/shell", OpenFlags::READ_WRITE).unwrap_err(), VfsError::ReadOnlyFileSystem);
+        assert_eq!(fs.create("tmp", FileType::Regular, FilePermissions::OWNER_READ), Err(VfsError::ReadOnlyFileSystem));
+        assert_eq!(fs.unlink("bin/shell"), Err(VfsError::ReadOnlyFileSystem));
+    }
+
+    #[test]
+    fn test_invalid_images_rejected() {
+        let mut fs = SystemImageFs::new();
+        assert_eq!(fs.mount(None), Err(VfsError::IoError));
+
+        let (mut image, _) = sample_image();
+        image[0] = b'X';
+        let mut fs = SystemImageFs::with_source(Box::new(image));
+        assert_eq!(fs.mount(None), Err(VfsError::IoError));
+
+        let (image, _) = sample_image();
+        let mut fs = SystemImageFs::with_source(Box::new(image[..HEADER_LEN + 10].to_vec()));
+        assert_eq!(fs.mount(None), Err(VfsError::IoError));
+    }
+}
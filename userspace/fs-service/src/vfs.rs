@@ -1,17 +1,43 @@
 use kosh_types::{
     FileDescriptor, InodeNumber, FileOffset, FileType, FilePermissions,
-    OpenFlags, FileMetadata, VfsError, DirectoryEntry
+    OpenFlags, FileMetadata, VfsError, DirectoryEntry, ProcessId
 };
-use crate::ext4::Ext4FileSystem;
+use crate::block::BlockDevice;
+use crate::cache::{BlockCache, CachedDevice, SharedBlockCache, DEFAULT_CACHE_BUDGET};
+use crate::ext4::{self, Ext4FileSystem};
+use crate::fat32::Fat32FileSystem;
+use crate::namespace::{normalize, strip_ancestor};
+use crate::sysimage::SystemImageFs;
+use crate::procfs::ProcFs;
+use crate::devfs::DevFs;
 use alloc::{vec, vec::Vec, string::{String, ToString}, collections::BTreeMap, boxed::Box};
 use core::result::Result;
 
+/// Identifies a mounted file system instance; bind mounts share the ID of
+/// the mount they show part of
+pub type FileSystemId = u32;
+
 /// Virtual File System abstraction layer
+///
+/// Mount points are kept by path, and a path belongs to the mount point
+/// that is its longest ancestor, so a file system mounted at `/mnt` hides
+/// whatever lies below `/mnt` in the one mounted at `/`. A file system
+/// instance is unmounted when the last mount point showing it goes.
+///
+/// Symbolic links are followed wherever they appear in a path, across
+/// mounts; absolute targets start again from the root. Relative paths are
+/// taken against the working directory of the process they come from.
+///
+/// Devices of the file systems it mounts share one block cache.
 pub struct Vfs {
     mount_points: BTreeMap<String, MountPoint>,
-    file_systems: BTreeMap<String, Box<dyn FileSystem>>,
+    file_systems: BTreeMap<FileSystemId, Box<dyn FileSystem>>,
     open_files: BTreeMap<FileDescriptor, OpenFile>,
     next_fd: FileDescriptor,
+    next_filesystem_id: FileSystemId,
+    cache: SharedBlockCache,
+    /// Working directories of processes that changed theirs from `/`
+    working_directories: BTreeMap<ProcessId, String>,
 }
 
 /// Mount point information
@@ -21,6 +47,11 @@ pub struct MountPoint {
     pub filesystem: FileSystemType,
     pub read_only: bool,
     pub device_id: Option<u32>,
+    /// File system instance shown here
+    pub filesystem_id: FileSystemId,
+    /// Directory of the file system shown at `path`; `/` except for bind
+    /// mounts
+    pub root: String,
 }
 
 /// File system type identifier
@@ -30,16 +61,117 @@ pub enum FileSystemType {
     TmpFs,
     ProcFs,
     DevFs,
+    /// Compressed read-only system image, see `sysimage`
+    SystemImage,
+    /// Boot partitions and removable media, see `fat32`
+    Fat32,
+}
+
+impl FileSystemType {
+    /// Type for its name in mount requests
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ext4" => Some(FileSystemType::Ext4),
+            "tmpfs" => Some(FileSystemType::TmpFs),
+            "proc" => Some(FileSystemType::ProcFs),
+            "devfs" => Some(FileSystemType::DevFs),
+            "sysimage" => Some(FileSystemType::SystemImage),
+            "vfat" | "fat32" => Some(FileSystemType::Fat32),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FileSystemType::Ext4 => "ext4",
+            FileSystemType::TmpFs => "tmpfs",
+            FileSystemType::ProcFs => "proc",
+            FileSystemType::DevFs => "devfs",
+            FileSystemType::SystemImage => "sysimage",
+            FileSystemType::Fat32 => "vfat",
+        }
+    }
 }
 
 /// Open file descriptor information
 #[derive(Debug, Clone)]
 pub struct OpenFile {
     pub inode: InodeNumber,
+    /// Path of the mount point the file was opened through
     pub mount_point: String,
+    pub filesystem_id: FileSystemId,
     pub flags: OpenFlags,
     pub offset: FileOffset,
     pub metadata: FileMetadata,
+    /// Descriptor `open` returned; `dup` copies share it, and with it
+    /// their offset
+    pub description: FileDescriptor,
+}
+
+/// Where `Vfs::seek` moves a descriptor's offset from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(FileOffset),
+    Current(i64),
+    End(i64),
+}
+
+impl SeekFrom {
+    /// The position `lseek` asks for with `offset` and `whence`
+    pub fn from_whence(offset: i64, whence: i32) -> Option<Self> {
+        match whence {
+            kosh_posix::SEEK_SET => u64::try_from(offset).ok().map(SeekFrom::Start),
+            kosh_posix::SEEK_CUR => Some(SeekFrom::Current(offset)),
+            kosh_posix::SEEK_END => Some(SeekFrom::End(offset)),
+            _ => None,
+        }
+    }
+}
+
+/// Whether descriptors opened with `flags` may be read and written
+fn access(flags: OpenFlags) -> (bool, bool) {
+    if flags.contains(OpenFlags::READ_WRITE) {
+        (true, true)
+    } else if flags.contains(OpenFlags::WRITE_ONLY) {
+        (false, true)
+    } else {
+        (true, false)
+    }
+}
+
+/// Where a path leads: a file system and the path within it
+#[derive(Debug, Clone)]
+struct ResolvedPath {
+    mount_path: String,
+    filesystem_id: FileSystemId,
+    read_only: bool,
+    path: String,
+}
+
+/// How `Vfs::clone_file` produced a copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloneMethod {
+    /// The copy shares the source's blocks copy-on-write
+    Reflink,
+    /// The data was copied
+    Copy,
+}
+
+/// Bytes moved per step when a clone falls back to copying
+const COPY_CHUNK_SIZE: usize = 4096;
+
+/// Symbolic links one lookup follows before it gives up on a loop
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+/// Seconds since the epoch, for file system timestamps
+#[cfg(not(test))]
+pub(crate) fn now() -> u32 {
+    kosh_posix::clock_gettime(kosh_posix::CLOCK_REALTIME).map_or(0, |time| time.tv_sec as u32)
+}
+
+#[cfg(test)]
+pub(crate) fn now() -> u32 {
+    0
 }
 
 /// File system interface trait that all file systems must implement
@@ -85,6 +217,37 @@ pub trait FileSystem {
     
     /// Sync file system data to storage
     fn sync(&mut self) -> Result<(), VfsError>;
+    
+    /// Current size of the open file `inode`, for appends and seeks from
+    /// the end
+    ///
+    /// File systems whose open files never change size keep the default,
+    /// and the VFS uses the size the file had when it was opened.
+    fn size(&mut self, _inode: InodeNumber) -> Result<FileOffset, VfsError> {
+        Err(VfsError::NotSupported)
+    }
+    
+    /// Create a symbolic link at `path` pointing to `target`
+    fn symlink(&mut self, _path: &str, _target: &str) -> Result<InodeNumber, VfsError> {
+        Err(VfsError::NotSupported)
+    }
+    
+    /// Target of the symbolic link at `path`
+    ///
+    /// Paths given to a file system have no links left in them but this
+    /// last one.
+    fn readlink(&mut self, _path: &str) -> Result<String, VfsError> {
+        Err(VfsError::NotSupported)
+    }
+    
+    /// Create `destination` sharing the data blocks of the regular file
+    /// `source`, copying a block only when either file writes to it
+    ///
+    /// File systems without shared blocks keep the default, and the VFS
+    /// copies the data instead.
+    fn reflink(&mut self, _source: &str, _destination: &str) -> Result<InodeNumber, VfsError> {
+        Err(VfsError::NotSupported)
+    }
 }
 
 impl Vfs {
@@ -95,9 +258,24 @@ impl Vfs {
             file_systems: BTreeMap::new(),
             open_files: BTreeMap::new(),
             next_fd: 1, // Start from 1, 0 is reserved
+            next_filesystem_id: 1,
+            cache: BlockCache::shared(DEFAULT_CACHE_BUDGET),
+            working_directories: BTreeMap::new(),
         }
     }
     
+    /// Put `device` behind the block cache file systems share
+    ///
+    /// For devices handed to `mount_filesystem`.
+    pub fn cache_device(&self, device: Box<dyn BlockDevice>) -> CachedDevice {
+        BlockCache::attach(&self.cache, device)
+    }
+    
+    /// The block cache, e.g. to change its budget or read its statistics
+    pub fn block_cache(&self) -> &SharedBlockCache {
+        &self.cache
+    }
+    
     /// Mount a file system at the specified path
     pub fn mount(&mut self, path: &str, fs_type: FileSystemType, device_id: Option<u32>, read_only: bool) -> Result<(), VfsError> {
         // Validate mount path
@@ -106,105 +284,295 @@ impl Vfs {
         }
         
         // Check if already mounted
-        if self.mount_points.contains_key(path) {
+        if self.mount_points.contains_key(&normalize(path)?) {
             return Err(VfsError::MountPointBusy);
         }
         
         // Create the appropriate file system instance
-        let mut filesystem: Box<dyn FileSystem> = match fs_type {
-            FileSystemType::Ext4 => Box::new(Ext4FileSystem::new()),
+        let filesystem: Box<dyn FileSystem> = match fs_type {
+            FileSystemType::Ext4 => {
+                let disk = self.cache_device(Box::new(ext4::ram_disk()?));
+                Box::new(Ext4FileSystem::with_device(Box::new(disk)))
+            }
+            // Needs its image source; see mount_filesystem
+            FileSystemType::SystemImage => Box::new(SystemImageFs::new()),
+            FileSystemType::ProcFs => Box::new(ProcFs::new()),
+            FileSystemType::DevFs => Box::new(DevFs::new()),
+            // Needs its device; see mount_filesystem
+            FileSystemType::Fat32 => Box::new(Fat32FileSystem::new()),
             _ => return Err(VfsError::IoError), // Other file systems not implemented yet
         };
         
+        self.mount_filesystem(path, fs_type, filesystem, device_id, read_only)
+    }
+    
+    /// Mount an already created file system instance at `path`
+    ///
+    /// Used for file systems that need more than a device ID to find their
+    /// data, such as a system image on one of the A/B system slots. System
+    /// images and procfs are always mounted read-only.
+    pub fn mount_filesystem(&mut self, path: &str, fs_type: FileSystemType, mut filesystem: Box<dyn FileSystem>, device_id: Option<u32>, read_only: bool) -> Result<(), VfsError> {
+        let path = self.check_mount_target(path)?;
+        
         // Initialize and mount the file system
         filesystem.init()?;
         filesystem.mount(device_id)?;
         
+        let filesystem_id = self.next_filesystem_id;
+        self.next_filesystem_id += 1;
+        
         let mount_point = MountPoint {
-            path: path.to_string(),
+            path: path.clone(),
             filesystem: fs_type,
-            read_only,
+            read_only: read_only || matches!(fs_type, FileSystemType::SystemImage | FileSystemType::ProcFs),
             device_id,
+            filesystem_id,
+            root: String::from("/"),
         };
         
         // Store both the mount point and the file system instance
-        self.mount_points.insert(path.to_string(), mount_point);
-        self.file_systems.insert(path.to_string(), filesystem);
+        self.mount_points.insert(path, mount_point);
+        self.file_systems.insert(filesystem_id, filesystem);
         
         Ok(())
     }
     
+    /// Show the directory `source` again at `path`
+    ///
+    /// Both paths then lead to the same files. A bind mount of a read-only
+    /// mount is read-only too; one of a writable mount may be made
+    /// read-only.
+    pub fn bind_mount(&mut self, source: &str, path: &str, read_only: bool) -> Result<(), VfsError> {
+        let resolved = self.lookup(source, true)?;
+        if self.filesystem(resolved.filesystem_id)?.stat(&resolved.path)?.file_type != FileType::Directory {
+            return Err(VfsError::NotDirectory);
+        }
+        let path = self.check_mount_target(path)?;
+        
+        let source_mount = &self.mount_points[&resolved.mount_path];
+        let mount_point = MountPoint {
+            path: path.clone(),
+            filesystem: source_mount.filesystem,
+            read_only: read_only || resolved.read_only,
+            device_id: source_mount.device_id,
+            filesystem_id: resolved.filesystem_id,
+            root: resolved.path,
+        };
+        self.mount_points.insert(path, mount_point);
+        Ok(())
+    }
+    
+    /// Normalise a path to mount something at and check it is free
+    ///
+    /// Below an existing mount the path has to be a directory there. Only
+    /// the first mount may go where nothing is mounted yet.
+    fn check_mount_target(&mut self, path: &str) -> Result<String, VfsError> {
+        let path = normalize(path)?;
+        if self.mount_points.contains_key(&path) {
+            return Err(VfsError::MountPointBusy);
+        }
+        if let Ok(parent) = self.resolve(&path) {
+            if self.filesystem(parent.filesystem_id)?.stat(&parent.path)?.file_type != FileType::Directory {
+                return Err(VfsError::NotDirectory);
+            }
+        }
+        Ok(path)
+    }
+    
     /// Unmount a file system
+    ///
+    /// Fails while files are open through the mount or other mounts lie
+    /// below it.
     pub fn unmount(&mut self, path: &str) -> Result<(), VfsError> {
+        let path = normalize(path)?;
+        let filesystem_id = self.mount_points.get(&path)
+            .ok_or(VfsError::NotMounted)?
+            .filesystem_id;
+        
         // Check if any files are still open from this mount point
-        for open_file in self.open_files.values() {
-            if open_file.mount_point == path {
-                return Err(VfsError::MountPointBusy);
-            }
+        if self.open_files.values().any(|open_file| open_file.mount_point == path) {
+            return Err(VfsError::MountPointBusy);
+        }
+        if self.mount_points.keys().any(|other| *other != path && strip_ancestor(other, &path).is_some()) {
+            return Err(VfsError::MountPointBusy);
         }
         
-        // Unmount the file system
-        if let Some(mut filesystem) = self.file_systems.remove(path) {
-            filesystem.unmount()?;
+        // The file system goes with the last mount showing it
+        let shared = self.mount_points.values()
+            .any(|mount_point| mount_point.path != path && mount_point.filesystem_id == filesystem_id);
+        if !shared {
+            if let Some(filesystem) = self.file_systems.get_mut(&filesystem_id) {
+                filesystem.unmount()?;
+            }
+            self.file_systems.remove(&filesystem_id);
         }
         
-        self.mount_points.remove(path)
+        self.mount_points.remove(&path);
+        Ok(())
+    }
+    
+    /// Find the file system and the path within it that `path` leads to
+    ///
+    /// The mount point that is the longest ancestor of `path` wins.
+    fn resolve(&self, path: &str) -> Result<ResolvedPath, VfsError> {
+        let path = normalize(path)?;
+        let (mount_point, rest) = self.mount_points.values()
+            .filter_map(|mount_point| strip_ancestor(&path, &mount_point.path).map(|rest| (mount_point, rest)))
+            .max_by_key(|(mount_point, _)| mount_point.path.len())
             .ok_or(VfsError::NotMounted)?;
         
-        Ok(())
+        let inner = match (mount_point.root.as_str(), rest) {
+            (root, "") => root.to_string(),
+            ("/", rest) => rest.to_string(),
+            (root, rest) => alloc::format!("{}{}", root, rest),
+        };
+        Ok(ResolvedPath {
+            mount_path: mount_point.path.clone(),
+            filesystem_id: mount_point.filesystem_id,
+            read_only: mount_point.read_only,
+            path: inner,
+        })
     }
     
-    /// Find the mount point for a given path
-    fn find_mount_point(&self, path: &str) -> Result<&MountPoint, VfsError> {
-        let mut best_match = "";
-        let mut best_mount = None;
+    /// `path` with every symbolic link in it followed, and `.` and `..`
+    /// resolved
+    ///
+    /// The last component is only followed with `follow_last`, for calls
+    /// that act on a link itself. Components that do not exist are kept as
+    /// they are, for the caller to fail on or create.
+    fn follow_links(&mut self, path: &str, follow_last: bool) -> Result<String, VfsError> {
+        if !path.starts_with('/') {
+            return Err(VfsError::InvalidPath);
+        }
         
-        for (mount_path, mount_point) in &self.mount_points {
-            if path.starts_with(mount_path) && mount_path.len() > best_match.len() {
-                best_match = mount_path;
-                best_mount = Some(mount_point);
+        fn push_components(pending: &mut Vec<String>, path: &str) {
+            pending.extend(path.split('/')
+                .filter(|component| !component.is_empty() && *component != ".")
+                .rev()
+                .map(String::from));
+        }
+        fn join(components: &[String]) -> String {
+            if components.is_empty() {
+                return String::from("/");
             }
+            components.iter().fold(String::new(), |path, component| path + "/" + component)
         }
         
-        best_mount.ok_or(VfsError::NotMounted)
+        // Components still to walk, the next one last
+        let mut pending = Vec::new();
+        push_components(&mut pending, path);
+        let mut walked: Vec<String> = Vec::new();
+        let mut follows = 0;
+        while let Some(component) = pending.pop() {
+            if component == ".." {
+                walked.pop();
+                continue;
+            }
+            walked.push(component);
+            if pending.is_empty() && !follow_last {
+                break;
+            }
+            
+            let Ok(resolved) = self.resolve(&join(&walked)) else { continue };
+            let filesystem = self.filesystem(resolved.filesystem_id)?;
+            if !matches!(filesystem.stat(&resolved.path), Ok(metadata) if metadata.file_type == FileType::SymbolicLink) {
+                continue;
+            }
+            follows += 1;
+            if follows > MAX_SYMLINK_FOLLOWS {
+                return Err(VfsError::SymlinkLoop);
+            }
+            let target = filesystem.readlink(&resolved.path)?;
+            
+            // The target replaces the link, relative to the link's directory
+            walked.pop();
+            if target.starts_with('/') {
+                walked.clear();
+            }
+            push_components(&mut pending, &target);
+        }
+        Ok(join(&walked))
+    }
+    
+    /// Find what `path` leads to after following its symbolic links
+    fn lookup(&mut self, path: &str, follow_last: bool) -> Result<ResolvedPath, VfsError> {
+        let path = self.follow_links(path, follow_last)?;
+        self.resolve(&path)
+    }
+    
+    /// Look up a path that is about to be modified
+    fn lookup_writable(&mut self, path: &str, follow_last: bool) -> Result<ResolvedPath, VfsError> {
+        let resolved = self.lookup(path, follow_last)?;
+        if resolved.read_only {
+            return Err(VfsError::ReadOnlyFileSystem);
+        }
+        Ok(resolved)
+    }
+    
+    /// The absolute path, free of `.`, `..` and symbolic links, of what
+    /// `path` leads to
+    pub fn canonicalize(&mut self, path: &str) -> Result<String, VfsError> {
+        self.follow_links(path, true)
+    }
+    
+    /// `path` made absolute against the working directory of `pid`
+    ///
+    /// The result is for the other calls to resolve, not yet normalised.
+    pub fn absolute(&self, pid: ProcessId, path: &str) -> String {
+        if path.starts_with('/') {
+            return String::from(path);
+        }
+        alloc::format!("{}/{}", self.getcwd(pid), path)
+    }
+    
+    /// Working directory of `pid`; `/` until it changes it
+    pub fn getcwd(&self, pid: ProcessId) -> String {
+        self.working_directories.get(&pid).cloned().unwrap_or_else(|| String::from("/"))
+    }
+    
+    /// Make the directory `path` leads to the working directory of `pid`
+    pub fn chdir(&mut self, pid: ProcessId, path: &str) -> Result<(), VfsError> {
+        let path = self.canonicalize(&self.absolute(pid, path))?;
+        if self.stat(&path)?.file_type != FileType::Directory {
+            return Err(VfsError::NotDirectory);
+        }
+        self.set_cwd(pid, path);
+        Ok(())
+    }
+    
+    /// Record `path` as the working directory of `pid` without checking it
+    ///
+    /// For callers that keep paths in a view of their own; see `namespace`.
+    pub fn set_cwd(&mut self, pid: ProcessId, path: String) {
+        if path == "/" {
+            self.working_directories.remove(&pid);
+        } else {
+            self.working_directories.insert(pid, path);
+        }
     }
     
     /// Open a file and return a file descriptor
     pub fn open(&mut self, path: &str, flags: OpenFlags) -> Result<FileDescriptor, VfsError> {
-        let mount_point = self.find_mount_point(path)?;
+        let resolved = self.lookup(path, true)?;
         
         // Check read-only mount for write operations
-        if mount_point.read_only && (flags == OpenFlags::WRITE_ONLY || flags == OpenFlags::READ_WRITE) {
+        if resolved.read_only && access(flags).1 {
             return Err(VfsError::ReadOnlyFileSystem);
         }
         
-        // Clone the mount point path to avoid borrowing issues
-        let mount_path = mount_point.path.clone();
-        
-        // Get the file system and delegate the open operation
-        let filesystem = self.file_systems.get_mut(&mount_path)
-            .ok_or(VfsError::NotMounted)?;
-        
-        // Convert absolute path to relative path within the file system
-        let relative_path = if path == &mount_path {
-            "/"
-        } else if path.starts_with(&mount_path) {
-            &path[mount_path.len()..]
-        } else {
-            path
-        };
-        
-        let (inode, metadata) = filesystem.open(relative_path, flags)?;
+        let (inode, metadata) = self.filesystem(resolved.filesystem_id)?.open(&resolved.path, flags)?;
         
         let fd = self.next_fd;
         self.next_fd += 1;
         
         let open_file = OpenFile {
             inode,
-            mount_point: mount_path,
+            mount_point: resolved.mount_path,
+            filesystem_id: resolved.filesystem_id,
             flags,
             offset: 0,
             metadata,
+            description: fd,
         };
         
         self.open_files.insert(fd, open_file);
@@ -212,207 +580,289 @@ impl Vfs {
     }
     
     /// Close a file descriptor
+    ///
+    /// The file system closes the file with the last descriptor `dup`
+    /// made of it.
     pub fn close(&mut self, fd: FileDescriptor) -> Result<(), VfsError> {
         let open_file = self.open_files.remove(&fd)
             .ok_or(VfsError::InvalidFileDescriptor)?;
+        if self.open_files.values().any(|other| other.description == open_file.description) {
+            return Ok(());
+        }
         
         // Delegate to the file system
-        let filesystem = self.file_systems.get_mut(&open_file.mount_point)
-            .ok_or(VfsError::NotMounted)?;
-        
-        filesystem.close(open_file.inode)?;
+        self.filesystem(open_file.filesystem_id)?.close(open_file.inode)?;
         Ok(())
     }
     
+    /// New descriptor for the file `fd` is open on, sharing its offset
+    pub fn dup(&mut self, fd: FileDescriptor) -> Result<FileDescriptor, VfsError> {
+        let open_file = self.open_files.get(&fd)
+            .ok_or(VfsError::InvalidFileDescriptor)?
+            .clone();
+        
+        let new_fd = self.next_fd;
+        self.next_fd += 1;
+        self.open_files.insert(new_fd, open_file);
+        Ok(new_fd)
+    }
+    
+    /// Move the offset of `fd`, and of the descriptors sharing it, and
+    /// return where it ended up
+    ///
+    /// The offset may go past the end of the file; a later write there
+    /// leaves a gap that reads as zeros.
+    pub fn seek(&mut self, fd: FileDescriptor, position: SeekFrom) -> Result<FileOffset, VfsError> {
+        let open_file = self.open_files.get(&fd)
+            .ok_or(VfsError::InvalidFileDescriptor)?;
+        
+        let offset = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => open_file.offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.size(fd)?.checked_add_signed(delta),
+        };
+        let offset = offset.ok_or(VfsError::InvalidArgument)?;
+        self.set_offset(fd, offset);
+        Ok(offset)
+    }
+    
+    /// Current size of the file `fd` is open on
+    fn size(&mut self, fd: FileDescriptor) -> Result<FileOffset, VfsError> {
+        let open_file = self.open_files.get(&fd)
+            .ok_or(VfsError::InvalidFileDescriptor)?;
+        let (filesystem_id, inode, size_at_open) = (open_file.filesystem_id, open_file.inode, open_file.metadata.size);
+        
+        match self.filesystem(filesystem_id)?.size(inode) {
+            Err(VfsError::NotSupported) => Ok(size_at_open),
+            result => result,
+        }
+    }
+    
+    /// Give `fd` and the descriptors sharing its offset the offset `offset`
+    fn set_offset(&mut self, fd: FileDescriptor, offset: FileOffset) {
+        let Some(description) = self.open_files.get(&fd).map(|open_file| open_file.description) else { return };
+        for open_file in self.open_files.values_mut().filter(|open_file| open_file.description == description) {
+            open_file.offset = offset;
+        }
+    }
+    
     /// Read from a file descriptor
     pub fn read(&mut self, fd: FileDescriptor, buffer: &mut [u8]) -> Result<usize, VfsError> {
-        let open_file = self.open_files.get_mut(&fd)
+        let open_file = self.open_files.get(&fd)
             .ok_or(VfsError::InvalidFileDescriptor)?;
         
         // Check if file is open for reading
-        if open_file.flags == OpenFlags::WRITE_ONLY {
+        if !access(open_file.flags).0 {
             return Err(VfsError::PermissionDenied);
         }
         
         // Get the file system and delegate the read operation
-        let filesystem = self.file_systems.get_mut(&open_file.mount_point)
+        let offset = open_file.offset;
+        let filesystem = self.file_systems.get_mut(&open_file.filesystem_id)
             .ok_or(VfsError::NotMounted)?;
         
-        let bytes_read = filesystem.read(open_file.inode, open_file.offset, buffer)?;
+        let bytes_read = filesystem.read(open_file.inode, offset, buffer)?;
         
         // Update the file offset
-        open_file.offset += bytes_read as u64;
+        self.set_offset(fd, offset + bytes_read as u64);
         
         Ok(bytes_read)
     }
     
-    /// Write to a file descriptor
-    pub fn write(&mut self, fd: FileDescriptor, buffer: &[u8]) -> Result<usize, VfsError> {
-        let open_file = self.open_files.get_mut(&fd)
+    /// Read from a file descriptor at `offset`, leaving its offset alone
+    pub fn read_at(&mut self, fd: FileDescriptor, offset: FileOffset, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        let open_file = self.open_files.get(&fd)
             .ok_or(VfsError::InvalidFileDescriptor)?;
         
-        // Check if file is open for writing
-        if open_file.flags == OpenFlags::READ_ONLY {
+        if !access(open_file.flags).0 {
             return Err(VfsError::PermissionDenied);
         }
         
-        // Get the file system and delegate the write operation
-        let filesystem = self.file_systems.get_mut(&open_file.mount_point)
+        let filesystem = self.file_systems.get_mut(&open_file.filesystem_id)
             .ok_or(VfsError::NotMounted)?;
         
-        let bytes_written = filesystem.write(open_file.inode, open_file.offset, buffer)?;
-        
-        // Update the file offset
-        open_file.offset += bytes_written as u64;
-        
-        Ok(bytes_written)
+        filesystem.read(open_file.inode, offset, buffer)
     }
     
-    /// Get file metadata
-    pub fn stat(&mut self, path: &str) -> Result<FileMetadata, VfsError> {
-        let mount_point = self.find_mount_point(path)?;
-        let mount_path = mount_point.path.clone();
+    /// Write to a file descriptor
+    ///
+    /// Descriptors opened with `APPEND` write at the end of the file,
+    /// wherever their offset was.
+    pub fn write(&mut self, fd: FileDescriptor, buffer: &[u8]) -> Result<usize, VfsError> {
+        let open_file = self.open_files.get(&fd)
+            .ok_or(VfsError::InvalidFileDescriptor)?;
         
-        // Get the file system and delegate the stat operation
-        let filesystem = self.file_systems.get_mut(&mount_path)
-            .ok_or(VfsError::NotMounted)?;
+        // Check if file is open for writing
+        if !access(open_file.flags).1 {
+            return Err(VfsError::PermissionDenied);
+        }
         
-        // Convert absolute path to relative path within the file system
-        let relative_path = if path == &mount_path {
-            "/"
-        } else if path.starts_with(&mount_path) {
-            &path[mount_path.len()..]
+        let offset = if open_file.flags.contains(OpenFlags::APPEND) {
+            self.size(fd)?
         } else {
-            path
+            open_file.offset
         };
+        let bytes_written = self.write_at(fd, offset, buffer)?;
+        
+        // Update the file offset
+        self.set_offset(fd, offset + bytes_written as u64);
         
-        filesystem.stat(relative_path)
+        Ok(bytes_written)
     }
     
-    /// Create a new file
-    pub fn create(&mut self, path: &str, file_type: FileType, permissions: FilePermissions) -> Result<(), VfsError> {
-        let mount_point = self.find_mount_point(path)?;
+    /// Write to a file descriptor at `offset`, leaving its offset alone
+    pub fn write_at(&mut self, fd: FileDescriptor, offset: FileOffset, buffer: &[u8]) -> Result<usize, VfsError> {
+        let open_file = self.open_files.get(&fd)
+            .ok_or(VfsError::InvalidFileDescriptor)?;
         
-        if mount_point.read_only {
-            return Err(VfsError::ReadOnlyFileSystem);
+        if !access(open_file.flags).1 {
+            return Err(VfsError::PermissionDenied);
         }
         
-        let mount_path = mount_point.path.clone();
-        
-        // Get the file system and delegate the create operation
-        let filesystem = self.file_systems.get_mut(&mount_path)
+        let filesystem = self.file_systems.get_mut(&open_file.filesystem_id)
             .ok_or(VfsError::NotMounted)?;
         
-        // Convert absolute path to relative path within the file system
-        let relative_path = if path == &mount_path {
-            "/"
-        } else if path.starts_with(&mount_path) {
-            &path[mount_path.len()..]
-        } else {
-            path
-        };
-        
-        filesystem.create(relative_path, file_type, permissions)?;
+        filesystem.write(open_file.inode, offset, buffer)
+    }
+    
+    /// Get file metadata
+    pub fn stat(&mut self, path: &str) -> Result<FileMetadata, VfsError> {
+        let resolved = self.lookup(path, true)?;
+        self.filesystem(resolved.filesystem_id)?.stat(&resolved.path)
+    }
+    
+    /// Get metadata of `path` itself, even if it is a symbolic link
+    pub fn lstat(&mut self, path: &str) -> Result<FileMetadata, VfsError> {
+        let resolved = self.lookup(path, false)?;
+        self.filesystem(resolved.filesystem_id)?.stat(&resolved.path)
+    }
+    
+    /// Create a new file
+    pub fn create(&mut self, path: &str, file_type: FileType, permissions: FilePermissions) -> Result<(), VfsError> {
+        let resolved = self.lookup_writable(path, false)?;
+        self.filesystem(resolved.filesystem_id)?.create(&resolved.path, file_type, permissions)?;
         Ok(())
     }
     
-    /// Delete a file
+    /// Create a symbolic link at `path` pointing to `target`
+    ///
+    /// `target` is stored as given and need not exist.
+    pub fn symlink(&mut self, target: &str, path: &str) -> Result<(), VfsError> {
+        let resolved = self.lookup_writable(path, false)?;
+        self.filesystem(resolved.filesystem_id)?.symlink(&resolved.path, target)?;
+        Ok(())
+    }
+    
+    /// Target of the symbolic link at `path`
+    pub fn readlink(&mut self, path: &str) -> Result<String, VfsError> {
+        let resolved = self.lookup(path, false)?;
+        self.filesystem(resolved.filesystem_id)?.readlink(&resolved.path)
+    }
+    
+    /// Delete a file; a symbolic link is removed, not what it points to
     pub fn unlink(&mut self, path: &str) -> Result<(), VfsError> {
-        let mount_point = self.find_mount_point(path)?;
-        
-        if mount_point.read_only {
-            return Err(VfsError::ReadOnlyFileSystem);
-        }
-        
-        let mount_path = mount_point.path.clone();
-        
-        // Get the file system and delegate the unlink operation
-        let filesystem = self.file_systems.get_mut(&mount_path)
-            .ok_or(VfsError::NotMounted)?;
-        
-        // Convert absolute path to relative path within the file system
-        let relative_path = if path == &mount_path {
-            "/"
-        } else if path.starts_with(&mount_path) {
-            &path[mount_path.len()..]
-        } else {
-            path
-        };
-        
-        filesystem.unlink(relative_path)
+        let resolved = self.lookup_writable(path, false)?;
+        self.filesystem(resolved.filesystem_id)?.unlink(&resolved.path)
     }
     
     /// Read directory entries
     pub fn readdir(&mut self, path: &str) -> Result<Vec<DirectoryEntry>, VfsError> {
-        let mount_point = self.find_mount_point(path)?;
-        let mount_path = mount_point.path.clone();
-        
-        // Get the file system and delegate the readdir operation
-        let filesystem = self.file_systems.get_mut(&mount_path)
-            .ok_or(VfsError::NotMounted)?;
-        
-        // Convert absolute path to relative path within the file system
-        let relative_path = if path == &mount_path {
-            "/"
-        } else if path.starts_with(&mount_path) {
-            &path[mount_path.len()..]
-        } else {
-            path
-        };
-        
-        filesystem.readdir(relative_path)
+        let resolved = self.lookup(path, true)?;
+        self.filesystem(resolved.filesystem_id)?.readdir(&resolved.path)
     }
     
     /// Create a directory
     pub fn mkdir(&mut self, path: &str, permissions: FilePermissions) -> Result<(), VfsError> {
-        let mount_point = self.find_mount_point(path)?;
-        
-        if mount_point.read_only {
-            return Err(VfsError::ReadOnlyFileSystem);
-        }
-        
-        let mount_path = mount_point.path.clone();
-        
-        // Get the file system and delegate the mkdir operation
-        let filesystem = self.file_systems.get_mut(&mount_path)
-            .ok_or(VfsError::NotMounted)?;
-        
-        // Convert absolute path to relative path within the file system
-        let relative_path = if path == &mount_path {
-            "/"
-        } else if path.starts_with(&mount_path) {
-            &path[mount_path.len()..]
-        } else {
-            path
-        };
-        
-        filesystem.mkdir(relative_path, permissions)
+        let resolved = self.lookup_writable(path, false)?;
+        self.filesystem(resolved.filesystem_id)?.mkdir(&resolved.path, permissions)
     }
     
     /// Remove a directory
     pub fn rmdir(&mut self, path: &str) -> Result<(), VfsError> {
-        let mount_point = self.find_mount_point(path)?;
-        
-        if mount_point.read_only {
-            return Err(VfsError::ReadOnlyFileSystem);
+        let resolved = self.lookup_writable(path, false)?;
+        self.filesystem(resolved.filesystem_id)?.rmdir(&resolved.path)
+    }
+    
+    /// Create `destination` as a copy of the regular file `source`
+    ///
+    /// Within a file system that supports reflinks the copy shares blocks
+    /// with the source copy-on-write, so it is cheap however large the file
+    /// is. Otherwise, including across file systems, the data is copied.
+    /// `destination` must not exist yet.
+    pub fn clone_file(&mut self, source: &str, destination: &str) -> Result<CloneMethod, VfsError> {
+        let metadata = self.stat(source)?;
+        if metadata.file_type == FileType::Directory {
+            return Err(VfsError::IsDirectory);
         }
         
-        let mount_path = mount_point.path.clone();
+        let source = self.lookup(source, true)?;
+        let destination = self.lookup_writable(destination, false)?;
+        let (source_fs, destination_fs) = (source.filesystem_id, destination.filesystem_id);
         
-        // Get the file system and delegate the rmdir operation
-        let filesystem = self.file_systems.get_mut(&mount_path)
-            .ok_or(VfsError::NotMounted)?;
+        // Bind mounts of one file system can still share blocks
+        if source_fs == destination_fs {
+            match self.filesystem(source_fs)?.reflink(&source.path, &destination.path) {
+                Ok(_) => return Ok(CloneMethod::Reflink),
+                Err(VfsError::NotSupported) => {}
+                Err(error) => return Err(error),
+            }
+        }
         
-        // Convert absolute path to relative path within the file system
-        let relative_path = if path == &mount_path {
-            "/"
-        } else if path.starts_with(&mount_path) {
-            &path[mount_path.len()..]
-        } else {
-            path
+        let (source_inode, _) = self.filesystem(source_fs)?.open(&source.path, OpenFlags::READ_ONLY)?;
+        let destination_inode = match self.filesystem(destination_fs)?.create(&destination.path, FileType::Regular, metadata.permissions) {
+            Ok(inode) => inode,
+            Err(error) => {
+                let _ = self.filesystem(source_fs)?.close(source_inode);
+                return Err(error);
+            }
         };
         
-        filesystem.rmdir(relative_path)
+        let copied = self.copy_data(source_fs, source_inode, destination_fs, destination_inode);
+        let _ = self.filesystem(source_fs)?.close(source_inode);
+        if let Err(error) = copied {
+            // Do not leave a truncated copy behind
+            let _ = self.filesystem(destination_fs)?.unlink(&destination.path);
+            return Err(error);
+        }
+        Ok(CloneMethod::Copy)
+    }
+    
+    /// Copy all data of one inode into another, possibly on another file system
+    fn copy_data(&mut self, source_fs: FileSystemId, source: InodeNumber, destination_fs: FileSystemId, destination: InodeNumber) -> Result<(), VfsError> {
+        let mut buffer = vec![0u8; COPY_CHUNK_SIZE];
+        let mut offset: FileOffset = 0;
+        loop {
+            let bytes_read = self.filesystem(source_fs)?.read(source, offset, &mut buffer)?;
+            if bytes_read == 0 {
+                return Ok(());
+            }
+            
+            let mut written = 0;
+            while written < bytes_read {
+                let count = self.filesystem(destination_fs)?
+                    .write(destination, offset + written as u64, &buffer[written..bytes_read])?;
+                if count == 0 {
+                    return Err(VfsError::NoSpace);
+                }
+                written += count;
+            }
+            offset += bytes_read as u64;
+        }
+    }
+    
+    /// Write everything file systems and the block cache hold back to
+    /// their devices
+    pub fn sync(&mut self) -> Result<(), VfsError> {
+        let mut result = Ok(());
+        for filesystem in self.file_systems.values_mut() {
+            result = result.and(filesystem.sync());
+        }
+        result.and(self.cache.borrow_mut().sync())
+    }
+    
+    /// File system instance `filesystem_id`
+    fn filesystem(&mut self, filesystem_id: FileSystemId) -> Result<&mut dyn FileSystem, VfsError> {
+        Ok(self.file_systems.get_mut(&filesystem_id).ok_or(VfsError::NotMounted)?.as_mut())
     }
     
     /// Get list of mount points
@@ -500,6 +950,13 @@ mod tests {
         assert!(read.is_ok());
         assert_eq!(read.unwrap(), data.len());
         
+        // Positional access does not move the offset
+        let mut prefix = [0u8; 5];
+        assert_eq!(vfs.read_at(fd, 0, &mut prefix), Ok(5));
+        assert_eq!(vfs.read_at(fd, data.len() as u64, &mut prefix), Ok(0));
+        assert_eq!(vfs.write_at(fd, 7, b"EXT4"), Ok(4));
+        assert_eq!(vfs.open_files.get(&fd).unwrap().offset, data.len() as u64);
+        
         // Test closing the file
         assert!(vfs.close(fd).is_ok());
         
@@ -520,4 +977,219 @@ mod tests {
         // Test unmounting
         assert!(vfs.unmount("/").is_ok());
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_clone_file() {
+        let mut vfs = Vfs::new();
+        assert!(vfs.mount("/", FileSystemType::Ext4, Some(1), false).is_ok());
+        
+        let permissions = FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE;
+        assert!(vfs.create("/config", FileType::Regular, permissions).is_ok());
+        let fd = vfs.open("/config", OpenFlags::READ_WRITE).unwrap();
+        let data = vec![0x55u8; COPY_CHUNK_SIZE + 100];
+        assert_eq!(vfs.write(fd, &data), Ok(data.len()));
+        assert!(vfs.close(fd).is_ok());
+        
+        // ext4 has no shared extents, so the clone is a full copy
+        assert_eq!(vfs.clone_file("/config", "/config.bak"), Ok(CloneMethod::Copy));
+        let copy = vfs.stat("/config.bak").unwrap();
+        assert_eq!(copy.size, data.len() as u64);
+        assert_eq!(copy.permissions, permissions);
+        
+        assert_eq!(vfs.clone_file("/config", "/config.bak"), Err(VfsError::AlreadyExists));
+        assert_eq!(vfs.clone_file("/missing", "/copy"), Err(VfsError::NotFound));
+        
+        assert!(vfs.mkdir("/dir", permissions).is_ok());
+        assert_eq!(vfs.clone_file("/dir", "/dir2"), Err(VfsError::IsDirectory));
+    }
+    
+    #[test]
+    fn test_mount_system_image() {
+        let mut builder = crate::sysimage::ImageBuilder::new();
+        builder.add_file("/etc/motd", FilePermissions::OWNER_READ, b"Welcome to Kosh");
+        let image = SystemImageFs::with_source(Box::new(builder.build()));
+        
+        let mut vfs = Vfs::new();
+        assert!(vfs.mount_filesystem("/system", FileSystemType::SystemImage, Box::new(image), Some(0), false).is_ok());
+        assert!(vfs.get_mount_points()[0].read_only);
+        
+        let fd = vfs.open("/system/etc/motd", OpenFlags::READ_ONLY).unwrap();
+        let mut buffer = [0u8; 32];
+        assert_eq!(vfs.read(fd, &mut buffer), Ok(15));
+        assert_eq!(&buffer[..15], b"Welcome to Kosh");
+        assert!(vfs.close(fd).is_ok());
+        
+        assert_eq!(vfs.open("/system/etc/motd", OpenFlags::READ_WRITE), Err(VfsError::ReadOnlyFileSystem));
+        assert_eq!(vfs.clone_file("/system/etc/motd", "/system/etc/motd.bak"), Err(VfsError::ReadOnlyFileSystem));
+        
+        // Without an image there is nothing to mount
+        assert_eq!(vfs.mount("/other", FileSystemType::SystemImage, Some(1), true), Err(VfsError::IoError));
+    }
+    
+    #[test]
+    fn test_longest_prefix_resolution() {
+        let permissions = FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE | FilePermissions::OWNER_EXECUTE;
+        let mut vfs = Vfs::new();
+        assert!(vfs.mount("/", FileSystemType::Ext4, Some(1), false).is_ok());
+        assert!(vfs.mkdir("/mnt", permissions).is_ok());
+        assert!(vfs.create("/mntfile", FileType::Regular, permissions).is_ok());
+        
+        // Only directories of the parent mount can be mounted on
+        assert_eq!(vfs.mount("/mntfile", FileSystemType::Ext4, Some(2), false), Err(VfsError::NotDirectory));
+        assert_eq!(vfs.mount("/missing", FileSystemType::Ext4, Some(2), false), Err(VfsError::NotFound));
+        assert!(vfs.mount("/mnt/", FileSystemType::Ext4, Some(2), false).is_ok());
+        
+        // Files below /mnt live on the second file system, /mntfile does not
+        assert!(vfs.create("/mnt/data", FileType::Regular, permissions).is_ok());
+        assert_eq!(vfs.stat("/data").err(), Some(VfsError::NotFound));
+        assert!(vfs.stat("/mntfile").is_ok());
+        let fd = vfs.open("/mnt/../mnt/data", OpenFlags::READ_ONLY).unwrap();
+        assert_eq!(vfs.get_fd_info(fd).unwrap().mount_point, "/mnt");
+        
+        // Neither the busy mount nor the one below it can go
+        assert_eq!(vfs.unmount("/mnt"), Err(VfsError::MountPointBusy));
+        assert!(vfs.close(fd).is_ok());
+        assert_eq!(vfs.unmount("/"), Err(VfsError::MountPointBusy));
+        assert!(vfs.unmount("/mnt").is_ok());
+        assert_eq!(vfs.stat("/mnt/data").err(), Some(VfsError::NotFound));
+    }
+    
+    #[test]
+    fn test_bind_mount() {
+        let permissions = FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE | FilePermissions::OWNER_EXECUTE;
+        let mut vfs = Vfs::new();
+        assert!(vfs.mount("/", FileSystemType::Ext4, Some(1), false).is_ok());
+        assert!(vfs.mkdir("/home", permissions).is_ok());
+        assert!(vfs.mkdir("/view", permissions).is_ok());
+        assert!(vfs.mkdir("/ro", permissions).is_ok());
+        assert!(vfs.create("/home/notes", FileType::Regular, permissions).is_ok());
+        
+        assert_eq!(vfs.bind_mount("/home/notes", "/view", false), Err(VfsError::NotDirectory));
+        assert!(vfs.bind_mount("/home", "/view", false).is_ok());
+        assert!(vfs.bind_mount("/home", "/ro", true).is_ok());
+        
+        // Both paths show the same directory
+        assert!(vfs.create("/view/todo", FileType::Regular, permissions).is_ok());
+        assert!(vfs.stat("/home/todo").is_ok());
+        assert!(vfs.stat("/ro/notes").is_ok());
+        assert_eq!(vfs.clone_file("/view/notes", "/home/copy"), Ok(CloneMethod::Copy));
+        
+        // A read-only bind refuses writes the original allows
+        assert_eq!(vfs.create("/ro/other", FileType::Regular, permissions), Err(VfsError::ReadOnlyFileSystem));
+        assert_eq!(vfs.open("/ro/notes", OpenFlags::WRITE_ONLY), Err(VfsError::ReadOnlyFileSystem));
+        assert_eq!(vfs.unlink("/ro/notes"), Err(VfsError::ReadOnlyFileSystem));
+        
+        // The file system stays mounted while a bind still shows it
+        assert!(vfs.unmount("/view").is_ok());
+        assert!(vfs.unmount("/ro").is_ok());
+        assert!(vfs.stat("/home/todo").is_ok());
+        assert!(vfs.unmount("/").is_ok());
+        assert!(vfs.file_systems.is_empty());
+    }
+    
+    #[test]
+    fn test_seek_append_and_dup() {
+        let permissions = FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE;
+        let mut vfs = Vfs::new();
+        assert!(vfs.mount("/", FileSystemType::Ext4, Some(1), false).is_ok());
+        assert!(vfs.create("/log", FileType::Regular, permissions).is_ok());
+        
+        let fd = vfs.open("/log", OpenFlags::READ_WRITE).unwrap();
+        assert_eq!(vfs.write(fd, b"0123456789"), Ok(10));
+        assert_eq!(vfs.seek(fd, SeekFrom::Start(2)), Ok(2));
+        assert_eq!(vfs.seek(fd, SeekFrom::Current(3)), Ok(5));
+        assert_eq!(vfs.seek(fd, SeekFrom::End(-1)), Ok(9));
+        assert_eq!(vfs.seek(fd, SeekFrom::Current(-10)), Err(VfsError::InvalidArgument));
+        assert_eq!(SeekFrom::from_whence(-1, kosh_posix::SEEK_SET), None);
+        assert_eq!(SeekFrom::from_whence(4, 7), None);
+        
+        // A duplicate shares the offset and keeps the file open
+        let copy = vfs.dup(fd).unwrap();
+        let mut buffer = [0u8; 4];
+        assert_eq!(vfs.seek(copy, SeekFrom::Start(6)), Ok(6));
+        assert_eq!(vfs.read(fd, &mut buffer), Ok(4));
+        assert_eq!(&buffer, b"6789");
+        assert_eq!(vfs.get_fd_info(copy).unwrap().offset, 10);
+        assert!(vfs.close(fd).is_ok());
+        assert_eq!(vfs.seek(copy, SeekFrom::Start(0)), Ok(0));
+        assert!(vfs.close(copy).is_ok());
+        assert_eq!(vfs.close(copy), Err(VfsError::InvalidFileDescriptor));
+        
+        // Appends land at the end even when the file grew meanwhile
+        let append = vfs.open("/log", OpenFlags::WRITE_ONLY | OpenFlags::APPEND).unwrap();
+        let other = vfs.open("/log", OpenFlags::READ_WRITE).unwrap();
+        assert_eq!(vfs.read(append, &mut buffer), Err(VfsError::PermissionDenied));
+        assert_eq!(vfs.write_at(other, 10, b"ab"), Ok(2));
+        assert_eq!(vfs.write(append, b"cd"), Ok(2));
+        assert_eq!(vfs.get_fd_info(append).unwrap().offset, 14);
+        let mut contents = [0u8; 14];
+        assert_eq!(vfs.read(other, &mut contents), Ok(14));
+        assert_eq!(&contents, b"0123456789abcd");
+    }
+    
+    #[test]
+    fn test_symlink_resolution() {
+        let permissions = FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE | FilePermissions::OWNER_EXECUTE;
+        let mut vfs = Vfs::new();
+        assert!(vfs.mount("/", FileSystemType::Ext4, Some(1), false).is_ok());
+        assert!(vfs.mkdir("/usr", permissions).is_ok());
+        assert!(vfs.mkdir("/usr/lib", permissions).is_ok());
+        assert!(vfs.create("/usr/lib/libc.so", FileType::Regular, permissions).is_ok());
+        
+        // Relative targets start at the link's directory, absolute ones at the root
+        assert!(vfs.symlink("usr/lib", "/lib").is_ok());
+        assert!(vfs.symlink("/lib/libc.so", "/usr/libc").is_ok());
+        assert!(vfs.symlink("../../usr/./lib/../lib", "/usr/lib/self").is_ok());
+        assert_eq!(vfs.readlink("/lib"), Ok(String::from("usr/lib")));
+        assert_eq!(vfs.canonicalize("/lib/libc.so"), Ok(String::from("/usr/lib/libc.so")));
+        assert_eq!(vfs.canonicalize("/usr/libc"), Ok(String::from("/usr/lib/libc.so")));
+        assert_eq!(vfs.canonicalize("/usr/lib/self/self/libc.so"), Ok(String::from("/usr/lib/libc.so")));
+        // `..` after a link leaves the link's target, not the link
+        assert_eq!(vfs.canonicalize("/lib/.."), Ok(String::from("/usr")));
+        
+        assert_eq!(vfs.stat("/usr/libc").unwrap().file_type, FileType::Regular);
+        assert_eq!(vfs.lstat("/usr/libc").unwrap().file_type, FileType::SymbolicLink);
+        let fd = vfs.open("/usr/libc", OpenFlags::READ_WRITE).unwrap();
+        assert_eq!(vfs.write(fd, b"elf"), Ok(3));
+        assert!(vfs.close(fd).is_ok());
+        assert_eq!(vfs.stat("/usr/lib/libc.so").unwrap().size, 3);
+        
+        // Targets too long for the inode get a block of their own
+        let long_target = "/usr/lib/self/self/self/self/self/self/self/self/self/libc.so";
+        assert!(vfs.symlink(long_target, "/long").is_ok());
+        assert_eq!(vfs.readlink("/long"), Ok(String::from(long_target)));
+        assert_eq!(vfs.stat("/long").unwrap().size, 3);
+        
+        // Loops end, and removing a link leaves its target alone
+        assert!(vfs.symlink("/loop-b", "/loop-a").is_ok());
+        assert!(vfs.symlink("/loop-a", "/loop-b").is_ok());
+        assert_eq!(vfs.stat("/loop-a/x").err(), Some(VfsError::SymlinkLoop));
+        assert!(vfs.unlink("/usr/libc").is_ok());
+        assert!(vfs.stat("/usr/lib/libc.so").is_ok());
+        assert_eq!(vfs.readlink("/usr/lib/libc.so"), Err(VfsError::InvalidArgument));
+    }
+    
+    #[test]
+    fn test_working_directories() {
+        let permissions = FilePermissions::OWNER_READ | FilePermissions::OWNER_WRITE | FilePermissions::OWNER_EXECUTE;
+        let mut vfs = Vfs::new();
+        assert!(vfs.mount("/", FileSystemType::Ext4, Some(1), false).is_ok());
+        assert!(vfs.mkdir("/home", permissions).is_ok());
+        assert!(vfs.create("/home/notes", FileType::Regular, permissions).is_ok());
+        assert!(vfs.symlink("/home", "/h").is_ok());
+        
+        assert_eq!(vfs.getcwd(7), "/");
+        assert_eq!(vfs.chdir(7, "/home/notes"), Err(VfsError::NotDirectory));
+        assert_eq!(vfs.chdir(7, "missing"), Err(VfsError::NotFound));
+        assert!(vfs.chdir(7, "h").is_ok());
+        assert_eq!(vfs.getcwd(7), "/home");
+        assert_eq!(vfs.getcwd(8), "/");
+        
+        let path = vfs.absolute(7, "notes");
+        assert!(vfs.stat(&path).is_ok());
+        assert_eq!(vfs.absolute(8, "notes"), "//notes");
+        assert_eq!(vfs.absolute(7, "/etc"), "/etc");
+        assert!(vfs.chdir(7, "..").is_ok());
+        assert_eq!(vfs.getcwd(7), "/");
+    }
+}
@@ -25,15 +25,25 @@ fn init_heap() {
 mod syscalls;
 mod service_manager;
 mod process_spawner;
+mod manifest;
 
 use service_manager::ServiceManager;
 use process_spawner::ProcessSpawner;
-use syscalls::{sys_debug_print, sys_wait, sys_getpid};
+use syscalls::{sys_debug_print, sys_waitpid, sys_getpid, sys_nanosleep, sys_timer_create_periodic, sys_timer_wait, sys_test_exit, sys_test_mode, WNOHANG};
 
-/// Signal numbers for process management
-const SIGTERM: i32 = 15;
-const SIGKILL: i32 = 9;
-const SIGCHLD: i32 = 17;
+const NANOS_PER_MILLI: u64 = 1_000_000;
+
+/// Time essential services get to start before the shell is launched
+const SERVICE_START_DELAY_MS: u64 = 100;
+
+/// Period of the main loop's child and service health checks
+const SERVICE_CHECK_INTERVAL_MS: u64 = 50;
+
+/// Pause between checks for stopped services during shutdown
+const SHUTDOWN_POLL_INTERVAL_MS: u64 = 10;
+
+/// Service name the integration test orchestrator is registered under
+const INTEGRATION_TESTS: &str = "integration-tests";
 
 /// Main init process state
 struct InitProcess {
@@ -41,6 +51,10 @@ struct InitProcess {
     process_spawner: ProcessSpawner,
     shutdown_requested: bool,
     essential_services: Vec<&'static str>,
+    /// The kernel booted for the integration tests, which take the
+    /// shell's place
+    integration_tests: bool,
+    test_orchestrator: Option<ProcessId>,
 }
 
 impl InitProcess {
@@ -52,7 +66,11 @@ impl InitProcess {
             essential_services: vec![
                 "fs-service",
                 "driver-manager",
+                "input-service",
+                "display-service",
             ],
+            integration_tests: sys_test_mode(),
+            test_orchestrator: None,
         }
     }
 
@@ -66,7 +84,8 @@ impl InitProcess {
 
         // Start essential system services
         for service_name in &self.essential_services {
-            match self.process_spawner.spawn_service(service_name, &[]) {
+            let fs_service = self.service_manager.get_service_pid("fs-service");
+            match self.process_spawner.spawn_service(service_name, &[], fs_service) {
                 Ok(pid) => {
                     self.service_manager.register_service(service_name, pid);
                     #[cfg(debug_assertions)]
@@ -90,7 +109,13 @@ impl InitProcess {
         // Give services time to initialize
         self.wait_for_services_to_start();
 
-        // Start a shell for testing/debugging
+        if self.integration_tests {
+            self.start_integration_tests();
+            return;
+        }
+
+        // Start a shell for testing/debugging; it runs /etc/rc.sh, if
+        // present, before its first prompt
         match self.process_spawner.spawn_shell() {
             Ok(pid) => {
                 self.service_manager.register_service("shell", pid);
@@ -110,12 +135,45 @@ impl InitProcess {
         }
     }
 
+    /// Start the integration test orchestrator in place of the shell
+    ///
+    /// A run that cannot start fails at once rather than waiting for the
+    /// test script to time out.
+    fn start_integration_tests(&mut self) {
+        let fs_service = self.service_manager.get_service_pid("fs-service");
+        let driver_manager = self.service_manager.get_service_pid("driver-manager");
+        match self.process_spawner.spawn_test_orchestrator(fs_service, driver_manager) {
+            Ok(pid) => {
+                self.service_manager.register_service(INTEGRATION_TESTS, pid);
+                self.test_orchestrator = Some(pid);
+                #[cfg(debug_assertions)]
+                {
+                    let message = b"Init: Integration tests started\n";
+                    sys_debug_print(message);
+                }
+            }
+            Err(_) => {
+                #[cfg(debug_assertions)]
+                {
+                    let message = b"Init: Failed to start integration tests\n";
+                    sys_debug_print(message);
+                }
+                self.finish_integration_tests(1);
+            }
+        }
+    }
+
+    /// Report the orchestrator's exit status to the kernel, which ends the
+    /// run under QEMU; anywhere else the system shuts down
+    fn finish_integration_tests(&mut self, status: i32) {
+        let _ = sys_test_exit(status);
+        self.request_shutdown();
+    }
+
     /// Wait for services to start up
     fn wait_for_services_to_start(&mut self) {
-        // Simple delay mechanism - in a real system this would be more sophisticated
-        for _ in 0..1000000 {
-            core::hint::spin_loop();
-        }
+        // Services do not report readiness yet, so give them a fixed time
+        sleep_ms(SERVICE_START_DELAY_MS);
         
         // Update service states
         self.service_manager.check_services();
@@ -129,6 +187,10 @@ impl InitProcess {
             sys_debug_print(message);
         }
 
+        // Children and services are checked on every expiry of a periodic
+        // timer; without one, sleep between checks instead
+        let check_timer = sys_timer_create_periodic(SERVICE_CHECK_INTERVAL_MS * NANOS_PER_MILLI).ok();
+
         loop {
             // Check for child process exits
             self.handle_child_processes();
@@ -142,8 +204,10 @@ impl InitProcess {
                 break;
             }
 
-            // Small delay to prevent busy waiting
-            self.yield_cpu();
+            match check_timer {
+                Some(timer) if sys_timer_wait(timer).is_ok() => {}
+                _ => sleep_ms(SERVICE_CHECK_INTERVAL_MS),
+            }
         }
     }
 
@@ -151,16 +215,27 @@ impl InitProcess {
     fn handle_child_processes(&mut self) {
         // Non-blocking wait for child processes
         loop {
-            match sys_wait() {
-                Ok((pid, _status)) => {
+            match sys_waitpid(0, WNOHANG) {
+                Ok(Some((pid, status))) => {
                     #[cfg(debug_assertions)]
                     {
                         let message = b"Init: Child process exited\n";
                         sys_debug_print(message);
                     }
                     
+                    // The run is over once the orchestrator exits
+                    if self.test_orchestrator == Some(pid) {
+                        self.finish_integration_tests(status);
+                    }
+
                     // Notify service manager about the exit
-                    self.service_manager.handle_process_exit(pid);
+                    self.service_manager.handle_process_exit(pid, status);
+
+                    // The file system service would otherwise apply the
+                    // namespace to the next process given this ID
+                    if let Some(fs_service) = self.service_manager.get_service_pid("fs-service") {
+                        let _ = manifest::release(fs_service, pid);
+                    }
                     
                     // Check if this was an essential service
                     if self.is_essential_service_pid(pid) {
@@ -171,8 +246,8 @@ impl InitProcess {
                         }
                     }
                 }
-                Err(_) => {
-                    // No more child processes to wait for
+                Ok(None) | Err(_) => {
+                    // No more exited children (or no children at all)
                     break;
                 }
             }
@@ -208,7 +283,7 @@ impl InitProcess {
         
         while !self.service_manager.all_services_stopped() && wait_cycles < MAX_WAIT_CYCLES {
             self.handle_child_processes();
-            self.yield_cpu();
+            sleep_ms(SHUTDOWN_POLL_INTERVAL_MS);
             wait_cycles += 1;
         }
 
@@ -229,20 +304,17 @@ impl InitProcess {
         }
     }
 
-    /// Yield CPU to other processes
-    fn yield_cpu(&self) {
-        // Simple spin loop for yielding - in a real system this would be a proper yield syscall
-        for _ in 0..10000 {
-            core::hint::spin_loop();
-        }
-    }
-
     /// Request system shutdown
     fn request_shutdown(&mut self) {
         self.shutdown_requested = true;
     }
 }
 
+/// Sleep for `ms` milliseconds
+fn sleep_ms(ms: u64) {
+    let _ = sys_nanosleep(ms * NANOS_PER_MILLI);
+}
+
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     #[cfg(debug_assertions)]
@@ -0,0 +1,68 @@
+//! Namespace manifests of the processes init starts
+//!
+//! A process with a manifest sees only the paths it names, plus a private
+//! `/tmp` if it asks for one; processes without a manifest see the whole
+//! file tree. The file system service enforces the view, init only tells
+//! it what the view is.
+
+use alloc::{format, string::String, vec::Vec};
+use kosh_service::{wire, FileSystemRequest, ServiceData, ServiceMessage, ServiceType};
+use kosh_types::ProcessId;
+use crate::syscalls::sys_send_message;
+
+pub struct Manifest {
+    pub name: &'static str,
+    /// Paths made visible unchanged, e.g. the process's device node
+    pub paths: &'static [&'static str],
+    /// Back `/tmp` with `/tmp/private/<name>`
+    pub private_tmp: bool,
+}
+
+const MANIFESTS: &[Manifest] = &[
+    Manifest {
+        name: "driver-manager",
+        paths: &["/dev", "/drivers", "/system/drivers"],
+        private_tmp: true,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Manifest> {
+    MANIFESTS.iter().find(|manifest| manifest.name == name)
+}
+
+impl Manifest {
+    /// `(path, target)` bindings of the process's view
+    pub fn bindings(&self) -> Vec<(String, String)> {
+        let mut bindings: Vec<(String, String)> = self.paths.iter()
+            .map(|path| (String::from(*path), String::from(*path)))
+            .collect();
+        if self.private_tmp {
+            bindings.push((String::from("/tmp"), format!("/tmp/private/{}", self.name)));
+        }
+        bindings
+    }
+}
+
+fn send(fs_service: ProcessId, request: FileSystemRequest) -> Result<(), i32> {
+    let message = ServiceMessage {
+        service_type: ServiceType::FileSystem,
+        request_id: 0,
+        data: ServiceData::FileSystemRequest(request),
+        on_behalf_of: None,
+    };
+    sys_send_message(fs_service, &wire::encode_message(&message))
+}
+
+/// Confine `pid` to the view `manifest` describes
+///
+/// Only processes that still see the whole tree may do this, so it has to
+/// happen before the process gets a namespace of its own: in the forked
+/// child before exec, or from init.
+pub fn apply(fs_service: ProcessId, pid: ProcessId, manifest: &Manifest) -> Result<(), i32> {
+    send(fs_service, FileSystemRequest::SetNamespace { pid, bindings: manifest.bindings() })
+}
+
+/// Forget the namespace of an exited process before its ID is reused
+pub fn release(fs_service: ProcessId, pid: ProcessId) -> Result<(), i32> {
+    send(fs_service, FileSystemRequest::ClearNamespace { pid })
+}
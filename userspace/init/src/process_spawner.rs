@@ -1,6 +1,16 @@
+use alloc::format;
 use alloc::string::String;
+use alloc::vec::Vec;
 use kosh_types::ProcessId;
-use crate::syscalls::{sys_fork, sys_exec, sys_debug_print};
+use crate::manifest;
+use crate::syscalls::{sys_fork, sys_exec, sys_debug_print, sys_getpid};
+
+/// Environment every process started by init begins with
+pub const BOOT_ENVIRONMENT: &[&str] = &[
+    "PATH=/system/bin:/bin",
+    "HOME=/",
+    "SHELL=/system/bin/shell",
+];
 
 pub struct ProcessSpawner {
     // Could store configuration or state here in the future
@@ -12,7 +22,10 @@ impl ProcessSpawner {
     }
     
     /// Spawn a new service process
-    pub fn spawn_service(&mut self, service_name: &str, args: &[&str]) -> Result<ProcessId, SpawnError> {
+    ///
+    /// With `fs_service` running, a service that has a manifest starts
+    /// confined to the namespace it describes.
+    pub fn spawn_service(&mut self, service_name: &str, args: &[&str], fs_service: Option<ProcessId>) -> Result<ProcessId, SpawnError> {
         // Create the full path to the service binary
         let service_path = self.get_service_path(service_name);
         
@@ -27,8 +40,9 @@ impl ProcessSpawner {
             Ok(pid) => {
                 if pid == 0 {
                     // We are in the child process
+                    confine_self(service_name, fs_service);
                     // Execute the service binary
-                    match sys_exec(&service_path, args) {
+                    match sys_exec(&service_path, args, BOOT_ENVIRONMENT) {
                         Ok(_) => {
                             // This should never return if exec succeeds
                             unreachable!("exec returned successfully");
@@ -66,7 +80,10 @@ impl ProcessSpawner {
     }
     
     /// Spawn a regular user process (not a service)
-    pub fn spawn_process(&mut self, program_path: &str, args: &[&str]) -> Result<ProcessId, SpawnError> {
+    ///
+    /// Programs with a manifest, looked up by file name, start confined
+    /// like services do.
+    pub fn spawn_process(&mut self, program_path: &str, args: &[&str], fs_service: Option<ProcessId>) -> Result<ProcessId, SpawnError> {
         #[cfg(debug_assertions)]
         {
             let message = b"Attempting to spawn process\n";
@@ -77,7 +94,9 @@ impl ProcessSpawner {
             Ok(pid) => {
                 if pid == 0 {
                     // Child process - execute the program
-                    match sys_exec(program_path, args) {
+                    let program_name = program_path.rsplit('/').next().unwrap_or(program_path);
+                    confine_self(program_name, fs_service);
+                    match sys_exec(program_path, args, BOOT_ENVIRONMENT) {
                         Ok(_) => {
                             unreachable!("exec returned successfully");
                         }
@@ -112,7 +131,39 @@ impl ProcessSpawner {
     
     /// Spawn a shell process for testing
     pub fn spawn_shell(&mut self) -> Result<ProcessId, SpawnError> {
-        self.spawn_process("/system/bin/shell", &[])
+        self.spawn_process("/system/bin/shell", &[], None)
+    }
+
+    /// Spawn the integration test orchestrator, telling it where the
+    /// services it tests are
+    pub fn spawn_test_orchestrator(&mut self, fs_service: Option<ProcessId>, driver_manager: Option<ProcessId>) -> Result<ProcessId, SpawnError> {
+        let mut args = Vec::new();
+        if let Some(pid) = fs_service {
+            args.push(format!("--fs-service={}", pid));
+        }
+        if let Some(pid) = driver_manager {
+            args.push(format!("--driver-manager={}", pid));
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.spawn_process("/system/bin/integration-tests", &args, fs_service)
+    }
+}
+
+/// In a freshly forked child, apply the manifest of `name` to itself
+///
+/// Done before exec so the program never runs with the whole tree in view;
+/// a child that cannot be confined exits instead of running unconfined.
+fn confine_self(name: &str, fs_service: Option<ProcessId>) {
+    let (Some(manifest), Some(fs_service)) = (manifest::find(name), fs_service) else {
+        return;
+    };
+    if manifest::apply(fs_service, sys_getpid(), manifest).is_err() {
+        #[cfg(debug_assertions)]
+        {
+            let message = b"Failed to set up namespace\n";
+            sys_debug_print(message);
+        }
+        crate::syscalls::sys_exit(1);
     }
 }
 
@@ -1,7 +1,7 @@
 use alloc::vec::Vec;
 use alloc::string::String;
 use kosh_types::ProcessId;
-use crate::syscalls::sys_kill;
+use crate::syscalls::{sys_kill, SIGTERM, SIGKILL};
 #[cfg(debug_assertions)]
 use crate::syscalls::sys_debug_print;
 
@@ -12,6 +12,8 @@ pub struct Service {
     pub state: ServiceState,
     pub restart_count: u32,
     pub max_restarts: u32,
+    /// Exit status of the most recent run, once it has exited
+    pub last_exit_status: Option<i32>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -42,6 +44,7 @@ impl ServiceManager {
             state: ServiceState::Starting,
             restart_count: 0,
             max_restarts: 3, // Allow up to 3 restarts
+            last_exit_status: None,
         };
         
         self.services.push(service);
@@ -53,10 +56,11 @@ impl ServiceManager {
         }
     }
     
-    /// Handle when a process exits
-    pub fn handle_process_exit(&mut self, pid: ProcessId) {
+    /// Handle when a process exits with the given status
+    pub fn handle_process_exit(&mut self, pid: ProcessId, status: i32) {
         for service in &mut self.services {
             if service.pid == pid {
+                service.last_exit_status = Some(status);
                 match service.state {
                     ServiceState::Stopping => {
                         service.state = ServiceState::Stopped;
@@ -66,6 +70,10 @@ impl ServiceManager {
                             sys_debug_print(message);
                         }
                     }
+                    ServiceState::Running | ServiceState::Starting if status == 0 => {
+                        // Clean exit - the service finished its work
+                        service.state = ServiceState::Stopped;
+                    }
                     ServiceState::Running | ServiceState::Starting => {
                         // Unexpected exit - mark as failed
                         service.state = ServiceState::Failed;
@@ -125,7 +133,7 @@ impl ServiceManager {
         for service in &mut self.services {
             if service.state == ServiceState::Running {
                 // Send SIGTERM to gracefully shutdown
-                match sys_kill(service.pid, 15) {
+                match sys_kill(service.pid, SIGTERM) {
                     Ok(_) => {
                         service.state = ServiceState::Stopping;
                         #[cfg(debug_assertions)]
@@ -148,7 +156,7 @@ impl ServiceManager {
         for service in &mut self.services {
             if service.state != ServiceState::Stopped {
                 // Send SIGKILL to force termination
-                let _ = sys_kill(service.pid, 9);
+                let _ = sys_kill(service.pid, SIGKILL);
                 service.state = ServiceState::Stopped;
                 
                 #[cfg(debug_assertions)]
@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use kosh_types::ProcessId;
 
 /// System call wrapper functions for the init process
@@ -6,7 +7,7 @@ use kosh_types::ProcessId;
 pub fn sys_exit(status: i32) -> ! {
     unsafe {
         core::arch::asm!(
-            "syscall",
+            "int 0x80",
             in("rax") 1u64, // SYS_EXIT
             in("rdi") status,
             options(noreturn)
@@ -19,7 +20,7 @@ pub fn sys_getpid() -> ProcessId {
     let pid: u64;
     unsafe {
         core::arch::asm!(
-            "syscall",
+            "int 0x80",
             in("rax") 5u64, // SYS_GETPID
             lateout("rax") pid,
             options(nostack, preserves_flags)
@@ -33,7 +34,7 @@ pub fn sys_fork() -> Result<ProcessId, i32> {
     let result: i64;
     unsafe {
         core::arch::asm!(
-            "syscall",
+            "int 0x80",
             in("rax") 2u64, // SYS_FORK
             lateout("rax") result,
             options(nostack, preserves_flags)
@@ -48,16 +49,106 @@ pub fn sys_fork() -> Result<ProcessId, i32> {
 }
 
 /// Execute a new program in the current process
-pub fn sys_exec(path: &str, args: &[&str]) -> Result<(), i32> {
+///
+/// The program gets `path` followed by `args` as its argument vector, and
+/// `env` (`NAME=value` strings) as its environment.
+pub fn sys_exec(path: &str, args: &[&str], env: &[&str]) -> Result<(), i32> {
+    // The kernel takes C strings and NULL-terminated pointer arrays
+    let program = c_string(path);
+    let args: Vec<Vec<u8>> = args.iter().map(|arg| c_string(arg)).collect();
+    let env: Vec<Vec<u8>> = env.iter().map(|entry| c_string(entry)).collect();
+    let argv: Vec<u64> = core::iter::once(&program).chain(args.iter())
+        .map(|arg| arg.as_ptr() as u64)
+        .chain(core::iter::once(0))
+        .collect();
+    let envp: Vec<u64> = env.iter()
+        .map(|entry| entry.as_ptr() as u64)
+        .chain(core::iter::once(0))
+        .collect();
+    
+    // Repeated while the kernel reads the program from the file system
+    loop {
+        let result: i64;
+        unsafe {
+            core::arch::asm!(
+                "int 0x80",
+                in("rax") 3u64, // SYS_EXEC
+                in("rdi") program.as_ptr(),
+                in("rsi") argv.as_ptr(),
+                in("rdx") envp.as_ptr(),
+                lateout("rax") result,
+                options(nostack, preserves_flags)
+            );
+        }
+        
+        match result {
+            EAGAIN => continue,
+            result if result < 0 => return Err(result as i32),
+            _ => return Ok(()),
+        }
+    }
+}
+
+fn c_string(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len() + 1);
+    bytes.extend_from_slice(text.as_bytes());
+    bytes.push(0);
+    bytes
+}
+
+/// Wait option: return immediately if no child has exited
+pub const WNOHANG: u64 = 0x1;
+
+/// Wait for child `pid` (0 = any child) to exit
+///
+/// Returns the reaped child and its exit status, or `None` when `WNOHANG`
+/// is set and no child has exited yet.
+pub fn sys_waitpid(pid: ProcessId, options: u64) -> Result<Option<(ProcessId, i32)>, i32> {
+    let mut status: i32 = 0;
+    
+    loop {
+        let result: i64;
+        unsafe {
+            core::arch::asm!(
+                "int 0x80",
+                in("rax") 4u64, // SYS_WAIT
+                in("rdi") &mut status as *mut i32,
+                in("rsi") options,
+                in("rdx") pid as u64,
+                lateout("rax") result,
+                options(nostack, preserves_flags)
+            );
+        }
+        
+        // The kernel blocked us until a child exits; ask again
+        if result == EAGAIN && options & WNOHANG == 0 {
+            continue;
+        }
+        
+        return if result < 0 {
+            Err(result as i32)
+        } else if result == 0 {
+            Ok(None)
+        } else {
+            Ok(Some((result as ProcessId, status)))
+        };
+    }
+}
+
+/// Signal numbers for process management
+pub const SIGKILL: i32 = 9;
+pub const SIGTERM: i32 = 15;
+pub const SIGCHLD: i32 = 17;
+
+/// Send a signal to a process
+pub fn sys_kill(pid: ProcessId, signal: i32) -> Result<(), i32> {
     let result: i64;
     unsafe {
         core::arch::asm!(
-            "syscall",
-            in("rax") 3u64, // SYS_EXEC
-            in("rdi") path.as_ptr(),
-            in("rsi") path.len(),
-            in("rdx") args.as_ptr(),
-            in("r10") args.len(),
+            "int 0x80",
+            in("rax") 7u64, // SYS_KILL
+            in("rdi") pid,
+            in("rsi") signal,
             lateout("rax") result,
             options(nostack, preserves_flags)
         );
@@ -70,36 +161,54 @@ pub fn sys_exec(path: &str, args: &[&str]) -> Result<(), i32> {
     }
 }
 
-/// Wait for a child process to exit
-pub fn sys_wait() -> Result<(ProcessId, i32), i32> {
-    let pid: i64;
-    let status: i64;
+/// Errno the kernel returns after blocking the caller; the call is repeated
+const EAGAIN: i64 = -11;
+
+/// Sleep for at least `duration_ns` nanoseconds
+pub fn sys_nanosleep(duration_ns: u64) -> Result<(), i32> {
+    loop {
+        let result: i64;
+        unsafe {
+            core::arch::asm!(
+                "int 0x80",
+                in("rax") 80u64, // SYS_NANOSLEEP
+                in("rdi") duration_ns,
+                lateout("rax") result,
+                options(nostack, preserves_flags)
+            );
+        }
+        
+        match result {
+            EAGAIN => continue,
+            result if result < 0 => return Err(result as i32),
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Create a periodic timer expiring every `interval_ns` nanoseconds
+pub fn sys_timer_create_periodic(interval_ns: u64) -> Result<u32, i32> {
+    let timer: i64;
     unsafe {
         core::arch::asm!(
-            "syscall",
-            in("rax") 4u64, // SYS_WAIT
-            lateout("rax") pid,
-            lateout("rdx") status,
+            "int 0x80",
+            in("rax") 81u64, // SYS_TIMER_CREATE
+            lateout("rax") timer,
             options(nostack, preserves_flags)
         );
     }
-    
-    if pid < 0 {
-        Err(pid as i32)
-    } else {
-        Ok((pid as ProcessId, status as i32))
+    if timer < 0 {
+        return Err(timer as i32);
     }
-}
-
-/// Send a signal to a process
-pub fn sys_kill(pid: ProcessId, signal: i32) -> Result<(), i32> {
+    
     let result: i64;
     unsafe {
         core::arch::asm!(
-            "syscall",
-            in("rax") 7u64, // SYS_KILL
-            in("rdi") pid,
-            in("rsi") signal,
+            "int 0x80",
+            in("rax") 82u64, // SYS_TIMER_SET
+            in("rdi") timer,
+            in("rsi") interval_ns,
+            in("rdx") interval_ns,
             lateout("rax") result,
             options(nostack, preserves_flags)
         );
@@ -108,7 +217,30 @@ pub fn sys_kill(pid: ProcessId, signal: i32) -> Result<(), i32> {
     if result < 0 {
         Err(result as i32)
     } else {
-        Ok(())
+        Ok(timer as u32)
+    }
+}
+
+/// Wait for `timer` to expire, returning how often it did since the last wait
+pub fn sys_timer_wait(timer: u32) -> Result<u64, i32> {
+    loop {
+        let result: i64;
+        unsafe {
+            core::arch::asm!(
+                "int 0x80",
+                in("rax") 83u64, // SYS_TIMER_WAIT
+                in("rdi") timer as u64,
+                in("rsi") 0u64, // flags: block until it expires
+                lateout("rax") result,
+                options(nostack, preserves_flags)
+            );
+        }
+        
+        match result {
+            EAGAIN => continue,
+            result if result < 0 => return Err(result as i32),
+            expirations => return Ok(expirations as u64),
+        }
     }
 }
 
@@ -116,7 +248,7 @@ pub fn sys_kill(pid: ProcessId, signal: i32) -> Result<(), i32> {
 pub fn sys_debug_print(message: &[u8]) {
     unsafe {
         core::arch::asm!(
-            "syscall",
+            "int 0x80",
             in("rax") 100u64, // SYS_DEBUG_PRINT
             in("rdi") message.as_ptr(),
             in("rsi") message.len(),
@@ -125,16 +257,53 @@ pub fn sys_debug_print(message: &[u8]) {
     }
 }
 
+/// `SYS_TEST_CONTROL` actions
+const TEST_CONTROL_MODE: u64 = 0;
+const TEST_CONTROL_EXIT: u64 = 1;
+
+fn sys_test_control(action: u64, status: i32) -> i64 {
+    let result: i64;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") 102u64, // SYS_TEST_CONTROL
+            in("rdi") action,
+            in("rsi") status as i64,
+            lateout("rax") result,
+            options(nostack, preserves_flags)
+        );
+    }
+    result
+}
+
+/// Whether the kernel booted for the userspace integration tests
+///
+/// Release kernels do not know the call, which reads as a normal boot.
+pub fn sys_test_mode() -> bool {
+    sys_test_control(TEST_CONTROL_MODE, 0) == 1
+}
+
+/// End an integration test run with the orchestrator's exit status
+///
+/// Under QEMU this does not return.
+pub fn sys_test_exit(status: i32) -> Result<(), i32> {
+    match sys_test_control(TEST_CONTROL_EXIT, status) {
+        result if result < 0 => Err(result as i32),
+        _ => Ok(()),
+    }
+}
+
 /// Send an IPC message to another process
 pub fn sys_send_message(receiver: ProcessId, data: &[u8]) -> Result<(), i32> {
     let result: i64;
     unsafe {
         core::arch::asm!(
-            "syscall",
+            "int 0x80",
             in("rax") 30u64, // SYS_SEND_MESSAGE
             in("rdi") receiver,
             in("rsi") data.as_ptr(),
             in("rdx") data.len(),
+            in("r10") 0u64, // flags: inline payload
             lateout("rax") result,
             options(nostack, preserves_flags)
         );
@@ -147,24 +316,25 @@ pub fn sys_send_message(receiver: ProcessId, data: &[u8]) -> Result<(), i32> {
     }
 }
 
-/// Receive an IPC message (blocking)
+/// Receive an IPC message (non-blocking)
 pub fn sys_receive_message(buffer: &mut [u8]) -> Result<(ProcessId, usize), i32> {
-    let sender: i64;
+    let mut sender: u64 = 0;
     let length: i64;
     unsafe {
         core::arch::asm!(
-            "syscall",
+            "int 0x80",
             in("rax") 31u64, // SYS_RECEIVE_MESSAGE
             in("rdi") buffer.as_mut_ptr(),
             in("rsi") buffer.len(),
-            lateout("rax") sender,
-            lateout("rdx") length,
+            in("rdx") &mut sender as *mut u64,
+            in("r10") 0u64, // flags not requested
+            lateout("rax") length,
             options(nostack, preserves_flags)
         );
     }
     
-    if sender < 0 {
-        Err(sender as i32)
+    if length < 0 {
+        Err(length as i32)
     } else {
         Ok((sender as ProcessId, length as usize))
     }
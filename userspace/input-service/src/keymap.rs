@@ -0,0 +1,279 @@
+//! Keymaps and dead keys
+//!
+//! Keyboard drivers type with the US layout. A keymap changes what some
+//! keys type, found by their set 1 scancode, and can make a key a dead
+//! key: one that types nothing itself but puts an accent on the next
+//! letter, as ´ then e types é. A dead key followed by a space, by
+//! itself or by a letter the accent does not go on types the accent
+//! alone, then that letter.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use kosh_driver::input::{InputEvent, KeyCode, KeyEventType, KeyModifiers};
+
+/// Accents dead keys put on the next letter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadKey {
+    Acute,
+    Grave,
+    Circumflex,
+    Diaeresis,
+    Tilde,
+}
+
+impl DeadKey {
+    /// The accent typed alone
+    pub fn spacing(self) -> char {
+        match self {
+            DeadKey::Acute => '\'',
+            DeadKey::Grave => '`',
+            DeadKey::Circumflex => '^',
+            DeadKey::Diaeresis => '"',
+            DeadKey::Tilde => '~',
+        }
+    }
+
+    /// `base` with the accent on it, if there is such a character
+    pub fn compose(self, base: char) -> Option<char> {
+        const LETTERS: &str = "aeiouAEIOU";
+        const ACUTE: &str = "áéíóúÁÉÍÓÚ";
+        const GRAVE: &str = "àèìòùÀÈÌÒÙ";
+        const CIRCUMFLEX: &str = "âêîôûÂÊÎÔÛ";
+        const DIAERESIS: &str = "äëïöüÄËÏÖÜ";
+        match (self, base) {
+            (DeadKey::Tilde, 'n') => return Some('ñ'),
+            (DeadKey::Tilde, 'N') => return Some('Ñ'),
+            (DeadKey::Tilde, 'a') => return Some('ã'),
+            (DeadKey::Tilde, 'o') => return Some('õ'),
+            (DeadKey::Tilde, 'A') => return Some('Ã'),
+            (DeadKey::Tilde, 'O') => return Some('Õ'),
+            (DeadKey::Acute, 'y') => return Some('ý'),
+            (DeadKey::Acute, 'Y') => return Some('Ý'),
+            (DeadKey::Diaeresis, 'y') => return Some('ÿ'),
+            (DeadKey::Tilde, _) => return None,
+            _ => {}
+        }
+        let accented = match self {
+            DeadKey::Acute => ACUTE,
+            DeadKey::Grave => GRAVE,
+            DeadKey::Circumflex => CIRCUMFLEX,
+            DeadKey::Diaeresis => DIAERESIS,
+            DeadKey::Tilde => return None,
+        };
+        let index = LETTERS.chars().position(|letter| letter == base)?;
+        accented.chars().nth(index)
+    }
+}
+
+/// What a key types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symbol {
+    Char(char),
+    Dead(DeadKey),
+}
+
+/// A key a keymap changes: its set 1 scancode and what it types without
+/// and with Shift
+type KeyEntry = (u8, Symbol, Symbol);
+
+use Symbol::{Char, Dead};
+
+/// US International: the quote, backtick and 6 keys are dead keys
+const US_INTL: &[KeyEntry] = &[
+    (0x28, Dead(DeadKey::Acute), Dead(DeadKey::Diaeresis)),
+    (0x29, Dead(DeadKey::Grave), Dead(DeadKey::Tilde)),
+    (0x07, Char('6'), Dead(DeadKey::Circumflex)),
+];
+
+/// German: Y and Z swapped, umlauts and ß on their own keys, and the
+/// accents as dead keys
+const DE: &[KeyEntry] = &[
+    (0x15, Char('z'), Char('Z')),
+    (0x2C, Char('y'), Char('Y')),
+    (0x1A, Char('ü'), Char('Ü')),
+    (0x27, Char('ö'), Char('Ö')),
+    (0x28, Char('ä'), Char('Ä')),
+    (0x0C, Char('ß'), Char('?')),
+    (0x0D, Dead(DeadKey::Acute), Dead(DeadKey::Grave)),
+    (0x29, Dead(DeadKey::Circumflex), Char('°')),
+];
+
+/// A keyboard layout
+#[derive(Debug, Clone, Copy)]
+pub struct Keymap {
+    pub name: &'static str,
+    keys: &'static [KeyEntry],
+}
+
+/// The keymaps there are, the driver's US layout first
+pub const KEYMAPS: &[Keymap] = &[
+    Keymap { name: "us", keys: &[] },
+    Keymap { name: "us-intl", keys: US_INTL },
+    Keymap { name: "de", keys: DE },
+];
+
+impl Keymap {
+    /// The keymap called `name`
+    pub fn find(name: &str) -> Option<Keymap> {
+        KEYMAPS.iter().copied().find(|keymap| keymap.name == name)
+    }
+
+    /// What the key press `event` types on this keymap, if anything
+    pub fn symbol(&self, event: &InputEvent) -> Option<Symbol> {
+        let Some(&(_, plain, shifted)) = self.keys.iter().find(|&&(scancode, _, _)| scancode == event.scancode) else {
+            return event.ascii_char.map(Char);
+        };
+        let shift = event.modifiers.contains(KeyModifiers::SHIFT);
+        // Caps Lock shifts letters only
+        let caps = event.modifiers.contains(KeyModifiers::CAPS_LOCK)
+            && matches!((plain, shifted), (Char(lower), Char(upper)) if lower.is_lowercase() && upper.is_uppercase());
+        Some(if shift != caps { shifted } else { plain })
+    }
+}
+
+/// Applies a keymap to key events, composing accented letters
+pub struct Compose {
+    keymap: Keymap,
+    /// Dead key waiting for the next letter
+    pending: Option<DeadKey>,
+}
+
+impl Compose {
+    pub fn new() -> Self {
+        Self {
+            keymap: KEYMAPS[0],
+            pending: None,
+        }
+    }
+
+    /// Type with `keymap` from now on
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+        self.pending = None;
+    }
+
+    /// The keymap typed with
+    pub fn keymap(&self) -> Keymap {
+        self.keymap
+    }
+
+    /// Whether a dead key is waiting for the next letter
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// The events to pass on for the key event `event`
+    ///
+    /// A dead key's press is passed on typing nothing. A press that does
+    /// not compose with the dead key before it comes after a press
+    /// synthesized to type the accent, with no key of its own.
+    pub fn key_event(&mut self, event: &InputEvent) -> Vec<InputEvent> {
+        if event.event_type == KeyEventType::KeyRelease || event.key_code.is_modifier() {
+            return vec![event.clone()];
+        }
+        let typed = |character: Option<char>| InputEvent { ascii_char: character, ..event.clone() };
+        match (self.keymap.symbol(event), self.pending.take()) {
+            (Some(Dead(dead)), None) => {
+                self.pending = Some(dead);
+                vec![typed(None)]
+            }
+            // The same dead key twice types the accent
+            (Some(Dead(dead)), Some(pending)) if dead == pending => vec![typed(Some(dead.spacing()))],
+            (Some(Dead(dead)), Some(pending)) => {
+                self.pending = Some(dead);
+                vec![self.accent(pending, event), typed(None)]
+            }
+            (Some(Char(' ')), Some(pending)) => vec![typed(Some(pending.spacing()))],
+            (Some(Char(base)), Some(pending)) => match pending.compose(base) {
+                Some(composed) => vec![typed(Some(composed))],
+                None => vec![self.accent(pending, event), typed(Some(base))],
+            },
+            (Some(Char(character)), None) => vec![typed(Some(character))],
+            // Keys that type nothing, such as the arrows, drop the accent
+            (None, _) => vec![event.clone()],
+        }
+    }
+
+    /// A press typing the accent of `dead` alone, just before `event`
+    fn accent(&self, dead: DeadKey, event: &InputEvent) -> InputEvent {
+        InputEvent {
+            event_type: KeyEventType::KeyPress,
+            key_code: KeyCode::Unknown,
+            scancode: 0,
+            modifiers: event.modifiers,
+            ascii_char: Some(dead.spacing()),
+            timestamp: event.timestamp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    fn press(scancode: u8, modifiers: KeyModifiers, ascii_char: Option<char>) -> InputEvent {
+        InputEvent {
+            event_type: KeyEventType::KeyPress,
+            key_code: KeyCode::from_byte(scancode),
+            scancode,
+            modifiers,
+            ascii_char,
+            timestamp: 0,
+        }
+    }
+
+    fn typed(compose: &mut Compose, keys: &[InputEvent]) -> String {
+        keys.iter()
+            .flat_map(|key| compose.key_event(key))
+            .filter_map(|event| event.ascii_char)
+            .collect()
+    }
+
+    #[test]
+    fn test_dead_keys_compose() {
+        let none = KeyModifiers::empty();
+        let quote = press(0x28, none, Some('\''));
+        let e = press(0x12, none, Some('e'));
+        let shift_o = press(0x18, KeyModifiers::SHIFT, Some('O'));
+        let space = press(0x39, none, Some(' '));
+        let k = press(0x25, none, Some('k'));
+
+        let mut compose = Compose::new();
+        assert_eq!(typed(&mut compose, &[quote.clone(), e.clone()]), "'e");
+
+        compose.set_keymap(Keymap::find("us-intl").unwrap());
+        assert_eq!(typed(&mut compose, &[quote.clone(), e.clone()]), "é");
+        let diaeresis = press(0x28, KeyModifiers::SHIFT, Some('"'));
+        assert_eq!(typed(&mut compose, &[diaeresis, shift_o]), "Ö");
+        assert_eq!(typed(&mut compose, &[quote.clone(), space]), "'");
+        assert_eq!(typed(&mut compose, &[quote.clone(), quote.clone()]), "'");
+        assert_eq!(typed(&mut compose, &[quote.clone(), k.clone()]), "'k");
+
+        // The dead key's press goes on, typing nothing, and the accent
+        // of a letter it does not go on is a press with no key
+        let events: Vec<_> = [quote, k].iter().flat_map(|key| compose.key_event(key)).collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!((events[0].key_code, events[0].ascii_char), (KeyCode::Unknown, None));
+        assert_eq!((events[1].key_code, events[1].ascii_char), (KeyCode::Unknown, Some('\'')));
+        assert_eq!((events[2].key_code, events[2].ascii_char), (KeyCode::K, Some('k')));
+        assert!(!compose.is_pending());
+    }
+
+    #[test]
+    fn test_german_keymap() {
+        let mut compose = Compose::new();
+        compose.set_keymap(Keymap::find("de").unwrap());
+        let none = KeyModifiers::empty();
+        let keys = [
+            press(0x15, none, Some('y')),
+            press(0x27, none, Some(';')),
+            press(0x28, KeyModifiers::CAPS_LOCK, Some('\'')),
+            press(0x0C, KeyModifiers::CAPS_LOCK, Some('-')),
+            press(0x29, none, Some('`')),
+            press(0x16, none, Some('u')),
+        ];
+        assert_eq!(typed(&mut compose, &keys), "zöÄßû");
+        assert!(Keymap::find("xx").is_none());
+    }
+}
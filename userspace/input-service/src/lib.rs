@@ -0,0 +1,26 @@
+#![no_std]
+
+//! Input service
+//!
+//! Gathers the events of every keyboard, mouse and touch screen driver
+//! into one stream and sends it to the process that has focus. Processes
+//! subscribe with `InputRequest::Subscribe`; the first to subscribe, or
+//! the one named with `InputRequest::SetFocus`, has focus. Held keys are
+//! repeated here, at a rate `InputRequest::SetKeyRepeat` sets, and typed
+//! with the keymap `InputRequest::SetKeymap` picks, which can have dead
+//! keys for accented letters.
+//!
+//! Keyboard and pointer drivers send batches of `InputRecord`s to the
+//! process named `INPUT_SERVICE_NAME`; touch drivers stream touch batches once the
+//! service is subscribed to them. Subscribers receive `InputRecord`
+//! batches.
+
+extern crate alloc;
+
+pub mod keymap;
+pub mod repeat;
+pub mod router;
+
+pub use keymap::{Compose, Keymap};
+pub use repeat::KeyRepeat;
+pub use router::InputRouter;
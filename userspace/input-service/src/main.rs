@@ -0,0 +1,124 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec;
+use kosh_input_service::InputRouter;
+use kosh_ipc::IpcError;
+use kosh_service::{wire, ServiceData, ServiceMessage, ServiceResponse, ServiceStatus};
+use kosh_types::ProcessId;
+use linked_list_allocator::LockedHeap;
+
+#[global_allocator]
+static ALLOCATOR: LockedHeap = LockedHeap::empty();
+
+/// Milliseconds on the monotonic clock, 0 until the kernel provides one
+fn monotonic_ms() -> u64 {
+    kosh_posix::clock_gettime(kosh_posix::CLOCK_MONOTONIC)
+        .map_or(0, |time| time.tv_sec as u64 * 1000 + time.tv_nsec as u64 / 1_000_000)
+}
+
+/// Answer a request `requester` made
+fn handle_request(router: &mut InputRouter, requester: ProcessId, request: ServiceMessage) -> ServiceResponse {
+    let (status, data) = match request.data {
+        ServiceData::InputRequest(input_request) => router.handle_request(requester, input_request),
+        _ => (ServiceStatus::InvalidRequest, ServiceData::Empty),
+    };
+    ServiceResponse { request_id: request.request_id, status, data }
+}
+
+/// Entry point for the input service
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    init_heap();
+    debug_print(b"Input Service: Starting\n");
+
+    let mut router = InputRouter::new();
+    let mut buffer = vec![0u8; kosh_ipc::syscall::MAX_MESSAGE_SIZE];
+
+    // Requests and driver batches arrive in the same queue, so the service
+    // reads it itself rather than through a `ServiceRunner`
+    loop {
+        // Sleep until a message arrives or a held key is due to repeat
+        let timeout = router.next_repeat_in(monotonic_ms()).unwrap_or(kosh_ipc::poll::INFINITE);
+        if kosh_ipc::poll::wait_for_message(timeout).is_err() {
+            debug_print(b"Input Service: Error waiting for messages\n");
+        }
+
+        loop {
+            let (sender, length) = match kosh_ipc::syscall::receive_message(&mut buffer) {
+                Ok(received) => received,
+                Err(IpcError::WouldBlock) => break,
+                Err(_) => {
+                    debug_print(b"Input Service: Error receiving message\n");
+                    break;
+                }
+            };
+            let message = &buffer[..length];
+            if router.handle_batch(message, monotonic_ms()) {
+                continue;
+            }
+            let response = match wire::decode_message(message) {
+                Ok(request) => {
+                    let requester = request.requester(sender);
+                    handle_request(&mut router, requester, request)
+                }
+                Err(_) => match wire::peek_request_id(message) {
+                    Ok(request_id) => ServiceResponse {
+                        request_id,
+                        status: ServiceStatus::InvalidRequest,
+                        data: ServiceData::Empty,
+                    },
+                    Err(_) => continue,
+                },
+            };
+            let _ = kosh_service::send_response(sender, &response);
+        }
+
+        router.poll_repeat(monotonic_ms());
+        router.flush(kosh_ipc::syscall::send_message);
+    }
+}
+
+fn init_heap() {
+    const HEAP_SIZE: usize = 256 * 1024;
+    static mut HEAP_MEMORY: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+
+    unsafe {
+        let heap_ptr = core::ptr::addr_of_mut!(HEAP_MEMORY);
+        ALLOCATOR.lock().init((*heap_ptr).as_mut_ptr(), HEAP_SIZE);
+    }
+}
+
+fn debug_print(message: &[u8]) {
+    #[cfg(debug_assertions)]
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") 100u64, // SYS_DEBUG_PRINT
+            in("rdi") message.as_ptr(),
+            in("rsi") message.len(),
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+#[cfg(not(test))]
+fn sys_exit(status: i32) -> ! {
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") 1u64, // SYS_EXIT
+            in("rdi") status,
+            options(noreturn)
+        );
+    }
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    debug_print(b"Input Service: PANIC occurred!\n");
+    sys_exit(1);
+}
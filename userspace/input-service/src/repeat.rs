@@ -0,0 +1,142 @@
+//! Key repeat
+//!
+//! A key held down is pressed again after a delay and then at a fixed
+//! interval until it is released or another key is pressed. Modifier keys
+//! do not repeat. PS/2 keyboards repeat keys themselves; those presses of a
+//! key already held are dropped, so that only the service's rate applies.
+
+use kosh_driver::input::{InputEvent, KeyEventType};
+
+/// Delay before a held key repeats
+pub const DEFAULT_DELAY_MS: u32 = 500;
+
+/// Time between repeats of a held key
+pub const DEFAULT_INTERVAL_MS: u32 = 33;
+
+struct HeldKey {
+    event: InputEvent,
+    /// When the key is next repeated
+    repeat_at_ms: u64,
+}
+
+/// Repeats the key held down
+pub struct KeyRepeat {
+    delay_ms: u32,
+    interval_ms: u32,
+    held: Option<HeldKey>,
+}
+
+impl KeyRepeat {
+    pub fn new() -> Self {
+        Self {
+            delay_ms: DEFAULT_DELAY_MS,
+            interval_ms: DEFAULT_INTERVAL_MS,
+            held: None,
+        }
+    }
+
+    /// Repeat after `delay_ms`, then every `interval_ms`; a delay of 0
+    /// turns repeating off
+    pub fn configure(&mut self, delay_ms: u32, interval_ms: u32) {
+        self.delay_ms = delay_ms;
+        self.interval_ms = interval_ms.max(1);
+        self.held = None;
+    }
+
+    /// Note a key event from a keyboard; returns whether to pass it on
+    pub fn key_event(&mut self, event: &InputEvent, now_ms: u64) -> bool {
+        match event.event_type {
+            KeyEventType::KeyPress => {
+                if self.held.as_ref().is_some_and(|held| held.event.key_code == event.key_code) {
+                    // The keyboard repeating the key itself
+                    return false;
+                }
+                if !event.key_code.is_modifier() {
+                    self.held = (self.delay_ms != 0).then(|| HeldKey {
+                        event: event.clone(),
+                        repeat_at_ms: now_ms + self.delay_ms as u64,
+                    });
+                }
+            }
+            KeyEventType::KeyRelease => {
+                if self.held.as_ref().is_some_and(|held| held.event.key_code == event.key_code) {
+                    self.held = None;
+                }
+            }
+        }
+        true
+    }
+
+    /// Stop repeating, as when focus moves
+    pub fn release(&mut self) {
+        self.held = None;
+    }
+
+    /// The repeated press due at `now_ms`, if any
+    pub fn poll(&mut self, now_ms: u64) -> Option<InputEvent> {
+        let held = self.held.as_mut().filter(|held| now_ms >= held.repeat_at_ms)?;
+        // A late poll repeats once rather than catching up
+        held.repeat_at_ms = now_ms + self.interval_ms as u64;
+        Some(InputEvent { timestamp: now_ms, ..held.event.clone() })
+    }
+
+    /// Milliseconds from `now_ms` until the next repeat
+    pub fn next_repeat_in(&self, now_ms: u64) -> Option<u64> {
+        self.held.as_ref().map(|held| held.repeat_at_ms.saturating_sub(now_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kosh_driver::input::{KeyCode, KeyModifiers};
+
+    fn key(event_type: KeyEventType, key_code: KeyCode) -> InputEvent {
+        InputEvent {
+            event_type,
+            key_code,
+            scancode: key_code as u8,
+            modifiers: KeyModifiers::empty(),
+            ascii_char: None,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_held_key_repeats_until_released() {
+        let mut repeat = KeyRepeat::new();
+        repeat.configure(500, 50);
+        assert!(repeat.key_event(&key(KeyEventType::KeyPress, KeyCode::A), 1000));
+        assert_eq!(repeat.next_repeat_in(1000), Some(500));
+        assert!(repeat.poll(1499).is_none());
+
+        let repeated = repeat.poll(1500).unwrap();
+        assert_eq!((repeated.key_code, repeated.event_type, repeated.timestamp), (KeyCode::A, KeyEventType::KeyPress, 1500));
+        assert!(repeat.poll(1549).is_none());
+        assert!(repeat.poll(1550).is_some());
+
+        // The keyboard's own repeat is dropped
+        assert!(!repeat.key_event(&key(KeyEventType::KeyPress, KeyCode::A), 1560));
+
+        assert!(repeat.key_event(&key(KeyEventType::KeyRelease, KeyCode::A), 1570));
+        assert!(repeat.poll(5000).is_none());
+        assert_eq!(repeat.next_repeat_in(5000), None);
+    }
+
+    #[test]
+    fn test_modifiers_do_not_repeat() {
+        let mut repeat = KeyRepeat::new();
+        repeat.key_event(&key(KeyEventType::KeyPress, KeyCode::B), 0);
+        // Shift pressed after B leaves B repeating
+        repeat.key_event(&key(KeyEventType::KeyPress, KeyCode::LeftShift), 10);
+        assert_eq!(repeat.poll(DEFAULT_DELAY_MS as u64).unwrap().key_code, KeyCode::B);
+
+        // Releasing another key leaves it repeating too
+        repeat.key_event(&key(KeyEventType::KeyRelease, KeyCode::C), 600);
+        assert!(repeat.next_repeat_in(600).is_some());
+
+        repeat.configure(0, 0);
+        repeat.key_event(&key(KeyEventType::KeyPress, KeyCode::D), 700);
+        assert!(repeat.poll(10_000).is_none());
+    }
+}
@@ -0,0 +1,327 @@
+//! Focus routing
+//!
+//! Events wait in a bounded queue until they are sent to the process with
+//! focus, which keeps them in order when its message queue is full. Events
+//! that arrive while no process has focus are dropped.
+//!
+//! Key events go through the keymap before they are queued, and a held
+//! key's repeats are typed again, so that a repeated letter after a dead
+//! key comes out plain, as on other systems.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use kosh_driver::input::{self, InputEvent, InputRecord};
+use kosh_ipc::IpcError;
+use kosh_service::{InputRequest, ServiceData, ServiceStatus};
+use kosh_types::ProcessId;
+use crate::keymap::{Compose, Keymap};
+use crate::repeat::KeyRepeat;
+
+/// Most events waiting for the process with focus; older ones are dropped
+pub const MAX_PENDING_EVENTS: usize = 256;
+
+/// Sends the events of every input device to the process with focus
+pub struct InputRouter {
+    /// In the order they subscribed
+    subscribers: Vec<ProcessId>,
+    focus: Option<ProcessId>,
+    pending: VecDeque<InputRecord>,
+    repeat: KeyRepeat,
+    compose: Compose,
+}
+
+impl InputRouter {
+    pub fn new() -> Self {
+        Self {
+            subscribers: Vec::new(),
+            focus: None,
+            pending: VecDeque::new(),
+            repeat: KeyRepeat::new(),
+            compose: Compose::new(),
+        }
+    }
+
+    /// The process with focus
+    pub fn focus(&self) -> Option<ProcessId> {
+        self.focus
+    }
+
+    /// Send `pid` input while it has focus
+    pub fn subscribe(&mut self, pid: ProcessId) {
+        if !self.subscribers.contains(&pid) {
+            self.subscribers.push(pid);
+        }
+        if self.focus.is_none() {
+            self.focus = Some(pid);
+        }
+    }
+
+    /// Stop sending `pid` input, as when it exits
+    pub fn unsubscribe(&mut self, pid: ProcessId) {
+        self.subscribers.retain(|&subscriber| subscriber != pid);
+        if self.focus == Some(pid) {
+            self.move_focus(self.subscribers.last().copied());
+        }
+    }
+
+    /// Give focus to `pid`; false if it has not subscribed
+    pub fn set_focus(&mut self, pid: ProcessId) -> bool {
+        if !self.subscribers.contains(&pid) {
+            return false;
+        }
+        if self.focus != Some(pid) {
+            self.move_focus(Some(pid));
+        }
+        true
+    }
+
+    fn move_focus(&mut self, pid: Option<ProcessId>) {
+        self.focus = pid;
+        // Neither a held key nor input typed for the old process carries over
+        self.repeat.release();
+        self.pending.clear();
+    }
+
+    /// Take the events of a message a driver sent; false if it is not an
+    /// input or touch batch
+    pub fn handle_batch(&mut self, bytes: &[u8], now_ms: u64) -> bool {
+        let records = if input::is_input_batch(bytes) {
+            input::decode_input_batch(bytes).ok()
+        } else if input::is_touch_batch(bytes) {
+            input::decode_touch_batch(bytes).ok()
+                .map(|events| events.into_iter().map(InputRecord::Touch).collect())
+        } else {
+            return false;
+        };
+        for record in records.into_iter().flatten() {
+            self.push(record, now_ms);
+        }
+        true
+    }
+
+    /// Queue an event for the process with focus
+    pub fn push(&mut self, record: InputRecord, now_ms: u64) {
+        let InputRecord::Key(event) = record else {
+            self.queue(record);
+            return;
+        };
+        if !self.repeat.key_event(&event, now_ms) {
+            return;
+        }
+        self.type_key(&event);
+        if self.compose.is_pending() {
+            // A dead key does not repeat
+            self.repeat.release();
+        }
+    }
+
+    /// Queue the events the keymap makes of a key event
+    fn type_key(&mut self, event: &InputEvent) {
+        for event in self.compose.key_event(event) {
+            self.queue(InputRecord::Key(event));
+        }
+    }
+
+    fn queue(&mut self, record: InputRecord) {
+        if self.focus.is_none() {
+            return;
+        }
+        if self.pending.len() >= MAX_PENDING_EVENTS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(record);
+    }
+
+    /// Queue the key repeat due at `now_ms`
+    pub fn poll_repeat(&mut self, now_ms: u64) {
+        if let Some(event) = self.repeat.poll(now_ms) {
+            self.type_key(&event);
+        }
+    }
+
+    /// Milliseconds from `now_ms` until a held key repeats
+    pub fn next_repeat_in(&self, now_ms: u64) -> Option<u64> {
+        self.repeat.next_repeat_in(now_ms)
+    }
+
+    /// Send the waiting events to the process with focus with `send`
+    ///
+    /// A process whose queue is full gets the rest later; one that has gone
+    /// away is unsubscribed.
+    pub fn flush<F>(&mut self, mut send: F)
+    where
+        F: FnMut(ProcessId, &[u8]) -> Result<(), IpcError>,
+    {
+        while let Some(pid) = self.focus.filter(|_| !self.pending.is_empty()) {
+            let (batch, count) = input::encode_input_batch(self.pending.make_contiguous());
+            match send(pid, &batch) {
+                Ok(()) => {
+                    self.pending.drain(..count);
+                }
+                Err(IpcError::ChannelFull | IpcError::WouldBlock) => return,
+                Err(_) => self.unsubscribe(pid),
+            }
+        }
+    }
+
+    /// Answer an `InputRequest` from `requester`
+    pub fn handle_request(&mut self, requester: ProcessId, request: InputRequest) -> (ServiceStatus, ServiceData) {
+        match request {
+            InputRequest::Subscribe => self.subscribe(requester),
+            InputRequest::Unsubscribe => self.unsubscribe(requester),
+            InputRequest::SetFocus { pid } => {
+                if !self.set_focus(pid) {
+                    return (ServiceStatus::NotFound, ServiceData::Empty);
+                }
+            }
+            InputRequest::GetFocus => {
+                return match self.focus {
+                    Some(pid) => (ServiceStatus::Success, ServiceData::Binary(pid.to_le_bytes().to_vec())),
+                    None => (ServiceStatus::NotFound, ServiceData::Empty),
+                };
+            }
+            InputRequest::SetKeyRepeat { delay_ms, interval_ms } => self.repeat.configure(delay_ms, interval_ms),
+            InputRequest::SetKeymap { name } => match Keymap::find(&name) {
+                Some(keymap) => self.compose.set_keymap(keymap),
+                None => return (ServiceStatus::NotFound, ServiceData::Empty),
+            },
+        }
+        (ServiceStatus::Success, ServiceData::Empty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use kosh_driver::input::{
+        InputEvent, KeyCode, KeyEventType, KeyModifiers, MouseButtons, MouseEvent, TouchEventType, TouchInputEvent,
+    };
+
+    fn key_press(key_code: KeyCode, timestamp: u64) -> InputRecord {
+        InputRecord::Key(InputEvent {
+            event_type: KeyEventType::KeyPress,
+            key_code,
+            scancode: key_code as u8,
+            modifiers: KeyModifiers::SHIFT,
+            ascii_char: Some('A'),
+            timestamp,
+        })
+    }
+
+    fn sent(router: &mut InputRouter) -> Vec<(ProcessId, Vec<InputRecord>)> {
+        let mut batches = Vec::new();
+        router.flush(|pid, batch| {
+            batches.push((pid, input::decode_input_batch(batch).unwrap()));
+            Ok(())
+        });
+        batches
+    }
+
+    #[test]
+    fn test_events_go_to_focus() {
+        let mut router = InputRouter::new();
+        router.push(key_press(KeyCode::Z, 0), 0);
+        assert!(sent(&mut router).is_empty());
+
+        router.subscribe(10);
+        router.subscribe(11);
+        assert_eq!(router.focus(), Some(10));
+
+        let (batch, _) = input::encode_input_batch(&[
+            key_press(KeyCode::A, 5),
+            InputRecord::Mouse(MouseEvent { dx: -3, dy: 4, wheel: -1, buttons: MouseButtons::LEFT, timestamp: 6 }),
+        ]);
+        assert!(router.handle_batch(&batch, 6));
+        let touch = TouchInputEvent { event_type: TouchEventType::Up, x: 7, y: 8, pressure: 0, timestamp_us: 7000, touch_id: 2 };
+        assert!(router.handle_batch(&input::encode_touch_batch(&[touch]), 7));
+        assert!(!router.handle_batch(b"not a batch", 7));
+
+        let batches = sent(&mut router);
+        assert_eq!(batches.len(), 1);
+        let (pid, records) = &batches[0];
+        assert_eq!((*pid, records.len()), (10, 3));
+        match &records[0] {
+            InputRecord::Key(event) => {
+                assert_eq!((event.key_code, event.ascii_char, event.timestamp), (KeyCode::A, Some('A'), 5));
+                assert!(event.modifiers.contains(KeyModifiers::SHIFT));
+            }
+            other => panic!("expected a key, got {:?}", other),
+        }
+        match &records[1] {
+            InputRecord::Mouse(event) => assert_eq!((event.dx, event.dy, event.wheel), (-3, 4, -1)),
+            other => panic!("expected a mouse event, got {:?}", other),
+        }
+        match &records[2] {
+            InputRecord::Touch(event) => assert_eq!(*event, touch),
+            other => panic!("expected a touch, got {:?}", other),
+        }
+
+        assert!(router.set_focus(11));
+        assert!(!router.set_focus(12));
+        router.push(key_press(KeyCode::B, 8), 8);
+        assert_eq!(sent(&mut router)[0].0, 11);
+    }
+
+    #[test]
+    fn test_keys_repeat_through_the_keymap() {
+        let mut router = InputRouter::new();
+        router.subscribe(10);
+        router.handle_request(10, InputRequest::SetKeyRepeat { delay_ms: 500, interval_ms: 50 });
+        let (status, _) = router.handle_request(10, InputRequest::SetKeymap { name: "dvorak".into() });
+        assert_eq!(status, ServiceStatus::NotFound);
+        let (status, _) = router.handle_request(10, InputRequest::SetKeymap { name: "us-intl".into() });
+        assert_eq!(status, ServiceStatus::Success);
+
+        let key = |key_code: KeyCode, scancode: u8, ascii_char: char| InputRecord::Key(InputEvent {
+            event_type: KeyEventType::KeyPress,
+            key_code,
+            scancode,
+            modifiers: KeyModifiers::empty(),
+            ascii_char: Some(ascii_char),
+            timestamp: 0,
+        });
+        // A held dead key does not repeat; the letter after it does,
+        // plain
+        router.push(key(KeyCode::Unknown, 0x28, '\''), 0);
+        router.poll_repeat(600);
+        router.push(key(KeyCode::E, 0x12, 'e'), 700);
+        router.poll_repeat(1200);
+        router.poll_repeat(1250);
+
+        let typed: Vec<Option<char>> = sent(&mut router).into_iter()
+            .flat_map(|(_, records)| records)
+            .map(|record| match record {
+                InputRecord::Key(event) => event.ascii_char,
+                other => panic!("expected a key, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(typed, [None, Some('é'), Some('e'), Some('e')]);
+    }
+
+    #[test]
+    fn test_focus_follows_unsubscribe_and_exit() {
+        let mut router = InputRouter::new();
+        for pid in [10, 11, 12] {
+            router.handle_request(pid, InputRequest::Subscribe);
+        }
+        router.handle_request(12, InputRequest::SetFocus { pid: 11 });
+        router.handle_request(11, InputRequest::Unsubscribe);
+        assert_eq!(router.focus(), Some(12));
+        let (status, data) = router.handle_request(10, InputRequest::GetFocus);
+        assert_eq!(status, ServiceStatus::Success);
+        assert!(matches!(data, ServiceData::Binary(bytes) if bytes == vec![12, 0, 0, 0]));
+
+        // A full queue keeps the events; a process that has gone away
+        // loses focus
+        router.push(key_press(KeyCode::C, 0), 0);
+        router.flush(|_, _| Err(IpcError::ChannelFull));
+        assert_eq!(router.focus(), Some(12));
+        router.flush(|_, _| Err(IpcError::InvalidReceiver));
+        assert_eq!(router.focus(), Some(10));
+
+        router.handle_request(10, InputRequest::Unsubscribe);
+        let (status, _) = router.handle_request(10, InputRequest::GetFocus);
+        assert_eq!(status, ServiceStatus::NotFound);
+    }
+}
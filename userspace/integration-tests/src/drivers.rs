@@ -0,0 +1,63 @@
+//! driver-manager, through the service requests the shell's `lsdrv`,
+//! `insmod` and `rmmod` send
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use kosh_ipc::poll::{poll, PollEntry};
+use kosh_service::{DriverRequest, ServiceClient, ServiceData, ServiceError, ServiceResponse, ServiceStatus, ServiceType};
+use kosh_types::ProcessId;
+use crate::runner::{ensure, failed, TestCase, TestCategory, TestError, TestResult};
+use crate::targets::Targets;
+
+/// How long a request may take before the test fails
+const REQUEST_TIMEOUT_MS: u64 = 2000;
+
+pub fn tests() -> Vec<TestCase> {
+    let case = |name, test_fn| TestCase { name, test_fn, category: TestCategory::DriverManager };
+    vec![
+        case("list_drivers", test_list_drivers),
+        case("load_missing_driver", test_load_missing_driver),
+        case("unload_unknown_driver", test_unload_unknown_driver),
+    ]
+}
+
+fn driver_manager(targets: &Targets) -> Result<ProcessId, TestError> {
+    targets.driver_manager.ok_or_else(|| TestError::Skipped(String::from("driver-manager is not running")))
+}
+
+/// Send `request` and wait for the response
+fn request(service: ProcessId, request: DriverRequest) -> Result<ServiceResponse, TestError> {
+    let mut client = ServiceClient::new();
+    client.send_request(service, ServiceType::DriverManager, ServiceData::DriverRequest(request))
+        .map_err(failed("send"))?;
+    loop {
+        let mut entries = [PollEntry::from_sender(service)];
+        if poll(&mut entries, REQUEST_TIMEOUT_MS).map_err(failed("poll"))? == 0 {
+            return Err(TestError::Failed(format!("no response within {} ms", REQUEST_TIMEOUT_MS)));
+        }
+        match client.receive_response() {
+            Ok(response) => return Ok(response),
+            Err(ServiceError::WouldBlock) => continue,
+            Err(error) => return Err(failed("receive")(error)),
+        }
+    }
+}
+
+fn test_list_drivers(targets: &Targets) -> TestResult {
+    let response = request(driver_manager(targets)?, DriverRequest::ListDrivers)?;
+    ensure(response.status == ServiceStatus::Success, || format!("status {:?}", response.status))?;
+    ensure(matches!(response.data, ServiceData::Text(_)), || String::from("the listing is not text"))
+}
+
+fn test_load_missing_driver(targets: &Targets) -> TestResult {
+    let path = String::from("/drivers/integration-tests-missing");
+    let response = request(driver_manager(targets)?, DriverRequest::LoadDriver { path })?;
+    ensure(response.status != ServiceStatus::Success, || String::from("loaded a driver that does not exist"))
+}
+
+fn test_unload_unknown_driver(targets: &Targets) -> TestResult {
+    let response = request(driver_manager(targets)?, DriverRequest::UnloadDriver { driver_id: u32::MAX })?;
+    ensure(response.status == ServiceStatus::InvalidRequest, || format!("status {:?}", response.status))
+}
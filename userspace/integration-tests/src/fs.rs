@@ -0,0 +1,118 @@
+//! fs-service, through the file system calls every program uses
+//!
+//! Files are created under `TEST_DIR`, which a run leaves behind; each
+//! test writes its own file, so a rerun on the same disk starts clean.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use kosh_posix::errno::ENOENT;
+use kosh_posix::{O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY};
+use crate::runner::{ensure, failed, TestCase, TestCategory, TestError, TestResult};
+use crate::targets::Targets;
+
+/// Directory the tests work in
+pub const TEST_DIR: &str = "/tmp/integration-tests";
+
+pub fn tests() -> Vec<TestCase> {
+    let case = |name, test_fn| TestCase { name, test_fn, category: TestCategory::FileSystem };
+    vec![
+        case("create_directory", test_create_directory),
+        case("write_then_read", test_write_then_read),
+        case("stat_reports_size", test_stat_reports_size),
+        case("truncate_on_open", test_truncate_on_open),
+        case("list_directory", test_list_directory),
+        case("open_missing_file", test_open_missing_file),
+    ]
+}
+
+/// The service answers requests at all; later tests need the directory
+fn prepare(targets: &Targets) -> TestResult {
+    if targets.fs_service.is_none() {
+        return Err(TestError::Skipped(String::from("fs-service is not running")));
+    }
+    // Left over from an earlier run is as good as new
+    let _ = kosh_posix::mkdir(TEST_DIR, 0o755);
+    let status = kosh_posix::stat(TEST_DIR).map_err(failed("stat"))?;
+    ensure(status.is_dir(), || format!("{} is not a directory", TEST_DIR))
+}
+
+fn write_file(path: &str, data: &[u8]) -> TestResult {
+    let fd = kosh_posix::open(path, O_WRONLY | O_CREAT | O_TRUNC, 0o644).map_err(failed("open for writing"))?;
+    let written = kosh_posix::write_all(fd, data).map_err(failed("write"));
+    kosh_posix::close(fd).map_err(failed("close"))?;
+    written
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, TestError> {
+    let fd = kosh_posix::open(path, O_RDONLY, 0).map_err(failed("open for reading"))?;
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 512];
+    let result = loop {
+        match kosh_posix::read(fd, &mut buffer) {
+            Ok(0) => break Ok(data),
+            Ok(count) => data.extend_from_slice(&buffer[..count]),
+            Err(errno) => break Err(failed("read")(errno)),
+        }
+    };
+    kosh_posix::close(fd).map_err(failed("close"))?;
+    result
+}
+
+fn test_create_directory(targets: &Targets) -> TestResult {
+    prepare(targets)
+}
+
+fn test_write_then_read(targets: &Targets) -> TestResult {
+    prepare(targets)?;
+    let path = format!("{}/roundtrip", TEST_DIR);
+    // Larger than one transfer through the kernel, so writes come back short
+    let data: Vec<u8> = (0..6000u32).map(|i| (i % 251) as u8).collect();
+    write_file(&path, &data)?;
+    let read = read_file(&path)?;
+    ensure(read == data, || format!("read back {} bytes that differ from the {} written", read.len(), data.len()))
+}
+
+fn test_stat_reports_size(targets: &Targets) -> TestResult {
+    prepare(targets)?;
+    let path = format!("{}/sized", TEST_DIR);
+    write_file(&path, b"seventeen bytes!\n")?;
+    let status = kosh_posix::stat(&path).map_err(failed("stat"))?;
+    ensure(status.is_file(), || format!("{} is not a regular file", path))?;
+    ensure(status.st_size == 17, || format!("size {}, expected 17", status.st_size))
+}
+
+fn test_truncate_on_open(targets: &Targets) -> TestResult {
+    prepare(targets)?;
+    let path = format!("{}/truncated", TEST_DIR);
+    write_file(&path, b"to be thrown away")?;
+    let fd = kosh_posix::open(&path, O_WRONLY | O_TRUNC, 0).map_err(failed("open"))?;
+    kosh_posix::close(fd).map_err(failed("close"))?;
+    let status = kosh_posix::stat(&path).map_err(failed("stat"))?;
+    ensure(status.st_size == 0, || format!("size {} after O_TRUNC", status.st_size))
+}
+
+fn test_list_directory(targets: &Targets) -> TestResult {
+    prepare(targets)?;
+    write_file(&format!("{}/listed", TEST_DIR), b"")?;
+    let mut dir = kosh_posix::opendir(TEST_DIR).map_err(failed("opendir"))?;
+    let mut found = false;
+    while let Some(entry) = kosh_posix::readdir(&mut dir) {
+        found |= entry.d_name == "listed";
+    }
+    kosh_posix::closedir(dir);
+    ensure(found, || format!("listed is missing from the listing of {}", TEST_DIR))
+}
+
+fn test_open_missing_file(targets: &Targets) -> TestResult {
+    prepare(targets)?;
+    match kosh_posix::open(&format!("{}/missing", TEST_DIR), O_RDONLY, 0) {
+        Err(errno) if errno == ENOENT => Ok(()),
+        Err(errno) => Err(TestError::Failed(format!("expected ENOENT, got {}", errno))),
+        Ok(fd) => {
+            let _ = kosh_posix::close(fd);
+            Err(TestError::Failed(String::from("opened a file that does not exist")))
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! IPC between processes, through messages and pipes
+//!
+//! Each test forks the peer it talks to, and grants the two of them the
+//! capabilities to message each other before the child starts. A send
+//! refused for lack of one is a failure like any other.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use kosh_ipc::capability::{self, CapabilityKind, ResourceDescriptor};
+use kosh_ipc::poll::wait_for_message;
+use kosh_ipc::syscall::{receive_message, send_message, MAX_MESSAGE_SIZE};
+use kosh_ipc::IpcError;
+use kosh_types::ProcessId;
+use crate::runner::{ensure, failed, TestCase, TestCategory, TestError, TestResult};
+use crate::targets::Targets;
+
+/// How long either side waits for the other
+const PEER_TIMEOUT_MS: u64 = 2000;
+
+/// A process ID no process has
+const NO_PROCESS: ProcessId = u32::MAX - 1;
+
+pub fn tests() -> Vec<TestCase> {
+    let case = |name, test_fn| TestCase { name, test_fn, category: TestCategory::Ipc };
+    vec![
+        case("send_to_missing_process", test_send_to_missing_process),
+        case("wait_times_out", test_wait_times_out),
+        case("message_round_trip", test_message_round_trip),
+        case("short_buffer_keeps_message", test_short_buffer_keeps_message),
+        case("pipe_between_processes", test_pipe_between_processes),
+    ]
+}
+
+fn test_send_to_missing_process(_: &Targets) -> TestResult {
+    match send_message(NO_PROCESS, b"anyone there?") {
+        Err(IpcError::InvalidReceiver) => Ok(()),
+        other => Err(TestError::Failed(format!("expected InvalidReceiver, got {:?}", other))),
+    }
+}
+
+fn test_wait_times_out(_: &Targets) -> TestResult {
+    let arrived = wait_for_message(10).map_err(failed("wait"))?;
+    ensure(!arrived, || String::from("a message arrived that nobody sent"))
+}
+
+/// Fork a child that runs `child`, which returns its exit status
+///
+/// The child waits on a pipe until the parent has given both of them the
+/// capabilities to message each other.
+fn spawn(child: impl FnOnce() -> i32) -> Result<ProcessId, TestError> {
+    let parent = kosh_posix::getpid();
+    let (ready_read, ready_write) = kosh_posix::pipe().map_err(failed("pipe"))?;
+    let pid = match kosh_posix::fork() {
+        Ok(0) => {
+            let _ = kosh_posix::close(ready_write);
+            let mut byte = [0u8; 1];
+            let status = match kosh_posix::read(ready_read, &mut byte) {
+                Ok(1) => child(),
+                // The parent could not connect the two of them
+                _ => 1,
+            };
+            kosh_posix::exit(status)
+        }
+        Ok(pid) => pid,
+        Err(errno) => {
+            let _ = kosh_posix::close(ready_read);
+            let _ = kosh_posix::close(ready_write);
+            return Err(failed("fork")(errno));
+        }
+    };
+    let _ = kosh_posix::close(ready_read);
+
+    let connected = connect(parent, pid);
+    if connected.is_ok() {
+        let _ = kosh_posix::write_all(ready_write, &[1]);
+    }
+    // Closing the pipe without writing lets a child left unconnected exit
+    let _ = kosh_posix::close(ready_write);
+    match connected {
+        Ok(()) => Ok(pid),
+        Err(error) => {
+            let _ = kosh_posix::waitpid(pid, 0);
+            Err(error)
+        }
+    }
+}
+
+/// Give this process and its child `child` the capabilities to message
+/// each other
+fn connect(parent: ProcessId, child: ProcessId) -> TestResult {
+    let grants = [
+        (child, CapabilityKind::SendMessage, parent),
+        (child, CapabilityKind::ReceiveMessage, parent),
+        (parent, CapabilityKind::SendMessage, child),
+        (parent, CapabilityKind::ReceiveMessage, child),
+    ];
+    for (holder, kind, peer) in grants {
+        capability::grant(holder, kind, &ResourceDescriptor::process(peer))
+            .map_err(failed(&format!("grant process {} {:?} for process {}", holder, kind, peer)))?;
+    }
+    Ok(())
+}
+
+/// Collect `child`
+///
+/// The child's status takes precedence over `result`, since a child that
+/// failed leaves the parent waiting for nothing.
+fn reap(child: ProcessId, result: TestResult) -> TestResult {
+    if result.is_err() {
+        let _ = kosh_posix::kill(child, kosh_posix::SIGKILL);
+    }
+    match kosh_posix::waitpid(child, 0) {
+        Ok(Some((_, status))) if status != 0 && result.is_ok() => {
+            Err(TestError::Failed(format!("the child exited with status {}", status)))
+        }
+        _ => result,
+    }
+}
+
+/// Send `payload` to `receiver`, as an exit status for a child
+fn child_send(receiver: ProcessId, payload: &[u8]) -> i32 {
+    match send_message(receiver, payload) {
+        Ok(()) => 0,
+        Err(_) => 1,
+    }
+}
+
+/// Wait up to `PEER_TIMEOUT_MS` for a message from `sender`
+fn receive_from(sender: ProcessId, buffer: &mut [u8]) -> Result<usize, IpcError> {
+    loop {
+        match receive_message(buffer) {
+            Ok((from, length)) if from == sender => return Ok(length),
+            Ok(_) => continue,
+            Err(IpcError::WouldBlock) => {
+                if !wait_for_message(PEER_TIMEOUT_MS)? {
+                    return Err(IpcError::Timeout);
+                }
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+fn test_message_round_trip(_: &Targets) -> TestResult {
+    let parent = kosh_posix::getpid();
+    let payload: Vec<u8> = (0..=255).collect();
+    let child = spawn(|| {
+        let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+        match receive_from(parent, &mut buffer) {
+            Ok(length) => child_send(parent, &buffer[..length]),
+            Err(_) => 1,
+        }
+    })?;
+
+    let result = (|| {
+        send_message(child, &payload).map_err(failed("send"))?;
+        let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+        let length = receive_from(child, &mut buffer).map_err(failed("receive the echo"))?;
+        ensure(buffer[..length] == payload[..], || format!("the echo of {} bytes came back as {} different ones", payload.len(), length))
+    })();
+    reap(child, result)
+}
+
+/// A message too large for the buffer offered is refused and stays queued
+fn test_short_buffer_keeps_message(_: &Targets) -> TestResult {
+    let parent = kosh_posix::getpid();
+    let child = spawn(|| child_send(parent, &[0x5A; 64]))?;
+
+    let result = (|| {
+        let mut short = [0u8; 16];
+        match receive_from(child, &mut short) {
+            Err(IpcError::MessageTooLarge) => {}
+            other => return Err(TestError::Failed(format!("expected MessageTooLarge, got {:?}", other))),
+        }
+        let mut buffer = [0u8; 64];
+        let length = receive_from(child, &mut buffer).map_err(failed("receive with room"))?;
+        ensure(length == 64 && buffer.iter().all(|&byte| byte == 0x5A), || format!("received {} bytes that differ from those sent", length))
+    })();
+    reap(child, result)
+}
+
+fn test_pipe_between_processes(_: &Targets) -> TestResult {
+    const TEXT: &[u8] = b"through a pipe to the parent";
+    let (read_end, write_end) = kosh_posix::pipe().map_err(failed("pipe"))?;
+    let child = spawn(|| {
+        let _ = kosh_posix::close(read_end);
+        match kosh_posix::write_all(write_end, TEXT) {
+            Ok(()) => 0,
+            Err(_) => 1,
+        }
+    });
+    let _ = kosh_posix::close(write_end);
+    let child = match child {
+        Ok(child) => child,
+        Err(error) => {
+            let _ = kosh_posix::close(read_end);
+            return Err(error);
+        }
+    };
+
+    let result = (|| {
+        let mut received = Vec::new();
+        let mut buffer = [0u8; 64];
+        // The child exiting closes the last write end
+        loop {
+            match kosh_posix::read(read_end, &mut buffer).map_err(failed("read"))? {
+                0 => break,
+                count => received.extend_from_slice(&buffer[..count]),
+            }
+        }
+        ensure(received == TEXT, || format!("read {} bytes that differ from the {} written", received.len(), TEXT.len()))
+    })();
+    let _ = kosh_posix::close(read_end);
+    reap(child, result)
+}
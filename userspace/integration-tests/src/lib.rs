@@ -0,0 +1,31 @@
+//! Userspace integration tests
+//!
+//! When the kernel boots with `integration_tests=1`, init starts this
+//! orchestrator in place of the shell. It exercises fs-service,
+//! driver-manager and IPC the way any other program would, through real
+//! system calls and service requests, prints a line per test and a summary
+//! to the console, and exits with 0 only when nothing failed. Init hands
+//! that status to the kernel, which leaves QEMU through the isa-debug-exit
+//! device, so `scripts/run-integration-tests.sh` reads the outcome from
+//! QEMU's exit code the way the kernel test script does.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod runner;
+pub mod targets;
+pub mod fs;
+pub mod drivers;
+pub mod ipc;
+
+pub use runner::{TestCase, TestCategory, TestError, TestOutcome, TestResult, TestRunner, TestStats};
+pub use targets::Targets;
+
+/// Every integration test, in the order they run
+pub fn all_tests() -> alloc::vec::Vec<TestCase> {
+    let mut tests = fs::tests();
+    tests.extend(drivers::tests());
+    tests.extend(ipc::tests());
+    tests
+}
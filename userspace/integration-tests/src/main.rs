@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use kosh_integration_tests::{all_tests, Targets, TestRunner};
+
+// Global allocator setup
+use linked_list_allocator::LockedHeap;
+
+#[global_allocator]
+static ALLOCATOR: LockedHeap = LockedHeap::empty();
+
+/// Exit status for arguments init should never have passed
+const EXIT_USAGE: i32 = 2;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    init_heap();
+
+    let args = kosh_posix::argv().unwrap_or_default();
+    let targets = match Targets::from_args(args.get(1..).unwrap_or(&[])) {
+        Ok(targets) => targets,
+        Err(message) => {
+            print(&alloc::format!("integration-tests: {}\n", message));
+            kosh_posix::exit(EXIT_USAGE);
+        }
+    };
+    if let Some(fs_service) = targets.fs_service {
+        kosh_posix::set_fs_service_pid(fs_service);
+    }
+
+    let mut runner = TestRunner::new(all_tests());
+    let stats = runner.run_all(&targets, &mut |text| print(text));
+    // Init hands the status to the kernel, which ends the QEMU run with it
+    kosh_posix::exit(stats.exit_status())
+}
+
+fn print(text: &str) {
+    let _ = kosh_posix::write_all(kosh_posix::STDOUT_FILENO, text.as_bytes());
+}
+
+fn init_heap() {
+    const HEAP_SIZE: usize = 128 * 1024;
+    static mut HEAP_MEMORY: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+
+    unsafe {
+        let heap_ptr = core::ptr::addr_of_mut!(HEAP_MEMORY);
+        ALLOCATOR.lock().init((*heap_ptr).as_mut_ptr(), HEAP_SIZE);
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    print("integration-tests: panicked\n");
+    kosh_posix::exit(1);
+}
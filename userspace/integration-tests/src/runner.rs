@@ -0,0 +1,211 @@
+//! Running tests and reporting their results
+//!
+//! Output follows the kernel test harness, a header per category and a
+//! `[PASS]`, `[FAIL]` or `[SKIP]` line per test, so both read alike in a
+//! QEMU serial log. Failures and skips also say why.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use crate::targets::Targets;
+
+/// How a test ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestOutcome {
+    Pass,
+    Fail,
+    Skip,
+}
+
+impl fmt::Display for TestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestOutcome::Pass => write!(f, "PASS"),
+            TestOutcome::Fail => write!(f, "FAIL"),
+            TestOutcome::Skip => write!(f, "SKIP"),
+        }
+    }
+}
+
+/// Why a test did not pass
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestError {
+    Failed(String),
+    /// What the test needs is missing, e.g. the service it exercises did
+    /// not start
+    Skipped(String),
+}
+
+pub type TestResult = Result<(), TestError>;
+
+/// Fail with the message `message` builds unless `condition` holds
+pub fn ensure(condition: bool, message: impl FnOnce() -> String) -> TestResult {
+    if condition {
+        Ok(())
+    } else {
+        Err(TestError::Failed(message()))
+    }
+}
+
+/// Turn the error of a step into a failure naming the step
+pub fn failed<E: fmt::Debug>(step: &str) -> impl FnOnce(E) -> TestError + '_ {
+    move |error| TestError::Failed(format!("{}: {:?}", step, error))
+}
+
+/// Groups tests are run and reported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestCategory {
+    FileSystem,
+    DriverManager,
+    Ipc,
+}
+
+impl fmt::Display for TestCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestCategory::FileSystem => write!(f, "File System"),
+            TestCategory::DriverManager => write!(f, "Driver Manager"),
+            TestCategory::Ipc => write!(f, "IPC"),
+        }
+    }
+}
+
+pub struct TestCase {
+    pub name: &'static str,
+    pub test_fn: fn(&Targets) -> TestResult,
+    pub category: TestCategory,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TestStats {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+impl TestStats {
+    /// Exit status of the run: 0 when nothing failed
+    pub fn exit_status(&self) -> i32 {
+        if self.failed == 0 { 0 } else { 1 }
+    }
+}
+
+pub struct TestRunner {
+    tests: Vec<TestCase>,
+    stats: TestStats,
+}
+
+impl TestRunner {
+    pub fn new(tests: Vec<TestCase>) -> Self {
+        Self { tests, stats: TestStats::default() }
+    }
+
+    /// Run every test, category by category in the order each first
+    /// appears, passing each line of output to `print`
+    pub fn run_all(&mut self, targets: &Targets, print: &mut dyn FnMut(&str)) -> TestStats {
+        print("\n=== Kosh Userspace Integration Tests ===\n");
+        print(&format!("Running {} tests...\n\n", self.tests.len()));
+
+        self.stats = TestStats { total: self.tests.len(), ..TestStats::default() };
+
+        let mut categories = Vec::new();
+        for test in &self.tests {
+            if !categories.contains(&test.category) {
+                categories.push(test.category);
+            }
+        }
+        for category in categories {
+            print(&format!("--- {} Tests ---\n", category));
+            for test in self.tests.iter().filter(|test| test.category == category) {
+                let (outcome, reason) = match (test.test_fn)(targets) {
+                    Ok(()) => (TestOutcome::Pass, None),
+                    Err(TestError::Failed(reason)) => (TestOutcome::Fail, Some(reason)),
+                    Err(TestError::Skipped(reason)) => (TestOutcome::Skip, Some(reason)),
+                };
+                match outcome {
+                    TestOutcome::Pass => self.stats.passed += 1,
+                    TestOutcome::Fail => self.stats.failed += 1,
+                    TestOutcome::Skip => self.stats.skipped += 1,
+                }
+                match reason {
+                    Some(reason) => print(&format!("  [{}] {}: {}\n", outcome, test.name, reason)),
+                    None => print(&format!("  [{}] {}\n", outcome, test.name)),
+                }
+            }
+            print("\n");
+        }
+
+        self.print_summary(print);
+        self.stats
+    }
+
+    fn print_summary(&self, print: &mut dyn FnMut(&str)) {
+        print("=== Test Summary ===\n");
+        print(&format!("Total:   {}\n", self.stats.total));
+        print(&format!("Passed:  {}\n", self.stats.passed));
+        print(&format!("Failed:  {}\n", self.stats.failed));
+        print(&format!("Skipped: {}\n", self.stats.skipped));
+        if self.stats.failed > 0 {
+            print("\nSome integration tests failed\n");
+        } else {
+            print("\nAll integration tests passed\n");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn passes(_: &Targets) -> TestResult {
+        Ok(())
+    }
+
+    fn fails(_: &Targets) -> TestResult {
+        ensure(1 + 1 == 3, || "arithmetic".to_string())
+    }
+
+    fn skips(_: &Targets) -> TestResult {
+        Err(TestError::Skipped("no service".to_string()))
+    }
+
+    fn case(name: &'static str, test_fn: fn(&Targets) -> TestResult, category: TestCategory) -> TestCase {
+        TestCase { name, test_fn, category }
+    }
+
+    #[test]
+    fn test_run_all_groups_and_counts() {
+        let mut runner = TestRunner::new(vec![
+            case("a", passes, TestCategory::FileSystem),
+            case("b", skips, TestCategory::Ipc),
+            case("c", fails, TestCategory::FileSystem),
+        ]);
+        let mut output = String::new();
+        let stats = runner.run_all(&Targets::default(), &mut |text| output.push_str(text));
+
+        assert_eq!(stats, TestStats { total: 3, passed: 1, failed: 1, skipped: 1 });
+        assert_eq!(stats.exit_status(), 1);
+        let lines: Vec<&str> = output.lines().collect();
+        let position = |line: &str| lines.iter().position(|&l| l == line).unwrap();
+        assert!(position("--- File System Tests ---") < position("  [PASS] a"));
+        assert!(position("  [FAIL] c: arithmetic") < position("--- IPC Tests ---"));
+        assert!(position("--- IPC Tests ---") < position("  [SKIP] b: no service"));
+    }
+
+    #[test]
+    fn test_skips_do_not_fail_the_run() {
+        let mut runner = TestRunner::new(vec![case("b", skips, TestCategory::Ipc)]);
+        let stats = runner.run_all(&Targets::default(), &mut |_| {});
+        assert_eq!(stats.exit_status(), 0);
+    }
+
+    #[test]
+    fn test_failed_names_the_step() {
+        let error = Err::<(), _>("EIO").map_err(failed("open")).unwrap_err();
+        assert_eq!(error, TestError::Failed("open: \"EIO\"".to_string()));
+    }
+}
@@ -0,0 +1,49 @@
+//! Where the services under test are
+//!
+//! There is no name lookup for services yet, so init passes the process
+//! IDs of the ones it started: `--fs-service=PID --driver-manager=PID`.
+
+use alloc::format;
+use alloc::string::String;
+use kosh_types::ProcessId;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Targets {
+    pub fs_service: Option<ProcessId>,
+    pub driver_manager: Option<ProcessId>,
+}
+
+impl Targets {
+    /// Parse the arguments after the program name
+    pub fn from_args<S: AsRef<str>>(args: &[S]) -> Result<Self, String> {
+        let mut targets = Self::default();
+        for arg in args.iter().map(AsRef::as_ref) {
+            let (option, value) = arg.split_once('=').ok_or_else(|| format!("unexpected argument {}", arg))?;
+            let pid = value.parse().map_err(|_| format!("bad process ID in {}", arg))?;
+            match option {
+                "--fs-service" => targets.fs_service = Some(pid),
+                "--driver-manager" => targets.driver_manager = Some(pid),
+                _ => return Err(format!("unknown option {}", option)),
+            }
+        }
+        Ok(targets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_args() {
+        assert_eq!(
+            Targets::from_args(&["--fs-service=2", "--driver-manager=3"]),
+            Ok(Targets { fs_service: Some(2), driver_manager: Some(3) })
+        );
+        let none: [&str; 0] = [];
+        assert_eq!(Targets::from_args(&none), Ok(Targets::default()));
+        assert!(Targets::from_args(&["--fs-service"]).is_err());
+        assert!(Targets::from_args(&["--fs-service=two"]).is_err());
+        assert!(Targets::from_args(&["--network=4"]).is_err());
+    }
+}
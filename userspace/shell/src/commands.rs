@@ -1,28 +1,161 @@
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use alloc::format;
+use crate::completion::{self, Completion};
 use crate::error::{ShellError, ShellResult};
+use crate::infrastructure::{CommandParser, DriverRequest, FileSystemRequest, ShellServiceClient};
+use crate::jobs::{format_job, split_background, JobTable};
+use crate::pipeline::{self, StageIo};
+use crate::script::{self, Statement};
+use crate::types::{Environment, JobStatus, ParsedCommand, ProcessInfo};
+use core::ops::ControlFlow;
+use kosh_types::{MountFlags, ProcessId};
+use kosh_posix::{AuditRecord, Fd, LogLevel, TraceRecord, Tracepoint};
+use kosh_posix::sched::{self, SchedInfo, SchedPolicy, MAX_TIME_SLICE_MS, MIN_TIME_SLICE_MS};
+use kosh_posix::power::{self, CpuFreqInfo, CpuGovernor, PowerSupplyInfo, ThermalZoneInfo};
 
 pub struct CommandProcessor {
-    // Basic command processor - will be enhanced in later tasks
+    services: ShellServiceClient,
+    jobs: JobTable,
+    /// Shell variables
+    environment: Environment,
+    /// Exit status of the last command, as `$?`
+    last_status: i32,
 }
 
 impl CommandProcessor {
     pub fn new() -> Self {
-        Self {}
+        let mut services = ShellServiceClient::new();
+        let _ = services.discover_services();
+        Self { services, jobs: JobTable::new(), environment: Environment::new(), last_status: 0 }
     }
     
+    /// Run one command line
+    ///
+    /// Programs, given by path, run as children, alone or as a pipeline.
+    /// With a trailing `&` they stay in the background; otherwise they form
+    /// the foreground job, which the caller waits out with `foreground_pid`
+    /// and `reap_children`. Built-in commands finish before this returns,
+    /// and one may start a pipeline to feed its output to programs.
+    ///
+    /// Variables are expanded first, and `NAME=value` sets one.
     pub fn process_command(&mut self, command_line: &str) -> ShellResult<String> {
+        let command_line = self.expand(command_line);
+        self.run_expanded(&command_line)
+    }
+    
+    /// Exit status of the last command, as `$?` shows it
+    #[allow(dead_code)]
+    pub fn last_status(&self) -> i32 {
+        self.last_status
+    }
+    
+    /// Value of a shell variable, or of `?`
+    pub fn variable(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.last_status.to_string()),
+            _ => self.environment.get_var(name).map(str::to_string),
+        }
+    }
+    
+    /// Run a script
+    ///
+    /// Each command runs as if typed at the prompt, with programs started
+    /// in the foreground waited for before the next one. The output of
+    /// built-in commands and any errors are collected and returned; a
+    /// failing command does not stop the script, but `exit [status]` does.
+    pub fn run_script(&mut self, text: &str) -> ShellResult<String> {
+        let statements = script::parse(text)?;
+        let mut output = Vec::new();
+        let _ = self.run_statements(&statements, &mut output);
+        Ok(output.join("\n"))
+    }
+    
+    /// Take over the environment the shell was started with
+    ///
+    /// Every `NAME=value` entry becomes an exported variable, so programs
+    /// started from the shell see it too.
+    pub fn import_environment(&mut self, entries: &[String]) {
+        for (name, value) in entries.iter().filter_map(|entry| entry.split_once('=')) {
+            if script::is_name(name) {
+                self.environment.set_var(name.to_string(), value.to_string());
+                self.environment.export_var(name);
+            }
+        }
+    }
+    
+    /// Run the boot script, if there is one
+    pub fn run_rc_script(&mut self) -> Option<ShellResult<String>> {
+        let text = read_text(script::RC_SCRIPT)?;
+        Some(self.run_script(&text))
+    }
+    
+    fn run_expanded(&mut self, command_line: &str) -> ShellResult<String> {
         let command_line = command_line.trim();
         
         if command_line.is_empty() {
             return Ok(String::new());
         }
         
-        let parts: Vec<&str> = command_line.split_whitespace().collect();
-        let command = parts[0];
-        let args = &parts[1..];
+        // Builtins that report a false condition set the status themselves
+        self.last_status = 0;
+        if let Some((name, value)) = script::parse_assignment(command_line) {
+            self.environment.set_var(name.to_string(), value.to_string());
+            return Ok(String::new());
+        }
+        let result = self.run_line(command_line);
+        if let Err(error) = &result {
+            self.last_status = error.exit_status();
+        }
+        result
+    }
+    
+    fn run_line(&mut self, command_line: &str) -> ShellResult<String> {
+        let parsed = CommandParser::new().parse(command_line)?;
+        
+        // Scripts are run by the shell itself, since there is no interpreter to exec
+        if parsed.command.ends_with(".sh") && parsed.pipe_to.is_none() && !parsed.background {
+            let result = self.cmd_source(&[parsed.command.as_str()]);
+            return match &parsed.output_redirect {
+                Some(redirect) => pipeline::redirect_builtin(result, redirect),
+                None => result,
+            };
+        }
+        let (job_text, _) = split_background(command_line);
+        let stages: Vec<&ParsedCommand> = core::iter::successors(Some(&parsed), |stage| stage.pipe_to.as_deref()).collect();
         
+        // Everything is checked before anything runs
+        for (index, stage) in stages.iter().enumerate() {
+            match (is_builtin(&stage.command), index) {
+                (true, 0) => {}
+                (true, _) => {
+                    return Err(ShellError::InvalidArguments(format!("{} is built in and can only start a pipeline", stage.command)));
+                }
+                (false, _) if stage.command.contains('/') => {}
+                (false, _) => return Err(ShellError::InvalidCommand(stage.command.clone())),
+            }
+        }
+        
+        let (builtin, programs) = match stages.split_first() {
+            Some((first, rest)) if is_builtin(&first.command) => (Some(*first), rest),
+            _ => (None, &stages[..]),
+        };
+        let mut stdin = None;
+        if let Some(builtin) = builtin {
+            let result = self.run_builtin(&builtin.command, &arg_refs(builtin));
+            if programs.is_empty() {
+                return match &builtin.output_redirect {
+                    Some(redirect) => pipeline::redirect_builtin(result, redirect),
+                    None => result,
+                };
+            }
+            let output = result?;
+            stdin = Some(pipeline::feed(&format!("{}\n", output))?);
+        }
+        self.run_programs(programs, stdin, job_text, parsed.background)
+    }
+    
+    fn run_builtin(&mut self, command: &str, args: &[&str]) -> ShellResult<String> {
         match command {
             "help" => self.cmd_help(),
             "echo" => self.cmd_echo(args),
@@ -35,9 +168,30 @@ impl CommandProcessor {
             "rm" => self.cmd_rm(args),
             "pwd" => self.cmd_pwd(),
             "cd" => self.cmd_cd(args),
+            "sched" => self.cmd_sched(args),
+            "powerctl" => self.cmd_powerctl(args),
+            "thermal" => self.cmd_thermal(args),
+            "drivers" => self.cmd_drivers(args),
+            "mount" => self.cmd_mount(args),
+            "umount" => self.cmd_umount(args),
             "clear" => self.cmd_clear(),
             "exit" => self.cmd_exit(),
             "shutdown" => self.cmd_shutdown(),
+            "jobs" => self.cmd_jobs(),
+            "fg" => self.cmd_fg(args),
+            "bg" => self.cmd_bg(args),
+            "source" => self.cmd_source(args),
+            "test" => self.cmd_test(args),
+            "export" => self.cmd_export(args),
+            "unset" => self.cmd_unset(args),
+            "env" => self.cmd_env(),
+            "dmesg" => self.cmd_dmesg(args),
+            "audit" => self.cmd_audit(args),
+            "trace" => self.cmd_trace(args),
+            "[" => match args.split_last() {
+                Some((&"]", condition)) => self.cmd_test(condition),
+                _ => Err(ShellError::InvalidArguments("Missing ']'".to_string())),
+            },
             _ => Err(ShellError::InvalidCommand(command.to_string())),
         }
     }
@@ -55,9 +209,32 @@ impl CommandProcessor {
             rm       - Remove file\n\
             pwd      - Print working directory\n\
             cd       - Change directory\n\
+            sched    - Show or change the scheduler policy\n\
+            powerctl - Show CPU frequency scaling and battery, or change the governor\n\
+            thermal  - Show temperatures and trip points\n\
+            drivers  - List, load or unload drivers\n\
+            mount    - Mount a device or bind a directory\n\
+            umount   - Unmount a file system\n\
             clear    - Clear screen\n\
             exit     - Exit shell\n\
-            shutdown - Shutdown system";
+            shutdown - Shutdown system\n\
+            jobs     - List jobs started by this shell\n\
+            fg       - Bring a job to the foreground\n\
+            bg       - Resume a stopped job in the background\n\
+            source   - Run a script in this shell\n\
+            test, [  - Check a condition, for if statements\n\
+            export   - Pass variables on to programs\n\
+            unset    - Remove variables\n\
+            env      - List the variables programs get\n\
+            dmesg    - Show or filter the kernel log\n\
+            audit    - Show security audit events\n\
+            trace    - Turn kernel tracepoints on or off, or dump what they recorded\n\
+            \n\
+            Run a program by path, e.g. /bin/app; end the line with & to run it in the background.\n\
+            Connect commands with |, and redirect with < file, > file, >> file or 2> file.\n\
+            Set variables with NAME=value and use them as $NAME; $? is the last exit status.\n\
+            Programs only see variables that have been exported.\n\
+            Scripts (*.sh) may use if/then/elif/else/fi and for/in/do/done.";
         
         Ok(String::from(help_text))
     }
@@ -66,9 +243,21 @@ impl CommandProcessor {
         Ok(args.join(" "))
     }
     
+    /// Processes as listed under /proc
     fn cmd_ps(&self) -> ShellResult<String> {
-        // In a real implementation, this would query the kernel for process list
-        Ok(String::from("PID  NAME\n1    init\n2    fs-service\n3    driver-manager\n4    shell"))
+        let mut dir = kosh_posix::opendir("/proc")
+            .map_err(|_| ShellError::ServiceUnavailable("/proc".to_string()))?;
+        let mut processes = Vec::new();
+        while let Some(entry) = kosh_posix::readdir(&mut dir) {
+            if entry.d_name.parse::<u32>().is_err() {
+                continue;
+            }
+            // A process may exit between listing and reading its status
+            if let Some(process) = read_text(&format!("/proc/{}/status", entry.d_name)).as_deref().and_then(parse_proc_status) {
+                processes.push(process);
+            }
+        }
+        Ok(format_ps(&processes))
     }
     
     fn cmd_ls(&self, args: &[&str]) -> ShellResult<String> {
@@ -149,8 +338,865 @@ impl CommandProcessor {
         Ok(String::from("Goodbye!"))
     }
     
+    /// `sched [policy <rr|priority|cfs>] [slice <ms>]`
+    ///
+    /// Without arguments, shows the current policy and statistics.
+    fn cmd_sched(&self, args: &[&str]) -> ShellResult<String> {
+        let (policy, time_slice_ms) = parse_sched_args(args)?;
+        if policy.is_some() || time_slice_ms.is_some() {
+            sched::sched_set(policy, time_slice_ms).map_err(|errno| match errno {
+                kosh_posix::errno::EPERM | kosh_posix::errno::EACCES => ShellError::PermissionDenied("scheduler".to_string()),
+                errno => ShellError::SystemCallFailed(kosh_posix::raw::SYS_SCHED_SET, errno.0),
+            })?;
+        }
+        
+        let info = sched::sched_info()
+            .map_err(|errno| ShellError::SystemCallFailed(kosh_posix::raw::SYS_SCHED_INFO, errno.0))?;
+        Ok(format_sched_info(&info))
+    }
+    
+    /// `powerctl [governor <name>]`
+    ///
+    /// Without arguments, shows the governor, frequency and backend, and
+    /// the battery if there is one.
+    fn cmd_powerctl(&self, args: &[&str]) -> ShellResult<String> {
+        let permission_denied = || ShellError::PermissionDenied("power".to_string());
+        if let Some(governor) = parse_powerctl_args(args)? {
+            power::cpufreq_set_governor(governor).map_err(|errno| match errno {
+                kosh_posix::errno::EPERM | kosh_posix::errno::EACCES => permission_denied(),
+                errno => ShellError::SystemCallFailed(kosh_posix::raw::SYS_CPUFREQ_SET, errno.0),
+            })?;
+        }
+        
+        let info = power::cpufreq_info().map_err(|errno| match errno {
+            kosh_posix::errno::EPERM | kosh_posix::errno::EACCES => permission_denied(),
+            errno => ShellError::SystemCallFailed(kosh_posix::raw::SYS_CPUFREQ_INFO, errno.0),
+        })?;
+        let mut output = format_cpufreq_info(&info);
+        if let Ok(supply) = power::battery_info() {
+            output.push('\n');
+            output.push_str(&format_power_supply_info(&supply));
+        }
+        Ok(output)
+    }
+    
+    /// `thermal`
+    fn cmd_thermal(&self, args: &[&str]) -> ShellResult<String> {
+        if !args.is_empty() {
+            return Err(ShellError::InvalidArguments("Usage: thermal".to_string()));
+        }
+        let zones = power::thermal_info()
+            .map_err(|errno| ShellError::SystemCallFailed(kosh_posix::raw::SYS_THERMAL_INFO, errno.0))?;
+        Ok(format_thermal_zones(&zones))
+    }
+    
+    /// `dmesg [-l <level>] [-n <level>]`, `dmesg -p [-c]`
+    ///
+    /// `-l` shows only records at that level or more severe; `-n` sets
+    /// which records the kernel keeps from now on. `-p` shows the crash
+    /// report the previous boot left instead, and `-c` then drops it.
+    fn cmd_dmesg(&self, args: &[&str]) -> ShellResult<String> {
+        if let ["-p", rest @ ..] = args {
+            return self.dmesg_crash_report(rest);
+        }
+        let (show, keep) = parse_dmesg_args(args)?;
+        if let Some(level) = keep {
+            kosh_posix::klog_set_level(level).map_err(|errno| match errno {
+                kosh_posix::errno::EPERM | kosh_posix::errno::EACCES => ShellError::PermissionDenied("klog".to_string()),
+                errno => ShellError::SystemCallFailed(kosh_posix::raw::SYS_KLOG, errno.0),
+            })?;
+            return Ok(String::new());
+        }
+        
+        let log = kosh_posix::klog_read()
+            .map_err(|errno| ShellError::SystemCallFailed(kosh_posix::raw::SYS_KLOG, errno.0))?;
+        Ok(filter_klog(&log, show))
+    }
+    
+    /// `dmesg -p [-c]`
+    fn dmesg_crash_report(&self, args: &[&str]) -> ShellResult<String> {
+        let clear = match args {
+            [] => false,
+            ["-c"] => true,
+            _ => return Err(ShellError::InvalidArguments(DMESG_USAGE.to_string())),
+        };
+        let report = kosh_posix::crash_report_read()
+            .map_err(|errno| ShellError::SystemCallFailed(kosh_posix::raw::SYS_KLOG, errno.0))?;
+        if clear {
+            kosh_posix::crash_report_clear().map_err(|errno| match errno {
+                kosh_posix::errno::EPERM | kosh_posix::errno::EACCES => ShellError::PermissionDenied("klog".to_string()),
+                errno => ShellError::SystemCallFailed(kosh_posix::raw::SYS_KLOG, errno.0),
+            })?;
+        }
+        Ok(report.unwrap_or_else(|| "No crash report from the previous boot".to_string()))
+    }
+    
+    /// `audit [-n <count>]`
+    ///
+    /// Shows the security events the kernel still holds, or only the
+    /// newest `count` of them.
+    fn cmd_audit(&self, args: &[&str]) -> ShellResult<String> {
+        let count = parse_audit_args(args)?;
+        let records = kosh_posix::audit_read(0).map_err(|errno| match errno {
+            kosh_posix::errno::EPERM | kosh_posix::errno::EACCES => ShellError::PermissionDenied("audit".to_string()),
+            errno => ShellError::SystemCallFailed(kosh_posix::raw::SYS_AUDIT, errno.0),
+        })?;
+        let skip = count.map_or(0, |count| records.len().saturating_sub(count));
+        Ok(format_audit(&records[skip..]))
+    }
+    
+    /// `trace [status]`, `trace on|off <tracepoint>... | all`, `trace dump`
+    ///
+    /// `dump` takes the records out of the kernel, so each dump shows what
+    /// was recorded since the last.
+    fn cmd_trace(&self, args: &[&str]) -> ShellResult<String> {
+        let trace_error = |errno: kosh_posix::Errno| match errno {
+            kosh_posix::errno::EPERM | kosh_posix::errno::EACCES => ShellError::PermissionDenied("trace".to_string()),
+            errno => ShellError::SystemCallFailed(kosh_posix::raw::SYS_TRACE, errno.0),
+        };
+        match parse_trace_args(args)? {
+            TraceCommand::Status => {
+                let mask = kosh_posix::trace_status().map_err(trace_error)?;
+                let lost = kosh_posix::trace_lost().map_err(trace_error)?;
+                Ok(format_trace_status(mask, lost))
+            }
+            TraceCommand::On(mask) => {
+                kosh_posix::trace_enable(mask).map_err(trace_error)?;
+                Ok(String::new())
+            }
+            TraceCommand::Off(mask) => {
+                kosh_posix::trace_disable(mask).map_err(trace_error)?;
+                Ok(String::new())
+            }
+            TraceCommand::Dump => Ok(format_trace(&kosh_posix::trace_read().map_err(trace_error)?)),
+        }
+    }
+    
+    /// `drivers [list | load <path> | unload <id>]`
+    fn cmd_drivers(&mut self, args: &[&str]) -> ShellResult<String> {
+        let request = parse_drivers_args(args)?;
+        self.services.send_driver_request(request)
+    }
+    
+    /// `mount [-r] -t <type> <device> <dir>` or `mount [-r] --bind <dir> <dir>`
+    fn cmd_mount(&mut self, args: &[&str]) -> ShellResult<String> {
+        let request = parse_mount_args(args)?;
+        self.services.send_fs_request(request)
+    }
+    
+    /// `umount <dir>`
+    fn cmd_umount(&mut self, args: &[&str]) -> ShellResult<String> {
+        let request = parse_umount_args(args)?;
+        self.services.send_fs_request(request)
+    }
+    
     fn cmd_shutdown(&self) -> ShellResult<String> {
         // In a real implementation, this would send shutdown signal to init
         Ok(String::from("System shutdown requested (not implemented)"))
     }
-}
\ No newline at end of file
+    
+    /// Start `programs` connected by pipes and track them as one job
+    ///
+    /// The job is represented by its last program, whose exit ends it;
+    /// earlier ones end by themselves once their output has nowhere to go.
+    fn run_programs(&mut self, programs: &[&ParsedCommand], mut stdin: Option<Fd>, command_line: &str, background: bool) -> ShellResult<String> {
+        let env = self.environment.exported_vars();
+        let env: Vec<&str> = env.iter().map(String::as_str).collect();
+        let mut last_pid = None;
+        for (index, stage) in programs.iter().enumerate() {
+            let (next_stdin, stdout) = if index + 1 < programs.len() {
+                match pipeline::pipe() {
+                    Ok((read_fd, write_fd)) => (Some(read_fd), Some(write_fd)),
+                    Err(error) => {
+                        pipeline::close_all(stdin.as_slice());
+                        return Err(error);
+                    }
+                }
+            } else {
+                (None, None)
+            };
+            let close: Vec<Fd> = next_stdin.into_iter().collect();
+            let io = StageIo {
+                stdin,
+                stdout,
+                input_file: stage.input_redirect.as_deref(),
+                output_file: stage.output_redirect.as_ref(),
+                close: &close,
+            };
+            let spawned = pipeline::spawn(&stage.command, &arg_refs(stage), &env, &io);
+            // The child holds its own copies of the pipe ends it uses
+            pipeline::close_all(&[stdin, stdout].into_iter().flatten().collect::<Vec<_>>());
+            stdin = next_stdin;
+            match spawned {
+                Ok(pid) => last_pid = Some(pid),
+                Err(error) => {
+                    pipeline::close_all(stdin.as_slice());
+                    return Err(error);
+                }
+            }
+        }
+        
+        let pid = last_pid.ok_or_else(|| ShellError::InternalError("Empty pipeline".to_string()))?;
+        if background {
+            let job_id = self.jobs.add(pid, format!("{} &", command_line));
+            Ok(format!("[{}] {}", job_id, pid))
+        } else {
+            let job_id = self.jobs.add(pid, command_line.to_string());
+            self.jobs.set_foreground(Some(job_id));
+            Ok(String::new())
+        }
+    }
+    
+    fn cmd_jobs(&mut self) -> ShellResult<String> {
+        self.collect_exited();
+        let jobs = self.jobs.jobs();
+        let listing: Vec<String> = jobs.iter()
+            .enumerate()
+            .map(|(index, job)| format_job(job, index + 1 == jobs.len()))
+            .collect();
+        // Finished jobs are reported once
+        self.jobs.take_finished();
+        Ok(listing.join("\n"))
+    }
+    
+    /// `fg [%job]`
+    fn cmd_fg(&mut self, args: &[&str]) -> ShellResult<String> {
+        let job = self.jobs.find(job_arg(args, "fg")?)?.clone();
+        if job.status == JobStatus::Stopped {
+            self.signal(job.pid, kosh_posix::SIGCONT)?;
+            self.jobs.set_status(job.job_id, JobStatus::Running);
+        }
+        self.jobs.set_foreground(Some(job.job_id));
+        Ok(job.command.trim_end_matches(" &").to_string())
+    }
+    
+    /// `bg [%job]`
+    fn cmd_bg(&mut self, args: &[&str]) -> ShellResult<String> {
+        let job = self.jobs.find(job_arg(args, "bg")?)?.clone();
+        match job.status {
+            JobStatus::Stopped => {
+                self.signal(job.pid, kosh_posix::SIGCONT)?;
+                self.jobs.set_status(job.job_id, JobStatus::Running);
+                Ok(format!("[{}] {}", job.job_id, job.command))
+            }
+            _ => Err(ShellError::InvalidArguments(format!("Job {} is already running in the background", job.job_id))),
+        }
+    }
+    
+    /// Process ID of the job the shell is waiting on, if any
+    pub fn foreground_pid(&self) -> Option<ProcessId> {
+        self.jobs.foreground().map(|job| job.pid)
+    }
+    
+    /// Forward Ctrl+C to the foreground job as SIGINT
+    pub fn interrupt_foreground(&mut self) -> ShellResult<()> {
+        match self.foreground_pid() {
+            Some(pid) => self.signal(pid, kosh_posix::SIGINT),
+            None => Ok(()),
+        }
+    }
+    
+    /// Collect exited children, returning a notice for each background job
+    /// that finished
+    pub fn reap_children(&mut self) -> Vec<String> {
+        self.collect_exited();
+        self.jobs.take_finished().iter()
+            .map(|job| format_job(job, false))
+            .collect()
+    }
+    
+    /// `source <file>`: run a script in this shell, sharing its variables
+    fn cmd_source(&mut self, args: &[&str]) -> ShellResult<String> {
+        let [path] = args else {
+            return Err(ShellError::InvalidArguments("Usage: source <file>".to_string()));
+        };
+        let text = read_text(path).ok_or_else(|| ShellError::FileNotFound(path.to_string()))?;
+        // `$?` is left as the script's last command set it
+        self.run_script(&text)
+    }
+    
+    /// `test <condition>`: no output, only an exit status
+    fn cmd_test(&mut self, args: &[&str]) -> ShellResult<String> {
+        let file = |path: &str| kosh_posix::stat(path).ok().map(|status| status.is_dir());
+        if !script::evaluate_test(args, file)? {
+            self.last_status = 1;
+        }
+        Ok(String::new())
+    }
+    
+    /// `export NAME[=value]...`: pass variables on to programs
+    fn cmd_export(&mut self, args: &[&str]) -> ShellResult<String> {
+        if args.is_empty() {
+            return self.cmd_env();
+        }
+        for arg in args {
+            let name = match script::parse_assignment(arg) {
+                Some((name, value)) => {
+                    self.environment.set_var(name.to_string(), value.to_string());
+                    name
+                }
+                None if script::is_name(arg) => *arg,
+                None => return Err(ShellError::InvalidArguments(format!("export: not a variable name: {}", arg))),
+            };
+            self.environment.export_var(name);
+        }
+        Ok(String::new())
+    }
+    
+    /// `unset NAME...`: remove variables
+    fn cmd_unset(&mut self, args: &[&str]) -> ShellResult<String> {
+        if args.is_empty() {
+            return Err(ShellError::InvalidArguments("Usage: unset <name>...".to_string()));
+        }
+        for name in args {
+            self.environment.unset_var(name);
+        }
+        Ok(String::new())
+    }
+    
+    /// `env`: exported variables as programs get them
+    fn cmd_env(&self) -> ShellResult<String> {
+        Ok(self.environment.exported_vars().join("\n"))
+    }
+    
+    /// Ways to finish the last word of a partly typed command line
+    ///
+    /// Paths are looked up in directory listings from fs-service.
+    pub fn complete(&self, line: &str) -> Completion {
+        completion::complete(line, BUILTINS, list_directory)
+    }
+    
+    /// Record the exit status of every child that has exited
+    fn collect_exited(&mut self) {
+        // Without children there is nothing to wait for
+        if self.jobs.is_empty() {
+            return;
+        }
+        while let Ok(Some((pid, exit_code))) = kosh_posix::waitpid(0, kosh_posix::WNOHANG) {
+            if self.foreground_pid() == Some(pid) {
+                self.last_status = exit_code;
+            }
+            self.jobs.child_exited(pid, exit_code);
+        }
+    }
+    
+    fn expand(&self, line: &str) -> String {
+        script::expand(line, |name| self.variable(name))
+    }
+    
+    /// Run statements until the end or an `exit`
+    fn run_statements(&mut self, statements: &[Statement], output: &mut Vec<String>) -> ControlFlow<()> {
+        for statement in statements {
+            match statement {
+                Statement::Command(line) => self.run_script_command(line, output)?,
+                Statement::If { condition, then_branch, else_branch } => {
+                    self.run_script_command(condition, output)?;
+                    let branch = if self.last_status == 0 { then_branch } else { else_branch };
+                    self.run_statements(branch, output)?;
+                }
+                Statement::For { variable, words, body } => {
+                    let words = self.expand(words);
+                    for word in words.split_whitespace() {
+                        self.environment.set_var(variable.clone(), word.to_string());
+                        self.run_statements(body, output)?;
+                    }
+                }
+            }
+        }
+        ControlFlow::Continue(())
+    }
+    
+    fn run_script_command(&mut self, line: &str, output: &mut Vec<String>) -> ControlFlow<()> {
+        let line = self.expand(line);
+        let mut words = line.split_whitespace();
+        if words.next() == Some("exit") {
+            if let Some(status) = words.next() {
+                self.last_status = status.parse().unwrap_or(2);
+            }
+            return ControlFlow::Break(());
+        }
+        match self.run_expanded(&line) {
+            Ok(text) if !text.is_empty() => output.push(text),
+            Ok(_) => {}
+            Err(error) => output.push(error.user_message()),
+        }
+        // Scripts run one command at a time
+        while self.foreground_pid().is_some() {
+            output.extend(self.reap_children());
+            crate::infrastructure::idle();
+        }
+        ControlFlow::Continue(())
+    }
+    
+    fn signal(&self, pid: ProcessId, signal: i32) -> ShellResult<()> {
+        kosh_posix::kill(pid, signal).map_err(|errno| match errno {
+            kosh_posix::errno::ESRCH => ShellError::ProcessNotFound(pid),
+            kosh_posix::errno::EPERM => ShellError::ProcessAccessDenied(pid),
+            errno => ShellError::SystemCallFailed(kosh_posix::raw::SYS_KILL, errno.0),
+        })
+    }
+}
+
+/// Job named by the only argument of `fg` or `bg`, if any
+fn job_arg<'a>(args: &[&'a str], command: &str) -> ShellResult<Option<&'a str>> {
+    match args {
+        [] => Ok(None),
+        [job] => Ok(Some(job)),
+        _ => Err(ShellError::InvalidArguments(format!("Usage: {} [%job]", command))),
+    }
+}
+
+/// Commands the shell runs itself
+const BUILTINS: &[&str] = &[
+    "help", "echo", "ps", "ls", "cat", "mkdir", "rmdir", "touch", "rm", "pwd", "cd", "sched",
+    "powerctl", "thermal", "drivers", "mount", "umount", "clear", "exit", "shutdown", "jobs", "fg", "bg", "source",
+    "test", "[", "export", "unset", "env", "dmesg", "audit", "trace",
+];
+
+fn is_builtin(command: &str) -> bool {
+    BUILTINS.contains(&command)
+}
+
+/// Entries of `path` as (name, is directory); empty if it cannot be read
+fn list_directory(path: &str) -> Vec<(String, bool)> {
+    let Ok(mut dir) = kosh_posix::opendir(path) else {
+        return Vec::new();
+    };
+    let mut entries = Vec::new();
+    while let Some(entry) = kosh_posix::readdir(&mut dir) {
+        entries.push((entry.d_name.clone(), entry.d_type == kosh_posix::dirent::DT_DIR));
+    }
+    entries
+}
+
+fn arg_refs(command: &ParsedCommand) -> Vec<&str> {
+    command.args.iter().map(String::as_str).collect()
+}
+
+/// Whole contents of a small text file
+fn read_text(path: &str) -> Option<String> {
+    let fd = kosh_posix::open(path, kosh_posix::O_RDONLY, 0).ok()?;
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 256];
+    let complete = loop {
+        match kosh_posix::read(fd, &mut chunk) {
+            Ok(0) => break true,
+            Ok(count) => data.extend_from_slice(&chunk[..count]),
+            Err(_) => break false,
+        }
+    };
+    let _ = kosh_posix::close(fd);
+    complete.then(|| String::from_utf8(data).ok()).flatten()
+}
+
+/// Process described by the text of /proc/<pid>/status
+pub fn parse_proc_status(text: &str) -> Option<ProcessInfo> {
+    let field = |key: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix(":\t"))
+    };
+    Some(ProcessInfo {
+        pid: field("Pid")?.parse().ok()?,
+        ppid: field("PPid")?.parse().ok()?,
+        name: field("Name")?.to_string(),
+        state: field("State")?.to_string(),
+        cpu_time: field("CpuTime")?.trim_end_matches(" ms").parse().ok()?,
+        // Absent from the status of older kernels
+        kernel_time: field("KernelTime").and_then(|time| time.trim_end_matches(" ms").parse().ok()).unwrap_or(0),
+        context_switches: field("ContextSwitches").and_then(|count| count.parse().ok()).unwrap_or(0),
+        memory_usage: field("VmRSS").and_then(|size| size.trim_end_matches(" kB").parse().ok()).unwrap_or(0),
+    })
+}
+
+/// `ps` table, ordered by process ID
+pub fn format_ps(processes: &[ProcessInfo]) -> String {
+    let mut processes: Vec<&ProcessInfo> = processes.iter().collect();
+    processes.sort_by_key(|process| process.pid);
+    
+    let mut table = String::from("  PID  PPID STATE     TIME(ms)  SYS(ms)    CSW RSS(KiB) NAME");
+    for process in processes {
+        table.push_str(&format!("\n{:>5} {:>5} {:<9} {:>8} {:>8} {:>6} {:>8} {}",
+            process.pid, process.ppid, process.state, process.cpu_time, process.kernel_time,
+            process.context_switches, process.memory_usage, process.name));
+    }
+    table
+}
+
+const SCHED_USAGE: &str = "Usage: sched [policy <rr|priority|cfs>] [slice <ms>]";
+
+/// Settings to change from `sched` arguments; `None` keeps the current one
+pub fn parse_sched_args(args: &[&str]) -> ShellResult<(Option<SchedPolicy>, Option<u64>)> {
+    let mut policy = None;
+    let mut time_slice_ms = None;
+    
+    let mut args = args.iter();
+    while let Some(setting) = args.next() {
+        let value = args.next().ok_or_else(|| ShellError::InvalidArguments(SCHED_USAGE.to_string()))?;
+        match *setting {
+            "policy" => {
+                policy = Some(SchedPolicy::from_name(value)
+                    .ok_or_else(|| ShellError::InvalidArguments(format!("Unknown scheduler policy: {}", value)))?);
+            }
+            "slice" => {
+                let ms = value.parse::<u64>().ok()
+                    .filter(|ms| (MIN_TIME_SLICE_MS..=MAX_TIME_SLICE_MS).contains(ms))
+                    .ok_or_else(|| ShellError::InvalidArguments(format!(
+                        "Time slice must be {}-{} ms", MIN_TIME_SLICE_MS, MAX_TIME_SLICE_MS)))?;
+                time_slice_ms = Some(ms);
+            }
+            _ => return Err(ShellError::InvalidArguments(SCHED_USAGE.to_string())),
+        }
+    }
+    
+    Ok((policy, time_slice_ms))
+}
+
+const POWERCTL_USAGE: &str = "Usage: powerctl [governor <performance|ondemand|powersave|conservative|interactive>]";
+
+/// Governor to switch to from `powerctl` arguments; `None` only shows the
+/// state
+pub fn parse_powerctl_args(args: &[&str]) -> ShellResult<Option<CpuGovernor>> {
+    match args {
+        [] => Ok(None),
+        ["governor", name] => CpuGovernor::from_name(name)
+            .map(Some)
+            .ok_or_else(|| ShellError::InvalidArguments(format!("Unknown CPU governor: {}", name))),
+        _ => Err(ShellError::InvalidArguments(POWERCTL_USAGE.to_string())),
+    }
+}
+
+const DMESG_USAGE: &str = "Usage: dmesg [-l <level>] [-n <level>] | dmesg -p [-c]";
+
+/// Levels from `dmesg` arguments: the one to show down to with `-l`, and
+/// the one the kernel should keep with `-n`
+pub fn parse_dmesg_args(args: &[&str]) -> ShellResult<(Option<LogLevel>, Option<LogLevel>)> {
+    let mut show = None;
+    let mut keep = None;
+    
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        let value = args.next().ok_or_else(|| ShellError::InvalidArguments(DMESG_USAGE.to_string()))?;
+        let level = LogLevel::from_name(value)
+            .ok_or_else(|| ShellError::InvalidArguments(format!("Unknown log level: {}", value)))?;
+        match *option {
+            "-l" => show = Some(level),
+            "-n" => keep = Some(level),
+            _ => return Err(ShellError::InvalidArguments(DMESG_USAGE.to_string())),
+        }
+    }
+    
+    Ok((show, keep))
+}
+
+/// Kernel log lines at `level` or more severe; all of them without one
+pub fn filter_klog(log: &str, level: Option<LogLevel>) -> String {
+    let lines = log.lines().filter(|line| match (level, LogLevel::of_record(line)) {
+        (Some(level), Some(record)) => record <= level,
+        (Some(_), None) => false,
+        (None, _) => true,
+    });
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+const AUDIT_USAGE: &str = "Usage: audit [-n <count>]";
+
+/// How many of the newest records `audit` arguments ask for; all of them
+/// without `-n`
+pub fn parse_audit_args(args: &[&str]) -> ShellResult<Option<usize>> {
+    match args {
+        [] => Ok(None),
+        ["-n", count] => count.parse::<usize>()
+            .map(Some)
+            .map_err(|_| ShellError::InvalidArguments(format!("Invalid count: {}", count))),
+        _ => Err(ShellError::InvalidArguments(AUDIT_USAGE.to_string())),
+    }
+}
+
+/// One line per audit record, timestamped like the kernel log
+pub fn format_audit(records: &[AuditRecord]) -> String {
+    use kosh_posix::audit::*;
+    
+    let lines: Vec<String> = records.iter().map(|record| {
+        let what = match record.kind {
+            AUDIT_CAPABILITY_DENIED => record.detail().to_string(),
+            AUDIT_SYSCALL_DENIED => format!("{} ({})", record.detail(), record.value),
+            AUDIT_DRIVER_LOADED => format!("DMA domain {}", record.value),
+            AUDIT_DRIVER_UNLOADED => format!("driver {}", record.value),
+            AUDIT_PROCESS_KILLED => format!("signal {}", record.value),
+            _ => format!("{}", record.value),
+        };
+        format!("[{:5}.{:03}] #{} pid {} {}: {}", record.time_ms / 1000, record.time_ms % 1000,
+                record.sequence, record.process, record.kind_name(), what)
+    }).collect();
+    lines.join("\n")
+}
+
+const TRACE_USAGE: &str = "Usage: trace [status] | trace on|off <tracepoint>... | trace dump";
+
+/// What `trace` arguments ask for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceCommand {
+    Status,
+    /// Turn on the tracepoints in the mask
+    On(u32),
+    Off(u32),
+    Dump,
+}
+
+pub fn parse_trace_args(args: &[&str]) -> ShellResult<TraceCommand> {
+    let mask = |names: &[&str]| -> ShellResult<u32> {
+        if names.is_empty() {
+            return Err(ShellError::InvalidArguments(TRACE_USAGE.to_string()));
+        }
+        names.iter().try_fold(0, |mask, name| match *name {
+            "all" => Ok(mask | Tracepoint::ALL.iter().fold(0, |all, point| all | point.bit())),
+            name => Tracepoint::from_name(name)
+                .map(|point| mask | point.bit())
+                .ok_or_else(|| ShellError::InvalidArguments(format!("Unknown tracepoint: {}", name))),
+        })
+    };
+    match args {
+        [] | ["status"] => Ok(TraceCommand::Status),
+        ["on", names @ ..] => mask(names).map(TraceCommand::On),
+        ["off", names @ ..] => mask(names).map(TraceCommand::Off),
+        ["dump"] => Ok(TraceCommand::Dump),
+        _ => Err(ShellError::InvalidArguments(TRACE_USAGE.to_string())),
+    }
+}
+
+/// Which tracepoints are on, and how many records were lost
+pub fn format_trace_status(mask: u32, lost: u64) -> String {
+    let on: Vec<&str> = Tracepoint::ALL.iter()
+        .filter(|point| mask & point.bit() != 0)
+        .map(|point| point.name())
+        .collect();
+    let on = if on.is_empty() { "none".to_string() } else { on.join(" ") };
+    format!("Tracepoints on: {}\nRecords lost: {}", on, lost)
+}
+
+/// Nanoseconds as microseconds with three decimals
+fn format_us(ns: u64) -> String {
+    format!("{}.{:03} us", ns / 1000, ns % 1000)
+}
+
+/// Latencies of one kind of operation
+#[derive(Default)]
+struct LatencyStats {
+    count: u64,
+    total_ns: u64,
+    max_ns: u64,
+}
+
+impl LatencyStats {
+    fn add(&mut self, ns: u64) {
+        self.count += 1;
+        self.total_ns += ns;
+        self.max_ns = self.max_ns.max(ns);
+    }
+    
+    fn summary(&self, what: &str) -> String {
+        format!("{}: {} timed, avg {}, max {}", what, self.count,
+                format_us(self.total_ns / self.count.max(1)), format_us(self.max_ns))
+    }
+}
+
+/// Trace records one per line, oldest first, then latency figures
+///
+/// A system call exit is paired with its thread's entry and an IPC receive
+/// with the send of the same message, and the time between them shown on
+/// the later record; pairs whose first half was read in an earlier dump,
+/// or lost, go untimed.
+pub fn format_trace(records: &[TraceRecord]) -> String {
+    use alloc::collections::BTreeMap;
+    use kosh_posix::trace::TRACE_IDLE;
+    
+    let process = |pid: u32| if pid == TRACE_IDLE { "idle".to_string() } else { pid.to_string() };
+    let mut entries: BTreeMap<u32, (u64, u64)> = BTreeMap::new();
+    let mut sends: BTreeMap<u64, u64> = BTreeMap::new();
+    let mut syscalls: BTreeMap<u64, LatencyStats> = BTreeMap::new();
+    let mut ipc = LatencyStats::default();
+    
+    let mut lines: Vec<String> = records.iter().map(|record| {
+        let detail = match record.tracepoint() {
+            Some(Tracepoint::SchedSwitch) => format!("from {}", process(record.arg0 as u32)),
+            Some(Tracepoint::IpcSend) => {
+                sends.insert(record.arg1, record.time_ns);
+                format!("to {} msg {}", record.arg0, record.arg1)
+            }
+            Some(Tracepoint::IpcReceive) => match sends.remove(&record.arg1) {
+                Some(sent) => {
+                    let latency = record.time_ns.saturating_sub(sent);
+                    ipc.add(latency);
+                    format!("from {} msg {} ({})", record.arg0, record.arg1, format_us(latency))
+                }
+                None => format!("from {} msg {}", record.arg0, record.arg1),
+            },
+            Some(Tracepoint::SyscallEnter) => {
+                entries.insert(record.process, (record.arg0, record.time_ns));
+                format!("#{} arg 0x{:x}", record.arg0, record.arg1)
+            }
+            Some(Tracepoint::SyscallExit) => match entries.remove(&record.process) {
+                Some((number, entered)) if number == record.arg0 => {
+                    let latency = record.time_ns.saturating_sub(entered);
+                    syscalls.entry(number).or_default().add(latency);
+                    format!("#{} = {} ({})", record.arg0, record.arg1 as i64, format_us(latency))
+                }
+                _ => format!("#{} = {}", record.arg0, record.arg1 as i64),
+            },
+            Some(Tracepoint::PageFault) => format!("0x{:016x} error 0x{:x}", record.arg0, record.arg1),
+            None => format!("0x{:x} 0x{:x}", record.arg0, record.arg1),
+        };
+        let name = record.tracepoint().map_or("unknown", Tracepoint::name);
+        format!("[{:5}.{:06}] cpu{} pid {:<5} {:<13} {}", record.time_ns / 1_000_000_000,
+                record.time_ns % 1_000_000_000 / 1000, record.cpu, process(record.process), name, detail)
+    }).collect();
+    
+    lines.extend(syscalls.iter().map(|(number, stats)| stats.summary(&format!("syscall #{}", number))));
+    if ipc.count > 0 {
+        lines.push(ipc.summary("ipc"));
+    }
+    lines.join("\n")
+}
+
+const DRIVERS_USAGE: &str = "Usage: drivers [list | load <path> | unload <id>]";
+
+/// Driver manager request for `drivers` arguments
+pub fn parse_drivers_args(args: &[&str]) -> ShellResult<DriverRequest> {
+    match args {
+        [] | ["list"] => Ok(DriverRequest::List),
+        ["load", path] if path.starts_with('/') => Ok(DriverRequest::Load { path: path.to_string() }),
+        ["unload", id] => id.parse::<u32>()
+            .map(|driver_id| DriverRequest::Unload { driver_id })
+            .map_err(|_| ShellError::InvalidArguments(format!("Invalid driver ID: {}", id))),
+        _ => Err(ShellError::InvalidArguments(DRIVERS_USAGE.to_string())),
+    }
+}
+
+const MOUNT_USAGE: &str = "Usage: mount [-r] -t <type> <device> <dir> | mount [-r] --bind <dir> <dir>";
+
+/// File system service request for `mount` arguments
+pub fn parse_mount_args(args: &[&str]) -> ShellResult<FileSystemRequest> {
+    let mut flags = MountFlags::empty();
+    let mut fs_type = None;
+    let mut paths = Vec::new();
+    
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "-r" => flags |= MountFlags::READ_ONLY,
+            "--bind" => flags |= MountFlags::BIND,
+            "-t" => fs_type = Some(args.next().ok_or_else(|| ShellError::InvalidArguments(MOUNT_USAGE.to_string()))?),
+            path => paths.push(path),
+        }
+    }
+    
+    let (source, target) = match paths[..] {
+        [source, target] => (source, target),
+        _ => return Err(ShellError::InvalidArguments(MOUNT_USAGE.to_string())),
+    };
+    let fs_type = match (flags.contains(MountFlags::BIND), fs_type) {
+        (true, None) => "",
+        (false, Some(fs_type)) => fs_type,
+        _ => return Err(ShellError::InvalidArguments(MOUNT_USAGE.to_string())),
+    };
+    if !target.starts_with('/') || (flags.contains(MountFlags::BIND) && !source.starts_with('/')) {
+        return Err(ShellError::InvalidArguments(format!("Paths must be absolute: {}", MOUNT_USAGE)));
+    }
+    
+    Ok(FileSystemRequest::Mount {
+        source: source.to_string(),
+        target: target.to_string(),
+        fs_type: fs_type.to_string(),
+        flags,
+    })
+}
+
+/// File system service request for `umount` arguments
+pub fn parse_umount_args(args: &[&str]) -> ShellResult<FileSystemRequest> {
+    match args {
+        [target] if target.starts_with('/') => Ok(FileSystemRequest::Unmount { target: target.to_string() }),
+        _ => Err(ShellError::InvalidArguments("Usage: umount <dir>".to_string())),
+    }
+}
+
+/// Human readable frequency scaling state for `powerctl`
+pub fn format_cpufreq_info(info: &CpuFreqInfo) -> String {
+    let governor = info.governor().map_or("unknown", CpuGovernor::name);
+    let backend = match info.backend() {
+        Some(backend) => format!("{} ({} levels)", backend, info.levels),
+        None => "none, frequency tracked only".to_string(),
+    };
+    format!(
+        "Governor:  {}\n\
+         Frequency: {} MHz\n\
+         Range:     {}-{} MHz\n\
+         Backend:   {}",
+        governor, info.current_mhz, info.min_mhz, info.max_mhz, backend,
+    )
+}
+
+/// Human readable battery and AC adapter state for `powerctl`
+pub fn format_power_supply_info(info: &PowerSupplyInfo) -> String {
+    let ac = if info.ac_online() { "online" } else { "offline" };
+    if !info.battery_present() {
+        return format!("Battery:   none\nAC power:  {}", ac);
+    }
+    let state = if info.critical() {
+        ", critical"
+    } else if info.low() {
+        ", low"
+    } else if info.charging() {
+        ", charging"
+    } else {
+        ""
+    };
+    let mut output = format!("Battery:   {}%{}\nAC power:  {}", info.level_percent, state, ac);
+    if info.minutes_remaining != 0 {
+        let until = if info.charging() { "full" } else { "empty" };
+        output.push_str(&format!("\nTime left: {}:{:02} until {}", info.minutes_remaining / 60, info.minutes_remaining % 60, until));
+    }
+    output
+}
+
+/// Temperature in thousandths of a degree as degrees with one decimal
+fn format_millicelsius(millicelsius: i32) -> String {
+    let sign = if millicelsius < 0 { "-" } else { "" };
+    let tenths = millicelsius.unsigned_abs() / 100;
+    format!("{}{}.{} C", sign, tenths / 10, tenths % 10)
+}
+
+/// Human readable thermal zones for `thermal`, one per line
+pub fn format_thermal_zones(zones: &[ThermalZoneInfo]) -> String {
+    if zones.is_empty() {
+        return "No temperature sensors".to_string();
+    }
+    zones.iter().map(|zone| {
+        let temperature = zone.temperature_mc().map_or("unknown".to_string(), format_millicelsius);
+        let mut line = format!("{:<8} {}", zone.name(), temperature);
+        let trips: Vec<String> = zone.trip_points()
+            .map(|(name, millicelsius)| format!("{} {}", name, format_millicelsius(millicelsius)))
+            .collect();
+        if !trips.is_empty() {
+            line.push_str(&format!("  ({})", trips.join(", ")));
+        }
+        if let Some(tripped) = zone.tripped_name() {
+            line.push_str(&format!("  past {}", tripped));
+        }
+        line
+    }).collect::<Vec<String>>().join("\n")
+}
+
+/// Human readable scheduler state for `sched`
+pub fn format_sched_info(info: &SchedInfo) -> String {
+    let policy = info.policy().map_or("unknown", SchedPolicy::name);
+    format!(
+        "Policy:               {}\n\
+         Time slice:           {} ms\n\
+         Runnable processes:   {}\n\
+         Context switches:     {}\n\
+         Scheduling decisions: {}\n\
+         Scheduler time:       {} us",
+        policy, info.time_slice_ms, info.runnable_processes,
+        info.context_switches, info.scheduling_decisions, info.scheduler_time_us,
+    )
+}
@@ -0,0 +1,85 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::format;
+
+/// Ways to finish the word at the end of a command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// Byte offset where the word starts
+    pub start: usize,
+    /// Byte offset within the word where the last path component starts
+    pub name_start: usize,
+    /// Complete words, sorted; directories end in '/'
+    pub candidates: Vec<String>,
+}
+
+impl Completion {
+    /// Longest text every candidate starts with
+    pub fn common_prefix(&self) -> &str {
+        let Some((first, rest)) = self.candidates.split_first() else {
+            return "";
+        };
+        let mut length = first.len();
+        for candidate in rest {
+            length = first.char_indices()
+                .zip(candidate.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map_or(0, |((index, ch), _)| index + ch.len_utf8())
+                .min(length);
+        }
+        &first[..length]
+    }
+
+    /// Candidates as listed after a second Tab, without their directory
+    pub fn names(&self) -> Vec<&str> {
+        self.candidates.iter()
+            .map(|candidate| &candidate[self.name_start..])
+            .collect()
+    }
+}
+
+/// Complete the last word of `line`
+///
+/// A word in command position completes to built-in commands, or to paths
+/// once it contains a '/'; any other word completes to paths. `list_dir`
+/// returns a directory's entries as (name, is directory) pairs, and
+/// nothing when the directory cannot be read.
+pub fn complete<F>(line: &str, builtins: &[&str], list_dir: F) -> Completion
+where
+    F: FnOnce(&str) -> Vec<(String, bool)>,
+{
+    let start = line.char_indices()
+        .rev()
+        .find(|&(_, ch)| ch.is_whitespace() || is_operator(ch))
+        .map_or(0, |(index, ch)| index + ch.len_utf8());
+    let word = &line[start..];
+    let before = line[..start].trim_end();
+    let command_position = before.is_empty() || before.ends_with('|');
+
+    if command_position && !word.contains('/') {
+        let mut candidates: Vec<String> = builtins.iter()
+            .filter(|name| name.starts_with(word))
+            .map(|name| name.to_string())
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        return Completion { start, name_start: 0, candidates };
+    }
+
+    let name_start = word.rfind('/').map_or(0, |index| index + 1);
+    let (directory, prefix) = word.split_at(name_start);
+    // Hidden entries only show up when asked for
+    let show_hidden = prefix.starts_with('.');
+    let mut candidates: Vec<String> = list_dir(if directory.is_empty() { "." } else { directory })
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(prefix) && (show_hidden || !name.starts_with('.')))
+        .map(|(name, is_directory)| format!("{}{}{}", directory, name, if is_directory { "/" } else { "" }))
+        .collect();
+    candidates.sort();
+    Completion { start, name_start, candidates }
+}
+
+fn is_operator(ch: char) -> bool {
+    matches!(ch, '|' | '<' | '>' | '&')
+}
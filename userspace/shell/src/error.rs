@@ -24,6 +24,7 @@ pub enum ShellError {
     ProcessNotFound(u32),
     ProcessAccessDenied(u32),
     InvalidSignal(String),
+    JobNotFound(String),
     
     // Service communication errors
     ServiceUnavailable(String),
@@ -61,6 +62,7 @@ impl ShellError {
             ShellError::ProcessNotFound(pid) => format!("Process not found: {}", pid),
             ShellError::ProcessAccessDenied(pid) => format!("Access denied to process: {}", pid),
             ShellError::InvalidSignal(signal) => format!("Invalid signal: {}", signal),
+            ShellError::JobNotFound(job) => format!("No such job: {}", job),
             
             ShellError::ServiceUnavailable(service) => format!("Service unavailable: {}", service),
             ShellError::ServiceTimeout(service) => format!("Service timeout: {}", service),
@@ -93,6 +95,9 @@ impl ShellError {
             ShellError::ProcessNotFound(_) => {
                 Some(String::from("Use 'ps' command to list running processes"))
             }
+            ShellError::JobNotFound(_) => {
+                Some(String::from("Use 'jobs' command to list jobs started by this shell"))
+            }
             ShellError::ServiceUnavailable(_) => {
                 Some(String::from("Wait for the service to become available or restart the system"))
             }
@@ -110,7 +115,8 @@ impl ShellError {
             ShellError::FileAlreadyExists(_) | ShellError::NotADirectory(_) | ShellError::IsADirectory(_) => {
                 ErrorCategory::FileSystem
             }
-            ShellError::ProcessNotFound(_) | ShellError::ProcessAccessDenied(_) | ShellError::InvalidSignal(_) => {
+            ShellError::ProcessNotFound(_) | ShellError::ProcessAccessDenied(_) | ShellError::InvalidSignal(_) |
+            ShellError::JobNotFound(_) => {
                 ErrorCategory::Process
             }
             ShellError::ServiceUnavailable(_) | ShellError::ServiceTimeout(_) | ShellError::ServiceError(_) => {
@@ -127,6 +133,17 @@ impl ShellError {
             }
         }
     }
+    
+    /// Status `$?` reports after a command fails this way
+    pub fn exit_status(&self) -> i32 {
+        match self {
+            // As in sh, for a command that cannot be found
+            ShellError::InvalidCommand(_) => 127,
+            // Usage errors, as for builtins in sh
+            ShellError::ParseError(_) | ShellError::InvalidArguments(_) => 2,
+            _ => 1,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1,12 +1,33 @@
 #![allow(dead_code)]
 
 use alloc::vec::Vec;
+use alloc::boxed::Box;
 use alloc::string::{String, ToString};
-use kosh_service::ServiceClient;
-use kosh_types::ProcessId;
+use alloc::format;
+use kosh_ipc::poll::{poll, PollEntry};
+use kosh_service::{ServiceClient, ServiceData, ServiceResponse, ServiceStatus, ServiceType};
+use kosh_types::{MountFlags, ProcessId};
 use crate::error::{ShellError, ShellResult};
+use crate::jobs::split_background;
 use crate::types::*;
 
+/// Process ID fs-service gets as the first service init spawns
+pub const FS_SERVICE_PID: ProcessId = 2;
+
+/// Process ID the driver manager gets when init spawns it after fs-service
+pub const DRIVER_MANAGER_PID: ProcessId = 3;
+
+/// How long the shell sleeps between checks for typed keys and ended jobs
+pub const IDLE_INTERVAL: kosh_posix::Timespec = kosh_posix::Timespec::new(0, 10_000_000);
+
+/// Sleep for `IDLE_INTERVAL`
+///
+/// Console input and child exits are polled, so the shell naps between
+/// checks rather than spinning.
+pub fn idle() {
+    let _ = kosh_posix::nanosleep(&IDLE_INTERVAL);
+}
+
 /// Service communication layer for the shell
 /// This will be enhanced in later tasks to provide real service communication
 pub struct ShellServiceClient {
@@ -34,17 +55,45 @@ impl ShellServiceClient {
         // 3. Establish connections
         
         // For now, just mock the service PIDs
-        self.fs_service_pid = Some(100);
+        self.fs_service_pid = Some(FS_SERVICE_PID);
         self.process_service_pid = Some(101);
-        self.driver_service_pid = Some(102);
+        self.driver_service_pid = Some(DRIVER_MANAGER_PID);
         
         Ok(())
     }
     
     /// Send a request to the file system service
-    pub fn send_fs_request(&mut self, _request: FileSystemRequest) -> ShellResult<String> {
-        // This will be implemented in later tasks
-        Err(ShellError::ServiceUnavailable("File system service not implemented".to_string()))
+    pub fn send_fs_request(&mut self, request: FileSystemRequest) -> ShellResult<String> {
+        let service = self.fs_service_pid
+            .ok_or_else(|| ShellError::ServiceUnavailable("fs-service".to_string()))?;
+        let service_request = match &request {
+            FileSystemRequest::Mount { source, target, fs_type, flags } => kosh_service::FileSystemRequest::Mount {
+                source: source.clone(),
+                target: target.clone(),
+                fs_type: fs_type.clone(),
+                flags: flags.bits(),
+            },
+            FileSystemRequest::Unmount { target } => kosh_service::FileSystemRequest::Unmount { target: target.clone() },
+            // This will be implemented in later tasks
+            _ => return Err(ShellError::ServiceUnavailable("File system service not implemented".to_string())),
+        };
+        let response = self.request(service, ServiceType::FileSystem, ServiceData::FileSystemRequest(service_request))?;
+        
+        match (request, response.status) {
+            (FileSystemRequest::Mount { source, target, .. }, ServiceStatus::Success) => Ok(format!("Mounted {} at {}", source, target)),
+            (FileSystemRequest::Unmount { target }, ServiceStatus::Success) => Ok(format!("Unmounted {}", target)),
+            (FileSystemRequest::Mount { target, .. } | FileSystemRequest::Unmount { target }, ServiceStatus::NotFound) => {
+                Err(ShellError::FileNotFound(target))
+            }
+            (FileSystemRequest::Mount { target, .. } | FileSystemRequest::Unmount { target }, ServiceStatus::PermissionDenied) => {
+                Err(ShellError::PermissionDenied(target))
+            }
+            (FileSystemRequest::Mount { target, fs_type, .. }, ServiceStatus::InvalidRequest) => {
+                Err(ShellError::InvalidArguments(format!("Cannot mount {} at {}", fs_type, target)))
+            }
+            (FileSystemRequest::Unmount { target }, _) => Err(ShellError::InternalError(format!("{} is busy", target))),
+            (_, _) => Err(ShellError::InternalError("Mount failed".to_string())),
+        }
     }
     
     /// Send a request to the process service
@@ -54,20 +103,64 @@ impl ShellServiceClient {
     }
     
     /// Send a request to the driver service
-    pub fn send_driver_request(&mut self, _request: DriverRequest) -> ShellResult<String> {
-        // This will be implemented in later tasks
-        Err(ShellError::ServiceUnavailable("Driver service not implemented".to_string()))
+    pub fn send_driver_request(&mut self, request: DriverRequest) -> ShellResult<String> {
+        let service = self.driver_service_pid
+            .ok_or_else(|| ShellError::ServiceUnavailable("driver-manager".to_string()))?;
+        let service_request = match &request {
+            DriverRequest::List => kosh_service::DriverRequest::ListDrivers,
+            DriverRequest::Load { path } => kosh_service::DriverRequest::LoadDriver { path: path.clone() },
+            DriverRequest::Unload { driver_id } => kosh_service::DriverRequest::UnloadDriver { driver_id: *driver_id },
+        };
+        let response = self.request(service, ServiceType::DriverManager, ServiceData::DriverRequest(service_request))?;
+        
+        match (request, response.status, response.data) {
+            (_, ServiceStatus::Success, ServiceData::Text(text)) => Ok(text),
+            (DriverRequest::Load { .. }, ServiceStatus::Success, ServiceData::Binary(bytes)) if bytes.len() == 4 => {
+                Ok(format!("Loaded driver {}", u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])))
+            }
+            (DriverRequest::Unload { driver_id }, ServiceStatus::Success, _) => Ok(format!("Unloaded driver {}", driver_id)),
+            (DriverRequest::Load { path }, ServiceStatus::PermissionDenied, _) => Err(ShellError::PermissionDenied(path)),
+            (DriverRequest::Load { path }, ServiceStatus::InvalidRequest | ServiceStatus::NotFound, _) => Err(ShellError::FileNotFound(path)),
+            (DriverRequest::Load { path }, _, _) => {
+                Err(ShellError::InternalError(format!("{} is not a driver, is already loaded or failed to start", path)))
+            }
+            (DriverRequest::Unload { driver_id }, ServiceStatus::InvalidRequest, _) => {
+                Err(ShellError::InvalidArguments(format!("No driver {}", driver_id)))
+            }
+            (DriverRequest::Unload { driver_id }, _, _) => {
+                Err(ShellError::InternalError(format!("Driver {} is in use by other drivers", driver_id)))
+            }
+            (DriverRequest::List, _, _) => Err(ShellError::ServiceUnavailable("driver-manager".to_string())),
+        }
+    }
+    
+    /// Send a request and wait for its response
+    fn request(&mut self, service: ProcessId, service_type: ServiceType, data: ServiceData) -> ShellResult<ServiceResponse> {
+        self.service_client.send_request(service, service_type, data)?;
+        loop {
+            let mut entries = [PollEntry::from_sender(service)];
+            poll(&mut entries, kosh_ipc::poll::INFINITE)
+                .map_err(|_| ShellError::ServiceTimeout(format!("process {}", service)))?;
+            match self.service_client.receive_response() {
+                Ok(response) => return Ok(response),
+                Err(kosh_service::ServiceError::WouldBlock) => continue,
+                Err(error) => return Err(error.into()),
+            }
+        }
     }
 }
 
 /// File system request types (will be enhanced in later tasks)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FileSystemRequest {
     List { path: String },
     Read { path: String },
     Write { path: String, data: Vec<u8> },
     Create { path: String, is_directory: bool },
     Delete { path: String },
+    /// Mount device `source`, or bind the directory `source`, at `target`
+    Mount { source: String, target: String, fs_type: String, flags: MountFlags },
+    Unmount { target: String },
 }
 
 /// Process request types (will be enhanced in later tasks)
@@ -79,7 +172,7 @@ pub enum ProcessRequest {
 }
 
 /// Driver request types (will be enhanced in later tasks)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DriverRequest {
     List,
     Load { path: String },
@@ -166,19 +259,22 @@ impl ExecutionContext {
     }
 }
 
-/// Basic command parser infrastructure
-/// This will be enhanced in later tasks with pipe and redirect support
-pub struct CommandParser {
-    // Basic parser - will be enhanced in task 2
-}
+/// Command line parser
+///
+/// Understands pipelines (`a | b`), redirection of standard input (`<`),
+/// output (`>`, `>>`) and errors (`2>`), and a trailing `&`.
+pub struct CommandParser {}
 
 impl CommandParser {
     pub fn new() -> Self {
         Self {}
     }
     
-    /// Parse a command line into a basic parsed command
-    /// This is a simplified version that will be enhanced in later tasks
+    /// Parse a command line into its first command, with later pipeline
+    /// stages chained through `pipe_to`
+    ///
+    /// Only the first command may read a file and only the last may write
+    /// one, since the others are connected to their neighbours.
     pub fn parse(&self, command_line: &str) -> ShellResult<ParsedCommand> {
         let command_line = command_line.trim();
         
@@ -186,18 +282,139 @@ impl CommandParser {
             return Err(ShellError::ParseError("Empty command".to_string()));
         }
         
-        let parts: Vec<&str> = command_line.split_whitespace().collect();
-        let command = parts[0].to_string();
-        let args = parts[1..].iter().map(|s| s.to_string()).collect();
+        let (command_line, background) = split_background(command_line);
+        let mut stages = Vec::new();
+        let mut stage = Stage::default();
+        let mut tokens = tokenize(command_line).into_iter();
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Word(word) => stage.words.push(word.to_string()),
+                Token::Pipe => stages.push(core::mem::take(&mut stage).finish()?),
+                Token::Input => {
+                    let path = redirect_target(tokens.next(), "<")?;
+                    if stage.input.replace(path).is_some() {
+                        return Err(ShellError::ParseError("Only one input redirection per command".to_string()));
+                    }
+                }
+                Token::Output | Token::Append | Token::ErrorOutput => {
+                    let path = redirect_target(tokens.next(), token.operator())?;
+                    let redirect = match token {
+                        Token::Output => RedirectType::Overwrite(path),
+                        Token::Append => RedirectType::Append(path),
+                        _ => RedirectType::Error(path),
+                    };
+                    if stage.output.replace(redirect).is_some() {
+                        return Err(ShellError::ParseError("Only one output redirection per command".to_string()));
+                    }
+                }
+            }
+        }
+        stages.push(stage.finish()?);
+        
+        let last = stages.len() - 1;
+        for (index, stage) in stages.iter().enumerate() {
+            if index > 0 && stage.input_redirect.is_some() {
+                return Err(ShellError::ParseError("Only the first command of a pipeline can read a file".to_string()));
+            }
+            if index < last && stage.output_redirect.is_some() {
+                return Err(ShellError::ParseError("Only the last command of a pipeline can write to a file".to_string()));
+            }
+        }
         
+        let mut pipeline = stages.pop().expect("a pipeline has at least one command");
+        while let Some(mut previous) = stages.pop() {
+            previous.pipe_to = Some(Box::new(pipeline));
+            pipeline = previous;
+        }
+        pipeline.background = background;
+        Ok(pipeline)
+    }
+}
+
+/// Word or operator of a command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Word(&'a str),
+    Pipe,
+    Input,
+    Output,
+    Append,
+    ErrorOutput,
+}
+
+impl Token<'_> {
+    fn operator(self) -> &'static str {
+        match self {
+            Token::Word(_) => "",
+            Token::Pipe => "|",
+            Token::Input => "<",
+            Token::Output => ">",
+            Token::Append => ">>",
+            Token::ErrorOutput => "2>",
+        }
+    }
+}
+
+/// Split a command line at whitespace and around operators
+///
+/// `2>` is only an operator at the start of a word, so `a2>b` writes the
+/// output of `a2` to `b`.
+fn tokenize(command_line: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = command_line;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return tokens;
+        }
+        let (token, length) = if rest.starts_with(">>") {
+            (Token::Append, 2)
+        } else if rest.starts_with("2>") {
+            (Token::ErrorOutput, 2)
+        } else if rest.starts_with('>') {
+            (Token::Output, 1)
+        } else if rest.starts_with('<') {
+            (Token::Input, 1)
+        } else if rest.starts_with('|') {
+            (Token::Pipe, 1)
+        } else {
+            let end = rest.find(|c: char| c.is_whitespace() || "|<>".contains(c)).unwrap_or(rest.len());
+            (Token::Word(&rest[..end]), end)
+        };
+        tokens.push(token);
+        rest = &rest[length..];
+    }
+}
+
+/// File name following a redirection operator
+fn redirect_target(token: Option<Token<'_>>, operator: &str) -> ShellResult<String> {
+    match token {
+        Some(Token::Word(path)) => Ok(path.to_string()),
+        _ => Err(ShellError::ParseError(format!("Missing file name after {}", operator))),
+    }
+}
+
+/// One pipeline stage while it is being parsed
+#[derive(Default)]
+struct Stage {
+    words: Vec<String>,
+    input: Option<String>,
+    output: Option<RedirectType>,
+}
+
+impl Stage {
+    fn finish(self) -> ShellResult<ParsedCommand> {
+        let mut words = self.words.into_iter();
+        let command = words.next()
+            .ok_or_else(|| ShellError::ParseError("Missing command".to_string()))?;
         Ok(ParsedCommand {
             command,
-            args,
-            input_redirect: None,
-            output_redirect: None,
+            args: words.collect(),
+            input_redirect: self.input,
+            output_redirect: self.output,
             pipe_to: None,
             background: false,
             conditional: None,
         })
     }
-}
\ No newline at end of file
+}
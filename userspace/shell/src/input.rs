@@ -1,73 +1,192 @@
-use alloc::string::String;
-use alloc::vec::Vec;
-use crate::types::SpecialKey;
+use alloc::string::{String, ToString};
+use alloc::format;
+use kosh_posix::STDIN_FILENO;
+use crate::completion::Completion;
+use crate::output::OutputHandler;
+use crate::types::{Key, KeyAction, SpecialKey};
+
+/// Progress through an escape sequence such as `ESC [ A`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    Started,
+    Bracket,
+}
 
 pub struct InputHandler {
-    input_buffer: Vec<u8>,
+    input_buffer: String,
+    /// Prompt of the line being edited, redrawn after listing candidates
+    prompt: String,
+    /// Whether the previous key was a Tab that could not complete further
+    tab_pending: bool,
+    escape: Escape,
 }
 
 impl InputHandler {
     pub fn new() -> Self {
         Self {
-            input_buffer: Vec::with_capacity(256),
+            input_buffer: String::with_capacity(256),
+            prompt: String::new(),
+            tab_pending: false,
+            escape: Escape::None,
         }
     }
-    
-    pub fn read_line(&mut self) -> String {
-        self.input_buffer.clear();
-        
-        // In a real implementation, this would:
-        // 1. Read from keyboard driver via IPC
-        // 2. Handle special keys (backspace, enter, etc.)
-        // 3. Echo characters to display
-        // 4. Return complete line when enter is pressed
-        
-        // For now, simulate some basic commands for testing
-        static mut COMMAND_INDEX: usize = 0;
-        let test_commands = [
-            "help",
-            "echo Hello, Kosh!",
-            "ps",
-            "ls",
-            "pwd",
-            "exit",
-        ];
-        
-        unsafe {
-            let command = test_commands[COMMAND_INDEX % test_commands.len()];
-            COMMAND_INDEX += 1;
-            String::from(command)
+
+    /// Read a line from the console, echoing and editing it key by key
+    ///
+    /// Tab asks `complete` how the last word could go on. Returns `None`
+    /// when Ctrl+D is typed on an empty line.
+    pub fn read_line<F>(&mut self, prompt: &str, output: &OutputHandler, complete: F) -> Option<String>
+    where
+        F: Fn(&str) -> Completion,
+    {
+        self.start_line(prompt);
+        output.print(prompt);
+        loop {
+            // Console input never blocks, so sleep between keys
+            let Some(byte) = self.read_byte() else {
+                crate::infrastructure::idle();
+                continue;
+            };
+            let Some(key) = self.decode(byte) else {
+                continue;
+            };
+            let (action, echo) = self.handle_key(key, &complete);
+            output.print(&echo);
+            match action {
+                KeyAction::Complete => return Some(core::mem::take(&mut self.input_buffer)),
+                KeyAction::Interrupt => return Some(String::new()),
+                KeyAction::Exit => return None,
+                KeyAction::Continue | KeyAction::Suspend => {}
+            }
         }
     }
-    
-    #[allow(dead_code)]
-    fn read_char(&self) -> Option<char> {
-        // In a real implementation, this would read a single character
-        // from the keyboard driver via system calls
-        None
+
+    /// Start editing an empty line shown after `prompt`
+    pub fn start_line(&mut self, prompt: &str) {
+        self.input_buffer.clear();
+        self.prompt = prompt.to_string();
+        self.tab_pending = false;
+        self.escape = Escape::None;
     }
-    
+
+    /// Line typed so far
     #[allow(dead_code)]
-    fn handle_special_key(&mut self, key: SpecialKey) {
-        match key {
-            SpecialKey::Backspace => {
-                if !self.input_buffer.is_empty() {
-                    self.input_buffer.pop();
-                    // Echo backspace to display
-                }
+    pub fn buffer(&self) -> &str {
+        &self.input_buffer
+    }
+
+    /// Key that a byte of console input finishes, if any
+    ///
+    /// Arrow and Home/End keys arrive as escape sequences over several bytes.
+    pub fn decode(&mut self, byte: u8) -> Option<Key> {
+        match (self.escape, byte) {
+            (Escape::None, 0x1b) => {
+                self.escape = Escape::Started;
+                None
             }
-            SpecialKey::Enter => {
-                // Line is complete
+            (Escape::Started, b'[') => {
+                self.escape = Escape::Bracket;
+                None
             }
-            SpecialKey::Tab => {
-                // Tab completion (not implemented)
+            (Escape::Started, _) => {
+                self.escape = Escape::None;
+                None
             }
-            SpecialKey::ArrowUp | SpecialKey::ArrowDown => {
-                // Command history (not implemented)
+            // Parameter bytes come before the one naming the key
+            (Escape::Bracket, b'0'..=b'9' | b';') => None,
+            (Escape::Bracket, final_byte) => {
+                self.escape = Escape::None;
+                let key = match final_byte {
+                    b'A' => SpecialKey::ArrowUp,
+                    b'B' => SpecialKey::ArrowDown,
+                    b'C' => SpecialKey::ArrowRight,
+                    b'D' => SpecialKey::ArrowLeft,
+                    b'H' => SpecialKey::Home,
+                    b'F' => SpecialKey::End,
+                    _ => return None,
+                };
+                Some(Key::Special(key))
             }
-            _ => {
-                // Other special keys ignored
+            (Escape::None, b'\t') => Some(Key::Special(SpecialKey::Tab)),
+            (Escape::None, b'\r' | b'\n') => Some(Key::Special(SpecialKey::Enter)),
+            (Escape::None, 0x7f | 0x08) => Some(Key::Special(SpecialKey::Backspace)),
+            (Escape::None, 0x03) => Some(Key::Special(SpecialKey::CtrlC)),
+            (Escape::None, 0x04) => Some(Key::Special(SpecialKey::CtrlD)),
+            (Escape::None, 0x1a) => Some(Key::Special(SpecialKey::CtrlZ)),
+            (Escape::None, 0x20..=0x7e) => Some(Key::Char(byte as char)),
+            (Escape::None, _) => None,
+        }
+    }
+
+    /// Apply a key to the line, returning what happens next and the text
+    /// to echo
+    pub fn handle_key<F>(&mut self, key: Key, complete: F) -> (KeyAction, String)
+    where
+        F: FnOnce(&str) -> Completion,
+    {
+        let tab_pending = core::mem::replace(&mut self.tab_pending, false);
+        match key {
+            Key::Char(ch) => {
+                self.input_buffer.push(ch);
+                (KeyAction::Continue, ch.to_string())
             }
+            Key::Special(SpecialKey::Backspace) => match self.input_buffer.pop() {
+                Some(_) => (KeyAction::Continue, String::from("\u{8} \u{8}")),
+                None => (KeyAction::Continue, String::new()),
+            },
+            Key::Special(SpecialKey::Enter) => (KeyAction::Complete, String::from("\n")),
+            Key::Special(SpecialKey::Tab) => {
+                let completion = complete(&self.input_buffer);
+                (KeyAction::Continue, self.complete_word(&completion, tab_pending))
+            }
+            Key::Special(SpecialKey::CtrlC) => {
+                self.input_buffer.clear();
+                (KeyAction::Interrupt, String::from("^C\n"))
+            }
+            Key::Special(SpecialKey::CtrlD) if self.input_buffer.is_empty() => {
+                (KeyAction::Exit, String::from("\n"))
+            }
+            // History and cursor movement are not supported yet
+            Key::Special(_) => (KeyAction::Continue, String::new()),
         }
     }
-}
\ No newline at end of file
+
+    /// Whether Ctrl+C was typed while a foreground job runs
+    pub fn interrupt_requested(&mut self) -> bool {
+        self.read_byte() == Some(0x03)
+    }
+
+    /// Extend the last word as far as every candidate agrees; once it
+    /// cannot go further, a second Tab lists the candidates
+    fn complete_word(&mut self, completion: &Completion, tab_pending: bool) -> String {
+        let prefix = completion.common_prefix();
+        let typed = self.input_buffer.len() - completion.start;
+        let mut insert = String::from(prefix.get(typed..).unwrap_or(""));
+        // A single match is finished, unless it is a directory to go into
+        if completion.candidates.len() == 1 && !prefix.ends_with('/') {
+            insert.push(' ');
+        }
+        if !insert.is_empty() {
+            self.input_buffer.push_str(&insert);
+            return insert;
+        }
+        if completion.candidates.len() < 2 {
+            return String::new();
+        }
+        if !tab_pending {
+            self.tab_pending = true;
+            return String::new();
+        }
+        format!("\n{}\n{}{}", completion.names().join("  "), self.prompt, self.input_buffer)
+    }
+
+    /// Next byte typed at the console, if one is waiting
+    fn read_byte(&self) -> Option<u8> {
+        let mut byte = [0u8; 1];
+        match kosh_posix::read(STDIN_FILENO, &mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::format;
+use kosh_posix::{SIGINT, SIGKILL, SIGNAL_EXIT_BASE, SIGTERM};
+use kosh_types::ProcessId;
+use crate::error::{ShellError, ShellResult};
+use crate::types::{BackgroundJob, JobStatus};
+
+/// Children the shell started, with at most one of them in the foreground
+pub struct JobTable {
+    jobs: Vec<BackgroundJob>,
+    foreground: Option<u32>,
+}
+
+impl JobTable {
+    pub fn new() -> Self {
+        Self {
+            jobs: Vec::new(),
+            foreground: None,
+        }
+    }
+
+    /// Track a running child, returning its job ID
+    ///
+    /// Like other shells, IDs restart above the highest one still in use.
+    pub fn add(&mut self, pid: ProcessId, command: String) -> u32 {
+        let job_id = self.jobs.iter().map(|job| job.job_id).max().unwrap_or(0) + 1;
+        self.jobs.push(BackgroundJob {
+            job_id,
+            pid,
+            command,
+            status: JobStatus::Running,
+        });
+        job_id
+    }
+
+    pub fn jobs(&self) -> &[BackgroundJob] {
+        &self.jobs
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Job a `fg`, `bg` or `kill` argument names; without one, the newest job
+    pub fn find(&self, spec: Option<&str>) -> ShellResult<&BackgroundJob> {
+        let job = match spec {
+            None => self.jobs.last(),
+            Some(spec) => {
+                let job_id = parse_job_spec(spec)?;
+                self.jobs.iter().find(|job| job.job_id == job_id)
+            }
+        };
+        job.ok_or_else(|| ShellError::JobNotFound(spec.unwrap_or("current").to_string()))
+    }
+
+    pub fn foreground(&self) -> Option<&BackgroundJob> {
+        let job_id = self.foreground?;
+        self.jobs.iter().find(|job| job.job_id == job_id)
+    }
+
+    /// Move a job to the foreground, or everything to the background with `None`
+    pub fn set_foreground(&mut self, job_id: Option<u32>) {
+        self.foreground = job_id;
+    }
+
+    pub fn set_status(&mut self, job_id: u32, status: JobStatus) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.job_id == job_id) {
+            job.status = status;
+        }
+    }
+
+    /// Record the exit of child `pid`; unknown children are ignored
+    pub fn child_exited(&mut self, pid: ProcessId, exit_code: i32) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.pid == pid) {
+            job.status = JobStatus::Completed(exit_code);
+        }
+    }
+
+    /// Remove finished jobs, returning the ones that ran in the background
+    ///
+    /// The foreground job's outcome is shown as it ends, so only background
+    /// jobs need announcing.
+    pub fn take_finished(&mut self) -> Vec<BackgroundJob> {
+        let foreground = self.foreground;
+        if self.foreground().is_some_and(|job| matches!(job.status, JobStatus::Completed(_))) {
+            self.foreground = None;
+        }
+        let (finished, running): (Vec<_>, Vec<_>) = self.jobs.drain(..)
+            .partition(|job| matches!(job.status, JobStatus::Completed(_)));
+        self.jobs = running;
+        finished.into_iter()
+            .filter(|job| Some(job.job_id) != foreground)
+            .collect()
+    }
+}
+
+/// Job ID from `%<id>` or a plain `<id>`
+pub fn parse_job_spec(spec: &str) -> ShellResult<u32> {
+    spec.strip_prefix('%').unwrap_or(spec).parse::<u32>()
+        .map_err(|_| ShellError::InvalidArguments(format!("Invalid job: {}", spec)))
+}
+
+/// Command line with a trailing `&` removed, and whether it was there
+pub fn split_background(command_line: &str) -> (&str, bool) {
+    let command_line = command_line.trim();
+    match command_line.strip_suffix('&') {
+        // `&&` belongs to a conditional, not to job control
+        Some(rest) if !rest.ends_with('&') => (rest.trim_end(), true),
+        _ => (command_line, false),
+    }
+}
+
+/// How an exit status reads in job listings
+pub fn describe_exit(exit_code: i32) -> String {
+    if exit_code == 0 {
+        return "Done".to_string();
+    }
+    match exit_code - SIGNAL_EXIT_BASE {
+        SIGINT => "Interrupt".to_string(),
+        SIGKILL => "Killed".to_string(),
+        SIGTERM => "Terminated".to_string(),
+        _ => format!("Exit {}", exit_code),
+    }
+}
+
+/// One `jobs` line; `current` marks the job `fg` and `bg` default to
+pub fn format_job(job: &BackgroundJob, current: bool) -> String {
+    let status = match job.status {
+        JobStatus::Running => "Running".to_string(),
+        JobStatus::Stopped => "Stopped".to_string(),
+        JobStatus::Completed(exit_code) => describe_exit(exit_code),
+    };
+    format!("[{}]{} {:<10} {}", job.job_id, if current { '+' } else { ' ' }, status, job.command)
+}
@@ -3,11 +3,15 @@
 extern crate alloc;
 
 pub mod commands;
+pub mod completion;
 pub mod input;
 pub mod output;
 pub mod error;
 pub mod types;
 pub mod infrastructure;
+pub mod jobs;
+pub mod pipeline;
+pub mod script;
 
 #[cfg(test)]
 mod tests;
@@ -17,4 +21,6 @@ pub use input::InputHandler;
 pub use output::OutputHandler;
 pub use error::{ShellError, ShellResult};
 pub use types::*;
-pub use infrastructure::*;
\ No newline at end of file
+pub use infrastructure::*;
+pub use jobs::JobTable;
+pub use completion::Completion;
\ No newline at end of file
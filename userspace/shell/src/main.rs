@@ -11,10 +11,15 @@ use linked_list_allocator::LockedHeap;
 static ALLOCATOR: LockedHeap = LockedHeap::empty();
 
 mod commands;
+mod completion;
 mod input;
 mod output;
 mod error;
 mod types;
+mod infrastructure;
+mod jobs;
+mod pipeline;
+mod script;
 
 use commands::CommandProcessor;
 use input::InputHandler;
@@ -36,6 +41,7 @@ pub extern "C" fn _start() -> ! {
 struct KoshShell {
     input_handler: InputHandler,
     output_handler: OutputHandler,
+    command_processor: CommandProcessor,
     running: bool,
 }
 
@@ -44,6 +50,7 @@ impl KoshShell {
         Self {
             input_handler: InputHandler::new(),
             output_handler: OutputHandler::new(),
+            command_processor: CommandProcessor::new(),
             running: true,
         }
     }
@@ -54,13 +61,28 @@ impl KoshShell {
         self.output_handler.print_line("Type 'help' for available commands");
         self.output_handler.print_line("Available commands: help, ls, cat, echo, ps, drivers, exit");
         
+        // Variables from init, or from the shell that started this one
+        if let Ok(environment) = kosh_posix::environ() {
+            self.command_processor.import_environment(&environment);
+        }
+        
+        // Boot-time setup, as init starts this shell
+        match self.command_processor.run_rc_script() {
+            Some(Ok(output)) if !output.is_empty() => self.output_handler.print_line(&output),
+            Some(Err(error)) => self.output_handler.print_line(&error.user_message()),
+            _ => {}
+        }
+        
         // Main shell loop
         while self.running {
-            // Print prompt
-            self.output_handler.print("kosh> ");
+            self.report_finished_jobs();
             
-            // Read command line
-            let command_line = self.input_handler.read_line();
+            // Read command line, completing words on Tab
+            let processor = &self.command_processor;
+            let Some(command_line) = self.input_handler.read_line("kosh> ", &self.output_handler, |line| processor.complete(line)) else {
+                // Ctrl+D on an empty line
+                break;
+            };
             
             // Process command
             match self.process_shell_command(&command_line) {
@@ -76,6 +98,7 @@ impl KoshShell {
                     }
                 }
             }
+            self.wait_foreground();
             
             // Check if exit was requested
             if command_line.trim() == "exit" {
@@ -90,10 +113,28 @@ impl KoshShell {
     }
     
     fn process_shell_command(&mut self, command_line: &str) -> ShellResult<String> {
-        let mut command_processor = CommandProcessor::new();
-        command_processor.process_command(command_line)
+        self.command_processor.process_command(command_line)
+    }
+    
+    /// Wait for the foreground job to end, passing Ctrl+C on to it
+    fn wait_foreground(&mut self) {
+        while self.command_processor.foreground_pid().is_some() {
+            if self.input_handler.interrupt_requested() {
+                if let Err(error) = self.command_processor.interrupt_foreground() {
+                    self.output_handler.print_line(&error.user_message());
+                }
+            }
+            self.report_finished_jobs();
+            infrastructure::idle();
+        }
     }
     
+    /// Announce background jobs that finished since the last prompt
+    fn report_finished_jobs(&mut self) {
+        for notice in self.command_processor.reap_children() {
+            self.output_handler.print_line(&notice);
+        }
+    }
 
 }
 
@@ -111,7 +152,7 @@ fn debug_print(message: &[u8]) {
     #[cfg(debug_assertions)]
     unsafe {
         core::arch::asm!(
-            "syscall",
+            "int 0x80",
             in("rax") 100u64, // SYS_DEBUG_PRINT
             in("rdi") message.as_ptr(),
             in("rsi") message.len(),
@@ -124,7 +165,7 @@ fn debug_print(message: &[u8]) {
 fn sys_exit(status: i32) -> ! {
     unsafe {
         core::arch::asm!(
-            "syscall",
+            "int 0x80",
             in("rax") 1u64, // SYS_EXIT
             in("rdi") status,
             options(noreturn)
@@ -57,7 +57,7 @@ impl OutputHandler {
         {
             unsafe {
                 core::arch::asm!(
-                    "syscall",
+                    "int 0x80",
                     in("rax") 100u64, // SYS_DEBUG_PRINT
                     in("rdi") data.as_ptr(),
                     in("rsi") data.len(),
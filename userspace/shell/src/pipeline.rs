@@ -0,0 +1,177 @@
+use alloc::string::{String, ToString};
+use alloc::format;
+use kosh_posix::{Errno, Fd, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+use kosh_types::ProcessId;
+use crate::error::{ShellError, ShellResult};
+use crate::types::RedirectType;
+
+/// Exit status shells use for a program that cannot be run
+const EXIT_CANNOT_RUN: i32 = 127;
+
+/// Where a spawned program's standard streams are connected
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageIo<'a> {
+    /// Pipe to read standard input from
+    pub stdin: Option<Fd>,
+    /// Pipe to write standard output to
+    pub stdout: Option<Fd>,
+    /// File to read standard input from
+    pub input_file: Option<&'a str>,
+    /// File to send output or errors to
+    pub output_file: Option<&'a RedirectType>,
+    /// Pipe ends the child inherits but must not keep open, or readers
+    /// would never see end of file
+    pub close: &'a [Fd],
+}
+
+/// Fork a child running `program` with its streams connected as `io` says
+///
+/// `env` holds the `NAME=value` strings the program starts with.
+///
+/// Files are opened by the child, since files served by fs-service are not
+/// inherited across fork.
+pub fn spawn(program: &str, args: &[&str], env: &[&str], io: &StageIo) -> ShellResult<ProcessId> {
+    match kosh_posix::fork() {
+        Ok(0) => {
+            if let Err((name, errno)) = connect_streams(io) {
+                report_in_child(&name, errno);
+                kosh_posix::exit(1)
+            }
+            let errno = kosh_posix::execve(program, args, env);
+            report_in_child(program, errno);
+            kosh_posix::exit(EXIT_CANNOT_RUN)
+        }
+        Ok(pid) => Ok(pid),
+        Err(errno) => Err(ShellError::SystemCallFailed(kosh_posix::raw::SYS_FORK, errno.0)),
+    }
+}
+
+/// Fork a child that writes `text` into a new pipe, returning the read end
+///
+/// Used for a built-in command at the head of a pipeline. The shell
+/// itself never writes into a pipe, so a reader that quits early sends
+/// SIGPIPE to the feeder rather than to the shell.
+pub fn feed(text: &str) -> ShellResult<Fd> {
+    let (read_fd, write_fd) = pipe()?;
+    match kosh_posix::fork() {
+        Ok(0) => {
+            let _ = kosh_posix::close(read_fd);
+            let status = match kosh_posix::write_all(write_fd, text.as_bytes()) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            };
+            kosh_posix::exit(status)
+        }
+        Ok(_) => {
+            let _ = kosh_posix::close(write_fd);
+            Ok(read_fd)
+        }
+        Err(errno) => {
+            close_all(&[read_fd, write_fd]);
+            Err(ShellError::SystemCallFailed(kosh_posix::raw::SYS_FORK, errno.0))
+        }
+    }
+}
+
+/// New pipe as (read end, write end)
+pub fn pipe() -> ShellResult<(Fd, Fd)> {
+    kosh_posix::pipe().map_err(|errno| ShellError::SystemCallFailed(kosh_posix::raw::SYS_PIPE, errno.0))
+}
+
+/// Close descriptors the shell no longer needs
+pub fn close_all(fds: &[Fd]) {
+    for &fd in fds {
+        let _ = kosh_posix::close(fd);
+    }
+}
+
+/// Send the outcome of a built-in command where `redirect` says
+///
+/// Returns what is still to be shown on the console.
+pub fn redirect_builtin(result: ShellResult<String>, redirect: &RedirectType) -> ShellResult<String> {
+    match (redirect, result) {
+        (RedirectType::Error(path), Err(error)) => {
+            write_file(redirect, &format!("{}\n", error.user_message()))
+                .map_err(|errno| file_error(path, errno))?;
+            Ok(String::new())
+        }
+        (RedirectType::Error(path), Ok(output)) => {
+            // The file is created even when nothing goes wrong
+            write_file(redirect, "").map_err(|errno| file_error(path, errno))?;
+            Ok(output)
+        }
+        (RedirectType::Overwrite(path) | RedirectType::Append(path), Ok(output)) => {
+            let text = if output.is_empty() { output } else { format!("{}\n", output) };
+            write_file(redirect, &text).map_err(|errno| file_error(path, errno))?;
+            Ok(String::new())
+        }
+        (_, Err(error)) => Err(error),
+    }
+}
+
+/// Open the file of a redirection, returning it with the stream it replaces
+fn open_redirect(redirect: &RedirectType) -> Result<(Fd, Fd), Errno> {
+    let create = kosh_posix::O_WRONLY | kosh_posix::O_CREAT;
+    let (path, flags, stream) = match redirect {
+        RedirectType::Overwrite(path) => (path, create | kosh_posix::O_TRUNC, STDOUT_FILENO),
+        RedirectType::Append(path) => (path, create | kosh_posix::O_APPEND, STDOUT_FILENO),
+        RedirectType::Error(path) => (path, create | kosh_posix::O_TRUNC, STDERR_FILENO),
+    };
+    Ok((kosh_posix::open(path, flags, 0o644)?, stream))
+}
+
+fn write_file(redirect: &RedirectType, text: &str) -> Result<(), Errno> {
+    let (fd, _) = open_redirect(redirect)?;
+    let result = kosh_posix::write_all(fd, text.as_bytes());
+    let _ = kosh_posix::close(fd);
+    result
+}
+
+fn file_error(path: &str, errno: Errno) -> ShellError {
+    match errno {
+        kosh_posix::errno::ENOENT | kosh_posix::errno::ENOTDIR => ShellError::FileNotFound(path.to_string()),
+        kosh_posix::errno::EACCES | kosh_posix::errno::EPERM => ShellError::PermissionDenied(path.to_string()),
+        errno => ShellError::OutputError(format!("{}: {}", path, errno.description())),
+    }
+}
+
+/// In a forked child, move pipes and files onto the standard streams
+///
+/// Fails with the name of what could not be connected.
+fn connect_streams(io: &StageIo) -> Result<(), (String, Errno)> {
+    close_all(io.close);
+    let pipe_error = |errno| ("pipe".to_string(), errno);
+    if let Some(fd) = io.stdin {
+        move_fd(fd, STDIN_FILENO).map_err(pipe_error)?;
+    }
+    if let Some(fd) = io.stdout {
+        move_fd(fd, STDOUT_FILENO).map_err(pipe_error)?;
+    }
+    if let Some(path) = io.input_file {
+        let fd = kosh_posix::open(path, kosh_posix::O_RDONLY, 0).map_err(|errno| (path.to_string(), errno))?;
+        move_fd(fd, STDIN_FILENO).map_err(|errno| (path.to_string(), errno))?;
+    }
+    if let Some(redirect) = io.output_file {
+        let path = match redirect {
+            RedirectType::Overwrite(path) | RedirectType::Append(path) | RedirectType::Error(path) => path,
+        };
+        let (fd, stream) = open_redirect(redirect).map_err(|errno| (path.clone(), errno))?;
+        move_fd(fd, stream).map_err(|errno| (path.clone(), errno))?;
+    }
+    Ok(())
+}
+
+/// Renumber `fd` as `target`
+fn move_fd(fd: Fd, target: Fd) -> Result<(), Errno> {
+    if fd != target {
+        kosh_posix::dup2(fd, target)?;
+        let _ = kosh_posix::close(fd);
+    }
+    Ok(())
+}
+
+/// Tell the user why a forked child gives up
+fn report_in_child(name: &str, errno: Errno) {
+    let message = format!("{}: {}\n", name, errno.description());
+    let _ = kosh_posix::write_all(STDERR_FILENO, message.as_bytes());
+}
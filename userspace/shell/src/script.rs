@@ -0,0 +1,249 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::format;
+use crate::error::{ShellError, ShellResult};
+
+/// Script the boot shell runs before its first prompt, if it exists
+pub const RC_SCRIPT: &str = "/etc/rc.sh";
+
+/// One statement of a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement {
+    /// Command line, expanded when it runs
+    Command(String),
+    /// `if`, with any `elif` nested in `else_branch`
+    If {
+        condition: String,
+        then_branch: Vec<Statement>,
+        else_branch: Vec<Statement>,
+    },
+    /// `for <variable> in <words>`; the words are expanded when the loop starts
+    For {
+        variable: String,
+        words: String,
+        body: Vec<Statement>,
+    },
+}
+
+/// Parse a script
+///
+/// Statements are separated by newlines or `;`, and `#` starts a comment.
+/// Keywords follow sh: `if`/`then`/`elif`/`else`/`fi` and
+/// `for`/`in`/`do`/`done`.
+pub fn parse(text: &str) -> ShellResult<Vec<Statement>> {
+    let mut parser = Parser { clauses: clauses(text), position: 0 };
+    let (statements, _) = parser.block(&[])?;
+    Ok(statements)
+}
+
+/// Replace `$NAME`, `${NAME}` and `$?` in `line` with what `lookup` gives
+///
+/// Unset variables expand to nothing, and a `$` not starting a name is
+/// kept. There is no quoting yet, so every `$` is expanded.
+pub fn expand<F>(line: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut expanded = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let (name, length) = if after.starts_with('?') {
+            ("?", 1)
+        } else if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) if is_name(&braced[..end]) => (&braced[..end], end + 2),
+                _ => ("", 0),
+            }
+        } else {
+            let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+            if is_name(&after[..end]) { (&after[..end], end) } else { ("", 0) }
+        };
+        if name.is_empty() {
+            expanded.push('$');
+        } else {
+            expanded.push_str(&lookup(name).unwrap_or_default());
+        }
+        rest = &after[length..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Name and value of a `NAME=value` line
+///
+/// The value is a single word, or is wrapped in matching quotes when it
+/// contains spaces.
+pub fn parse_assignment(line: &str) -> Option<(&str, &str)> {
+    let (name, value) = line.trim().split_once('=')?;
+    if !is_name(name) {
+        return None;
+    }
+    for quote in ['"', '\''] {
+        if let Some(quoted) = value.strip_prefix(quote).and_then(|value| value.strip_suffix(quote)) {
+            return Some((name, quoted));
+        }
+    }
+    (!value.contains(char::is_whitespace)).then_some((name, value))
+}
+
+/// Whether `name` can name a variable
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Words after which a new statement starts on the same clause
+const LEADING_KEYWORDS: &[&str] = &["then", "else", "do"];
+
+/// Statement or keyword of a script as (line number, text)
+type Clause = (usize, String);
+
+/// Clauses of a script, in order
+fn clauses(text: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(start) if start == 0 || line[..start].ends_with(char::is_whitespace) => &line[..start],
+            _ => line,
+        };
+        for clause in line.split(';').map(str::trim).filter(|clause| !clause.is_empty()) {
+            let (first, rest) = split_word(clause);
+            if LEADING_KEYWORDS.contains(&first) && !rest.is_empty() {
+                clauses.push((index + 1, first.to_string()));
+                clauses.push((index + 1, rest.to_string()));
+            } else {
+                clauses.push((index + 1, clause.to_string()));
+            }
+        }
+    }
+    clauses
+}
+
+/// First word of `clause` and what follows it
+fn split_word(clause: &str) -> (&str, &str) {
+    match clause.split_once(char::is_whitespace) {
+        Some((first, rest)) => (first, rest.trim_start()),
+        None => (clause, ""),
+    }
+}
+
+struct Parser {
+    clauses: Vec<Clause>,
+    position: usize,
+}
+
+impl Parser {
+    /// Statements up to one of `terminators`, returned with the clause
+    /// that ended them; the whole script when there are none
+    fn block(&mut self, terminators: &[&str]) -> ShellResult<(Vec<Statement>, Option<Clause>)> {
+        let mut statements = Vec::new();
+        while let Some((line, clause)) = self.next() {
+            let (keyword, rest) = split_word(&clause);
+            if terminators.contains(&keyword) {
+                return Ok((statements, Some((line, clause))));
+            }
+            match keyword {
+                "if" => statements.push(self.conditional(line, rest)?),
+                "for" => statements.push(self.for_loop(line, rest)?),
+                "then" | "elif" | "else" | "fi" | "do" | "done" | "in" => {
+                    return Err(syntax_error(line, &format!("unexpected '{}'", keyword)));
+                }
+                _ => statements.push(Statement::Command(clause)),
+            }
+        }
+        match terminators.last() {
+            None => Ok((statements, None)),
+            Some(expected) => Err(ShellError::ParseError(format!("Script ends before '{}'", expected))),
+        }
+    }
+
+    fn conditional(&mut self, line: usize, condition: &str) -> ShellResult<Statement> {
+        if condition.is_empty() {
+            return Err(syntax_error(line, "'if' without a condition"));
+        }
+        self.expect(line, "then")?;
+        let (then_branch, end) = self.block(&["elif", "else", "fi"])?;
+        let (end_line, end) = end.expect("a terminated block");
+        let else_branch = match split_word(&end) {
+            ("elif", condition) => {
+                let condition = condition.to_string();
+                // The nested `if` takes the shared `fi`
+                alloc::vec![self.conditional(end_line, &condition)?]
+            }
+            ("else", _) => self.block(&["fi"])?.0,
+            _ => Vec::new(),
+        };
+        Ok(Statement::If { condition: condition.to_string(), then_branch, else_branch })
+    }
+
+    fn for_loop(&mut self, line: usize, header: &str) -> ShellResult<Statement> {
+        let (variable, rest) = split_word(header);
+        let words = match split_word(rest) {
+            ("in", words) if is_name(variable) => words,
+            _ => return Err(syntax_error(line, "expected 'for <name> in <words>'")),
+        };
+        self.expect(line, "do")?;
+        let (body, _) = self.block(&["done"])?;
+        Ok(Statement::For { variable: variable.to_string(), words: words.to_string(), body })
+    }
+
+    fn expect(&mut self, line: usize, keyword: &str) -> ShellResult<()> {
+        let message = format!("expected '{}'", keyword);
+        match self.next() {
+            Some((_, clause)) if clause == keyword => Ok(()),
+            Some((line, _)) => Err(syntax_error(line, &message)),
+            None => Err(syntax_error(line, &message)),
+        }
+    }
+
+    fn next(&mut self) -> Option<Clause> {
+        let clause = self.clauses.get(self.position).cloned()?;
+        self.position += 1;
+        Some(clause)
+    }
+}
+
+fn syntax_error(line: usize, message: &str) -> ShellError {
+    ShellError::ParseError(format!("Script line {}: {}", line, message))
+}
+
+/// Evaluate the condition of a `test` or `[` command
+///
+/// `file` tells whether a path exists and if so, whether it is a directory.
+pub fn evaluate_test<F>(args: &[&str], file: F) -> ShellResult<bool>
+where
+    F: Fn(&str) -> Option<bool>,
+{
+    match args {
+        [] => Ok(false),
+        ["!", rest @ ..] => Ok(!evaluate_test(rest, file)?),
+        [text] => Ok(!text.is_empty()),
+        ["-n", text] => Ok(!text.is_empty()),
+        ["-z", text] => Ok(text.is_empty()),
+        ["-e", path] => Ok(file(path).is_some()),
+        ["-d", path] => Ok(file(path) == Some(true)),
+        ["-f", path] => Ok(file(path) == Some(false)),
+        [left, "=", right] => Ok(left == right),
+        [left, "!=", right] => Ok(left != right),
+        [left, operator, right] => {
+            let (left, right) = (integer(left)?, integer(right)?);
+            match *operator {
+                "-eq" => Ok(left == right),
+                "-ne" => Ok(left != right),
+                "-lt" => Ok(left < right),
+                "-le" => Ok(left <= right),
+                "-gt" => Ok(left > right),
+                "-ge" => Ok(left >= right),
+                _ => Err(ShellError::InvalidArguments(format!("test: unknown operator {}", operator))),
+            }
+        }
+        _ => Err(ShellError::InvalidArguments("test: too many arguments".to_string())),
+    }
+}
+
+fn integer(text: &str) -> ShellResult<i64> {
+    text.parse().map_err(|_| ShellError::InvalidArguments(format!("test: integer expected: {}", text)))
+}
@@ -2,10 +2,19 @@
 mod tests {
     use alloc::vec;
     use alloc::string::ToString;
+    use alloc::vec::Vec;
     use crate::error::{ShellError, ErrorCategory};
     use crate::types::*;
     use crate::infrastructure::*;
-    use crate::commands::CommandProcessor;
+    use crate::completion::complete;
+    use crate::input::InputHandler;
+    use crate::script::{self, Statement};
+    use crate::jobs::{JobTable, split_background, parse_job_spec, describe_exit, format_job};
+    use crate::commands::{CommandProcessor, parse_drivers_args, parse_mount_args, parse_umount_args, parse_proc_status, format_ps, parse_sched_args, format_sched_info, parse_powerctl_args, format_cpufreq_info, format_power_supply_info, format_thermal_zones, parse_dmesg_args, filter_klog, parse_audit_args, format_audit, parse_trace_args, format_trace_status, format_trace, TraceCommand};
+    use kosh_types::MountFlags;
+    use kosh_posix::sched::{SchedInfo, SchedPolicy};
+    use kosh_posix::power::{self, CpuFreqInfo, CpuGovernor, PowerSupplyInfo, ThermalZoneInfo};
+    use kosh_posix::LogLevel;
 
     #[test]
     fn test_shell_error_user_message() {
@@ -59,6 +68,64 @@ mod tests {
         assert!(parsed.pipe_to.is_none());
     }
 
+    #[test]
+    fn test_command_parser_background() {
+        let parser = CommandParser::new();
+        
+        let parsed = parser.parse("/bin/sleep 10 &").unwrap();
+        assert_eq!(parsed.command, "/bin/sleep");
+        assert_eq!(parsed.args, vec!["10"]);
+        assert!(parsed.background);
+        
+        assert!(parser.parse("/bin/sleep 10&").unwrap().background);
+        assert!(parser.parse("&").is_err());
+        
+        assert_eq!(split_background("  /bin/app arg &  "), ("/bin/app arg", true));
+        assert_eq!(split_background("/bin/app && echo ok"), ("/bin/app && echo ok", false));
+        assert_eq!(split_background("/bin/app &&"), ("/bin/app &&", false));
+    }
+
+    #[test]
+    fn test_command_parser_pipeline() {
+        let parser = CommandParser::new();
+        
+        let parsed = parser.parse("echo hello world | /bin/upper|/bin/wc -l > /tmp/count &").unwrap();
+        assert_eq!(parsed.command, "echo");
+        assert_eq!(parsed.args, vec!["hello", "world"]);
+        assert!(parsed.background);
+        assert!(parsed.output_redirect.is_none());
+        
+        let upper = parsed.pipe_to.as_deref().unwrap();
+        assert_eq!(upper.command, "/bin/upper");
+        assert!(upper.args.is_empty());
+        
+        let count = upper.pipe_to.as_deref().unwrap();
+        assert_eq!(count.command, "/bin/wc");
+        assert_eq!(count.args, vec!["-l"]);
+        assert_eq!(count.output_redirect, Some(RedirectType::Overwrite("/tmp/count".to_string())));
+        assert!(count.pipe_to.is_none());
+    }
+
+    #[test]
+    fn test_command_parser_redirection() {
+        let parser = CommandParser::new();
+        
+        let parsed = parser.parse("/bin/sort</tmp/in >>/tmp/out").unwrap();
+        assert_eq!(parsed.command, "/bin/sort");
+        assert!(parsed.args.is_empty());
+        assert_eq!(parsed.input_redirect.as_deref(), Some("/tmp/in"));
+        assert_eq!(parsed.output_redirect, Some(RedirectType::Append("/tmp/out".to_string())));
+        
+        let parsed = parser.parse("/bin/app 2 2> /tmp/err").unwrap();
+        assert_eq!(parsed.args, vec!["2"]);
+        assert_eq!(parsed.output_redirect, Some(RedirectType::Error("/tmp/err".to_string())));
+        
+        for line in ["echo >", "/bin/a | | /bin/b", "| /bin/b", "/bin/a > /x > /y", "/bin/a < /x < /y",
+                     "/bin/a > /x | /bin/b", "/bin/a | /bin/b < /x", "/bin/a > | /bin/b"] {
+            assert!(matches!(parser.parse(line), Err(ShellError::ParseError(_))), "{}", line);
+        }
+    }
+
     #[test]
     fn test_command_parser_empty() {
         let parser = CommandParser::new();
@@ -105,6 +172,71 @@ mod tests {
         assert_eq!(jobs.len(), 0);
     }
 
+    #[test]
+    fn test_job_table() {
+        let mut jobs = JobTable::new();
+        assert_eq!(jobs.add(40, "/bin/a &".to_string()), 1);
+        assert_eq!(jobs.add(41, "/bin/b".to_string()), 2);
+        jobs.set_foreground(Some(2));
+        
+        assert_eq!(jobs.find(None).unwrap().pid, 41);
+        assert_eq!(jobs.find(Some("%1")).unwrap().pid, 40);
+        assert_eq!(jobs.find(Some("1")).unwrap().pid, 40);
+        assert!(matches!(jobs.find(Some("%3")), Err(ShellError::JobNotFound(_))));
+        assert!(matches!(jobs.find(Some("%x")), Err(ShellError::InvalidArguments(_))));
+        assert_eq!(jobs.foreground().unwrap().pid, 41);
+        
+        // Only background jobs are announced; the foreground one is let go
+        jobs.child_exited(40, 0);
+        jobs.child_exited(41, 130);
+        jobs.child_exited(99, 1);
+        let finished = jobs.take_finished();
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].job_id, 1);
+        assert!(jobs.foreground().is_none());
+        assert!(jobs.is_empty());
+        
+        // IDs restart above the highest one in use
+        assert_eq!(jobs.add(42, "/bin/c &".to_string()), 1);
+        assert_eq!(jobs.add(43, "/bin/d &".to_string()), 2);
+        jobs.child_exited(42, 0);
+        jobs.take_finished();
+        assert_eq!(jobs.add(44, "/bin/e &".to_string()), 3);
+    }
+
+    #[test]
+    fn test_job_format() {
+        assert_eq!(parse_job_spec("%4").unwrap(), 4);
+        assert_eq!(describe_exit(0), "Done");
+        assert_eq!(describe_exit(3), "Exit 3");
+        assert_eq!(describe_exit(130), "Interrupt");
+        assert_eq!(describe_exit(137), "Killed");
+        assert_eq!(describe_exit(143), "Terminated");
+        
+        let mut job = BackgroundJob { job_id: 2, pid: 7, command: "/bin/sleep 5 &".to_string(), status: JobStatus::Running };
+        assert_eq!(format_job(&job, true), "[2]+ Running    /bin/sleep 5 &");
+        job.status = JobStatus::Completed(1);
+        assert_eq!(format_job(&job, false), "[2]  Exit 1     /bin/sleep 5 &");
+    }
+
+    #[test]
+    fn test_job_commands_without_jobs() {
+        let mut processor = CommandProcessor::new();
+        assert_eq!(processor.process_command("jobs").unwrap(), "");
+        assert!(processor.foreground_pid().is_none());
+        assert!(processor.reap_children().is_empty());
+        assert!(matches!(processor.process_command("fg"), Err(ShellError::JobNotFound(_))));
+        assert!(matches!(processor.process_command("bg %2"), Err(ShellError::JobNotFound(_))));
+        assert!(matches!(processor.process_command("fg %1 %2"), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(processor.process_command(" & "), Err(ShellError::ParseError(_))));
+        
+        // Pipelines are checked before anything is started
+        assert!(matches!(processor.process_command("/bin/a | echo"), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(processor.process_command("echo hi | upper"), Err(ShellError::InvalidCommand(_))));
+        assert!(matches!(processor.process_command("echo hi |"), Err(ShellError::ParseError(_))));
+        assert!(processor.interrupt_foreground().is_ok());
+    }
+
     #[test]
     fn test_command_processor_basic() {
         let mut processor = CommandProcessor::new();
@@ -130,6 +262,229 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sched_args() {
+        assert_eq!(parse_sched_args(&[]).unwrap(), (None, None));
+        assert_eq!(parse_sched_args(&["policy", "cfs"]).unwrap(), (Some(SchedPolicy::CompletelyFair), None));
+        assert_eq!(parse_sched_args(&["slice", "5", "policy", "rr"]).unwrap(), (Some(SchedPolicy::RoundRobin), Some(5)));
+        
+        // Rejected before any system call is made
+        let mut processor = CommandProcessor::new();
+        assert!(matches!(processor.process_command("sched policy lottery"), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(processor.process_command("sched slice 0"), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(processor.process_command("sched slice"), Err(ShellError::InvalidArguments(_))));
+    }
+
+    #[test]
+    fn test_dmesg() {
+        assert_eq!(parse_dmesg_args(&[]).unwrap(), (None, None));
+        assert_eq!(parse_dmesg_args(&["-l", "warn"]).unwrap(), (Some(LogLevel::Warn), None));
+        assert_eq!(parse_dmesg_args(&["-n", "DEBUG"]).unwrap(), (None, Some(LogLevel::Debug)));
+        assert!(matches!(parse_dmesg_args(&["-l", "loud"]), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(parse_dmesg_args(&["-l"]), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(parse_dmesg_args(&["-x", "info"]), Err(ShellError::InvalidArguments(_))));
+        
+        let log = "[    0.000] INFO  kosh_kernel: Kosh Kernel Starting...\n\
+                   [    1.250] WARN  syscall: Unknown system call: 99\n\
+                   [    1.300] DEBUG process: Process 2 forked\n\
+                   [    2.000] ERROR memory: Out of frames";
+        assert_eq!(filter_klog(log, Some(LogLevel::Warn)),
+                   "[    1.250] WARN  syscall: Unknown system call: 99\n[    2.000] ERROR memory: Out of frames");
+        assert_eq!(filter_klog(log, None).lines().count(), 4);
+        assert_eq!(filter_klog(log, Some(LogLevel::Off)), "");
+    }
+
+    #[test]
+    fn test_audit() {
+        use kosh_posix::audit::*;
+        
+        assert_eq!(parse_audit_args(&[]).unwrap(), None);
+        assert_eq!(parse_audit_args(&["-n", "5"]).unwrap(), Some(5));
+        assert!(matches!(parse_audit_args(&["-n", "many"]), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(parse_audit_args(&["-x"]), Err(ShellError::InvalidArguments(_))));
+        
+        let mut denied = AuditRecord { sequence: 17, time_ms: 12_345, kind: AUDIT_CAPABILITY_DENIED, process: 7, value: 1, ..Default::default() };
+        denied.detail[..17].copy_from_slice(b"Write system:klog");
+        let killed = AuditRecord { sequence: 18, time_ms: 13_000, kind: AUDIT_PROCESS_KILLED, process: 9, value: 9, ..Default::default() };
+        assert_eq!(format_audit(&[denied, killed]),
+                   "[   12.345] #17 pid 7 capability-denied: Write system:klog\n[   13.000] #18 pid 9 killed: signal 9");
+        assert_eq!(format_audit(&[]), "");
+    }
+
+    #[test]
+    fn test_trace() {
+        use kosh_posix::{TraceRecord, Tracepoint};
+        
+        let syscalls = Tracepoint::SyscallEnter.bit() | Tracepoint::SyscallExit.bit();
+        assert_eq!(parse_trace_args(&[]).unwrap(), TraceCommand::Status);
+        assert_eq!(parse_trace_args(&["on", "syscall_enter", "syscall_exit"]).unwrap(), TraceCommand::On(syscalls));
+        assert_eq!(parse_trace_args(&["off", "all"]).unwrap(), TraceCommand::Off(0x7E));
+        assert_eq!(parse_trace_args(&["dump"]).unwrap(), TraceCommand::Dump);
+        assert!(matches!(parse_trace_args(&["on"]), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(parse_trace_args(&["on", "syscalls"]), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(parse_trace_args(&["dump", "-x"]), Err(ShellError::InvalidArguments(_))));
+        
+        assert_eq!(format_trace_status(syscalls, 3), "Tracepoints on: syscall_enter syscall_exit\nRecords lost: 3");
+        assert_eq!(format_trace_status(0, 0), "Tracepoints on: none\nRecords lost: 0");
+        
+        let record = |time_ns, tracepoint: Tracepoint, process, arg0, arg1| TraceRecord {
+            time_ns, tracepoint: tracepoint as u16, cpu: 0, process, arg0, arg1,
+        };
+        let records = [
+            record(1_000_000_000, Tracepoint::SyscallEnter, 7, 3, 0x10),
+            record(1_000_012_500, Tracepoint::IpcSend, 7, 4, 120),
+            record(1_000_020_000, Tracepoint::SyscallExit, 7, 3, (-22i64) as u64),
+            record(1_000_030_000, Tracepoint::IpcReceive, 4, 7, 120),
+            record(1_000_040_000, Tracepoint::SchedSwitch, 4, u32::MAX as u64, 0),
+        ];
+        assert_eq!(format_trace(&records), "\
+[    1.000000] cpu0 pid 7     syscall_enter #3 arg 0x10
+[    1.000012] cpu0 pid 7     ipc_send      to 4 msg 120
+[    1.000020] cpu0 pid 7     syscall_exit  #3 = -22 (20.000 us)
+[    1.000030] cpu0 pid 4     ipc_receive   from 7 msg 120 (17.500 us)
+[    1.000040] cpu0 pid 4     sched_switch  from idle
+syscall #3: 1 timed, avg 20.000 us, max 20.000 us
+ipc: 1 timed, avg 17.500 us, max 17.500 us");
+        // An exit without its entry goes untimed
+        assert_eq!(format_trace(&records[2..3]), "[    1.000020] cpu0 pid 7     syscall_exit  #3 = -22");
+    }
+
+    #[test]
+    fn test_drivers_args() {
+        assert_eq!(parse_drivers_args(&[]).unwrap(), DriverRequest::List);
+        assert_eq!(parse_drivers_args(&["list"]).unwrap(), DriverRequest::List);
+        assert_eq!(parse_drivers_args(&["load", "/drivers/usb.ko"]).unwrap(),
+                   DriverRequest::Load { path: "/drivers/usb.ko".to_string() });
+        assert_eq!(parse_drivers_args(&["unload", "3"]).unwrap(), DriverRequest::Unload { driver_id: 3 });
+        
+        // Rejected before the driver manager is asked
+        let mut processor = CommandProcessor::new();
+        assert!(matches!(processor.process_command("drivers load usb.ko"), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(processor.process_command("drivers unload usb"), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(processor.process_command("drivers reload"), Err(ShellError::InvalidArguments(_))));
+    }
+
+    #[test]
+    fn test_mount_args() {
+        assert_eq!(parse_mount_args(&["-t", "ext4", "2", "/mnt"]).unwrap(), FileSystemRequest::Mount {
+            source: "2".to_string(),
+            target: "/mnt".to_string(),
+            fs_type: "ext4".to_string(),
+            flags: MountFlags::empty(),
+        });
+        assert_eq!(parse_mount_args(&["-r", "--bind", "/home", "/mnt"]).unwrap(), FileSystemRequest::Mount {
+            source: "/home".to_string(),
+            target: "/mnt".to_string(),
+            fs_type: "".to_string(),
+            flags: MountFlags::BIND | MountFlags::READ_ONLY,
+        });
+        assert_eq!(parse_umount_args(&["/mnt"]).unwrap(), FileSystemRequest::Unmount { target: "/mnt".to_string() });
+        
+        // Rejected before fs-service is asked
+        let mut processor = CommandProcessor::new();
+        assert!(matches!(processor.process_command("mount 2 /mnt"), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(processor.process_command("mount -t ext4 2 mnt"), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(processor.process_command("mount --bind -t ext4 /home /mnt"), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(processor.process_command("mount --bind home /mnt"), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(processor.process_command("umount"), Err(ShellError::InvalidArguments(_))));
+    }
+
+    #[test]
+    fn test_ps_from_proc_status() {
+        let shell = parse_proc_status("Name:\tshell\nState:\trunning\nPid:\t4\nPPid:\t1\nPriority:\tinteractive\nCpu:\t0\nCpuTime:\t1250 ms\nUserTime:\t1000 ms\nKernelTime:\t250 ms\nContextSwitches:\t31\nLastRun:\t9000 ms\nVmRSS:\t48 kB\nVmHeap:\t16 kB\nChildren:\t0\n").unwrap();
+        assert_eq!(shell.pid, 4);
+        assert_eq!(shell.ppid, 1);
+        assert_eq!(shell.name, "shell");
+        assert_eq!(shell.state, "running");
+        assert_eq!(shell.cpu_time, 1250);
+        assert_eq!(shell.kernel_time, 250);
+        assert_eq!(shell.context_switches, 31);
+        assert_eq!(shell.memory_usage, 48);
+        assert!(parse_proc_status("Name:\tinit\nPid:\t1\n").is_none());
+        
+        let init = parse_proc_status("Name:\tinit\nState:\tblocked\nPid:\t1\nPPid:\t0\nCpuTime:\t3 ms\n").unwrap();
+        let table = format_ps(&[shell, init]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "  PID  PPID STATE     TIME(ms)  SYS(ms)    CSW RSS(KiB) NAME");
+        assert_eq!(lines[1], "    1     0 blocked          3        0      0        0 init");
+        assert_eq!(lines[2], "    4     1 running       1250      250     31       48 shell");
+    }
+
+    #[test]
+    fn test_sched_info_format() {
+        let info = SchedInfo { algorithm: 1, time_slice_ms: 10, context_switches: 42, ..Default::default() };
+        let output = format_sched_info(&info);
+        assert!(output.contains("Policy:               priority"));
+        assert!(output.contains("Time slice:           10 ms"));
+        assert!(output.contains("Context switches:     42"));
+        
+        let info = SchedInfo { algorithm: 9, ..Default::default() };
+        assert!(format_sched_info(&info).contains("unknown"));
+    }
+
+    #[test]
+    fn test_powerctl() {
+        assert_eq!(parse_powerctl_args(&[]).unwrap(), None);
+        assert_eq!(parse_powerctl_args(&["governor", "powersave"]).unwrap(), Some(CpuGovernor::PowerSave));
+        assert!(matches!(parse_powerctl_args(&["governor", "turbo"]), Err(ShellError::InvalidArguments(_))));
+        assert!(matches!(parse_powerctl_args(&["governor"]), Err(ShellError::InvalidArguments(_))));
+
+        let mut processor = CommandProcessor::new();
+        assert!(matches!(processor.process_command("powerctl frequency 800"), Err(ShellError::InvalidArguments(_))));
+
+        let mut backend = [0; 16];
+        backend[..9].copy_from_slice(b"intel-hwp");
+        let info = CpuFreqInfo { governor: 2, current_mhz: 1600, min_mhz: 800, max_mhz: 3400, levels: 27, backend };
+        let output = format_cpufreq_info(&info);
+        assert!(output.contains("Governor:  ondemand"));
+        assert!(output.contains("Range:     800-3400 MHz"));
+        assert!(output.contains("Backend:   intel-hwp (27 levels)"));
+
+        let info = CpuFreqInfo { governor: 9, ..Default::default() };
+        let output = format_cpufreq_info(&info);
+        assert!(output.contains("unknown"));
+        assert!(output.contains("frequency tracked only"));
+
+        let supply = PowerSupplyInfo {
+            flags: power::SUPPLY_BATTERY_PRESENT | power::SUPPLY_LOW,
+            level_percent: 12,
+            minutes_remaining: 75,
+            ..Default::default()
+        };
+        let output = format_power_supply_info(&supply);
+        assert!(output.contains("Battery:   12%, low"));
+        assert!(output.contains("AC power:  offline"));
+        assert!(output.contains("Time left: 1:15 until empty"));
+
+        let supply = PowerSupplyInfo { flags: power::SUPPLY_AC_ONLINE, ..Default::default() };
+        assert_eq!(format_power_supply_info(&supply), "Battery:   none\nAC power:  online");
+    }
+
+    #[test]
+    fn test_thermal() {
+        assert_eq!(format_thermal_zones(&[]), "No temperature sensors");
+
+        let mut cpu = ThermalZoneInfo {
+            flags: power::ZONE_TEMPERATURE_VALID | power::ZONE_HAS_PASSIVE | power::ZONE_HAS_HOT,
+            tripped: 1,
+            temperature_mc: 91_250,
+            passive_mc: 90_000,
+            hot_mc: 95_000,
+            ..Default::default()
+        };
+        cpu.name[..3].copy_from_slice(b"cpu");
+        let mut zone = ThermalZoneInfo { flags: power::ZONE_HAS_CRITICAL, critical_mc: 103_800, ..Default::default() };
+        zone.name[..3].copy_from_slice(b"TZ0");
+        assert_eq!(
+            format_thermal_zones(&[cpu, zone]),
+            "cpu      91.2 C  (passive 90.0 C, hot 95.0 C)  past passive\n\
+             TZ0      unknown  (critical 103.8 C)"
+        );
+
+        let mut processor = CommandProcessor::new();
+        assert!(matches!(processor.process_command("thermal now"), Err(ShellError::InvalidArguments(_))));
+    }
+
     #[test]
     fn test_ls_flags_default() {
         let flags = LsFlags::default();
@@ -138,4 +493,206 @@ mod tests {
         assert!(!flags.human_readable);
         assert!(!flags.recursive);
     }
-}
\ No newline at end of file
+
+    /// Directory listing served to completion in place of fs-service
+    fn fake_listing(path: &str) -> Vec<(alloc::string::String, bool)> {
+        let entries: &[(&str, bool)] = match path {
+            "." => &[("notes.txt", false), ("src", true), (".profile", false)],
+            "/" => &[("bin", true), ("boot", true), ("etc", true)],
+            "/bin/" => &[("shell", false)],
+            _ => &[],
+        };
+        entries.iter().map(|&(name, is_dir)| (name.to_string(), is_dir)).collect()
+    }
+
+    #[test]
+    fn test_completion_candidates() {
+        let builtins = ["cat", "cd", "clear", "echo"];
+        let completion = complete("c", &builtins, fake_listing);
+        assert_eq!(completion.candidates, vec!["cat", "cd", "clear"]);
+        assert_eq!(completion.common_prefix(), "c");
+        assert_eq!(complete("ech", &builtins, fake_listing).common_prefix(), "echo");
+
+        // Later words are paths; hidden entries need a leading dot
+        let completion = complete("cat ", &builtins, fake_listing);
+        assert_eq!(completion.start, 4);
+        assert_eq!(completion.candidates, vec!["notes.txt", "src/"]);
+        assert_eq!(complete("cat .p", &builtins, fake_listing).candidates, vec![".profile"]);
+
+        let completion = complete("ls /b", &builtins, fake_listing);
+        assert_eq!(completion.candidates, vec!["/bin/", "/boot/"]);
+        assert_eq!(completion.common_prefix(), "/b");
+        assert_eq!(completion.names(), vec!["bin/", "boot/"]);
+
+        // A command word with a '/' names a program, as does one after a pipe
+        assert_eq!(complete("/bin/s", &builtins, fake_listing).candidates, vec!["/bin/shell"]);
+        assert_eq!(complete("echo hi | ca", &builtins, fake_listing).candidates, vec!["cat"]);
+        assert_eq!(complete("echo hi >n", &builtins, fake_listing).candidates, vec!["notes.txt"]);
+        assert!(complete("cat missing/", &builtins, fake_listing).candidates.is_empty());
+    }
+
+    #[test]
+    fn test_input_decode_keys() {
+        let mut input = InputHandler::new();
+        assert_eq!(input.decode(b'a'), Some(Key::Char('a')));
+        assert_eq!(input.decode(b'\t'), Some(Key::Special(SpecialKey::Tab)));
+        assert_eq!(input.decode(b'\r'), Some(Key::Special(SpecialKey::Enter)));
+        assert_eq!(input.decode(0x7f), Some(Key::Special(SpecialKey::Backspace)));
+        assert_eq!(input.decode(0x03), Some(Key::Special(SpecialKey::CtrlC)));
+
+        // Escape sequences span several bytes
+        assert_eq!(input.decode(0x1b), None);
+        assert_eq!(input.decode(b'['), None);
+        assert_eq!(input.decode(b'A'), Some(Key::Special(SpecialKey::ArrowUp)));
+        assert_eq!(input.decode(0x1b), None);
+        assert_eq!(input.decode(b'x'), None);
+        assert_eq!(input.decode(b'x'), Some(Key::Char('x')));
+    }
+
+    #[test]
+    fn test_input_line_editing_and_tab() {
+        let builtins = ["cat", "cd", "clear", "echo"];
+        let completer = |line: &str| complete(line, &builtins, fake_listing);
+        let mut input = InputHandler::new();
+        input.start_line("kosh> ");
+
+        for ch in "ecx".chars() {
+            input.handle_key(Key::Char(ch), completer);
+        }
+        let (action, echo) = input.handle_key(Key::Special(SpecialKey::Backspace), completer);
+        assert_eq!((action, echo.as_str()), (KeyAction::Continue, "\u{8} \u{8}"));
+
+        // A single match is finished off with a space
+        let (_, echo) = input.handle_key(Key::Special(SpecialKey::Tab), completer);
+        assert_eq!(echo, "ho ");
+        assert_eq!(input.buffer(), "echo ");
+
+        // Several matches: the first Tab does nothing, the second lists them
+        input.start_line("kosh> ");
+        input.handle_key(Key::Char('c'), completer);
+        assert_eq!(input.handle_key(Key::Special(SpecialKey::Tab), completer).1, "");
+        let (_, echo) = input.handle_key(Key::Special(SpecialKey::Tab), completer);
+        assert_eq!(echo, "\ncat  cd  clear\nkosh> c");
+
+        // Directories are completed up to their '/', ready for more
+        input.start_line("kosh> ");
+        for ch in "cat s".chars() {
+            input.handle_key(Key::Char(ch), completer);
+        }
+        input.handle_key(Key::Special(SpecialKey::Tab), completer);
+        assert_eq!(input.buffer(), "cat src/");
+        let (action, _) = input.handle_key(Key::Special(SpecialKey::Enter), completer);
+        assert_eq!(action, KeyAction::Complete);
+
+        let (action, echo) = input.handle_key(Key::Special(SpecialKey::CtrlC), completer);
+        assert_eq!((action, echo.as_str()), (KeyAction::Interrupt, "^C\n"));
+        assert_eq!(input.buffer(), "");
+        assert_eq!(input.handle_key(Key::Special(SpecialKey::CtrlD), completer).0, KeyAction::Exit);
+    }
+
+    #[test]
+    fn test_script_parse() {
+        let statements = script::parse("# setup\nX=1; echo $X\nif test $X = 1; then echo one\nelif test $X = 2\nthen echo two\nelse\n  echo other # note\nfi\nfor i in a b; do echo $i; done\n").unwrap();
+        assert_eq!(statements.len(), 4);
+        assert_eq!(statements[0], Statement::Command("X=1".to_string()));
+        assert_eq!(statements[1], Statement::Command("echo $X".to_string()));
+        match &statements[2] {
+            Statement::If { condition, then_branch, else_branch } => {
+                assert_eq!(condition, "test $X = 1");
+                assert_eq!(then_branch, &vec![Statement::Command("echo one".to_string())]);
+                assert!(matches!(&else_branch[..], [Statement::If { else_branch, .. }] if else_branch == &vec![Statement::Command("echo other".to_string())]));
+            }
+            other => panic!("expected if, got {:?}", other),
+        }
+        assert_eq!(statements[3], Statement::For {
+            variable: "i".to_string(),
+            words: "a b".to_string(),
+            body: vec![Statement::Command("echo $i".to_string())],
+        });
+
+        let error = script::parse("if true\necho hi\nfi").unwrap_err();
+        assert_eq!(error.user_message(), "Parse error: Script line 2: expected 'then'");
+        assert!(script::parse("for i in a; do echo $i").is_err());
+        assert!(script::parse("fi").is_err());
+        assert!(script::parse("for 1x in a; do; done").is_err());
+    }
+
+    #[test]
+    fn test_script_expand_and_assign() {
+        let lookup = |name: &str| match name {
+            "HOME" => Some("/home/user".to_string()),
+            "?" => Some("0".to_string()),
+            _ => None,
+        };
+        assert_eq!(script::expand("cd $HOME/docs", lookup), "cd /home/user/docs");
+        assert_eq!(script::expand("echo ${HOME}x $? $MISSING.", lookup), "echo /home/userx 0 .");
+        assert_eq!(script::expand("echo $ 5$ ${", lookup), "echo $ 5$ ${");
+
+        assert_eq!(script::parse_assignment("NAME=value"), Some(("NAME", "value")));
+        assert_eq!(script::parse_assignment("GREETING=\"hello world\""), Some(("GREETING", "hello world")));
+        assert_eq!(script::parse_assignment("EMPTY="), Some(("EMPTY", "")));
+        assert_eq!(script::parse_assignment("A=b c"), None);
+        assert_eq!(script::parse_assignment("1A=b"), None);
+        assert_eq!(script::parse_assignment("echo a=b"), None);
+    }
+
+    #[test]
+    fn test_script_conditions() {
+        let file = |path: &str| match path {
+            "/etc" => Some(true),
+            "/etc/rc.sh" => Some(false),
+            _ => None,
+        };
+        assert!(script::evaluate_test(&["-d", "/etc"], file).unwrap());
+        assert!(script::evaluate_test(&["-f", "/etc/rc.sh"], file).unwrap());
+        assert!(!script::evaluate_test(&["-e", "/missing"], file).unwrap());
+        assert!(script::evaluate_test(&["!", "-z", "text"], file).unwrap());
+        assert!(script::evaluate_test(&["a", "!=", "b"], file).unwrap());
+        assert!(script::evaluate_test(&["3", "-lt", "10"], file).unwrap());
+        assert!(!script::evaluate_test(&[], file).unwrap());
+        assert!(script::evaluate_test(&["x", "-lt", "1"], file).is_err());
+    }
+
+    #[test]
+    fn test_script_run() {
+        let mut processor = CommandProcessor::new();
+        let output = processor.run_script("\
+            COUNT=0
+            for name in alpha beta; do
+                if test $name = beta; then echo last $name; else echo first $name; fi
+            done
+            [ 1 -eq 2 ]
+            echo status $?
+            nosuch
+            echo status $?
+            exit 3
+            echo unreachable
+        ").unwrap();
+        assert_eq!(output, "first alpha\nlast beta\nstatus 1\nCommand not found: nosuch\nstatus 127");
+        assert_eq!(processor.last_status(), 3);
+        assert_eq!(processor.variable("name").as_deref(), Some("beta"));
+
+        // Variables set in a script stay set for the prompt
+        assert_eq!(processor.process_command("echo $COUNT").unwrap(), "0");
+        assert!(processor.process_command("[ 1 -eq 1").is_err());
+        assert_eq!(processor.last_status(), 2);
+    }
+
+    #[test]
+    fn test_export_env_unset() {
+        let mut processor = CommandProcessor::new();
+        processor.import_environment(&["HOME=/".to_string(), "bad entry".to_string()]);
+        processor.process_command("LOCAL=1").unwrap();
+        processor.process_command("export EDITOR=vi PAGER").unwrap();
+        processor.process_command("PAGER=more").unwrap();
+        // Unexported variables stay in the shell
+        assert_eq!(processor.process_command("env").unwrap(), "HOME=/\nEDITOR=vi\nPAGER=more");
+        assert_eq!(processor.process_command("export").unwrap(), "HOME=/\nEDITOR=vi\nPAGER=more");
+
+        processor.process_command("unset EDITOR LOCAL").unwrap();
+        assert_eq!(processor.variable("LOCAL"), None);
+        assert_eq!(processor.process_command("env").unwrap(), "HOME=/\nPAGER=more");
+        assert!(processor.process_command("export 1X").is_err());
+        assert!(processor.process_command("unset").is_err());
+    }
+}
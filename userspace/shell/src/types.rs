@@ -1,8 +1,9 @@
 #![allow(dead_code)]
 
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use alloc::boxed::Box;
+use alloc::format;
 use kosh_types::ProcessId;
 
 /// Core types for the enhanced shell
@@ -20,7 +21,7 @@ pub struct ParsedCommand {
 }
 
 /// Types of output redirection
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RedirectType {
     Overwrite(String),  // >
     Append(String),     // >>
@@ -86,6 +87,13 @@ pub enum SpecialKey {
     CtrlZ,
 }
 
+/// Key decoded from terminal input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Special(SpecialKey),
+}
+
 /// Key action results
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyAction {
@@ -136,6 +144,10 @@ pub struct ProcessInfo {
     pub name: String,
     pub state: String,
     pub cpu_time: u64,
+    /// Part of `cpu_time` spent in the kernel
+    pub kernel_time: u64,
+    pub context_switches: u64,
+    /// Resident memory in KiB
     pub memory_usage: usize,
 }
 
@@ -166,6 +178,8 @@ pub struct FileSystemInfo {
 #[derive(Debug, Clone)]
 pub struct Environment {
     pub variables: Vec<(String, String)>,
+    /// Names of the variables passed on to programs
+    pub exported: Vec<String>,
     pub working_directory: String,
     pub path: Vec<String>,
 }
@@ -174,6 +188,7 @@ impl Environment {
     pub fn new() -> Self {
         Self {
             variables: Vec::new(),
+            exported: Vec::new(),
             working_directory: String::from("/"),
             path: Vec::new(),
         }
@@ -195,5 +210,25 @@ impl Environment {
     
     pub fn unset_var(&mut self, name: &str) {
         self.variables.retain(|(key, _)| key != name);
+        self.exported.retain(|key| key != name);
+    }
+    
+    /// Pass `name` on to programs started from now on
+    pub fn export_var(&mut self, name: &str) {
+        if !self.is_exported(name) {
+            self.exported.push(name.to_string());
+        }
+    }
+    
+    pub fn is_exported(&self, name: &str) -> bool {
+        self.exported.iter().any(|key| key == name)
+    }
+    
+    /// Exported variables that are set, as `NAME=value` strings
+    pub fn exported_vars(&self) -> Vec<String> {
+        self.variables.iter()
+            .filter(|(key, _)| self.is_exported(key))
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect()
     }
 }
\ No newline at end of file
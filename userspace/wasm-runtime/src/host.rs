@@ -0,0 +1,548 @@
+//! Host imports and the app sandbox
+//!
+//! Apps import host functions from four modules:
+//!
+//! | Module         | Function   | Signature                                 | Capability |
+//! |----------------|------------|-------------------------------------------|------------|
+//! | `kosh`         | `exit`     | `(status: i32)`                           | none       |
+//! | `kosh`         | `log`      | `(ptr: i32, len: i32)`                    | none       |
+//! | `kosh_fs`      | `open`     | `(ptr: i32, len: i32, flags: i32) -> i32` | `fs`       |
+//! | `kosh_fs`      | `read`     | `(fd: i32, ptr: i32, len: i32) -> i32`    | `fs`       |
+//! | `kosh_fs`      | `write`    | `(fd: i32, ptr: i32, len: i32) -> i32`    | `fs`       |
+//! | `kosh_fs`      | `close`    | `(fd: i32) -> i32`                        | `fs`       |
+//! | `kosh_display` | `write`    | `(ptr: i32, len: i32) -> i32`             | `display`  |
+//! | `kosh_display` | `clear`    | `() -> i32`                               | `display`  |
+//! | `kosh_input`   | `read_key` | `() -> i32`                               | `input`    |
+//!
+//! Functions returning `i32` return a non-negative result or a negated
+//! errno. A module importing from a module whose capability was not
+//! granted fails to link, so it never starts.
+//!
+//! File descriptors handed to an app index its own table and never alias
+//! the runtime's descriptors. Paths must be absolute, may not contain `.`
+//! or `..`, and are resolved below the sandbox's file system root.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use kosh_posix::errno::{self, Errno};
+use kosh_types::OpenFlags;
+use crate::interp::{Trap, Value};
+use crate::module::{Module, ValType};
+
+/// Files one app may hold open at a time
+pub const MAX_APP_FILES: usize = 32;
+
+/// Longest resolved path handed to the host
+const PATH_MAX: usize = kosh_posix::raw::PATH_MAX;
+
+/// Rights an app can be granted when it is started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Files below the sandbox root
+    FileSystem,
+    /// Drawing to the app's display surface
+    Display,
+    /// Keyboard input
+    Input,
+}
+
+impl Capability {
+    /// Parse the name used when starting an app
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fs" => Some(Capability::FileSystem),
+            "display" => Some(Capability::Display),
+            "input" => Some(Capability::Input),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::FileSystem => "fs",
+            Capability::Display => "display",
+            Capability::Input => "input",
+        }
+    }
+}
+
+/// Services the runtime provides to apps
+///
+/// Implemented on top of Kosh system calls by the runtime service, and by
+/// mocks in tests. The sandbox has already checked capabilities, pointers
+/// and paths by the time these are called.
+pub trait Host {
+    /// Diagnostic output
+    fn log(&mut self, message: &[u8]);
+    /// Open a file, returning a host descriptor
+    fn open(&mut self, path: &str, flags: OpenFlags) -> Result<i32, Errno>;
+    fn read(&mut self, fd: i32, buffer: &mut [u8]) -> Result<usize, Errno>;
+    fn write(&mut self, fd: i32, data: &[u8]) -> Result<usize, Errno>;
+    fn close(&mut self, fd: i32) -> Result<(), Errno>;
+    /// Write text to the app's display surface
+    fn display_write(&mut self, text: &[u8]) -> Result<usize, Errno>;
+    fn display_clear(&mut self) -> Result<(), Errno>;
+    /// Next pending key, or `None` if there is none
+    fn read_key(&mut self) -> Result<Option<u8>, Errno>;
+}
+
+/// Host function an import was bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HostFunction {
+    Exit,
+    Log,
+    FsOpen,
+    FsRead,
+    FsWrite,
+    FsClose,
+    DisplayWrite,
+    DisplayClear,
+    InputReadKey,
+}
+
+struct HostImport {
+    module: &'static str,
+    name: &'static str,
+    params: &'static [ValType],
+    result: Option<ValType>,
+    capability: Option<Capability>,
+    function: HostFunction,
+}
+
+const I32: ValType = ValType::I32;
+
+const HOST_IMPORTS: &[HostImport] = &[
+    HostImport { module: "kosh", name: "exit", params: &[I32], result: None, capability: None, function: HostFunction::Exit },
+    HostImport { module: "kosh", name: "log", params: &[I32, I32], result: None, capability: None, function: HostFunction::Log },
+    HostImport { module: "kosh_fs", name: "open", params: &[I32, I32, I32], result: Some(I32), capability: Some(Capability::FileSystem), function: HostFunction::FsOpen },
+    HostImport { module: "kosh_fs", name: "read", params: &[I32, I32, I32], result: Some(I32), capability: Some(Capability::FileSystem), function: HostFunction::FsRead },
+    HostImport { module: "kosh_fs", name: "write", params: &[I32, I32, I32], result: Some(I32), capability: Some(Capability::FileSystem), function: HostFunction::FsWrite },
+    HostImport { module: "kosh_fs", name: "close", params: &[I32], result: Some(I32), capability: Some(Capability::FileSystem), function: HostFunction::FsClose },
+    HostImport { module: "kosh_display", name: "write", params: &[I32, I32], result: Some(I32), capability: Some(Capability::Display), function: HostFunction::DisplayWrite },
+    HostImport { module: "kosh_display", name: "clear", params: &[], result: Some(I32), capability: Some(Capability::Display), function: HostFunction::DisplayClear },
+    HostImport { module: "kosh_input", name: "read_key", params: &[], result: Some(I32), capability: Some(Capability::Input), function: HostFunction::InputReadKey },
+];
+
+/// Import resolution errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkError {
+    /// No host function of that name
+    UnknownImport { module: String, name: String },
+    /// The import needs a capability the app was not granted
+    CapabilityDenied(Capability),
+    /// The import's declared signature differs from the host function's
+    SignatureMismatch { module: String, name: String },
+}
+
+/// Bind every import of `module` to a host function the sandbox allows
+pub(crate) fn link(module: &Module, sandbox: &Sandbox) -> Result<Vec<HostFunction>, LinkError> {
+    let mut functions = Vec::with_capacity(module.imports.len());
+
+    for import in &module.imports {
+        let host_import = HOST_IMPORTS.iter()
+            .find(|candidate| candidate.module == import.module && candidate.name == import.name)
+            .ok_or_else(|| LinkError::UnknownImport { module: import.module.clone(), name: import.name.clone() })?;
+
+        if let Some(capability) = host_import.capability {
+            if !sandbox.has_capability(capability) {
+                return Err(LinkError::CapabilityDenied(capability));
+            }
+        }
+
+        let ty = &module.types[import.type_index as usize];
+        if ty.params != host_import.params || ty.results.first().copied() != host_import.result {
+            return Err(LinkError::SignatureMismatch { module: import.module.clone(), name: import.name.clone() });
+        }
+
+        functions.push(host_import.function);
+    }
+
+    Ok(functions)
+}
+
+/// Per-app rights and resources
+pub struct Sandbox {
+    capabilities: Vec<Capability>,
+    fs_root: String,
+    /// Host descriptors, indexed by the app's descriptor numbers
+    files: Vec<Option<i32>>,
+}
+
+impl Sandbox {
+    /// Sandbox with the given capabilities and the whole file system as root
+    pub fn new(capabilities: Vec<Capability>) -> Self {
+        Self {
+            capabilities,
+            fs_root: String::new(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Confine file access to the directory `root`
+    pub fn set_fs_root(&mut self, root: &str) {
+        self.fs_root = String::from(root.trim_end_matches('/'));
+    }
+
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Number of files the app holds open
+    pub fn open_files(&self) -> usize {
+        self.files.iter().filter(|file| file.is_some()).count()
+    }
+
+    /// Close every file the app left open
+    pub fn close_all(&mut self, host: &mut dyn Host) {
+        for fd in self.files.drain(..).flatten() {
+            let _ = host.close(fd);
+        }
+    }
+
+    /// Map an app path to a host path below the root
+    fn resolve_path(&self, path: &str) -> Result<String, Errno> {
+        if !path.starts_with('/') {
+            return Err(errno::EINVAL);
+        }
+        if path.split('/').any(|component| component == "." || component == "..") {
+            return Err(errno::EACCES);
+        }
+        if self.fs_root.len() + path.len() >= PATH_MAX {
+            return Err(errno::ENAMETOOLONG);
+        }
+
+        let mut resolved = self.fs_root.clone();
+        resolved.push_str(path);
+        Ok(resolved)
+    }
+
+    fn host_fd(&self, fd: i32) -> Result<i32, Errno> {
+        usize::try_from(fd).ok()
+            .and_then(|fd| self.files.get(fd).copied().flatten())
+            .ok_or(errno::EBADF)
+    }
+
+    fn install(&mut self, host_fd: i32) -> Option<i32> {
+        if let Some(fd) = self.files.iter().position(|file| file.is_none()) {
+            self.files[fd] = Some(host_fd);
+            return Some(fd as i32);
+        }
+        if self.files.len() >= MAX_APP_FILES {
+            return None;
+        }
+        self.files.push(Some(host_fd));
+        Some(self.files.len() as i32 - 1)
+    }
+
+    /// Run a host function with arguments taken from the app
+    pub(crate) fn call(&mut self, function: HostFunction, args: &[Value], memory: &mut [u8], host: &mut dyn Host) -> Result<Option<Value>, Trap> {
+        let arg = |index: usize| match args.get(index) {
+            Some(Value::I32(value)) => Ok(*value),
+            _ => Err(Trap::InvalidCode),
+        };
+
+        let result = match function {
+            HostFunction::Exit => return Err(Trap::Exit(arg(0)?)),
+            HostFunction::Log => {
+                if let Ok(message) = guest_slice(memory, arg(0)?, arg(1)?) {
+                    host.log(message);
+                }
+                return Ok(None);
+            }
+            HostFunction::FsOpen => {
+                let flags = OpenFlags::from_bits_truncate(arg(2)? as u32);
+                guest_str(memory, arg(0)?, arg(1)?)
+                    .and_then(|path| self.resolve_path(path))
+                    .and_then(|path| {
+                        if self.open_files() >= MAX_APP_FILES {
+                            return Err(errno::EMFILE);
+                        }
+                        let host_fd = host.open(&path, flags)?;
+                        self.install(host_fd).ok_or(errno::EMFILE)
+                    })
+            }
+            HostFunction::FsRead => {
+                let fd = self.host_fd(arg(0)?);
+                guest_slice_mut(memory, arg(1)?, arg(2)?)
+                    .and_then(|buffer| host.read(fd?, buffer))
+                    .map(|count| count as i32)
+            }
+            HostFunction::FsWrite => {
+                let fd = self.host_fd(arg(0)?);
+                guest_slice(memory, arg(1)?, arg(2)?)
+                    .and_then(|data| host.write(fd?, data))
+                    .map(|count| count as i32)
+            }
+            HostFunction::FsClose => {
+                let fd = arg(0)?;
+                self.host_fd(fd).and_then(|host_fd| {
+                    self.files[fd as usize] = None;
+                    while let Some(None) = self.files.last() {
+                        self.files.pop();
+                    }
+                    host.close(host_fd).map(|_| 0)
+                })
+            }
+            HostFunction::DisplayWrite => guest_slice(memory, arg(0)?, arg(1)?)
+                .and_then(|text| host.display_write(text))
+                .map(|count| count as i32),
+            HostFunction::DisplayClear => host.display_clear().map(|_| 0),
+            HostFunction::InputReadKey => host.read_key().and_then(|key| key.map(i32::from).ok_or(errno::EAGAIN)),
+        };
+
+        Ok(Some(Value::I32(result.unwrap_or_else(|errno| -errno.0))))
+    }
+}
+
+/// Bytes of app memory named by a pointer and length
+fn guest_slice(memory: &[u8], ptr: i32, len: i32) -> Result<&[u8], Errno> {
+    let start = ptr as u32 as usize;
+    let end = start.checked_add(len as u32 as usize).ok_or(errno::EFAULT)?;
+    memory.get(start..end).ok_or(errno::EFAULT)
+}
+
+fn guest_slice_mut(memory: &mut [u8], ptr: i32, len: i32) -> Result<&mut [u8], Errno> {
+    let start = ptr as u32 as usize;
+    let end = start.checked_add(len as u32 as usize).ok_or(errno::EFAULT)?;
+    memory.get_mut(start..end).ok_or(errno::EFAULT)
+}
+
+fn guest_str(memory: &[u8], ptr: i32, len: i32) -> Result<&str, Errno> {
+    core::str::from_utf8(guest_slice(memory, ptr, len)?).map_err(|_| errno::EINVAL)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use alloc::collections::BTreeMap;
+    use alloc::vec;
+    use crate::interp::{Instance, RuntimeConfig};
+    use crate::module::tests::{code_entry, header, section};
+
+    /// Host with an in-memory file system and display
+    pub(crate) struct MockHost {
+        pub files: BTreeMap<String, Vec<u8>>,
+        pub open: BTreeMap<i32, (String, usize)>,
+        pub next_fd: i32,
+        pub log: Vec<u8>,
+        pub display: Vec<u8>,
+        pub keys: Vec<u8>,
+    }
+
+    impl MockHost {
+        pub(crate) fn new() -> Self {
+            Self {
+                files: BTreeMap::new(),
+                open: BTreeMap::new(),
+                // Skip the runtime's own stdio descriptors
+                next_fd: 3,
+                log: Vec::new(),
+                display: Vec::new(),
+                keys: Vec::new(),
+            }
+        }
+    }
+
+    impl Host for MockHost {
+        fn log(&mut self, message: &[u8]) {
+            self.log.extend_from_slice(message);
+        }
+
+        fn open(&mut self, path: &str, flags: OpenFlags) -> Result<i32, Errno> {
+            if !self.files.contains_key(path) {
+                if !flags.contains(OpenFlags::CREATE) {
+                    return Err(errno::ENOENT);
+                }
+                self.files.insert(String::from(path), Vec::new());
+            }
+            let fd = self.next_fd;
+            self.next_fd += 1;
+            self.open.insert(fd, (String::from(path), 0));
+            Ok(fd)
+        }
+
+        fn read(&mut self, fd: i32, buffer: &mut [u8]) -> Result<usize, Errno> {
+            let (path, offset) = self.open.get_mut(&fd).ok_or(errno::EBADF)?;
+            let data = &self.files[path.as_str()][*offset..];
+            let count = data.len().min(buffer.len());
+            buffer[..count].copy_from_slice(&data[..count]);
+            *offset += count;
+            Ok(count)
+        }
+
+        fn write(&mut self, fd: i32, data: &[u8]) -> Result<usize, Errno> {
+            let (path, offset) = self.open.get_mut(&fd).ok_or(errno::EBADF)?;
+            self.files.get_mut(path.as_str()).unwrap().extend_from_slice(data);
+            *offset += data.len();
+            Ok(data.len())
+        }
+
+        fn close(&mut self, fd: i32) -> Result<(), Errno> {
+            self.open.remove(&fd).map(|_| ()).ok_or(errno::EBADF)
+        }
+
+        fn display_write(&mut self, text: &[u8]) -> Result<usize, Errno> {
+            self.display.extend_from_slice(text);
+            Ok(text.len())
+        }
+
+        fn display_clear(&mut self) -> Result<(), Errno> {
+            self.display.clear();
+            Ok(())
+        }
+
+        fn read_key(&mut self) -> Result<Option<u8>, Errno> {
+            Ok(if self.keys.is_empty() { None } else { Some(self.keys.remove(0)) })
+        }
+    }
+
+    /// App importing `kosh_fs.open`, `kosh_fs.write` and `kosh_fs.close`
+    /// that writes "hi" to the path stored at address 0, returning the
+    /// result of open
+    fn fs_app(path: &[u8]) -> Module {
+        let mut bytes = header();
+        // 0: (i32 i32 i32) -> i32, 1: (i32) -> i32, 2: () -> i32
+        section(&mut bytes, 1, &[
+            0x03,
+            0x60, 0x03, 0x7F, 0x7F, 0x7F, 0x01, 0x7F,
+            0x60, 0x01, 0x7F, 0x01, 0x7F,
+            0x60, 0x00, 0x01, 0x7F,
+        ]);
+        let mut imports = vec![0x03];
+        for (name, ty) in [(&b"open"[..], 0u8), (&b"write"[..], 0), (&b"close"[..], 1)] {
+            imports.push(0x07);
+            imports.extend_from_slice(b"kosh_fs");
+            imports.push(name.len() as u8);
+            imports.extend_from_slice(name);
+            imports.extend_from_slice(&[0x00, ty]);
+        }
+        section(&mut bytes, 2, &imports);
+        section(&mut bytes, 3, &[0x01, 0x02]);
+        section(&mut bytes, 5, &[0x01, 0x00, 0x01]);
+        section(&mut bytes, 7, &[0x01, 0x01, b'f', 0x00, 0x03]);
+
+        // (local i32)
+        // i32.const 0; i32.const len; i32.const CREATE|WRITE_ONLY; call open; local.tee 0
+        // i32.const 0; i32.lt_s; if; local.get 0; return; end
+        // local.get 0; i32.const 64; i32.const 2; call write; drop
+        // local.get 0; call close; drop; local.get 0
+        // Flags are 65, which takes two bytes as a signed LEB128
+        let flags = (OpenFlags::CREATE | OpenFlags::WRITE_ONLY).bits() as u8;
+        assert!((64..128).contains(&flags));
+        let body = [
+            0x01, 0x01, 0x7F,
+            0x41, 0x00, 0x41, path.len() as u8, 0x41, flags | 0x80, 0x00, 0x10, 0x00, 0x22, 0x00,
+            0x41, 0x00, 0x48, 0x04, 0x40, 0x20, 0x00, 0x0F, 0x0B,
+            0x20, 0x00, 0x41, 0xC0, 0x00, 0x41, 0x02, 0x10, 0x01, 0x1A,
+            0x20, 0x00, 0x10, 0x02, 0x1A, 0x20, 0x00, 0x0B,
+        ];
+        let mut code = vec![0x01];
+        code.extend(code_entry(&body));
+        section(&mut bytes, 10, &code);
+
+        let mut data = vec![0x02, 0x00, 0x41, 0x00, 0x0B, path.len() as u8];
+        data.extend_from_slice(path);
+        data.extend_from_slice(&[0x00, 0x41, 0xC0, 0x00, 0x0B, 0x02, b'h', b'i']);
+        section(&mut bytes, 11, &data);
+
+        Module::parse(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_capability_required_to_link() {
+        let result = Instance::instantiate(fs_app(b"/notes.txt"), Sandbox::new(vec![Capability::Display]), RuntimeConfig::default());
+        assert_eq!(result.err(), Some(crate::InstantiateError::Link(LinkError::CapabilityDenied(Capability::FileSystem))));
+
+        // Imports the host does not provide
+        let mut bytes = header();
+        section(&mut bytes, 1, &[0x01, 0x60, 0x00, 0x00]);
+        section(&mut bytes, 2, &[0x01, 0x04, b'w', b'a', b's', b'i', 0x04, b'e', b'x', b'i', b't', 0x00, 0x00]);
+        let module = Module::parse(&bytes).unwrap();
+        let result = Instance::instantiate(module, Sandbox::new(vec![]), RuntimeConfig::default());
+        assert!(matches!(result.err(), Some(crate::InstantiateError::Link(LinkError::UnknownImport { .. }))));
+
+        // kosh.exit declared with the wrong signature
+        let mut bytes = header();
+        section(&mut bytes, 1, &[0x01, 0x60, 0x00, 0x00]);
+        section(&mut bytes, 2, &[0x01, 0x04, b'k', b'o', b's', b'h', 0x04, b'e', b'x', b'i', b't', 0x00, 0x00]);
+        let module = Module::parse(&bytes).unwrap();
+        let result = Instance::instantiate(module, Sandbox::new(vec![]), RuntimeConfig::default());
+        assert!(matches!(result.err(), Some(crate::InstantiateError::Link(LinkError::SignatureMismatch { .. }))));
+    }
+
+    #[test]
+    fn test_file_access_confined_to_root() {
+        let mut sandbox = Sandbox::new(vec![Capability::FileSystem]);
+        sandbox.set_fs_root("/apps/notes/");
+        let mut instance = Instance::instantiate(fs_app(b"/notes.txt"), sandbox, RuntimeConfig::default()).unwrap();
+        let mut host = MockHost::new();
+
+        // The app sees its first descriptor as 0, not the host's 3
+        assert_eq!(instance.invoke("f", &[], &mut host), Ok(Some(Value::I32(0))));
+        assert_eq!(host.files["/apps/notes/notes.txt"], b"hi");
+        assert!(host.open.is_empty());
+
+        // Escaping the root is refused before the host sees the path
+        let mut sandbox = Sandbox::new(vec![Capability::FileSystem]);
+        sandbox.set_fs_root("/apps/notes");
+        let mut instance = Instance::instantiate(fs_app(b"/../secret"), sandbox, RuntimeConfig::default()).unwrap();
+        assert_eq!(instance.invoke("f", &[], &mut host), Ok(Some(Value::I32(-errno::EACCES.0))));
+
+        let sandbox = Sandbox::new(vec![Capability::FileSystem]);
+        let mut instance = Instance::instantiate(fs_app(b"relative"), sandbox, RuntimeConfig::default()).unwrap();
+        assert_eq!(instance.invoke("f", &[], &mut host), Ok(Some(Value::I32(-errno::EINVAL.0))));
+        assert_eq!(host.files.len(), 1);
+    }
+
+    #[test]
+    fn test_sandbox_descriptors() {
+        let mut sandbox = Sandbox::new(vec![Capability::FileSystem]);
+        let mut host = MockHost::new();
+        host.files.insert(String::from("/a"), b"abc".to_vec());
+        let mut memory = vec![0u8; 16];
+        memory[..2].copy_from_slice(b"/a");
+
+        let open = [Value::I32(0), Value::I32(2), Value::I32(0)];
+        assert_eq!(sandbox.call(HostFunction::FsOpen, &open, &mut memory, &mut host), Ok(Some(Value::I32(0))));
+        assert_eq!(sandbox.call(HostFunction::FsOpen, &open, &mut memory, &mut host), Ok(Some(Value::I32(1))));
+        assert_eq!(sandbox.open_files(), 2);
+
+        // Read into app memory; pointers outside it fail with EFAULT
+        let read = [Value::I32(1), Value::I32(8), Value::I32(8)];
+        assert_eq!(sandbox.call(HostFunction::FsRead, &read, &mut memory, &mut host), Ok(Some(Value::I32(3))));
+        assert_eq!(&memory[8..11], b"abc");
+        let read = [Value::I32(1), Value::I32(12), Value::I32(8)];
+        assert_eq!(sandbox.call(HostFunction::FsRead, &read, &mut memory, &mut host), Ok(Some(Value::I32(-errno::EFAULT.0))));
+
+        // Descriptors the app never opened, including the host's own
+        for fd in [2, 3, -1] {
+            let close = [Value::I32(fd)];
+            assert_eq!(sandbox.call(HostFunction::FsClose, &close, &mut memory, &mut host), Ok(Some(Value::I32(-errno::EBADF.0))));
+        }
+
+        assert_eq!(sandbox.call(HostFunction::FsClose, &[Value::I32(0)], &mut memory, &mut host), Ok(Some(Value::I32(0))));
+        assert_eq!(sandbox.open_files(), 1);
+        sandbox.close_all(&mut host);
+        assert_eq!(sandbox.open_files(), 0);
+        assert!(host.open.is_empty());
+    }
+
+    #[test]
+    fn test_display_and_input() {
+        let mut sandbox = Sandbox::new(vec![Capability::Display, Capability::Input]);
+        let mut host = MockHost::new();
+        let mut memory = b"hello".to_vec();
+
+        let write = [Value::I32(0), Value::I32(5)];
+        assert_eq!(sandbox.call(HostFunction::DisplayWrite, &write, &mut memory, &mut host), Ok(Some(Value::I32(5))));
+        assert_eq!(host.display, b"hello");
+
+        host.keys.push(b'q');
+        assert_eq!(sandbox.call(HostFunction::InputReadKey, &[], &mut memory, &mut host), Ok(Some(Value::I32(b'q' as i32))));
+        assert_eq!(sandbox.call(HostFunction::InputReadKey, &[], &mut memory, &mut host), Ok(Some(Value::I32(-errno::EAGAIN.0))));
+
+        assert_eq!(sandbox.call(HostFunction::Exit, &[Value::I32(3)], &mut memory, &mut host), Err(Trap::Exit(3)));
+        assert_eq!(Capability::from_name("display"), Some(Capability::Display));
+        assert_eq!(Capability::from_name("network"), None);
+    }
+}
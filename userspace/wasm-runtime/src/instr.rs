@@ -0,0 +1,355 @@
+//! Instruction decoding
+//!
+//! Function bodies are decoded once, up front, into a flat list of
+//! instructions with the targets of structured control flow resolved, so
+//! the interpreter never scans for a matching `end`.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use crate::module::{ParseError, Reader};
+
+/// Nesting depth of blocks within one function
+const MAX_BLOCK_DEPTH: usize = 1024;
+
+/// Integer comparison
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    LtS,
+    LtU,
+    GtS,
+    GtU,
+    LeS,
+    LeU,
+    GeS,
+    GeU,
+}
+
+/// Integer unary operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Clz,
+    Ctz,
+    Popcnt,
+}
+
+/// Integer binary operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    DivS,
+    DivU,
+    RemS,
+    RemU,
+    And,
+    Or,
+    Xor,
+    Shl,
+    ShrS,
+    ShrU,
+    Rotl,
+    Rotr,
+}
+
+/// Memory load: result type, access width and sign extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadOp {
+    I32,
+    I64,
+    I32S8,
+    I32U8,
+    I32S16,
+    I32U16,
+    I64S8,
+    I64U8,
+    I64S16,
+    I64U16,
+    I64S32,
+    I64U32,
+}
+
+/// Memory store: operand type and access width
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreOp {
+    I32,
+    I64,
+    I32As8,
+    I32As16,
+    I64As8,
+    I64As16,
+    I64As32,
+}
+
+/// Sign extension within a type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtendOp {
+    I32From8,
+    I32From16,
+    I64From8,
+    I64From16,
+    I64From32,
+}
+
+/// Decoded instruction
+///
+/// Block-like instructions carry the index of their matching `end` (and
+/// `else`), and the number of values they produce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instr {
+    Unreachable,
+    Nop,
+    Block { end: usize, arity: usize },
+    Loop { arity: usize },
+    If { else_at: Option<usize>, end: usize, arity: usize },
+    Else { end: usize },
+    End,
+    Br(u32),
+    BrIf(u32),
+    BrTable { targets: Box<[u32]>, default: u32 },
+    Return,
+    Call(u32),
+    Drop,
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    /// Load with a static offset
+    Load(LoadOp, u32),
+    /// Store with a static offset
+    Store(StoreOp, u32),
+    MemorySize,
+    MemoryGrow,
+    I32Const(i32),
+    I64Const(i64),
+    I32Eqz,
+    I64Eqz,
+    I32Cmp(CmpOp),
+    I64Cmp(CmpOp),
+    I32Unary(UnOp),
+    I64Unary(UnOp),
+    I32Binary(BinOp),
+    I64Binary(BinOp),
+    I32WrapI64,
+    I64ExtendI32S,
+    I64ExtendI32U,
+    Extend(ExtendOp),
+}
+
+const CMP_OPS: [CmpOp; 10] = [
+    CmpOp::Eq, CmpOp::Ne, CmpOp::LtS, CmpOp::LtU, CmpOp::GtS,
+    CmpOp::GtU, CmpOp::LeS, CmpOp::LeU, CmpOp::GeS, CmpOp::GeU,
+];
+
+const UN_OPS: [UnOp; 3] = [UnOp::Clz, UnOp::Ctz, UnOp::Popcnt];
+
+const BIN_OPS: [BinOp; 15] = [
+    BinOp::Add, BinOp::Sub, BinOp::Mul, BinOp::DivS, BinOp::DivU,
+    BinOp::RemS, BinOp::RemU, BinOp::And, BinOp::Or, BinOp::Xor,
+    BinOp::Shl, BinOp::ShrS, BinOp::ShrU, BinOp::Rotl, BinOp::Rotr,
+];
+
+const LOAD_OPS: [LoadOp; 12] = [
+    LoadOp::I32, LoadOp::I64, LoadOp::I32S8, LoadOp::I32U8, LoadOp::I32S16, LoadOp::I32U16,
+    LoadOp::I64S8, LoadOp::I64U8, LoadOp::I64S16, LoadOp::I64U16, LoadOp::I64S32, LoadOp::I64U32,
+];
+
+/// An open block whose `end` has not been seen yet
+struct OpenBlock {
+    start: usize,
+    else_at: Option<usize>,
+}
+
+/// Decode a function body up to and including its final `end`
+pub(crate) fn decode_body(reader: &mut Reader) -> Result<Vec<Instr>, ParseError> {
+    let mut body = Vec::new();
+    let mut open: Vec<OpenBlock> = Vec::new();
+
+    loop {
+        let at = body.len();
+        let opcode = reader.byte()?;
+        let instr = match opcode {
+            0x00 => Instr::Unreachable,
+            0x01 => Instr::Nop,
+            0x02..=0x04 => {
+                let arity = block_arity(reader)?;
+                if open.len() == MAX_BLOCK_DEPTH {
+                    return Err(ParseError::Unsupported("deeply nested blocks"));
+                }
+                open.push(OpenBlock { start: at, else_at: None });
+                match opcode {
+                    0x02 => Instr::Block { end: 0, arity },
+                    0x03 => Instr::Loop { arity },
+                    _ => Instr::If { else_at: None, end: 0, arity },
+                }
+            }
+            0x05 => {
+                let block = open.last_mut().ok_or(ParseError::InvalidOpcode(opcode))?;
+                if !matches!(body[block.start], Instr::If { .. }) || block.else_at.is_some() {
+                    return Err(ParseError::InvalidOpcode(opcode));
+                }
+                block.else_at = Some(at);
+                Instr::Else { end: 0 }
+            }
+            0x0B => {
+                match open.pop() {
+                    Some(block) => {
+                        match &mut body[block.start] {
+                            Instr::Block { end, .. } => *end = at,
+                            Instr::If { else_at, end, .. } => {
+                                *else_at = block.else_at;
+                                *end = at;
+                            }
+                            _ => {}
+                        }
+                        if let Some(else_index) = block.else_at {
+                            body[else_index] = Instr::Else { end: at };
+                        }
+                    }
+                    // End of the function body
+                    None => {
+                        body.push(Instr::End);
+                        if !reader.is_empty() {
+                            return Err(ParseError::Malformed("code after end of function"));
+                        }
+                        return Ok(body);
+                    }
+                }
+                Instr::End
+            }
+            0x0C => Instr::Br(label(reader, open.len())?),
+            0x0D => Instr::BrIf(label(reader, open.len())?),
+            0x0E => {
+                let targets = reader.vec(|r| label(r, open.len()))?;
+                let default = label(reader, open.len())?;
+                Instr::BrTable { targets: targets.into_boxed_slice(), default }
+            }
+            0x0F => Instr::Return,
+            0x10 => Instr::Call(reader.u32()?),
+            0x11 => return Err(ParseError::Unsupported("call_indirect")),
+            0x1A => Instr::Drop,
+            0x1B => Instr::Select,
+            0x1C => return Err(ParseError::Unsupported("typed select")),
+            0x20 => Instr::LocalGet(reader.u32()?),
+            0x21 => Instr::LocalSet(reader.u32()?),
+            0x22 => Instr::LocalTee(reader.u32()?),
+            0x23 => Instr::GlobalGet(reader.u32()?),
+            0x24 => Instr::GlobalSet(reader.u32()?),
+            0x28 | 0x29 => Instr::Load(LOAD_OPS[(opcode - 0x28) as usize], memarg(reader)?),
+            0x2C..=0x35 => Instr::Load(LOAD_OPS[(opcode - 0x2A) as usize], memarg(reader)?),
+            0x36 => Instr::Store(StoreOp::I32, memarg(reader)?),
+            0x37 => Instr::Store(StoreOp::I64, memarg(reader)?),
+            0x3A => Instr::Store(StoreOp::I32As8, memarg(reader)?),
+            0x3B => Instr::Store(StoreOp::I32As16, memarg(reader)?),
+            0x3C => Instr::Store(StoreOp::I64As8, memarg(reader)?),
+            0x3D => Instr::Store(StoreOp::I64As16, memarg(reader)?),
+            0x3E => Instr::Store(StoreOp::I64As32, memarg(reader)?),
+            0x3F | 0x40 => {
+                if reader.byte()? != 0 {
+                    return Err(ParseError::Unsupported("multiple memories"));
+                }
+                if opcode == 0x3F { Instr::MemorySize } else { Instr::MemoryGrow }
+            }
+            0x41 => Instr::I32Const(reader.i32()?),
+            0x42 => Instr::I64Const(reader.i64()?),
+            0x45 => Instr::I32Eqz,
+            0x46..=0x4F => Instr::I32Cmp(CMP_OPS[(opcode - 0x46) as usize]),
+            0x50 => Instr::I64Eqz,
+            0x51..=0x5A => Instr::I64Cmp(CMP_OPS[(opcode - 0x51) as usize]),
+            0x67..=0x69 => Instr::I32Unary(UN_OPS[(opcode - 0x67) as usize]),
+            0x6A..=0x78 => Instr::I32Binary(BIN_OPS[(opcode - 0x6A) as usize]),
+            0x79..=0x7B => Instr::I64Unary(UN_OPS[(opcode - 0x79) as usize]),
+            0x7C..=0x8A => Instr::I64Binary(BIN_OPS[(opcode - 0x7C) as usize]),
+            0xA7 => Instr::I32WrapI64,
+            0xAC => Instr::I64ExtendI32S,
+            0xAD => Instr::I64ExtendI32U,
+            0xC0 => Instr::Extend(ExtendOp::I32From8),
+            0xC1 => Instr::Extend(ExtendOp::I32From16),
+            0xC2 => Instr::Extend(ExtendOp::I64From8),
+            0xC3 => Instr::Extend(ExtendOp::I64From16),
+            0xC4 => Instr::Extend(ExtendOp::I64From32),
+            0x2A | 0x2B | 0x38 | 0x39 | 0x43 | 0x44 | 0x5B..=0x66 | 0x8B..=0xA6 | 0xA8..=0xAB | 0xAE..=0xBF => {
+                return Err(ParseError::Unsupported("floating point"));
+            }
+            0xFC => return Err(ParseError::Unsupported("bulk memory and saturating conversions")),
+            0xFD => return Err(ParseError::Unsupported("SIMD")),
+            _ => return Err(ParseError::InvalidOpcode(opcode)),
+        };
+        body.push(instr);
+    }
+}
+
+/// Number of results of a block type
+fn block_arity(reader: &mut Reader) -> Result<usize, ParseError> {
+    match reader.s33()? {
+        // 0x40: empty
+        -64 => Ok(0),
+        // 0x7F / 0x7E: i32 / i64
+        -1 | -2 => Ok(1),
+        -3 | -4 => Err(ParseError::Unsupported("floating point")),
+        index if index >= 0 => Err(ParseError::Unsupported("multi-value blocks")),
+        _ => Err(ParseError::Malformed("invalid block type")),
+    }
+}
+
+/// Branch depth, which must name an enclosing block or the function itself
+fn label(reader: &mut Reader, depth: usize) -> Result<u32, ParseError> {
+    let label = reader.u32()?;
+    if label as usize > depth {
+        return Err(ParseError::InvalidIndex);
+    }
+    Ok(label)
+}
+
+/// Alignment hint, which is ignored, and static offset
+fn memarg(reader: &mut Reader) -> Result<u32, ParseError> {
+    reader.u32()?;
+    reader.u32()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Result<Vec<Instr>, ParseError> {
+        decode_body(&mut Reader::new(bytes))
+    }
+
+    #[test]
+    fn test_block_targets_resolved() {
+        // block; i32.const 1; if; nop; else; nop; end; end; end
+        let body = decode(&[0x02, 0x40, 0x41, 0x01, 0x04, 0x40, 0x01, 0x05, 0x01, 0x0B, 0x0B, 0x0B]).unwrap();
+        assert_eq!(body[0], Instr::Block { end: 7, arity: 0 });
+        assert_eq!(body[2], Instr::If { else_at: Some(4), end: 6, arity: 0 });
+        assert_eq!(body[4], Instr::Else { end: 6 });
+        assert_eq!(body[8], Instr::End);
+        assert_eq!(body.len(), 9);
+    }
+
+    #[test]
+    fn test_decode_operators() {
+        let body = decode(&[0x28, 0x02, 0x08, 0x30, 0x00, 0x00, 0x3E, 0x02, 0x04, 0x7C, 0x51, 0xC4, 0x0B]).unwrap();
+        assert_eq!(body[0], Instr::Load(LoadOp::I32, 8));
+        assert_eq!(body[1], Instr::Load(LoadOp::I64S8, 0));
+        assert_eq!(body[2], Instr::Store(StoreOp::I64As32, 4));
+        assert_eq!(body[3], Instr::I64Binary(BinOp::Add));
+        assert_eq!(body[4], Instr::I64Cmp(CmpOp::Eq));
+        assert_eq!(body[5], Instr::Extend(ExtendOp::I64From32));
+    }
+
+    #[test]
+    fn test_decode_rejects_bad_bodies() {
+        // Branch past the function body
+        assert_eq!(decode(&[0x0C, 0x01, 0x0B]), Err(ParseError::InvalidIndex));
+        // else outside if
+        assert_eq!(decode(&[0x02, 0x40, 0x05, 0x0B, 0x0B]), Err(ParseError::InvalidOpcode(0x05)));
+        // Missing end
+        assert_eq!(decode(&[0x02, 0x40, 0x0B]), Err(ParseError::UnexpectedEnd));
+        assert_eq!(decode(&[0x43, 0, 0, 0, 0, 0x0B]), Err(ParseError::Unsupported("floating point")));
+        assert_eq!(decode(&[0x02, 0x00, 0x0B, 0x0B]), Err(ParseError::Unsupported("multi-value blocks")));
+    }
+}
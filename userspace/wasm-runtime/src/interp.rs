@@ -0,0 +1,807 @@
+//! Interpreter
+//!
+//! Runs decoded functions with an explicit frame stack, so deep guest
+//! recursion is bounded by `RuntimeConfig::max_call_depth` rather than by
+//! the service's own stack. Every instruction costs one unit of fuel; an
+//! app that runs out is stopped.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::host::{self, HostFunction, Host, LinkError, Sandbox};
+use crate::instr::{BinOp, CmpOp, ExtendOp, Instr, LoadOp, StoreOp, UnOp};
+use crate::module::{Module, ValType, PAGE_SIZE};
+
+/// Runtime value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+}
+
+impl Value {
+    pub fn ty(self) -> ValType {
+        match self {
+            Value::I32(_) => ValType::I32,
+            Value::I64(_) => ValType::I64,
+        }
+    }
+}
+
+/// Reasons execution stops abnormally
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trap {
+    /// `unreachable` was executed
+    Unreachable,
+    MemoryOutOfBounds,
+    DivideByZero,
+    IntegerOverflow,
+    /// Call depth exceeded `max_call_depth`
+    CallStackExhausted,
+    /// Value stack exceeded `max_stack_values`
+    ValueStackExhausted,
+    /// Instruction budget used up
+    OutOfFuel,
+    /// Operands of the wrong type or count; modules are not validated
+    /// ahead of time, so ill-typed code is caught as it runs
+    InvalidCode,
+    /// No exported function of that name
+    UndefinedExport,
+    /// Arguments do not match the export's signature
+    ArgumentMismatch,
+    /// The app called `kosh.exit` with this status
+    Exit(i32),
+}
+
+/// Resource limits for one instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    /// Instructions the instance may execute over its lifetime
+    pub fuel: u64,
+    /// Largest linear memory, in pages, whatever the module declares
+    pub max_memory_pages: u32,
+    pub max_call_depth: usize,
+    pub max_stack_values: usize,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            fuel: 50_000_000,
+            max_memory_pages: 16,
+            max_call_depth: 128,
+            max_stack_values: 16 * 1024,
+        }
+    }
+}
+
+/// Instantiation errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstantiateError {
+    Link(LinkError),
+    /// Initial memory is larger than the configured limit
+    MemoryLimitExceeded,
+    /// A data segment does not fit in the initial memory
+    DataOutOfBounds,
+}
+
+impl From<LinkError> for InstantiateError {
+    fn from(error: LinkError) -> Self {
+        InstantiateError::Link(error)
+    }
+}
+
+/// Control flow label
+#[derive(Debug, Clone, Copy)]
+struct Label {
+    /// Instruction to continue at when branched to
+    target: usize,
+    /// Values carried by a branch
+    arity: usize,
+    /// Value stack height on entry
+    height: usize,
+    /// Branches to a loop re-enter it and keep the label
+    is_loop: bool,
+}
+
+/// Activation of a defined function
+struct Frame {
+    function: usize,
+    pc: usize,
+    locals: Vec<Value>,
+    stack_base: usize,
+    label_base: usize,
+    arity: usize,
+}
+
+/// Instantiated module with its memory, globals and sandbox
+pub struct Instance {
+    module: Module,
+    imports: Vec<HostFunction>,
+    sandbox: Sandbox,
+    memory: Vec<u8>,
+    max_memory_pages: u32,
+    globals: Vec<Value>,
+    config: RuntimeConfig,
+    fuel: u64,
+}
+
+impl Instance {
+    /// Link a module against the sandbox's capabilities and set up its
+    /// memory and globals; the start function is not run yet
+    pub fn instantiate(module: Module, sandbox: Sandbox, config: RuntimeConfig) -> Result<Self, InstantiateError> {
+        let imports = host::link(&module, &sandbox)?;
+
+        let (pages, max_memory_pages) = match module.memory {
+            Some(limits) => {
+                if limits.min > config.max_memory_pages {
+                    return Err(InstantiateError::MemoryLimitExceeded);
+                }
+                (limits.min, limits.max.unwrap_or(u32::MAX).min(config.max_memory_pages))
+            }
+            None => (0, 0),
+        };
+
+        let mut memory = vec![0u8; pages as usize * PAGE_SIZE];
+        for segment in &module.data {
+            let start = segment.offset as usize;
+            let end = start.checked_add(segment.bytes.len()).ok_or(InstantiateError::DataOutOfBounds)?;
+            memory.get_mut(start..end)
+                .ok_or(InstantiateError::DataOutOfBounds)?
+                .copy_from_slice(&segment.bytes);
+        }
+
+        let globals = module.globals.iter().map(|global| global.init).collect();
+
+        Ok(Self {
+            module,
+            imports,
+            sandbox,
+            memory,
+            max_memory_pages,
+            globals,
+            config,
+            fuel: config.fuel,
+        })
+    }
+
+    /// Run the module's start function, if it has one
+    pub fn start(&mut self, host: &mut dyn Host) -> Result<(), Trap> {
+        match self.module.start {
+            Some(function) => self.execute(function, &[], host).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// Call an exported function
+    pub fn invoke(&mut self, name: &str, args: &[Value], host: &mut dyn Host) -> Result<Option<Value>, Trap> {
+        let function = self.module.exported_function(name).ok_or(Trap::UndefinedExport)?;
+        let ty = self.module.function_type(function).ok_or(Trap::UndefinedExport)?;
+        if ty.params.len() != args.len() || ty.params.iter().zip(args).any(|(param, arg)| *param != arg.ty()) {
+            return Err(Trap::ArgumentMismatch);
+        }
+        self.execute(function, args, host)
+    }
+
+    /// Linear memory contents
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Instructions left before the instance runs out of fuel
+    pub fn fuel_remaining(&self) -> u64 {
+        self.fuel
+    }
+
+    /// Close whatever the app left open
+    pub fn release(&mut self, host: &mut dyn Host) {
+        self.sandbox.close_all(host);
+    }
+
+    fn execute(&mut self, function: u32, args: &[Value], host: &mut dyn Host) -> Result<Option<Value>, Trap> {
+        let Instance { module, imports, sandbox, memory, max_memory_pages, globals, config, fuel } = self;
+        let mut stack: Vec<Value> = args.to_vec();
+        let mut labels: Vec<Label> = Vec::new();
+        let mut frames: Vec<Frame> = Vec::new();
+
+        if let Some(&import) = imports.get(function as usize) {
+            let result = sandbox.call(import, &stack, memory, host)?;
+            return Ok(result);
+        }
+        enter(module, function, &mut stack, &mut labels, &mut frames, config)?;
+
+        while let Some(frame) = frames.last_mut() {
+            let body = &module.functions[frame.function].body;
+
+            if frame.pc >= body.len() {
+                // Keep the results, drop everything else the function pushed
+                let results = stack.len().checked_sub(frame.arity)
+                    .filter(|&start| start >= frame.stack_base)
+                    .ok_or(Trap::InvalidCode)?;
+                stack.drain(frame.stack_base..results);
+                labels.truncate(frame.label_base);
+                frames.pop();
+                continue;
+            }
+
+            if *fuel == 0 {
+                return Err(Trap::OutOfFuel);
+            }
+            *fuel -= 1;
+
+            let instr = &body[frame.pc];
+            frame.pc += 1;
+
+            match instr {
+                Instr::Unreachable => return Err(Trap::Unreachable),
+                Instr::Nop => {}
+                Instr::Block { end, arity } => {
+                    labels.push(Label { target: end + 1, arity: *arity, height: stack.len(), is_loop: false });
+                }
+                Instr::Loop { .. } => {
+                    labels.push(Label { target: frame.pc, arity: 0, height: stack.len(), is_loop: true });
+                }
+                Instr::If { else_at, end, arity } => {
+                    let condition = pop_i32(&mut stack)?;
+                    labels.push(Label { target: end + 1, arity: *arity, height: stack.len(), is_loop: false });
+                    if condition == 0 {
+                        // Without an else branch, land on the end, which pops the label
+                        frame.pc = else_at.map_or(*end, |else_at| else_at + 1);
+                    }
+                }
+                // Reached the end of the taken branch of an if
+                Instr::Else { end } => frame.pc = *end,
+                Instr::End => {
+                    labels.pop();
+                }
+                Instr::Br(depth) => branch(frame, &mut labels, &mut stack, *depth)?,
+                Instr::BrIf(depth) => {
+                    if pop_i32(&mut stack)? != 0 {
+                        branch(frame, &mut labels, &mut stack, *depth)?;
+                    }
+                }
+                Instr::BrTable { targets, default } => {
+                    let index = pop_i32(&mut stack)? as u32 as usize;
+                    let depth = targets.get(index).copied().unwrap_or(*default);
+                    branch(frame, &mut labels, &mut stack, depth)?;
+                }
+                Instr::Return => frame.pc = body.len(),
+                Instr::Call(function) => {
+                    if let Some(&import) = imports.get(*function as usize) {
+                        let params = module.function_type(*function).ok_or(Trap::InvalidCode)?.params.len();
+                        let start = stack.len().checked_sub(params).ok_or(Trap::InvalidCode)?;
+                        if let Some(result) = sandbox.call(import, &stack[start..], memory, host)? {
+                            stack.truncate(start);
+                            stack.push(result);
+                        } else {
+                            stack.truncate(start);
+                        }
+                    } else {
+                        enter(module, *function, &mut stack, &mut labels, &mut frames, config)?;
+                    }
+                }
+                Instr::Drop => {
+                    pop(&mut stack)?;
+                }
+                Instr::Select => {
+                    let condition = pop_i32(&mut stack)?;
+                    let second = pop(&mut stack)?;
+                    let first = pop(&mut stack)?;
+                    if first.ty() != second.ty() {
+                        return Err(Trap::InvalidCode);
+                    }
+                    stack.push(if condition != 0 { first } else { second });
+                }
+                Instr::LocalGet(index) => {
+                    let value = *frame.locals.get(*index as usize).ok_or(Trap::InvalidCode)?;
+                    stack.push(value);
+                }
+                Instr::LocalSet(index) | Instr::LocalTee(index) => {
+                    let value = pop(&mut stack)?;
+                    let local = frame.locals.get_mut(*index as usize).ok_or(Trap::InvalidCode)?;
+                    if local.ty() != value.ty() {
+                        return Err(Trap::InvalidCode);
+                    }
+                    *local = value;
+                    if matches!(instr, Instr::LocalTee(_)) {
+                        stack.push(value);
+                    }
+                }
+                Instr::GlobalGet(index) => stack.push(globals[*index as usize]),
+                Instr::GlobalSet(index) => {
+                    let value = pop(&mut stack)?;
+                    let global = &module.globals[*index as usize];
+                    if !global.mutable || global.ty != value.ty() {
+                        return Err(Trap::InvalidCode);
+                    }
+                    globals[*index as usize] = value;
+                }
+                Instr::Load(op, offset) => {
+                    let address = effective_address(&mut stack, *offset)?;
+                    stack.push(load(memory, address, *op)?);
+                }
+                Instr::Store(op, offset) => {
+                    let value = pop(&mut stack)?;
+                    let address = effective_address(&mut stack, *offset)?;
+                    store(memory, address, *op, value)?;
+                }
+                Instr::MemorySize => stack.push(Value::I32((memory.len() / PAGE_SIZE) as i32)),
+                Instr::MemoryGrow => {
+                    let delta = pop_i32(&mut stack)? as u32;
+                    let pages = (memory.len() / PAGE_SIZE) as u32;
+                    match pages.checked_add(delta).filter(|&total| total <= *max_memory_pages) {
+                        Some(total) => {
+                            memory.resize(total as usize * PAGE_SIZE, 0);
+                            stack.push(Value::I32(pages as i32));
+                        }
+                        None => stack.push(Value::I32(-1)),
+                    }
+                }
+                Instr::I32Const(value) => stack.push(Value::I32(*value)),
+                Instr::I64Const(value) => stack.push(Value::I64(*value)),
+                Instr::I32Eqz => {
+                    let value = pop_i32(&mut stack)?;
+                    stack.push(Value::I32((value == 0) as i32));
+                }
+                Instr::I64Eqz => {
+                    let value = pop_i64(&mut stack)?;
+                    stack.push(Value::I32((value == 0) as i32));
+                }
+                Instr::I32Cmp(op) => {
+                    let b = pop_i32(&mut stack)?;
+                    let a = pop_i32(&mut stack)?;
+                    stack.push(Value::I32(compare(*op, a as i64, b as i64, a as u32 as u64, b as u32 as u64) as i32));
+                }
+                Instr::I64Cmp(op) => {
+                    let b = pop_i64(&mut stack)?;
+                    let a = pop_i64(&mut stack)?;
+                    stack.push(Value::I32(compare(*op, a, b, a as u64, b as u64) as i32));
+                }
+                Instr::I32Unary(op) => {
+                    let value = pop_i32(&mut stack)?;
+                    let result = match op {
+                        UnOp::Clz => value.leading_zeros(),
+                        UnOp::Ctz => value.trailing_zeros(),
+                        UnOp::Popcnt => value.count_ones(),
+                    };
+                    stack.push(Value::I32(result as i32));
+                }
+                Instr::I64Unary(op) => {
+                    let value = pop_i64(&mut stack)?;
+                    let result = match op {
+                        UnOp::Clz => value.leading_zeros(),
+                        UnOp::Ctz => value.trailing_zeros(),
+                        UnOp::Popcnt => value.count_ones(),
+                    };
+                    stack.push(Value::I64(result as i64));
+                }
+                Instr::I32Binary(op) => {
+                    let b = pop_i32(&mut stack)?;
+                    let a = pop_i32(&mut stack)?;
+                    stack.push(Value::I32(binary_i32(*op, a, b)?));
+                }
+                Instr::I64Binary(op) => {
+                    let b = pop_i64(&mut stack)?;
+                    let a = pop_i64(&mut stack)?;
+                    stack.push(Value::I64(binary_i64(*op, a, b)?));
+                }
+                Instr::I32WrapI64 => {
+                    let value = pop_i64(&mut stack)?;
+                    stack.push(Value::I32(value as i32));
+                }
+                Instr::I64ExtendI32S => {
+                    let value = pop_i32(&mut stack)?;
+                    stack.push(Value::I64(value as i64));
+                }
+                Instr::I64ExtendI32U => {
+                    let value = pop_i32(&mut stack)?;
+                    stack.push(Value::I64(value as u32 as i64));
+                }
+                Instr::Extend(op) => {
+                    let value = pop(&mut stack)?;
+                    let extended = match (op, value) {
+                        (ExtendOp::I32From8, Value::I32(v)) => Value::I32(v as i8 as i32),
+                        (ExtendOp::I32From16, Value::I32(v)) => Value::I32(v as i16 as i32),
+                        (ExtendOp::I64From8, Value::I64(v)) => Value::I64(v as i8 as i64),
+                        (ExtendOp::I64From16, Value::I64(v)) => Value::I64(v as i16 as i64),
+                        (ExtendOp::I64From32, Value::I64(v)) => Value::I64(v as i32 as i64),
+                        _ => return Err(Trap::InvalidCode),
+                    };
+                    stack.push(extended);
+                }
+            }
+
+            if stack.len() > config.max_stack_values {
+                return Err(Trap::ValueStackExhausted);
+            }
+        }
+
+        let ty = module.function_type(function).ok_or(Trap::InvalidCode)?;
+        match ty.results.first() {
+            Some(&result_type) => {
+                let result = pop(&mut stack)?;
+                if result.ty() != result_type {
+                    return Err(Trap::InvalidCode);
+                }
+                Ok(Some(result))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Push a frame for a defined function, taking its arguments off the stack
+fn enter(
+    module: &Module,
+    function: u32,
+    stack: &mut Vec<Value>,
+    labels: &mut Vec<Label>,
+    frames: &mut Vec<Frame>,
+    config: &RuntimeConfig,
+) -> Result<(), Trap> {
+    if frames.len() >= config.max_call_depth {
+        return Err(Trap::CallStackExhausted);
+    }
+
+    let index = function as usize - module.imports.len();
+    let definition = &module.functions[index];
+    let ty = &module.types[definition.type_index as usize];
+
+    let start = stack.len().checked_sub(ty.params.len()).ok_or(Trap::InvalidCode)?;
+    if stack[start..].iter().zip(&ty.params).any(|(value, param)| value.ty() != *param) {
+        return Err(Trap::InvalidCode);
+    }
+    let mut locals: Vec<Value> = stack.drain(start..).collect();
+    locals.extend(definition.locals.iter().map(|local| local.zero()));
+
+    let label_base = labels.len();
+    labels.push(Label { target: definition.body.len(), arity: ty.results.len(), height: stack.len(), is_loop: false });
+    frames.push(Frame {
+        function: index,
+        pc: 0,
+        locals,
+        stack_base: stack.len(),
+        label_base,
+        arity: ty.results.len(),
+    });
+    Ok(())
+}
+
+/// Unwind to the label `depth` levels out, keeping the values it carries
+fn branch(frame: &mut Frame, labels: &mut Vec<Label>, stack: &mut Vec<Value>, depth: u32) -> Result<(), Trap> {
+    let index = labels.len().checked_sub(1 + depth as usize)
+        .filter(|&index| index >= frame.label_base)
+        .ok_or(Trap::InvalidCode)?;
+    let label = labels[index];
+
+    let carried = stack.len().checked_sub(label.arity)
+        .filter(|&start| start >= label.height)
+        .ok_or(Trap::InvalidCode)?;
+    stack.drain(label.height..carried);
+
+    labels.truncate(if label.is_loop { index + 1 } else { index });
+    frame.pc = label.target;
+    Ok(())
+}
+
+fn pop(stack: &mut Vec<Value>) -> Result<Value, Trap> {
+    stack.pop().ok_or(Trap::InvalidCode)
+}
+
+fn pop_i32(stack: &mut Vec<Value>) -> Result<i32, Trap> {
+    match pop(stack)? {
+        Value::I32(value) => Ok(value),
+        Value::I64(_) => Err(Trap::InvalidCode),
+    }
+}
+
+fn pop_i64(stack: &mut Vec<Value>) -> Result<i64, Trap> {
+    match pop(stack)? {
+        Value::I64(value) => Ok(value),
+        Value::I32(_) => Err(Trap::InvalidCode),
+    }
+}
+
+/// Address operand plus static offset, computed without wrapping
+fn effective_address(stack: &mut Vec<Value>, offset: u32) -> Result<usize, Trap> {
+    let base = pop_i32(stack)? as u32;
+    Ok(base as usize + offset as usize)
+}
+
+fn access(memory: &[u8], address: usize, width: usize) -> Result<[u8; 8], Trap> {
+    let bytes = address.checked_add(width)
+        .and_then(|end| memory.get(address..end))
+        .ok_or(Trap::MemoryOutOfBounds)?;
+    let mut buffer = [0u8; 8];
+    buffer[..width].copy_from_slice(bytes);
+    Ok(buffer)
+}
+
+fn load(memory: &[u8], address: usize, op: LoadOp) -> Result<Value, Trap> {
+    let width = match op {
+        LoadOp::I32S8 | LoadOp::I32U8 | LoadOp::I64S8 | LoadOp::I64U8 => 1,
+        LoadOp::I32S16 | LoadOp::I32U16 | LoadOp::I64S16 | LoadOp::I64U16 => 2,
+        LoadOp::I32 | LoadOp::I64S32 | LoadOp::I64U32 => 4,
+        LoadOp::I64 => 8,
+    };
+    let raw = u64::from_le_bytes(access(memory, address, width)?);
+
+    Ok(match op {
+        LoadOp::I32 | LoadOp::I32U8 | LoadOp::I32U16 => Value::I32(raw as u32 as i32),
+        LoadOp::I32S8 => Value::I32(raw as i8 as i32),
+        LoadOp::I32S16 => Value::I32(raw as i16 as i32),
+        LoadOp::I64 | LoadOp::I64U8 | LoadOp::I64U16 | LoadOp::I64U32 => Value::I64(raw as i64),
+        LoadOp::I64S8 => Value::I64(raw as i8 as i64),
+        LoadOp::I64S16 => Value::I64(raw as i16 as i64),
+        LoadOp::I64S32 => Value::I64(raw as i32 as i64),
+    })
+}
+
+fn store(memory: &mut [u8], address: usize, op: StoreOp, value: Value) -> Result<(), Trap> {
+    let (raw, width) = match (op, value) {
+        (StoreOp::I32, Value::I32(v)) => (v as u32 as u64, 4),
+        (StoreOp::I32As8, Value::I32(v)) => (v as u32 as u64, 1),
+        (StoreOp::I32As16, Value::I32(v)) => (v as u32 as u64, 2),
+        (StoreOp::I64, Value::I64(v)) => (v as u64, 8),
+        (StoreOp::I64As8, Value::I64(v)) => (v as u64, 1),
+        (StoreOp::I64As16, Value::I64(v)) => (v as u64, 2),
+        (StoreOp::I64As32, Value::I64(v)) => (v as u64, 4),
+        _ => return Err(Trap::InvalidCode),
+    };
+    let bytes = address.checked_add(width)
+        .and_then(|end| memory.get_mut(address..end))
+        .ok_or(Trap::MemoryOutOfBounds)?;
+    bytes.copy_from_slice(&raw.to_le_bytes()[..width]);
+    Ok(())
+}
+
+/// Compare two operands, given both sign- and zero-extended
+fn compare(op: CmpOp, a: i64, b: i64, ua: u64, ub: u64) -> bool {
+    match op {
+        CmpOp::Eq => a == b,
+        CmpOp::Ne => a != b,
+        CmpOp::LtS => a < b,
+        CmpOp::LtU => ua < ub,
+        CmpOp::GtS => a > b,
+        CmpOp::GtU => ua > ub,
+        CmpOp::LeS => a <= b,
+        CmpOp::LeU => ua <= ub,
+        CmpOp::GeS => a >= b,
+        CmpOp::GeU => ua >= ub,
+    }
+}
+
+fn binary_i32(op: BinOp, a: i32, b: i32) -> Result<i32, Trap> {
+    Ok(match op {
+        BinOp::Add => a.wrapping_add(b),
+        BinOp::Sub => a.wrapping_sub(b),
+        BinOp::Mul => a.wrapping_mul(b),
+        BinOp::DivS => {
+            if b == 0 {
+                return Err(Trap::DivideByZero);
+            }
+            a.checked_div(b).ok_or(Trap::IntegerOverflow)?
+        }
+        BinOp::DivU => (a as u32).checked_div(b as u32).ok_or(Trap::DivideByZero)? as i32,
+        BinOp::RemS => {
+            if b == 0 {
+                return Err(Trap::DivideByZero);
+            }
+            a.wrapping_rem(b)
+        }
+        BinOp::RemU => (a as u32).checked_rem(b as u32).ok_or(Trap::DivideByZero)? as i32,
+        BinOp::And => a & b,
+        BinOp::Or => a | b,
+        BinOp::Xor => a ^ b,
+        BinOp::Shl => a.wrapping_shl(b as u32),
+        BinOp::ShrS => a.wrapping_shr(b as u32),
+        BinOp::ShrU => (a as u32).wrapping_shr(b as u32) as i32,
+        BinOp::Rotl => a.rotate_left(b as u32 % 32),
+        BinOp::Rotr => a.rotate_right(b as u32 % 32),
+    })
+}
+
+fn binary_i64(op: BinOp, a: i64, b: i64) -> Result<i64, Trap> {
+    Ok(match op {
+        BinOp::Add => a.wrapping_add(b),
+        BinOp::Sub => a.wrapping_sub(b),
+        BinOp::Mul => a.wrapping_mul(b),
+        BinOp::DivS => {
+            if b == 0 {
+                return Err(Trap::DivideByZero);
+            }
+            a.checked_div(b).ok_or(Trap::IntegerOverflow)?
+        }
+        BinOp::DivU => (a as u64).checked_div(b as u64).ok_or(Trap::DivideByZero)? as i64,
+        BinOp::RemS => {
+            if b == 0 {
+                return Err(Trap::DivideByZero);
+            }
+            a.wrapping_rem(b)
+        }
+        BinOp::RemU => (a as u64).checked_rem(b as u64).ok_or(Trap::DivideByZero)? as i64,
+        BinOp::And => a & b,
+        BinOp::Or => a | b,
+        BinOp::Xor => a ^ b,
+        BinOp::Shl => a.wrapping_shl(b as u32),
+        BinOp::ShrS => a.wrapping_shr(b as u32),
+        BinOp::ShrU => (a as u64).wrapping_shr(b as u32) as i64,
+        BinOp::Rotl => a.rotate_left((b as u64 % 64) as u32),
+        BinOp::Rotr => a.rotate_right((b as u64 % 64) as u32),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use crate::host::tests::MockHost;
+    use crate::module::tests::{code_entry, header, leb, section};
+
+    /// Module exporting one function `f` of the given signature and body
+    fn single_function(params: &[u8], results: &[u8], body: &[u8], memory: bool) -> Module {
+        let mut bytes = header();
+        let mut ty = vec![0x01, 0x60, params.len() as u8];
+        ty.extend_from_slice(params);
+        ty.push(results.len() as u8);
+        ty.extend_from_slice(results);
+        section(&mut bytes, 1, &ty);
+        section(&mut bytes, 3, &[0x01, 0x00]);
+        if memory {
+            section(&mut bytes, 5, &[0x01, 0x00, 0x01]);
+        }
+        section(&mut bytes, 7, &[0x01, 0x01, b'f', 0x00, 0x00]);
+        let mut code = vec![0x01];
+        code.extend(code_entry(body));
+        section(&mut bytes, 10, &code);
+        Module::parse(&bytes).unwrap()
+    }
+
+    fn run(module: Module, args: &[Value]) -> Result<Option<Value>, Trap> {
+        let mut instance = Instance::instantiate(module, Sandbox::new(vec![]), RuntimeConfig::default()).unwrap();
+        instance.invoke("f", args, &mut MockHost::new())
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        // (param i32 i32) (result i32): local.get 0; local.get 1; i32.add
+        let add = single_function(&[0x7F, 0x7F], &[0x7F], &[0x00, 0x20, 0x00, 0x20, 0x01, 0x6A, 0x0B], false);
+        assert_eq!(run(add.clone(), &[Value::I32(2), Value::I32(40)]), Ok(Some(Value::I32(42))));
+        assert_eq!(run(add.clone(), &[Value::I32(i32::MAX), Value::I32(1)]), Ok(Some(Value::I32(i32::MIN))));
+        assert_eq!(run(add, &[Value::I64(1), Value::I32(1)]), Err(Trap::ArgumentMismatch));
+
+        // i32.div_s
+        let div = single_function(&[0x7F, 0x7F], &[0x7F], &[0x00, 0x20, 0x00, 0x20, 0x01, 0x6D, 0x0B], false);
+        assert_eq!(run(div.clone(), &[Value::I32(-7), Value::I32(2)]), Ok(Some(Value::I32(-3))));
+        assert_eq!(run(div.clone(), &[Value::I32(1), Value::I32(0)]), Err(Trap::DivideByZero));
+        assert_eq!(run(div, &[Value::I32(i32::MIN), Value::I32(-1)]), Err(Trap::IntegerOverflow));
+
+        // i64.extend_i32_u of -1
+        let extend = single_function(&[0x7F], &[0x7E], &[0x00, 0x20, 0x00, 0xAD, 0x0B], false);
+        assert_eq!(run(extend, &[Value::I32(-1)]), Ok(Some(Value::I64(0xFFFF_FFFF))));
+    }
+
+    #[test]
+    fn test_loop_and_branches() {
+        // Sum 1..=n:
+        // (local i32) block loop
+        //   local.get 0; i32.eqz; br_if 1
+        //   local.get 1; local.get 0; i32.add; local.set 1
+        //   local.get 0; i32.const 1; i32.sub; local.set 0
+        //   br 0
+        // end end local.get 1
+        let body = [
+            0x01, 0x01, 0x7F,
+            0x02, 0x40, 0x03, 0x40,
+            0x20, 0x00, 0x45, 0x0D, 0x01,
+            0x20, 0x01, 0x20, 0x00, 0x6A, 0x21, 0x01,
+            0x20, 0x00, 0x41, 0x01, 0x6B, 0x21, 0x00,
+            0x0C, 0x00,
+            0x0B, 0x0B, 0x20, 0x01, 0x0B,
+        ];
+        let sum = single_function(&[0x7F], &[0x7F], &body, false);
+        assert_eq!(run(sum.clone(), &[Value::I32(100)]), Ok(Some(Value::I32(5050))));
+        assert_eq!(run(sum, &[Value::I32(0)]), Ok(Some(Value::I32(0))));
+
+        // if/else with a result: local.get 0; if (result i32) 10 else 20 end
+        let choose = single_function(&[0x7F], &[0x7F], &[0x00, 0x20, 0x00, 0x04, 0x7F, 0x41, 0x0A, 0x05, 0x41, 0x14, 0x0B, 0x0B], false);
+        assert_eq!(run(choose.clone(), &[Value::I32(1)]), Ok(Some(Value::I32(10))));
+        assert_eq!(run(choose, &[Value::I32(0)]), Ok(Some(Value::I32(20))));
+
+        // br_table selecting one of three blocks, each leaving a constant
+        // block block block local.get 0; br_table 0 1 2; end
+        // i32.const 100 return end i32.const 101 return end i32.const 102
+        let table = single_function(&[0x7F], &[0x7F], &[
+            0x00, 0x02, 0x40, 0x02, 0x40, 0x02, 0x40,
+            0x20, 0x00, 0x0E, 0x02, 0x00, 0x01, 0x02, 0x0B,
+            0x41, 0xE4, 0x00, 0x0F, 0x0B,
+            0x41, 0xE5, 0x00, 0x0F, 0x0B,
+            0x41, 0xE6, 0x00, 0x0B,
+        ], false);
+        assert_eq!(run(table.clone(), &[Value::I32(0)]), Ok(Some(Value::I32(100))));
+        assert_eq!(run(table.clone(), &[Value::I32(1)]), Ok(Some(Value::I32(101))));
+        assert_eq!(run(table, &[Value::I32(7)]), Ok(Some(Value::I32(102))));
+    }
+
+    #[test]
+    fn test_memory_access() {
+        // i32.const 8; local.get 0; i64.store offset=4; i32.const 12; i32.load8_s
+        let body = [0x00, 0x41, 0x08, 0x20, 0x00, 0x37, 0x03, 0x04, 0x41, 0x0C, 0x2C, 0x00, 0x00, 0x0B];
+        let module = single_function(&[0x7E], &[0x7F], &body, true);
+        let mut instance = Instance::instantiate(module, Sandbox::new(vec![]), RuntimeConfig::default()).unwrap();
+        let result = instance.invoke("f", &[Value::I64(0x0102_0304_0506_07F8)], &mut MockHost::new());
+        assert_eq!(result, Ok(Some(Value::I32(-8))));
+        assert_eq!(&instance.memory()[12..20], &[0xF8, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]);
+
+        // Loads past the end of memory trap
+        let body = [0x00, 0x20, 0x00, 0x28, 0x02, 0x00, 0x0B];
+        let module = single_function(&[0x7F], &[0x7F], &body, true);
+        assert_eq!(run(module.clone(), &[Value::I32(PAGE_SIZE as i32 - 4)]), Ok(Some(Value::I32(0))));
+        assert_eq!(run(module.clone(), &[Value::I32(PAGE_SIZE as i32 - 3)]), Err(Trap::MemoryOutOfBounds));
+        assert_eq!(run(module, &[Value::I32(-1)]), Err(Trap::MemoryOutOfBounds));
+    }
+
+    #[test]
+    fn test_memory_grow_limited() {
+        // local.get 0; memory.grow
+        let body = [0x00, 0x20, 0x00, 0x40, 0x00, 0x0B];
+        let module = single_function(&[0x7F], &[0x7F], &body, true);
+        let config = RuntimeConfig { max_memory_pages: 4, ..RuntimeConfig::default() };
+        let mut instance = Instance::instantiate(module, Sandbox::new(vec![]), config).unwrap();
+        let mut host = MockHost::new();
+
+        assert_eq!(instance.invoke("f", &[Value::I32(2)], &mut host), Ok(Some(Value::I32(1))));
+        assert_eq!(instance.memory().len(), 3 * PAGE_SIZE);
+        assert_eq!(instance.invoke("f", &[Value::I32(2)], &mut host), Ok(Some(Value::I32(-1))));
+        assert_eq!(instance.invoke("f", &[Value::I32(1)], &mut host), Ok(Some(Value::I32(3))));
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        // Infinite loop: loop br 0 end
+        let spin = single_function(&[], &[], &[0x00, 0x03, 0x40, 0x0C, 0x00, 0x0B, 0x0B], false);
+        let config = RuntimeConfig { fuel: 1000, ..RuntimeConfig::default() };
+        let mut instance = Instance::instantiate(spin, Sandbox::new(vec![]), config).unwrap();
+        assert_eq!(instance.invoke("f", &[], &mut MockHost::new()), Err(Trap::OutOfFuel));
+        assert_eq!(instance.fuel_remaining(), 0);
+
+        // Unbounded recursion: call 0
+        let recurse = single_function(&[], &[], &[0x00, 0x10, 0x00, 0x0B], false);
+        assert_eq!(run(recurse, &[]), Err(Trap::CallStackExhausted));
+
+        // Ill-typed code: i32.add of an i64
+        let ill_typed = single_function(&[], &[0x7F], &[0x00, 0x42, 0x01, 0x41, 0x01, 0x6A, 0x0B], false);
+        assert_eq!(run(ill_typed, &[]), Err(Trap::InvalidCode));
+
+        assert_eq!(run(single_function(&[], &[], &[0x00, 0x00, 0x0B], false), &[]), Err(Trap::Unreachable));
+    }
+
+    #[test]
+    fn test_data_segments_must_fit() {
+        let mut bytes = header();
+        section(&mut bytes, 5, &[0x01, 0x00, 0x01]);
+        let mut data = vec![0x01, 0x00, 0x41];
+        data.extend(leb_signed(PAGE_SIZE as i32 - 1));
+        data.extend_from_slice(&[0x0B, 0x02, b'h', b'i']);
+        section(&mut bytes, 11, &data);
+        let module = Module::parse(&bytes).unwrap();
+        let result = Instance::instantiate(module, Sandbox::new(vec![]), RuntimeConfig::default());
+        assert_eq!(result.err(), Some(InstantiateError::DataOutOfBounds));
+
+        let mut bytes = header();
+        section(&mut bytes, 5, &[0x01, 0x00, 0x20]);
+        let module = Module::parse(&bytes).unwrap();
+        let config = RuntimeConfig { max_memory_pages: 16, ..RuntimeConfig::default() };
+        let result = Instance::instantiate(module, Sandbox::new(vec![]), config);
+        assert_eq!(result.err(), Some(InstantiateError::MemoryLimitExceeded));
+    }
+
+    /// Encode a non-negative signed LEB128 integer
+    fn leb_signed(value: i32) -> Vec<u8> {
+        let mut bytes = leb(value as u32);
+        if bytes.last().is_some_and(|byte| byte & 0x40 != 0) {
+            *bytes.last_mut().unwrap() |= 0x80;
+            bytes.push(0x00);
+        }
+        bytes
+    }
+}
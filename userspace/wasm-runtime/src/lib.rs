@@ -0,0 +1,24 @@
+//! WebAssembly runtime for sandboxed applications
+//!
+//! A small interpreter for the integer subset of WebAssembly 1.0. Apps can
+//! only reach the system through the imports listed in [`host`], and each
+//! import module is gated on a capability granted when the app is started,
+//! so an app isolated here cannot touch anything it was not handed even
+//! though it shares the runtime's address space.
+//!
+//! Not supported: floating point, tables and `call_indirect`, multi-value
+//! blocks, imported memories and globals, and the bulk memory and SIMD
+//! proposals. Modules using them are rejected when decoded.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod module;
+pub mod instr;
+pub mod interp;
+pub mod host;
+
+pub use module::{Module, ParseError, ValType};
+pub use interp::{Instance, InstantiateError, RuntimeConfig, Trap, Value};
+pub use host::{Capability, Host, LinkError, Sandbox};
@@ -0,0 +1,243 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use kosh_posix::errno::{self, Errno};
+use kosh_types::OpenFlags;
+use kosh_service::{ServiceHandler, ServiceMessage, ServiceResponse, ServiceType, ServiceData, ServiceStatus, ServiceRunner, ProcessRequest};
+use kosh_wasm_runtime::{Capability, Host, Instance, Module, RuntimeConfig, Sandbox, Trap};
+
+// Global allocator setup
+use linked_list_allocator::LockedHeap;
+
+#[global_allocator]
+static ALLOCATOR: LockedHeap = LockedHeap::empty();
+
+/// Largest app binary accepted
+const MAX_MODULE_SIZE: usize = 256 * 1024;
+
+/// Export run as the app's entry point
+const ENTRY_POINT: &str = "_start";
+
+/// Host functions backed by Kosh system calls
+///
+/// There is no display manager yet, so the display surface is the console
+/// and keys come from standard input.
+struct KoshHost;
+
+impl Host for KoshHost {
+    fn log(&mut self, message: &[u8]) {
+        debug_print(message);
+    }
+
+    fn open(&mut self, path: &str, flags: OpenFlags) -> Result<i32, Errno> {
+        kosh_posix::open(path, flags.bits() as i32, 0)
+    }
+
+    fn read(&mut self, fd: i32, buffer: &mut [u8]) -> Result<usize, Errno> {
+        kosh_posix::read(fd, buffer)
+    }
+
+    fn write(&mut self, fd: i32, data: &[u8]) -> Result<usize, Errno> {
+        kosh_posix::write(fd, data)
+    }
+
+    fn close(&mut self, fd: i32) -> Result<(), Errno> {
+        kosh_posix::close(fd)
+    }
+
+    fn display_write(&mut self, text: &[u8]) -> Result<usize, Errno> {
+        kosh_posix::write_all(kosh_posix::STDOUT_FILENO, text)?;
+        Ok(text.len())
+    }
+
+    fn display_clear(&mut self) -> Result<(), Errno> {
+        Err(errno::ENOSYS)
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Errno> {
+        let mut key = [0u8; 1];
+        match kosh_posix::read(kosh_posix::STDIN_FILENO, &mut key)? {
+            0 => Ok(None),
+            _ => Ok(Some(key[0])),
+        }
+    }
+}
+
+/// WebAssembly Runtime Service Handler
+struct WasmRuntimeService {
+    apps_run: u64,
+}
+
+impl WasmRuntimeService {
+    fn new() -> Self {
+        Self { apps_run: 0 }
+    }
+
+    /// Read a whole app binary through the file system service
+    fn load_binary(path: &str) -> Result<Vec<u8>, String> {
+        let fd = kosh_posix::open(path, kosh_posix::O_RDONLY, 0)
+            .map_err(|error| format!("cannot open {}: {}", path, error))?;
+
+        let mut binary = Vec::new();
+        let mut chunk = [0u8; 1024];
+        let result = loop {
+            match kosh_posix::read(fd, &mut chunk) {
+                Ok(0) => break Ok(binary),
+                Ok(count) if binary.len() + count <= MAX_MODULE_SIZE => binary.extend_from_slice(&chunk[..count]),
+                Ok(_) => break Err(format!("{} is larger than {} bytes", path, MAX_MODULE_SIZE)),
+                Err(error) => break Err(format!("cannot read {}: {}", path, error)),
+            }
+        };
+        let _ = kosh_posix::close(fd);
+        result
+    }
+
+    /// Run an app to completion, returning its exit status
+    ///
+    /// `args` are capability names, plus `root=<dir>` to confine file access.
+    fn run_app(&mut self, program: &str, args: &[String]) -> Result<i32, String> {
+        let mut capabilities = Vec::new();
+        let mut fs_root = None;
+        for arg in args {
+            if let Some(root) = arg.strip_prefix("root=") {
+                fs_root = Some(root);
+            } else {
+                let capability = Capability::from_name(arg)
+                    .ok_or_else(|| format!("unknown capability {}", arg))?;
+                capabilities.push(capability);
+            }
+        }
+
+        let binary = Self::load_binary(program)?;
+        let module = Module::parse(&binary).map_err(|error| format!("invalid module: {:?}", error))?;
+        drop(binary);
+
+        let mut sandbox = Sandbox::new(capabilities);
+        if let Some(root) = fs_root {
+            sandbox.set_fs_root(root);
+        }
+
+        let mut instance = Instance::instantiate(module, sandbox, RuntimeConfig::default())
+            .map_err(|error| format!("cannot instantiate: {:?}", error))?;
+
+        self.apps_run += 1;
+        let mut host = KoshHost;
+        let result = instance.start(&mut host)
+            .and_then(|_| instance.invoke(ENTRY_POINT, &[], &mut host));
+        instance.release(&mut host);
+
+        match result {
+            Ok(_) => Ok(0),
+            Err(Trap::Exit(status)) => Ok(status),
+            Err(trap) => Err(format!("trapped: {:?}", trap)),
+        }
+    }
+}
+
+impl ServiceHandler for WasmRuntimeService {
+    fn handle_request(&mut self, request: ServiceMessage) -> ServiceResponse {
+        let (status, data) = match request.data {
+            ServiceData::ProcessRequest(ProcessRequest::Spawn { program, args }) => {
+                match self.run_app(&program, &args) {
+                    Ok(exit_status) => (ServiceStatus::Success, ServiceData::Text(format!("{} exited with status {}", program, exit_status))),
+                    Err(reason) => {
+                        debug_print(format!("WASM Runtime: {}: {}\n", program, reason).as_bytes());
+                        (ServiceStatus::Error, ServiceData::Text(reason))
+                    }
+                }
+            }
+            _ => (ServiceStatus::InvalidRequest, ServiceData::Empty),
+        };
+
+        ServiceResponse {
+            request_id: request.request_id,
+            status,
+            data,
+        }
+    }
+
+    fn get_service_type(&self) -> ServiceType {
+        ServiceType::AppRuntime
+    }
+
+    fn initialize(&mut self) -> Result<(), kosh_service::ServiceError> {
+        debug_print(b"WASM Runtime: Ready to run apps\n");
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> Result<(), kosh_service::ServiceError> {
+        debug_print(format!("WASM Runtime: Shutting down after {} apps\n", self.apps_run).as_bytes());
+        Ok(())
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    // Initialize heap allocator
+    init_heap();
+
+    debug_print(b"WASM Runtime: Starting WebAssembly runtime service\n");
+
+    let runtime = WasmRuntimeService::new();
+    let mut service_runner = ServiceRunner::new(runtime);
+
+    if service_runner.start().is_err() {
+        debug_print(b"WASM Runtime: Failed to start service\n");
+        sys_exit(1);
+    }
+
+    // Main service loop; apps run one at a time, each to completion
+    loop {
+        if service_runner.poll_and_dispatch(kosh_ipc::poll::INFINITE).is_err() {
+            debug_print(b"WASM Runtime: Error processing request\n");
+        }
+    }
+}
+
+fn init_heap() {
+    // Room for one app's linear memory at the default limit, its decoded
+    // code and the binary it was loaded from
+    const HEAP_SIZE: usize = 2 * 1024 * 1024;
+    static mut HEAP_MEMORY: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+
+    unsafe {
+        let heap_ptr = core::ptr::addr_of_mut!(HEAP_MEMORY);
+        ALLOCATOR.lock().init((*heap_ptr).as_mut_ptr(), HEAP_SIZE);
+    }
+}
+
+fn debug_print(message: &[u8]) {
+    #[cfg(debug_assertions)]
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") 100u64, // SYS_DEBUG_PRINT
+            in("rdi") message.as_ptr(),
+            in("rsi") message.len(),
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+fn sys_exit(status: i32) -> ! {
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            in("rax") 1u64, // SYS_EXIT
+            in("rdi") status,
+            options(noreturn)
+        );
+    }
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    debug_print(b"WASM Runtime: PANIC occurred!\n");
+    sys_exit(1);
+}
@@ -0,0 +1,660 @@
+//! WebAssembly binary module decoding
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::instr::{self, Instr};
+use crate::interp::Value;
+
+const MAGIC: &[u8; 4] = b"\0asm";
+const VERSION: u32 = 1;
+
+/// Size of one linear memory page
+pub const PAGE_SIZE: usize = 64 * 1024;
+
+/// Largest memory a module may declare, in pages (4 GiB)
+const MAX_PAGES: u32 = 65536;
+
+/// Section IDs
+const SECTION_CUSTOM: u8 = 0;
+const SECTION_TYPE: u8 = 1;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_FUNCTION: u8 = 3;
+const SECTION_TABLE: u8 = 4;
+const SECTION_MEMORY: u8 = 5;
+const SECTION_GLOBAL: u8 = 6;
+const SECTION_EXPORT: u8 = 7;
+const SECTION_START: u8 = 8;
+const SECTION_ELEMENT: u8 = 9;
+const SECTION_CODE: u8 = 10;
+const SECTION_DATA: u8 = 11;
+const SECTION_DATA_COUNT: u8 = 12;
+
+/// Module decoding errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// Input ended in the middle of a construct
+    UnexpectedEnd,
+    /// Not a WebAssembly binary
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// LEB128 integer is too long or out of range
+    MalformedInteger,
+    InvalidUtf8,
+    /// Unknown section ID or section out of order
+    InvalidSection(u8),
+    /// Unknown or misplaced opcode
+    InvalidOpcode(u8),
+    /// Reference to a type, function, global or label that does not exist
+    InvalidIndex,
+    /// Structurally invalid content
+    Malformed(&'static str),
+    /// Valid WebAssembly outside the subset this runtime implements
+    Unsupported(&'static str),
+}
+
+/// Value types; only the integer types are supported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValType {
+    I32,
+    I64,
+}
+
+impl ValType {
+    fn from_byte(byte: u8) -> Result<Self, ParseError> {
+        match byte {
+            0x7F => Ok(ValType::I32),
+            0x7E => Ok(ValType::I64),
+            0x7D | 0x7C => Err(ParseError::Unsupported("floating point")),
+            0x7B => Err(ParseError::Unsupported("SIMD")),
+            0x70 | 0x6F => Err(ParseError::Unsupported("reference types")),
+            _ => Err(ParseError::Malformed("invalid value type")),
+        }
+    }
+
+    /// Zero value of this type, used to initialize locals
+    pub fn zero(self) -> Value {
+        match self {
+            ValType::I32 => Value::I32(0),
+            ValType::I64 => Value::I64(0),
+        }
+    }
+}
+
+/// Function signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    /// At most one result without the multi-value proposal
+    pub results: Vec<ValType>,
+}
+
+/// Imported function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub type_index: u32,
+}
+
+/// Function defined by the module
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub type_index: u32,
+    /// Declared locals, not including parameters
+    pub locals: Vec<ValType>,
+    pub body: Vec<Instr>,
+}
+
+/// Linear memory size limits, in pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLimits {
+    pub min: u32,
+    pub max: Option<u32>,
+}
+
+/// Global variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Global {
+    pub ty: ValType,
+    pub mutable: bool,
+    pub init: Value,
+}
+
+/// Kind of an exported item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportKind {
+    Function,
+    Table,
+    Memory,
+    Global,
+}
+
+/// Exported item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub name: String,
+    pub kind: ExportKind,
+    pub index: u32,
+}
+
+/// Active data segment copied into memory on instantiation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataSegment {
+    pub offset: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// Decoded module
+///
+/// Functions are indexed with imports first, as in the binary format.
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    pub types: Vec<FuncType>,
+    pub imports: Vec<Import>,
+    pub functions: Vec<Function>,
+    pub memory: Option<MemoryLimits>,
+    pub globals: Vec<Global>,
+    pub exports: Vec<Export>,
+    pub start: Option<u32>,
+    pub data: Vec<DataSegment>,
+}
+
+impl Module {
+    /// Decode a module from its binary encoding
+    pub fn parse(bytes: &[u8]) -> Result<Self, ParseError> {
+        let mut reader = Reader::new(bytes);
+        if reader.bytes(4).map_err(|_| ParseError::BadMagic)? != MAGIC {
+            return Err(ParseError::BadMagic);
+        }
+        let version = u32::from_le_bytes(reader.array()?);
+        if version != VERSION {
+            return Err(ParseError::UnsupportedVersion(version));
+        }
+
+        let mut module = Module::default();
+        let mut function_types = Vec::new();
+        let mut last_section = 0;
+
+        while !reader.is_empty() {
+            let id = reader.byte()?;
+            let size = reader.u32()? as usize;
+            let mut section = Reader::new(reader.bytes(size)?);
+
+            // Non-custom sections appear at most once, in ascending order
+            // except for data count, which sits between element and code
+            if id != SECTION_CUSTOM {
+                let order = section_order(id);
+                if order <= last_section {
+                    return Err(ParseError::InvalidSection(id));
+                }
+                last_section = order;
+            }
+
+            match id {
+                SECTION_CUSTOM => continue,
+                SECTION_TYPE => module.types = section.vec(parse_func_type)?,
+                SECTION_IMPORT => module.imports = section.vec(parse_import)?,
+                SECTION_FUNCTION => function_types = section.vec(|r| r.u32())?,
+                SECTION_TABLE => {
+                    if section.u32()? != 0 {
+                        return Err(ParseError::Unsupported("tables"));
+                    }
+                }
+                SECTION_MEMORY => {
+                    let mut memories = section.vec(parse_limits)?;
+                    if memories.len() > 1 {
+                        return Err(ParseError::Unsupported("multiple memories"));
+                    }
+                    module.memory = memories.pop();
+                }
+                SECTION_GLOBAL => module.globals = section.vec(parse_global)?,
+                SECTION_EXPORT => module.exports = section.vec(parse_export)?,
+                SECTION_START => module.start = Some(section.u32()?),
+                SECTION_ELEMENT => {
+                    if section.u32()? != 0 {
+                        return Err(ParseError::Unsupported("tables"));
+                    }
+                }
+                SECTION_CODE => {
+                    let count = section.u32()? as usize;
+                    if count != function_types.len() {
+                        return Err(ParseError::Malformed("function and code counts differ"));
+                    }
+                    for &type_index in &function_types {
+                        module.functions.push(parse_function(&mut section, type_index)?);
+                    }
+                }
+                SECTION_DATA => module.data = section.vec(parse_data)?,
+                SECTION_DATA_COUNT => {
+                    section.u32()?;
+                }
+                _ => return Err(ParseError::InvalidSection(id)),
+            }
+
+            if !section.is_empty() {
+                return Err(ParseError::Malformed("section size mismatch"));
+            }
+        }
+
+        if module.functions.len() != function_types.len() {
+            return Err(ParseError::Malformed("function and code counts differ"));
+        }
+        module.check_indices()?;
+        Ok(module)
+    }
+
+    /// Total number of functions, imported and defined
+    pub fn function_count(&self) -> usize {
+        self.imports.len() + self.functions.len()
+    }
+
+    /// Signature of a function in the combined index space
+    pub fn function_type(&self, index: u32) -> Option<&FuncType> {
+        let index = index as usize;
+        let type_index = if index < self.imports.len() {
+            self.imports[index].type_index
+        } else {
+            self.functions.get(index - self.imports.len())?.type_index
+        };
+        self.types.get(type_index as usize)
+    }
+
+    /// Look up an exported function by name
+    pub fn exported_function(&self, name: &str) -> Option<u32> {
+        self.exports.iter()
+            .find(|export| export.kind == ExportKind::Function && export.name == name)
+            .map(|export| export.index)
+    }
+
+    /// Reject references to items that do not exist, so the interpreter
+    /// never has to
+    fn check_indices(&self) -> Result<(), ParseError> {
+        let type_count = self.types.len() as u32;
+        if self.imports.iter().any(|import| import.type_index >= type_count)
+            || self.functions.iter().any(|function| function.type_index >= type_count)
+        {
+            return Err(ParseError::InvalidIndex);
+        }
+
+        let function_count = self.function_count() as u32;
+        if self.start.is_some_and(|start| start >= function_count) {
+            return Err(ParseError::InvalidIndex);
+        }
+
+        for export in &self.exports {
+            let valid = match export.kind {
+                ExportKind::Function => export.index < function_count,
+                ExportKind::Memory => export.index == 0 && self.memory.is_some(),
+                ExportKind::Global => (export.index as usize) < self.globals.len(),
+                ExportKind::Table => false,
+            };
+            if !valid {
+                return Err(ParseError::InvalidIndex);
+            }
+        }
+
+        if !self.data.is_empty() && self.memory.is_none() {
+            return Err(ParseError::InvalidIndex);
+        }
+
+        for function in &self.functions {
+            for instr in &function.body {
+                let valid = match *instr {
+                    Instr::Call(index) => index < function_count,
+                    Instr::GlobalGet(index) | Instr::GlobalSet(index) => (index as usize) < self.globals.len(),
+                    Instr::Load(..) | Instr::Store(..) | Instr::MemorySize | Instr::MemoryGrow => self.memory.is_some(),
+                    _ => true,
+                };
+                if !valid {
+                    return Err(ParseError::InvalidIndex);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn section_order(id: u8) -> u8 {
+    match id {
+        SECTION_DATA_COUNT => 2 * SECTION_ELEMENT + 1,
+        id => 2 * id,
+    }
+}
+
+fn parse_func_type(reader: &mut Reader) -> Result<FuncType, ParseError> {
+    if reader.byte()? != 0x60 {
+        return Err(ParseError::Malformed("expected function type"));
+    }
+    let params = reader.vec(|r| ValType::from_byte(r.byte()?))?;
+    let results = reader.vec(|r| ValType::from_byte(r.byte()?))?;
+    if results.len() > 1 {
+        return Err(ParseError::Unsupported("multiple results"));
+    }
+    Ok(FuncType { params, results })
+}
+
+fn parse_import(reader: &mut Reader) -> Result<Import, ParseError> {
+    let module = reader.name()?;
+    let name = reader.name()?;
+    match reader.byte()? {
+        0x00 => Ok(Import { module, name, type_index: reader.u32()? }),
+        0x01 => Err(ParseError::Unsupported("table imports")),
+        0x02 => Err(ParseError::Unsupported("memory imports")),
+        0x03 => Err(ParseError::Unsupported("global imports")),
+        _ => Err(ParseError::Malformed("invalid import kind")),
+    }
+}
+
+fn parse_limits(reader: &mut Reader) -> Result<MemoryLimits, ParseError> {
+    let limits = match reader.byte()? {
+        0x00 => MemoryLimits { min: reader.u32()?, max: None },
+        0x01 => MemoryLimits { min: reader.u32()?, max: Some(reader.u32()?) },
+        0x02 | 0x03 => return Err(ParseError::Unsupported("shared memory")),
+        _ => return Err(ParseError::Malformed("invalid limits")),
+    };
+    if limits.min > MAX_PAGES || limits.max.is_some_and(|max| max > MAX_PAGES || max < limits.min) {
+        return Err(ParseError::Malformed("invalid memory limits"));
+    }
+    Ok(limits)
+}
+
+fn parse_global(reader: &mut Reader) -> Result<Global, ParseError> {
+    let ty = ValType::from_byte(reader.byte()?)?;
+    let mutable = match reader.byte()? {
+        0x00 => false,
+        0x01 => true,
+        _ => return Err(ParseError::Malformed("invalid mutability")),
+    };
+    let init = parse_const_expr(reader)?;
+    if init.ty() != ty {
+        return Err(ParseError::Malformed("global initializer type mismatch"));
+    }
+    Ok(Global { ty, mutable, init })
+}
+
+fn parse_export(reader: &mut Reader) -> Result<Export, ParseError> {
+    let name = reader.name()?;
+    let kind = match reader.byte()? {
+        0x00 => ExportKind::Function,
+        0x01 => ExportKind::Table,
+        0x02 => ExportKind::Memory,
+        0x03 => ExportKind::Global,
+        _ => return Err(ParseError::Malformed("invalid export kind")),
+    };
+    Ok(Export { name, kind, index: reader.u32()? })
+}
+
+fn parse_function(reader: &mut Reader, type_index: u32) -> Result<Function, ParseError> {
+    let size = reader.u32()? as usize;
+    let mut body = Reader::new(reader.bytes(size)?);
+
+    let mut locals = Vec::new();
+    for _ in 0..body.u32()? {
+        let count = body.u32()? as usize;
+        let ty = ValType::from_byte(body.byte()?)?;
+        if locals.len() + count > u16::MAX as usize {
+            return Err(ParseError::Unsupported("too many locals"));
+        }
+        locals.resize(locals.len() + count, ty);
+    }
+
+    Ok(Function { type_index, locals, body: instr::decode_body(&mut body)? })
+}
+
+fn parse_data(reader: &mut Reader) -> Result<DataSegment, ParseError> {
+    match reader.u32()? {
+        0x00 => {}
+        0x01 => return Err(ParseError::Unsupported("passive data segments")),
+        0x02 => {
+            if reader.u32()? != 0 {
+                return Err(ParseError::InvalidIndex);
+            }
+        }
+        _ => return Err(ParseError::Malformed("invalid data segment")),
+    }
+    let offset = match parse_const_expr(reader)? {
+        Value::I32(offset) => offset as u32,
+        Value::I64(_) => return Err(ParseError::Malformed("data offset must be i32")),
+    };
+    let size = reader.u32()? as usize;
+    Ok(DataSegment { offset, bytes: reader.bytes(size)?.to_vec() })
+}
+
+/// Constant expression: a single constant followed by `end`
+fn parse_const_expr(reader: &mut Reader) -> Result<Value, ParseError> {
+    let value = match reader.byte()? {
+        0x41 => Value::I32(reader.i32()?),
+        0x42 => Value::I64(reader.i64()?),
+        0x23 => return Err(ParseError::Unsupported("global imports")),
+        _ => return Err(ParseError::Unsupported("non-constant initializer")),
+    };
+    if reader.byte()? != 0x0B {
+        return Err(ParseError::Malformed("expected end of constant expression"));
+    }
+    Ok(value)
+}
+
+/// Cursor over a byte slice with the binary format's primitive encodings
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, ParseError> {
+        let byte = *self.bytes.get(self.position).ok_or(ParseError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    pub(crate) fn bytes(&mut self, count: usize) -> Result<&'a [u8], ParseError> {
+        let end = self.position.checked_add(count).ok_or(ParseError::UnexpectedEnd)?;
+        let bytes = self.bytes.get(self.position..end).ok_or(ParseError::UnexpectedEnd)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ParseError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    /// Unsigned LEB128 of at most 32 bits
+    pub(crate) fn u32(&mut self) -> Result<u32, ParseError> {
+        let mut result = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            if shift == 28 && byte & 0x70 != 0 {
+                return Err(ParseError::MalformedInteger);
+            }
+            result |= ((byte & 0x7F) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        Err(ParseError::MalformedInteger)
+    }
+
+    /// Signed LEB128 of at most `bits` bits
+    fn signed(&mut self, bits: u32) -> Result<i64, ParseError> {
+        let mut result = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= bits {
+                return Err(ParseError::MalformedInteger);
+            }
+            result |= ((byte & 0x7F) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1i64 << shift;
+                }
+                break;
+            }
+        }
+        // Unused bits of the final byte must extend the sign
+        if bits < 64 && (result < -(1i64 << (bits - 1)) || result >= 1i64 << (bits - 1)) {
+            return Err(ParseError::MalformedInteger);
+        }
+        Ok(result)
+    }
+
+    pub(crate) fn i32(&mut self) -> Result<i32, ParseError> {
+        Ok(self.signed(32)? as i32)
+    }
+
+    pub(crate) fn i64(&mut self) -> Result<i64, ParseError> {
+        self.signed(64)
+    }
+
+    /// Block type used by the signed 33-bit encoding
+    pub(crate) fn s33(&mut self) -> Result<i64, ParseError> {
+        self.signed(33)
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        let size = self.u32()? as usize;
+        let bytes = self.bytes(size)?;
+        core::str::from_utf8(bytes)
+            .map(String::from)
+            .map_err(|_| ParseError::InvalidUtf8)
+    }
+
+    pub(crate) fn vec<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, ParseError>) -> Result<Vec<T>, ParseError> {
+        let count = self.u32()? as usize;
+        // Every item takes at least one byte; do not trust the count further
+        if count > self.bytes.len() - self.position {
+            return Err(ParseError::UnexpectedEnd);
+        }
+        let mut items = Vec::with_capacity(count);
+        for _ in 0..count {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// Encode an unsigned LEB128 integer
+    pub(crate) fn leb(mut value: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte);
+                return bytes;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+
+    /// Append a section with its size prefix
+    pub(crate) fn section(module: &mut Vec<u8>, id: u8, contents: &[u8]) {
+        module.push(id);
+        module.extend(leb(contents.len() as u32));
+        module.extend_from_slice(contents);
+    }
+
+    /// Wrap a function body (locals declaration included) with its size
+    pub(crate) fn code_entry(body: &[u8]) -> Vec<u8> {
+        let mut entry = leb(body.len() as u32);
+        entry.extend_from_slice(body);
+        entry
+    }
+
+    pub(crate) fn header() -> Vec<u8> {
+        vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00]
+    }
+
+    #[test]
+    fn test_leb128() {
+        assert_eq!(Reader::new(&[0xE5, 0x8E, 0x26]).u32(), Ok(624485));
+        assert_eq!(Reader::new(&[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]).u32(), Ok(u32::MAX));
+        assert_eq!(Reader::new(&[0xFF, 0xFF, 0xFF, 0xFF, 0x1F]).u32(), Err(ParseError::MalformedInteger));
+        assert_eq!(Reader::new(&[0x80, 0x80]).u32(), Err(ParseError::UnexpectedEnd));
+
+        assert_eq!(Reader::new(&[0x7F]).i32(), Ok(-1));
+        assert_eq!(Reader::new(&[0xC0, 0xBB, 0x78]).i32(), Ok(-123456));
+        assert_eq!(Reader::new(&[0x80, 0x80, 0x80, 0x80, 0x78]).i32(), Ok(i32::MIN));
+        assert_eq!(Reader::new(&[0x80, 0x80, 0x80, 0x80, 0x70]).i32(), Err(ParseError::MalformedInteger));
+        assert_eq!(
+            Reader::new(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7F]).i64(),
+            Ok(i64::MIN)
+        );
+    }
+
+    #[test]
+    fn test_parse_module() {
+        let mut bytes = header();
+        // (type (func (param i32 i32) (result i32)))
+        section(&mut bytes, SECTION_TYPE, &[0x01, 0x60, 0x02, 0x7F, 0x7F, 0x01, 0x7F]);
+        // (import "kosh" "log" (func (type 0)))
+        section(&mut bytes, SECTION_IMPORT, &[0x01, 0x04, b'k', b'o', b's', b'h', 0x03, b'l', b'o', b'g', 0x00, 0x00]);
+        section(&mut bytes, SECTION_FUNCTION, &[0x01, 0x00]);
+        section(&mut bytes, SECTION_MEMORY, &[0x01, 0x01, 0x01, 0x02]);
+        // (global (mut i64) (i64.const -2))
+        section(&mut bytes, SECTION_GLOBAL, &[0x01, 0x7E, 0x01, 0x42, 0x7E, 0x0B]);
+        section(&mut bytes, SECTION_EXPORT, &[0x01, 0x03, b'a', b'd', b'd', 0x00, 0x01]);
+        let mut code = vec![0x01];
+        code.extend(code_entry(&[0x00, 0x20, 0x00, 0x20, 0x01, 0x6A, 0x0B]));
+        section(&mut bytes, SECTION_CODE, &code);
+        section(&mut bytes, SECTION_DATA, &[0x01, 0x00, 0x41, 0x10, 0x0B, 0x02, b'h', b'i']);
+        section(&mut bytes, SECTION_CUSTOM, &[0x04, b'n', b'a', b'm', b'e']);
+
+        let module = Module::parse(&bytes).unwrap();
+        assert_eq!(module.types[0].params, vec![ValType::I32, ValType::I32]);
+        assert_eq!(module.imports[0].module, "kosh");
+        assert_eq!(module.function_count(), 2);
+        assert_eq!(module.memory, Some(MemoryLimits { min: 1, max: Some(2) }));
+        assert_eq!(module.globals[0].init, Value::I64(-2));
+        assert_eq!(module.exported_function("add"), Some(1));
+        assert_eq!(module.function_type(1).unwrap().results, vec![ValType::I32]);
+        assert_eq!(module.data[0], DataSegment { offset: 16, bytes: vec![b'h', b'i'] });
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_modules() {
+        assert_eq!(Module::parse(b"\0elf\x01\0\0\0").unwrap_err(), ParseError::BadMagic);
+        assert_eq!(Module::parse(b"\0asm\x02\0\0\0").unwrap_err(), ParseError::UnsupportedVersion(2));
+
+        // Floating point is outside the supported subset
+        let mut bytes = header();
+        section(&mut bytes, SECTION_TYPE, &[0x01, 0x60, 0x01, 0x7D, 0x00]);
+        assert_eq!(Module::parse(&bytes).unwrap_err(), ParseError::Unsupported("floating point"));
+
+        // Sections out of order
+        let mut bytes = header();
+        section(&mut bytes, SECTION_FUNCTION, &[0x00]);
+        section(&mut bytes, SECTION_TYPE, &[0x00]);
+        assert_eq!(Module::parse(&bytes).unwrap_err(), ParseError::InvalidSection(SECTION_TYPE));
+
+        // Call to a function that does not exist
+        let mut bytes = header();
+        section(&mut bytes, SECTION_TYPE, &[0x01, 0x60, 0x00, 0x00]);
+        section(&mut bytes, SECTION_FUNCTION, &[0x01, 0x00]);
+        let mut code = vec![0x01];
+        code.extend(code_entry(&[0x00, 0x10, 0x05, 0x0B]));
+        section(&mut bytes, SECTION_CODE, &code);
+        assert_eq!(Module::parse(&bytes).unwrap_err(), ParseError::InvalidIndex);
+
+        // Truncated section
+        let mut bytes = header();
+        bytes.extend_from_slice(&[SECTION_TYPE, 0x05, 0x01]);
+        assert_eq!(Module::parse(&bytes).unwrap_err(), ParseError::UnexpectedEnd);
+    }
+}